@@ -0,0 +1,81 @@
+// ============================================================================
+// BASIC COMPUTE - Controlador montado em código
+// ============================================================================
+//
+// The fan controller built in code rather than read from a config: each
+// variable from `FuzzyVariable::new` and its sets, the rules from their
+// text form, the system from both. It then computes the fan speed over a
+// few rooms and checks that it matches the built-in demo exactly.
+//
+//     cargo run --example basic_compute
+
+use fuzzy_logic::{FuzzyController, FuzzyRule, FuzzySystem, FuzzyVariable, MembershipFunction};
+
+fn triangle(a: f64, b: f64, c: f64) -> MembershipFunction {
+    MembershipFunction::Triangular { a, b, c }
+}
+
+fn trapezoid(a: f64, b: f64, c: f64, d: f64) -> MembershipFunction {
+    MembershipFunction::Trapezoidal { a, b, c, d }
+}
+
+fn fan_controller() -> Result<FuzzySystem, String> {
+    let temperature = FuzzyVariable::new("temperature", 0.0, 50.0)
+        .with_unit("°C")
+        .with_set("Cold", trapezoid(0.0, 0.0, 15.0, 20.0))
+        .with_set("Mild", triangle(15.0, 22.5, 30.0))
+        .with_set("Hot", trapezoid(25.0, 30.0, 50.0, 50.0));
+    let humidity = FuzzyVariable::new("humidity", 0.0, 100.0)
+        .with_unit("%")
+        .with_set("Low", trapezoid(0.0, 0.0, 30.0, 50.0))
+        .with_set("Medium", triangle(30.0, 50.0, 70.0))
+        .with_set("High", trapezoid(50.0, 70.0, 100.0, 100.0));
+    let fan_speed = FuzzyVariable::new("fan_speed", 0.0, 100.0)
+        .with_unit("%")
+        .with_set("Off", triangle(0.0, 0.0, 20.0))
+        .with_set("Low", triangle(0.0, 25.0, 50.0))
+        .with_set("Medium", triangle(25.0, 50.0, 75.0))
+        .with_set("High", triangle(50.0, 100.0, 100.0));
+
+    let speeds = [
+        ["Off", "Off", "Low"],
+        ["Low", "Medium", "Medium"],
+        ["Medium", "High", "High"],
+    ];
+    let mut rules = Vec::new();
+    for (t, row) in ["Cold", "Mild", "Hot"].iter().zip(speeds) {
+        for (h, speed) in ["Low", "Medium", "High"].iter().zip(row) {
+            rules.push(FuzzyRule::parse(&format!(
+                "IF temperature IS {} AND humidity IS {} THEN fan_speed IS {}",
+                t, h, speed
+            ))?);
+        }
+    }
+    FuzzySystem::new(vec![temperature, humidity], vec![fan_speed], rules)
+}
+
+fn main() -> Result<(), String> {
+    let controller = FuzzyController::from_system(fan_controller()?);
+    let demo = FuzzyController::from_system(FuzzySystem::demo());
+
+    println!("temperature  humidity  fan_speed");
+    for (temperature, humidity) in [(12.0, 40.0), (22.0, 55.0), (28.0, 65.0), (35.0, 80.0)] {
+        let speed = controller.compute(&[temperature, humidity]);
+        println!("{:>9.1}°C {:>8.1}% {:>9.1}%", temperature, humidity, speed);
+        assert_eq!(speed, demo.compute(&[temperature, humidity]));
+    }
+
+    // A hot, humid room runs the fan well above half speed
+    let evaluation = controller.evaluate(&[35.0, 80.0]);
+    assert!(evaluation.outputs[0] > 60.0, "{:?}", evaluation.outputs);
+    assert!(evaluation.diagnostics.is_empty());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn runs() {
+        super::main().unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+// ============================================================================
+// CUSTOM SYSTEM - Sistema de três entradas lido de TOML
+// ============================================================================
+//
+// A controller for a meeting-room fan with three inputs, read from config
+// text with `config::parse_system` as `--config` would read a file. The
+// inference is the crate's only one, Mamdani with centroid
+// defuzzification: there is no Sugeno inference to pick, so the outputs
+// are sets here too. Occupancy pushes the fan up even in a cool room.
+//
+//     cargo run --example custom_system
+
+use fuzzy_logic::config;
+use fuzzy_logic::{FuzzyController, FuzzySystem};
+
+const CONFIG: &str = r#"
+rules = [
+    "IF temperature IS Cool AND occupancy IS Empty THEN fan_speed IS Off",
+    "IF temperature IS Cool AND occupancy IS Full THEN fan_speed IS Low",
+    "IF temperature IS Warm AND humidity IS Dry THEN fan_speed IS Low",
+    "IF temperature IS Warm AND humidity IS Damp THEN fan_speed IS High",
+    "IF occupancy IS Full AND humidity IS Damp THEN fan_speed IS High",
+]
+
+[[input]]
+name = "temperature"
+min = 10.0
+max = 35.0
+unit = "°C"
+
+[[input.set]]
+name = "Cool"
+shape = "trapezoidal"
+params = [10.0, 10.0, 20.0, 26.0]
+
+[[input.set]]
+name = "Warm"
+shape = "trapezoidal"
+params = [20.0, 26.0, 35.0, 35.0]
+
+[[input]]
+name = "humidity"
+min = 0.0
+max = 100.0
+unit = "%"
+
+[[input.set]]
+name = "Dry"
+shape = "trapezoidal"
+params = [0.0, 0.0, 40.0, 60.0]
+
+[[input.set]]
+name = "Damp"
+shape = "trapezoidal"
+params = [40.0, 60.0, 100.0, 100.0]
+
+[[input]]
+name = "occupancy"
+min = 0.0
+max = 20.0
+
+[[input.set]]
+name = "Empty"
+shape = "trapezoidal"
+params = [0.0, 0.0, 1.0, 5.0]
+
+[[input.set]]
+name = "Full"
+shape = "trapezoidal"
+params = [1.0, 5.0, 20.0, 20.0]
+
+[output]
+name = "fan_speed"
+min = 0.0
+max = 100.0
+unit = "%"
+
+[[output.set]]
+name = "Off"
+shape = "triangular"
+params = [0.0, 0.0, 30.0]
+
+[[output.set]]
+name = "Low"
+shape = "triangular"
+params = [10.0, 40.0, 70.0]
+
+[[output.set]]
+name = "High"
+shape = "triangular"
+params = [50.0, 100.0, 100.0]
+"#;
+
+fn main() -> Result<(), String> {
+    let system: FuzzySystem = config::parse_system(CONFIG)?;
+    let controller = FuzzyController::from_system(system);
+
+    let empty = controller.compute(&[18.0, 50.0, 0.0]);
+    let meeting = controller.compute(&[18.0, 50.0, 12.0]);
+    let humid_meeting = controller.compute(&[30.0, 80.0, 12.0]);
+    println!("cool, empty room:      {:.1}%", empty);
+    println!("cool, meeting:         {:.1}%", meeting);
+    println!("warm, humid, meeting:  {:.1}%", humid_meeting);
+
+    assert!(empty < 15.0, "{}", empty);
+    assert!(meeting > empty + 20.0, "{} vs {}", meeting, empty);
+    assert!(humid_meeting > 70.0, "{}", humid_meeting);
+
+    // Names that no variable declares are refused when the text is read
+    let broken = CONFIG.replace("occupancy IS Full AND", "occupants IS Full AND");
+    assert!(config::parse_system(&broken).is_err());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn runs() {
+        super::main().unwrap();
+    }
+}
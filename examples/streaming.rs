@@ -0,0 +1,69 @@
+// ============================================================================
+// STREAMING - Leituras ruidosas passo a passo
+// ============================================================================
+//
+// A noisy temperature sensor sampled ten times a second, fed through a
+// `Stepper` one reading at a time. The crate has no smoothing filter on
+// the inputs; the output pipeline's rate limit is what keeps the fan from
+// following the noise, so this system turns it on, along with a hysteresis
+// band. The noise is seeded, so every run prints the same thing.
+//
+//     cargo run --example streaming
+
+use fuzzy_logic::FuzzySystem;
+use fuzzy_logic::pipeline::PipelineConfig;
+use fuzzy_logic::stepper::Stepper;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Most the output may move per second, in percentage points
+const RATE_LIMIT: f64 = 5.0;
+const DT: f64 = 0.1;
+
+fn main() -> Result<(), String> {
+    let system = FuzzySystem::demo().with_pipeline(PipelineConfig {
+        rate_limit: RATE_LIMIT,
+        hysteresis: 2.0,
+        ..PipelineConfig::default()
+    });
+    let mut stepper = Stepper::new(system);
+    let mut rng = StdRng::seed_from_u64(483);
+
+    // The room warms from 20 to 32 °C over a minute, read with ±1.5 °C of
+    // noise
+    let mut previous: Option<f64> = None;
+    let mut last = None;
+    for i in 0..600 {
+        let trend = 20.0 + 12.0 * i as f64 / 600.0;
+        let reading = trend + rng.gen_range(-1.5..=1.5);
+        let step = stepper.step(&[reading, 60.0], DT)?;
+        if let Some(previous) = previous {
+            assert!((step.output.value - previous).abs() <= RATE_LIMIT * DT + 1e-9);
+        }
+        previous = Some(step.output.value);
+        if i % 100 == 0 {
+            println!(
+                "{:>5.1}s  read {:>5.1}°C  fuzzy {:>5.1}%  fan {:>5.1}% {}",
+                step.time,
+                reading,
+                step.evaluation.outputs[0],
+                step.output.value,
+                step.output.band.label()
+            );
+        }
+        last = Some(step);
+    }
+
+    // By the end the fan has caught up with the warm room
+    let last = last.expect("600 steps");
+    assert!(last.output.value > 50.0, "{}", last.output.value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn runs() {
+        super::main().unwrap();
+    }
+}
@@ -0,0 +1,46 @@
+// ============================================================================
+// SURFACE EXPORT - Superfície de controle em CSV
+// ============================================================================
+//
+// The demo's control surface over a 21 x 21 grid of temperature and
+// humidity, written as the CSV `fuzzy_logic surface` prints, to a buffer
+// here and to stdout at the end.
+//
+//     cargo run --example surface_export > surface.csv
+
+use fuzzy_logic::surface::{self, Surface};
+use fuzzy_logic::{FuzzyController, FuzzySystem};
+use std::io::Write;
+
+const GRID: usize = 21;
+
+fn main() -> std::io::Result<()> {
+    let system = FuzzySystem::demo();
+    let controllers = [FuzzyController::from_system(system.clone())];
+    let surfaces: Vec<Surface> = surface::compute_surfaces(&controllers, GRID, GRID);
+
+    let mut csv = Vec::new();
+    surface::write_csv(&system, &surfaces, &mut csv)?;
+    let text = String::from_utf8(csv).expect("the CSV is text");
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 1 + GRID * GRID);
+    assert_eq!(lines[0], "temperature [°C],humidity [%],fan_speed [%]");
+
+    // The hottest, most humid corner is the last point
+    let corner: Vec<f64> = lines[GRID * GRID]
+        .split(',')
+        .map(|v| v.parse().expect("a number"))
+        .collect();
+    assert_eq!(corner[..2], [50.0, 100.0]);
+    assert!((corner[2] - controllers[0].compute(&[50.0, 100.0])).abs() < 1e-4);
+
+    std::io::stdout().lock().write_all(text.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn runs() {
+        super::main().unwrap();
+    }
+}
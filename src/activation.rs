@@ -0,0 +1,444 @@
+// ============================================================================
+// RULE ACTIVATIONS - Ativações das regras
+// ============================================================================
+//
+// What the rules conclude about one output, kept without a name per rule:
+//
+//     peaks    one accumulator per set of the output, in the output's set
+//              order, holding the strongest rule concluding that set (the
+//              max s-norm, folded in as the rules are evaluated)
+//     fired    each rule that fired for the output: its index, the index of
+//              its set and its strength, in rule order
+//
+// Under the default max aggregation, a set clipped at each of its rules is
+// the same set clipped at its peak, so aggregation reads the peaks alone.
+// The soft-max and the bounded sum add up the clipped set of every rule,
+// which the peaks cannot stand in for, so they read `fired`. `fired` is
+// also the per-rule breakdown for whoever traces a computation.
+//
+// A `Session` keeps these buffers, and the others one computation needs,
+// across the computations of one system. It resolves rule conditions and
+// consequents to indices once, when it is created. From then on `compute`
+// allocates nothing, unless an envelope pulls or a reading is refused:
+// their reports carry names.
+//
+// Each output is defuzzified with its own method and resolution, the
+// controller's where it sets none; the session notes which method produced
+// each value. A session `within` a latency budget (budget.rs) times each
+// computation and defuzzifies at the rung the budget is on. After a computation that
+// ran over, the next one keeps only the peaks when the aggregation lets
+// it, leaving `fired` empty.
+
+use crate::budget::{self, Budget, Rung};
+use crate::envelope;
+use crate::pipeline::Clock;
+use crate::{Aggregation, DefuzzMethod, FuzzySystem, defuzzify_at};
+
+/// A rule that fired for an output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Firing {
+    pub rule: usize,
+    /// Index of the concluded set among the output's sets
+    pub set: usize,
+    pub strength: f64,
+}
+
+/// What the rules conclude about one output
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Activations {
+    peaks: Vec<f64>,
+    fired: Vec<Firing>,
+}
+
+impl Activations {
+    /// Only set `set` of `sets`, at `strength`, as a lone rule would make it
+    pub fn single(sets: usize, set: usize, strength: f64) -> Self {
+        let mut activations = Activations::default();
+        activations.reset(sets);
+        activations.fire(0, set, strength);
+        activations
+    }
+
+    /// Nothing fired, for an output of `sets` sets; the buffers are kept
+    pub fn reset(&mut self, sets: usize) {
+        self.peaks.clear();
+        self.peaks.resize(sets, 0.0);
+        self.fired.clear();
+    }
+
+    /// Rule `rule` concludes set `set` at `strength`; a rule that does not
+    /// fire is not recorded
+    pub fn fire(&mut self, rule: usize, set: usize, strength: f64) {
+        if strength > 0.0 {
+            self.peaks[set] = self.peaks[set].max(strength);
+            self.fired.push(Firing {
+                rule,
+                set,
+                strength,
+            });
+        }
+    }
+
+    /// Like `fire`, keeping only the peak
+    pub fn raise(&mut self, set: usize, strength: f64) {
+        if strength > 0.0 {
+            self.peaks[set] = self.peaks[set].max(strength);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peaks.iter().all(|peak| *peak == 0.0)
+    }
+
+    /// Strongest rule concluding set `set`, 0 when none fired
+    pub fn peak(&self, set: usize) -> f64 {
+        self.peaks[set]
+    }
+
+    /// Rules that fired, in rule order
+    pub fn fired(&self) -> &[Firing] {
+        &self.fired
+    }
+}
+
+/// A rule with its conditions and consequents resolved to indices
+struct IndexedRule {
+    /// Input and set of each condition, none for one naming neither
+    conditions: Vec<Option<(usize, usize)>>,
+    weight: f64,
+    /// Weight of the rule's group, 0 while the group is disabled
+    group_weight: f64,
+    /// Set concluded for each output, in declaration order
+    consequents: Vec<Option<usize>>,
+}
+
+/// Repeated computations of one system, reusing their buffers
+pub struct Session<'a> {
+    system: &'a FuzzySystem,
+    rules: Vec<IndexedRule>,
+    /// Membership of each input in each of its sets
+    memberships: Vec<Vec<f64>>,
+    strengths: Vec<f64>,
+    activations: Vec<Activations>,
+    /// Readings as the envelopes see them
+    admitted: Vec<f64>,
+    outputs: Vec<f64>,
+    /// Method each output was last defuzzified with
+    methods: Vec<DefuzzMethod>,
+    /// Centroid of each set of each output, for the weighted average;
+    /// empty for the other outputs outside a budget
+    centroids: Vec<Vec<f64>>,
+    /// The budget computations are timed against, and its clock
+    budget: Option<(&'a mut Budget, &'a dyn Clock)>,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(system: &'a FuzzySystem) -> Self {
+        let rules = system
+            .rules
+            .iter()
+            .map(|rule| IndexedRule {
+                conditions: rule
+                    .conditions
+                    .iter()
+                    .map(|(var, set)| {
+                        let input = system.inputs.iter().position(|v| v.name == *var)?;
+                        let set = system.inputs[input]
+                            .sets
+                            .iter()
+                            .position(|s| s.name == *set)?;
+                        Some((input, set))
+                    })
+                    .collect(),
+                weight: rule.weight,
+                group_weight: match &rule.group {
+                    Some(name) => match system.group(name) {
+                        Some(group) if group.enabled => group.weight,
+                        _ => 0.0,
+                    },
+                    None => 1.0,
+                },
+                consequents: system
+                    .outputs
+                    .iter()
+                    .map(|output| {
+                        let set = rule.consequent(&output.name)?;
+                        output.sets.iter().position(|s| s.name == set)
+                    })
+                    .collect(),
+            })
+            .collect();
+        let activations = system
+            .outputs
+            .iter()
+            .map(|output| {
+                let mut activations = Activations {
+                    peaks: Vec::with_capacity(output.sets.len()),
+                    fired: Vec::with_capacity(system.rules.len()),
+                };
+                activations.reset(output.sets.len());
+                activations
+            })
+            .collect();
+        Session {
+            system,
+            rules,
+            memberships: system
+                .inputs
+                .iter()
+                .map(|var| vec![0.0; var.sets.len()])
+                .collect(),
+            strengths: vec![0.0; system.rules.len()],
+            activations,
+            admitted: vec![0.0; system.inputs.len()],
+            outputs: vec![0.0; system.outputs.len()],
+            methods: (system.outputs.iter())
+                .map(|output| system.defuzzification.method_for(output))
+                .collect(),
+            centroids: (system.outputs.iter())
+                .map(|output| match system.defuzzification.method_for(output) {
+                    DefuzzMethod::WeightedAverage => budget::centroids(output),
+                    _ => Vec::new(),
+                })
+                .collect(),
+            budget: None,
+        }
+    }
+
+    /// A session whose computations are timed on `clock` and get cheaper
+    /// as `budget` says
+    pub fn within(system: &'a FuzzySystem, budget: &'a mut Budget, clock: &'a dyn Clock) -> Self {
+        let mut session = Session::new(system);
+        session.centroids = system.outputs.iter().map(budget::centroids).collect();
+        session.budget = Some((budget, clock));
+        session
+    }
+
+    /// Crisp primary output for one value per input, in declaration order,
+    /// with the safety envelopes applied
+    pub fn compute(&mut self, inputs: &[f64]) -> f64 {
+        self.infer(inputs);
+        self.apply_envelopes(inputs);
+        self.outputs[0]
+    }
+
+    /// Fuzzify `inputs`, fire the rules and defuzzify every output, before
+    /// the envelopes
+    pub fn infer(&mut self, inputs: &[f64]) {
+        let system = self.system;
+        let (rung, trace, start) = match &self.budget {
+            Some((budget, clock)) => (
+                budget.rung(),
+                budget.tracing() || system.defuzzification.aggregation != Aggregation::Max,
+                clock.now(),
+            ),
+            None => (Rung::Full, true, 0.0),
+        };
+        for ((var, x), memberships) in system.inputs.iter().zip(inputs).zip(&mut self.memberships) {
+            let x = var.held(*x);
+            for (set, membership) in var.sets.iter().zip(memberships.iter_mut()) {
+                *membership = if x.is_finite() {
+                    set.function.evaluate(x)
+                } else {
+                    0.0
+                };
+            }
+        }
+        for (activations, output) in self.activations.iter_mut().zip(&system.outputs) {
+            activations.reset(output.sets.len());
+        }
+        for (index, (rule, strength)) in self.rules.iter().zip(&mut self.strengths).enumerate() {
+            let activation = rule
+                .conditions
+                .iter()
+                .map(|condition| {
+                    condition
+                        .map(|(input, set)| self.memberships[input][set])
+                        .unwrap_or(0.0)
+                })
+                .fold(1.0, f64::min);
+            *strength = activation * rule.weight * rule.group_weight;
+            for (set, activations) in rule.consequents.iter().zip(&mut self.activations) {
+                match set {
+                    Some(set) if trace => activations.fire(index, *set, *strength),
+                    Some(set) => activations.raise(*set, *strength),
+                    None => {}
+                }
+            }
+        }
+        let settings = &system.defuzzification;
+        for (index, (output, activations)) in
+            system.outputs.iter().zip(&self.activations).enumerate()
+        {
+            let resolution = match settings.method_for(output) {
+                DefuzzMethod::WeightedAverage => None,
+                _ => rung.resolution(settings.resolution_for(output)),
+            };
+            self.outputs[index] = match resolution {
+                Some(resolution) => defuzzify_at(activations, output, settings, resolution),
+                None => {
+                    budget::weighted_average(activations, output, settings, &self.centroids[index])
+                }
+            };
+            self.methods[index] = match resolution {
+                Some(_) => settings.method_for(output),
+                None => DefuzzMethod::WeightedAverage,
+            };
+        }
+        if let Some((budget, clock)) = &mut self.budget {
+            budget.record(clock.now() - start);
+        }
+    }
+
+    /// Pull the first output toward the strongest envelope, if any pulls
+    pub fn apply_envelopes(&mut self, inputs: &[f64]) -> Option<envelope::Override> {
+        let system = self.system;
+        // The envelopes see a clamped reading at the limit, and a refused
+        // one not at all
+        for ((var, x), admitted) in system.inputs.iter().zip(inputs).zip(&mut self.admitted) {
+            *admitted = var.admit(*x).unwrap_or(f64::NAN);
+        }
+        let (first, envelope) = envelope::apply(
+            &system.envelopes,
+            &system.inputs,
+            &self.admitted,
+            self.outputs[0],
+            system.defuzzification.tie_tolerance,
+        );
+        self.outputs[0] = first;
+        envelope
+    }
+
+    /// Crisp value of every output, in declaration order
+    pub fn outputs(&self) -> &[f64] {
+        &self.outputs
+    }
+
+    /// Method every output was defuzzified with, in declaration order
+    pub fn methods(&self) -> &[DefuzzMethod] {
+        &self.methods
+    }
+
+    /// Firing strength of every rule, in rule order
+    pub fn strengths(&self) -> &[f64] {
+        &self.strengths
+    }
+
+    /// What the rules concluded about output `index`
+    pub fn activations(&self, index: usize) -> &Activations {
+        &self.activations[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::{Aggregation, ConsequentCap, DefuzzMethod, FuzzyController};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// The system allocator, counting allocations per thread so tests
+    /// running alongside do not count toward each other
+    struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.with(|n| n.set(n.get() + 1));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static COUNTING: Counting = Counting;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(Cell::get)
+    }
+
+    /// The demo under every aggregation, and capped with plateau centroids
+    fn systems() -> Vec<FuzzySystem> {
+        let mut systems = Vec::new();
+        for aggregation in Aggregation::ALL {
+            let mut system = fixtures::fan();
+            system.defuzzification.aggregation = aggregation;
+            systems.push(system.clone());
+            system.defuzzification.method = DefuzzMethod::PlateauCentroid;
+            system.defuzzification.caps.push(ConsequentCap {
+                profile: "quiet".to_string(),
+                output: "fan_speed".to_string(),
+                set: "High".to_string(),
+                max: 0.4,
+            });
+            system.defuzzification.cap_profile = Some("quiet".to_string());
+            systems.push(system);
+        }
+        systems
+    }
+
+    #[test]
+    fn a_steady_session_computes_without_allocating() {
+        for system in systems() {
+            let mut session = Session::new(&system);
+            let before = allocations();
+            let mut total = 0.0;
+            for i in 0..=200 {
+                total += session.compute(&[i as f64 / 4.0, (i * 7 % 101) as f64]);
+            }
+            assert_eq!(allocations() - before, 0, "{:?}", system.defuzzification);
+            assert!(total.is_finite());
+        }
+    }
+
+    #[test]
+    fn a_session_agrees_with_the_controller() {
+        for system in systems() {
+            let controller = FuzzyController::from_system(system.clone());
+            let mut session = Session::new(&system);
+            for t in [0.0, 8.0, 22.0, 27.5, 33.0, 45.0, 60.0] {
+                for h in [10.0, 45.0, 64.0, 90.0] {
+                    let evaluation = controller.evaluate(&[t, h]);
+                    assert_eq!(session.compute(&[t, h]), evaluation.outputs[0]);
+                    assert_eq!(session.strengths(), evaluation.strengths);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn peaks_keep_the_strongest_rule_and_fired_keeps_them_all() {
+        let system = fixtures::fan();
+        let mut session = Session::new(&system);
+        session.compute(&[27.5, 64.0]);
+        let activations = session.activations(0);
+        let fired = activations.fired();
+        assert!(fired.len() >= 2, "{:?}", fired);
+        for (set, definition) in system.outputs[0].sets.iter().enumerate() {
+            let strongest = fired
+                .iter()
+                .filter(|f| f.set == set)
+                .map(|f| f.strength)
+                .fold(0.0, f64::max);
+            assert_eq!(activations.peak(set), strongest, "{}", definition.name);
+        }
+        for firing in fired {
+            assert_eq!(session.strengths()[firing.rule], firing.strength);
+        }
+
+        // Reset keeps nothing of the previous computation: cold and dry
+        // concludes Off alone
+        session.compute(&[0.0, 0.0]);
+        let activations = session.activations(0);
+        assert!(activations.fired().iter().all(|f| f.set == 0));
+        assert_eq!(activations.peak(0), 1.0);
+        assert!((1..system.outputs[0].sets.len()).all(|set| activations.peak(set) == 0.0));
+    }
+}
@@ -0,0 +1,515 @@
+// ============================================================================
+// ADAPTATION - Adaptação dos pesos por retorno do usuário
+// ============================================================================
+//
+// Someone watching the TUI often knows better than the rules: "the fan
+// should have been higher here". With an adaptation section, that remark
+// nudges the weights of the rules firing at the moment:
+//
+//     [adaptation]           # optional, TUI only (config.rs)
+//     enabled = true         # false keeps the weights as they are
+//     rate = 0.5             # learning rate, above 0
+//     max_step = 0.05        # largest change of one weight per feedback,
+//                            # above 0 and at most 1
+//
+// '>' in the TUI says the output should have been higher, '<' lower ('+'
+// and '-' already zoom and weight rule groups). Each firing rule moves its
+// weight by
+//
+//     rate · degree · (centroid − output) / range     (± for higher/lower)
+//
+// held to ±max_step, and the weight stays within [0, 1]. The degree is how
+// far the rule's conditions hold, before its weight, so a rule adapted down
+// to 0 can still come back; the centroid is that of the set it concludes
+// for the primary output. A rule concluding above the output gains weight
+// when the output should have been higher and loses it otherwise, which
+// pulls the output the way the feedback asks. Repeated consistent feedback
+// drives the weights to their bounds and stops changing them.
+//
+// Every feedback is kept with its trace: the inputs, the output the rules
+// gave, every rule's strength and the weights it changed. The rule table
+// shows each rule's drift from the configured weight, and 'w' puts the
+// configured weights back.
+//
+// Adapted weights are not written into the config. They go to a file next
+// to the TUI's state file (`<state>.adapted.toml`), loaded with it:
+//
+//     [[weight]]
+//     rule = "IF temperature IS Hot AND humidity IS Medium THEN fan_speed IS High"
+//     base = 1.0             # weight in the config
+//     adapted = 0.85
+//
+//     [[feedback]]
+//     time = 1760612345.2    # wall-clock epoch seconds
+//     direction = "higher"
+//     inputs = [27.5, 64.0]
+//     output = 58.25
+//     strengths = [0.0, 0.0, 0.0, 0.0, 0.36, 0.3, 0.0, 0.36, 0.3]
+//     rules = [4, 5]         # the weights it changed, before and after
+//     before = [1.0, 1.0]
+//     after = [0.98, 0.99]
+//
+// Rules are matched by their text without the weight; an entry whose rule
+// is gone or whose configured weight changed since is left out, since the
+// config has moved on. Keeping the adapted weights is then a matter of
+// reviewing the file and copying them into the rules (`WITH 0.85`).
+
+use crate::activation::Session;
+use crate::config::{self, Section};
+use crate::{FuzzySystem, backup, budget};
+
+/// `[adaptation]` settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptationConfig {
+    pub enabled: bool,
+    pub rate: f64,
+    pub max_step: f64,
+}
+
+impl Default for AdaptationConfig {
+    fn default() -> Self {
+        AdaptationConfig {
+            enabled: true,
+            rate: 0.5,
+            max_step: 0.05,
+        }
+    }
+}
+
+/// Which way the output should have gone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Higher,
+    Lower,
+}
+
+impl Direction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::Higher => "higher",
+            Direction::Lower => "lower",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "higher" => Some(Direction::Higher),
+            "lower" => Some(Direction::Lower),
+            _ => None,
+        }
+    }
+
+    fn sign(&self) -> f64 {
+        match self {
+            Direction::Higher => 1.0,
+            Direction::Lower => -1.0,
+        }
+    }
+}
+
+/// One rule weight a feedback moved
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub rule: usize,
+    pub before: f64,
+    pub after: f64,
+}
+
+/// One feedback with what the rules did at the time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feedback {
+    /// Wall-clock epoch seconds
+    pub time: f64,
+    pub direction: Direction,
+    pub inputs: Vec<f64>,
+    /// Primary output the rules gave
+    pub output: f64,
+    /// Firing strength of every rule, in rule order
+    pub strengths: Vec<f64>,
+    pub changes: Vec<Change>,
+}
+
+/// The weights `direction` asks for at `inputs`, as changes to the
+/// system's current ones
+pub fn nudge(
+    system: &FuzzySystem,
+    config: &AdaptationConfig,
+    inputs: &[f64],
+    direction: Direction,
+) -> (f64, Vec<f64>, Vec<Change>) {
+    let mut session = Session::new(system);
+    session.infer(inputs);
+    let output = session.outputs()[0];
+    let strengths = session.strengths().to_vec();
+
+    // How far each rule's conditions hold, its own weight left out
+    let mut unweighted = system.clone();
+    for rule in &mut unweighted.rules {
+        rule.weight = 1.0;
+    }
+    let mut session = Session::new(&unweighted);
+    session.infer(inputs);
+    let degrees = session.strengths();
+
+    let primary = system.output();
+    let centroids = budget::centroids(primary);
+    let range = primary.max - primary.min;
+    let mut changes = Vec::new();
+    for (index, (rule, degree)) in system.rules.iter().zip(degrees).enumerate() {
+        let Some(set) = rule
+            .consequent(&primary.name)
+            .and_then(|set| primary.sets.iter().position(|s| s.name == set))
+        else {
+            continue;
+        };
+        if *degree <= 0.0 {
+            continue;
+        }
+        let pull = direction.sign() * config.rate * degree * (centroids[set] - output) / range;
+        let step = pull.clamp(-config.max_step, config.max_step);
+        let after = (rule.weight + step).clamp(0.0, 1.0);
+        if after != rule.weight {
+            changes.push(Change {
+                rule: index,
+                before: rule.weight,
+                after,
+            });
+        }
+    }
+    (output, strengths, changes)
+}
+
+/// The feedback given in a session, and the weights it started from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adaptation {
+    /// Configured weight of every rule by its statement, what `revert`
+    /// puts back
+    base: Vec<(String, f64)>,
+    events: Vec<Feedback>,
+}
+
+impl Adaptation {
+    pub fn new(system: &FuzzySystem) -> Self {
+        Adaptation {
+            base: (system.rules.iter())
+                .map(|rule| (rule.statement(), rule.weight))
+                .collect(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Follow a change of the rules: the configured weight of each rule
+    /// still there is kept, a new rule starts from the weight it has
+    pub fn retarget(&mut self, system: &FuzzySystem) {
+        let mut old = std::mem::take(&mut self.base);
+        self.base = (system.rules.iter())
+            .map(|rule| {
+                let statement = rule.statement();
+                match old.iter().position(|(s, _)| *s == statement) {
+                    Some(i) => old.swap_remove(i),
+                    None => (statement, rule.weight),
+                }
+            })
+            .collect();
+    }
+
+    pub fn events(&self) -> &[Feedback] {
+        &self.events
+    }
+
+    /// How far rule `index` moved from its configured weight
+    pub fn drift(&self, system: &FuzzySystem, index: usize) -> f64 {
+        match (system.rules.get(index), self.base.get(index)) {
+            (Some(rule), Some((_, base))) => rule.weight - base,
+            _ => 0.0,
+        }
+    }
+
+    /// Take `direction` as feedback at `inputs`, moving the system's
+    /// weights; refused without an enabled `[adaptation]` section
+    pub fn give(
+        &mut self,
+        system: &mut FuzzySystem,
+        inputs: &[f64],
+        direction: Direction,
+        time: f64,
+    ) -> Result<&Feedback, String> {
+        let config = match &system.adaptation {
+            None => return Err("no [adaptation] section, feedback is off".to_string()),
+            Some(config) if !config.enabled => {
+                return Err("adaptation is disabled in the config".to_string());
+            }
+            Some(config) => config.clone(),
+        };
+        let (output, strengths, changes) = nudge(system, &config, inputs, direction);
+        for change in &changes {
+            system.rules[change.rule].weight = change.after;
+        }
+        self.events.push(Feedback {
+            time,
+            direction,
+            inputs: inputs.to_vec(),
+            output,
+            strengths,
+            changes,
+        });
+        Ok(&self.events[self.events.len() - 1])
+    }
+
+    /// Put the configured weights back and forget the feedback, returning
+    /// how many rules had drifted
+    pub fn revert(&mut self, system: &mut FuzzySystem) -> usize {
+        let mut reverted = 0;
+        for (rule, (_, base)) in system.rules.iter_mut().zip(&self.base) {
+            if rule.weight != *base {
+                rule.weight = *base;
+                reverted += 1;
+            }
+        }
+        self.events.clear();
+        reverted
+    }
+
+    /// Contents of the adapted weights file
+    pub fn to_toml(&self, system: &FuzzySystem) -> String {
+        let mut out = String::from(
+            "# fuzzy_logic adapted rule weights: copy the ones to keep into the config\n",
+        );
+        for (rule, (_, base)) in system.rules.iter().zip(&self.base) {
+            if rule.weight == *base {
+                continue;
+            }
+            out.push_str(&format!(
+                "\n[[weight]]\nrule = {}\nbase = {:?}\nadapted = {:?}\n",
+                config::quote(&rule.statement()),
+                base,
+                rule.weight
+            ));
+        }
+        for event in &self.events {
+            let field =
+                |f: fn(&Change) -> f64| -> Vec<f64> { event.changes.iter().map(f).collect() };
+            out.push_str(&format!(
+                "\n[[feedback]]\ntime = {}\ndirection = {}\ninputs = {}\noutput = {:?}\n\
+                 strengths = {}\nrules = {}\nbefore = {}\nafter = {}\n",
+                event.time,
+                config::quote(event.direction.name()),
+                numbers(&event.inputs),
+                event.output,
+                numbers(&event.strengths),
+                numbers(&field(|c| c.rule as f64)),
+                numbers(&field(|c| c.before)),
+                numbers(&field(|c| c.after)),
+            ));
+        }
+        out
+    }
+
+    /// Apply the adapted weights in `text` to `system` and take its
+    /// feedback, returning the entries left out
+    pub fn parse(&mut self, text: &str, system: &mut FuzzySystem) -> Result<Vec<String>, String> {
+        let sections = config::parse_document(text)?;
+        let mut weights = Vec::new();
+        let mut skipped = Vec::new();
+        for section in sections.iter().filter(|s| s.header == "weight") {
+            let statement = section.str("rule")?;
+            let (base, adapted) = (section.num("base")?, section.num("adapted")?);
+            if !(0.0..=1.0).contains(&adapted) {
+                return Err(format!(
+                    "line {}: 'adapted' must be between 0 and 1",
+                    section.line_of("adapted")
+                ));
+            }
+            let index = system.rules.iter().position(|r| r.statement() == statement);
+            match index.filter(|i| self.base.get(*i).is_some_and(|(_, b)| *b == base)) {
+                Some(index) => weights.push((index, adapted)),
+                None => skipped.push(statement.to_string()),
+            }
+        }
+        let events = sections
+            .iter()
+            .filter(|s| s.header == "feedback")
+            .map(feedback_from_section)
+            .collect::<Result<Vec<_>, _>>()?;
+        for (index, adapted) in weights {
+            system.rules[index].weight = adapted;
+        }
+        self.events = events;
+        Ok(skipped)
+    }
+
+    /// `parse` the adapted weights file at `path`; a missing file has none
+    pub fn load(&mut self, path: &str, system: &mut FuzzySystem) -> Result<Vec<String>, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => self
+                .parse(&text, system)
+                .map_err(|e| format!("{}: {}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        }
+    }
+
+    /// Write the adapted weights file
+    pub fn save(&self, path: &str, system: &FuzzySystem) -> Result<(), String> {
+        backup::atomic_write(path, &self.to_toml(system))
+    }
+}
+
+/// Where the adapted weights go for the state file at `state_path`
+pub fn path_for(state_path: &str) -> String {
+    format!("{}.adapted.toml", state_path)
+}
+
+fn numbers(values: &[f64]) -> String {
+    let items: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn feedback_from_section(section: &Section) -> Result<Feedback, String> {
+    let direction = section.str("direction")?;
+    let direction = Direction::parse(direction).ok_or(format!(
+        "line {}: 'direction' must be \"higher\" or \"lower\"",
+        section.line_of("direction")
+    ))?;
+    let (rules, before, after) = (
+        section.nums("rules")?,
+        section.nums("before")?,
+        section.nums("after")?,
+    );
+    if rules.len() != before.len() || rules.len() != after.len() {
+        return Err(format!(
+            "line {}: 'rules', 'before' and 'after' must be as long",
+            section.line
+        ));
+    }
+    Ok(Feedback {
+        time: section.num("time")?,
+        direction,
+        inputs: section.nums("inputs")?,
+        output: section.num("output")?,
+        strengths: section.nums("strengths")?,
+        changes: (rules.iter().zip(before).zip(after))
+            .map(|((rule, before), after)| Change {
+                rule: *rule as usize,
+                before,
+                after,
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn adaptive() -> FuzzySystem {
+        let mut system = fixtures::fan();
+        system.adaptation = Some(AdaptationConfig::default());
+        system
+    }
+
+    fn output(system: &FuzzySystem, inputs: &[f64]) -> f64 {
+        Session::new(system).compute(inputs)
+    }
+
+    #[test]
+    fn consistent_feedback_converges_within_the_bounds() {
+        for direction in [Direction::Higher, Direction::Lower] {
+            let mut system = adaptive();
+            let mut adaptation = Adaptation::new(&system);
+            let point = [27.5, 64.0];
+            let start = output(&system, &point);
+            let mut last = start;
+            for _ in 0..60 {
+                let feedback = adaptation
+                    .give(&mut system, &point, direction, 0.0)
+                    .unwrap();
+                for change in &feedback.changes {
+                    assert!((change.after - change.before).abs() <= 0.05 + 1e-12);
+                    assert!((0.0..=1.0).contains(&change.after));
+                }
+                // Each feedback moves the output its way, or not at all
+                let now = output(&system, &point);
+                assert!(
+                    direction.sign() * (now - last) >= -1e-9,
+                    "{} then {}",
+                    last,
+                    now
+                );
+                last = now;
+            }
+            // Settled at the bounds: one more feedback changes nothing
+            let settled = adaptation.give(&mut system, &point, direction, 0.0);
+            assert!(settled.unwrap().changes.is_empty());
+            assert!(
+                direction.sign() * (last - start) > 5.0,
+                "{} to {}",
+                start,
+                last
+            );
+            // Hot+Medium concludes High, above the output; Mild+Medium
+            // concludes Medium, below it
+            let (hot, mild) = (adaptation.drift(&system, 7), adaptation.drift(&system, 4));
+            match direction {
+                Direction::Higher => assert!(hot == 0.0 && mild == -1.0, "{} {}", hot, mild),
+                Direction::Lower => assert!(hot == -1.0 && mild == 0.0, "{} {}", hot, mild),
+            }
+            assert_eq!(adaptation.revert(&mut system), 2);
+            assert_eq!(system, adaptive());
+        }
+    }
+
+    #[test]
+    fn rules_that_do_not_fire_keep_their_weights_and_off_means_off() {
+        let mut system = adaptive();
+        let mut adaptation = Adaptation::new(&system);
+        let feedback = adaptation
+            .give(&mut system, &[27.5, 64.0], Direction::Lower, 0.0)
+            .unwrap();
+        let firing: Vec<usize> = (feedback.strengths.iter().enumerate())
+            .filter(|(_, s)| **s > 0.0)
+            .map(|(i, _)| i)
+            .collect();
+        assert!(feedback.changes.iter().all(|c| firing.contains(&c.rule)));
+        assert_eq!(feedback.changes.len(), 2);
+
+        system.adaptation.as_mut().unwrap().enabled = false;
+        let refused = adaptation.give(&mut system, &[27.5, 64.0], Direction::Lower, 0.0);
+        assert!(refused.unwrap_err().contains("disabled"));
+        system.adaptation = None;
+        let refused = adaptation.give(&mut system, &[27.5, 64.0], Direction::Lower, 0.0);
+        assert!(refused.unwrap_err().contains("no [adaptation] section"));
+        assert_eq!(adaptation.events().len(), 1);
+    }
+
+    #[test]
+    fn adapted_weights_round_trip_apart_from_the_config() {
+        let mut system = adaptive();
+        let mut adaptation = Adaptation::new(&system);
+        for direction in [Direction::Higher, Direction::Higher] {
+            adaptation
+                .give(&mut system, &[27.5, 64.0], direction, 1760612345.5)
+                .unwrap();
+        }
+        let text = adaptation.to_toml(&system);
+        assert_eq!(text.matches("[[weight]]").count(), 2);
+        assert!(text.contains(
+            "rule = \"IF temperature IS Mild AND humidity IS Medium THEN fan_speed IS Medium\""
+        ));
+
+        let mut fresh = adaptive();
+        let mut loaded = Adaptation::new(&fresh);
+        assert!(loaded.parse(&text, &mut fresh).unwrap().is_empty());
+        assert_eq!((&fresh, &loaded), (&system, &adaptation));
+
+        // A rule whose configured weight changed since is left out
+        let mut edited = adaptive();
+        edited.rules[4].weight = 0.5;
+        let mut loaded = Adaptation::new(&edited);
+        let skipped = loaded.parse(&text, &mut edited).unwrap();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(edited.rules[4].weight, 0.5);
+        assert_ne!(edited.rules[5].weight, 1.0);
+    }
+}
@@ -0,0 +1,172 @@
+// ============================================================================
+// INPUT ADAPTERS - Conversão de entradas
+// ============================================================================
+//
+// Raw readings go through the input's adapter before fuzzification. An
+// adapter may read the raw values of other inputs: a `dewpoint` input
+// receives a dew point (°C) and is converted to relative humidity with the
+// Magnus formula using the raw value of its temperature input.
+//
+//     [[input]]
+//     name = "humidity"
+//     kind = "dewpoint"
+//     temperature = "temperature"   # input holding the air temperature
+//
+// Everything downstream of the adapters (rules, diff, surface) works on the
+// converted values.
+
+use crate::FuzzyVariable;
+
+/// Magnus coefficients (Alduchov & Eskridge, 1996), valid for -45..60 °C
+const MAGNUS_A: f64 = 17.625;
+const MAGNUS_B: f64 = 243.04;
+
+/// How the raw reading of an input is turned into its value
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum InputKind {
+    #[default]
+    Direct,
+    /// Dew point in °C, converted to relative humidity in %
+    DewPoint { temperature: String },
+}
+
+impl InputKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputKind::Direct => "direct",
+            InputKind::DewPoint { .. } => "dewpoint",
+        }
+    }
+}
+
+/// Relative humidity (%) for an air temperature and dew point, both in °C
+pub fn relative_humidity(temperature: f64, dew_point: f64) -> f64 {
+    let gamma = |t: f64| MAGNUS_A * t / (MAGNUS_B + t);
+    100.0 * (gamma(dew_point) - gamma(temperature)).exp()
+}
+
+/// Dew point (°C) for an air temperature (°C) and relative humidity (%)
+pub fn dew_point(temperature: f64, humidity: f64) -> f64 {
+    let gamma =
+        (humidity.max(1e-6) / 100.0).ln() + MAGNUS_A * temperature / (MAGNUS_B + temperature);
+    MAGNUS_B * gamma / (MAGNUS_A - gamma)
+}
+
+/// Raw readings that `convert` turns into `values` (declaration order),
+/// e.g. the dew point giving a relative humidity at the input's temperature
+pub fn raw_readings(inputs: &[FuzzyVariable], values: &[f64]) -> Vec<f64> {
+    inputs
+        .iter()
+        .zip(values)
+        .map(|(var, value)| match &var.kind {
+            InputKind::Direct => *value,
+            InputKind::DewPoint { temperature } => {
+                let air = inputs
+                    .iter()
+                    .position(|v| v.name == *temperature)
+                    .and_then(|i| values.get(i))
+                    .copied()
+                    .unwrap_or(*value);
+                dew_point(air, *value)
+            }
+        })
+        .collect()
+}
+
+/// Convert one raw reading per input (declaration order) into the values
+/// the inputs are fuzzified on, with a warning for every reading that had
+/// to be corrected
+pub fn convert(inputs: &[FuzzyVariable], raw: &[f64]) -> (Vec<f64>, Vec<String>) {
+    let mut warnings = Vec::new();
+    let values = inputs
+        .iter()
+        .zip(raw)
+        .map(|(var, value)| match &var.kind {
+            InputKind::Direct => *value,
+            InputKind::DewPoint { temperature } => {
+                let air = inputs
+                    .iter()
+                    .position(|v| v.name == *temperature)
+                    .and_then(|i| raw.get(i))
+                    .copied()
+                    .unwrap_or(*value);
+                let mut dew_point = *value;
+                if dew_point > air {
+                    warnings.push(format!(
+                        "{}: dew point {:.1}°C above the temperature {:.1}°C, clamped",
+                        var.name, dew_point, air
+                    ));
+                    dew_point = air;
+                }
+                relative_humidity(air, dew_point).clamp(var.min, var.max)
+            }
+        })
+        .collect();
+    (values, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FuzzySystem, config};
+
+    /// The fan controller with its humidity input reading a dew point
+    fn dewpoint_system() -> FuzzySystem {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        config::parse_system(&fan.replace(
+            "name = \"humidity\"\n",
+            "name = \"humidity\"\nkind = \"dewpoint\"\ntemperature = \"temperature\"\n",
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn magnus_matches_reference_pairs_within_one_percent() {
+        // (temperature, dew point, RH) from saturation vapour pressure
+        // tables over water
+        let pairs = [
+            (20.0, 10.0, 52.5),
+            (25.0, 20.0, 73.8),
+            (30.0, 15.0, 40.2),
+            (0.0, -10.0, 46.9),
+            (35.0, 25.0, 56.3),
+            (10.0, 10.0, 100.0),
+        ];
+        for (temperature, dew, rh) in pairs {
+            let converted = relative_humidity(temperature, dew);
+            assert!(
+                (converted - rh).abs() < 1.0,
+                "{}°C / {}°C gave {:.2}%, expected {}%",
+                temperature,
+                dew,
+                converted,
+                rh
+            );
+            assert!((dew_point(temperature, converted) - dew).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn dew_points_are_converted_using_the_raw_temperature() {
+        let system = dewpoint_system();
+        let (values, warnings) = convert(&system.inputs, &[25.0, 20.0]);
+        assert_eq!(values[0], 25.0);
+        assert!((values[1] - 73.8).abs() < 1.0);
+        assert!(warnings.is_empty());
+
+        let raw = raw_readings(&system.inputs, &values);
+        assert_eq!(raw[0], 25.0);
+        assert!((raw[1] - 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_dew_point_above_the_temperature_is_clamped_with_a_warning() {
+        let system = dewpoint_system();
+        let (values, warnings) = convert(&system.inputs, &[18.0, 21.0]);
+        assert_eq!(values[1], 100.0);
+        assert_eq!(
+            warnings,
+            ["humidity: dew point 21.0°C above the temperature 18.0°C, clamped"]
+        );
+    }
+}
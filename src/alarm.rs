@@ -0,0 +1,848 @@
+// ============================================================================
+// COMPUTED ALARMS - Alarmes calculados
+// ============================================================================
+//
+// An alarm raised by a condition written in the config, over what one
+// computation saw and concluded:
+//
+//     [[alarm]]              # optional, any number
+//     name = "stuck high"
+//     when = "holds_for(fan_speed > 80 and humidity > 70, 10m)"
+//
+// The condition is an expression of
+//
+//     numbers                42, 2.5, and durations 30s, 10m, 2h (seconds)
+//     names                  an input or output, its value this computation
+//     membership('v', 'S')   membership of input or output v in its set S
+//     strength(n)            firing strength of rule n, counting from 1
+//     holds_for(e, d)        e has been true, without a break, for d seconds
+//     rising(e)              e is above its value at the previous computation
+//     abs(e), min(a, b), max(a, b)
+//
+// joined by + - * /, the comparisons < <= > >= == != and `and`, `or`,
+// `not` (in any case), with parentheses. A comparison is 1 when true and 0
+// when false; anything not 0 counts as true. Strings take single or double
+// quotes, single ones reading better inside the config's double-quoted
+// value.
+//
+// Conditions are parsed when the config loads, and names resolved against
+// its inputs, outputs, sets and rules; a mistake is reported with its
+// column in `when`. A condition is at most MAX_LENGTH characters, nested
+// (parentheses, calls, `not` and minus signs) at most MAX_DEPTH deep, so
+// a malformed one is an error rather than a stack overflow (fuzz.rs). Each computation evaluates every condition at the time
+// the caller passes in, the daemon's clock or the TUI's, and an alarm is
+// raised when its condition turns true and cleared when it turns false.
+// Every part of a condition is evaluated every time, `and` and `or`
+// included, so `holds_for` and `rising` never miss a computation.
+//
+// The daemon logs each alarm raised and cleared, and its `alarms` command
+// lists those raised now; the TUI posts a warning when one is
+// raised and a note when it clears.
+//
+// The config's objective (objective.rs) is an expression of the same kind,
+// read as a number rather than as true or false.
+
+use crate::FuzzySystem;
+
+/// Longest condition, in characters
+pub const MAX_LENGTH: usize = 1000;
+/// Deepest nesting of parentheses, calls, `not` and minus signs
+pub const MAX_DEPTH: usize = 32;
+
+/// `[[alarm]]` settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlarmConfig {
+    pub name: String,
+    pub when: Expression,
+}
+
+/// A value an expression reads from a computation
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Variable {
+    Input(usize),
+    Output(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Binary {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f64),
+    Value(Variable),
+    /// A variable and the index of one of its sets
+    Membership(Variable, usize),
+    /// Rule index, from 0
+    Strength(usize),
+    Negate(Box<Node>),
+    Not(Box<Node>),
+    Abs(Box<Node>),
+    Binary(Binary, Box<Node>, Box<Node>),
+    /// Expression, seconds, and the memory slot keeping since when it holds
+    HoldsFor(Box<Node>, f64, usize),
+    /// Expression and the memory slot keeping its previous value
+    Rising(Box<Node>, usize),
+}
+
+/// A parsed and resolved alarm condition
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    source: String,
+    root: Node,
+    /// Memory slots the temporal operators need
+    slots: usize,
+}
+
+/// What one computation saw and concluded
+pub struct Sample<'a> {
+    pub system: &'a FuzzySystem,
+    pub inputs: &'a [f64],
+    pub outputs: &'a [f64],
+    pub strengths: &'a [f64],
+}
+
+impl Expression {
+    /// Parse `source` and resolve its names against `system`; errors start
+    /// with the column they were found at
+    pub fn parse(source: &str, system: &FuzzySystem) -> Result<Self, String> {
+        let length = source.chars().count();
+        if length > MAX_LENGTH {
+            return Err(format!(
+                "column {}: longer than the {} characters a condition may have",
+                MAX_LENGTH + 1,
+                MAX_LENGTH
+            ));
+        }
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            at: 0,
+            depth: 0,
+            system,
+            slots: 0,
+            end: source.chars().count() + 1,
+        };
+        let root = parser.or()?;
+        if let Some((token, column)) = parser.tokens.get(parser.at) {
+            return Err(format!(
+                "column {}: unexpected {}",
+                column,
+                token.describe()
+            ));
+        }
+        Ok(Expression {
+            source: source.to_string(),
+            root,
+            slots: parser.slots,
+        })
+    }
+
+    /// The text it was parsed from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Its value over `sample` at `now`; `memory` keeps what the temporal
+    /// operators remember between computations, empty to start with
+    pub fn evaluate(&self, sample: &Sample, now: f64, memory: &mut Vec<Option<f64>>) -> f64 {
+        memory.resize(self.slots, None);
+        evaluate(&self.root, sample, now, memory)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Text(String),
+    Symbol(&'static str),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Number(n) => format!("number {}", n),
+            Token::Name(name) => format!("'{}'", name),
+            Token::Text(text) => format!("string '{}'", text),
+            Token::Symbol(symbol) => format!("'{}'", symbol),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 14] = [
+    "<=", ">=", "==", "!=", "<", ">", "+", "-", "*", "/", "(", ")", ",", "!",
+];
+
+/// Tokens of `source`, each with its column from 1
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let mut number: f64 = text
+                .parse()
+                .map_err(|_| format!("column {}: bad number '{}'", column, text))?;
+            // A duration suffix, when nothing else of a name follows
+            let scale = match chars.get(i) {
+                Some('s') => Some(1.0),
+                Some('m') => Some(60.0),
+                Some('h') => Some(3600.0),
+                _ => None,
+            };
+            if let Some(scale) = scale
+                && !chars
+                    .get(i + 1)
+                    .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+            {
+                number *= scale;
+                i += 1;
+            }
+            if chars
+                .get(i)
+                .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+            {
+                return Err(format!(
+                    "column {}: unknown unit after '{}'; durations take s, m or h",
+                    i + 1,
+                    text
+                ));
+            }
+            tokens.push((Token::Number(number), column));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Name(chars[start..i].iter().collect()), column));
+        } else if c == '\'' || c == '"' {
+            let end = chars[i + 1..]
+                .iter()
+                .position(|d| *d == c)
+                .ok_or_else(|| format!("column {}: unterminated string", column))?;
+            tokens.push((
+                Token::Text(chars[i + 1..i + 1 + end].iter().collect()),
+                column,
+            ));
+            i += end + 2;
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = SYMBOLS
+                .into_iter()
+                .find(|s| rest.starts_with(s))
+                .ok_or_else(|| format!("column {}: unexpected '{}'", column, c))?;
+            tokens.push((Token::Symbol(symbol), column));
+            i += symbol.len();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, lowest precedence first:
+/// or, and, not, comparison, sum, product, unary, atom
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    at: usize,
+    /// Nesting around the current token
+    depth: usize,
+    system: &'a FuzzySystem,
+    slots: usize,
+    /// Column just past the end, for errors there
+    end: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at).map(|(t, _)| t)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens.get(self.at).map_or(self.end, |(_, c)| *c)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Name(name)) if name.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat(&mut self, symbol: &'static str) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), String> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", symbol)))
+        }
+    }
+
+    /// "column N: message", with what was found instead
+    fn error(&self, message: &str) -> String {
+        match self.peek() {
+            Some(token) => format!(
+                "column {}: {}, found {}",
+                self.column(),
+                message,
+                token.describe()
+            ),
+            None => format!("column {}: {} at the end", self.column(), message),
+        }
+    }
+
+    /// `parse` one level deeper, just past the token opening the level;
+    /// refused past MAX_DEPTH
+    fn nested(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<Node, String>,
+    ) -> Result<Node, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!(
+                "column {}: nested more than {} deep",
+                self.tokens[self.at - 1].1,
+                MAX_DEPTH
+            ));
+        }
+        self.depth += 1;
+        let node = parse(self);
+        self.depth -= 1;
+        node
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        let mut left = self.and()?;
+        while self.is_keyword("or") {
+            self.at += 1;
+            left = Node::Binary(Binary::Or, Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut left = self.not()?;
+        while self.is_keyword("and") {
+            self.at += 1;
+            left = Node::Binary(Binary::And, Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Node, String> {
+        if self.is_keyword("not") {
+            self.at += 1;
+            return Ok(Node::Not(Box::new(self.nested(Self::not)?)));
+        }
+        if self.eat("!") {
+            return Ok(Node::Not(Box::new(self.nested(Self::not)?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Node, String> {
+        let left = self.sum()?;
+        let op = match self.peek() {
+            Some(Token::Symbol("<")) => Binary::Less,
+            Some(Token::Symbol("<=")) => Binary::LessEq,
+            Some(Token::Symbol(">")) => Binary::Greater,
+            Some(Token::Symbol(">=")) => Binary::GreaterEq,
+            Some(Token::Symbol("==")) => Binary::Equal,
+            Some(Token::Symbol("!=")) => Binary::NotEqual,
+            _ => return Ok(left),
+        };
+        self.at += 1;
+        Ok(Node::Binary(op, Box::new(left), Box::new(self.sum()?)))
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        let mut left = self.product()?;
+        loop {
+            let op = if self.eat("+") {
+                Binary::Add
+            } else if self.eat("-") {
+                Binary::Sub
+            } else {
+                return Ok(left);
+            };
+            left = Node::Binary(op, Box::new(left), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Node, String> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat("*") {
+                Binary::Mul
+            } else if self.eat("/") {
+                Binary::Div
+            } else {
+                return Ok(left);
+            };
+            left = Node::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.eat("-") {
+            return Ok(Node::Negate(Box::new(self.nested(Self::unary)?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let column = self.column();
+        match self.peek().cloned() {
+            Some(Token::Number(n)) => {
+                self.at += 1;
+                Ok(Node::Number(n))
+            }
+            Some(Token::Symbol("(")) => {
+                self.at += 1;
+                let inner = self.nested(Self::or)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            Some(Token::Name(name)) => {
+                self.at += 1;
+                if self.eat("(") {
+                    let node = self.nested(|parser| parser.call(&name, column))?;
+                    self.expect(")")?;
+                    Ok(node)
+                } else {
+                    self.variable(&name, column).map(Node::Value)
+                }
+            }
+            _ => Err(self.error("expected a number, a name or '('")),
+        }
+    }
+
+    fn variable(&self, name: &str, column: usize) -> Result<Variable, String> {
+        let system = self.system;
+        if let Some(i) = system.inputs.iter().position(|v| v.name == name) {
+            Ok(Variable::Input(i))
+        } else if let Some(i) = system.outputs.iter().position(|v| v.name == name) {
+            Ok(Variable::Output(i))
+        } else {
+            Err(format!("column {}: no input or output '{}'", column, name))
+        }
+    }
+
+    /// Arguments of function `name`, the opening parenthesis eaten
+    fn call(&mut self, name: &str, column: usize) -> Result<Node, String> {
+        match name {
+            "membership" => {
+                let (var_column, var) = self.text()?;
+                self.expect(",")?;
+                let (set_column, set) = self.text()?;
+                let variable = self.variable(&var, var_column)?;
+                let sets = match variable {
+                    Variable::Input(i) => &self.system.inputs[i].sets,
+                    Variable::Output(i) => &self.system.outputs[i].sets,
+                };
+                let index = sets.iter().position(|s| s.name == set).ok_or_else(|| {
+                    format!("column {}: '{}' has no set '{}'", set_column, var, set)
+                })?;
+                Ok(Node::Membership(variable, index))
+            }
+            "strength" => {
+                let column = self.column();
+                let rules = self.system.rules.len();
+                match self.peek() {
+                    Some(Token::Number(n))
+                        if n.fract() == 0.0 && *n >= 1.0 && *n <= rules as f64 =>
+                    {
+                        let rule = *n as usize - 1;
+                        self.at += 1;
+                        Ok(Node::Strength(rule))
+                    }
+                    Some(Token::Number(n)) => Err(format!(
+                        "column {}: no rule {}; rules count from 1 to {}",
+                        column, n, rules
+                    )),
+                    _ => Err(self.error("expected a rule number")),
+                }
+            }
+            "holds_for" => {
+                let inner = self.or()?;
+                self.expect(",")?;
+                let column = self.column();
+                let secs = match self.peek() {
+                    Some(Token::Number(n)) if *n > 0.0 => *n,
+                    Some(Token::Number(_)) => {
+                        return Err(format!("column {}: the duration must be above 0", column));
+                    }
+                    _ => return Err(self.error("expected a duration like 30s or 10m")),
+                };
+                self.at += 1;
+                self.slots += 1;
+                Ok(Node::HoldsFor(Box::new(inner), secs, self.slots - 1))
+            }
+            "rising" => {
+                let inner = self.or()?;
+                self.slots += 1;
+                Ok(Node::Rising(Box::new(inner), self.slots - 1))
+            }
+            "abs" => Ok(Node::Abs(Box::new(self.or()?))),
+            "min" | "max" => {
+                let a = self.or()?;
+                self.expect(",")?;
+                let b = self.or()?;
+                let op = if name == "min" {
+                    Binary::Min
+                } else {
+                    Binary::Max
+                };
+                Ok(Node::Binary(op, Box::new(a), Box::new(b)))
+            }
+            _ => Err(format!(
+                "column {}: unknown function '{}'; there are membership, strength, holds_for, rising, abs, min and max",
+                column, name
+            )),
+        }
+    }
+
+    /// A quoted string and its column
+    fn text(&mut self) -> Result<(usize, String), String> {
+        match self.peek().cloned() {
+            Some(Token::Text(text)) => {
+                let column = self.column();
+                self.at += 1;
+                Ok((column, text))
+            }
+            _ => Err(self.error("expected a quoted name")),
+        }
+    }
+}
+
+fn truth(value: f64) -> bool {
+    value != 0.0 && !value.is_nan()
+}
+
+fn flag(value: bool) -> f64 {
+    if value { 1.0 } else { 0.0 }
+}
+
+/// `node` over `sample` at `now`, updating the temporal operators' `memory`
+fn evaluate(node: &Node, sample: &Sample, now: f64, memory: &mut [Option<f64>]) -> f64 {
+    let value = |variable: &Variable| match *variable {
+        Variable::Input(i) => sample.inputs.get(i).copied().unwrap_or(f64::NAN),
+        Variable::Output(i) => sample.outputs.get(i).copied().unwrap_or(f64::NAN),
+    };
+    match node {
+        Node::Number(n) => *n,
+        Node::Value(variable) => value(variable),
+        Node::Membership(variable, set) => {
+            let var = match *variable {
+                Variable::Input(i) => sample.system.inputs.get(i),
+                Variable::Output(i) => sample.system.outputs.get(i),
+            };
+            let x = value(variable);
+            match var.and_then(|v| v.sets.get(*set)) {
+                Some(set) if x.is_finite() => set.function.evaluate(x),
+                _ => 0.0,
+            }
+        }
+        Node::Strength(rule) => sample.strengths.get(*rule).copied().unwrap_or(0.0),
+        Node::Negate(inner) => -evaluate(inner, sample, now, memory),
+        Node::Not(inner) => flag(!truth(evaluate(inner, sample, now, memory))),
+        Node::Abs(inner) => evaluate(inner, sample, now, memory).abs(),
+        Node::Binary(op, left, right) => {
+            // Both sides always, so the temporal operators see every
+            // computation
+            let a = evaluate(left, sample, now, memory);
+            let b = evaluate(right, sample, now, memory);
+            match op {
+                Binary::Add => a + b,
+                Binary::Sub => a - b,
+                Binary::Mul => a * b,
+                Binary::Div => a / b,
+                Binary::Less => flag(a < b),
+                Binary::LessEq => flag(a <= b),
+                Binary::Greater => flag(a > b),
+                Binary::GreaterEq => flag(a >= b),
+                Binary::Equal => flag(a == b),
+                Binary::NotEqual => flag(a != b),
+                Binary::And => flag(truth(a) && truth(b)),
+                Binary::Or => flag(truth(a) || truth(b)),
+                Binary::Min => a.min(b),
+                Binary::Max => a.max(b),
+            }
+        }
+        Node::HoldsFor(inner, secs, slot) => {
+            if truth(evaluate(inner, sample, now, memory)) {
+                let since = *memory[*slot].get_or_insert(now);
+                flag(now - since >= *secs)
+            } else {
+                memory[*slot] = None;
+                0.0
+            }
+        }
+        Node::Rising(inner, slot) => {
+            let value = evaluate(inner, sample, now, memory);
+            let rising = memory[*slot].is_some_and(|previous| value > previous);
+            memory[*slot] = Some(value).filter(|v| !v.is_nan());
+            flag(rising)
+        }
+    }
+}
+
+/// An alarm raised or cleared by a computation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub name: String,
+    pub raised: bool,
+}
+
+/// The state of every configured alarm
+#[derive(Debug, Clone)]
+pub struct Alarms {
+    configs: Vec<AlarmConfig>,
+    memory: Vec<Vec<Option<f64>>>,
+    active: Vec<bool>,
+}
+
+impl Alarms {
+    pub fn new(configs: &[AlarmConfig]) -> Self {
+        Alarms {
+            configs: configs.to_vec(),
+            memory: configs.iter().map(|c| vec![None; c.when.slots]).collect(),
+            active: vec![false; configs.len()],
+        }
+    }
+
+    /// Evaluate every condition over `sample` at `now`; the alarms this
+    /// raised or cleared, in config order
+    pub fn update(&mut self, sample: &Sample, now: f64) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for ((config, memory), active) in self
+            .configs
+            .iter()
+            .zip(&mut self.memory)
+            .zip(&mut self.active)
+        {
+            let raised = truth(evaluate(&config.when.root, sample, now, memory));
+            if raised != *active {
+                *active = raised;
+                changes.push(Change {
+                    name: config.name.clone(),
+                    raised,
+                });
+            }
+        }
+        changes
+    }
+
+    /// Names of the alarms raised now, in config order
+    pub fn active(&self) -> impl Iterator<Item = &str> {
+        self.configs
+            .iter()
+            .zip(&self.active)
+            .filter(|(_, active)| **active)
+            .map(|(config, _)| config.name.as_str())
+    }
+
+    /// The condition of alarm `name`
+    pub fn condition(&self, name: &str) -> Option<&str> {
+        self.configs
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| c.when.source())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn alarms(when: &str) -> (FuzzySystem, Alarms) {
+        let system = fixtures::fan();
+        let config = AlarmConfig {
+            name: "watch".to_string(),
+            when: Expression::parse(when, &system).unwrap(),
+        };
+        let alarms = Alarms::new(&[config]);
+        (system, alarms)
+    }
+
+    /// Feed `(time, temperature, humidity, fan_speed)` steps; the times the
+    /// alarm was raised or cleared
+    fn script(when: &str, steps: &[(f64, f64, f64, f64)]) -> Vec<(f64, bool)> {
+        let (system, mut alarms) = alarms(when);
+        let mut changes = Vec::new();
+        for &(time, temperature, humidity, fan) in steps {
+            let sample = Sample {
+                system: &system,
+                inputs: &[temperature, humidity],
+                outputs: &[fan],
+                strengths: &[0.0; 9],
+            };
+            for change in alarms.update(&sample, time) {
+                changes.push((time, change.raised));
+            }
+        }
+        changes
+    }
+
+    #[test]
+    fn holds_for_needs_the_condition_true_without_a_break() {
+        let when = "holds_for(fan_speed > 80 AND humidity > 70, 10m)";
+        // True from t = 0; a break at 300 starts the wait over
+        let steps: Vec<(f64, f64, f64, f64)> = (0..=30)
+            .map(|i| {
+                let t = i as f64 * 60.0;
+                let fan = if t == 300.0 { 50.0 } else { 90.0 };
+                (t, 25.0, 80.0, fan)
+            })
+            .collect();
+        assert_eq!(script(when, &steps), [(960.0, true)]);
+
+        // Cleared the computation it stops holding
+        let steps = [
+            (0.0, 25.0, 80.0, 90.0),
+            (600.0, 25.0, 80.0, 90.0),
+            (601.0, 25.0, 60.0, 90.0),
+        ];
+        assert_eq!(script(when, &steps), [(600.0, true), (601.0, false)]);
+    }
+
+    #[test]
+    fn rising_compares_with_the_previous_computation() {
+        let fans = [10.0, 20.0, 20.0, 15.0, 30.0, 40.0];
+        let steps: Vec<(f64, f64, f64, f64)> = fans
+            .iter()
+            .enumerate()
+            .map(|(i, fan)| (i as f64, 25.0, 50.0, *fan))
+            .collect();
+        assert_eq!(
+            script("rising(fan_speed)", &steps),
+            [(1.0, true), (2.0, false), (4.0, true)]
+        );
+
+        // Evaluated even where `and` already knows its answer
+        let when = "temperature > 30 and rising(fan_speed)";
+        let steps = [
+            (0.0, 20.0, 50.0, 10.0),
+            (1.0, 20.0, 50.0, 20.0),
+            (2.0, 35.0, 50.0, 30.0),
+        ];
+        assert_eq!(script(when, &steps), [(2.0, true)]);
+    }
+
+    #[test]
+    fn memberships_and_strengths_read_the_computation() {
+        let (system, mut alarms) =
+            alarms("membership('temperature', 'Hot') >= 0.5 or strength(2) > 0.9");
+        let mut update = |inputs: [f64; 2], strengths: [f64; 9], now: f64| {
+            let sample = Sample {
+                system: &system,
+                inputs: &inputs,
+                outputs: &[30.0],
+                strengths: &strengths,
+            };
+            alarms.update(&sample, now)
+        };
+        let mut strong = [0.0; 9];
+        strong[1] = 1.0;
+        assert!(update([10.0, 50.0], [0.0; 9], 0.0).is_empty());
+        assert_eq!(
+            update([10.0, 50.0], strong, 1.0),
+            [Change {
+                name: "watch".to_string(),
+                raised: true
+            }]
+        );
+        // Hot keeps it raised once rule 2 lets go
+        assert!(update([45.0, 50.0], [0.0; 9], 2.0).is_empty());
+        assert!(!update([10.0, 50.0], [0.0; 9], 3.0)[0].raised);
+    }
+
+    #[test]
+    fn parse_errors_give_their_column() {
+        let system = fixtures::fan();
+        let error = |when: &str| Expression::parse(when, &system).unwrap_err();
+        assert_eq!(error("fan > 80"), "column 1: no input or output 'fan'");
+        assert_eq!(
+            error("membership('temperature', 'Hott') > 0"),
+            "column 27: 'temperature' has no set 'Hott'"
+        );
+        assert_eq!(
+            error("strength(12) > 0"),
+            "column 10: no rule 12; rules count from 1 to 9"
+        );
+        assert_eq!(
+            error("holds_for(fan_speed > 80, 10x)"),
+            "column 29: unknown unit after '10'; durations take s, m or h"
+        );
+        assert_eq!(
+            error("(fan_speed > 80"),
+            "column 16: expected ')' at the end"
+        );
+        assert_eq!(
+            error("fan_speed > > 80"),
+            "column 13: expected a number, a name or '(', found '>'"
+        );
+        assert_eq!(error("fan_speed 80"), "column 11: unexpected number 80");
+        assert!(error("sqrt(4)").starts_with("column 1: unknown function 'sqrt'"));
+        assert_eq!(error("'open"), "column 1: unterminated string");
+    }
+
+    #[test]
+    fn long_or_deeply_nested_conditions_are_refused() {
+        let system = fixtures::fan();
+        let parse = |when: &str| Expression::parse(when, &system);
+        let nested = |open: &str, depth: usize, close: &str| {
+            format!("{}1{}", open.repeat(depth), close.repeat(depth))
+        };
+        for (open, close) in [
+            ("(", ")"),
+            ("abs(", ")"),
+            ("-", ""),
+            ("not ", ""),
+            ("!", ""),
+        ] {
+            assert!(parse(&nested(open, MAX_DEPTH, close)).is_ok(), "{}", open);
+            let error = parse(&nested(open, MAX_DEPTH + 1, close)).unwrap_err();
+            assert!(error.ends_with("nested more than 32 deep"), "{}", error);
+        }
+        assert_eq!(
+            parse(&nested("(", MAX_DEPTH + 1, ")")).unwrap_err(),
+            "column 33: nested more than 32 deep"
+        );
+
+        // A long chain is not nesting, up to the length limit
+        let chain = |terms: usize| vec!["fan_speed"; terms].join("+");
+        assert!(parse(&chain(MAX_LENGTH / 10)).is_ok());
+        assert_eq!(
+            parse(&chain(MAX_LENGTH / 10 + 1)).unwrap_err(),
+            "column 1001: longer than the 1000 characters a condition may have"
+        );
+    }
+}
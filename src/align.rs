@@ -0,0 +1,280 @@
+// ============================================================================
+// INPUT ALIGNMENT - Alinhamento das entradas
+// ============================================================================
+//
+// Sensors do not report at the same delay: a humidity probe whose readings
+// reach the daemon four seconds after they were taken, next to a
+// temperature sensor that reports at once, has the controller pair the
+// temperature of now with the humidity of four seconds ago. During a fast
+// transient that is a combination that never happened. An input may
+// declare how late its readings arrive:
+//
+//     [[input]]
+//     name = "humidity"
+//     latency_secs = 4.0     # optional, daemon only; not with window_secs
+//
+//     [alignment]            # optional, with any latency_secs
+//     max_wait_secs = 5.0    # how long a reading waits for the others
+//
+// A reading arriving at clock time `now` was taken at `now - latency_secs`,
+// its instant. The daemon files every reading in an `Aligner`, which holds
+// each instant back until every input has reported a reading taken at or
+// after it, and then releases it with each input's last reading taken at
+// or before it: values from the same moment. Instants come out in the
+// order they were taken, whatever order their readings arrived in, and a
+// reading taken before an instant already released is too late to change
+// it: it is counted, and dropped once a newer one stands for its input.
+//
+// An input that stops reporting would hold every instant back, so one
+// waits at most `max_wait_secs` after its reading arrived. It is then
+// released anyway with the freshest reading of each input, and marked as
+// timed out: the daemon's log line says "unaligned", its audit record has
+// cause `align_timeout`, and the `stats` command counts them. An input
+// with no reading at all leaves its current value alone. The wait must
+// cover the spread of the latencies, or every instant would time out, so a
+// shorter one is refused at load.
+//
+// Every computation's audit record carries the instant as `aligned_at`,
+// and the state lines how long before their publication it was taken, so
+// an attached TUI plots the history at the corrected times. Without a
+// `latency_secs` anywhere there is no aligner and readings are taken up
+// as they arrive, as before. Like the windows (window.rs), the aligner
+// takes the time from its caller instead of reading a clock.
+
+use std::collections::VecDeque;
+
+/// How long a reading waits for the other inputs by default
+pub const DEFAULT_MAX_WAIT_SECS: f64 = 5.0;
+
+/// `[alignment]` settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlignConfig {
+    pub max_wait_secs: f64,
+}
+
+impl Default for AlignConfig {
+    fn default() -> Self {
+        AlignConfig {
+            max_wait_secs: DEFAULT_MAX_WAIT_SECS,
+        }
+    }
+}
+
+/// An instant released by the aligner
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aligned {
+    /// When the readings were taken, on the caller's clock
+    pub at: f64,
+    /// Each input's reading, in declaration order; none for an input that
+    /// has not reported yet
+    pub values: Vec<Option<f64>>,
+    /// Released when the wait ran out, with the freshest readings
+    pub timed_out: bool,
+}
+
+/// Holds readings back until every input has one from the same instant
+#[derive(Debug, Clone)]
+pub struct Aligner {
+    latencies: Vec<f64>,
+    max_wait_secs: f64,
+    /// Each input's readings as (instant, value), oldest first
+    readings: Vec<VecDeque<(f64, f64)>>,
+    /// Instants waiting for the other inputs, oldest first, with the clock
+    /// time their reading arrived
+    waiting: VecDeque<(f64, f64)>,
+    /// The last instant released
+    released: Option<f64>,
+    /// Instants released with every input's reading from the instant
+    pub aligned: u64,
+    pub timed_out: u64,
+    /// Readings taken before an instant already released
+    pub late: u64,
+}
+
+impl Aligner {
+    /// An aligner of inputs reporting `latencies` seconds late, in
+    /// declaration order
+    pub fn new(latencies: Vec<f64>, config: &AlignConfig) -> Self {
+        Aligner {
+            readings: vec![VecDeque::new(); latencies.len()],
+            latencies,
+            max_wait_secs: config.max_wait_secs,
+            waiting: VecDeque::new(),
+            released: None,
+            aligned: 0,
+            timed_out: 0,
+            late: 0,
+        }
+    }
+
+    /// File `value` of input `index`, arriving at clock time `now`, and
+    /// release the instants it completes
+    pub fn push(&mut self, index: usize, now: f64, value: f64) -> Vec<Aligned> {
+        let at = now - self.latencies[index];
+        let readings = &mut self.readings[index];
+        let slot = readings.partition_point(|(t, _)| *t <= at);
+        readings.insert(slot, (at, value));
+        if self.released.is_some_and(|released| at <= released) {
+            self.late += 1;
+        } else {
+            let slot = self.waiting.partition_point(|(t, _)| *t < at);
+            if self.waiting.get(slot).is_none_or(|(t, _)| *t != at) {
+                self.waiting.insert(slot, (at, now));
+            }
+        }
+        self.release(now)
+    }
+
+    /// Release the instants that waited `max_wait_secs` by clock time
+    /// `now`, and those complete behind them
+    pub fn poll(&mut self, now: f64) -> Vec<Aligned> {
+        self.release(now)
+    }
+
+    /// Instants waiting to be released
+    pub fn pending(&self) -> usize {
+        self.waiting.len()
+    }
+
+    fn release(&mut self, now: f64) -> Vec<Aligned> {
+        let mut released = Vec::new();
+        while let Some(&(at, arrived)) = self.waiting.front() {
+            let complete = (self.readings.iter())
+                .all(|readings| readings.back().is_some_and(|(t, _)| *t >= at));
+            let timed_out = !complete && now - arrived >= self.max_wait_secs;
+            if !complete && !timed_out {
+                break;
+            }
+            self.waiting.pop_front();
+            let values = match timed_out {
+                true => self.values_at(f64::INFINITY),
+                false => self.values_at(at),
+            };
+            match timed_out {
+                true => self.timed_out += 1,
+                false => self.aligned += 1,
+            }
+            self.released = Some(at);
+            self.forget(at);
+            released.push(Aligned {
+                at,
+                values,
+                timed_out,
+            });
+        }
+        released
+    }
+
+    /// Each input's last reading taken at or before `at`
+    fn values_at(&self, at: f64) -> Vec<Option<f64>> {
+        (self.readings.iter())
+            .map(|readings| {
+                let taken = readings.partition_point(|(t, _)| *t <= at);
+                readings.get(taken.checked_sub(1)?).map(|(_, value)| *value)
+            })
+            .collect()
+    }
+
+    /// Drop the readings no instant after `at` can use
+    fn forget(&mut self, at: f64) {
+        for readings in &mut self.readings {
+            let taken = readings.partition_point(|(t, _)| *t <= at);
+            readings.drain(..taken.saturating_sub(1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Temperature reporting at once, humidity four seconds late, waiting
+    /// up to five seconds
+    fn aligner() -> Aligner {
+        Aligner::new(vec![0.0, 4.0], &AlignConfig { max_wait_secs: 5.0 })
+    }
+
+    #[test]
+    fn readings_in_order_pair_values_taken_at_the_same_instant() {
+        let mut aligner = aligner();
+        // Humidity taken at -4 and 0 arrives at 0 and 4; the first
+        // temperature, taken after -4, completes that instant with nothing
+        // of its own from before it
+        assert!(aligner.push(1, 0.0, 40.0).is_empty());
+        assert_eq!(
+            aligner.push(0, 0.0, 20.0),
+            [Aligned {
+                at: -4.0,
+                values: vec![None, Some(40.0)],
+                timed_out: false
+            }]
+        );
+        assert!(aligner.push(0, 2.0, 30.0).is_empty());
+        assert_eq!(
+            aligner.push(1, 4.0, 50.0),
+            [Aligned {
+                at: 0.0,
+                values: vec![Some(20.0), Some(50.0)],
+                timed_out: false
+            }]
+        );
+        // Temperature at 2 pairs with the humidity taken at 0, the last
+        // one before it, once humidity taken after it arrives
+        let released = aligner.push(1, 7.0, 60.0);
+        assert_eq!(released.len(), 1);
+        assert_eq!(
+            (released[0].at, &released[0].values),
+            (2.0, &vec![Some(30.0), Some(50.0)])
+        );
+        assert_eq!(
+            (aligner.aligned, aligner.timed_out, aligner.late),
+            (3, 0, 0)
+        );
+        assert_eq!(aligner.pending(), 1);
+    }
+
+    #[test]
+    fn readings_out_of_order_come_out_in_the_order_they_were_taken() {
+        let mut aligner = aligner();
+        aligner.push(0, 10.0, 21.0);
+        aligner.push(0, 9.0, 20.0);
+        let released = aligner.push(1, 14.5, 55.0);
+        let times: Vec<f64> = released.iter().map(|a| a.at).collect();
+        assert_eq!(times, [9.0, 10.0]);
+        assert_eq!(released[0].values, [Some(20.0), None]);
+        assert_eq!(released[1].values, [Some(21.0), None]);
+        assert_eq!(aligner.pending(), 1);
+
+        // Taken before an instant already released: too late to change it
+        assert!(aligner.push(0, 8.0, 19.0).is_empty());
+        assert_eq!(aligner.late, 1);
+        let released = aligner.push(0, 12.0, 22.0);
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].values, [Some(21.0), Some(55.0)]);
+    }
+
+    #[test]
+    fn an_instant_waits_no_longer_than_the_bound_and_says_so() {
+        let mut aligner = aligner();
+        aligner.push(1, 0.0, 40.0);
+        aligner.push(0, 1.0, 20.0);
+        assert_eq!(aligner.poll(5.9), []);
+        // Humidity went quiet: the temperature at 1 goes with the freshest
+        // humidity after five seconds
+        let released = aligner.poll(6.0);
+        assert_eq!(
+            released.last(),
+            Some(&Aligned {
+                at: 1.0,
+                values: vec![Some(20.0), Some(40.0)],
+                timed_out: true
+            })
+        );
+        assert_eq!(aligner.timed_out, 1);
+        // Later instants still wait their own time
+        aligner.push(0, 7.0, 25.0);
+        assert!(aligner.poll(11.0).is_empty());
+        assert!(aligner.poll(12.0)[0].timed_out);
+        assert_eq!(aligner.pending(), 0);
+    }
+}
@@ -0,0 +1,413 @@
+// ============================================================================
+// ANNOTATIONS - Estados congelados com anotação
+// ============================================================================
+//
+// A surprising operating point, kept whole with a note to share it ("the
+// fan jumps here when humidity crosses 60"). Where a bookmark (bookmarks.rs)
+// keeps the inputs to come back to, an annotation freezes everything the
+// controller made of them: the raw readings, every rule's strength, each
+// output's dominant set, the outputs, the primary output's aggregated set
+// sampled across its universe, and a hash of the config that gave them.
+//
+// Annotations go to a file next to the TUI's state file
+// (`<state>.annotations.toml`), loaded with it:
+//
+//     [[annotation]]
+//     note = "fan jumps at 60 %"
+//     saved = 1760612345.2        # wall-clock epoch seconds
+//     config_hash = "5f0c2e1a"    # CRC-32 of the config, links kept
+//     inputs = [27.5, 64.0]       # raw readings, in declaration order
+//     outputs = [58.25]
+//     strengths = [0.0, 0.4, ...] # one per rule
+//     dominant = ["High"]         # one per output
+//     aggregated = [0.0, ...]     # SAMPLES points, output min to max
+//
+// An annotation made with another config than the running one may no
+// longer describe what the controller does at its inputs: the browser marks
+// it possibly stale. The whole set exports as JSON, or as an HTML
+// `<section>` to append to a report.
+
+use crate::config::{self, Section};
+use crate::scenario::Trace;
+use crate::timestamp::{self, Zone};
+use crate::{FuzzyController, FuzzySystem, backup, bundle, json};
+
+/// Points the aggregated output set is sampled at, its ends included
+pub const SAMPLES: usize = 41;
+
+/// Where the annotations go for the state file at `state_path`
+pub fn path_for(state_path: &str) -> String {
+    format!("{}.annotations.toml", state_path)
+}
+
+/// CRC-32 of the config `system` serializes to, links kept, so the same
+/// config hashes the same however it was loaded
+pub fn config_hash(system: &FuzzySystem) -> u32 {
+    bundle::crc32(config::to_toml(system, true).as_bytes())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub note: String,
+    pub saved: f64,
+    pub config_hash: u32,
+    pub inputs: Vec<f64>,
+    pub outputs: Vec<f64>,
+    pub strengths: Vec<f64>,
+    pub dominant: Vec<String>,
+    /// The primary output's aggregated set at SAMPLES points
+    pub aggregated: Vec<f64>,
+}
+
+impl Annotation {
+    /// What `controller` makes of raw `inputs` at wall-clock `now`, with
+    /// no note yet
+    pub fn freeze(controller: &FuzzyController, inputs: Vec<f64>, now: f64) -> Self {
+        let system = &controller.system;
+        let trace = Trace::capture(controller, &inputs);
+        let output = system.output();
+        let activations = crate::apply_rules(&trace.strengths, &system.rules, output);
+        let aggregated = (0..SAMPLES)
+            .map(|i| {
+                let x = output.min + (output.max - output.min) * i as f64 / (SAMPLES - 1) as f64;
+                crate::aggregated(&activations, output, &system.defuzzification, x)
+            })
+            .collect();
+        Annotation {
+            note: String::new(),
+            saved: now,
+            config_hash: config_hash(system),
+            inputs,
+            outputs: trace.outputs,
+            strengths: trace.strengths,
+            dominant: trace.dominant,
+            aggregated,
+        }
+    }
+
+    /// Whether it was made with another config than the one hashing to
+    /// `current`
+    pub fn is_stale(&self, current: u32) -> bool {
+        self.config_hash != current
+    }
+
+    fn to_toml(&self) -> String {
+        let dominant: Vec<String> = self.dominant.iter().map(|s| config::quote(s)).collect();
+        format!(
+            "\n[[annotation]]\nnote = {}\nsaved = {:?}\nconfig_hash = \"{:08x}\"\n\
+             inputs = {}\noutputs = {}\nstrengths = {}\ndominant = [{}]\naggregated = {}\n",
+            config::quote(&self.note),
+            self.saved,
+            self.config_hash,
+            numbers(&self.inputs),
+            numbers(&self.outputs),
+            numbers(&self.strengths),
+            dominant.join(", "),
+            numbers(&self.aggregated)
+        )
+    }
+
+    fn to_json(&self) -> String {
+        let list = |values: &[f64]| {
+            let items: Vec<String> = values.iter().map(|v| json::number(*v)).collect();
+            format!("[{}]", items.join(", "))
+        };
+        let dominant: Vec<String> = self.dominant.iter().map(|s| json::string(s)).collect();
+        format!(
+            "    {{\"note\": {}, \"saved\": \"{}\", \"config_hash\": \"{:08x}\", \
+             \"inputs\": {}, \"outputs\": {}, \"strengths\": {}, \"dominant\": [{}], \
+             \"aggregated\": {}}}",
+            json::string(&self.note),
+            timestamp::rfc3339(self.saved),
+            self.config_hash,
+            list(&self.inputs),
+            list(&self.outputs),
+            list(&self.strengths),
+            dominant.join(", "),
+            list(&self.aggregated)
+        )
+    }
+}
+
+/// One row of the browser: the annotation at `index` in the order they
+/// were saved, and whether another config made it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entry<'a> {
+    pub index: usize,
+    pub annotation: &'a Annotation,
+    pub stale: bool,
+}
+
+/// The session's annotations, in the order they were saved
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Annotations {
+    items: Vec<Annotation>,
+}
+
+impl Annotations {
+    pub fn list(&self) -> &[Annotation] {
+        &self.items
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Annotation> {
+        self.items.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Keep `annotation` with `note`, which may not be empty
+    pub fn add(&mut self, mut annotation: Annotation, note: &str) -> Result<(), String> {
+        let note = note.trim();
+        if note.is_empty() {
+            return Err("an annotation needs a note".to_string());
+        }
+        annotation.note = note.to_string();
+        self.items.push(annotation);
+        Ok(())
+    }
+
+    /// The browser's rows, newest first, marked stale against the config
+    /// hashing to `current`
+    pub fn entries(&self, current: u32) -> Vec<Entry<'_>> {
+        let mut entries: Vec<Entry> = (self.items.iter().enumerate())
+            .map(|(index, annotation)| Entry {
+                index,
+                annotation,
+                stale: annotation.is_stale(current),
+            })
+            .collect();
+        // Stable, so annotations saved in the same instant keep their order
+        entries.sort_by(|a, b| b.annotation.saved.total_cmp(&a.annotation.saved));
+        entries
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let items = config::parse_document(text)?
+            .iter()
+            .filter(|s| s.header == "annotation")
+            .map(annotation_from_section)
+            .collect::<Result<_, _>>()?;
+        Ok(Annotations { items })
+    }
+
+    /// Annotations from their file; a missing file has none
+    pub fn load(path: &str) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Annotations::parse(&text).map_err(|e| format!("{}: {}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Annotations::default()),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        }
+    }
+
+    /// Write the annotations file
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        backup::atomic_write(path, &self.to_toml())
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut out = String::from("# Annotated states, written by the TUI\n");
+        for annotation in &self.items {
+            out.push_str(&annotation.to_toml());
+        }
+        out
+    }
+
+    /// Every annotation as a JSON object in an array, in the order they
+    /// were saved, the times in RFC 3339
+    pub fn to_json(&self) -> String {
+        let items: Vec<String> = self.items.iter().map(Annotation::to_json).collect();
+        match items.is_empty() {
+            true => "[]\n".to_string(),
+            false => format!("[\n{}\n]\n", items.join(",\n")),
+        }
+    }
+
+    /// An HTML `<section>` listing the annotations newest first, the times
+    /// in `zone`, those made with another config than the one hashing to
+    /// `current` marked possibly stale
+    pub fn to_html(&self, current: u32, zone: Zone, output: &str) -> String {
+        let mut out = String::from(
+            "<section id=\"annotations\">\n<h2>Annotations</h2>\n<table>\n<tr><th>Note</th>\
+             <th>Saved</th><th>Inputs</th>",
+        );
+        out.push_str(&format!("<th>{}</th><th>Config</th></tr>\n", html(output)));
+        for entry in self.entries(current) {
+            let annotation = entry.annotation;
+            let inputs: Vec<String> = annotation
+                .inputs
+                .iter()
+                .map(|v| format!("{:.2}", v))
+                .collect();
+            let config = match entry.stale {
+                true => format!("{:08x} (possibly stale)", annotation.config_hash),
+                false => format!("{:08x}", annotation.config_hash),
+            };
+            out.push_str(&format!(
+                "<tr><td>{}</td><td>{} ({})</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                html(&annotation.note),
+                zone.short(annotation.saved),
+                html(&zone.label()),
+                inputs.join(", "),
+                annotation
+                    .outputs
+                    .first()
+                    .map_or("-".to_string(), |v| format!("{:.2}", v)),
+                config
+            ));
+        }
+        out.push_str("</table>\n</section>\n");
+        out
+    }
+}
+
+fn numbers(values: &[f64]) -> String {
+    let items: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+// JSON has no NaN or infinities
+fn html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn annotation_from_section(section: &Section) -> Result<Annotation, String> {
+    let config_hash = u32::from_str_radix(section.str("config_hash")?, 16).map_err(|_| {
+        format!(
+            "line {}: 'config_hash' must be hexadecimal",
+            section.line_of("config_hash")
+        )
+    })?;
+    Ok(Annotation {
+        note: section.str("note")?.to_string(),
+        saved: section.num_or("saved", 0.0)?,
+        config_hash,
+        inputs: section.nums("inputs")?,
+        outputs: section.nums("outputs")?,
+        strengths: section.nums("strengths")?,
+        dominant: section
+            .strs("dominant")?
+            .into_iter()
+            .map(|(s, _)| s)
+            .collect(),
+        aggregated: section.nums("aggregated")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn annotated() -> Annotations {
+        let controller = FuzzyController::from_system(fixtures::fan());
+        let mut annotations = Annotations::default();
+        let frozen = Annotation::freeze(&controller, vec![27.5, 64.0], 10.0);
+        annotations.add(frozen, " fan <jumps> \"here\" ").unwrap();
+        let frozen = Annotation::freeze(&controller, vec![2.0, 10.0], 20.5);
+        annotations.add(frozen, "cold corner").unwrap();
+        annotations
+    }
+
+    #[test]
+    fn a_frozen_state_keeps_the_trace_and_the_aggregated_set() {
+        let controller = FuzzyController::from_system(fixtures::fan());
+        let frozen = Annotation::freeze(&controller, vec![27.5, 64.0], 10.0);
+        let trace = Trace::capture(&controller, &[27.5, 64.0]);
+        assert_eq!(frozen.outputs, trace.outputs);
+        assert_eq!(frozen.strengths, trace.strengths);
+        assert_eq!(frozen.dominant, trace.dominant);
+        assert_eq!(frozen.aggregated.len(), SAMPLES);
+        assert!(frozen.aggregated.iter().all(|m| (0.0..=1.0).contains(m)));
+        assert!(frozen.aggregated.iter().any(|m| *m > 0.0));
+        assert_eq!(frozen.config_hash, config_hash(&controller.system));
+
+        let mut annotations = Annotations::default();
+        assert_eq!(
+            annotations.add(frozen, "  "),
+            Err("an annotation needs a note".to_string())
+        );
+        assert!(annotations.is_empty());
+    }
+
+    #[test]
+    fn annotations_round_trip_through_their_file() {
+        let annotations = annotated();
+        assert_eq!(annotations.list()[0].note, "fan <jumps> \"here\"");
+        assert_eq!(
+            Annotations::parse(&annotations.to_toml()),
+            Ok(annotations.clone())
+        );
+
+        let path = std::env::temp_dir().join(format!("annotations-{}", std::process::id()));
+        let path = path_for(path.to_str().unwrap());
+        assert_eq!(Annotations::load(&path), Ok(Annotations::default()));
+        annotations.save(&path).unwrap();
+        assert_eq!(Annotations::load(&path), Ok(annotations));
+        std::fs::remove_file(&path).unwrap();
+
+        let broken = "[[annotation]]\nnote = \"x\"\nconfig_hash = \"nope\"\n";
+        assert!(
+            Annotations::parse(broken)
+                .unwrap_err()
+                .contains("'config_hash' must be hexadecimal")
+        );
+    }
+
+    #[test]
+    fn the_browser_lists_newest_first_and_marks_other_configs_stale() {
+        let annotations = annotated();
+        let current = config_hash(&FuzzyController::from_system(fixtures::fan()).system);
+        let entries = annotations.entries(current);
+        let order: Vec<usize> = entries.iter().map(|e| e.index).collect();
+        assert_eq!(order, [1, 0]);
+        assert!(entries.iter().all(|e| !e.stale));
+
+        // Any edit of the config changes its hash
+        let mut edited = FuzzyController::from_system(fixtures::fan()).system;
+        edited.inputs[0].max += 5.0;
+        let other = config_hash(&edited);
+        assert_ne!(other, current);
+        assert!(annotations.entries(other).iter().all(|e| e.stale));
+    }
+
+    #[test]
+    fn exports_escape_the_notes_and_mark_stale_entries() {
+        let annotations = annotated();
+        let current = config_hash(&FuzzyController::from_system(fixtures::fan()).system);
+
+        let json = annotations.to_json();
+        assert!(json.starts_with("[\n    {\"note\": \"fan <jumps> \\\"here\\\"\", "));
+        assert!(json.contains("\"saved\": \"1970-01-01T00:00:10.000Z\""));
+        assert_eq!(json.matches("\"config_hash\"").count(), 2);
+        assert_eq!(Annotations::default().to_json(), "[]\n");
+        // Any control character, not only those TOML escapes
+        let mut controlled = annotated();
+        controlled.items[0].note = "line\r\u{1}end".to_string();
+        controlled.items[0].outputs[0] = f64::NAN;
+        let json = controlled.to_json();
+        assert!(json.contains("\"note\": \"line\\r\\u0001end\""), "{}", json);
+        assert!(json.contains("\"outputs\": [null"), "{}", json);
+
+        let html = annotations.to_html(current, Zone::Utc, "fan_speed");
+        assert!(html.starts_with("<section id=\"annotations\">"));
+        assert!(html.contains("<td>fan &lt;jumps&gt; &quot;here&quot;</td>"));
+        assert!(html.find("cold corner").unwrap() < html.find("fan &lt;jumps").unwrap());
+        assert!(!html.contains("possibly stale"));
+        assert_eq!(
+            annotations
+                .to_html(current ^ 1, Zone::Utc, "fan_speed")
+                .matches("(possibly stale)")
+                .count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,738 @@
+// ============================================================================
+// AUDIT TRAIL - Trilha de auditoria das saídas
+// ============================================================================
+//
+// `daemon --audit FILE` records every output command the daemon publishes,
+// with how the output pipeline (pipeline.rs) got to it and what made it
+// compute, one record per line:
+//
+//     fuzzy_logic audit 1
+//     2026-10-17T08:30:00.250Z session=12 cause=command defuzzified=6.7 rate_limit=56.9 band=HIGH hysteresis=MEDIUM value=63.6 output_band=HIGH command="set temperature 8"
+//
+// A record is written whenever the published value or band changes, or the
+// dwell stage starts holding back another band, or the shadow's output
+// changes, or an exec program finishes; the state lines repeat the same
+// output in between. Its fields:
+//
+//     session        session time of the daemon's clock, in seconds
+//     cause          what computed the output: start, command (with the
+//                    client's command line), interval (the recompute
+//                    triggers' max interval), injection_expired,
+//                    override_expired (overrides.rs), window_closed
+//                    (window.rs), source_silent (a fused input's source
+//                    timed out, fusion.rs), align_timeout (readings
+//                    waited past [alignment] max_wait_secs, align.rs),
+//                    config_loaded (out of safe mode), interlock (with
+//                    the `switch` made, e.g. "door on") or shutdown
+//     aligned_at     session time the computed readings were taken at,
+//                    their latency taken off, with a `latency_secs`
+//     defuzzified    the crisp output of the inference
+//     cascade        the PID's output, with an [output.cascade]
+//     rate_limit     what the rate limiter added to its input, 0 when it
+//                    let the value through
+//     band           the band after hysteresis, and `hysteresis` the band
+//                    the limited value alone is in, when hysteresis held
+//                    another one
+//     dwell          the band the dwell stage holds back and `dwell_secs`
+//                    how long it still holds it, the output held meanwhile
+//     safe_output    the value that replaced the pipeline's, in safe mode
+//                    or on shutdown
+//     interlock      the engaged interlock whose value replaced it
+//                    (interlock.rs)
+//     value          what was sent, in `output_band`
+//     shadow         what the shadow config would have sent, in
+//                    `shadow_band`, with `--shadow` (shadow.rs)
+//     exec           the value an `[output.exec]` program finished
+//                    running with since the last record (exec.rs), its
+//                    `exec_attempt`, `exec_status` (exit code, signal,
+//                    timeout or error) and `exec_stderr`
+//
+// The audit file is separate from the daemon's log on stderr, which is
+// for people watching it. It rotates by size: once a record would take it
+// past `--audit-size BYTES` (1 MiB by default) it becomes FILE.1, FILE.1
+// becomes FILE.2 and so on, keeping `--audit-keep N` old files (5 by
+// default). `fuzzy_logic audit tail FILE [-n N]` prints the last N records
+// (20 by default) of FILE and its rotated files, oldest first.
+//
+// The pipeline has no smoothing, dead band or mapping stage other than
+// the hysteresis bands, and the daemon no watchdog or alarm override
+// (alarm.rs only reports), so records carry none of those.
+
+use crate::exec::{Invocation, Status};
+use crate::pipeline::{FanBand, OutputPipeline, Pending, PipelineOutput};
+use crate::timestamp;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+const VERSION: u32 = 1;
+pub const DEFAULT_SIZE: u64 = 1 << 20;
+pub const DEFAULT_KEEP: usize = 5;
+const DEFAULT_TAIL: usize = 20;
+pub const USAGE: &str = "Usage: fuzzy_logic audit tail FILE [-n N]";
+
+/// What computed a published output
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cause {
+    /// The daemon started
+    Start,
+    /// A client's command line
+    Command(String),
+    /// The recompute triggers' max interval ran out
+    Interval,
+    /// A failure injection expired
+    InjectionExpired,
+    /// Hand-set inputs stopped holding against the readings
+    OverrideExpired,
+    /// A sample window closed on a new reading
+    WindowClosed,
+    /// A source of a fused input went silent
+    SourceSilent,
+    /// Readings waited for the other inputs' past the bound
+    AlignTimeout,
+    /// The config loaded, ending safe mode
+    ConfigLoaded,
+    /// An interlock was engaged or released, e.g. "door on"
+    Interlock(String),
+    /// The daemon shut down
+    Shutdown,
+}
+
+impl Cause {
+    fn name(&self) -> &'static str {
+        match self {
+            Cause::Start => "start",
+            Cause::Command(_) => "command",
+            Cause::Interval => "interval",
+            Cause::InjectionExpired => "injection_expired",
+            Cause::OverrideExpired => "override_expired",
+            Cause::WindowClosed => "window_closed",
+            Cause::SourceSilent => "source_silent",
+            Cause::AlignTimeout => "align_timeout",
+            Cause::ConfigLoaded => "config_loaded",
+            Cause::Interlock(_) => "interlock",
+            Cause::Shutdown => "shutdown",
+        }
+    }
+
+    fn parse(name: &str, command: Option<String>, switch: Option<String>) -> Option<Self> {
+        if name == "interlock" {
+            return switch.map(Cause::Interlock);
+        }
+        match (name, command) {
+            ("start", None) => Some(Cause::Start),
+            ("command", Some(command)) => Some(Cause::Command(command)),
+            ("interval", None) => Some(Cause::Interval),
+            ("injection_expired", None) => Some(Cause::InjectionExpired),
+            ("override_expired", None) => Some(Cause::OverrideExpired),
+            ("window_closed", None) => Some(Cause::WindowClosed),
+            ("source_silent", None) => Some(Cause::SourceSilent),
+            ("align_timeout", None) => Some(Cause::AlignTimeout),
+            ("config_loaded", None) => Some(Cause::ConfigLoaded),
+            ("shutdown", None) => Some(Cause::Shutdown),
+            _ => None,
+        }
+    }
+}
+
+/// One published output and how it came about
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// Wall-clock time, in epoch seconds
+    pub stamp: f64,
+    /// Session time, in seconds
+    pub session: f64,
+    pub cause: Cause,
+    /// Session time the readings were taken at, with input latencies
+    pub aligned_at: Option<f64>,
+    pub defuzzified: f64,
+    /// The PID's output, with a cascade
+    pub cascade: Option<f64>,
+    pub rate_limit: f64,
+    pub band: FanBand,
+    /// The band of the limited value, when hysteresis held another
+    pub hysteresis: Option<FanBand>,
+    pub dwell: Option<Pending>,
+    pub safe_output: Option<f64>,
+    /// The engaged interlock whose value was sent
+    pub interlock: Option<String>,
+    pub output: PipelineOutput,
+    /// The shadow's output and band, with `--shadow`
+    pub shadow: Option<(f64, FanBand)>,
+    /// A run of the `[output.exec]` program that finished
+    pub exec: Option<Invocation>,
+}
+
+impl Record {
+    /// The record of `output`, published after `pipeline` processed
+    /// `input`, the defuzzified value or the PID's output
+    pub fn new(
+        cause: &Cause,
+        session: f64,
+        defuzzified: f64,
+        input: f64,
+        pipeline: &OutputPipeline,
+        output: PipelineOutput,
+    ) -> Self {
+        let nominal = FanBand::from_speed(pipeline.limited());
+        Record {
+            stamp: timestamp::now(),
+            session,
+            cause: cause.clone(),
+            aligned_at: None,
+            defuzzified,
+            cascade: (input != defuzzified).then_some(input),
+            rate_limit: pipeline.limited() - input,
+            band: pipeline.band(),
+            hysteresis: (nominal != pipeline.band()).then_some(nominal),
+            dwell: output.pending,
+            safe_output: None,
+            interlock: None,
+            output,
+            shadow: None,
+            exec: None,
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        let mut line = format!(
+            "{} session={} cause={}",
+            timestamp::rfc3339(self.stamp),
+            self.session,
+            self.cause.name()
+        );
+        if let Some(at) = self.aligned_at {
+            line.push_str(&format!(" aligned_at={}", at));
+        }
+        line.push_str(&format!(" defuzzified={}", self.defuzzified));
+        if let Some(cascade) = self.cascade {
+            line.push_str(&format!(" cascade={}", cascade));
+        }
+        line.push_str(&format!(
+            " rate_limit={} band={}",
+            self.rate_limit,
+            self.band.label()
+        ));
+        if let Some(nominal) = self.hysteresis {
+            line.push_str(&format!(" hysteresis={}", nominal.label()));
+        }
+        if let Some(dwell) = self.dwell {
+            line.push_str(&format!(
+                " dwell={} dwell_secs={}",
+                dwell.band.label(),
+                dwell.remaining_secs
+            ));
+        }
+        if let Some(value) = self.safe_output {
+            line.push_str(&format!(" safe_output={}", value));
+        }
+        if let Some(name) = &self.interlock {
+            line.push_str(&format!(" interlock={}", crate::config::quote(name)));
+        }
+        line.push_str(&format!(
+            " value={} output_band={}",
+            self.output.value,
+            self.output.band.label()
+        ));
+        if let Some((value, band)) = self.shadow {
+            line.push_str(&format!(" shadow={} shadow_band={}", value, band.label()));
+        }
+        if let Some(exec) = &self.exec {
+            line.push_str(&format!(
+                " exec={} exec_attempt={} exec_status={} exec_stderr={}",
+                exec.value,
+                exec.attempt,
+                exec.status.label(),
+                crate::config::quote(&exec.stderr)
+            ));
+        }
+        match &self.cause {
+            Cause::Command(command) => {
+                line.push_str(&format!(" command={}", crate::config::quote(command)))
+            }
+            Cause::Interlock(switch) => {
+                line.push_str(&format!(" switch={}", crate::config::quote(switch)))
+            }
+            _ => {}
+        }
+        line
+    }
+
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let (stamp, rest) = line.split_once(' ').ok_or("no fields")?;
+        let stamp = timestamp::parse_rfc3339(stamp)
+            .ok_or_else(|| format!("invalid timestamp '{}'", stamp))?;
+        let fields = split_fields(rest)?;
+        let field = |key: &str| {
+            fields
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value.as_str())
+        };
+        let number = |key: &str| -> Result<Option<f64>, String> {
+            field(key)
+                .map(|value| {
+                    value
+                        .parse::<f64>()
+                        .map_err(|_| format!("{}: invalid number '{}'", key, value))
+                })
+                .transpose()
+        };
+        let band = |key: &str| -> Result<Option<FanBand>, String> {
+            field(key)
+                .map(|label| {
+                    band_from_label(label).ok_or(format!("{}: unknown band '{}'", key, label))
+                })
+                .transpose()
+        };
+        let required = |key: &str| format!("missing {}", key);
+        let cause = field("cause").ok_or(required("cause"))?;
+        let dwell = match (band("dwell")?, number("dwell_secs")?) {
+            (Some(band), Some(remaining_secs)) => Some(Pending {
+                band,
+                remaining_secs,
+            }),
+            (None, None) => None,
+            _ => return Err("dwell and dwell_secs go together".to_string()),
+        };
+        let safe_output = number("safe_output")?;
+        let shadow = match (number("shadow")?, band("shadow_band")?) {
+            (Some(value), Some(band)) => Some((value, band)),
+            (None, None) => None,
+            _ => return Err("shadow and shadow_band go together".to_string()),
+        };
+        let exec = match number("exec")? {
+            Some(value) => {
+                let status = field("exec_status").ok_or(required("exec_status"))?;
+                Some(Invocation {
+                    value,
+                    attempt: field("exec_attempt")
+                        .and_then(|attempt| attempt.parse().ok())
+                        .ok_or("exec_attempt: missing or invalid")?,
+                    status: Status::parse(status)
+                        .ok_or_else(|| format!("exec_status: invalid status '{}'", status))?,
+                    stderr: field("exec_stderr").unwrap_or_default().to_string(),
+                })
+            }
+            None => None,
+        };
+        Ok(Record {
+            stamp,
+            session: number("session")?.ok_or(required("session"))?,
+            cause: Cause::parse(
+                cause,
+                field("command").map(str::to_string),
+                field("switch").map(str::to_string),
+            )
+            .ok_or_else(|| format!("unknown cause '{}'", cause))?,
+            aligned_at: number("aligned_at")?,
+            defuzzified: number("defuzzified")?.ok_or(required("defuzzified"))?,
+            cascade: number("cascade")?,
+            rate_limit: number("rate_limit")?.ok_or(required("rate_limit"))?,
+            band: band("band")?.ok_or(required("band"))?,
+            hysteresis: band("hysteresis")?,
+            dwell,
+            safe_output,
+            interlock: field("interlock").map(str::to_string),
+            output: PipelineOutput {
+                value: number("value")?.ok_or(required("value"))?,
+                band: band("output_band")?.ok_or(required("output_band"))?,
+                // A safe output or an interlock stands in for whatever
+                // the dwell held
+                pending: dwell.filter(|_| safe_output.is_none() && field("interlock").is_none()),
+            },
+            shadow,
+            exec,
+        })
+    }
+
+    /// The record for a person: when and why, then each stage that changed
+    /// the value on its way out
+    pub fn describe(&self) -> String {
+        let mut cause = match &self.cause {
+            Cause::Command(command) => format!("'{}'", command),
+            Cause::Interlock(switch) => format!("interlock {}", switch),
+            cause => cause.name().replace('_', " "),
+        };
+        if let Some(at) = self.aligned_at {
+            cause.push_str(&format!(", readings taken at {:.1}s", at));
+        }
+        let mut stages = vec![format!("defuzzified {:.2}", self.defuzzified)];
+        if let Some(cascade) = self.cascade {
+            stages.push(format!("cascade {:.2}", cascade));
+        }
+        if self.rate_limit != 0.0 {
+            stages.push(format!("rate limit {:+.2}", self.rate_limit));
+        }
+        match self.hysteresis {
+            Some(nominal) => stages.push(format!(
+                "hysteresis holds {} over {}",
+                self.band.label(),
+                nominal.label()
+            )),
+            None => stages.push(format!("band {}", self.band.label())),
+        }
+        if let Some(dwell) = self.dwell {
+            stages.push(format!(
+                "dwell defers {} for {:.1}s",
+                dwell.band.label(),
+                dwell.remaining_secs
+            ));
+        }
+        if let Some(value) = self.safe_output {
+            stages.push(format!("safe output {}", value));
+        }
+        if let Some(name) = &self.interlock {
+            stages.push(format!("interlock '{}' forces {}", name, self.output.value));
+        }
+        let mut described = format!(
+            "{} [{:>8.1}s] {}\n    {} -> sent {:.2} {}",
+            timestamp::rfc3339(self.stamp),
+            self.session,
+            cause,
+            stages.join(", "),
+            self.output.value,
+            self.output.band.label()
+        );
+        if let Some((value, band)) = self.shadow {
+            described.push_str(&format!(" (shadow {:.2} {})", value, band.label()));
+        }
+        if let Some(exec) = &self.exec {
+            described.push_str(&format!("\n    {}", exec.describe()));
+        }
+        described
+    }
+}
+
+fn band_from_label(label: &str) -> Option<FanBand> {
+    [FanBand::Off, FanBand::Low, FanBand::Medium, FanBand::High]
+        .into_iter()
+        .find(|band| band.label() == label)
+}
+
+/// `key=value` fields separated by spaces, where a value in double quotes
+/// may hold spaces and the escapes of `config::quote`
+fn split_fields(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut fields = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.next_if_eq(&' ').is_some() {}
+        if chars.peek().is_none() {
+            return Ok(fields);
+        }
+        let key: String =
+            std::iter::from_fn(|| chars.next_if(|c| *c != '=' && *c != ' ')).collect();
+        if chars.next() != Some('=') {
+            return Err(format!("field '{}' has no value", key));
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some(c) => value.push(c),
+                        None => return Err(format!("{}: unterminated string", key)),
+                    },
+                    Some(c) => value.push(c),
+                    None => return Err(format!("{}: unterminated string", key)),
+                }
+            }
+        } else {
+            value.extend(std::iter::from_fn(|| chars.next_if(|c| *c != ' ')));
+        }
+        fields.push((key, value));
+    }
+}
+
+/// Value, band, deferred band and shadow output of a record: a record
+/// with the same as the last one is not written
+type Published = (f64, FanBand, Option<FanBand>, Option<(f64, FanBand)>);
+
+/// The audit file of a daemon, rotating by size
+pub struct AuditLog {
+    path: String,
+    max_bytes: u64,
+    keep: usize,
+    file: File,
+    size: u64,
+    last: Option<Published>,
+}
+
+impl AuditLog {
+    /// Append to `path`, starting it with the format version if it is new
+    pub fn open(path: &str, max_bytes: u64, keep: usize) -> Result<Self, String> {
+        let (file, size) = open_file(path)?;
+        Ok(AuditLog {
+            path: path.to_string(),
+            max_bytes,
+            keep,
+            file,
+            size,
+            last: None,
+        })
+    }
+
+    /// Write `record` if it publishes something other than the last one,
+    /// or tells how an exec program ran
+    pub fn record(&mut self, record: &Record) -> Result<(), String> {
+        let output = record.output;
+        let key = (
+            output.value,
+            output.band,
+            output.pending.map(|p| p.band),
+            record.shadow,
+        );
+        if self.last == Some(key) && record.exec.is_none() {
+            return Ok(());
+        }
+        let line = record.to_line() + "\n";
+        if self.size > header().len() as u64 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file
+            .write_all(line.as_bytes())
+            .map_err(|e| format!("{}: {}", self.path, e))?;
+        self.size += line.len() as u64;
+        self.last = Some(key);
+        Ok(())
+    }
+
+    /// Shift FILE to FILE.1 and every FILE.n to FILE.n+1, dropping the
+    /// ones past `keep`, and start FILE over
+    fn rotate(&mut self) -> Result<(), String> {
+        let rotated = |n: usize| format!("{}.{}", self.path, n);
+        let shift = |from: &str, to: &str| match std::fs::rename(from, to) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("{}: {}", from, e)),
+            _ => Ok(()),
+        };
+        if self.keep == 0 {
+            let _ = std::fs::remove_file(&self.path);
+        } else {
+            let _ = std::fs::remove_file(rotated(self.keep));
+            for n in (1..self.keep).rev() {
+                shift(&rotated(n), &rotated(n + 1))?;
+            }
+            shift(&self.path, &rotated(1))?;
+        }
+        (self.file, self.size) = open_file(&self.path)?;
+        Ok(())
+    }
+}
+
+fn header() -> String {
+    format!("fuzzy_logic audit {}\n", VERSION)
+}
+
+/// `path` opened for appending and its size, with the header written if
+/// it is empty
+fn open_file(path: &str) -> Result<(File, u64), String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let mut size = file
+        .metadata()
+        .map_err(|e| format!("{}: {}", path, e))?
+        .len();
+    if size == 0 {
+        file.write_all(header().as_bytes())
+            .map_err(|e| format!("{}: {}", path, e))?;
+        size = header().len() as u64;
+    }
+    Ok((file, size))
+}
+
+/// The records of `path` and its rotated files, oldest first, and a
+/// "FILE line N: ..." problem for each line that could not be read
+pub fn read(path: &str) -> Result<(Vec<Record>, Vec<String>), String> {
+    let mut files: Vec<String> = (1..)
+        .map(|n| format!("{}.{}", path, n))
+        .take_while(|rotated| std::path::Path::new(rotated).exists())
+        .collect();
+    files.reverse();
+    files.push(path.to_string());
+    let (mut records, mut problems) = (Vec::new(), Vec::new());
+    for file in files {
+        let text = std::fs::read_to_string(&file).map_err(|e| format!("{}: {}", file, e))?;
+        for (index, line) in text.lines().enumerate() {
+            if line.starts_with("fuzzy_logic audit ") || line.trim().is_empty() {
+                continue;
+            }
+            match Record::parse(line) {
+                Ok(record) => records.push(record),
+                Err(e) => problems.push(format!("{} line {}: {}", file, index + 1, e)),
+            }
+        }
+    }
+    Ok((records, problems))
+}
+
+/// `audit` subcommand; returns the exit code
+pub fn run(args: &[String]) -> i32 {
+    let (path, count) = match args {
+        [tail, path] if tail == "tail" => (path, DEFAULT_TAIL),
+        [tail, path, n, count] | [tail, n, count, path] if tail == "tail" && n == "-n" => {
+            match count.parse::<usize>() {
+                Ok(count) => (path, count),
+                Err(_) => {
+                    eprintln!("-n: invalid count '{}'", count);
+                    return 2;
+                }
+            }
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    let (records, problems) = match read(path) {
+        Ok(read) => read,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 2;
+        }
+    };
+    for problem in &problems {
+        eprintln!("warning: {}", problem);
+    }
+    for record in &records[records.len().saturating_sub(count)..] {
+        println!("{}", record.describe());
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(session: f64, value: f64) -> Record {
+        Record {
+            stamp: 1_792_218_600.25,
+            session,
+            cause: Cause::Command("set temperature \"33\"".to_string()),
+            aligned_at: Some(session - 4.0),
+            defuzzified: 83.5,
+            cascade: Some(80.0),
+            rate_limit: -60.0,
+            band: FanBand::High,
+            hysteresis: Some(FanBand::Medium),
+            dwell: Some(Pending {
+                band: FanBand::Off,
+                remaining_secs: 2.5,
+            }),
+            safe_output: None,
+            interlock: None,
+            output: PipelineOutput {
+                value,
+                band: FanBand::High,
+                pending: Some(Pending {
+                    band: FanBand::Off,
+                    remaining_secs: 2.5,
+                }),
+            },
+            shadow: Some((50.0, FanBand::Medium)),
+            exec: None,
+        }
+    }
+
+    #[test]
+    fn records_round_trip_through_their_lines() {
+        let full = record(12.5, 20.0);
+        assert_eq!(Record::parse(&full.to_line()), Ok(full.clone()));
+        assert!(
+            full.describe()
+                .contains("'set temperature \"33\"', readings taken at 8.5s")
+        );
+        let unaligned = Record {
+            cause: Cause::AlignTimeout,
+            ..full.clone()
+        };
+        let line = unaligned.to_line();
+        assert!(
+            line.contains(" cause=align_timeout aligned_at=8.5 "),
+            "{}",
+            line
+        );
+        assert_eq!(Record::parse(&line), Ok(unaligned));
+        let bare = Record {
+            cause: Cause::Shutdown,
+            aligned_at: None,
+            cascade: None,
+            shadow: None,
+            exec: None,
+            hysteresis: None,
+            dwell: None,
+            safe_output: Some(0.0),
+            output: PipelineOutput {
+                pending: None,
+                ..full.output
+            },
+            ..full
+        };
+        assert_eq!(Record::parse(&bare.to_line()), Ok(bare));
+        let forced = Record {
+            cause: Cause::Interlock("door on".to_string()),
+            interlock: Some("door".to_string()),
+            output: PipelineOutput {
+                value: 0.0,
+                band: FanBand::Off,
+                pending: None,
+            },
+            ..full
+        };
+        let line = forced.to_line();
+        assert!(line.contains(" switch=\"door on\""), "{}", line);
+        assert_eq!(Record::parse(&line), Ok(forced.clone()));
+        assert!(forced.describe().contains("interlock 'door' forces 0"));
+        let executed = Record {
+            exec: Some(Invocation {
+                value: 63.6,
+                attempt: 2,
+                status: Status::TimedOut,
+                stderr: "no \"fan\"\nat all".to_string(),
+            }),
+            ..forced
+        };
+        assert_eq!(Record::parse(&executed.to_line()), Ok(executed.clone()));
+        assert!(
+            (executed.describe()).ends_with(
+                "exec of 63.6 timed out and was killed (attempt 2): no \"fan\" / at all"
+            )
+        );
+        let line = "2026-10-17T08:30:00.250Z session=1 cause=start defuzzified=2";
+        assert_eq!(Record::parse(line), Err("missing rate_limit".to_string()));
+    }
+
+    #[test]
+    fn the_file_rotates_by_size_keeping_the_newest() {
+        let path = std::env::temp_dir().join(format!("fuzzy-audit-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let line = record(0.0, 0.0).to_line().len() as u64 + 1;
+        // Two records per file, three files
+        let mut log = AuditLog::open(path, header().len() as u64 + 2 * line + 4, 2).unwrap();
+        for i in 0..7 {
+            let record = record(i as f64, i as f64);
+            log.record(&record).unwrap();
+            // The same output again is not a new command
+            log.record(&record).unwrap();
+        }
+
+        let (records, problems) = read(path).unwrap();
+        let sessions: Vec<f64> = records.iter().map(|r| r.session).collect();
+        assert_eq!(sessions, [2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert!(problems.is_empty());
+        assert!(!std::path::Path::new(&format!("{}.3", path)).exists());
+        let text = std::fs::read_to_string(format!("{}.1", path)).unwrap();
+        assert!(text.starts_with("fuzzy_logic audit 1\n"), "{}", text);
+        for file in [
+            path.to_string(),
+            format!("{}.1", path),
+            format!("{}.2", path),
+        ] {
+            std::fs::remove_file(file).unwrap();
+        }
+    }
+}
@@ -0,0 +1,248 @@
+// ============================================================================
+// CHART AUTO-RANGING - Escala automática dos gráficos
+// ============================================================================
+//
+// The value axis of each input's history chart follows one of three ranges,
+// chosen per chart ('x' for temperature, 'X' for humidity):
+//
+//     window       min to max of the entries in view (the default)
+//     percentile   2nd to 98th percentile of them, so one spike does not
+//                  flatten the rest; values past it are drawn at the edge
+//     universe     the input's whole universe
+//
+// Ranges are computed from the level-of-detail buckets (lod.rs) of the view
+// once it holds more than RANGE_BUCKETS entries, and from the entries
+// themselves below that: a percentile then weighs each bucket's min (for
+// the low end) or max (for the high end) by its count, which keeps a spike
+// inside one bucket out of the range just as well.
+//
+// A new range is not jumped to: the axis moves from where it is to the new
+// bounds over TRANSITION_FRAMES frames, and a range changing again on the
+// way starts from wherever the axis got to.
+
+use crate::lod::Bucket;
+
+/// Entries in view beyond which the range comes from buckets
+pub const RANGE_BUCKETS: usize = 240;
+/// Frames the axis takes to reach a new range
+pub const TRANSITION_FRAMES: u32 = 6;
+/// Share of the entries the percentile range leaves out at each end
+pub const PERCENTILE: f64 = 0.02;
+
+/// What the value axis of a history chart spans
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RangeMode {
+    #[default]
+    Window,
+    Percentile,
+    Universe,
+}
+
+impl RangeMode {
+    pub fn next(self) -> Self {
+        match self {
+            RangeMode::Window => RangeMode::Percentile,
+            RangeMode::Percentile => RangeMode::Universe,
+            RangeMode::Universe => RangeMode::Window,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RangeMode::Window => "min–max of the window",
+            RangeMode::Percentile => "2nd–98th percentile",
+            RangeMode::Universe => "full universe",
+        }
+    }
+
+    /// Marks before the top and bottom axis labels
+    pub fn marks(self) -> (&'static str, &'static str) {
+        match self {
+            RangeMode::Window => ("max ", "min "),
+            RangeMode::Percentile => ("p98 ", "p2 "),
+            RangeMode::Universe => ("", ""),
+        }
+    }
+
+    /// Bounds of `buckets` (single entries or summaries) in this mode, the
+    /// universe `min..max` when there are none; never an empty range
+    pub fn range(self, buckets: &[Bucket], universe: (f64, f64)) -> (f64, f64) {
+        let range = match self {
+            RangeMode::Universe => Some(universe),
+            RangeMode::Window => (!buckets.is_empty()).then(|| {
+                buckets
+                    .iter()
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), b| {
+                        (lo.min(b.min), hi.max(b.max))
+                    })
+            }),
+            RangeMode::Percentile => percentile_range(buckets, PERCENTILE),
+        };
+        let (lo, hi) = range.unwrap_or(universe);
+        if hi > lo {
+            (lo, hi)
+        } else {
+            (lo - 1.0, hi + 1.0)
+        }
+    }
+}
+
+/// A bucket of the single entry `value`
+pub fn point(time: f64, value: f64) -> Bucket {
+    Bucket {
+        start: time,
+        min: value,
+        max: value,
+        sum: value,
+        count: 1,
+    }
+}
+
+/// The `share` and `1 - share` quantiles of the entries behind `buckets`,
+/// interpolated between ranks: the low one over the bucket minimums and
+/// the high one over the maximums, each counted once per entry
+pub fn percentile_range(buckets: &[Bucket], share: f64) -> Option<(f64, f64)> {
+    let low = quantile(buckets.iter().map(|b| (b.min, b.count)).collect(), share)?;
+    let high = quantile(
+        buckets.iter().map(|b| (b.max, b.count)).collect(),
+        1.0 - share,
+    )?;
+    Some((low, high))
+}
+
+/// The `p` quantile of `values`, each repeated its count of times
+fn quantile(mut values: Vec<(f64, usize)>, p: f64) -> Option<f64> {
+    values.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let total: usize = values.iter().map(|(_, count)| count).sum();
+    if total == 0 {
+        return None;
+    }
+    let at = |rank: usize| {
+        let mut seen = 0;
+        for &(value, count) in &values {
+            seen += count;
+            if rank < seen {
+                return value;
+            }
+        }
+        values[values.len() - 1].0
+    };
+    let rank = p.clamp(0.0, 1.0) * (total - 1) as f64;
+    let (below, fraction) = (rank.floor() as usize, rank.fract());
+    let low = at(below);
+    Some(low + (at(below + 1) - low) * fraction)
+}
+
+/// Axis bounds moving toward a range over a few frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+    from: (f64, f64),
+    to: (f64, f64),
+    frame: u32,
+}
+
+impl Transition {
+    /// Already at `bounds`
+    pub fn at(bounds: (f64, f64)) -> Self {
+        Transition {
+            from: bounds,
+            to: bounds,
+            frame: TRANSITION_FRAMES,
+        }
+    }
+
+    /// Head for `target` from the bounds shown now, unless already heading
+    /// there
+    pub fn retarget(&mut self, target: (f64, f64)) {
+        if target != self.to {
+            self.from = self.bounds();
+            self.to = target;
+            self.frame = 0;
+        }
+    }
+
+    /// One frame further
+    pub fn advance(&mut self) {
+        self.frame = (self.frame + 1).min(TRANSITION_FRAMES);
+    }
+
+    /// Bounds to draw this frame
+    pub fn bounds(&self) -> (f64, f64) {
+        let t = self.frame as f64 / TRANSITION_FRAMES as f64;
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+        (lerp(self.from.0, self.to.0), lerp(self.from.1, self.to.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lod::Series;
+
+    #[test]
+    fn the_percentile_range_leaves_spikes_out() {
+        // 0..=100 in steps of 1: p2 and p98 on the entries themselves
+        let points: Vec<Bucket> = (0..=100).map(|i| point(i as f64, i as f64)).collect();
+        assert_eq!(percentile_range(&points, 0.02), Some((2.0, 98.0)));
+        assert_eq!(percentile_range(&points[..2], 0.25), Some((0.25, 0.75)));
+        assert_eq!(percentile_range(&[], 0.02), None);
+
+        // A flat signal with a one-entry spike each way
+        let mut values = vec![20.0; 500];
+        values[100] = 45.0;
+        values[400] = -10.0;
+        let points: Vec<Bucket> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| point(i as f64, *v))
+            .collect();
+        assert_eq!(RangeMode::Window.range(&points, (0.0, 50.0)), (-10.0, 45.0));
+        assert_eq!(
+            RangeMode::Percentile.range(&points, (0.0, 50.0)),
+            (19.0, 21.0)
+        );
+        assert_eq!(RangeMode::Universe.range(&points, (0.0, 50.0)), (0.0, 50.0));
+        assert_eq!(RangeMode::Percentile.range(&[], (0.0, 50.0)), (0.0, 50.0));
+
+        // From per-second buckets of a ten-per-second sweep, close to the
+        // entries' own percentiles
+        let entries: Vec<(f64, f64)> = (0..6000)
+            .map(|i| (i as f64 / 10.0, (i % 1000) as f64 / 10.0))
+            .collect();
+        let series = Series::from_points(entries.iter().copied());
+        let buckets = series.buckets(0, 0.0, 600.0);
+        assert_eq!(buckets.len(), 600);
+        let points: Vec<Bucket> = entries.iter().map(|&(t, v)| point(t, v)).collect();
+        let (lo, hi) = percentile_range(&buckets, 0.02).unwrap();
+        let (exact_lo, exact_hi) = percentile_range(&points, 0.02).unwrap();
+        assert!((lo - exact_lo).abs() <= 1.0, "{} vs {}", lo, exact_lo);
+        assert!((hi - exact_hi).abs() <= 1.0, "{} vs {}", hi, exact_hi);
+    }
+
+    #[test]
+    fn a_new_range_is_reached_over_a_few_frames() {
+        let mut axis = Transition::at((0.0, 60.0));
+        axis.retarget((0.0, 60.0));
+        assert_eq!(axis.bounds(), (0.0, 60.0));
+
+        axis.retarget((30.0, 90.0));
+        assert_eq!(axis.bounds(), (0.0, 60.0));
+        axis.advance();
+        assert_eq!(axis.bounds(), (5.0, 65.0));
+        axis.advance();
+        axis.advance();
+        assert_eq!(axis.bounds(), (15.0, 75.0));
+
+        // Retargeting on the way starts from where the axis is, and asking
+        // for the same target again does not restart it
+        axis.retarget((45.0, 75.0));
+        assert_eq!(axis.bounds(), (15.0, 75.0));
+        axis.advance();
+        axis.retarget((45.0, 75.0));
+        assert_eq!(axis.bounds(), (20.0, 75.0));
+        for _ in 0..TRANSITION_FRAMES * 2 {
+            axis.advance();
+        }
+        assert_eq!(axis.bounds(), (45.0, 75.0));
+    }
+}
@@ -0,0 +1,309 @@
+// ============================================================================
+// BACKUP - Gravação atômica da configuração e cópias rotativas
+// ============================================================================
+//
+// Saving a config from the TUI ('o') never writes over the only copy:
+//
+//   1. the new text goes to a temporary file in the config's directory,
+//      with the config's permissions, and is synced to disk
+//   2. the backups rotate: FILE.bak1 becomes FILE.bak2 and so on, the one
+//      past `keep` is dropped, and the config as it was is copied to
+//      FILE.bak1
+//   3. the temporary file is renamed over the config and the directory
+//      synced, so the rename itself survives a crash
+//
+// A crash or a failure before the rename leaves the config as it was, and
+// the temporary file is removed when the failure is an error. `keep` is 0
+// for no backups. `atomic_write` is that save without backups, for the
+// other files the program rewrites: the state file, adapted weights,
+// checkpoints, migrated configs and annotations.
+//
+// Restoring a backup ('K' in the TUI lists them, with the name and version
+// of each one's [metadata] and when it was written) is a save of its text:
+// the config being replaced becomes FILE.bak1, so a restore can be undone
+// by restoring again.
+
+use crate::config;
+use crate::timestamp;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Backups kept unless the TUI is started with `--backups N`
+pub const DEFAULT_KEEP: usize = 5;
+
+/// Where backup `number` of the config at `path` goes, 1 the newest
+pub fn path_for(path: &str, number: usize) -> String {
+    format!("{}.bak{}", path, number)
+}
+
+/// A backup as the TUI lists it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub number: usize,
+    pub path: String,
+    /// Modification time, in epoch seconds
+    pub modified: Option<f64>,
+    /// The backup's [metadata] name and version
+    pub name: Option<String>,
+    pub version: Option<String>,
+    /// Why the backup is not a config that loads, if it is not
+    pub error: Option<String>,
+}
+
+impl Backup {
+    /// Its line in the TUI's list
+    pub fn describe(&self) -> String {
+        let written = self
+            .modified
+            .map_or("unknown time".to_string(), timestamp::rfc3339);
+        let what = match (&self.error, &self.name, &self.version) {
+            (Some(e), _, _) => format!("⚠ {}", e),
+            (None, Some(name), Some(version)) => format!("{} {}", name, version),
+            (None, Some(name), None) => name.clone(),
+            (None, None, Some(version)) => format!("version {}", version),
+            (None, None, None) => "no name".to_string(),
+        };
+        format!("bak{}  {}  {}", self.number, written, what)
+    }
+}
+
+/// Replace the config at `path` with `text` (see the module comment)
+pub fn save(path: &str, text: &str, keep: usize) -> Result<(), String> {
+    save_with(path, text, keep, |_| Ok(()))
+}
+
+/// Replace the file at `path` with `text` as `save` does, keeping no
+/// backups
+pub fn atomic_write(path: &str, text: &str) -> Result<(), String> {
+    save(path, text, 0)
+}
+
+/// `save`, with `before_rename` run on the synced temporary file last
+/// thing before it replaces the config
+fn save_with(
+    path: &str,
+    text: &str,
+    keep: usize,
+    before_rename: impl FnOnce(&str) -> io::Result<()>,
+) -> Result<(), String> {
+    let tmp = temporary(path);
+    let written = write_synced(&tmp, text, path)
+        .and_then(|_| rotate(path, keep))
+        .and_then(|_| before_rename(&tmp))
+        .and_then(|_| fs::rename(&tmp, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("{}: {}", path, e));
+    }
+    sync_directory(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// A name next to `path` no other process saving it picks
+fn temporary(path: &str) -> String {
+    let file = Path::new(path);
+    let name = file
+        .file_name()
+        .map_or("config".into(), |name| name.to_string_lossy());
+    let tmp = format!(".{}.{}.tmp", name, std::process::id());
+    match file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => dir.join(tmp).to_string_lossy().to_string(),
+        None => tmp,
+    }
+}
+
+/// Write `text` to `tmp` with the permissions of `original`, if it exists,
+/// and wait for the disk
+fn write_synced(tmp: &str, text: &str, original: &str) -> io::Result<()> {
+    let mut file = File::create(tmp)?;
+    if let Ok(metadata) = fs::metadata(original) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.write_all(text.as_bytes())?;
+    file.sync_all()
+}
+
+/// Shift every backup of `path` one number up, dropping the one past
+/// `keep`, and copy `path` to the first
+fn rotate(path: &str, keep: usize) -> io::Result<()> {
+    if keep == 0 || !Path::new(path).exists() {
+        return Ok(());
+    }
+    match fs::remove_file(path_for(path, keep)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    for number in (1..keep).rev() {
+        match fs::rename(path_for(path, number), path_for(path, number + 1)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    // A copy keeps the config in place until the rename replaces it
+    fs::copy(path, path_for(path, 1)).map(|_| ())
+}
+
+/// Make a rename in the directory of `path` durable
+fn sync_directory(path: &str) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let dir = Path::new(path)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        File::open(dir)?.sync_all()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(())
+    }
+}
+
+/// The backups of the config at `path`, newest first; rotation leaves no
+/// gaps, so the first missing number ends the list
+pub fn list(path: &str) -> Vec<Backup> {
+    let mut backups = Vec::new();
+    for number in 1.. {
+        let backup = path_for(path, number);
+        let Ok(text) = fs::read_to_string(&backup) else {
+            break;
+        };
+        let modified = fs::metadata(&backup)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|since| since.as_secs_f64());
+        let (name, version, error) = match config::parse_system(&text) {
+            Ok(system) => (system.metadata.name, system.metadata.version, None),
+            Err(e) => (None, None, Some(e)),
+        };
+        backups.push(Backup {
+            number,
+            path: backup,
+            modified,
+            name,
+            version,
+            error,
+        });
+    }
+    backups
+}
+
+/// Put backup `number` back as the config at `path`, the config it
+/// replaces becoming the first backup
+pub fn restore(path: &str, number: usize, keep: usize) -> Result<(), String> {
+    let backup = path_for(path, number);
+    let text = fs::read_to_string(&backup).map_err(|e| format!("{}: {}", backup, e))?;
+    save(path, &text, keep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// A fresh directory for one test
+    fn directory(name: &str) -> String {
+        let dir =
+            std::env::temp_dir().join(format!("fuzzy-backup-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir.to_string_lossy().to_string()
+    }
+
+    /// The demo at version `version`.0.0
+    fn config(version: usize) -> String {
+        format!(
+            "[metadata]\nname = \"fan\"\nversion = \"{}.0.0\"\n{}",
+            version,
+            config::to_toml(&fixtures::fan(), false)
+        )
+    }
+
+    fn files(dir: &str) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn saves_rotate_the_backups_keeping_the_newest() {
+        let dir = directory("rotate");
+        let path = format!("{}/fan.toml", dir);
+        for version in 1..=4 {
+            save(&path, &config(version), 2).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), config(4));
+        assert_eq!(files(&dir), ["fan.toml", "fan.toml.bak1", "fan.toml.bak2"]);
+        let backups = list(&path);
+        let versions: Vec<Option<&str>> = backups.iter().map(|b| b.version.as_deref()).collect();
+        assert_eq!(versions, [Some("3.0.0"), Some("2.0.0")]);
+        assert!(backups[0].modified.is_some());
+        assert!(backups[0].describe().starts_with("bak1  "));
+        assert!(backups[0].describe().ends_with("  fan 3.0.0"));
+
+        // No backups at all with keep 0
+        save(&path, &config(5), 0).unwrap();
+        assert_eq!(
+            fs::read_to_string(format!("{}.bak1", path)).unwrap(),
+            config(3)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_failure_between_the_write_and_the_rename_leaves_the_config_whole() {
+        let dir = directory("failure");
+        let path = format!("{}/fan.toml", dir);
+        save(&path, &config(1), 3).unwrap();
+        let failed = save_with(&path, &config(2), 3, |tmp| {
+            // The new text is on disk next to the config by then
+            assert_eq!(fs::read_to_string(tmp).unwrap(), config(2));
+            Err(io::Error::other("disk pulled"))
+        });
+        assert_eq!(failed, Err(format!("{}: disk pulled", path)));
+        assert_eq!(fs::read_to_string(&path).unwrap(), config(1));
+        assert!(!files(&dir).iter().any(|name| name.ends_with(".tmp")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_save_keeps_the_permissions_of_the_config() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = directory("permissions");
+        let path = format!("{}/fan.toml", dir);
+        fs::write(&path, config(1)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        save(&path, &config(2), 1).unwrap();
+        let mode = |path: &str| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), 0o640);
+        assert_eq!(mode(&format!("{}.bak1", path)), 0o640);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn restoring_a_backup_keeps_the_config_it_replaces() {
+        let dir = directory("restore");
+        let path = format!("{}/fan.toml", dir);
+        for version in 1..=3 {
+            save(&path, &config(version), 3).unwrap();
+        }
+        fs::write(path_for(&path, 3), "[[input]\n").unwrap();
+        restore(&path, 2, 3).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), config(1));
+        let backups = list(&path);
+        let versions: Vec<Option<&str>> = backups.iter().map(|b| b.version.as_deref()).collect();
+        // bak3 held what bak2 held before the restore pushed it down
+        assert_eq!(versions, [Some("3.0.0"), Some("2.0.0"), Some("1.0.0")]);
+        assert!(restore(&path, 4, 3).is_err());
+
+        fs::write(path_for(&path, 1), "[[input]\n").unwrap();
+        assert!(list(&path)[0].error.is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
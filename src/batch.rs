@@ -0,0 +1,889 @@
+// ============================================================================
+// BATCH MODE - Processamento de CSV
+// ============================================================================
+//
+// `batch FILE.csv` reads one row per sample, with a column per input
+// variable holding its raw reading (see adapters.rs), and writes the rows
+// back to stdout with the controller output appended. The output pipeline
+// (rate limit, hysteresis, dwell) sees one second per row, or the row
+// timestamps with `--validate-time`. Systems with several outputs get one
+// more column per additional output. With an `[output.cascade]` the PID
+// tracks the first output against the measured input's column, and the
+// `output` column is the PID output after the pipeline.
+//
+// With an `[objective]` (objective.rs) each row gets its score in a last
+// `objective` column, and the mean, best and worst are printed at the end,
+// so configs run over the same CSV compare by one number each.
+//
+// `--out FILE` writes the rows to FILE instead, and `--checkpoint FILE`
+// makes a long run resumable: rows are written a chunk at a time and the
+// checkpoint records how far the run got, so re-running the same command
+// after a kill goes on from the last chunk (checkpoint.rs). `--progress`
+// shows rows per second and the time left on stderr.
+//
+// Input values are read with Rust's number syntax unless `--locale` names
+// how people wrote them (numbers.rs); a decimal comma then needs the field
+// quoted, as in `"23,5"`.
+
+use crate::adapters;
+use crate::alarm::Sample;
+use crate::bundle;
+use crate::cascade::Cascade;
+use crate::checkpoint::{self, Checkpoint};
+use crate::config;
+use crate::numbers::{self, Locale};
+use crate::objective::Objective;
+use crate::pipeline::OutputPipeline;
+use crate::timestamp;
+use crate::{FuzzyController, FuzzySystem};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, Seek, SeekFrom, Write};
+use std::time::Instant;
+
+const USAGE: &str = "Usage: fuzzy_logic batch FILE.csv [--config FILE] [--validate-time] \
+                     [--time-policy warn|reject] [--max-gap SECS] \
+                     [--locale auto|point|comma|system] [--out FILE] \
+                     [--checkpoint FILE] [--chunk-rows N] [--progress]";
+
+/// Rows between checkpoints
+const DEFAULT_CHUNK_ROWS: usize = 10_000;
+
+/// What to do with rows whose timestamp does not move forward
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TimePolicy {
+    /// Keep the row, processed at the previous timestamp
+    Warn,
+    /// Drop the row from the output
+    Reject,
+}
+
+#[derive(Debug, Default)]
+struct TimeWarnings {
+    unparseable: usize,
+    duplicate: usize,
+    out_of_order: usize,
+    gaps: usize,
+}
+
+impl TimeWarnings {
+    fn total(&self) -> usize {
+        self.unparseable + self.duplicate + self.out_of_order + self.gaps
+    }
+}
+
+struct Options {
+    path: String,
+    system: FuzzySystem,
+    validate_time: bool,
+    policy: TimePolicy,
+    max_gap: Option<f64>,
+    /// How input values are read, Rust's number syntax when unset
+    locale: Option<Locale>,
+    /// Where the rows go, stdout when unset
+    out: Option<String>,
+    checkpoint: Option<String>,
+    /// Lines read between writes of the output and the checkpoint
+    chunk_rows: usize,
+    progress: bool,
+    /// CRC-32 of the arguments but `--progress`, which a checkpoint keeps
+    args: u32,
+    /// Chunks to write before failing, as a killed run would
+    #[cfg(test)]
+    stop_after: Option<usize>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut path = None;
+    let mut system = FuzzySystem::demo();
+    let mut validate_time = false;
+    let mut policy = TimePolicy::Warn;
+    let mut max_gap = None;
+    let mut locale = None;
+    let mut out = None;
+    let mut checkpoint = None;
+    let mut chunk_rows = DEFAULT_CHUNK_ROWS;
+    let mut progress = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || {
+            iter.next()
+                .ok_or_else(|| format!("{} expects a value", arg))
+        };
+        match arg.as_str() {
+            "--config" => system = config::load_system(value()?)?,
+            "--validate-time" => validate_time = true,
+            "--time-policy" => {
+                policy = match value()?.as_str() {
+                    "warn" => TimePolicy::Warn,
+                    "reject" => TimePolicy::Reject,
+                    other => return Err(format!("unknown time policy '{}'", other)),
+                }
+            }
+            "--max-gap" => {
+                max_gap = Some(
+                    value()?
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|g| *g > 0.0)
+                        .ok_or("--max-gap expects a positive number of seconds")?,
+                )
+            }
+            "--locale" => {
+                let name = value()?;
+                locale = Some(Locale::parse(name).ok_or_else(|| {
+                    format!("unknown locale '{}' (auto, point, comma or system)", name)
+                })?)
+            }
+            "--out" => out = Some(value()?.clone()),
+            "--checkpoint" => checkpoint = Some(value()?.clone()),
+            "--chunk-rows" => {
+                chunk_rows = value()?
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or("--chunk-rows expects a positive number of rows")?
+            }
+            "--progress" => progress = true,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    if checkpoint.is_some() && out.is_none() {
+        return Err("--checkpoint needs --out FILE: stdout cannot be resumed".to_string());
+    }
+    let kept: Vec<&str> = args
+        .iter()
+        .map(String::as_str)
+        .filter(|arg| *arg != "--progress")
+        .collect();
+
+    Ok(Options {
+        path: path.ok_or_else(|| USAGE.to_string())?,
+        system,
+        validate_time,
+        policy,
+        max_gap,
+        locale,
+        out,
+        checkpoint,
+        chunk_rows,
+        progress,
+        args: bundle::crc32(kept.join("\0").as_bytes()),
+        #[cfg(test)]
+        stop_after: None,
+    })
+}
+
+/// Header of the output: the input's columns, then the first output, the
+/// `output` after the pipeline (both in the first output's unit), the band,
+/// the other outputs and the objective's score
+fn output_header(system: &FuzzySystem, header: &[String]) -> String {
+    let output = system.output();
+    let mut columns = header.to_vec();
+    columns.push(output.unit.column(&output.name));
+    columns.push(output.unit.column("output"));
+    columns.push("band".to_string());
+    columns.extend(
+        system.outputs[1..]
+            .iter()
+            .map(|var| var.unit.column(&var.name)),
+    );
+    if system.objective.is_some() {
+        columns.push("objective".to_string());
+    }
+    columns.join(",")
+}
+
+/// What a run carries from one row to the next, which a checkpoint keeps
+struct Run {
+    pipeline: OutputPipeline,
+    cascade: Option<(Cascade, usize)>,
+    objective: Option<Objective>,
+    warnings: TimeWarnings,
+    support_warnings: usize,
+    last_time: Option<f64>,
+}
+
+impl Run {
+    fn new(system: &FuzzySystem) -> Self {
+        Run {
+            pipeline: OutputPipeline::new(system.pipeline.clone()),
+            cascade: system.cascade.clone().map(|config| {
+                let index = system
+                    .inputs
+                    .iter()
+                    .position(|var| var.name == config.measured)
+                    .expect("validated cascade input");
+                (Cascade::new(config), index)
+            }),
+            objective: system.objective.clone().map(Objective::new),
+            warnings: TimeWarnings::default(),
+            support_warnings: 0,
+            last_time: None,
+        }
+    }
+
+    /// The checkpoint's `state` (checkpoint.rs)
+    fn save(&self) -> Vec<f64> {
+        let mut saved = vec![
+            self.last_time.unwrap_or(f64::NAN),
+            self.support_warnings as f64,
+            self.warnings.unparseable as f64,
+            self.warnings.duplicate as f64,
+            self.warnings.out_of_order as f64,
+            self.warnings.gaps as f64,
+        ];
+        self.pipeline.save(&mut saved);
+        if let Some((cascade, _)) = &self.cascade {
+            cascade.save(&mut saved);
+        }
+        if let Some(objective) = &self.objective {
+            objective.save(&mut saved);
+        }
+        saved
+    }
+
+    /// Take up what `save` wrote, none unless it is exactly a state of
+    /// this run's system
+    fn restore(&mut self, saved: &[f64]) -> Option<()> {
+        let mut saved = saved.iter().copied();
+        self.last_time = Some(saved.next()?).filter(|t| !t.is_nan());
+        self.support_warnings = saved.next()? as usize;
+        self.warnings = TimeWarnings {
+            unparseable: saved.next()? as usize,
+            duplicate: saved.next()? as usize,
+            out_of_order: saved.next()? as usize,
+            gaps: saved.next()? as usize,
+        };
+        self.pipeline.restore(&mut saved)?;
+        if let Some((cascade, _)) = &mut self.cascade {
+            cascade.restore(&mut saved)?;
+        }
+        if let Some(objective) = &mut self.objective {
+            objective.restore(&mut saved)?;
+        }
+        saved.next().is_none().then_some(())
+    }
+
+    /// The output row for the `index`th line past the header, none for a
+    /// blank line or a rejected row
+    fn row(
+        &mut self,
+        options: &Options,
+        controller: &FuzzyController,
+        columns: (&[usize], Option<usize>),
+        index: usize,
+        line: &str,
+    ) -> Result<Option<String>, String> {
+        let row = index + 2;
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        let (input_columns, time_column) = columns;
+        let fields = config::split_csv_line(line);
+
+        let inputs: Vec<f64> = input_columns
+            .iter()
+            .map(|&c| {
+                let field = fields.get(c).map_or("", String::as_str);
+                match options.locale {
+                    Some(locale) => numbers::parse(field, locale)
+                        .map_err(|e| format!("{}:{}: {}", options.path, row, e)),
+                    None => field
+                        .parse::<f64>()
+                        .map_err(|_| format!("{}:{}: invalid input value", options.path, row)),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        let time = if options.validate_time {
+            let warnings = &mut self.warnings;
+            let raw = time_column.and_then(|c| fields.get(c)).map(String::as_str);
+            let parsed = raw.and_then(timestamp::parse);
+            let problem = match (parsed, self.last_time) {
+                (None, _) => {
+                    warnings.unparseable += 1;
+                    Some(format!("unparseable timestamp '{}'", raw.unwrap_or("")))
+                }
+                (Some(t), Some(last)) if t == last => {
+                    warnings.duplicate += 1;
+                    Some("duplicate timestamp".to_string())
+                }
+                (Some(t), Some(last)) if t < last => {
+                    warnings.out_of_order += 1;
+                    Some(format!("timestamp goes back {:.3}s", last - t))
+                }
+                (Some(t), Some(last)) => {
+                    if let Some(max_gap) = options.max_gap
+                        && t - last > max_gap
+                    {
+                        warnings.gaps += 1;
+                        eprintln!("{}:{}: warning: gap of {:.3}s", options.path, row, t - last);
+                    }
+                    None
+                }
+                (Some(_), None) => None,
+            };
+
+            if let Some(problem) = problem {
+                let action = match options.policy {
+                    TimePolicy::Warn => "kept at previous time",
+                    TimePolicy::Reject => "rejected",
+                };
+                eprintln!(
+                    "{}:{}: warning: {} ({})",
+                    options.path, row, problem, action
+                );
+                if options.policy == TimePolicy::Reject {
+                    return Ok(None);
+                }
+            }
+
+            match (parsed, self.last_time) {
+                (Some(t), Some(last)) => t.max(last),
+                (Some(t), None) => t,
+                (None, Some(last)) => last,
+                (None, None) => 0.0,
+            }
+        } else {
+            index as f64
+        };
+        self.last_time = Some(time);
+
+        let (inputs, conversion_warnings) = adapters::convert(&options.system.inputs, &inputs);
+        for warning in conversion_warnings {
+            eprintln!("{}:{}: warning: {}", options.path, row, warning);
+        }
+        let evaluation = controller.evaluate(&inputs);
+        for diagnostic in &evaluation.diagnostics {
+            eprintln!("{}:{}: warning: {}", options.path, row, diagnostic);
+        }
+        if !evaluation.diagnostics.is_empty() {
+            self.support_warnings += 1;
+        }
+        let score = self.objective.as_mut().map(|objective| {
+            let sample = Sample {
+                system: &options.system,
+                inputs: &inputs,
+                outputs: &evaluation.outputs,
+                strengths: &evaluation.strengths,
+            };
+            format!(",{:.4}", objective.update(&sample, time, time))
+        });
+        let values = evaluation.outputs;
+        let actuator = match &mut self.cascade {
+            Some((cascade, index)) => cascade.process(values[0], inputs[*index], time),
+            None => values[0],
+        };
+        let output = self.pipeline.process(actuator, time);
+        Ok(Some(format!(
+            "{},{:.4},{:.4},{}{}{}\n",
+            fields.join(","),
+            values[0],
+            output.value,
+            output.band.label(),
+            values[1..]
+                .iter()
+                .map(|v| format!(",{:.4}", v))
+                .collect::<String>(),
+            score.unwrap_or_default()
+        )))
+    }
+}
+
+/// Where the rows go: stdout, or `--out`, which a checkpoint can cut back
+enum Output {
+    Stdout(io::StdoutLock<'static>),
+    File(String, File),
+}
+
+impl Output {
+    /// Write a chunk of rows in one go; `durable` waits for the disk, so
+    /// a checkpoint written next never counts rows that could be lost
+    fn write(&mut self, chunk: &str, durable: bool) -> Result<(), String> {
+        match self {
+            Output::Stdout(out) => out
+                .write_all(chunk.as_bytes())
+                .and_then(|_| out.flush())
+                .map_err(|e| format!("stdout: {}", e)),
+            Output::File(path, file) => file
+                .write_all(chunk.as_bytes())
+                .and_then(|_| if durable { file.sync_data() } else { Ok(()) })
+                .map_err(|e| format!("{}: {}", path, e)),
+        }
+    }
+}
+
+/// Rows per second and time left on stderr, at most once a second
+struct Progress {
+    started: Instant,
+    shown: Option<Instant>,
+    /// Input offset and lines when this run started, so a resumed run's
+    /// rate counts only its own rows
+    from: (u64, usize),
+    size: u64,
+}
+
+impl Progress {
+    fn show(&mut self, offset: u64, lines: usize, done: bool) {
+        if !done
+            && self
+                .shown
+                .is_some_and(|shown| shown.elapsed().as_secs() < 1)
+        {
+            return;
+        }
+        self.shown = Some(Instant::now());
+        let elapsed = self.started.elapsed().as_secs_f64().max(1e-9);
+        let rate = (lines - self.from.1) as f64 / elapsed;
+        let byte_rate = (offset - self.from.0) as f64 / elapsed;
+        let eta = match byte_rate > 0.0 {
+            true => {
+                let left = (self.size.saturating_sub(offset) as f64 / byte_rate).round() as u64;
+                format!("{}:{:02}", left / 60, left % 60)
+            }
+            false => "?".to_string(),
+        };
+        eprint!(
+            "\r{} rows, {:.0} rows/s, {:.1}%, ETA {}{}",
+            lines,
+            rate,
+            100.0 * offset as f64 / self.size.max(1) as f64,
+            eta,
+            if done { "\n" } else { "" }
+        );
+    }
+}
+
+/// A line as `lines()` gives it, without its "\n" or "\r\n"
+fn content(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+fn process(options: Options) -> Result<TimeWarnings, String> {
+    let file = File::open(&options.path).map_err(|e| format!("{}: {}", options.path, e))?;
+    let size = file
+        .metadata()
+        .map_err(|e| format!("{}: {}", options.path, e))?
+        .len();
+    let mut input = io::BufReader::new(file);
+    let mut line = String::new();
+    let read_line = |input: &mut io::BufReader<File>, line: &mut String| {
+        line.clear();
+        input
+            .read_line(line)
+            .map_err(|e| format!("{}: {}", options.path, e))
+    };
+    let header_bytes = read_line(&mut input, &mut line)?;
+    if header_bytes == 0 {
+        return Err(format!("{}: empty file", options.path));
+    }
+    let header = config::split_csv_line(content(&line));
+    let input_columns: Vec<usize> = options
+        .system
+        .inputs
+        .iter()
+        .map(|var| {
+            header
+                .iter()
+                .position(|h| *h == var.name)
+                .ok_or_else(|| format!("{}: missing column '{}'", options.path, var.name))
+        })
+        .collect::<Result<_, _>>()?;
+    let time_column = header.iter().position(|h| h == "timestamp" || h == "time");
+    if options.validate_time && time_column.is_none() {
+        return Err(format!(
+            "{}: --validate-time needs a 'timestamp' or 'time' column",
+            options.path
+        ));
+    }
+
+    let controller = FuzzyController::from_system(options.system.clone());
+    let mut run = Run::new(&options.system);
+    let mut checkpoint = match &options.checkpoint {
+        Some(path) => {
+            let (input_size, input_crc) = checkpoint::fingerprint(&options.path)?;
+            let start = Checkpoint {
+                args: options.args,
+                input_size,
+                input_crc,
+                offset: header_bytes as u64,
+                lines: 0,
+                output_bytes: 0,
+                state: Vec::new(),
+            };
+            let saved = Checkpoint::load(path)?;
+            if let Some(saved) = &saved {
+                if saved.args != start.args {
+                    return Err(format!(
+                        "{}: written for other arguments; remove it to start over",
+                        path
+                    ));
+                }
+                if (saved.input_size, saved.input_crc) != (input_size, input_crc) {
+                    return Err(format!(
+                        "{}: {} changed since the checkpoint; remove it to start over",
+                        path, options.path
+                    ));
+                }
+            }
+            Some((path, saved.unwrap_or(start)))
+        }
+        None => None,
+    };
+
+    let (mut offset, mut lines) = (header_bytes as u64, 0);
+    let mut out = match (&options.out, &checkpoint) {
+        (Some(out), Some((path, saved))) if saved.lines > 0 || saved.output_bytes > 0 => {
+            let error = |e: io::Error| format!("{}: {}", out, e);
+            let mut file = OpenOptions::new().write(true).open(out).map_err(error)?;
+            if file.metadata().map_err(error)?.len() < saved.output_bytes {
+                return Err(format!(
+                    "{}: shorter than {} says it was written",
+                    out, path
+                ));
+            }
+            file.set_len(saved.output_bytes).map_err(error)?;
+            file.seek(SeekFrom::End(0)).map_err(error)?;
+            input
+                .seek(SeekFrom::Start(saved.offset))
+                .map_err(|e| format!("{}: {}", options.path, e))?;
+            run.restore(&saved.state)
+                .ok_or_else(|| format!("{}: state does not fit this config", path))?;
+            (offset, lines) = (saved.offset, saved.lines);
+            eprintln!("{}: resuming at row {}", options.path, lines + 2);
+            Output::File(out.clone(), file)
+        }
+        (Some(out), _) => {
+            let file = File::create(out).map_err(|e| format!("{}: {}", out, e))?;
+            Output::File(out.clone(), file)
+        }
+        (None, _) => Output::Stdout(io::stdout().lock()),
+    };
+    if lines == 0 {
+        let header = format!("{}\n", output_header(&options.system, &header));
+        out.write(&header, false)?;
+        if let Some((_, saved)) = &mut checkpoint {
+            saved.output_bytes = header.len() as u64;
+        }
+    }
+
+    let mut progress = options.progress.then(|| Progress {
+        started: Instant::now(),
+        shown: None,
+        from: (offset, lines),
+        size,
+    });
+    let mut chunk = String::new();
+    let mut chunk_lines = 0;
+    #[cfg(test)]
+    let mut chunks = 0;
+    loop {
+        let bytes = read_line(&mut input, &mut line)?;
+        if bytes > 0 {
+            let columns = (&input_columns[..], time_column);
+            if let Some(row) = run.row(&options, &controller, columns, lines, content(&line))? {
+                chunk.push_str(&row);
+            }
+            offset += bytes as u64;
+            lines += 1;
+            chunk_lines += 1;
+        }
+        if chunk_lines == options.chunk_rows || (bytes == 0 && chunk_lines > 0) {
+            out.write(&chunk, checkpoint.is_some())?;
+            if let Some((path, saved)) = &mut checkpoint {
+                saved.offset = offset;
+                saved.lines = lines;
+                saved.output_bytes += chunk.len() as u64;
+                saved.state = run.save();
+                saved.save(path)?;
+            }
+            chunk.clear();
+            chunk_lines = 0;
+            #[cfg(test)]
+            {
+                chunks += 1;
+                if options.stop_after == Some(chunks) {
+                    return Err("stopped".to_string());
+                }
+            }
+        }
+        if let Some(progress) = &mut progress {
+            progress.show(offset, lines, bytes == 0);
+        }
+        if bytes == 0 {
+            break;
+        }
+    }
+
+    if let Some((path, _)) = checkpoint {
+        std::fs::remove_file(path).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    if run.support_warnings > 0 {
+        eprintln!(
+            "{} rows with an output outside the activated sets",
+            run.support_warnings
+        );
+    }
+    if let Some(objective) = &run.objective {
+        eprintln!("objective: {}", objective.summary());
+    }
+    Ok(run.warnings)
+}
+
+/// `batch` subcommand. Exit code 0 on success, 1 when time validation
+/// produced warnings and 2 on errors.
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    let validate_time = options.validate_time;
+
+    match process(options) {
+        Ok(warnings) => {
+            if !validate_time {
+                return 0;
+            }
+            eprintln!("Time validation summary:");
+            eprintln!("  unparseable timestamps: {}", warnings.unparseable);
+            eprintln!("  duplicate timestamps:   {}", warnings.duplicate);
+            eprintln!("  out-of-order rows:      {}", warnings.out_of_order);
+            eprintln!("  gaps:                   {}", warnings.gaps);
+            if warnings.total() > 0 { 1 } else { 0 }
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// Options for `path` as `batch path` parses them
+    fn options(path: &str) -> Options {
+        parse_args(&[path.to_string()]).unwrap()
+    }
+
+    /// Time validation of a fixture under tests/data
+    fn validate(name: &str, policy: TimePolicy, max_gap: Option<f64>) -> TimeWarnings {
+        process(Options {
+            validate_time: true,
+            policy,
+            max_gap,
+            ..options(&format!(
+                "{}/tests/data/{}",
+                env!("CARGO_MANIFEST_DIR"),
+                name
+            ))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn duplicates_are_counted_under_either_policy() {
+        for policy in [TimePolicy::Warn, TimePolicy::Reject] {
+            let warnings = validate("time-duplicates.csv", policy, None);
+            assert_eq!(warnings.duplicate, 2);
+            assert_eq!(warnings.total(), 2);
+        }
+    }
+
+    #[test]
+    fn reversals_and_unreadable_times_are_told_apart() {
+        let warnings = validate("time-reversals.csv", TimePolicy::Warn, None);
+        assert_eq!(warnings.out_of_order, 1);
+        assert_eq!(warnings.unparseable, 1);
+        assert_eq!(warnings.total(), 2);
+    }
+
+    #[test]
+    fn gaps_count_only_past_the_threshold() {
+        let warnings = validate("time-gaps.csv", TimePolicy::Warn, Some(60.0));
+        assert_eq!(warnings.gaps, 2);
+        assert_eq!(warnings.total(), 2);
+        let warnings = validate("time-gaps.csv", TimePolicy::Warn, Some(200.0));
+        assert_eq!(warnings.gaps, 1);
+        let warnings = validate("time-gaps.csv", TimePolicy::Warn, None);
+        assert_eq!(warnings.total(), 0);
+    }
+
+    #[test]
+    fn a_file_without_a_time_column_is_refused() {
+        let error = process(Options {
+            validate_time: true,
+            ..options(&format!(
+                "{}/tests/data/no-time.csv",
+                env!("CARGO_MANIFEST_DIR")
+            ))
+        })
+        .unwrap_err();
+        assert!(
+            error.ends_with("no-time.csv: --validate-time needs a 'timestamp' or 'time' column")
+        );
+    }
+
+    #[test]
+    fn output_columns_name_their_unit() {
+        let header = ["timestamp", "temperature", "humidity"].map(String::from);
+        assert_eq!(
+            output_header(&fixtures::fan(), &header),
+            "timestamp,temperature,humidity,fan_speed [%],output [%],band"
+        );
+    }
+
+    #[test]
+    fn a_locale_reads_decimal_commas() {
+        let run = |locale| {
+            process(Options {
+                locale,
+                ..options(&format!(
+                    "{}/tests/data/comma-decimals.csv",
+                    env!("CARGO_MANIFEST_DIR")
+                ))
+            })
+        };
+        assert!(run(Some(Locale::Comma)).is_ok());
+        assert!(
+            run(None)
+                .unwrap_err()
+                .ends_with("comma-decimals.csv:2: invalid input value")
+        );
+        // "30,125" could be a thousands separator
+        assert!(
+            run(Some(Locale::Auto))
+                .unwrap_err()
+                .ends_with("comma-decimals.csv:3: '30,125' is ambiguous: 30.125 or 30125? Type '.' for the decimal point")
+        );
+    }
+
+    /// A CSV of `rows` readings one second apart, and a system whose
+    /// pipeline, cascade and objective all carry state from row to row
+    fn stateful_run(name: &str, rows: usize) -> (String, FuzzySystem) {
+        let dir = std::env::temp_dir().join(format!("fuzzy-batch-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut csv = "time,temperature,humidity\n".to_string();
+        for i in 0..rows {
+            let t = i as f64;
+            csv += &format!(
+                "{},{:.2},{:.2}\n",
+                i,
+                22.0 + 12.0 * (t / 17.0).sin(),
+                40.0 + 15.0 * (t / 29.0).cos()
+            );
+        }
+        let path = dir.join("in.csv").to_string_lossy().into_owned();
+        std::fs::write(&path, csv).unwrap();
+
+        let mut system = fixtures::fan();
+        system.pipeline.rate_limit = 5.0;
+        system.pipeline.hysteresis = 3.0;
+        system.pipeline.min_on_secs = 4.0;
+        system.pipeline.min_off_secs = 6.0;
+        system.cascade = Some(crate::cascade::CascadeConfig {
+            kp: 0.5,
+            ki: 0.2,
+            kd: 0.1,
+            ..crate::cascade::CascadeConfig::new("humidity")
+        });
+        let score = crate::alarm::Expression::parse("abs(temperature - 22)", &system).unwrap();
+        system.objective = Some(crate::objective::ObjectiveConfig {
+            score,
+            better: Default::default(),
+        });
+        (path, system)
+    }
+
+    #[test]
+    fn a_killed_run_resumes_to_the_output_of_an_uninterrupted_one() {
+        let (path, system) = stateful_run("resume", 250);
+        let whole = format!("{}.whole", path);
+        let resumed = format!("{}.resumed", path);
+        let checkpoint = format!("{}.checkpoint", path);
+        let run = |out: &str, checkpoint: Option<&str>, stop_after| {
+            process(Options {
+                system: system.clone(),
+                validate_time: true,
+                out: Some(out.to_string()),
+                checkpoint: checkpoint.map(String::from),
+                chunk_rows: 40,
+                stop_after,
+                ..options(&path)
+            })
+        };
+        run(&whole, None, None).unwrap();
+
+        assert_eq!(
+            run(&resumed, Some(&checkpoint), Some(3)).unwrap_err(),
+            "stopped"
+        );
+        let saved = Checkpoint::load(&checkpoint).unwrap().unwrap();
+        assert_eq!(saved.lines, 120);
+        // Killed halfway through writing the fourth chunk
+        let mut out = OpenOptions::new().append(true).open(&resumed).unwrap();
+        out.write_all(b"120,23.1,4").unwrap();
+
+        run(&resumed, Some(&checkpoint), None).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&resumed).unwrap(),
+            std::fs::read_to_string(&whole).unwrap()
+        );
+        // Done: a re-run starts over
+        assert!(Checkpoint::load(&checkpoint).unwrap().is_none());
+        std::fs::remove_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn a_changed_input_or_other_arguments_do_not_resume() {
+        let (path, system) = stateful_run("changed", 100);
+        let out = format!("{}.out", path);
+        let checkpoint = format!("{}.checkpoint", path);
+        let run = |args: u32| {
+            process(Options {
+                system: system.clone(),
+                out: Some(out.clone()),
+                checkpoint: Some(checkpoint.clone()),
+                chunk_rows: 30,
+                args,
+                stop_after: Some(1),
+                ..options(&path)
+            })
+        };
+        assert_eq!(run(1).unwrap_err(), "stopped");
+        assert!(
+            run(2)
+                .unwrap_err()
+                .ends_with("written for other arguments; remove it to start over")
+        );
+
+        let mut input = OpenOptions::new().append(true).open(&path).unwrap();
+        input.write_all(b"100,20,40\n").unwrap();
+        assert!(
+            run(1)
+                .unwrap_err()
+                .ends_with("in.csv changed since the checkpoint; remove it to start over")
+        );
+
+        assert_eq!(
+            parse_args(&[
+                "in.csv".to_string(),
+                "--checkpoint".to_string(),
+                "c".to_string()
+            ])
+            .err()
+            .unwrap(),
+            "--checkpoint needs --out FILE: stdout cannot be resumed"
+        );
+        std::fs::remove_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+    }
+}
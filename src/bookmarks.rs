@@ -0,0 +1,338 @@
+// ============================================================================
+// BOOKMARKS - Pontos de operação marcados
+// ============================================================================
+//
+// Input pairs worth coming back to while exploring ("this is where it
+// oscillates"). A bookmark keeps the raw readings and the outputs they gave
+// when it was saved, and lives in the app state file next to the rule
+// statistics (state.rs):
+//
+//     [[bookmark]]
+//     name = "oscillates"
+//     inputs = [27.5, 64.0]      # raw readings, in declaration order
+//     outputs = [58.25]          # when saved
+//     saved = 1760612345.2       # wall-clock epoch seconds
+//
+// Comparing the recorded outputs with the current ones shows which points
+// an edit of the rule base moved: more than scenario::TRACE_TOLERANCE is
+// drift. A bookmark that proved its worth can be promoted into a scenario
+// preset, which then guards it with `check --scenarios`.
+
+use crate::FuzzyController;
+use crate::config::{self, Section};
+use crate::scenario::{self, Scenario, TRACE_TOLERANCE, Trace};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    pub inputs: Vec<f64>,
+    pub outputs: Vec<f64>,
+    pub saved: f64,
+}
+
+/// A bookmark next to what the controller makes of it now
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    /// Outputs now; `None` when the system has another number of inputs
+    pub current: Option<Vec<f64>>,
+    pub drifted: bool,
+}
+
+impl Bookmark {
+    pub fn compare(&self, controller: &FuzzyController) -> Comparison {
+        if self.inputs.len() != controller.system.inputs.len() {
+            return Comparison {
+                current: None,
+                drifted: true,
+            };
+        }
+        let current = Trace::capture(controller, &self.inputs).outputs;
+        let drifted = current.len() != self.outputs.len()
+            || current
+                .iter()
+                .zip(&self.outputs)
+                .any(|(now, then)| (now - then).abs() > TRACE_TOLERANCE);
+        Comparison {
+            current: Some(current),
+            drifted,
+        }
+    }
+}
+
+/// The session's bookmarks, in the order they were saved
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bookmarks {
+    items: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    pub fn list(&self) -> &[Bookmark] {
+        &self.items
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Bookmark> {
+        self.items.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    fn check_name(&self, name: &str, except: Option<usize>) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("a bookmark needs a name".to_string());
+        }
+        let taken = self
+            .items
+            .iter()
+            .enumerate()
+            .any(|(i, b)| b.name == name && Some(i) != except);
+        if taken {
+            return Err(format!("a bookmark named '{}' exists", name));
+        }
+        Ok(name.to_string())
+    }
+
+    /// Bookmark raw `inputs` where `controller` gives its current outputs,
+    /// at wall-clock `now`
+    pub fn add(
+        &mut self,
+        name: &str,
+        inputs: Vec<f64>,
+        controller: &FuzzyController,
+        now: f64,
+    ) -> Result<(), String> {
+        let name = self.check_name(name, None)?;
+        let outputs = Trace::capture(controller, &inputs).outputs;
+        self.items.push(Bookmark {
+            name,
+            inputs,
+            outputs,
+            saved: now,
+        });
+        Ok(())
+    }
+
+    pub fn rename(&mut self, index: usize, name: &str) -> Result<(), String> {
+        let name = self.check_name(name, Some(index))?;
+        let bookmark = self
+            .items
+            .get_mut(index)
+            .ok_or_else(|| format!("no bookmark {}", index + 1))?;
+        bookmark.name = name;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Bookmark> {
+        (index < self.items.len()).then(|| self.items.remove(index))
+    }
+
+    /// Bookmarks from the contents of a state file; other sections are
+    /// left to their owners
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let items = config::parse_document(text)?
+            .iter()
+            .filter(|s| s.header == "bookmark")
+            .map(bookmark_from_section)
+            .collect::<Result<_, _>>()?;
+        Ok(Bookmarks { items })
+    }
+
+    /// Bookmarks from a state file; a missing file has none
+    pub fn load(path: &str) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Bookmarks::parse(&text).map_err(|e| format!("{}: {}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Bookmarks::default()),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        }
+    }
+
+    /// The `[[bookmark]]` sections of the state file
+    pub fn to_toml(&self) -> String {
+        let mut out = String::new();
+        for bookmark in &self.items {
+            out.push_str(&format!(
+                "\n[[bookmark]]\nname = {}\ninputs = {}\noutputs = {}\nsaved = {}\n",
+                config::quote(&bookmark.name),
+                numbers(&bookmark.inputs),
+                numbers(&bookmark.outputs),
+                bookmark.saved
+            ));
+        }
+        out
+    }
+}
+
+fn numbers(values: &[f64]) -> String {
+    let items: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn bookmark_from_section(section: &Section) -> Result<Bookmark, String> {
+    Ok(Bookmark {
+        name: section.str("name")?.to_string(),
+        inputs: section.nums("inputs")?,
+        outputs: section.nums("outputs")?,
+        saved: section.num_or("saved", 0.0)?,
+    })
+}
+
+/// Add `bookmark` to the scenario presets at `path` (a missing file is
+/// started), expecting the first output `controller` gives there now
+pub fn promote(
+    bookmark: &Bookmark,
+    path: &str,
+    controller: &FuzzyController,
+) -> Result<(), String> {
+    let mut scenarios = match std::fs::read_to_string(path) {
+        Ok(text) => scenario::parse(&text).map_err(|e| format!("{}: {}", path, e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(format!("{}: {}", path, e)),
+    };
+    if scenarios.iter().any(|s| s.name == bookmark.name) {
+        return Err(format!(
+            "{}: a scenario named '{}' exists",
+            path, bookmark.name
+        ));
+    }
+    let comparison = bookmark.compare(controller);
+    let Some(current) = comparison.current else {
+        return Err(format!(
+            "bookmark '{}' has {} inputs, the system {}",
+            bookmark.name,
+            bookmark.inputs.len(),
+            controller.system.inputs.len()
+        ));
+    };
+    scenarios.push(Scenario {
+        name: bookmark.name.clone(),
+        inputs: bookmark.inputs.clone(),
+        expect: Some(current[0]),
+        tolerance: 0.01,
+        golden: false,
+        trace: None,
+    });
+    std::fs::write(path, scenario::to_toml(&scenarios)).map_err(|e| format!("{}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn saved() -> Bookmarks {
+        let controller = FuzzyController::from_system(fixtures::fan());
+        let mut bookmarks = Bookmarks::default();
+        bookmarks
+            .add("oscillates", vec![27.5, 64.0], &controller, 10.0)
+            .unwrap();
+        bookmarks
+            .add(" cold \"corner\" ", vec![2.0, 10.0], &controller, 20.5)
+            .unwrap();
+        bookmarks
+    }
+
+    #[test]
+    fn names_are_checked_on_add_and_rename() {
+        let controller = FuzzyController::from_system(fixtures::fan());
+        let mut bookmarks = saved();
+        assert_eq!(bookmarks.list()[1].name, "cold \"corner\"");
+        assert_eq!(
+            bookmarks.add("oscillates", vec![1.0, 1.0], &controller, 0.0),
+            Err("a bookmark named 'oscillates' exists".to_string())
+        );
+        assert!(
+            bookmarks
+                .add("  ", vec![1.0, 1.0], &controller, 0.0)
+                .is_err()
+        );
+
+        assert!(bookmarks.rename(1, "oscillates").is_err());
+        // Keeping its own name is fine
+        bookmarks.rename(0, "oscillates").unwrap();
+        bookmarks.rename(1, "corner").unwrap();
+        assert_eq!(bookmarks.list()[1].name, "corner");
+        assert!(bookmarks.rename(5, "x").is_err());
+
+        assert_eq!(bookmarks.remove(0).unwrap().name, "oscillates");
+        assert_eq!(bookmarks.remove(3), None);
+        assert_eq!(bookmarks.len(), 1);
+    }
+
+    #[test]
+    fn bookmarks_round_trip_next_to_the_rule_statistics() {
+        let bookmarks = saved();
+        let stats = crate::state::RuleUsageStats::new(vec!["IF a IS b THEN c IS d".to_string()]);
+        let text = format!("{}{}", stats.to_toml(), bookmarks.to_toml());
+        assert_eq!(Bookmarks::parse(&text).unwrap(), bookmarks);
+        assert_eq!(
+            Bookmarks::parse("# fuzzy_logic app state\n").unwrap(),
+            Bookmarks::default()
+        );
+        assert!(Bookmarks::parse("[[bookmark]]\nname = \"x\"\n").is_err());
+    }
+
+    #[test]
+    fn an_edited_rule_base_shows_as_drift() {
+        let bookmarks = saved();
+        let unchanged = FuzzyController::from_system(fixtures::fan());
+        let comparison = bookmarks.list()[0].compare(&unchanged);
+        assert!(!comparison.drifted);
+        assert_eq!(comparison.current.unwrap(), bookmarks.list()[0].outputs);
+
+        // Hot and humid air now only asks for a medium fan
+        let mut system = fixtures::fan();
+        for rule in &mut system.rules {
+            for (_, set) in &mut rule.consequents {
+                if set == "High" {
+                    *set = "Medium".to_string();
+                }
+            }
+        }
+        let edited = FuzzyController::from_system(system.clone());
+        assert!(bookmarks.list()[0].compare(&edited).drifted);
+        // The cold corner never reached those rules
+        assert!(!bookmarks.list()[1].compare(&edited).drifted);
+
+        system.inputs.pop();
+        let narrower = FuzzyController::from_system(system);
+        assert_eq!(
+            bookmarks.list()[1].compare(&narrower),
+            Comparison {
+                current: None,
+                drifted: true
+            }
+        );
+    }
+
+    #[test]
+    fn a_bookmark_is_promoted_into_the_scenario_presets() {
+        let path = std::env::temp_dir().join(format!(
+            "fuzzy-bookmarks-{}.scenarios.toml",
+            std::process::id()
+        ));
+        let path = path.to_string_lossy().to_string();
+        let controller = FuzzyController::from_system(fixtures::fan());
+        let bookmarks = saved();
+        promote(&bookmarks.list()[0], &path, &controller).unwrap();
+        promote(&bookmarks.list()[1], &path, &controller).unwrap();
+        let again = promote(&bookmarks.list()[0], &path, &controller);
+        let scenarios = scenario::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(
+            again
+                .unwrap_err()
+                .contains("a scenario named 'oscillates' exists")
+        );
+        assert_eq!(scenarios.len(), 2);
+        assert_eq!(scenarios[0].inputs, [27.5, 64.0]);
+        assert_eq!(scenarios[0].expect, Some(bookmarks.list()[0].outputs[0]));
+        assert!(scenario::check_all(&scenarios, &controller).is_empty());
+    }
+}
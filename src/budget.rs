@@ -0,0 +1,419 @@
+// ============================================================================
+// LATENCY BUDGET - Orçamento de latência
+// ============================================================================
+//
+// On a target with a hard time budget per control cycle, a late output is
+// worse than a slightly coarser one. A system with a budget times each of
+// its computations and, when one runs over, makes the next ones cheaper:
+//
+//     [budget]               # optional (config.rs)
+//     cycle_ms = 2.0         # time one computation may take, above 0
+//     recover_cycles = 20    # calm cycles in a row before climbing back
+//
+// The cost of a computation is mostly defuzzification, which samples the
+// aggregated set across the output universe. The ladder, cheapest last:
+//
+//     rung       defuzzification                    demo error bound
+//     full       the output's resolution (100)      -
+//     half       half of it (50)                    1.0
+//     coarse     a fifth of it (20)                 3.0
+//     weighted   weighted average of the set        15.0
+//                centroids, each set weighted by
+//                its strongest rule (capped)
+//
+// The bounds are the largest difference from the full rung over the demo's
+// universe, in fan speed percent (the golden tests below hold them). The
+// weighted average ignores the aggregation and the plateau method: as a
+// rung it is the analytic fallback, not a replacement. An output whose
+// `method` is "weighted_average" takes it on every rung.
+//
+// Hysteresis keeps the rung from flapping:
+//
+//   - a cycle over `cycle_ms` steps one rung down, straight away
+//   - a cycle under RECOVER_SHARE of `cycle_ms` is calm; `recover_cycles`
+//     calm cycles in a row step one rung up, and start counting over
+//   - any other cycle, too slow to be calm, breaks the run of calm ones
+//
+// A computation following one that ran over also skips its trace, the
+// per-rule breakdown kept for cap hits and rule tracing (activation.rs),
+// when the max aggregation lets it: the other aggregations read it.
+//
+// Every cycle computed below the full rung counts as degraded. The daemon's
+// `stats` reply carries the count (remote.rs), and the TUI puts it and the
+// rung in the fan speed gauge's title.
+//
+// Time comes from a `Clock` (pipeline.rs), so tests drive slow cycles with
+// a fake one.
+
+use crate::activation::Activations;
+use crate::logging::event;
+use crate::{Defuzzification, FuzzyVariable};
+
+/// Share of the budget below which a cycle counts toward climbing back
+pub const RECOVER_SHARE: f64 = 0.5;
+
+/// How defuzzification samples the output, dearest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rung {
+    #[default]
+    Full,
+    Half,
+    Coarse,
+    WeightedAverage,
+}
+
+impl Rung {
+    pub const ALL: [Rung; 4] = [Rung::Full, Rung::Half, Rung::Coarse, Rung::WeightedAverage];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rung::Full => "full",
+            Rung::Half => "half",
+            Rung::Coarse => "coarse",
+            Rung::WeightedAverage => "weighted",
+        }
+    }
+
+    /// Samples across an output universe sampled `full` times on the full
+    /// rung, none for the weighted average
+    pub fn resolution(&self, full: usize) -> Option<usize> {
+        match self {
+            Rung::Full => Some(full),
+            Rung::Half => Some((full / 2).max(1)),
+            Rung::Coarse => Some((full / 5).max(1)),
+            Rung::WeightedAverage => None,
+        }
+    }
+
+    /// The cheaper rung, this one at the bottom
+    fn down(self) -> Self {
+        let index = Self::ALL.iter().position(|r| *r == self).unwrap_or(0);
+        Self::ALL[(index + 1).min(Self::ALL.len() - 1)]
+    }
+
+    /// The dearer rung, this one at the top
+    fn up(self) -> Self {
+        let index = Self::ALL.iter().position(|r| *r == self).unwrap_or(0);
+        Self::ALL[index.saturating_sub(1)]
+    }
+}
+
+/// `[budget]` settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetConfig {
+    /// Time one computation may take, in milliseconds
+    pub cycle_ms: f64,
+    /// Calm cycles in a row before climbing one rung
+    pub recover_cycles: usize,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        BudgetConfig {
+            cycle_ms: 2.0,
+            recover_cycles: 20,
+        }
+    }
+}
+
+/// The rung computations run at, moved by how long they take
+#[derive(Debug, Clone)]
+pub struct Budget {
+    config: BudgetConfig,
+    rung: Rung,
+    /// Calm cycles in a row
+    calm: usize,
+    /// The last cycle ran over
+    over: bool,
+    /// Cycles computed below the full rung
+    degraded: usize,
+}
+
+impl Budget {
+    pub fn new(config: BudgetConfig) -> Self {
+        Budget {
+            config,
+            rung: Rung::Full,
+            calm: 0,
+            over: false,
+            degraded: 0,
+        }
+    }
+
+    /// The rung the next computation runs at
+    pub fn rung(&self) -> Rung {
+        self.rung
+    }
+
+    /// Whether the next computation keeps its trace
+    pub fn tracing(&self) -> bool {
+        !self.over
+    }
+
+    pub fn degraded(&self) -> usize {
+        self.degraded
+    }
+
+    /// A computation at `rung()` took `elapsed` seconds
+    pub fn record(&mut self, elapsed: f64) {
+        if self.rung != Rung::Full {
+            self.degraded += 1;
+        }
+        let budget = self.config.cycle_ms / 1000.0;
+        self.over = elapsed > budget;
+        if self.over {
+            self.calm = 0;
+            let rung = self.rung.down();
+            if rung != self.rung {
+                event!(
+                    Warn,
+                    "computation took {:.3} ms of a {} ms budget, down to the {} rung",
+                    elapsed * 1000.0,
+                    self.config.cycle_ms,
+                    rung.name()
+                );
+            }
+            self.rung = rung;
+        } else if elapsed <= budget * RECOVER_SHARE {
+            self.calm += 1;
+            if self.calm >= self.config.recover_cycles && self.rung != Rung::Full {
+                self.calm = 0;
+                self.rung = self.rung.up();
+                event!(
+                    Info,
+                    "{} calm computations, back up to the {} rung",
+                    self.config.recover_cycles,
+                    self.rung.name()
+                );
+            }
+        } else {
+            self.calm = 0;
+        }
+    }
+
+    /// `degraded 12 cycles · coarse`, for the gauge title
+    pub fn describe(&self) -> String {
+        format!(
+            "degraded {} cycle{} · {}",
+            self.degraded,
+            if self.degraded == 1 { "" } else { "s" },
+            self.rung.name()
+        )
+    }
+}
+
+/// Centroid of each set of `output` over the output universe, for the
+/// weighted average
+pub fn centroids(output: &FuzzyVariable) -> Vec<f64> {
+    (0..output.sets.len())
+        .map(|set| output.set_centroid(set))
+        .collect()
+}
+
+/// The set centroids weighted by the strongest rule concluding each set,
+/// held to its cap; 0 when nothing fired, as the sampled paths give
+pub(crate) fn weighted_average(
+    activations: &Activations,
+    output: &FuzzyVariable,
+    settings: &Defuzzification,
+    centroids: &[f64],
+) -> f64 {
+    let (mut moment, mut weight) = (0.0, 0.0);
+    for (index, (set, centroid)) in output.sets.iter().zip(centroids).enumerate() {
+        let peak = activations.peak(index);
+        if peak <= 0.0 {
+            continue;
+        }
+        let peak = settings
+            .cap(&output.name, &set.name)
+            .map_or(peak, |max| peak.min(max));
+        moment += peak * centroid;
+        weight += peak;
+    }
+    if weight == 0.0 { 0.0 } else { moment / weight }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FuzzySystem;
+    use crate::activation::Session;
+    use crate::fixtures;
+    use crate::pipeline::Clock;
+    use std::cell::Cell;
+
+    /// Time that moves `cycle` seconds across each computation: every other
+    /// reading is a computation's end
+    struct SlowClock {
+        now: Cell<f64>,
+        cycle: Cell<f64>,
+        reads: Cell<usize>,
+    }
+
+    impl SlowClock {
+        fn new(cycle_ms: f64) -> Self {
+            SlowClock {
+                now: Cell::new(0.0),
+                cycle: Cell::new(cycle_ms / 1000.0),
+                reads: Cell::new(0),
+            }
+        }
+
+        fn set(&self, cycle_ms: f64) {
+            self.cycle.set(cycle_ms / 1000.0);
+        }
+    }
+
+    impl Clock for SlowClock {
+        fn now(&self) -> f64 {
+            let reads = self.reads.get() + 1;
+            self.reads.set(reads);
+            if reads.is_multiple_of(2) {
+                self.now.set(self.now.get() + self.cycle.get());
+            }
+            self.now.get()
+        }
+    }
+
+    fn budget(recover_cycles: usize) -> Budget {
+        Budget::new(BudgetConfig {
+            cycle_ms: 2.0,
+            recover_cycles,
+        })
+    }
+
+    #[test]
+    fn slow_cycles_step_down_and_calm_runs_climb_back_one_rung_at_a_time() {
+        let mut budget = budget(3);
+        let mut rungs = Vec::new();
+        // Over, over, over, over (stays at the bottom), then calm cycles
+        for elapsed_ms in [3.0, 3.0, 3.0, 3.0, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5] {
+            budget.record(elapsed_ms / 1000.0);
+            rungs.push(budget.rung());
+        }
+        use Rung::*;
+        assert_eq!(
+            rungs,
+            [
+                Half,
+                Coarse,
+                WeightedAverage,
+                WeightedAverage,
+                WeightedAverage,
+                WeightedAverage,
+                Coarse,
+                Coarse,
+                Coarse,
+                Half
+            ]
+        );
+        // Every cycle but the first ran below the full rung
+        assert_eq!(budget.degraded(), 9);
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn each_step_down_and_climb_is_logged_once() {
+        use log::Level::{Info, Warn};
+
+        let mut budget = budget(3);
+        let logged = crate::logging::capture(|| {
+            for elapsed_ms in [3.0, 3.0, 3.0, 3.0, 3.0, 0.5, 0.5, 0.5, 1.5, 0.5] {
+                budget.record(elapsed_ms / 1000.0);
+            }
+        });
+        let line = |text: &str| format!("budget: {}", text);
+        assert_eq!(
+            logged,
+            [
+                (
+                    Warn,
+                    line("computation took 3.000 ms of a 2 ms budget, down to the half rung")
+                ),
+                (
+                    Warn,
+                    line("computation took 3.000 ms of a 2 ms budget, down to the coarse rung")
+                ),
+                (
+                    Warn,
+                    line("computation took 3.000 ms of a 2 ms budget, down to the weighted rung")
+                ),
+                (
+                    Info,
+                    line("3 calm computations, back up to the coarse rung")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn cycles_near_the_budget_neither_climb_nor_flap() {
+        let mut budget = budget(2);
+        budget.record(0.003);
+        assert_eq!(budget.rung(), Rung::Half);
+        // Within the budget but not calm: the run of calm cycles starts over
+        for elapsed_ms in [0.5, 1.5, 0.5, 1.5, 0.5, 1.9] {
+            budget.record(elapsed_ms / 1000.0);
+            assert_eq!(budget.rung(), Rung::Half);
+        }
+        budget.record(0.0005);
+        budget.record(0.0005);
+        assert_eq!(budget.rung(), Rung::Full);
+        assert_eq!(budget.describe(), "degraded 8 cycles · full");
+    }
+
+    #[test]
+    fn a_session_degrades_on_a_slow_clock_and_drops_its_trace_after_an_overrun() {
+        let system = fixtures::fan();
+        let clock = SlowClock::new(5.0);
+        let mut budget = budget(3);
+        let mut full = Session::new(&system);
+        let point = [27.5, 64.0];
+        let exact = full.compute(&point);
+        {
+            let mut session = Session::within(&system, &mut budget, &clock);
+            session.compute(&point);
+            assert!(!session.activations(0).fired().is_empty());
+            // The overrun costs the next cycle its trace but not its peaks
+            session.compute(&point);
+            assert!(session.activations(0).fired().is_empty());
+            assert!(!session.activations(0).is_empty());
+            assert_eq!(session.strengths(), full.strengths());
+            clock.set(0.1);
+            session.compute(&point);
+            assert!(session.activations(0).fired().is_empty());
+            let coarse = session.compute(&point);
+            assert!((coarse - exact).abs() <= 3.0, "{} vs {}", coarse, exact);
+            assert!(!session.activations(0).fired().is_empty());
+        }
+        assert_eq!(budget.rung(), Rung::Coarse);
+        assert_eq!(budget.degraded(), 3);
+    }
+
+    /// Largest difference from the full rung across the demo's universe
+    fn worst_error(system: &FuzzySystem, rung: Rung) -> f64 {
+        let mut full = Session::new(system);
+        let clock = SlowClock::new(0.0);
+        let mut budget = budget(usize::MAX);
+        budget.rung = rung;
+        let mut session = Session::within(system, &mut budget, &clock);
+        let mut worst: f64 = 0.0;
+        for t in 0..=50 {
+            for h in 0..=50 {
+                let point = [t as f64, h as f64 * 2.0];
+                worst = worst.max((session.compute(&point) - full.compute(&point)).abs());
+            }
+        }
+        worst
+    }
+
+    #[test]
+    fn each_rung_stays_within_its_documented_error_on_the_demo() {
+        let system = fixtures::fan();
+        let bounds = [0.0, 1.0, 3.0, 15.0];
+        for (rung, bound) in Rung::ALL.into_iter().zip(bounds) {
+            let error = worst_error(&system, rung);
+            assert!(error <= bound, "{}: {} above {}", rung.name(), error, bound);
+        }
+    }
+}
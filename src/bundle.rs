@@ -0,0 +1,509 @@
+// ============================================================================
+// BUG REPORT BUNDLES - Pacote para relatórios
+// ============================================================================
+//
+// `bundle --out report.tar.gz` packs everything needed to reproduce a
+// session into one file:
+//
+//     manifest.txt   version, creation time (UTC, SOURCE_DATE_EPOCH when
+//                    set, timestamp.rs), where the system came from and
+//                    its name/version/author from `[metadata]`
+//     config.toml    the resolved system, re-serialized from the model;
+//                    `--keep-links` keeps the set parameter links (links.rs)
+//     history.csv    the recorded inputs and outputs (TUI bundles only),
+//                    with the what-if column when the TUI showed it and
+//                    the interlock that forced the output, if any
+//     state.toml     the rule usage statistics, when a state file is in use
+//
+// `bundle --open report.tar.gz` replays the history through the bundled
+// system in the TUI and reports any entry whose output differs from the
+// recorded one. Entries recorded with sensor noise (noise.rs) are replayed
+// with the values the sensors reported.
+//
+// Writing the config from the model rather than copying the file means
+// comments and keys the parser does not know never leave the machine.
+//
+// Without a compression crate the gzip stream uses stored (uncompressed)
+// deflate blocks. Any gzip/tar tool reads the result, but `--open` only
+// accepts stored blocks, i.e. bundles written by this program.
+
+use crate::FuzzySystem;
+use crate::config;
+use crate::generator::GeneratorKind;
+use crate::state::RuleUsageStats;
+use crate::timestamp;
+
+const USAGE: &str = "Usage: fuzzy_logic bundle --out FILE.tar.gz [--config FILE] [--state FILE] \
+                     [--keep-links]\n       \
+                     fuzzy_logic bundle --open FILE.tar.gz";
+
+/// One history row as stored in `history.csv`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryRow {
+    /// Seconds between the computation and the export
+    pub age_secs: f64,
+    pub temperature: f64,
+    pub humidity: f64,
+    pub fan_speed: f64,
+    pub status: String,
+    /// A failure injection was active (see inject.rs)
+    pub injected: bool,
+    /// Generator that drew the inputs, when random (see generator.rs)
+    pub generator: Option<GeneratorKind>,
+    /// Temperature and humidity the simulated sensors reported, when
+    /// noisy (see noise.rs)
+    pub sensed: Option<(f64, f64)>,
+    /// What the running rules gave for the row's readings at the export,
+    /// when the TUI's what-if column was shown
+    pub what_if: Option<f64>,
+    /// Interlock forcing the output, if one was (see interlock.rs)
+    pub interlock: Option<String>,
+}
+
+/// Contents of a bundle
+pub struct Bundle {
+    pub source: String,
+    pub system: FuzzySystem,
+    pub history: Vec<HistoryRow>,
+    pub state: Option<String>,
+    /// Write set parameter links rather than the numbers they resolved to
+    pub keep_links: bool,
+}
+
+impl Bundle {
+    /// Everything that goes into a bundle built right now; `state` is the
+    /// rule usage statistics, if they are being tracked
+    pub fn new(
+        source: &str,
+        system: &FuzzySystem,
+        history: Vec<HistoryRow>,
+        state: Option<&RuleUsageStats>,
+    ) -> Self {
+        Bundle {
+            source: source.to_string(),
+            system: system.clone(),
+            history,
+            state: state.map(RuleUsageStats::to_toml),
+            keep_links: false,
+        }
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let bytes = self.to_bytes(timestamp::export_time());
+        std::fs::write(path, bytes).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// The `.tar.gz` stamped as created at `created`, epoch seconds
+    pub fn to_bytes(&self, created: f64) -> Vec<u8> {
+        let mut manifest = format!(
+            "fuzzy_logic {}\ncreated {}\nsource {}\n",
+            env!("CARGO_PKG_VERSION"),
+            timestamp::rfc3339(created),
+            self.source
+        );
+        if let Some(summary) = self.system.metadata.summary() {
+            manifest.push_str(&format!("system {}\n", summary));
+        }
+        manifest.push_str(&format!("history {} entries\n", self.history.len()));
+
+        let mut csv = String::from(
+            "age_secs,temperature,humidity,fan_speed,status,injected,generator,\
+             sensed_temperature,sensed_humidity,what_if,interlock\n",
+        );
+        for row in &self.history {
+            let (sensed_temperature, sensed_humidity) = match row.sensed {
+                Some((t, h)) => (t.to_string(), h.to_string()),
+                None => (String::new(), String::new()),
+            };
+            csv.push_str(&format!(
+                "{:.3},{},{},{},{},{},{},{},{},{},{}\n",
+                row.age_secs,
+                row.temperature,
+                row.humidity,
+                row.fan_speed,
+                row.status,
+                row.injected,
+                row.generator.map(GeneratorKind::name).unwrap_or_default(),
+                sensed_temperature,
+                sensed_humidity,
+                row.what_if.map(|v| v.to_string()).unwrap_or_default(),
+                row.interlock.as_deref().unwrap_or_default()
+            ));
+        }
+
+        let mut files = vec![
+            ("manifest.txt", manifest),
+            (
+                "config.toml",
+                config::to_toml(&self.system, self.keep_links),
+            ),
+            ("history.csv", csv),
+        ];
+        if let Some(state) = &self.state {
+            files.push(("state.toml", state.clone()));
+        }
+
+        let mut archive = Vec::new();
+        for (name, contents) in &files {
+            tar_append(&mut archive, name, contents.as_bytes(), created as u64);
+        }
+        archive.extend_from_slice(&[0; 1024]);
+        gzip_stored(&archive)
+    }
+
+    pub fn read(path: &str) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        let archive = gunzip_stored(&data).map_err(|e| format!("{}: {}", path, e))?;
+        let files = tar_entries(&archive).map_err(|e| format!("{}: {}", path, e))?;
+        let file = |name: &str| {
+            files
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, contents)| String::from_utf8_lossy(contents).into_owned())
+        };
+
+        let manifest = file("manifest.txt").ok_or(format!("{}: missing manifest.txt", path))?;
+        let source = manifest
+            .lines()
+            .find_map(|line| line.strip_prefix("source "))
+            .unwrap_or("unknown")
+            .to_string();
+        let config = file("config.toml").ok_or(format!("{}: missing config.toml", path))?;
+        let system =
+            config::parse_system(&config).map_err(|e| format!("{}: config.toml: {}", path, e))?;
+
+        let mut history = Vec::new();
+        for (index, line) in file("history.csv")
+            .unwrap_or_default()
+            .lines()
+            .enumerate()
+            .skip(1)
+        {
+            let fields: Vec<&str> = line.split(',').collect();
+            let number = |i: usize| fields.get(i).and_then(|v| v.parse::<f64>().ok());
+            let row = (|| {
+                Some(HistoryRow {
+                    age_secs: number(0)?,
+                    temperature: number(1)?,
+                    humidity: number(2)?,
+                    fan_speed: number(3)?,
+                    status: fields.get(4)?.to_string(),
+                    // Missing in bundles from before failure injection
+                    injected: match fields.get(5) {
+                        None => false,
+                        Some(flag) => flag.parse().ok()?,
+                    },
+                    // Empty for inputs that were not random, missing in
+                    // older bundles
+                    generator: match fields.get(6) {
+                        None | Some(&"") => None,
+                        Some(name) => Some(GeneratorKind::parse(name)?),
+                    },
+                    // Empty without sensor noise, missing in older bundles
+                    sensed: match (fields.get(7), fields.get(8)) {
+                        (None, _) | (Some(&""), _) => None,
+                        _ => Some((number(7)?, number(8)?)),
+                    },
+                    // Empty without the what-if column, missing in older
+                    // bundles
+                    what_if: match fields.get(9) {
+                        None | Some(&"") => None,
+                        _ => Some(number(9)?),
+                    },
+                    // Empty while none was engaged, missing in older
+                    // bundles
+                    interlock: match fields.get(10) {
+                        None | Some(&"") => None,
+                        Some(name) => Some(name.to_string()),
+                    },
+                })
+            })();
+            history.push(row.ok_or(format!("{}: history.csv:{}: invalid row", path, index + 1))?);
+        }
+
+        Ok(Bundle {
+            source,
+            system,
+            history,
+            state: file("state.toml"),
+            keep_links: false,
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// tar (ustar) and gzip with stored blocks
+// ---------------------------------------------------------------------------
+
+fn octal(field: &mut [u8], value: u64) {
+    let text = format!("{:0width$o}\0", value, width = field.len() - 1);
+    field.copy_from_slice(text.as_bytes());
+}
+
+fn tar_append(archive: &mut Vec<u8>, name: &str, contents: &[u8], mtime: u64) {
+    let mut header = [0u8; 512];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], contents.len() as u64);
+    octal(&mut header[136..148], mtime);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is computed with its own field filled with spaces
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|b| *b as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+    archive.extend_from_slice(&header);
+    archive.extend_from_slice(contents);
+    archive.resize(archive.len().div_ceil(512) * 512, 0);
+}
+
+fn tar_entries(archive: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while pos + 512 <= archive.len() {
+        let header = &archive[pos..pos + 512];
+        if header.iter().all(|b| *b == 0) {
+            return Ok(entries);
+        }
+        let name_end = header[..100].iter().position(|b| *b == 0).unwrap_or(100);
+        let name = String::from_utf8_lossy(&header[..name_end]).into_owned();
+        let size_text = String::from_utf8_lossy(&header[124..136]);
+        let size = u64::from_str_radix(size_text.trim_matches(['\0', ' ']), 8)
+            .map_err(|_| format!("invalid tar header for '{}'", name))? as usize;
+        let start = pos + 512;
+        let contents = archive
+            .get(start..start + size)
+            .ok_or(format!("truncated tar entry '{}'", name))?;
+        entries.push((name, contents.to_vec()));
+        pos = start + size.div_ceil(512) * 512;
+    }
+    Err("truncated tar archive".to_string())
+}
+
+/// CRC-32 as gzip computes it
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn gzip_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+    let mut chunks = data.chunks(0xFFFF).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let len = chunk.len() as u16;
+        out.push(chunks.peek().is_none() as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+fn gunzip_stored(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 18 || data[..3] != [0x1f, 0x8b, 8] {
+        return Err("not a gzip file".to_string());
+    }
+    if data[3] != 0 {
+        return Err("unsupported gzip header flags".to_string());
+    }
+
+    let mut out = Vec::new();
+    let mut pos = 10;
+    loop {
+        let block = *data.get(pos).ok_or("truncated gzip stream")?;
+        if block & 0b110 != 0 {
+            return Err(
+                "compressed bundles are not supported, only ones written by \
+                        fuzzy_logic bundle"
+                    .to_string(),
+            );
+        }
+        let len = data
+            .get(pos + 1..pos + 3)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or("truncated gzip stream")?;
+        let contents = data
+            .get(pos + 5..pos + 5 + len)
+            .ok_or("truncated gzip stream")?;
+        out.extend_from_slice(contents);
+        pos += 5 + len;
+        if block & 1 != 0 {
+            break;
+        }
+    }
+
+    let crc = data
+        .get(pos..pos + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or("truncated gzip stream")?;
+    if crc != crc32(&out) {
+        return Err("gzip checksum mismatch".to_string());
+    }
+    Ok(out)
+}
+
+/// `bundle --out` subcommand: bundle a config and state file without a
+/// session history
+pub fn run(args: &[String]) -> i32 {
+    let mut out = None;
+    let mut config_path = None;
+    let mut state_path = None;
+    let mut keep_links = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--keep-links" {
+            keep_links = true;
+            continue;
+        }
+        match (arg.as_str(), iter.next()) {
+            ("--out", Some(path)) => out = Some(path.clone()),
+            ("--config", Some(path)) => config_path = Some(path.clone()),
+            ("--state", Some(path)) => state_path = Some(path.clone()),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    let Some(out) = out else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+
+    let result = (|| {
+        let (source, system) = match &config_path {
+            Some(path) => (path.as_str(), config::load_system(path)?),
+            None => ("built-in demo", FuzzySystem::demo()),
+        };
+        let stats = match &state_path {
+            Some(path) => Some(RuleUsageStats::load(path, system.rule_texts())?),
+            None => None,
+        };
+        let mut bundle = Bundle::new(source, &system, Vec::new(), stats.as_ref());
+        bundle.keep_links = keep_links;
+        bundle.write(&out)
+    })();
+    match result {
+        Ok(()) => {
+            eprintln!("Wrote {}", out);
+            0
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("fuzzy-{}-{}", std::process::id(), name))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn a_bundle_round_trips() {
+        let system = config::load_system("configs/fan.toml").unwrap();
+        let mut stats = RuleUsageStats::new(system.rule_texts());
+        stats.record(&vec![0.5; system.rules.len()], 1760612345.0);
+        let history = vec![
+            HistoryRow {
+                age_secs: 12.5,
+                temperature: 28.0,
+                humidity: 45.0,
+                fan_speed: 60.875,
+                status: "MEDIUM".to_string(),
+                injected: false,
+                generator: None,
+                sensed: Some((28.25, 44.5)),
+                what_if: None,
+                interlock: None,
+            },
+            HistoryRow {
+                age_secs: 0.0,
+                temperature: 35.0,
+                humidity: 80.0,
+                fan_speed: 81.25,
+                status: "HIGH".to_string(),
+                injected: true,
+                generator: Some(GeneratorKind::Boundary),
+                sensed: None,
+                what_if: Some(79.5),
+                interlock: Some("door".to_string()),
+            },
+        ];
+
+        let path = temp("round-trip.tar.gz");
+        Bundle::new("configs/fan.toml", &system, history.clone(), Some(&stats))
+            .write(&path)
+            .unwrap();
+        let bundle = Bundle::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(bundle.source, "configs/fan.toml");
+        assert_eq!(bundle.system, system);
+        assert_eq!(bundle.system.metadata.version.as_deref(), Some("1.0.0"));
+        assert_eq!(bundle.history, history);
+        assert_eq!(bundle.state, Some(stats.to_toml()));
+    }
+
+    #[test]
+    fn only_the_model_leaves_the_machine() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let secret = format!(
+            "# broker password: hunter2\nmqtt_password = \"hunter2\"\n{}",
+            fan.replacen("[output]", "[output]\nmqtt_topic = \"fan/hunter2\"", 1)
+        );
+        let system = config::parse_system(&secret).unwrap();
+
+        let path = temp("scrub.tar.gz");
+        Bundle::new("secret.toml", &system, Vec::new(), None)
+            .write(&path)
+            .unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let bundle = Bundle::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Stored blocks leave the text readable, so a plain search suffices
+        let text = String::from_utf8_lossy(&data);
+        assert!(text.contains("[[input]]"));
+        assert!(!text.contains("hunter2") && !text.contains("mqtt"));
+        assert_eq!(bundle.system, system);
+        assert_eq!(bundle.state, None);
+    }
+
+    #[test]
+    fn corrupt_archives_are_refused() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        let mut data = gzip_stored(b"hello");
+        assert_eq!(gunzip_stored(&data).unwrap(), b"hello");
+        let last = data.len() - 5;
+        data[last] ^= 1;
+        assert!(gunzip_stored(&data).is_err());
+    }
+}
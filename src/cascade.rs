@@ -0,0 +1,253 @@
+// ============================================================================
+// CASCADE - Malha em cascata (fuzzy + PI)
+// ============================================================================
+//
+// With an `[output.cascade]` section the crisp fuzzy output is no longer
+// sent to the fan: it is the setpoint of a small PID block that tracks it
+// against another input (e.g. a tachometer `fan_rpm`), and the PID output
+// goes through the output pipeline instead. The first output variable and
+// the measured input should therefore share their units.
+//
+//     [output.cascade]
+//     measured = "fan_rpm"   # input holding the measured value
+//     kp = 0.02
+//     ki = 0.05
+//     kd = 0.0               # optional, like every key but `measured`
+//     min = 0.0              # actuator limits, 0 to 100 by default
+//     max = 100.0
+//     plant_gain = 30.0      # simulated fan, see below
+//     plant_tau_secs = 2.0
+//
+// Anti-windup is by clamping: the integral stops growing while the output
+// is saturated and the error pushes it further out.
+//
+// The TUI has no way to enter the measured value, so a first-order model
+// of the fan stands in for it there: the measurement settles at
+// `plant_gain` times the actuator with a time constant of
+// `plant_tau_secs`.
+
+/// `[output.cascade]` settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct CascadeConfig {
+    pub measured: String,
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    pub min: f64,
+    pub max: f64,
+    pub plant_gain: f64,
+    pub plant_tau_secs: f64,
+}
+
+impl CascadeConfig {
+    pub fn new(measured: &str) -> Self {
+        CascadeConfig {
+            measured: measured.to_string(),
+            kp: 0.0,
+            ki: 0.0,
+            kd: 0.0,
+            min: 0.0,
+            max: 100.0,
+            plant_gain: 1.0,
+            plant_tau_secs: 0.0,
+        }
+    }
+}
+
+/// PID with output limits and clamping anti-windup; the timestep is passed
+/// to every step
+#[derive(Debug, Clone)]
+pub struct Pid {
+    config: CascadeConfig,
+    integral: f64,
+    last_error: Option<f64>,
+    output: f64,
+}
+
+impl Pid {
+    pub fn new(config: CascadeConfig) -> Self {
+        Pid {
+            output: config.min,
+            config,
+            integral: 0.0,
+            last_error: None,
+        }
+    }
+
+    /// Advance by `dt` seconds toward `setpoint`. A missing measurement
+    /// (NaN) holds the previous output.
+    pub fn step(&mut self, setpoint: f64, measured: f64, dt: f64) -> f64 {
+        let error = setpoint - measured;
+        if !error.is_finite() {
+            return self.output;
+        }
+        let config = &self.config;
+        let derivative = match self.last_error {
+            Some(last) if dt > 0.0 => (error - last) / dt,
+            _ => 0.0,
+        };
+        self.last_error = Some(error);
+
+        let unclamped = config.kp * error + config.ki * (self.integral + error * dt);
+        let winding_up =
+            (unclamped > config.max && error > 0.0) || (unclamped < config.min && error < 0.0);
+        if !winding_up {
+            self.integral += error * dt;
+        }
+        self.output = (config.kp * error + config.ki * self.integral + config.kd * derivative)
+            .clamp(config.min, config.max);
+        self.output
+    }
+}
+
+/// The PID stepped on clock time, with the simulated fan for the TUI
+pub struct Cascade {
+    pid: Pid,
+    last_time: Option<f64>,
+    /// Latest measurement, simulated or not
+    pub measured: f64,
+    /// Latest PID output
+    pub actuator: f64,
+}
+
+impl Cascade {
+    pub fn new(config: CascadeConfig) -> Self {
+        Cascade {
+            actuator: config.min,
+            measured: 0.0,
+            pid: Pid::new(config),
+            last_time: None,
+        }
+    }
+
+    pub fn config(&self) -> &CascadeConfig {
+        &self.pid.config
+    }
+
+    fn elapsed(&mut self, now: f64) -> f64 {
+        let dt = self.last_time.map_or(0.0, |last| (now - last).max(0.0));
+        self.last_time = Some(now);
+        dt
+    }
+
+    /// Append the PID's state and the last step to `saved` (a batch
+    /// checkpoint, checkpoint.rs)
+    #[cfg(feature = "batch")]
+    pub fn save(&self, saved: &mut Vec<f64>) {
+        saved.extend([
+            self.pid.integral,
+            self.pid.last_error.unwrap_or(f64::NAN),
+            self.pid.output,
+            self.last_time.unwrap_or(f64::NAN),
+            self.measured,
+            self.actuator,
+        ]);
+    }
+
+    /// Take up what `save` wrote, none if `saved` runs out
+    #[cfg(feature = "batch")]
+    pub fn restore(&mut self, saved: &mut dyn Iterator<Item = f64>) -> Option<()> {
+        self.pid.integral = saved.next()?;
+        self.pid.last_error = Some(saved.next()?).filter(|e| !e.is_nan());
+        self.pid.output = saved.next()?;
+        self.last_time = Some(saved.next()?).filter(|t| !t.is_nan());
+        self.measured = saved.next()?;
+        self.actuator = saved.next()?;
+        Some(())
+    }
+
+    /// Step the PID at clock time `now` with a measured value
+    pub fn process(&mut self, setpoint: f64, measured: f64, now: f64) -> f64 {
+        let dt = self.elapsed(now);
+        self.measured = measured;
+        self.actuator = self.pid.step(setpoint, measured, dt);
+        self.actuator
+    }
+
+    /// Step the PID at clock time `now` against the simulated fan, which
+    /// has followed the previous actuator value since the last step
+    pub fn simulate(&mut self, setpoint: f64, now: f64) -> f64 {
+        let dt = self.elapsed(now);
+        let config = &self.pid.config;
+        let target = config.plant_gain * self.actuator;
+        self.measured += if config.plant_tau_secs > 0.0 {
+            (target - self.measured) * (1.0 - (-dt / config.plant_tau_secs).exp())
+        } else {
+            target - self.measured
+        };
+        self.actuator = self.pid.step(setpoint, self.measured, dt);
+        self.actuator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> CascadeConfig {
+        CascadeConfig {
+            kp: 0.02,
+            ki: 0.05,
+            plant_gain: 30.0,
+            plant_tau_secs: 2.0,
+            ..CascadeConfig::new("fan_rpm")
+        }
+    }
+
+    #[test]
+    fn a_proportional_step_is_limited() {
+        let mut pid = Pid::new(CascadeConfig {
+            kp: 2.0,
+            ..CascadeConfig::new("x")
+        });
+        assert_eq!(pid.step(10.0, 0.0, 0.1), 20.0);
+        assert_eq!(pid.step(100.0, 0.0, 0.1), 100.0);
+        assert_eq!(pid.step(0.0, 10.0, 0.1), 0.0);
+    }
+
+    #[test]
+    fn the_simulated_fan_settles_on_the_setpoint() {
+        let mut cascade = Cascade::new(config());
+        let mut now = 0.0;
+        while now < 60.0 {
+            cascade.simulate(1500.0, now);
+            now += 0.1;
+        }
+        assert!(
+            (cascade.measured - 1500.0).abs() < 5.0,
+            "{}",
+            cascade.measured
+        );
+        assert!(
+            (cascade.actuator - 50.0).abs() < 0.5,
+            "{}",
+            cascade.actuator
+        );
+    }
+
+    #[test]
+    fn saturation_does_not_wind_up_the_integral() {
+        let mut pid = Pid::new(config());
+        // Far out of reach for a long time: pinned at the limit
+        for _ in 0..1000 {
+            assert_eq!(pid.step(5000.0, 0.0, 0.1), 100.0);
+        }
+        // Back within reach, the output leaves the limit at once instead of
+        // unwinding a huge integral first
+        let output = pid.step(1000.0, 1200.0, 0.1);
+        assert!(output < 100.0, "{}", output);
+    }
+
+    #[test]
+    fn the_derivative_acts_on_the_error_rate() {
+        let mut pid = Pid::new(CascadeConfig {
+            kd: 1.0,
+            min: -100.0,
+            ..CascadeConfig::new("x")
+        });
+        assert_eq!(pid.step(0.0, 0.0, 0.5), 0.0);
+        assert_eq!(pid.step(5.0, 0.0, 0.5), 10.0);
+        // A missing measurement holds the output
+        assert_eq!(pid.step(5.0, f64::NAN, 0.5), 10.0);
+    }
+}
@@ -0,0 +1,631 @@
+// ============================================================================
+// PROCESSING CHAIN - Cadeia de processamento
+// ============================================================================
+//
+// `pipeline [--config FILE]` prints what a config does to a reading on its
+// way to the fan, without running anything: every stage of every input
+// and output in the order the front ends run them, with its settings, the
+// ones the config leaves off marked "off", and where each runs:
+//
+//     input temperature
+//        1. sources        off
+//        2. window         off                              daemon
+//        ...
+//     output fan_speed
+//       ...
+//        8. rate limit     8/s
+//
+// The order is the one the code runs, not a copy of it: `Chain::of` is
+// the description, and the Stepper (stepper.rs) runs the stages it takes
+// part in by walking that description, so a stage moved here moves there.
+// The stages are:
+//
+//     sources     fusion of the input's [[input.source]] (fusion.rs)
+//     window      aggregate of the readings of `window_secs` (window.rs)
+//     hold        hand-set values holding against readings (overrides.rs)
+//     align       readings paired by when they were taken (align.rs)
+//     trigger     `min_delta` and [recompute] (trigger.rs)
+//     adapter     the input's kind, e.g. a dew point (adapters.rs)
+//     noise       simulated sensor errors (noise.rs)
+//     injection   failures injected by hand (inject.rs)
+//     range       the input's `out_of_range` policy
+//     fuzzify     the input's sets
+//     rules ... envelopes    inference, one evaluation (lib.rs)
+//     cascade     PID on the first output (cascade.rs)
+//     rate limit, hysteresis, dwell    (pipeline.rs)
+//     safe output, interlocks, exec    (safemode.rs, interlock.rs, exec.rs)
+//
+// Only the first output goes past the envelopes; the others end at their
+// defuzzification. This tree has no derived inputs, smoothing, dead-band
+// or output mapping stage, so none is listed. The interlocks are engaged
+// by hand ('i' in the TUI, `interlock` on the daemon), never by a reading.
+//
+// The command also warns about settings that defeat themselves: a
+// hysteresis that leaves a band out of reach, a `min_delta` wider than a
+// whole set of its input, a forced recompute shorter than the windows it
+// would recompute from, and an interlock engaged from the start. The TUI
+// shows the same description in a popup ('Y').
+
+use crate::pipeline::FanBand;
+use crate::{DefuzzMethod, FuzzySystem, config};
+
+const USAGE: &str = "Usage: fuzzy_logic pipeline [--config FILE]";
+
+/// One kind of processing stage, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Sources,
+    Window,
+    Hold,
+    Align,
+    Trigger,
+    Adapter,
+    Noise,
+    Injection,
+    Range,
+    Fuzzify,
+    Rules,
+    Implication,
+    Aggregation,
+    Caps,
+    Defuzzify,
+    Envelopes,
+    Cascade,
+    RateLimit,
+    Hysteresis,
+    Dwell,
+    SafeOutput,
+    Interlocks,
+    Exec,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Sources => "sources",
+            Kind::Window => "window",
+            Kind::Hold => "hold",
+            Kind::Align => "align",
+            Kind::Trigger => "trigger",
+            Kind::Adapter => "adapter",
+            Kind::Noise => "noise",
+            Kind::Injection => "injection",
+            Kind::Range => "range",
+            Kind::Fuzzify => "fuzzify",
+            Kind::Rules => "rules",
+            Kind::Implication => "implication",
+            Kind::Aggregation => "aggregation",
+            Kind::Caps => "caps",
+            Kind::Defuzzify => "defuzzify",
+            Kind::Envelopes => "envelopes",
+            Kind::Cascade => "cascade",
+            Kind::RateLimit => "rate limit",
+            Kind::Hysteresis => "hysteresis",
+            Kind::Dwell => "dwell",
+            Kind::SafeOutput => "safe output",
+            Kind::Interlocks => "interlocks",
+            Kind::Exec => "exec",
+        }
+    }
+
+    /// Part of the one evaluation of the rules (FuzzyController::evaluate)
+    pub fn is_inference(self) -> bool {
+        matches!(
+            self,
+            Kind::Fuzzify
+                | Kind::Rules
+                | Kind::Implication
+                | Kind::Aggregation
+                | Kind::Caps
+                | Kind::Defuzzify
+                | Kind::Envelopes
+        )
+    }
+
+    /// The front ends running the stage
+    pub fn scope(self) -> Scope {
+        match self {
+            Kind::Sources | Kind::Window | Kind::Align | Kind::Trigger | Kind::SafeOutput => {
+                Scope::Daemon
+            }
+            Kind::Noise => Scope::Tui,
+            Kind::Hold | Kind::Injection | Kind::Interlocks | Kind::Exec => Scope::Live,
+            _ => Scope::Everywhere,
+        }
+    }
+}
+
+/// Where a stage runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    /// Every front end, the Stepper included
+    Everywhere,
+    /// The TUI and the daemon
+    Live,
+    Tui,
+    Daemon,
+}
+
+impl Scope {
+    pub fn name(self) -> &'static str {
+        match self {
+            Scope::Everywhere => "",
+            Scope::Live => "tui, daemon",
+            Scope::Tui => "tui",
+            Scope::Daemon => "daemon",
+        }
+    }
+}
+
+/// One stage of a variable's chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stage {
+    pub kind: Kind,
+    /// Whether the config turns it on
+    pub enabled: bool,
+    /// Its settings, or why it is off
+    pub settings: String,
+}
+
+impl Stage {
+    fn on(kind: Kind, settings: impl Into<String>) -> Self {
+        Stage {
+            kind,
+            enabled: true,
+            settings: settings.into(),
+        }
+    }
+
+    fn off(kind: Kind) -> Self {
+        Stage {
+            kind,
+            enabled: false,
+            settings: String::new(),
+        }
+    }
+
+    fn either(kind: Kind, enabled: bool, settings: impl Into<String>) -> Self {
+        match enabled {
+            true => Stage::on(kind, settings),
+            false => Stage::off(kind),
+        }
+    }
+}
+
+/// What a system does to its readings, stage by stage
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chain {
+    /// Each input's stages up to its fuzzy sets, in declaration order
+    pub inputs: Vec<(String, Vec<Stage>)>,
+    /// Each output's stages from the rules on, in declaration order
+    pub outputs: Vec<(String, Vec<Stage>)>,
+    /// Settings that defeat themselves
+    pub warnings: Vec<String>,
+}
+
+impl Chain {
+    pub fn of(system: &FuzzySystem) -> Self {
+        let inputs = (system.inputs.iter())
+            .map(|var| (var.name.clone(), input_stages(system, var)))
+            .collect();
+        let outputs = (system.outputs.iter().enumerate())
+            .map(|(i, var)| (var.name.clone(), output_stages(system, i)))
+            .collect();
+        Chain {
+            inputs,
+            outputs,
+            warnings: warnings(system),
+        }
+    }
+
+    /// The kinds the Stepper runs, in order: the inputs' stages, then the
+    /// first output's, of the ones running everywhere
+    pub fn stepped(&self) -> Vec<Kind> {
+        let first = |list: &[(String, Vec<Stage>)]| {
+            list.first()
+                .map(|(_, stages)| stages.iter().map(|s| s.kind).collect::<Vec<_>>())
+                .unwrap_or_default()
+        };
+        (first(&self.inputs).into_iter())
+            .chain(first(&self.outputs))
+            .filter(|kind| kind.scope() == Scope::Everywhere)
+            .collect()
+    }
+
+    /// The description as printed, one line per stage
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let groups = (self.inputs.iter().map(|i| ("input", i)))
+            .chain(self.outputs.iter().map(|o| ("output", o)));
+        for (role, (name, stages)) in groups {
+            lines.push(format!("{} {}", role, name));
+            for (i, stage) in stages.iter().enumerate() {
+                let settings = match stage.enabled {
+                    true => stage.settings.as_str(),
+                    false => "off",
+                };
+                let line = format!(
+                    "  {:>2}. {:<12} {:<44} {}",
+                    i + 1,
+                    stage.kind.name(),
+                    settings,
+                    stage.kind.scope().name()
+                );
+                lines.push(line.trim_end().to_string());
+            }
+        }
+        if !self.warnings.is_empty() {
+            lines.push("warnings".to_string());
+            lines.extend(self.warnings.iter().map(|w| format!("  {}", w)));
+        }
+        lines
+    }
+}
+
+fn input_stages(system: &FuzzySystem, var: &crate::FuzzyVariable) -> Vec<Stage> {
+    let fusion = var.fusion.as_ref().map(|fusion| {
+        let names: Vec<&str> = fusion.sources.iter().map(|s| s.name.as_str()).collect();
+        let mut settings = format!("{} of {}", fusion.strategy.name(), names.join(", "));
+        if let Some(spread) = fusion.max_spread {
+            settings.push_str(&format!(", max spread {}", spread));
+        }
+        settings
+    });
+    let window = var
+        .window
+        .as_ref()
+        .map(|window| format!("{} of {}s", window.aggregate.name(), window.secs));
+    let hold = system.overrides.duration_secs;
+    let align = format!(
+        "{}s late, waits up to {}s",
+        var.latency_secs, system.alignment.max_wait_secs
+    );
+    let interval = system.recompute.max_interval_secs;
+    let trigger = match (var.min_delta > 0.0, interval > 0.0) {
+        (true, true) => format!("min_delta {}, at least every {}s", var.min_delta, interval),
+        (true, false) => format!("min_delta {}", var.min_delta),
+        (false, true) => format!("every reading, at least every {}s", interval),
+        (false, false) => "every reading".to_string(),
+    };
+    let adapter = match &var.kind {
+        crate::adapters::InputKind::Direct => None,
+        crate::adapters::InputKind::DewPoint { temperature } => Some(format!(
+            "dew point to relative humidity with {}",
+            temperature
+        )),
+    };
+    let noise = system.noise.iter().find(|n| n.input == var.name);
+    let sets: Vec<String> = (var.sets.iter())
+        .map(|s| format!("{} {}", s.name, s.function.kind()))
+        .collect();
+    vec![
+        Stage::either(Kind::Sources, fusion.is_some(), fusion.unwrap_or_default()),
+        Stage::either(Kind::Window, window.is_some(), window.unwrap_or_default()),
+        Stage::either(Kind::Hold, hold > 0.0, format!("{}s", hold)),
+        Stage::either(Kind::Align, var.latency_secs > 0.0, align),
+        Stage::on(Kind::Trigger, trigger),
+        Stage::either(
+            Kind::Adapter,
+            adapter.is_some(),
+            adapter.unwrap_or_default(),
+        ),
+        Stage::either(
+            Kind::Noise,
+            noise.is_some(),
+            noise.map_or(String::new(), |n| {
+                format!("sigma {}, drift {}", n.sigma, n.drift)
+            }),
+        ),
+        Stage::on(Kind::Injection, "by hand"),
+        Stage::on(
+            Kind::Range,
+            format!("{} to {} {}", var.min, var.max, var.out_of_range.name()),
+        ),
+        Stage::on(Kind::Fuzzify, sets.join(", ")),
+    ]
+}
+
+fn output_stages(system: &FuzzySystem, index: usize) -> Vec<Stage> {
+    let output = &system.outputs[index];
+    let settings = &system.defuzzification;
+    let concluding: Vec<_> = (system.rules.iter())
+        .filter(|rule| {
+            rule.consequents
+                .iter()
+                .any(|(name, _)| *name == output.name)
+        })
+        .collect();
+    let disabled = (concluding.iter())
+        .filter(|rule| {
+            let group = rule.group.as_ref();
+            group.is_some_and(|g| system.groups.iter().any(|x| x.name == *g && !x.enabled))
+        })
+        .count();
+    let mut rules = format!("{} rules", concluding.len());
+    if disabled > 0 {
+        rules.push_str(&format!(", {} in disabled groups", disabled));
+    }
+    let aggregation = match settings.aggregation {
+        crate::Aggregation::SoftMax => {
+            format!("softmax, temperature {}", settings.softmax_temperature)
+        }
+        other => other.name().to_string(),
+    };
+    let caps: Vec<String> = (settings.caps.iter())
+        .filter(|cap| cap.output == output.name)
+        .filter(|cap| settings.cap_profile.as_ref() == Some(&cap.profile))
+        .map(|cap| format!("{} at most {}", cap.set, cap.max))
+        .collect();
+    let method = settings.method_for(output);
+    let mut defuzzify = method.name().to_string();
+    if method != DefuzzMethod::WeightedAverage {
+        defuzzify.push_str(&format!(", {} samples", settings.resolution_for(output)));
+    }
+    if system.budget.is_some() {
+        defuzzify.push_str(", coarser over budget");
+    }
+    let mut stages = vec![
+        Stage::on(Kind::Rules, rules),
+        Stage::on(Kind::Implication, settings.implication_for(output).name()),
+        Stage::on(Kind::Aggregation, aggregation),
+        Stage::either(
+            Kind::Caps,
+            !caps.is_empty(),
+            format!(
+                "profile {}: {}",
+                settings.cap_profile.as_deref().unwrap_or_default(),
+                caps.join(", ")
+            ),
+        ),
+        Stage::on(Kind::Defuzzify, defuzzify),
+    ];
+    // The rest drive the first output alone
+    if index > 0 {
+        return stages;
+    }
+    let envelopes: Vec<&str> = system.envelopes.iter().map(|e| e.input.as_str()).collect();
+    let pipeline = &system.pipeline;
+    let interlocks: Vec<String> = (system.interlocks.iter())
+        .map(|i| format!("{} {} (priority {})", i.name, i.value, i.priority))
+        .collect();
+    stages.extend([
+        Stage::either(
+            Kind::Envelopes,
+            !envelopes.is_empty(),
+            format!("near the limits of {}", envelopes.join(", ")),
+        ),
+        Stage::either(
+            Kind::Cascade,
+            system.cascade.is_some(),
+            system.cascade.as_ref().map_or(String::new(), |c| {
+                format!("PID on {}, kp {} ki {} kd {}", c.measured, c.kp, c.ki, c.kd)
+            }),
+        ),
+        Stage::either(
+            Kind::RateLimit,
+            pipeline.rate_limit > 0.0,
+            format!("{}/s", pipeline.rate_limit),
+        ),
+        Stage::either(
+            Kind::Hysteresis,
+            pipeline.hysteresis > 0.0,
+            format!("{} wide", pipeline.hysteresis),
+        ),
+        Stage::either(
+            Kind::Dwell,
+            pipeline.min_on_secs > 0.0 || pipeline.min_off_secs > 0.0,
+            format!(
+                "on at least {}s, off at least {}s",
+                pipeline.min_on_secs, pipeline.min_off_secs
+            ),
+        ),
+        Stage::on(Kind::SafeOutput, "while the config fails to load"),
+        Stage::either(
+            Kind::Interlocks,
+            !interlocks.is_empty(),
+            format!("by hand: {}", interlocks.join(", ")),
+        ),
+        Stage::either(
+            Kind::Exec,
+            system.exec.is_some(),
+            system.exec.as_ref().map_or(String::new(), |e| {
+                format!("{} on {}", e.command.source(), e.on.name())
+            }),
+        ),
+    ]);
+    stages
+}
+
+/// Settings of `system` that defeat themselves
+fn warnings(system: &FuzzySystem) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let output = system.output();
+    let margin = system.pipeline.hysteresis / 2.0;
+    if margin > 0.0 {
+        for (band, threshold) in FanBand::thresholds() {
+            if threshold + margin > output.max {
+                warnings.push(format!(
+                    "hysteresis {} puts {} out of reach: it needs {} above {}",
+                    system.pipeline.hysteresis,
+                    band.label(),
+                    output.name,
+                    output.max
+                ));
+            }
+            if threshold - margin <= output.min {
+                warnings.push(format!(
+                    "hysteresis {} never lets {} drop below {}: it needs {} under {}",
+                    system.pipeline.hysteresis,
+                    output.name,
+                    band.label(),
+                    output.name,
+                    output.min
+                ));
+            }
+        }
+    }
+    for var in &system.inputs {
+        let narrowest = (var.sets.iter())
+            .map(|set| {
+                let (start, end) = set.function.support();
+                (set, end - start)
+            })
+            .filter(|(_, width)| width.is_finite())
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((set, width)) = narrowest
+            && var.min_delta > 0.0
+            && var.min_delta >= width
+        {
+            warnings.push(format!(
+                "{}: min_delta {} is as wide as all of {} ({}): a reading can cross it without a recompute",
+                var.name, var.min_delta, set.name, width
+            ));
+        }
+        let interval = system.recompute.max_interval_secs;
+        if let Some(window) = &var.window
+            && interval > 0.0
+            && interval < window.secs
+        {
+            warnings.push(format!(
+                "{}: the forced recompute every {}s is shorter than its {}s window, and recomputes the same value",
+                var.name, interval, window.secs
+            ));
+        }
+    }
+    for interlock in system.interlocks.iter().filter(|i| i.engaged) {
+        warnings.push(format!(
+            "interlock '{}' is engaged from the start: {} is {} until it is released",
+            interlock.name, output.name, interlock.value
+        ));
+    }
+    warnings
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let (system, source) = match args {
+        [] => (FuzzySystem::demo(), "the demo".to_string()),
+        [flag, path] if flag == "--config" => match config::load_system(path) {
+            Ok(system) => (system, path.clone()),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return 2;
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    let chain = Chain::of(&system);
+    println!("Processing chain of {}", source);
+    for line in chain.lines() {
+        println!("{}", line);
+    }
+    match chain.warnings.is_empty() {
+        true => 0,
+        false => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::interlock::InterlockConfig;
+    use crate::pipeline::PipelineConfig;
+    use crate::window::{Aggregate, WindowConfig};
+
+    fn kinds(stages: &[Stage]) -> Vec<&'static str> {
+        stages.iter().map(|s| s.kind.name()).collect()
+    }
+
+    #[test]
+    fn every_stage_is_listed_in_order_with_the_ones_left_off() {
+        let chain = Chain::of(&fixtures::fan());
+        assert_eq!(
+            kinds(&chain.inputs[0].1),
+            [
+                "sources",
+                "window",
+                "hold",
+                "align",
+                "trigger",
+                "adapter",
+                "noise",
+                "injection",
+                "range",
+                "fuzzify"
+            ]
+        );
+        assert_eq!(
+            kinds(&chain.outputs[0].1),
+            [
+                "rules",
+                "implication",
+                "aggregation",
+                "caps",
+                "defuzzify",
+                "envelopes",
+                "cascade",
+                "rate limit",
+                "hysteresis",
+                "dwell",
+                "safe output",
+                "interlocks",
+                "exec"
+            ]
+        );
+        let lines = chain.lines();
+        assert_eq!(lines[0], "input temperature");
+        assert!(lines[1].starts_with("   1. sources      off") && lines[1].ends_with(" daemon"));
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("fuzzify      Cold trapezoidal, Mild triangular")),
+            "{:#?}",
+            lines
+        );
+        assert!(
+            lines.contains(&"   8. rate limit   off".to_string()),
+            "{:#?}",
+            lines
+        );
+        assert!(chain.warnings.is_empty(), "{:?}", chain.warnings);
+    }
+
+    #[test]
+    fn settings_that_defeat_themselves_are_flagged() {
+        let mut system = fixtures::fan();
+        system.pipeline = PipelineConfig {
+            rate_limit: 5.0,
+            hysteresis: 40.0,
+            ..PipelineConfig::default()
+        };
+        system.inputs[0].min_delta = 16.0;
+        system.inputs[1].window = Some(WindowConfig {
+            secs: 60.0,
+            aggregate: Aggregate::Mean,
+            stale_windows: 0,
+        });
+        system.recompute.max_interval_secs = 30.0;
+        system.interlocks.push(InterlockConfig {
+            name: "door".to_string(),
+            value: 0.0,
+            priority: 1,
+            engaged: true,
+        });
+        let chain = Chain::of(&system);
+        let pipeline = &chain.outputs[0].1;
+        assert_eq!(pipeline[7].settings, "5/s");
+        assert!(pipeline[8].enabled && !pipeline[9].enabled);
+        assert_eq!(
+            chain.warnings,
+            [
+                "hysteresis 40 never lets fan_speed drop below LOW: it needs fan_speed under 0",
+                "temperature: min_delta 16 is as wide as all of Mild (15): a reading can cross it without a recompute",
+                "humidity: the forced recompute every 30s is shorter than its 60s window, and recomputes the same value",
+                "interlock 'door' is engaged from the start: fan_speed is 0 until it is released",
+            ]
+        );
+    }
+}
@@ -0,0 +1,212 @@
+// ============================================================================
+// CHECKPOINTS - Retomada de lotes
+// ============================================================================
+//
+// `batch FILE.csv --out OUT.csv --checkpoint FILE` works through the input
+// in chunks of rows (`--chunk-rows`, 10000 by default). Each chunk's rows
+// are written to the output in one write and synced, then the checkpoint
+// is replaced (written to FILE.tmp and renamed over FILE) with how far the
+// run got:
+//
+//     fuzzy_logic checkpoint 1
+//     args 5d41402a
+//     input 73400320 c3b0a2f1
+//     offset 1048642
+//     lines 20000
+//     output 1384551
+//     state 19999 0 0 0 0 0 19999 42.5 2 2 19950 42.5
+//
+// `args` is a CRC-32 of the arguments the run was started with, so a re-run
+// with other ones refuses to resume; `input` is the input's size and the
+// CRC-32 of its first 64 KiB, so a changed input is noticed; `offset` is
+// how many input bytes were processed, `lines` how many lines past the
+// header (blank ones included), and `output` how long the output was after
+// their rows. `state` is what the run carries from one row to the next:
+// the last time, the warning counts and the pipeline's, the cascade's and
+// the objective's state (batch.rs), so a resumed run writes what an
+// uninterrupted one would have.
+//
+// A re-run with the same arguments finds the checkpoint, cuts the output
+// back to `output` bytes, which drops the rows of a chunk the run was
+// killed in, and goes on from `offset`. The checkpoint is removed once the
+// input is done.
+
+use crate::backup;
+use crate::bundle::crc32;
+use std::io::Read;
+
+pub const VERSION: u32 = 1;
+
+/// Input bytes hashed to notice it changed
+const FINGERPRINT_BYTES: u64 = 64 * 1024;
+
+/// How far a batch run got, as of its last fully written chunk
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// CRC-32 of the arguments the run was started with
+    pub args: u32,
+    pub input_size: u64,
+    /// CRC-32 of the input's first 64 KiB
+    pub input_crc: u32,
+    /// Input bytes processed, the header included
+    pub offset: u64,
+    /// Lines processed past the header, blank ones included
+    pub lines: usize,
+    /// Length of the output after those lines' rows
+    pub output_bytes: u64,
+    /// What the run carries from one row to the next
+    pub state: Vec<f64>,
+}
+
+impl Checkpoint {
+    pub fn to_text(&self) -> String {
+        format!(
+            "fuzzy_logic checkpoint {}\nargs {:08x}\ninput {} {:08x}\noffset {}\nlines {}\noutput {}\nstate {}\n",
+            VERSION,
+            self.args,
+            self.input_size,
+            self.input_crc,
+            self.offset,
+            self.lines,
+            self.output_bytes,
+            self.state
+                .iter()
+                .map(f64::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        )
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, line)) if line == format!("fuzzy_logic checkpoint {}", VERSION) => {}
+            _ => return Err("line 1: not a fuzzy_logic checkpoint".to_string()),
+        }
+        let mut field = |key: &str| -> Result<(usize, Vec<&str>), String> {
+            match lines.next() {
+                Some((n, line)) => match line.split(' ').collect::<Vec<_>>()[..] {
+                    [first, ref rest @ ..] if first == key => Ok((n + 1, rest.to_vec())),
+                    _ => Err(format!("line {}: expected '{}'", n + 1, key)),
+                },
+                None => Err(format!("missing '{}'", key)),
+            }
+        };
+        let number = |(n, values): (usize, Vec<&str>)| match values[..] {
+            [value] => value
+                .parse::<u64>()
+                .map_err(|_| format!("line {}: '{}' is not a count", n, value)),
+            _ => Err(format!("line {}: expected one value", n)),
+        };
+        let crc = |n: usize, value: &str| {
+            u32::from_str_radix(value, 16)
+                .map_err(|_| format!("line {}: '{}' is not a CRC-32", n, value))
+        };
+
+        let (n, values) = field("args")?;
+        let args = match values[..] {
+            [value] => crc(n, value)?,
+            _ => return Err(format!("line {}: expected one value", n)),
+        };
+        let (n, values) = field("input")?;
+        let (input_size, input_crc) = match values[..] {
+            [size, value] => (
+                size.parse::<u64>()
+                    .map_err(|_| format!("line {}: '{}' is not a size", n, size))?,
+                crc(n, value)?,
+            ),
+            _ => return Err(format!("line {}: expected a size and a CRC-32", n)),
+        };
+        let offset = number(field("offset")?)?;
+        let lines_done = number(field("lines")?)? as usize;
+        let output_bytes = number(field("output")?)?;
+        let (n, values) = field("state")?;
+        let state = values
+            .iter()
+            .filter(|v| !v.is_empty())
+            .map(|v| {
+                v.parse::<f64>()
+                    .map_err(|_| format!("line {}: '{}' is not a number", n, v))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Checkpoint {
+            args,
+            input_size,
+            input_crc,
+            offset,
+            lines: lines_done,
+            output_bytes,
+            state,
+        })
+    }
+
+    /// The checkpoint at `path`, none if there is none
+    pub fn load(path: &str) -> Result<Option<Self>, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text)
+                .map(Some)
+                .map_err(|e| format!("{}: {}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        }
+    }
+
+    /// Replace the checkpoint at `path`, through a synced temporary file
+    /// so a kill leaves the old one or the new one (backup.rs)
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        backup::atomic_write(path, &self.to_text())
+    }
+}
+
+/// Size and CRC-32 of the first 64 KiB of the file at `path`
+pub fn fingerprint(path: &str) -> Result<(u64, u32), String> {
+    let error = |e: std::io::Error| format!("{}: {}", path, e);
+    let file = std::fs::File::open(path).map_err(error)?;
+    let size = file.metadata().map_err(error)?.len();
+    let mut head = Vec::new();
+    file.take(FINGERPRINT_BYTES)
+        .read_to_end(&mut head)
+        .map_err(error)?;
+    Ok((size, crc32(&head)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoints_round_trip_and_errors_name_their_line() {
+        let checkpoint = Checkpoint {
+            args: 0x5d41_402a,
+            input_size: 73_400_320,
+            input_crc: 0xc3b0_a2f1,
+            offset: 1_048_642,
+            lines: 20_000,
+            output_bytes: 1_384_551,
+            state: vec![19999.0, f64::NEG_INFINITY, 0.1 + 0.2, -3.5],
+        };
+        let text = checkpoint.to_text();
+        assert!(text.contains("args 5d41402a\ninput 73400320 c3b0a2f1\n"));
+        assert_eq!(Checkpoint::parse(&text).unwrap(), checkpoint);
+
+        // Nothing lost for a value that is not a number
+        let state = Checkpoint {
+            state: vec![f64::NAN],
+            ..checkpoint.clone()
+        };
+        assert!(Checkpoint::parse(&state.to_text()).unwrap().state[0].is_nan());
+
+        assert_eq!(
+            Checkpoint::parse(&text.replace("lines 20000", "lines many")).unwrap_err(),
+            "line 5: 'many' is not a count"
+        );
+        assert_eq!(
+            Checkpoint::parse(&text.replace("state", "status")).unwrap_err(),
+            "line 7: expected 'state'"
+        );
+        assert_eq!(
+            Checkpoint::parse("fuzzy_logic checkpoint 9\n").unwrap_err(),
+            "line 1: not a fuzzy_logic checkpoint"
+        );
+    }
+}
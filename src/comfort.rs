@@ -0,0 +1,318 @@
+// ============================================================================
+// COMFORT ZONE - Zona de conforto sobre temperatura e umidade
+// ============================================================================
+//
+// An HVAC comfort envelope is a region of the temperature/humidity plane
+// people find comfortable. A `[comfort]` section draws one over the TUI's
+// control surface and input scatter ('i'), and the TUI reports the share
+// of the session the readings spent inside it:
+//
+//     [comfort]              # optional, TUI only
+//     show = true            # draw the zone; false keeps only the share
+//     vertices = [[23.0, 30.0], [27.0, 30.0], [26.0, 60.0], [22.5, 65.0]]
+//
+// The vertices are (temperature, humidity) pairs in the inputs' stored
+// units, in order around the zone. Without `vertices` the zone is SUMMER,
+// a rough approximation of the ASHRAE-style summer zone in °C and %RH;
+// it is no substitute for the standard's own chart. The zone needs at
+// least 3 vertices and its edges may only meet at their shared corners.
+//
+// The share is weighted by time like the shadow comparison (shadow.rs):
+// each computation's readings hold until the next one, and the last until
+// now. A reading on the zone's edge may count either way.
+
+/// Default zone: warm side leaning cooler as the air gets more humid
+pub const SUMMER: [(f64, f64); 4] = [(23.0, 30.0), (27.0, 30.0), (26.0, 60.0), (22.5, 65.0)];
+
+/// A simple polygon over (temperature, humidity)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zone {
+    vertices: Vec<(f64, f64)>,
+}
+
+impl Zone {
+    /// The polygon through `vertices`, or why it is not a simple one
+    pub fn new(vertices: Vec<(f64, f64)>) -> Result<Self, String> {
+        if vertices.len() < 3 {
+            return Err(format!(
+                "a zone needs at least 3 vertices, got {}",
+                vertices.len()
+            ));
+        }
+        if let Some(i) = vertices
+            .iter()
+            .position(|(x, y)| !x.is_finite() || !y.is_finite())
+        {
+            return Err(format!("vertex {} is not a finite point", i + 1));
+        }
+        let zone = Zone { vertices };
+        let edges = zone.edges();
+        let n = edges.len();
+        for (i, &(a, b)) in edges.iter().enumerate() {
+            if a == b {
+                return Err(format!(
+                    "vertices {} and {} are the same point",
+                    i + 1,
+                    (i + 1) % n + 1
+                ));
+            }
+            for (j, &(c, d)) in edges.iter().enumerate().skip(i + 1) {
+                let neighbours = j == i + 1 || (i == 0 && j == n - 1);
+                // Neighbouring edges share a corner; they may only overlap
+                // when one folds back along the other
+                let crossing = match neighbours {
+                    true => cross(a, b, c, d) == 0.0 && dot(a, b, c, d) < 0.0,
+                    false => intersect(a, b, c, d),
+                };
+                if crossing {
+                    return Err(format!("edges {} and {} cross", i + 1, j + 1));
+                }
+            }
+        }
+        Ok(zone)
+    }
+
+    /// The default summer zone
+    pub fn summer() -> Self {
+        Zone::new(SUMMER.to_vec()).expect("the summer zone is simple")
+    }
+
+    pub fn vertices(&self) -> &[(f64, f64)] {
+        &self.vertices
+    }
+
+    /// Each edge from a vertex to the next, the last closing the polygon
+    fn edges(&self) -> Vec<((f64, f64), (f64, f64))> {
+        let n = self.vertices.len();
+        (0..n)
+            .map(|i| (self.vertices[i], self.vertices[(i + 1) % n]))
+            .collect()
+    }
+
+    /// Whether (`temperature`, `humidity`) lies inside, by counting the
+    /// edges a ray to its right crosses. NaN readings are outside
+    pub fn contains(&self, temperature: f64, humidity: f64) -> bool {
+        let (x, y) = (temperature, humidity);
+        let mut inside = false;
+        for ((x1, y1), (x2, y2)) in self.edges() {
+            if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    /// Cells of the `xs` by `ys` grid inside the zone next to one outside
+    /// it, `[row][column]` like a surface (surface.rs): the zone's outline
+    /// at the grid's resolution
+    pub fn outline(&self, xs: &[f64], ys: &[f64]) -> Vec<Vec<bool>> {
+        let inside: Vec<Vec<bool>> = ys
+            .iter()
+            .map(|y| xs.iter().map(|x| self.contains(*x, *y)).collect())
+            .collect();
+        let at = |row: isize, column: isize| {
+            usize::try_from(row)
+                .ok()
+                .and_then(|row| inside.get(row))
+                .zip(usize::try_from(column).ok())
+                .and_then(|(cells, column)| cells.get(column))
+                .copied()
+                .unwrap_or(false)
+        };
+        (0..ys.len() as isize)
+            .map(|row| {
+                (0..xs.len() as isize)
+                    .map(|column| {
+                        at(row, column)
+                            && [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                                .iter()
+                                .any(|(dr, dc)| !at(row + dr, column + dc))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// z of (b - a) x (d - c)
+fn cross(a: (f64, f64), b: (f64, f64), c: (f64, f64), d: (f64, f64)) -> f64 {
+    (b.0 - a.0) * (d.1 - c.1) - (b.1 - a.1) * (d.0 - c.0)
+}
+
+/// (b - a) . (d - c)
+fn dot(a: (f64, f64), b: (f64, f64), c: (f64, f64), d: (f64, f64)) -> f64 {
+    (b.0 - a.0) * (d.0 - c.0) + (b.1 - a.1) * (d.1 - c.1)
+}
+
+/// Which side of the line through `a` and `b` `p` is on, 0 on it
+fn side(a: (f64, f64), b: (f64, f64), p: (f64, f64)) -> f64 {
+    match cross(a, b, a, p) {
+        z if z > 0.0 => 1.0,
+        z if z < 0.0 => -1.0,
+        _ => 0.0,
+    }
+}
+
+/// Whether `p`, on the line through `a` and `b`, lies between them
+fn between(a: (f64, f64), b: (f64, f64), p: (f64, f64)) -> bool {
+    p.0 >= a.0.min(b.0) && p.0 <= a.0.max(b.0) && p.1 >= a.1.min(b.1) && p.1 <= a.1.max(b.1)
+}
+
+/// Whether segments ab and cd have a point in common, touching included
+fn intersect(a: (f64, f64), b: (f64, f64), c: (f64, f64), d: (f64, f64)) -> bool {
+    let (c_side, d_side) = (side(a, b, c), side(a, b, d));
+    let (a_side, b_side) = (side(c, d, a), side(c, d, b));
+    if c_side * d_side < 0.0 && a_side * b_side < 0.0 {
+        return true;
+    }
+    (c_side == 0.0 && between(a, b, c))
+        || (d_side == 0.0 && between(a, b, d))
+        || (a_side == 0.0 && between(c, d, a))
+        || (b_side == 0.0 && between(c, d, b))
+}
+
+/// The `[comfort]` section
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComfortConfig {
+    /// Draw the zone over the surface and the scatter
+    pub show: bool,
+    pub zone: Zone,
+}
+
+impl Default for ComfortConfig {
+    fn default() -> Self {
+        ComfortConfig {
+            show: true,
+            zone: Zone::summer(),
+        }
+    }
+}
+
+/// Time spent inside the zone, each reading held until the next
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Occupancy {
+    /// Seconds between the first reading and the last
+    seconds: f64,
+    inside: f64,
+    last: Option<(f64, bool)>,
+}
+
+impl Occupancy {
+    pub fn record(&mut self, now: f64, inside: bool) {
+        if let Some((then, was_inside)) = self.last {
+            let held = (now - then).max(0.0);
+            self.seconds += held;
+            if was_inside {
+                self.inside += held;
+            }
+        }
+        self.last = Some((now, inside));
+    }
+
+    /// Share of the time inside from the first reading to `now`, from 0
+    /// to 1; none before any time has passed
+    pub fn share(&self, now: f64) -> Option<f64> {
+        let (then, was_inside) = self.last?;
+        let held = (now - then).max(0.0);
+        let seconds = self.seconds + held;
+        let inside = self.inside + if was_inside { held } else { 0.0 };
+        (seconds > 0.0).then(|| inside / seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> Zone {
+        Zone::new(vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]).unwrap()
+    }
+
+    #[test]
+    fn points_inside_and_outside_a_zone() {
+        let zone = square();
+        assert!(zone.contains(5.0, 5.0));
+        assert!(!zone.contains(15.0, 5.0));
+        assert!(!zone.contains(5.0, -1.0));
+        assert!(!zone.contains(f64::NAN, 5.0));
+
+        // A concave zone leaves its notch out
+        let notched = Zone::new(vec![
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 10.0),
+            (5.0, 5.0),
+            (0.0, 10.0),
+        ])
+        .unwrap();
+        assert!(notched.contains(5.0, 2.0));
+        assert!(!notched.contains(5.0, 8.0));
+        assert!(notched.contains(9.0, 8.0));
+
+        let summer = Zone::summer();
+        assert!(summer.contains(25.0, 45.0));
+        assert!(!summer.contains(25.0, 80.0));
+        assert!(!summer.contains(30.0, 45.0));
+    }
+
+    #[test]
+    fn zones_that_are_not_simple_polygons_are_refused() {
+        let zone = |vertices: &[(f64, f64)]| Zone::new(vertices.to_vec());
+        assert_eq!(
+            zone(&[(0.0, 0.0), (1.0, 0.0)]),
+            Err("a zone needs at least 3 vertices, got 2".to_string())
+        );
+        // A bow tie
+        assert_eq!(
+            zone(&[(0.0, 0.0), (10.0, 10.0), (10.0, 0.0), (0.0, 10.0)]),
+            Err("edges 1 and 3 cross".to_string())
+        );
+        // An edge running back over the one before it
+        assert!(zone(&[(0.0, 0.0), (10.0, 0.0), (5.0, 0.0), (5.0, 5.0)]).is_err());
+        // A vertex touching an edge it is not on
+        assert!(
+            zone(&[
+                (0.0, 0.0),
+                (10.0, 0.0),
+                (10.0, 10.0),
+                (5.0, 0.0),
+                (0.0, 10.0)
+            ])
+            .is_err()
+        );
+        assert!(zone(&[(0.0, 0.0), (0.0, 0.0), (1.0, 1.0)]).is_err());
+        // Vertices on one line fold the polygon back on itself
+        assert!(zone(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]).is_err());
+        assert!(zone(&[(0.0, 0.0), (f64::NAN, 1.0), (2.0, 0.0)]).is_err());
+    }
+
+    #[test]
+    fn the_outline_is_the_inside_cells_next_to_outside_ones() {
+        let axis: Vec<f64> = (0..7).map(|i| i as f64 * 2.0 - 1.0).collect();
+        let outline = square().outline(&axis, &axis);
+        let drawn: Vec<String> = outline
+            .iter()
+            .map(|row| row.iter().map(|on| if *on { 'o' } else { '.' }).collect())
+            .collect();
+        assert_eq!(
+            drawn,
+            [
+                ".......", ".ooooo.", ".o...o.", ".o...o.", ".o...o.", ".ooooo.", ".......",
+            ]
+        );
+    }
+
+    #[test]
+    fn occupancy_holds_each_reading_until_the_next() {
+        let mut occupancy = Occupancy::default();
+        assert_eq!(occupancy.share(10.0), None);
+        occupancy.record(0.0, true);
+        assert_eq!(occupancy.share(0.0), None);
+        occupancy.record(30.0, false);
+        assert_eq!(occupancy.share(30.0), Some(1.0));
+        // The last reading counts until now
+        assert_eq!(occupancy.share(60.0), Some(0.5));
+        occupancy.record(90.0, true);
+        assert_eq!(occupancy.share(120.0), Some(0.5));
+    }
+}
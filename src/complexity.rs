@@ -0,0 +1,423 @@
+// ============================================================================
+// COMPLEXITY - Métricas da base de regras
+// ============================================================================
+//
+// How big and how tangled a rule base is, to prune it before it goes to a
+// small target. `check CONFIG` prints the report after the config's `ok`
+// and 'C' in the TUI opens it over the panels:
+//
+//     rules                   the count, and per group when there are any
+//     antecedents per rule    the mean number of conditions
+//     consequents used        distinct output sets concluded, of all sets
+//     evaluation cost         rules × the samples each output is
+//                             defuzzified over (its resolution + 1, or
+//                             one per set for the weighted average),
+//                             then each output's method and samples
+//     redundancy candidates   pairs of rules on the same inputs and outputs
+//                             whose sets are all alike: the smallest
+//                             similarity of their sets, as guard.rs works it
+//                             out, at least `[tuning] max_similarity`
+//     more than K firing      the fraction of SAMPLES points, uniform over
+//                             the universes, where more than K rules fire
+//                             at once (`check --crowd K`, CROWD by default)
+//
+// The points come from a fixed seed so the same config always reports the
+// same fraction. Set similarities are worked out once per pair of sets of
+// a variable, so the pairs of a few hundred rules cost only lookups.
+
+use crate::activation::Session;
+use crate::{DefuzzMethod, FuzzySystem, FuzzyVariable, guard};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+
+/// Rules firing at once past which a point counts as crowded, by default
+pub const CROWD: usize = 4;
+/// Points the input space is sampled at
+const SAMPLES: usize = 2000;
+const SEED: u64 = 479;
+/// Redundancy candidates the report lists one by one
+pub const LISTED: usize = 10;
+
+/// Two rules that may say the same thing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redundancy {
+    /// Indices of the rules, the first one declared first
+    pub first: usize,
+    pub second: usize,
+    /// Smallest similarity of their sets
+    pub similarity: f64,
+}
+
+/// Complexity metrics of a rule base
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub rules: usize,
+    /// Rules of each group, in declaration order
+    pub groups: Vec<(String, usize)>,
+    pub ungrouped: usize,
+    pub mean_antecedents: f64,
+    /// Distinct (output, set) pairs the rules conclude
+    pub consequents: usize,
+    pub output_sets: usize,
+    /// Samples the outputs are defuzzified over, together
+    pub defuzz_samples: usize,
+    /// Each output's name and how it is defuzzified, in declaration order
+    pub methods: Vec<(String, String)>,
+    pub redundant: Vec<Redundancy>,
+    pub max_similarity: f64,
+    pub crowd: usize,
+    /// Fraction of the sampled points where more than `crowd` rules fire
+    pub crowded: f64,
+    /// Most rules seen firing at one point
+    pub most_firing: usize,
+}
+
+impl Report {
+    /// Rules × defuzzification samples
+    pub fn cost(&self) -> usize {
+        self.rules * self.defuzz_samples
+    }
+
+    /// The report as label and value columns, listing at most `listed`
+    /// redundancy candidates
+    pub fn lines(&self, listed: usize) -> Vec<String> {
+        let mut rows = vec![("rules".to_string(), self.rules.to_string())];
+        if !self.groups.is_empty() {
+            for (name, count) in &self.groups {
+                rows.push((format!("  group {}", name), count.to_string()));
+            }
+            rows.push(("  no group".to_string(), self.ungrouped.to_string()));
+        }
+        rows.push((
+            "antecedents per rule".to_string(),
+            format!("{:.2}", self.mean_antecedents),
+        ));
+        rows.push((
+            "consequents used".to_string(),
+            format!("{} of {} output sets", self.consequents, self.output_sets),
+        ));
+        rows.push((
+            "evaluation cost".to_string(),
+            format!(
+                "{} ({} rules × {} defuzz samples)",
+                self.cost(),
+                self.rules,
+                self.defuzz_samples
+            ),
+        ));
+        for (output, method) in &self.methods {
+            rows.push((format!("  {}", output), method.clone()));
+        }
+        rows.push((
+            "redundancy candidates".to_string(),
+            format!(
+                "{} (similarity ≥ {:.2})",
+                self.redundant.len(),
+                self.max_similarity
+            ),
+        ));
+        for pair in self.redundant.iter().take(listed) {
+            rows.push((
+                format!("  rules {} and {}", pair.first + 1, pair.second + 1),
+                format!("{:.2}", pair.similarity),
+            ));
+        }
+        if self.redundant.len() > listed {
+            rows.push((
+                "  ...".to_string(),
+                format!("{} more", self.redundant.len() - listed),
+            ));
+        }
+        rows.push((
+            format!("more than {} firing", self.crowd),
+            format!(
+                "{:.1}% of {} samples (at most {})",
+                self.crowded * 100.0,
+                SAMPLES,
+                self.most_firing
+            ),
+        ));
+        let width = rows.iter().map(|(label, _)| label.chars().count()).max();
+        rows.iter()
+            .map(|(label, value)| format!("{:<w$}  {}", label, value, w = width.unwrap_or(0)))
+            .collect()
+    }
+}
+
+/// Similarity of every pair of sets of `var`, indexed by set
+fn similarities(var: &FuzzyVariable) -> Vec<Vec<f64>> {
+    let universe = (var.min, var.max);
+    (var.sets.iter())
+        .map(|f| {
+            (var.sets.iter())
+                .map(|g| {
+                    if f.name == g.name {
+                        1.0
+                    } else {
+                        guard::similarity(&f.function, &g.function, universe)
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// `(variable, set)` indices of `pairs` into `vars`, sorted by variable
+fn indices(vars: &[FuzzyVariable], pairs: &[(String, String)]) -> Vec<(usize, usize)> {
+    let mut indices: Vec<(usize, usize)> = pairs
+        .iter()
+        .filter_map(|(name, set)| {
+            let v = vars.iter().position(|var| &var.name == name)?;
+            Some((v, vars[v].sets.iter().position(|s| &s.name == set)?))
+        })
+        .collect();
+    indices.sort();
+    indices
+}
+
+/// Pairs of rules of `system` at least `tuning.max_similarity` alike
+fn redundant(system: &FuzzySystem) -> Vec<Redundancy> {
+    let inputs: Vec<Vec<Vec<f64>>> = system.inputs.iter().map(similarities).collect();
+    let outputs: Vec<Vec<Vec<f64>>> = system.outputs.iter().map(similarities).collect();
+    let rules: Vec<_> = (system.rules.iter())
+        .map(|rule| {
+            (
+                indices(&system.inputs, &rule.conditions),
+                indices(&system.outputs, &rule.consequents),
+            )
+        })
+        .collect();
+    // Smallest similarity of two rules' sets on the same variables
+    let alike = |a: &[(usize, usize)], b: &[(usize, usize)], table: &[Vec<Vec<f64>>]| {
+        let same_variables = a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.0 == y.0);
+        same_variables.then(|| {
+            (a.iter().zip(b))
+                .map(|(&(v, s), &(_, t))| table[v][s][t])
+                .fold(1.0, f64::min)
+        })
+    };
+    let threshold = system.tuning.max_similarity;
+    let mut pairs = Vec::new();
+    for (first, (conditions, consequents)) in rules.iter().enumerate() {
+        for (second, (other_conditions, other_consequents)) in
+            rules.iter().enumerate().skip(first + 1)
+        {
+            let similarity = alike(conditions, other_conditions, &inputs)
+                .zip(alike(consequents, other_consequents, &outputs))
+                .map(|(a, b)| a.min(b));
+            if let Some(similarity) = similarity.filter(|s| *s >= threshold) {
+                pairs.push(Redundancy {
+                    first,
+                    second,
+                    similarity,
+                });
+            }
+        }
+    }
+    pairs
+}
+
+/// The complexity metrics of `system`, counting a point as crowded when
+/// more than `crowd` rules fire at it
+pub fn analyze(system: &FuzzySystem, crowd: usize) -> Report {
+    let rules = &system.rules;
+    let groups: Vec<(String, usize)> = (system.groups.iter())
+        .map(|group| {
+            let count = (rules.iter())
+                .filter(|rule| rule.group.as_ref() == Some(&group.name))
+                .count();
+            (group.name.clone(), count)
+        })
+        .collect();
+    let antecedents: usize = rules.iter().map(|rule| rule.conditions.len()).sum();
+    let consequents: HashSet<&(String, String)> =
+        rules.iter().flat_map(|rule| &rule.consequents).collect();
+    let settings = &system.defuzzification;
+    let defuzz: Vec<(usize, String)> = (system.outputs.iter())
+        .map(|output| match settings.method_for(output) {
+            DefuzzMethod::WeightedAverage => (
+                output.sets.len(),
+                format!("weighted_average of {} sets", output.sets.len()),
+            ),
+            method => {
+                let samples = settings.resolution_for(output) + 1;
+                (
+                    samples,
+                    format!("{} over {} samples", method.name(), samples),
+                )
+            }
+        })
+        .collect();
+
+    let mut session = Session::new(system);
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let (mut crowded, mut most_firing) = (0, 0);
+    let mut point = vec![0.0; system.inputs.len()];
+    for _ in 0..SAMPLES {
+        for (x, var) in point.iter_mut().zip(&system.inputs) {
+            *x = rng.gen_range(var.min..=var.max);
+        }
+        session.infer(&point);
+        let firing = session.strengths().iter().filter(|s| **s > 0.0).count();
+        crowded += usize::from(firing > crowd);
+        most_firing = most_firing.max(firing);
+    }
+
+    Report {
+        rules: rules.len(),
+        ungrouped: rules.len() - groups.iter().map(|(_, n)| n).sum::<usize>(),
+        groups,
+        mean_antecedents: antecedents as f64 / rules.len().max(1) as f64,
+        consequents: consequents.len(),
+        output_sets: system.outputs.iter().map(|var| var.sets.len()).sum(),
+        defuzz_samples: defuzz.iter().map(|(samples, _)| samples).sum(),
+        methods: (system.outputs.iter().zip(defuzz))
+            .map(|(output, (_, method))| (output.name.clone(), method))
+            .collect(),
+        redundant: redundant(system),
+        max_similarity: system.tuning.max_similarity,
+        crowd,
+        crowded: crowded as f64 / SAMPLES as f64,
+        most_firing,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RESOLUTION;
+    use crate::config;
+    use crate::fixtures;
+
+    /// Three inputs of seven triangles each and a rule for every
+    /// combination, 343 rules split over two groups, plus copies of the
+    /// first rule: one exact and one concluding a set next door
+    fn large() -> FuzzySystem {
+        let names = ["A", "B", "C", "D", "E", "F", "G"];
+        let mut text = String::new();
+        let mut rules: [Vec<String>; 2] = [Vec::new(), Vec::new()];
+        for (i, a) in names.iter().enumerate() {
+            for (j, b) in names.iter().enumerate() {
+                for (k, c) in names.iter().enumerate() {
+                    rules[i % 2].push(format!(
+                        "\"IF x IS {} AND y IS {} AND z IS {} THEN out IS {}\"",
+                        a,
+                        b,
+                        c,
+                        names[(i + j + k) / 3]
+                    ));
+                }
+            }
+        }
+        rules[0].push("\"IF x IS A AND y IS A AND z IS A THEN out IS A WITH 0.5\"".to_string());
+        rules[0].push("\"IF x IS A AND y IS A AND z IS A THEN out IS B\"".to_string());
+        for (name, rules) in ["even", "odd"].iter().zip(&rules) {
+            text.push_str(&format!(
+                "[[group]]\nname = \"{}\"\nrules = [{}]\n\n",
+                name,
+                rules.join(", ")
+            ));
+        }
+        for (section, var) in [
+            ("input", "x"),
+            ("input", "y"),
+            ("input", "z"),
+            ("output", "out"),
+        ] {
+            text.push_str(&format!(
+                "[[{}]]\nname = \"{}\"\nmin = 0.0\nmax = 60.0\n\n",
+                section, var
+            ));
+            for (i, set) in names.iter().enumerate() {
+                let center = i as f64 * 10.0;
+                text.push_str(&format!(
+                    "[[{}.set]]\nname = \"{}\"\nshape = \"triangular\"\n\
+                     params = [{:?}, {:?}, {:?}]\n\n",
+                    section,
+                    set,
+                    center - 10.0,
+                    center,
+                    center + 10.0
+                ));
+            }
+        }
+        config::parse_system(&text).unwrap()
+    }
+
+    #[test]
+    fn the_demo_is_small_and_never_crowded() {
+        let report = analyze(&fixtures::fan(), CROWD);
+        assert_eq!(report.rules, 9);
+        assert!(report.groups.is_empty());
+        assert_eq!(report.mean_antecedents, 2.0);
+        assert_eq!((report.consequents, report.output_sets), (4, 4));
+        assert_eq!(report.cost(), 9 * (RESOLUTION + 1));
+        assert_eq!(
+            report.methods,
+            [(
+                "fan_speed".to_string(),
+                "centroid over 101 samples".to_string()
+            )]
+        );
+        // A weighted average costs one sample per set
+        let mut system = fixtures::fan();
+        system.outputs[0].defuzz.method = Some(DefuzzMethod::WeightedAverage);
+        let report = analyze(&system, CROWD);
+        assert_eq!(report.cost(), 9 * 4);
+        assert_eq!(report.methods[0].1, "weighted_average of 4 sets");
+        assert!(report.redundant.is_empty());
+        // Two inputs whose neighbouring sets overlap fire four rules at most
+        assert_eq!(report.crowded, 0.0);
+        assert_eq!(report.most_firing, 4);
+        assert!(analyze(&fixtures::fan(), 1).crowded > 0.0);
+    }
+
+    #[test]
+    fn a_large_rule_base_is_counted_and_its_copies_found() {
+        let system = large();
+        let report = analyze(&system, CROWD);
+        assert_eq!(report.rules, 345);
+        assert_eq!(
+            report.groups,
+            [("even".to_string(), 198), ("odd".to_string(), 147)]
+        );
+        assert_eq!(report.ungrouped, 0);
+        assert_eq!(report.mean_antecedents, 3.0);
+        assert_eq!((report.consequents, report.output_sets), (7, 7));
+        assert_eq!(report.cost(), 345 * (RESOLUTION + 1));
+        // The exact copy, whatever its weight; triangles next door are
+        // only a seventh alike, well below the 0.9 of [tuning]
+        assert_eq!(
+            report.redundant,
+            [Redundancy {
+                first: 0,
+                second: 196,
+                similarity: 1.0
+            }]
+        );
+        // Three inputs fire up to eight rules between two centers
+        assert!(report.crowded > 0.5, "{}", report.crowded);
+        assert_eq!(report.most_firing, 8 + 2);
+        assert_eq!(analyze(&system, CROWD), report);
+    }
+
+    #[test]
+    fn the_report_lines_up_its_columns() {
+        let mut system = large();
+        system.tuning.max_similarity = 0.1;
+        let report = analyze(&system, CROWD);
+        assert!(report.redundant.len() > 3);
+        let lines = report.lines(3);
+        let column = lines[0].find("345").unwrap();
+        for line in &lines {
+            let value = line.chars().skip(column).collect::<String>();
+            assert!(!value.starts_with(' '), "{:?}", line);
+            assert!(line.chars().nth(column - 1) == Some(' '), "{:?}", line);
+        }
+        assert!(lines.iter().any(|l| l.starts_with("  group odd ")));
+        assert!(lines.iter().any(|l| l.starts_with("  rules 1 and ")));
+        let more = format!("{} more", report.redundant.len() - 3);
+        assert!(lines.iter().any(|l| l.ends_with(&more)), "{:#?}", lines);
+    }
+}
@@ -0,0 +1,3185 @@
+// ============================================================================
+// CONFIG FILES - Definição do sistema em TOML
+// ============================================================================
+//
+// A fuzzy system is described by a small subset of TOML:
+//
+//     schema = 1             # optional, the format's version (schema.rs)
+//     rules = [
+//         "IF temperature IS Cold AND humidity IS Low THEN fan_speed IS Off",
+//         # with several outputs one rule may conclude each of them:
+//         # "IF temperature IS Hot THEN fan_speed IS High AND damper IS Open"
+//     ]
+//
+//     [metadata]             # optional, every key too
+//     name = "office fan"
+//     description = "Ventilation of the second floor"
+//     version = "1.2.0"      # MAJOR.MINOR.PATCH
+//     author = "Facilities"
+//     created = "2025-03-01"
+//     modified = "2025-06-14T09:30:00Z"   # dates start YYYY-MM-DD
+//
+//     [[group]]              # optional, rules switched on/off together
+//     name = "energy-saving"
+//     enabled = true
+//     weight = 0.8           # multiplies the weights of its rules
+//     rules = [
+//         "IF temperature IS Mild AND humidity IS Low THEN fan_speed IS Off WITH 0.5",
+//     ]
+//
+//     [[input]]
+//     name = "temperature"
+//     min = 0.0
+//     max = 50.0
+//     kind = "direct"        # optional, "dewpoint" converts (adapters.rs)
+//     min_delta = 0.2        # optional, see trigger.rs
+//     window_secs = 1.0      # optional, daemon sample windows, with
+//     aggregate = "median"   # "aggregate" and "stale_windows" (window.rs)
+//     latency_secs = 4.0     # optional, how late its readings reach the
+//                            # daemon, not with window_secs (align.rs)
+//     out_of_range = "clamp" # optional: "clamp", "extend" or "error"
+//     unit = "°C"            # optional, and a display unit (units.rs)
+//
+//     fusion = "median"     # optional, how [[input.source]] readings
+//                            # fuse, with "max_spread" (fusion.rs)
+//
+//     [[input.source]]       # optional, a sensor fused into the input:
+//     name = "north"         # "trust", "min", "max", "max_rate" and
+//     max_rate = 0.5         # "timeout_secs" (fusion.rs)
+//
+//     [[input.set]]
+//     name = "Cold"
+//     shape = "trapezoidal"  # or "triangular" (a, b, c) / "gaussian"
+//                            # (mean, sigma) / "sigmoid" (center, slope)
+//                            # / "gbell" (a, b, c) / "s_curve" or
+//                            # "z_curve" (a, b)
+//     params = [0.0, 0.0, 15.0, 20.0]   # or "Set.b" links, see links.rs
+//     uncertainty = [0.0, 0.0, 1.0, 1.0]   # optional ± range per parameter
+//
+//     [output]               # [[output]] once per output for several
+//     name = "fan_speed"
+//     ...
+//     method = "weighted_average"   # optional, these three override
+//     resolution = 400       # [defuzzification] for this output alone
+//     implication = "product"
+//
+//     [[output.set]]
+//     ...
+//
+//     [defuzzification]      # optional
+//     method = "centroid"    # or "plateau_centroid" / "weighted_average"
+//     resolution = 100       # samples across the universe, 2 to 1000;
+//                            # none with "weighted_average"
+//     implication = "min"    # clip each set at its rule, or "product"
+//     support_threshold = 0.05   # warn below this membership, 0 = never
+//     aggregation = "max"    # or "softmax" / "bounded_sum"
+//     softmax_temperature = 0.1  # above 0; smaller is closer to max
+//     tie_tolerance = 1e-9   # strengths this close tie, 0 to 1 (ties.rs)
+//     cap_profile = "quiet"  # optional, cap profile active at start
+//
+//     [[cap]]                # optional, output set ceiling (see aggregated
+//     profile = "quiet"      # in main.rs); 'Q' in the TUI or `caps` on
+//     output = "fan_speed"   # the daemon switches between the profiles
+//     set = "High"
+//     max = 0.4              # 0 to 1
+//
+//     [recompute]            # optional, daemon only (trigger.rs)
+//     max_interval_secs = 30.0
+//
+//     [alignment]            # optional, daemon only (align.rs)
+//     max_wait_secs = 5.0    # at least the spread of the latency_secs
+//
+//     [override]             # optional, daemon only (overrides.rs)
+//     duration_secs = 30.0   # how long hand-set inputs hold; 0 for never
+//
+//     [forecast]             # optional, TUI only (forecast.rs)
+//     extrapolation = "linear"   # or "hold"
+//     horizon_secs = 300.0   # above 0
+//     steps = 10             # whole number, 1 to 1000
+//     window_secs = 120.0    # above 0
+//
+//     [budget]               # optional, latency budget (budget.rs)
+//     cycle_ms = 2.0         # above 0
+//     recover_cycles = 20    # whole number of at least 1
+//
+//     [adaptation]           # optional, TUI feedback on rule weights
+//     enabled = true         # (adapt.rs)
+//     rate = 0.5             # above 0
+//     max_step = 0.05        # above 0 and at most 1
+//
+//     [tuning]               # optional, daemon `param` checks (guard.rs)
+//     max_similarity = 0.9
+//     max_hole = 2.0
+//
+//     [oscillation]          # optional, limit-cycle alarm on the first
+//     window_secs = 60.0     # output (oscillation.rs)
+//     min_reversals = 4
+//     min_amplitude = 10.0
+//
+//     [[alarm]]              # optional, computed alarm (alarm.rs)
+//     name = "stuck high"
+//     when = "holds_for(fan_speed > 80 and humidity > 70, 10m)"
+//
+//     [objective]            # optional, comfort score (objective.rs)
+//     score = "abs(temperature - 22) + 0.05 * fan_speed"
+//     better = "lower"       # or "higher"
+//
+//     [display]              # optional, TUI only (timestamp.rs)
+//     timezone = "local"     # or "UTC", or an offset like "+02:00"
+//     palette = "classic"    # or "okabe_ito" / "tol" (palette.rs)
+//     pause_on_blur = false  # simulation stops out of focus (termguard.rs)
+//
+//     [comfort]              # optional, TUI comfort zone (comfort.rs)
+//     show = true
+//     vertices = [[23.0, 30.0], [27.0, 30.0], [26.0, 60.0], [22.5, 65.0]]
+//
+//     [output.pipeline]      # optional, applies to the first output
+//     rate_limit = 10.0
+//     hysteresis = 4.0
+//     min_on_secs = 30.0
+//     min_off_secs = 60.0
+//     status = "dominant_set"   # or "thresholds" (default), see pipeline.rs
+//
+//     [output.cascade]       # optional, PID after the first output
+//     measured = "fan_rpm"   # see cascade.rs for the other keys
+//
+//     [output.exec]          # optional, runs a program with the first
+//     command = "/usr/local/bin/set-fan {value}"   # output (exec.rs)
+//     on = "change"          # or "publish" (default); see exec.rs for
+//     timeout_secs = 5.0     # stdin, retries and stall_secs
+//
+//     [[envelope]]           # optional, safety envelope (envelope.rs)
+//     input = "temperature"
+//     margin = 2.0
+//     high = 100.0           # and/or low, first-output targets
+//
+//     [[noise]]              # optional, simulated sensor (noise.rs)
+//     input = "temperature"
+//     sigma = 0.5
+//     correlation = 0.9
+//
+//     [[interlock]]          # optional, forces the first output while
+//     name = "door"          # engaged (interlock.rs)
+//     value = 0.0
+//     priority = 10
+//
+//     [generator]            # optional, random inputs (generator.rs)
+//     kind = "boundary"      # "uniform" (default) or "constrained"
+//
+//     [generator.ranges]     # optional, for "constrained"
+//     temperature = [20.0, 35.0]
+//
+// Only strings, numbers, booleans and (possibly multi-line) arrays are
+// supported as values, which is all the system definition needs. A
+// document is at most MAX_DOCUMENT bytes and its arrays nest at most
+// MAX_NESTING deep, so a malformed one is an error rather than a stack
+// overflow (see fuzz.rs).
+
+use crate::adapt::AdaptationConfig;
+use crate::adapters::InputKind;
+use crate::alarm::{AlarmConfig, Expression};
+use crate::align::{AlignConfig, DEFAULT_MAX_WAIT_SECS};
+use crate::budget::BudgetConfig;
+use crate::cascade::CascadeConfig;
+use crate::comfort::{ComfortConfig, Zone as ComfortZone};
+use crate::envelope::EnvelopeConfig;
+use crate::exec::{ExecConfig, Invoke, Stdin, Template};
+use crate::forecast::{Extrapolation, ForecastConfig, MAX_STEPS};
+use crate::fusion::{self, FusionConfig, SourceConfig, Strategy};
+use crate::generator::{Condition, GeneratorConfig, GeneratorKind};
+use crate::guard::TuningConfig;
+use crate::interlock::InterlockConfig;
+use crate::links::{self, Link, Param};
+use crate::noise::NoiseConfig;
+use crate::objective::{Better, ObjectiveConfig};
+use crate::oscillation::OscillationConfig;
+use crate::overrides::OverrideConfig;
+use crate::palette::PaletteName;
+use crate::pipeline::{PipelineConfig, StatusMode};
+use crate::schema;
+use crate::ties;
+use crate::timestamp::Zone;
+use crate::trigger::RecomputeConfig;
+use crate::units::{Conversion, Unit};
+use crate::window::{Aggregate, WindowConfig};
+use crate::{
+    Aggregation, ConsequentCap, DefuzzMethod, Defuzzification, FuzzyRule, FuzzySystem,
+    FuzzyVariable, Implication, MAX_RESOLUTION, MIN_RESOLUTION, MembershipFunction, Metadata,
+    OutOfRange, OutputDefuzz, RuleGroup, SetDefinition,
+};
+use std::collections::HashSet;
+
+/// Longest document read, in bytes; configs and state files are far smaller
+pub(crate) const MAX_DOCUMENT: usize = 1024 * 1024;
+/// Deepest nesting of arrays
+pub(crate) const MAX_NESTING: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Str(String),
+    Num(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+/// A `[header]` or `[[header]]` section with its key/value entries
+#[derive(Debug)]
+pub(crate) struct Section {
+    pub header: String,
+    pub is_array: bool,
+    pub line: usize,
+    pub entries: Vec<(String, Value, usize)>,
+}
+
+impl Section {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries
+            .iter()
+            .find(|(k, _, _)| k == key)
+            .map(|(_, v, _)| v)
+    }
+
+    pub fn line_of(&self, key: &str) -> usize {
+        self.entries
+            .iter()
+            .find(|(k, _, _)| k == key)
+            .map(|(_, _, line)| *line)
+            .unwrap_or(self.line)
+    }
+
+    pub fn str(&self, key: &str) -> Result<&str, String> {
+        match self.get(key) {
+            Some(Value::Str(s)) => Ok(s),
+            Some(_) => Err(format!(
+                "line {}: '{}' must be a string",
+                self.line_of(key),
+                key
+            )),
+            None => Err(format!("line {}: missing '{}'", self.line, key)),
+        }
+    }
+
+    pub fn num(&self, key: &str) -> Result<f64, String> {
+        match self.get(key) {
+            Some(Value::Num(n)) => Ok(*n),
+            Some(_) => Err(format!(
+                "line {}: '{}' must be a number",
+                self.line_of(key),
+                key
+            )),
+            None => Err(format!("line {}: missing '{}'", self.line, key)),
+        }
+    }
+
+    /// Optional boolean, `default` when the key is absent
+    pub fn bool_or(&self, key: &str, default: bool) -> Result<bool, String> {
+        match self.get(key) {
+            None => Ok(default),
+            Some(Value::Bool(b)) => Ok(*b),
+            Some(_) => Err(format!(
+                "line {}: '{}' must be a boolean",
+                self.line_of(key),
+                key
+            )),
+        }
+    }
+
+    /// Optional number, `default` when the key is absent
+    pub fn num_or(&self, key: &str, default: f64) -> Result<f64, String> {
+        match self.get(key) {
+            None => Ok(default),
+            Some(_) => self.num(key),
+        }
+    }
+
+    pub fn nums(&self, key: &str) -> Result<Vec<f64>, String> {
+        let err = || {
+            format!(
+                "line {}: '{}' must be an array of numbers",
+                self.line_of(key),
+                key
+            )
+        };
+        match self.get(key) {
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|v| match v {
+                    Value::Num(n) => Ok(*n),
+                    _ => Err(err()),
+                })
+                .collect(),
+            Some(_) => Err(err()),
+            None => Err(format!("line {}: missing '{}'", self.line, key)),
+        }
+    }
+
+    pub fn strs(&self, key: &str) -> Result<Vec<(String, usize)>, String> {
+        let line = self.line_of(key);
+        let err = || format!("line {}: '{}' must be an array of strings", line, key);
+        match self.get(key) {
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|v| match v {
+                    Value::Str(s) => Ok((s.clone(), line)),
+                    _ => Err(err()),
+                })
+                .collect(),
+            Some(_) => Err(err()),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Split TOML text into sections; the first section holds top-level keys
+pub(crate) fn parse_document(text: &str) -> Result<Vec<Section>, String> {
+    if text.len() > MAX_DOCUMENT {
+        return Err(format!(
+            "{} bytes is more than the {} a document may have",
+            text.len(),
+            MAX_DOCUMENT
+        ));
+    }
+    let mut sections = vec![Section {
+        header: String::new(),
+        is_array: false,
+        line: 1,
+        entries: Vec::new(),
+    }];
+
+    let lines: Vec<&str> = text.lines().collect();
+    // Keys of the last section, for duplicates
+    let mut keys = HashSet::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line_no = i + 1;
+        let line = strip_comment(lines[i]).trim().to_string();
+        i += 1;
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+            keys.clear();
+            sections.push(Section {
+                header: header.trim().to_string(),
+                is_array: true,
+                line: line_no,
+                entries: Vec::new(),
+            });
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            keys.clear();
+            sections.push(Section {
+                header: header.trim().to_string(),
+                is_array: false,
+                line: line_no,
+                entries: Vec::new(),
+            });
+            continue;
+        }
+
+        let (key, rest) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected 'key = value'", line_no))?;
+        let key = key.trim().trim_matches('"').to_string();
+        if key.is_empty() {
+            return Err(format!("line {}: empty key", line_no));
+        }
+
+        // Arrays may span several lines: keep reading until brackets balance
+        let mut raw = rest.trim().to_string();
+        let mut brackets = Brackets::default();
+        brackets.feed(&raw);
+        while brackets.depth > 0 {
+            if i >= lines.len() {
+                return Err(format!("line {}: unterminated array", line_no));
+            }
+            let next = strip_comment(lines[i]).trim();
+            brackets.feed(" ");
+            brackets.feed(next);
+            raw.push(' ');
+            raw.push_str(next);
+            i += 1;
+        }
+
+        let mut parser = ValueParser {
+            chars: raw.chars().collect(),
+            pos: 0,
+            depth: 0,
+        };
+        let value = parser
+            .value()
+            .map_err(|e| format!("line {}: {}", line_no, e))?;
+        parser.skip_ws();
+        if parser.pos != parser.chars.len() {
+            return Err(format!("line {}: unexpected trailing characters", line_no));
+        }
+
+        let section = sections.last_mut().expect("root section always exists");
+        if !keys.insert(key.clone()) {
+            return Err(format!("line {}: duplicate key '{}'", line_no, key));
+        }
+        section.entries.push((key, value, line_no));
+    }
+
+    Ok(sections)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\\' if in_string => {
+                escaped = !escaped;
+                continue;
+            }
+            '"' if !escaped => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+/// How deep in brackets the text fed so far ends, outside strings; fed
+/// line by line so a long array is scanned once
+#[derive(Default)]
+struct Brackets {
+    depth: i32,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl Brackets {
+    fn feed(&mut self, text: &str) {
+        for c in text.chars() {
+            match c {
+                '\\' if self.in_string => {
+                    self.escaped = !self.escaped;
+                    continue;
+                }
+                '"' if !self.escaped => self.in_string = !self.in_string,
+                '[' if !self.in_string => self.depth += 1,
+                ']' if !self.in_string => self.depth -= 1,
+                _ => {}
+            }
+            self.escaped = false;
+        }
+    }
+}
+
+struct ValueParser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Arrays open around the current value
+    depth: usize,
+}
+
+impl ValueParser {
+    fn skip_ws(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_ws();
+        match self.chars.get(self.pos) {
+            Some('"') => self.string(),
+            Some('[') => self.array(),
+            Some(_) => self.scalar(),
+            None => Err("missing value".to_string()),
+        }
+    }
+
+    fn string(&mut self) -> Result<Value, String> {
+        self.pos += 1;
+        let mut out = String::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            self.pos += 1;
+            match c {
+                '"' => return Ok(Value::Str(out)),
+                '\\' => {
+                    let escaped = self.chars.get(self.pos).copied();
+                    self.pos += 1;
+                    match escaped {
+                        Some('n') => out.push('\n'),
+                        Some('t') => out.push('\t'),
+                        Some('"') => out.push('"'),
+                        Some('\\') => out.push('\\'),
+                        _ => return Err("invalid escape sequence".to_string()),
+                    }
+                }
+                _ => out.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        if self.depth == MAX_NESTING {
+            return Err(format!("arrays nested more than {} deep", MAX_NESTING));
+        }
+        self.pos += 1;
+        self.depth += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_ws();
+            if self.chars.get(self.pos) == Some(&']') {
+                self.pos += 1;
+                self.depth -= 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_ws();
+            match self.chars.get(self.pos) {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                _ => return Err("expected ',' or ']' in array".to_string()),
+            }
+        }
+    }
+
+    fn scalar(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| !c.is_whitespace() && *c != ',' && *c != ']')
+        {
+            self.pos += 1;
+        }
+        let word: String = self.chars[start..self.pos].iter().collect();
+        match word.as_str() {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => word
+                .replace('_', "")
+                .parse::<f64>()
+                .map(Value::Num)
+                .map_err(|_| format!("invalid value '{}'", word)),
+        }
+    }
+}
+
+// ============================================================================
+// SYSTEM <-> TOML
+// ============================================================================
+
+fn variable_from_section(section: &Section) -> Result<FuzzyVariable, String> {
+    let (min, max) = (section.num("min")?, section.num("max")?);
+    for (key, value) in [("min", min), ("max", max)] {
+        if !value.is_finite() {
+            return Err(format!(
+                "line {}: '{}' must be a finite number, not {}",
+                section.line_of(key),
+                key,
+                value
+            ));
+        }
+    }
+    if min >= max {
+        return Err(format!(
+            "line {}: 'min' must be below 'max', got {} and {}",
+            section.line_of("max"),
+            min,
+            max
+        ));
+    }
+    let mut var = FuzzyVariable::new(section.str("name")?, min, max);
+    var.unit = unit_from_section(section)?;
+    Ok(var)
+}
+
+/// `method`, `resolution` and `implication` of a `[defuzzification]` or
+/// output section, each None when the section leaves it out
+fn defuzz_from_section(section: &Section) -> Result<OutputDefuzz, String> {
+    let method = match section.get("method") {
+        Some(_) => Some(DefuzzMethod::parse(section.str("method")?).ok_or_else(|| {
+            format!(
+                "line {}: 'method' must be \"centroid\", \"plateau_centroid\" or \"weighted_average\"",
+                section.line_of("method")
+            )
+        })?),
+        None => None,
+    };
+    let resolution = match section.get("resolution") {
+        Some(_) => {
+            let resolution = section.num("resolution")?;
+            if resolution.fract() != 0.0
+                || !(MIN_RESOLUTION as f64..=MAX_RESOLUTION as f64).contains(&resolution)
+            {
+                return Err(format!(
+                    "line {}: 'resolution' must be a whole number from {} to {}",
+                    section.line_of("resolution"),
+                    MIN_RESOLUTION,
+                    MAX_RESOLUTION
+                ));
+            }
+            Some(resolution as usize)
+        }
+        None => None,
+    };
+    let implication = match section.get("implication") {
+        Some(_) => Some(
+            Implication::parse(section.str("implication")?).ok_or_else(|| {
+                format!(
+                    "line {}: 'implication' must be \"min\" or \"product\"",
+                    section.line_of("implication")
+                )
+            })?,
+        ),
+        None => None,
+    };
+    Ok(OutputDefuzz {
+        method,
+        resolution,
+        implication,
+    })
+}
+
+fn unit_from_section(section: &Section) -> Result<Unit, String> {
+    let optional_str = |key: &str| match section.get(key) {
+        Some(_) => section.str(key).map(|s| Some(s.to_string())),
+        None => Ok(None),
+    };
+    let precision = match section.get("precision") {
+        Some(_) => {
+            let precision = section.num("precision")?;
+            if precision < 0.0 || precision.fract() != 0.0 {
+                return Err(format!(
+                    "line {}: 'precision' must be a whole number of decimals",
+                    section.line_of("precision")
+                ));
+            }
+            Some(precision as usize)
+        }
+        None => None,
+    };
+    let name = optional_str("unit")?.unwrap_or_default();
+    let display = match optional_str("display_unit")? {
+        Some(unit) if section.get("display_scale").is_some() => Some(Conversion {
+            unit,
+            scale: section.num("display_scale")?,
+            offset: section.num_or("display_offset", 0.0)?,
+        }),
+        Some(unit) => match Conversion::known(&name, &unit) {
+            Some(conversion) if section.get("display_offset").is_none() => Some(conversion),
+            _ => {
+                return Err(format!(
+                    "line {}: no known conversion from '{}' to '{}', give 'display_scale'",
+                    section.line_of("display_unit"),
+                    name,
+                    unit
+                ));
+            }
+        },
+        None => {
+            for key in ["display_scale", "display_offset"] {
+                if section.get(key).is_some() {
+                    return Err(format!(
+                        "line {}: '{}' needs a 'display_unit'",
+                        section.line_of(key),
+                        key
+                    ));
+                }
+            }
+            None
+        }
+    };
+    let unit = Unit {
+        name,
+        precision,
+        display,
+    };
+    unit.check().map_err(|e| {
+        let key = [
+            "precision",
+            "display_unit",
+            "display_scale",
+            "display_offset",
+        ]
+        .into_iter()
+        .find(|key| e.contains(key))
+        .unwrap_or("unit");
+        format!("line {}: {}", section.line_of(key), e)
+    })?;
+    Ok(unit)
+}
+
+/// An array of [x, y] pairs
+fn points(section: &Section, key: &str) -> Result<Vec<(f64, f64)>, String> {
+    let err = || {
+        format!(
+            "line {}: '{}' must be an array of [x, y] pairs",
+            section.line_of(key),
+            key
+        )
+    };
+    match section.get(key) {
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::Array(pair) => match pair.as_slice() {
+                    [Value::Num(x), Value::Num(y)] => Ok((*x, *y)),
+                    _ => Err(err()),
+                },
+                _ => Err(err()),
+            })
+            .collect(),
+        Some(_) => Err(err()),
+        None => Err(format!("line {}: missing '{}'", section.line, key)),
+    }
+}
+
+/// `params` as written: numbers and links to other sets' parameters
+fn param_specs(section: &Section) -> Result<Vec<Param>, String> {
+    let line = section.line_of("params");
+    match section.get("params") {
+        Some(Value::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::Num(n) => Ok(Param::Value(*n)),
+                Value::Str(text) => Link::parse(text).map(Param::Link).ok_or_else(|| {
+                    format!(
+                        "line {}: '{}' is not a parameter link like \"Mild.b\"",
+                        line, text
+                    )
+                }),
+                _ => Err(format!(
+                    "line {}: 'params' must be an array of numbers and links",
+                    line
+                )),
+            })
+            .collect(),
+        Some(_) => Err(format!(
+            "line {}: 'params' must be an array of numbers and links",
+            line
+        )),
+        None => Err(format!("line {}: missing 'params'", section.line)),
+    }
+}
+
+/// The sets of one variable, their parameter links resolved
+fn sets_from_sections(sections: &[&Section]) -> Result<Vec<SetDefinition>, String> {
+    let specs = sections
+        .iter()
+        .map(|section| Ok((section.str("name")?.to_string(), param_specs(section)?)))
+        .collect::<Result<Vec<_>, String>>()?;
+    let resolved = links::resolve(&specs)
+        .map_err(|(i, e)| format!("line {}: {}", sections[i].line_of("params"), e))?;
+    sections
+        .iter()
+        .zip(&specs)
+        .zip(resolved)
+        .map(|((section, (_, spec)), params)| {
+            let mut set = set_from_section(section, &params)?;
+            set.links = links::links_of(spec);
+            Ok(set)
+        })
+        .collect()
+}
+
+fn set_from_section(section: &Section, params: &[f64]) -> Result<SetDefinition, String> {
+    let function = MembershipFunction::from_params(section.str("shape")?, params)
+        .map_err(|e| format!("line {}: {}", section.line_of("params"), e))?;
+    let uncertainty = match section.get("uncertainty") {
+        Some(_) => section.nums("uncertainty")?,
+        None => Vec::new(),
+    };
+    if !uncertainty.is_empty() && uncertainty.len() != params.len() {
+        return Err(format!(
+            "line {}: 'uncertainty' needs one range per parameter ({})",
+            section.line,
+            params.len()
+        ));
+    }
+    if uncertainty.iter().any(|u| !(u.is_finite() && *u >= 0.0)) {
+        return Err(format!(
+            "line {}: 'uncertainty' ranges must be finite and not negative",
+            section.line_of("uncertainty")
+        ));
+    }
+    Ok(SetDefinition {
+        name: section.str("name")?.to_string(),
+        function,
+        uncertainty,
+        links: Vec::new(),
+    })
+}
+
+/// MAJOR.MINOR.PATCH, optionally followed by `-prerelease` or `+build`
+fn is_semver(text: &str) -> bool {
+    let core = text.split(['-', '+']).next().unwrap_or("");
+    let parts: Vec<&str> = core.split('.').collect();
+    parts.len() == 3
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Starts with a YYYY-MM-DD date
+fn is_date(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() >= 10
+        && bytes[..10].iter().enumerate().all(|(i, b)| match i {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+fn metadata_from_section(section: &Section) -> Result<Metadata, String> {
+    let field = |key: &str| -> Result<Option<String>, String> {
+        match section.get(key) {
+            None => Ok(None),
+            Some(_) => section.str(key).map(|v| Some(v.to_string())),
+        }
+    };
+    let metadata = Metadata {
+        name: field("name")?,
+        description: field("description")?,
+        version: field("version")?,
+        author: field("author")?,
+        created: field("created")?,
+        modified: field("modified")?,
+    };
+    if let Some(version) = &metadata.version
+        && !is_semver(version)
+    {
+        return Err(format!(
+            "line {}: 'version' must be MAJOR.MINOR.PATCH, got '{}'",
+            section.line_of("version"),
+            version
+        ));
+    }
+    for (key, value) in [
+        ("created", &metadata.created),
+        ("modified", &metadata.modified),
+    ] {
+        if let Some(value) = value
+            && !is_date(value)
+        {
+            return Err(format!(
+                "line {}: '{}' must start with a YYYY-MM-DD date",
+                section.line_of(key),
+                key
+            ));
+        }
+    }
+    Ok(metadata)
+}
+
+/// Build a system definition from TOML text
+pub fn parse_system(text: &str) -> Result<FuzzySystem, String> {
+    let text = schema::upgrade(text)?.text;
+    let sections = parse_document(&text)?;
+    let mut inputs: Vec<FuzzyVariable> = Vec::new();
+    let mut outputs: Vec<FuzzyVariable> = Vec::new();
+    // Set sections per variable, built once every set is known
+    let mut input_sets: Vec<Vec<&Section>> = Vec::new();
+    let mut output_sets: Vec<Vec<&Section>> = Vec::new();
+    let mut rules = Vec::new();
+    let mut groups: Vec<RuleGroup> = Vec::new();
+    let mut pipeline = PipelineConfig::default();
+    let mut defuzzification = Defuzzification::default();
+    let mut metadata = Metadata::default();
+    let mut recompute = RecomputeConfig::default();
+    let mut alignment = AlignConfig::default();
+    // Line of the [alignment] section, for a wait too short
+    let mut alignment_line = None;
+    let mut overrides = OverrideConfig::default();
+    let mut forecast = ForecastConfig::default();
+    let mut tuning = TuningConfig::default();
+    let mut oscillation = None;
+    let mut budget = None;
+    let mut adaptation = None;
+    // Name, condition and its line, resolved once every name is known
+    let mut alarms: Vec<(String, String, usize)> = Vec::new();
+    // Score, its line and which way it improves, resolved like the alarms
+    let mut objective: Option<(String, usize, Better)> = None;
+    let mut timezone = Zone::Local;
+    let mut palette = PaletteName::Classic;
+    let mut pause_on_blur = false;
+    let mut comfort = None;
+    let mut cascade = None;
+    let mut exec = None;
+    let mut generator = GeneratorConfig::default();
+    let mut envelopes = Vec::new();
+    let mut noise = Vec::new();
+    let mut interlocks: Vec<InterlockConfig> = Vec::new();
+    let mut caps = Vec::new();
+
+    for section in &sections {
+        match (section.header.as_str(), section.is_array) {
+            ("", false) => {
+                for (text, line) in section.strs("rules")? {
+                    let rule =
+                        FuzzyRule::parse(&text).map_err(|e| format!("line {}: {}", line, e))?;
+                    rules.push(rule);
+                }
+            }
+            ("metadata", false) => metadata = metadata_from_section(section)?,
+            ("group", true) => {
+                let name = section.str("name")?.to_string();
+                if groups.iter().any(|g| g.name == name) {
+                    return Err(format!("line {}: duplicate group '{}'", section.line, name));
+                }
+                let weight = section.num_or("weight", 1.0)?;
+                if !(0.0..=1.0).contains(&weight) {
+                    return Err(format!(
+                        "line {}: group weight must be between 0 and 1",
+                        section.line
+                    ));
+                }
+                for (text, line) in section.strs("rules")? {
+                    let mut rule =
+                        FuzzyRule::parse(&text).map_err(|e| format!("line {}: {}", line, e))?;
+                    rule.group = Some(name.clone());
+                    rules.push(rule);
+                }
+                groups.push(RuleGroup {
+                    name,
+                    enabled: section.bool_or("enabled", true)?,
+                    weight,
+                });
+            }
+            ("input", true) => {
+                let mut input = variable_from_section(section)?;
+                input.kind = match section.get("kind") {
+                    None => InputKind::Direct,
+                    Some(_) => match section.str("kind")? {
+                        "direct" => InputKind::Direct,
+                        "dewpoint" => InputKind::DewPoint {
+                            temperature: match section.get("temperature") {
+                                Some(_) => section.str("temperature")?.to_string(),
+                                None => "temperature".to_string(),
+                            },
+                        },
+                        other => {
+                            return Err(format!(
+                                "line {}: unknown input kind '{}'",
+                                section.line, other
+                            ));
+                        }
+                    },
+                };
+                input.min_delta = section.num_or("min_delta", 0.0)?;
+                if input.min_delta < 0.0 {
+                    return Err(format!(
+                        "line {}: 'min_delta' must not be negative",
+                        section.line_of("min_delta")
+                    ));
+                }
+                if section.get("window_secs").is_some() {
+                    let secs = section.num("window_secs")?;
+                    if secs <= 0.0 {
+                        return Err(format!(
+                            "line {}: 'window_secs' must be positive",
+                            section.line_of("window_secs")
+                        ));
+                    }
+                    let aggregate = match section.get("aggregate") {
+                        None => Aggregate::default(),
+                        Some(_) => {
+                            Aggregate::parse(section.str("aggregate")?).ok_or_else(|| {
+                                format!(
+                                    "line {}: 'aggregate' must be \"mean\", \"median\", \
+                                 \"trimmed_mean\" or \"max\"",
+                                    section.line_of("aggregate")
+                                )
+                            })?
+                        }
+                    };
+                    let stale_windows = section.num_or("stale_windows", 0.0)?;
+                    if stale_windows < 0.0 || stale_windows.fract() != 0.0 {
+                        return Err(format!(
+                            "line {}: 'stale_windows' must be a whole number, 0 or more",
+                            section.line_of("stale_windows")
+                        ));
+                    }
+                    input.window = Some(WindowConfig {
+                        secs,
+                        aggregate,
+                        stale_windows: stale_windows as usize,
+                    });
+                } else if let Some(key) = ["aggregate", "stale_windows"]
+                    .into_iter()
+                    .find(|key| section.get(key).is_some())
+                {
+                    return Err(format!(
+                        "line {}: '{}' needs a 'window_secs'",
+                        section.line_of(key),
+                        key
+                    ));
+                }
+                input.latency_secs = section.num_or("latency_secs", 0.0)?;
+                if input.latency_secs < 0.0 {
+                    return Err(format!(
+                        "line {}: 'latency_secs' must not be negative",
+                        section.line_of("latency_secs")
+                    ));
+                }
+                if input.latency_secs > 0.0 && input.window.is_some() {
+                    // A window takes its samples as they arrive
+                    return Err(format!(
+                        "line {}: 'latency_secs' cannot go with 'window_secs'",
+                        section.line_of("latency_secs")
+                    ));
+                }
+                if section.get("fusion").is_some() || section.get("max_spread").is_some() {
+                    let strategy = match section.get("fusion") {
+                        None => Strategy::default(),
+                        Some(_) => Strategy::parse(section.str("fusion")?).ok_or_else(|| {
+                            format!(
+                                "line {}: 'fusion' must be \"mean\", \"median\", \"min\", \
+                                 \"max\" or \"weighted\"",
+                                section.line_of("fusion")
+                            )
+                        })?,
+                    };
+                    let max_spread = match section.get("max_spread") {
+                        None => None,
+                        Some(_) => Some(section.num("max_spread")?),
+                    };
+                    if max_spread.is_some_and(|spread| spread <= 0.0) {
+                        return Err(format!(
+                            "line {}: 'max_spread' must be positive",
+                            section.line_of("max_spread")
+                        ));
+                    }
+                    input.fusion = Some(FusionConfig {
+                        strategy,
+                        max_spread,
+                        sources: Vec::new(),
+                    });
+                }
+                if section.get("out_of_range").is_some() {
+                    input.out_of_range = OutOfRange::parse(section.str("out_of_range")?)
+                        .ok_or_else(|| {
+                            format!(
+                                "line {}: 'out_of_range' must be \"clamp\", \"extend\" or \"error\"",
+                                section.line_of("out_of_range")
+                            )
+                        })?;
+                }
+                inputs.push(input);
+                input_sets.push(Vec::new());
+            }
+            ("input.set", true) => {
+                input_sets
+                    .last_mut()
+                    .ok_or_else(|| {
+                        format!("line {}: [[input.set]] before [[input]]", section.line)
+                    })?
+                    .push(section);
+            }
+            ("input.source", true) => {
+                let input = inputs.last_mut().ok_or_else(|| {
+                    format!("line {}: [[input.source]] before [[input]]", section.line)
+                })?;
+                let fusion = input.fusion.get_or_insert_with(FusionConfig::default);
+                let name = section.str("name")?;
+                if !fusion::valid_name(name) {
+                    return Err(format!(
+                        "line {}: source name '{}' may only have letters, digits, '_' and '-'",
+                        section.line_of("name"),
+                        name
+                    ));
+                }
+                if fusion.sources.iter().any(|s| s.name == name) {
+                    return Err(format!(
+                        "line {}: duplicate source '{}'",
+                        section.line_of("name"),
+                        name
+                    ));
+                }
+                let optional = |key: &str| match section.get(key) {
+                    None => Ok(None),
+                    Some(_) => section.num(key).map(Some),
+                };
+                let source = SourceConfig {
+                    name: name.to_string(),
+                    trust: section.num_or("trust", 1.0)?,
+                    min: optional("min")?,
+                    max: optional("max")?,
+                    max_rate: optional("max_rate")?,
+                    timeout_secs: optional("timeout_secs")?,
+                };
+                for (key, value) in [
+                    ("trust", Some(source.trust)),
+                    ("max_rate", source.max_rate),
+                    ("timeout_secs", source.timeout_secs),
+                ] {
+                    if value.is_some_and(|value| value <= 0.0) {
+                        return Err(format!(
+                            "line {}: '{}' must be positive",
+                            section.line_of(key),
+                            key
+                        ));
+                    }
+                }
+                if let (Some(min), Some(max)) = (source.min, source.max)
+                    && min >= max
+                {
+                    return Err(format!(
+                        "line {}: 'min' must be below 'max'",
+                        section.line_of("max")
+                    ));
+                }
+                fusion.sources.push(source);
+            }
+            ("output", is_array) => {
+                if !is_array && !outputs.is_empty() {
+                    return Err(format!(
+                        "line {}: duplicate [output], use [[output]] for several outputs",
+                        section.line
+                    ));
+                }
+                let mut output = variable_from_section(section)?;
+                output.defuzz = defuzz_from_section(section)?;
+                if outputs.iter().any(|o| o.name == output.name) {
+                    return Err(format!(
+                        "line {}: duplicate output '{}'",
+                        section.line, output.name
+                    ));
+                }
+                outputs.push(output);
+                output_sets.push(Vec::new());
+            }
+            ("output.set", true) => {
+                output_sets
+                    .last_mut()
+                    .ok_or_else(|| {
+                        format!("line {}: [[output.set]] before [output]", section.line)
+                    })?
+                    .push(section);
+            }
+            ("defuzzification", false) => {
+                let defaults = defuzz_from_section(section)?;
+                defuzzification.method = defaults.method.unwrap_or(defuzzification.method);
+                defuzzification.resolution =
+                    defaults.resolution.unwrap_or(defuzzification.resolution);
+                defuzzification.implication =
+                    defaults.implication.unwrap_or(defuzzification.implication);
+                defuzzification.support_threshold =
+                    section.num_or("support_threshold", defuzzification.support_threshold)?;
+                if !(0.0..=1.0).contains(&defuzzification.support_threshold) {
+                    return Err(format!(
+                        "line {}: 'support_threshold' must be between 0 and 1",
+                        section.line
+                    ));
+                }
+                if section.get("aggregation").is_some() {
+                    defuzzification.aggregation = Aggregation::parse(section.str("aggregation")?)
+                        .ok_or_else(|| {
+                            format!(
+                                "line {}: 'aggregation' must be \"max\", \"softmax\" or \"bounded_sum\"",
+                                section.line_of("aggregation")
+                            )
+                        })?;
+                }
+                defuzzification.softmax_temperature =
+                    section.num_or("softmax_temperature", defuzzification.softmax_temperature)?;
+                if !(defuzzification.softmax_temperature > 0.0
+                    && defuzzification.softmax_temperature.is_finite())
+                {
+                    return Err(format!(
+                        "line {}: 'softmax_temperature' must be above 0",
+                        section.line_of("softmax_temperature")
+                    ));
+                }
+                defuzzification.tie_tolerance =
+                    section.num_or("tie_tolerance", defuzzification.tie_tolerance)?;
+                if !(0.0..=1.0).contains(&defuzzification.tie_tolerance) {
+                    return Err(format!(
+                        "line {}: 'tie_tolerance' must be between 0 and 1",
+                        section.line_of("tie_tolerance")
+                    ));
+                }
+                if section.get("cap_profile").is_some() {
+                    defuzzification.cap_profile = Some(section.str("cap_profile")?.to_string());
+                }
+            }
+            ("cap", true) => {
+                let max = section.num("max")?;
+                if !(0.0..=1.0).contains(&max) {
+                    return Err(format!(
+                        "line {}: 'max' must be between 0 and 1",
+                        section.line_of("max")
+                    ));
+                }
+                caps.push(ConsequentCap {
+                    profile: section.str("profile")?.to_string(),
+                    output: section.str("output")?.to_string(),
+                    set: section.str("set")?.to_string(),
+                    max,
+                });
+            }
+            ("recompute", false) => {
+                recompute.max_interval_secs = section.num_or("max_interval_secs", 0.0)?;
+                if recompute.max_interval_secs < 0.0 {
+                    return Err(format!(
+                        "line {}: 'max_interval_secs' must not be negative",
+                        section.line_of("max_interval_secs")
+                    ));
+                }
+            }
+            ("alignment", false) => {
+                alignment.max_wait_secs = section.num_or("max_wait_secs", DEFAULT_MAX_WAIT_SECS)?;
+                if alignment.max_wait_secs < 0.0 {
+                    return Err(format!(
+                        "line {}: 'max_wait_secs' must not be negative",
+                        section.line_of("max_wait_secs")
+                    ));
+                }
+                alignment_line = Some(section.line_of("max_wait_secs"));
+            }
+            ("override", false) => {
+                overrides.duration_secs = section.num_or("duration_secs", 30.0)?;
+                if overrides.duration_secs < 0.0 {
+                    return Err(format!(
+                        "line {}: 'duration_secs' must not be negative",
+                        section.line_of("duration_secs")
+                    ));
+                }
+            }
+            ("forecast", false) => {
+                if section.get("extrapolation").is_some() {
+                    forecast.extrapolation = Extrapolation::parse(section.str("extrapolation")?)
+                        .ok_or_else(|| {
+                            format!(
+                                "line {}: 'extrapolation' must be \"linear\" or \"hold\"",
+                                section.line_of("extrapolation")
+                            )
+                        })?;
+                }
+                for (key, value) in [
+                    ("horizon_secs", &mut forecast.horizon_secs),
+                    ("window_secs", &mut forecast.window_secs),
+                ] {
+                    *value = section.num_or(key, *value)?;
+                    if *value <= 0.0 {
+                        return Err(format!(
+                            "line {}: '{}' must be above 0",
+                            section.line_of(key),
+                            key
+                        ));
+                    }
+                }
+                let steps = section.num_or("steps", forecast.steps as f64)?;
+                if !((1.0..=MAX_STEPS as f64).contains(&steps) && steps.fract() == 0.0) {
+                    return Err(format!(
+                        "line {}: 'steps' must be a whole number from 1 to {}",
+                        section.line_of("steps"),
+                        MAX_STEPS
+                    ));
+                }
+                forecast.steps = steps as usize;
+            }
+            ("budget", false) => {
+                let defaults = BudgetConfig::default();
+                let config = BudgetConfig {
+                    cycle_ms: section.num_or("cycle_ms", defaults.cycle_ms)?,
+                    recover_cycles: section
+                        .num_or("recover_cycles", defaults.recover_cycles as f64)?
+                        as usize,
+                };
+                if config.cycle_ms <= 0.0 {
+                    return Err(format!(
+                        "line {}: 'cycle_ms' must be above 0",
+                        section.line_of("cycle_ms")
+                    ));
+                }
+                let cycles = section.num_or("recover_cycles", 1.0)?;
+                if !(cycles >= 1.0 && cycles.fract() == 0.0) {
+                    return Err(format!(
+                        "line {}: 'recover_cycles' must be a whole number of at least 1",
+                        section.line_of("recover_cycles")
+                    ));
+                }
+                budget = Some(config);
+            }
+            ("adaptation", false) => {
+                let defaults = AdaptationConfig::default();
+                let config = AdaptationConfig {
+                    enabled: section.bool_or("enabled", defaults.enabled)?,
+                    rate: section.num_or("rate", defaults.rate)?,
+                    max_step: section.num_or("max_step", defaults.max_step)?,
+                };
+                if !(config.rate > 0.0 && config.rate.is_finite()) {
+                    return Err(format!(
+                        "line {}: 'rate' must be above 0",
+                        section.line_of("rate")
+                    ));
+                }
+                if !(config.max_step > 0.0 && config.max_step <= 1.0) {
+                    return Err(format!(
+                        "line {}: 'max_step' must be above 0 and at most 1",
+                        section.line_of("max_step")
+                    ));
+                }
+                adaptation = Some(config);
+            }
+            ("tuning", false) => {
+                tuning.max_similarity = section.num_or("max_similarity", 0.9)?;
+                if !(tuning.max_similarity > 0.0 && tuning.max_similarity <= 1.0) {
+                    return Err(format!(
+                        "line {}: 'max_similarity' must be above 0 and at most 1",
+                        section.line_of("max_similarity")
+                    ));
+                }
+                if section.get("max_hole").is_some() {
+                    let max_hole = section.num("max_hole")?;
+                    if max_hole < 0.0 {
+                        return Err(format!(
+                            "line {}: 'max_hole' must not be negative",
+                            section.line_of("max_hole")
+                        ));
+                    }
+                    tuning.max_hole = Some(max_hole);
+                }
+            }
+            ("oscillation", false) => {
+                let defaults = OscillationConfig::default();
+                let config = OscillationConfig {
+                    window_secs: section.num_or("window_secs", defaults.window_secs)?,
+                    min_reversals: section.num_or("min_reversals", defaults.min_reversals as f64)?
+                        as usize,
+                    min_amplitude: section.num_or("min_amplitude", defaults.min_amplitude)?,
+                };
+                for key in ["window_secs", "min_amplitude"] {
+                    if section.num_or(key, 1.0)? <= 0.0 {
+                        return Err(format!(
+                            "line {}: '{}' must be above 0",
+                            section.line_of(key),
+                            key
+                        ));
+                    }
+                }
+                let reversals = section.num_or("min_reversals", 1.0)?;
+                if !(reversals >= 1.0 && reversals.fract() == 0.0) {
+                    return Err(format!(
+                        "line {}: 'min_reversals' must be a whole number of at least 1",
+                        section.line_of("min_reversals")
+                    ));
+                }
+                oscillation = Some(config);
+            }
+            ("alarm", true) => {
+                let name = section.str("name")?.to_string();
+                if alarms.iter().any(|(other, _, _)| *other == name) {
+                    return Err(format!("line {}: duplicate alarm '{}'", section.line, name));
+                }
+                let when = section.str("when")?.to_string();
+                alarms.push((name, when, section.line_of("when")));
+            }
+            ("objective", false) => {
+                let score = section.str("score")?.to_string();
+                let better = match section.get("better") {
+                    Some(_) => Better::parse(section.str("better")?).ok_or_else(|| {
+                        format!(
+                            "line {}: 'better' must be \"lower\" or \"higher\"",
+                            section.line_of("better")
+                        )
+                    })?,
+                    None => Better::default(),
+                };
+                objective = Some((score, section.line_of("score"), better));
+            }
+            ("display", false) => {
+                if section.get("timezone").is_some() {
+                    timezone = Zone::parse(section.str("timezone")?).ok_or_else(|| {
+                        format!(
+                            "line {}: 'timezone' must be \"local\", \"UTC\" or an offset like \"+02:00\"",
+                            section.line_of("timezone")
+                        )
+                    })?;
+                }
+                if section.get("palette").is_some() {
+                    palette = PaletteName::parse(section.str("palette")?).ok_or_else(|| {
+                        format!(
+                            "line {}: 'palette' must be \"classic\", \"okabe_ito\" or \"tol\"",
+                            section.line_of("palette")
+                        )
+                    })?;
+                }
+                pause_on_blur = section.bool_or("pause_on_blur", false)?;
+            }
+            ("comfort", false) => {
+                let zone = match section.get("vertices") {
+                    Some(_) => ComfortZone::new(points(section, "vertices")?).map_err(|e| {
+                        format!("line {}: 'vertices': {}", section.line_of("vertices"), e)
+                    })?,
+                    None => ComfortZone::summer(),
+                };
+                let show = section.bool_or("show", true)?;
+                comfort = Some((ComfortConfig { show, zone }, section.line));
+            }
+            ("output.pipeline", false) => {
+                pipeline = PipelineConfig {
+                    rate_limit: section.num_or("rate_limit", 0.0)?,
+                    hysteresis: section.num_or("hysteresis", 0.0)?,
+                    min_on_secs: section.num_or("min_on_secs", 0.0)?,
+                    min_off_secs: section.num_or("min_off_secs", 0.0)?,
+                    status: match section.get("status") {
+                        None => StatusMode::Thresholds,
+                        Some(_) => StatusMode::parse(section.str("status")?).ok_or_else(|| {
+                            format!(
+                                "line {}: 'status' must be \"thresholds\" or \"dominant_set\"",
+                                section.line_of("status")
+                            )
+                        })?,
+                    },
+                };
+                for (key, _, line) in &section.entries {
+                    if key != "status" && section.num(key)? < 0.0 {
+                        return Err(format!("line {}: '{}' must not be negative", line, key));
+                    }
+                }
+            }
+            ("output.cascade", false) => {
+                let defaults = CascadeConfig::new(section.str("measured")?);
+                let config = CascadeConfig {
+                    kp: section.num_or("kp", defaults.kp)?,
+                    ki: section.num_or("ki", defaults.ki)?,
+                    kd: section.num_or("kd", defaults.kd)?,
+                    min: section.num_or("min", defaults.min)?,
+                    max: section.num_or("max", defaults.max)?,
+                    plant_gain: section.num_or("plant_gain", defaults.plant_gain)?,
+                    plant_tau_secs: section.num_or("plant_tau_secs", defaults.plant_tau_secs)?,
+                    ..defaults
+                };
+                for key in ["kp", "ki", "kd", "plant_tau_secs"] {
+                    if section.num_or(key, 0.0)? < 0.0 {
+                        return Err(format!(
+                            "line {}: '{}' must not be negative",
+                            section.line_of(key),
+                            key
+                        ));
+                    }
+                }
+                cascade = Some(config);
+            }
+            ("output.exec", false) => {
+                let command = Template::parse(section.str("command")?).map_err(|e| {
+                    format!("line {}: 'command': {}", section.line_of("command"), e)
+                })?;
+                let defaults = ExecConfig::new(command);
+                let choice = |key: &str, names: &str| {
+                    format!("line {}: '{}' must be {}", section.line_of(key), key, names)
+                };
+                let config = ExecConfig {
+                    stdin: match section.get("stdin") {
+                        None => defaults.stdin,
+                        Some(_) => Stdin::parse(section.str("stdin")?)
+                            .ok_or_else(|| choice("stdin", "\"none\" or \"json\""))?,
+                    },
+                    on: match section.get("on") {
+                        None => defaults.on,
+                        Some(_) => Invoke::parse(section.str("on")?)
+                            .ok_or_else(|| choice("on", "\"publish\" or \"change\""))?,
+                    },
+                    timeout_secs: section.num_or("timeout_secs", defaults.timeout_secs)?,
+                    retries: section.num_or("retries", defaults.retries as f64)? as usize,
+                    stall_secs: section.num_or("stall_secs", defaults.stall_secs)?,
+                    ..defaults
+                };
+                for (key, secs) in [
+                    ("timeout_secs", config.timeout_secs),
+                    ("stall_secs", config.stall_secs),
+                ] {
+                    if secs <= 0.0 || !secs.is_finite() {
+                        return Err(format!(
+                            "line {}: '{}' must be above 0",
+                            section.line_of(key),
+                            key
+                        ));
+                    }
+                }
+                let retries = section.num_or("retries", 0.0)?;
+                if retries < 0.0 || retries.fract() != 0.0 {
+                    return Err(format!(
+                        "line {}: 'retries' must be a whole number, 0 or more",
+                        section.line_of("retries")
+                    ));
+                }
+                exec = Some(config);
+            }
+            ("envelope", true) => {
+                let target = |key: &str| match section.get(key) {
+                    None => Ok(None),
+                    Some(_) => section.num(key).map(Some),
+                };
+                let envelope = EnvelopeConfig {
+                    input: section.str("input")?.to_string(),
+                    margin: section.num("margin")?,
+                    low: target("low")?,
+                    high: target("high")?,
+                };
+                if envelope.low.is_none() && envelope.high.is_none() {
+                    return Err(format!(
+                        "line {}: an envelope needs a 'low' or 'high' target",
+                        section.line
+                    ));
+                }
+                envelopes.push(envelope);
+            }
+            ("noise", true) => {
+                let seed = section.num_or("seed", 0.0)?;
+                if !(seed >= 0.0 && seed.fract() == 0.0) {
+                    return Err(format!(
+                        "line {}: 'seed' must be a whole number of at least 0",
+                        section.line_of("seed")
+                    ));
+                }
+                noise.push(NoiseConfig {
+                    input: section.str("input")?.to_string(),
+                    sigma: section.num("sigma")?,
+                    correlation: section.num("correlation")?,
+                    drift: section.num_or("drift", 0.0)?,
+                    seed: seed as u64,
+                });
+            }
+            ("interlock", true) => {
+                let name = section.str("name")?.to_string();
+                // Switched by name in a command line, listed in a CSV
+                if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == ',') {
+                    return Err(format!(
+                        "line {}: interlock name '{}' must be one word without commas",
+                        section.line_of("name"),
+                        name
+                    ));
+                }
+                if interlocks.iter().any(|other| other.name == name) {
+                    return Err(format!(
+                        "line {}: duplicate interlock '{}'",
+                        section.line, name
+                    ));
+                }
+                let priority = section.num_or("priority", 0.0)?;
+                if priority.fract() != 0.0 {
+                    return Err(format!(
+                        "line {}: 'priority' must be a whole number",
+                        section.line_of("priority")
+                    ));
+                }
+                interlocks.push(InterlockConfig {
+                    name,
+                    value: section.num("value")?,
+                    priority: priority as i64,
+                    engaged: section.bool_or("engaged", false)?,
+                });
+            }
+            ("generator", false) => {
+                if section.get("kind").is_some() {
+                    generator.kind = GeneratorKind::parse(section.str("kind")?).ok_or_else(|| {
+                        format!(
+                            "line {}: 'kind' must be \"uniform\", \"boundary\" or \"constrained\"",
+                            section.line_of("kind")
+                        )
+                    })?;
+                }
+                generator.spread = section.num_or("spread", generator.spread)?;
+                if !(generator.spread > 0.0 && generator.spread <= 1.0) {
+                    return Err(format!(
+                        "line {}: 'spread' must be above 0 and at most 1",
+                        section.line_of("spread")
+                    ));
+                }
+                for (text, line) in section.strs("where")? {
+                    let condition =
+                        Condition::parse(&text).map_err(|e| format!("line {}: {}", line, e))?;
+                    generator.conditions.push(condition);
+                }
+            }
+            ("generator.ranges", false) => {
+                for (name, _, line) in &section.entries {
+                    let [min, max] = section.nums(name)?[..] else {
+                        return Err(format!("line {}: '{}' must be [min, max]", line, name));
+                    };
+                    generator.ranges.push((name.clone(), min, max));
+                }
+            }
+            (header, _) => {
+                return Err(format!(
+                    "line {}: unknown section '{}'",
+                    section.line, header
+                ));
+            }
+        }
+    }
+
+    if outputs.is_empty() {
+        return Err("missing [output] section".to_string());
+    }
+    if let Some(input) = inputs
+        .iter()
+        .find(|var| var.fusion.as_ref().is_some_and(|f| f.sources.is_empty()))
+    {
+        return Err(format!(
+            "input '{}': 'fusion' and 'max_spread' need an [[input.source]]",
+            input.name
+        ));
+    }
+    for (var, sections) in inputs
+        .iter_mut()
+        .zip(&input_sets)
+        .chain(outputs.iter_mut().zip(&output_sets))
+    {
+        var.sets = sets_from_sections(sections)?;
+    }
+    let latencies = inputs.iter().map(|var| var.latency_secs);
+    let spread = latencies.clone().fold(f64::NEG_INFINITY, f64::max)
+        - latencies.fold(f64::INFINITY, f64::min);
+    if spread > alignment.max_wait_secs {
+        return Err(match alignment_line {
+            Some(line) => format!(
+                "line {}: 'max_wait_secs' must be at least the spread of the inputs' \
+                 'latency_secs', {}",
+                line, spread
+            ),
+            None => format!(
+                "the inputs' 'latency_secs' spread over {}s, past the {}s an \
+                 [alignment] 'max_wait_secs' waits by default",
+                spread, DEFAULT_MAX_WAIT_SECS
+            ),
+        });
+    }
+    // Kept apart so a [[cap]] may come before [defuzzification]
+    defuzzification.caps = caps;
+
+    let mut system = FuzzySystem {
+        metadata,
+        inputs,
+        outputs,
+        rules,
+        groups,
+        defuzzification,
+        pipeline,
+        cascade,
+        exec,
+        recompute,
+        alignment,
+        overrides,
+        budget,
+        adaptation,
+        tuning,
+        oscillation,
+        alarms: Vec::new(),
+        objective: None,
+        generator,
+        envelopes,
+        noise,
+        forecast,
+        interlocks,
+        timezone,
+        palette,
+        pause_on_blur,
+        comfort: None,
+    };
+    if let Some((comfort, line)) = comfort {
+        for name in ["temperature", "humidity"] {
+            if system.input(name).is_none() {
+                return Err(format!(
+                    "line {}: [comfort] needs the inputs 'temperature' and 'humidity'",
+                    line
+                ));
+            }
+        }
+        system.comfort = Some(comfort);
+    }
+    for (name, when, line) in alarms {
+        let when = Expression::parse(&when, &system)
+            .map_err(|e| format!("line {}: 'when' {}", line, e))?;
+        system.alarms.push(AlarmConfig { name, when });
+    }
+    if let Some((score, line, better)) = objective {
+        let score = Expression::parse(&score, &system)
+            .map_err(|e| format!("line {}: 'score' {}", line, e))?;
+        system.objective = Some(ObjectiveConfig { score, better });
+    }
+    system.validate()?;
+    Ok(system)
+}
+
+/// Quote a string for the TOML subset understood by `parse_document`
+pub(crate) fn quote(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
+
+/// Split one line of a CSV file (`batch`, `suggest-sets`), honoring
+/// double-quoted fields
+pub(crate) fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field).trim().to_string()),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+fn numbers(values: &[f64]) -> String {
+    let items: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn params_with_links(params: &[Param]) -> String {
+    let items: Vec<String> = params
+        .iter()
+        .map(|p| match p {
+            Param::Value(v) => format!("{:?}", v),
+            Param::Link(link) => quote(&link.to_text()),
+        })
+        .collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// `[[input]]`/`[output]` section (`[[output]]` when `array`) for one
+/// variable, followed by its sets; `keep_links` writes parameter links
+/// instead of the numbers they resolved to
+pub(crate) fn variable_to_toml(
+    kind: &str,
+    array: bool,
+    var: &FuzzyVariable,
+    keep_links: bool,
+) -> String {
+    let header = if array {
+        format!("[[{}]]", kind)
+    } else {
+        format!("[{}]", kind)
+    };
+    let mut out = format!(
+        "{}\nname = {}\nmin = {:?}\nmax = {:?}\n",
+        header,
+        quote(&var.name),
+        var.min,
+        var.max
+    );
+    if let InputKind::DewPoint { temperature } = &var.kind {
+        out.push_str(&format!(
+            "kind = \"dewpoint\"\ntemperature = {}\n",
+            quote(temperature)
+        ));
+    }
+    if var.min_delta != 0.0 {
+        out.push_str(&format!("min_delta = {:?}\n", var.min_delta));
+    }
+    if let Some(window) = &var.window {
+        out.push_str(&format!(
+            "window_secs = {:?}\naggregate = {}\n",
+            window.secs,
+            quote(window.aggregate.name())
+        ));
+        if window.stale_windows > 0 {
+            out.push_str(&format!("stale_windows = {}\n", window.stale_windows));
+        }
+    }
+    if let Some(fusion) = &var.fusion {
+        out.push_str(&format!("fusion = {}\n", quote(fusion.strategy.name())));
+        if let Some(max_spread) = fusion.max_spread {
+            out.push_str(&format!("max_spread = {:?}\n", max_spread));
+        }
+    }
+    if var.latency_secs != 0.0 {
+        out.push_str(&format!("latency_secs = {:?}\n", var.latency_secs));
+    }
+    if var.out_of_range != OutOfRange::default() {
+        out.push_str(&format!(
+            "out_of_range = {}\n",
+            quote(var.out_of_range.name())
+        ));
+    }
+    if !var.unit.name.is_empty() {
+        out.push_str(&format!("unit = {}\n", quote(&var.unit.name)));
+    }
+    if let Some(precision) = var.unit.precision {
+        out.push_str(&format!("precision = {}\n", precision));
+    }
+    if let Some(conversion) = &var.unit.display {
+        out.push_str(&format!(
+            "display_unit = {}\ndisplay_scale = {:?}\ndisplay_offset = {:?}\n",
+            quote(&conversion.unit),
+            conversion.scale,
+            conversion.offset
+        ));
+    }
+    if let Some(method) = var.defuzz.method {
+        out.push_str(&format!("method = {}\n", quote(method.name())));
+    }
+    if let Some(resolution) = var.defuzz.resolution {
+        out.push_str(&format!("resolution = {}\n", resolution));
+    }
+    if let Some(implication) = var.defuzz.implication {
+        out.push_str(&format!("implication = {}\n", quote(implication.name())));
+    }
+    for set in &var.sets {
+        out.push_str(&format!(
+            "\n[[{}.set]]\nname = {}\nshape = {}\nparams = {}\n",
+            kind,
+            quote(&set.name),
+            quote(set.function.kind()),
+            if keep_links {
+                params_with_links(&links::specs(set))
+            } else {
+                numbers(&set.function.params())
+            }
+        ));
+        if !set.uncertainty.is_empty() {
+            out.push_str(&format!("uncertainty = {}\n", numbers(&set.uncertainty)));
+        }
+    }
+    for source in var.fusion.iter().flat_map(|f| &f.sources) {
+        out.push_str(&format!(
+            "\n[[{}.source]]\nname = {}\ntrust = {:?}\n",
+            kind,
+            quote(&source.name),
+            source.trust
+        ));
+        for (key, value) in [
+            ("min", source.min),
+            ("max", source.max),
+            ("max_rate", source.max_rate),
+            ("timeout_secs", source.timeout_secs),
+        ] {
+            if let Some(value) = value {
+                out.push_str(&format!("{} = {:?}\n", key, value));
+            }
+        }
+    }
+    out
+}
+
+/// Serialize a system definition; `parse_system` reads it back unchanged.
+/// Only the model is written, so keys the parser ignored do not survive.
+/// Linked set parameters are written as the numbers they resolved to,
+/// or as their links with `keep_links`.
+pub fn to_toml(system: &FuzzySystem, keep_links: bool) -> String {
+    let rule_list = |group: Option<&str>| {
+        let mut list = String::from("rules = [\n");
+        for rule in system.rules.iter().filter(|r| r.group.as_deref() == group) {
+            list.push_str(&format!("    {},\n", quote(&rule.to_text())));
+        }
+        list + "]\n"
+    };
+    let mut out = format!("schema = {}\n", schema::CURRENT) + &rule_list(None);
+    let metadata = system.metadata.fields();
+    if !metadata.is_empty() {
+        out.push_str("\n[metadata]\n");
+        for (key, value) in metadata {
+            out.push_str(&format!("{} = {}\n", key, quote(value)));
+        }
+    }
+    for group in &system.groups {
+        out.push_str(&format!(
+            "\n[[group]]\nname = {}\nenabled = {}\nweight = {:?}\n{}",
+            quote(&group.name),
+            group.enabled,
+            group.weight,
+            rule_list(Some(&group.name))
+        ));
+    }
+
+    let variables = system
+        .inputs
+        .iter()
+        .map(|var| ("input", var))
+        .chain(system.outputs.iter().map(|var| ("output", var)));
+    for (kind, var) in variables {
+        let array = kind == "input" || system.outputs.len() > 1;
+        out.push('\n');
+        out.push_str(&variable_to_toml(kind, array, var, keep_links));
+    }
+
+    let defuzzification = &system.defuzzification;
+    let settings = Defuzzification {
+        caps: Vec::new(),
+        ..defuzzification.clone()
+    };
+    if settings != Defuzzification::default() {
+        out.push_str(&format!(
+            "\n[defuzzification]\nmethod = {}\nresolution = {}\nimplication = {}\nsupport_threshold = {:?}\naggregation = {}\nsoftmax_temperature = {:?}\n",
+            quote(defuzzification.method.name()),
+            defuzzification.resolution,
+            quote(defuzzification.implication.name()),
+            defuzzification.support_threshold,
+            quote(defuzzification.aggregation.name()),
+            defuzzification.softmax_temperature
+        ));
+        if defuzzification.tie_tolerance != ties::TOLERANCE {
+            out.push_str(&format!(
+                "tie_tolerance = {:?}\n",
+                defuzzification.tie_tolerance
+            ));
+        }
+        if let Some(profile) = &defuzzification.cap_profile {
+            out.push_str(&format!("cap_profile = {}\n", quote(profile)));
+        }
+    }
+    for cap in &defuzzification.caps {
+        out.push_str(&format!(
+            "\n[[cap]]\nprofile = {}\noutput = {}\nset = {}\nmax = {:?}\n",
+            quote(&cap.profile),
+            quote(&cap.output),
+            quote(&cap.set),
+            cap.max
+        ));
+    }
+
+    if system.recompute != RecomputeConfig::default() {
+        out.push_str(&format!(
+            "\n[recompute]\nmax_interval_secs = {:?}\n",
+            system.recompute.max_interval_secs
+        ));
+    }
+
+    if system.alignment != AlignConfig::default() {
+        out.push_str(&format!(
+            "\n[alignment]\nmax_wait_secs = {:?}\n",
+            system.alignment.max_wait_secs
+        ));
+    }
+
+    if system.overrides != OverrideConfig::default() {
+        out.push_str(&format!(
+            "\n[override]\nduration_secs = {:?}\n",
+            system.overrides.duration_secs
+        ));
+    }
+
+    if system.forecast != ForecastConfig::default() {
+        let forecast = &system.forecast;
+        out.push_str(&format!(
+            "\n[forecast]\nextrapolation = {}\nhorizon_secs = {:?}\nsteps = {}\nwindow_secs = {:?}\n",
+            quote(forecast.extrapolation.name()),
+            forecast.horizon_secs,
+            forecast.steps,
+            forecast.window_secs
+        ));
+    }
+
+    if let Some(budget) = &system.budget {
+        out.push_str(&format!(
+            "\n[budget]\ncycle_ms = {:?}\nrecover_cycles = {}\n",
+            budget.cycle_ms, budget.recover_cycles
+        ));
+    }
+
+    if let Some(adaptation) = &system.adaptation {
+        out.push_str(&format!(
+            "\n[adaptation]\nenabled = {}\nrate = {:?}\nmax_step = {:?}\n",
+            adaptation.enabled, adaptation.rate, adaptation.max_step
+        ));
+    }
+
+    if system.tuning != TuningConfig::default() {
+        out.push_str(&format!(
+            "\n[tuning]\nmax_similarity = {:?}\n",
+            system.tuning.max_similarity
+        ));
+        if let Some(max_hole) = system.tuning.max_hole {
+            out.push_str(&format!("max_hole = {:?}\n", max_hole));
+        }
+    }
+
+    if let Some(oscillation) = &system.oscillation {
+        out.push_str(&format!(
+            "\n[oscillation]\nwindow_secs = {:?}\nmin_reversals = {}\nmin_amplitude = {:?}\n",
+            oscillation.window_secs, oscillation.min_reversals, oscillation.min_amplitude
+        ));
+    }
+
+    for alarm in &system.alarms {
+        out.push_str(&format!(
+            "\n[[alarm]]\nname = {}\nwhen = {}\n",
+            quote(&alarm.name),
+            quote(alarm.when.source())
+        ));
+    }
+
+    if let Some(objective) = &system.objective {
+        out.push_str(&format!(
+            "\n[objective]\nscore = {}\n",
+            quote(objective.score.source())
+        ));
+        if objective.better != Better::default() {
+            out.push_str(&format!("better = \"{}\"\n", objective.better.name()));
+        }
+    }
+
+    if system.timezone != Zone::Local
+        || system.palette != PaletteName::Classic
+        || system.pause_on_blur
+    {
+        out.push_str("\n[display]\n");
+        if system.timezone != Zone::Local {
+            out.push_str(&format!("timezone = \"{}\"\n", system.timezone.name()));
+        }
+        if system.palette != PaletteName::Classic {
+            out.push_str(&format!("palette = \"{}\"\n", system.palette.name()));
+        }
+        if system.pause_on_blur {
+            out.push_str("pause_on_blur = true\n");
+        }
+    }
+
+    if let Some(comfort) = &system.comfort {
+        out.push_str("\n[comfort]\n");
+        if !comfort.show {
+            out.push_str("show = false\n");
+        }
+        let vertices: Vec<String> = (comfort.zone.vertices().iter())
+            .map(|(temperature, humidity)| format!("[{:?}, {:?}]", temperature, humidity))
+            .collect();
+        out.push_str(&format!("vertices = [{}]\n", vertices.join(", ")));
+    }
+
+    let pipeline = &system.pipeline;
+    if *pipeline != PipelineConfig::default() {
+        out.push_str("\n[output.pipeline]\n");
+        for (key, value) in [
+            ("rate_limit", pipeline.rate_limit),
+            ("hysteresis", pipeline.hysteresis),
+            ("min_on_secs", pipeline.min_on_secs),
+            ("min_off_secs", pipeline.min_off_secs),
+        ] {
+            out.push_str(&format!("{} = {:?}\n", key, value));
+        }
+        out.push_str(&format!("status = {}\n", quote(pipeline.status.name())));
+    }
+
+    if let Some(cascade) = &system.cascade {
+        out.push_str(&format!(
+            "\n[output.cascade]\nmeasured = {}\n",
+            quote(&cascade.measured)
+        ));
+        for (key, value) in [
+            ("kp", cascade.kp),
+            ("ki", cascade.ki),
+            ("kd", cascade.kd),
+            ("min", cascade.min),
+            ("max", cascade.max),
+            ("plant_gain", cascade.plant_gain),
+            ("plant_tau_secs", cascade.plant_tau_secs),
+        ] {
+            out.push_str(&format!("{} = {:?}\n", key, value));
+        }
+    }
+
+    if let Some(exec) = &system.exec {
+        out.push_str(&format!(
+            "\n[output.exec]\ncommand = {}\nstdin = {}\non = {}\ntimeout_secs = {:?}\nretries = {}\n\
+             stall_secs = {:?}\n",
+            quote(exec.command.source()),
+            quote(exec.stdin.name()),
+            quote(exec.on.name()),
+            exec.timeout_secs,
+            exec.retries,
+            exec.stall_secs
+        ));
+    }
+
+    for envelope in &system.envelopes {
+        out.push_str(&format!(
+            "\n[[envelope]]\ninput = {}\nmargin = {:?}\n",
+            quote(&envelope.input),
+            envelope.margin
+        ));
+        for (key, target) in [("low", envelope.low), ("high", envelope.high)] {
+            if let Some(target) = target {
+                out.push_str(&format!("{} = {:?}\n", key, target));
+            }
+        }
+    }
+
+    for noise in &system.noise {
+        out.push_str(&format!(
+            "\n[[noise]]\ninput = {}\nsigma = {:?}\ncorrelation = {:?}\n",
+            quote(&noise.input),
+            noise.sigma,
+            noise.correlation
+        ));
+        if noise.drift != 0.0 {
+            out.push_str(&format!("drift = {:?}\n", noise.drift));
+        }
+        if noise.seed != 0 {
+            out.push_str(&format!("seed = {}\n", noise.seed));
+        }
+    }
+
+    for interlock in &system.interlocks {
+        out.push_str(&format!(
+            "\n[[interlock]]\nname = {}\nvalue = {:?}\npriority = {}\n",
+            quote(&interlock.name),
+            interlock.value,
+            interlock.priority
+        ));
+        if interlock.engaged {
+            out.push_str("engaged = true\n");
+        }
+    }
+
+    let generator = &system.generator;
+    if *generator != GeneratorConfig::default() {
+        let conditions: Vec<String> = generator
+            .conditions
+            .iter()
+            .map(|c| quote(&c.to_text()))
+            .collect();
+        out.push_str(&format!(
+            "\n[generator]\nkind = {}\nspread = {:?}\nwhere = [{}]\n",
+            quote(generator.kind.name()),
+            generator.spread,
+            conditions.join(", ")
+        ));
+        if !generator.ranges.is_empty() {
+            out.push_str("\n[generator.ranges]\n");
+            for (name, min, max) in &generator.ranges {
+                out.push_str(&format!("{} = [{:?}, {:?}]\n", name, min, max));
+            }
+        }
+    }
+    out
+}
+
+pub fn load_system(path: &str) -> Result<FuzzySystem, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let upgrade = schema::upgrade(&text).map_err(|e| format!("{}: {}", path, e))?;
+    if !upgrade.steps.is_empty() {
+        eprintln!(
+            "{}: schema {} read as schema {} ({}); `fuzzy_logic migrate {}` rewrites the file",
+            path,
+            upgrade.from,
+            schema::CURRENT,
+            upgrade.steps.join("; "),
+            path
+        );
+    }
+    parse_system(&text).map_err(|e| format!("{}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One input with a single set, its universe and set given as TOML
+    fn system(universe: &str, params: &str) -> Result<FuzzySystem, String> {
+        parse_system(&format!(
+            "rules = [\"IF x IS A THEN y IS B\"]\n\
+             [[input]]\nname = \"x\"\n{}\n\
+             [[input.set]]\nname = \"A\"\nshape = \"trapezoidal\"\nparams = {}\n\
+             [output]\nname = \"y\"\nmin = 0\nmax = 1\n\
+             [[output.set]]\nname = \"B\"\nshape = \"triangular\"\nparams = [0, 0.5, 1]\n",
+            universe, params
+        ))
+    }
+
+    #[test]
+    fn a_sound_system_loads() {
+        assert!(system("min = 0\nmax = 50", "[0, 0, 15, 20]").is_ok());
+    }
+
+    #[test]
+    fn universes_must_be_finite() {
+        assert_eq!(
+            system("min = 0\nmax = inf", "[0, 0, 15, 20]").unwrap_err(),
+            "line 5: 'max' must be a finite number, not inf"
+        );
+        assert_eq!(
+            system("min = nan\nmax = 50", "[0, 0, 15, 20]").unwrap_err(),
+            "line 4: 'min' must be a finite number, not NaN"
+        );
+        assert_eq!(
+            system("min = -inf\nmax = 50", "[0, 0, 15, 20]").unwrap_err(),
+            "line 4: 'min' must be a finite number, not -inf"
+        );
+    }
+
+    #[test]
+    fn universes_must_not_be_empty() {
+        assert_eq!(
+            system("min = 50\nmax = 50", "[0, 0, 15, 20]").unwrap_err(),
+            "line 5: 'min' must be below 'max', got 50 and 50"
+        );
+    }
+
+    #[test]
+    fn set_parameters_must_be_numbers_in_order() {
+        assert_eq!(
+            system("min = 0\nmax = 50", "[20, 15, 0, 0]").unwrap_err(),
+            "line 9: trapezoidal expects a <= b <= c <= d, got 20, 15, 0, 0"
+        );
+        assert_eq!(
+            system("min = 0\nmax = 50", "[0, nan, 15, 20]").unwrap_err(),
+            "line 9: trapezoidal parameters must be numbers, got NaN"
+        );
+        assert_eq!(
+            system("min = 0\nmax = 50", "[0, 0, 15, inf]").unwrap_err(),
+            "line 9: trapezoidal parameters must be numbers, got inf"
+        );
+    }
+
+    #[test]
+    fn the_status_mode_is_read_from_the_pipeline_section() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |status: &str| {
+            parse_system(&format!(
+                "{}\n[output.pipeline]\nstatus = {}\n",
+                fan, status
+            ))
+        };
+        assert_eq!(
+            parse_system(&fan).unwrap().pipeline.status,
+            StatusMode::Thresholds
+        );
+        assert_eq!(
+            with("\"dominant_set\"").unwrap().pipeline.status,
+            StatusMode::DominantSet
+        );
+        assert!(
+            with("\"loudest\"")
+                .unwrap_err()
+                .contains("'status' must be")
+        );
+    }
+
+    #[test]
+    fn metadata_round_trips_and_is_checked() {
+        let fan = parse_system(&std::fs::read_to_string("configs/fan.toml").unwrap()).unwrap();
+        let mut system = fan.clone();
+        system.metadata.author = Some("Facilities \"B\" wing".to_string());
+        system.metadata.modified = Some("2025-06-14T09:30:00Z".to_string());
+        let text = to_toml(&system, false);
+        assert!(text.contains("[metadata]\nname = \"fan controller\"\n"));
+        assert_eq!(parse_system(&text).unwrap(), system);
+        assert_eq!(
+            system.metadata.summary().as_deref(),
+            Some("fan controller v1.0.0 by Facilities \"B\" wing")
+        );
+
+        let toml = to_toml(&fan, false);
+        let with = |from: &str, to: &str| parse_system(&toml.replace(from, to));
+        assert!(
+            with("version = \"1.0.0\"", "version = \"1.0\"")
+                .unwrap_err()
+                .contains("'version' must be MAJOR.MINOR.PATCH")
+        );
+        assert!(
+            with("created = \"2025-01-01\"", "created = \"01/02/2025\"")
+                .unwrap_err()
+                .contains("'created' must start with a YYYY-MM-DD date")
+        );
+        assert!(with("version = \"1.0.0\"", "version = \"2.0.0-rc.1+42\"").is_ok());
+    }
+
+    #[test]
+    fn the_cascade_section_round_trips_and_is_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let fan = fan.replace(
+            "[[input]]\nname = \"humidity\"",
+            "[[input]]\nname = \"fan_rpm\"\nmin = 0.0\nmax = 3000.0\n\n\
+             [[input.set]]\nname = \"Any\"\nshape = \"triangular\"\n\
+             params = [0.0, 1500.0, 3000.0]\n\n[[input]]\nname = \"humidity\"",
+        );
+        let with = |cascade: &str| parse_system(&format!("{}\n[output.cascade]\n{}", fan, cascade));
+        let system = with("measured = \"fan_rpm\"\nki = 0.05\nmax = 80").unwrap();
+        let cascade = system.cascade.clone().unwrap();
+        assert_eq!((cascade.ki, cascade.min, cascade.max), (0.05, 0.0, 80.0));
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        assert!(
+            with("measured = \"rpm\"")
+                .unwrap_err()
+                .contains("unknown measured input 'rpm'")
+        );
+        assert!(
+            with("measured = \"fan_rpm\"\nkp = -1")
+                .unwrap_err()
+                .contains("'kp' must not be negative")
+        );
+        assert!(with("kp = 1").unwrap_err().contains("missing 'measured'"));
+    }
+
+    #[test]
+    fn the_generator_sections_round_trip_and_are_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |generator: &str| parse_system(&format!("{}\n{}", fan, generator));
+        let system = with(
+            "[generator.ranges]\ntemperature = [20.0, 35.0]\n\n\
+             [generator]\nkind = \"constrained\"\nwhere = [\"humidity > temperature\"]",
+        )
+        .unwrap();
+        assert_eq!(system.generator.kind, GeneratorKind::Constrained);
+        assert_eq!(
+            system.generator.ranges,
+            [("temperature".to_string(), 20.0, 35.0)]
+        );
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        assert!(
+            with("[generator.ranges]\ntemperature = [20.0, 60.0]")
+                .unwrap_err()
+                .contains("must be increasing and within 0 to 50")
+        );
+        assert!(
+            with("[generator]\nwhere = [\"dew > 3\"]")
+                .unwrap_err()
+                .contains("names unknown input 'dew'")
+        );
+        assert!(
+            with("[generator]\nkind = \"gaussian\"")
+                .unwrap_err()
+                .contains("'kind' must be")
+        );
+    }
+
+    #[test]
+    fn envelopes_round_trip_and_are_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |envelope: &str| parse_system(&format!("{}\n[[envelope]]\n{}", fan, envelope));
+        let system = with("input = \"temperature\"\nmargin = 2.0\nhigh = 100.0").unwrap();
+        assert_eq!(system.envelopes[0].high, Some(100.0));
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        assert!(
+            with("input = \"temperature\"\nmargin = 2.0")
+                .unwrap_err()
+                .contains("needs a 'low' or 'high' target")
+        );
+        assert!(
+            with("input = \"temperature\"\nmargin = 30.0\nlow = 0.0")
+                .unwrap_err()
+                .contains("at most half the universe")
+        );
+        assert!(
+            with("input = \"temperature\"\nmargin = 2.0\nhigh = 150.0")
+                .unwrap_err()
+                .contains("target 150 is outside fan_speed 0 to 100")
+        );
+    }
+
+    #[test]
+    fn the_out_of_range_policy_round_trips_and_is_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |policy: &str| {
+            parse_system(&fan.replacen(
+                "name = \"temperature\"\n",
+                &format!("name = \"temperature\"\nout_of_range = {}\n", policy),
+                1,
+            ))
+        };
+        let system = with("\"extend\"").unwrap();
+        assert_eq!(system.inputs[0].out_of_range, OutOfRange::Extend);
+        assert_eq!(system.inputs[1].out_of_range, OutOfRange::Clamp);
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        assert!(!to_toml(&parse_system(&fan).unwrap(), false).contains("out_of_range"));
+
+        assert!(
+            with("\"wrap\"")
+                .unwrap_err()
+                .contains("'out_of_range' must be \"clamp\", \"extend\" or \"error\"")
+        );
+    }
+
+    #[test]
+    fn units_and_display_conversions_round_trip_and_are_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |keys: &str| {
+            parse_system(&fan.replacen("unit = \"°C\"\n", &format!("unit = \"°C\"\n{}", keys), 1))
+        };
+        let system = with("precision = 2\ndisplay_unit = \"°F\"\n").unwrap();
+        let unit = &system.inputs[0].unit;
+        assert_eq!(unit.precision, Some(2));
+        assert_eq!(unit.format(100.0), "212.00°F");
+        assert_eq!(system.output().unit.name, "%");
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        let system =
+            with("display_unit = \"kelvin\"\ndisplay_scale = 1.0\ndisplay_offset = 273.15\n")
+                .unwrap();
+        assert_eq!(system.inputs[0].unit.format(0.0), "273.1 kelvin");
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        assert_eq!(
+            with("display_unit = \"kelvin\"\n").unwrap_err(),
+            "line 26: no known conversion from '°C' to 'kelvin', give 'display_scale'"
+        );
+        assert!(
+            with("display_offset = 1.0\n")
+                .unwrap_err()
+                .contains("'display_offset' needs a 'display_unit'")
+        );
+        assert!(
+            with("display_unit = \"x\"\ndisplay_scale = 0.0\n")
+                .unwrap_err()
+                .contains("line 27: 'display_scale' must be a nonzero number")
+        );
+        assert!(
+            with("precision = 1.5\n")
+                .unwrap_err()
+                .contains("'precision' must be a whole number")
+        );
+    }
+
+    #[test]
+    fn the_aggregation_round_trips_and_is_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with =
+            |settings: &str| parse_system(&format!("{}\n[defuzzification]\n{}", fan, settings));
+        let system = with("aggregation = \"softmax\"\nsoftmax_temperature = 0.25").unwrap();
+        assert_eq!(system.defuzzification.aggregation, Aggregation::SoftMax);
+        assert_eq!(system.defuzzification.softmax_temperature, 0.25);
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        assert!(
+            with("aggregation = \"sum\"")
+                .unwrap_err()
+                .contains("'aggregation' must be")
+        );
+        assert!(
+            with("softmax_temperature = 0")
+                .unwrap_err()
+                .contains("'softmax_temperature' must be above 0")
+        );
+    }
+
+    #[test]
+    fn the_tie_tolerance_round_trips_and_is_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with =
+            |settings: &str| parse_system(&format!("{}\n[defuzzification]\n{}", fan, settings));
+        let system = with("tie_tolerance = 0.001").unwrap();
+        assert_eq!(system.defuzzification.tie_tolerance, 0.001);
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        assert_eq!(
+            with("").unwrap().defuzzification.tie_tolerance,
+            ties::TOLERANCE
+        );
+        assert!(
+            with("tie_tolerance = -1e-9")
+                .unwrap_err()
+                .contains("'tie_tolerance' must be between 0 and 1")
+        );
+    }
+
+    #[test]
+    fn cap_profiles_round_trip_and_are_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let cap = |profile: &str, set: &str, max: &str| {
+            format!(
+                "[[cap]]\nprofile = \"{}\"\noutput = \"fan_speed\"\nset = \"{}\"\nmax = {}\n",
+                profile, set, max
+            )
+        };
+        let with = |active: &str, caps: &str| {
+            parse_system(&format!(
+                "{}\n[defuzzification]\ncap_profile = \"{}\"\n{}",
+                fan, active, caps
+            ))
+        };
+        let caps = cap("quiet", "High", "0.4") + &cap("night", "Medium", "0.2");
+        let system = with("night", &caps).unwrap();
+        assert_eq!(system.defuzzification.cap("fan_speed", "Medium"), Some(0.2));
+        assert_eq!(system.defuzzification.cap("fan_speed", "High"), None);
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        assert!(
+            with("quiet", &cap("quiet", "High", "1.2"))
+                .unwrap_err()
+                .contains("'max' must be between 0 and 1")
+        );
+        assert!(
+            with("quiet", &cap("quiet", "Loud", "0.5"))
+                .unwrap_err()
+                .contains("Loud")
+        );
+        assert!(with("loud", &caps).unwrap_err().contains("loud"));
+        assert!(
+            with(
+                "quiet",
+                &(cap("quiet", "High", "0.4") + &cap("quiet", "High", "0.3"))
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn tuning_section_round_trips_and_is_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |tuning: &str| parse_system(&format!("{}\n[tuning]\n{}", fan, tuning));
+        let system = with("max_similarity = 0.8\nmax_hole = 1.5").unwrap();
+        assert_eq!(
+            system.tuning,
+            TuningConfig {
+                max_similarity: 0.8,
+                max_hole: Some(1.5)
+            }
+        );
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        assert_eq!(with("").unwrap().tuning, TuningConfig::default());
+
+        assert!(
+            with("max_similarity = 0")
+                .unwrap_err()
+                .contains("'max_similarity' must be above 0 and at most 1")
+        );
+        assert!(
+            with("max_hole = -1")
+                .unwrap_err()
+                .contains("'max_hole' must not be negative")
+        );
+    }
+
+    #[test]
+    fn the_forecast_section_round_trips_and_is_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |forecast: &str| parse_system(&format!("{}\n[forecast]\n{}", fan, forecast));
+        let system = with("extrapolation = \"hold\"\nhorizon_secs = 60\nsteps = 4").unwrap();
+        assert_eq!(
+            system.forecast,
+            ForecastConfig {
+                extrapolation: Extrapolation::Hold,
+                horizon_secs: 60.0,
+                steps: 4,
+                window_secs: crate::forecast::DEFAULT_WINDOW_SECS,
+            }
+        );
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        assert_eq!(with("").unwrap().forecast, ForecastConfig::default());
+
+        assert!(
+            with("extrapolation = \"cubic\"")
+                .unwrap_err()
+                .contains("'extrapolation' must be \"linear\" or \"hold\"")
+        );
+        assert!(
+            with("window_secs = 0")
+                .unwrap_err()
+                .contains("'window_secs' must be above 0")
+        );
+        assert!(
+            with("steps = 2.5")
+                .unwrap_err()
+                .contains("'steps' must be a whole number from 1 to 1000")
+        );
+    }
+
+    #[test]
+    fn input_windows_round_trip_and_are_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |keys: &str| {
+            parse_system(&fan.replacen(
+                "name = \"humidity\"\n",
+                &format!("name = \"humidity\"\n{}\n", keys),
+                1,
+            ))
+        };
+        let system =
+            with("window_secs = 0.5\naggregate = \"trimmed_mean\"\nstale_windows = 4").unwrap();
+        assert_eq!(
+            system.input("humidity").unwrap().window,
+            Some(WindowConfig {
+                secs: 0.5,
+                aggregate: Aggregate::TrimmedMean,
+                stale_windows: 4,
+            })
+        );
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        let system = with("window_secs = 2").unwrap();
+        assert_eq!(
+            system
+                .input("humidity")
+                .unwrap()
+                .window
+                .as_ref()
+                .unwrap()
+                .aggregate,
+            Aggregate::Mean
+        );
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        for (keys, error) in [
+            ("window_secs = 0", "'window_secs' must be positive"),
+            (
+                "window_secs = 1\naggregate = \"mode\"",
+                "'aggregate' must be",
+            ),
+            (
+                "window_secs = 1\nstale_windows = 1.5",
+                "'stale_windows' must be a whole",
+            ),
+            ("aggregate = \"max\"", "'aggregate' needs a 'window_secs'"),
+        ] {
+            let e = with(keys).unwrap_err();
+            assert!(e.contains(error), "{}: {}", keys, e);
+        }
+    }
+
+    #[test]
+    fn input_latencies_round_trip_and_need_a_long_enough_wait() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |keys: &str, sections: &str| {
+            parse_system(&format!(
+                "{}\n{}",
+                fan.replacen(
+                    "name = \"humidity\"\n",
+                    &format!("name = \"humidity\"\n{}\n", keys),
+                    1,
+                ),
+                sections
+            ))
+        };
+        let system = with("latency_secs = 4", "").unwrap();
+        assert_eq!(system.input("humidity").unwrap().latency_secs, 4.0);
+        assert_eq!(system.alignment, AlignConfig::default());
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        let system = with("latency_secs = 8", "[alignment]\nmax_wait_secs = 10").unwrap();
+        assert_eq!(system.alignment.max_wait_secs, 10.0);
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        for (keys, sections, error) in [
+            (
+                "latency_secs = -1",
+                "",
+                "'latency_secs' must not be negative",
+            ),
+            (
+                "latency_secs = 1\nwindow_secs = 1",
+                "",
+                "'latency_secs' cannot go with 'window_secs'",
+            ),
+            (
+                "latency_secs = 6",
+                "",
+                "spread over 6s, past the 5s an [alignment] 'max_wait_secs' waits by default",
+            ),
+            (
+                "latency_secs = 6",
+                "[alignment]\nmax_wait_secs = 3",
+                "'max_wait_secs' must be at least the spread of the inputs' 'latency_secs', 6",
+            ),
+            (
+                "",
+                "[alignment]\nmax_wait_secs = -1",
+                "must not be negative",
+            ),
+        ] {
+            let e = with(keys, sections).unwrap_err();
+            assert!(e.contains(error), "{}: {}", keys, e);
+        }
+    }
+
+    #[test]
+    fn fused_inputs_round_trip_and_are_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        // Keys of the humidity input, and sources after its sets
+        let with = |keys: &str, sources: &str| {
+            parse_system(
+                &fan.replacen(
+                    "name = \"humidity\"\n",
+                    &format!("name = \"humidity\"\n{}\n", keys),
+                    1,
+                )
+                .replacen("[output]", &format!("{}\n[output]", sources), 1),
+            )
+        };
+        let two = "[[input.source]]\nname = \"north\"\ntrust = 2.0\nmin = 0.0\nmax = 100.0\n\
+                   max_rate = 5.0\ntimeout_secs = 30.0\n\n[[input.source]]\nname = \"south\"\n";
+        let system = with("fusion = \"weighted\"\nmax_spread = 4.0", two).unwrap();
+        let fusion = system.input("humidity").unwrap().fusion.clone().unwrap();
+        assert_eq!(fusion.strategy, Strategy::Weighted);
+        assert_eq!(fusion.max_spread, Some(4.0));
+        assert_eq!(
+            fusion.sources,
+            vec![
+                SourceConfig {
+                    trust: 2.0,
+                    min: Some(0.0),
+                    max: Some(100.0),
+                    max_rate: Some(5.0),
+                    timeout_secs: Some(30.0),
+                    ..SourceConfig::new("north")
+                },
+                SourceConfig::new("south"),
+            ]
+        );
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        // Sources alone fuse by the mean
+        let system = with("", two).unwrap();
+        let fusion = system.input("humidity").unwrap().fusion.as_ref().unwrap();
+        assert_eq!((fusion.strategy, fusion.max_spread), (Strategy::Mean, None));
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        let source = |keys: &str| format!("[[input.source]]\nname = \"north\"\n{}\n", keys);
+        for (keys, sources, error) in [
+            ("fusion = \"mode\"", two.to_string(), "'fusion' must be"),
+            (
+                "max_spread = 0",
+                two.to_string(),
+                "'max_spread' must be positive",
+            ),
+            (
+                "fusion = \"min\"",
+                String::new(),
+                "need an [[input.source]]",
+            ),
+            ("", source("trust = 0"), "'trust' must be positive"),
+            ("", source("max_rate = -1"), "'max_rate' must be positive"),
+            (
+                "",
+                source("timeout_secs = 0"),
+                "'timeout_secs' must be positive",
+            ),
+            ("", source("min = 5\nmax = 5"), "'min' must be below 'max'"),
+            ("", source("") + &source(""), "duplicate source 'north'"),
+            (
+                "",
+                "[[input.source]]\nname = \"a.b\"\n".to_string(),
+                "source name 'a.b' may only",
+            ),
+        ] {
+            let e = with(keys, &sources).unwrap_err();
+            assert!(e.contains(error), "{} {}: {}", keys, sources, e);
+        }
+    }
+
+    #[test]
+    fn the_override_section_round_trips_and_is_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |section: &str| parse_system(&format!("{}\n[override]\n{}", fan, section));
+        for secs in [0.0, 90.0] {
+            let system = with(&format!("duration_secs = {:?}", secs)).unwrap();
+            assert_eq!(system.overrides.duration_secs, secs);
+            assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        }
+        assert_eq!(with("").unwrap().overrides, OverrideConfig::default());
+        assert!(
+            with("duration_secs = -1")
+                .unwrap_err()
+                .contains("'duration_secs' must not be negative")
+        );
+    }
+
+    #[test]
+    fn the_budget_section_round_trips_and_is_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |section: &str| parse_system(&format!("{}\n[budget]\n{}", fan, section));
+        let system = with("cycle_ms = 0.5").unwrap();
+        assert_eq!(
+            system.budget,
+            Some(BudgetConfig {
+                cycle_ms: 0.5,
+                recover_cycles: 20,
+            })
+        );
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        assert_eq!(parse_system(&fan).unwrap().budget, None);
+        assert!(
+            with("cycle_ms = 0")
+                .unwrap_err()
+                .contains("'cycle_ms' must be above 0")
+        );
+        assert!(
+            with("recover_cycles = 0")
+                .unwrap_err()
+                .contains("'recover_cycles' must be a whole number of at least 1")
+        );
+    }
+
+    #[test]
+    fn the_adaptation_section_round_trips_and_is_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |section: &str| parse_system(&format!("{}\n[adaptation]\n{}", fan, section));
+        let system = with("enabled = false\nrate = 0.25").unwrap();
+        assert_eq!(
+            system.adaptation,
+            Some(AdaptationConfig {
+                enabled: false,
+                rate: 0.25,
+                max_step: 0.05,
+            })
+        );
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        assert_eq!(parse_system(&fan).unwrap().adaptation, None);
+        assert!(
+            with("rate = 0")
+                .unwrap_err()
+                .contains("'rate' must be above 0")
+        );
+        assert!(
+            with("max_step = 1.5")
+                .unwrap_err()
+                .contains("'max_step' must be above 0 and at most 1")
+        );
+    }
+
+    #[test]
+    fn alarm_conditions_round_trip_and_errors_name_line_and_column() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |when: &str| {
+            parse_system(&format!(
+                "{}\n[[alarm]]\nname = \"stuck high\"\nwhen = {}\n",
+                fan,
+                quote(when)
+            ))
+        };
+        let system = with("holds_for(fan_speed > 80 and humidity > 70, 10m)").unwrap();
+        assert_eq!(system.alarms.len(), 1);
+        assert_eq!(system.alarms[0].name, "stuck high");
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        let line = fan.lines().count() + 4;
+        assert_eq!(
+            with("membership(\"temperature\", \"Hot\") > 0.5 and rising(fanspeed)").unwrap_err(),
+            format!(
+                "line {}: 'when' column 51: no input or output 'fanspeed'",
+                line
+            )
+        );
+        let twice = format!(
+            "{}\n[[alarm]]\nname = \"a\"\nwhen = \"1\"\n[[alarm]]\nname = \"a\"\nwhen = \"0\"\n",
+            fan
+        );
+        assert!(
+            parse_system(&twice)
+                .unwrap_err()
+                .contains("duplicate alarm 'a'")
+        );
+    }
+
+    #[test]
+    fn an_objective_round_trips_and_errors_name_its_line() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |body: &str| parse_system(&format!("{}\n[objective]\n{}", fan, body));
+        let system =
+            with("score = \"abs(temperature - 22) + 0.05 * fan_speed\"\nbetter = \"higher\"\n")
+                .unwrap();
+        let objective = system.objective.as_ref().unwrap();
+        assert_eq!(objective.better, Better::Higher);
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        let line = fan.lines().count() + 3;
+        assert_eq!(
+            with("score = \"abs(temp - 22)\"\n").unwrap_err(),
+            format!("line {}: 'score' column 5: no input or output 'temp'", line)
+        );
+        assert_eq!(
+            with("score = \"1\"\nbetter = \"less\"\n").unwrap_err(),
+            format!(
+                "line {}: 'better' must be \"lower\" or \"higher\"",
+                line + 1
+            )
+        );
+    }
+
+    #[test]
+    fn oscillation_section_round_trips_and_is_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |section: &str| parse_system(&format!("{}\n[oscillation]\n{}", fan, section));
+        let system = with("window_secs = 30.0\nmin_reversals = 6").unwrap();
+        assert_eq!(
+            system.oscillation,
+            Some(OscillationConfig {
+                window_secs: 30.0,
+                min_reversals: 6,
+                min_amplitude: 10.0,
+            })
+        );
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        assert_eq!(parse_system(&fan).unwrap().oscillation, None);
+
+        assert!(
+            with("window_secs = 0")
+                .unwrap_err()
+                .contains("'window_secs' must be above 0")
+        );
+        assert!(
+            with("min_reversals = 2.5")
+                .unwrap_err()
+                .contains("'min_reversals' must be a whole number of at least 1")
+        );
+    }
+
+    #[test]
+    fn the_display_timezone_round_trips_and_is_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |zone: &str| parse_system(&format!("{}\n[display]\ntimezone = {:?}", fan, zone));
+        assert_eq!(parse_system(&fan).unwrap().timezone, Zone::Local);
+        for (text, zone) in [("UTC", Zone::Utc), ("-03:30", Zone::Fixed(-12600))] {
+            let system = with(text).unwrap();
+            assert_eq!(system.timezone, zone);
+            assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        }
+        assert!(
+            with("Mars/Olympus")
+                .unwrap_err()
+                .contains("'timezone' must be \"local\", \"UTC\" or an offset")
+        );
+    }
+
+    #[test]
+    fn the_display_palette_round_trips_and_is_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |name: &str| parse_system(&format!("{}\n[display]\npalette = {:?}", fan, name));
+        assert_eq!(parse_system(&fan).unwrap().palette, PaletteName::Classic);
+        for palette in PaletteName::ALL {
+            let mut system = with(palette.name()).unwrap();
+            assert_eq!(system.palette, palette);
+            assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+            system.timezone = Zone::Utc;
+            assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+            system.pause_on_blur = true;
+            assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        }
+        let paused = parse_system(&format!("{}\n[display]\npause_on_blur = true", fan));
+        assert!(paused.unwrap().pause_on_blur);
+        assert!(
+            with("rainbow")
+                .unwrap_err()
+                .contains("'palette' must be \"classic\", \"okabe_ito\" or \"tol\"")
+        );
+    }
+
+    #[test]
+    fn the_comfort_zone_round_trips_and_is_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |section: &str| parse_system(&format!("{}\n[comfort]\n{}", fan, section));
+        assert_eq!(parse_system(&fan).unwrap().comfort, None);
+        let system = with("").unwrap();
+        assert_eq!(system.comfort, Some(ComfortConfig::default()));
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        let system = with("show = false\nvertices = [[20, 30], [26, 30], [24, 60.5]]").unwrap();
+        let comfort = system.comfort.as_ref().unwrap();
+        assert!(!comfort.show);
+        assert_eq!(
+            comfort.zone.vertices(),
+            [(20.0, 30.0), (26.0, 30.0), (24.0, 60.5)]
+        );
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        assert!(
+            with("vertices = [[20, 30], [26, 30]]")
+                .unwrap_err()
+                .contains("'vertices': a zone needs at least 3 vertices")
+        );
+        assert!(
+            with("vertices = [[0, 0], [10, 10], [10, 0], [0, 10]]")
+                .unwrap_err()
+                .contains("'vertices': edges 1 and 3 cross")
+        );
+        assert!(
+            with("vertices = [20, 30, 26]")
+                .unwrap_err()
+                .contains("'vertices' must be an array of [x, y] pairs")
+        );
+    }
+
+    #[test]
+    fn noise_sections_round_trip_and_are_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |noise: &str| parse_system(&format!("{}\n[[noise]]\n{}", fan, noise));
+        let system =
+            with("input = \"humidity\"\nsigma = 2.0\ncorrelation = 0.8\ndrift = 0.1\nseed = 3")
+                .unwrap();
+        assert_eq!(system.noise[0].seed, 3);
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        assert!(
+            with("input = \"humidity\"\nsigma = 2.0\ncorrelation = 1.0")
+                .unwrap_err()
+                .contains("correlation must be at least 0 and below 1")
+        );
+        assert!(
+            with("input = \"co2\"\nsigma = 2.0\ncorrelation = 0.5")
+                .unwrap_err()
+                .contains("unknown input 'co2'")
+        );
+        assert!(
+            with("input = \"humidity\"\nsigma = 2.0\ncorrelation = 0.5\nseed = -1")
+                .unwrap_err()
+                .contains("'seed' must be a whole number")
+        );
+    }
+
+    #[test]
+    fn oversized_or_deeply_nested_documents_are_refused() {
+        let nested = |depth: usize| format!("x = {}{}\n", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse_document(&nested(MAX_NESTING)).is_ok());
+        assert_eq!(
+            parse_document(&nested(MAX_NESTING + 1)).unwrap_err(),
+            "line 1: arrays nested more than 32 deep"
+        );
+        // Far past the limit, where the recursion used to overflow
+        assert!(parse_document(&nested(200_000)).is_err());
+
+        // A long multi-line array is read in one pass
+        let items = "1.0,\n".repeat(100_000);
+        let sections = parse_document(&format!("x = [\n{}]\n", items)).unwrap();
+        assert_eq!(sections[0].nums("x").unwrap().len(), 100_000);
+        let huge = format!("x = [\n{}]\n", "1.0,\n".repeat(MAX_DOCUMENT / 5));
+        assert_eq!(
+            parse_document(&huge).unwrap_err(),
+            format!(
+                "{} bytes is more than the 1048576 a document may have",
+                huge.len()
+            )
+        );
+    }
+
+    #[test]
+    fn exec_outputs_round_trip_and_are_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |exec: &str| parse_system(&format!("{}\n[output.exec]\n{}", fan, exec));
+        let system = with("command = \"set-fan {value}\"").unwrap();
+        let exec = system.exec.as_ref().unwrap();
+        assert_eq!(exec.command.source(), "set-fan {value}");
+        assert_eq!((exec.on, exec.stdin), (Invoke::Publish, Stdin::None));
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+        let system = with(
+            "command = \"set-fan '{band}' \\\"{output}\\\"\"\nstdin = \"json\"\non = \"change\"\n\
+             timeout_secs = 0.5\nretries = 0\nstall_secs = 4.5",
+        )
+        .unwrap();
+        assert_eq!(system.exec.as_ref().unwrap().retries, 0);
+        assert_eq!(system.exec.as_ref().unwrap().stall_secs, 4.5);
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        assert!(
+            with("command = \"set-fan {speed}\"")
+                .unwrap_err()
+                .contains("'command': unknown placeholder '{speed}'")
+        );
+        assert!(
+            with("command = \"set-fan\"\non = \"always\"")
+                .unwrap_err()
+                .contains("'on' must be \"publish\" or \"change\"")
+        );
+        assert!(
+            with("command = \"set-fan\"\ntimeout_secs = 0")
+                .unwrap_err()
+                .contains("'timeout_secs' must be above 0")
+        );
+        assert!(
+            with("command = \"set-fan\"\nstall_secs = -1")
+                .unwrap_err()
+                .contains("'stall_secs' must be above 0")
+        );
+        assert!(
+            with("command = \"set-fan\"\nretries = 1.5")
+                .unwrap_err()
+                .contains("'retries' must be a whole number")
+        );
+    }
+
+    #[test]
+    fn interlock_sections_round_trip_and_are_checked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let with = |sections: &[&str]| {
+            let sections: Vec<String> = sections
+                .iter()
+                .map(|section| format!("[[interlock]]\n{}\n", section))
+                .collect();
+            parse_system(&format!("{}\n{}", fan, sections.join("")))
+        };
+        let system = with(&[
+            "name = \"door\"\nvalue = 0.0\npriority = 10",
+            "name = \"overtemp\"\nvalue = 100.0\nengaged = true",
+        ])
+        .unwrap();
+        assert_eq!(
+            (system.interlocks[1].priority, system.interlocks[1].engaged),
+            (0, true)
+        );
+        assert_eq!(parse_system(&to_toml(&system, false)).unwrap(), system);
+
+        let error = |sections: &[&str]| with(sections).unwrap_err();
+        assert!(
+            error(&[
+                "name = \"door\"\nvalue = 0.0",
+                "name = \"door\"\nvalue = 5.0"
+            ])
+            .contains("duplicate interlock 'door'")
+        );
+        assert!(
+            error(&["name = \"door\"\nvalue = 0.0\npriority = 1.5"])
+                .contains("'priority' must be a whole number")
+        );
+        assert!(
+            error(&["name = \"front door\"\nvalue = 0.0"])
+                .contains("must be one word without commas")
+        );
+        assert!(
+            error(&["name = \"door\"\nvalue = 120.0"])
+                .contains("interlock 'door': value 120 is outside")
+        );
+    }
+
+    #[test]
+    fn linked_parameters_resolve_and_round_trip_either_way() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let linked = fan.replace(
+            "params = [25.0, 30.0, 50.0, 50.0]",
+            "params = [\"Mild.b\", 30.0, 50.0, 50.0]",
+        );
+        let system = parse_system(&linked).unwrap();
+        let hot = system.input("temperature").unwrap().set("Hot").unwrap();
+        assert_eq!(hot.function.params(), [22.5, 30.0, 50.0, 50.0]);
+
+        // Numbers only by default, the links themselves on request
+        let resolved = parse_system(&to_toml(&system, false)).unwrap();
+        assert!(
+            resolved
+                .inputs
+                .iter()
+                .all(|v| v.sets.iter().all(|s| s.links.is_empty()))
+        );
+        assert_eq!(
+            resolved
+                .input("temperature")
+                .unwrap()
+                .set("Hot")
+                .unwrap()
+                .function,
+            hot.function
+        );
+        let text = to_toml(&system, true);
+        assert!(text.contains("params = [\"Mild.b\", 30.0, 50.0, 50.0]"));
+        assert_eq!(parse_system(&text).unwrap(), system);
+
+        let with = |params: &str| {
+            parse_system(&fan.replace("params = [25.0, 30.0, 50.0, 50.0]", params)).unwrap_err()
+        };
+        assert_eq!(
+            with("params = [\"Warm.b\", 30.0, 50.0, 50.0]"),
+            "line 40: Hot.a links to unknown set 'Warm'"
+        );
+        assert_eq!(
+            with("params = [\"Mild.z\", 30.0, 50.0, 50.0]"),
+            "line 40: 'Mild.z' is not a parameter link like \"Mild.b\""
+        );
+        assert_eq!(
+            with("params = [\"Hot.b\", \"Hot.a\", 50.0, 50.0]"),
+            "line 40: parameter links form a cycle: Hot.a -> Hot.b -> Hot.a"
+        );
+        // A link must still give a shape in order
+        assert!(with("params = [\"Mild.c\", 29.0, 50.0, 50.0]").contains("a <= b <= c <= d"));
+    }
+}
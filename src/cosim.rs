@@ -0,0 +1,1038 @@
+// ============================================================================
+// CO-SIMULATION - Cossimulação via stdin/stdout
+// ============================================================================
+//
+// `cosim [--config FILE]` puts the controller in the loop of an external
+// simulator (a Modelica building model, say). It reads one JSON request
+// per line on stdin and answers each with exactly one JSON line on stdout,
+// in order:
+//
+//     {"cmd":"init","trace":true}
+//         -> {"ok":true,"inputs":["temperature","humidity"],"outputs":["fan_speed"],
+//             "units":{"temperature":"°C","humidity":"%","fan_speed":"%"}}
+//     {"cmd":"step","time":10.0,"inputs":{"temperature":31.5,"humidity":60}}
+//         -> {"time":10,"outputs":{"fan_speed":62.5},"actuator":58.1,"band":"HIGH"}
+//     {"cmd":"weights","weights":[1,0.5,1,...]}
+//         -> {"ok":true}
+//     {"cmd":"terminate"}
+//         -> {"ok":true}
+//
+// `init` comes first and may be sent again to start over; `trace` (off by
+// default) adds the memberships of every input, the rule strengths, the
+// method that defuzzified each output, any safety envelope override (envelope.rs), the output sets held down by
+// the active cap profile and the readings outside their universe, with
+// what became of them, to each step. `units` names the
+// storage unit of each variable that has one (units.rs). Inputs are raw
+// readings (see adapters.rs); one left out keeps its previous value, but
+// the first step needs them all; one outside its universe is an error for
+// an input with `out_of_range = "error"`. `outputs` are the crisp fuzzy
+// outputs and `actuator` the first one after the cascade and the output
+// pipeline.
+//
+// Time is the simulation's, never the wall clock: every time-dependent
+// stage (rate limit, dwell, the cascade PID) is stepped with the request's
+// `time`, which must not go backwards. The same requests therefore always
+// get the same responses. A bad request gets `{"error":"..."}` and leaves
+// the session as it was. `weights` replaces the weight of every rule, in
+// config order, as the daemon's command of the same name does.
+//
+// A simulator exchanging at 100 Hz can send `wire 1` as its first line
+// instead, answered with the same line, and then trade binary frames
+// (wire.rs) for the rest of the session: `init` answers `ready` with the
+// names, `set inputs` stages readings by input index for the next
+// `compute`, which steps at its time and answers the `state`, and `get
+// state` repeats the last one. Staged readings stay until replaced, and a
+// failed compute keeps them. Any other first line is a JSON request as
+// above. The binary session carries no trace.
+//
+// A request nested deeper than MAX_DEPTH arrays and objects is refused
+// like any bad request, and a line longer than MAX_LINE bytes ends the
+// session after its error, rather than be buffered without end.
+
+use crate::FuzzySystem;
+use crate::config;
+use crate::json;
+use crate::stepper::{Step, Stepper};
+use crate::wire::{self, Message};
+use std::io::{self, BufRead, Read, Write};
+
+const USAGE: &str = "Usage: fuzzy_logic cosim [--config FILE]";
+
+// ---------------------------------------------------------------------------
+// JSON, as much as the protocol needs
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: text.chars().collect(),
+            pos: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_ws();
+        if parser.pos < parser.chars.len() {
+            return Err(format!("unexpected text at column {}", parser.pos + 1));
+        }
+        Ok(value)
+    }
+
+    pub fn to_text(&self) -> String {
+        match self {
+            Json::Null => "null".to_string(),
+            Json::Bool(b) => b.to_string(),
+            Json::Num(n) => json::number(*n),
+            Json::Str(s) => json::string(s),
+            Json::Array(items) => {
+                let items: Vec<String> = items.iter().map(Json::to_text).collect();
+                format!("[{}]", items.join(","))
+            }
+            Json::Object(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(k, v)| format!("{}:{}", json::string(k), v.to_text()))
+                    .collect();
+                format!("{{{}}}", entries.join(","))
+            }
+        }
+    }
+}
+
+/// Longest request line
+pub const MAX_LINE: usize = 64 * 1024;
+/// Arrays and objects a request may nest
+pub const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Arrays and objects open around `pos`
+    depth: usize,
+}
+
+impl Parser {
+    fn skip_ws(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_ws();
+        if self.chars.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at column {}", c, self.pos + 1))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_ws();
+        match self.chars.get(self.pos) {
+            Some('{' | '[') if self.depth == MAX_DEPTH => Err(format!(
+                "nested deeper than {} levels at column {}",
+                MAX_DEPTH,
+                self.pos + 1
+            )),
+            Some('{') => self.nested(Parser::object),
+            Some('[') => self.nested(Parser::array),
+            Some('"') => self.string().map(Json::Str),
+            Some(_) => self.literal(),
+            None => Err("unexpected end of input".to_string()),
+        }
+    }
+
+    /// `parse` one level deeper
+    fn nested(&mut self, parse: fn(&mut Parser) -> Result<Json, String>) -> Result<Json, String> {
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut entries = Vec::new();
+        self.skip_ws();
+        if self.chars.get(self.pos) == Some(&'}') {
+            self.pos += 1;
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_ws();
+            let key = self.string()?;
+            self.expect(':')?;
+            entries.push((key, self.value()?));
+            self.skip_ws();
+            match self.chars.get(self.pos) {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(entries));
+                }
+                _ => return Err(format!("expected ',' or '}}' at column {}", self.pos + 1)),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_ws();
+        if self.chars.get(self.pos) == Some(&']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_ws();
+            match self.chars.get(self.pos) {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(format!("expected ',' or ']' at column {}", self.pos + 1)),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.chars.get(self.pos) != Some(&'"') {
+            return Err(format!("expected a string at column {}", self.pos + 1));
+        }
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let Some(&c) = self.chars.get(self.pos) else {
+                return Err("unterminated string".to_string());
+            };
+            self.pos += 1;
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escaped = self.chars.get(self.pos).copied();
+                    self.pos += 1;
+                    match escaped {
+                        Some('n') => out.push('\n'),
+                        Some('t') => out.push('\t'),
+                        Some('r') => out.push('\r'),
+                        Some('u') => {
+                            let hex: String = self.chars.iter().skip(self.pos).take(4).collect();
+                            let code = u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or("invalid \\u escape")?;
+                            out.push(code);
+                            self.pos += 4;
+                        }
+                        Some(c @ ('"' | '\\' | '/')) => out.push(c),
+                        _ => return Err("invalid escape".to_string()),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn literal(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.'))
+        {
+            self.pos += 1;
+        }
+        let word: String = self.chars[start..self.pos].iter().collect();
+        match word.as_str() {
+            "null" => Ok(Json::Null),
+            "true" => Ok(Json::Bool(true)),
+            "false" => Ok(Json::Bool(false)),
+            // Rust also reads "NaN" and "inf", which JSON does not have
+            _ => match word.parse::<f64>() {
+                Ok(number) if number.is_finite() => Ok(Json::Num(number)),
+                _ => Err(format!("unexpected '{}' at column {}", word, start + 1)),
+            },
+        }
+    }
+}
+
+fn numbers(names: &[&str], values: &[f64]) -> Json {
+    Json::Object(
+        names
+            .iter()
+            .zip(values)
+            .map(|(name, value)| (name.to_string(), Json::Num(*value)))
+            .collect(),
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Session
+// ---------------------------------------------------------------------------
+
+/// State between `init` and `terminate`
+struct Running {
+    trace: bool,
+    /// Every stage, stepped at the requests' times (stepper.rs)
+    stepper: Stepper,
+    /// Latest raw reading per input, NaN until given
+    raw: Vec<f64>,
+    started: bool,
+    /// What the last binary `compute` answered
+    last: Option<wire::State>,
+}
+
+impl Running {
+    /// Step at `time` from the raw readings `raw`, keeping them if it
+    /// computes
+    fn advance(&mut self, system: &FuzzySystem, raw: Vec<f64>, time: f64) -> Result<Step, String> {
+        if !self.started
+            && let Some((var, _)) = system.inputs.iter().zip(&raw).find(|(_, v)| v.is_nan())
+        {
+            return Err(format!("the first step needs input '{}'", var.name));
+        }
+        let step = self.stepper.step_at(&raw, time)?;
+        self.raw = raw;
+        self.started = true;
+        Ok(step)
+    }
+}
+
+/// One co-simulation, fed a request at a time
+pub struct Session {
+    system: FuzzySystem,
+    running: Option<Running>,
+    pub terminated: bool,
+}
+
+impl Session {
+    pub fn new(system: FuzzySystem) -> Self {
+        Session {
+            system,
+            running: None,
+            terminated: false,
+        }
+    }
+
+    /// The response line to one request line
+    pub fn handle(&mut self, line: &str) -> String {
+        let response = Json::parse(line).and_then(|request| self.request(&request));
+        match response {
+            Ok(json) => json.to_text(),
+            Err(e) => Json::Object(vec![("error".to_string(), Json::Str(e))]).to_text(),
+        }
+    }
+
+    /// Start over from the config
+    fn start(&mut self, trace: bool) {
+        self.running = Some(Running {
+            trace,
+            stepper: Stepper::new(self.system.clone()),
+            raw: vec![f64::NAN; self.system.inputs.len()],
+            started: false,
+            last: None,
+        });
+    }
+
+    /// The reply to one binary request (wire.rs)
+    pub fn handle_message(&mut self, message: Message) -> Message {
+        if let Message::Init = message {
+            self.start(false);
+            let names =
+                |vars: &[crate::FuzzyVariable]| vars.iter().map(|v| v.name.clone()).collect();
+            return Message::Ready {
+                inputs: names(&self.system.inputs),
+                outputs: names(&self.system.outputs),
+            };
+        }
+        if let Message::Terminate = message {
+            self.running = None;
+            self.terminated = true;
+            return Message::Ok;
+        }
+        let system = &self.system;
+        let Some(running) = self.running.as_mut() else {
+            return Message::Error("send init first".to_string());
+        };
+        let reply = match message {
+            Message::SetInputs(readings) => {
+                match readings
+                    .iter()
+                    .find(|(index, _)| *index as usize >= running.raw.len())
+                {
+                    Some((index, _)) => Err(format!("unknown input index {}", index)),
+                    None => {
+                        for (index, value) in readings {
+                            running.raw[index as usize] = value;
+                        }
+                        Ok(Message::Ok)
+                    }
+                }
+            }
+            Message::Compute { time } => {
+                let raw = running.raw.clone();
+                running.advance(system, raw, time).map(|step| {
+                    let state = wire::State {
+                        time: step.time,
+                        outputs: step.evaluation.outputs,
+                        actuator: step.output.value,
+                        band: step.output.band,
+                    };
+                    running.last = Some(state.clone());
+                    Message::State(state)
+                })
+            }
+            Message::GetState => (running.last.clone())
+                .map(Message::State)
+                .ok_or_else(|| "nothing computed yet".to_string()),
+            Message::Weights(weights) => {
+                running.stepper.set_weights(&weights).map(|()| Message::Ok)
+            }
+            _ => Err("not a request".to_string()),
+        };
+        reply.unwrap_or_else(Message::Error)
+    }
+
+    fn request(&mut self, request: &Json) -> Result<Json, String> {
+        match request.get("cmd") {
+            Some(Json::Str(cmd)) if cmd == "init" => {
+                let trace = match request.get("trace") {
+                    None => false,
+                    Some(Json::Bool(trace)) => *trace,
+                    Some(_) => return Err("'trace' must be true or false".to_string()),
+                };
+                self.start(trace);
+                let system = &self.system;
+                let names = |vars: &[crate::FuzzyVariable]| {
+                    Json::Array(vars.iter().map(|v| Json::Str(v.name.clone())).collect())
+                };
+                // Variables without a unit are left out
+                let units = system
+                    .inputs
+                    .iter()
+                    .chain(&system.outputs)
+                    .filter(|v| !v.unit.name.is_empty())
+                    .map(|v| (v.name.clone(), Json::Str(v.unit.name.clone())))
+                    .collect();
+                Ok(Json::Object(vec![
+                    ("ok".to_string(), Json::Bool(true)),
+                    ("inputs".to_string(), names(&system.inputs)),
+                    ("outputs".to_string(), names(&system.outputs)),
+                    ("units".to_string(), Json::Object(units)),
+                ]))
+            }
+            Some(Json::Str(cmd)) if cmd == "step" => self.step(request),
+            Some(Json::Str(cmd)) if cmd == "weights" => {
+                let running = self.running.as_mut().ok_or("send init first")?;
+                let weights = match request.get("weights") {
+                    Some(Json::Array(items)) => items
+                        .iter()
+                        .map(|item| match item {
+                            Json::Num(weight) => Ok(*weight),
+                            _ => Err("weights must be numbers".to_string()),
+                        })
+                        .collect::<Result<Vec<f64>, String>>()?,
+                    _ => return Err("'weights' must be an array".to_string()),
+                };
+                running.stepper.set_weights(&weights)?;
+                Ok(Json::Object(vec![("ok".to_string(), Json::Bool(true))]))
+            }
+            Some(Json::Str(cmd)) if cmd == "terminate" => {
+                self.running = None;
+                self.terminated = true;
+                Ok(Json::Object(vec![("ok".to_string(), Json::Bool(true))]))
+            }
+            Some(Json::Str(cmd)) => Err(format!("unknown cmd '{}'", cmd)),
+            _ => Err("missing 'cmd'".to_string()),
+        }
+    }
+
+    fn step(&mut self, request: &Json) -> Result<Json, String> {
+        let system = &self.system;
+        let running = self.running.as_mut().ok_or("send init first")?;
+        let Some(Json::Num(time)) = request.get("time") else {
+            return Err("'time' must be a number".to_string());
+        };
+        let time = *time;
+
+        let mut raw = running.raw.clone();
+        match request.get("inputs") {
+            Some(Json::Object(entries)) => {
+                for (name, value) in entries {
+                    let index = system
+                        .inputs
+                        .iter()
+                        .position(|var| var.name == *name)
+                        .ok_or_else(|| format!("unknown input '{}'", name))?;
+                    raw[index] = match value {
+                        Json::Num(value) => *value,
+                        // A failed sensor, as in the other front ends
+                        Json::Null => f64::NAN,
+                        _ => return Err(format!("input '{}' must be a number", name)),
+                    };
+                }
+            }
+            None => {}
+            Some(_) => return Err("'inputs' must be an object".to_string()),
+        }
+        let step = running.advance(system, raw, time)?;
+        let (time, inputs, evaluation, output) =
+            (step.time, step.inputs, step.evaluation, step.output);
+
+        let output_names: Vec<&str> = system.outputs.iter().map(|v| v.name.as_str()).collect();
+        let mut response = vec![
+            ("time".to_string(), Json::Num(time)),
+            (
+                "outputs".to_string(),
+                numbers(&output_names, &evaluation.outputs),
+            ),
+            ("actuator".to_string(), Json::Num(output.value)),
+            (
+                "band".to_string(),
+                Json::Str(output.band.label().to_string()),
+            ),
+        ];
+        if running.trace {
+            let memberships = system
+                .inputs
+                .iter()
+                .zip(&inputs)
+                .map(|(var, value)| {
+                    let sets = var
+                        .fuzzify(*value)
+                        .into_iter()
+                        .map(|set| (set.name, Json::Num(set.membership)))
+                        .collect();
+                    (var.name.clone(), Json::Object(sets))
+                })
+                .collect();
+            response.push(("memberships".to_string(), Json::Object(memberships)));
+            response.push((
+                "strengths".to_string(),
+                Json::Array(evaluation.strengths.into_iter().map(Json::Num).collect()),
+            ));
+            let methods = (output_names.iter().zip(&evaluation.methods))
+                .map(|(name, method)| (name.to_string(), Json::Str(method.name().to_string())))
+                .collect();
+            response.push(("methods".to_string(), Json::Object(methods)));
+            response.push((
+                "caps".to_string(),
+                Json::Array(
+                    evaluation
+                        .cap_hits
+                        .iter()
+                        .map(|hit| Json::Str(hit.describe()))
+                        .collect(),
+                ),
+            ));
+            response.push((
+                "envelope".to_string(),
+                evaluation
+                    .envelope
+                    .map_or(Json::Null, |e| Json::Str(e.describe())),
+            ));
+            response.push((
+                "out_of_range".to_string(),
+                Json::Array(
+                    evaluation
+                        .overflows
+                        .iter()
+                        .map(|o| Json::Str(o.describe()))
+                        .collect(),
+                ),
+            ));
+        }
+        Ok(Json::Object(response))
+    }
+}
+
+/// Answer the requests on `input` one line at a time, or one frame at a
+/// time after a `wire` handshake, until `terminate` or the end of the input
+pub fn serve(system: FuzzySystem, mut input: impl BufRead, mut out: impl Write) -> io::Result<()> {
+    let mut session = Session::new(system);
+    let mut first = true;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = (&mut input)
+            .take(MAX_LINE as u64 + 1)
+            .read_line(&mut line)?;
+        if read == 0 {
+            break;
+        }
+        if read > MAX_LINE {
+            let error = format!("request longer than {} bytes", MAX_LINE);
+            let error = Json::Object(vec![("error".to_string(), Json::Str(error))]);
+            writeln!(out, "{}", error.to_text())?;
+            out.flush()?;
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        match wire::requested(&line).filter(|_| first) {
+            Some(Ok(())) => {
+                writeln!(out, "{}", wire::handshake())?;
+                out.flush()?;
+                return serve_frames(session, input, out);
+            }
+            Some(Err(e)) => {
+                let error = Json::Object(vec![("error".to_string(), Json::Str(e))]);
+                writeln!(out, "{}", error.to_text())?;
+            }
+            None => writeln!(out, "{}", session.handle(&line))?,
+        }
+        first = false;
+        // The simulator waits for every response before its next request
+        out.flush()?;
+        if session.terminated {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// The binary rest of a session (wire.rs)
+fn serve_frames(
+    mut session: Session,
+    mut input: impl BufRead,
+    mut out: impl Write,
+) -> io::Result<()> {
+    while let Some(frame) = wire::read_frame(&mut input)? {
+        let reply = match wire::decode(&frame) {
+            Ok(message) => session.handle_message(message),
+            Err(e) => Message::Error(e),
+        };
+        wire::write(&mut out, &reply)?;
+        if session.terminated {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// `cosim` subcommand
+pub fn run(args: &[String]) -> i32 {
+    let system = match args {
+        [] => FuzzySystem::demo(),
+        [flag, path] if flag == "--config" => match config::load_system(path) {
+            Ok(system) => system,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return 2;
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    match serve(system, io::stdin().lock(), io::stdout().lock()) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange(requests: &[&str]) -> Vec<String> {
+        let system = config::load_system("configs/fan.toml").unwrap();
+        let mut out = Vec::new();
+        serve(system, requests.join("\n").as_bytes(), &mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn a_scripted_exchange_is_answered_in_order_and_deterministically() {
+        let script = [
+            r#"{"cmd":"step","time":0,"inputs":{"temperature":20}}"#,
+            r#"{"cmd":"init","trace":true}"#,
+            r#"{"cmd":"step","time":0,"inputs":{"temperature":20}}"#,
+            r#"{"cmd":"step","time":0,"inputs":{"temperature":35,"humidity":80}}"#,
+            r#"{"cmd":"step","time":1.5,"inputs":{"temperature":24}}"#,
+            r#"{"cmd":"step","time":1}"#,
+            r#"{"cmd":"terminate"}"#,
+            r#"{"cmd":"init"}"#,
+        ];
+        let first = exchange(&script);
+        assert_eq!(first, exchange(&script));
+        // Nothing after terminate
+        assert_eq!(first.len(), 7);
+
+        assert_eq!(first[0], r#"{"error":"send init first"}"#);
+        assert_eq!(
+            first[1],
+            r#"{"ok":true,"inputs":["temperature","humidity"],"outputs":["fan_speed"],"units":{"temperature":"°C","humidity":"%","fan_speed":"%"}}"#
+        );
+        assert_eq!(
+            first[2],
+            r#"{"error":"the first step needs input 'humidity'"}"#
+        );
+        let step = Json::parse(&first[3]).unwrap();
+        let Some(Json::Num(fan)) = step.get("outputs").and_then(|o| o.get("fan_speed")) else {
+            panic!("{}", first[3]);
+        };
+        assert!(*fan > 75.0, "{}", fan);
+        assert_eq!(
+            step.get("memberships")
+                .and_then(|m| m.get("temperature"))
+                .and_then(|t| t.get("Hot")),
+            Some(&Json::Num(1.0))
+        );
+        assert_eq!(
+            step.get("methods").and_then(|m| m.get("fan_speed")),
+            Some(&Json::Str("centroid".to_string()))
+        );
+        // Humidity held from the previous step
+        let step = Json::parse(&first[4]).unwrap();
+        assert_eq!(step.get("time"), Some(&Json::Num(1.5)));
+        assert_eq!(
+            step.get("memberships")
+                .and_then(|m| m.get("humidity"))
+                .and_then(|h| h.get("High")),
+            Some(&Json::Num(1.0))
+        );
+        assert_eq!(
+            first[5],
+            r#"{"error":"time 1 is before the previous step at 1.5"}"#
+        );
+        assert_eq!(first[6], r#"{"ok":true}"#);
+    }
+
+    #[test]
+    fn pipeline_stages_follow_the_simulation_time() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let system =
+            config::parse_system(&format!("{}\n[output.pipeline]\nrate_limit = 10.0\n", fan))
+                .unwrap();
+        let mut session = Session::new(system);
+        session.handle(r#"{"cmd":"init"}"#);
+        let actuator = |response: String| match Json::parse(&response).unwrap().get("actuator") {
+            Some(Json::Num(value)) => *value,
+            _ => panic!("{}", response),
+        };
+        let start = actuator(
+            session.handle(r#"{"cmd":"step","time":100,"inputs":{"temperature":5,"humidity":20}}"#),
+        );
+        // Two simulated seconds at 10 %/s toward a much higher output,
+        // however long the call takes
+        let later = actuator(
+            session
+                .handle(r#"{"cmd":"step","time":102,"inputs":{"temperature":40,"humidity":90}}"#),
+        );
+        assert!(
+            (later - start - 20.0).abs() < 1e-9,
+            "{} -> {}",
+            start,
+            later
+        );
+        assert!(session.handle("{cmd}").starts_with(r#"{"error":"#));
+    }
+
+    #[test]
+    fn readings_outside_the_universe_are_traced_or_refused() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let policy = |policy: &str| {
+            let system = config::parse_system(&fan.replacen(
+                "name = \"temperature\"\n",
+                &format!("name = \"temperature\"\nout_of_range = \"{}\"\n", policy),
+                1,
+            ))
+            .unwrap();
+            let mut session = Session::new(system);
+            session.handle(r#"{"cmd":"init","trace":true}"#);
+            session
+        };
+        let step = |temperature: f64| {
+            format!(
+                r#"{{"cmd":"step","time":0,"inputs":{{"temperature":{},"humidity":60}}}}"#,
+                temperature
+            )
+        };
+
+        let response = Json::parse(&policy("extend").handle(&step(55.0))).unwrap();
+        assert_eq!(
+            response.get("out_of_range"),
+            Some(&Json::Array(vec![Json::Str(
+                "temperature 55 above 50, held at the limit".to_string()
+            )]))
+        );
+        let response = Json::parse(&policy("clamp").handle(&step(20.0))).unwrap();
+        assert_eq!(response.get("out_of_range"), Some(&Json::Array(Vec::new())));
+
+        let mut refusing = policy("error");
+        assert_eq!(
+            refusing.handle(&step(-5.0)),
+            r#"{"error":"temperature = -5 is outside 0 to 50"}"#
+        );
+        // Still waiting for a first step
+        assert_eq!(
+            refusing.handle(r#"{"cmd":"step","time":0,"inputs":{"temperature":20}}"#),
+            r#"{"error":"the first step needs input 'humidity'"}"#
+        );
+    }
+
+    /// Times and readings of temperature and humidity
+    type Script = [(f64, [f64; 2])];
+
+    /// The readings of a swinging day, one per simulated hundredth of a
+    /// second
+    fn script(steps: usize) -> Vec<(f64, [f64; 2])> {
+        (0..steps)
+            .map(|i| {
+                let t = i as f64 * 0.01;
+                (
+                    t,
+                    [
+                        24.0 + 12.0 * (t / 8.0).sin(),
+                        55.0 + 30.0 * (t / 13.0).cos(),
+                    ],
+                )
+            })
+            .collect()
+    }
+
+    fn json_session(system: FuzzySystem, script: &Script) -> Vec<f64> {
+        let mut requests = vec![r#"{"cmd":"init"}"#.to_string()];
+        requests.extend(script.iter().map(|(time, [t, h])| {
+            format!(
+                r#"{{"cmd":"step","time":{},"inputs":{{"temperature":{},"humidity":{}}}}}"#,
+                time, t, h
+            )
+        }));
+        let mut out = Vec::new();
+        serve(system, requests.join("\n").as_bytes(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        (out.lines().skip(1))
+            .map(|line| match Json::parse(line).unwrap().get("actuator") {
+                Some(Json::Num(value)) => *value,
+                _ => panic!("{}", line),
+            })
+            .collect()
+    }
+
+    fn binary_session(system: FuzzySystem, script: &Script) -> Vec<f64> {
+        let mut requests = format!("{}\n", wire::handshake()).into_bytes();
+        wire::write(&mut requests, &Message::Init).unwrap();
+        for (time, [t, h]) in script {
+            wire::write(&mut requests, &Message::SetInputs(vec![(0, *t), (1, *h)])).unwrap();
+            wire::write(&mut requests, &Message::Compute { time: *time }).unwrap();
+        }
+        let mut out = Vec::new();
+        serve(system, &requests[..], &mut out).unwrap();
+        let mut replies = &out[wire::handshake().len() + 1..];
+        let mut actuators = Vec::new();
+        while let Some(frame) = wire::read_frame(&mut replies).unwrap() {
+            if let Message::State(state) = wire::decode(&frame).unwrap() {
+                actuators.push(state.actuator);
+            }
+        }
+        actuators
+    }
+
+    #[test]
+    fn binary_frames_answer_what_the_json_lines_do() {
+        let system = config::load_system("configs/fan.toml").unwrap();
+        let script = script(300);
+        let json = json_session(system.clone(), &script);
+        assert_eq!(json.len(), 300);
+        // Bit for bit: the JSON numbers print in full
+        assert_eq!(binary_session(system.clone(), &script), json);
+
+        let mut session = Session::new(system);
+        assert_eq!(
+            session.handle_message(Message::GetState),
+            Message::Error("send init first".to_string())
+        );
+        assert_eq!(
+            session.handle_message(Message::Init),
+            Message::Ready {
+                inputs: vec!["temperature".to_string(), "humidity".to_string()],
+                outputs: vec!["fan_speed".to_string()],
+            }
+        );
+        assert_eq!(
+            session.handle_message(Message::GetState),
+            Message::Error("nothing computed yet".to_string())
+        );
+        assert_eq!(
+            session.handle_message(Message::SetInputs(vec![(0, 30.0), (2, 1.0)])),
+            Message::Error("unknown input index 2".to_string())
+        );
+        assert_eq!(
+            session.handle_message(Message::Compute { time: 0.0 }),
+            Message::Error("the first step needs input 'temperature'".to_string())
+        );
+        session.handle_message(Message::SetInputs(vec![(0, 30.0), (1, 60.0)]));
+        let Message::State(state) = session.handle_message(Message::Compute { time: 0.0 }) else {
+            panic!("no state");
+        };
+        assert_eq!(state.outputs.len(), 1);
+        assert_eq!(
+            session.handle_message(Message::GetState),
+            Message::State(state.clone())
+        );
+        assert_eq!(
+            session.handle_message(Message::Weights(vec![1.0; 3])),
+            Message::Error("expected 9 weights, got 3".to_string())
+        );
+        assert_eq!(
+            session.handle_message(Message::Weights(vec![0.0; 9])),
+            Message::Ok
+        );
+        let Message::State(silenced) = session.handle_message(Message::Compute { time: 0.0 })
+        else {
+            panic!("no state");
+        };
+        assert_ne!(silenced.outputs, state.outputs);
+        assert_eq!(
+            session.handle_message(Message::Ok),
+            Message::Error("not a request".to_string())
+        );
+        assert_eq!(session.handle_message(Message::Terminate), Message::Ok);
+        assert!(session.terminated);
+    }
+
+    #[test]
+    fn the_handshake_is_only_taken_first_and_bad_frames_are_answered() {
+        let replies = exchange(&[r#"{"cmd":"init"}"#, "wire 1"]);
+        assert_eq!(replies[1], r#"{"error":"unexpected 'wire' at column 1"}"#);
+        assert_eq!(
+            exchange(&["wire 9"]),
+            [r#"{"error":"unsupported wire version 9"}"#]
+        );
+
+        // A frame that does not decode is answered, and the next one read
+        let system = config::load_system("configs/fan.toml").unwrap();
+        let mut requests = b"wire 1\n".to_vec();
+        requests.extend([2, 0, 0, 0, wire::VERSION, 0x7f]);
+        wire::write(&mut requests, &Message::Init).unwrap();
+        let mut out = Vec::new();
+        serve(system.clone(), &requests[..], &mut out).unwrap();
+        let mut replies = &out[b"wire 1\n".len()..];
+        let mut read = || wire::decode(&wire::read_frame(&mut replies).unwrap().unwrap()).unwrap();
+        assert_eq!(
+            read(),
+            Message::Error("unknown message id 0x7f".to_string())
+        );
+        assert!(matches!(read(), Message::Ready { .. }));
+
+        // One too long ends the session unread
+        let mut requests = b"wire 1\n".to_vec();
+        requests.extend(u32::MAX.to_le_bytes());
+        assert!(serve(system, &requests[..], &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn json_weights_replace_every_rule_weight() {
+        let replies = exchange(&[
+            r#"{"cmd":"weights","weights":[1]}"#,
+            r#"{"cmd":"init"}"#,
+            r#"{"cmd":"weights","weights":[1]}"#,
+            r#"{"cmd":"weights","weights":[1,1,1,1,1,1,1,1,"x"]}"#,
+            r#"{"cmd":"weights","weights":[1,1,1,1,1,1,1,1,2]}"#,
+            r#"{"cmd":"weights","weights":[1,1,1,1,1,1,1,1,0.5]}"#,
+        ]);
+        assert_eq!(
+            replies,
+            [
+                r#"{"error":"send init first"}"#,
+                replies[1].as_str(),
+                r#"{"error":"expected 9 weights, got 1"}"#,
+                r#"{"error":"weights must be numbers"}"#,
+                r#"{"error":"weight 2 is not between 0 and 1"}"#,
+                r#"{"ok":true}"#,
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore = "benchmark; run with --ignored --nocapture"]
+    fn binary_frames_outrun_json_lines_in_the_loop() {
+        let system = config::load_system("configs/fan.toml").unwrap();
+        let script = script(20_000);
+        let timed = |session: fn(FuzzySystem, &Script) -> Vec<f64>| {
+            let start = std::time::Instant::now();
+            let actuators = session(system.clone(), &script);
+            (actuators, start.elapsed().as_secs_f64())
+        };
+        let (json, json_secs) = timed(json_session);
+        let (binary, binary_secs) = timed(binary_session);
+        assert_eq!(binary, json);
+        let rate = |secs: f64| script.len() as f64 / secs;
+        println!(
+            "json {:.0} steps/s, binary {:.0} steps/s ({:.2}x)",
+            rate(json_secs),
+            rate(binary_secs),
+            json_secs / binary_secs
+        );
+        assert!(binary_secs < json_secs);
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let text = r#"{"a":[1,2.5,-3e2],"b":"x\"y\\u00e9","c":null,"d":{}}"#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.get("b"), Some(&Json::Str("x\"y\\u00e9".to_string())));
+        assert_eq!(Json::parse(&json.to_text()).unwrap(), json);
+        assert_eq!(
+            Json::parse(r#""\u00e9""#).unwrap(),
+            Json::Str("é".to_string())
+        );
+        assert!(Json::parse(r#"{"a":1"#).is_err());
+    }
+
+    #[test]
+    fn deep_nesting_and_endless_lines_are_refused() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            Json::parse(&nested(MAX_DEPTH + 1)).unwrap_err(),
+            format!(
+                "nested deeper than {} levels at column {}",
+                MAX_DEPTH,
+                MAX_DEPTH + 1
+            )
+        );
+        // What used to overflow the stack
+        let flood = "[".repeat(200_000);
+        assert!(exchange(&[&flood[..MAX_LINE - 1]])[0].starts_with("{\"error\":\"nested deeper"));
+
+        let long = format!("{{\"cmd\":\"init\",\"pad\":\"{}\"}}", "x".repeat(MAX_LINE));
+        let responses = exchange(&[r#"{"cmd":"init"}"#, &long, r#"{"cmd":"init"}"#]);
+        assert_eq!(responses.len(), 2, "{:?}", responses);
+        assert_eq!(
+            responses[1],
+            format!("{{\"error\":\"request longer than {} bytes\"}}", MAX_LINE)
+        );
+    }
+}
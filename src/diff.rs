@@ -0,0 +1,1221 @@
+// ============================================================================
+// SYSTEM DIFF - Comparação estrutural e comportamental
+// ============================================================================
+//
+// Two systems compared twice: what differs in their definitions, line by
+// line, and how far their primary outputs are apart over a grid of the
+// old system's universes. The `diff` subcommand compares two configs on a
+// fine grid; the TUI's diff popup compares the running system with the one
+// it loaded, on the coarse DEVIATION_POINTS grid, off the UI thread.
+
+use crate::config;
+use crate::{ConsequentCap, FuzzyController, FuzzyRule, FuzzySystem, FuzzyVariable};
+
+/// Character ramp used by the deviation heatmap, from no deviation to the worst cell
+const HEAT_RAMP: &[char] = &[' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+const HEAT_COLUMNS: usize = 40;
+const HEAT_ROWS: usize = 12;
+/// Points per input axis of the TUI's deviation summary
+pub const DEVIATION_POINTS: usize = 21;
+
+/// Output deviation between two systems over a sampled input grid
+struct BehaviorDiff {
+    max_deviation: f64,
+    mean_deviation: f64,
+    worst_inputs: Vec<f64>,
+    /// Max deviation per heatmap cell, `[row][column]`, row 0 at the top
+    heat: Vec<Vec<f64>>,
+}
+
+fn describe_variable_changes(kind: &str, old: &FuzzyVariable, new: &FuzzyVariable) -> Vec<String> {
+    let mut changes = Vec::new();
+    if old.min != new.min || old.max != new.max {
+        changes.push(format!(
+            "~ {} '{}' range [{}, {}] -> [{}, {}]",
+            kind, old.name, old.min, old.max, new.min, new.max
+        ));
+    }
+    if old.kind != new.kind {
+        changes.push(format!(
+            "~ {} '{}' kind: {} -> {}",
+            kind,
+            old.name,
+            old.kind.name(),
+            new.kind.name()
+        ));
+    }
+    if old.min_delta != new.min_delta {
+        changes.push(format!(
+            "~ {} '{}' min_delta: {} -> {}",
+            kind, old.name, old.min_delta, new.min_delta
+        ));
+    }
+    if old.window != new.window {
+        let describe = |window: &Option<crate::window::WindowConfig>| match window {
+            Some(w) => format!("{}s {}", w.secs, w.aggregate.name()),
+            None => "none".to_string(),
+        };
+        changes.push(format!(
+            "~ {} '{}' window: {} -> {}",
+            kind,
+            old.name,
+            describe(&old.window),
+            describe(&new.window)
+        ));
+    }
+    if old.latency_secs != new.latency_secs {
+        changes.push(format!(
+            "~ {} '{}' latency_secs: {} -> {}",
+            kind, old.name, old.latency_secs, new.latency_secs
+        ));
+    }
+    if old.fusion != new.fusion {
+        let describe = |fusion: &Option<crate::fusion::FusionConfig>| match fusion {
+            Some(f) => {
+                let names: Vec<&str> = f.sources.iter().map(|s| s.name.as_str()).collect();
+                format!("{} of {}", f.strategy.name(), names.join(", "))
+            }
+            None => "none".to_string(),
+        };
+        let (before, after) = (describe(&old.fusion), describe(&new.fusion));
+        changes.push(match before == after {
+            // The same sources, checked or weighted otherwise
+            true => format!("~ {} '{}' fusion: {} checks", kind, old.name, after),
+            false => format!("~ {} '{}' fusion: {} -> {}", kind, old.name, before, after),
+        });
+    }
+    if old.out_of_range != new.out_of_range {
+        changes.push(format!(
+            "~ {} '{}' out_of_range: {} -> {}",
+            kind,
+            old.name,
+            old.out_of_range.name(),
+            new.out_of_range.name()
+        ));
+    }
+    if old.unit != new.unit {
+        changes.push(format!(
+            "~ {} '{}' unit: {} -> {}",
+            kind,
+            old.name,
+            old.unit.describe(),
+            new.unit.describe()
+        ));
+    }
+    // An output's own defuzzification, "default" where it takes the
+    // controller's
+    let own = |value: Option<String>| value.unwrap_or("default".to_string());
+    let (a, b) = (&old.defuzz, &new.defuzz);
+    for (key, before, after) in [
+        (
+            "method",
+            a.method.map(|m| m.name().to_string()),
+            b.method.map(|m| m.name().to_string()),
+        ),
+        (
+            "resolution",
+            a.resolution.map(|r| r.to_string()),
+            b.resolution.map(|r| r.to_string()),
+        ),
+        (
+            "implication",
+            a.implication.map(|i| i.name().to_string()),
+            b.implication.map(|i| i.name().to_string()),
+        ),
+    ] {
+        if before != after {
+            changes.push(format!(
+                "~ {} '{}' {}: {} -> {}",
+                kind,
+                old.name,
+                key,
+                own(before),
+                own(after)
+            ));
+        }
+    }
+    for set in &old.sets {
+        match new.set(&set.name) {
+            None => changes.push(format!("- {} '{}' set '{}'", kind, old.name, set.name)),
+            Some(other) if other.function != set.function => changes.push(format!(
+                "~ {} '{}' set '{}': {} {:?} -> {} {:?}",
+                kind,
+                old.name,
+                set.name,
+                set.function.kind(),
+                set.function.params(),
+                other.function.kind(),
+                other.function.params()
+            )),
+            Some(other) if other.uncertainty != set.uncertainty => changes.push(format!(
+                "~ {} '{}' set '{}' uncertainty: {:?} -> {:?}",
+                kind, old.name, set.name, set.uncertainty, other.uncertainty
+            )),
+            Some(_) => {}
+        }
+    }
+    for set in &new.sets {
+        if old.set(&set.name).is_none() {
+            changes.push(format!(
+                "+ {} '{}' set '{}': {} {:?}",
+                kind,
+                new.name,
+                set.name,
+                set.function.kind(),
+                set.function.params()
+            ));
+        }
+    }
+    changes
+}
+
+/// `~ key 'old' -> 'new'` for every metadata field that differs
+fn metadata_diff(old: &FuzzySystem, new: &FuzzySystem) -> Vec<String> {
+    let (old, new) = (old.metadata.fields(), new.metadata.fields());
+    let value = |fields: &[(&str, &str)], key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| format!("'{}'", v))
+            .unwrap_or_else(|| "unset".to_string())
+    };
+    let mut changes = Vec::new();
+    for key in [
+        "name",
+        "description",
+        "version",
+        "author",
+        "created",
+        "modified",
+    ] {
+        let (a, b) = (value(&old, key), value(&new, key));
+        if a != b {
+            changes.push(format!("~ {} {} -> {}", key, a, b));
+        }
+    }
+    changes
+}
+
+/// Version both systems declare alike, to flag next to a behavior change
+fn unbumped_version<'a>(old: &'a FuzzySystem, new: &FuzzySystem) -> Option<&'a str> {
+    old.metadata
+        .version
+        .as_deref()
+        .filter(|_| old.metadata.version == new.metadata.version)
+}
+
+/// Rule with its clauses sorted, so rules that only differ in clause order compare equal
+fn normalized(rule: &FuzzyRule) -> FuzzyRule {
+    let mut rule = rule.clone();
+    rule.conditions.sort();
+    rule.consequents.sort();
+    rule
+}
+
+/// Differences in variables, sets and rules, ignoring declaration order
+pub fn structural_diff(old: &FuzzySystem, new: &FuzzySystem) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    for var in &old.inputs {
+        match new.input(&var.name) {
+            None => changes.push(format!("- input '{}'", var.name)),
+            Some(other) => changes.extend(describe_variable_changes("input", var, other)),
+        }
+    }
+    for var in &new.inputs {
+        if old.input(&var.name).is_none() {
+            changes.push(format!("+ input '{}'", var.name));
+        }
+    }
+
+    if let ([a], [b]) = (&old.outputs[..], &new.outputs[..]) {
+        if a.name != b.name {
+            changes.push(format!("~ output renamed '{}' -> '{}'", a.name, b.name));
+        }
+        changes.extend(describe_variable_changes("output", a, b));
+    } else {
+        for var in &old.outputs {
+            match new.output_var(&var.name) {
+                None => changes.push(format!("- output '{}'", var.name)),
+                Some(other) => changes.extend(describe_variable_changes("output", var, other)),
+            }
+        }
+        for var in &new.outputs {
+            if old.output_var(&var.name).is_none() {
+                changes.push(format!("+ output '{}'", var.name));
+            }
+        }
+    }
+
+    let (a, b) = (&old.defuzzification, &new.defuzzification);
+    if a.method != b.method {
+        changes.push(format!(
+            "~ defuzzification method: {} -> {}",
+            a.method.name(),
+            b.method.name()
+        ));
+    }
+    if a.resolution != b.resolution {
+        changes.push(format!(
+            "~ defuzzification resolution: {} -> {}",
+            a.resolution, b.resolution
+        ));
+    }
+    if a.implication != b.implication {
+        changes.push(format!(
+            "~ defuzzification implication: {} -> {}",
+            a.implication.name(),
+            b.implication.name()
+        ));
+    }
+    if a.support_threshold != b.support_threshold {
+        changes.push(format!(
+            "~ defuzzification support_threshold: {} -> {}",
+            a.support_threshold, b.support_threshold
+        ));
+    }
+    if a.aggregation != b.aggregation {
+        changes.push(format!(
+            "~ defuzzification aggregation: {} -> {}",
+            a.aggregation.name(),
+            b.aggregation.name()
+        ));
+    }
+    if a.softmax_temperature != b.softmax_temperature {
+        changes.push(format!(
+            "~ defuzzification softmax_temperature: {} -> {}",
+            a.softmax_temperature, b.softmax_temperature
+        ));
+    }
+    if a.cap_profile != b.cap_profile {
+        let name = |profile: &Option<String>| profile.clone().unwrap_or("none".to_string());
+        changes.push(format!(
+            "~ defuzzification cap_profile: {} -> {}",
+            name(&a.cap_profile),
+            name(&b.cap_profile)
+        ));
+    }
+    let same = |x: &ConsequentCap, y: &ConsequentCap| {
+        (&x.profile, &x.output, &x.set) == (&y.profile, &y.output, &y.set)
+    };
+    for cap in &a.caps {
+        match b.caps.iter().find(|c| same(c, cap)) {
+            None => changes.push(format!(
+                "- cap '{}' on {} {}",
+                cap.profile, cap.output, cap.set
+            )),
+            Some(new) if new.max != cap.max => changes.push(format!(
+                "~ cap '{}' on {} {}: {} -> {}",
+                cap.profile, cap.output, cap.set, cap.max, new.max
+            )),
+            Some(_) => {}
+        }
+    }
+    for cap in &b.caps {
+        if !a.caps.iter().any(|c| same(c, cap)) {
+            changes.push(format!(
+                "+ cap '{}' on {} {} at {}",
+                cap.profile, cap.output, cap.set, cap.max
+            ));
+        }
+    }
+
+    let (a, b) = (&old.pipeline, &new.pipeline);
+    if a.status != b.status {
+        changes.push(format!(
+            "~ output pipeline status: {} -> {}",
+            a.status.name(),
+            b.status.name()
+        ));
+    }
+    for (key, before, after) in [
+        ("rate_limit", a.rate_limit, b.rate_limit),
+        ("hysteresis", a.hysteresis, b.hysteresis),
+        ("min_on_secs", a.min_on_secs, b.min_on_secs),
+        ("min_off_secs", a.min_off_secs, b.min_off_secs),
+    ] {
+        if before != after {
+            changes.push(format!(
+                "~ output pipeline {}: {} -> {}",
+                key, before, after
+            ));
+        }
+    }
+
+    match (&old.cascade, &new.cascade) {
+        (None, None) => {}
+        (None, Some(_)) => changes.push("+ output cascade".to_string()),
+        (Some(_), None) => changes.push("- output cascade".to_string()),
+        (Some(a), Some(b)) => {
+            if a.measured != b.measured {
+                changes.push(format!(
+                    "~ output cascade measured: {} -> {}",
+                    a.measured, b.measured
+                ));
+            }
+            for (key, before, after) in [
+                ("kp", a.kp, b.kp),
+                ("ki", a.ki, b.ki),
+                ("kd", a.kd, b.kd),
+                ("min", a.min, b.min),
+                ("max", a.max, b.max),
+                ("plant_gain", a.plant_gain, b.plant_gain),
+                ("plant_tau_secs", a.plant_tau_secs, b.plant_tau_secs),
+            ] {
+                if before != after {
+                    changes.push(format!("~ output cascade {}: {} -> {}", key, before, after));
+                }
+            }
+        }
+    }
+
+    match (&old.exec, &new.exec) {
+        (None, None) => {}
+        (None, Some(_)) => changes.push("+ output exec".to_string()),
+        (Some(_), None) => changes.push("- output exec".to_string()),
+        (Some(a), Some(b)) => {
+            for (key, before, after) in [
+                (
+                    "command",
+                    a.command.source().to_string(),
+                    b.command.source().to_string(),
+                ),
+                (
+                    "stdin",
+                    a.stdin.name().to_string(),
+                    b.stdin.name().to_string(),
+                ),
+                ("on", a.on.name().to_string(), b.on.name().to_string()),
+                (
+                    "timeout_secs",
+                    a.timeout_secs.to_string(),
+                    b.timeout_secs.to_string(),
+                ),
+                ("retries", a.retries.to_string(), b.retries.to_string()),
+                (
+                    "stall_secs",
+                    a.stall_secs.to_string(),
+                    b.stall_secs.to_string(),
+                ),
+            ] {
+                if before != after {
+                    changes.push(format!("~ output exec {}: {} -> {}", key, before, after));
+                }
+            }
+        }
+    }
+
+    let (a, b) = (&old.recompute, &new.recompute);
+    if a.max_interval_secs != b.max_interval_secs {
+        changes.push(format!(
+            "~ recompute max_interval_secs: {} -> {}",
+            a.max_interval_secs, b.max_interval_secs
+        ));
+    }
+
+    let (a, b) = (&old.alignment, &new.alignment);
+    if a.max_wait_secs != b.max_wait_secs {
+        changes.push(format!(
+            "~ alignment max_wait_secs: {} -> {}",
+            a.max_wait_secs, b.max_wait_secs
+        ));
+    }
+
+    let (a, b) = (&old.overrides, &new.overrides);
+    if a.duration_secs != b.duration_secs {
+        changes.push(format!(
+            "~ override duration_secs: {} -> {}",
+            a.duration_secs, b.duration_secs
+        ));
+    }
+
+    let (a, b) = (&old.forecast, &new.forecast);
+    if a.extrapolation != b.extrapolation {
+        changes.push(format!(
+            "~ forecast extrapolation: {} -> {}",
+            a.extrapolation.name(),
+            b.extrapolation.name()
+        ));
+    }
+    for (key, before, after) in [
+        ("horizon_secs", a.horizon_secs, b.horizon_secs),
+        ("steps", a.steps as f64, b.steps as f64),
+        ("window_secs", a.window_secs, b.window_secs),
+    ] {
+        if before != after {
+            changes.push(format!("~ forecast {}: {} -> {}", key, before, after));
+        }
+    }
+
+    match (&old.budget, &new.budget) {
+        (None, None) => {}
+        (None, Some(_)) => changes.push("+ latency budget".to_string()),
+        (Some(_), None) => changes.push("- latency budget".to_string()),
+        (Some(a), Some(b)) => {
+            for (key, before, after) in [
+                ("cycle_ms", a.cycle_ms, b.cycle_ms),
+                (
+                    "recover_cycles",
+                    a.recover_cycles as f64,
+                    b.recover_cycles as f64,
+                ),
+            ] {
+                if before != after {
+                    changes.push(format!("~ budget {}: {} -> {}", key, before, after));
+                }
+            }
+        }
+    }
+
+    match (&old.adaptation, &new.adaptation) {
+        (None, None) => {}
+        (None, Some(_)) => changes.push("+ adaptation".to_string()),
+        (Some(_), None) => changes.push("- adaptation".to_string()),
+        (Some(a), Some(b)) => {
+            if a.enabled != b.enabled {
+                changes.push(format!(
+                    "~ adaptation enabled: {} -> {}",
+                    a.enabled, b.enabled
+                ));
+            }
+            for (key, before, after) in [
+                ("rate", a.rate, b.rate),
+                ("max_step", a.max_step, b.max_step),
+            ] {
+                if before != after {
+                    changes.push(format!("~ adaptation {}: {} -> {}", key, before, after));
+                }
+            }
+        }
+    }
+
+    let (a, b) = (&old.tuning, &new.tuning);
+    if a.max_similarity != b.max_similarity {
+        changes.push(format!(
+            "~ tuning max_similarity: {} -> {}",
+            a.max_similarity, b.max_similarity
+        ));
+    }
+    if a.max_hole != b.max_hole {
+        let show = |v: Option<f64>| v.map_or("none".to_string(), |v| v.to_string());
+        changes.push(format!(
+            "~ tuning max_hole: {} -> {}",
+            show(a.max_hole),
+            show(b.max_hole)
+        ));
+    }
+
+    match (&old.oscillation, &new.oscillation) {
+        (None, None) => {}
+        (None, Some(_)) => changes.push("+ oscillation alarm".to_string()),
+        (Some(_), None) => changes.push("- oscillation alarm".to_string()),
+        (Some(a), Some(b)) => {
+            for (key, before, after) in [
+                ("window_secs", a.window_secs, b.window_secs),
+                (
+                    "min_reversals",
+                    a.min_reversals as f64,
+                    b.min_reversals as f64,
+                ),
+                ("min_amplitude", a.min_amplitude, b.min_amplitude),
+            ] {
+                if before != after {
+                    changes.push(format!("~ oscillation {}: {} -> {}", key, before, after));
+                }
+            }
+        }
+    }
+
+    for alarm in &old.alarms {
+        match new.alarms.iter().find(|a| a.name == alarm.name) {
+            None => changes.push(format!("- alarm '{}'", alarm.name)),
+            Some(after) if after.when != alarm.when => changes.push(format!(
+                "~ alarm '{}': {} -> {}",
+                alarm.name,
+                alarm.when.source(),
+                after.when.source()
+            )),
+            Some(_) => {}
+        }
+    }
+    for alarm in &new.alarms {
+        if !old.alarms.iter().any(|a| a.name == alarm.name) {
+            changes.push(format!("+ alarm '{}': {}", alarm.name, alarm.when.source()));
+        }
+    }
+
+    match (&old.objective, &new.objective) {
+        (None, None) => {}
+        (None, Some(b)) => changes.push(format!("+ objective: {}", b.score.source())),
+        (Some(_), None) => changes.push("- objective".to_string()),
+        (Some(a), Some(b)) => {
+            if a.score != b.score {
+                changes.push(format!(
+                    "~ objective score: {} -> {}",
+                    a.score.source(),
+                    b.score.source()
+                ));
+            }
+            if a.better != b.better {
+                changes.push(format!(
+                    "~ objective better: {} -> {}",
+                    a.better.name(),
+                    b.better.name()
+                ));
+            }
+        }
+    }
+
+    if old.timezone != new.timezone {
+        changes.push(format!(
+            "~ display timezone: {} -> {}",
+            old.timezone.name(),
+            new.timezone.name()
+        ));
+    }
+
+    if old.palette != new.palette {
+        changes.push(format!(
+            "~ display palette: {} -> {}",
+            old.palette.name(),
+            new.palette.name()
+        ));
+    }
+
+    if old.pause_on_blur != new.pause_on_blur {
+        changes.push(format!(
+            "~ display pause_on_blur: {} -> {}",
+            old.pause_on_blur, new.pause_on_blur
+        ));
+    }
+
+    match (&old.comfort, &new.comfort) {
+        (None, None) => {}
+        (None, Some(_)) => changes.push("+ comfort zone".to_string()),
+        (Some(_), None) => changes.push("- comfort zone".to_string()),
+        (Some(a), Some(b)) => {
+            if a.show != b.show {
+                changes.push(format!("~ comfort show: {} -> {}", a.show, b.show));
+            }
+            if a.zone != b.zone {
+                changes.push("~ comfort vertices".to_string());
+            }
+        }
+    }
+
+    for envelope in &old.envelopes {
+        match new.envelopes.iter().find(|e| e.input == envelope.input) {
+            None => changes.push(format!("- envelope '{}'", envelope.input)),
+            Some(other) if other != envelope => {
+                changes.push(format!("~ envelope '{}'", envelope.input));
+            }
+            Some(_) => {}
+        }
+    }
+    for envelope in &new.envelopes {
+        if !old.envelopes.iter().any(|e| e.input == envelope.input) {
+            changes.push(format!("+ envelope '{}'", envelope.input));
+        }
+    }
+
+    for interlock in &old.interlocks {
+        match new.interlocks.iter().find(|i| i.name == interlock.name) {
+            None => changes.push(format!("- interlock '{}'", interlock.name)),
+            Some(other) if other != interlock => {
+                changes.push(format!("~ interlock '{}'", interlock.name));
+            }
+            Some(_) => {}
+        }
+    }
+    for interlock in &new.interlocks {
+        if !old.interlocks.iter().any(|i| i.name == interlock.name) {
+            changes.push(format!("+ interlock '{}'", interlock.name));
+        }
+    }
+
+    for noise in &old.noise {
+        match new.noise.iter().find(|n| n.input == noise.input) {
+            None => changes.push(format!("- noise '{}'", noise.input)),
+            Some(other) if other != noise => {
+                changes.push(format!("~ noise '{}'", noise.input));
+            }
+            Some(_) => {}
+        }
+    }
+    for noise in &new.noise {
+        if !old.noise.iter().any(|n| n.input == noise.input) {
+            changes.push(format!("+ noise '{}'", noise.input));
+        }
+    }
+
+    let (a, b) = (&old.generator, &new.generator);
+    if a.kind != b.kind {
+        changes.push(format!(
+            "~ generator kind: {} -> {}",
+            a.kind.name(),
+            b.kind.name()
+        ));
+    }
+    if a.spread != b.spread {
+        changes.push(format!("~ generator spread: {} -> {}", a.spread, b.spread));
+    }
+    if a.ranges != b.ranges || a.conditions != b.conditions {
+        changes.push("~ generator constraints".to_string());
+    }
+
+    for group in &old.groups {
+        match new.group(&group.name) {
+            None => changes.push(format!("- group '{}'", group.name)),
+            Some(other) => {
+                if group.enabled != other.enabled {
+                    changes.push(format!(
+                        "~ group '{}' enabled: {} -> {}",
+                        group.name, group.enabled, other.enabled
+                    ));
+                }
+                if group.weight != other.weight {
+                    changes.push(format!(
+                        "~ group '{}' weight: {} -> {}",
+                        group.name, group.weight, other.weight
+                    ));
+                }
+            }
+        }
+    }
+    for group in &new.groups {
+        if old.group(&group.name).is_none() {
+            changes.push(format!("+ group '{}'", group.name));
+        }
+    }
+
+    // Rules are compared as multisets so duplicates are accounted for
+    let describe = |rule: &FuzzyRule| match &rule.group {
+        Some(group) => format!("[{}] {}", group, rule.to_text()),
+        None => rule.to_text(),
+    };
+    let mut remaining: Vec<&FuzzyRule> = new.rules.iter().collect();
+    for rule in &old.rules {
+        let key = normalized(rule);
+        match remaining.iter().position(|r| normalized(r) == key) {
+            Some(i) => {
+                remaining.remove(i);
+            }
+            None => changes.push(format!("- rule {}", describe(rule))),
+        }
+    }
+    for rule in remaining {
+        changes.push(format!("+ rule {}", describe(rule)));
+    }
+
+    changes
+}
+
+/// True when the definitions only differ in the order things are declared
+fn order_differs(old: &FuzzySystem, new: &FuzzySystem) -> bool {
+    let names =
+        |vars: &[FuzzyVariable]| -> Vec<String> { vars.iter().map(|v| v.name.clone()).collect() };
+    let set_names =
+        |var: &FuzzyVariable| -> Vec<String> { var.sets.iter().map(|s| s.name.clone()).collect() };
+
+    names(&old.inputs) != names(&new.inputs)
+        || old
+            .inputs
+            .iter()
+            .zip(&new.inputs)
+            .any(|(a, b)| set_names(a) != set_names(b))
+        || names(&old.outputs) != names(&new.outputs)
+        || old
+            .outputs
+            .iter()
+            .zip(&new.outputs)
+            .any(|(a, b)| set_names(a) != set_names(b))
+        || old.rules != new.rules
+        || old.groups != new.groups
+}
+
+/// Max and mean |Δ output| between two systems on the coarse grid
+pub fn deviation(old: &FuzzySystem, new: &FuzzySystem) -> Result<(f64, f64), String> {
+    let diff = behavioral_diff(old, new, DEVIATION_POINTS)?;
+    Ok((diff.max_deviation, diff.mean_deviation))
+}
+
+/// Sample both systems on the same grid, `points_per_axis` points along
+/// each of the old system's universes
+fn behavioral_diff(
+    old: &FuzzySystem,
+    new: &FuzzySystem,
+    points_per_axis: usize,
+) -> Result<BehaviorDiff, String> {
+    for var in &old.inputs {
+        if new.input(&var.name).is_none() {
+            return Err(format!(
+                "input '{}' is missing from the new system, behavior cannot be compared",
+                var.name
+            ));
+        }
+    }
+    if old.inputs.len() != new.inputs.len() {
+        return Err("the systems have different inputs, behavior cannot be compared".to_string());
+    }
+
+    let dims = old.inputs.len();
+    let old_controller = FuzzyController::from_system(old.clone());
+    let new_controller = FuzzyController::from_system(new.clone());
+    // Position of each old input inside the new system's declaration order
+    let new_order: Vec<usize> = old
+        .inputs
+        .iter()
+        .map(|var| {
+            new.inputs
+                .iter()
+                .position(|v| v.name == var.name)
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut diff = BehaviorDiff {
+        max_deviation: 0.0,
+        mean_deviation: 0.0,
+        worst_inputs: old.inputs.iter().map(|v| v.min).collect(),
+        heat: vec![vec![0.0; HEAT_COLUMNS]; HEAT_ROWS],
+    };
+
+    let mut index = vec![0usize; dims];
+    let mut count = 0usize;
+    let mut total = 0.0;
+    loop {
+        let inputs: Vec<f64> = old
+            .inputs
+            .iter()
+            .zip(&index)
+            .map(|(var, i)| {
+                var.min + (var.max - var.min) * (*i as f64) / (points_per_axis - 1) as f64
+            })
+            .collect();
+        let mut reordered = vec![0.0; dims];
+        for (i, x) in inputs.iter().enumerate() {
+            reordered[new_order[i]] = *x;
+        }
+
+        let deviation =
+            (old_controller.compute(&inputs) - new_controller.compute(&reordered)).abs();
+        total += deviation;
+        count += 1;
+        if deviation > diff.max_deviation {
+            diff.max_deviation = deviation;
+            diff.worst_inputs = inputs.clone();
+        }
+
+        // First input runs along the columns, second (if any) along the rows
+        let column = index[0] * HEAT_COLUMNS / points_per_axis;
+        let row = if dims > 1 {
+            HEAT_ROWS - 1 - index[1] * HEAT_ROWS / points_per_axis
+        } else {
+            0
+        };
+        let cell = &mut diff.heat[row][column];
+        *cell = cell.max(deviation);
+
+        // Advance the odometer over all input dimensions
+        let mut d = 0;
+        while d < dims {
+            index[d] += 1;
+            if index[d] < points_per_axis {
+                break;
+            }
+            index[d] = 0;
+            d += 1;
+        }
+        if d == dims {
+            break;
+        }
+    }
+
+    diff.mean_deviation = total / count as f64;
+    Ok(diff)
+}
+
+fn render_heatmap(system: &FuzzySystem, diff: &BehaviorDiff) -> String {
+    let x_var = &system.inputs[0];
+    let rows: Vec<usize> = if system.inputs.len() > 1 {
+        (0..HEAT_ROWS).collect()
+    } else {
+        vec![0]
+    };
+
+    let mut out = String::new();
+    for row in rows {
+        let label = match system.inputs.get(1) {
+            Some(y_var) => {
+                let y = y_var.max - (y_var.max - y_var.min) * row as f64 / HEAT_ROWS as f64;
+                format!("{:>8.1} |", y)
+            }
+            None => "         |".to_string(),
+        };
+        out.push_str(&label);
+        for deviation in &diff.heat[row] {
+            let level = if diff.max_deviation > 0.0 {
+                ((deviation / diff.max_deviation) * (HEAT_RAMP.len() - 1) as f64).round() as usize
+            } else {
+                0
+            };
+            out.push(HEAT_RAMP[level.min(HEAT_RAMP.len() - 1)]);
+        }
+        out.push_str("|\n");
+    }
+    let (low, high) = (format!("{:.1}", x_var.min), format!("{:.1}", x_var.max));
+    out.push_str(&format!(
+        "          {:<width$}{}\n",
+        low,
+        high,
+        width = (HEAT_COLUMNS + 2).saturating_sub(high.len())
+    ));
+    out.push_str(&format!(
+        "          x: {}{}\n",
+        x_var.name,
+        system
+            .inputs
+            .get(1)
+            .map(|v| format!(", y: {}", v.name))
+            .unwrap_or_default()
+    ));
+    out
+}
+
+/// Input region (one heatmap cell) around the worst sampled point
+fn worst_region(system: &FuzzySystem, diff: &BehaviorDiff) -> String {
+    system
+        .inputs
+        .iter()
+        .zip(&diff.worst_inputs)
+        .enumerate()
+        .map(|(i, (var, x))| {
+            let cells = match i {
+                0 => HEAT_COLUMNS,
+                1 => HEAT_ROWS,
+                _ => return format!("{} = {:.2}", var.name, x),
+            };
+            let width = (var.max - var.min) / cells as f64;
+            let cell = (((x - var.min) / width).floor() as usize).min(cells - 1);
+            let low = var.min + cell as f64 * width;
+            format!("{} {:.1}-{:.1}", var.name, low, low + width)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+const USAGE: &str = "Usage: fuzzy_logic diff OLD.toml NEW.toml [--tolerance X]";
+
+/// `diff` subcommand. Exit code 0 when the behavior stays within tolerance,
+/// 1 when it deviates beyond it and 2 on usage or load errors.
+pub fn run(args: &[String]) -> i32 {
+    let mut paths = Vec::new();
+    let mut tolerance = 1e-9;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--tolerance" {
+            match iter.next().and_then(|v| v.parse::<f64>().ok()) {
+                Some(t) if t >= 0.0 => tolerance = t,
+                _ => {
+                    eprintln!("--tolerance expects a non-negative number");
+                    return 2;
+                }
+            }
+        } else {
+            paths.push(arg.as_str());
+        }
+    }
+    let [old_path, new_path] = paths[..] else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+
+    let (old, new) = match (config::load_system(old_path), config::load_system(new_path)) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("error: {}", e);
+            return 2;
+        }
+    };
+
+    let summary = |system: &FuzzySystem| {
+        system
+            .metadata
+            .summary()
+            .unwrap_or_else(|| "(no metadata)".to_string())
+    };
+    println!("Old: {}  {}", old_path, summary(&old));
+    println!("New: {}  {}", new_path, summary(&new));
+    let metadata = metadata_diff(&old, &new);
+    if !metadata.is_empty() {
+        println!();
+        println!("Metadata differences:");
+        for change in &metadata {
+            println!("  {}", change);
+        }
+    }
+
+    println!();
+    println!("Structural differences:");
+    let changes = structural_diff(&old, &new);
+    if changes.is_empty() {
+        println!("  none");
+    }
+    for change in &changes {
+        println!("  {}", change);
+    }
+    let reordered = changes.is_empty() && order_differs(&old, &new);
+    if reordered {
+        println!("  (declaration order differs)");
+    }
+
+    println!();
+    println!("Behavioral differences:");
+    let points_per_axis = if old.inputs.len() <= 2 { 81 } else { 11 };
+    let behavior = match behavioral_diff(&old, &new, points_per_axis) {
+        Ok(behavior) => behavior,
+        Err(e) => {
+            println!("  {}", e);
+            return 1;
+        }
+    };
+    println!("  max |Δ output|:  {:.4}", behavior.max_deviation);
+    println!("  mean |Δ output|: {:.4}", behavior.mean_deviation);
+
+    if behavior.max_deviation == 0.0 {
+        if !changes.is_empty() || reordered {
+            println!();
+            println!("✔ Behavior is IDENTICAL despite the textual changes.");
+        } else {
+            println!();
+            println!("✔ No differences.");
+        }
+        return 0;
+    }
+
+    println!("  largest deviation at: {}", worst_region(&old, &behavior));
+    let worst: Vec<String> = old
+        .inputs
+        .iter()
+        .zip(&behavior.worst_inputs)
+        .map(|(var, x)| format!("{}={:.2}", var.name, x))
+        .collect();
+    println!("  worst sample: {}", worst.join(", "));
+    println!();
+    print!("{}", render_heatmap(&old, &behavior));
+    println!();
+
+    if behavior.max_deviation > tolerance {
+        println!(
+            "✘ Behavior changed: max deviation {:.4} exceeds tolerance {}",
+            behavior.max_deviation, tolerance
+        );
+        if let Some(version) = unbumped_version(&old, &new) {
+            println!("⚠ The version is still {}; consider bumping it.", version);
+        }
+        1
+    } else {
+        println!(
+            "✔ Behavior within tolerance {} (max deviation {:.4})",
+            tolerance, behavior.max_deviation
+        );
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAN: &str = "
+rules = [
+    \"IF temperature IS Cold AND humidity IS Dry THEN fan IS Off\",
+    \"IF temperature IS Hot THEN fan IS High\",
+]
+[[input]]
+name = \"temperature\"
+min = 0
+max = 50
+[[input.set]]
+name = \"Cold\"
+shape = \"trapezoidal\"
+params = [0, 0, 15, 25]
+[[input.set]]
+name = \"Hot\"
+shape = \"trapezoidal\"
+params = [20, 35, 50, 50]
+[[input]]
+name = \"humidity\"
+min = 0
+max = 100
+[[input.set]]
+name = \"Dry\"
+shape = \"triangular\"
+params = [0, 0, 60]
+[output]
+name = \"fan\"
+min = 0
+max = 100
+[[output.set]]
+name = \"Off\"
+shape = \"triangular\"
+params = [0, 0, 40]
+[[output.set]]
+name = \"High\"
+shape = \"triangular\"
+params = [60, 100, 100]
+";
+
+    /// FAN with the inputs, sets, rules and conditions declared the other
+    /// way round
+    const REORDERED: &str = "
+rules = [
+    \"IF temperature IS Hot THEN fan IS High\",
+    \"IF humidity IS Dry AND temperature IS Cold THEN fan IS Off\",
+]
+[[input]]
+name = \"humidity\"
+min = 0
+max = 100
+[[input.set]]
+name = \"Dry\"
+shape = \"triangular\"
+params = [0, 0, 60]
+[[input]]
+name = \"temperature\"
+min = 0
+max = 50
+[[input.set]]
+name = \"Hot\"
+shape = \"trapezoidal\"
+params = [20, 35, 50, 50]
+[[input.set]]
+name = \"Cold\"
+shape = \"trapezoidal\"
+params = [0, 0, 15, 25]
+[output]
+name = \"fan\"
+min = 0
+max = 100
+[[output.set]]
+name = \"High\"
+shape = \"triangular\"
+params = [60, 100, 100]
+[[output.set]]
+name = \"Off\"
+shape = \"triangular\"
+params = [0, 0, 40]
+";
+
+    fn load(text: &str) -> FuzzySystem {
+        config::parse_system(text).unwrap()
+    }
+
+    #[test]
+    fn a_reordered_copy_behaves_identically() {
+        let (old, new) = (load(FAN), load(REORDERED));
+        assert!(structural_diff(&old, &new).is_empty());
+        assert!(order_differs(&old, &new));
+        let behavior = behavioral_diff(&old, &new, 81).unwrap();
+        assert_eq!(behavior.max_deviation, 0.0);
+        assert_eq!(behavior.mean_deviation, 0.0);
+    }
+
+    #[test]
+    fn a_changed_config_reports_what_changed_and_where() {
+        let old = load(FAN);
+        let new = load(&FAN.replace("params = [20, 35, 50, 50]", "params = [30, 40, 50, 50]"));
+        assert_eq!(
+            structural_diff(&old, &new),
+            vec![
+                "~ input 'temperature' set 'Hot': trapezoidal [20.0, 35.0, 50.0, 50.0] \
+                 -> trapezoidal [30.0, 40.0, 50.0, 50.0]"
+            ]
+        );
+        assert!(!order_differs(&old, &new));
+        let behavior = behavioral_diff(&old, &new, 81).unwrap();
+        assert!(behavior.max_deviation > 10.0);
+        assert!(behavior.mean_deviation > 0.0);
+        assert!(behavior.mean_deviation < behavior.max_deviation);
+        // Hot moved up, so the worst point is where it used to fire alone
+        let temperature = behavior.worst_inputs[0];
+        assert!((20.0..=40.0).contains(&temperature), "{}", temperature);
+    }
+
+    #[test]
+    fn unit_changes_are_listed() {
+        let old = load(FAN);
+        let new = load(&FAN.replacen(
+            "max = 50\n",
+            "max = 50\nunit = \"°C\"\ndisplay_unit = \"°F\"\n",
+            1,
+        ));
+        assert_eq!(
+            structural_diff(&old, &new),
+            vec!["~ input 'temperature' unit: none -> °C, shown as °F (x 1.8 + 32)"]
+        );
+        // Only how values are shown changed
+        assert_eq!(behavioral_diff(&old, &new, 81).unwrap().max_deviation, 0.0);
+    }
+
+    #[test]
+    fn defuzzification_changes_name_the_output_they_apply_to() {
+        let old = load(FAN);
+        let new = load(&format!(
+            "{}[defuzzification]\nresolution = 200\n",
+            FAN.replacen(
+                "name = \"fan\"\n",
+                "name = \"fan\"\nmethod = \"plateau_centroid\"\nimplication = \"product\"\n",
+                1,
+            )
+        ));
+        assert_eq!(
+            structural_diff(&old, &new),
+            vec![
+                "~ output 'fan' method: default -> plateau_centroid",
+                "~ output 'fan' implication: default -> product",
+                "~ defuzzification resolution: 100 -> 200",
+            ]
+        );
+    }
+
+    #[test]
+    fn removed_rules_and_sets_are_listed() {
+        let old = load(FAN);
+        let new = load(
+            &FAN.replace("    \"IF temperature IS Hot THEN fan IS High\",\n", "")
+                .replace(
+                    "[[output.set]]\nname = \"High\"",
+                    "[[output.set]]\nname = \"Full\"",
+                )
+                .replace("THEN fan IS High", "THEN fan IS Full"),
+        );
+        assert_eq!(
+            structural_diff(&old, &new),
+            vec![
+                "- output 'fan' set 'High'",
+                "+ output 'fan' set 'Full': triangular [60.0, 100.0, 100.0]",
+                "- rule IF temperature IS Hot THEN fan IS High",
+            ]
+        );
+    }
+
+    #[test]
+    fn metadata_changes_are_listed_and_an_unbumped_version_is_caught() {
+        let with = |metadata: &str| load(&format!("[metadata]\n{}\n{}", metadata, FAN));
+        let old = with("name = \"fan\"\nversion = \"1.0.0\"\nauthor = \"ops\"");
+        let same = with("name = \"fan v2\"\nversion = \"1.0.0\"");
+        let bumped = with("name = \"fan\"\nversion = \"1.1.0\"\nauthor = \"ops\"");
+        assert_eq!(
+            metadata_diff(&old, &same),
+            vec!["~ name 'fan' -> 'fan v2'", "~ author 'ops' -> unset"]
+        );
+        assert_eq!(unbumped_version(&old, &same), Some("1.0.0"));
+        assert_eq!(
+            metadata_diff(&old, &bumped),
+            vec!["~ version '1.0.0' -> '1.1.0'"]
+        );
+        assert_eq!(unbumped_version(&old, &bumped), None);
+        assert_eq!(unbumped_version(&load(FAN), &load(FAN)), None);
+    }
+}
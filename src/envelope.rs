@@ -0,0 +1,191 @@
+// ============================================================================
+// SAFETY ENVELOPE - Envelope de segurança nos limites
+// ============================================================================
+//
+// Near the edges of an input's universe the shoulder sets have long since
+// saturated, so the rules say nothing about how close the reading is to
+// the physical limit. An envelope blends the first output toward a fixed
+// response as an input comes within `margin` of its limit, whatever the
+// rules concluded:
+//
+//     [[envelope]]           # optional, any number of them
+//     input = "temperature"
+//     margin = 2.0           # within 2 °C of the universe limits
+//     high = 100.0           # target near the max; optional
+//     low = 0.0              # target near the min; optional
+//
+// The blend weight grows linearly from 0 at `margin` away from the limit
+// to 1 at the limit itself (and beyond it), so the output reaches the
+// target exactly at the edge. With several envelopes active the one with
+// the largest weight wins, the first declared among weights within the
+// controller's tie tolerance of each other (ties.rs).
+// It runs after defuzzification and is off unless configured; the
+// evaluation reports it as e.g. "boundary override: 30% blend toward 100".
+
+use crate::{FuzzyVariable, ties};
+
+/// One `[[envelope]]` section
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvelopeConfig {
+    pub input: String,
+    pub margin: f64,
+    pub low: Option<f64>,
+    pub high: Option<f64>,
+}
+
+/// An envelope that moved the output
+#[derive(Debug, Clone, PartialEq)]
+pub struct Override {
+    pub input: String,
+    /// Share of the target in the output, above 0 and at most 1
+    pub weight: f64,
+    pub target: f64,
+}
+
+impl Override {
+    pub fn describe(&self) -> String {
+        format!(
+            "boundary override: {:.0}% blend toward {} ({})",
+            self.weight * 100.0,
+            self.target,
+            self.input
+        )
+    }
+}
+
+/// Blend weight for `value` whose limit is `distance` away: 0 at or past
+/// `margin`, 1 at the limit or beyond
+pub fn weight(distance: f64, margin: f64) -> f64 {
+    if distance.is_nan() || margin <= 0.0 {
+        return 0.0;
+    }
+    (1.0 - distance / margin).clamp(0.0, 1.0)
+}
+
+impl EnvelopeConfig {
+    /// Weight and target for `value` of `var`, if the envelope applies
+    fn pull(&self, var: &FuzzyVariable, value: f64, tolerance: f64) -> Option<(f64, f64)> {
+        let pulls: Vec<(f64, f64)> = [(self.low, value - var.min), (self.high, var.max - value)]
+            .into_iter()
+            .filter_map(|(target, distance)| Some((weight(distance, self.margin), target?)))
+            .collect();
+        let index = ties::strongest(pulls.iter().map(|(weight, _)| *weight), tolerance)?;
+        Some(pulls[index])
+    }
+}
+
+/// `output` after the envelopes, given the inputs in declaration order,
+/// with the override that applied; weights within `tolerance` tie
+pub fn apply(
+    envelopes: &[EnvelopeConfig],
+    inputs: &[FuzzyVariable],
+    values: &[f64],
+    output: f64,
+    tolerance: f64,
+) -> (f64, Option<Override>) {
+    let mut overrides: Vec<Override> = envelopes
+        .iter()
+        .filter_map(|envelope| {
+            let index = inputs.iter().position(|v| v.name == envelope.input)?;
+            let (weight, target) = envelope.pull(&inputs[index], values[index], tolerance)?;
+            Some(Override {
+                input: envelope.input.clone(),
+                weight,
+                target,
+            })
+        })
+        .collect();
+    let strongest = ties::strongest(overrides.iter().map(|o| o.weight), tolerance)
+        .map(|index| overrides.swap_remove(index));
+    match strongest {
+        // A failed computation stays failed rather than jumping to the target
+        Some(o) if output.is_finite() => ((1.0 - o.weight) * output + o.weight * o.target, Some(o)),
+        _ => (output, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::assert_output_close;
+    use crate::ties::TOLERANCE;
+
+    fn temperature() -> EnvelopeConfig {
+        EnvelopeConfig {
+            input: "temperature".to_string(),
+            margin: 2.0,
+            low: None,
+            high: Some(100.0),
+        }
+    }
+
+    #[test]
+    fn the_blend_weight_grows_across_the_margin() {
+        // Outside the margin, at its edge, inside it, at and past the limit
+        assert_eq!(weight(5.0, 2.0), 0.0);
+        assert_eq!(weight(2.0, 2.0), 0.0);
+        assert_eq!(weight(1.5, 2.0), 0.25);
+        assert_eq!(weight(0.5, 2.0), 0.75);
+        assert_eq!(weight(0.0, 2.0), 1.0);
+        assert_eq!(weight(-1.0, 2.0), 1.0);
+        assert_eq!(weight(f64::NAN, 2.0), 0.0);
+    }
+
+    #[test]
+    fn the_output_is_pulled_toward_the_target() {
+        let inputs = [FuzzyVariable::new("temperature", 0.0, 50.0)];
+        let envelopes = [temperature()];
+        assert_eq!(
+            apply(&envelopes, &inputs, &[40.0], 60.0, TOLERANCE),
+            (60.0, None)
+        );
+
+        let (output, applied) = apply(&envelopes, &inputs, &[48.6], 60.0, TOLERANCE);
+        assert_output_close(output, 72.0, 1e-9);
+        assert_eq!(
+            applied.unwrap().describe(),
+            "boundary override: 30% blend toward 100 (temperature)"
+        );
+        assert_eq!(
+            apply(&envelopes, &inputs, &[50.0], 60.0, TOLERANCE).0,
+            100.0
+        );
+        // No target for the low side
+        assert_eq!(
+            apply(&envelopes, &inputs, &[0.5], 60.0, TOLERANCE),
+            (60.0, None)
+        );
+    }
+
+    #[test]
+    fn the_strongest_envelope_wins() {
+        let inputs = [
+            FuzzyVariable::new("temperature", 0.0, 50.0),
+            FuzzyVariable::new("humidity", 0.0, 100.0),
+        ];
+        let envelopes = [
+            temperature(),
+            EnvelopeConfig {
+                input: "humidity".to_string(),
+                margin: 10.0,
+                low: Some(0.0),
+                high: None,
+            },
+        ];
+        let (output, applied) = apply(&envelopes, &inputs, &[49.0, 2.0], 50.0, TOLERANCE);
+        assert_eq!(applied.unwrap().input, "humidity");
+        assert_output_close(output, 10.0, 1e-9);
+        assert_eq!(
+            apply(&envelopes, &inputs, &[49.0, 2.0], f64::NAN, TOLERANCE).1,
+            None
+        );
+
+        // Humidity pulls harder by less than the tolerance: a tie, which
+        // the envelope declared first takes
+        let near = [49.0, 5.0 - 1e-11];
+        let (_, applied) = apply(&envelopes, &inputs, &near, 50.0, TOLERANCE);
+        assert_eq!(applied.unwrap().input, "temperature");
+        let (_, applied) = apply(&envelopes, &inputs, &near, 50.0, 0.0);
+        assert_eq!(applied.unwrap().input, "humidity");
+    }
+}
@@ -0,0 +1,1038 @@
+// ============================================================================
+// EXEC OUTPUT - Saída por comando externo
+// ============================================================================
+//
+// Until there is proper plugin support, an `[output.exec]` section hands
+// the first output to any program that can drive an actuator:
+//
+//     [output.exec]          # optional, daemon and TUI
+//     command = "/usr/local/bin/set-fan {value}"
+//     stdin = "none"         # or "json"
+//     on = "publish"         # or "change"
+//     timeout_secs = 5.0
+//     retries = 2
+//     stall_secs = 30.0      # undelivered this long raises exec_stalled
+//
+// `command` is a template, split into the program and its arguments like
+// a shell would split it but never run by one:
+//
+//     spaces                 separate arguments
+//     'text'                 taken as it is, spaces and braces included
+//     "text"                 keeps spaces; \" and \\ are a quote and a
+//                            backslash, and placeholders are replaced
+//     \c                     outside quotes, the character c itself
+//     {value}                the output's value, e.g. 63.6
+//     {band}                 its band, OFF, LOW, MEDIUM or HIGH
+//     {output}               the output's name
+//     {{ and }}              a brace
+//
+// A placeholder's text goes into the argument it is in as it is: a space
+// or a quote in it never splits the argument or starts a string, so no
+// value can turn into another argument or a command. Any other `{...}`,
+// an unterminated quote or an empty template is a config error.
+//
+// With `stdin = "json"` the program also reads the publication on its
+// standard input, `{"output":"fan_speed","value":63.6,"band":"HIGH"}`,
+// and the template need not mention it. Its standard output is ignored.
+//
+// `on = "publish"` runs the program for every output published, each new
+// value or band, as in the audit trail (audit.rs); `on = "change"` only
+// when the band changes. A program still running after `timeout_secs` is
+// killed. Only one runs at a time, on a worker thread of its own, so a
+// slow or stuck program never holds up the computations.
+//
+// Publications made while the program runs wait in a bounded queue that
+// never grows past two: the newest publication, and a priority one such as
+// the daemon's safe output. A newer publication replaces the one waiting
+// (latest value wins: it is counted as coalesced). A priority publication
+// is only ever replaced by a newer priority one: it discards the ordinary
+// one waiting (counted as dropped) and runs before any that come after it,
+// so the order they run in is always the order they were made in, less
+// those replaced. The queue's latency is how long the publication waiting
+// has waited.
+//
+// When nothing could be delivered for more than `stall_secs` (30) since
+// the oldest publication not yet delivered, a program that hangs until
+// its timeout or keeps failing, the `exec_stalled` alarm is raised; the
+// next run that succeeds clears it.
+//
+// A run that does not exit with 0 is tried again, with the newest
+// publication, after RETRY_DELAY, up to `retries` times; the last failure
+// gives up and raises the `exec` alarm, which the next run that succeeds
+// clears. Every run's exit status and the first MAX_STDERR bytes of what
+// it wrote to stderr go into the daemon's log and audit records, or the
+// TUI's messages.
+
+use crate::pipeline::FanBand;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_TIMEOUT_SECS: f64 = 5.0;
+pub const DEFAULT_RETRIES: usize = 2;
+pub const DEFAULT_STALL_SECS: f64 = 30.0;
+/// Bytes of a run's stderr kept
+pub const MAX_STDERR: usize = 1000;
+/// Wait before a failed run is tried again
+pub const RETRY_DELAY: Duration = Duration::from_millis(200);
+/// How often a running program is checked on
+const POLL: Duration = Duration::from_millis(10);
+/// How long the stderr of a killed program is waited for, in case a
+/// child of its own still holds the pipe open
+const STDERR_GRACE: Duration = Duration::from_millis(100);
+
+/// What replaces a placeholder
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Placeholder {
+    Value,
+    Band,
+    Output,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+/// A command line with placeholders, split into arguments once
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    source: String,
+    words: Vec<Vec<Piece>>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut words = Vec::new();
+        let mut word: Option<Vec<Piece>> = None;
+        let mut chars = source.chars().peekable();
+        // Text goes into the word being read, starting one if needed
+        let push = |word: &mut Option<Vec<Piece>>, piece: Piece| {
+            let pieces = word.get_or_insert_with(Vec::new);
+            match (pieces.last_mut(), piece) {
+                (Some(Piece::Text(text)), Piece::Text(more)) => text.push_str(&more),
+                (_, piece) => pieces.push(piece),
+            }
+        };
+        while let Some(c) = chars.next() {
+            match c {
+                c if c.is_whitespace() => words.extend(word.take()),
+                '\'' => {
+                    let mut text = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('\'') => break,
+                            Some(c) => text.push(c),
+                            None => return Err("unterminated ' quote".to_string()),
+                        }
+                    }
+                    push(&mut word, Piece::Text(text));
+                }
+                '"' => loop {
+                    match chars.next() {
+                        Some('"') => {
+                            // "" is an empty argument of its own
+                            push(&mut word, Piece::Text(String::new()));
+                            break;
+                        }
+                        Some('\\') if matches!(chars.peek(), Some('"' | '\\')) => {
+                            let c = chars.next().unwrap_or_default();
+                            push(&mut word, Piece::Text(c.to_string()));
+                        }
+                        Some('{') => push(&mut word, brace(&mut chars)?),
+                        Some('}') => push(&mut word, closing(&mut chars)?),
+                        Some(c) => push(&mut word, Piece::Text(c.to_string())),
+                        None => return Err("unterminated \" quote".to_string()),
+                    }
+                },
+                '\\' => match chars.next() {
+                    Some(c) => push(&mut word, Piece::Text(c.to_string())),
+                    None => return Err("a \\ ends the command".to_string()),
+                },
+                '{' => push(&mut word, brace(&mut chars)?),
+                '}' => push(&mut word, closing(&mut chars)?),
+                c => push(&mut word, Piece::Text(c.to_string())),
+            }
+        }
+        words.extend(word);
+        if words.is_empty() {
+            return Err("the command is empty".to_string());
+        }
+        Ok(Template {
+            source: source.to_string(),
+            words,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The program and its arguments for `publication`
+    pub fn render(&self, publication: &Publication) -> Vec<String> {
+        (self.words.iter())
+            .map(|pieces| {
+                pieces
+                    .iter()
+                    .map(|piece| match piece {
+                        Piece::Text(text) => text.clone(),
+                        Piece::Placeholder(Placeholder::Value) => publication.value.to_string(),
+                        Piece::Placeholder(Placeholder::Band) => {
+                            publication.band.label().to_string()
+                        }
+                        Piece::Placeholder(Placeholder::Output) => publication.output.clone(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// The placeholder or brace after a `{`
+fn brace(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Piece, String> {
+    if chars.next_if_eq(&'{').is_some() {
+        return Ok(Piece::Text("{".to_string()));
+    }
+    let name: String = std::iter::from_fn(|| chars.next_if(|c| *c != '}')).collect();
+    if chars.next().is_none() {
+        return Err(format!("unterminated placeholder '{{{}'", name));
+    }
+    match name.as_str() {
+        "value" => Ok(Piece::Placeholder(Placeholder::Value)),
+        "band" => Ok(Piece::Placeholder(Placeholder::Band)),
+        "output" => Ok(Piece::Placeholder(Placeholder::Output)),
+        _ => Err(format!(
+            "unknown placeholder '{{{}}}' (use {{value}}, {{band}} or {{output}}, \
+             or {{{{ for a brace)",
+            name
+        )),
+    }
+}
+
+/// The brace of a `}}`
+fn closing(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Piece, String> {
+    match chars.next_if_eq(&'}') {
+        Some(_) => Ok(Piece::Text("}".to_string())),
+        None => Err("a single } (use }} for a brace)".to_string()),
+    }
+}
+
+/// What the program is given on its standard input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stdin {
+    None,
+    Json,
+}
+
+impl Stdin {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stdin::None => "none",
+            Stdin::Json => "json",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [Stdin::None, Stdin::Json]
+            .into_iter()
+            .find(|stdin| stdin.name() == name)
+    }
+}
+
+/// Which publications run the program
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Invoke {
+    /// Every new value or band
+    Publish,
+    /// A new band only
+    Change,
+}
+
+impl Invoke {
+    pub fn name(self) -> &'static str {
+        match self {
+            Invoke::Publish => "publish",
+            Invoke::Change => "change",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [Invoke::Publish, Invoke::Change]
+            .into_iter()
+            .find(|on| on.name() == name)
+    }
+}
+
+/// `[output.exec]` settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecConfig {
+    pub command: Template,
+    pub stdin: Stdin,
+    pub on: Invoke,
+    pub timeout_secs: f64,
+    /// Runs after a failed one before giving up
+    pub retries: usize,
+    /// Undelivered this long raises the `exec_stalled` alarm
+    pub stall_secs: f64,
+}
+
+impl ExecConfig {
+    pub fn new(command: Template) -> Self {
+        ExecConfig {
+            command,
+            stdin: Stdin::None,
+            on: Invoke::Publish,
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+            retries: DEFAULT_RETRIES,
+            stall_secs: DEFAULT_STALL_SECS,
+        }
+    }
+}
+
+/// An output as it was published
+#[derive(Debug, Clone, PartialEq)]
+pub struct Publication {
+    pub output: String,
+    pub value: f64,
+    pub band: FanBand,
+    /// Never coalesced with a newer publication, e.g. a safe output
+    pub priority: bool,
+}
+
+impl Publication {
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"output\":{},\"value\":{},\"band\":\"{}\"}}",
+            crate::config::quote(&self.output),
+            // JSON has no NaN or infinities
+            match self.value.is_finite() {
+                true => self.value.to_string(),
+                false => "null".to_string(),
+            },
+            self.band.label()
+        )
+    }
+}
+
+/// How a run ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    /// It exited with this code
+    Exited(i32),
+    /// A signal ended it
+    Signalled,
+    /// It ran past the timeout and was killed
+    TimedOut,
+    /// It could not be started or waited for; the run's stderr says why
+    Error,
+}
+
+impl Status {
+    pub fn succeeded(self) -> bool {
+        self == Status::Exited(0)
+    }
+
+    /// The exit code, or `signal`, `timeout` or `error`
+    pub fn label(self) -> String {
+        match self {
+            Status::Exited(code) => code.to_string(),
+            Status::Signalled => "signal".to_string(),
+            Status::TimedOut => "timeout".to_string(),
+            Status::Error => "error".to_string(),
+        }
+    }
+
+    pub fn parse(label: &str) -> Option<Self> {
+        match label {
+            "signal" => Some(Status::Signalled),
+            "timeout" => Some(Status::TimedOut),
+            "error" => Some(Status::Error),
+            code => code.parse().ok().map(Status::Exited),
+        }
+    }
+}
+
+/// One run of the program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    /// The value it was given
+    pub value: f64,
+    /// Counting from 1; above 1 for the retries after a failure
+    pub attempt: usize,
+    pub status: Status,
+    /// At most MAX_STDERR bytes, trimmed
+    pub stderr: String,
+}
+
+impl Invocation {
+    /// The run for a person, e.g. "exec of 63.6 exited with 3 (attempt 2):
+    /// no such fan"
+    pub fn describe(&self) -> String {
+        let ended = match self.status {
+            Status::Exited(code) => format!("exited with {}", code),
+            Status::Signalled => "was killed by a signal".to_string(),
+            Status::TimedOut => "timed out and was killed".to_string(),
+            Status::Error => "could not run".to_string(),
+        };
+        let mut described = format!("exec of {} {}", self.value, ended);
+        if self.attempt > 1 {
+            described.push_str(&format!(" (attempt {})", self.attempt));
+        }
+        if !self.stderr.is_empty() {
+            described.push_str(&format!(": {}", self.stderr.replace('\n', " / ")));
+        }
+        described
+    }
+}
+
+/// A finished run, and whether it used up the retries
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub invocation: Invocation,
+    /// The run failed and no retry is left: the `exec` alarm is raised
+    pub gave_up: bool,
+}
+
+/// A publication in the queue, and when it was handed over (seconds
+/// since the queue's start)
+#[derive(Debug, Clone, PartialEq)]
+struct Queued {
+    publication: Publication,
+    at: f64,
+}
+
+/// What the queue has been through, at a moment
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueueStats {
+    /// Publications replaced by a newer one while waiting
+    pub coalesced: u64,
+    /// Publications discarded by a priority one while waiting
+    pub dropped: u64,
+    /// Seconds the publication waiting has waited, 0 with none
+    pub latency: f64,
+    /// Seconds since the oldest publication not yet delivered was handed
+    /// over, 0 with all delivered
+    pub undelivered: f64,
+}
+
+/// The publications waiting for the program: the newest ordinary one,
+/// and a priority one to run before it
+#[derive(Debug, Default)]
+struct Queue {
+    priority: Option<Queued>,
+    waiting: Option<Queued>,
+    coalesced: u64,
+    dropped: u64,
+    /// When the oldest publication not yet delivered was handed over
+    undelivered_since: Option<f64>,
+}
+
+impl Queue {
+    fn push(&mut self, publication: Publication, now: f64) {
+        let slot = match publication.priority {
+            true => {
+                if self.waiting.take().is_some() {
+                    self.dropped += 1;
+                }
+                &mut self.priority
+            }
+            false => &mut self.waiting,
+        };
+        let queued = Queued {
+            publication,
+            at: now,
+        };
+        if slot.replace(queued).is_some() {
+            self.coalesced += 1;
+        }
+        self.undelivered_since.get_or_insert(now);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.priority.is_none() && self.waiting.is_none()
+    }
+
+    /// The next publication to run, the priority one first
+    fn pop(&mut self) -> Option<Queued> {
+        self.priority.take().or_else(|| self.waiting.take())
+    }
+
+    /// A run ended at `now`; a delivery leaves undelivered only what
+    /// still waits
+    fn ran(&mut self, delivered: bool, now: f64) {
+        if delivered {
+            self.undelivered_since = [&self.priority, &self.waiting]
+                .into_iter()
+                .flatten()
+                .map(|queued| queued.at)
+                .reduce(f64::min);
+        } else {
+            self.undelivered_since.get_or_insert(now);
+        }
+    }
+
+    fn stats(&self, now: f64) -> QueueStats {
+        let oldest = [&self.priority, &self.waiting]
+            .into_iter()
+            .flatten()
+            .map(|queued| queued.at)
+            .reduce(f64::min);
+        QueueStats {
+            coalesced: self.coalesced,
+            dropped: self.dropped,
+            latency: oldest.map_or(0.0, |at| now - at),
+            undelivered: self.undelivered_since.map_or(0.0, |at| now - at),
+        }
+    }
+}
+
+/// The worker's inbox: the queue, and whether the worker should stop
+#[derive(Default)]
+struct Slot {
+    queue: Queue,
+    closed: bool,
+}
+
+/// Where publications are delivered: the config's program, or a stand-in
+/// in the tests
+trait Backend: Send + 'static {
+    /// Deliver `publication`: how it ended, and what went wrong
+    fn deliver(&mut self, publication: &Publication) -> (Status, String);
+}
+
+/// The `command` of an `[output.exec]`
+struct Program {
+    config: ExecConfig,
+}
+
+impl Backend for Program {
+    fn deliver(&mut self, publication: &Publication) -> (Status, String) {
+        let argv = self.config.command.render(publication);
+        let input = (self.config.stdin == Stdin::Json).then(|| publication.to_json());
+        let timeout = Duration::from_secs_f64(self.config.timeout_secs);
+        run(&argv, input.as_deref(), timeout)
+    }
+}
+
+type Shared = Arc<(Mutex<Slot>, Condvar)>;
+
+/// The program of an `[output.exec]`, run on a worker thread
+pub struct Exec {
+    config: ExecConfig,
+    shared: Shared,
+    outcomes: Receiver<Outcome>,
+    /// The last publication handed to the worker, for the `on` policy
+    last: Option<Publication>,
+    /// What the queue's times count from
+    started: Instant,
+}
+
+impl Exec {
+    /// Start the worker, which waits for the first publication
+    pub fn new(config: ExecConfig) -> Self {
+        let program = Program {
+            config: config.clone(),
+        };
+        Exec::start(config, program)
+    }
+
+    fn start(config: ExecConfig, backend: impl Backend) -> Self {
+        let shared: Shared = Arc::default();
+        let (sender, outcomes) = mpsc::channel();
+        let started = Instant::now();
+        let worker = (config.retries, shared.clone());
+        std::thread::spawn(move || work(backend, worker.0, worker.1, started, sender));
+        Exec {
+            config,
+            shared,
+            outcomes,
+            last: None,
+            started,
+        }
+    }
+
+    pub fn config(&self) -> &ExecConfig {
+        &self.config
+    }
+
+    /// Hand `publication` to the worker if the `on` policy runs the
+    /// program for it, replacing any still waiting; returns whether it
+    /// did. Never waits for the program
+    pub fn publish(&mut self, publication: Publication) -> bool {
+        let due = self.last.as_ref().is_none_or(|last| match self.config.on {
+            Invoke::Publish => (last.value, last.band) != (publication.value, publication.band),
+            Invoke::Change => last.band != publication.band,
+        });
+        if !due {
+            return false;
+        }
+        self.last = Some(publication.clone());
+        let now = self.started.elapsed().as_secs_f64();
+        let (slot, wake) = &*self.shared;
+        let mut slot = slot.lock().unwrap_or_else(|e| e.into_inner());
+        slot.queue.push(publication, now);
+        wake.notify_one();
+        true
+    }
+
+    /// What the queue has been through so far
+    pub fn stats(&self) -> QueueStats {
+        let now = self.started.elapsed().as_secs_f64();
+        let (slot, _) = &*self.shared;
+        slot.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .queue
+            .stats(now)
+    }
+
+    /// How long nothing could be delivered, once that is more than
+    /// `stall_secs`: the `exec_stalled` alarm
+    pub fn stalled(&self) -> Option<f64> {
+        let undelivered = self.stats().undelivered;
+        (undelivered > self.config.stall_secs).then_some(undelivered)
+    }
+
+    /// The runs finished since the last call, oldest first
+    pub fn finished(&self) -> Vec<Outcome> {
+        self.outcomes.try_iter().collect()
+    }
+}
+
+impl Drop for Exec {
+    /// Stop the worker once its program ends; a program still running is
+    /// left to its timeout
+    fn drop(&mut self) {
+        let (slot, wake) = &*self.shared;
+        slot.lock().unwrap_or_else(|e| e.into_inner()).closed = true;
+        wake.notify_one();
+    }
+}
+
+fn work(
+    mut backend: impl Backend,
+    retries: usize,
+    shared: Shared,
+    started: Instant,
+    outcomes: Sender<Outcome>,
+) {
+    let (slot, wake) = &*shared;
+    let mut failures = 0;
+    let mut retry: Option<Publication> = None;
+    loop {
+        let publication = {
+            let mut slot = slot.lock().unwrap_or_else(|e| e.into_inner());
+            if retry.is_none() {
+                slot = wake
+                    .wait_while(slot, |s| s.queue.is_empty() && !s.closed)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+            if slot.closed {
+                return;
+            }
+            // The next publication, whether or not this is a retry
+            let retried = retry.take();
+            match slot
+                .queue
+                .pop()
+                .map(|queued| queued.publication)
+                .or(retried)
+            {
+                Some(publication) => publication,
+                None => continue,
+            }
+        };
+        let (status, stderr) = backend.deliver(&publication);
+        {
+            let mut slot = slot.lock().unwrap_or_else(|e| e.into_inner());
+            let now = started.elapsed().as_secs_f64();
+            slot.queue.ran(status.succeeded(), now);
+        }
+        let invocation = Invocation {
+            value: publication.value,
+            attempt: failures + 1,
+            status,
+            stderr,
+        };
+        failures = if status.succeeded() { 0 } else { failures + 1 };
+        let gave_up = failures > retries;
+        if outcomes
+            .send(Outcome {
+                invocation,
+                gave_up,
+            })
+            .is_err()
+        {
+            return;
+        }
+        if gave_up {
+            failures = 0;
+        } else if failures > 0 {
+            let slot = slot.lock().unwrap_or_else(|e| e.into_inner());
+            // A newer publication cuts the wait short
+            let _ = wake.wait_timeout_while(slot, RETRY_DELAY, |s| s.queue.is_empty() && !s.closed);
+            retry = Some(publication);
+        }
+    }
+}
+
+/// Run `argv`, given `input` on stdin, killing it after `timeout`: how it
+/// ended and what it wrote to stderr
+fn run(argv: &[String], input: Option<&str>, timeout: Duration) -> (Status, String) {
+    let mut command = Command::new(&argv[0]);
+    command
+        .args(&argv[1..])
+        .stdin(match input {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => return (Status::Error, format!("{}: {}", argv[0], e)),
+    };
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        // A program that does not read its input may close it first
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    let (sender, stderr) = mpsc::channel();
+    if let Some(mut pipe) = child.stderr.take() {
+        std::thread::spawn(move || {
+            let (mut kept, mut chunk) = (Vec::new(), [0; 4096]);
+            // Read it all, so the program never blocks on a full pipe
+            while let Ok(n @ 1..) = pipe.read(&mut chunk) {
+                let room = MAX_STDERR.saturating_sub(kept.len());
+                kept.extend_from_slice(&chunk[..n.min(room)]);
+            }
+            let _ = sender.send(kept);
+        });
+    }
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status.code().map_or(Status::Signalled, Status::Exited),
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break Status::TimedOut;
+            }
+            Ok(None) => std::thread::sleep(POLL),
+            Err(e) => return (Status::Error, format!("{}: {}", argv[0], e)),
+        }
+    };
+    let grace = match status {
+        Status::TimedOut => STDERR_GRACE,
+        _ => timeout.max(STDERR_GRACE),
+    };
+    let kept = stderr.recv_timeout(grace).unwrap_or_default();
+    // A character cut in two at MAX_STDERR reads as a replacement
+    // character, which may take the text past it again
+    let mut text = String::from_utf8_lossy(&kept).trim().to_string();
+    let mut end = text.len().min(MAX_STDERR);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    (status, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn publication(value: f64, band: FanBand) -> Publication {
+        Publication {
+            output: "fan speed".to_string(),
+            value,
+            band,
+            priority: false,
+        }
+    }
+
+    fn argv(template: &str) -> Vec<String> {
+        Template::parse(template)
+            .unwrap()
+            .render(&publication(63.6, FanBand::High))
+    }
+
+    /// The outcomes of `exec` until one satisfies `last`, failing after
+    /// `secs`
+    fn wait_for(exec: &Exec, secs: f64, last: impl Fn(&Outcome) -> bool) -> Vec<Outcome> {
+        let deadline = Instant::now() + Duration::from_secs_f64(secs);
+        let mut outcomes = Vec::new();
+        while Instant::now() < deadline {
+            outcomes.extend(exec.finished());
+            if outcomes.last().is_some_and(&last) {
+                return outcomes;
+            }
+            std::thread::sleep(POLL);
+        }
+        panic!("no such outcome in {}s: {:?}", secs, outcomes);
+    }
+
+    #[test]
+    fn templates_substitute_inside_one_argument() {
+        assert_eq!(
+            argv("/usr/local/bin/set-fan {value}"),
+            ["/usr/local/bin/set-fan", "63.6"]
+        );
+        assert_eq!(
+            argv("set --speed={value} --band {band}"),
+            ["set", "--speed=63.6", "--band", "HIGH"]
+        );
+        // The name's space stays inside its argument, quoted or not
+        assert_eq!(
+            argv("set {output} \"{output}!\""),
+            ["set", "fan speed", "fan speed!"]
+        );
+        // Single quotes take everything as it is; doubled braces are braces
+        assert_eq!(
+            argv("echo '{value} $HOME' {{{value}}} \"a \\\"b\\\"\" c\\ d"),
+            ["echo", "{value} $HOME", "{63.6}", "a \"b\"", "c d"]
+        );
+        assert_eq!(argv("echo \"\" ''"), ["echo", "", ""]);
+
+        let error = |template: &str| Template::parse(template).unwrap_err();
+        assert_eq!(error("  "), "the command is empty");
+        assert_eq!(error("set 'fan"), "unterminated ' quote");
+        assert_eq!(error("set \"fan"), "unterminated \" quote");
+        assert_eq!(error("set {value"), "unterminated placeholder '{value'");
+        assert!(error("set {speed}").starts_with("unknown placeholder '{speed}'"));
+        assert_eq!(error("set }"), "a single } (use }} for a brace)");
+        assert_eq!(error("set \\"), "a \\ ends the command");
+    }
+
+    #[test]
+    fn publications_go_to_stdin_as_json() {
+        assert_eq!(
+            publication(63.6, FanBand::High).to_json(),
+            r#"{"output":"fan speed","value":63.6,"band":"HIGH"}"#
+        );
+        assert_eq!(
+            publication(f64::NAN, FanBand::Off).to_json(),
+            r#"{"output":"fan speed","value":null,"band":"OFF"}"#
+        );
+        let config = ExecConfig {
+            stdin: Stdin::Json,
+            retries: 0,
+            // Fails with what it read, to get it back through stderr
+            ..ExecConfig::new(Template::parse("sh -c 'cat >&2; exit 1'").unwrap())
+        };
+        let mut exec = Exec::new(config);
+        assert!(exec.publish(publication(20.0, FanBand::Low)));
+        let outcomes = wait_for(&exec, 5.0, |_| true);
+        assert_eq!(outcomes[0].invocation.status, Status::Exited(1));
+        assert_eq!(
+            outcomes[0].invocation.stderr,
+            r#"{"output":"fan speed","value":20,"band":"LOW"}"#
+        );
+    }
+
+    #[test]
+    fn the_policy_picks_the_publications_that_run() {
+        let template = Template::parse("true").unwrap();
+        let mut every = Exec::new(ExecConfig::new(template.clone()));
+        let mut changes = Exec::new(ExecConfig {
+            on: Invoke::Change,
+            ..ExecConfig::new(template)
+        });
+        for (value, band, publish, change) in [
+            (10.0, FanBand::Low, true, true),
+            (10.0, FanBand::Low, false, false),
+            (12.0, FanBand::Low, true, false),
+            (50.0, FanBand::Medium, true, true),
+        ] {
+            assert_eq!(every.publish(publication(value, band)), publish);
+            assert_eq!(changes.publish(publication(value, band)), change);
+        }
+    }
+
+    #[test]
+    fn a_hanging_program_is_killed_and_the_newest_publication_runs_next() {
+        let config = ExecConfig {
+            timeout_secs: 0.2,
+            retries: 0,
+            ..ExecConfig::new(Template::parse("sleep {value}").unwrap())
+        };
+        let mut exec = Exec::new(config);
+        let started = Instant::now();
+        exec.publish(publication(30.0, FanBand::Low));
+        // Publishing never waits for the running program
+        std::thread::sleep(Duration::from_millis(50));
+        exec.publish(publication(40.0, FanBand::Low));
+        exec.publish(publication(0.0, FanBand::Off));
+        assert!(started.elapsed() < Duration::from_millis(150));
+        let outcomes = wait_for(&exec, 5.0, |o| o.invocation.value == 0.0);
+        let runs: Vec<(f64, Status)> = (outcomes.iter())
+            .map(|o| (o.invocation.value, o.invocation.status))
+            .collect();
+        // 40 was replaced by 0 while 30 ran
+        assert_eq!(runs, [(30.0, Status::TimedOut), (0.0, Status::Exited(0))]);
+        assert!(outcomes[0].gave_up);
+        assert!(!outcomes[1].gave_up);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn a_program_failing_every_retry_gives_up() {
+        let config = ExecConfig {
+            retries: 2,
+            ..ExecConfig::new(Template::parse("sh -c 'echo no fan >&2; exit 3'").unwrap())
+        };
+        let mut exec = Exec::new(config);
+        exec.publish(publication(50.0, FanBand::Medium));
+        let outcomes = wait_for(&exec, 5.0, |o| o.gave_up);
+        let attempts: Vec<(usize, bool)> = (outcomes.iter())
+            .map(|o| (o.invocation.attempt, o.gave_up))
+            .collect();
+        assert_eq!(attempts, [(1, false), (2, false), (3, true)]);
+        let last = &outcomes[2].invocation;
+        assert_eq!(last.status, Status::Exited(3));
+        assert_eq!(last.stderr, "no fan");
+        assert_eq!(
+            last.describe(),
+            "exec of 50 exited with 3 (attempt 3): no fan"
+        );
+        // Given up, it waits for the next publication
+        std::thread::sleep(RETRY_DELAY * 2);
+        assert_eq!(exec.finished(), []);
+
+        let mut missing = Exec::new(ExecConfig {
+            retries: 0,
+            ..ExecConfig::new(Template::parse("/nonexistent/set-fan {value}").unwrap())
+        });
+        missing.publish(publication(50.0, FanBand::Medium));
+        let outcomes = wait_for(&missing, 5.0, |o| o.gave_up);
+        assert_eq!(outcomes[0].invocation.status, Status::Error);
+        assert!(
+            outcomes[0]
+                .invocation
+                .stderr
+                .starts_with("/nonexistent/set-fan: ")
+        );
+    }
+
+    /// A link that finishes each delivery when the test lets it, with
+    /// success or failure, saying when one starts
+    struct Link {
+        started: Sender<f64>,
+        gate: Receiver<bool>,
+    }
+
+    impl Backend for Link {
+        fn deliver(&mut self, publication: &Publication) -> (Status, String) {
+            let _ = self.started.send(publication.value);
+            match self.gate.recv() {
+                Ok(true) => (Status::Exited(0), String::new()),
+                _ => (Status::Exited(1), "link down".to_string()),
+            }
+        }
+    }
+
+    /// An exec on a `Link`, its start notices and its gate
+    fn linked(config: ExecConfig) -> (Exec, Receiver<f64>, Sender<bool>) {
+        let (started, starts) = mpsc::channel();
+        let (gate, opens) = mpsc::channel();
+        let link = Link {
+            started,
+            gate: opens,
+        };
+        (Exec::start(config, link), starts, gate)
+    }
+
+    fn config() -> ExecConfig {
+        ExecConfig {
+            retries: 0,
+            ..ExecConfig::new(Template::parse("true").unwrap())
+        }
+    }
+
+    #[test]
+    fn a_stalled_link_gets_only_the_newest_publication() {
+        let (mut exec, starts, gate) = linked(config());
+        exec.publish(publication(10.0, FanBand::Low));
+        assert_eq!(starts.recv().unwrap(), 10.0);
+        for value in [20.0, 30.0, 40.0] {
+            exec.publish(publication(value, FanBand::Low));
+        }
+        std::thread::sleep(Duration::from_millis(30));
+        let stats = exec.stats();
+        assert_eq!((stats.coalesced, stats.dropped), (2, 0));
+        assert!(stats.latency >= 0.03 && stats.undelivered > stats.latency);
+
+        gate.send(true).unwrap();
+        assert_eq!(starts.recv().unwrap(), 40.0);
+        gate.send(true).unwrap();
+        let outcomes = wait_for(&exec, 5.0, |o| o.invocation.value == 40.0);
+        let values: Vec<f64> = outcomes.iter().map(|o| o.invocation.value).collect();
+        assert_eq!(values, [10.0, 40.0]);
+        let stats = exec.stats();
+        assert_eq!((stats.latency, stats.undelivered), (0.0, 0.0));
+    }
+
+    #[test]
+    fn a_priority_publication_is_never_coalesced_away() {
+        let (mut exec, starts, gate) = linked(config());
+        exec.publish(publication(10.0, FanBand::Low));
+        assert_eq!(starts.recv().unwrap(), 10.0);
+        exec.publish(publication(20.0, FanBand::Low));
+        exec.publish(Publication {
+            priority: true,
+            ..publication(0.0, FanBand::Off)
+        });
+        // Made after the safe output, so it runs after it
+        exec.publish(publication(30.0, FanBand::Low));
+        assert_eq!((exec.stats().coalesced, exec.stats().dropped), (0, 1));
+
+        let mut order = Vec::new();
+        gate.send(true).unwrap();
+        for _ in 0..2 {
+            order.push(starts.recv().unwrap());
+            gate.send(true).unwrap();
+        }
+        assert_eq!(order, [0.0, 30.0]);
+        wait_for(&exec, 5.0, |o| o.invocation.value == 30.0);
+    }
+
+    #[test]
+    fn nothing_delivered_for_stall_secs_is_a_stall_until_a_delivery() {
+        let (mut exec, starts, gate) = linked(ExecConfig {
+            stall_secs: 0.05,
+            ..config()
+        });
+        exec.publish(publication(10.0, FanBand::Low));
+        starts.recv().unwrap();
+        assert_eq!(exec.stalled(), None);
+        std::thread::sleep(Duration::from_millis(80));
+        assert!(exec.stalled().is_some_and(|secs| secs >= 0.08));
+
+        // A failed run delivers nothing either
+        gate.send(false).unwrap();
+        let outcomes = wait_for(&exec, 5.0, |o| o.gave_up);
+        assert_eq!(outcomes[0].invocation.stderr, "link down");
+        assert!(exec.stalled().is_some());
+
+        exec.publish(publication(20.0, FanBand::Low));
+        starts.recv().unwrap();
+        gate.send(true).unwrap();
+        wait_for(&exec, 5.0, |o| o.invocation.status.succeeded());
+        assert_eq!(exec.stalled(), None);
+    }
+
+    #[test]
+    fn statuses_read_back_from_their_labels() {
+        for status in [
+            Status::Exited(0),
+            Status::Exited(-2),
+            Status::Signalled,
+            Status::TimedOut,
+            Status::Error,
+        ] {
+            assert_eq!(Status::parse(&status.label()), Some(status));
+        }
+        assert_eq!(Status::parse("lost"), None);
+    }
+}
@@ -0,0 +1,558 @@
+// ============================================================================
+// BEHAVIOR SNAPSHOTS - Impressões digitais do comportamento
+// ============================================================================
+//
+// Has the controller's behavior changed since last month? `snapshot`
+// records a fingerprint of the system as it is now, and `snapshot
+// --compare` checks the system against the latest one:
+//
+//     fuzzy_logic snapshot --state FILE [--config FILE]
+//     fuzzy_logic snapshot --state FILE [--config FILE] --compare
+//                          [--tolerance X]
+//
+// The snapshots live next to the state file, in FILE.snapshots.toml, the
+// newest last and at most KEPT of them:
+//
+//     [[snapshot]]
+//     taken = 1760612345.2           # wall-clock epoch seconds, or
+//                                    # SOURCE_DATE_EPOCH (timestamp.rs)
+//     system = "fan controller v1.2" # the metadata summary, if any
+//     rules_hash = "5f0c2e1a"        # CRC-32 of the rule texts
+//     surface_hash = "9b44d013"      # CRC-32 of the outputs below
+//     inputs = ["temperature", "humidity"]
+//     mins = [0.0, 0.0]              # universe of each input
+//     maxs = [50.0, 100.0]
+//     points = 11                    # per input axis
+//     outputs = [0.0, ...]           # first output over the grid
+//     low = 0.0                      # min, mean and max of the outputs
+//     mean = 41.3
+//     high = 100.0
+//
+// The grid is coarse: 11 points per axis up to two inputs, 5 beyond. The
+// comparison evaluates the system at the snapshot's own points, so a
+// widened universe does not move the grid, and finds the largest |Δ
+// output|:
+//
+//     identical              the surface hash matches
+//     within tolerance       no point moved by more than the tolerance,
+//                            1% of the output's universe unless given
+//     materially different   some point moved by more; the region around
+//                            the worst point is reported
+//
+// A system with other inputs than the snapshot's cannot be compared and
+// counts as materially different. Exit codes: 0 identical or within
+// tolerance, 1 materially different, 2 usage or file errors.
+
+use crate::bundle;
+use crate::config::{self, Section};
+use crate::timestamp;
+use crate::{FuzzyController, FuzzySystem};
+
+/// Snapshots kept; taking another drops the oldest
+pub const KEPT: usize = 30;
+/// Default tolerance, as a fraction of the output's universe width
+pub const TOLERANCE_FRACTION: f64 = 0.01;
+
+pub const USAGE: &str =
+    "usage: fuzzy_logic snapshot --state FILE [--config FILE] [--compare [--tolerance X]]";
+
+/// A fingerprint of a system's behavior
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub taken: f64,
+    /// Metadata summary, empty without metadata
+    pub system: String,
+    pub rules_hash: u32,
+    pub surface_hash: u32,
+    pub inputs: Vec<String>,
+    pub mins: Vec<f64>,
+    pub maxs: Vec<f64>,
+    pub points: usize,
+    /// First output at every grid point, the first input varying fastest
+    pub outputs: Vec<f64>,
+    pub low: f64,
+    pub mean: f64,
+    pub high: f64,
+}
+
+/// How the system compares with a snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Identical,
+    WithinTolerance,
+    MateriallyDifferent,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub verdict: Verdict,
+    pub rules_changed: bool,
+    pub max_deviation: f64,
+    pub mean_deviation: f64,
+    /// Input ranges around the worst point, one per input; empty when
+    /// nothing moved or the inputs differ
+    pub region: Vec<(String, f64, f64)>,
+    /// Why the systems could not be compared point by point
+    pub incompatible: Option<String>,
+}
+
+fn points_per_axis(inputs: usize) -> usize {
+    if inputs <= 2 { 11 } else { 5 }
+}
+
+/// The grid point with flat index `index`
+fn grid_point(index: usize, mins: &[f64], maxs: &[f64], points: usize) -> Vec<f64> {
+    let mut rest = index;
+    mins.iter()
+        .zip(maxs)
+        .map(|(min, max)| {
+            let i = rest % points;
+            rest /= points;
+            min + (max - min) * i as f64 / (points - 1) as f64
+        })
+        .collect()
+}
+
+fn hash_values(values: &[f64]) -> u32 {
+    let bytes: Vec<u8> = values
+        .iter()
+        .flat_map(|v| v.to_bits().to_le_bytes())
+        .collect();
+    bundle::crc32(&bytes)
+}
+
+fn hash_rules(system: &FuzzySystem) -> u32 {
+    bundle::crc32(system.rule_texts().join("\n").as_bytes())
+}
+
+impl Snapshot {
+    /// Fingerprint `system` at wall-clock `now`
+    pub fn take(system: &FuzzySystem, now: f64) -> Self {
+        let points = points_per_axis(system.inputs.len());
+        let mins: Vec<f64> = system.inputs.iter().map(|v| v.min).collect();
+        let maxs: Vec<f64> = system.inputs.iter().map(|v| v.max).collect();
+        let controller = FuzzyController::from_system(system.clone());
+        let outputs: Vec<f64> = (0..points.pow(mins.len() as u32))
+            .map(|i| controller.compute(&grid_point(i, &mins, &maxs, points)))
+            .collect();
+        Snapshot {
+            taken: now,
+            system: system.metadata.summary().unwrap_or_default(),
+            rules_hash: hash_rules(system),
+            surface_hash: hash_values(&outputs),
+            inputs: system.inputs.iter().map(|v| v.name.clone()).collect(),
+            low: outputs.iter().copied().fold(f64::INFINITY, f64::min),
+            mean: outputs.iter().sum::<f64>() / outputs.len() as f64,
+            high: outputs.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            mins,
+            maxs,
+            points,
+            outputs,
+        }
+    }
+
+    /// `system` against this snapshot; `tolerance` in output units
+    pub fn compare(&self, system: &FuzzySystem, tolerance: f64) -> Comparison {
+        let rules_changed = hash_rules(system) != self.rules_hash;
+        let mismatch = |reason: String| Comparison {
+            verdict: Verdict::MateriallyDifferent,
+            rules_changed,
+            max_deviation: f64::INFINITY,
+            mean_deviation: f64::INFINITY,
+            region: Vec::new(),
+            incompatible: Some(reason),
+        };
+        // Where each of the snapshot's inputs is in the system now
+        let mut order = Vec::new();
+        for name in &self.inputs {
+            match system.inputs.iter().position(|v| v.name == *name) {
+                Some(i) => order.push(i),
+                None => return mismatch(format!("input '{}' is gone", name)),
+            }
+        }
+        if system.inputs.len() != self.inputs.len() {
+            return mismatch(format!(
+                "the snapshot has {} inputs, the system {}",
+                self.inputs.len(),
+                system.inputs.len()
+            ));
+        }
+
+        let controller = FuzzyController::from_system(system.clone());
+        let mut now = Vec::with_capacity(self.outputs.len());
+        let mut reordered = vec![0.0; order.len()];
+        for i in 0..self.outputs.len() {
+            let point = grid_point(i, &self.mins, &self.maxs, self.points);
+            for (x, at) in point.iter().zip(&order) {
+                reordered[*at] = *x;
+            }
+            now.push(controller.compute(&reordered));
+        }
+        if hash_values(&now) == self.surface_hash {
+            return Comparison {
+                verdict: Verdict::Identical,
+                rules_changed,
+                max_deviation: 0.0,
+                mean_deviation: 0.0,
+                region: Vec::new(),
+                incompatible: None,
+            };
+        }
+
+        let deviations: Vec<f64> = now
+            .iter()
+            .zip(&self.outputs)
+            .map(|(a, b)| (a - b).abs())
+            .collect();
+        let (worst, max_deviation) =
+            deviations
+                .iter()
+                .copied()
+                .enumerate()
+                .fold(
+                    (0, 0.0),
+                    |(wi, wd), (i, d)| if d > wd { (i, d) } else { (wi, wd) },
+                );
+        // One grid step either side of the worst point, within the universe
+        let point = grid_point(worst, &self.mins, &self.maxs, self.points);
+        let region = self
+            .inputs
+            .iter()
+            .zip(point)
+            .zip(self.mins.iter().zip(&self.maxs))
+            .map(|((name, x), (min, max))| {
+                let step = (max - min) / (self.points - 1) as f64;
+                (name.clone(), (x - step).max(*min), (x + step).min(*max))
+            })
+            .collect();
+        Comparison {
+            verdict: if max_deviation > tolerance {
+                Verdict::MateriallyDifferent
+            } else {
+                Verdict::WithinTolerance
+            },
+            rules_changed,
+            max_deviation,
+            mean_deviation: deviations.iter().sum::<f64>() / deviations.len() as f64,
+            region,
+            incompatible: None,
+        }
+    }
+
+    fn to_toml(&self) -> String {
+        let strings: Vec<String> = self.inputs.iter().map(|s| config::quote(s)).collect();
+        format!(
+            "\n[[snapshot]]\ntaken = {:?}\nsystem = {}\nrules_hash = \"{:08x}\"\n\
+             surface_hash = \"{:08x}\"\ninputs = [{}]\nmins = {}\nmaxs = {}\npoints = {}\n\
+             outputs = {}\nlow = {:?}\nmean = {:?}\nhigh = {:?}\n",
+            self.taken,
+            config::quote(&self.system),
+            self.rules_hash,
+            self.surface_hash,
+            strings.join(", "),
+            numbers(&self.mins),
+            numbers(&self.maxs),
+            self.points,
+            numbers(&self.outputs),
+            self.low,
+            self.mean,
+            self.high
+        )
+    }
+}
+
+fn numbers(values: &[f64]) -> String {
+    let items: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn snapshot_from_section(section: &Section) -> Result<Snapshot, String> {
+    let hash = |key: &str| {
+        u32::from_str_radix(section.str(key)?, 16)
+            .map_err(|_| format!("line {}: '{}' must be hexadecimal", section.line, key))
+    };
+    let snapshot = Snapshot {
+        taken: section.num("taken")?,
+        system: section.str("system")?.to_string(),
+        rules_hash: hash("rules_hash")?,
+        surface_hash: hash("surface_hash")?,
+        inputs: section
+            .strs("inputs")?
+            .into_iter()
+            .map(|(s, _)| s)
+            .collect(),
+        mins: section.nums("mins")?,
+        maxs: section.nums("maxs")?,
+        points: section.num("points")? as usize,
+        outputs: section.nums("outputs")?,
+        low: section.num("low")?,
+        mean: section.num("mean")?,
+        high: section.num("high")?,
+    };
+    let dims = snapshot.inputs.len();
+    if snapshot.points < 2
+        || snapshot.mins.len() != dims
+        || snapshot.maxs.len() != dims
+        || snapshot.outputs.len() != snapshot.points.pow(dims as u32)
+    {
+        return Err(format!(
+            "line {}: the snapshot's grid does not match its inputs",
+            section.line
+        ));
+    }
+    Ok(snapshot)
+}
+
+/// Snapshots in the order they were taken
+pub fn parse(text: &str) -> Result<Vec<Snapshot>, String> {
+    config::parse_document(text)?
+        .iter()
+        .filter(|s| s.header == "snapshot")
+        .map(snapshot_from_section)
+        .collect()
+}
+
+pub fn to_toml(snapshots: &[Snapshot]) -> String {
+    let mut out = "# Behavior snapshots (fingerprint.rs), the newest last\n".to_string();
+    for snapshot in snapshots {
+        out.push_str(&snapshot.to_toml());
+    }
+    out
+}
+
+/// Where the snapshots of state file `state` live
+pub fn history_path(state: &str) -> String {
+    format!("{}.snapshots.toml", state)
+}
+
+/// Snapshots at `path`; a missing file has none
+pub fn load(path: &str) -> Result<Vec<Snapshot>, String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => parse(&text).map_err(|e| format!("{}: {}", path, e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("{}: {}", path, e)),
+    }
+}
+
+/// Add `snapshot` to `snapshots`, dropping the oldest beyond KEPT
+pub fn push(snapshots: &mut Vec<Snapshot>, snapshot: Snapshot) {
+    snapshots.push(snapshot);
+    let excess = snapshots.len().saturating_sub(KEPT);
+    snapshots.drain(..excess);
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let mut system = FuzzySystem::demo();
+    let mut state = None;
+    let mut compare = false;
+    let mut tolerance = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let result = match arg.as_str() {
+            "--compare" => {
+                compare = true;
+                Ok(())
+            }
+            "--config" => match iter.next() {
+                Some(path) => config::load_system(path).map(|s| system = s),
+                None => Err(USAGE.to_string()),
+            },
+            "--state" => match iter.next() {
+                Some(path) => {
+                    state = Some(path.clone());
+                    Ok(())
+                }
+                None => Err(USAGE.to_string()),
+            },
+            "--tolerance" => match iter.next().and_then(|v| v.parse::<f64>().ok()) {
+                Some(t) if t >= 0.0 => {
+                    tolerance = Some(t);
+                    Ok(())
+                }
+                _ => Err("--tolerance expects a non-negative number".to_string()),
+            },
+            _ => Err(USAGE.to_string()),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            return 2;
+        }
+    }
+    let Some(state) = state else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+    let path = history_path(&state);
+    let mut snapshots = match load(&path) {
+        Ok(snapshots) => snapshots,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 2;
+        }
+    };
+
+    if !compare {
+        let snapshot = Snapshot::take(&system, timestamp::export_time());
+        println!(
+            "Snapshot of {} taken: surface {:08x}, rules {:08x}, output {:.2} to {:.2} (mean {:.2})",
+            describe(&snapshot.system),
+            snapshot.surface_hash,
+            snapshot.rules_hash,
+            snapshot.low,
+            snapshot.high,
+            snapshot.mean
+        );
+        push(&mut snapshots, snapshot);
+        if let Err(e) = std::fs::write(&path, to_toml(&snapshots)) {
+            eprintln!("error: {}: {}", path, e);
+            return 2;
+        }
+        println!("{} snapshot(s) kept in {}", snapshots.len(), path);
+        return 0;
+    }
+
+    let Some(latest) = snapshots.last() else {
+        eprintln!("error: {}: no snapshot to compare with", path);
+        return 2;
+    };
+    let output = system.output();
+    let tolerance = tolerance.unwrap_or((output.max - output.min) * TOLERANCE_FRACTION);
+    let comparison = latest.compare(&system, tolerance);
+    println!(
+        "Snapshot: {}  {}",
+        timestamp::rfc3339(latest.taken),
+        describe(&latest.system)
+    );
+    println!(
+        "Now:      {}",
+        describe(&system.metadata.summary().unwrap_or_default())
+    );
+    println!(
+        "Rule base: {}",
+        if comparison.rules_changed {
+            "changed"
+        } else {
+            "unchanged"
+        }
+    );
+    if let Some(reason) = &comparison.incompatible {
+        println!("✘ Behavior cannot be compared: {}", reason);
+        return 1;
+    }
+    println!(
+        "max |Δ output|: {:.4}  mean |Δ output|: {:.4}",
+        comparison.max_deviation, comparison.mean_deviation
+    );
+    let region: Vec<String> = comparison
+        .region
+        .iter()
+        .map(|(name, low, high)| format!("{} {:.2} to {:.2}", name, low, high))
+        .collect();
+    match comparison.verdict {
+        Verdict::Identical => {
+            println!("✔ Behavior is identical");
+            0
+        }
+        Verdict::WithinTolerance => {
+            println!(
+                "✔ Behavior changed within tolerance {} (largest around {})",
+                tolerance,
+                region.join(", ")
+            );
+            0
+        }
+        Verdict::MateriallyDifferent => {
+            println!(
+                "✘ Behavior changed materially: max deviation {:.4} exceeds tolerance {}",
+                comparison.max_deviation, tolerance
+            );
+            println!("  largest deviation around {}", region.join(", "));
+            1
+        }
+    }
+}
+
+fn describe(summary: &str) -> &str {
+    if summary.is_empty() {
+        "(no metadata)"
+    } else {
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn an_unchanged_system_is_identical_and_snapshots_round_trip() {
+        let system = fixtures::fan();
+        let snapshot = Snapshot::take(&system, 1790841600.25);
+        assert_eq!(snapshot.outputs.len(), 121);
+        assert!(snapshot.low <= snapshot.mean && snapshot.mean <= snapshot.high);
+
+        let comparison = snapshot.compare(&system, 0.0);
+        assert_eq!(comparison.verdict, Verdict::Identical);
+        assert!(!comparison.rules_changed);
+
+        // A reworded metadata block is not a change of behavior
+        let mut renamed = system.clone();
+        renamed.metadata.version = Some("2".to_string());
+        assert_eq!(snapshot.compare(&renamed, 0.0).verdict, Verdict::Identical);
+
+        let mut snapshots = Vec::new();
+        for i in 0..KEPT + 3 {
+            push(&mut snapshots, Snapshot::take(&system, i as f64));
+        }
+        assert_eq!(snapshots.len(), KEPT);
+        assert_eq!(snapshots[0].taken, 3.0);
+        assert_eq!(parse(&to_toml(&snapshots)).unwrap(), snapshots);
+    }
+
+    #[test]
+    fn a_small_edit_is_within_tolerance_and_a_large_one_is_not() {
+        let system = fixtures::fan();
+        let snapshot = Snapshot::take(&system, 0.0);
+
+        let mut nudged = system.clone();
+        nudged.rules[0].weight = 0.98;
+        let comparison = snapshot.compare(&nudged, 1.0);
+        assert_eq!(
+            comparison.verdict,
+            Verdict::WithinTolerance,
+            "{:?}",
+            comparison
+        );
+        assert!(comparison.rules_changed);
+        assert!(comparison.max_deviation > 0.0 && comparison.max_deviation <= 1.0);
+
+        let mut reworked = system.clone();
+        for rule in &mut reworked.rules {
+            rule.consequents[0].1 = "High".to_string();
+        }
+        let comparison = snapshot.compare(&reworked, 1.0);
+        assert_eq!(comparison.verdict, Verdict::MateriallyDifferent);
+        assert!(comparison.max_deviation > 10.0);
+        // The region is a grid step either side of the worst point
+        let [(name, low, high), _] = &comparison.region[..] else {
+            panic!("{:?}", comparison.region);
+        };
+        assert_eq!(name, "temperature");
+        assert!(high - low <= 2.0 * (system.inputs[0].max - system.inputs[0].min) / 10.0);
+    }
+
+    #[test]
+    fn a_system_with_other_inputs_cannot_be_compared() {
+        let system = fixtures::fan();
+        let snapshot = Snapshot::take(&system, 0.0);
+        let mut renamed = system.clone();
+        renamed.inputs[1].name = "moisture".to_string();
+        let comparison = snapshot.compare(&renamed, 1.0);
+        assert_eq!(comparison.verdict, Verdict::MateriallyDifferent);
+        assert_eq!(
+            comparison.incompatible.as_deref(),
+            Some("input 'humidity' is gone")
+        );
+    }
+}
@@ -0,0 +1,351 @@
+// ============================================================================
+// TEST FIXTURES - Sistemas de teste
+// ============================================================================
+//
+// Small deterministic systems for the tests of crates that embed this one,
+// behind the `test-fixtures` feature:
+//
+//     [dev-dependencies]
+//     fuzzy_logic = { version = "0.1", features = ["test-fixtures"] }
+//
+//     fan()           the built-in fan controller
+//     single_rule()   one input, one rule, an output known in closed form
+//     conflict()      two rules always firing toward opposite ends
+//     linear()        an output linear in its input
+//     broken()        a builder of systems that fail to load, one way each
+//
+// Each documents what it computes at reference points, and this crate's
+// tests check those values and build on the fixtures, so a change that
+// moves them fails here before it reaches anyone downstream.
+// `assert_output_close` and `assert_trace_fired` are the checks those
+// tests need most.
+//
+// The tree has no Sugeno inference (see the README); `linear` is the
+// nearest it has. A weighted average of constant consequents, with
+// complementary ramps for strengths, is a zero-order Sugeno system, and
+// here gives y = 20 + 6x exactly.
+
+use crate::{Evaluation, FuzzyRule, FuzzySystem, FuzzyVariable, MembershipFunction, config};
+
+/// Reference points of `fan`: (temperature, humidity) and the fan speed
+/// the demo computes there, to the last bit
+pub const FAN_REFERENCE: [([f64; 2], f64); 5] = [
+    ([10.0, 30.0], 6.333333333333333),
+    ([18.0, 40.0], 34.80851063829792),
+    ([22.5, 50.0], 50.00000000000001),
+    ([27.0, 65.0], 65.17974322396584),
+    ([35.0, 85.0], 83.66666666666667),
+];
+
+/// How far `single_rule` is from `single_rule_expected` at most, from
+/// sampling the output 1000 times
+pub const SINGLE_RULE_TOLERANCE: f64 = 0.05;
+
+/// The built-in fan controller, `FuzzySystem::demo`
+pub fn fan() -> FuzzySystem {
+    FuzzySystem::demo()
+}
+
+/// One input `x` from 0 to 10 with a rising set High, and one rule
+/// driving a rising output set Up of `y` from 0 to 100: the rule fires at
+/// x / 10, clipping Up, whose centroid `single_rule_expected` gives. At
+/// x = 0 nothing fires and the output is 0.
+pub fn single_rule() -> FuzzySystem {
+    config::parse_system(SINGLE_RULE).expect("the fixture is valid")
+}
+
+const SINGLE_RULE: &str = r#"
+rules = ["IF x IS High THEN y IS Up"]
+
+[[input]]
+name = "x"
+min = 0.0
+max = 10.0
+
+[[input.set]]
+name = "High"
+shape = "triangular"
+params = [0.0, 10.0, 10.0]
+
+[[output]]
+name = "y"
+min = 0.0
+max = 100.0
+resolution = 1000
+
+[[output.set]]
+name = "Up"
+shape = "triangular"
+params = [0.0, 100.0, 100.0]
+"#;
+
+/// The centroid of `single_rule`'s Up clipped at h = x / 10, for x
+/// above 0: 100 (3 - h²) / (3 (2 - h)), 66.67 at x = 10
+pub fn single_rule_expected(x: f64) -> f64 {
+    let h = x / 10.0;
+    100.0 * (3.0 - h * h) / (3.0 * (2.0 - h))
+}
+
+/// Two rules firing fully at every `x` from 0 to 10 with opposite
+/// conclusions, sets of `y` mirrored about 50: the output is 50 (to within
+/// 1e-12) wherever `x` is, and both rules fire
+pub fn conflict() -> FuzzySystem {
+    config::parse_system(CONFLICT).expect("the fixture is valid")
+}
+
+const CONFLICT: &str = r#"
+rules = [
+    "IF x IS Any THEN y IS Low",
+    "IF x IS Any THEN y IS High",
+]
+
+[[input]]
+name = "x"
+min = 0.0
+max = 10.0
+
+[[input.set]]
+name = "Any"
+shape = "trapezoidal"
+params = [0.0, 0.0, 10.0, 10.0]
+
+[[output]]
+name = "y"
+min = 0.0
+max = 100.0
+
+[[output.set]]
+name = "Low"
+shape = "triangular"
+params = [0.0, 25.0, 50.0]
+
+[[output.set]]
+name = "High"
+shape = "triangular"
+params = [50.0, 75.0, 100.0]
+"#;
+
+/// Two complementary ramps of `x` from 0 to 10 driving `y` sets centered
+/// on 20 and 80, defuzzified by weighted average: y = 20 + 6x, exactly at
+/// 0, 2.5, 5 and 10
+pub fn linear() -> FuzzySystem {
+    config::parse_system(LINEAR).expect("the fixture is valid")
+}
+
+const LINEAR: &str = r#"
+rules = [
+    "IF x IS Low THEN y IS Down",
+    "IF x IS High THEN y IS Up",
+]
+
+[[input]]
+name = "x"
+min = 0.0
+max = 10.0
+
+[[input.set]]
+name = "Low"
+shape = "triangular"
+params = [0.0, 0.0, 10.0]
+
+[[input.set]]
+name = "High"
+shape = "triangular"
+params = [0.0, 10.0, 10.0]
+
+[[output]]
+name = "y"
+min = 0.0
+max = 100.0
+method = "weighted_average"
+
+[[output.set]]
+name = "Down"
+shape = "triangular"
+params = [10.0, 20.0, 30.0]
+
+[[output.set]]
+name = "Up"
+shape = "triangular"
+params = [70.0, 80.0, 90.0]
+"#;
+
+/// Builder of a system that fails to load: `single_rule` made from the
+/// public constructors, with whatever is broken
+#[derive(Debug, Clone)]
+pub struct Broken {
+    input: FuzzyVariable,
+    output: FuzzyVariable,
+    rule: String,
+}
+
+/// `single_rule`, as a builder to break; built unbroken it loads
+pub fn broken() -> Broken {
+    let ramp = MembershipFunction::Triangular {
+        a: 0.0,
+        b: 10.0,
+        c: 10.0,
+    };
+    let up = MembershipFunction::Triangular {
+        a: 0.0,
+        b: 100.0,
+        c: 100.0,
+    };
+    Broken {
+        input: FuzzyVariable::new("x", 0.0, 10.0).with_set("High", ramp),
+        output: FuzzyVariable::new("y", 0.0, 100.0).with_set("Up", up),
+        rule: "IF x IS High THEN y IS Up".to_string(),
+    }
+}
+
+impl Broken {
+    /// The rule reads a set `x` lacks: "rule 1: 'x' has no set 'Missing'"
+    pub fn unknown_set(mut self) -> Self {
+        self.rule = "IF x IS Missing THEN y IS Up".to_string();
+        self
+    }
+
+    /// The rule reads an input there is not: "rule 1: unknown input 'z'"
+    pub fn unknown_input(mut self) -> Self {
+        self.rule = "IF z IS High THEN y IS Up".to_string();
+        self
+    }
+
+    /// The rule concludes an output there is not: "rule 1: unknown output
+    /// 'w'"
+    pub fn unknown_output(mut self) -> Self {
+        self.rule = "IF x IS High THEN w IS Up".to_string();
+        self
+    }
+
+    /// The rule does not parse: "rule must start with IF: 'x IS High THEN
+    /// y IS Up'"
+    pub fn unparsable_rule(mut self) -> Self {
+        self.rule = "x IS High THEN y IS Up".to_string();
+        self
+    }
+
+    /// `x` runs from 10 to 10: "variable 'x' has an empty range"
+    pub fn empty_range(mut self) -> Self {
+        self.input = FuzzyVariable {
+            min: 10.0,
+            ..self.input
+        };
+        self
+    }
+
+    /// `x` has no sets: "variable 'x' has no sets"
+    pub fn without_sets(mut self) -> Self {
+        self.input.sets.clear();
+        self
+    }
+
+    /// The system, or why it does not load
+    pub fn build(self) -> Result<FuzzySystem, String> {
+        let rule = FuzzyRule::parse(&self.rule)?;
+        FuzzySystem::new(vec![self.input], vec![self.output], vec![rule])
+    }
+}
+
+/// Assert that `actual` is within `tolerance` of `expected`
+#[track_caller]
+pub fn assert_output_close(actual: f64, expected: f64, tolerance: f64) {
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "output {} is not within {} of {}",
+        actual,
+        tolerance,
+        expected
+    );
+}
+
+/// Assert that rule `rule_idx`, counted from 0 in config order, fired in
+/// `evaluation`
+#[track_caller]
+pub fn assert_trace_fired(evaluation: &Evaluation, rule_idx: usize) {
+    match evaluation.strengths.get(rule_idx) {
+        Some(strength) => assert!(*strength > 0.0, "rule {} did not fire", rule_idx),
+        None => panic!(
+            "no rule {}: the system has {}",
+            rule_idx,
+            evaluation.strengths.len()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FuzzyController;
+
+    #[test]
+    fn the_fixtures_compute_what_they_document() {
+        let fan = FuzzyController::from_system(fan());
+        for (point, expected) in FAN_REFERENCE {
+            assert_eq!(fan.compute(&point), expected, "{:?}", point);
+        }
+
+        let single = FuzzyController::from_system(single_rule());
+        assert_eq!(single.compute(&[0.0]), 0.0);
+        for x in [0.5, 1.0, 2.5, 5.0, 7.5, 9.0, 10.0] {
+            assert_output_close(
+                single.compute(&[x]),
+                single_rule_expected(x),
+                SINGLE_RULE_TOLERANCE,
+            );
+            assert_trace_fired(&single.evaluate(&[x]), 0);
+        }
+        assert_output_close(single_rule_expected(10.0), 200.0 / 3.0, 1e-12);
+
+        let conflict = FuzzyController::from_system(conflict());
+        for x in [0.0, 3.0, 10.0] {
+            let evaluation = conflict.evaluate(&[x]);
+            assert_output_close(evaluation.outputs[0], 50.0, 1e-12);
+            assert_trace_fired(&evaluation, 0);
+            assert_trace_fired(&evaluation, 1);
+        }
+
+        let linear = FuzzyController::from_system(linear());
+        for x in [0.0, 2.5, 5.0, 10.0] {
+            assert_eq!(linear.compute(&[x]), 20.0 + 6.0 * x);
+        }
+        for x in [1.0, 3.3, 9.9] {
+            assert_output_close(linear.compute(&[x]), 20.0 + 6.0 * x, 1e-9);
+        }
+    }
+
+    #[test]
+    fn broken_systems_fail_with_their_documented_errors() {
+        assert_eq!(
+            broken().build().map(|s| s.rules.len()),
+            Ok(single_rule().rules.len())
+        );
+        let cases = [
+            (broken().unknown_set(), "rule 1: 'x' has no set 'Missing'"),
+            (broken().unknown_input(), "rule 1: unknown input 'z'"),
+            (broken().unknown_output(), "rule 1: unknown output 'w'"),
+            (
+                broken().unparsable_rule(),
+                "rule must start with IF: 'x IS High THEN y IS Up'",
+            ),
+            (broken().empty_range(), "variable 'x' has an empty range"),
+            (broken().without_sets(), "variable 'x' has no sets"),
+        ];
+        for (builder, error) in cases {
+            assert_eq!(builder.build().unwrap_err(), error);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "rule 0 did not fire")]
+    fn a_rule_that_did_not_fire_fails_the_assertion() {
+        let single = FuzzyController::from_system(single_rule());
+        assert_trace_fired(&single.evaluate(&[0.0]), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "output 50 is not within 0.1 of 51")]
+    fn an_output_too_far_fails_the_assertion() {
+        assert_output_close(50.0, 51.0, 0.1);
+    }
+}
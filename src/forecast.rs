@@ -0,0 +1,392 @@
+// ============================================================================
+// FORECAST - Prévia ingênua da saída
+// ============================================================================
+//
+// "Where is the fan going if this keeps up?" The TUI's forecast panel ('f')
+// extrapolates every input from its recent readings and runs the controller
+// over the path they would take:
+//
+//     [forecast]             # optional, TUI only
+//     extrapolation = "linear"   # or "hold": the latest reading stays
+//     horizon_secs = 300.0   # how far ahead, above 0
+//     steps = 10             # points computed over the horizon, 1 to 1000
+//     window_secs = 120.0    # readings the trend is fitted to, above 0
+//
+// Each input's trend is the least-squares line through its readings of the
+// last `window_secs`, and its noise the spread of the readings around that
+// line. The uncertainty cone around the predicted output is what the
+// controller gives with each input in turn SIGMAS of its prediction's
+// standard deviation above and below its path, the others on theirs. The
+// deviation grows the further the line is taken from the readings it was
+// fitted to, as a regression's prediction interval does; "hold" keeps it
+// at the readings' own spread. One input at a time keeps the cost at
+// 2·inputs+1 computations a step, and misses what two inputs moving
+// together would do.
+//
+// It is a naive extrapolation: no input is known to follow a line, the
+// output pipeline's rate limit and dwell times are left out, and the
+// controller's rules are all it knows. The TUI labels it so. It computes a
+// forecast on a worker thread when an input moved past its `min_delta`
+// (trigger.rs) since the last one, or the system changed.
+
+use crate::{FuzzyController, adapters};
+
+pub const DEFAULT_HORIZON_SECS: f64 = 300.0;
+pub const DEFAULT_STEPS: usize = 10;
+pub const DEFAULT_WINDOW_SECS: f64 = 120.0;
+pub const MAX_STEPS: usize = 1000;
+/// Standard deviations the cone spans on either side of the path
+pub const SIGMAS: f64 = 2.0;
+
+/// How an input is taken past its latest reading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Extrapolation {
+    /// Along the trend of its recent readings
+    Linear,
+    /// At its latest reading
+    Hold,
+}
+
+impl Extrapolation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Extrapolation::Linear => "linear",
+            Extrapolation::Hold => "hold",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        [Extrapolation::Linear, Extrapolation::Hold]
+            .into_iter()
+            .find(|e| e.name() == name)
+    }
+}
+
+/// `[forecast]` settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForecastConfig {
+    pub extrapolation: Extrapolation,
+    pub horizon_secs: f64,
+    pub steps: usize,
+    pub window_secs: f64,
+}
+
+impl Default for ForecastConfig {
+    fn default() -> Self {
+        ForecastConfig {
+            extrapolation: Extrapolation::Linear,
+            horizon_secs: DEFAULT_HORIZON_SECS,
+            steps: DEFAULT_STEPS,
+            window_secs: DEFAULT_WINDOW_SECS,
+        }
+    }
+}
+
+/// The trend of one input's recent readings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// The latest reading
+    pub latest: f64,
+    /// Where the fitted line is now
+    pub value: f64,
+    /// Change per second
+    pub slope: f64,
+    /// Standard deviation of the readings around the line
+    pub noise: f64,
+    /// Standard deviation of the readings around their mean
+    pub spread: f64,
+    count: usize,
+    /// Mean time of the readings, seconds from now (0 or less)
+    mean_time: f64,
+    /// Sum of the squared distances of their times from `mean_time`
+    time_spread: f64,
+}
+
+impl Estimate {
+    /// The trend of the finite readings in `samples`, (clock time, value)
+    /// oldest first, of the `window_secs` up to `now`; `None` with none
+    pub fn fit(samples: &[(f64, f64)], now: f64, window_secs: f64) -> Option<Self> {
+        let recent: Vec<(f64, f64)> = samples
+            .iter()
+            .filter(|(time, value)| *time >= now - window_secs && *time <= now && value.is_finite())
+            .map(|(time, value)| (time - now, *value))
+            .collect();
+        let (_, latest) = *recent.last()?;
+        let n = recent.len() as f64;
+        let mean_time = recent.iter().map(|(t, _)| t).sum::<f64>() / n;
+        let mean = recent.iter().map(|(_, v)| v).sum::<f64>() / n;
+        let time_spread: f64 = recent.iter().map(|(t, _)| (t - mean_time).powi(2)).sum();
+        let slope = match time_spread > 0.0 {
+            true => {
+                recent
+                    .iter()
+                    .map(|(t, v)| (t - mean_time) * (v - mean))
+                    .sum::<f64>()
+                    / time_spread
+            }
+            false => 0.0,
+        };
+        let value = mean - slope * mean_time;
+        // A line through two points fits them exactly: the noise needs a third
+        let residuals: f64 = (recent.iter())
+            .map(|(t, v)| (v - (value + slope * t)).powi(2))
+            .sum();
+        let noise = match recent.len() > 2 {
+            true => (residuals / (n - 2.0)).sqrt(),
+            false => 0.0,
+        };
+        let deviations: f64 = recent.iter().map(|(_, v)| (v - mean).powi(2)).sum();
+        let spread = match recent.len() > 1 {
+            true => (deviations / (n - 1.0)).sqrt(),
+            false => 0.0,
+        };
+        Some(Estimate {
+            latest,
+            value,
+            slope,
+            noise,
+            spread,
+            count: recent.len(),
+            mean_time,
+            time_spread,
+        })
+    }
+
+    /// The reading `ahead` seconds from now
+    pub fn at(&self, extrapolation: Extrapolation, ahead: f64) -> f64 {
+        match extrapolation {
+            Extrapolation::Linear => self.value + self.slope * ahead,
+            Extrapolation::Hold => self.latest,
+        }
+    }
+
+    /// Standard deviation of the reading `ahead` seconds from now
+    pub fn deviation(&self, extrapolation: Extrapolation, ahead: f64) -> f64 {
+        match extrapolation {
+            Extrapolation::Hold => self.spread,
+            Extrapolation::Linear if self.time_spread > 0.0 => {
+                let n = self.count as f64;
+                let leverage = (ahead - self.mean_time).powi(2) / self.time_spread;
+                self.noise * (1.0 + 1.0 / n + leverage).sqrt()
+            }
+            Extrapolation::Linear => self.noise,
+        }
+    }
+}
+
+/// One point of the predicted path
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// Seconds after the forecast was made
+    pub ahead: f64,
+    /// Raw readings there, in declaration order
+    pub inputs: Vec<f64>,
+    /// The primary output there
+    pub output: f64,
+    /// The cone around it
+    pub low: f64,
+    pub high: f64,
+}
+
+/// The primary output's predicted path
+#[derive(Debug, Clone, PartialEq)]
+pub struct Forecast {
+    /// Clock time it was made at
+    pub made_at: f64,
+    pub extrapolation: Extrapolation,
+    /// One per input, in declaration order
+    pub estimates: Vec<Estimate>,
+    pub steps: Vec<Step>,
+}
+
+/// What `controller` gives over the `config.horizon_secs` after `now`,
+/// each input extrapolated from its readings in `series` (one list of
+/// (clock time, raw reading) per input, in declaration order), or why it
+/// cannot tell
+pub fn predict(
+    controller: &FuzzyController,
+    series: &[Vec<(f64, f64)>],
+    now: f64,
+    config: &ForecastConfig,
+) -> Result<Forecast, String> {
+    let inputs = &controller.system.inputs;
+    if series.len() != inputs.len() {
+        return Err(format!(
+            "{} input series for {} inputs",
+            series.len(),
+            inputs.len()
+        ));
+    }
+    let estimates = inputs
+        .iter()
+        .zip(series)
+        .map(|(var, samples)| {
+            Estimate::fit(samples, now, config.window_secs).ok_or_else(|| {
+                format!(
+                    "no reading of '{}' in the last {}s",
+                    var.name, config.window_secs
+                )
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let output = |raw: &[f64]| {
+        let (values, _) = adapters::convert(inputs, raw);
+        controller.evaluate(&values).outputs[0]
+    };
+    let extrapolation = config.extrapolation;
+    let steps = (1..=config.steps)
+        .map(|k| {
+            let ahead = config.horizon_secs * k as f64 / config.steps as f64;
+            let path: Vec<f64> = (estimates.iter())
+                .map(|e| e.at(extrapolation, ahead))
+                .collect();
+            let center = output(&path);
+            let (mut low, mut high) = (center, center);
+            for (i, estimate) in estimates.iter().enumerate() {
+                let deviation = SIGMAS * estimate.deviation(extrapolation, ahead);
+                if deviation == 0.0 {
+                    continue;
+                }
+                for sign in [-1.0, 1.0] {
+                    let mut moved = path.clone();
+                    moved[i] += sign * deviation;
+                    let value = output(&moved);
+                    // NaN, where no rule fires, widens nothing
+                    low = low.min(value);
+                    high = high.max(value);
+                }
+            }
+            Step {
+                ahead,
+                inputs: path,
+                output: center,
+                low,
+                high,
+            }
+        })
+        .collect();
+    Ok(Forecast {
+        made_at: now,
+        extrapolation,
+        estimates,
+        steps,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// `values` one every `every` seconds, the last at `now`
+    fn scripted(values: &[f64], every: f64, now: f64) -> Vec<(f64, f64)> {
+        let first = now - every * (values.len() - 1) as f64;
+        (values.iter().enumerate())
+            .map(|(i, v)| (first + every * i as f64, *v))
+            .collect()
+    }
+
+    #[test]
+    fn trends_are_fitted_to_the_window_only() {
+        // Rising 0.5 a second, after an old reading far off the line
+        let mut samples = vec![(0.0, 90.0)];
+        samples.extend(scripted(&[20.0, 25.0, 30.0, 35.0], 10.0, 1000.0));
+        let estimate = Estimate::fit(&samples, 1000.0, 60.0).unwrap();
+        assert!((estimate.slope - 0.5).abs() < 1e-12);
+        assert!((estimate.value - 35.0).abs() < 1e-9);
+        assert!(estimate.noise.abs() < 1e-9);
+        assert_eq!(estimate.at(Extrapolation::Linear, 60.0), 65.0);
+        assert_eq!(estimate.at(Extrapolation::Hold, 60.0), 35.0);
+
+        // Jumping around a level: no trend, but noise
+        let samples = scripted(&[50.0, 54.0, 50.0, 54.0, 50.0, 54.0], 5.0, 100.0);
+        let estimate = Estimate::fit(&samples, 100.0, 60.0).unwrap();
+        assert!(estimate.slope.abs() < 0.2);
+        assert!(estimate.noise > 1.5);
+        // The further out, the less the line is to be trusted
+        let near = estimate.deviation(Extrapolation::Linear, 10.0);
+        let far = estimate.deviation(Extrapolation::Linear, 300.0);
+        assert!(near > estimate.noise && far > 2.0 * near);
+        assert_eq!(
+            estimate.deviation(Extrapolation::Hold, 300.0),
+            estimate.spread
+        );
+
+        let one = Estimate::fit(&[(99.0, 21.0), (100.0, f64::NAN)], 100.0, 60.0).unwrap();
+        assert_eq!((one.value, one.slope, one.noise), (21.0, 0.0, 0.0));
+        assert_eq!(Estimate::fit(&[(0.0, 21.0)], 100.0, 60.0), None);
+    }
+
+    #[test]
+    fn a_rising_temperature_is_followed_to_the_horizon() {
+        let controller = FuzzyController::from_system(fixtures::fan());
+        // Warming 1°C a minute at a steady 60 %
+        let temperatures: Vec<f64> = (0..7).map(|i| 20.0 + i as f64 / 6.0).collect();
+        let series = vec![
+            scripted(&temperatures, 10.0, 500.0),
+            scripted(&[60.0; 7], 10.0, 500.0),
+        ];
+        let config = ForecastConfig {
+            horizon_secs: 600.0,
+            steps: 5,
+            ..ForecastConfig::default()
+        };
+        let forecast = predict(&controller, &series, 500.0, &config).unwrap();
+        let aheads: Vec<f64> = forecast.steps.iter().map(|s| s.ahead).collect();
+        assert_eq!(aheads, [120.0, 240.0, 360.0, 480.0, 600.0]);
+        let last = &forecast.steps[4];
+        assert!((last.inputs[0] - 31.0).abs() < 1e-9, "{:?}", last.inputs);
+        let expected = controller.evaluate(&last.inputs).outputs[0];
+        assert_eq!(last.output, expected);
+        assert!(forecast.steps[0].output < last.output);
+        // Readings right on their lines leave no cone
+        assert!(
+            forecast
+                .steps
+                .iter()
+                .all(|s| s.low == s.output && s.high == s.output)
+        );
+
+        let held = predict(
+            &controller,
+            &series,
+            500.0,
+            &ForecastConfig {
+                extrapolation: Extrapolation::Hold,
+                ..config.clone()
+            },
+        )
+        .unwrap();
+        let now = controller.evaluate(&[21.0, 60.0]).outputs[0];
+        assert!(held.steps.iter().all(|s| s.output == now));
+    }
+
+    #[test]
+    fn noisy_readings_open_a_cone_around_the_path() {
+        let controller = FuzzyController::from_system(fixtures::fan());
+        let series = vec![
+            scripted(&[27.0, 29.0, 27.0, 29.0, 27.0, 29.0], 10.0, 60.0),
+            scripted(&[65.0; 6], 10.0, 60.0),
+        ];
+        let forecast = predict(&controller, &series, 60.0, &ForecastConfig::default()).unwrap();
+        let widths: Vec<f64> = forecast.steps.iter().map(|s| s.high - s.low).collect();
+        assert!(
+            forecast
+                .steps
+                .iter()
+                .all(|s| s.low <= s.output && s.output <= s.high)
+        );
+        assert!(widths[0] > 0.0);
+        assert!(widths.last().unwrap() >= &widths[0], "{:?}", widths);
+
+        assert_eq!(
+            predict(&controller, &series[..1], 60.0, &ForecastConfig::default()),
+            Err("1 input series for 2 inputs".to_string())
+        );
+        let stale = vec![series[0].clone(), vec![(-500.0, 65.0)]];
+        assert_eq!(
+            predict(&controller, &stale, 60.0, &ForecastConfig::default()),
+            Err("no reading of 'humidity' in the last 120s".to_string())
+        );
+    }
+}
@@ -0,0 +1,632 @@
+// ============================================================================
+// SENSOR FUSION - Fusão de vários sensores numa entrada
+// ============================================================================
+//
+// A room with two temperature sensors is better served by one value both
+// agree on than by whichever spoke last. An input with sources takes its
+// readings from them instead, each routed to the daemon as
+// `set <input>.<source> <value>` (remote.rs):
+//
+//     [[input]]
+//     name = "temperature"
+//     fusion = "median"      # optional: "mean" (default), "median", "min",
+//                            # "max" or "weighted" (by trust)
+//     max_spread = 2.0       # optional, see disagreement below
+//
+//     [[input.source]]       # one per sensor, at least one
+//     name = "north"         # letters, digits, '_' and '-'
+//     trust = 2.0            # optional weight under "weighted", above 0
+//     min = 5.0              # optional plausible range, in the input's
+//     max = 40.0             # stored units
+//     max_rate = 0.5         # optional, most it may change per second
+//     timeout_secs = 10.0    # optional, silence before it counts as failed
+//
+// Every reading of a source is checked against its plausible range and,
+// from its previous reading on, its rate of change. A source failing a
+// check is excluded and its alarm raised (`alarms` on the daemon); it
+// joins again after RECOVER readings in a row pass every check. A source
+// that has not reported for `timeout_secs` is excluded the same way, and a
+// source that never reported waits without an alarm.
+//
+// With `max_spread`, three or more healthy sources may outvote one: while
+// they spread wider than it, the one furthest from their median is
+// excluded as disagreeing, the first declared of those within the
+// controller's tie tolerance of the furthest (ties.rs). Two sources cannot tell which of them is wrong,
+// so both stay and the strategy decides.
+//
+// The fused value is the strategy over the latest reading of each healthy
+// source, so the input runs on whichever remain, down to one. At zero it
+// gets no reading: a windowed input's windows go empty and its stale-input
+// policy takes over (window.rs), and any other input reads NaN, firing
+// none of its rules like a failed sensor. Fusion comes first: the fused
+// value is what the input's window, range policy and adapter see.
+
+use crate::ties;
+
+/// Plausible readings in a row an excluded source needs to join again
+pub const RECOVER: usize = 3;
+
+/// How the healthy sources' readings make the input's
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Strategy {
+    #[default]
+    Mean,
+    Median,
+    Min,
+    Max,
+    /// Mean weighted by each source's trust
+    Weighted,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 5] = [
+        Strategy::Mean,
+        Strategy::Median,
+        Strategy::Min,
+        Strategy::Max,
+        Strategy::Weighted,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::Mean => "mean",
+            Strategy::Median => "median",
+            Strategy::Min => "min",
+            Strategy::Max => "max",
+            Strategy::Weighted => "weighted",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.name() == name)
+    }
+
+    /// The reading of `(value, trust)` pairs, none when there are none
+    pub fn apply(&self, readings: &[(f64, f64)]) -> Option<f64> {
+        if readings.is_empty() {
+            return None;
+        }
+        let mut values: Vec<f64> = readings.iter().map(|(value, _)| *value).collect();
+        values.sort_by(f64::total_cmp);
+        let n = values.len();
+        Some(match self {
+            Strategy::Mean => values.iter().sum::<f64>() / n as f64,
+            Strategy::Median => median(&values),
+            Strategy::Min => values[0],
+            Strategy::Max => values[n - 1],
+            Strategy::Weighted => {
+                let weights: f64 = readings.iter().map(|(_, trust)| trust).sum();
+                readings
+                    .iter()
+                    .map(|(value, trust)| value * trust)
+                    .sum::<f64>()
+                    / weights
+            }
+        })
+    }
+}
+
+/// Median of sorted `values`, which are not empty
+fn median(values: &[f64]) -> f64 {
+    let n = values.len();
+    if n.is_multiple_of(2) {
+        (values[n / 2 - 1] + values[n / 2]) / 2.0
+    } else {
+        values[n / 2]
+    }
+}
+
+/// One `[[input.source]]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceConfig {
+    pub name: String,
+    pub trust: f64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Most the reading may change per second
+    pub max_rate: Option<f64>,
+    pub timeout_secs: Option<f64>,
+}
+
+impl SourceConfig {
+    pub fn new(name: &str) -> Self {
+        SourceConfig {
+            name: name.to_string(),
+            trust: 1.0,
+            min: None,
+            max: None,
+            max_rate: None,
+            timeout_secs: None,
+        }
+    }
+}
+
+/// Whether `name` may name a source: it goes into commands and state lines
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// An input's fusion settings
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FusionConfig {
+    pub strategy: Strategy,
+    /// Widest spread of three or more healthy sources before one is
+    /// excluded as disagreeing
+    pub max_spread: Option<f64>,
+    pub sources: Vec<SourceConfig>,
+}
+
+/// Where a source stands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    /// No reading yet
+    Waiting,
+    Healthy,
+    OutOfRange,
+    TooFast,
+    Disagrees,
+    Silent,
+}
+
+impl Status {
+    pub const ALL: [Status; 6] = [
+        Status::Waiting,
+        Status::Healthy,
+        Status::OutOfRange,
+        Status::TooFast,
+        Status::Disagrees,
+        Status::Silent,
+    ];
+
+    /// One word for state lines and the TUI's popup
+    pub fn label(&self) -> &'static str {
+        match self {
+            Status::Waiting => "waiting",
+            Status::Healthy => "ok",
+            Status::OutOfRange => "range",
+            Status::TooFast => "rate",
+            Status::Disagrees => "disagrees",
+            Status::Silent => "silent",
+        }
+    }
+
+    pub fn parse(label: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.label() == label)
+    }
+
+    /// A few words for the TUI's messages and popup
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Status::Waiting => "waiting for a reading",
+            Status::Healthy => "healthy",
+            Status::OutOfRange => "out of range",
+            Status::TooFast => "changing too fast",
+            Status::Disagrees => "disagreeing with the others",
+            Status::Silent => "silent",
+        }
+    }
+
+    /// Whether the source is left out with its alarm raised
+    pub fn is_excluded(&self) -> bool {
+        !matches!(self, Status::Waiting | Status::Healthy)
+    }
+}
+
+/// A source excluded or joining again
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub source: String,
+    /// Why it was excluded; none when it joined again
+    pub excluded: Option<String>,
+}
+
+/// What the state lines and the TUI's popup say of a source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reported {
+    pub input: String,
+    pub source: String,
+    /// Its latest reading, NaN before the first
+    pub value: f64,
+    pub status: Status,
+}
+
+impl Reported {
+    /// `temperature.north` as the source is routed
+    pub fn route(&self) -> String {
+        format!("{}.{}", self.input, self.source)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Source {
+    config: SourceConfig,
+    /// Clock time and value of its latest reading
+    last: Option<(f64, f64)>,
+    status: Status,
+    /// Why it is excluded
+    reason: String,
+    /// Plausible readings in a row while excluded
+    passes: usize,
+}
+
+/// Fuses an input's sources on a clock the caller passes in
+#[derive(Debug, Clone)]
+pub struct Fusion {
+    strategy: Strategy,
+    max_spread: Option<f64>,
+    /// Distances from the median this close tie
+    tolerance: f64,
+    sources: Vec<Source>,
+}
+
+impl Fusion {
+    /// The sources of `config`, distances from their median within
+    /// `tolerance` of each other counting as equal
+    pub fn new(config: FusionConfig, tolerance: f64) -> Self {
+        Fusion {
+            strategy: config.strategy,
+            max_spread: config.max_spread,
+            tolerance,
+            sources: config
+                .sources
+                .into_iter()
+                .map(|config| Source {
+                    config,
+                    last: None,
+                    status: Status::Waiting,
+                    reason: String::new(),
+                    passes: 0,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn has_source(&self, name: &str) -> bool {
+        self.sources.iter().any(|s| s.config.name == name)
+    }
+
+    /// File `value` of source `name`, arriving at clock time `now`,
+    /// returning the sources excluded or joining again. Unknown names
+    /// change nothing.
+    pub fn push(&mut self, name: &str, now: f64, value: f64) -> Vec<Change> {
+        let Some(index) = self.sources.iter().position(|s| s.config.name == name) else {
+            return Vec::new();
+        };
+        let mut changes = Vec::new();
+        let failure = self.check(index, now, value);
+        let source = &mut self.sources[index];
+        source.last = Some((now, value));
+        match failure {
+            Some((status, reason)) => {
+                source.passes = 0;
+                source.reason = reason.clone();
+                if !source.status.is_excluded() {
+                    changes.push(Change {
+                        source: name.to_string(),
+                        excluded: Some(reason),
+                    });
+                }
+                source.status = status;
+            }
+            None if source.status.is_excluded() => {
+                source.passes += 1;
+                if source.passes >= RECOVER {
+                    source.status = Status::Healthy;
+                    source.passes = 0;
+                    source.reason.clear();
+                    changes.push(Change {
+                        source: name.to_string(),
+                        excluded: None,
+                    });
+                }
+            }
+            None => source.status = Status::Healthy,
+        }
+        changes.extend(self.outvote());
+        changes
+    }
+
+    /// Exclude the sources silent for longer than their timeout by `now`
+    pub fn poll(&mut self, now: f64) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for source in &mut self.sources {
+            let (Some(timeout), Some((then, _))) = (source.config.timeout_secs, source.last) else {
+                continue;
+            };
+            if source.status == Status::Silent || now - then < timeout {
+                continue;
+            }
+            let reason = format!("no reading for {}s", now - then);
+            if !source.status.is_excluded() {
+                changes.push(Change {
+                    source: source.config.name.clone(),
+                    excluded: Some(reason.clone()),
+                });
+            }
+            source.status = Status::Silent;
+            source.reason = reason;
+            source.passes = 0;
+        }
+        changes
+    }
+
+    /// The failed check of reading `value` of source `index`, if any
+    fn check(&self, index: usize, now: f64, value: f64) -> Option<(Status, String)> {
+        let source = &self.sources[index];
+        let config = &source.config;
+        if !value.is_finite() {
+            return Some((Status::OutOfRange, format!("{} is not a reading", value)));
+        }
+        if let Some(min) = config.min.filter(|min| value < *min) {
+            return Some((Status::OutOfRange, format!("{} is below {}", value, min)));
+        }
+        if let Some(max) = config.max.filter(|max| value > *max) {
+            return Some((Status::OutOfRange, format!("{} is above {}", value, max)));
+        }
+        if let (Some(max_rate), Some((then, last))) = (config.max_rate, source.last)
+            && now > then
+        {
+            let rate = (value - last).abs() / (now - then);
+            if rate > max_rate {
+                return Some((
+                    Status::TooFast,
+                    format!("changing {:.3}/s, more than {}/s", rate, max_rate),
+                ));
+            }
+        }
+        // An excluded source rejoins only if it agrees with the others
+        if source.status.is_excluded()
+            && let Some(max_spread) = self.max_spread
+        {
+            let others: Vec<f64> = self.healthy().map(|(_, s)| s).collect();
+            if others.len() >= 2 {
+                let mut sorted = others;
+                sorted.sort_by(f64::total_cmp);
+                let off = (value - median(&sorted)).abs();
+                if off > max_spread {
+                    return Some((
+                        Status::Disagrees,
+                        format!("{:.3} off the others' median", off),
+                    ));
+                }
+            }
+        }
+        None
+    }
+
+    /// Index and latest reading of each healthy source
+    fn healthy(&self) -> impl Iterator<Item = (usize, f64)> + '_ {
+        self.sources
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.status == Status::Healthy)
+            .filter_map(|(i, s)| s.last.map(|(_, value)| (i, value)))
+    }
+
+    /// Exclude the source furthest from the median while three or more
+    /// healthy ones spread wider than `max_spread`
+    fn outvote(&mut self) -> Vec<Change> {
+        let mut changes = Vec::new();
+        let Some(max_spread) = self.max_spread else {
+            return changes;
+        };
+        loop {
+            let healthy: Vec<(usize, f64)> = self.healthy().collect();
+            if healthy.len() < 3 {
+                return changes;
+            }
+            let mut sorted: Vec<f64> = healthy.iter().map(|(_, value)| *value).collect();
+            sorted.sort_by(f64::total_cmp);
+            if sorted[sorted.len() - 1] - sorted[0] <= max_spread {
+                return changes;
+            }
+            let middle = median(&sorted);
+            // The first declared of sources equally far off
+            let offsets = healthy.iter().map(|(_, value)| (value - middle).abs());
+            let (index, value) = ties::greatest(offsets, self.tolerance)
+                .map(|furthest| healthy[furthest])
+                .expect("three or more healthy sources");
+            let reason = format!("{:.3} off the others' median", (value - middle).abs());
+            let source = &mut self.sources[index];
+            source.status = Status::Disagrees;
+            source.reason = reason.clone();
+            source.passes = 0;
+            changes.push(Change {
+                source: source.config.name.clone(),
+                excluded: Some(reason),
+            });
+        }
+    }
+
+    /// The fused reading of the healthy sources, none when there are none
+    pub fn value(&self) -> Option<f64> {
+        let readings: Vec<(f64, f64)> = self
+            .healthy()
+            .map(|(i, value)| (value, self.sources[i].config.trust))
+            .collect();
+        self.strategy.apply(&readings)
+    }
+
+    /// Why source `name` is excluded, if it is
+    pub fn reason(&self, name: &str) -> Option<&str> {
+        self.sources
+            .iter()
+            .find(|s| s.config.name == name && s.status.is_excluded())
+            .map(|s| s.reason.as_str())
+    }
+
+    /// Every source of `input`, in config order
+    pub fn report(&self, input: &str) -> Vec<Reported> {
+        self.sources
+            .iter()
+            .map(|s| Reported {
+                input: input.to_string(),
+                source: s.config.name.clone(),
+                value: s.last.map_or(f64::NAN, |(_, value)| value),
+                status: s.status,
+            })
+            .collect()
+    }
+
+    /// `north=23.1 south=23.4 east=80(range)`, for log lines
+    pub fn describe(&self) -> String {
+        let sources: Vec<String> = self
+            .sources
+            .iter()
+            .map(|s| {
+                let value = s
+                    .last
+                    .map_or("-".to_string(), |(_, value)| value.to_string());
+                match s.status {
+                    Status::Healthy => format!("{}={}", s.config.name, value),
+                    status => format!("{}={}({})", s.config.name, value, status.label()),
+                }
+            })
+            .collect();
+        sources.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fusion(strategy: Strategy, max_spread: Option<f64>, names: &[&str]) -> Fusion {
+        Fusion::new(
+            FusionConfig {
+                strategy,
+                max_spread,
+                sources: names
+                    .iter()
+                    .map(|name| SourceConfig {
+                        min: Some(-10.0),
+                        max: Some(50.0),
+                        max_rate: Some(1.0),
+                        timeout_secs: Some(10.0),
+                        ..SourceConfig::new(name)
+                    })
+                    .collect(),
+            },
+            crate::ties::TOLERANCE,
+        )
+    }
+
+    fn status(fusion: &Fusion, name: &str) -> Status {
+        let report = fusion.report("temperature");
+        report.iter().find(|r| r.source == name).unwrap().status
+    }
+
+    #[test]
+    fn each_strategy_fuses_the_healthy_readings() {
+        let readings = [(20.0, 1.0), (22.0, 3.0), (27.0, 1.0)];
+        let value = |s: Strategy| s.apply(&readings).unwrap();
+        assert_eq!(value(Strategy::Mean), 23.0);
+        assert_eq!(value(Strategy::Median), 22.0);
+        assert_eq!(value(Strategy::Min), 20.0);
+        assert_eq!(value(Strategy::Max), 27.0);
+        assert_eq!(value(Strategy::Weighted), 22.6);
+        assert_eq!(Strategy::Median.apply(&[(1.0, 1.0), (2.0, 1.0)]), Some(1.5));
+        assert_eq!(Strategy::Mean.apply(&[]), None);
+        for strategy in Strategy::ALL {
+            assert_eq!(Strategy::parse(strategy.name()), Some(strategy));
+        }
+        for status in Status::ALL {
+            assert_eq!(Status::parse(status.label()), Some(status));
+        }
+    }
+
+    #[test]
+    fn a_disagreeing_source_is_outvoted_and_two_keep_each_other() {
+        let mut three = fusion(Strategy::Mean, Some(2.0), &["a", "b", "c"]);
+        assert_eq!(three.value(), None);
+        assert!(three.push("a", 0.0, 21.0).is_empty());
+        assert!(three.push("b", 0.0, 22.0).is_empty());
+        assert_eq!(status(&three, "c"), Status::Waiting);
+        assert_eq!(three.value(), Some(21.5));
+        let changes = three.push("c", 0.0, 26.0);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].source, "c");
+        assert_eq!(status(&three, "c"), Status::Disagrees);
+        assert_eq!(three.reason("c"), Some("4.000 off the others' median"));
+        assert_eq!(three.value(), Some(21.5));
+        assert_eq!(three.describe(), "a=21 b=22 c=26(disagrees)");
+
+        // Two sources cannot outvote each other
+        let mut two = fusion(Strategy::Median, Some(2.0), &["a", "b"]);
+        two.push("a", 0.0, 21.0);
+        assert!(two.push("b", 0.0, 26.0).is_empty());
+        assert_eq!(two.value(), Some(23.5));
+
+        // c is further off than a by less than the tolerance: a tie, and
+        // the source declared first goes
+        let outvoted = |tolerance: f64| {
+            let mut three = fusion(Strategy::Mean, Some(2.0), &["a", "b", "c"]);
+            three.tolerance = tolerance;
+            three.push("a", 0.0, 20.0);
+            three.push("b", 0.0, 22.0);
+            three.push("c", 0.0, 24.0 + 1e-12)[0].source.clone()
+        };
+        assert_eq!(outvoted(crate::ties::TOLERANCE), "a");
+        assert_eq!(outvoted(0.0), "c");
+    }
+
+    #[test]
+    fn implausible_and_silent_sources_drop_out_down_to_none() {
+        let mut fusion = fusion(Strategy::Weighted, None, &["a", "b"]);
+        fusion.push("a", 0.0, 20.0);
+        fusion.push("b", 0.0, 22.0);
+        // Out of range, then too fast for its own last reading
+        let changes = fusion.push("a", 1.0, 80.0);
+        assert_eq!(
+            changes,
+            vec![Change {
+                source: "a".to_string(),
+                excluded: Some("80 is above 50".to_string()),
+            }]
+        );
+        assert_eq!(fusion.value(), Some(22.0));
+        assert!(fusion.push("a", 2.0, 20.0).is_empty());
+        assert_eq!(status(&fusion, "a"), Status::TooFast);
+        assert_eq!(fusion.reason("a"), Some("changing 60.000/s, more than 1/s"));
+
+        // The other goes quiet past its timeout
+        assert!(fusion.poll(9.0).is_empty());
+        let changes = fusion.poll(10.0);
+        assert_eq!(changes[0].source, "b");
+        assert_eq!(status(&fusion, "b"), Status::Silent);
+        assert_eq!(fusion.value(), None);
+        assert!(fusion.poll(20.0).is_empty(), "excluded once");
+    }
+
+    #[test]
+    fn an_excluded_source_recovers_after_plausible_readings() {
+        let mut fusion = fusion(Strategy::Mean, Some(2.0), &["a", "b", "c"]);
+        for (name, value) in [("a", 20.0), ("b", 20.5), ("c", 21.0)] {
+            fusion.push(name, 0.0, value);
+        }
+        fusion.push("c", 1.0, 60.0);
+        assert_eq!(status(&fusion, "c"), Status::OutOfRange);
+        // Back in range, but too far off the others to count
+        fusion.push("c", 60.0, 25.0);
+        assert_eq!(status(&fusion, "c"), Status::Disagrees);
+        for (i, now) in [70.0, 80.0, 90.0].into_iter().enumerate() {
+            let changes = fusion.push("c", now, 20.8);
+            assert_eq!(changes.is_empty(), i < RECOVER - 1);
+        }
+        assert_eq!(status(&fusion, "c"), Status::Healthy);
+        assert_eq!(fusion.reason("c"), None);
+        assert!((fusion.value().unwrap() - 20.433333333333334).abs() < 1e-9);
+
+        // Silent sources rejoin the same way once they speak again
+        assert_eq!(fusion.poll(95.0).len(), 2);
+        assert_eq!(fusion.value(), Some(20.8));
+        for now in [96.0, 97.0, 98.0] {
+            fusion.push("a", now, 20.0);
+        }
+        assert!((fusion.value().unwrap() - 20.4).abs() < 1e-9);
+        assert!(fusion.push("z", 99.0, 1.0).is_empty());
+    }
+}
@@ -0,0 +1,623 @@
+// ============================================================================
+// FUZZ - Fuzzing dos leitores
+// ============================================================================
+//
+// Every reader of text someone else wrote, a config, a rule, an alarm
+// condition, a rule CSV, a daemon command, must answer a malformed input
+// with an error, never a panic or a hang. `fuzz` throws mutated inputs at
+// each of them, in process, and stops at the first that breaks one:
+//
+//     fuzzy_logic fuzz [--target NAME] [--secs N] [--seed S] [--timeout SECS]
+//
+// A target is a reader and the inputs it starts from, valid ones out of
+// the repository's configs and tests. Each input is one of those with a
+// few mutations: characters replaced, dropped or inserted, a span
+// repeated, which is how nesting grows, a token of the reader's own
+// (`[`, `(`, `IF`, `holds_for(`...) dropped in, or the tail of another
+// input spliced on. The run is repeatable from its seed, `--secs` (10 by
+// default) is spent on every target, or on the one named.
+//
+// A panic, or an input still running after `--timeout` seconds (5 by
+// default), ends the run with exit code 1 and prints the input as a Rust
+// string, ready for the regression list in this module's tests. A stack
+// overflow aborts the whole process instead, which is why the readers
+// limit the size and nesting of what they accept (config.rs, alarm.rs,
+// cosim.rs).
+//
+// The targets are
+//
+//     config       config::parse_system, rules and conditions included
+//     rule         one `IF ... THEN ...` rule
+//     expression   an alarm condition against the demo system, evaluated
+//     rulecsv      a rule CSV imported into the demo system
+//     scenario     a scenario file (scenario.rs)
+//     state        a state file's rule usage (state.rs)
+//     number       a number typed by a person (numbers.rs)
+//     inject       a failure injection command (inject.rs)
+//     audit        an audit trail line (audit.rs)
+//     cosim        a co-simulation request's JSON (cosim.rs), written back
+//                  out and read again
+//
+// inject and audit come with the tui and daemon features, cosim with its
+// own. The daemon has no other parser of its own: its commands are words
+// split on whitespace, their values numbers and injections, on lines of at
+// most MAX_LINE bytes (remote.rs).
+
+use crate::alarm::{Expression, Sample};
+use crate::{FuzzyRule, FuzzySystem, config, numbers, rulecsv, scenario, state};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const USAGE: &str =
+    "Usage: fuzzy_logic fuzz [--target NAME] [--secs N] [--seed S] [--timeout SECS]";
+/// Inputs grow no longer than this; past it a mutation only shrinks them
+const MAX_INPUT: usize = 64 * 1024;
+/// Mutations applied to a starting input
+const MAX_MUTATIONS: usize = 8;
+
+const FAN: &str = include_str!("../configs/fan.toml");
+
+/// A reader under test
+pub struct Target {
+    pub name: &'static str,
+    /// Valid inputs to start from
+    seeds: fn() -> Vec<String>,
+    /// Tokens of the reader's grammar worth dropping in
+    dictionary: &'static [&'static str],
+    /// Read `input`; errors are fine, panics are not
+    read: fn(&str),
+}
+
+fn strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+fn read_config(input: &str) {
+    let _ = config::parse_system(input);
+}
+
+fn read_rule(input: &str) {
+    let _ = FuzzyRule::parse(input);
+}
+
+fn read_expression(input: &str) {
+    let system = FuzzySystem::demo();
+    if let Ok(expression) = Expression::parse(input, &system) {
+        let sample = Sample {
+            system: &system,
+            inputs: &[30.0, 60.0],
+            outputs: &[55.0],
+            strengths: &[0.5; 9],
+        };
+        let mut memory = Vec::new();
+        for now in [0.0, 30.0, 900.0] {
+            expression.evaluate(&sample, now, &mut memory);
+        }
+    }
+}
+
+fn read_rulecsv(input: &str) {
+    let _ = rulecsv::import(input, &FuzzySystem::demo());
+}
+
+fn read_scenario(input: &str) {
+    let _ = scenario::parse(input);
+}
+
+fn read_state(input: &str) {
+    let _ = state::RuleUsageStats::parse(input, FuzzySystem::demo().rule_texts());
+}
+
+fn read_number(input: &str) {
+    for locale in [
+        numbers::Locale::Auto,
+        numbers::Locale::Point,
+        numbers::Locale::Comma,
+    ] {
+        let _ = numbers::parse(input, locale);
+    }
+}
+
+#[cfg(any(feature = "tui", feature = "daemon"))]
+fn read_inject(input: &str) {
+    let system = FuzzySystem::demo();
+    let _ = crate::inject::Injector::default().command(input, &system.inputs, 0.0);
+}
+
+#[cfg(feature = "daemon")]
+fn read_audit(input: &str) {
+    if let Ok(record) = crate::audit::Record::parse(input) {
+        record.describe();
+    }
+}
+
+#[cfg(feature = "cosim")]
+fn read_cosim(input: &str) {
+    use crate::cosim::Json;
+    if let Ok(json) = Json::parse(input) {
+        let _ = Json::parse(&json.to_text());
+    }
+}
+
+/// Every target of this build
+pub fn targets() -> Vec<Target> {
+    // The inject, audit and cosim targets come with their features
+    #[cfg_attr(
+        not(any(feature = "tui", feature = "daemon", feature = "cosim")),
+        allow(unused_mut)
+    )]
+    let mut targets = vec![
+        Target {
+            name: "config",
+            seeds: || {
+                let rules: Vec<String> = FuzzySystem::demo().rule_texts();
+                vec![
+                    FAN.to_string(),
+                    config::to_toml(&FuzzySystem::demo(), false),
+                    format!("rules = {:?}\n", rules),
+                    format!(
+                        "{}\n[[alarm]]\nname = \"stuck\"\nwhen = \"holds_for(fan_speed > 80, 10m)\"\n",
+                        FAN
+                    ),
+                ]
+            },
+            dictionary: &[
+                "[",
+                "]",
+                "[[",
+                "]]",
+                "\"",
+                "\\",
+                "=",
+                ",",
+                "\n",
+                "#",
+                "[[input]]\n",
+                "[[input.set]]\n",
+                "[[output]]\n",
+                "[[interlock]]\n",
+                "rules = [",
+                "params = [",
+                "1e308",
+                "-0",
+                "nan",
+                "inf",
+                "1_000",
+                "true",
+                "IF ",
+                " IS ",
+                " AND ",
+                " THEN ",
+                " WITH ",
+                "\"${",
+                "}\"",
+            ],
+            read: read_config,
+        },
+        Target {
+            name: "rule",
+            seeds: || FuzzySystem::demo().rule_texts(),
+            dictionary: &[
+                "IF ", " IS ", " AND ", " THEN ", " WITH ", "1e308", "-1", "nan", " ", "\t",
+            ],
+            read: read_rule,
+        },
+        Target {
+            name: "expression",
+            seeds: || {
+                strings(&[
+                    "holds_for(fan_speed > 80 and humidity > 70, 10m)",
+                    "rising(temperature) or not membership('humidity', 'High') > 0.5",
+                    "abs(fan_speed - 50) / 2 >= min(strength(1), max(1, 2h)) != -temperature",
+                ])
+            },
+            dictionary: &[
+                "(",
+                ")",
+                "((",
+                "))",
+                "-",
+                "!",
+                "not ",
+                " and ",
+                " or ",
+                "holds_for(",
+                "rising(",
+                "abs(",
+                "min(",
+                ",",
+                "'",
+                "\"",
+                "strength(",
+                "1e308",
+                "0s",
+                "10m",
+                ".",
+                "==",
+            ],
+            read: read_expression,
+        },
+        Target {
+            name: "rulecsv",
+            seeds: || {
+                let system = FuzzySystem::demo();
+                let csv = rulecsv::export(&system).unwrap_or_default();
+                vec![csv.clone(), csv.replace(',', ";").replace('.', ",")]
+            },
+            dictionary: &[
+                ",", ";", "\"", "\"\"", "\n", "\r\n", "weight", "group", "enabled",
+            ],
+            read: read_rulecsv,
+        },
+        Target {
+            name: "scenario",
+            seeds: || {
+                strings(&[
+                    "[[scenario]]\nname = \"hot\"\ninputs = [35.0, 80.0]\nexpect = [81.25]\n\
+                     tolerance = 0.5\n",
+                    "[[scenario]]\nname = \"cold\"\ninputs = [5, 30]\nexpect = [\"Off\"]\n\
+                     golden = true\n",
+                ])
+            },
+            dictionary: &[
+                "[",
+                "]",
+                "[[scenario]]\n",
+                "\"",
+                "=",
+                ",",
+                "\n",
+                "nan",
+                "1e308",
+            ],
+            read: read_scenario,
+        },
+        Target {
+            name: "state",
+            seeds: || {
+                let texts = FuzzySystem::demo().rule_texts();
+                vec![format!(
+                    "[[rule]]\ntext = {:?}\nfired = 3\nlast_fired = 1760612345.0\npeak = 0.5\n",
+                    texts[0]
+                )]
+            },
+            dictionary: &["[[rule]]\n", "\"", "=", "\n", "nan", "-1", "1e308"],
+            read: read_state,
+        },
+        Target {
+            name: "number",
+            seeds: || strings(&["23,5", "1.234,5", "-0.5", "1 000", "1e3", "1_000.25"]),
+            dictionary: &[",", ".", "-", "+", "e", "_", "\u{2009}", "\u{00A0}", "9"],
+            read: read_number,
+        },
+    ];
+    #[cfg(any(feature = "tui", feature = "daemon"))]
+    targets.push(Target {
+        name: "inject",
+        seeds: || {
+            strings(&[
+                "temperature freeze 30",
+                "humidity offset -2,5 60",
+                "temperature noise 1.5 10",
+                "clear",
+            ])
+        },
+        dictionary: &[
+            " ", "freeze", "nan", "offset", "noise", "clear", "1e308", "-1", "inf",
+        ],
+        read: read_inject,
+    });
+    #[cfg(feature = "daemon")]
+    targets.push(Target {
+        name: "audit",
+        seeds: || {
+            strings(&[
+                "2026-10-17T08:30:00.250Z session=12.5 cause=command defuzzified=83.5 \
+                 cascade=80 rate_limit=-60 band=HIGH hysteresis=MEDIUM dwell=OFF \
+                 dwell_secs=2.5 value=20 output_band=HIGH command=\"set temperature 33\"",
+                "2026-10-17T06:30:00.250Z session=12.5 cause=interlock defuzzified=83.5 \
+                 rate_limit=0 band=HIGH interlock=\"door\" value=0 output_band=OFF \
+                 switch=\"door on\"",
+            ])
+        },
+        dictionary: &["=", "\"", " ", "\\", "cause=", "nan", "1e308", "Z", "T"],
+        read: read_audit,
+    });
+    #[cfg(feature = "cosim")]
+    targets.push(Target {
+        name: "cosim",
+        seeds: || {
+            strings(&[
+                r#"{"cmd":"init","trace":true}"#,
+                r#"{"cmd":"step","time":10.0,"inputs":{"temperature":31.5,"humidity":60}}"#,
+                r#"{"cmd":"weights","weights":[1,0.5,1e-3,-2E2]}"#,
+                r#"{"a":[null,false,"x\"y\u00e9\n"],"b":{}}"#,
+            ])
+        },
+        dictionary: &[
+            "{", "}", "[", "]", "\"", ":", ",", "\\", "\\u", "null", "true", "1e308", "-",
+        ],
+        read: read_cosim,
+    });
+    targets
+}
+
+/// `input` with one mutation; `others` are inputs to splice from
+fn mutate(
+    input: &[char],
+    others: &[Vec<char>],
+    dictionary: &[&str],
+    rng: &mut StdRng,
+) -> Vec<char> {
+    let mut out = input.to_vec();
+    let at = |rng: &mut StdRng, len: usize| rng.gen_range(0..=len);
+    match rng.gen_range(0..6) {
+        // Replace a character
+        0 if !out.is_empty() => {
+            let i = rng.gen_range(0..out.len());
+            out[i] = match rng.gen_range(0..4) {
+                0 => char::from(rng.gen_range(0u8..128)),
+                1 => ['\0', '\u{FFFD}', 'é', '\u{202F}', '😀'][rng.gen_range(0..5)],
+                _ => out[rng.gen_range(0..out.len())],
+            };
+        }
+        // Drop a span
+        1 if !out.is_empty() => {
+            let start = rng.gen_range(0..out.len());
+            let end = (start + rng.gen_range(1..=16)).min(out.len());
+            out.drain(start..end);
+        }
+        // Repeat a span, up to deep nesting
+        2 if !out.is_empty() => {
+            let start = rng.gen_range(0..out.len());
+            let end = (start + rng.gen_range(1..=8)).min(out.len());
+            let span: Vec<char> = out[start..end].to_vec();
+            let times = [1, 2, 16, 256, 4096][rng.gen_range(0..5)];
+            let span: Vec<char> = std::iter::repeat_n(span, times).flatten().collect();
+            out.splice(end..end, span);
+        }
+        // Splice on the tail of another input
+        3 if !others.is_empty() => {
+            let other = &others[rng.gen_range(0..others.len())];
+            let from = at(rng, other.len());
+            out.truncate(at(rng, out.len()));
+            out.extend_from_slice(&other[from..]);
+        }
+        // Drop in a token, sometimes many times over
+        _ => {
+            let token = dictionary[rng.gen_range(0..dictionary.len())];
+            let times = [1, 1, 1, 64, 2048][rng.gen_range(0..5)];
+            let i = at(rng, out.len());
+            let tokens: Vec<char> = token.repeat(times).chars().collect();
+            out.splice(i..i, tokens);
+        }
+    }
+    out
+}
+
+/// What broke a target
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub input: String,
+    /// The panic's message, or how long it had been running
+    pub problem: String,
+}
+
+/// The input being read and since when
+type Current = Arc<Mutex<(String, Instant)>>;
+
+/// Throw inputs at `target` for `secs`; how many it read, or what broke it
+pub fn run_target(target: &Target, secs: f64, seed: u64, timeout: f64) -> Result<usize, Finding> {
+    let seeds: Vec<Vec<char>> = (target.seeds)()
+        .iter()
+        .map(|s| s.chars().collect())
+        .collect();
+    let (dictionary, read) = (target.dictionary, target.read);
+    let current: Current = Arc::new(Mutex::new((String::new(), Instant::now())));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (found, findings) = std::sync::mpsc::channel();
+
+    let worker = {
+        let (current, stop) = (current.clone(), stop.clone());
+        std::thread::spawn(move || {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut count = 0;
+            while !stop.load(Ordering::Relaxed) {
+                let mut input = seeds[rng.gen_range(0..seeds.len())].clone();
+                for _ in 0..rng.gen_range(1..=MAX_MUTATIONS) {
+                    let mutated = mutate(&input, &seeds, dictionary, &mut rng);
+                    if mutated.len() <= MAX_INPUT || mutated.len() < input.len() {
+                        input = mutated;
+                    }
+                }
+                let input: String = input.into_iter().collect();
+                *current.lock().unwrap() = (input.clone(), Instant::now());
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| read(&input))) {
+                    let problem = payload
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| payload.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "panic".to_string());
+                    let _ = found.send(Finding { input, problem });
+                    return count;
+                }
+                count += 1;
+            }
+            count
+        })
+    };
+
+    // Panics are reported as findings, not printed as they happen
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let started = Instant::now();
+    let result = loop {
+        std::thread::sleep(Duration::from_millis(20));
+        if let Ok(finding) = findings.try_recv() {
+            break Err(finding);
+        }
+        let (input, since) = current.lock().unwrap().clone();
+        if since.elapsed().as_secs_f64() > timeout && !worker.is_finished() {
+            // The worker is left to the process's exit
+            stop.store(true, Ordering::Relaxed);
+            break Err(Finding {
+                input,
+                problem: format!("still running after {}s", timeout),
+            });
+        }
+        if started.elapsed().as_secs_f64() >= secs {
+            stop.store(true, Ordering::Relaxed);
+            let count = worker.join().unwrap_or_default();
+            break match findings.try_recv() {
+                Ok(finding) => Err(finding),
+                Err(_) => Ok(count),
+            };
+        }
+    };
+    panic::set_hook(hook);
+    result
+}
+
+/// `fuzz` subcommand
+pub fn run(args: &[String]) -> i32 {
+    let (mut target, mut secs, mut seed, mut timeout) = (None, 10.0, None, 5.0);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = iter.next();
+        let number = |what: &str| -> Result<f64, String> {
+            value
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|v| *v > 0.0 && v.is_finite())
+                .ok_or_else(|| format!("{} needs a number above 0", what))
+        };
+        let result = match arg.as_str() {
+            "--target" => value
+                .map(|v| target = Some(v.clone()))
+                .ok_or_else(|| "--target needs a name".to_string()),
+            "--secs" => number("--secs").map(|v| secs = v),
+            "--timeout" => number("--timeout").map(|v| timeout = v),
+            "--seed" => value
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(|v| seed = Some(v))
+                .ok_or_else(|| "--seed needs a whole number".to_string()),
+            other => Err(format!("unknown argument '{}'", other)),
+        };
+        if let Err(e) = result {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    }
+
+    let targets = targets();
+    let chosen: Vec<&Target> = match &target {
+        Some(name) => match targets.iter().find(|t| t.name == name) {
+            Some(target) => vec![target],
+            None => {
+                let names: Vec<&str> = targets.iter().map(|t| t.name).collect();
+                eprintln!("unknown target '{}' ({})", name, names.join(", "));
+                return 2;
+            }
+        },
+        None => targets.iter().collect(),
+    };
+    let seed = seed.unwrap_or_else(|| rand::thread_rng().r#gen());
+    println!("seed {}", seed);
+    for target in chosen {
+        match run_target(target, secs, seed, timeout) {
+            Ok(count) => println!("{}: {} inputs in {}s, clean", target.name, count, secs),
+            Err(finding) => {
+                println!("{}: {}", target.name, finding.problem);
+                println!("{:?}", finding.input);
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(name: &str) -> Target {
+        targets().into_iter().find(|t| t.name == name).unwrap()
+    }
+
+    /// Inputs that broke a reader once, by target
+    fn regressions() -> Vec<(&'static str, String)> {
+        #[cfg_attr(not(feature = "cosim"), allow(unused_mut))]
+        let mut regressions = vec![
+            // Recursion without end, before MAX_DEPTH
+            ("expression", format!("{}1", "(".repeat(20_000))),
+            ("expression", format!("{}fan_speed", "-".repeat(20_000))),
+            ("expression", "not ".repeat(5_000)),
+            ("expression", "abs(".repeat(5_000)),
+            // Recursion without end, before MAX_NESTING
+            ("config", format!("rules = {}", "[".repeat(100_000))),
+            (
+                "config",
+                format!("x = {}{}", "[".repeat(100_000), "]".repeat(100_000)),
+            ),
+            // Quadratic: the brackets of a multi-line array counted again
+            // for every line
+            (
+                "config",
+                format!("rules = [\n{}]", "\"IF\",\n".repeat(100_000)),
+            ),
+            (
+                "scenario",
+                format!("[[scenario]]\ninputs = {}", "[".repeat(100_000)),
+            ),
+            // Quadratic: every key compared with every other one
+            (
+                "state",
+                (0..60_000).map(|i| format!("k{} = 1\n", i)).collect(),
+            ),
+        ];
+        // Recursion without end, before cosim's MAX_DEPTH
+        #[cfg(feature = "cosim")]
+        regressions.push(("cosim", "[".repeat(200_000)));
+        regressions
+    }
+
+    #[test]
+    fn inputs_that_broke_a_reader_read_in_time() {
+        for (name, input) in regressions() {
+            let started = Instant::now();
+            (target(name).read)(&input);
+            assert!(
+                started.elapsed() < Duration::from_secs(2),
+                "{}: {:?}",
+                name,
+                &input[..40]
+            );
+        }
+    }
+
+    #[test]
+    fn every_target_runs_clean_and_a_panic_is_reported() {
+        for target in targets() {
+            let count = run_target(&target, 0.2, 1, 5.0).unwrap_or_else(|finding| {
+                panic!(
+                    "{}: {} on {:?}",
+                    target.name, finding.problem, finding.input
+                )
+            });
+            assert!(count > 0, "{}", target.name);
+        }
+
+        let broken = Target {
+            name: "broken",
+            seeds: || vec!["ok".to_string()],
+            dictionary: &["("],
+            read: |input| assert!(!input.contains('('), "unbalanced"),
+        };
+        let finding = run_target(&broken, 5.0, 1, 5.0).unwrap_err();
+        assert_eq!(finding.problem, "unbalanced");
+        assert!(finding.input.contains('('));
+    }
+}
@@ -0,0 +1,234 @@
+// ============================================================================
+// MARKER GAUGE - Medidor com marcadores
+// ============================================================================
+//
+// ratatui's Gauge cannot draw anything on top of its bar, so the fan speed
+// uses this one: the same filled bar and centered label, plus vertical
+// markers at arbitrary positions (the rule consequent preview). The input
+// gauges use it to hatch the end of the bar a reading ran past.
+
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    text::Line,
+    widgets::{Block, Widget},
+};
+
+/// A vertical line drawn across the gauge at `ratio` of its width
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    pub ratio: f64,
+    pub symbol: &'static str,
+    pub color: Color,
+}
+
+/// End of the bar a value ran past
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edge {
+    Low,
+    High,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MarkerGauge<'a> {
+    block: Option<Block<'a>>,
+    ratio: f64,
+    label: Line<'a>,
+    gauge_style: Style,
+    markers: Vec<Marker>,
+    overflow: Option<Edge>,
+}
+
+impl<'a> MarkerGauge<'a> {
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    /// Filled fraction; out-of-range values are clamped
+    pub fn ratio(mut self, ratio: f64) -> Self {
+        self.ratio = if ratio.is_finite() {
+            ratio.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        self
+    }
+
+    /// Centered on the bar; styled spans keep the bar's colors where
+    /// they set none
+    pub fn label(mut self, label: impl Into<Line<'a>>) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    /// The foreground color fills the bar, as with ratatui's Gauge
+    pub fn gauge_style(mut self, style: Style) -> Self {
+        self.gauge_style = style;
+        self
+    }
+
+    pub fn markers(mut self, markers: Vec<Marker>) -> Self {
+        self.markers = markers;
+        self
+    }
+
+    /// Hatch a tenth of the bar, at least a cell, at `edge`
+    pub fn overflow(mut self, edge: Option<Edge>) -> Self {
+        self.overflow = edge;
+        self
+    }
+}
+
+impl Widget for MarkerGauge<'_> {
+    fn render(mut self, area: Rect, buf: &mut Buffer) {
+        let area = match self.block.take() {
+            Some(block) => {
+                let inner = block.inner(area);
+                block.render(area, buf);
+                inner
+            }
+            None => area,
+        };
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+        buf.set_style(area, self.gauge_style);
+
+        let fill = self.gauge_style.fg.unwrap_or(Color::Reset);
+        let end = area.left() + (f64::from(area.width) * self.ratio).round() as u16;
+        for y in area.top()..area.bottom() {
+            for x in area.left()..end {
+                // Swapped colors so the label stays readable on the bar
+                buf.get_mut(x, y)
+                    .set_symbol(" ")
+                    .set_fg(self.gauge_style.bg.unwrap_or(Color::Reset))
+                    .set_bg(fill);
+            }
+        }
+
+        if let Some(edge) = self.overflow {
+            let width = (area.width / 10).max(1);
+            let start = match edge {
+                Edge::Low => area.left(),
+                Edge::High => area.right() - width,
+            };
+            for y in area.top()..area.bottom() {
+                for x in start..start + width {
+                    // Drawn in the color of the bar where it is not filled
+                    let hatch = if x < end {
+                        self.gauge_style.bg.unwrap_or(Color::Reset)
+                    } else {
+                        fill
+                    };
+                    buf.get_mut(x, y).set_symbol("╱").set_fg(hatch);
+                }
+            }
+        }
+
+        // Markers leave the label row alone unless there is no other
+        let label_row = area.top() + area.height / 2;
+        for marker in &self.markers {
+            let ratio = marker.ratio.clamp(0.0, 1.0);
+            let x = area.left() + (f64::from(area.width - 1) * ratio).round() as u16;
+            for y in area.top()..area.bottom() {
+                if y != label_row || area.height == 1 {
+                    buf.get_mut(x, y)
+                        .set_symbol(marker.symbol)
+                        .set_fg(marker.color);
+                }
+            }
+        }
+
+        let width = area.width.min(self.label.width() as u16);
+        buf.set_line(
+            area.left() + (area.width - width) / 2,
+            label_row,
+            &self.label,
+            width,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(gauge: MarkerGauge, width: u16, height: u16) -> Buffer {
+        let area = Rect::new(0, 0, width, height);
+        let mut buf = Buffer::empty(area);
+        gauge.render(area, &mut buf);
+        buf
+    }
+
+    fn row(buf: &Buffer, y: u16) -> String {
+        (0..buf.area.width)
+            .map(|x| buf.get(x, y).symbol.clone())
+            .collect()
+    }
+
+    #[test]
+    fn markers_cross_the_bar_around_the_label() {
+        let gauge = MarkerGauge::default()
+            .ratio(0.5)
+            .label("50%".to_string())
+            .gauge_style(Style::default().fg(Color::Yellow))
+            .markers(vec![
+                Marker {
+                    ratio: 0.25,
+                    symbol: "│",
+                    color: Color::Cyan,
+                },
+                Marker {
+                    ratio: 1.5,
+                    symbol: "┃",
+                    color: Color::White,
+                },
+            ]);
+        let buf = render(gauge, 13, 3);
+        assert_eq!(row(&buf, 0), "   │        ┃");
+        assert_eq!(row(&buf, 1), "     50%     ");
+        assert_eq!(row(&buf, 2), "   │        ┃");
+
+        // The first half is filled with the gauge color
+        assert_eq!(buf.get(5, 0).bg, Color::Yellow);
+        assert_eq!(buf.get(7, 0).bg, Color::Reset);
+        assert_eq!(buf.get(3, 0).fg, Color::Cyan);
+    }
+
+    #[test]
+    fn a_single_row_gauge_draws_markers_over_the_label() {
+        let gauge = MarkerGauge::default()
+            .ratio(f64::NAN)
+            .label("0%".to_string())
+            .markers(vec![Marker {
+                ratio: 0.0,
+                symbol: "│",
+                color: Color::Cyan,
+            }]);
+        let buf = render(gauge, 6, 1);
+        assert_eq!(row(&buf, 0), "│ 0%  ");
+    }
+
+    #[test]
+    fn an_overflow_hatches_the_end_it_ran_past() {
+        let gauge = |edge, ratio| {
+            MarkerGauge::default()
+                .ratio(ratio)
+                .label("x".to_string())
+                .gauge_style(Style::default().fg(Color::Red))
+                .overflow(Some(edge))
+        };
+        let buf = render(gauge(Edge::High, 1.0), 21, 3);
+        assert_eq!(row(&buf, 0), "                   ╱╱");
+        assert_eq!(row(&buf, 1), "          x        ╱╱");
+        // Over the filled bar, in the bar's background color
+        assert_eq!(buf.get(20, 0).bg, Color::Red);
+        assert_eq!(buf.get(20, 0).fg, Color::Reset);
+
+        let buf = render(gauge(Edge::Low, 0.0), 9, 1);
+        assert_eq!(row(&buf, 0), "╱   x    ");
+        assert_eq!(buf.get(0, 0).fg, Color::Red);
+    }
+}
@@ -0,0 +1,495 @@
+// ============================================================================
+// RANDOM GENERATORS - Geradores de cenários aleatórios
+// ============================================================================
+//
+// What 'r' in the TUI, `random` on the daemon and the `explore` command
+// sample, chosen in the config and cycled with 'R' in the TUI:
+//
+//     uniform       every input uniformly over its universe
+//     boundary      near the set breakpoints, where the output changes
+//                   fastest: a breakpoint picked at random per input, then
+//                   moved by up to `spread` times the universe width
+//     constrained   uniformly over `[generator.ranges]`, keeping only the
+//                   points where every `where` condition holds
+//
+//     [generator]
+//     kind = "boundary"
+//     spread = 0.05                        # optional
+//     where = ["humidity > temperature"]   # optional, constrained only
+//
+//     [generator.ranges]                   # optional, constrained only
+//     temperature = [20.0, 35.0]
+//
+// Samples are in the units of each universe, i.e. after any input
+// conversion. A constrained generator that finds nothing in MAX_TRIES
+// attempts gives up rather than spinning.
+
+use crate::config;
+use crate::{FuzzyController, FuzzySystem};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Attempts a constrained sample makes before giving up
+pub const MAX_TRIES: usize = 1000;
+
+const USAGE: &str = "Usage: fuzzy_logic explore [--config FILE] [--samples N] \
+                     [--kind uniform|boundary|constrained] [--seed S]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratorKind {
+    Uniform,
+    Boundary,
+    Constrained,
+}
+
+impl GeneratorKind {
+    pub const ALL: [GeneratorKind; 3] = [
+        GeneratorKind::Uniform,
+        GeneratorKind::Boundary,
+        GeneratorKind::Constrained,
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        GeneratorKind::ALL.into_iter().find(|k| k.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GeneratorKind::Uniform => "uniform",
+            GeneratorKind::Boundary => "boundary",
+            GeneratorKind::Constrained => "constrained",
+        }
+    }
+
+    /// The next kind, for cycling
+    pub fn next(self) -> Self {
+        let index = GeneratorKind::ALL
+            .iter()
+            .position(|k| *k == self)
+            .unwrap_or(0);
+        GeneratorKind::ALL[(index + 1) % GeneratorKind::ALL.len()]
+    }
+}
+
+/// One side of a `where` comparison
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Input(String),
+    Number(f64),
+}
+
+impl Operand {
+    fn parse(text: &str) -> Self {
+        match text.parse() {
+            Ok(number) => Operand::Number(number),
+            Err(_) => Operand::Input(text.to_string()),
+        }
+    }
+
+    fn to_text(&self) -> String {
+        match self {
+            Operand::Input(name) => name.clone(),
+            Operand::Number(number) => number.to_string(),
+        }
+    }
+}
+
+/// `left op right`, with `op` one of < <= > >=
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub left: Operand,
+    pub op: &'static str,
+    pub right: Operand,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let [left, op, right] = words[..] else {
+            return Err(format!("expected 'a < b', got '{}'", text));
+        };
+        let op = ["<", "<=", ">", ">="]
+            .into_iter()
+            .find(|o| *o == op)
+            .ok_or_else(|| format!("unknown comparison '{}'", op))?;
+        Ok(Condition {
+            left: Operand::parse(left),
+            op,
+            right: Operand::parse(right),
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.left.to_text(),
+            self.op,
+            self.right.to_text()
+        )
+    }
+
+    /// Input names the condition refers to
+    pub fn inputs(&self) -> impl Iterator<Item = &str> {
+        [&self.left, &self.right]
+            .into_iter()
+            .filter_map(|o| match o {
+                Operand::Input(name) => Some(name.as_str()),
+                Operand::Number(_) => None,
+            })
+    }
+
+    fn holds(&self, value: impl Fn(&str) -> f64) -> bool {
+        let operand = |o: &Operand| match o {
+            Operand::Input(name) => value(name),
+            Operand::Number(number) => *number,
+        };
+        let (left, right) = (operand(&self.left), operand(&self.right));
+        match self.op {
+            "<" => left < right,
+            "<=" => left <= right,
+            ">" => left > right,
+            _ => left >= right,
+        }
+    }
+}
+
+/// `[generator]` settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorConfig {
+    pub kind: GeneratorKind,
+    /// Reach around a breakpoint, as a fraction of the universe width
+    pub spread: f64,
+    /// Input name and sub-range, for the constrained kind
+    pub ranges: Vec<(String, f64, f64)>,
+    pub conditions: Vec<Condition>,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            kind: GeneratorKind::Uniform,
+            spread: 0.05,
+            ranges: Vec::new(),
+            conditions: Vec::new(),
+        }
+    }
+}
+
+/// Draws input points for one system
+pub struct Sampler {
+    config: GeneratorConfig,
+    names: Vec<String>,
+    universes: Vec<(f64, f64)>,
+    breakpoints: Vec<Vec<f64>>,
+}
+
+impl Sampler {
+    pub fn new(controller: &FuzzyController) -> Self {
+        let system = &controller.system;
+        Sampler {
+            config: system.generator.clone(),
+            names: system.inputs.iter().map(|v| v.name.clone()).collect(),
+            universes: system.inputs.iter().map(|v| (v.min, v.max)).collect(),
+            breakpoints: controller.breakpoints(),
+        }
+    }
+
+    /// One value per input in declaration order, or `None` when a
+    /// constrained generator found no point satisfying its conditions
+    pub fn sample<R: Rng + ?Sized>(&self, kind: GeneratorKind, rng: &mut R) -> Option<Vec<f64>> {
+        match kind {
+            GeneratorKind::Uniform => Some(
+                self.universes
+                    .iter()
+                    .map(|(min, max)| rng.gen_range(*min..=*max))
+                    .collect(),
+            ),
+            GeneratorKind::Boundary => Some(
+                self.universes
+                    .iter()
+                    .zip(&self.breakpoints)
+                    .map(|((min, max), points)| {
+                        if points.is_empty() {
+                            return rng.gen_range(*min..=*max);
+                        }
+                        let point = points[rng.gen_range(0..points.len())];
+                        // Triangular around the breakpoint
+                        let offset = (rng.r#gen::<f64>() - rng.r#gen::<f64>())
+                            * self.config.spread
+                            * (max - min);
+                        (point + offset).clamp(*min, *max)
+                    })
+                    .collect(),
+            ),
+            GeneratorKind::Constrained => {
+                let ranges: Vec<(f64, f64)> = self
+                    .names
+                    .iter()
+                    .zip(&self.universes)
+                    .map(|(name, universe)| {
+                        self.config
+                            .ranges
+                            .iter()
+                            .find(|(input, _, _)| input == name)
+                            .map_or(*universe, |(_, min, max)| (*min, *max))
+                    })
+                    .collect();
+                (0..MAX_TRIES).find_map(|_| {
+                    let values: Vec<f64> = ranges
+                        .iter()
+                        .map(|(min, max)| rng.gen_range(*min..=*max))
+                        .collect();
+                    let value = |name: &str| {
+                        let index = self.names.iter().position(|n| n == name);
+                        index.map_or(f64::NAN, |i| values[i])
+                    };
+                    self.config
+                        .conditions
+                        .iter()
+                        .all(|c| c.holds(value))
+                        .then_some(values)
+                })
+            }
+        }
+    }
+}
+
+/// Check that the generator only names existing inputs and ranges inside
+/// their universes
+pub fn validate(system: &FuzzySystem) -> Result<(), String> {
+    let config = &system.generator;
+    for (name, min, max) in &config.ranges {
+        let input = system
+            .input(name)
+            .ok_or_else(|| format!("generator: unknown input '{}'", name))?;
+        if !(input.min <= *min && min < max && *max <= input.max) {
+            return Err(format!(
+                "generator: range {} to {} of '{}' must be increasing and within {} to {}",
+                min, max, name, input.min, input.max
+            ));
+        }
+    }
+    for condition in &config.conditions {
+        if let Some(name) = condition.inputs().find(|n| system.input(n).is_none()) {
+            return Err(format!(
+                "generator: '{}' names unknown input '{}'",
+                condition.to_text(),
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Summary of a set of outputs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+    pub count: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    /// Minimum, 10th percentile, median, 90th percentile and maximum
+    pub quantiles: [f64; 5],
+}
+
+impl Distribution {
+    pub fn of(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / count as f64;
+        let variance = sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count as f64;
+        let quantile = |q: f64| sorted[((count - 1) as f64 * q).round() as usize];
+        Some(Distribution {
+            count,
+            mean,
+            std_dev: variance.sqrt(),
+            quantiles: [0.0, 0.1, 0.5, 0.9, 1.0].map(quantile),
+        })
+    }
+}
+
+/// `explore` subcommand: sample each generator headlessly and print the
+/// distribution of the primary output
+pub fn run(args: &[String]) -> i32 {
+    let mut system = FuzzySystem::demo();
+    let mut samples = 1000;
+    let mut kinds = GeneratorKind::ALL.to_vec();
+    let mut seed = 0;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = iter.next();
+        let result = match (arg.as_str(), value) {
+            ("--config", Some(path)) => config::load_system(path).map(|s| system = s),
+            ("--samples", Some(n)) => match n.parse::<usize>() {
+                Ok(n) if n > 0 => {
+                    samples = n;
+                    Ok(())
+                }
+                _ => Err("--samples expects a positive integer".to_string()),
+            },
+            ("--kind", Some(kind)) => match GeneratorKind::parse(kind) {
+                Some(kind) => {
+                    kinds = vec![kind];
+                    Ok(())
+                }
+                None => Err(format!("unknown generator '{}'", kind)),
+            },
+            ("--seed", Some(s)) => s
+                .parse()
+                .map(|s| seed = s)
+                .map_err(|_| "--seed expects an integer".to_string()),
+            _ => Err(USAGE.to_string()),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            return 2;
+        }
+    }
+
+    let controller = FuzzyController::from_system(system);
+    let sampler = Sampler::new(&controller);
+    let mut rng = StdRng::seed_from_u64(seed);
+    println!(
+        "{:<12} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "generator", "samples", "mean", "sd", "min", "p10", "median", "p90", "max"
+    );
+    for kind in kinds {
+        let outputs: Vec<f64> = (0..samples)
+            .filter_map(|_| sampler.sample(kind, &mut rng))
+            .map(|inputs| controller.compute(&inputs))
+            .collect();
+        match Distribution::of(&outputs) {
+            Some(d) => {
+                let [min, p10, median, p90, max] = d.quantiles;
+                println!(
+                    "{:<12} {:>8} {:>8.2} {:>8.2} {:>8.2} {:>8.2} {:>8.2} {:>8.2} {:>8.2}",
+                    kind.name(),
+                    d.count,
+                    d.mean,
+                    d.std_dev,
+                    min,
+                    p10,
+                    median,
+                    p90,
+                    max
+                );
+            }
+            None => println!(
+                "{:<12} {:>8} no point satisfies the constraints",
+                kind.name(),
+                0
+            ),
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    const N: usize = 20_000;
+
+    fn sampler(generator: GeneratorConfig) -> Sampler {
+        let system = FuzzySystem {
+            generator,
+            ..fixtures::fan()
+        };
+        Sampler::new(&FuzzyController::from_system(system))
+    }
+
+    fn draw(sampler: &Sampler, kind: GeneratorKind) -> Vec<Vec<f64>> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..N)
+            .map(|_| sampler.sample(kind, &mut rng).unwrap())
+            .collect()
+    }
+
+    /// Share of the temperatures within `reach` of a breakpoint
+    fn near_breakpoints(points: &[Vec<f64>], reach: f64) -> f64 {
+        let breakpoints = [15.0, 20.0, 22.5, 25.0, 30.0];
+        let near = points
+            .iter()
+            .filter(|p| breakpoints.iter().any(|b| (p[0] - b).abs() <= reach))
+            .count();
+        near as f64 / points.len() as f64
+    }
+
+    #[test]
+    fn uniform_samples_fill_the_universes_evenly() {
+        let points = draw(&sampler(GeneratorConfig::default()), GeneratorKind::Uniform);
+        assert!(points.iter().all(|p| (0.0..=50.0).contains(&p[0])));
+        assert!(points.iter().all(|p| (0.0..=100.0).contains(&p[1])));
+        let mean = Distribution::of(&points.iter().map(|p| p[1]).collect::<Vec<_>>()).unwrap();
+        assert!((mean.mean - 50.0).abs() < 1.0, "{}", mean.mean);
+        // Standard deviation of a uniform distribution over 0..100
+        assert!((mean.std_dev - 100.0 / 12f64.sqrt()).abs() < 1.0);
+        // A quarter of them in each quarter
+        let low = points.iter().filter(|p| p[0] < 12.5).count() as f64 / N as f64;
+        assert!((low - 0.25).abs() < 0.02, "{}", low);
+        // Plenty of them nowhere near a breakpoint
+        assert!(near_breakpoints(&points, 2.5) < 0.5);
+    }
+
+    #[test]
+    fn boundary_samples_gather_at_the_breakpoints() {
+        let points = draw(
+            &sampler(GeneratorConfig::default()),
+            GeneratorKind::Boundary,
+        );
+        // spread 0.05 of a 50-wide universe
+        assert_eq!(near_breakpoints(&points, 2.5), 1.0);
+        assert!(near_breakpoints(&points, 1.0) > 0.55);
+        // The universe edges are not breakpoints worth sampling
+        assert!(points.iter().all(|p| p[0] > 12.0 && p[0] < 33.0));
+        // Every breakpoint gets its share
+        let at_15 = points.iter().filter(|p| (p[0] - 15.0).abs() < 1.0).count();
+        assert!(
+            (at_15 as f64 / N as f64 - 0.2 * 0.64).abs() < 0.02,
+            "{}",
+            at_15
+        );
+    }
+
+    #[test]
+    fn constrained_samples_respect_ranges_and_conditions() {
+        let config = GeneratorConfig {
+            kind: GeneratorKind::Constrained,
+            ranges: vec![("temperature".to_string(), 20.0, 30.0)],
+            conditions: vec![Condition::parse("humidity < temperature").unwrap()],
+            ..GeneratorConfig::default()
+        };
+        let points = draw(&sampler(config.clone()), GeneratorKind::Constrained);
+        assert!(
+            points
+                .iter()
+                .all(|p| (20.0..=30.0).contains(&p[0]) && p[1] < p[0])
+        );
+        // Rejection keeps the accepted points uniform: P(t) grows with t
+        let t = Distribution::of(&points.iter().map(|p| p[0]).collect::<Vec<_>>()).unwrap();
+        let expected = (30f64.powi(3) - 20f64.powi(3)) / 1.5 / (30f64.powi(2) - 20f64.powi(2));
+        assert!(
+            (t.mean - expected).abs() < 0.1,
+            "{} vs {}",
+            t.mean,
+            expected
+        );
+
+        // An impossible constraint gives up
+        let config = GeneratorConfig {
+            conditions: vec![Condition::parse("temperature > 60").unwrap()],
+            ..config
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(
+            sampler(config).sample(GeneratorKind::Constrained, &mut rng),
+            None
+        );
+        assert!(Condition::parse("humidity ~ 3").is_err());
+    }
+}
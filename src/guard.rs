@@ -0,0 +1,341 @@
+// ============================================================================
+// TUNING GUARDS - Salvaguardas da afinação
+// ============================================================================
+//
+// The daemon's `param` command moves a set's breakpoint while the
+// controller runs, and a few moves too far leave part of the universe in
+// no set at all, or make two sets all but the same. After each move the
+// variable is checked where the move could have changed anything: the
+// supports of the sets it changed, before and after (linked parameters
+// move other sets along, links.rs).
+//
+//     hole      a stretch of the universe no set covers, wider than zero,
+//               that reaches into that region
+//     similar   a changed set and another one whose overlap is at least
+//               `max_similarity` of their union (the area under the
+//               smaller of the two over the area under the larger)
+//
+// Both are worked out from the sets' supports and shapes rather than by
+// sampling the universe: the holes from the union of the supports, the
+// similarity of a pair by integrating over the span of the two sets only,
+// at a fixed number of points. A keypress therefore costs the same on a
+// universe of 10 or of 10 000.
+//
+//     [tuning]               # optional (config.rs)
+//     max_similarity = 0.9   # above 0, at most 1
+//     max_hole = 2.0         # optional: refuse a move that opens a wider
+//                            # hole; without it holes only warn
+//
+// A refused move changes nothing. A move that leaves a hole no wider than
+// before (narrowing one, or moving elsewhere) is never refused, so a
+// config that starts with a hole can still be tuned out of it.
+
+use crate::logging::event;
+use crate::{FuzzyVariable, MembershipFunction, SetDefinition};
+
+/// Points the similarity of a pair of sets is integrated over
+const SIMILARITY_POINTS: usize = 256;
+
+/// `[tuning]` settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuningConfig {
+    pub max_similarity: f64,
+    /// Widest hole a move may open; none only warns
+    pub max_hole: Option<f64>,
+}
+
+impl Default for TuningConfig {
+    fn default() -> Self {
+        TuningConfig {
+            max_similarity: 0.9,
+            max_hole: None,
+        }
+    }
+}
+
+/// Something a move left behind, reported next to its `ok`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    Hole {
+        start: f64,
+        end: f64,
+    },
+    Similar {
+        set: String,
+        other: String,
+        similarity: f64,
+    },
+}
+
+impl Warning {
+    pub fn describe(&self, variable: &str) -> String {
+        match self {
+            Warning::Hole { start, end } => {
+                format!("hole in {} from {} to {}", variable, start, end)
+            }
+            Warning::Similar {
+                set,
+                other,
+                similarity,
+            } => format!(
+                "{} {} and {} {:.0}% alike",
+                variable,
+                set,
+                other,
+                similarity * 100.0
+            ),
+        }
+    }
+}
+
+/// Stretches of `min..max` no set of `sets` covers, that reach into
+/// `region`
+fn holes(sets: &[SetDefinition], (min, max): (f64, f64), region: (f64, f64)) -> Vec<(f64, f64)> {
+    let mut supports: Vec<(f64, f64)> = sets.iter().map(|s| s.function.support()).collect();
+    supports.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut holes = Vec::new();
+    // Covered up to here
+    let mut reach = min;
+    for (start, end) in supports.into_iter().chain([(max, max)]) {
+        let start = start.min(max);
+        if start > reach {
+            holes.push((reach, start));
+        }
+        reach = reach.max(end);
+    }
+    holes
+        .into_iter()
+        .filter(|(start, end)| *end >= region.0 && *start <= region.1)
+        .collect()
+}
+
+/// Area under the smaller of `f` and `g` over the area under the larger,
+/// within `min..max`
+pub fn similarity(f: &MembershipFunction, g: &MembershipFunction, (min, max): (f64, f64)) -> f64 {
+    let ((fa, fb), (ga, gb)) = (f.support(), g.support());
+    if fb <= ga || gb <= fa {
+        return 0.0;
+    }
+    let (start, end) = (fa.min(ga).max(min), fb.max(gb).min(max));
+    if end <= start {
+        return 0.0;
+    }
+    let step = (end - start) / SIMILARITY_POINTS as f64;
+    let (mut lower, mut upper) = (0.0, 0.0);
+    for i in 0..=SIMILARITY_POINTS {
+        let x = start + i as f64 * step;
+        // Trapezoidal rule: the ends count half
+        let weight = if i == 0 || i == SIMILARITY_POINTS {
+            0.5
+        } else {
+            1.0
+        };
+        let (u, v) = (f.evaluate(x), g.evaluate(x));
+        lower += weight * u.min(v);
+        upper += weight * u.max(v);
+    }
+    if upper > 0.0 { lower / upper } else { 0.0 }
+}
+
+/// Check `after`, `before` with a parameter moved: the warnings to show,
+/// or why the move is refused
+pub fn check(
+    before: &FuzzyVariable,
+    after: &FuzzyVariable,
+    config: &TuningConfig,
+) -> Result<Vec<Warning>, String> {
+    let universe = (after.min, after.max);
+    let changed: Vec<usize> = (0..after.sets.len())
+        .filter(|&i| before.sets.get(i).map(|s| &s.function) != Some(&after.sets[i].function))
+        .collect();
+    if changed.is_empty() {
+        return Ok(Vec::new());
+    }
+    let region = changed
+        .iter()
+        .flat_map(|&i| {
+            let now = after.sets[i].function.support();
+            let then = before.sets.get(i).map_or(now, |s| s.function.support());
+            [now, then]
+        })
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), (a, b)| {
+            (lo.min(a), hi.max(b))
+        });
+
+    let opened = holes(&after.sets, universe, region);
+    if let Some(limit) = config.max_hole {
+        let widest = |holes: &[(f64, f64)]| holes.iter().map(|(a, b)| b - a).fold(0.0, f64::max);
+        let was = widest(&holes(&before.sets, universe, region));
+        if let Some(&(start, end)) = opened.iter().find(|(a, b)| b - a > limit && b - a > was) {
+            let refusal = format!(
+                "would open a hole in {} from {} to {}, wider than max_hole {}",
+                after.name, start, end, limit
+            );
+            event!(Warn, "move refused: {}", refusal);
+            return Err(refusal);
+        }
+    }
+
+    let mut warnings: Vec<Warning> = opened
+        .into_iter()
+        .map(|(start, end)| Warning::Hole { start, end })
+        .collect();
+    for (i, set) in after.sets.iter().enumerate() {
+        for &j in &changed {
+            // Each pair once, in declaration order
+            if i == j || (changed.contains(&i) && i < j) {
+                continue;
+            }
+            let (first, second) = if i < j {
+                (set, &after.sets[j])
+            } else {
+                (&after.sets[j], set)
+            };
+            let alike = similarity(&first.function, &second.function, universe);
+            if alike >= config.max_similarity {
+                warnings.push(Warning::Similar {
+                    set: first.name.clone(),
+                    other: second.name.clone(),
+                    similarity: alike,
+                });
+            }
+        }
+    }
+    for warning in &warnings {
+        event!(Warn, "{}", warning.describe(&after.name));
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::links;
+
+    #[test]
+    fn holes_come_from_the_supports_alone() {
+        let system = fixtures::fan();
+        let mut temperature = system.input("temperature").unwrap().clone();
+        let everywhere = (temperature.min, temperature.max);
+        assert!(holes(&temperature.sets, everywhere, everywhere).is_empty());
+
+        // Cold ends at 20; Mild starting at 22 leaves 20 to 22 uncovered
+        links::set_param(&mut temperature.sets, "Mild", 0, 22.0).unwrap();
+        assert_eq!(
+            holes(&temperature.sets, everywhere, everywhere),
+            [(20.0, 22.0)]
+        );
+        // Only holes reaching into the region are reported
+        assert!(holes(&temperature.sets, everywhere, (40.0, 50.0)).is_empty());
+
+        let cold = &temperature.sets[0].function;
+        assert!((similarity(cold, cold, everywhere) - 1.0).abs() < 1e-9);
+        let hot = &temperature.sets[2].function;
+        assert_eq!(similarity(cold, hot, everywhere), 0.0);
+    }
+
+    #[test]
+    fn dragging_a_breakpoint_warns_then_is_refused_past_max_hole() {
+        let system = fixtures::fan();
+        let before = system.input("temperature").unwrap().clone();
+        let low_end = before.sets[0].function.support().1;
+        let drag = |to: f64, config: &TuningConfig| {
+            let mut after = before.clone();
+            links::set_param(&mut after.sets, "Mild", 0, to).unwrap();
+            check(&before, &after, config).map(|w| {
+                w.iter()
+                    .map(|w| w.describe(&after.name))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let config = TuningConfig {
+            max_hole: Some(2.0),
+            ..TuningConfig::default()
+        };
+        // Still overlapping Cold: nothing to say
+        assert_eq!(drag(low_end - 1.0, &config), Ok(Vec::new()));
+        // A hole under the threshold warns
+        assert_eq!(
+            drag(low_end + 1.5, &config),
+            Ok(vec![format!(
+                "hole in temperature from {} to {}",
+                low_end,
+                low_end + 1.5
+            )])
+        );
+        // Past it the move is refused
+        assert_eq!(
+            drag(low_end + 2.5, &config),
+            Err(format!(
+                "would open a hole in temperature from {} to {}, wider than max_hole 2",
+                low_end,
+                low_end + 2.5
+            ))
+        );
+        // Without max_hole it only warns
+        assert_eq!(
+            drag(low_end + 2.5, &TuningConfig::default()).unwrap().len(),
+            1
+        );
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn each_warning_and_refusal_is_logged_once() {
+        let before = fixtures::fan().input("temperature").unwrap().clone();
+        let low_end = before.sets[0].function.support().1;
+        let config = TuningConfig {
+            max_hole: Some(2.0),
+            ..TuningConfig::default()
+        };
+        let logged = crate::logging::capture(|| {
+            for to in [low_end - 1.0, low_end + 1.5, low_end + 2.5] {
+                let mut after = before.clone();
+                links::set_param(&mut after.sets, "Mild", 0, to).unwrap();
+                let _ = check(&before, &after, &config);
+            }
+        });
+        let lines: Vec<(log::Level, String)> = vec![
+            (
+                log::Level::Warn,
+                format!(
+                    "guard: hole in temperature from {} to {}",
+                    low_end,
+                    low_end + 1.5
+                ),
+            ),
+            (
+                log::Level::Warn,
+                format!(
+                    "guard: move refused: would open a hole in temperature from {} to {}, \
+                     wider than max_hole 2",
+                    low_end,
+                    low_end + 2.5
+                ),
+            ),
+        ];
+        assert_eq!(logged, lines);
+    }
+
+    #[test]
+    fn sets_dragged_onto_each_other_are_reported_alike() {
+        let system = fixtures::fan();
+        let before = system.input("temperature").unwrap().clone();
+        let mut after = before.clone();
+        let cold = after.sets[0].function.clone();
+        after.sets[1].function = cold;
+        let warnings = check(&before, &after, &TuningConfig::default()).unwrap();
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|w| w.describe("temperature"))
+                .collect::<Vec<_>>(),
+            [
+                "hole in temperature from 20 to 25",
+                "temperature Cold and Mild 100% alike"
+            ]
+        );
+    }
+}
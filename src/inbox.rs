@@ -0,0 +1,221 @@
+// ============================================================================
+// STATUS MESSAGES - Caixa de mensagens
+// ============================================================================
+//
+// What the TUI has to say, kept as a queue rather than one line the next
+// message overwrites. Each message has a severity and the app time it was
+// posted at:
+//
+//     info       confirmations; shown for INFO_SECS
+//     warning    readings refused or clamped, edits that did not apply;
+//                shown for WARNING_SECS
+//     error      lost connections, files that could not be written; shown
+//                until acknowledged
+//
+// The status bar shows the most severe message still showing, the newest
+// of them on a tie, so "Generated random values!" waits behind the warning
+// the same keypress raised instead of covering it. The last SCROLLBACK
+// messages stay for the message popup, along with any error not yet
+// acknowledged however old.
+
+use std::collections::VecDeque;
+
+/// Seconds an info message shows for
+pub const INFO_SECS: f64 = 5.0;
+/// Seconds a warning shows for
+pub const WARNING_SECS: f64 = 15.0;
+/// Messages kept for the message popup
+pub const SCROLLBACK: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub severity: Severity,
+    pub text: String,
+    /// App time it was posted at
+    pub time: f64,
+    /// An error the user has seen; other messages expire instead
+    pub acknowledged: bool,
+}
+
+impl Message {
+    /// Whether the status bar may still show it at `now`
+    pub fn is_showing(&self, now: f64) -> bool {
+        match self.severity {
+            Severity::Info => now - self.time < INFO_SECS,
+            Severity::Warning => now - self.time < WARNING_SECS,
+            Severity::Error => !self.acknowledged,
+        }
+    }
+
+    fn is_pending_error(&self) -> bool {
+        self.severity == Severity::Error && !self.acknowledged
+    }
+}
+
+/// Messages in the order they were posted
+#[derive(Debug, Clone, Default)]
+pub struct Inbox {
+    messages: VecDeque<Message>,
+}
+
+impl Inbox {
+    pub fn post(&mut self, severity: Severity, text: impl Into<String>, now: f64) {
+        self.messages.push_back(Message {
+            severity,
+            text: text.into(),
+            time: now,
+            acknowledged: false,
+        });
+        if self.messages.len() > SCROLLBACK {
+            // The oldest message that may go; an error waits for its
+            // acknowledgement
+            if let Some(i) = self.messages.iter().position(|m| !m.is_pending_error()) {
+                self.messages.remove(i);
+            }
+        }
+    }
+
+    /// What the status bar shows at `now`: the most severe message
+    /// showing, the newest of them on a tie
+    pub fn current(&self, now: f64) -> Option<&Message> {
+        self.shown(now).map(|i| &self.messages[i])
+    }
+
+    fn shown(&self, now: f64) -> Option<usize> {
+        self.messages
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.is_showing(now))
+            .max_by(|(_, a), (_, b)| a.severity.cmp(&b.severity).then(a.time.total_cmp(&b.time)))
+            .map(|(i, _)| i)
+    }
+
+    /// Messages showing at `now` besides the current one
+    pub fn waiting(&self, now: f64) -> usize {
+        self.messages
+            .iter()
+            .filter(|m| m.is_showing(now))
+            .count()
+            .saturating_sub(1)
+    }
+
+    /// Whether the status bar shows an error to acknowledge
+    pub fn has_error(&self, now: f64) -> bool {
+        self.current(now).is_some_and(Message::is_pending_error)
+    }
+
+    /// Acknowledge the error shown at `now`, if any; the next one, or what
+    /// else is showing, takes its place
+    pub fn acknowledge(&mut self, now: f64) -> bool {
+        match self.shown(now) {
+            Some(i) if self.messages[i].is_pending_error() => {
+                self.messages[i].acknowledged = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Every message kept, newest first
+    pub fn newest_first(&self) -> impl Iterator<Item = &Message> {
+        self.messages.iter().rev()
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infos_and_warnings_expire_and_errors_wait() {
+        let mut inbox = Inbox::default();
+        assert_eq!(inbox.current(0.0), None);
+        inbox.post(Severity::Info, "random values", 0.0);
+        assert_eq!(
+            inbox.current(INFO_SECS - 0.1).unwrap().text,
+            "random values"
+        );
+        assert_eq!(inbox.current(INFO_SECS), None);
+
+        inbox.post(Severity::Warning, "clamped", 10.0);
+        assert!(inbox.current(10.0 + WARNING_SECS - 0.1).is_some());
+        assert_eq!(inbox.current(10.0 + WARNING_SECS), None);
+
+        inbox.post(Severity::Error, "lost the daemon", 100.0);
+        assert_eq!(inbox.current(1e6).unwrap().text, "lost the daemon");
+        assert!(inbox.has_error(1e6));
+        // Expired messages stay in the scrollback
+        assert_eq!(inbox.len(), 3);
+    }
+
+    #[test]
+    fn the_most_severe_message_shows_then_the_newest() {
+        let mut inbox = Inbox::default();
+        inbox.post(Severity::Warning, "temperature clamped", 0.0);
+        inbox.post(Severity::Info, "Generated random values!", 0.0);
+        assert_eq!(inbox.current(0.0).unwrap().text, "temperature clamped");
+        assert_eq!(inbox.waiting(0.0), 1);
+        assert_eq!(
+            inbox.newest_first().next().unwrap().text,
+            "Generated random values!"
+        );
+
+        inbox.post(Severity::Warning, "humidity clamped", 1.0);
+        assert_eq!(inbox.current(1.0).unwrap().text, "humidity clamped");
+        // The info has expired by the time the warnings have
+        assert_eq!(
+            inbox.current(WARNING_SECS + 0.5).unwrap().text,
+            "humidity clamped"
+        );
+        assert_eq!(inbox.current(WARNING_SECS + 1.0), None);
+
+        let texts: Vec<&str> = inbox.newest_first().map(|m| m.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            [
+                "humidity clamped",
+                "Generated random values!",
+                "temperature clamped"
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_are_acknowledged_one_at_a_time_and_outlive_the_scrollback() {
+        let mut inbox = Inbox::default();
+        inbox.post(Severity::Error, "first", 0.0);
+        inbox.post(Severity::Error, "second", 1.0);
+        inbox.post(Severity::Info, "note", 2.0);
+        assert!(inbox.acknowledge(2.0));
+        assert_eq!(inbox.current(2.0).unwrap().text, "first");
+        assert!(inbox.acknowledge(2.0));
+        assert_eq!(inbox.current(2.0).unwrap().text, "note");
+        // Nothing to acknowledge: an info is not taken down early
+        assert!(!inbox.acknowledge(2.0));
+        assert!(!inbox.has_error(2.0));
+        assert_eq!(inbox.current(2.0).unwrap().text, "note");
+
+        inbox.post(Severity::Error, "unread", 3.0);
+        for i in 0..SCROLLBACK * 2 {
+            inbox.post(Severity::Info, format!("info {}", i), 4.0);
+        }
+        assert_eq!(inbox.len(), SCROLLBACK);
+        assert_eq!(inbox.current(100.0).unwrap().text, "unread");
+        assert_eq!(inbox.newest_first().last().unwrap().text, "unread");
+    }
+}
@@ -0,0 +1,239 @@
+// ============================================================================
+// FAILURE INJECTION - Injeção de falhas
+// ============================================================================
+//
+// Rehearsal of sensor failures: an injection replaces or distorts the value
+// of one input for a while, between the input adapters and the controller,
+// so the TUI (key 'F') and the daemon (`inject` command) share it. The
+// readings themselves are left alone, which keeps the gauges showing what
+// the sensor would have reported.
+//
+//     <input> freeze <secs>           hold the value seen when injected
+//     <input> nan <secs>              replace the value with NaN
+//     <input> offset <delta> <secs>   add a constant
+//     <input> noise <amplitude> <secs>  add uniform noise, drawn anew at
+//                                       every computation
+//     clear                           remove every injection
+//
+// Injections expire on the clock they were injected with; a new injection
+// on the same input replaces the previous one (the `Holds` of overrides.rs).
+
+use crate::FuzzyVariable;
+use crate::numbers::{self, Locale};
+use crate::overrides::{Hold, Holds};
+use rand::Rng;
+
+/// How an injection distorts its input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    Freeze,
+    NaN,
+    Offset(f64),
+    Noise(f64),
+}
+
+impl Fault {
+    pub fn label(&self) -> String {
+        match self {
+            Fault::Freeze => "frozen".to_string(),
+            Fault::NaN => "NaN".to_string(),
+            Fault::Offset(delta) => format!("offset {:+.1}", delta),
+            Fault::Noise(amplitude) => format!("noise ±{:.1}", amplitude),
+        }
+    }
+}
+
+/// What one active injection does to its input
+#[derive(Debug, Clone, PartialEq)]
+pub struct Injected {
+    pub fault: Fault,
+    /// Value held by `Fault::Freeze`, captured at the first computation
+    frozen: Option<f64>,
+}
+
+/// One active injection
+pub type Injection = Hold<Injected>;
+
+/// The injection stage of the input pipeline
+#[derive(Debug, Clone, Default)]
+pub struct Injector {
+    injections: Holds<Injected>,
+}
+
+impl Injector {
+    /// Run an injection command (see the module comment) at clock time
+    /// `now`, returning a description of what changed
+    pub fn command(
+        &mut self,
+        text: &str,
+        inputs: &[FuzzyVariable],
+        now: f64,
+    ) -> Result<String, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let number = |word: &str| numbers::parse(word, Locale::Auto).map_err(|e| e.to_string());
+        let (input, fault, secs) = match words[..] {
+            ["clear"] => {
+                self.injections.clear();
+                return Ok("All injections cleared".to_string());
+            }
+            [input, "freeze", secs] => (input, Fault::Freeze, secs),
+            [input, "nan", secs] => (input, Fault::NaN, secs),
+            [input, "offset", delta, secs] => (input, Fault::Offset(number(delta)?), secs),
+            [input, "noise", amplitude, secs] => {
+                (input, Fault::Noise(number(amplitude)?.abs()), secs)
+            }
+            _ => {
+                return Err(
+                    "expected '<input> freeze|nan|offset <delta>|noise <amplitude> <secs>' \
+                     or 'clear'"
+                        .to_string(),
+                );
+            }
+        };
+        if !inputs.iter().any(|var| var.name == input) {
+            return Err(format!("unknown input '{}'", input));
+        }
+        let secs = number(secs)?;
+        if secs <= 0.0 {
+            return Err("the duration must be positive".to_string());
+        }
+
+        self.injections.insert(
+            input,
+            now + secs,
+            Injected {
+                fault,
+                frozen: None,
+            },
+        );
+        Ok(format!(
+            "Injected: {} {} for {}s",
+            input,
+            fault.label(),
+            secs
+        ))
+    }
+
+    /// Drop the injections that expired by `now`, returning whether any did
+    pub fn expire(&mut self, now: f64) -> bool {
+        !self.injections.expire(now).is_empty()
+    }
+
+    pub fn get(&self, input: &str) -> Option<&Injection> {
+        self.injections.get(input)
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.injections.is_empty()
+    }
+
+    /// `input fault` for every active injection, for log lines
+    pub fn describe(&self) -> String {
+        self.injections
+            .iter()
+            .map(|i| format!("{} {}", i.input, i.value.fault.label()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Values the controller sees for the converted input `values`
+    /// (declaration order) at clock time `now`
+    pub fn apply(&mut self, inputs: &[FuzzyVariable], values: &[f64], now: f64) -> Vec<f64> {
+        self.expire(now);
+        let mut rng = rand::thread_rng();
+        inputs
+            .iter()
+            .zip(values)
+            .map(|(var, value)| {
+                let Some(Hold {
+                    value: injection, ..
+                }) = self.injections.get_mut(&var.name)
+                else {
+                    return *value;
+                };
+                match injection.fault {
+                    Fault::Freeze => *injection.frozen.get_or_insert(*value),
+                    Fault::NaN => f64::NAN,
+                    Fault::Offset(delta) => value + delta,
+                    Fault::Noise(amplitude) if amplitude > 0.0 => {
+                        value + rng.gen_range(-amplitude..=amplitude)
+                    }
+                    Fault::Noise(_) => *value,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FuzzyController;
+    use crate::fixtures;
+
+    fn inject(command: &str, now: f64) -> (Injector, Vec<FuzzyVariable>) {
+        let inputs = fixtures::fan().inputs;
+        let mut injector = Injector::default();
+        injector.command(command, &inputs, now).unwrap();
+        (injector, inputs)
+    }
+
+    #[test]
+    fn each_fault_reaches_the_controller_and_expires() {
+        let (mut injector, inputs) = inject("temperature offset 5 10", 0.0);
+        assert_eq!(injector.apply(&inputs, &[20.0, 50.0], 1.0), [25.0, 50.0]);
+        assert_eq!(injector.apply(&inputs, &[20.0, 50.0], 10.0), [20.0, 50.0]);
+        assert!(!injector.is_active());
+
+        let (mut injector, inputs) = inject("humidity freeze 2", 0.0);
+        assert_eq!(injector.apply(&inputs, &[20.0, 40.0], 0.5), [20.0, 40.0]);
+        assert_eq!(injector.apply(&inputs, &[21.0, 90.0], 1.5), [21.0, 40.0]);
+        assert_eq!(injector.apply(&inputs, &[21.0, 90.0], 2.5), [21.0, 90.0]);
+
+        let (mut injector, inputs) = inject("humidity noise 3 5", 0.0);
+        for _ in 0..100 {
+            let seen = injector.apply(&inputs, &[20.0, 50.0], 1.0);
+            assert_eq!(seen[0], 20.0);
+            assert!((seen[1] - 50.0).abs() <= 3.0);
+        }
+
+        let (mut injector, inputs) = inject("temperature nan 1", 0.0);
+        let seen = injector.apply(&inputs, &[30.0, 50.0], 0.5);
+        assert!(seen[0].is_nan());
+        assert!(injector.expire(1.0));
+    }
+
+    #[test]
+    fn a_nan_input_fires_none_of_its_rules() {
+        let (mut injector, inputs) = inject("temperature nan 1", 0.0);
+        let seen = injector.apply(&inputs, &[30.0, 50.0], 0.5);
+        let strengths = FuzzyController::from_system(fixtures::fan())
+            .evaluate(&seen)
+            .strengths;
+        assert!(strengths.iter().all(|s| *s == 0.0), "{:?}", strengths);
+    }
+
+    #[test]
+    fn commands_are_checked_and_replace_earlier_injections() {
+        let inputs = fixtures::fan().inputs;
+        let mut injector = Injector::default();
+        assert_eq!(
+            injector.command("co2 nan 5", &inputs, 0.0),
+            Err("unknown input 'co2'".to_string())
+        );
+        assert!(
+            injector
+                .command("humidity offset inf 5", &inputs, 0.0)
+                .is_err()
+        );
+        assert!(injector.command("humidity nan 0", &inputs, 0.0).is_err());
+
+        injector.command("humidity nan 5", &inputs, 0.0).unwrap();
+        injector
+            .command("humidity offset -10 5", &inputs, 0.0)
+            .unwrap();
+        assert_eq!(injector.describe(), "humidity offset -10.0");
+        injector.command("clear", &inputs, 0.0).unwrap();
+        assert!(!injector.is_active());
+    }
+}
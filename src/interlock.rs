@@ -0,0 +1,202 @@
+// ============================================================================
+// INTERLOCKS - Intertravamentos de segurança
+// ============================================================================
+//
+// An interlock is a switch that forces the first output to a set value
+// whatever the rules conclude, for a door-open contact or an
+// over-temperature cutout:
+//
+//     [[interlock]]          # optional, any number of them
+//     name = "door"          # what switches it
+//     value = 0.0            # the first output while engaged
+//     priority = 10          # optional, 0 by default; the highest wins
+//     engaged = false        # optional, its state at start
+//
+// It is applied after the whole output pipeline (pipeline.rs), and after
+// the daemon's safe output, so no stage softens it. While one is engaged
+// the pipeline is held at its value: the rate limiter goes on from it and
+// the dwell stage counts from when the forced band began, so releasing it
+// hands control back through the rate limit and dwell like any other
+// change of the output. With several engaged the one with the highest
+// priority forces the output, the first declared among equal ones
+// (ties.rs); priorities are integers, so only equal ones tie.
+//
+// The switches are the daemon's `interlock <name> on|off` command
+// (remote.rs), which a bridge from MQTT, a serial line or a GPIO pin can
+// send; the program reads none of those itself. In the TUI 'I' asks for
+// the name of one to switch. The interlock forcing the output is shown as
+// a red banner over the TUI, marked on its history entries, written to
+// the audit trail (audit.rs) and reported by the daemon's `stats`.
+
+use crate::pipeline::{FanBand, OutputPipeline, PipelineOutput};
+use crate::ties;
+
+/// One `[[interlock]]` section
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterlockConfig {
+    pub name: String,
+    /// The first output while engaged
+    pub value: f64,
+    pub priority: i64,
+    /// Engaged at start
+    pub engaged: bool,
+}
+
+/// The interlocks of a system and which of them are engaged
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Interlocks {
+    configs: Vec<InterlockConfig>,
+    /// In the order of `configs`
+    engaged: Vec<bool>,
+}
+
+impl Interlocks {
+    pub fn new(configs: &[InterlockConfig]) -> Self {
+        Interlocks {
+            configs: configs.to_vec(),
+            engaged: configs.iter().map(|c| c.engaged).collect(),
+        }
+    }
+
+    /// Keep engaged the interlocks of the same name that were engaged in
+    /// `previous`, when the system is replaced
+    pub fn take_over(&mut self, previous: &Interlocks) {
+        for (config, engaged) in self.configs.iter().zip(&mut self.engaged) {
+            *engaged |= previous.is_engaged(&config.name);
+        }
+    }
+
+    pub fn is_engaged(&self, name: &str) -> bool {
+        self.configs
+            .iter()
+            .zip(&self.engaged)
+            .any(|(config, engaged)| config.name == name && *engaged)
+    }
+
+    /// Engage or release interlock `name`; whether that changed it
+    pub fn set(&mut self, name: &str, engaged: bool) -> Result<bool, String> {
+        let Some(index) = self.configs.iter().position(|c| c.name == name) else {
+            let names: Vec<&str> = self.configs.iter().map(|c| c.name.as_str()).collect();
+            return Err(match names.is_empty() {
+                true => "no interlocks are configured".to_string(),
+                false => format!("unknown interlock '{}' ({})", name, names.join(", ")),
+            });
+        };
+        let changed = self.engaged[index] != engaged;
+        self.engaged[index] = engaged;
+        Ok(changed)
+    }
+
+    /// The engaged interlock forcing the output: the highest priority,
+    /// the first declared among equal ones
+    pub fn active(&self) -> Option<&InterlockConfig> {
+        let engaged: Vec<&InterlockConfig> = (self.configs.iter())
+            .zip(&self.engaged)
+            .filter(|(_, engaged)| **engaged)
+            .map(|(config, _)| config)
+            .collect();
+        let priorities = engaged.iter().map(|config| config.priority as f64);
+        ties::greatest(priorities, 0.0).map(|index| engaged[index])
+    }
+
+    /// `output`, just out of `pipeline`, with the active interlock's value
+    /// in its place; the pipeline is held at that value
+    pub fn apply(
+        &self,
+        pipeline: &mut OutputPipeline,
+        output: PipelineOutput,
+        now: f64,
+    ) -> PipelineOutput {
+        match self.active() {
+            Some(interlock) => pipeline.hold(interlock.value, now),
+            None => output,
+        }
+    }
+
+    /// What the TUI's banner says while `interlock` forces the output
+    pub fn banner(interlock: &InterlockConfig, shown: &str) -> String {
+        format!(
+            "🔒 INTERLOCK '{}' forces the output to {} ({}, priority {})",
+            interlock.name,
+            shown,
+            FanBand::from_speed(interlock.value).label(),
+            interlock.priority
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::PipelineConfig;
+
+    fn interlock(name: &str, value: f64, priority: i64) -> InterlockConfig {
+        InterlockConfig {
+            name: name.to_string(),
+            value,
+            priority,
+            engaged: false,
+        }
+    }
+
+    #[test]
+    fn the_highest_priority_wins_and_the_first_declared_among_equals() {
+        let mut interlocks = Interlocks::new(&[
+            interlock("door", 0.0, 10),
+            interlock("overtemp", 100.0, 20),
+            interlock("smoke", 0.0, 20),
+        ]);
+        assert_eq!(interlocks.active(), None);
+        assert_eq!(interlocks.set("door", true), Ok(true));
+        assert_eq!(interlocks.set("door", true), Ok(false));
+        assert_eq!(interlocks.active().unwrap().name, "door");
+        interlocks.set("smoke", true).unwrap();
+        interlocks.set("overtemp", true).unwrap();
+        assert_eq!(interlocks.active().unwrap().name, "overtemp");
+        interlocks.set("overtemp", false).unwrap();
+        assert_eq!(interlocks.active().unwrap().name, "smoke");
+        assert_eq!(
+            interlocks.set("window", true).unwrap_err(),
+            "unknown interlock 'window' (door, overtemp, smoke)"
+        );
+
+        let mut reloaded = Interlocks::new(&[interlock("door", 0.0, 1)]);
+        reloaded.take_over(&interlocks);
+        assert!(reloaded.is_engaged("door"));
+        assert_eq!(
+            Interlocks::default().set("door", true).unwrap_err(),
+            "no interlocks are configured"
+        );
+    }
+
+    #[test]
+    fn releasing_goes_back_through_the_rate_limit_and_dwell() {
+        let mut pipeline = OutputPipeline::new(PipelineConfig {
+            rate_limit: 10.0,
+            min_off_secs: 30.0,
+            ..PipelineConfig::default()
+        });
+        let mut interlocks = Interlocks::new(&[interlock("door", 0.0, 0)]);
+        let mut step = |interlocks: &Interlocks, now: f64| {
+            let output = pipeline.process(80.0, now);
+            interlocks.apply(&mut pipeline, output, now)
+        };
+        assert_eq!(step(&interlocks, 0.0).value, 80.0);
+
+        // Engaged: off at once, rate limit and dwell notwithstanding
+        interlocks.set("door", true).unwrap();
+        let output = step(&interlocks, 1.0);
+        assert_eq!((output.value, output.band), (0.0, FanBand::Off));
+
+        // Released: the rate limiter climbs from the forced value, and
+        // turning on again waits out the 30 s off dwell counted from when
+        // the interlock turned it off
+        interlocks.set("door", false).unwrap();
+        assert_eq!(step(&interlocks, 2.0).value, 10.0);
+        let output = step(&interlocks, 3.0);
+        assert_eq!(output.value, 10.0);
+        assert_eq!(output.pending.unwrap().remaining_secs, 28.0);
+        let output = step(&interlocks, 31.0);
+        assert_eq!((output.value, output.band), (80.0, FanBand::High));
+    }
+}
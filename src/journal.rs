@@ -0,0 +1,511 @@
+// ============================================================================
+// EDIT JOURNAL - Diário de alterações
+// ============================================================================
+//
+// Edits made to the running system in the TUI are appended, one per line
+// and synced before they take effect, to a journal next to the state file
+// (`<state>.journal`):
+//
+//     fuzzy_logic journal 1
+//     2025-10-16T10:59:05.200Z group energy-saving enabled false
+//     2025-10-16T10:59:11.700Z group energy-saving weight 0.6
+//     2025-10-16T10:59:20.100Z aggregation softmax 0.05
+//     2025-10-16T10:59:32.400Z caps quiet
+//     2025-10-16T10:59:36.000Z method fan_speed weighted_average
+//     2025-10-16T10:59:40.900Z rules csv configs/fan.rules.csv
+//
+// The first line carries the format version. Entries are stamped in UTC
+// (timestamp.rs); a replay also takes the epoch seconds older journals
+// were stamped with. A clean exit removes the
+// journal, so one that is still there on startup holds the edits of a
+// session that died; the TUI offers to replay them onto the loaded
+// system. Every entry is validated again when replayed and the ones that
+// no longer apply (unknown group, unreadable line) are skipped and
+// reported. A rule reload names its CSV (rulecsv.rs), which a replay
+// reads again as it is then.
+
+use crate::rulecsv;
+use crate::timestamp;
+use crate::{Aggregation, DefuzzMethod, Defuzzification, FuzzySystem};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+
+const VERSION: u32 = 1;
+
+/// One modification of the running system
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    GroupEnabled {
+        group: String,
+        enabled: bool,
+    },
+    GroupWeight {
+        group: String,
+        weight: f64,
+    },
+    /// Output aggregation and soft-max temperature
+    Aggregation {
+        aggregation: Aggregation,
+        temperature: f64,
+    },
+    /// Active cap profile, none for no caps
+    CapProfile {
+        profile: Option<String>,
+    },
+    /// Defuzzification method of one output
+    Method {
+        output: String,
+        method: DefuzzMethod,
+    },
+    /// Rules and groups read from a rule CSV
+    RulesCsv {
+        path: String,
+    },
+}
+
+impl Edit {
+    fn to_line(&self) -> String {
+        match self {
+            Edit::GroupEnabled { group, enabled } => format!("group {} enabled {}", group, enabled),
+            Edit::GroupWeight { group, weight } => format!("group {} weight {}", group, weight),
+            Edit::Aggregation {
+                aggregation,
+                temperature,
+            } => format!("aggregation {} {}", aggregation.name(), temperature),
+            Edit::CapProfile { profile } => format!("caps {}", profile.as_deref().unwrap_or("off")),
+            Edit::Method { output, method } => format!("method {} {}", output, method.name()),
+            Edit::RulesCsv { path } => format!("rules csv {}", path),
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        // The path may hold spaces
+        if let Some(path) = line.strip_prefix("rules csv ") {
+            return Some(Edit::RulesCsv {
+                path: path.to_string(),
+            });
+        }
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["group", group, "enabled", enabled] => Some(Edit::GroupEnabled {
+                group: group.to_string(),
+                enabled: enabled.parse().ok()?,
+            }),
+            ["group", group, "weight", weight] => Some(Edit::GroupWeight {
+                group: group.to_string(),
+                weight: weight.parse().ok()?,
+            }),
+            ["aggregation", aggregation, temperature] => Some(Edit::Aggregation {
+                aggregation: Aggregation::parse(aggregation)?,
+                temperature: temperature.parse().ok()?,
+            }),
+            ["caps", "off"] => Some(Edit::CapProfile { profile: None }),
+            ["caps", profile] => Some(Edit::CapProfile {
+                profile: Some(profile.to_string()),
+            }),
+            ["method", output, method] => Some(Edit::Method {
+                output: output.to_string(),
+                method: DefuzzMethod::parse(method)?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Short description for status messages
+    pub fn describe(&self) -> String {
+        match self {
+            Edit::GroupEnabled { group, enabled } => {
+                format!("group {} {}", group, if *enabled { "on" } else { "off" })
+            }
+            Edit::GroupWeight { group, weight } => format!("group {} weight {}", group, weight),
+            Edit::Aggregation {
+                aggregation: Aggregation::SoftMax,
+                temperature,
+            } => format!("softmax aggregation, temperature {}", temperature),
+            Edit::Aggregation { aggregation, .. } => format!("{} aggregation", aggregation.name()),
+            Edit::CapProfile { profile: None } => "caps off".to_string(),
+            Edit::CapProfile {
+                profile: Some(profile),
+            } => format!("{} caps", profile),
+            Edit::Method { output, method } => format!("{} by {}", output, method.name()),
+            Edit::RulesCsv { path } => format!("rules from {}", path),
+        }
+    }
+
+    /// Apply to `system`, failing when the edit does not fit it
+    pub fn apply(&self, system: &mut FuzzySystem) -> Result<(), String> {
+        let name = match self {
+            Edit::GroupEnabled { group, .. } | Edit::GroupWeight { group, .. } => group,
+            Edit::Aggregation {
+                aggregation,
+                temperature,
+            } => {
+                if !(*temperature > 0.0 && temperature.is_finite()) {
+                    return Err("temperature must be above 0".to_string());
+                }
+                let settings = Defuzzification {
+                    aggregation: *aggregation,
+                    ..system.defuzzification.clone()
+                };
+                for output in &system.outputs {
+                    settings.check(output)?;
+                }
+                system.defuzzification.aggregation = *aggregation;
+                system.defuzzification.softmax_temperature = *temperature;
+                return Ok(());
+            }
+            Edit::CapProfile { profile } => {
+                let settings = &mut system.defuzzification;
+                if let Some(name) = profile
+                    && !settings.cap_profiles().contains(&name.as_str())
+                {
+                    return Err(format!("unknown cap profile '{}'", name));
+                }
+                settings.cap_profile = profile.clone();
+                return Ok(());
+            }
+            Edit::Method { output, method } => {
+                let index = (system.outputs.iter())
+                    .position(|o| o.name == *output)
+                    .ok_or_else(|| format!("unknown output '{}'", output))?;
+                let mut changed = system.outputs[index].clone();
+                changed.defuzz.method = Some(*method);
+                system.defuzzification.check(&changed)?;
+                system.outputs[index] = changed;
+                return Ok(());
+            }
+            Edit::RulesCsv { path } => {
+                *system = rulecsv::load(path, system)?;
+                return Ok(());
+            }
+        };
+        let group = system
+            .groups
+            .iter_mut()
+            .find(|g| g.name == *name)
+            .ok_or_else(|| format!("unknown group '{}'", name))?;
+        match self {
+            Edit::GroupEnabled { enabled, .. } => group.enabled = *enabled,
+            Edit::GroupWeight { weight, .. } if (0.0..=1.0).contains(weight) => {
+                group.weight = *weight
+            }
+            Edit::GroupWeight { .. } => return Err("weight must be between 0 and 1".to_string()),
+            Edit::Aggregation { .. }
+            | Edit::CapProfile { .. }
+            | Edit::Method { .. }
+            | Edit::RulesCsv { .. } => unreachable!("handled above"),
+        }
+        Ok(())
+    }
+}
+
+/// Journal file for the state file at `state_path`
+pub fn path_for(state_path: &str) -> String {
+    format!("{}.journal", state_path)
+}
+
+/// Entries of a journal left behind, as edits or, for lines that cannot be
+/// read, a description of the problem. A missing journal has no entries.
+pub fn read(path: &str) -> Result<Vec<Result<Edit, String>>, String> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("{}: {}", path, e)),
+    };
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        None => return Ok(Vec::new()),
+        Some((_, header)) if header == format!("fuzzy_logic journal {}", VERSION) => {}
+        Some((_, header)) => {
+            return Err(format!("{}: unsupported journal '{}'", path, header));
+        }
+    }
+    Ok(lines
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            // A line cut short by the crash is reported like any other
+            line.split_once(' ')
+                .filter(|(time, _)| timestamp::parse(time).is_some())
+                .and_then(|(_, edit)| Edit::parse(edit))
+                .ok_or_else(|| format!("line {}: unreadable entry '{}'", index + 1, line))
+        })
+        .collect())
+}
+
+/// Open journal of the running session
+pub struct Journal {
+    path: String,
+    file: File,
+}
+
+impl Journal {
+    /// Append to the journal at `path`, starting it if there is none
+    pub fn open(path: &str) -> Result<Self, String> {
+        let error = |e: std::io::Error| format!("{}: {}", path, e);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(error)?;
+        if file.metadata().map_err(error)?.len() == 0 {
+            writeln!(file, "fuzzy_logic journal {}", VERSION).map_err(error)?;
+        }
+        Ok(Journal {
+            path: path.to_string(),
+            file,
+        })
+    }
+
+    /// Write one edit and wait until it is on disk
+    pub fn record(&mut self, edit: &Edit) -> Result<(), String> {
+        writeln!(
+            self.file,
+            "{} {}",
+            timestamp::rfc3339(timestamp::now()),
+            edit.to_line()
+        )
+        .and_then(|_| self.file.sync_data())
+        .map_err(|e| format!("{}: {}", self.path, e))
+    }
+
+    /// Drop every entry, after the recovery was declined
+    pub fn discard(&mut self) -> Result<(), String> {
+        self.file
+            .set_len(0)
+            .and_then(|_| writeln!(self.file, "fuzzy_logic journal {}", VERSION))
+            .map_err(|e| format!("{}: {}", self.path, e))
+    }
+
+    /// Remove the journal after a clean exit
+    pub fn close(self) -> Result<(), String> {
+        drop(self.file);
+        std::fs::remove_file(&self.path).map_err(|e| format!("{}: {}", self.path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+
+    const CHILD: &str = "FUZZY_JOURNAL_CHILD";
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("fuzzy-{}-{}", name, std::process::id()));
+        path.to_string_lossy().to_string()
+    }
+
+    fn system() -> FuzzySystem {
+        config::parse_system(
+            "[[group]]\nname = \"comfort\"\n\
+             rules = [\"IF x IS A THEN y IS B\"]\n\
+             [[input]]\nname = \"x\"\nmin = 0\nmax = 10\n\
+             [[input.set]]\nname = \"A\"\nshape = \"trapezoidal\"\nparams = [-1, 0, 10, 11]\n\
+             [output]\nname = \"y\"\nmin = 0\nmax = 10\n\
+             [[output.set]]\nname = \"B\"\nshape = \"triangular\"\nparams = [0, 2, 4]\n",
+        )
+        .unwrap()
+    }
+
+    /// Body of the process the crash test kills; a no-op in a normal run
+    #[test]
+    fn journaling_child() {
+        let Ok(path) = std::env::var(CHILD) else {
+            return;
+        };
+        let mut journal = Journal::open(&path).unwrap();
+        for edit in [
+            Edit::GroupEnabled {
+                group: "comfort".to_string(),
+                enabled: false,
+            },
+            Edit::GroupWeight {
+                group: "gone".to_string(),
+                weight: 0.5,
+            },
+            Edit::GroupWeight {
+                group: "comfort".to_string(),
+                weight: 0.25,
+            },
+            Edit::Aggregation {
+                aggregation: Aggregation::SoftMax,
+                temperature: 0.05,
+            },
+        ] {
+            journal.record(&edit).unwrap();
+        }
+        println!("ready");
+        loop {
+            std::thread::sleep(std::time::Duration::from_secs(60));
+        }
+    }
+
+    #[test]
+    fn a_killed_session_leaves_a_journal_that_replays() {
+        let path = temp_path("killed.journal");
+        let _ = std::fs::remove_file(&path);
+        let mut child = Command::new(std::env::current_exe().unwrap())
+            .args(["journal::tests::journaling_child", "--exact", "--nocapture"])
+            .env(CHILD, &path)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut line = String::new();
+        while !line.contains("ready") {
+            line.clear();
+            assert_ne!(
+                stdout.read_line(&mut line).unwrap(),
+                0,
+                "child exited early"
+            );
+        }
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        // A write the crash interrupted halfway
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "1760612360.1 group comfort wei").unwrap();
+        drop(file);
+
+        let entries = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(
+            entries[4],
+            Err("line 6: unreadable entry '1760612360.1 group comfort wei'".to_string())
+        );
+
+        let mut system = system();
+        let skipped: Vec<String> = entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Ok(edit) => edit.apply(&mut system).err(),
+                Err(e) => Some(e.clone()),
+            })
+            .collect();
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(skipped[0], "unknown group 'gone'");
+        assert!(!system.groups[0].enabled);
+        assert_eq!(system.groups[0].weight, 0.25);
+        assert_eq!(system.defuzzification.aggregation, Aggregation::SoftMax);
+        assert_eq!(system.defuzzification.softmax_temperature, 0.05);
+    }
+
+    #[test]
+    fn a_missing_journal_has_no_entries_and_other_versions_are_refused() {
+        let path = temp_path("version.journal");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(read(&path).unwrap(), Vec::new());
+
+        std::fs::write(
+            &path,
+            "fuzzy_logic journal 2\n1 group comfort enabled true\n",
+        )
+        .unwrap();
+        let result = read(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            result
+                .unwrap_err()
+                .contains("unsupported journal 'fuzzy_logic journal 2'")
+        );
+    }
+
+    #[test]
+    fn entries_are_stamped_in_utc_and_either_stamp_replays() {
+        let path = temp_path("stamps.journal");
+        let _ = std::fs::remove_file(&path);
+        let edit = Edit::CapProfile { profile: None };
+        Journal::open(&path).unwrap().record(&edit).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let stamp = text.lines().nth(1).unwrap().split(' ').next().unwrap();
+        assert!(stamp.ends_with('Z') && stamp.len() == 24, "{}", stamp);
+
+        std::fs::write(
+            &path,
+            "fuzzy_logic journal 1
+\
+             1760612345.2 caps off
+\
+             2025-10-16T12:59:05.2+02:00 caps off
+\
+             yesterday caps off
+",
+        )
+        .unwrap();
+        let entries = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries[..2], [Ok(edit.clone()), Ok(edit)]);
+        assert_eq!(
+            entries[2],
+            Err("line 4: unreadable entry 'yesterday caps off'".to_string())
+        );
+    }
+
+    #[test]
+    fn discarding_keeps_the_header_and_closing_removes_the_journal() {
+        let path = temp_path("discard.journal");
+        let _ = std::fs::remove_file(&path);
+        let edit = Edit::GroupEnabled {
+            group: "comfort".to_string(),
+            enabled: true,
+        };
+        let mut journal = Journal::open(&path).unwrap();
+        journal.record(&edit).unwrap();
+        assert_eq!(read(&path).unwrap(), vec![Ok(edit.clone())]);
+
+        journal.discard().unwrap();
+        assert_eq!(read(&path).unwrap(), Vec::new());
+        journal.record(&edit).unwrap();
+        assert_eq!(read(&path).unwrap(), vec![Ok(edit)]);
+
+        journal.close().unwrap();
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
+    #[test]
+    fn weights_outside_the_unit_range_do_not_apply() {
+        let mut system = system();
+        let edit = Edit::parse("group comfort weight 1.5").unwrap();
+        assert!(edit.apply(&mut system).is_err());
+        assert_eq!(system.groups[0].weight, 1.0);
+    }
+
+    #[test]
+    fn a_method_edit_replays_unless_the_aggregation_rules_it_out() {
+        let mut system = system();
+        let edit = Edit::parse("method y weighted_average").unwrap();
+        assert_eq!(edit.to_line(), "method y weighted_average");
+        edit.apply(&mut system).unwrap();
+        assert_eq!(
+            system.defuzzification.method_for(system.output()),
+            DefuzzMethod::WeightedAverage
+        );
+        let softmax = Edit::parse("aggregation softmax 0.1").unwrap();
+        assert!(
+            softmax
+                .apply(&mut system)
+                .unwrap_err()
+                .contains("max aggregation")
+        );
+        assert_eq!(system.defuzzification.aggregation, Aggregation::Max);
+
+        Edit::parse("method y centroid")
+            .unwrap()
+            .apply(&mut system)
+            .unwrap();
+        softmax.apply(&mut system).unwrap();
+        let back = Edit::parse("method y weighted_average").unwrap();
+        assert!(back.apply(&mut system).is_err());
+        assert_eq!(
+            system.defuzzification.method_for(system.output()),
+            DefuzzMethod::Centroid
+        );
+        assert_eq!(
+            Edit::parse("method z centroid").unwrap().apply(&mut system),
+            Err("unknown output 'z'".to_string())
+        );
+    }
+}
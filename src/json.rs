@@ -0,0 +1,57 @@
+// ============================================================================
+// JSON TEXT - Texto JSON
+// ============================================================================
+//
+// What the program writes as JSON: quantized tables (quantize.rs),
+// annotation exports (annotations.rs) and the co-simulation's replies
+// (cosim.rs). Strings are quoted with every control character escaped, the
+// common ones by their short forms and the others as \u00XX. A number that
+// is not finite, which JSON has no token for, is written as null.
+
+/// `text` as a JSON string, quotes included
+pub fn string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => {
+                out.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `value` as a JSON number, null when it is NaN or infinite
+pub fn number(value: f64) -> String {
+    match value.is_finite() {
+        true => value.to_string(),
+        false => "null".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_characters_are_escaped_and_non_finite_numbers_are_null() {
+        assert_eq!(string("a \"b\" \\ c"), r#""a \"b\" \\ c""#);
+        assert_eq!(string("\n\r\t"), r#""\n\r\t""#);
+        assert_eq!(string("\u{1}x\u{1f}\u{7f}"), r#""\u0001x\u001f\u007f""#);
+        assert_eq!(string("°C é"), "\"°C é\"");
+
+        assert_eq!(number(2.5), "2.5");
+        assert_eq!(number(-0.0), "-0");
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(number(value), "null");
+        }
+    }
+}
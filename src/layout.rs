@@ -0,0 +1,331 @@
+// ============================================================================
+// LAYOUT - Disposição dos painéis
+// ============================================================================
+//
+// The TUI's body is the readings panel (the input and output gauges, left)
+// and the sets panel (the memberships, or the rule table, matrix, surface
+// or curves in their place, right) side by side, over the history. Each
+// of the three can be hidden and sized:
+//
+//     Tab          focus the next panel
+//     Ctrl+→ / ↑   grow the focused panel; Ctrl+← / ↓ shrink it
+//     p            hide or show the focused panel
+//     P            back to the default layout
+//
+// The readings and sets panels share the width by weight, 1 to 9 each (5
+// and 5 by default); the history's size is its height in rows, 4 to 30
+// (12 by default). A hidden panel leaves its room to the others. With all
+// three hidden the body says how to bring them back, and a terminal
+// smaller than 40x12 shows only its size. The tutorial draws the default
+// layout whatever this one is, since it points at every panel.
+//
+// The layout lives in the app state file (state.rs) when it is not the
+// default; the daemon, which shares the file, keeps it as it found it:
+//
+//     [layout]
+//     focus = "history"
+//     hidden = ["sets"]
+//     readings = 6
+//     sets = 4
+//     history = 16
+
+use crate::config;
+#[cfg(feature = "tui")]
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+
+/// Below this the TUI draws only a note of the terminal's size
+#[cfg(feature = "tui")]
+pub const MIN_WIDTH: u16 = 40;
+#[cfg(feature = "tui")]
+pub const MIN_HEIGHT: u16 = 12;
+
+/// Rows the readings and sets panels keep when the history is shown
+#[cfg(feature = "tui")]
+const MIN_MAIN_HEIGHT: u16 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pane {
+    Readings,
+    Sets,
+    History,
+}
+
+impl Pane {
+    pub const ALL: [Pane; 3] = [Pane::Readings, Pane::Sets, Pane::History];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pane::Readings => "readings",
+            Pane::Sets => "sets",
+            Pane::History => "history",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Pane::ALL.into_iter().find(|pane| pane.name() == name)
+    }
+
+    /// Smallest, largest and default size, and the size of one step
+    fn sizes(self) -> (u16, u16, u16, u16) {
+        match self {
+            Pane::Readings | Pane::Sets => (1, 9, 5, 1),
+            Pane::History => (4, 30, 12, 2),
+        }
+    }
+}
+
+/// Which panels are shown, how big, and which one the keys resize
+#[derive(Debug, Clone, PartialEq)]
+pub struct PanelLayout {
+    /// In the order of `Pane::ALL`
+    sizes: [u16; 3],
+    hidden: [bool; 3],
+    focus: Pane,
+}
+
+impl Default for PanelLayout {
+    fn default() -> Self {
+        PanelLayout {
+            sizes: Pane::ALL.map(|pane| pane.sizes().2),
+            hidden: [false; 3],
+            focus: Pane::Readings,
+        }
+    }
+}
+
+impl PanelLayout {
+    pub fn focus(&self) -> Pane {
+        self.focus
+    }
+
+    /// Focus the next panel, hidden ones included so they can be shown
+    pub fn focus_next(&mut self) -> Pane {
+        self.focus = Pane::ALL[(self.focus as usize + 1) % Pane::ALL.len()];
+        self.focus
+    }
+
+    pub fn is_visible(&self, pane: Pane) -> bool {
+        !self.hidden[pane as usize]
+    }
+
+    pub fn size(&self, pane: Pane) -> u16 {
+        self.sizes[pane as usize]
+    }
+
+    /// Hide or show the focused panel; whether it is shown now
+    pub fn toggle(&mut self) -> bool {
+        let hidden = &mut self.hidden[self.focus as usize];
+        *hidden = !*hidden;
+        !*hidden
+    }
+
+    /// Grow (positive) or shrink the focused panel by `steps`, within its
+    /// limits; its size now
+    pub fn resize(&mut self, steps: i16) -> u16 {
+        let (min, max, _, step) = self.focus.sizes();
+        let size = &mut self.sizes[self.focus as usize];
+        *size = (*size as i32 + steps as i32 * step as i32).clamp(min as i32, max as i32) as u16;
+        *size
+    }
+
+    pub fn reset(&mut self) {
+        *self = PanelLayout::default();
+    }
+
+    /// The layout in a state file; the default when there is none
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut layout = PanelLayout::default();
+        let sections = config::parse_document(text)?;
+        let Some(section) = sections.iter().find(|s| s.header == "layout") else {
+            return Ok(layout);
+        };
+        let pane = |key: &str, name: &str| {
+            Pane::parse(name).ok_or_else(|| {
+                format!(
+                    "line {}: unknown panel '{}' in '{}' (readings, sets or history)",
+                    section.line_of(key),
+                    name,
+                    key
+                )
+            })
+        };
+        if section.get("focus").is_some() {
+            layout.focus = pane("focus", section.str("focus")?)?;
+        }
+        if section.get("hidden").is_some() {
+            for (name, _) in section.strs("hidden")? {
+                layout.hidden[pane("hidden", &name)? as usize] = true;
+            }
+        }
+        for pane in Pane::ALL {
+            let (min, max, default, _) = pane.sizes();
+            let size = section.num_or(pane.name(), default as f64)?;
+            if size.fract() != 0.0 || size < min as f64 || size > max as f64 {
+                return Err(format!(
+                    "line {}: '{}' must be a whole number from {} to {}",
+                    section.line_of(pane.name()),
+                    pane.name(),
+                    min,
+                    max
+                ));
+            }
+            layout.sizes[pane as usize] = size as u16;
+        }
+        Ok(layout)
+    }
+
+    /// The layout in the state file at `path`; the default when there is
+    /// no file
+    pub fn load(path: &str) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => PanelLayout::parse(&text).map_err(|e| format!("{}: {}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(PanelLayout::default()),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        }
+    }
+
+    /// The `[layout]` section of the state file, nothing for the default
+    pub fn to_toml(&self) -> String {
+        if *self == PanelLayout::default() {
+            return String::new();
+        }
+        let hidden: Vec<String> = Pane::ALL
+            .into_iter()
+            .filter(|pane| !self.is_visible(*pane))
+            .map(|pane| config::quote(pane.name()))
+            .collect();
+        let mut out = format!("\n[layout]\nfocus = {}\n", config::quote(self.focus.name()));
+        if !hidden.is_empty() {
+            out.push_str(&format!("hidden = [{}]\n", hidden.join(", ")));
+        }
+        for pane in Pane::ALL {
+            out.push_str(&format!("{} = {}\n", pane.name(), self.size(pane)));
+        }
+        out
+    }
+
+    /// Where each shown panel goes in the body `area`
+    #[cfg(feature = "tui")]
+    pub fn split(&self, area: Rect) -> Areas {
+        let readings = self.is_visible(Pane::Readings);
+        let sets = self.is_visible(Pane::Sets);
+        let (main, history) = match (readings || sets, self.is_visible(Pane::History)) {
+            (true, true) => {
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([
+                        Constraint::Min(MIN_MAIN_HEIGHT),
+                        Constraint::Length(self.size(Pane::History)),
+                    ])
+                    .split(area);
+                (Some(chunks[0]), Some(chunks[1]))
+            }
+            (true, false) => (Some(area), None),
+            (false, true) => (None, Some(area)),
+            (false, false) => (None, None),
+        };
+        let (readings, sets) = match (main, readings, sets) {
+            (Some(main), true, true) => {
+                let left = self.size(Pane::Readings) as u32;
+                let percent = (100 * left / (left + self.size(Pane::Sets) as u32)) as u16;
+                let chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([
+                        Constraint::Percentage(percent),
+                        Constraint::Percentage(100 - percent),
+                    ])
+                    .split(main);
+                (Some(chunks[0]), Some(chunks[1]))
+            }
+            (Some(main), true, false) => (Some(main), None),
+            (Some(main), false, true) => (None, Some(main)),
+            _ => (None, None),
+        };
+        Areas {
+            readings,
+            sets,
+            history,
+            main,
+        }
+    }
+}
+
+/// The body split by a `PanelLayout`; none for a hidden panel
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Areas {
+    pub readings: Option<Rect>,
+    pub sets: Option<Rect>,
+    pub history: Option<Rect>,
+    /// The readings and sets panels together
+    pub main: Option<Rect>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_stay_within_limits_and_the_layout_round_trips() {
+        let mut layout = PanelLayout::default();
+        assert_eq!(layout.to_toml(), "");
+        assert_eq!(layout.resize(10), 9);
+        assert_eq!(layout.focus_next(), Pane::Sets);
+        assert_eq!(layout.resize(-10), 1);
+        assert!(!layout.toggle());
+        assert_eq!(layout.focus_next(), Pane::History);
+        assert_eq!(layout.resize(2), 16);
+        assert_eq!(
+            layout.to_toml(),
+            "\n[layout]\nfocus = \"history\"\nhidden = [\"sets\"]\n\
+             readings = 9\nsets = 1\nhistory = 16\n"
+        );
+        assert_eq!(PanelLayout::parse(&layout.to_toml()).unwrap(), layout);
+
+        assert_eq!(
+            PanelLayout::parse("[layout]\nfocus = \"rules\"\n").unwrap_err(),
+            "line 2: unknown panel 'rules' in 'focus' (readings, sets or history)"
+        );
+        assert_eq!(
+            PanelLayout::parse("[layout]\nhistory = 2\n").unwrap_err(),
+            "line 2: 'history' must be a whole number from 4 to 30"
+        );
+        layout.reset();
+        assert_eq!(layout, PanelLayout::default());
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn hidden_panels_leave_their_room_to_the_others() {
+        let area = Rect::new(0, 3, 100, 34);
+        let areas = PanelLayout::default().split(area);
+        assert_eq!(areas.readings, Some(Rect::new(0, 3, 50, 22)));
+        assert_eq!(areas.sets, Some(Rect::new(50, 3, 50, 22)));
+        assert_eq!(areas.history, Some(Rect::new(0, 25, 100, 12)));
+
+        let mut layout = PanelLayout::default();
+        layout.resize(2);
+        layout.focus_next();
+        layout.toggle();
+        let areas = layout.split(area);
+        assert_eq!(areas.readings, Some(Rect::new(0, 3, 100, 22)));
+        assert_eq!(areas.sets, None);
+
+        layout.toggle();
+        layout.focus_next();
+        layout.toggle();
+        let areas = layout.split(area);
+        // 7 to 5
+        assert_eq!(areas.readings, Some(Rect::new(0, 3, 58, 34)));
+        assert_eq!(areas.sets, Some(Rect::new(58, 3, 42, 34)));
+        assert_eq!(areas.history, None);
+
+        for _ in Pane::ALL {
+            layout.focus_next();
+            layout.toggle();
+        }
+        let areas = layout.split(area);
+        assert_eq!(areas.history, Some(area));
+        assert_eq!(areas.main, None);
+    }
+}
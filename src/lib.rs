@@ -0,0 +1,3236 @@
+// ============================================================================
+// FUZZY LOGIC - Biblioteca do controlador
+// ============================================================================
+//
+// The controller and the engine of every mode, as a library: the
+// `fuzzy_logic` binary (main.rs) only picks the mode from its command line,
+// and the programs under examples/ drive the same API another crate would.
+// `cargo test` runs the examples too (Cargo.toml), so a change that breaks
+// them fails there.
+//
+// What is public has been made so as the examples needed it: the core
+// types below, building a system in code (`FuzzyVariable::new`, `with_set`,
+// `FuzzyRule::parse`, `FuzzySystem::new`) and computing with it
+// (`FuzzyController`), gathered in `prelude` (prelude.rs), and the modules
+// config, pipeline, stepper and surface. Fields stay private; the modules
+// reach them as children of this one. Every other module is public only
+// because the binary's modes live in it, and is hidden from the docs; it
+// may change in any release. tests/public_api.rs checks the documented
+// surface against a listing, so changing it is a decision.
+// Items of the shared core that only some modes use carry those modes'
+// features, so every build is checked for dead code.
+
+#[doc(hidden)]
+pub mod activation;
+#[doc(hidden)]
+pub mod adapt;
+#[doc(hidden)]
+pub mod adapters;
+#[doc(hidden)]
+pub mod alarm;
+#[doc(hidden)]
+pub mod align;
+#[doc(hidden)]
+pub mod annotations;
+#[cfg(feature = "daemon")]
+#[doc(hidden)]
+pub mod audit;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod autorange;
+#[doc(hidden)]
+pub mod backup;
+#[cfg(feature = "batch")]
+#[doc(hidden)]
+pub mod batch;
+#[doc(hidden)]
+pub mod bookmarks;
+#[doc(hidden)]
+pub mod budget;
+#[doc(hidden)]
+pub mod bundle;
+#[doc(hidden)]
+pub mod cascade;
+#[doc(hidden)]
+pub mod chain;
+#[cfg(feature = "batch")]
+#[doc(hidden)]
+pub mod checkpoint;
+#[doc(hidden)]
+pub mod comfort;
+#[doc(hidden)]
+pub mod complexity;
+pub mod config;
+#[cfg(feature = "cosim")]
+#[doc(hidden)]
+pub mod cosim;
+#[doc(hidden)]
+pub mod diff;
+#[doc(hidden)]
+pub mod envelope;
+#[doc(hidden)]
+pub mod exec;
+#[doc(hidden)]
+pub mod fingerprint;
+#[cfg(any(test, feature = "test-fixtures"))]
+pub mod fixtures;
+#[doc(hidden)]
+pub mod forecast;
+#[doc(hidden)]
+pub mod fusion;
+#[doc(hidden)]
+pub mod fuzz;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod gauge;
+#[doc(hidden)]
+pub mod generator;
+#[doc(hidden)]
+pub mod guard;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod inbox;
+#[cfg(any(feature = "tui", feature = "daemon"))]
+#[doc(hidden)]
+pub mod inject;
+#[doc(hidden)]
+pub mod interlock;
+#[cfg(any(feature = "tui", feature = "daemon"))]
+#[doc(hidden)]
+pub mod journal;
+#[doc(hidden)]
+pub mod json;
+#[doc(hidden)]
+pub mod layout;
+#[doc(hidden)]
+pub mod links;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod lod;
+#[doc(hidden)]
+pub mod logging;
+#[doc(hidden)]
+pub mod matrix;
+#[doc(hidden)]
+pub mod monotone;
+#[doc(hidden)]
+pub mod noise;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod nudge;
+#[doc(hidden)]
+pub mod numbers;
+#[doc(hidden)]
+pub mod objective;
+#[doc(hidden)]
+pub mod oscillation;
+#[doc(hidden)]
+pub mod overrides;
+#[doc(hidden)]
+pub mod palette;
+pub mod pipeline;
+pub mod prelude;
+#[doc(hidden)]
+pub mod quantize;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod refine;
+#[cfg(all(unix, feature = "daemon"))]
+#[doc(hidden)]
+pub mod remote;
+#[doc(hidden)]
+pub mod repl;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod rulecolor;
+#[doc(hidden)]
+pub mod rulecsv;
+#[cfg(any(feature = "tui", feature = "daemon"))]
+#[doc(hidden)]
+pub mod safemode;
+#[doc(hidden)]
+pub mod scenario;
+#[doc(hidden)]
+pub mod schema;
+#[doc(hidden)]
+pub mod shadow;
+#[cfg(any(feature = "tui", feature = "daemon"))]
+#[doc(hidden)]
+pub mod shutdown;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod spotlight;
+#[doc(hidden)]
+pub mod state;
+pub mod stepper;
+#[doc(hidden)]
+pub mod suggest;
+pub mod surface;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod termguard;
+#[doc(hidden)]
+pub mod ties;
+#[doc(hidden)]
+pub mod timestamp;
+#[doc(hidden)]
+pub mod trigger;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod tui;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod tutorial;
+#[doc(hidden)]
+pub mod units;
+#[cfg(feature = "tui")]
+#[doc(hidden)]
+pub mod viewport;
+#[doc(hidden)]
+pub mod window;
+#[cfg(any(feature = "daemon", feature = "cosim"))]
+#[doc(hidden)]
+pub mod wire;
+
+use activation::{Activations, Session};
+use adapters::InputKind;
+use alarm::AlarmConfig;
+use budget::{Budget, BudgetConfig};
+use cascade::CascadeConfig;
+use comfort::ComfortConfig;
+use envelope::EnvelopeConfig;
+use exec::ExecConfig;
+use generator::GeneratorConfig;
+use guard::TuningConfig;
+use interlock::InterlockConfig;
+use logging::{Change, Streak, event};
+use noise::NoiseConfig;
+use objective::ObjectiveConfig;
+use oscillation::OscillationConfig;
+use pipeline::{Clock, PipelineConfig};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+use trigger::RecomputeConfig;
+use units::Unit;
+
+// ============================================================================
+// MEMBERSHIP FUNCTIONS - Funções de Pertinência
+// ============================================================================
+
+/// Trapezoidal membership function
+/// Returns the degree of membership [0.0, 1.0]; a vertical side (a = b or
+/// c = d, as on a shoulder) includes its top corner
+fn trapezoidal(x: f64, a: f64, b: f64, c: f64, d: f64) -> f64 {
+    if x >= b && x <= c {
+        1.0
+    } else if x <= a || x >= d {
+        0.0
+    } else if x < b {
+        (x - a) / (b - a)
+    } else {
+        (d - x) / (d - c)
+    }
+}
+
+/// Triangular membership function
+/// Returns the degree of membership [0.0, 1.0]; like trapezoidal's, a
+/// vertical side includes the peak
+fn triangular(x: f64, a: f64, b: f64, c: f64) -> f64 {
+    if x == b {
+        1.0
+    } else if x <= a || x >= c {
+        0.0
+    } else if x < b {
+        (x - a) / (b - a)
+    } else {
+        (c - x) / (c - b)
+    }
+}
+
+/// Gaussian membership function
+/// Returns the degree of membership [0.0, 1.0], 1 at the mean and never
+/// quite 0; a negative sigma counts as its absolute value, and a zero one
+/// leaves a single point at the mean
+fn gaussian(x: f64, mean: f64, sigma: f64) -> f64 {
+    let sigma = sigma.abs();
+    if sigma == 0.0 {
+        return if x == mean { 1.0 } else { 0.0 };
+    }
+    (-0.5 * ((x - mean) / sigma).powi(2)).exp()
+}
+
+/// Sigmoid membership function
+/// Returns the degree of membership [0.0, 1.0], exactly 0.5 at `c` and
+/// rising toward 1 past it for a positive slope `a`, 0 and 1 at the
+/// infinities; a zero slope is flat at 0.5
+fn sigmoid(x: f64, c: f64, a: f64) -> f64 {
+    if a == 0.0 {
+        return 0.5;
+    }
+    1.0 / (1.0 + (-a * (x - c)).exp())
+}
+
+/// `1 - sigmoid`, falling past `c` for a positive slope `a`, computed
+/// without the cancellation of the subtraction
+fn sigmoid_complement(x: f64, c: f64, a: f64) -> f64 {
+    sigmoid(x, c, -a)
+}
+
+/// Generalized bell membership function, `1 / (1 + |(x - c) / a|^(2b))`
+/// Returns the degree of membership [0.0, 1.0], 1 at `c` and 0.5 at
+/// `c ± a`, flatter on top and steeper on the sides as `b` grows; negative
+/// `a` and `b` count as their absolute values, a zero `a` leaves a single
+/// point at `c`, and a zero `b` is flat at 0.5
+fn gbell(x: f64, a: f64, b: f64, c: f64) -> f64 {
+    let (a, b) = (a.abs(), b.abs());
+    if b == 0.0 {
+        return 0.5;
+    }
+    if a == 0.0 {
+        return if x == c { 1.0 } else { 0.0 };
+    }
+    1.0 / (1.0 + ((x - c) / a).abs().powf(2.0 * b))
+}
+
+/// S-shaped membership function
+/// Returns the degree of membership [0.0, 1.0]: 0 up to `a`, 1 from `b`,
+/// and between them two quadratic halves meeting at 0.5 halfway; `a == b`
+/// is a step at `a`. Panics if `a > b`, which `from_params` refuses first
+fn s_curve(x: f64, a: f64, b: f64) -> f64 {
+    assert!(
+        a <= b,
+        "s_curve expects a <= b, got a = {} and b = {}",
+        a,
+        b
+    );
+    if x >= b {
+        1.0
+    } else if x <= a {
+        0.0
+    } else if x <= (a + b) / 2.0 {
+        2.0 * ((x - a) / (b - a)).powi(2)
+    } else {
+        1.0 - 2.0 * ((x - b) / (b - a)).powi(2)
+    }
+}
+
+/// Z-shaped membership function, `1 - s_curve`: 1 up to `a`, 0 from `b`.
+/// The two add up to exactly 1 at any `x`. Panics if `a > b`
+fn z_curve(x: f64, a: f64, b: f64) -> f64 {
+    assert!(
+        a <= b,
+        "z_curve expects a <= b, got a = {} and b = {}",
+        a,
+        b
+    );
+    1.0 - s_curve(x, a, b)
+}
+
+/// A membership function written in code, for a set none of the shapes
+/// above fits; any `Fn(f64) -> f64` closure is one
+pub trait MembershipFn: Send + Sync {
+    /// Degree of membership of `x`, within [0.0, 1.0]
+    fn evaluate(&self, x: f64) -> f64;
+}
+
+impl<F: Fn(f64) -> f64 + Send + Sync> MembershipFn for F {
+    fn evaluate(&self, x: f64) -> f64 {
+        self(x)
+    }
+}
+
+impl MembershipFn for MembershipFunction {
+    fn evaluate(&self, x: f64) -> f64 {
+        MembershipFunction::evaluate(self, x)
+    }
+}
+
+/// A `MembershipFn` shared by the copies of the set it shapes, equal only
+/// to itself
+#[derive(Clone)]
+pub struct CustomFn(Arc<dyn MembershipFn>);
+
+impl fmt::Debug for CustomFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomFn(..)")
+    }
+}
+
+impl PartialEq for CustomFn {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Shape of a fuzzy set, evaluated by one of the membership functions above
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum MembershipFunction {
+    Triangular {
+        a: f64,
+        b: f64,
+        c: f64,
+    },
+    Trapezoidal {
+        a: f64,
+        b: f64,
+        c: f64,
+        d: f64,
+    },
+    Gaussian {
+        mean: f64,
+        sigma: f64,
+    },
+    /// Open-ended shoulder: rising past `center` for a positive slope,
+    /// falling for a negative one
+    Sigmoid {
+        center: f64,
+        slope: f64,
+    },
+    /// Bell of half-width `a` at its 0.5 crossings, slope `b` and center
+    /// `c`, as in ANFIS
+    GeneralizedBell {
+        a: f64,
+        b: f64,
+        c: f64,
+    },
+    /// Smooth ramp from 0 at `a` to 1 at `b`
+    SCurve {
+        a: f64,
+        b: f64,
+    },
+    /// Smooth ramp from 1 at `a` to 0 at `b`, the complement of `SCurve`
+    ZCurve {
+        a: f64,
+        b: f64,
+    },
+    /// Computed by a function written in code (`custom`); a config cannot
+    /// hold one
+    Custom(CustomFn),
+}
+
+impl MembershipFunction {
+    /// A shape computed by `function`, e.g. a closure; what it returns is
+    /// taken within [0.0, 1.0], NaN as 0
+    pub fn custom(function: impl MembershipFn + 'static) -> Self {
+        MembershipFunction::Custom(CustomFn(Arc::new(function)))
+    }
+
+    /// Build a shape from its config name and parameter list
+    fn from_params(kind: &str, params: &[f64]) -> Result<Self, String> {
+        if let Some(p) = params.iter().find(|p| !p.is_finite()) {
+            return Err(format!("{} parameters must be numbers, got {}", kind, p));
+        }
+        match (kind, params) {
+            ("triangular", [a, b, c]) if !(a <= b && b <= c) => Err(format!(
+                "triangular expects a <= b <= c, got {}, {}, {}",
+                a, b, c
+            )),
+            ("trapezoidal", [a, b, c, d]) if !(a <= b && b <= c && c <= d) => Err(format!(
+                "trapezoidal expects a <= b <= c <= d, got {}, {}, {}, {}",
+                a, b, c, d
+            )),
+            ("s_curve" | "z_curve", [a, b]) if a > b => {
+                Err(format!("{} expects a <= b, got {}, {}", kind, a, b))
+            }
+            ("triangular", [a, b, c]) => Ok(MembershipFunction::Triangular {
+                a: *a,
+                b: *b,
+                c: *c,
+            }),
+            ("trapezoidal", [a, b, c, d]) => Ok(MembershipFunction::Trapezoidal {
+                a: *a,
+                b: *b,
+                c: *c,
+                d: *d,
+            }),
+            ("gaussian", [mean, sigma]) => Ok(MembershipFunction::Gaussian {
+                mean: *mean,
+                sigma: *sigma,
+            }),
+            ("sigmoid", [_, 0.0]) => Err("sigmoid expects a slope other than 0".to_string()),
+            ("sigmoid", [center, slope]) => Ok(MembershipFunction::Sigmoid {
+                center: *center,
+                slope: *slope,
+            }),
+            ("gbell", [_, 0.0, _]) => Err("gbell expects a slope b other than 0".to_string()),
+            ("gbell", [a, b, c]) => Ok(MembershipFunction::GeneralizedBell {
+                a: *a,
+                b: *b,
+                c: *c,
+            }),
+            ("s_curve", [a, b]) => Ok(MembershipFunction::SCurve { a: *a, b: *b }),
+            ("z_curve", [a, b]) => Ok(MembershipFunction::ZCurve { a: *a, b: *b }),
+            ("triangular", _) => Err(format!(
+                "triangular expects 3 parameters, got {}",
+                params.len()
+            )),
+            ("trapezoidal", _) => Err(format!(
+                "trapezoidal expects 4 parameters, got {}",
+                params.len()
+            )),
+            ("gaussian", _) => Err(format!(
+                "gaussian expects 2 parameters (mean, sigma), got {}",
+                params.len()
+            )),
+            ("sigmoid", _) => Err(format!(
+                "sigmoid expects 2 parameters (center, slope), got {}",
+                params.len()
+            )),
+            ("gbell", _) => Err(format!(
+                "gbell expects 3 parameters (a, b, c), got {}",
+                params.len()
+            )),
+            ("custom", _) => Err(
+                "custom shapes are written in code (MembershipFunction::custom), not read \
+                 from a config"
+                    .to_string(),
+            ),
+            ("s_curve" | "z_curve", _) => Err(format!(
+                "{} expects 2 parameters (a, b), got {}",
+                kind,
+                params.len()
+            )),
+            _ => Err(format!("unknown shape '{}'", kind)),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            MembershipFunction::Triangular { .. } => "triangular",
+            MembershipFunction::Trapezoidal { .. } => "trapezoidal",
+            MembershipFunction::Gaussian { .. } => "gaussian",
+            MembershipFunction::Sigmoid { .. } => "sigmoid",
+            MembershipFunction::GeneralizedBell { .. } => "gbell",
+            MembershipFunction::SCurve { .. } => "s_curve",
+            MembershipFunction::ZCurve { .. } => "z_curve",
+            MembershipFunction::Custom(_) => "custom",
+        }
+    }
+
+    fn params(&self) -> Vec<f64> {
+        match *self {
+            MembershipFunction::Triangular { a, b, c } => vec![a, b, c],
+            MembershipFunction::Trapezoidal { a, b, c, d } => vec![a, b, c, d],
+            MembershipFunction::Gaussian { mean, sigma } => vec![mean, sigma],
+            MembershipFunction::Sigmoid { center, slope } => vec![center, slope],
+            MembershipFunction::GeneralizedBell { a, b, c } => vec![a, b, c],
+            MembershipFunction::SCurve { a, b } | MembershipFunction::ZCurve { a, b } => {
+                vec![a, b]
+            }
+            MembershipFunction::Custom(_) => Vec::new(),
+        }
+    }
+
+    /// Points where the shape bends: the corners, a Gaussian's mean and
+    /// inflection points, a sigmoid's center and where it is within 12%
+    /// of either end, a bell's center and 0.5 crossings, or an S or Z
+    /// curve's ends and the 0.5 crossing halfway
+    fn corners(&self) -> Vec<f64> {
+        match *self {
+            MembershipFunction::Gaussian { mean, sigma } => {
+                vec![mean - sigma.abs(), mean, mean + sigma.abs()]
+            }
+            MembershipFunction::Sigmoid { center, slope } => {
+                let reach = 2.0 / slope.abs();
+                vec![center - reach, center, center + reach]
+            }
+            MembershipFunction::GeneralizedBell { a, c, .. } => vec![c - a.abs(), c, c + a.abs()],
+            MembershipFunction::SCurve { a, b } | MembershipFunction::ZCurve { a, b } => {
+                vec![a, (a + b) / 2.0, b]
+            }
+            _ => self.params(),
+        }
+    }
+
+    fn evaluate(&self, x: f64) -> f64 {
+        match *self {
+            MembershipFunction::Triangular { a, b, c } => triangular(x, a, b, c),
+            MembershipFunction::Trapezoidal { a, b, c, d } => trapezoidal(x, a, b, c, d),
+            MembershipFunction::Gaussian { mean, sigma } => gaussian(x, mean, sigma),
+            // A falling shoulder, such as the sigmoid demo's Cold, is the
+            // complement of the rising one
+            MembershipFunction::Sigmoid { center, slope } if slope < 0.0 => {
+                sigmoid_complement(x, center, -slope)
+            }
+            MembershipFunction::Sigmoid { center, slope } => sigmoid(x, center, slope),
+            MembershipFunction::GeneralizedBell { a, b, c } => gbell(x, a, b, c),
+            MembershipFunction::SCurve { a, b } => s_curve(x, a, b),
+            MembershipFunction::ZCurve { a, b } => z_curve(x, a, b),
+            MembershipFunction::Custom(ref custom) => match custom.0.evaluate(x) {
+                m if m.is_nan() => 0.0,
+                m => m.clamp(0.0, 1.0),
+            },
+        }
+    }
+
+    /// Closed interval outside of which the membership is exactly 0; the
+    /// whole line for a Gaussian, a sigmoid, a bell or a function written
+    /// in code, and from the foot on for an S or Z curve
+    fn support(&self) -> (f64, f64) {
+        match *self {
+            MembershipFunction::Triangular { a, c, .. } => (a, c),
+            MembershipFunction::Trapezoidal { a, d, .. } => (a, d),
+            MembershipFunction::SCurve { a, .. } => (a, f64::INFINITY),
+            MembershipFunction::ZCurve { b, .. } => (f64::NEG_INFINITY, b),
+            MembershipFunction::Gaussian { mean, sigma: 0.0 } => (mean, mean),
+            MembershipFunction::GeneralizedBell { a: 0.0, c, .. } => (c, c),
+            MembershipFunction::Gaussian { .. }
+            | MembershipFunction::Sigmoid { .. }
+            | MembershipFunction::GeneralizedBell { .. }
+            | MembershipFunction::Custom(_) => (f64::NEG_INFINITY, f64::INFINITY),
+        }
+    }
+}
+
+// ============================================================================
+// FUZZY VARIABLES - Variáveis Fuzzy
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub(crate) struct FuzzySet {
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    name: String,
+    membership: f64,
+}
+
+/// Named fuzzy set definition of a variable
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SetDefinition {
+    name: String,
+    function: MembershipFunction,
+    /// Optional ± range per parameter, used by the surface uncertainty bands
+    uncertainty: Vec<f64>,
+    /// Per parameter, the other set's parameter it follows (links.rs);
+    /// empty when none does
+    links: Vec<Option<links::Link>>,
+}
+
+/// What an input does with a reading outside its universe
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutOfRange {
+    /// The reading is taken as the nearest limit
+    #[default]
+    Clamp,
+    /// The reading stands, and every set holds its membership at the
+    /// nearest limit
+    Extend,
+    /// The reading is refused, and belongs to no set if it still arrives
+    Error,
+}
+
+impl OutOfRange {
+    const ALL: [OutOfRange; 3] = [OutOfRange::Clamp, OutOfRange::Extend, OutOfRange::Error];
+
+    fn parse(name: &str) -> Option<Self> {
+        OutOfRange::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            OutOfRange::Clamp => "clamp",
+            OutOfRange::Extend => "extend",
+            OutOfRange::Error => "error",
+        }
+    }
+}
+
+/// A reading outside its input's universe, and what became of it
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Overflow {
+    input: String,
+    value: f64,
+    /// The limit it passed
+    limit: f64,
+    /// Above the max rather than below the min
+    high: bool,
+    policy: OutOfRange,
+}
+
+impl Overflow {
+    #[cfg(any(test, feature = "tui", feature = "cosim"))]
+    fn describe(&self) -> String {
+        format!(
+            "{} {} {} {}, {}",
+            self.input,
+            self.value,
+            if self.high { "above" } else { "below" },
+            self.limit,
+            self.action()
+        )
+    }
+
+    #[cfg(any(test, feature = "tui", feature = "cosim"))]
+    fn action(&self) -> &'static str {
+        match self.policy {
+            OutOfRange::Clamp => "clamped",
+            OutOfRange::Extend => "held at the limit",
+            OutOfRange::Error => "refused",
+        }
+    }
+}
+
+/// Linguistic variable: a universe of discourse and its fuzzy sets
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyVariable {
+    name: String,
+    min: f64,
+    max: f64,
+    sets: Vec<SetDefinition>,
+    /// How raw readings are converted, for inputs
+    kind: InputKind,
+    /// Smallest change worth a recompute in the daemon, for inputs
+    /// (trigger.rs); zero recomputes on every reading
+    min_delta: f64,
+    /// Samples aggregated into one reading per window in the daemon, for
+    /// inputs (window.rs)
+    window: Option<window::WindowConfig>,
+    /// How long its readings reach the daemon after they were taken, for
+    /// inputs (align.rs)
+    latency_secs: f64,
+    /// Sources fused into the reading in the daemon, for inputs
+    /// (fusion.rs)
+    fusion: Option<fusion::FusionConfig>,
+    /// What a reading outside `min..=max` does, for inputs
+    out_of_range: OutOfRange,
+    /// Unit and display precision of the values (units.rs)
+    unit: Unit,
+    /// Defuzzification settings of its own, for outputs
+    defuzz: OutputDefuzz,
+}
+
+impl FuzzyVariable {
+    pub fn new(name: &str, min: f64, max: f64) -> Self {
+        FuzzyVariable {
+            name: name.to_string(),
+            min,
+            max,
+            sets: Vec::new(),
+            kind: InputKind::Direct,
+            min_delta: 0.0,
+            window: None,
+            latency_secs: 0.0,
+            fusion: None,
+            out_of_range: OutOfRange::Clamp,
+            unit: Unit::default(),
+            defuzz: OutputDefuzz::default(),
+        }
+    }
+
+    pub fn with_unit(mut self, unit: &str) -> Self {
+        self.unit = Unit::new(unit);
+        self
+    }
+
+    pub fn with_set(mut self, name: &str, function: MembershipFunction) -> Self {
+        self.sets.push(SetDefinition {
+            name: name.to_string(),
+            function,
+            uncertainty: Vec::new(),
+            links: Vec::new(),
+        });
+        self
+    }
+
+    fn set(&self, name: &str) -> Option<&SetDefinition> {
+        self.sets.iter().find(|s| s.name == name)
+    }
+
+    /// Center of area of set `set` over the universe, sampled at
+    /// RESOLUTION steps; the middle of the universe when the set is zero
+    /// throughout
+    pub fn set_centroid(&self, set: usize) -> f64 {
+        let function = &self.sets[set].function;
+        let (mut moment, mut area) = (0.0, 0.0);
+        for i in 0..=RESOLUTION {
+            let x = self.min + (i as f64 / RESOLUTION as f64) * (self.max - self.min);
+            let m = function.evaluate(x);
+            moment += x * m;
+            area += m;
+        }
+        if area == 0.0 {
+            (self.min + self.max) / 2.0
+        } else {
+            moment / area
+        }
+    }
+
+    /// Set parameters strictly inside the universe, where memberships
+    /// start or stop changing, sorted and without repeats
+    fn breakpoints(&self) -> Vec<f64> {
+        let mut points: Vec<f64> = self
+            .sets
+            .iter()
+            .flat_map(|s| s.function.corners())
+            .filter(|p| *p > self.min && *p < self.max)
+            .collect();
+        points.sort_by(f64::total_cmp);
+        points.dedup();
+        points
+    }
+
+    /// Whether reading `x` lies above the universe and the limit it
+    /// passed, if it lies outside
+    fn past(&self, x: f64) -> Option<(bool, f64)> {
+        if x > self.max {
+            Some((true, self.max))
+        } else if x < self.min {
+            Some((false, self.min))
+        } else {
+            None
+        }
+    }
+
+    /// The overflow of reading `x`, if it lies outside the universe
+    fn overflow(&self, x: f64) -> Option<Overflow> {
+        let (high, limit) = self.past(x)?;
+        Some(Overflow {
+            input: self.name.clone(),
+            value: x,
+            limit,
+            high,
+            policy: self.out_of_range,
+        })
+    }
+
+    /// Reading `x` as the controller takes it under the out-of-range
+    /// policy, or why it is refused
+    fn admit(&self, x: f64) -> Result<f64, String> {
+        match self.past(x) {
+            Some(_) if self.out_of_range == OutOfRange::Error => Err(format!(
+                "{} = {} is outside {} to {}",
+                self.name, x, self.min, self.max
+            )),
+            Some((_, limit)) if self.out_of_range == OutOfRange::Clamp => Ok(limit),
+            _ => Ok(x),
+        }
+    }
+
+    /// Where the sets are evaluated for reading `x`: the nearest limit
+    /// past the universe, NaN when the policy refuses the reading
+    fn held(&self, x: f64) -> f64 {
+        match self.past(x) {
+            Some(_) if self.out_of_range == OutOfRange::Error => f64::NAN,
+            Some((_, limit)) => limit,
+            None => x,
+        }
+    }
+
+    /// Membership of `x` in every set; a non-finite value (a failed
+    /// sensor) belongs to none. Past the universe every shape holds its
+    /// membership at the limit, unless the policy refuses the reading.
+    fn fuzzify(&self, x: f64) -> Vec<FuzzySet> {
+        let x = self.held(x);
+        self.sets
+            .iter()
+            .map(|s| FuzzySet {
+                name: s.name.clone(),
+                membership: if x.is_finite() {
+                    s.function.evaluate(x)
+                } else {
+                    0.0
+                },
+            })
+            .collect()
+    }
+}
+
+/// Temperature fuzzy sets: Cold, Mild, Hot
+fn temperature_variable() -> FuzzyVariable {
+    FuzzyVariable::new("temperature", 0.0, 50.0)
+        .with_unit("°C")
+        .with_set(
+            "Cold",
+            MembershipFunction::Trapezoidal {
+                a: 0.0,
+                b: 0.0,
+                c: 15.0,
+                d: 20.0,
+            },
+        )
+        .with_set(
+            "Mild",
+            MembershipFunction::Triangular {
+                a: 15.0,
+                b: 22.5,
+                c: 30.0,
+            },
+        )
+        .with_set(
+            "Hot",
+            MembershipFunction::Trapezoidal {
+                a: 25.0,
+                b: 30.0,
+                c: 50.0,
+                d: 50.0,
+            },
+        )
+}
+
+/// Humidity fuzzy sets: Low, Medium, High
+fn humidity_variable() -> FuzzyVariable {
+    FuzzyVariable::new("humidity", 0.0, 100.0)
+        .with_unit("%")
+        .with_set(
+            "Low",
+            MembershipFunction::Trapezoidal {
+                a: 0.0,
+                b: 0.0,
+                c: 30.0,
+                d: 50.0,
+            },
+        )
+        .with_set(
+            "Medium",
+            MembershipFunction::Triangular {
+                a: 30.0,
+                b: 50.0,
+                c: 70.0,
+            },
+        )
+        .with_set(
+            "High",
+            MembershipFunction::Trapezoidal {
+                a: 50.0,
+                b: 70.0,
+                c: 100.0,
+                d: 100.0,
+            },
+        )
+}
+
+/// Temperature fuzzy sets as Gaussian curves, crossing close to where
+/// `temperature_variable`'s do, the shoulders centered on the limits
+fn temperature_variable_gaussian() -> FuzzyVariable {
+    FuzzyVariable::new("temperature", 0.0, 50.0)
+        .with_unit("°C")
+        .with_set(
+            "Cold",
+            MembershipFunction::Gaussian {
+                mean: 0.0,
+                sigma: 10.0,
+            },
+        )
+        .with_set(
+            "Mild",
+            MembershipFunction::Gaussian {
+                mean: 22.5,
+                sigma: 4.0,
+            },
+        )
+        .with_set(
+            "Hot",
+            MembershipFunction::Gaussian {
+                mean: 50.0,
+                sigma: 12.0,
+            },
+        )
+}
+
+/// Temperature fuzzy sets with sigmoid shoulders: Cold and Hot cross Mild
+/// where `temperature_variable`'s trapezoids do, but go on past the
+/// universe instead of ending at its limits
+fn temperature_variable_sigmoid() -> FuzzyVariable {
+    FuzzyVariable::new("temperature", 0.0, 50.0)
+        .with_unit("°C")
+        .with_set(
+            "Cold",
+            MembershipFunction::Sigmoid {
+                center: 17.5,
+                slope: -0.9,
+            },
+        )
+        .with_set(
+            "Mild",
+            MembershipFunction::Triangular {
+                a: 15.0,
+                b: 22.5,
+                c: 30.0,
+            },
+        )
+        .with_set(
+            "Hot",
+            MembershipFunction::Sigmoid {
+                center: 27.5,
+                slope: 0.9,
+            },
+        )
+}
+
+/// Humidity fuzzy sets with a Z-curve Low: it falls from 30 to 50 % like
+/// `humidity_variable`'s trapezoid, smoothly instead of along a line
+fn humidity_variable_z_curve() -> FuzzyVariable {
+    FuzzyVariable::new("humidity", 0.0, 100.0)
+        .with_unit("%")
+        .with_set("Low", MembershipFunction::ZCurve { a: 30.0, b: 50.0 })
+        .with_set(
+            "Medium",
+            MembershipFunction::Triangular {
+                a: 30.0,
+                b: 50.0,
+                c: 70.0,
+            },
+        )
+        .with_set(
+            "High",
+            MembershipFunction::Trapezoidal {
+                a: 50.0,
+                b: 70.0,
+                c: 100.0,
+                d: 100.0,
+            },
+        )
+}
+
+/// Humidity fuzzy sets as Gaussian curves, like
+/// `temperature_variable_gaussian`
+fn humidity_variable_gaussian() -> FuzzyVariable {
+    FuzzyVariable::new("humidity", 0.0, 100.0)
+        .with_unit("%")
+        .with_set(
+            "Low",
+            MembershipFunction::Gaussian {
+                mean: 0.0,
+                sigma: 25.0,
+            },
+        )
+        .with_set(
+            "Medium",
+            MembershipFunction::Gaussian {
+                mean: 50.0,
+                sigma: 10.0,
+            },
+        )
+        .with_set(
+            "High",
+            MembershipFunction::Gaussian {
+                mean: 100.0,
+                sigma: 25.0,
+            },
+        )
+}
+
+/// Fan speed fuzzy sets: Off, Low, Medium, High
+fn fan_speed_variable() -> FuzzyVariable {
+    FuzzyVariable::new("fan_speed", 0.0, 100.0)
+        .with_unit("%")
+        .with_set(
+            "Off",
+            MembershipFunction::Triangular {
+                a: 0.0,
+                b: 0.0,
+                c: 20.0,
+            },
+        )
+        .with_set(
+            "Low",
+            MembershipFunction::Triangular {
+                a: 0.0,
+                b: 25.0,
+                c: 50.0,
+            },
+        )
+        .with_set(
+            "Medium",
+            MembershipFunction::Triangular {
+                a: 25.0,
+                b: 50.0,
+                c: 75.0,
+            },
+        )
+        .with_set(
+            "High",
+            MembershipFunction::Triangular {
+                a: 50.0,
+                b: 100.0,
+                c: 100.0,
+            },
+        )
+}
+
+// ============================================================================
+// FUZZY RULES - Regras Fuzzy (Mamdani Method)
+// ============================================================================
+
+/// `IF <variable> IS <set> AND ... THEN <output> IS <set> AND ... [WITH <weight>]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyRule {
+    conditions: Vec<(String, String)>,
+    /// (output, set) pairs; the rule's strength is applied to each of them
+    consequents: Vec<(String, String)>,
+    /// Multiplies the firing strength
+    weight: f64,
+    /// Group the rule belongs to, if any
+    group: Option<String>,
+}
+
+/// Named set of rules that can be switched off or weighted as a whole
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RuleGroup {
+    name: String,
+    enabled: bool,
+    /// Applied on top of each rule's own weight
+    weight: f64,
+}
+
+impl FuzzyRule {
+    fn new(conditions: &[(&str, &str)], consequents: &[(&str, &str)]) -> Self {
+        let pairs = |list: &[(&str, &str)]| {
+            list.iter()
+                .map(|(var, set)| (var.to_string(), set.to_string()))
+                .collect()
+        };
+        FuzzyRule {
+            conditions: pairs(conditions),
+            consequents: pairs(consequents),
+            weight: 1.0,
+            group: None,
+        }
+    }
+
+    /// Parse the textual rule form, e.g.
+    /// `IF temperature IS Hot AND humidity IS High THEN fan_speed IS High WITH 0.8`
+    /// or, with several outputs, `... THEN fan IS High AND damper IS Open`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let keyword =
+            |i: usize, word: &str| tokens.get(i).is_some_and(|t| t.eq_ignore_ascii_case(word));
+
+        if !keyword(0, "IF") {
+            return Err(format!("rule must start with IF: '{}'", text));
+        }
+
+        let mut conditions = Vec::new();
+        let mut i = 1;
+        loop {
+            match (tokens.get(i), tokens.get(i + 2)) {
+                (Some(var), Some(set)) if keyword(i + 1, "IS") => {
+                    conditions.push((var.to_string(), set.to_string()));
+                }
+                _ => return Err(format!("expected '<variable> IS <set>' in '{}'", text)),
+            }
+            i += 3;
+            if keyword(i, "AND") {
+                i += 1;
+            } else if keyword(i, "THEN") {
+                i += 1;
+                break;
+            } else {
+                return Err(format!("expected AND or THEN in '{}'", text));
+            }
+        }
+
+        let mut consequents = Vec::new();
+        loop {
+            match (tokens.get(i), tokens.get(i + 2)) {
+                (Some(output), Some(set)) if keyword(i + 1, "IS") => {
+                    consequents.push((output.to_string(), set.to_string()));
+                }
+                _ => return Err(format!("expected 'THEN <output> IS <set>' in '{}'", text)),
+            }
+            i += 3;
+            if keyword(i, "AND") {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+
+        let weight = match &tokens[i..] {
+            [] => 1.0,
+            [with, weight] if with.eq_ignore_ascii_case("WITH") => weight
+                .parse::<f64>()
+                .ok()
+                .filter(|w| (0.0..=1.0).contains(w))
+                .ok_or_else(|| format!("weight must be between 0 and 1 in '{}'", text))?,
+            _ => {
+                return Err(format!(
+                    "expected 'WITH <weight>' or nothing after the consequents in '{}'",
+                    text
+                ));
+            }
+        };
+
+        Ok(FuzzyRule {
+            conditions,
+            consequents,
+            weight,
+            group: None,
+        })
+    }
+
+    /// The rule without its weight
+    fn statement(&self) -> String {
+        let clauses = |pairs: &[(String, String)]| {
+            pairs
+                .iter()
+                .map(|(var, set)| format!("{} IS {}", var, set))
+                .collect::<Vec<_>>()
+                .join(" AND ")
+        };
+        format!(
+            "IF {} THEN {}",
+            clauses(&self.conditions),
+            clauses(&self.consequents)
+        )
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = self.statement();
+        if self.weight != 1.0 {
+            text.push_str(&format!(" WITH {}", self.weight));
+        }
+        text
+    }
+
+    /// Set this rule concludes for `output`, if it concludes anything for it
+    fn consequent(&self, output: &str) -> Option<&str> {
+        self.consequents
+            .iter()
+            .find(|(name, _)| name == output)
+            .map(|(_, set)| set.as_str())
+    }
+}
+
+/// Define fuzzy rules for fan control
+fn create_rules() -> Vec<FuzzyRule> {
+    let table = [
+        ("Cold", "Low", "Off"),
+        ("Cold", "Medium", "Off"),
+        ("Cold", "High", "Low"),
+        ("Mild", "Low", "Low"),
+        ("Mild", "Medium", "Medium"),
+        ("Mild", "High", "Medium"),
+        ("Hot", "Low", "Medium"),
+        ("Hot", "Medium", "High"),
+        ("Hot", "High", "High"),
+    ];
+
+    table
+        .iter()
+        .map(|(temp, humidity, fan)| {
+            FuzzyRule::new(
+                &[("temperature", temp), ("humidity", humidity)],
+                &[("fan_speed", fan)],
+            )
+        })
+        .collect()
+}
+
+// ============================================================================
+// FUZZY SYSTEM - Definição do Sistema
+// ============================================================================
+
+/// Descriptive fields of a system definition; none affects its behavior
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Metadata {
+    name: Option<String>,
+    description: Option<String>,
+    /// Semantic version, MAJOR.MINOR.PATCH
+    version: Option<String>,
+    author: Option<String>,
+    created: Option<String>,
+    modified: Option<String>,
+}
+
+impl Metadata {
+    /// `(key, value)` for every field that is set, in declaration order
+    fn fields(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("name", &self.name),
+            ("description", &self.description),
+            ("version", &self.version),
+            ("author", &self.author),
+            ("created", &self.created),
+            ("modified", &self.modified),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.as_deref().map(|v| (key, v)))
+        .collect()
+    }
+
+    /// `name vVERSION by AUTHOR`, with whatever of it is set
+    fn summary(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        parts.extend(self.name.clone());
+        parts.extend(self.version.as_ref().map(|v| format!("v{}", v)));
+        parts.extend(self.author.as_ref().map(|a| format!("by {}", a)));
+        (!parts.is_empty()).then(|| parts.join(" "))
+    }
+}
+
+/// Complete system definition: input variables, output variables and rules
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzySystem {
+    metadata: Metadata,
+    inputs: Vec<FuzzyVariable>,
+    /// The first output is the one the pipeline and the TUI gauge drive
+    outputs: Vec<FuzzyVariable>,
+    rules: Vec<FuzzyRule>,
+    groups: Vec<RuleGroup>,
+    defuzzification: Defuzzification,
+    pipeline: PipelineConfig,
+    /// PID tracking the first output against a measured input
+    /// (cascade.rs)
+    cascade: Option<CascadeConfig>,
+    /// Program run with the first output (exec.rs)
+    exec: Option<ExecConfig>,
+    recompute: RecomputeConfig,
+    /// How long a reading waits for the other inputs' from the same
+    /// instant (align.rs)
+    alignment: align::AlignConfig,
+    /// How long hand-set inputs hold against the daemon's readings
+    /// (overrides.rs)
+    overrides: overrides::OverrideConfig,
+    /// Time one computation may take before they get cheaper (budget.rs)
+    budget: Option<BudgetConfig>,
+    /// Rule weights nudged by feedback, TUI only (adapt.rs)
+    adaptation: Option<adapt::AdaptationConfig>,
+    /// Checks on the daemon's `param` moves (guard.rs)
+    tuning: TuningConfig,
+    /// Limit-cycle watch on the first output (oscillation.rs)
+    oscillation: Option<OscillationConfig>,
+    /// Conditions raising an alarm (alarm.rs)
+    alarms: Vec<AlarmConfig>,
+    /// How good a computation's conditions are (objective.rs)
+    objective: Option<ObjectiveConfig>,
+    /// What random inputs are drawn from (generator.rs)
+    generator: GeneratorConfig,
+    /// Overrides of the first output near the input limits (envelope.rs)
+    envelopes: Vec<EnvelopeConfig>,
+    /// Simulated sensor errors, TUI only (noise.rs)
+    noise: Vec<NoiseConfig>,
+    /// How the TUI extrapolates the inputs ahead (forecast.rs)
+    forecast: forecast::ForecastConfig,
+    /// Switches forcing the first output (interlock.rs)
+    interlocks: Vec<InterlockConfig>,
+    /// Time zone the TUI shows wall-clock times in (timestamp.rs)
+    timezone: timestamp::Zone,
+    /// Colors the TUI draws with (palette.rs)
+    palette: palette::PaletteName,
+    /// The TUI stops advancing the simulation while its terminal is out
+    /// of focus (termguard.rs)
+    pause_on_blur: bool,
+    /// Zone of the temperature/humidity plane drawn in the TUI
+    /// (comfort.rs)
+    comfort: Option<ComfortConfig>,
+}
+
+impl FuzzySystem {
+    /// The built-in fan controller
+    pub fn demo() -> Self {
+        FuzzySystem::new(
+            vec![temperature_variable(), humidity_variable()],
+            vec![fan_speed_variable()],
+            create_rules(),
+        )
+        .expect("the demo is valid")
+    }
+
+    /// The built-in fan controller with Gaussian input sets in place of the
+    /// triangles and trapezoids, same rules and output, to compare the two
+    /// shapes
+    pub fn demo_gaussian() -> Self {
+        FuzzySystem::new(
+            vec![
+                temperature_variable_gaussian(),
+                humidity_variable_gaussian(),
+            ],
+            vec![fan_speed_variable()],
+            create_rules(),
+        )
+        .expect("the demo is valid")
+    }
+
+    /// The built-in fan controller with sigmoid Cold and Hot temperature
+    /// sets in place of the shoulder trapezoids, same rules and output
+    pub fn demo_sigmoid() -> Self {
+        FuzzySystem::new(
+            vec![temperature_variable_sigmoid(), humidity_variable()],
+            vec![fan_speed_variable()],
+            create_rules(),
+        )
+        .expect("the demo is valid")
+    }
+
+    /// The built-in fan controller with a Z-curve Low humidity set in place
+    /// of the trapezoid, same rules and output
+    pub fn demo_z_curve() -> Self {
+        FuzzySystem::new(
+            vec![temperature_variable(), humidity_variable_z_curve()],
+            vec![fan_speed_variable()],
+            create_rules(),
+        )
+        .expect("the demo is valid")
+    }
+
+    /// A system of `inputs`, `outputs` and `rules` with every other setting
+    /// at its default, as a config with only those sections would give, or
+    /// why the rules do not fit the variables
+    pub fn new(
+        inputs: Vec<FuzzyVariable>,
+        outputs: Vec<FuzzyVariable>,
+        rules: Vec<FuzzyRule>,
+    ) -> Result<Self, String> {
+        let system = FuzzySystem {
+            metadata: Metadata::default(),
+            inputs,
+            outputs,
+            rules,
+            groups: Vec::new(),
+            defuzzification: Defuzzification::default(),
+            pipeline: PipelineConfig::default(),
+            cascade: None,
+            exec: None,
+            recompute: RecomputeConfig::default(),
+            alignment: align::AlignConfig::default(),
+            overrides: overrides::OverrideConfig::default(),
+            budget: None,
+            adaptation: None,
+            tuning: TuningConfig::default(),
+            oscillation: None,
+            alarms: Vec::new(),
+            objective: None,
+            generator: GeneratorConfig::default(),
+            envelopes: Vec::new(),
+            noise: Vec::new(),
+            forecast: forecast::ForecastConfig::default(),
+            interlocks: Vec::new(),
+            timezone: timestamp::Zone::Local,
+            palette: palette::PaletteName::Classic,
+            pause_on_blur: false,
+            comfort: None,
+        };
+        system.validate()?;
+        Ok(system)
+    }
+
+    /// The system with the output pipeline of `[output.pipeline]`
+    pub fn with_pipeline(mut self, pipeline: PipelineConfig) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    fn input(&self, name: &str) -> Option<&FuzzyVariable> {
+        self.inputs.iter().find(|v| v.name == name)
+    }
+
+    /// The primary output
+    fn output(&self) -> &FuzzyVariable {
+        &self.outputs[0]
+    }
+
+    fn output_var(&self, name: &str) -> Option<&FuzzyVariable> {
+        self.outputs.iter().find(|v| v.name == name)
+    }
+
+    fn group(&self, name: &str) -> Option<&RuleGroup> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    /// Textual form of every rule, which identifies it in the state file
+    fn rule_texts(&self) -> Vec<String> {
+        self.rules.iter().map(FuzzyRule::to_text).collect()
+    }
+
+    /// Check that every name referenced by the rules exists
+    fn validate(&self) -> Result<(), String> {
+        if self.inputs.is_empty() {
+            return Err("system has no input variables".to_string());
+        }
+        if self.outputs.is_empty() {
+            return Err("system has no output variables".to_string());
+        }
+        for var in self.inputs.iter().chain(&self.outputs) {
+            if !(var.min.is_finite() && var.max.is_finite()) {
+                return Err(format!(
+                    "variable '{}' has a range of {} to {}, which is not finite",
+                    var.name, var.min, var.max
+                ));
+            }
+            if var.min >= var.max {
+                return Err(format!("variable '{}' has an empty range", var.name));
+            }
+            if var.sets.is_empty() {
+                return Err(format!("variable '{}' has no sets", var.name));
+            }
+        }
+        for var in &self.inputs {
+            if let InputKind::DewPoint { temperature } = &var.kind
+                && self
+                    .input(temperature)
+                    .is_none_or(|t| t.kind != InputKind::Direct)
+            {
+                return Err(format!(
+                    "input '{}': temperature input '{}' must exist and be direct",
+                    var.name, temperature
+                ));
+            }
+        }
+        if let Some(cascade) = &self.cascade {
+            if self.input(&cascade.measured).is_none() {
+                return Err(format!(
+                    "cascade: unknown measured input '{}'",
+                    cascade.measured
+                ));
+            }
+            if cascade.min >= cascade.max {
+                return Err("cascade: 'min' must be below 'max'".to_string());
+            }
+        }
+        for envelope in &self.envelopes {
+            let input = self
+                .input(&envelope.input)
+                .ok_or_else(|| format!("envelope: unknown input '{}'", envelope.input))?;
+            if !(envelope.margin > 0.0 && envelope.margin <= (input.max - input.min) / 2.0) {
+                return Err(format!(
+                    "envelope '{}': margin must be above 0 and at most half the universe",
+                    envelope.input
+                ));
+            }
+            let output = self.output();
+            for target in [envelope.low, envelope.high].into_iter().flatten() {
+                if !(output.min..=output.max).contains(&target) {
+                    return Err(format!(
+                        "envelope '{}': target {} is outside {} {} to {}",
+                        envelope.input, target, output.name, output.min, output.max
+                    ));
+                }
+            }
+        }
+        for interlock in &self.interlocks {
+            let output = self.output();
+            if !(output.min..=output.max).contains(&interlock.value) {
+                return Err(format!(
+                    "interlock '{}': value {} is outside {} {} to {}",
+                    interlock.name, interlock.value, output.name, output.min, output.max
+                ));
+            }
+        }
+        for (i, rule) in self.rules.iter().enumerate() {
+            for (var, set) in &rule.conditions {
+                let variable = self
+                    .input(var)
+                    .ok_or_else(|| format!("rule {}: unknown input '{}'", i + 1, var))?;
+                if variable.set(set).is_none() {
+                    return Err(format!("rule {}: '{}' has no set '{}'", i + 1, var, set));
+                }
+            }
+            if let Some(group) = &rule.group
+                && self.group(group).is_none()
+            {
+                return Err(format!("rule {}: unknown group '{}'", i + 1, group));
+            }
+            for (j, (var, set)) in rule.consequents.iter().enumerate() {
+                let variable = self
+                    .output_var(var)
+                    .ok_or_else(|| format!("rule {}: unknown output '{}'", i + 1, var))?;
+                if variable.set(set).is_none() {
+                    return Err(format!("rule {}: '{}' has no set '{}'", i + 1, var, set));
+                }
+                if rule.consequents[..j].iter().any(|(v, _)| v == var) {
+                    return Err(format!("rule {}: '{}' concluded twice", i + 1, var));
+                }
+            }
+        }
+        let defuzzification = &self.defuzzification;
+        for output in &self.outputs {
+            defuzzification.check(output)?;
+        }
+        for (i, cap) in defuzzification.caps.iter().enumerate() {
+            let output = self
+                .output_var(&cap.output)
+                .ok_or_else(|| format!("cap: unknown output '{}'", cap.output))?;
+            if output.set(&cap.set).is_none() {
+                return Err(format!("cap: '{}' has no set '{}'", cap.output, cap.set));
+            }
+            if defuzzification.caps[..i]
+                .iter()
+                .any(|c| (&c.profile, &c.output, &c.set) == (&cap.profile, &cap.output, &cap.set))
+            {
+                return Err(format!(
+                    "cap: '{}' caps {} {} twice",
+                    cap.profile, cap.output, cap.set
+                ));
+            }
+        }
+        if defuzzification.cap_profiles().contains(&"off") {
+            return Err("cap: 'off' cannot name a profile, it turns the caps off".to_string());
+        }
+        if let Some(profile) = &defuzzification.cap_profile
+            && !defuzzification.cap_profiles().contains(&profile.as_str())
+        {
+            return Err(format!("unknown cap profile '{}'", profile));
+        }
+        generator::validate(self)?;
+        noise::validate(self)
+    }
+}
+
+// ============================================================================
+// FUZZY INFERENCE ENGINE
+// ============================================================================
+
+/// What `rules` firing at `strengths` conclude about `output`
+fn apply_rules(strengths: &[f64], rules: &[FuzzyRule], output: &FuzzyVariable) -> Activations {
+    let mut activations = Activations::default();
+    activations.reset(output.sets.len());
+    for (index, (rule, strength)) in rules.iter().zip(strengths).enumerate() {
+        if let Some(set) = rule
+            .consequent(&output.name)
+            .and_then(|name| output.sets.iter().position(|s| s.name == name))
+        {
+            activations.fire(index, set, *strength);
+        }
+    }
+    activations
+}
+
+/// Classify by the output set with the highest aggregated strength (max
+/// over the rules concluding it); ties within `tolerance` go to the set
+/// declared first (ties.rs)
+fn dominant_set<'a>(
+    strengths: &[f64],
+    rules: &[FuzzyRule],
+    output: &'a FuzzyVariable,
+    tolerance: f64,
+) -> Option<&'a str> {
+    let aggregated = output.sets.iter().map(|set| {
+        rules
+            .iter()
+            .zip(strengths)
+            .filter(|(rule, _)| rule.consequent(&output.name) == Some(set.name.as_str()))
+            .map(|(_, s)| *s)
+            .fold(0.0, f64::max)
+    });
+    ties::strongest(aggregated, tolerance).map(|index| output.sets[index].name.as_str())
+}
+
+// ============================================================================
+// DEFUZZIFICATION - Center of Area (COA) Method
+// ============================================================================
+
+/// How the aggregated output set is reduced to a crisp value
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[non_exhaustive]
+pub enum DefuzzMethod {
+    /// Center of area of the whole aggregated set
+    #[default]
+    Centroid,
+    /// Center of area of the connected region holding the highest plateau
+    /// only, so disjoint activations cannot pull the result in between
+    PlateauCentroid,
+    /// Centroids of the sets weighted by the strongest rule concluding
+    /// each, with nothing sampled per inference (budget.rs)
+    WeightedAverage,
+}
+
+impl DefuzzMethod {
+    const ALL: [DefuzzMethod; 3] = [
+        DefuzzMethod::Centroid,
+        DefuzzMethod::PlateauCentroid,
+        DefuzzMethod::WeightedAverage,
+    ];
+
+    fn parse(name: &str) -> Option<Self> {
+        DefuzzMethod::ALL.into_iter().find(|m| m.name() == name)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            DefuzzMethod::Centroid => "centroid",
+            DefuzzMethod::PlateauCentroid => "plateau_centroid",
+            DefuzzMethod::WeightedAverage => "weighted_average",
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    fn next(self) -> Self {
+        let index = DefuzzMethod::ALL
+            .iter()
+            .position(|m| *m == self)
+            .unwrap_or(0);
+        DefuzzMethod::ALL[(index + 1) % DefuzzMethod::ALL.len()]
+    }
+}
+
+/// How a rule's strength shapes the consequent set it concludes
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[non_exhaustive]
+pub enum Implication {
+    /// The set clipped at the strength (Mamdani)
+    #[default]
+    Min,
+    /// The set scaled by the strength (Larsen), keeping its shape
+    Product,
+}
+
+impl Implication {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "min" => Some(Implication::Min),
+            "product" => Some(Implication::Product),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Implication::Min => "min",
+            Implication::Product => "product",
+        }
+    }
+
+    fn apply(self, strength: f64, membership: f64) -> f64 {
+        match self {
+            Implication::Min => strength.min(membership),
+            Implication::Product => strength * membership,
+        }
+    }
+}
+
+/// Defuzzification settings one output overrides, the controller's
+/// where unset
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct OutputDefuzz {
+    method: Option<DefuzzMethod>,
+    resolution: Option<usize>,
+    implication: Option<Implication>,
+}
+
+/// How the consequent sets, each clipped at its rule strength, combine
+/// into the aggregated output set
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[non_exhaustive]
+pub enum Aggregation {
+    /// The strongest rule alone decides; supporting rules change nothing
+    #[default]
+    Max,
+    /// Smooth maximum T·ln(1 + Σ(e^(m/T) - 1)), at most 1: every
+    /// supporting rule adds a little, close to max for a small
+    /// temperature T and to the bounded sum for a large one
+    SoftMax,
+    /// Sum of the memberships, at most 1
+    BoundedSum,
+}
+
+impl Aggregation {
+    const ALL: [Aggregation; 3] = [
+        Aggregation::Max,
+        Aggregation::SoftMax,
+        Aggregation::BoundedSum,
+    ];
+
+    fn parse(name: &str) -> Option<Self> {
+        Aggregation::ALL.into_iter().find(|a| a.name() == name)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Aggregation::Max => "max",
+            Aggregation::SoftMax => "softmax",
+            Aggregation::BoundedSum => "bounded_sum",
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    fn next(self) -> Self {
+        let index = Aggregation::ALL
+            .iter()
+            .position(|a| *a == self)
+            .unwrap_or(0);
+        Aggregation::ALL[(index + 1) % Aggregation::ALL.len()]
+    }
+
+    /// Combine memberships in 0..=1; `temperature` is the soft-max's
+    fn combine(self, memberships: impl Iterator<Item = f64> + Clone, temperature: f64) -> f64 {
+        let max = memberships.clone().fold(0.0, f64::max);
+        match self {
+            Aggregation::Max => max,
+            Aggregation::BoundedSum => memberships.sum::<f64>().min(1.0),
+            Aggregation::SoftMax => {
+                // Factored by e^(max/T) so small temperatures do not overflow
+                let floor = (-max / temperature).exp();
+                let sum: f64 = memberships
+                    .map(|m| ((m - max) / temperature).exp() - floor)
+                    .sum();
+                (max + temperature * (floor + sum).ln()).min(1.0)
+            }
+        }
+    }
+}
+
+/// Ceiling on what one output set may contribute while its profile is
+/// active, e.g. High at most 0.4 in a "quiet" profile
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ConsequentCap {
+    profile: String,
+    output: String,
+    set: String,
+    /// Highest activation of the set, 0 to 1; 1 caps nothing
+    max: f64,
+}
+
+/// An output set that fired above its cap in one inference
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CapHit {
+    output: String,
+    set: String,
+    /// Activation before the cap, rule and group weights included
+    activation: f64,
+    max: f64,
+}
+
+impl CapHit {
+    #[cfg(any(test, feature = "cosim"))]
+    fn describe(&self) -> String {
+        format!(
+            "{} {} {:.2} capped at {:.2}",
+            self.output, self.set, self.activation, self.max
+        )
+    }
+}
+
+/// Controller-level defuzzification settings
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Defuzzification {
+    method: DefuzzMethod,
+    /// Aggregated membership below which a crisp output is reported as
+    /// lying outside the activated sets; 0 disables the check
+    support_threshold: f64,
+    aggregation: Aggregation,
+    /// Temperature of the soft-max aggregation, above 0
+    softmax_temperature: f64,
+    /// Caps of every profile, in config order
+    caps: Vec<ConsequentCap>,
+    /// Profile whose caps apply; none applies when unset
+    cap_profile: Option<String>,
+    /// Samples across an output universe, MIN_RESOLUTION to MAX_RESOLUTION
+    resolution: usize,
+    implication: Implication,
+    /// Strengths this close count as tied, the lowest index winning
+    /// (ties.rs)
+    tie_tolerance: f64,
+}
+
+impl Default for Defuzzification {
+    fn default() -> Self {
+        Defuzzification {
+            method: DefuzzMethod::Centroid,
+            support_threshold: 0.05,
+            aggregation: Aggregation::Max,
+            softmax_temperature: 0.1,
+            caps: Vec::new(),
+            cap_profile: None,
+            resolution: RESOLUTION,
+            implication: Implication::Min,
+            tie_tolerance: ties::TOLERANCE,
+        }
+    }
+}
+
+impl Defuzzification {
+    /// Method of `output`, its own or the controller's
+    fn method_for(&self, output: &FuzzyVariable) -> DefuzzMethod {
+        output.defuzz.method.unwrap_or(self.method)
+    }
+
+    /// Samples across the universe of `output`, its own or the
+    /// controller's
+    fn resolution_for(&self, output: &FuzzyVariable) -> usize {
+        output.defuzz.resolution.unwrap_or(self.resolution)
+    }
+
+    fn implication_for(&self, output: &FuzzyVariable) -> Implication {
+        output.defuzz.implication.unwrap_or(self.implication)
+    }
+
+    /// Whether `output` can be defuzzified as these settings and its own
+    /// say: the weighted average samples nothing and reads each set's
+    /// strongest rule, which only the max aggregation keeps
+    fn check(&self, output: &FuzzyVariable) -> Result<(), String> {
+        if let Some(resolution) = output.defuzz.resolution
+            && !(MIN_RESOLUTION..=MAX_RESOLUTION).contains(&resolution)
+        {
+            return Err(format!(
+                "output '{}': resolution must be from {} to {}, got {}",
+                output.name, MIN_RESOLUTION, MAX_RESOLUTION, resolution
+            ));
+        }
+        if self.method_for(output) != DefuzzMethod::WeightedAverage {
+            return Ok(());
+        }
+        if output.defuzz.resolution.is_some() {
+            return Err(format!(
+                "output '{}': weighted_average samples nothing, so it takes no resolution",
+                output.name
+            ));
+        }
+        if self.aggregation != Aggregation::Max {
+            return Err(format!(
+                "output '{}': weighted_average weighs each set by its strongest rule, \
+                 which needs the max aggregation, not {}",
+                output.name,
+                self.aggregation.name()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Cap of `set` of `output` under the active profile, if it caps
+    /// anything
+    fn cap(&self, output: &str, set: &str) -> Option<f64> {
+        let profile = self.cap_profile.as_deref()?;
+        self.caps
+            .iter()
+            .find(|c| c.profile == profile && c.output == output && c.set == set)
+            .map(|c| c.max)
+            .filter(|max| *max < 1.0)
+    }
+
+    /// Names of the cap profiles, in the order they first appear
+    fn cap_profiles(&self) -> Vec<&str> {
+        let mut profiles: Vec<&str> = Vec::new();
+        for cap in &self.caps {
+            if !profiles.contains(&cap.profile.as_str()) {
+                profiles.push(&cap.profile);
+            }
+        }
+        profiles
+    }
+
+    /// The profile after the active one, none after the last
+    #[cfg(any(test, feature = "tui"))]
+    fn next_cap_profile(&self) -> Option<String> {
+        let profiles = self.cap_profiles();
+        let next = match &self.cap_profile {
+            None => 0,
+            Some(active) => profiles.iter().position(|p| p == active)? + 1,
+        };
+        profiles.get(next).map(|p| p.to_string())
+    }
+}
+
+/// Aggregated output membership at `x`: the consequent sets, each clipped
+/// at its rule strength (scaled by it with the product implication),
+/// combined as `settings` says.
+///
+/// Rule strengths already carry the rule and group weights. The rules of
+/// a capped set are combined first and the result clipped at the cap, so
+/// the set as a whole never exceeds it however many rules conclude it;
+/// only then is it combined with the other sets and defuzzified.
+fn aggregated(
+    activations: &Activations,
+    output: &FuzzyVariable,
+    settings: &Defuzzification,
+    x: f64,
+) -> f64 {
+    let temperature = settings.softmax_temperature;
+    let implication = settings.implication_for(output);
+    let clipped = |set: usize, strength: f64| {
+        implication.apply(strength, output.sets[set].function.evaluate(x))
+    };
+    let cap = |set: usize| settings.cap(&output.name, &output.sets[set].name);
+    if settings.aggregation == Aggregation::Max {
+        // A set clipped at (or scaled by) each of its rules is the set
+        // clipped at (scaled by) the strongest of them
+        return (0..output.sets.len())
+            .filter(|set| activations.peak(*set) > 0.0)
+            .map(|set| {
+                let membership = clipped(set, activations.peak(set));
+                cap(set).map_or(membership, |max| membership.min(max))
+            })
+            .fold(0.0, f64::max);
+    }
+    let fired = activations.fired();
+    let implied = fired
+        .iter()
+        .filter(|f| cap(f.set).is_none())
+        .map(|f| clipped(f.set, f.strength));
+    // Each capped set once, where its first rule fired
+    let capped = fired
+        .iter()
+        .enumerate()
+        .filter(|(i, f)| !fired[..*i].iter().any(|g| g.set == f.set))
+        .filter_map(|(_, f)| {
+            let max = cap(f.set)?;
+            let rules = fired
+                .iter()
+                .filter(|g| g.set == f.set)
+                .map(|g| clipped(g.set, g.strength));
+            Some(settings.aggregation.combine(rules, temperature).min(max))
+        });
+    settings
+        .aggregation
+        .combine(implied.chain(capped), temperature)
+}
+
+/// Sets of `output` whose rules fire above their cap
+fn cap_hits(
+    activations: &Activations,
+    output: &FuzzyVariable,
+    settings: &Defuzzification,
+) -> Vec<CapHit> {
+    output
+        .sets
+        .iter()
+        .enumerate()
+        .filter_map(|(index, set)| {
+            let max = settings.cap(&output.name, &set.name)?;
+            let strengths = activations
+                .fired()
+                .iter()
+                .filter(|f| f.set == index)
+                .map(|f| f.strength);
+            let activation = settings
+                .aggregation
+                .combine(strengths, settings.softmax_temperature);
+            (activation > max).then(|| CapHit {
+                output: output.name.clone(),
+                set: set.name.clone(),
+                activation,
+                max,
+            })
+        })
+        .collect()
+}
+
+/// Samples across the output universe the center of area is taken over,
+/// unless `[defuzzification]` or the output says otherwise
+const RESOLUTION: usize = 100;
+/// Bounds of a configured resolution
+pub const MIN_RESOLUTION: usize = 2;
+pub const MAX_RESOLUTION: usize = 1000;
+
+/// Defuzzify `output` as its settings say: the center of area over the
+/// whole aggregated set or its highest plateau's region, or the weighted
+/// average of the set centroids
+#[cfg(feature = "tui")]
+fn defuzzify(activations: &Activations, output: &FuzzyVariable, settings: &Defuzzification) -> f64 {
+    match settings.method_for(output) {
+        DefuzzMethod::WeightedAverage => {
+            budget::weighted_average(activations, output, settings, &budget::centroids(output))
+        }
+        _ => defuzzify_at(
+            activations,
+            output,
+            settings,
+            settings.resolution_for(output),
+        ),
+    }
+}
+
+/// The center of area over `resolution` samples, at most MAX_RESOLUTION;
+/// a latency budget lowers it (budget.rs)
+fn defuzzify_at(
+    activations: &Activations,
+    output: &FuzzyVariable,
+    settings: &Defuzzification,
+    resolution: usize,
+) -> f64 {
+    let resolution = resolution.clamp(1, MAX_RESOLUTION);
+    // The default resolution and below never allocate
+    let mut buffer = [(0.0, 0.0); RESOLUTION + 1];
+    let mut finer = Vec::new();
+    let samples = if resolution <= RESOLUTION {
+        &mut buffer[..=resolution]
+    } else {
+        finer.resize(resolution + 1, (0.0, 0.0));
+        &mut finer[..]
+    };
+    for (i, sample) in samples.iter_mut().enumerate() {
+        let x = output.min + (i as f64 / resolution as f64) * (output.max - output.min);
+        *sample = (x, aggregated(activations, output, settings, x));
+    }
+
+    let samples = &*samples;
+    let region = match settings.method_for(output) {
+        // Weighted averages are not sampled (activation.rs)
+        DefuzzMethod::Centroid | DefuzzMethod::WeightedAverage => samples,
+        DefuzzMethod::PlateauCentroid => {
+            // Grow from the first sample at the peak while the set is
+            // non-zero; plateaus within the tolerance tie (ties.rs)
+            let top = ties::strongest(samples.iter().map(|(_, m)| *m), settings.tie_tolerance)
+                .unwrap_or(0);
+            let start = samples[..top]
+                .iter()
+                .rposition(|(_, m)| *m <= 0.0)
+                .map_or(0, |i| i + 1);
+            let end = samples[top..]
+                .iter()
+                .position(|(_, m)| *m <= 0.0)
+                .map_or(samples.len(), |i| top + i);
+            &samples[start..end]
+        }
+    };
+
+    let numerator: f64 = region.iter().map(|(x, m)| x * m).sum();
+    let denominator: f64 = region.iter().map(|(_, m)| m).sum();
+    if denominator == 0.0 {
+        0.0
+    } else {
+        numerator / denominator
+    }
+}
+
+// ============================================================================
+// FUZZY CONTROLLER
+// ============================================================================
+
+pub struct FuzzyController {
+    system: FuzzySystem,
+    /// Computations in a row no rule fired in, for the log (logging.rs)
+    quiet: Streak,
+}
+
+/// Result of one inference
+pub struct Evaluation {
+    /// Crisp value of every output, in declaration order
+    pub outputs: Vec<f64>,
+    /// Firing strength of every rule, in rule order
+    pub strengths: Vec<f64>,
+    /// Suspicious results worth pointing out to the designer
+    pub diagnostics: Vec<String>,
+    /// Safety envelope that moved the first output, if any
+    #[cfg_attr(not(any(feature = "tui", feature = "cosim")), allow(dead_code))]
+    envelope: Option<envelope::Override>,
+    /// Readings outside their input's universe, in declaration order
+    #[cfg_attr(not(any(feature = "tui", feature = "cosim")), allow(dead_code))]
+    overflows: Vec<Overflow>,
+    /// Output sets held down by the active cap profile
+    #[cfg_attr(not(any(feature = "tui", feature = "cosim")), allow(dead_code))]
+    cap_hits: Vec<CapHit>,
+    /// Method each output was defuzzified with, a latency budget's
+    /// weighted average included
+    #[cfg_attr(not(feature = "cosim"), allow(dead_code))]
+    methods: Vec<DefuzzMethod>,
+}
+
+impl FuzzyController {
+    pub fn from_system(system: FuzzySystem) -> Self {
+        FuzzyController {
+            system,
+            quiet: Streak::default(),
+        }
+    }
+
+    /// Crisp primary output for one value per input variable, in
+    /// declaration order
+    pub fn compute(&self, inputs: &[f64]) -> f64 {
+        self.session().compute(inputs)
+    }
+
+    /// A session for computing many times over without allocating
+    fn session(&self) -> Session<'_> {
+        Session::new(&self.system)
+    }
+
+    /// Breakpoints of every input, in declaration order
+    fn breakpoints(&self) -> Vec<Vec<f64>> {
+        self.system
+            .inputs
+            .iter()
+            .map(FuzzyVariable::breakpoints)
+            .collect()
+    }
+
+    /// Crisp outputs, rule strengths and diagnostics for one value per
+    /// input variable, with the safety envelopes applied
+    pub fn evaluate(&self, inputs: &[f64]) -> Evaluation {
+        self.evaluate_in(self.session(), inputs)
+    }
+
+    /// `evaluate` within a latency budget, timed on `clock` (budget.rs)
+    #[doc(hidden)]
+    pub fn evaluate_within(
+        &self,
+        inputs: &[f64],
+        budget: &mut Budget,
+        clock: &dyn Clock,
+    ) -> Evaluation {
+        self.evaluate_in(Session::within(&self.system, budget, clock), inputs)
+    }
+
+    fn evaluate_in(&self, mut session: Session, inputs: &[f64]) -> Evaluation {
+        let system = &self.system;
+        let overflows: Vec<Overflow> = system
+            .inputs
+            .iter()
+            .zip(inputs)
+            .filter_map(|(var, x)| var.overflow(*x))
+            .collect();
+        session.infer(inputs);
+
+        let settings = &system.defuzzification;
+        let mut diagnostics = Vec::new();
+        let mut hits = Vec::new();
+        for (index, output) in system.outputs.iter().enumerate() {
+            let activations = session.activations(index);
+            hits.extend(cap_hits(activations, output, settings));
+            let value = session.outputs()[index];
+            // Far-apart activations can put the centroid in the gap
+            // between them, where no activated set really applies
+            let support = aggregated(activations, output, settings, value);
+            if !activations.is_empty() && support < settings.support_threshold {
+                diagnostics.push(format!(
+                    "{} = {:.1} lies outside the activated sets (membership {:.2})",
+                    output.name, value, support
+                ));
+            }
+        }
+        let envelope = session.apply_envelopes(inputs);
+        let outputs = session.outputs().to_vec();
+        let strengths = session.strengths().to_vec();
+        self.log_activation(inputs, &strengths);
+        Evaluation {
+            outputs,
+            strengths,
+            methods: session.methods().to_vec(),
+            diagnostics,
+            envelope,
+            overflows,
+            cap_hits: hits,
+        }
+    }
+
+    /// Log when no rule fires at `inputs`, and when rules fire again
+    fn log_activation(&self, inputs: &[f64], strengths: &[f64]) {
+        let fired = strengths.iter().any(|s| *s > 0.0);
+        match self.quiet.update(!fired, Instant::now()) {
+            Change::Began { skipped } => {
+                let readings: Vec<String> = self
+                    .system
+                    .inputs
+                    .iter()
+                    .zip(inputs)
+                    .map(|(var, x)| format!("{}={}", var.name, x))
+                    .collect();
+                let skipped = match skipped {
+                    0 => String::new(),
+                    n => format!(" ({} more since the last report)", n),
+                };
+                event!(Info, "no rule fires at {}{}", readings.join(" "), skipped);
+            }
+            Change::Ended { run } => event!(
+                Info,
+                "rules fire again after {} computation{} without",
+                run,
+                if run == 1 { "" } else { "s" }
+            ),
+            Change::Quiet => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, assert_output_close, assert_trace_fired};
+
+    /// `x` is fully `A` everywhere, so each rule fires at its weights
+    fn grouped_controller() -> FuzzyController {
+        let system = config::parse_system(
+            "rules = [\"IF x IS A THEN y IS B WITH 0.5\"]\n\
+             [[group]]\nname = \"comfort\"\nweight = 0.5\n\
+             rules = [\"IF x IS A THEN y IS B WITH 0.8\", \"IF x IS A THEN y IS C\"]\n\
+             [[group]]\nname = \"safety\"\nenabled = false\n\
+             rules = [\"IF x IS A THEN y IS C\"]\n\
+             [[input]]\nname = \"x\"\nmin = 0\nmax = 10\n\
+             [[input.set]]\nname = \"A\"\nshape = \"trapezoidal\"\nparams = [-1, 0, 10, 11]\n\
+             [output]\nname = \"y\"\nmin = 0\nmax = 10\n\
+             [[output.set]]\nname = \"B\"\nshape = \"triangular\"\nparams = [0, 2, 4]\n\
+             [[output.set]]\nname = \"C\"\nshape = \"triangular\"\nparams = [6, 8, 10]\n",
+        )
+        .unwrap();
+        FuzzyController::from_system(system)
+    }
+
+    #[test]
+    fn outputs_keep_their_golden_values() {
+        // The demo at five points, recorded before rule activations were
+        // kept per set index
+        let points = [
+            (8.0, 45.0),
+            (22.0, 64.0),
+            (27.5, 64.0),
+            (33.0, 90.0),
+            (45.0, 10.0),
+        ];
+        let golden = [
+            (
+                Aggregation::Max,
+                false,
+                [
+                    6.717948717948718,
+                    50.00000000000003,
+                    68.37534818941504,
+                    83.66666666666667,
+                ],
+            ),
+            (
+                Aggregation::SoftMax,
+                false,
+                [
+                    7.047706816185629,
+                    49.999999999999986,
+                    66.99173380182674,
+                    83.66666666666667,
+                ],
+            ),
+            (
+                Aggregation::BoundedSum,
+                false,
+                [
+                    7.298245614035086,
+                    50.00000000000001,
+                    66.36692931906394,
+                    83.66666666666667,
+                ],
+            ),
+            // Plateau centroids with High capped at 0.4
+            (
+                Aggregation::Max,
+                true,
+                [
+                    6.717948717948718,
+                    50.00000000000003,
+                    66.42129629629636,
+                    79.83950617283945,
+                ],
+            ),
+            (
+                Aggregation::BoundedSum,
+                true,
+                [
+                    7.298245614035086,
+                    50.00000000000001,
+                    61.2197456993269,
+                    79.83950617283945,
+                ],
+            ),
+        ];
+        for (aggregation, capped, expected) in golden {
+            let mut system = fixtures::fan();
+            system.defuzzification.aggregation = aggregation;
+            if capped {
+                system.defuzzification.method = DefuzzMethod::PlateauCentroid;
+                system.defuzzification.caps.push(ConsequentCap {
+                    profile: "quiet".to_string(),
+                    output: "fan_speed".to_string(),
+                    set: "High".to_string(),
+                    max: 0.4,
+                });
+                system.defuzzification.cap_profile = Some("quiet".to_string());
+            }
+            let controller = FuzzyController::from_system(system);
+            let outputs: Vec<f64> = points
+                .iter()
+                .map(|(t, h)| controller.compute(&[*t, *h]))
+                .collect();
+            assert_eq!(
+                outputs[..4],
+                expected,
+                "{:?} capped {}",
+                aggregation,
+                capped
+            );
+            assert_eq!(outputs[4], 50.00000000000001);
+        }
+    }
+
+    #[test]
+    fn group_weights_multiply_rule_weights() {
+        let controller = grouped_controller();
+        let strengths = controller.evaluate(&[5.0]).strengths;
+        assert_eq!(strengths, vec![0.5, 0.4, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn disabling_a_group_removes_exactly_its_rules() {
+        let mut controller = grouped_controller();
+        let before = controller.evaluate(&[5.0]).strengths;
+        controller.system.groups[0].enabled = false;
+        controller.system.groups[1].enabled = true;
+        let after = controller.evaluate(&[5.0]).strengths;
+        assert_eq!(after, vec![before[0], 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn a_coupled_rule_behaves_like_the_pair_it_replaces() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let variables = &fan[fan.find("[[input]]").unwrap()..];
+        let damper = "\n[[output]]\nname = \"damper\"\nmin = 0\nmax = 1\n\
+                      [[output.set]]\nname = \"Closed\"\nshape = \"triangular\"\nparams = [0, 0, 1]\n\
+                      [[output.set]]\nname = \"Open\"\nshape = \"triangular\"\nparams = [0, 1, 1]\n";
+        let system = |rules: &[&str]| {
+            let rules: Vec<String> = rules.iter().map(|r| format!("{:?}", r)).collect();
+            let text = format!(
+                "rules = [{}]\n{}{}",
+                rules.join(", "),
+                variables.replace("[output]", "[[output]]"),
+                damper
+            );
+            FuzzyController::from_system(config::parse_system(&text).unwrap())
+        };
+        let coupled = system(&[
+            "IF temperature IS Cold THEN fan_speed IS Off AND damper IS Closed",
+            "IF temperature IS Mild AND humidity IS High THEN fan_speed IS Medium AND damper IS Open WITH 0.5",
+            "IF temperature IS Hot THEN fan_speed IS High AND damper IS Open",
+        ]);
+        let pairs = system(&[
+            "IF temperature IS Cold THEN fan_speed IS Off",
+            "IF temperature IS Cold THEN damper IS Closed",
+            "IF temperature IS Mild AND humidity IS High THEN fan_speed IS Medium WITH 0.5",
+            "IF temperature IS Mild AND humidity IS High THEN damper IS Open WITH 0.5",
+            "IF temperature IS Hot THEN fan_speed IS High",
+            "IF temperature IS Hot THEN damper IS Open",
+        ]);
+
+        for temperature in (0..=10).map(|t| t as f64 * 5.0) {
+            for humidity in (0..=10).map(|h| h as f64 * 10.0) {
+                let point = [temperature, humidity];
+                let got = coupled.evaluate(&point);
+                let expected = pairs.evaluate(&point);
+                assert_eq!(got.outputs, expected.outputs, "at {:?}", point);
+                assert_eq!(got.outputs.len(), 2);
+                for (i, strength) in got.strengths.iter().enumerate() {
+                    assert_eq!(*strength, expected.strengths[2 * i]);
+                    assert_eq!(*strength, expected.strengths[2 * i + 1]);
+                }
+            }
+        }
+    }
+
+    /// A fan and a damper driven by the same two rules, each output with
+    /// `fan` and `damper` added to its section; at x = 2.5 Low holds 0.75
+    /// and High 0.25
+    fn fan_and_damper(
+        fan: &str,
+        damper: &str,
+        defuzzification: &str,
+    ) -> Result<FuzzySystem, String> {
+        config::parse_system(&format!(
+            "rules = [\"IF x IS Low THEN fan IS Slow AND damper IS Shut\", \
+                      \"IF x IS High THEN fan IS Fast AND damper IS Open\"]\n\
+             [[input]]\nname = \"x\"\nmin = 0\nmax = 10\n\
+             [[input.set]]\nname = \"Low\"\nshape = \"triangular\"\nparams = [-10, 0, 10]\n\
+             [[input.set]]\nname = \"High\"\nshape = \"triangular\"\nparams = [0, 10, 20]\n\
+             [[output]]\nname = \"fan\"\nmin = 0\nmax = 100\n{}\n\
+             [[output.set]]\nname = \"Slow\"\nshape = \"triangular\"\nparams = [0, 25, 50]\n\
+             [[output.set]]\nname = \"Fast\"\nshape = \"triangular\"\nparams = [50, 75, 100]\n\
+             [[output]]\nname = \"damper\"\nmin = 0\nmax = 10\n{}\n\
+             [[output.set]]\nname = \"Shut\"\nshape = \"triangular\"\nparams = [0, 2, 4]\n\
+             [[output.set]]\nname = \"Open\"\nshape = \"triangular\"\nparams = [6, 8, 10]\n\
+             [defuzzification]\n{}\n",
+            fan, damper, defuzzification
+        ))
+    }
+
+    #[test]
+    fn each_output_is_defuzzified_its_own_way() {
+        let system = fan_and_damper(
+            "method = \"weighted_average\"",
+            "resolution = 4\nimplication = \"product\"",
+            "",
+        )
+        .unwrap();
+        let evaluation = FuzzyController::from_system(system.clone()).evaluate(&[2.5]);
+        // Slow and Fast centered on 25 and 75: 0.75·25 + 0.25·75
+        assert_output_close(evaluation.outputs[0], 37.5, 1e-9);
+        // Samples at 0, 2.5, ..., 10; Shut and Open are 0.75 at 2.5 and
+        // 7.5, scaled to 0.5625 and 0.1875: 2.8125 / 0.75
+        assert_output_close(evaluation.outputs[1], 3.75, 1e-9);
+        assert_eq!(
+            evaluation.methods,
+            [DefuzzMethod::WeightedAverage, DefuzzMethod::Centroid]
+        );
+
+        let written = config::to_toml(&system, false);
+        assert_eq!(config::parse_system(&written).unwrap(), system);
+
+        // The controller's defaults for whatever an output leaves out. Over
+        // 11 samples, Shut clipped at 0.75 is 0.5, 0.75 and 0.5 at 1, 2
+        // and 3, the region around the highest plateau: 3.5 / 1.75
+        let system =
+            fan_and_damper("", "", "resolution = 10\nmethod = \"plateau_centroid\"").unwrap();
+        let evaluation = FuzzyController::from_system(system).evaluate(&[2.5]);
+        assert_eq!(
+            evaluation.methods,
+            [DefuzzMethod::PlateauCentroid, DefuzzMethod::PlateauCentroid]
+        );
+        assert_output_close(evaluation.outputs[1], 2.0, 1e-9);
+        // Slow at 0.4, 0.75, 0.75 and 0.4 from 10 to 40: 57.5 / 2.3
+        assert_output_close(evaluation.outputs[0], 25.0, 1e-9);
+    }
+
+    #[test]
+    fn a_method_an_output_cannot_take_is_refused() {
+        let refused = |fan: &str, defuzzification: &str| {
+            fan_and_damper(fan, "", defuzzification).unwrap_err()
+        };
+        assert_eq!(
+            refused("method = \"weighted_average\"\nresolution = 50", ""),
+            "output 'fan': weighted_average samples nothing, so it takes no resolution"
+        );
+        assert!(
+            refused(
+                "",
+                "method = \"weighted_average\"\naggregation = \"softmax\""
+            )
+            .contains("output 'fan': weighted_average weighs each set by its strongest rule")
+        );
+        // The controller's resolution is for the outputs that sample
+        assert!(fan_and_damper("method = \"weighted_average\"", "", "resolution = 50").is_ok());
+        assert!(
+            refused("resolution = 1", "")
+                .contains("'resolution' must be a whole number from 2 to 1000")
+        );
+        assert!(refused("resolution = 12.5", "").contains("whole number"));
+        assert!(
+            refused("implication = \"max\"", "")
+                .contains("'implication' must be \"min\" or \"product\"")
+        );
+        assert!(refused("", "method = \"mean_of_maximum\"").contains("'method' must be"));
+    }
+
+    /// Two disjoint output sets both activated, Low fully and High at 0.8
+    fn bimodal(defuzzification: &str) -> FuzzyController {
+        let system = config::parse_system(&format!(
+            "rules = [\"IF x IS A THEN y IS Low\", \"IF x IS A THEN y IS High WITH 0.8\"]\n\
+             [[input]]\nname = \"x\"\nmin = 0\nmax = 10\n\
+             [[input.set]]\nname = \"A\"\nshape = \"trapezoidal\"\nparams = [-1, 0, 10, 11]\n\
+             [output]\nname = \"y\"\nmin = 0\nmax = 100\n\
+             [[output.set]]\nname = \"Low\"\nshape = \"triangular\"\nparams = [0, 10, 20]\n\
+             [[output.set]]\nname = \"High\"\nshape = \"triangular\"\nparams = [80, 90, 100]\n\
+             {}",
+            defuzzification
+        ))
+        .unwrap();
+        FuzzyController::from_system(system)
+    }
+
+    #[test]
+    fn a_centroid_in_the_dead_zone_is_diagnosed() {
+        let evaluation = bimodal("").evaluate(&[5.0]);
+        assert_trace_fired(&evaluation, 0);
+        assert_trace_fired(&evaluation, 1);
+        // (10 * 10 + 9.6 * 90) / 19.6, between the two sets
+        assert_output_close(evaluation.outputs[0], 49.2, 0.5);
+        assert_eq!(evaluation.diagnostics.len(), 1);
+        assert!(evaluation.diagnostics[0].starts_with("y = 49."));
+
+        let quiet = bimodal("[defuzzification]\nsupport_threshold = 0\n").evaluate(&[5.0]);
+        assert!(quiet.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn the_plateau_centroid_keeps_to_the_dominant_region() {
+        let evaluation =
+            bimodal("[defuzzification]\nmethod = \"plateau_centroid\"\n").evaluate(&[5.0]);
+        assert_output_close(evaluation.outputs[0], 10.0, 1e-9);
+        assert!(evaluation.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn every_reading_breaks_a_tie_at_a_crossover_alike() {
+        // At 10 both inputs sit where Low and High cross, each at 0.5
+        let config = |defuzzification: &str| {
+            let input = |name: &str| {
+                format!(
+                    "[[input]]\nname = \"{}\"\nmin = 0\nmax = 20\n\
+                     [[input.set]]\nname = \"Low\"\nshape = \"triangular\"\nparams = [0, 0, 20]\n\
+                     [[input.set]]\nname = \"High\"\nshape = \"triangular\"\nparams = [0, 20, 20]\n",
+                    name
+                )
+            };
+            config::parse_system(&format!(
+                "rules = [\"IF t IS Low AND h IS Low THEN fan IS Slow\", \
+                 \"IF t IS High AND h IS High THEN fan IS Fast\"]\n{}{}\
+                 [output]\nname = \"fan\"\nmin = 0\nmax = 100\n\
+                 [[output.set]]\nname = \"Slow\"\nshape = \"triangular\"\nparams = [0, 25, 50]\n\
+                 [[output.set]]\nname = \"Fast\"\nshape = \"triangular\"\nparams = [50, 75, 100]\n\
+                 [defuzzification]\nsupport_threshold = 0\n{}",
+                input("t"),
+                input("h"),
+                defuzzification
+            ))
+            .unwrap()
+        };
+        let system = config("method = \"plateau_centroid\"\n");
+        let tolerance = system.defuzzification.tie_tolerance;
+        let controller = FuzzyController::from_system(system.clone());
+        let evaluation = controller.evaluate(&[10.0, 10.0]);
+        assert_eq!(evaluation.strengths, [0.5, 0.5]);
+
+        // The first rule, the first output set, the first set of each axis
+        // and the lowest plateau
+        #[cfg(feature = "tui")]
+        assert_eq!(
+            rulecolor::dominant(&evaluation.strengths, tolerance),
+            Some(0)
+        );
+        assert_eq!(
+            dominant_set(
+                &evaluation.strengths,
+                &system.rules,
+                system.output(),
+                tolerance
+            ),
+            Some("Slow")
+        );
+        let trace = scenario::Trace::capture(&controller, &[10.0, 10.0]);
+        assert_eq!(trace.dominant, ["Slow"]);
+        let rule_matrix = matrix::build(&system, &matrix::Axes::default()).unwrap();
+        assert_eq!(rule_matrix.dominant(&system, &[10.0, 10.0]), Some((0, 0)));
+        assert_output_close(evaluation.outputs[0], 25.0, 1e-9);
+
+        // Reversing the rules hands the color to the other, not the set
+        let mut reversed = system.clone();
+        reversed.rules.reverse();
+        let evaluation = FuzzyController::from_system(reversed.clone()).evaluate(&[10.0, 10.0]);
+        #[cfg(feature = "tui")]
+        assert_eq!(
+            rulecolor::dominant(&evaluation.strengths, tolerance),
+            Some(0)
+        );
+        assert_eq!(
+            dominant_set(
+                &evaluation.strengths,
+                &reversed.rules,
+                reversed.output(),
+                tolerance
+            ),
+            Some("Slow")
+        );
+        assert_output_close(evaluation.outputs[0], 25.0, 1e-9);
+
+        // Strengths a rounding apart tie, unless the tolerance is 0
+        let near = [0.5, 0.5 + 1e-12];
+        assert_eq!(
+            dominant_set(&near, &system.rules, system.output(), tolerance),
+            Some("Slow")
+        );
+        #[cfg(feature = "tui")]
+        assert_eq!(rulecolor::dominant(&near, tolerance), Some(0));
+        let exact = config("tie_tolerance = 0\n");
+        let tolerance = exact.defuzzification.tie_tolerance;
+        assert_eq!(
+            dominant_set(&near, &exact.rules, exact.output(), tolerance),
+            Some("Fast")
+        );
+        #[cfg(feature = "tui")]
+        assert_eq!(rulecolor::dominant(&near, tolerance), Some(1));
+    }
+
+    /// `y` for `x` fully A, Low and High at 0.5 and `extra` more High rules
+    fn supported(extra: usize, defuzzification: &str) -> f64 {
+        let mut rules = vec!["\"IF x IS A THEN y IS Low WITH 0.5\""];
+        rules.extend(vec!["\"IF x IS A THEN y IS High WITH 0.5\""; 1 + extra]);
+        let system = config::parse_system(&format!(
+            "rules = [{}]\n\
+             [[input]]\nname = \"x\"\nmin = 0\nmax = 10\n\
+             [[input.set]]\nname = \"A\"\nshape = \"trapezoidal\"\nparams = [-1, 0, 10, 11]\n\
+             [output]\nname = \"y\"\nmin = 0\nmax = 100\n\
+             [[output.set]]\nname = \"Low\"\nshape = \"triangular\"\nparams = [0, 10, 20]\n\
+             [[output.set]]\nname = \"High\"\nshape = \"triangular\"\nparams = [80, 90, 100]\n\
+             [defuzzification]\nsupport_threshold = 0\n{}",
+            rules.join(", "),
+            defuzzification
+        ))
+        .unwrap();
+        FuzzyController::from_system(system)
+            .evaluate(&[5.0])
+            .outputs[0]
+    }
+
+    #[test]
+    fn soft_max_combines_close_to_max_when_cold_and_to_the_sum_when_hot() {
+        let combine = |t| Aggregation::SoftMax.combine([0.5, 0.5].into_iter(), t);
+        // 0.5 + T·ln(2 - e^(-0.5/T))
+        assert!((combine(0.01) - 0.506931).abs() < 1e-6);
+        assert!((combine(0.1) - 0.568977).abs() < 1e-6);
+        assert!((combine(1.0) - 0.831797).abs() < 1e-6);
+        // A lone membership is left as it is, and nothing overflows
+        assert!((Aggregation::SoftMax.combine([0.7].into_iter(), 0.001) - 0.7).abs() < 1e-9);
+        assert_eq!(
+            Aggregation::SoftMax.combine([1.0, 1.0].into_iter(), 10.0),
+            1.0
+        );
+        assert_eq!(Aggregation::Max.combine([0.5, 0.5].into_iter(), 1.0), 0.5);
+        assert_eq!(
+            Aggregation::BoundedSum.combine([0.5, 0.7].into_iter(), 1.0),
+            1.0
+        );
+    }
+
+    #[test]
+    fn a_supporting_rule_shifts_the_output_unless_aggregating_by_max() {
+        let max = "aggregation = \"max\"\n";
+        assert_output_close(supported(0, max), 50.0, 1e-9);
+        assert_output_close(supported(1, max), 50.0, 1e-9);
+
+        // Alone, each set is aggregated as under max
+        let cold = "aggregation = \"softmax\"\nsoftmax_temperature = 0.01\n";
+        assert_output_close(supported(0, cold), 50.0, 1e-9);
+        // The second High rule pulls the centroid up, more the hotter
+        let shifted = |defuzzification: &str| supported(1, defuzzification);
+        assert_output_close(shifted(cold), 50.348, 1e-3);
+        assert_output_close(shifted("aggregation = \"softmax\"\n"), 53.081, 1e-3);
+        let hot = "aggregation = \"softmax\"\nsoftmax_temperature = 1\n";
+        assert_output_close(shifted(hot), 60.290, 1e-3);
+        // High becomes min(1, 2·triangle): (7.5·10 + 15·90) / 22.5
+        let sum = "aggregation = \"bounded_sum\"\n";
+        assert_output_close(shifted(sum), 63.333, 1e-3);
+    }
+
+    /// Caps on y's High set under a "quiet" profile, active or not
+    fn capped(active: &str, aggregation: &str, max: f64) -> String {
+        format!(
+            "aggregation = \"{}\"\ncap_profile = \"{}\"\n\
+             [[cap]]\nprofile = \"quiet\"\noutput = \"y\"\nset = \"High\"\nmax = {}\n",
+            aggregation, active, max
+        )
+    }
+
+    #[test]
+    fn a_cap_clips_its_set_after_aggregation() {
+        // High clipped at 0.3 instead of 0.5: (7.5·10 + 5.1·90) / 12.6
+        assert_output_close(supported(0, &capped("quiet", "max", 0.3)), 42.381, 1e-3);
+        // The cap holds the summed High too: min(0.3, 2·triangle), which
+        // reads 0.2 at 81 and 99 and 0.3 between, so (7.5·10 + 5.5·90) / 13
+        // rather than 63.333 uncapped
+        let sum = capped("quiet", "bounded_sum", 0.3);
+        assert_output_close(supported(1, &sum), 43.846, 1e-3);
+        // Under another profile nothing changes
+        let idle = capped("quiet", "max", 0.3).replace("cap_profile = \"quiet\"\n", "");
+        assert_output_close(supported(0, &idle), 50.0, 1e-9);
+        // A cap of 1 is no cap at all, whatever the aggregation
+        for aggregation in ["max", "softmax", "bounded_sum"] {
+            let uncapped = supported(1, &format!("aggregation = \"{}\"\n", aggregation));
+            let capped = supported(1, &capped("quiet", aggregation, 1.0));
+            assert!((capped - uncapped).abs() < 1e-9, "{}", aggregation);
+        }
+    }
+
+    #[test]
+    fn only_the_active_profile_caps_and_reports_its_hits() {
+        let settings = |active: &str| {
+            let mut text = capped(active, "max", 0.3);
+            text.push_str(
+                "[[cap]]\nprofile = \"night\"\noutput = \"y\"\nset = \"Low\"\nmax = 0.2\n",
+            );
+            text
+        };
+        let system = |active: &str| {
+            config::parse_system(&format!(
+                "rules = [\"IF x IS A THEN y IS High WITH 0.5\"]\n\
+                 [[input]]\nname = \"x\"\nmin = 0\nmax = 10\n\
+                 [[input.set]]\nname = \"A\"\nshape = \"trapezoidal\"\nparams = [-1, 0, 10, 11]\n\
+                 [output]\nname = \"y\"\nmin = 0\nmax = 100\n\
+                 [[output.set]]\nname = \"Low\"\nshape = \"triangular\"\nparams = [0, 10, 20]\n\
+                 [[output.set]]\nname = \"High\"\nshape = \"triangular\"\nparams = [80, 90, 100]\n\
+                 [defuzzification]\n{}",
+                settings(active)
+            ))
+            .unwrap()
+        };
+        let hits = |active: &str| {
+            FuzzyController::from_system(system(active))
+                .evaluate(&[5.0])
+                .cap_hits
+                .iter()
+                .map(CapHit::describe)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(hits("quiet"), ["y High 0.50 capped at 0.30"]);
+        // Low is capped under "night" but no rule fires it
+        assert!(hits("night").is_empty());
+        let mut settings = system("quiet").defuzzification;
+        assert_eq!(settings.cap_profiles(), ["quiet", "night"]);
+        assert_eq!(settings.next_cap_profile().as_deref(), Some("night"));
+        settings.cap_profile = settings.next_cap_profile();
+        assert_eq!(settings.next_cap_profile(), None);
+        settings.cap_profile = None;
+        assert_eq!(settings.next_cap_profile().as_deref(), Some("quiet"));
+        assert_eq!(settings.cap("y", "High"), None);
+
+        let err = config::parse_system(&capped("quiet", "max", 1.5)).unwrap_err();
+        assert!(
+            err.to_string().contains("'max' must be between 0 and 1"),
+            "{}",
+            err
+        );
+    }
+
+    /// A universe of 0 to 10 with a set of every kind at each edge: a
+    /// triangle and a shoulder peaking on it, and a triangle whose support
+    /// runs past it
+    fn edges(policy: OutOfRange) -> FuzzyVariable {
+        let mut var = FuzzyVariable::new("x", 0.0, 10.0)
+            .with_set(
+                "Floor",
+                MembershipFunction::Triangular {
+                    a: 0.0,
+                    b: 0.0,
+                    c: 5.0,
+                },
+            )
+            .with_set(
+                "Under",
+                MembershipFunction::Triangular {
+                    a: -2.0,
+                    b: 0.0,
+                    c: 2.0,
+                },
+            )
+            .with_set(
+                "Ceiling",
+                MembershipFunction::Trapezoidal {
+                    a: 5.0,
+                    b: 8.0,
+                    c: 10.0,
+                    d: 10.0,
+                },
+            )
+            .with_set(
+                "Over",
+                MembershipFunction::Triangular {
+                    a: 8.0,
+                    b: 10.0,
+                    c: 12.0,
+                },
+            );
+        var.out_of_range = policy;
+        var
+    }
+
+    fn memberships(var: &FuzzyVariable, x: f64) -> Vec<f64> {
+        var.fuzzify(x).iter().map(|s| s.membership).collect()
+    }
+
+    #[test]
+    fn shoulders_include_their_limit() {
+        let var = edges(OutOfRange::Clamp);
+        assert_eq!(memberships(&var, 0.0), [1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(memberships(&var, 10.0), [0.0, 0.0, 1.0, 1.0]);
+        let temperature = temperature_variable();
+        assert_eq!(temperature.fuzzify(0.0)[0].membership, 1.0);
+        assert_eq!(temperature.fuzzify(50.0)[2].membership, 1.0);
+    }
+
+    #[test]
+    fn gaussian_sets_are_smooth_and_follow_the_demo() {
+        assert_eq!(gaussian(3.0, 3.0, 1.0), 1.0);
+        assert_eq!(gaussian(4.0, 3.0, 1.0), (-0.5f64).exp());
+        assert_eq!(gaussian(4.0, 3.0, -1.0), gaussian(4.0, 3.0, 1.0));
+        assert_eq!(gaussian(3.0, 3.0, 0.0), 1.0);
+        assert_eq!(gaussian(3.1, 3.0, 0.0), 0.0);
+        assert!(gaussian(1e3, 3.0, 1.0) >= 0.0);
+        assert_eq!(
+            MembershipFunction::from_params("gaussian", &[1.0]),
+            Err("gaussian expects 2 parameters (mean, sigma), got 1".to_string())
+        );
+
+        // The config ships the same system
+        let system = FuzzySystem::demo_gaussian();
+        let shipped = config::load_system("configs/fan_gaussian.toml").unwrap();
+        assert_eq!(
+            (&shipped.inputs, &shipped.outputs, &shipped.rules),
+            (&system.inputs, &system.outputs, &system.rules)
+        );
+        assert_eq!(
+            config::parse_system(&config::to_toml(&system, false)).unwrap(),
+            system
+        );
+
+        // Both shapes agree on the plain cases, and only the Gaussian one
+        // has no kink where a triangle's side ends
+        let (linear, smooth) = (
+            FuzzyController::from_system(fixtures::fan()),
+            FuzzyController::from_system(system),
+        );
+        for point in [[5.0, 20.0], [22.5, 50.0], [45.0, 90.0]] {
+            let (a, b) = (linear.compute(&point), smooth.compute(&point));
+            assert_output_close(a, b, 10.0);
+        }
+        let slopes = |controller: &FuzzyController| {
+            let at = |t: f64| controller.compute(&[t, 60.0]);
+            [14.9, 15.0, 15.1].map(|t| at(t + 0.01) - at(t))
+        };
+        let kink = |s: [f64; 3]| (s[2] - s[0]).abs();
+        assert!(
+            kink(slopes(&smooth)) < kink(slopes(&linear)),
+            "{:?} {:?}",
+            slopes(&smooth),
+            slopes(&linear)
+        );
+    }
+
+    #[test]
+    fn sigmoid_shoulders_are_open_ended_and_stay_in_range() {
+        assert_eq!(sigmoid(4.0, 4.0, 0.9), 0.5);
+        assert_eq!(sigmoid_complement(4.0, 4.0, 0.9), 0.5);
+        assert_eq!(sigmoid(-3.0, -3.0, -2.0), 0.5);
+        // A steep one is a step at the center
+        assert!(sigmoid(4.01, 4.0, 1e4) > 0.999);
+        assert!(sigmoid(3.99, 4.0, 1e4) < 0.001);
+        assert!(sigmoid_complement(3.99, 4.0, 1e4) > 0.999);
+        let edges = [
+            f64::NEG_INFINITY,
+            f64::MIN,
+            -1e300,
+            -1.0,
+            -f64::MIN_POSITIVE,
+            0.0,
+            f64::MIN_POSITIVE,
+            1.0,
+            1e300,
+            f64::MAX,
+            f64::INFINITY,
+        ];
+        for x in edges {
+            for (c, a) in [(0.0, 1.0), (17.5, -0.9), (1e300, 1e300), (-5.0, 0.0)] {
+                for value in [sigmoid(x, c, a), sigmoid_complement(x, c, a)] {
+                    assert!((0.0..=1.0).contains(&value), "{} {} {}: {}", x, c, a, value);
+                }
+            }
+        }
+        assert_eq!(sigmoid(f64::INFINITY, 0.0, 1.0), 1.0);
+        assert_eq!(sigmoid_complement(f64::INFINITY, 0.0, 1.0), 0.0);
+        let cold = MembershipFunction::Sigmoid {
+            center: 17.5,
+            slope: -0.9,
+        };
+        for x in [-10.0, 10.0, 17.5, 30.0] {
+            assert_eq!(cold.evaluate(x), sigmoid_complement(x, 17.5, 0.9));
+        }
+        assert_eq!(
+            MembershipFunction::from_params("sigmoid", &[1.0, 0.0]),
+            Err("sigmoid expects a slope other than 0".to_string())
+        );
+
+        // The shipped config is the demo's variant, and its shoulders reach
+        // past the universe where the trapezoids stop at its limits
+        let system = FuzzySystem::demo_sigmoid();
+        let shipped = config::load_system("configs/fan_sigmoid.toml").unwrap();
+        assert_eq!(
+            (&shipped.inputs, &shipped.outputs, &shipped.rules),
+            (&system.inputs, &system.outputs, &system.rules)
+        );
+        assert_eq!(
+            config::parse_system(&config::to_toml(&system, false)).unwrap(),
+            system
+        );
+        let (cold, hot) = (&system.inputs[0].sets[0], &system.inputs[0].sets[2]);
+        assert!(cold.function.evaluate(0.0) > cold.function.evaluate(5.0));
+        assert!(hot.function.evaluate(50.0) > hot.function.evaluate(45.0));
+        assert_eq!(cold.function.evaluate(17.5), 0.5);
+        let (linear, smooth) = (
+            FuzzyController::from_system(fixtures::fan()),
+            FuzzyController::from_system(system),
+        );
+        for point in [[5.0, 20.0], [22.5, 50.0], [45.0, 90.0]] {
+            let (a, b) = (linear.compute(&point), smooth.compute(&point));
+            assert_output_close(a, b, 5.0);
+        }
+    }
+
+    #[test]
+    fn generalized_bells_peak_at_their_center_and_square_off_with_the_slope() {
+        for a in [1e-300, 0.5, 3.0, -3.0, 1e300] {
+            for b in [1e-9, 0.5, 1.0, 2.0, -2.0, 1e3, f64::MAX] {
+                assert_eq!(gbell(7.0, a, b, 7.0), 1.0, "{} {}", a, b);
+            }
+        }
+        assert_eq!(gbell(10.0, 3.0, 2.0, 7.0), 0.5);
+        assert_eq!(gbell(4.0, 3.0, 2.0, 7.0), 0.5);
+        assert_eq!(gbell(4.0, -3.0, -2.0, 7.0), 0.5);
+
+        // A steep one is the rectangle from c - a to c + a
+        let rectangle = |x: f64| if (x - 7.0).abs() < 3.0 { 1.0 } else { 0.0 };
+        for x in [0.0, 3.9, 4.1, 5.0, 7.0, 9.0, 9.9, 10.1, 15.0] {
+            let (gentle, steep) = (gbell(x, 3.0, 2.0, 7.0), gbell(x, 3.0, 1000.0, 7.0));
+            assert!((steep - rectangle(x)).abs() < 1e-6, "{}: {}", x, steep);
+            assert!((steep - rectangle(x)).abs() <= (gentle - rectangle(x)).abs());
+        }
+
+        // The degenerate ones, and no panic at any float
+        assert_eq!(gbell(-1e6, 3.0, 0.0, 7.0), 0.5);
+        assert_eq!(gbell(7.0, 3.0, 0.0, 7.0), 0.5);
+        assert_eq!(gbell(7.0, 0.0, 2.0, 7.0), 1.0);
+        assert_eq!(gbell(7.1, 0.0, 2.0, 7.0), 0.0);
+        let edges = [
+            f64::NEG_INFINITY,
+            f64::MIN,
+            -1.0,
+            -f64::MIN_POSITIVE,
+            0.0,
+            f64::MIN_POSITIVE,
+            1.0,
+            f64::MAX,
+            f64::INFINITY,
+        ];
+        for x in edges {
+            for (a, b, c) in [
+                (1.0, 1.0, 0.0),
+                (f64::MAX, 0.5, f64::MIN),
+                (1e-300, 1e300, 1.0),
+            ] {
+                let value = gbell(x, a, b, c);
+                assert!(
+                    (0.0..=1.0).contains(&value),
+                    "{} {} {} {}: {}",
+                    x,
+                    a,
+                    b,
+                    c,
+                    value
+                );
+            }
+        }
+        assert!(gbell(f64::NAN, 1.0, 1.0, 0.0).is_nan());
+
+        let bell = MembershipFunction::from_params("gbell", &[3.0, 2.0, 7.0]).unwrap();
+        assert_eq!(bell.evaluate(7.0), 1.0);
+        assert_eq!(bell.corners(), [4.0, 7.0, 10.0]);
+        assert_eq!(bell.support(), (f64::NEG_INFINITY, f64::INFINITY));
+        assert_eq!(
+            MembershipFunction::from_params("gbell", &[3.0, 0.0, 7.0]),
+            Err("gbell expects a slope b other than 0".to_string())
+        );
+        assert_eq!(
+            MembershipFunction::from_params("gbell", &[3.0, 2.0]),
+            Err("gbell expects 3 parameters (a, b, c), got 2".to_string())
+        );
+    }
+
+    #[test]
+    fn s_and_z_curves_cross_halfway_and_add_up_to_one() {
+        for (a, b) in [(30.0, 50.0), (0.0, 1.0), (-3.0, 7.0), (1e-300, 1e300)] {
+            let middle = (a + b) / 2.0;
+            assert_eq!(s_curve(middle, a, b), 0.5, "{} {}", a, b);
+            assert_eq!(z_curve(middle, a, b), 0.5, "{} {}", a, b);
+            assert_eq!((s_curve(a, a, b), s_curve(b, a, b)), (0.0, 1.0));
+            assert_eq!((z_curve(a, a, b), z_curve(b, a, b)), (1.0, 0.0));
+        }
+        let edges = [
+            f64::NEG_INFINITY,
+            f64::MIN,
+            -1.0,
+            0.0,
+            f64::MIN_POSITIVE,
+            1.0,
+            f64::MAX,
+            f64::INFINITY,
+        ];
+        for (a, b) in [(30.0, 50.0), (0.0, 1.0), (5.0, 5.0), (f64::MIN, f64::MAX)] {
+            for x in edges {
+                let (s, z) = (s_curve(x, a, b), z_curve(x, a, b));
+                assert_eq!(s + z, 1.0, "{} {} {}", x, a, b);
+                assert!((0.0..=1.0).contains(&s), "{} {} {}: {}", x, a, b, s);
+            }
+        }
+        for (a, b) in [(30.0, 50.0), (0.0, 1.0), (5.0, 5.0), (-1e-9, 1e-9)] {
+            let mut last = 0.0;
+            for i in -100..=1100 {
+                let x = a + (b - a + 1.0) * i as f64 / 1000.0;
+                let (s, z) = (s_curve(x, a, b), z_curve(x, a, b));
+                assert_eq!(s + z, 1.0, "{} {} {}", x, a, b);
+                assert!(s >= last, "{} {} {}: {} after {}", x, a, b, s, last);
+                last = s;
+            }
+        }
+        // Equal ends are a step, and the halves meet without a jump
+        assert_eq!((s_curve(4.9, 5.0, 5.0), s_curve(5.0, 5.0, 5.0)), (0.0, 1.0));
+        assert_eq!(s_curve(35.0, 30.0, 50.0), 0.125);
+        assert_eq!(s_curve(45.0, 30.0, 50.0), 0.875);
+        let step = s_curve(40.0 + 1e-9, 30.0, 50.0) - s_curve(40.0 - 1e-9, 30.0, 50.0);
+        assert!(step > 0.0 && step < 1e-9, "{}", step);
+
+        let s = MembershipFunction::from_params("s_curve", &[30.0, 50.0]).unwrap();
+        let z = MembershipFunction::from_params("z_curve", &[30.0, 50.0]).unwrap();
+        assert_eq!((s.kind(), z.kind()), ("s_curve", "z_curve"));
+        assert_eq!(s.corners(), [30.0, 40.0, 50.0]);
+        assert_eq!(s.support(), (30.0, f64::INFINITY));
+        assert_eq!(z.support(), (f64::NEG_INFINITY, 50.0));
+        assert_eq!(
+            MembershipFunction::from_params("z_curve", &[50.0, 30.0]),
+            Err("z_curve expects a <= b, got 50, 30".to_string())
+        );
+        assert_eq!(
+            MembershipFunction::from_params("s_curve", &[30.0]),
+            Err("s_curve expects 2 parameters (a, b), got 1".to_string())
+        );
+
+        // The shipped config is the demo's variant, whose Low humidity
+        // falls over the trapezoid's stretch, crossing it halfway
+        let system = FuzzySystem::demo_z_curve();
+        let shipped = config::load_system("configs/fan_z_curve.toml").unwrap();
+        assert_eq!(
+            (&shipped.inputs, &shipped.outputs, &shipped.rules),
+            (&system.inputs, &system.outputs, &system.rules)
+        );
+        assert_eq!(
+            config::parse_system(&config::to_toml(&system, false)).unwrap(),
+            system
+        );
+        let (smooth, straight) = (
+            &system.inputs[1].sets[0].function,
+            &fixtures::fan().inputs[1].sets[0].function,
+        );
+        for x in [0.0, 30.0, 40.0, 50.0, 100.0] {
+            assert_eq!(smooth.evaluate(x), straight.evaluate(x), "{}", x);
+        }
+        assert!(smooth.evaluate(35.0) > straight.evaluate(35.0));
+        assert!(smooth.evaluate(45.0) < straight.evaluate(45.0));
+        let (linear, smooth) = (
+            FuzzyController::from_system(fixtures::fan()),
+            FuzzyController::from_system(system),
+        );
+        for point in [[10.0, 20.0], [22.5, 35.0], [22.5, 45.0], [35.0, 90.0]] {
+            let (a, b) = (linear.compute(&point), smooth.compute(&point));
+            assert_output_close(a, b, 5.0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "s_curve expects a <= b, got a = 50 and b = 30")]
+    fn an_s_curve_ending_before_it_starts_panics() {
+        s_curve(40.0, 50.0, 30.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "z_curve expects a <= b, got a = 50 and b = 30")]
+    fn a_z_curve_ending_before_it_starts_panics() {
+        z_curve(40.0, 50.0, 30.0);
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn a_run_without_rules_firing_is_logged_when_it_begins_and_ends() {
+        use log::Level::Info;
+
+        // Nothing fires at x = 0
+        let controller = FuzzyController::from_system(fixtures::single_rule());
+        let logged = crate::logging::capture(|| {
+            for x in [5.0, 0.0, 0.0, 0.0, 5.0, 5.0] {
+                controller.evaluate(&[x]);
+            }
+            // Again within the interval: counted for the next report
+            controller.evaluate(&[0.0]);
+            controller.evaluate(&[5.0]);
+            // Computations that skip the trace are not watched
+            controller.compute(&[0.0]);
+        });
+        assert_eq!(
+            logged,
+            [
+                (Info, "fuzzy_logic: no rule fires at x=0".to_string()),
+                (
+                    Info,
+                    "fuzzy_logic: rules fire again after 3 computations without".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn closures_shape_sets_like_the_built_in_functions() {
+        // The demo's Mild temperature as a closure computes the same
+        let mild = |x: f64| triangular(x, 15.0, 22.5, 30.0);
+        let mut system = fixtures::fan();
+        system.inputs[0].sets[1].function = MembershipFunction::custom(mild);
+        let (built_in, custom) = (
+            FuzzyController::from_system(fixtures::fan()),
+            FuzzyController::from_system(system.clone()),
+        );
+        for point in [[10.0, 30.0], [18.0, 40.0], [22.5, 50.0], [27.0, 65.0]] {
+            assert_eq!(
+                custom.evaluate(&point).outputs,
+                built_in.evaluate(&point).outputs
+            );
+        }
+
+        // Taken within [0, 1], NaN as 0, and sharing the function makes
+        // copies equal
+        let wild = MembershipFunction::custom(|x: f64| if x < 0.0 { f64::NAN } else { x });
+        assert_eq!([-1.0, 0.5, 3.0].map(|x| wild.evaluate(x)), [0.0, 0.5, 1.0]);
+        assert_eq!(wild.clone(), wild);
+        assert_ne!(wild, MembershipFunction::custom(|x: f64| x));
+        assert_eq!((wild.kind(), wild.params()), ("custom", Vec::new()));
+        assert_eq!(wild.support(), (f64::NEG_INFINITY, f64::INFINITY));
+
+        // A neighbour's breakpoint moves without touching it
+        let temperature = &mut system.inputs[0];
+        links::set_param(&mut temperature.sets, "Hot", 0, 26.0).unwrap();
+        assert_eq!(temperature.sets[1].function.kind(), "custom");
+        assert_eq!(
+            links::set_param(&mut temperature.sets, "Mild", 0, 16.0),
+            Err("set 'Mild' has no parameter a".to_string())
+        );
+
+        // A config cannot hold one, so one written out does not load
+        let error = config::parse_system(&config::to_toml(&system, false)).unwrap_err();
+        assert!(
+            error.contains("custom shapes are written in code"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn readings_past_either_limit_follow_the_input_policy() {
+        // Just and far past the min, then the max
+        let past = [(-0.1, 0.0), (-1e6, 0.0), (10.1, 10.0), (1e6, 10.0)];
+        for (x, limit) in past {
+            for policy in [OutOfRange::Clamp, OutOfRange::Extend] {
+                let var = edges(policy);
+                // Every shape holds its membership at the limit
+                assert_eq!(memberships(&var, x), memberships(&var, limit), "{}", x);
+                let overflow = var.overflow(x).unwrap();
+                assert_eq!((overflow.limit, overflow.high), (limit, limit > 0.0));
+                assert_eq!(overflow.policy, policy);
+            }
+            assert_eq!(edges(OutOfRange::Clamp).admit(x), Ok(limit));
+            assert_eq!(edges(OutOfRange::Extend).admit(x), Ok(x));
+
+            let refusing = edges(OutOfRange::Error);
+            assert_eq!(
+                refusing.admit(x),
+                Err(format!("x = {} is outside 0 to 10", x))
+            );
+            assert_eq!(memberships(&refusing, x), [0.0; 4]);
+        }
+        let var = edges(OutOfRange::Error);
+        assert_eq!(var.overflow(10.0), None);
+        assert_eq!(var.admit(10.0), Ok(10.0));
+        assert_eq!(var.overflow(f64::NAN), None);
+    }
+
+    #[test]
+    fn evaluations_report_readings_outside_the_universe() {
+        let controller = |policy| {
+            let mut system = fixtures::fan();
+            system.inputs[0].out_of_range = policy;
+            FuzzyController::from_system(system)
+        };
+        let at_limit = controller(OutOfRange::Clamp).evaluate(&[50.0, 60.0]);
+        assert!(at_limit.overflows.is_empty());
+        for policy in [OutOfRange::Clamp, OutOfRange::Extend] {
+            for x in [50.5, 1e6] {
+                let evaluation = controller(policy).evaluate(&[x, 60.0]);
+                assert_eq!(evaluation.outputs, at_limit.outputs);
+                assert_eq!(evaluation.overflows.len(), 1);
+            }
+        }
+        // A refused reading counts as a failed sensor
+        let refused = controller(OutOfRange::Error).evaluate(&[-3.0, 60.0]);
+        let failed = controller(OutOfRange::Error).evaluate(&[f64::NAN, 60.0]);
+        assert_eq!(refused.outputs, failed.outputs);
+        assert_eq!(
+            refused.overflows[0].describe(),
+            "temperature -3 below 0, refused"
+        );
+    }
+}
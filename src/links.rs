@@ -0,0 +1,325 @@
+// ============================================================================
+// PARAMETER LINKS - Parâmetros vinculados
+// ============================================================================
+//
+// A set parameter in a config may name a parameter of another set of the
+// same variable instead of giving a number, so related edges stay
+// together when one of them is edited:
+//
+//     [[input.set]]
+//     name = "Hot"
+//     shape = "trapezoidal"
+//     params = ["Mild.b", 30.0, 50.0, 50.0]   # Hot starts where Mild peaks
+//
+// Parameters are named a, b, c, d in order. Links are resolved when the
+// config loads, following chains in dependency order whatever the order
+// of the sets; a cycle, or a link to a missing set or parameter, refuses
+// the config. The model keeps the resolved numbers, which is what the
+// controller and exports use, and remembers the links so that
+// `bundle --keep-links` can write them back and a `param` edit on the
+// daemon moves the linked parameters along.
+
+use crate::SetDefinition;
+
+const NAMES: [char; 4] = ['a', 'b', 'c', 'd'];
+
+/// Position of the parameter called `name` (a to d)
+pub fn param_index(name: &str) -> Option<usize> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => NAMES.iter().position(|n| *n == c),
+        _ => None,
+    }
+}
+
+/// Reference to parameter `param` of the set named `set`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub set: String,
+    pub param: usize,
+}
+
+impl Link {
+    /// Read `Set.b`
+    pub fn parse(text: &str) -> Option<Self> {
+        let (set, name) = text.rsplit_once('.')?;
+        let param = param_index(name)?;
+        if set.is_empty() {
+            return None;
+        }
+        Some(Link {
+            set: set.to_string(),
+            param,
+        })
+    }
+
+    pub fn to_text(&self) -> String {
+        format!("{}.{}", self.set, NAMES[self.param])
+    }
+}
+
+/// A set parameter as written: a number or a link
+#[derive(Debug, Clone, PartialEq)]
+pub enum Param {
+    Value(f64),
+    Link(Link),
+}
+
+/// Parameters of `set` as written, its links in place of their values
+pub(crate) fn specs(set: &SetDefinition) -> Vec<Param> {
+    let params = set.function.params();
+    params
+        .iter()
+        .enumerate()
+        .map(|(i, value)| match set.links.get(i) {
+            Some(Some(link)) => Param::Link(link.clone()),
+            _ => Param::Value(*value),
+        })
+        .collect()
+}
+
+/// Links among `params`, or nothing when there are none
+pub fn links_of(params: &[Param]) -> Vec<Option<Link>> {
+    if params.iter().all(|p| matches!(p, Param::Value(_))) {
+        return Vec::new();
+    }
+    params
+        .iter()
+        .map(|p| match p {
+            Param::Link(link) => Some(link.clone()),
+            Param::Value(_) => None,
+        })
+        .collect()
+}
+
+/// Concrete parameters of every set of one variable, given by name and
+/// parameters as written. Errors carry the index of the set at fault.
+pub fn resolve(sets: &[(String, Vec<Param>)]) -> Result<Vec<Vec<f64>>, (usize, String)> {
+    let mut values: Vec<Vec<Option<f64>>> = sets
+        .iter()
+        .map(|(_, params)| vec![None; params.len()])
+        .collect();
+    for set in 0..sets.len() {
+        for param in 0..sets[set].1.len() {
+            resolve_one(sets, (set, param), &mut values, &mut Vec::new())?;
+        }
+    }
+    Ok(values
+        .into_iter()
+        .map(|params| params.into_iter().flatten().collect())
+        .collect())
+}
+
+/// Depth-first, so every parameter is resolved after the one it links to;
+/// `path` holds the links being followed, to catch cycles
+fn resolve_one(
+    sets: &[(String, Vec<Param>)],
+    node: (usize, usize),
+    values: &mut [Vec<Option<f64>>],
+    path: &mut Vec<(usize, usize)>,
+) -> Result<f64, (usize, String)> {
+    let (set, param) = node;
+    if let Some(value) = values[set][param] {
+        return Ok(value);
+    }
+    let name = |(set, param): (usize, usize)| format!("{}.{}", sets[set].0, NAMES[param]);
+    if let Some(start) = path.iter().position(|n| *n == node) {
+        let cycle: Vec<String> = path[start..]
+            .iter()
+            .chain([&node])
+            .map(|n| name(*n))
+            .collect();
+        return Err((
+            path[start].0,
+            format!("parameter links form a cycle: {}", cycle.join(" -> ")),
+        ));
+    }
+    let value = match &sets[set].1[param] {
+        Param::Value(value) => *value,
+        Param::Link(link) => {
+            let Some(target) = sets.iter().position(|(name, _)| *name == link.set) else {
+                return Err((
+                    set,
+                    format!("{} links to unknown set '{}'", name(node), link.set),
+                ));
+            };
+            if link.param >= sets[target].1.len() {
+                return Err((
+                    set,
+                    format!(
+                        "{} links to missing parameter {}",
+                        name(node),
+                        link.to_text()
+                    ),
+                ));
+            }
+            path.push(node);
+            let value = resolve_one(sets, (target, link.param), values, path)?;
+            path.pop();
+            value
+        }
+    };
+    values[set][param] = Some(value);
+    Ok(value)
+}
+
+/// Set parameter `param` of set `set` among `sets` to `value`, moving the
+/// parameters linked to it along. The edited parameter keeps the value and
+/// drops its own link. Nothing changes when a shape would break.
+#[cfg(any(test, feature = "daemon"))]
+pub(crate) fn set_param(
+    sets: &mut [SetDefinition],
+    set: &str,
+    param: usize,
+    value: f64,
+) -> Result<(), String> {
+    let index = sets
+        .iter()
+        .position(|s| s.name == set)
+        .ok_or_else(|| format!("unknown set '{}'", set))?;
+    let mut specs: Vec<(String, Vec<Param>)> =
+        sets.iter().map(|s| (s.name.clone(), specs(s))).collect();
+    let Some(spec) = specs[index].1.get_mut(param) else {
+        return Err(format!(
+            "set '{}' has no parameter {}",
+            set,
+            NAMES[param.min(3)]
+        ));
+    };
+    *spec = Param::Value(value);
+
+    let resolved = resolve(&specs).map_err(|(_, e)| e)?;
+    let mut updated = sets.to_vec();
+    for ((set, (_, spec)), params) in updated.iter_mut().zip(&specs).zip(resolved) {
+        // Unmoved sets stay as they are, custom ones included
+        if params != set.function.params() {
+            set.function = crate::MembershipFunction::from_params(set.function.kind(), &params)
+                .map_err(|e| format!("set '{}': {}", set.name, e))?;
+        }
+        set.links = links_of(spec);
+    }
+    sets.clone_from_slice(&updated);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MembershipFunction;
+
+    fn link(text: &str) -> Param {
+        Param::Link(Link::parse(text).unwrap())
+    }
+
+    fn sets(specs: &[(&str, Vec<Param>)]) -> Vec<(String, Vec<Param>)> {
+        specs
+            .iter()
+            .map(|(name, params)| (name.to_string(), params.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn links_resolve_through_chains_in_any_order() {
+        // Hot links to Mild, declared after it, which links to Cold
+        let resolved = resolve(&sets(&[
+            (
+                "Hot",
+                vec![link("Mild.b"), Param::Value(40.0), Param::Value(50.0)],
+            ),
+            (
+                "Mild",
+                vec![link("Cold.c"), Param::Value(25.0), Param::Value(30.0)],
+            ),
+            (
+                "Cold",
+                vec![Param::Value(0.0), Param::Value(10.0), Param::Value(20.0)],
+            ),
+        ]))
+        .unwrap();
+        assert_eq!(
+            resolved,
+            [
+                vec![25.0, 40.0, 50.0],
+                vec![20.0, 25.0, 30.0],
+                vec![0.0, 10.0, 20.0]
+            ]
+        );
+    }
+
+    #[test]
+    fn cycles_and_dangling_links_are_refused() {
+        let error = resolve(&sets(&[
+            ("A", vec![link("B.b"), Param::Value(1.0), Param::Value(2.0)]),
+            ("B", vec![Param::Value(0.0), link("A.a"), Param::Value(2.0)]),
+        ]))
+        .unwrap_err();
+        assert_eq!(
+            error,
+            (
+                0,
+                "parameter links form a cycle: A.a -> B.b -> A.a".to_string()
+            )
+        );
+        assert_eq!(
+            resolve(&sets(&[("A", vec![link("A.a")])])).unwrap_err().1,
+            "parameter links form a cycle: A.a -> A.a"
+        );
+
+        let error = resolve(&sets(&[("A", vec![Param::Value(0.0), link("C.a")])]));
+        assert_eq!(
+            error.unwrap_err(),
+            (0, "A.b links to unknown set 'C'".to_string())
+        );
+        let error = resolve(&sets(&[
+            (
+                "A",
+                vec![Param::Value(0.0), Param::Value(1.0), Param::Value(2.0)],
+            ),
+            ("B", vec![link("A.d")]),
+        ]));
+        assert_eq!(
+            error.unwrap_err(),
+            (1, "B.a links to missing parameter A.d".to_string())
+        );
+        assert_eq!(Link::parse("Mild.e"), None);
+        assert_eq!(Link::parse(".a"), None);
+    }
+
+    #[test]
+    fn editing_a_parameter_moves_its_links() {
+        let mut sets = vec![
+            SetDefinition {
+                name: "Mild".to_string(),
+                function: MembershipFunction::Triangular {
+                    a: 15.0,
+                    b: 22.5,
+                    c: 30.0,
+                },
+                uncertainty: Vec::new(),
+                links: Vec::new(),
+            },
+            SetDefinition {
+                name: "Hot".to_string(),
+                function: MembershipFunction::Triangular {
+                    a: 22.5,
+                    b: 35.0,
+                    c: 50.0,
+                },
+                uncertainty: Vec::new(),
+                links: vec![Some(Link::parse("Mild.b").unwrap()), None, None],
+            },
+        ];
+        set_param(&mut sets, "Mild", 1, 25.0).unwrap();
+        assert_eq!(sets[1].function.params(), [25.0, 35.0, 50.0]);
+
+        // A shape the edit would break leaves everything as it was
+        let before = sets.clone();
+        assert!(set_param(&mut sets, "Mild", 1, 40.0).is_err());
+        assert_eq!(sets, before);
+
+        // Editing the linked parameter itself unlinks it
+        set_param(&mut sets, "Hot", 0, 20.0).unwrap();
+        assert_eq!(sets[1].links, Vec::new());
+        assert!(set_param(&mut sets, "Hot", 3, 1.0).is_err());
+    }
+}
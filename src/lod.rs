@@ -0,0 +1,322 @@
+// ============================================================================
+// HISTORY LEVELS OF DETAIL - Níveis de detalhe do histórico
+// ============================================================================
+//
+// A long history (`--history N` in the TUI) is too much to redraw every
+// frame. Next to the entries, each charted series keeps summaries at
+// coarser levels, per second and per minute: buckets of min, max, sum
+// and count over aligned stretches of time, updated as entries arrive or
+// leave rather than recomputed.
+//
+// The sparklines draw the entries themselves while a view holds few
+// enough of them, and otherwise the finest level whose buckets fit the
+// width (`Series::level_for`), as a band from each bucket's min to its max
+// around its mean, so a spike lasting one entry still shows. The cost of
+// a frame then depends on the width, not on the length of the history.
+//
+// An entry leaving a bucket takes its value out of the sum; only when it
+// was the bucket's min or max are that bucket's remaining entries read
+// again, and a bucket never spans more than a minute of them.
+
+use std::collections::VecDeque;
+
+/// Widths of the levels in seconds, finest first
+pub const LEVELS: [f64; 2] = [1.0, 60.0];
+
+/// Summary of the entries of one stretch of time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    /// Start of the stretch, a multiple of the level's width
+    pub start: f64,
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub count: usize,
+}
+
+impl Bucket {
+    fn new(start: f64, value: f64) -> Self {
+        Bucket {
+            start,
+            min: value,
+            max: value,
+            sum: value,
+            count: 1,
+        }
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+
+    fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Take `value` out, reading `rest` (the bucket's remaining values)
+    /// again only if it was an extreme
+    fn remove(&mut self, value: f64, rest: impl Iterator<Item = f64>) {
+        self.sum -= value;
+        self.count -= 1;
+        if value <= self.min || value >= self.max {
+            let (min, max) = rest.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+                (lo.min(v), hi.max(v))
+            });
+            (self.min, self.max) = (min, max);
+        }
+    }
+
+    fn merge(&mut self, other: &Bucket) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Level {
+    width: f64,
+    buckets: VecDeque<Bucket>,
+}
+
+impl Level {
+    fn start_of(&self, time: f64) -> f64 {
+        (time / self.width).floor() * self.width
+    }
+
+    /// Buckets that start inside `from..to`
+    fn range(&self, from: f64, to: f64) -> impl Iterator<Item = &Bucket> {
+        let first = self.buckets.partition_point(|b| b.start < from);
+        let last = self.buckets.partition_point(|b| b.start < to);
+        self.buckets.range(first..last.max(first))
+    }
+}
+
+/// Levels of detail of one series of (time, value) entries, which arrive
+/// in time order
+#[derive(Debug, Clone)]
+pub struct Series {
+    levels: Vec<Level>,
+}
+
+impl Default for Series {
+    fn default() -> Self {
+        Series {
+            levels: LEVELS
+                .iter()
+                .map(|&width| Level {
+                    width,
+                    buckets: VecDeque::new(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Series {
+    /// Summaries of `points` from scratch
+    pub fn from_points(points: impl Iterator<Item = (f64, f64)>) -> Self {
+        let mut series = Series::default();
+        for (time, value) in points {
+            series.push(time, value);
+        }
+        series
+    }
+
+    pub fn push(&mut self, time: f64, value: f64) {
+        for level in &mut self.levels {
+            let start = level.start_of(time);
+            match level.buckets.back_mut() {
+                Some(bucket) if bucket.start == start => bucket.add(value),
+                _ => level.buckets.push_back(Bucket::new(start, value)),
+            }
+        }
+    }
+
+    /// The oldest entry, `value`, left; `rest` are the entries still kept,
+    /// oldest first
+    pub fn remove_first(&mut self, value: f64, rest: impl Iterator<Item = (f64, f64)> + Clone) {
+        for level in &mut self.levels {
+            let Some(bucket) = level.buckets.front_mut() else {
+                continue;
+            };
+            if bucket.count == 1 {
+                level.buckets.pop_front();
+                continue;
+            }
+            let end = bucket.start + level.width;
+            let rest = rest.clone().take_while(|(t, _)| *t < end).map(|(_, v)| v);
+            bucket.remove(value, rest);
+        }
+    }
+
+    /// The newest entry, `value`, left; `rest` are the entries still kept,
+    /// oldest first
+    pub fn remove_last(
+        &mut self,
+        value: f64,
+        rest: impl DoubleEndedIterator<Item = (f64, f64)> + Clone,
+    ) {
+        for level in &mut self.levels {
+            let Some(bucket) = level.buckets.back_mut() else {
+                continue;
+            };
+            if bucket.count == 1 {
+                level.buckets.pop_back();
+                continue;
+            }
+            let start = bucket.start;
+            let rest = rest
+                .clone()
+                .rev()
+                .take_while(|(t, _)| *t >= start)
+                .map(|(_, v)| v);
+            bucket.remove(value, rest);
+        }
+    }
+
+    /// Finest level with at most `points` buckets over `from..to`, none
+    /// when even the coarsest has more
+    pub fn level_for(&self, from: f64, to: f64, points: usize) -> Option<usize> {
+        self.levels
+            .iter()
+            .position(|level| level.range(from, to).count() <= points)
+    }
+
+    /// Buckets of `level` that start inside `from..to`
+    pub fn buckets(&self, level: usize, from: f64, to: f64) -> Vec<Bucket> {
+        self.levels[level].range(from, to).copied().collect()
+    }
+
+    /// Min, mean and max of the entries in the whole seconds of `from..to`,
+    /// from minute buckets where they fit and second buckets at the edges
+    pub fn summary(&self, from: f64, to: f64) -> Option<Bucket> {
+        let (seconds, minutes) = (&self.levels[0], &self.levels[1]);
+        let mut total: Option<Bucket> = None;
+        let mut add = |bucket: &Bucket| match &mut total {
+            Some(total) => total.merge(bucket),
+            None => total = Some(*bucket),
+        };
+        let whole = minutes
+            .range(from, to)
+            .filter(|b| b.start + minutes.width <= to)
+            .fold(None, |span: Option<(f64, f64)>, b| {
+                add(b);
+                Some((span.map_or(b.start, |s| s.0), b.start + minutes.width))
+            });
+        for bucket in seconds.range(from, to) {
+            let inside = whole.is_some_and(|(lo, hi)| bucket.start >= lo && bucket.start < hi);
+            if !inside && bucket.start + seconds.width <= to {
+                add(bucket);
+            }
+        }
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Every bucket of every level recomputed from the entries
+    fn brute_force(points: &[(f64, f64)]) -> Vec<Vec<Bucket>> {
+        LEVELS
+            .iter()
+            .map(|&width| {
+                let mut buckets: Vec<Bucket> = Vec::new();
+                for &(time, value) in points {
+                    let start = (time / width).floor() * width;
+                    match buckets.last_mut() {
+                        Some(b) if b.start == start => b.add(value),
+                        _ => buckets.push(Bucket::new(start, value)),
+                    }
+                }
+                buckets
+            })
+            .collect()
+    }
+
+    fn assert_matches(series: &Series, points: &[(f64, f64)]) {
+        for (level, expected) in series.levels.iter().zip(brute_force(points)) {
+            assert_eq!(level.buckets.len(), expected.len());
+            for (got, want) in level.buckets.iter().zip(&expected) {
+                assert_eq!((got.start, got.count), (want.start, want.count));
+                assert_eq!((got.min, got.max), (want.min, want.max));
+                assert!((got.sum - want.sum).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_buckets_match_a_recomputation() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut points: VecDeque<(f64, f64)> = VecDeque::new();
+        let mut series = Series::default();
+        let mut time = 0.0;
+        for step in 0..20_000 {
+            time += rng.gen_range(0.0..0.9);
+            // Repeated values, so removed extremes are often tied
+            let value = (rng.gen_range(0.0..50.0_f64) * 2.0).round() / 2.0;
+            series.push(time, value);
+            points.push_back((time, value));
+            // A capped history, and now and then a coalesced nudge
+            if points.len() > 3_000 {
+                let (_, value) = points.pop_front().unwrap();
+                series.remove_first(value, points.iter().copied());
+            }
+            if rng.gen_bool(0.05) {
+                let (_, value) = points.pop_back().unwrap();
+                series.remove_last(value, points.iter().copied());
+            }
+            if step % 997 == 0 {
+                assert_matches(&series, points.make_contiguous());
+            }
+        }
+        let points = points.make_contiguous();
+        assert_matches(&series, points);
+        assert_eq!(
+            Series::from_points(points.iter().copied()).buckets(1, 0.0, time + 1.0),
+            series.buckets(1, 0.0, time + 1.0)
+        );
+
+        // The window summary against the entries of its whole seconds
+        for _ in 0..50 {
+            let from = rng.gen_range(points[0].0..time).floor();
+            let to = (from + rng.gen_range(1.0..600.0_f64)).floor();
+            let inside: Vec<f64> = points
+                .iter()
+                .filter(|(t, _)| *t >= from && *t < to)
+                .map(|(_, v)| *v)
+                .collect();
+            let Some(summary) = series.summary(from, to) else {
+                assert!(inside.is_empty());
+                continue;
+            };
+            assert_eq!(summary.count, inside.len());
+            let min = inside.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = inside.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            assert_eq!((summary.min, summary.max), (min, max));
+            let mean = inside.iter().sum::<f64>() / inside.len() as f64;
+            assert!((summary.mean() - mean).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn the_finest_level_that_fits_is_chosen() {
+        // Ten entries a second for ten minutes
+        let series = Series::from_points((0..6000).map(|i| (i as f64 / 10.0, i as f64)));
+        assert_eq!(series.level_for(0.0, 600.0, 600), Some(0));
+        assert_eq!(series.level_for(0.0, 600.0, 100), Some(1));
+        assert_eq!(series.level_for(0.0, 600.0, 5), None);
+        let minutes = series.buckets(1, 60.0, 180.0);
+        assert_eq!(minutes.len(), 2);
+        assert_eq!((minutes[0].min, minutes[0].max), (600.0, 1199.0));
+        assert_eq!(minutes[1].count, 600);
+    }
+}
@@ -0,0 +1,461 @@
+// ============================================================================
+// LOGGING - Registo de eventos
+// ============================================================================
+//
+// With the `logging` feature (on by default) the library reports what an
+// embedding application may want to hear of through the `log` facade, so
+// the application's own logger picks it up:
+//
+//     target                  level   event
+//     fuzzy_logic             info    no rule fires, and when rules fire
+//                                     again (FuzzyController::evaluate)
+//     fuzzy_logic::guard      warn    a tuning move refused, or leaving a
+//                                     hole or sets alike (guard.rs)
+//     fuzzy_logic::safemode   info    the config loaded again, leaving safe
+//                                     mode (safemode.rs)
+//     fuzzy_logic::budget     warn    a computation over its latency budget
+//                                     stepping down a rung; info when one
+//                                     climbs back (budget.rs)
+//
+// The tree has no watchdog; the latency budget's step down is its nearest
+// counterpart. Without the feature the events compile to nothing.
+//
+// Some conditions hold computation after computation, no rule firing
+// being one; a `Streak` logs one when it begins and when it ends rather
+// than every time, and its beginnings at most once per INTERVAL, telling
+// how many it skipped.
+//
+// The binary installs a `Logger` (main.rs) filtered by FUZZY_LOGIC_LOG, in
+// the syntax of env_logger: a default level and `target=level` overrides,
+// comma separated, the longest matching target winning:
+//
+//     FUZZY_LOGIC_LOG=info
+//     FUZZY_LOGIC_LOG=warn,fuzzy_logic::budget=info
+//
+// Unset, it is `warn`. The headless modes write each record to stderr as
+// "warning: guard: hole in humidity from 45 to 50", the daemon's own lines
+// going on as before. The TUI's records wait in a bounded queue until the
+// loop posts them to its messages (inbox.rs): nothing reaches stderr while
+// the alternate screen is up, and what is left is written once the
+// terminal is restored (`release`).
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Least time between two logged beginnings of a streak
+pub const INTERVAL: Duration = Duration::from_secs(10);
+
+/// Variable holding the filter of the binary's logger
+pub const VAR: &str = "FUZZY_LOGIC_LOG";
+
+/// Records the TUI's queue holds before dropping the oldest
+pub const QUEUE_LIMIT: usize = 256;
+
+/// `log::log!` at `Level::$level`, or nothing without the `logging`
+/// feature
+#[cfg(feature = "logging")]
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {
+        log::log!(log::Level::$level, $($arg)+)
+    };
+}
+
+#[cfg(not(feature = "logging"))]
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {{
+        let _ = format_args!($($arg)+);
+    }};
+}
+
+pub(crate) use event;
+
+/// What a streak's update calls for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// Log the beginning, noting the ones skipped since the last logged
+    Began {
+        skipped: u64,
+    },
+    /// Log the end of a logged streak, `run` updates long
+    Ended {
+        run: u64,
+    },
+    Quiet,
+}
+
+/// A condition checked computation after computation
+#[derive(Debug, Default)]
+pub struct Streak {
+    state: Mutex<StreakState>,
+}
+
+#[derive(Debug, Default)]
+struct StreakState {
+    /// Updates in a row the condition has held for
+    run: u64,
+    /// This streak's beginning was logged
+    logged: bool,
+    /// When a beginning was last logged
+    last: Option<Instant>,
+    /// Beginnings not logged since
+    skipped: u64,
+}
+
+impl Streak {
+    /// The condition holds at `now`, or not
+    pub fn update(&self, holds: bool, now: Instant) -> Change {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match (holds, state.run) {
+            (true, 0) => {
+                state.run = 1;
+                let due = state
+                    .last
+                    .is_none_or(|last| now.saturating_duration_since(last) >= INTERVAL);
+                state.logged = due;
+                if !due {
+                    state.skipped += 1;
+                    return Change::Quiet;
+                }
+                state.last = Some(now);
+                Change::Began {
+                    skipped: std::mem::take(&mut state.skipped),
+                }
+            }
+            (true, _) => {
+                state.run += 1;
+                Change::Quiet
+            }
+            (false, 0) => Change::Quiet,
+            (false, run) => {
+                state.run = 0;
+                if std::mem::take(&mut state.logged) {
+                    Change::Ended { run }
+                } else {
+                    Change::Quiet
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "logging")]
+pub use logger::*;
+
+#[cfg(feature = "logging")]
+mod logger {
+    use super::{QUEUE_LIMIT, VAR};
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+    use std::collections::VecDeque;
+    use std::sync::{Mutex, OnceLock};
+
+    /// Level of each target, from FUZZY_LOGIC_LOG
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Filter {
+        default: LevelFilter,
+        /// Overrides, longest target first
+        targets: Vec<(String, LevelFilter)>,
+    }
+
+    impl Default for Filter {
+        fn default() -> Self {
+            Filter {
+                default: LevelFilter::Warn,
+                targets: Vec::new(),
+            }
+        }
+    }
+
+    impl Filter {
+        /// `warn,fuzzy_logic::budget=info`, or why not
+        pub fn parse(spec: &str) -> Result<Self, String> {
+            let mut filter = Filter::default();
+            for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                let (target, level) = match part.split_once('=') {
+                    Some((target, level)) => (Some(target.trim()), level.trim()),
+                    None => (None, part),
+                };
+                let level: LevelFilter = level
+                    .parse()
+                    .map_err(|_| format!("{}: unknown level '{}'", VAR, level))?;
+                match target {
+                    Some("") => return Err(format!("{}: empty target in '{}'", VAR, part)),
+                    Some(target) => filter.targets.push((target.to_string(), level)),
+                    None => filter.default = level,
+                }
+            }
+            filter
+                .targets
+                .sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+            Ok(filter)
+        }
+
+        /// The filter FUZZY_LOGIC_LOG sets, `warn` when unset
+        pub fn from_env() -> Result<Self, String> {
+            match std::env::var(VAR) {
+                Ok(spec) => Filter::parse(&spec),
+                Err(_) => Ok(Filter::default()),
+            }
+        }
+
+        /// Most detailed level `target` is logged at: that of the longest
+        /// override naming it or a module inside it
+        pub fn level(&self, target: &str) -> LevelFilter {
+            self.targets
+                .iter()
+                .find(|(t, _)| {
+                    target
+                        .strip_prefix(t.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+                })
+                .map_or(self.default, |(_, level)| *level)
+        }
+
+        /// Most detailed level any target is logged at
+        pub fn max(&self) -> LevelFilter {
+            self.targets
+                .iter()
+                .map(|(_, level)| *level)
+                .fold(self.default, Ord::max)
+        }
+    }
+
+    /// "guard: hole in humidity from 45 to 50", the target shorn of this
+    /// crate's name
+    pub fn line(record: &Record) -> String {
+        let target = record.target();
+        let module = target.strip_prefix("fuzzy_logic::").unwrap_or(target);
+        format!("{}: {}", module, record.args())
+    }
+
+    /// The binary's logger
+    pub struct Logger {
+        filter: Filter,
+        /// Records waiting for the TUI, while it has the terminal
+        queue: Mutex<Option<VecDeque<(Level, String)>>>,
+    }
+
+    impl Log for Logger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= self.filter.level(metadata.target())
+        }
+
+        fn log(&self, record: &Record) {
+            if !self.enabled(record.metadata()) {
+                return;
+            }
+            let line = line(record);
+            let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+            match &mut *queue {
+                Some(queue) => {
+                    if queue.len() == QUEUE_LIMIT {
+                        queue.pop_front();
+                    }
+                    queue.push_back((record.level(), line));
+                }
+                None => eprintln!("{}: {}", prefix(record.level()), line),
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// "warning", "error", "info"..., as the program's own lines start
+    fn prefix(level: Level) -> &'static str {
+        match level {
+            Level::Error => "error",
+            Level::Warn => "warning",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    impl Logger {
+        /// A logger filtered by `filter`, its records queued for the TUI
+        /// when `queued`, or written to stderr
+        pub fn new(filter: Filter, queued: bool) -> Self {
+            Logger {
+                filter,
+                queue: Mutex::new(queued.then(VecDeque::new)),
+            }
+        }
+
+        /// The records queued since the last call, oldest first
+        pub fn drain(&self) -> Vec<(Level, String)> {
+            let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+            queue
+                .as_mut()
+                .map_or_else(Vec::new, |queue| queue.drain(..).collect())
+        }
+
+        /// Stop queueing: what waits, in the lines stderr would have had,
+        /// for the caller to write; later records go straight to stderr
+        pub fn release(&self) -> Vec<String> {
+            let waiting = self.queue.lock().unwrap_or_else(|e| e.into_inner()).take();
+            waiting
+                .into_iter()
+                .flatten()
+                .map(|(level, line)| format!("{}: {}", prefix(level), line))
+                .collect()
+        }
+    }
+
+    static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+    /// Make `logger` the process's
+    pub fn install(logger: Logger) -> Result<(), String> {
+        let max = logger.filter.max();
+        if LOGGER.set(logger).is_err() {
+            return Err("logger already installed".to_string());
+        }
+        let logger = LOGGER.get().expect("just set");
+        log::set_logger(logger).map_err(|e| e.to_string())?;
+        log::set_max_level(max);
+        Ok(())
+    }
+
+    /// The records queued for the TUI since the last call
+    pub fn drain() -> Vec<(Level, String)> {
+        LOGGER.get().map_or_else(Vec::new, Logger::drain)
+    }
+
+    /// Stop queueing, writing what waits to stderr
+    pub fn release() {
+        for line in LOGGER.get().map_or_else(Vec::new, Logger::release) {
+            eprintln!("{}", line);
+        }
+    }
+
+    /// Records logged on this thread while running `f`, for the tests: a
+    /// capturing logger is the process's from the first call on
+    #[cfg(test)]
+    pub(crate) fn capture(f: impl FnOnce()) -> Vec<(Level, String)> {
+        use std::cell::RefCell;
+
+        struct Capture;
+
+        thread_local! {
+            static CAPTURED: RefCell<Option<Vec<(Level, String)>>> = const { RefCell::new(None) };
+        }
+
+        impl Log for Capture {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &Record) {
+                CAPTURED.with(|captured| {
+                    if let Some(captured) = &mut *captured.borrow_mut() {
+                        captured.push((record.level(), line(record)));
+                    }
+                });
+            }
+
+            fn flush(&self) {}
+        }
+
+        static INSTALLED: OnceLock<()> = OnceLock::new();
+        INSTALLED.get_or_init(|| {
+            log::set_logger(&Capture).expect("no other logger in the tests");
+            log::set_max_level(LevelFilter::Trace);
+        });
+        CAPTURED.with(|captured| *captured.borrow_mut() = Some(Vec::new()));
+        f();
+        CAPTURED.with(|captured| captured.borrow_mut().take().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_streak_logs_its_ends_and_at_most_one_beginning_per_interval() {
+        let streak = Streak::default();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        assert_eq!(streak.update(false, at(0)), Change::Quiet);
+        assert_eq!(streak.update(true, at(0)), Change::Began { skipped: 0 });
+        assert_eq!(streak.update(true, at(1)), Change::Quiet);
+        assert_eq!(streak.update(true, at(2)), Change::Quiet);
+        assert_eq!(streak.update(false, at(3)), Change::Ended { run: 3 });
+        assert_eq!(streak.update(false, at(3)), Change::Quiet);
+
+        // Flapping within the interval is counted, not logged
+        for secs in 4..8 {
+            assert_eq!(streak.update(true, at(secs)), Change::Quiet);
+            assert_eq!(streak.update(false, at(secs)), Change::Quiet);
+        }
+        assert_eq!(streak.update(true, at(10)), Change::Began { skipped: 4 });
+        assert_eq!(streak.update(false, at(11)), Change::Ended { run: 1 });
+        assert_eq!(streak.update(true, at(12)), Change::Quiet);
+        assert_eq!(streak.update(false, at(13)), Change::Quiet);
+        assert_eq!(streak.update(true, at(20)), Change::Began { skipped: 1 });
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn filters_pick_the_longest_target_and_refuse_unknown_levels() {
+        use log::LevelFilter;
+
+        let unset = Filter::default();
+        assert_eq!(unset.level("fuzzy_logic::budget"), LevelFilter::Warn);
+        assert_eq!(Filter::parse("").unwrap(), unset);
+
+        let filter =
+            Filter::parse("error, fuzzy_logic=info,fuzzy_logic::budget=trace ,ratatui=off")
+                .unwrap();
+        assert_eq!(filter.level("fuzzy_logic"), LevelFilter::Info);
+        assert_eq!(filter.level("fuzzy_logic::guard"), LevelFilter::Info);
+        assert_eq!(filter.level("fuzzy_logic::budget"), LevelFilter::Trace);
+        assert_eq!(filter.level("fuzzy_logic::budgets"), LevelFilter::Info);
+        assert_eq!(filter.level("fuzzy_logical"), LevelFilter::Error);
+        assert_eq!(filter.level("ratatui::widgets"), LevelFilter::Off);
+        assert_eq!(filter.max(), LevelFilter::Trace);
+        assert_eq!(
+            Filter::parse("warn,fuzzy_logic=loud"),
+            Err("FUZZY_LOGIC_LOG: unknown level 'loud'".to_string())
+        );
+        assert_eq!(
+            Filter::parse("=info"),
+            Err("FUZZY_LOGIC_LOG: empty target in '=info'".to_string())
+        );
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn the_tui_queue_keeps_the_newest_records_until_released() {
+        use log::{Level, Log, Record};
+
+        let logger = Logger::new(Filter::parse("info").unwrap(), true);
+        let log = |level: Level, target: &str, text: &str| {
+            logger.log(
+                &Record::builder()
+                    .level(level)
+                    .target(target)
+                    .args(format_args!("{}", text))
+                    .build(),
+            );
+        };
+        log(Level::Debug, "fuzzy_logic", "too detailed");
+        log(Level::Warn, "fuzzy_logic::guard", "hole in x from 1 to 2");
+        log(Level::Info, "other", "hello");
+        assert_eq!(
+            logger.drain(),
+            [
+                (Level::Warn, "guard: hole in x from 1 to 2".to_string()),
+                (Level::Info, "other: hello".to_string())
+            ]
+        );
+        assert!(logger.drain().is_empty());
+
+        for i in 0..QUEUE_LIMIT + 2 {
+            log(Level::Error, "fuzzy_logic::budget", &i.to_string());
+        }
+        let waiting = logger.release();
+        assert_eq!(waiting.len(), QUEUE_LIMIT);
+        assert_eq!(waiting[0], "error: budget: 2");
+        assert!(logger.drain().is_empty());
+        assert!(logger.release().is_empty());
+    }
+}
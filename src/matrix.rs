@@ -0,0 +1,448 @@
+// ============================================================================
+// RULE MATRIX - Matriz de decisão das regras
+// ============================================================================
+//
+// `rules --matrix` prints the rule base as the classic decision table: the
+// sets of one input down the rows, those of another across the columns and
+// in each cell the first output's set the rules conclude there.
+//
+//     temperature \ humidity  Low     Medium  High
+//     Cold                    Off     Off     Low
+//     Mild                    Low     Medium  Medium
+//     Hot                     Medium  High    -
+//
+// A '-' cell has no rule. Rules that do not fit a cell exactly are still
+// shown, marked:
+//
+//     Low*     the rule leaves the other axis open and fills its whole row
+//              or column
+//     Low+     the rule also depends on an input off the axes
+//     Low/Off  several rules conclude different sets there
+//
+// and rules that constrain neither axis (or one axis twice) are listed
+// under the table. The axes are the first two inputs unless `--rows` and
+// `--columns` name others; `--fix INPUT=SET` keeps only the rules that
+// agree with SET on an input off the axes, instead of marking them all
+// '+'. `--csv` prints the bare table as CSV. The TUI shows the same table
+// with 'm', the cell of the currently dominant sets highlighted.
+
+use crate::config;
+use crate::ties;
+use crate::{FuzzySystem, FuzzyVariable};
+
+const USAGE: &str = "Usage: fuzzy_logic rules [--config FILE] [--matrix [--csv] \
+                     [--rows INPUT] [--columns INPUT] [--fix INPUT=SET]...]";
+
+/// Which inputs span the matrix and which sets the other inputs are held at
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Axes {
+    /// Input down the rows; the first input if `None`
+    pub rows: Option<String>,
+    /// Input across the columns; the second input if `None`
+    pub columns: Option<String>,
+    /// (input, set) pairs for inputs off the axes
+    pub fixed: Vec<(String, String)>,
+}
+
+/// What the rules conclude for one combination of axis sets
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cell {
+    /// Sets of the first output, in rule order without repeats
+    pub consequents: Vec<String>,
+    /// Filled by a rule that leaves the other axis open
+    pub dont_care: bool,
+    /// Filled by a rule that also depends on an input off the axes
+    pub marginal: bool,
+}
+
+impl Cell {
+    /// Text of the cell, empty when no rule covers it
+    pub fn text(&self) -> String {
+        let mut text = self.consequents.join("/");
+        if self.dont_care {
+            text.push('*');
+        }
+        if self.marginal {
+            text.push('+');
+        }
+        text
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    pub row_input: String,
+    pub column_input: String,
+    pub rows: Vec<String>,
+    pub columns: Vec<String>,
+    /// `cells[row][column]`
+    pub cells: Vec<Vec<Cell>>,
+    /// Indices of the rules that fit no cell
+    pub unplaced: Vec<usize>,
+}
+
+/// The input named `name`, or the one at `default` in declaration order
+fn axis<'a>(
+    system: &'a FuzzySystem,
+    name: Option<&str>,
+    default: usize,
+) -> Result<&'a FuzzyVariable, String> {
+    match name {
+        Some(name) => system
+            .input(name)
+            .ok_or_else(|| format!("unknown input '{}'", name)),
+        None => system
+            .inputs
+            .get(default)
+            .ok_or_else(|| "the matrix needs at least two inputs".to_string()),
+    }
+}
+
+/// The single set `rule` requires of `input`: `Ok(None)` if it leaves the
+/// input open, `Err` if it names it more than once
+fn condition_on<'a>(
+    conditions: &'a [(String, String)],
+    input: &str,
+) -> Result<Option<&'a str>, ()> {
+    let mut sets = conditions
+        .iter()
+        .filter(|(var, _)| var == input)
+        .map(|(_, set)| set.as_str());
+    match (sets.next(), sets.next()) {
+        (Some(_), Some(_)) => Err(()),
+        (set, _) => Ok(set),
+    }
+}
+
+/// Lay out the rules concluding the first output of `system` along `axes`
+pub fn build(system: &FuzzySystem, axes: &Axes) -> Result<Matrix, String> {
+    let row_var = axis(system, axes.rows.as_deref(), 0)?;
+    let column_var = axis(system, axes.columns.as_deref(), 1)?;
+    if row_var.name == column_var.name {
+        return Err(format!("'{}' cannot be both axes", row_var.name));
+    }
+    for (input, set) in &axes.fixed {
+        let var = system
+            .input(input)
+            .ok_or_else(|| format!("unknown input '{}'", input))?;
+        if *input == row_var.name || *input == column_var.name {
+            return Err(format!("'{}' is an axis and cannot be fixed", input));
+        }
+        if var.set(set).is_none() {
+            return Err(format!("'{}' has no set '{}'", input, set));
+        }
+    }
+
+    let names = |var: &FuzzyVariable| var.sets.iter().map(|s| s.name.clone()).collect();
+    let mut matrix = Matrix {
+        row_input: row_var.name.clone(),
+        column_input: column_var.name.clone(),
+        rows: names(row_var),
+        columns: names(column_var),
+        cells: vec![vec![Cell::default(); column_var.sets.len()]; row_var.sets.len()],
+        unplaced: Vec::new(),
+    };
+
+    let output = &system.output().name;
+    for (index, rule) in system.rules.iter().enumerate() {
+        let Some(consequent) = rule.consequent(output) else {
+            continue;
+        };
+        let mut marginal = false;
+        let mut applies = true;
+        for (var, set) in &rule.conditions {
+            if *var == row_var.name || *var == column_var.name {
+                continue;
+            }
+            match axes.fixed.iter().find(|(input, _)| input == var) {
+                Some((_, fixed)) => applies &= fixed == set,
+                None => marginal = true,
+            }
+        }
+        if !applies {
+            continue;
+        }
+
+        let (Ok(row), Ok(column)) = (
+            condition_on(&rule.conditions, &row_var.name),
+            condition_on(&rule.conditions, &column_var.name),
+        ) else {
+            matrix.unplaced.push(index);
+            continue;
+        };
+        if row.is_none() && column.is_none() {
+            matrix.unplaced.push(index);
+            continue;
+        }
+        let span = |set: Option<&str>, sets: &[String]| -> Vec<usize> {
+            match set {
+                Some(set) => sets.iter().position(|s| s == set).into_iter().collect(),
+                None => (0..sets.len()).collect(),
+            }
+        };
+        for r in span(row, &matrix.rows) {
+            for c in span(column, &matrix.columns) {
+                let cell = &mut matrix.cells[r][c];
+                if !cell.consequents.iter().any(|s| s == consequent) {
+                    cell.consequents.push(consequent.to_string());
+                }
+                cell.dont_care |= row.is_none() || column.is_none();
+                cell.marginal |= marginal;
+            }
+        }
+    }
+    Ok(matrix)
+}
+
+impl Matrix {
+    /// Header and rows as text, an uncovered cell shown as `empty`
+    pub fn table(&self, empty: &str) -> Vec<Vec<String>> {
+        let mut header = vec![format!("{} \\ {}", self.row_input, self.column_input)];
+        header.extend(self.columns.iter().cloned());
+        let mut table = vec![header];
+        for (name, cells) in self.rows.iter().zip(&self.cells) {
+            let mut row = vec![name.clone()];
+            row.extend(cells.iter().map(|cell| {
+                let text = cell.text();
+                if text.is_empty() {
+                    empty.to_string()
+                } else {
+                    text
+                }
+            }));
+            table.push(row);
+        }
+        table
+    }
+
+    /// Width of every column of `table`
+    pub fn widths(table: &[Vec<String>]) -> Vec<usize> {
+        let columns = table.first().map_or(0, Vec::len);
+        (0..columns)
+            .map(|c| {
+                table
+                    .iter()
+                    .map(|row| row[c].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    /// Aligned text, followed by the rules that fit no cell
+    pub fn to_text(&self, system: &FuzzySystem) -> Vec<String> {
+        let table = self.table("-");
+        let widths = Matrix::widths(&table);
+        let mut lines: Vec<String> = table
+            .iter()
+            .map(|row| {
+                let cells: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(text, width)| format!("{:<width$}", text, width = width))
+                    .collect();
+                cells.join("  ").trim_end().to_string()
+            })
+            .collect();
+        if !self.unplaced.is_empty() {
+            lines.push(String::new());
+            lines.push("Not in the matrix:".to_string());
+            for index in &self.unplaced {
+                lines.push(format!(
+                    "  rule {}: {}",
+                    index + 1,
+                    system.rules[*index].to_text()
+                ));
+            }
+        }
+        lines
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = String::new();
+        for row in self.table("") {
+            out.push_str(&row.join(","));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Cell of the sets with the highest membership on both axes, given
+    /// the inputs in declaration order, the first set of an axis on a tie
+    /// (ties.rs); none while either axis is silent
+    pub fn dominant(&self, system: &FuzzySystem, values: &[f64]) -> Option<(usize, usize)> {
+        let strongest = |name: &str| {
+            let index = system.inputs.iter().position(|v| v.name == name)?;
+            let sets = system.inputs[index].fuzzify(*values.get(index)?);
+            let tolerance = system.defuzzification.tie_tolerance;
+            ties::strongest(sets.iter().map(|set| set.membership), tolerance)
+        };
+        Some((strongest(&self.row_input)?, strongest(&self.column_input)?))
+    }
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let mut system = FuzzySystem::demo();
+    let mut matrix = false;
+    let mut csv = false;
+    let mut axes = Axes::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--matrix" => {
+                matrix = true;
+                continue;
+            }
+            "--csv" => {
+                csv = true;
+                continue;
+            }
+            _ => {}
+        }
+        let value = iter.next();
+        let result = match (arg.as_str(), value) {
+            ("--config", Some(path)) => config::load_system(path).map(|s| system = s),
+            ("--rows", Some(input)) => {
+                axes.rows = Some(input.clone());
+                Ok(())
+            }
+            ("--columns", Some(input)) => {
+                axes.columns = Some(input.clone());
+                Ok(())
+            }
+            ("--fix", Some(pair)) => match pair.split_once('=') {
+                Some((input, set)) => {
+                    axes.fixed.push((input.to_string(), set.to_string()));
+                    Ok(())
+                }
+                None => Err("--fix expects INPUT=SET".to_string()),
+            },
+            _ => Err(USAGE.to_string()),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            return 2;
+        }
+    }
+
+    if !matrix {
+        for (i, rule) in system.rules.iter().enumerate() {
+            println!("{:>3}  {}", i + 1, rule.to_text());
+        }
+        return 0;
+    }
+    match build(&system, &axes) {
+        Ok(matrix) if csv => {
+            print!("{}", matrix.to_csv());
+            0
+        }
+        Ok(matrix) => {
+            for line in matrix.to_text(&system) {
+                println!("{}", line);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn the_demo_rules_fill_the_matrix() {
+        let system = fixtures::fan();
+        let matrix = build(&system, &Axes::default()).unwrap();
+        assert_eq!(
+            matrix.to_text(&system),
+            [
+                "temperature \\ humidity  Low     Medium  High",
+                "Cold                    Off     Off     Low",
+                "Mild                    Low     Medium  Medium",
+                "Hot                     Medium  High    High",
+            ]
+        );
+        assert_eq!(matrix.to_csv().lines().nth(1), Some("Cold,Off,Off,Low"));
+
+        // Swapped axes transpose it
+        let axes = Axes {
+            rows: Some("humidity".to_string()),
+            columns: Some("temperature".to_string()),
+            fixed: Vec::new(),
+        };
+        let swapped = build(&system, &axes).unwrap();
+        assert_eq!(swapped.cells[2][0].text(), "Low");
+        assert_eq!(matrix.dominant(&system, &[40.0, 90.0]), Some((2, 2)));
+    }
+
+    #[test]
+    fn uncovered_and_loose_rules_are_marked() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let rules = "rules = [\n\
+             \"IF temperature IS Cold AND humidity IS Low THEN fan_speed IS Off\",\n\
+             \"IF temperature IS Cold AND humidity IS Low THEN fan_speed IS Low\",\n\
+             \"IF temperature IS Hot THEN fan_speed IS High\",\n\
+             \"IF humidity IS High AND temperature IS Mild AND temperature IS Hot THEN fan_speed IS Medium\",\n\
+             ]\n";
+        let body = &fan[fan.find("\n[metadata]").unwrap()..];
+        let system = config::parse_system(&format!("{}{}", rules, body)).unwrap();
+        let matrix = build(&system, &Axes::default()).unwrap();
+        assert_eq!(
+            matrix.to_text(&system),
+            [
+                "temperature \\ humidity  Low      Medium  High",
+                "Cold                    Off/Low  -       -",
+                "Mild                    -        -       -",
+                "Hot                     High*    High*   High*",
+                "",
+                "Not in the matrix:",
+                "  rule 4: IF humidity IS High AND temperature IS Mild AND temperature IS Hot THEN fan_speed IS Medium",
+            ]
+        );
+        assert_eq!(matrix.to_csv().lines().nth(2), Some("Mild,,,"));
+    }
+
+    #[test]
+    fn a_third_input_is_marginalized_or_fixed() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let rules = "rules = [\n\
+             \"IF temperature IS Hot AND humidity IS High AND occupancy IS Busy THEN fan_speed IS High\",\n\
+             \"IF temperature IS Hot AND humidity IS High AND occupancy IS Empty THEN fan_speed IS Low\",\n\
+             ]\n";
+        let body = &fan[fan.find("\n[metadata]").unwrap()..];
+        let occupancy = "\n[[input]]\nname = \"occupancy\"\nmin = 0\nmax = 1\n\
+             [[input.set]]\nname = \"Empty\"\nshape = \"triangular\"\nparams = [0, 0, 1]\n\
+             [[input.set]]\nname = \"Busy\"\nshape = \"triangular\"\nparams = [0, 1, 1]\n";
+        let system = config::parse_system(&format!("{}{}{}", rules, body, occupancy)).unwrap();
+
+        let marginal = build(&system, &Axes::default()).unwrap();
+        assert_eq!(marginal.cells[2][2].text(), "High/Low+");
+        let fixed = Axes {
+            fixed: vec![("occupancy".to_string(), "Empty".to_string())],
+            ..Axes::default()
+        };
+        assert_eq!(build(&system, &fixed).unwrap().cells[2][2].text(), "Low");
+
+        let axes = Axes {
+            rows: Some("occupancy".to_string()),
+            fixed: vec![("temperature".to_string(), "Hot".to_string())],
+            ..Axes::default()
+        };
+        let matrix = build(&system, &axes).unwrap();
+        assert_eq!(matrix.to_csv().lines().nth(1), Some("Empty,,,Low"));
+        let bad = Axes {
+            fixed: vec![("occupancy".to_string(), "Full".to_string())],
+            ..Axes::default()
+        };
+        assert_eq!(
+            build(&system, &bad).unwrap_err(),
+            "'occupancy' has no set 'Full'"
+        );
+    }
+}
@@ -0,0 +1,629 @@
+// ============================================================================
+// MONOTONICITY - Monotonia estrutural da saída
+// ============================================================================
+//
+// `monotone [--config FILE] [--input NAME] [--output NAME]
+// [--fix-suggestions]` checks an output nondecreasing in an input from the
+// shape of the rule base rather than by trying points: the first input
+// and output unless named. The output is structurally monotone when all
+// of these hold:
+//
+//     ordered      the input's sets, taken by their nominal centroids, have
+//                  the starts and ends of their supports in that order too
+//     partition    each set overlaps the next, no set reaches the one
+//                  after the next, and the first and last reach the ends
+//                  of the universe, so at most two neighbouring sets are
+//                  ever above zero
+//     grid         every rule concluding the output conditions on the
+//                  input and on the same other inputs, and has the same
+//                  weight once its group's is applied (disabled groups'
+//                  rules are left out)
+//     complete     every context, a combination of sets of the other
+//                  inputs, has a rule for each of the input's sets
+//     consequents  within a context, the sets concluded for increasing
+//                  input sets have nondecreasing nominal centroids
+//
+// With two neighbouring sets at most, raising the reading moves firing
+// strength from each context's rule to the next one up, whose consequent
+// is no lower. That is what the conditions are sufficient for: the rule
+// base is monotone, and so is any average of the consequents' centroids
+// weighted by the strengths. The engine's output is the centroid of the
+// clipped consequents under max aggregation, before the output pipeline
+// and the envelopes, and that only follows the average closely: where two
+// clipped consequents overlap, the crisp output can dip a little as the
+// strengths trade places. The demo drops by 0.008 on its 0 to 100 range
+// near 20 °C. Structure alone therefore does not prove the crisp output
+// monotone, and a system can be monotone without the structure.
+//
+// So the command also samples the system: SAMPLED_LINES lines along the
+// input through random points of the other inputs (fixed seed),
+// LINE_POINTS each, and reports the first decrease found, with its size.
+// A violation names what breaks the argument, and for consequents the pair
+// of rules. `--fix-suggestions` proposes the fewest consequent changes
+// that make every context nondecreasing, the changed sets as near their
+// old ones as the order allows; the other conditions have no consequent
+// fix. The verdict takes both: monotone as far as sampled when the
+// structure holds and no sampled decrease exceeds DECREASE_TOLERANCE, not
+// monotone otherwise. Exit code 0 for the first, 1 for the second, 2 on
+// errors; the demo, with its dip, gets 1.
+
+use crate::activation::Session;
+use crate::{FuzzySystem, FuzzyVariable, config};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeMap;
+
+const USAGE: &str = "Usage: fuzzy_logic monotone [--config FILE] [--input NAME] \
+                     [--output NAME] [--fix-suggestions]";
+
+const SAMPLED_LINES: usize = 100;
+const LINE_POINTS: usize = 201;
+const SEED: u64 = 480;
+/// Drop in the output, as a fraction of its range, that counts as a
+/// decrease rather than rounding
+const DECREASE_TOLERANCE: f64 = 1e-9;
+
+/// Indices of `var`'s sets by increasing nominal centroid
+pub fn order(var: &FuzzyVariable) -> Vec<usize> {
+    let centroids: Vec<f64> = (0..var.sets.len()).map(|i| var.set_centroid(i)).collect();
+    let mut order: Vec<usize> = (0..var.sets.len()).collect();
+    order.sort_by(|&a, &b| centroids[a].total_cmp(&centroids[b]));
+    order
+}
+
+/// What breaks the ordered and partition conditions of `var`'s sets
+fn set_violations(var: &FuzzyVariable) -> Vec<String> {
+    let order = order(var);
+    let supports: Vec<(f64, f64)> = (order.iter())
+        .map(|&i| var.sets[i].function.support())
+        .collect();
+    let name = |k: usize| &var.sets[order[k]].name;
+    let mut violations = Vec::new();
+    for k in 1..order.len() {
+        let (before, after) = (supports[k - 1], supports[k]);
+        if after.0 < before.0 || after.1 < before.1 {
+            violations.push(format!(
+                "{}: {} and {} are out of order, their supports cross",
+                var.name,
+                name(k - 1),
+                name(k)
+            ));
+        } else if after.0 >= before.1 {
+            violations.push(format!(
+                "{}: {} and {} do not overlap ({} to {} is left to neither)",
+                var.name,
+                name(k - 1),
+                name(k),
+                before.1,
+                after.0
+            ));
+        }
+        if k >= 2 && supports[k].0 < supports[k - 2].1 {
+            violations.push(format!(
+                "{}: {} reaches {}, three sets overlap",
+                var.name,
+                name(k - 2),
+                name(k)
+            ));
+        }
+    }
+    if let (Some(first), Some(last)) = (supports.first(), supports.last()) {
+        if first.0 > var.min {
+            violations.push(format!(
+                "{}: nothing covers {} to {}",
+                var.name, var.min, first.0
+            ));
+        }
+        if last.1 < var.max {
+            violations.push(format!(
+                "{}: nothing covers {} to {}",
+                var.name, last.1, var.max
+            ));
+        }
+    }
+    violations
+}
+
+/// A consequent change that helps restore monotonicity
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    /// Index of the rule
+    pub rule: usize,
+    pub from: String,
+    pub to: String,
+}
+
+/// Rules of one combination of the other inputs' sets: by the position of
+/// the input's set, the rule and the rank of the output set it concludes
+type Context = BTreeMap<usize, (usize, usize)>;
+
+/// Structural analysis of one output against one input
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub input: String,
+    pub output: String,
+    /// The input's sets by nominal centroid, with it
+    pub order: Vec<(String, f64)>,
+    /// Why the system is not structurally monotone; none when it is
+    pub violations: Vec<String>,
+    /// Fewest consequent changes making every context nondecreasing
+    pub fixes: Vec<Fix>,
+}
+
+impl Analysis {
+    pub fn is_monotone(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Verdict line and exit code, from whether the structure holds and
+/// whether sampling found a decrease
+fn verdict(structural: bool, decrease: bool) -> (&'static str, i32) {
+    match (structural, decrease) {
+        (true, false) => ("monotone as far as sampled", 0),
+        (_, true) => ("not monotone: the sampled output falls", 1),
+        (false, false) => (
+            "not monotone by structure, though no decrease was sampled",
+            1,
+        ),
+    }
+}
+
+/// Values of `original` made nondecreasing with the fewest changes, then
+/// the least total distance moved; values range over `0..levels`
+fn nondecreasing(original: &[usize], levels: usize) -> Vec<usize> {
+    // cost[i][v]: best (changes, distance) of the first i + 1 values with
+    // the last one at v; from[i][v] the value before it. Ties keep the
+    // lower value.
+    let mut cost = vec![vec![(0, 0); levels]; original.len()];
+    let mut from = vec![vec![0; levels]; original.len()];
+    for (i, &was) in original.iter().enumerate() {
+        // Best value before, at most v
+        let mut best: Option<((usize, usize), usize)> = None;
+        for v in 0..levels {
+            if i > 0 && best.is_none_or(|(c, _)| cost[i - 1][v] < c) {
+                best = Some((cost[i - 1][v], v));
+            }
+            let ((changes, distance), previous) = best.unwrap_or(((0, 0), 0));
+            cost[i][v] = (changes + usize::from(v != was), distance + v.abs_diff(was));
+            from[i][v] = previous;
+        }
+    }
+    let Some(last) = cost.last() else {
+        return Vec::new();
+    };
+    let mut v = (0..levels).min_by_key(|&v| last[v]).unwrap_or(0);
+    let mut values = vec![0; original.len()];
+    for i in (0..original.len()).rev() {
+        values[i] = v;
+        v = from[i][v];
+    }
+    values
+}
+
+/// Whether `output` of `system` is structurally nondecreasing in `input`
+pub fn analyze(system: &FuzzySystem, input: &str, output: &str) -> Result<Analysis, String> {
+    let x = (system.inputs.iter())
+        .position(|var| var.name == input)
+        .ok_or_else(|| format!("unknown input '{}'", input))?;
+    let y = system
+        .output_var(output)
+        .ok_or_else(|| format!("unknown output '{}'", output))?;
+    let x_var = &system.inputs[x];
+    let x_order = order(x_var);
+    let y_order = order(y);
+    // Rank of each set of the output and position of each set of the input
+    let mut y_rank = vec![0; y.sets.len()];
+    for (rank, &set) in y_order.iter().enumerate() {
+        y_rank[set] = rank;
+    }
+    let mut x_position = vec![0; x_var.sets.len()];
+    for (position, &set) in x_order.iter().enumerate() {
+        x_position[set] = position;
+    }
+
+    let mut violations = set_violations(x_var);
+    let mut contexts: BTreeMap<Vec<(String, String)>, Context> = BTreeMap::new();
+    let mut shape: Option<(usize, Vec<String>, f64)> = None;
+    for (i, rule) in system.rules.iter().enumerate() {
+        let Some(concluded) = rule.consequent(output) else {
+            continue;
+        };
+        let group_weight = match &rule.group {
+            Some(name) => match system.group(name) {
+                Some(group) if group.enabled => group.weight,
+                _ => continue,
+            },
+            None => 1.0,
+        };
+        let weight = rule.weight * group_weight;
+        let Some((_, set)) = rule.conditions.iter().find(|(var, _)| var == input) else {
+            violations.push(format!("rule {} does not condition on {}", i + 1, input));
+            continue;
+        };
+        let mut context: Vec<(String, String)> = (rule.conditions.iter())
+            .filter(|(var, _)| var != input)
+            .cloned()
+            .collect();
+        context.sort();
+        let variables: Vec<String> = context.iter().map(|(var, _)| var.clone()).collect();
+        match &shape {
+            None => shape = Some((i, variables, weight)),
+            Some((first, first_variables, first_weight)) => {
+                if variables != *first_variables {
+                    violations.push(format!(
+                        "rule {} conditions on other inputs than rule {}",
+                        i + 1,
+                        first + 1
+                    ));
+                    continue;
+                }
+                if weight != *first_weight {
+                    violations.push(format!(
+                        "rule {} weighs {}, rule {} weighs {}",
+                        i + 1,
+                        weight,
+                        first + 1,
+                        first_weight
+                    ));
+                }
+            }
+        }
+        let (Some(x_set), Some(y_set)) = (
+            x_var.sets.iter().position(|s| s.name == *set),
+            y.sets.iter().position(|s| s.name == concluded),
+        ) else {
+            continue;
+        };
+        let rules = contexts.entry(context).or_default();
+        match rules.get(&x_position[x_set]) {
+            Some(&(other, rank)) if rank != y_rank[y_set] => violations.push(format!(
+                "rules {} and {} conclude different sets for the same conditions",
+                other + 1,
+                i + 1
+            )),
+            Some(_) => {}
+            None => {
+                rules.insert(x_position[x_set], (i, y_rank[y_set]));
+            }
+        }
+    }
+
+    let describe = |context: &[(String, String)]| {
+        if context.is_empty() {
+            String::new()
+        } else {
+            let clauses: Vec<String> = (context.iter())
+                .map(|(var, set)| format!("{} IS {}", var, set))
+                .collect();
+            format!(" with {}", clauses.join(" AND "))
+        }
+    };
+    let mut fixes = Vec::new();
+    for (context, rules) in &contexts {
+        for (position, &set) in x_order.iter().enumerate() {
+            if !rules.contains_key(&position) {
+                violations.push(format!(
+                    "no rule for {} IS {}{}",
+                    input,
+                    x_var.sets[set].name,
+                    describe(context)
+                ));
+            }
+        }
+        let sequence: Vec<(usize, usize)> = rules.values().copied().collect();
+        for pair in sequence.windows(2) {
+            let ((a, a_rank), (b, b_rank)) = (pair[0], pair[1]);
+            if b_rank < a_rank {
+                let (a_set, b_set) = (y_order[a_rank], y_order[b_rank]);
+                violations.push(format!(
+                    "rules {} and {}{}: {} rises but {} falls from {} ({:.1}) to {} ({:.1})",
+                    a + 1,
+                    b + 1,
+                    describe(context),
+                    input,
+                    output,
+                    y.sets[a_set].name,
+                    y.set_centroid(a_set),
+                    y.sets[b_set].name,
+                    y.set_centroid(b_set)
+                ));
+            }
+        }
+        let ranks: Vec<usize> = sequence.iter().map(|(_, rank)| *rank).collect();
+        let fixed = nondecreasing(&ranks, y.sets.len());
+        for ((rule, was), now) in sequence.iter().zip(fixed) {
+            if now != *was {
+                fixes.push(Fix {
+                    rule: *rule,
+                    from: y.sets[y_order[*was]].name.clone(),
+                    to: y.sets[y_order[now]].name.clone(),
+                });
+            }
+        }
+    }
+    fixes.sort_by_key(|fix| fix.rule);
+
+    Ok(Analysis {
+        input: input.to_string(),
+        output: output.to_string(),
+        order: (x_order.iter())
+            .map(|&i| (x_var.sets[i].name.clone(), x_var.set_centroid(i)))
+            .collect(),
+        violations,
+        fixes,
+    })
+}
+
+/// First decrease of `output` along `input` found by sampling: the point
+/// before it, and the output there and one step on
+pub fn sampled(system: &FuzzySystem, input: usize, output: usize) -> Option<(Vec<f64>, f64, f64)> {
+    let mut session = Session::new(system);
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let x_var = &system.inputs[input];
+    let y_var = &system.outputs[output];
+    let tolerance = DECREASE_TOLERANCE * (y_var.max - y_var.min);
+    let step = (x_var.max - x_var.min) / (LINE_POINTS - 1) as f64;
+    let mut point: Vec<f64> = vec![0.0; system.inputs.len()];
+    for _ in 0..SAMPLED_LINES {
+        for (x, var) in point.iter_mut().zip(&system.inputs) {
+            *x = rng.gen_range(var.min..=var.max);
+        }
+        let mut previous: Option<(Vec<f64>, f64)> = None;
+        for i in 0..LINE_POINTS {
+            point[input] = x_var.min + i as f64 * step;
+            session.infer(&point);
+            let value = session.outputs()[output];
+            if let Some((at, before)) = previous
+                && value < before - tolerance
+            {
+                return Some((at, before, value));
+            }
+            previous = Some((point.clone(), value));
+        }
+    }
+    None
+}
+
+/// `monotone` subcommand
+pub fn run(args: &[String]) -> i32 {
+    let mut system = FuzzySystem::demo();
+    let (mut input, mut output, mut fix) = (None, None, false);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let result = match arg.as_str() {
+            "--fix-suggestions" => {
+                fix = true;
+                Ok(())
+            }
+            "--config" => match iter.next() {
+                Some(path) => config::load_system(path).map(|s| system = s),
+                None => Err(USAGE.to_string()),
+            },
+            "--input" | "--output" => match iter.next() {
+                Some(name) if arg == "--input" => {
+                    input = Some(name.clone());
+                    Ok(())
+                }
+                Some(name) => {
+                    output = Some(name.clone());
+                    Ok(())
+                }
+                None => Err(USAGE.to_string()),
+            },
+            _ => Err(USAGE.to_string()),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            return 2;
+        }
+    }
+    let input = input.unwrap_or_else(|| system.inputs[0].name.clone());
+    let output = output.unwrap_or_else(|| system.outputs[0].name.clone());
+    let analysis = match analyze(&system, &input, &output) {
+        Ok(analysis) => analysis,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 2;
+        }
+    };
+
+    println!("{} -> {}", analysis.input, analysis.output);
+    let sets: Vec<String> = (analysis.order.iter())
+        .map(|(name, centroid)| format!("{} ({:.1})", name, centroid))
+        .collect();
+    println!("  {} sets by centroid: {}", input, sets.join(" < "));
+    if analysis.is_monotone() {
+        println!("  structure: every condition holds");
+    } else {
+        println!("  structure: conditions broken:");
+        for violation in &analysis.violations {
+            println!("    {}", violation);
+        }
+    }
+    let x = system.inputs.iter().position(|v| v.name == input);
+    let y = system.outputs.iter().position(|v| v.name == output);
+    let mut decrease = false;
+    if let (Some(x), Some(y)) = (x, y) {
+        let found = sampled(&system, x, y);
+        decrease = found.is_some();
+        match found {
+            None => println!(
+                "  sampled: nondecreasing along {} lines of {} points",
+                SAMPLED_LINES, LINE_POINTS
+            ),
+            Some((point, before, after)) => {
+                let at: Vec<String> = (system.inputs.iter())
+                    .zip(&point)
+                    .map(|(var, value)| format!("{}={:.2}", var.name, value))
+                    .collect();
+                println!(
+                    "  sampled: {} falls from {:.3} to {:.3} just after {}",
+                    output,
+                    before,
+                    after,
+                    at.join(" ")
+                );
+            }
+        }
+    }
+    if fix {
+        if analysis.fixes.is_empty() {
+            println!("no consequent changes to suggest");
+        } else {
+            println!("suggested consequent changes ({}):", analysis.fixes.len());
+            for change in &analysis.fixes {
+                println!(
+                    "    rule {}: {} IS {} -> {}",
+                    change.rule + 1,
+                    output,
+                    change.from,
+                    change.to
+                );
+            }
+        }
+    }
+    let (line, code) = verdict(analysis.is_monotone(), decrease);
+    println!("  verdict: {}", line);
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FuzzyRule;
+    use crate::fixtures;
+
+    fn demo_with(rule: usize, text: &str) -> FuzzySystem {
+        let mut system = fixtures::fan();
+        system.rules[rule] = FuzzyRule::parse(text).unwrap();
+        system
+    }
+
+    #[test]
+    fn the_demo_is_structurally_monotone_in_temperature() {
+        let system = fixtures::fan();
+        let analysis = analyze(&system, "temperature", "fan_speed").unwrap();
+        assert!(analysis.is_monotone(), "{:#?}", analysis.violations);
+        let names: Vec<&str> = analysis.order.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["Cold", "Mild", "Hot"]);
+        // A triangle's centroid is the mean of its corners
+        assert!((analysis.order[1].1 - 22.5).abs() < 0.01);
+        assert!(analysis.fixes.is_empty());
+        // The clipped consequents dip a little where Cold ends
+        let (point, before, after) = sampled(&system, 0, 0).unwrap();
+        assert!(before - after < 0.01, "{} -> {}", before, after);
+        assert!((15.0..20.0).contains(&point[0]), "{:?}", point);
+        // ...and the dip, not the structure, decides
+        assert_eq!(verdict(true, true).1, 1);
+        assert_eq!(verdict(true, false).1, 0);
+        assert_eq!(verdict(false, false).1, 1);
+    }
+
+    #[test]
+    fn a_falling_consequent_names_the_rules_and_gets_a_fix() {
+        let system = demo_with(
+            7,
+            "IF temperature IS Hot AND humidity IS Medium THEN fan_speed IS Off",
+        );
+        let analysis = analyze(&system, "temperature", "fan_speed").unwrap();
+        assert_eq!(analysis.violations.len(), 1, "{:#?}", analysis.violations);
+        assert!(
+            analysis.violations[0]
+                .starts_with("rules 5 and 8 with humidity IS Medium: temperature rises"),
+            "{}",
+            analysis.violations[0]
+        );
+        assert!(sampled(&system, 0, 0).is_some());
+
+        // One change, and the system it leaves is monotone again
+        assert_eq!(analysis.fixes.len(), 1);
+        let mut fixed = system.clone();
+        let change = &analysis.fixes[0];
+        fixed.rules[change.rule].consequents[0].1 = change.to.clone();
+        assert!(
+            analyze(&fixed, "temperature", "fan_speed")
+                .unwrap()
+                .is_monotone()
+        );
+
+        // Sets that leave a gap, or that three overlap, break the partition
+        let violations = |set: usize, function| {
+            let mut system = fixtures::fan();
+            system.inputs[0].sets[set].function = function;
+            analyze(&system, "temperature", "fan_speed")
+                .unwrap()
+                .violations
+        };
+        assert_eq!(
+            violations(
+                1,
+                crate::MembershipFunction::Triangular {
+                    a: 10.0,
+                    b: 22.5,
+                    c: 24.0
+                }
+            ),
+            ["temperature: Mild and Hot do not overlap (24 to 25 is left to neither)"]
+        );
+        assert_eq!(
+            violations(
+                2,
+                crate::MembershipFunction::Trapezoidal {
+                    a: 18.0,
+                    b: 30.0,
+                    c: 50.0,
+                    d: 50.0
+                }
+            ),
+            ["temperature: Cold reaches Hot, three sets overlap"]
+        );
+    }
+
+    #[test]
+    fn monotone_behaviour_without_the_structure_is_told_apart() {
+        // Two consequents far apart: the crisp output follows the
+        // strengths, and a lighter rule breaks the argument, not that
+        let system = |weight: &str| {
+            config::parse_system(&format!(
+                "rules = [\"IF x IS Low THEN y IS Small\", \"IF x IS High THEN y IS Big{}\"]\n\
+                 [[input]]\nname = \"x\"\nmin = 0.0\nmax = 100.0\n\
+                 [[input.set]]\nname = \"Low\"\nshape = \"trapezoidal\"\n\
+                 params = [0.0, 0.0, 30.0, 70.0]\n\
+                 [[input.set]]\nname = \"High\"\nshape = \"trapezoidal\"\n\
+                 params = [30.0, 70.0, 100.0, 100.0]\n\
+                 [[output]]\nname = \"y\"\nmin = 0.0\nmax = 100.0\n\
+                 [[output.set]]\nname = \"Small\"\nshape = \"triangular\"\n\
+                 params = [0.0, 10.0, 20.0]\n\
+                 [[output.set]]\nname = \"Big\"\nshape = \"triangular\"\n\
+                 params = [80.0, 90.0, 100.0]\n",
+                weight
+            ))
+            .unwrap()
+        };
+        let even = system("");
+        assert!(analyze(&even, "x", "y").unwrap().is_monotone());
+        assert_eq!(sampled(&even, 0, 0), None);
+        let lighter = system(" WITH 0.8");
+        let analysis = analyze(&lighter, "x", "y").unwrap();
+        assert_eq!(analysis.violations, ["rule 2 weighs 0.8, rule 1 weighs 1"]);
+        assert!(analysis.fixes.is_empty());
+        assert_eq!(sampled(&lighter, 0, 0), None);
+
+        // Humidity is no grid axis of its own: still one rule per context
+        let analysis = analyze(&fixtures::fan(), "humidity", "fan_speed").unwrap();
+        assert!(analysis.is_monotone(), "{:#?}", analysis.violations);
+        assert!(matches!(
+            analyze(&even, "pressure", "fan_speed"),
+            Err(e) if e == "unknown input 'pressure'"
+        ));
+    }
+
+    #[test]
+    fn the_fewest_changes_are_chosen_nearest_the_old_values() {
+        assert_eq!(nondecreasing(&[0, 3, 1, 2], 4), [0, 1, 1, 2]);
+        assert_eq!(nondecreasing(&[3, 0, 1, 2], 4), [0, 0, 1, 2]);
+        assert_eq!(nondecreasing(&[1, 1, 0], 3), [1, 1, 1]);
+        assert_eq!(nondecreasing(&[], 3), Vec::<usize>::new());
+    }
+}
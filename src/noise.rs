@@ -0,0 +1,332 @@
+// ============================================================================
+// SENSOR NOISE - Ruído de sensor simulado
+// ============================================================================
+//
+// A simulated sensor reports the true value of an input plus a random walk
+// error, so the TUI can show how much of the output's movement is the
+// controller reacting to noise:
+//
+//     [[noise]]              # optional, one per input
+//     input = "temperature"
+//     sigma = 0.5            # standard deviation of the error
+//     correlation = 0.9      # of the error one second apart, 0 to below 1
+//     drift = 0.01           # bias added per second, optional
+//     seed = 7               # optional, 0 by default
+//
+// The error is an AR(1) process: over `dt` seconds it decays by
+// `correlation^dt` and gains a Gaussian innovation that keeps its standard
+// deviation at `sigma`, so its statistics do not depend on how often it is
+// sampled. The drift adds a bias that grows without bound, like a sensor
+// losing its calibration. Every input has its own generator, seeded from
+// `seed` and the input's position, so a run with the same inputs at the
+// same times reports the same values.
+//
+// The noise sits between the input adapters and the failure injection
+// (inject.rs). While it is on, the TUI samples the sensors every
+// SAMPLE_INTERVAL even with the true values unchanged, keeps both values in
+// the history and draws the true trajectory as a line with the sensed one
+// as points. 'N' changes the parameters of the running session:
+//
+//     <input> <sigma> <correlation> [<drift>]
+//     <input> off
+
+use crate::numbers::{self, Locale};
+use crate::{FuzzySystem, FuzzyVariable};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Seconds between sensor samples in the TUI while noise is on
+pub const SAMPLE_INTERVAL: f64 = 1.0;
+
+/// One `[[noise]]` section
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoiseConfig {
+    pub input: String,
+    pub sigma: f64,
+    pub correlation: f64,
+    /// Bias added per second
+    pub drift: f64,
+    pub seed: u64,
+}
+
+impl NoiseConfig {
+    fn check(&self) -> Result<(), String> {
+        if !(self.sigma >= 0.0 && self.sigma.is_finite()) {
+            return Err(format!(
+                "noise '{}': sigma must not be negative",
+                self.input
+            ));
+        }
+        if !(0.0..1.0).contains(&self.correlation) {
+            return Err(format!(
+                "noise '{}': correlation must be at least 0 and below 1",
+                self.input
+            ));
+        }
+        if !self.drift.is_finite() {
+            return Err(format!("noise '{}': drift must be a number", self.input));
+        }
+        Ok(())
+    }
+}
+
+/// Check the `[[noise]]` sections of `system`
+pub fn validate(system: &FuzzySystem) -> Result<(), String> {
+    for (i, noise) in system.noise.iter().enumerate() {
+        if system.input(&noise.input).is_none() {
+            return Err(format!("noise: unknown input '{}'", noise.input));
+        }
+        if system.noise[..i].iter().any(|n| n.input == noise.input) {
+            return Err(format!("noise '{}': declared twice", noise.input));
+        }
+        noise.check()?;
+    }
+    Ok(())
+}
+
+/// Standard normal draw (Box-Muller)
+fn gaussian(rng: &mut StdRng) -> f64 {
+    let u: f64 = 1.0 - rng.r#gen::<f64>();
+    let v: f64 = rng.r#gen();
+    (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+}
+
+/// The error process of one sensor
+#[derive(Debug, Clone)]
+pub struct Sensor {
+    pub config: NoiseConfig,
+    rng: StdRng,
+    error: f64,
+    bias: f64,
+    /// Clock time of the last sample, `None` before the first
+    last: Option<f64>,
+}
+
+impl Sensor {
+    pub fn new(config: NoiseConfig, index: usize) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed.wrapping_add(index as u64));
+        // Start in the stationary distribution, not at zero
+        let error = config.sigma * gaussian(&mut rng);
+        Sensor {
+            config,
+            rng,
+            error,
+            bias: 0.0,
+            last: None,
+        }
+    }
+
+    /// Error of the reading at clock time `now`; several readings at the
+    /// same time share it
+    pub fn sample(&mut self, now: f64) -> f64 {
+        let dt = self.last.map_or(0.0, |last| (now - last).max(0.0));
+        self.last = Some(now);
+        if dt > 0.0 {
+            let decay = self.config.correlation.powf(dt);
+            let spread = self.config.sigma * (1.0 - decay * decay).sqrt();
+            self.error = decay * self.error + spread * gaussian(&mut self.rng);
+            self.bias += self.config.drift * dt;
+        }
+        self.error + self.bias
+    }
+}
+
+/// The noise stage of the input pipeline
+#[derive(Debug, Clone, Default)]
+pub struct Noise {
+    sensors: Vec<Sensor>,
+}
+
+impl Noise {
+    /// Sensors for the `[[noise]]` sections of `system`
+    pub fn new(system: &FuzzySystem) -> Self {
+        Noise {
+            sensors: system
+                .noise
+                .iter()
+                .enumerate()
+                .map(|(i, config)| Sensor::new(config.clone(), i))
+                .collect(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.sensors.is_empty()
+    }
+
+    pub fn get(&self, input: &str) -> Option<&NoiseConfig> {
+        self.sensors
+            .iter()
+            .map(|s| &s.config)
+            .find(|c| c.input == input)
+    }
+
+    /// Values the sensors report for the true `values` (declaration
+    /// order) at clock time `now`
+    pub fn apply(&mut self, inputs: &[FuzzyVariable], values: &[f64], now: f64) -> Vec<f64> {
+        inputs
+            .iter()
+            .zip(values)
+            .map(|(var, value)| {
+                match self.sensors.iter_mut().find(|s| s.config.input == var.name) {
+                    Some(sensor) => value + sensor.sample(now),
+                    None => *value,
+                }
+            })
+            .collect()
+    }
+
+    /// Run a noise command (see the module comment), returning a
+    /// description of what changed. Changed parameters keep the sensor's
+    /// error and bias so far.
+    pub fn command(&mut self, text: &str, inputs: &[FuzzyVariable]) -> Result<String, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let number = |word: &str| numbers::parse(word, Locale::Auto).map_err(|e| e.to_string());
+        let (input, sigma, correlation, drift) = match words[..] {
+            [input, "off"] => {
+                let before = self.sensors.len();
+                self.sensors.retain(|s| s.config.input != input);
+                return if self.sensors.len() < before {
+                    Ok(format!("Noise off on {}", input))
+                } else {
+                    Err(format!("no noise on '{}'", input))
+                };
+            }
+            [input, sigma, correlation] => (input, number(sigma)?, number(correlation)?, 0.0),
+            [input, sigma, correlation, drift] => {
+                (input, number(sigma)?, number(correlation)?, number(drift)?)
+            }
+            _ => {
+                return Err(
+                    "expected '<input> <sigma> <correlation> [<drift>]' or '<input> off'"
+                        .to_string(),
+                );
+            }
+        };
+        let Some(index) = inputs.iter().position(|var| var.name == input) else {
+            return Err(format!("unknown input '{}'", input));
+        };
+        let config = NoiseConfig {
+            input: input.to_string(),
+            sigma,
+            correlation,
+            drift,
+            seed: 0,
+        };
+        config.check()?;
+        let message = format!(
+            "Noise on {}: σ {} correlation {} drift {}/s",
+            input, sigma, correlation, drift
+        );
+        match self.sensors.iter_mut().find(|s| s.config.input == input) {
+            Some(sensor) => {
+                sensor.config = NoiseConfig {
+                    seed: sensor.config.seed,
+                    ..config
+                }
+            }
+            None => self.sensors.push(Sensor::new(config, index)),
+        }
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn sensor(sigma: f64, correlation: f64, drift: f64) -> Sensor {
+        let config = NoiseConfig {
+            input: "temperature".to_string(),
+            sigma,
+            correlation,
+            drift,
+            seed: 42,
+        };
+        Sensor::new(config, 0)
+    }
+
+    /// Mean, standard deviation and lag-1 autocorrelation of `errors`
+    fn statistics(errors: &[f64]) -> (f64, f64, f64) {
+        let n = errors.len() as f64;
+        let mean = errors.iter().sum::<f64>() / n;
+        let variance = errors.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / n;
+        let covariance = errors
+            .windows(2)
+            .map(|w| (w[0] - mean) * (w[1] - mean))
+            .sum::<f64>()
+            / (n - 1.0);
+        (mean, variance.sqrt(), covariance / variance)
+    }
+
+    #[test]
+    fn the_error_has_the_configured_spread_and_memory() {
+        let mut noise = sensor(0.5, 0.9, 0.0);
+        let errors: Vec<f64> = (0..200_000).map(|i| noise.sample(i as f64)).collect();
+        let (mean, sigma, correlation) = statistics(&errors);
+        // The standard error of the mean is σ·sqrt((1 + φ) / (1 - φ) / n)
+        assert!(mean.abs() < 0.02, "mean {}", mean);
+        assert!((sigma - 0.5).abs() < 0.01, "sigma {}", sigma);
+        assert!(
+            (correlation - 0.9).abs() < 0.01,
+            "correlation {}",
+            correlation
+        );
+
+        // Sampled twice as often, the correlation per sample is sqrt(0.9)
+        let mut noise = sensor(0.5, 0.9, 0.0);
+        let errors: Vec<f64> = (0..200_000).map(|i| noise.sample(i as f64 / 2.0)).collect();
+        let (_, sigma, correlation) = statistics(&errors);
+        assert!((sigma - 0.5).abs() < 0.01, "sigma {}", sigma);
+        assert!(
+            (correlation - 0.9f64.sqrt()).abs() < 0.01,
+            "correlation {}",
+            correlation
+        );
+
+        // Without memory the samples are independent
+        let mut noise = sensor(1.0, 0.0, 0.0);
+        let errors: Vec<f64> = (0..200_000).map(|i| noise.sample(i as f64)).collect();
+        let (mean, sigma, correlation) = statistics(&errors);
+        assert!(mean.abs() < 0.01 && (sigma - 1.0).abs() < 0.01);
+        assert!(correlation.abs() < 0.01, "correlation {}", correlation);
+    }
+
+    #[test]
+    fn the_bias_drifts_and_runs_repeat() {
+        let mut noise = sensor(0.0, 0.5, 0.1);
+        assert_eq!(noise.sample(0.0), 0.0);
+        assert!((noise.sample(10.0) - 1.0).abs() < 1e-9);
+        // Same time, same reading
+        assert!((noise.sample(10.0) - 1.0).abs() < 1e-9);
+
+        let run = || {
+            let mut noise = sensor(0.5, 0.9, 0.01);
+            (0..100).map(|i| noise.sample(i as f64)).collect::<Vec<_>>()
+        };
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn commands_are_checked_and_keep_the_sensor_state() {
+        let inputs = fixtures::fan().inputs;
+        let mut noise = Noise::default();
+        assert_eq!(
+            noise.command("co2 1 0.5", &inputs),
+            Err("unknown input 'co2'".to_string())
+        );
+        assert!(noise.command("humidity 1 1.5", &inputs).is_err());
+        assert!(noise.command("humidity -1 0.5", &inputs).is_err());
+        assert!(noise.command("humidity off", &inputs).is_err());
+
+        noise.command("humidity 2 0.5 0.1", &inputs).unwrap();
+        let seen = noise.apply(&inputs, &[20.0, 50.0], 0.0);
+        assert_eq!(seen[0], 20.0);
+        assert_ne!(seen[1], 50.0);
+        noise.command("humidity 0 0.5", &inputs).unwrap();
+        assert_eq!(noise.get("humidity").unwrap().sigma, 0.0);
+        noise.command("humidity off", &inputs).unwrap();
+        assert!(!noise.is_active());
+    }
+}
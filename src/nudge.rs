@@ -0,0 +1,143 @@
+// ============================================================================
+// NUDGE - Ajuste fino com aceleração
+// ============================================================================
+//
+// Up/Down nudge the temperature and PageUp/PageDown the humidity in the
+// TUI. A single press moves the input by the smallest step; holding the
+// key lets the terminal repeat it, and the step grows through STEPS every
+// REPEATS_PER_STEP repeats as long as they come less than REPEAT_WINDOW
+// apart. A pause, the other direction or another input starts over at the
+// smallest step. Presses in one such burst count as one change: the TUI
+// keeps a single history entry for the whole burst.
+//
+// The accelerator is fed the clock time of each press, so it does not
+// depend on the terminal's actual repeat rate.
+
+/// Step sizes, in the input's storage unit, from a single press up
+pub const STEPS: [f64; 4] = [0.5, 1.0, 2.0, 5.0];
+
+/// Most seconds between two presses of a held key; it covers the delay
+/// before the terminal starts repeating
+pub const REPEAT_WINDOW: f64 = 0.6;
+
+/// Repeats at one step before the next larger one
+pub const REPEATS_PER_STEP: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+impl Direction {
+    pub fn sign(self) -> f64 {
+        match self {
+            Direction::Up => 1.0,
+            Direction::Down => -1.0,
+        }
+    }
+}
+
+/// What one press does
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Nudge {
+    /// Signed change of the input
+    pub delta: f64,
+    /// The press continues the burst of the previous one
+    pub continues: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Accelerator {
+    /// Input, direction and time of the previous press
+    last: Option<(String, Direction, f64)>,
+    /// Presses in the current burst after the first
+    repeats: usize,
+}
+
+impl Accelerator {
+    /// Register a press of the key that nudges `input` in `direction` at
+    /// clock time `now`
+    pub fn press(&mut self, input: &str, direction: Direction, now: f64) -> Nudge {
+        let continues = self.last.as_ref().is_some_and(|(last, dir, time)| {
+            last == input && *dir == direction && (0.0..REPEAT_WINDOW).contains(&(now - time))
+        });
+        self.repeats = if continues { self.repeats + 1 } else { 0 };
+        self.last = Some((input.to_string(), direction, now));
+        let level = (self.repeats / REPEATS_PER_STEP).min(STEPS.len() - 1);
+        Nudge {
+            delta: STEPS[level] * direction.sign(),
+            continues,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Steps of `presses` presses of one key, `interval` seconds apart
+    fn held(presses: usize, interval: f64) -> Vec<f64> {
+        let mut accelerator = Accelerator::default();
+        (0..presses)
+            .map(|i| {
+                let nudge = accelerator.press("temperature", Direction::Up, i as f64 * interval);
+                nudge.delta
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_held_key_grows_the_step() {
+        let steps = held(30, 0.03);
+        assert_eq!(steps[..6], [0.5; 6]);
+        assert_eq!(steps[6..12], [1.0; 6]);
+        assert_eq!(steps[12..18], [2.0; 6]);
+        assert_eq!(steps[18..], [5.0; 12]);
+        // 10 °C to 35 °C takes 19 presses instead of 50
+        assert!(steps[..18].iter().sum::<f64>() < 25.0);
+        assert!(steps[..19].iter().sum::<f64>() >= 25.0);
+
+        // Pressed slowly, every press is a fresh burst
+        assert_eq!(held(10, 1.0), [0.5; 10]);
+    }
+
+    #[test]
+    fn a_pause_another_direction_or_input_starts_over() {
+        let mut accelerator = Accelerator::default();
+        for i in 0..8 {
+            accelerator.press("temperature", Direction::Up, i as f64 * 0.05);
+        }
+        let growing = accelerator.press("temperature", Direction::Up, 0.6);
+        assert_eq!(
+            growing,
+            Nudge {
+                delta: 1.0,
+                continues: true
+            }
+        );
+        let reversed = accelerator.press("temperature", Direction::Down, 0.65);
+        assert_eq!(
+            reversed,
+            Nudge {
+                delta: -0.5,
+                continues: false
+            }
+        );
+        assert!(
+            !accelerator
+                .press("humidity", Direction::Down, 0.7)
+                .continues
+        );
+        assert!(
+            accelerator
+                .press("humidity", Direction::Down, 0.75)
+                .continues
+        );
+        assert!(
+            !accelerator
+                .press("humidity", Direction::Down, 2.0)
+                .continues
+        );
+    }
+}
@@ -0,0 +1,246 @@
+// ============================================================================
+// NUMBERS - Leitura de números digitados
+// ============================================================================
+//
+// Numbers typed by people (the TUI prompts, the noise and injection
+// commands, the control socket, and CSV files with `batch --locale`) are
+// read by `parse`, so "23,5" means the same everywhere:
+//
+//   - spaces (thin and no-break ones too) and underscores are dropped,
+//     so "1 234,5" and "1_234.5" read as digit groups;
+//   - with both '.' and ',' the last one is the decimal separator and the
+//     other separates groups of three digits;
+//   - a single ',' is a decimal comma, except that "1,234" (one to three
+//     digits, a comma, three digits) could be either and is refused as
+//     ambiguous rather than guessed;
+//   - a single '.' is a decimal point, as in the config files.
+//
+// A strict locale drops the guessing: `point` reads '.' as the decimal
+// separator and ',' only between digit groups, `comma` the other way
+// round. `system` takes the locale from LC_ALL, LC_NUMERIC or LANG, and
+// falls back to the guessing for languages it does not know.
+//
+// Machine-written text (the state and journal files, the daemon's state
+// lines) keeps Rust's own number syntax.
+
+use std::fmt;
+
+/// How the decimal separator is told from digit grouping
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Locale {
+    /// Either separator, where the text leaves no doubt
+    #[default]
+    Auto,
+    /// "1,234.5"
+    Point,
+    /// "1.234,5"
+    Comma,
+}
+
+impl Locale {
+    /// `auto`, `point`, `comma`, or `system` for the environment's locale
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Locale::Auto),
+            "point" => Some(Locale::Point),
+            "comma" => Some(Locale::Comma),
+            "system" => Some(Locale::from_env()),
+            _ => None,
+        }
+    }
+
+    /// The locale of the environment, `Auto` when unset or unknown
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|key| std::env::var(key).ok())
+            .find(|value| !value.is_empty())
+            .map_or(Locale::Auto, |value| Locale::of_language(&value))
+    }
+
+    /// The locale for a POSIX locale name such as "de_DE.UTF-8"
+    pub fn of_language(name: &str) -> Self {
+        let language = name.split(['_', '.', '@', '-']).next().unwrap_or("");
+        match language {
+            "C" | "POSIX" | "en" | "ja" | "ko" | "zh" | "he" | "th" | "hi" => Locale::Point,
+            "de" | "fr" | "es" | "it" | "pt" | "nl" | "ru" | "pl" | "sv" | "da" | "fi" | "nb"
+            | "nn" | "cs" | "sk" | "tr" | "el" | "hu" | "ro" | "uk" | "id" | "vi" => Locale::Comma,
+            _ => Locale::Auto,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NumberError {
+    Invalid(String),
+    /// Both readings of a single comma are plausible
+    Ambiguous {
+        text: String,
+        decimal: f64,
+        grouped: f64,
+    },
+}
+
+impl fmt::Display for NumberError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NumberError::Invalid(text) => write!(f, "invalid number '{}'", text),
+            NumberError::Ambiguous {
+                text,
+                decimal,
+                grouped,
+            } => write!(
+                f,
+                "'{}' is ambiguous: {} or {}? Type '.' for the decimal point",
+                text, decimal, grouped
+            ),
+        }
+    }
+}
+
+/// Read a number typed by a person (see the module comment)
+pub fn parse(text: &str, locale: Locale) -> Result<f64, NumberError> {
+    let invalid = || NumberError::Invalid(text.trim().to_string());
+    let cleaned: String = text
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, '_' | '\u{2009}' | '\u{202F}' | '\u{00A0}' | ' '))
+        .collect();
+    let (decimal, group) = match locale {
+        Locale::Point => ('.', ','),
+        Locale::Comma => (',', '.'),
+        Locale::Auto => {
+            let (dots, commas) = (cleaned.matches('.').count(), cleaned.matches(',').count());
+            match (dots, commas) {
+                (_, 0) if dots > 1 => (',', '.'),
+                (_, 0) => ('.', ','),
+                (0, 1) => {
+                    let (whole, fraction) = cleaned.split_once(',').ok_or_else(invalid)?;
+                    let digits = whole.trim_start_matches(['-', '+']);
+                    let grouped = fraction.len() == 3
+                        && (1..=3).contains(&digits.len())
+                        && fraction.chars().all(|c| c.is_ascii_digit())
+                        && digits.chars().all(|c| c.is_ascii_digit());
+                    if grouped {
+                        return Err(NumberError::Ambiguous {
+                            text: text.trim().to_string(),
+                            decimal: read(&cleaned, ',', '.').ok_or_else(invalid)?,
+                            grouped: read(&cleaned, '.', ',').ok_or_else(invalid)?,
+                        });
+                    }
+                    (',', '.')
+                }
+                (0, _) => ('.', ','),
+                _ => match (cleaned.rfind('.'), cleaned.rfind(',')) {
+                    (Some(dot), Some(comma)) if comma > dot => (',', '.'),
+                    _ => ('.', ','),
+                },
+            }
+        }
+    };
+    read(&cleaned, decimal, group).ok_or_else(invalid)
+}
+
+/// `text` with `decimal` as the decimal separator and `group` only between
+/// groups of three digits before it
+fn read(text: &str, decimal: char, group: char) -> Option<f64> {
+    let (whole, fraction) = match text.split_once(decimal) {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (text, None),
+    };
+    if fraction.is_some_and(|f| f.contains(group) || f.contains(decimal)) {
+        return None;
+    }
+    let unsigned = whole.trim_start_matches(['-', '+']);
+    let mut groups = unsigned.split(group);
+    let first = groups.next()?;
+    let rest: Vec<&str> = groups.collect();
+    if !rest.is_empty()
+        && (first.is_empty()
+            || first.len() > 3
+            || rest
+                .iter()
+                .any(|g| g.len() != 3 || !g.chars().all(|c| c.is_ascii_digit())))
+    {
+        return None;
+    }
+    let mut number = whole.replace(group, "");
+    if let Some(fraction) = fraction {
+        number.push('.');
+        number.push_str(fraction);
+    }
+    number.parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn either_separator_is_read_when_unambiguous() {
+        let auto = |text| parse(text, Locale::Auto);
+        assert_eq!(auto("23,5"), Ok(23.5));
+        assert_eq!(auto("23.5"), Ok(23.5));
+        assert_eq!(auto("-0,25"), Ok(-0.25));
+        assert_eq!(auto("1.234,5"), Ok(1234.5));
+        assert_eq!(auto("1,234.5"), Ok(1234.5));
+        assert_eq!(auto("1,234,567"), Ok(1234567.0));
+        assert_eq!(auto("1.234.567"), Ok(1234567.0));
+        assert_eq!(auto("12,3456"), Ok(12.3456));
+        assert_eq!(auto(" 1\u{2009}234,5 "), Ok(1234.5));
+        assert_eq!(auto("1_000"), Ok(1000.0));
+        assert_eq!(auto("1.234"), Ok(1.234));
+        assert_eq!(auto("1e3"), Ok(1000.0));
+
+        for bad in [
+            "",
+            "abc",
+            "1,2,3",
+            "1.2.3,4,5",
+            "12,34.5",
+            "inf",
+            "NaN",
+            "1,23.4",
+        ] {
+            assert!(matches!(auto(bad), Err(NumberError::Invalid(_))), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn a_lone_thousands_comma_is_ambiguous_unless_the_locale_says() {
+        let error = parse("1,234", Locale::Auto).unwrap_err();
+        assert_eq!(
+            error,
+            NumberError::Ambiguous {
+                text: "1,234".to_string(),
+                decimal: 1.234,
+                grouped: 1234.0
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "'1,234' is ambiguous: 1.234 or 1234? Type '.' for the decimal point"
+        );
+        assert!(matches!(
+            parse("-12,500", Locale::Auto),
+            Err(NumberError::Ambiguous { .. })
+        ));
+
+        assert_eq!(parse("1,234", Locale::Point), Ok(1234.0));
+        assert_eq!(parse("1,234", Locale::Comma), Ok(1.234));
+        assert_eq!(parse("1.234,5", Locale::Comma), Ok(1234.5));
+        // Strict locales refuse the other convention
+        assert!(parse("23,5", Locale::Point).is_err());
+        assert!(parse("23.5", Locale::Comma).is_err());
+    }
+
+    #[test]
+    fn locales_are_named_or_taken_from_the_language() {
+        assert_eq!(Locale::parse("comma"), Some(Locale::Comma));
+        assert_eq!(Locale::parse("metric"), None);
+        assert_eq!(Locale::of_language("de_DE.UTF-8"), Locale::Comma);
+        assert_eq!(Locale::of_language("en_US.UTF-8"), Locale::Point);
+        assert_eq!(Locale::of_language("C"), Locale::Point);
+        assert_eq!(Locale::of_language("xx_YY"), Locale::Auto);
+    }
+}
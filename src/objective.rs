@@ -0,0 +1,312 @@
+// ============================================================================
+// OBJECTIVE - Função objetivo
+// ============================================================================
+//
+// How good conditions have been, as one number per computation from an
+// expression in the config:
+//
+//     [objective]            # optional
+//     score = "abs(temperature - 22) + abs(humidity - 45) / 2 + 0.05 * fan_speed"
+//     better = "lower"       # the default; or "higher"
+//
+// `score` is an expression like an alarm's condition (alarm.rs), read as a
+// number: the inputs as the controller saw them, the outputs it computed,
+// memberships, rule strengths, and abs, min and max. Every computation
+// scores, and the running best, worst and mean are kept with the time and
+// the inputs of the best and the worst. A score that is not a number
+// (a division by zero) is shown but not kept.
+//
+// The TUI shows the current score and the records under the output gauge;
+// the daemon's `objective` command reports them; `batch` adds a column of
+// scores and ends with the mean, best and worst, so two configs run over
+// the same CSV compare by one number each. A replayed bundle reports the
+// mean for the recorded outputs and for the replayed ones. Recorded
+// outputs keep no rule strengths, so `strength(n)` reads 0 for them.
+//
+// There is no derived-input evaluator or metrics endpoint in this program;
+// the alarm expressions and the daemon's commands stand in for them.
+
+use crate::alarm::{Expression, Sample};
+
+/// Which way the score improves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Better {
+    #[default]
+    Lower,
+    Higher,
+}
+
+impl Better {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "lower" => Some(Better::Lower),
+            "higher" => Some(Better::Higher),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Better::Lower => "lower",
+            Better::Higher => "higher",
+        }
+    }
+
+    /// Whether `a` is a better score than `b`
+    fn prefers(self, a: f64, b: f64) -> bool {
+        match self {
+            Better::Lower => a < b,
+            Better::Higher => a > b,
+        }
+    }
+}
+
+/// `[objective]` settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectiveConfig {
+    pub score: Expression,
+    pub better: Better,
+}
+
+/// A score kept as a record, with when and at which inputs it was reached
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mark {
+    pub score: f64,
+    /// Wall-clock time, in epoch seconds
+    pub stamp: f64,
+    /// Input values, in declaration order
+    pub inputs: Vec<f64>,
+}
+
+/// The running score of a session
+#[derive(Debug, Clone)]
+pub struct Objective {
+    config: ObjectiveConfig,
+    memory: Vec<Option<f64>>,
+    current: Option<f64>,
+    best: Option<Mark>,
+    worst: Option<Mark>,
+    sum: f64,
+    count: usize,
+}
+
+impl Objective {
+    pub fn new(config: ObjectiveConfig) -> Self {
+        Objective {
+            config,
+            memory: Vec::new(),
+            current: None,
+            best: None,
+            worst: None,
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn config(&self) -> &ObjectiveConfig {
+        &self.config
+    }
+
+    /// Score `sample` at `now` (the caller's clock, for the temporal
+    /// operators) and keep it if it is a number; `stamp` is the wall-clock
+    /// time its records are kept with
+    pub fn update(&mut self, sample: &Sample, now: f64, stamp: f64) -> f64 {
+        let score = self.config.score.evaluate(sample, now, &mut self.memory);
+        self.current = Some(score);
+        if !score.is_finite() {
+            return score;
+        }
+        let mark = || Mark {
+            score,
+            stamp,
+            inputs: sample.inputs.to_vec(),
+        };
+        let better = self.config.better;
+        if self
+            .best
+            .as_ref()
+            .is_none_or(|best| better.prefers(score, best.score))
+        {
+            self.best = Some(mark());
+        }
+        if self
+            .worst
+            .as_ref()
+            .is_none_or(|worst| better.prefers(worst.score, score))
+        {
+            self.worst = Some(mark());
+        }
+        self.sum += score;
+        self.count += 1;
+        score
+    }
+
+    /// The score of the last computation
+    pub fn current(&self) -> Option<f64> {
+        self.current
+    }
+
+    pub fn best(&self) -> Option<&Mark> {
+        self.best.as_ref()
+    }
+
+    pub fn worst(&self) -> Option<&Mark> {
+        self.worst.as_ref()
+    }
+
+    /// Mean of the scores kept, none before the first
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+
+    /// How many scores were kept
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Where the current score lies from the worst (0) to the best (1)
+    pub fn ratio(&self) -> Option<f64> {
+        let (current, best, worst) = (self.current?, self.best()?, self.worst()?);
+        if best.score == worst.score {
+            return current.is_finite().then_some(1.0);
+        }
+        Some(((current - worst.score) / (best.score - worst.score)).clamp(0.0, 1.0))
+    }
+
+    /// Append the running records and what the temporal operators
+    /// remember to `saved` (a batch checkpoint, checkpoint.rs)
+    #[cfg(feature = "batch")]
+    pub fn save(&self, saved: &mut Vec<f64>) {
+        saved.extend([
+            self.sum,
+            self.count as f64,
+            self.current.unwrap_or(f64::NAN),
+        ]);
+        for mark in [&self.best, &self.worst].into_iter().flatten() {
+            saved.extend([mark.score, mark.stamp, mark.inputs.len() as f64]);
+            saved.extend(&mark.inputs);
+        }
+        saved.push(self.memory.len() as f64);
+        saved.extend(self.memory.iter().map(|m| m.unwrap_or(f64::NAN)));
+    }
+
+    /// Take up what `save` wrote, none if `saved` runs out
+    #[cfg(feature = "batch")]
+    pub fn restore(&mut self, saved: &mut dyn Iterator<Item = f64>) -> Option<()> {
+        let mut take = |count: f64| -> Option<Vec<f64>> {
+            (0..count as usize).map(|_| saved.next()).collect()
+        };
+        let [sum, count, current] = take(3.0)?[..] else {
+            return None;
+        };
+        (self.sum, self.count) = (sum, count as usize);
+        self.current = Some(current).filter(|c| !c.is_nan());
+        let mut mark = || -> Option<Mark> {
+            let [score, stamp, inputs] = take(3.0)?[..] else {
+                return None;
+            };
+            Some(Mark {
+                score,
+                stamp,
+                inputs: take(inputs)?,
+            })
+        };
+        (self.best, self.worst) = match self.count {
+            0 => (None, None),
+            _ => (Some(mark()?), Some(mark()?)),
+        };
+        let slots = take(1.0)?[0];
+        self.memory = take(slots)?
+            .into_iter()
+            .map(|m| Some(m).filter(|m| !m.is_nan()))
+            .collect();
+        Some(())
+    }
+
+    /// "mean M, best B, worst W over N", or "no scores"
+    pub fn summary(&self) -> String {
+        match (self.mean(), self.best(), self.worst()) {
+            (Some(mean), Some(best), Some(worst)) => format!(
+                "mean {:.3}, best {:.3}, worst {:.3} over {}",
+                mean, best.score, worst.score, self.count
+            ),
+            _ => "no scores".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FuzzySystem;
+    use crate::fixtures;
+
+    fn scored(score: &str, better: Better) -> (FuzzySystem, Objective) {
+        let system = fixtures::fan();
+        let score = Expression::parse(score, &system).unwrap();
+        (system, Objective::new(ObjectiveConfig { score, better }))
+    }
+
+    fn sample<'a>(system: &'a FuzzySystem, inputs: &'a [f64], outputs: &'a [f64]) -> Sample<'a> {
+        Sample {
+            system,
+            inputs,
+            outputs,
+            strengths: &[],
+        }
+    }
+
+    #[test]
+    fn the_score_reads_the_inputs_and_outputs_of_the_computation() {
+        let (system, mut objective) = scored(
+            "abs(temperature - 22) + abs(humidity - 45) / 2 + 0.05 * fan_speed",
+            Better::Lower,
+        );
+        let score = objective.update(&sample(&system, &[25.0, 41.0], &[40.0]), 0.0, 0.0);
+        assert_eq!(score, 3.0 + 2.0 + 2.0);
+        let score = objective.update(&sample(&system, &[20.0, 45.0], &[0.0]), 1.0, 0.0);
+        assert_eq!(score, 2.0);
+
+        let (system, mut objective) =
+            scored("max(temperature, 30) - min(0, -humidity)", Better::Lower);
+        let score = objective.update(&sample(&system, &[25.0, 10.0], &[0.0]), 0.0, 0.0);
+        assert_eq!(score, 40.0);
+    }
+
+    #[test]
+    fn records_keep_the_best_worst_and_mean_with_their_inputs() {
+        let (system, mut lower) = scored("temperature", Better::Lower);
+        let (_, mut higher) = scored("temperature", Better::Higher);
+        for (stamp, temperature) in [(10.0, 24.0), (11.0, 20.0), (12.0, 30.0), (13.0, 22.0)] {
+            let inputs = [temperature, 50.0];
+            lower.update(&sample(&system, &inputs, &[0.0]), stamp, stamp);
+            higher.update(&sample(&system, &inputs, &[0.0]), stamp, stamp);
+        }
+        let best = lower.best().unwrap();
+        assert_eq!(
+            (best.score, best.stamp, &best.inputs[..]),
+            (20.0, 11.0, &[20.0, 50.0][..])
+        );
+        assert_eq!(lower.worst().unwrap().stamp, 12.0);
+        assert_eq!(higher.best().unwrap().stamp, 12.0);
+        assert_eq!(higher.worst().unwrap().stamp, 11.0);
+        assert_eq!((lower.mean(), lower.count()), (Some(24.0), 4));
+        // 22 lies a fifth of the way from the worst, 30, to the best, 20
+        assert_eq!(lower.ratio(), Some(0.8));
+        assert_eq!(higher.ratio(), Some(0.2));
+
+        // Not a number: shown, not kept
+        let (system, mut objective) = scored("1 / (temperature - 20)", Better::Lower);
+        objective.update(&sample(&system, &[21.0, 50.0], &[0.0]), 0.0, 0.0);
+        assert!(
+            objective
+                .update(&sample(&system, &[20.0, 50.0], &[0.0]), 1.0, 1.0)
+                .is_infinite()
+        );
+        assert_eq!((objective.mean(), objective.count()), (Some(1.0), 1));
+        assert_eq!(
+            objective.summary(),
+            "mean 1.000, best 1.000, worst 1.000 over 1"
+        );
+    }
+}
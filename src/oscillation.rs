@@ -0,0 +1,254 @@
+// ============================================================================
+// OSCILLATION DETECTOR - Detector de oscilação
+// ============================================================================
+//
+// A closed loop can settle into a limit cycle: the output swinging between
+// two values for as long as it runs. The detector watches the first
+// output after the pipeline over the last `window_secs` and calls it
+// oscillating when, within that window,
+//
+//     the output reversed direction at least `min_reversals` times, each
+//     time coming back from its latest extreme by `min_amplitude`, and
+//     the reversals came at a steady pace: the longest stretch between
+//     two of them at most MAX_SPREAD times the shortest
+//
+// Jitter smaller than `min_amplitude` never counts, however often it
+// changes sign, and neither does a step or a slow drift, which never turn
+// back. Noise larger than that does reverse now and then, but at random
+// intervals, where a limit cycle keeps its period.
+//
+//     [oscillation]          # optional; without it nothing is watched
+//     window_secs = 60.0     # above 0
+//     min_reversals = 4      # a whole number of at least 1
+//     min_amplitude = 10.0   # above 0, in the output's units
+//
+// The daemon logs when an oscillation starts and ends, and `stats` reports
+// `oscillating` and `amplitude`. The TUI says so in its message line and
+// marks the band the output swings over on the fan gauge.
+
+use std::collections::VecDeque;
+
+/// Longest over shortest time between reversals a cycle may show
+pub const MAX_SPREAD: f64 = 2.0;
+
+/// `[oscillation]` settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscillationConfig {
+    pub window_secs: f64,
+    pub min_reversals: usize,
+    pub min_amplitude: f64,
+}
+
+impl Default for OscillationConfig {
+    fn default() -> Self {
+        OscillationConfig {
+            window_secs: 60.0,
+            min_reversals: 4,
+            min_amplitude: 10.0,
+        }
+    }
+}
+
+/// What the window shows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Swing {
+    /// Lowest and highest output in the window
+    pub low: f64,
+    pub high: f64,
+    /// Direction changes by at least `min_amplitude`
+    pub reversals: usize,
+    /// Longest over shortest time between consecutive reversals, 1 with
+    /// fewer than three
+    pub spread: f64,
+}
+
+impl Swing {
+    pub fn amplitude(&self) -> f64 {
+        self.high - self.low
+    }
+}
+
+/// The output over the detection window
+#[derive(Debug, Clone)]
+pub struct Detector {
+    config: OscillationConfig,
+    samples: VecDeque<(f64, f64)>,
+    oscillating: bool,
+}
+
+impl Detector {
+    pub fn new(config: OscillationConfig) -> Self {
+        Detector {
+            config,
+            samples: VecDeque::new(),
+            oscillating: false,
+        }
+    }
+
+    /// Record the output `value` at `time`; true when this starts or ends
+    /// an oscillation
+    pub fn push(&mut self, time: f64, value: f64) -> bool {
+        self.samples.push_back((time, value));
+        while self
+            .samples
+            .front()
+            .is_some_and(|(t, _)| time - t > self.config.window_secs)
+        {
+            self.samples.pop_front();
+        }
+        let oscillating = self.swing().is_some_and(|swing| {
+            swing.reversals >= self.config.min_reversals
+                && swing.amplitude() >= self.config.min_amplitude
+                && swing.spread <= MAX_SPREAD
+        });
+        let changed = oscillating != self.oscillating;
+        self.oscillating = oscillating;
+        changed
+    }
+
+    pub fn is_oscillating(&self) -> bool {
+        self.oscillating
+    }
+
+    /// The window's swing while oscillating
+    pub fn oscillation(&self) -> Option<Swing> {
+        self.swing().filter(|_| self.oscillating)
+    }
+
+    /// Range and reversals of the samples in the window
+    pub fn swing(&self) -> Option<Swing> {
+        let mut samples = self.samples.iter().copied();
+        let (_, first) = samples.next()?;
+        let threshold = self.config.min_amplitude;
+        let (mut low, mut high) = (first, first);
+        // The latest extreme and whether the output is heading up from it;
+        // no direction until it has moved by the threshold
+        let (mut extreme, mut rising) = (first, None);
+        let mut reversals: Vec<f64> = Vec::new();
+        for (time, value) in samples {
+            low = low.min(value);
+            high = high.max(value);
+            match rising {
+                None if (value - extreme).abs() >= threshold => {
+                    rising = Some(value > extreme);
+                    extreme = value;
+                }
+                None => {}
+                Some(up) if (up && value > extreme) || (!up && value < extreme) => extreme = value,
+                Some(up) if (extreme - value).abs() >= threshold => {
+                    reversals.push(time);
+                    rising = Some(!up);
+                    extreme = value;
+                }
+                Some(_) => {}
+            }
+        }
+        let gaps = reversals.windows(2).map(|pair| pair[1] - pair[0]);
+        let (shortest, longest) = gaps.fold((f64::INFINITY, 0.0_f64), |(lo, hi), gap| {
+            (lo.min(gap), hi.max(gap))
+        });
+        Some(Swing {
+            low,
+            high,
+            reversals: reversals.len(),
+            spread: if longest > 0.0 {
+                longest / shortest
+            } else {
+                1.0
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// The detector over `value(t)` sampled every 0.5 s for two minutes:
+    /// whether it ended oscillating, and its last swing
+    fn run(value: impl Fn(f64) -> f64) -> (bool, Swing) {
+        let mut detector = Detector::new(OscillationConfig::default());
+        for i in 0..240 {
+            let t = i as f64 * 0.5;
+            detector.push(t, value(t));
+        }
+        (detector.is_oscillating(), detector.swing().unwrap())
+    }
+
+    #[test]
+    fn a_limit_cycle_is_caught() {
+        // ±15 around 50 with a 10 s period: 12 reversals a minute
+        let (oscillating, swing) = run(|t| 50.0 + 15.0 * (t * std::f64::consts::TAU / 10.0).sin());
+        assert!(oscillating);
+        assert!(swing.reversals >= 11, "{:?}", swing);
+        assert!((swing.amplitude() - 30.0).abs() < 0.5, "{:?}", swing);
+
+        // Too small a swing, however regular
+        let (oscillating, _) = run(|t| 50.0 + 3.0 * (t * std::f64::consts::TAU / 10.0).sin());
+        assert!(!oscillating);
+    }
+
+    #[test]
+    fn steps_noise_and_steady_outputs_are_not_oscillations() {
+        // One step, and a step and back
+        let (oscillating, swing) = run(|t| if t < 60.0 { 20.0 } else { 80.0 });
+        assert!(!oscillating);
+        assert_eq!(swing.reversals, 0);
+        let pulse = |t: f64| {
+            if (30.0..40.0).contains(&t) {
+                80.0
+            } else {
+                20.0
+            }
+        };
+        assert!(!run(pulse).0);
+
+        // Noise changing sign on most samples, alone and on a slow ramp
+        let mut rng = StdRng::seed_from_u64(5);
+        let noise: Vec<f64> = (0..240).map(|_| rng.gen_range(-4.0..4.0)).collect();
+        let at = |t: f64| noise[(t * 2.0) as usize];
+        let (oscillating, swing) = run(|t| 50.0 + at(t));
+        assert!(!oscillating);
+        assert_eq!(swing.reversals, 0);
+        assert!(!run(|t| 20.0 + t / 2.0 + at(t)).0);
+
+        // Noise wider than min_amplitude reverses, but irregularly
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let noise: Vec<f64> = (0..240).map(|_| rng.gen_range(-10.0..10.0)).collect();
+            let (oscillating, swing) = run(|t| 50.0 + noise[(t * 2.0) as usize]);
+            assert!(swing.reversals >= 4, "{:?}", swing);
+            assert!(!oscillating, "seed {}: {:?}", seed, swing);
+        }
+
+        // Steady, and settling after a damped swing
+        assert!(!run(|_| 42.0).0);
+        let (oscillating, _) =
+            run(|t| 50.0 + 30.0 * (-t / 8.0).exp() * (t * std::f64::consts::TAU / 10.0).sin());
+        assert!(!oscillating);
+    }
+
+    #[test]
+    fn the_alarm_starts_and_ends_once() {
+        let mut detector = Detector::new(OscillationConfig::default());
+        let mut changes = Vec::new();
+        for i in 0..480 {
+            let t = i as f64 * 0.5;
+            // Oscillating for the first two minutes, steady after
+            let value = if t < 120.0 {
+                50.0 + 15.0 * (t * std::f64::consts::TAU / 10.0).sin()
+            } else {
+                50.0
+            };
+            if detector.push(t, value) {
+                changes.push((t, detector.is_oscillating()));
+            }
+        }
+        assert_eq!(changes.len(), 2, "{:?}", changes);
+        assert!(changes[0].1 && changes[0].0 < 30.0, "{:?}", changes);
+        assert!(!changes[1].1 && changes[1].0 > 120.0, "{:?}", changes);
+        assert!(detector.oscillation().is_none());
+    }
+}
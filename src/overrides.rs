@@ -0,0 +1,229 @@
+// ============================================================================
+// OVERRIDES - Substituição temporária de entradas
+// ============================================================================
+//
+// The daemon's readings come from whoever sends `set`, in practice the
+// sensors. A value put in by hand, `random` or `override` on the socket or
+// 'r' and typed readings in an attached TUI, would only last until the
+// sensor's next line: the value flickers back and the history fills with
+// entries nobody meant. So a value put in by hand holds its input for a
+// while instead:
+//
+//     [override]             # optional (config.rs)
+//     duration_secs = 30.0   # how long a hold lasts; 0 turns holds off,
+//                            # refusing `random` and `override`
+//
+// While an input is held, `set` readings of it are kept back, the latest
+// one per input, and answered `ok held`. The hold ends when it expires on
+// the daemon's clock, or on `release` (Esc in an attached TUI); the kept
+// reading then becomes the input's as if it had just arrived, and an input
+// no sensor spoke for meanwhile keeps the value it was held at. The state
+// lines name the held inputs and the time left (remote.rs), which an
+// attached TUI shows in its title bar.
+//
+// `Holds` is the bookkeeping shared with failure injection (inject.rs):
+// something per input until a clock time, a newer one on the same input
+// replacing the older.
+
+/// Something holding an input until a clock time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hold<T> {
+    pub input: String,
+    /// Clock time at which it expires
+    pub until: f64,
+    pub value: T,
+}
+
+/// At most one `Hold` per input
+#[derive(Debug, Clone)]
+pub struct Holds<T> {
+    holds: Vec<Hold<T>>,
+}
+
+impl<T> Default for Holds<T> {
+    fn default() -> Self {
+        Holds { holds: Vec::new() }
+    }
+}
+
+impl<T> Holds<T> {
+    /// Hold `input` with `value` until `until`, returning what held it
+    /// before
+    pub fn insert(&mut self, input: &str, until: f64, value: T) -> Option<Hold<T>> {
+        let previous = self.remove(input);
+        self.holds.push(Hold {
+            input: input.to_string(),
+            until,
+            value,
+        });
+        previous
+    }
+
+    pub fn remove(&mut self, input: &str) -> Option<Hold<T>> {
+        let index = self.holds.iter().position(|h| h.input == input)?;
+        Some(self.holds.remove(index))
+    }
+
+    /// Take out the holds that expired by `now`
+    pub fn expire(&mut self, now: f64) -> Vec<Hold<T>> {
+        let (expired, kept) = std::mem::take(&mut self.holds)
+            .into_iter()
+            .partition(|h| h.until <= now);
+        self.holds = kept;
+        expired
+    }
+
+    /// Take out every hold
+    pub fn clear(&mut self) -> Vec<Hold<T>> {
+        std::mem::take(&mut self.holds)
+    }
+
+    pub fn get(&self, input: &str) -> Option<&Hold<T>> {
+        self.holds.iter().find(|h| h.input == input)
+    }
+
+    pub fn get_mut(&mut self, input: &str) -> Option<&mut Hold<T>> {
+        self.holds.iter_mut().find(|h| h.input == input)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Hold<T>> {
+        self.holds.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.holds.is_empty()
+    }
+}
+
+/// `[override]` settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverrideConfig {
+    /// How long a value put in by hand holds its input; zero turns holds
+    /// off
+    pub duration_secs: f64,
+}
+
+impl Default for OverrideConfig {
+    fn default() -> Self {
+        OverrideConfig {
+            duration_secs: 30.0,
+        }
+    }
+}
+
+/// What the state lines say of the holds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Held {
+    pub inputs: Vec<String>,
+    /// Time left of the hold that ends last
+    pub remaining_secs: f64,
+}
+
+/// Inputs held against the sensors, with the reading each has kept back
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    holds: Holds<Option<f64>>,
+}
+
+impl Overrides {
+    /// Hold `input` until `until`; a reading kept back by an earlier hold
+    /// stays kept
+    pub fn hold(&mut self, input: &str, until: f64) {
+        let kept = self.holds.remove(input).and_then(|h| h.value);
+        self.holds.insert(input, until, kept);
+    }
+
+    /// Keep `reading` back if `input` is held, returning whether it was
+    pub fn keep(&mut self, input: &str, reading: f64) -> bool {
+        match self.holds.get_mut(input) {
+            Some(hold) => {
+                hold.value = Some(reading);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// End the holds that expired by `now`: each input, with the reading
+    /// it kept back if any
+    pub fn expire(&mut self, now: f64) -> Vec<(String, Option<f64>)> {
+        Self::ended(self.holds.expire(now))
+    }
+
+    /// End every hold, like `expire`
+    pub fn release(&mut self) -> Vec<(String, Option<f64>)> {
+        Self::ended(self.holds.clear())
+    }
+
+    fn ended(holds: Vec<Hold<Option<f64>>>) -> Vec<(String, Option<f64>)> {
+        holds.into_iter().map(|h| (h.input, h.value)).collect()
+    }
+
+    /// The held inputs at clock time `now`, none when nothing is held
+    pub fn held(&self, now: f64) -> Option<Held> {
+        let until = self.holds.iter().map(|h| h.until).reduce(f64::max)?;
+        Some(Held {
+            inputs: self.holds.iter().map(|h| h.input.clone()).collect(),
+            remaining_secs: (until - now).max(0.0),
+        })
+    }
+
+    /// `held` for the title bar of an attached TUI
+    pub fn banner(held: &Held) -> String {
+        format!(
+            "⏸ HELD {} against the daemon's sensors for {:.0}s (Esc releases)",
+            held.inputs.join(", "),
+            held.remaining_secs.ceil()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holds_replace_one_another_per_input_and_expire_in_time() {
+        let mut holds = Holds::default();
+        assert_eq!(holds.insert("temperature", 5.0, 'a'), None);
+        holds.insert("humidity", 10.0, 'b');
+        let replaced = holds.insert("temperature", 8.0, 'c').unwrap();
+        assert_eq!((replaced.until, replaced.value), (5.0, 'a'));
+        assert!(holds.expire(7.9).is_empty());
+        let expired = holds.expire(8.0);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(
+            (expired[0].input.as_str(), expired[0].value),
+            ("temperature", 'c')
+        );
+        assert_eq!(holds.get("humidity").map(|h| h.value), Some('b'));
+        assert_eq!(holds.clear().len(), 1);
+        assert!(holds.is_empty());
+    }
+
+    #[test]
+    fn readings_of_held_inputs_are_kept_back_until_the_hold_ends() {
+        let mut overrides = Overrides::default();
+        assert!(!overrides.keep("temperature", 20.0));
+        overrides.hold("temperature", 30.0);
+        overrides.hold("humidity", 10.0);
+        assert!(overrides.keep("temperature", 21.0));
+        assert!(overrides.keep("temperature", 22.0));
+        assert_eq!(
+            overrides.held(4.0),
+            Some(Held {
+                inputs: vec!["temperature".to_string(), "humidity".to_string()],
+                remaining_secs: 26.0,
+            })
+        );
+        // Nothing came for the humidity: it keeps its held value
+        assert_eq!(overrides.expire(10.0), [("humidity".to_string(), None)]);
+        // Holding again keeps what was kept back
+        overrides.hold("temperature", 40.0);
+        assert_eq!(
+            overrides.release(),
+            [("temperature".to_string(), Some(22.0))]
+        );
+        assert_eq!(overrides.held(0.0), None);
+    }
+}
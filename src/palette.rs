@@ -0,0 +1,531 @@
+// ============================================================================
+// PALETTES - Paletas de cores
+// ============================================================================
+//
+// Every color the TUI draws with comes from a palette, by the role it plays
+// rather than by its hue: the sets of each input, the rules, the surface's
+// ramp, the output bands, and the good/caution/warning/error of the status
+// line. Three palettes are built in:
+//
+//     classic     the terminal's named colors, as the TUI always had them
+//     okabe_ito   Okabe & Ito's categorical colors, distinct under the
+//                 common color-vision deficiencies, with viridis ramps
+//     tol         Paul Tol's bright scheme with cividis ramps
+//
+// The ramps run dark to light as well as through their hues, so their
+// order reads in grayscale too. Okabe & Ito's black stands aside for a
+// gray, since the TUI draws on a dark background.
+//
+//     [display]              # optional, TUI only (config.rs)
+//     palette = "okabe_ito"  # "classic" (default), "okabe_ito" or "tol"
+//
+// 'O' in the TUI previews them all on the terminal at hand and switches
+// for the session.
+//
+// The TUI had no theme system to extend: its colors were spelled out in
+// each render function. This module is that system. The tutorial's
+// dimming (spotlight.rs) stays a fixed dark gray, laid over whichever
+// palette is drawn.
+//
+// The scientific palettes are given in RGB and quantized to what the
+// terminal reports (crossterm's color count): true color as given, else
+// the nearest entry of the 256-color cube and gray ramp, else a named
+// color. The nearest named color would turn most of these muted tones
+// gray, so each tone names its 16-color fallback beside its RGB value,
+// picked so the colors that must stay apart do. The tests below pin that
+// mapping, so a change to it or to the depth detection shows up as a
+// failing test rather than on screen. Named colors pass through every
+// depth unchanged.
+
+/// The 16 named terminal colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ansi {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    Gray,
+    DarkGray,
+    LightRed,
+    LightGreen,
+    LightYellow,
+    LightBlue,
+    LightMagenta,
+    LightCyan,
+    White,
+}
+
+impl Ansi {
+    pub const ALL: [Ansi; 16] = [
+        Ansi::Black,
+        Ansi::Red,
+        Ansi::Green,
+        Ansi::Yellow,
+        Ansi::Blue,
+        Ansi::Magenta,
+        Ansi::Cyan,
+        Ansi::Gray,
+        Ansi::DarkGray,
+        Ansi::LightRed,
+        Ansi::LightGreen,
+        Ansi::LightYellow,
+        Ansi::LightBlue,
+        Ansi::LightMagenta,
+        Ansi::LightCyan,
+        Ansi::White,
+    ];
+}
+
+/// A color as a palette gives it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tone {
+    Ansi(Ansi),
+    /// Entry of the 256-color table
+    Indexed(u8),
+    /// A true color, and the named color standing in for it on a
+    /// 16-color terminal
+    Rgb(u8, u8, u8, Ansi),
+}
+
+/// Levels of the 256-color cube along each channel
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance((r, g, b): (u8, u8, u8), (s, h, c): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r, s) + d(g, h) + d(b, c)
+}
+
+impl Tone {
+    /// The tone a terminal of `depth` shows for this one
+    pub fn quantize(self, depth: Depth) -> Tone {
+        let Tone::Rgb(r, g, b, fallback) = self else {
+            return self;
+        };
+        let rgb = (r, g, b);
+        match depth {
+            Depth::TrueColor => self,
+            Depth::Ansi256 => {
+                let level = |v: u8| {
+                    (0..CUBE.len())
+                        .min_by_key(|i| (CUBE[*i] as i32 - v as i32).abs())
+                        .unwrap_or(0)
+                };
+                let (i, j, k) = (level(r), level(g), level(b));
+                let cube = (16 + 36 * i + 6 * j + k) as u8;
+                let cube_rgb = (CUBE[i], CUBE[j], CUBE[k]);
+                // The gray ramp: 232 is 8, each step 10 lighter
+                let mean = (r as u32 + g as u32 + b as u32) / 3;
+                let step = (mean.saturating_sub(3) / 10).min(23) as u8;
+                let gray = 8 + 10 * step;
+                if distance(rgb, (gray, gray, gray)) < distance(rgb, cube_rgb) {
+                    Tone::Indexed(232 + step)
+                } else {
+                    Tone::Indexed(cube)
+                }
+            }
+            Depth::Ansi16 => Tone::Ansi(fallback),
+        }
+    }
+}
+
+/// How many colors the terminal shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Depth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl Depth {
+    /// From the count crossterm reports: `u16::MAX` for true color
+    pub fn from_count(count: u16) -> Self {
+        match count {
+            u16::MAX => Depth::TrueColor,
+            256.. => Depth::Ansi256,
+            _ => Depth::Ansi16,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Depth::TrueColor => "true color",
+            Depth::Ansi256 => "256 colors",
+            Depth::Ansi16 => "16 colors",
+        }
+    }
+}
+
+/// The built-in palettes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaletteName {
+    #[default]
+    Classic,
+    OkabeIto,
+    Tol,
+}
+
+impl PaletteName {
+    pub const ALL: [PaletteName; 3] = [
+        PaletteName::Classic,
+        PaletteName::OkabeIto,
+        PaletteName::Tol,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PaletteName::Classic => "classic",
+            PaletteName::OkabeIto => "okabe_ito",
+            PaletteName::Tol => "tol",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    /// One line on what the palette is, for the preview
+    pub fn describe(&self) -> &'static str {
+        match self {
+            PaletteName::Classic => "the terminal's named colors",
+            PaletteName::OkabeIto => "Okabe-Ito sets and rules, viridis ramps",
+            PaletteName::Tol => "Paul Tol's bright sets and rules, cividis ramps",
+        }
+    }
+}
+
+/// Colors by the role they play, as tones or, once resolved for a
+/// terminal, as whatever it draws with
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette<C = Tone> {
+    /// Curves of sets without a color of their own, in turn
+    pub sets: Vec<C>,
+    /// Rules 1, 2, ... in turn (rulecolor.rs)
+    pub rules: Vec<C>,
+    /// Cold, mild and hot temperature
+    pub temperature: [C; 3],
+    /// Low, medium and high humidity
+    pub humidity: [C; 3],
+    /// Surface heatmap, low output to high
+    pub ramp: [C; 5],
+    /// OFF, LOW, MEDIUM and HIGH output bands
+    pub bands: [C; 4],
+    pub good: C,
+    pub caution: C,
+    pub warning: C,
+    pub error: C,
+    /// Title bar, rule groups and the first input
+    pub accent: C,
+    /// The second input
+    pub accent_alt: C,
+    /// Markers of the selected rule at full strength, and injected entries
+    pub highlight: C,
+    /// The selected rule's marker at its current strength
+    pub highlight_soft: C,
+    /// A cascade's measurement
+    pub measured: C,
+    pub rising: C,
+    pub falling: C,
+    pub text: C,
+    pub muted: C,
+    pub faint: C,
+    /// Text drawn on a colored background
+    pub ink: C,
+}
+
+impl Palette {
+    pub fn of(name: PaletteName) -> Self {
+        match name {
+            PaletteName::Classic => classic(),
+            PaletteName::OkabeIto => okabe_ito(),
+            PaletteName::Tol => tol(),
+        }
+    }
+
+    /// Every tone as `f` makes it
+    pub fn map<D: Copy>(&self, f: impl Fn(Tone) -> D) -> Palette<D> {
+        Palette {
+            sets: self.sets.iter().map(|t| f(*t)).collect(),
+            rules: self.rules.iter().map(|t| f(*t)).collect(),
+            temperature: self.temperature.map(&f),
+            humidity: self.humidity.map(&f),
+            ramp: self.ramp.map(&f),
+            bands: self.bands.map(&f),
+            good: f(self.good),
+            caution: f(self.caution),
+            warning: f(self.warning),
+            error: f(self.error),
+            accent: f(self.accent),
+            accent_alt: f(self.accent_alt),
+            highlight: f(self.highlight),
+            highlight_soft: f(self.highlight_soft),
+            measured: f(self.measured),
+            rising: f(self.rising),
+            falling: f(self.falling),
+            text: f(self.text),
+            muted: f(self.muted),
+            faint: f(self.faint),
+            ink: f(self.ink),
+        }
+    }
+
+    /// The palette as a terminal of `depth` shows it
+    pub fn quantized(&self, depth: Depth) -> Self {
+        self.map(|tone| tone.quantize(depth))
+    }
+}
+
+fn classic() -> Palette {
+    use Ansi::*;
+    let a = Tone::Ansi;
+    Palette {
+        sets: [Cyan, Yellow, Red, LightBlue, Green, Magenta]
+            .map(a)
+            .to_vec(),
+        rules: [
+            Cyan,
+            LightBlue,
+            Blue,
+            LightGreen,
+            Green,
+            Yellow,
+            LightYellow,
+            Magenta,
+            LightRed,
+            Red,
+            LightMagenta,
+            LightCyan,
+        ]
+        .map(a)
+        .to_vec(),
+        temperature: [Cyan, Yellow, Red].map(a),
+        humidity: [LightYellow, LightBlue, Blue].map(a),
+        ramp: [Blue, Cyan, Green, Yellow, Red].map(a),
+        bands: [Gray, Green, Yellow, Red].map(a),
+        good: a(Green),
+        caution: a(Yellow),
+        warning: a(LightRed),
+        error: a(Red),
+        accent: a(Cyan),
+        accent_alt: a(LightBlue),
+        highlight: a(Magenta),
+        highlight_soft: a(LightMagenta),
+        measured: a(LightGreen),
+        rising: a(LightRed),
+        falling: a(LightBlue),
+        text: a(White),
+        muted: a(Gray),
+        faint: a(DarkGray),
+        ink: a(Black),
+    }
+}
+
+/// `0xRRGGBB` as a tone, with its 16-color fallback
+const fn rgb(hex: u32, fallback: Ansi) -> Tone {
+    Tone::Rgb((hex >> 16) as u8, (hex >> 8) as u8, hex as u8, fallback)
+}
+
+/// The neutral roles, the same in every palette
+fn neutral(palette: Palette) -> Palette {
+    Palette {
+        text: Tone::Ansi(Ansi::White),
+        muted: Tone::Ansi(Ansi::Gray),
+        faint: Tone::Ansi(Ansi::DarkGray),
+        ink: Tone::Ansi(Ansi::Black),
+        ..palette
+    }
+}
+
+fn okabe_ito() -> Palette {
+    use Ansi::*;
+    let orange = rgb(0xE69F00, Yellow);
+    let sky_blue = rgb(0x56B4E9, LightCyan);
+    let bluish_green = rgb(0x009E73, Green);
+    let yellow = rgb(0xF0E442, LightYellow);
+    let blue = rgb(0x0072B2, Blue);
+    let vermillion = rgb(0xD55E00, Red);
+    let reddish_purple = rgb(0xCC79A7, Magenta);
+    let gray = rgb(0x999999, Gray);
+    // viridis at 0, 1/4, 1/2, 3/4 and 1
+    let viridis = [
+        rgb(0x440154, Magenta),
+        rgb(0x3B528B, Blue),
+        rgb(0x21918C, Cyan),
+        rgb(0x5EC962, Green),
+        rgb(0xFDE725, LightYellow),
+    ];
+    neutral(Palette {
+        sets: vec![
+            sky_blue,
+            orange,
+            vermillion,
+            blue,
+            bluish_green,
+            reddish_purple,
+        ],
+        rules: vec![
+            orange,
+            sky_blue,
+            bluish_green,
+            yellow,
+            blue,
+            vermillion,
+            reddish_purple,
+            gray,
+        ],
+        temperature: [sky_blue, yellow, vermillion],
+        humidity: [viridis[4], viridis[2], viridis[1]],
+        ramp: viridis,
+        bands: [gray, bluish_green, orange, vermillion],
+        good: bluish_green,
+        caution: yellow,
+        warning: orange,
+        error: vermillion,
+        accent: sky_blue,
+        accent_alt: blue,
+        highlight: reddish_purple,
+        highlight_soft: rgb(0xE1A6C8, LightMagenta),
+        measured: bluish_green,
+        rising: orange,
+        falling: sky_blue,
+        ..classic()
+    })
+}
+
+fn tol() -> Palette {
+    use Ansi::*;
+    let blue = rgb(0x4477AA, Blue);
+    let cyan = rgb(0x66CCEE, LightCyan);
+    let green = rgb(0x228833, Green);
+    let yellow = rgb(0xCCBB44, Yellow);
+    let red = rgb(0xEE6677, LightRed);
+    let purple = rgb(0xAA3377, Magenta);
+    let gray = rgb(0xBBBBBB, Gray);
+    // cividis at 0, 1/4, 1/2, 3/4 and 1
+    let cividis = [
+        rgb(0x00204D, Blue),
+        rgb(0x414D6B, DarkGray),
+        rgb(0x7C7B78, Gray),
+        rgb(0xBCAF6F, Yellow),
+        rgb(0xFFEA46, LightYellow),
+    ];
+    neutral(Palette {
+        sets: vec![cyan, yellow, red, blue, green, purple],
+        rules: vec![blue, red, green, yellow, cyan, purple, gray],
+        temperature: [cyan, yellow, red],
+        humidity: [cividis[4], cividis[3], cividis[2]],
+        ramp: cividis,
+        bands: [gray, green, yellow, red],
+        good: green,
+        caution: yellow,
+        warning: red,
+        error: red,
+        accent: cyan,
+        accent_alt: blue,
+        highlight: purple,
+        highlight_soft: rgb(0xFFAABB, LightMagenta),
+        measured: green,
+        rising: red,
+        falling: cyan,
+        ..classic()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every tone of `palette`, in field order
+    fn tones(palette: &Palette) -> Vec<Tone> {
+        let tones = std::cell::RefCell::new(Vec::new());
+        palette.map(|tone| tones.borrow_mut().push(tone));
+        tones.into_inner()
+    }
+
+    #[test]
+    fn quantization_keeps_named_colors_and_finds_the_nearest_entry() {
+        for depth in [Depth::TrueColor, Depth::Ansi256, Depth::Ansi16] {
+            assert_eq!(
+                Palette::of(PaletteName::Classic).quantized(depth),
+                classic()
+            );
+        }
+        assert_eq!(
+            rgb(0xE69F00, Ansi::Yellow).quantize(Depth::TrueColor),
+            rgb(0xE69F00, Ansi::Yellow)
+        );
+        // Cube levels 4, 3, 0
+        assert_eq!(
+            rgb(0xE69F00, Ansi::Yellow).quantize(Depth::Ansi256),
+            Tone::Indexed(178)
+        );
+        // Grays go to the gray ramp rather than the cube
+        assert_eq!(
+            rgb(0x999999, Ansi::Gray).quantize(Depth::Ansi256),
+            Tone::Indexed(247)
+        );
+        assert_eq!(
+            rgb(0x000000, Ansi::Black).quantize(Depth::Ansi256),
+            Tone::Indexed(16)
+        );
+        assert_eq!(Depth::from_count(u16::MAX), Depth::TrueColor);
+        assert_eq!(Depth::from_count(256), Depth::Ansi256);
+        assert_eq!(Depth::from_count(8), Depth::Ansi16);
+        for name in PaletteName::ALL {
+            assert_eq!(PaletteName::parse(name.name()), Some(name));
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn the_16_color_fallback_is_pinned() {
+        use Ansi::*;
+        let ansi = |name: PaletteName| -> Vec<Ansi> {
+            tones(&Palette::of(name).quantized(Depth::Ansi16))
+                .into_iter()
+                .map(|tone| match tone {
+                    Tone::Ansi(ansi) => ansi,
+                    other => panic!("{:?} left unquantized", other),
+                })
+                .collect()
+        };
+        assert_eq!(
+            ansi(PaletteName::OkabeIto),
+            [
+                // sets
+                LightCyan, Yellow, Red, Blue, Green, Magenta,
+                // rules
+                Yellow, LightCyan, Green, LightYellow, Blue, Red, Magenta, Gray,
+                // temperature, humidity
+                LightCyan, LightYellow, Red, LightYellow, Cyan, Blue,
+                // ramp, bands
+                Magenta, Blue, Cyan, Green, LightYellow, Gray, Green, Yellow, Red,
+                // good to falling
+                Green, LightYellow, Yellow, Red, LightCyan, Blue, Magenta, LightMagenta,
+                Green, Yellow, LightCyan,
+                // text, muted, faint, ink
+                White, Gray, DarkGray, Black,
+            ]
+        );
+        assert_eq!(
+            ansi(PaletteName::Tol),
+            [
+                // sets
+                LightCyan, Yellow, LightRed, Blue, Green, Magenta,
+                // rules
+                Blue, LightRed, Green, Yellow, LightCyan, Magenta, Gray,
+                // temperature, humidity
+                LightCyan, Yellow, LightRed, LightYellow, Yellow, Gray,
+                // ramp, bands
+                Blue, DarkGray, Gray, Yellow, LightYellow, Gray, Green, Yellow, LightRed,
+                // good to falling
+                Green, Yellow, LightRed, LightRed, LightCyan, Blue, Magenta, LightMagenta,
+                Green, LightRed, LightCyan,
+                // text, muted, faint, ink
+                White, Gray, DarkGray, Black,
+            ]
+        );
+    }
+}
@@ -0,0 +1,489 @@
+// ============================================================================
+// OUTPUT PIPELINE - Estágios de saída
+// ============================================================================
+//
+// The crisp controller output goes through a fixed sequence of stages before
+// it reaches the fan:
+//
+//   1. rate limiter  - bounds how fast the value may change (units/second)
+//   2. hysteresis    - maps the value to an OFF/LOW/MEDIUM/HIGH band, only
+//                      leaving a band once the value is `hysteresis / 2` past
+//                      the threshold
+//   3. dwell         - once the fan turns off it stays off for at least
+//                      `min_off_secs`, once on it stays on for `min_on_secs`;
+//                      transitions requested earlier are deferred and the
+//                      previous output is held meanwhile
+//
+// The rate limiter tracks the raw value on its own, so it never winds up
+// while the dwell stage is holding the output.
+//
+// The band drives the fan and its dwell times. The status label shown to the
+// user either follows the band (`StatusMode::Thresholds`) or names the
+// dominant output set (`StatusMode::DominantSet`), since the fixed band
+// thresholds need not line up with the output variable's sets.
+
+use std::time::Instant;
+
+/// Time source for the time-dependent stages, in seconds
+pub trait Clock {
+    fn now(&self) -> f64;
+}
+
+/// Wall-clock time measured from the moment the clock was created
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+}
+
+/// Per-output stage settings; zero disables a stage
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PipelineConfig {
+    pub rate_limit: f64,
+    pub hysteresis: f64,
+    pub min_on_secs: f64,
+    pub min_off_secs: f64,
+    pub status: StatusMode,
+}
+
+/// Where the status label comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatusMode {
+    /// The OFF/LOW/MEDIUM/HIGH band of the pipeline output
+    #[default]
+    Thresholds,
+    /// The output set with the highest aggregated rule strength
+    DominantSet,
+}
+
+impl StatusMode {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "thresholds" => Some(StatusMode::Thresholds),
+            "dominant_set" => Some(StatusMode::DominantSet),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StatusMode::Thresholds => "thresholds",
+            StatusMode::DominantSet => "dominant_set",
+        }
+    }
+}
+
+/// Fan speed status band shown in the UI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FanBand {
+    Off,
+    Low,
+    Medium,
+    High,
+}
+
+impl FanBand {
+    /// Lower bound of LOW, MEDIUM and HIGH
+    const THRESHOLDS: [f64; 3] = [15.0, 40.0, 65.0];
+    const ALL: [FanBand; 4] = [FanBand::Off, FanBand::Low, FanBand::Medium, FanBand::High];
+
+    pub fn from_speed(speed: f64) -> Self {
+        let index = FanBand::THRESHOLDS.iter().filter(|t| speed >= **t).count();
+        FanBand::ALL[index]
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FanBand::Off => "OFF",
+            FanBand::Low => "LOW",
+            FanBand::Medium => "MEDIUM",
+            FanBand::High => "HIGH",
+        }
+    }
+
+    pub fn is_on(self) -> bool {
+        self != FanBand::Off
+    }
+
+    /// LOW, MEDIUM and HIGH with the speed each begins at
+    pub fn thresholds() -> impl Iterator<Item = (FanBand, f64)> {
+        FanBand::ALL[1..].iter().copied().zip(FanBand::THRESHOLDS)
+    }
+}
+
+/// A transition the dwell stage is holding back
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pending {
+    pub band: FanBand,
+    pub remaining_secs: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PipelineOutput {
+    pub value: f64,
+    pub band: FanBand,
+    pub pending: Option<Pending>,
+}
+
+pub struct OutputPipeline {
+    config: PipelineConfig,
+    last_time: Option<f64>,
+    limited: f64,
+    band: FanBand,
+    state: FanBand,
+    state_since: f64,
+    output: f64,
+}
+
+impl OutputPipeline {
+    pub fn new(config: PipelineConfig) -> Self {
+        OutputPipeline {
+            config,
+            last_time: None,
+            limited: 0.0,
+            band: FanBand::Off,
+            state: FanBand::Off,
+            // The initial state counts as having been held forever
+            state_since: f64::NEG_INFINITY,
+            output: 0.0,
+        }
+    }
+
+    /// Append what the stages carry from one value to the next to `saved`
+    /// (a batch checkpoint, checkpoint.rs)
+    #[cfg(feature = "batch")]
+    pub fn save(&self, saved: &mut Vec<f64>) {
+        saved.extend([
+            self.last_time.unwrap_or(f64::NAN),
+            self.limited,
+            self.band as usize as f64,
+            self.state as usize as f64,
+            self.state_since,
+            self.output,
+        ]);
+    }
+
+    /// Take up what `save` wrote, none if `saved` runs out or is not
+    /// a pipeline's
+    #[cfg(feature = "batch")]
+    pub fn restore(&mut self, saved: &mut dyn Iterator<Item = f64>) -> Option<()> {
+        let band = |index: f64| FanBand::ALL.get(index as usize).copied();
+        self.last_time = Some(saved.next()?).filter(|t| !t.is_nan());
+        self.limited = saved.next()?;
+        self.band = band(saved.next()?)?;
+        self.state = band(saved.next()?)?;
+        self.state_since = saved.next()?;
+        self.output = saved.next()?;
+        Some(())
+    }
+
+    /// The value after the rate limiter, as of the last `process`
+    pub fn limited(&self) -> f64 {
+        self.limited
+    }
+
+    /// The band after hysteresis, before the dwell stage
+    pub fn band(&self) -> FanBand {
+        self.band
+    }
+
+    /// Hold every stage at `value` from `now`, for an interlock forcing it
+    /// (interlock.rs): the rate limiter goes on from `value` and the dwell
+    /// stage counts from when its band began
+    pub fn hold(&mut self, value: f64, now: f64) -> PipelineOutput {
+        let band = FanBand::from_speed(value);
+        if band.is_on() != self.state.is_on() {
+            self.state_since = now;
+        }
+        self.last_time = Some(now);
+        self.limited = value;
+        self.band = band;
+        self.state = band;
+        self.output = value;
+        PipelineOutput {
+            value,
+            band,
+            pending: None,
+        }
+    }
+
+    /// Run `raw` through every stage at time `now` (seconds, from a `Clock`)
+    pub fn process(&mut self, raw: f64, now: f64) -> PipelineOutput {
+        self.limit(raw, now);
+        self.map_band();
+        self.dwell(now)
+    }
+
+    /// Stage 1, the rate limiter: move toward `raw` by at most what the
+    /// time since the last call allows
+    pub fn limit(&mut self, raw: f64, now: f64) {
+        match self.last_time {
+            Some(last) if self.config.rate_limit > 0.0 => {
+                let max_step = self.config.rate_limit * (now - last).max(0.0);
+                self.limited += (raw - self.limited).clamp(-max_step, max_step);
+            }
+            Some(_) => self.limited = raw,
+            None => {
+                self.limited = raw;
+                self.band = FanBand::from_speed(raw);
+                self.state = self.band;
+                self.output = raw;
+            }
+        }
+        self.last_time = Some(now);
+    }
+
+    /// Stage 2, the hysteresis: the band of the limited value, which
+    /// changes only once it is half the hysteresis past a threshold
+    pub fn map_band(&mut self) {
+        let margin = self.config.hysteresis / 2.0;
+        while self.band < FanBand::High
+            && self.limited >= FanBand::THRESHOLDS[self.band as usize] + margin
+        {
+            self.band = FanBand::ALL[self.band as usize + 1];
+        }
+        while self.band > FanBand::Off
+            && self.limited < FanBand::THRESHOLDS[self.band as usize - 1] - margin
+        {
+            self.band = FanBand::ALL[self.band as usize - 1];
+        }
+    }
+
+    /// Stage 3, the minimum on/off dwell: hold the last output until the
+    /// fan has been on or off long enough to switch
+    pub fn dwell(&mut self, now: f64) -> PipelineOutput {
+        let mut pending = None;
+        if self.band.is_on() != self.state.is_on() {
+            let required = if self.state.is_on() {
+                self.config.min_on_secs
+            } else {
+                self.config.min_off_secs
+            };
+            // A clock that stepped back restarts the dwell from where it
+            // is now, rather than holding until it catches up
+            self.state_since = self.state_since.min(now);
+            let elapsed = now - self.state_since;
+            if elapsed < required {
+                pending = Some(Pending {
+                    band: self.band,
+                    remaining_secs: required - elapsed,
+                });
+            } else {
+                self.state = self.band;
+                self.state_since = now;
+            }
+        } else {
+            self.state = self.band;
+        }
+
+        if pending.is_none() {
+            self.output = self.limited;
+        }
+
+        PipelineOutput {
+            value: self.output,
+            band: self.state,
+            pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Time that moves only when it is set
+    #[derive(Debug, Clone, Default)]
+    struct ManualClock {
+        now: f64,
+    }
+
+    impl ManualClock {
+        fn set(&mut self, now: f64) {
+            self.now = now;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> f64 {
+            self.now
+        }
+    }
+
+    /// A pipeline and the clock its steps read
+    struct Rig {
+        pipeline: OutputPipeline,
+        clock: ManualClock,
+    }
+
+    impl Rig {
+        fn new(config: PipelineConfig) -> Self {
+            Rig {
+                pipeline: OutputPipeline::new(config),
+                clock: ManualClock::default(),
+            }
+        }
+
+        fn at(&mut self, now: f64, raw: f64) -> Trace {
+            self.clock.set(now);
+            let output = self.pipeline.process(raw, self.clock.now());
+            Trace {
+                limited: self.pipeline.limited,
+                nominal_band: FanBand::from_speed(self.pipeline.limited),
+                band: self.pipeline.band,
+                pending: output.pending,
+                output,
+            }
+        }
+    }
+
+    /// What each stage made of one value
+    struct Trace {
+        /// After the rate limiter
+        limited: f64,
+        /// The band of the limited value, without hysteresis
+        nominal_band: FanBand,
+        /// After hysteresis
+        band: FanBand,
+        /// What the dwell stage holds back
+        pending: Option<Pending>,
+        output: PipelineOutput,
+    }
+
+    fn config(rate_limit: f64, hysteresis: f64, min_on: f64, min_off: f64) -> PipelineConfig {
+        PipelineConfig {
+            rate_limit,
+            hysteresis,
+            min_on_secs: min_on,
+            min_off_secs: min_off,
+            ..PipelineConfig::default()
+        }
+    }
+
+    #[test]
+    fn hysteresis_reads_the_rate_limited_value() {
+        let mut rig = Rig::new(config(10.0, 0.0, 0.0, 0.0));
+        rig.at(0.0, 0.0);
+        // 80 asks for HIGH, but the limiter only lets 10 through
+        let trace = rig.at(1.0, 80.0);
+        assert_eq!(trace.limited, 10.0);
+        assert_eq!(
+            (trace.nominal_band, trace.band),
+            (FanBand::Off, FanBand::Off)
+        );
+        let trace = rig.at(2.0, 80.0);
+        assert_eq!(trace.limited, 20.0);
+        assert_eq!(trace.output.band, FanBand::Low);
+    }
+
+    #[test]
+    fn hysteresis_keeps_dwell_from_seeing_a_jittering_value() {
+        let mut rig = Rig::new(config(0.0, 4.0, 0.0, 30.0));
+        rig.at(0.0, 20.0);
+        // Within 2 of the 15 threshold: LOW holds, nothing to defer
+        for (i, raw) in [13.5, 16.0, 13.1, 15.5].into_iter().enumerate() {
+            let trace = rig.at(1.0 + i as f64, raw);
+            assert_eq!(trace.band, FanBand::Low);
+            assert_eq!(trace.pending, None);
+            assert_eq!(trace.output.value, raw);
+        }
+        let trace = rig.at(10.0, 12.9);
+        assert_eq!(trace.output.band, FanBand::Off);
+        // Now off, so turning on again waits for the 30 s dwell
+        let trace = rig.at(11.0, 50.0);
+        assert_eq!(trace.band, FanBand::Medium);
+        assert_eq!(
+            trace.pending,
+            Some(Pending {
+                band: FanBand::Medium,
+                remaining_secs: 29.0
+            })
+        );
+        assert_eq!(trace.output.value, 12.9);
+    }
+
+    #[test]
+    fn dwell_defers_a_transition_until_it_expires() {
+        let mut rig = Rig::new(config(0.0, 0.0, 20.0, 0.0));
+        rig.at(0.0, 0.0);
+        rig.at(5.0, 50.0);
+        let trace = rig.at(15.0, 5.0);
+        assert_eq!(trace.output.band, FanBand::Medium);
+        assert_eq!(trace.output.value, 50.0);
+        assert_eq!(trace.pending.unwrap().remaining_secs, 10.0);
+        // Moves within the on bands are not held
+        let trace = rig.at(16.0, 70.0);
+        assert_eq!(
+            (trace.output.band, trace.output.value),
+            (FanBand::High, 70.0)
+        );
+        rig.at(20.0, 5.0);
+        let trace = rig.at(25.0, 5.0);
+        assert_eq!(trace.pending, None);
+        assert_eq!((trace.output.band, trace.output.value), (FanBand::Off, 5.0));
+    }
+
+    #[test]
+    fn the_rate_limiter_keeps_tracking_while_dwell_holds() {
+        let mut rig = Rig::new(config(5.0, 0.0, 0.0, 10.0));
+        rig.at(0.0, 20.0);
+        rig.at(1.0, 0.0);
+        let trace = rig.at(2.0, 0.0);
+        assert_eq!((trace.output.band, trace.limited), (FanBand::Off, 10.0));
+        for now in 3..=8 {
+            let trace = rig.at(now as f64, 100.0);
+            assert_eq!(trace.limited, 5.0 * now as f64);
+            // Held off until 12 s, whatever the limiter has reached
+            assert_eq!(trace.output.value, 10.0);
+            assert!(trace.pending.is_some());
+        }
+        for now in 9..=11 {
+            rig.at(now as f64, 0.0);
+        }
+        let trace = rig.at(12.0, 100.0);
+        // No windup: the ramp went down with the raw value while held
+        assert_eq!(trace.limited, 30.0);
+        assert_eq!(trace.pending, None);
+        assert_eq!(
+            (trace.output.band, trace.output.value),
+            (FanBand::Low, 30.0)
+        );
+    }
+
+    #[test]
+    fn a_clock_stepping_back_passes_no_time() {
+        let mut rig = Rig::new(config(5.0, 0.0, 0.0, 0.0));
+        rig.at(100.0, 0.0);
+        rig.at(110.0, 100.0);
+        // Back by a minute: the ramp does not move, then goes on from there
+        assert_eq!(rig.at(50.0, 100.0).limited, 50.0);
+        assert_eq!(rig.at(51.0, 100.0).limited, 55.0);
+
+        let mut rig = Rig::new(config(0.0, 0.0, 20.0, 0.0));
+        rig.at(100.0, 0.0);
+        rig.at(110.0, 50.0);
+        let trace = rig.at(50.0, 0.0);
+        assert_eq!(trace.pending.unwrap().remaining_secs, 20.0);
+        // The dwell counts from where the clock now is
+        assert_eq!(rig.at(69.0, 0.0).pending.unwrap().remaining_secs, 1.0);
+        assert_eq!(rig.at(70.0, 0.0).pending, None);
+    }
+}
@@ -0,0 +1,29 @@
+// ============================================================================
+// PRELUDE - Importações comuns da biblioteca
+// ============================================================================
+//
+// What a crate embedding the controller needs, in one import:
+//
+//     use fuzzy_logic::prelude::*;
+//
+// Building a system: FuzzyVariable (`new`, `with_unit`, `with_set`),
+// MembershipFunction (or MembershipFn, for a closure through
+// `MembershipFunction::custom`), FuzzyRule (`parse`) and FuzzySystem (`new`, `demo`),
+// or a config through `config::load_system` and `config::parse_system`.
+// Computing with it: FuzzyController for one inference at a time, or
+// Stepper for a session with the output pipeline, stepped by the caller.
+//
+// The enums that will grow (MembershipFunction, DefuzzMethod, Aggregation,
+// Implication) are `#[non_exhaustive]`: a match on one needs a `_` arm.
+// Errors are messages, `Result<_, String>` everywhere, so there is no
+// error type to import. There are no pluggable norms either: AND is the
+// minimum and the aggregation one of `Aggregation`. The two traits, Clock
+// and termguard's Screen, are for callers to implement (tests drive their
+// own time and terminal), so neither is sealed.
+
+pub use crate::pipeline::{Clock, FanBand, PipelineConfig, PipelineOutput, SystemClock};
+pub use crate::stepper::{Step, Stepper};
+pub use crate::{
+    Aggregation, DefuzzMethod, Evaluation, FuzzyController, FuzzyRule, FuzzySystem, FuzzyVariable,
+    Implication, MembershipFn, MembershipFunction, OutOfRange,
+};
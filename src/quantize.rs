@@ -0,0 +1,672 @@
+// ============================================================================
+// QUANTIZE - Tabela de degraus para termostatos simples
+// ============================================================================
+//
+// Some deployments end on hardware that only stores a small step table:
+// a few temperature bands by a few humidity bands, one fixed speed per
+// cell. `quantize --size 4x3` fits such a table to the control surface.
+// Each band is stored by its lower edge, the first at the input's minimum,
+// so a 4x3 table holds 4 temperature and 3 humidity thresholds.
+//
+// The surface is sampled on a `--grid` x `--grid` grid (surface.rs) and
+// thresholds sit halfway between grid points. Given the thresholds, the
+// best value of a cell is known: the midrange of its points for the worst
+// deviation, their median for the mean one. Coordinate descent moves one
+// threshold at a time to wherever it lowers the objective most (the other
+// one breaking ties), starting from equal spacing, until no move helps.
+// The result is never worse than equal spacing. The table is written as
+// CSV, JSON or a C header with the deviation it was fitted to: the worst,
+// where it happens, and the mean. `--check TABLE.csv` evaluates a table
+// written before against the current config.
+//
+// The TUI surface view draws the fitted table over the heatmap ('S').
+
+use crate::config;
+use crate::json;
+use crate::surface::{self, Surface};
+use crate::{FuzzyController, FuzzySystem};
+
+const USAGE: &str = "Usage: fuzzy_logic quantize --size COLUMNSxROWS [--config FILE] \
+                     [--objective worst|mean] [--grid N] [--format csv|json|c] \
+                     [--out FILE]\n       fuzzy_logic quantize --check TABLE.csv \
+                     [--config FILE] [--grid N]";
+
+/// What the fit minimizes
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Objective {
+    /// Largest deviation anywhere on the grid
+    #[default]
+    Worst,
+    /// Mean absolute deviation over the grid
+    Mean,
+}
+
+impl Objective {
+    pub fn name(self) -> &'static str {
+        match self {
+            Objective::Worst => "worst",
+            Objective::Mean => "mean",
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "worst" => Ok(Objective::Worst),
+            "mean" => Ok(Objective::Mean),
+            _ => Err(format!("unknown objective '{}' (worst, mean)", text)),
+        }
+    }
+}
+
+/// Table size written as COLUMNSxROWS, e.g. "4x3"
+pub fn parse_size(text: &str) -> Result<(usize, usize), String> {
+    let size = text
+        .split_once(['x', 'X'])
+        .map(|(columns, rows)| (columns.trim().parse(), rows.trim().parse()));
+    match size {
+        Some((Ok(columns), Ok(rows))) if columns > 0 && rows > 0 => Ok((columns, rows)),
+        _ => Err(format!(
+            "table size '{}': expected COLUMNSxROWS, e.g. 4x3",
+            text
+        )),
+    }
+}
+
+/// Step table over the first two inputs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    /// Lower edge of each band of the first input
+    pub columns: Vec<f64>,
+    /// Lower edge of each band of the second input
+    pub rows: Vec<f64>,
+    /// `values[row][column]`
+    pub values: Vec<Vec<f64>>,
+}
+
+/// How far a table is from the surface it approximates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fit {
+    /// Largest |table - surface| on the grid
+    pub worst: f64,
+    /// Grid point where `worst` happens, the first one in row order
+    pub at: (f64, f64),
+    /// Mean |table - surface| on the grid
+    pub mean: f64,
+}
+
+impl Fit {
+    pub fn summary(&self) -> String {
+        format!(
+            "worst {:.2} at ({:.1}, {:.1}), mean {:.2}",
+            self.worst, self.at.0, self.at.1, self.mean
+        )
+    }
+}
+
+/// Band of `value` among the lower `edges`: the last edge at or below it
+fn band(edges: &[f64], value: f64) -> usize {
+    edges.iter().rposition(|edge| value >= *edge).unwrap_or(0)
+}
+
+impl Table {
+    /// Value the table holds at `(x, y)`
+    pub fn lookup(&self, x: f64, y: f64) -> f64 {
+        self.values[band(&self.rows, y)][band(&self.columns, x)]
+    }
+
+    /// The table's value at every point of `surface`'s grid
+    pub fn sampled(&self, surface: &Surface) -> Surface {
+        let values = (surface.ys.iter())
+            .map(|y| surface.xs.iter().map(|x| self.lookup(*x, *y)).collect())
+            .collect();
+        Surface {
+            xs: surface.xs.clone(),
+            ys: surface.ys.clone(),
+            values,
+        }
+    }
+
+    /// The table as CSV: the first line holds the column edges, each
+    /// further line a row's edge and its values. Numbers are written in
+    /// full so that reading the file back gives the same table.
+    pub fn to_csv(&self, system: &FuzzySystem) -> String {
+        let (x, y) = axis_names(system);
+        let mut out = format!("{} \\ {}", y, x);
+        for edge in &self.columns {
+            out.push_str(&format!(",{}", edge));
+        }
+        out.push('\n');
+        for (edge, values) in self.rows.iter().zip(&self.values) {
+            out.push_str(&edge.to_string());
+            for value in values {
+                out.push_str(&format!(",{}", value));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// A table written by `to_csv`
+    pub fn from_csv(text: &str) -> Result<Table, String> {
+        let number = |line: usize, field: &str| {
+            field
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| format!("line {}: '{}' is not a number", line, field.trim()))
+        };
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty());
+        let Some((_, header)) = lines.next() else {
+            return Err("the table is empty".to_string());
+        };
+        let columns = (header.split(',').skip(1))
+            .map(|field| number(1, field))
+            .collect::<Result<Vec<f64>, String>>()?;
+        let (mut rows, mut values) = (Vec::new(), Vec::new());
+        for (i, line) in lines {
+            let fields = line
+                .split(',')
+                .map(|field| number(i + 1, field))
+                .collect::<Result<Vec<f64>, String>>()?;
+            if fields.len() != columns.len() + 1 {
+                return Err(format!(
+                    "line {}: {} values for {} columns",
+                    i + 1,
+                    fields.len() - 1,
+                    columns.len()
+                ));
+            }
+            rows.push(fields[0]);
+            values.push(fields[1..].to_vec());
+        }
+        if columns.is_empty() || rows.is_empty() {
+            return Err("the table has no cells".to_string());
+        }
+        for (edges, what) in [(&columns, "column"), (&rows, "row")] {
+            if edges.windows(2).any(|pair| pair[0] >= pair[1]) {
+                return Err(format!("the {} edges must increase", what));
+            }
+        }
+        Ok(Table {
+            columns,
+            rows,
+            values,
+        })
+    }
+
+    /// The table and its fit as a JSON object, each axis with its input's
+    /// name and unit ("input": null for a missing second input)
+    pub fn to_json(&self, system: &FuzzySystem, fit: &Fit) -> String {
+        let list = |values: &[f64]| {
+            let items: Vec<String> = values.iter().map(|v| json::number(*v)).collect();
+            format!("[{}]", items.join(", "))
+        };
+        let axis =
+            |input: Option<usize>, edges: &[f64]| match input.and_then(|i| system.inputs.get(i)) {
+                Some(var) => format!(
+                    "{{\"input\": {}, \"unit\": {}, \"edges\": {}}}",
+                    json::string(&var.name),
+                    json::string(&var.unit.name),
+                    list(edges)
+                ),
+                None => format!("{{\"input\": null, \"edges\": {}}}", list(edges)),
+            };
+        let rows: Vec<String> = (self.values.iter())
+            .map(|row| format!("    {}", list(row)))
+            .collect();
+        let output = system.output();
+        format!(
+            "{{\n  \"output\": {},\n  \"unit\": {},\n  \"columns\": {},\n  \
+             \"rows\": {},\n  \"values\": [\n{}\n  ],\n  \
+             \"error\": {{\"worst\": {}, \"at\": [{}, {}], \"mean\": {}}}\n}}\n",
+            json::string(&output.name),
+            json::string(&output.unit.name),
+            axis(Some(0), &self.columns),
+            axis(Some(1), &self.rows),
+            rows.join(",\n"),
+            json::number(fit.worst),
+            json::number(fit.at.0),
+            json::number(fit.at.1),
+            json::number(fit.mean)
+        )
+    }
+
+    /// The table and its fit as a C header of `float` arrays named after
+    /// the output
+    pub fn to_c_header(&self, system: &FuzzySystem, fit: &Fit) -> String {
+        let (x, y) = axis_names(system);
+        let output = system.output();
+        let prefix = identifier(&output.name);
+        let guard = format!("{}_TABLE_H", prefix.to_uppercase());
+        let list = |values: &[f64]| {
+            let items: Vec<String> = values.iter().map(|v| c_float(*v)).collect();
+            format!("{{{}}}", items.join(", "))
+        };
+        let mut out = format!(
+            "/* Step table of {} over {} and {},\n \
+             * written by fuzzy_logic quantize.\n \
+             * Deviation from the fuzzy surface: {}. */\n\
+             #ifndef {}\n#define {}\n\n",
+            output.unit.column(&output.name),
+            x,
+            y,
+            fit.summary(),
+            guard,
+            guard
+        );
+        let (columns, rows) = (self.columns.len(), self.rows.len());
+        out.push_str(&format!(
+            "#define {}_COLUMNS {}\n#define {}_ROWS {}\n\n",
+            prefix.to_uppercase(),
+            columns,
+            prefix.to_uppercase(),
+            rows
+        ));
+        out.push_str(&format!(
+            "/* Lower edge of each band of {} */\n\
+             static const float {}_column_edges[{}] = {};\n",
+            x,
+            prefix,
+            columns,
+            list(&self.columns)
+        ));
+        out.push_str(&format!(
+            "/* Lower edge of each band of {} */\n\
+             static const float {}_row_edges[{}] = {};\n\n",
+            y,
+            prefix,
+            rows,
+            list(&self.rows)
+        ));
+        out.push_str(&format!(
+            "/* {}_table[row][column] */\nstatic const float {}_table[{}][{}] = {{\n",
+            prefix, prefix, rows, columns
+        ));
+        for row in &self.values {
+            out.push_str(&format!("    {},\n", list(row)));
+        }
+        out.push_str(&format!("}};\n\n#endif /* {} */\n", guard));
+        out
+    }
+}
+
+/// Column headers of the first two inputs with their units, "_" for a
+/// missing second input as in the surface CSV
+fn axis_names(system: &FuzzySystem) -> (String, String) {
+    let x = &system.inputs[0];
+    let y = match system.inputs.get(1) {
+        Some(var) => var.unit.column(&var.name),
+        None => "_".to_string(),
+    };
+    (x.unit.column(&x.name), y)
+}
+
+/// `name` with everything but letters, digits and '_' turned into '_'
+fn identifier(name: &str) -> String {
+    let id: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    match id.starts_with(|c: char| c.is_ascii_digit()) {
+        true => format!("_{}", id),
+        false => id,
+    }
+}
+
+/// `value` as a C float literal, which needs a decimal point
+fn c_float(value: f64) -> String {
+    let text = value.to_string();
+    match text.contains('.') {
+        true => format!("{}f", text),
+        false => format!("{}.0f", text),
+    }
+}
+
+/// The output over a `grid` x `grid` grid of the first two inputs
+pub fn sample(system: &FuzzySystem, grid: usize) -> Surface {
+    let controllers = [FuzzyController::from_system(system.clone())];
+    surface::compute_surfaces(&controllers, grid, grid).remove(0)
+}
+
+/// Deviation of `table` from `surface` over its grid points; points the
+/// controller failed to compute are left out
+pub fn evaluate(table: &Table, surface: &Surface) -> Fit {
+    let mut fit = Fit {
+        worst: 0.0,
+        at: (surface.xs[0], surface.ys[0]),
+        mean: 0.0,
+    };
+    let mut count = 0;
+    for (row, y) in surface.ys.iter().enumerate() {
+        for (column, x) in surface.xs.iter().enumerate() {
+            let value = surface.values[row][column];
+            if !value.is_finite() {
+                continue;
+            }
+            let deviation = (table.lookup(*x, *y) - value).abs();
+            if deviation > fit.worst {
+                fit.worst = deviation;
+                fit.at = (*x, *y);
+            }
+            fit.mean += deviation;
+            count += 1;
+        }
+    }
+    fit.mean /= count.max(1) as f64;
+    fit
+}
+
+/// Index of the first grid point of each band along one axis
+type Cuts = Vec<usize>;
+
+/// `bands` cuts spaced as evenly as `points` grid points allow
+fn equal_cuts(points: usize, bands: usize) -> Cuts {
+    (0..bands)
+        .map(|i| (i as f64 * points as f64 / bands as f64).round() as usize)
+        .collect()
+}
+
+/// Band edges for `cuts`: the axis minimum, then halfway between the last
+/// point of a band and the first of the next
+fn edges(axis: &[f64], cuts: &Cuts) -> Vec<f64> {
+    cuts.iter()
+        .map(|cut| match cut {
+            0 => axis[0],
+            _ => (axis[cut - 1] + axis[*cut]) / 2.0,
+        })
+        .collect()
+}
+
+/// Table with bands at `cuts` and the value of each cell that suits
+/// `objective` best, with the fit it reaches
+fn fitted(surface: &Surface, cuts: (&Cuts, &Cuts), objective: Objective) -> (Table, Fit) {
+    let (column_cuts, row_cuts) = cuts;
+    let mut cells = vec![vec![Vec::new(); column_cuts.len()]; row_cuts.len()];
+    for (row, values) in surface.values.iter().enumerate() {
+        let cell_row = row_cuts.iter().rposition(|cut| row >= *cut).unwrap_or(0);
+        for (column, value) in values.iter().enumerate() {
+            if value.is_finite() {
+                let cell = column_cuts.iter().rposition(|cut| column >= *cut);
+                cells[cell_row][cell.unwrap_or(0)].push(*value);
+            }
+        }
+    }
+    let values = cells
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|mut points| {
+                    points.sort_by(f64::total_cmp);
+                    match (points.first(), points.last()) {
+                        // A cell with no computed point holds 0
+                        (None, _) | (_, None) => 0.0,
+                        (Some(low), Some(high)) => match objective {
+                            Objective::Worst => (low + high) / 2.0,
+                            Objective::Mean => points[points.len() / 2],
+                        },
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let table = Table {
+        columns: edges(&surface.xs, column_cuts),
+        rows: edges(&surface.ys, row_cuts),
+        values,
+    };
+    let fit = evaluate(&table, surface);
+    (table, fit)
+}
+
+/// The objective's measure first, the other one breaking ties
+fn score(fit: &Fit, objective: Objective) -> (f64, f64) {
+    match objective {
+        Objective::Worst => (fit.worst, fit.mean),
+        Objective::Mean => (fit.mean, fit.worst),
+    }
+}
+
+fn check_size(surface: &Surface, size: (usize, usize)) -> Result<(), String> {
+    let (columns, rows) = size;
+    if columns == 0 || rows == 0 {
+        return Err("a table needs at least one column and one row".to_string());
+    }
+    for (bands, points, what) in [
+        (columns, surface.xs.len(), "columns"),
+        (rows, surface.ys.len(), "rows"),
+    ] {
+        if bands > points {
+            return Err(format!(
+                "{} {} but the grid has {} points across them",
+                bands, what, points
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Table of `size` (columns, rows) with equally spaced bands
+pub fn equal_spacing(
+    surface: &Surface,
+    size: (usize, usize),
+    objective: Objective,
+) -> Result<(Table, Fit), String> {
+    check_size(surface, size)?;
+    let cuts = (
+        equal_cuts(surface.xs.len(), size.0),
+        equal_cuts(surface.ys.len(), size.1),
+    );
+    Ok(fitted(surface, (&cuts.0, &cuts.1), objective))
+}
+
+/// Table of `size` (columns, rows) with its thresholds placed by
+/// coordinate descent from equal spacing
+pub fn optimize(
+    surface: &Surface,
+    size: (usize, usize),
+    objective: Objective,
+) -> Result<(Table, Fit), String> {
+    check_size(surface, size)?;
+    let mut cuts = [
+        equal_cuts(surface.xs.len(), size.0),
+        equal_cuts(surface.ys.len(), size.1),
+    ];
+    let points = [surface.xs.len(), surface.ys.len()];
+    let mut best = fitted(surface, (&cuts[0], &cuts[1]), objective);
+    loop {
+        let mut moved = false;
+        for axis in 0..2 {
+            for i in 1..cuts[axis].len() {
+                let low = cuts[axis][i - 1] + 1;
+                let high = cuts[axis].get(i + 1).copied().unwrap_or(points[axis]) - 1;
+                for cut in low..=high {
+                    let mut tried = cuts.clone();
+                    tried[axis][i] = cut;
+                    let candidate = fitted(surface, (&tried[0], &tried[1]), objective);
+                    if score(&candidate.1, objective) < score(&best.1, objective) {
+                        best = candidate;
+                        cuts = tried;
+                        moved = true;
+                    }
+                }
+            }
+        }
+        if !moved {
+            return Ok(best);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    Json,
+    C,
+}
+
+pub fn run(args: &[String]) -> i32 {
+    let mut system = FuzzySystem::demo();
+    let mut size = None;
+    let mut objective = Objective::Worst;
+    let mut grid = 41;
+    let mut format = Format::Csv;
+    let mut out = None;
+    let mut check = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let result = match (arg.as_str(), iter.next()) {
+            ("--config", Some(path)) => config::load_system(path).map(|s| system = s),
+            ("--size", Some(text)) => parse_size(text).map(|s| size = Some(s)),
+            ("--objective", Some(name)) => Objective::parse(name).map(|o| objective = o),
+            ("--grid", Some(n)) => match n.parse::<usize>() {
+                Ok(n) if n >= 2 => {
+                    grid = n;
+                    Ok(())
+                }
+                _ => Err("--grid expects an integer of at least 2".to_string()),
+            },
+            ("--format", Some(name)) => match name.as_str() {
+                "csv" => Ok(Format::Csv),
+                "json" => Ok(Format::Json),
+                "c" => Ok(Format::C),
+                _ => Err(format!("unknown format '{}' (csv, json, c)", name)),
+            }
+            .map(|f| format = f),
+            ("--out", Some(path)) => {
+                out = Some(path.clone());
+                Ok(())
+            }
+            ("--check", Some(path)) => {
+                check = Some(path.clone());
+                Ok(())
+            }
+            _ => Err(USAGE.to_string()),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            return 2;
+        }
+    }
+
+    let surface = sample(&system, grid);
+    if let Some(path) = check {
+        let table = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| Table::from_csv(&text));
+        return match table {
+            Ok(table) => {
+                let fit = evaluate(&table, &surface);
+                println!(
+                    "{}x{} table from {}: {}",
+                    table.columns.len(),
+                    table.rows.len(),
+                    path,
+                    fit.summary()
+                );
+                0
+            }
+            Err(e) => {
+                eprintln!("error: {}: {}", path, e);
+                1
+            }
+        };
+    }
+    let Some(size) = size else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+    let (naive, (table, fit)) = match equal_spacing(&surface, size, objective)
+        .and_then(|naive| optimize(&surface, size, objective).map(|fitted| (naive.1, fitted)))
+    {
+        Ok(fitted) => fitted,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 2;
+        }
+    };
+    let text = match format {
+        Format::Csv => table.to_csv(&system),
+        Format::Json => table.to_json(&system, &fit),
+        Format::C => table.to_c_header(&system, &fit),
+    };
+    let report = format!(
+        "{}x{} table, {} deviation minimized: {} (equal spacing: {})",
+        size.0,
+        size.1,
+        objective.name(),
+        fit.summary(),
+        naive.summary()
+    );
+    match out {
+        Some(path) => match std::fs::write(&path, text) {
+            Ok(()) => {
+                println!("{}, written to {}", report, path);
+                0
+            }
+            Err(e) => {
+                eprintln!("error: {}: {}", path, e);
+                1
+            }
+        },
+        None => {
+            print!("{}", text);
+            eprintln!("{}", report);
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn the_fitted_table_beats_equal_spacing_on_the_demo() {
+        let surface = sample(&fixtures::fan(), 41);
+        for objective in [Objective::Worst, Objective::Mean] {
+            let (_, naive) = equal_spacing(&surface, (4, 3), objective).unwrap();
+            let (table, fit) = optimize(&surface, (4, 3), objective).unwrap();
+            let (naive, fitted) = (score(&naive, objective).0, score(&fit, objective).0);
+            assert!(fitted < naive, "{:?}: {} vs {}", objective, fitted, naive);
+            assert_eq!((table.columns.len(), table.rows.len()), (4, 3));
+            assert_eq!((table.columns[0], table.rows[0]), (0.0, 0.0));
+        }
+        assert!(optimize(&surface, (42, 1), Objective::Worst).is_err());
+        assert_eq!(parse_size("4x3"), Ok((4, 3)));
+        assert!(parse_size("4x0").is_err());
+    }
+
+    #[test]
+    fn an_exported_table_reproduces_its_reported_error() {
+        let system = fixtures::fan();
+        let surface = sample(&system, 41);
+        let (table, fit) = optimize(&surface, (4, 3), Objective::Worst).unwrap();
+        let read = Table::from_csv(&table.to_csv(&system)).unwrap();
+        assert_eq!(read, table);
+        assert_eq!(evaluate(&read, &surface), fit);
+        // The worst point is where the table and the surface differ most
+        let (x, y) = fit.at;
+        let row = surface.ys.iter().position(|v| *v == y).unwrap();
+        let column = surface.xs.iter().position(|v| *v == x).unwrap();
+        let deviation = (table.lookup(x, y) - surface.values[row][column]).abs();
+        assert_eq!(deviation, fit.worst);
+
+        let json = table.to_json(&system, &fit);
+        assert!(json.contains("\"output\": \"fan_speed\""), "{}", json);
+        assert!(json.contains(&format!("\"worst\": {}", fit.worst)));
+        // JSON has no token for a failed computation
+        let mut failed = table.clone();
+        failed.values[0][0] = f64::NAN;
+        let json = failed.to_json(&system, &fit);
+        assert!(json.contains("    [null, "), "{}", json);
+        assert!(!json.contains("NaN"), "{}", json);
+        let header = table.to_c_header(&system, &fit);
+        assert!(header.contains("static const float fan_speed_table[3][4] = {"));
+        assert!(header.contains("fan_speed_column_edges[4] = {0.0f, "));
+        assert!(Table::from_csv("x \\ y,0,5\n0,1\n").is_err());
+    }
+}
@@ -0,0 +1,277 @@
+// ============================================================================
+// REFINE - Refinamento progressivo
+// ============================================================================
+//
+// Something slow to compute finely but quick to compute coarsely, like
+// the control surface, is shown at once and sharpened in the background.
+// A `Refiner` is given a key (what to compute: the system and the view)
+// and a ladder of resolutions, coarse to fine:
+//
+//     20 ──> 50 ──> 200
+//     now    worker thread, one rung after the other
+//
+// The first rung is computed on the spot, so there is always something to
+// show; the worker computes the others in order and `poll` swaps each in
+// as it arrives, never a coarser one over a finer one. Asking for another
+// key cancels the worker through its token (the computation checks it and
+// gives up, returning none) and drops whatever it was about to deliver, so
+// a stale result never shows. Asking for the same key again changes
+// nothing, which is what lets the caller ask on every frame.
+//
+// A key that differs little from the last one can be reached by patching
+// the finest result instead (`request_patched`): the caller's `update`
+// turns the old key's result into the new one's, keeping its rung, and no
+// worker starts. That only happens once the refinement is done and the
+// ladder is the same; otherwise, or when `update` declines, it is a
+// `request` like any other. The surface view patches the cells a moved
+// set parameter can reach (surface.rs).
+//
+// The refiner knows nothing of what it computes or how it is drawn; the
+// TUI's surface view (tui.rs) is its user.
+
+use crate::shutdown::Token;
+use std::sync::mpsc;
+
+/// The worker refining the current key
+struct Worker<T> {
+    results: mpsc::Receiver<(usize, T)>,
+    token: Token,
+}
+
+/// The finest result so far for a key, refined in the background
+pub struct Refiner<K, T> {
+    key: Option<K>,
+    ladder: Vec<usize>,
+    /// Finest result so far, with its rung of the ladder
+    current: Option<(usize, T)>,
+    worker: Option<Worker<T>>,
+}
+
+impl<K, T> Default for Refiner<K, T> {
+    fn default() -> Self {
+        Refiner {
+            key: None,
+            ladder: Vec::new(),
+            current: None,
+            worker: None,
+        }
+    }
+}
+
+impl<K, T> Refiner<K, T>
+where
+    K: PartialEq + Clone + Send + 'static,
+    T: Send + 'static,
+{
+    /// Show `key` with the resolutions of `ladder`, coarse to fine.
+    /// `compute(key, resolution, token)` gives the result at one rung, or
+    /// none once the token is cancelled. Nothing happens when `key` is the
+    /// one already shown
+    pub fn request<F>(&mut self, key: K, ladder: &[usize], compute: F)
+    where
+        F: Fn(&K, usize, &Token) -> Option<T> + Clone + Send + 'static,
+    {
+        if self.key.as_ref() == Some(&key) {
+            return;
+        }
+        self.clear();
+        let Some(&first) = ladder.first() else {
+            return;
+        };
+        // Never cancelled: the first rung is always shown
+        self.current = compute(&key, first, &Token::default()).map(|result| (0, result));
+        self.key = Some(key.clone());
+        self.ladder = ladder.to_vec();
+        if ladder.len() == 1 {
+            return;
+        }
+
+        let (sender, results) = mpsc::channel();
+        let token = Token::default();
+        let cancelled = token.clone();
+        let ladder = self.ladder.clone();
+        std::thread::spawn(move || {
+            for (rung, &resolution) in ladder.iter().enumerate().skip(1) {
+                if cancelled.is_cancelled() {
+                    return;
+                }
+                let Some(result) = compute(&key, resolution, &cancelled) else {
+                    return;
+                };
+                // The refiner moved on to another key
+                if sender.send((rung, result)).is_err() {
+                    return;
+                }
+            }
+        });
+        self.worker = Some(Worker { results, token });
+    }
+
+    /// `request`, reaching `key` by `update(old key, new key, result)`
+    /// when the finest result is shown and `update` gives one
+    pub fn request_patched<F, U>(&mut self, key: K, ladder: &[usize], compute: F, update: U)
+    where
+        F: Fn(&K, usize, &Token) -> Option<T> + Clone + Send + 'static,
+        U: FnOnce(&K, &K, &T) -> Option<T>,
+    {
+        if self.key.as_ref() == Some(&key) {
+            return;
+        }
+        let patched = match (&self.key, &self.current) {
+            (Some(old), Some((rung, shown))) if self.worker.is_none() && self.ladder == ladder => {
+                update(old, &key, shown).map(|result| (*rung, result))
+            }
+            _ => None,
+        };
+        match patched {
+            Some(current) => {
+                self.current = Some(current);
+                self.key = Some(key);
+            }
+            None => self.request(key, ladder, compute),
+        }
+    }
+
+    /// Swap in what the worker finished; whether the result changed
+    pub fn poll(&mut self) -> bool {
+        let Some(worker) = &self.worker else {
+            return false;
+        };
+        let mut changed = false;
+        loop {
+            match worker.results.try_recv() {
+                Ok((rung, result)) => {
+                    if self.current.as_ref().is_none_or(|(shown, _)| rung > *shown) {
+                        self.current = Some((rung, result));
+                        changed = true;
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.worker = None;
+                    break;
+                }
+            }
+        }
+        changed
+    }
+
+    /// Cancel the worker and forget the key and its results
+    pub fn clear(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            worker.token.cancel();
+        }
+        self.key = None;
+        self.current = None;
+    }
+
+    /// The finest result so far
+    pub fn current(&self) -> Option<&T> {
+        self.current.as_ref().map(|(_, result)| result)
+    }
+
+    /// Resolution of the result shown, and whether a finer one is coming
+    pub fn level(&self) -> Option<(usize, bool)> {
+        let (rung, _) = self.current.as_ref()?;
+        Some((self.ladder[*rung], self.worker.is_some()))
+    }
+}
+
+impl<K, T> Drop for Refiner<K, T> {
+    fn drop(&mut self) {
+        if let Some(worker) = &self.worker {
+            worker.token.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Waits until the refiner has swapped in its finest result
+    fn settle<K, T>(refiner: &mut Refiner<K, T>)
+    where
+        K: PartialEq + Clone + Send + 'static,
+        T: Send + 'static,
+    {
+        while refiner.level().is_some_and(|(_, refining)| refining) {
+            refiner.poll();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn results_arrive_coarse_to_fine_and_a_new_key_cancels_the_old_one() {
+        let computed = Arc::new(Mutex::new(Vec::new()));
+        // Rungs of key 1 past the first wait for the test to let them go
+        let gate = Arc::new(Mutex::new(()));
+        let compute = {
+            let (computed, gate) = (computed.clone(), gate.clone());
+            move |key: &u32, resolution: usize, token: &Token| {
+                if *key == 1 && resolution > 2 {
+                    drop(gate.lock().unwrap());
+                }
+                if token.is_cancelled() {
+                    return None;
+                }
+                computed.lock().unwrap().push((*key, resolution));
+                Some(format!("{} at {}", key, resolution))
+            }
+        };
+
+        let mut refiner = Refiner::default();
+        let held = gate.lock().unwrap();
+        refiner.request(1, &[2, 5, 20], compute.clone());
+        // The coarse rung is there at once
+        assert_eq!(refiner.current().map(String::as_str), Some("1 at 2"));
+        assert_eq!(refiner.level(), Some((2, true)));
+        assert!(!refiner.poll());
+        // The same key again changes nothing
+        refiner.request(1, &[2, 5, 20], compute.clone());
+        assert_eq!(computed.lock().unwrap().len(), 1);
+
+        // Another key while key 1 is held: its rungs never show
+        refiner.request(2, &[3, 30], compute.clone());
+        drop(held);
+        assert_eq!(refiner.current().map(String::as_str), Some("2 at 3"));
+        settle(&mut refiner);
+        assert_eq!(refiner.current().map(String::as_str), Some("2 at 30"));
+        assert_eq!(refiner.level(), Some((30, false)));
+        assert_eq!(*computed.lock().unwrap(), [(1, 2), (2, 3), (2, 30)]);
+
+        refiner.clear();
+        assert!(refiner.current().is_none());
+        refiner.request(2, &[3, 30], compute);
+        assert_eq!(refiner.level(), Some((3, true)));
+    }
+
+    #[test]
+    fn a_finished_result_is_patched_instead_of_computed_again() {
+        let compute =
+            |key: &u32, resolution: usize, _: &Token| Some(format!("{} at {}", key, resolution));
+        let patch = |old: &u32, new: &u32, shown: &String| {
+            (new - old == 1).then(|| format!("{}, patched to {}", shown, new))
+        };
+        let mut refiner = Refiner::default();
+        // Still refining: computed from the first rung again
+        refiner.request(1, &[2, 20], compute);
+        refiner.request_patched(2, &[2, 20], compute, patch);
+        assert_eq!(refiner.current().map(String::as_str), Some("2 at 2"));
+
+        settle(&mut refiner);
+        refiner.request_patched(3, &[2, 20], compute, patch);
+        assert_eq!(
+            refiner.current().map(String::as_str),
+            Some("2 at 20, patched to 3")
+        );
+        assert_eq!(refiner.level(), Some((20, false)));
+        // Declined by the update, or with another ladder
+        refiner.request_patched(5, &[2, 20], compute, patch);
+        assert_eq!(refiner.current().map(String::as_str), Some("5 at 2"));
+        settle(&mut refiner);
+        refiner.request_patched(6, &[2, 40], compute, patch);
+        assert_eq!(refiner.current().map(String::as_str), Some("6 at 2"));
+    }
+}
@@ -0,0 +1,3553 @@
+// ============================================================================
+// REMOTE CONTROL - Daemon e attach via socket Unix
+// ============================================================================
+//
+// `daemon --socket PATH` runs the controller and output pipeline headless
+// and serves a line-based protocol on a Unix socket:
+//
+//     set <input> <value>                  -> ok | error <reason>
+//                                             (a dew point for `dewpoint`
+//                                             inputs)
+//     set <input>.<source> <value>         -> ok | error <reason>
+//                                             (a sensor of a fused input,
+//                                             fusion.rs)
+//     random                               -> ok | error <reason>
+//                                             (drawn by the configured
+//                                             generator, generator.rs,
+//                                             and held, overrides.rs)
+//     override <input> <value> [<secs>]    -> ok | error <reason>
+//                                             (held against `set`)
+//     release                              -> ok
+//                                             (ends every hold)
+//     reset_latches                        -> ok | error <reason>
+//     group <name> on|off                  -> ok | error <reason>
+//     group <name> weight <0..1>           -> ok | error <reason>
+//     aggregation <kind> <temperature>     -> ok | error <reason>
+//                                             (max, softmax or bounded_sum)
+//     caps                                 -> caps <active|off> <profile> ...
+//     caps <profile>|off                   -> ok | error <reason>
+//                                             (output set caps, config.rs)
+//     method <output> <method>             -> ok | error <reason>
+//                                             (centroid, plateau_centroid or
+//                                             weighted_average)
+//     weights                              -> weights <w1> ... <wN>
+//     weights <w1> ... <wN>                -> ok | error <reason>
+//     weights revert                       -> ok | error <reason>
+//     evaluate <point> ...                 -> outputs <o1> ... | error <reason>
+//     weights <w1> ... <wN> evaluate <point> ...
+//                                          -> outputs <o1> ... | error <reason>
+//     inject <injection command>           -> ok | error <reason>
+//                                             (see inject.rs)
+//     interlock <name> on|off              -> ok | error <reason>
+//                                             (interlock.rs)
+//     interlocks                           -> interlocks [<name>=on|off ...]
+//     param <var> <set> a|b|c|d <value>    -> ok [warn <warning>; ...]
+//                                             | error <reason>
+//                                             (linked parameters follow,
+//                                             see links.rs; holes and
+//                                             look-alike sets warn or
+//                                             refuse, see guard.rs)
+//     snapshot                             -> state ...
+//     stats                                -> stats executed <n> suppressed <n>
+//                                             support_warnings <n>
+//                                             oscillating <true|false>
+//                                             amplitude <a>
+//                                             degraded <n>
+//                                             interlock <name|none>
+//                                             [exec_coalesced <n>
+//                                             exec_dropped <n>
+//                                             exec_latency <secs>]
+//                                             [shadow_mean_abs_diff <d>
+//                                             shadow_band_differs <share>
+//                                             shadow_energy_diff <hours>]
+//                                             (oscillation.rs; cycles
+//                                             computed below the full
+//                                             rung, budget.rs; the
+//                                             interlock forcing the output;
+//                                             the exec program's publish
+//                                             queue, exec.rs; the
+//                                             comparison with
+//                                             `--shadow`, shadow.rs)
+//     alarms                               -> alarms ["<name>"="<condition>" ...]
+//                                             (those raised now, alarm.rs,
+//                                             "exec" while an
+//                                             [output.exec] program is
+//                                             failing, exec.rs,
+//                                             "exec_stalled" while it
+//                                             delivered nothing for
+//                                             stall_secs, and
+//                                             "<input>.<source>" while a
+//                                             fused source is excluded)
+//     objective                            -> objective <score> mean <m>
+//                                             best <b> worst <w> count <n>
+//                                             | objective off
+//                                             (objective.rs)
+//     metadata                             -> metadata [<key>="<value>" ...]
+//                                             (the config's [metadata])
+//     units                                -> units [<var>="<unit>" ...]
+//                                             (storage units, units.rs)
+//     subscribe                            -> ok, then a state line every
+//                                             STATE_INTERVAL until the
+//                                             client disconnects
+//
+// where a state line is
+//
+//     state <fan_speed> <output> <band> [<pending band> <secs>]
+//           [held <secs> <input>,...] [window <input>:<count>:<spread>,...]
+//           [sources <input>.<source>:<value>:<status>,...]
+//           <input>=<value> ...
+//
+// with one `<input>=<value>` per input of the loaded system, so any config
+// that validates can be served, `held` while inputs set by hand hold
+// against the readings, and `window` once sample windows have closed,
+// with the sample count and spread of each input's last one. `sources`
+// lists every source of the fused inputs with its latest reading (NaN
+// before the first) and status. Lines longer than MAX_LINE bytes end the
+// client's connection.
+//
+// A client sending `wire 1` as its first line is answered with the same
+// line and trades binary frames from then on (wire.rs). They stand for
+// commands above: `set inputs` is a `set` of each input by index, stopping
+// at the first refused (NaN included, as `set` refuses it), `weights` the command of the same name, and
+// `compute` and `get state` both answer the state, `fan_speed` the only
+// output, as computing is up to the triggers and the daemon's clock.
+// `terminate` ends the connection; there is no binary `subscribe`.
+//
+// `attach --socket PATH` runs the TUI against a daemon: it mirrors the
+// subscribed state and forwards input changes as `override` commands (or
+// `set` with holds turned off) and group edits as `group` commands. Several clients may attach at once; the last
+// `set` wins. Disconnecting only ends that client's connection. Group
+// changes made by other clients apply to the daemon only; the rule table
+// of an attached TUI keeps the groups of its own config.
+//
+// `set` readings go through the recompute triggers (trigger.rs); the
+// other commands always recompute. An input with a `window_secs` collects
+// its `set` readings as samples instead, and its window's aggregate goes
+// through the triggers when the window closes (window.rs). An input with
+// sources takes `set` readings of its sources only and fuses them first,
+// the fused value going on as the input's reading would (fusion.rs); the
+// log lines carry each source's latest raw reading, and a source going
+// silent takes the others' reading up on the next tick. A `set` of an input held by `random` or
+// `override` is kept back and answered `ok held` until the hold expires or
+// `release` ends it (overrides.rs).
+//
+// Values in commands are read like typed numbers (numbers.rs), so "23,5"
+// works; the points of `evaluate` separate inputs by commas and take a
+// decimal point only.
+//
+// `weights` serves an external optimizer: it replaces the weight of every
+// rule at once (one weight from 0 to 1 per rule, in config order, or
+// nothing changes) and keeps the weights from before the first swap until
+// `weights revert` restores them. `evaluate` computes the outputs for a
+// batch of points, each the raw readings of every input in declaration
+// order separated by commas (`30,60`), without touching the daemon's
+// inputs, pipeline or statistics; every point answers with its outputs
+// separated by commas. Both in one line make an optimizer iteration one
+// round trip, applied under the same lock so no other client sees half of
+// it.
+//
+// With an `[output.cascade]` the PID (cascade.rs) runs on every tick
+// against the last `set` reading of its measured input, and its output is
+// what the pipeline gets; `fan_speed` in the state lines is the setpoint.
+//
+// With `--state FILE` the daemon keeps the rule usage statistics there,
+// saving them every STATE_SAVE_INTERVAL and on `reset_latches`.
+//
+// A `--config` that fails to load starts the daemon in safe mode
+// (safemode.rs): it serves `--fallback FILE` or the demo, publishes
+// `--safe-output VALUE` as its output when given, and loads the config
+// again every RETRY_INTERVAL until it can serve it.
+//
+// With `--audit FILE` every output it publishes is recorded there, with
+// each pipeline stage's part in it and its cause (audit.rs).
+//
+// With `--shadow FILE` a second config evaluates every computation's
+// inputs next to the served one without driving anything; its output goes
+// into the log lines and the audit records, and `stats` compares the two
+// (shadow.rs). A shadow that stops fitting the config loaded out of safe
+// mode is stopped.
+//
+// With an `[output.exec]` every output it publishes, the safe output on
+// shutdown included, is handed to the config's program, which runs on a
+// worker thread of its own (exec.rs). Its failures go into the log, each
+// finished run into an audit record, and a run that used up its retries
+// raises the `exec` alarm until one succeeds. Publications made while the
+// program runs are coalesced, the newest one waiting; the safe output and
+// an interlock's forced output are priority publications, never coalesced
+// away by a newer ordinary one. Nothing delivered for `stall_secs` raises
+// the `exec_stalled` alarm.
+//
+// SIGINT or SIGTERM shut the daemon down in order (shutdown.rs): commands
+// are refused with `error shutting down`, the socket file is removed and
+// the ticker stopped; with `--safe-output VALUE` the output is then set to
+// VALUE and left for the subscribers to see; last the state is saved.
+
+use crate::adapters::{self, InputKind};
+use crate::alarm::{Alarms, Sample};
+use crate::align::{Aligned, Aligner};
+use crate::audit::{self, AuditLog, Cause, Record};
+use crate::bookmarks::Bookmarks;
+use crate::budget::Budget;
+use crate::cascade::Cascade;
+use crate::config;
+use crate::exec::{Exec, Outcome, Publication};
+use crate::fusion::{self, Fusion, Reported};
+use crate::generator::Sampler;
+use crate::guard;
+use crate::inject::Injector;
+use crate::interlock::Interlocks;
+use crate::journal::Edit;
+use crate::layout::PanelLayout;
+use crate::links;
+use crate::numbers::{self, Locale};
+use crate::objective::Objective;
+use crate::oscillation::Detector;
+use crate::overrides::{Held, Overrides};
+use crate::pipeline::{Clock, FanBand, OutputPipeline, Pending, PipelineOutput, SystemClock};
+use crate::safemode::{self, Retry};
+use crate::shadow::Shadow;
+use crate::shutdown::{self, Coordinator, Report, Stage};
+use crate::state::RuleUsageStats;
+use crate::timestamp;
+use crate::trigger::Trigger;
+use crate::window::{Aggregator, Sampled};
+use crate::wire::{self, Message};
+use crate::{Aggregation, DefuzzMethod, FuzzyController, FuzzySystem};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const STATE_INTERVAL: Duration = Duration::from_millis(200);
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+/// Longest command line a client may send
+const MAX_LINE: usize = 4096;
+const STATE_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+/// How often the listener looks for a shutdown between connections
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+
+/// Everything an attached TUI needs to mirror the daemon
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// Reading of each input, in the order the system declares them
+    pub inputs: Vec<(String, f64)>,
+    pub fan_speed: f64,
+    pub output: PipelineOutput,
+    /// Inputs set by hand that the readings do not change for now
+    pub held: Option<Held>,
+    /// Last closed sample window of each windowed input
+    pub windows: Vec<Sampled>,
+    /// Every source of each fused input
+    pub sources: Vec<Reported>,
+    /// How long before the computation its readings were taken, with
+    /// input latencies (align.rs)
+    pub lag: Option<f64>,
+}
+
+fn band_from_label(label: &str) -> Option<FanBand> {
+    [FanBand::Off, FanBand::Low, FanBand::Medium, FanBand::High]
+        .into_iter()
+        .find(|band| band.label() == label)
+}
+
+impl Snapshot {
+    pub fn input(&self, name: &str) -> Option<f64> {
+        self.inputs.iter().find(|(n, _)| n == name).map(|(_, v)| *v)
+    }
+
+    fn to_line(&self) -> String {
+        let mut line = format!(
+            "state {} {} {}",
+            self.fan_speed,
+            self.output.value,
+            self.output.band.label()
+        );
+        if let Some(pending) = self.output.pending {
+            line.push_str(&format!(
+                " {} {}",
+                pending.band.label(),
+                pending.remaining_secs
+            ));
+        }
+        if let Some(held) = &self.held {
+            line.push_str(&format!(
+                " held {} {}",
+                held.remaining_secs,
+                held.inputs.join(",")
+            ));
+        }
+        if !self.windows.is_empty() {
+            let windows: Vec<String> = self
+                .windows
+                .iter()
+                .map(|w| format!("{}:{}:{}", w.input, w.count, w.spread))
+                .collect();
+            line.push_str(&format!(" window {}", windows.join(",")));
+        }
+        if !self.sources.is_empty() {
+            let sources: Vec<String> = self
+                .sources
+                .iter()
+                .map(|s| format!("{}:{}:{}", s.route(), s.value, s.status.label()))
+                .collect();
+            line.push_str(&format!(" sources {}", sources.join(",")));
+        }
+        if let Some(lag) = self.lag {
+            line.push_str(&format!(" lag {}", lag));
+        }
+        for (name, value) in &self.inputs {
+            line.push_str(&format!(" {}={}", name, value));
+        }
+        line
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let (readings, mut fields): (Vec<&str>, Vec<&str>) =
+            line.split_whitespace().partition(|f| f.contains('='));
+        let lag = match fields.len().checked_sub(2) {
+            Some(at) if fields[at] == "lag" => {
+                let lag = fields[at + 1].parse().ok()?;
+                fields.truncate(at);
+                Some(lag)
+            }
+            _ => None,
+        };
+        let sources = match fields.iter().position(|f| *f == "sources") {
+            Some(at) if fields.len() == at + 2 => {
+                let sources = fields[at + 1]
+                    .split(',')
+                    .map(|source| {
+                        let mut parts = source.split(':');
+                        let (input, source) = parts.next()?.rsplit_once('.')?;
+                        let reported = Reported {
+                            input: input.to_string(),
+                            source: source.to_string(),
+                            value: parts.next()?.parse().ok()?,
+                            status: fusion::Status::parse(parts.next()?)?,
+                        };
+                        parts.next().is_none().then_some(reported)
+                    })
+                    .collect::<Option<_>>()?;
+                fields.truncate(at);
+                sources
+            }
+            Some(_) => return None,
+            None => Vec::new(),
+        };
+        let windows = match fields.iter().position(|f| *f == "window") {
+            Some(at) if fields.len() == at + 2 => {
+                let windows = fields[at + 1]
+                    .split(',')
+                    .map(|window| {
+                        let mut parts = window.split(':');
+                        let sampled = Sampled {
+                            input: parts.next()?.to_string(),
+                            count: parts.next()?.parse().ok()?,
+                            spread: parts.next()?.parse().ok()?,
+                        };
+                        parts.next().is_none().then_some(sampled)
+                    })
+                    .collect::<Option<_>>()?;
+                fields.truncate(at);
+                windows
+            }
+            Some(_) => return None,
+            None => Vec::new(),
+        };
+        let held = match fields.iter().position(|f| *f == "held") {
+            Some(at) if fields.len() == at + 3 => {
+                let held = Held {
+                    inputs: fields[at + 2].split(',').map(str::to_string).collect(),
+                    remaining_secs: fields[at + 1].parse().ok()?,
+                };
+                fields.truncate(at);
+                Some(held)
+            }
+            Some(_) => return None,
+            None => None,
+        };
+        if fields.first() != Some(&"state") || !(fields.len() == 4 || fields.len() == 6) {
+            return None;
+        }
+        let number = |i: usize| fields[i].parse::<f64>().ok();
+        let pending = if fields.len() == 6 {
+            Some(Pending {
+                band: band_from_label(fields[4])?,
+                remaining_secs: number(5)?,
+            })
+        } else {
+            None
+        };
+        let inputs = readings
+            .into_iter()
+            .map(|reading| {
+                let (name, value) = reading.split_once('=')?;
+                Some((name.to_string(), value.parse().ok()?))
+            })
+            .collect::<Option<_>>()?;
+        Some(Snapshot {
+            inputs,
+            fan_speed: number(1)?,
+            output: PipelineOutput {
+                value: number(2)?,
+                band: band_from_label(fields[3])?,
+                pending,
+            },
+            held,
+            windows,
+            sources,
+            lag,
+        })
+    }
+}
+
+/// Headless controller state shared by every connection
+struct Runtime {
+    controller: FuzzyController,
+    pipeline: OutputPipeline,
+    /// Watches the output for a limit cycle, with an [oscillation]
+    oscillation: Option<Detector>,
+    /// The config's [[alarm]] conditions
+    alarms: Alarms,
+    /// The running score, with an [objective]
+    objective: Option<Objective>,
+    /// The rung computations run at, with a [budget]
+    budget: Option<Budget>,
+    /// Time of the time-dependent stages; tests drive their own
+    clock: Box<dyn Clock + Send>,
+    snapshot: Snapshot,
+    /// Last raw reading of each input, before its adapter
+    raw: Vec<f64>,
+    rule_stats: RuleUsageStats,
+    /// Bookmarks of the TUI in the state file, written back untouched
+    bookmarks: Bookmarks,
+    /// Panel layout of the TUI in the state file, likewise
+    layout: PanelLayout,
+    state_path: Option<String>,
+    injector: Injector,
+    /// Inputs set by hand, held against `set` readings
+    overrides: Overrides,
+    /// Sample window of each input that has one, in declaration order
+    windows: Vec<Option<Aggregator>>,
+    /// Sources fused into each input that has them, likewise
+    fusions: Vec<Option<Fusion>>,
+    /// Pairs readings taken at the same instant, with input latencies
+    aligner: Option<Aligner>,
+    /// When the readings computed were taken, and whether they waited
+    /// past the bound instead
+    instant: Option<(f64, bool)>,
+    /// Computations whose output fell outside the activated sets
+    support_warnings: usize,
+    trigger: Trigger,
+    cascade: Option<Cascade>,
+    /// The config's [[interlock]] switches and which are engaged
+    interlocks: Interlocks,
+    /// Rule weights from before the first `weights` swap
+    saved_weights: Option<Vec<f64>>,
+    /// Set once the shutdown starts; commands are refused from then on
+    closing: bool,
+    /// Set while a fallback stands in for a broken config
+    safe_mode: Option<SafeMode>,
+    /// Where published outputs are recorded, with `--audit`
+    audit: Option<AuditLog>,
+    /// What made the last computation
+    cause: Cause,
+    /// A config evaluated next to this one, with `--shadow`
+    shadow: Option<Shadow>,
+    /// The program run with the published output, with an [output.exec]
+    exec: Option<Exec>,
+    /// Why the `exec` alarm is raised: the run that used up the retries
+    exec_alarm: Option<String>,
+    /// Why the `exec_stalled` alarm is raised: how long nothing could be
+    /// delivered
+    exec_stalled: Option<String>,
+}
+
+/// A daemon running on a fallback (safemode.rs)
+struct SafeMode {
+    retry: Retry,
+    /// Output published instead of the fallback's, from `--safe-output`
+    output: Option<f64>,
+    /// State file to take up once the config loads
+    state_path: Option<String>,
+}
+
+impl Runtime {
+    fn new(system: FuzzySystem, state_path: Option<String>) -> Result<Self, String> {
+        let pipeline = OutputPipeline::new(system.pipeline.clone());
+        let cascade = system.cascade.clone().map(Cascade::new);
+        let oscillation = system.oscillation.clone().map(Detector::new);
+        let alarms = Alarms::new(&system.alarms);
+        let objective = system.objective.clone().map(Objective::new);
+        let budget = system.budget.clone().map(Budget::new);
+        let interlocks = Interlocks::new(&system.interlocks);
+        let exec = system.exec.clone().map(Exec::new);
+        // Start each input at the middle of its universe
+        let middles: Vec<f64> = system
+            .inputs
+            .iter()
+            .map(|var| (var.min + var.max) / 2.0)
+            .collect();
+        let raw = adapters::raw_readings(&system.inputs, &middles);
+        let inputs = system
+            .inputs
+            .iter()
+            .zip(middles)
+            .map(|(var, value)| (var.name.clone(), value))
+            .collect();
+        let (rule_stats, bookmarks, layout) = match &state_path {
+            Some(path) => (
+                RuleUsageStats::load(path, system.rule_texts())?,
+                Bookmarks::load(path)?,
+                PanelLayout::load(path)?,
+            ),
+            None => (
+                RuleUsageStats::new(system.rule_texts()),
+                Bookmarks::default(),
+                PanelLayout::default(),
+            ),
+        };
+        let windows = system
+            .inputs
+            .iter()
+            .map(|var| var.window.clone().map(Aggregator::new))
+            .collect();
+        let fusions: Vec<Option<Fusion>> = system
+            .inputs
+            .iter()
+            .map(|var| {
+                let tolerance = system.defuzzification.tie_tolerance;
+                var.fusion
+                    .clone()
+                    .map(|config| Fusion::new(config, tolerance))
+            })
+            .collect();
+        let aligner = (system.inputs.iter().any(|var| var.latency_secs > 0.0)).then(|| {
+            let latencies = system.inputs.iter().map(|var| var.latency_secs).collect();
+            Aligner::new(latencies, &system.alignment)
+        });
+        let sources = system
+            .inputs
+            .iter()
+            .zip(&fusions)
+            .filter_map(|(var, fusion)| fusion.as_ref().map(|f| f.report(&var.name)))
+            .flatten()
+            .collect();
+        let mut runtime = Runtime {
+            raw,
+            windows,
+            fusions,
+            aligner,
+            instant: None,
+            controller: FuzzyController::from_system(system),
+            pipeline,
+            oscillation,
+            alarms,
+            objective,
+            budget,
+            rule_stats,
+            bookmarks,
+            layout,
+            state_path,
+            injector: Injector::default(),
+            overrides: Overrides::default(),
+            support_warnings: 0,
+            trigger: Trigger::default(),
+            cascade,
+            interlocks,
+            saved_weights: None,
+            closing: false,
+            safe_mode: None,
+            audit: None,
+            cause: Cause::Start,
+            shadow: None,
+            exec,
+            exec_alarm: None,
+            exec_stalled: None,
+            clock: Box::new(SystemClock::new()),
+            snapshot: Snapshot {
+                inputs,
+                fan_speed: 0.0,
+                output: PipelineOutput {
+                    value: 0.0,
+                    band: FanBand::Off,
+                    pending: None,
+                },
+                held: None,
+                windows: Vec::new(),
+                sources,
+                lag: None,
+            },
+        };
+        runtime.compute();
+        Ok(runtime)
+    }
+
+    fn compute(&mut self) {
+        self.trigger.computed(&self.raw, self.clock.now());
+        let system = &self.controller.system;
+        let (inputs, warnings) = adapters::convert(&system.inputs, &self.raw);
+        for ((_, value), converted) in self.snapshot.inputs.iter_mut().zip(&inputs) {
+            *value = *converted;
+        }
+        for warning in warnings {
+            eprintln!("warning: {}", warning);
+        }
+        let inputs = self
+            .injector
+            .apply(&system.inputs, &inputs, self.clock.now());
+        let evaluation = match &mut self.budget {
+            Some(budget) => self
+                .controller
+                .evaluate_within(&inputs, budget, &*self.clock),
+            None => self.controller.evaluate(&inputs),
+        };
+        if !evaluation.diagnostics.is_empty() {
+            self.support_warnings += 1;
+        }
+        for diagnostic in &evaluation.diagnostics {
+            eprintln!("warning: {} ({} so far)", diagnostic, self.support_warnings);
+        }
+        // The same preprocessed inputs, converted and injected once
+        if let Some(shadow) = &mut self.shadow {
+            shadow.infer(&inputs);
+        }
+        self.snapshot.fan_speed = evaluation.outputs[0];
+        let sample = Sample {
+            system: &self.controller.system,
+            inputs: &inputs,
+            outputs: &evaluation.outputs,
+            strengths: &evaluation.strengths,
+        };
+        if let Some(objective) = &mut self.objective {
+            objective.update(&sample, self.clock.now(), timestamp::now());
+        }
+        for change in self.alarms.update(&sample, self.clock.now()) {
+            if change.raised {
+                eprintln!(
+                    "{} alarm '{}' raised: {}",
+                    self.stamp(),
+                    change.name,
+                    self.alarms.condition(&change.name).unwrap_or_default()
+                );
+            } else {
+                eprintln!("{} alarm '{}' cleared", self.stamp(), change.name);
+            }
+        }
+        self.rule_stats
+            .record(&evaluation.strengths, timestamp::now());
+        self.tick();
+        let readings: Vec<String> = self
+            .snapshot
+            .inputs
+            .iter()
+            .zip(&self.controller.system.inputs)
+            .zip(&self.fusions)
+            .map(|(((name, value), var), fusion)| {
+                let mut reading = format!("{}={}", name, var.unit.stored().format(*value));
+                if let Some(window) = self.snapshot.windows.iter().find(|w| w.input == *name) {
+                    reading.push_str(&format!(" ({})", window.describe()));
+                }
+                // Each source's latest raw reading, for later analysis
+                if let Some(fusion) = fusion {
+                    reading.push_str(&format!(" ({})", fusion.describe()));
+                }
+                reading
+            })
+            .collect();
+        let now = self.clock.now();
+        self.snapshot.lag = self.instant.map(|(at, _)| now - at);
+        let aligned = match self.instant {
+            Some((at, false)) => format!(" (taken at {:.1}s)", at),
+            Some((at, true)) => format!(" (unaligned, waited from {:.1}s)", at),
+            None => String::new(),
+        };
+        let injected = if self.injector.is_active() {
+            format!(" (injected: {})", self.injector.describe())
+        } else {
+            String::new()
+        };
+        let output = self.controller.system.output();
+        let shadow = match &self.shadow {
+            Some(shadow) => format!(
+                " (shadow {})",
+                output.unit.stored().format(shadow.fan_speed)
+            ),
+            None => String::new(),
+        };
+        eprintln!(
+            "{} {}{} -> {}={}{}{}",
+            self.stamp(),
+            readings.join(" "),
+            aligned,
+            output.name,
+            output.unit.stored().format(self.snapshot.fan_speed),
+            shadow,
+            injected
+        );
+    }
+
+    /// Write `record` to the audit file, if there is one
+    fn audit(&mut self, record: &Record) {
+        if let Some(audit) = &mut self.audit
+            && let Err(e) = audit.record(record)
+        {
+            eprintln!("{} error: audit: {}", self.stamp(), e);
+        }
+    }
+
+    /// Wall-clock time in UTC and session time, before each log line
+    fn stamp(&self) -> String {
+        format!(
+            "{} [{:>8.1}s]",
+            timestamp::rfc3339(timestamp::now()),
+            self.clock.now()
+        )
+    }
+
+    fn tick(&mut self) {
+        if self.injector.expire(self.clock.now()) {
+            eprintln!("{} injection expired", self.stamp());
+            self.cause = Cause::InjectionExpired;
+            // Recomputes and ticks again, with nothing left to expire
+            self.compute();
+            return;
+        }
+        let ended = self.overrides.expire(self.clock.now());
+        if !ended.is_empty() {
+            let names: Vec<&str> = ended.iter().map(|(name, _)| name.as_str()).collect();
+            eprintln!("{} override of {} expired", self.stamp(), names.join(", "));
+            self.reconcile(ended);
+            self.cause = Cause::OverrideExpired;
+            self.compute();
+            return;
+        }
+        self.snapshot.held = self.overrides.held(self.clock.now());
+        if self.poll_sources() {
+            self.cause = Cause::SourceSilent;
+            self.compute();
+            return;
+        }
+        if let Some(aligner) = &mut self.aligner {
+            let released = aligner.poll(self.clock.now());
+            if !released.is_empty() {
+                // Each instant computes, and ticks, on its own
+                self.take_aligned(released, Cause::AlignTimeout);
+                return;
+            }
+        }
+        if self.close_windows() {
+            self.cause = Cause::WindowClosed;
+            self.compute();
+            return;
+        }
+        if self
+            .trigger
+            .is_due(&self.controller.system.recompute, self.clock.now())
+        {
+            // The computation resets the interval before ticking again
+            self.cause = Cause::Interval;
+            self.compute();
+            return;
+        }
+        let value = match &mut self.cascade {
+            Some(cascade) => {
+                let system = &self.controller.system;
+                let index = system
+                    .inputs
+                    .iter()
+                    .position(|var| var.name == cascade.config().measured)
+                    .expect("validated cascade input");
+                cascade.process(self.snapshot.fan_speed, self.raw[index], self.clock.now())
+            }
+            None => self.snapshot.fan_speed,
+        };
+        self.snapshot.output = self.pipeline.process(value, self.clock.now());
+        let mut record = Record::new(
+            &self.cause,
+            self.clock.now(),
+            self.snapshot.fan_speed,
+            value,
+            &self.pipeline,
+            self.snapshot.output,
+        );
+        record.aligned_at = self.instant.map(|(at, _)| at);
+        if let Some(value) = self.safe_mode.as_ref().and_then(|safe| safe.output) {
+            self.snapshot.output = PipelineOutput {
+                value,
+                band: FanBand::from_speed(value),
+                pending: None,
+            };
+            record.safe_output = Some(value);
+            record.output = self.snapshot.output;
+        }
+        if let Some(interlock) = self.interlocks.active() {
+            record.interlock = Some(interlock.name.clone());
+            self.snapshot.output =
+                self.interlocks
+                    .apply(&mut self.pipeline, self.snapshot.output, self.clock.now());
+            record.output = self.snapshot.output;
+        }
+        if let Some(shadow) = &mut self.shadow {
+            let output = shadow.tick(self.snapshot.output, self.clock.now());
+            record.shadow = Some((output.value, output.band));
+        }
+        let finished = self.exec.as_ref().map(Exec::finished).unwrap_or_default();
+        for outcome in finished {
+            self.exec_finished(&outcome);
+            self.audit(&Record {
+                exec: Some(outcome.invocation),
+                ..record.clone()
+            });
+        }
+        self.audit(&record);
+        // A safe or forced output is never coalesced away
+        self.publish_exec(record.safe_output.is_some() || record.interlock.is_some());
+        if let Some(detector) = &mut self.oscillation
+            && detector.push(self.clock.now(), self.snapshot.output.value)
+        {
+            match detector.oscillation() {
+                Some(swing) => eprintln!(
+                    "{} oscillation: output swinging {:.1} to {:.1}, {} reversals",
+                    self.stamp(),
+                    swing.low,
+                    swing.high,
+                    swing.reversals
+                ),
+                None => eprintln!("{} oscillation over", self.stamp()),
+            }
+        }
+    }
+
+    /// Hand the published output to the exec program, which runs it when
+    /// its `on` policy says so, and raise or clear the `exec_stalled` alarm
+    fn publish_exec(&mut self, priority: bool) {
+        let Some(exec) = &mut self.exec else {
+            return;
+        };
+        exec.publish(Publication {
+            output: self.controller.system.output().name.clone(),
+            value: self.snapshot.output.value,
+            band: self.snapshot.output.band,
+            priority,
+        });
+        let stalled = exec.stalled().map(|secs| {
+            let stats = exec.stats();
+            format!(
+                "nothing delivered for {:.0}s, {} coalesced, {} dropped",
+                secs, stats.coalesced, stats.dropped
+            )
+        });
+        match (stalled, self.exec_stalled.is_some()) {
+            (Some(condition), false) => {
+                eprintln!(
+                    "{} alarm 'exec_stalled' raised: {}",
+                    self.stamp(),
+                    condition
+                );
+                self.exec_stalled = Some(condition);
+            }
+            (Some(condition), true) => self.exec_stalled = Some(condition),
+            (None, true) => {
+                eprintln!("{} alarm 'exec_stalled' cleared", self.stamp());
+                self.exec_stalled = None;
+            }
+            (None, false) => {}
+        }
+    }
+
+    /// Log a finished run of the exec program, raising the `exec` alarm
+    /// when it gave up and clearing it when a run succeeds
+    fn exec_finished(&mut self, outcome: &Outcome) {
+        let invocation = &outcome.invocation;
+        if invocation.status.succeeded() {
+            if !invocation.stderr.is_empty() {
+                eprintln!("{} {}", self.stamp(), invocation.describe());
+            }
+            if self.exec_alarm.take().is_some() {
+                eprintln!("{} alarm 'exec' cleared", self.stamp());
+            }
+            return;
+        }
+        eprintln!("{} warning: {}", self.stamp(), invocation.describe());
+        if outcome.gave_up {
+            if self.exec_alarm.is_none() {
+                eprintln!(
+                    "{} alarm 'exec' raised: {}",
+                    self.stamp(),
+                    invocation.describe()
+                );
+            }
+            self.exec_alarm = Some(invocation.describe());
+        }
+    }
+
+    /// Take up the aggregate of every sample window that closed, returning
+    /// whether the new readings are worth a computation
+    fn close_windows(&mut self) -> bool {
+        let now = self.clock.now();
+        let inputs = &self.controller.system.inputs;
+        let mut changed = false;
+        for (index, aggregator) in self.windows.iter_mut().enumerate() {
+            let Some(window) = aggregator.as_mut().and_then(|a| a.poll(now)) else {
+                continue;
+            };
+            let name = &inputs[index].name;
+            let sampled = Sampled {
+                input: name.clone(),
+                count: window.count,
+                spread: window.spread,
+            };
+            match self.snapshot.windows.iter_mut().find(|w| w.input == *name) {
+                Some(last) => *last = sampled,
+                None => self.snapshot.windows.push(sampled),
+            }
+            // A hold keeps the aggregate back like any other reading
+            if !self.overrides.keep(name, window.value) {
+                self.raw[index] = window.value;
+                changed = true;
+            }
+        }
+        if !changed {
+            return false;
+        }
+        if self.trigger.should_compute(inputs, &self.raw) {
+            return true;
+        }
+        self.trigger.suppress();
+        false
+    }
+
+    /// Exclude the fused sources that went silent, returning whether the
+    /// fused readings left are worth a computation
+    fn poll_sources(&mut self) -> bool {
+        let now = self.clock.now();
+        let mut changed = false;
+        for index in 0..self.fusions.len() {
+            let Some(fusion) = &mut self.fusions[index] else {
+                continue;
+            };
+            let changes = fusion.poll(now);
+            if changes.is_empty() {
+                continue;
+            }
+            let fused = fusion.value();
+            self.sources_changed(index, changes);
+            // A window takes its samples as they come, so only the other
+            // inputs take up what the remaining sources make
+            if self.windows[index].is_none() {
+                let reading = fused.map_or(Ok(f64::NAN), |fused| self.admit_reading(index, fused));
+                let name = &self.controller.system.inputs[index].name;
+                if let Ok(reading) = reading
+                    && !self.overrides.keep(name, reading)
+                {
+                    self.raw[index] = reading;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return false;
+        }
+        if self
+            .trigger
+            .should_compute(&self.controller.system.inputs, &self.raw)
+        {
+            return true;
+        }
+        self.trigger.suppress();
+        false
+    }
+
+    /// Log the sources of input `index` excluded or joining again, raising
+    /// and clearing their alarms, and report every source anew
+    fn sources_changed(&mut self, index: usize, changes: Vec<fusion::Change>) {
+        let name = &self.controller.system.inputs[index].name;
+        for change in changes {
+            match change.excluded {
+                Some(reason) => eprintln!(
+                    "{} alarm '{}.{}' raised: {}",
+                    self.stamp(),
+                    name,
+                    change.source,
+                    reason
+                ),
+                None => eprintln!(
+                    "{} alarm '{}.{}' cleared",
+                    self.stamp(),
+                    name,
+                    change.source
+                ),
+            }
+        }
+        let system = &self.controller.system;
+        self.snapshot.sources = system
+            .inputs
+            .iter()
+            .zip(&self.fusions)
+            .filter_map(|(var, fusion)| fusion.as_ref().map(|f| f.report(&var.name)))
+            .flatten()
+            .collect();
+    }
+
+    /// Input index and source of a reading routed as `<input>.<source>`
+    fn route<'a>(&self, name: &'a str) -> Option<(usize, &'a str)> {
+        let (input, source) = name.rsplit_once('.')?;
+        let inputs = &self.controller.system.inputs;
+        let index = inputs.iter().position(|var| var.name == input)?;
+        (self.fusions[index].as_ref())
+            .is_some_and(|fusion| fusion.has_source(source))
+            .then_some((index, source))
+    }
+
+    /// File `value` of `source` of fused input `index`, and take up the
+    /// reading its healthy sources make now
+    fn fuse(&mut self, index: usize, source: &str, value: &str) -> String {
+        let value = match numbers::parse(value, Locale::Auto) {
+            Ok(value) => value,
+            Err(e) => return format!("error {}", e),
+        };
+        let now = self.clock.now();
+        let fusion = self.fusions[index]
+            .as_mut()
+            .expect("routed to a fused input");
+        let changes = fusion.push(source, now, value);
+        let fused = fusion.value();
+        self.sources_changed(index, changes);
+        match fused {
+            Some(fused) => match self.admit_reading(index, fused) {
+                Ok(reading) => self.take_reading(index, reading),
+                Err(e) => format!("error {}", e),
+            },
+            // No healthy source left: a window goes without samples until
+            // it is stale, any other input fails
+            None if self.windows[index].is_some() => "ok".to_string(),
+            None => self.take_reading(index, f64::NAN),
+        }
+    }
+
+    /// Take up raw `value` of input `index` as `set` does: as a sample of
+    /// its window, kept back by a hold, or through the triggers
+    fn take_reading(&mut self, index: usize, value: f64) -> String {
+        if let Some(window) = &mut self.windows[index] {
+            window.push(self.clock.now(), value);
+            return "ok".to_string();
+        }
+        let name = &self.controller.system.inputs[index].name;
+        if self.overrides.keep(name, value) {
+            return "ok held".to_string();
+        }
+        if let Some(aligner) = &mut self.aligner {
+            let released = aligner.push(index, self.clock.now(), value);
+            let cause = self.cause.clone();
+            self.take_aligned(released, cause);
+            return "ok".to_string();
+        }
+        self.raw[index] = value;
+        if self
+            .trigger
+            .should_compute(&self.controller.system.inputs, &self.raw)
+        {
+            self.compute();
+        } else {
+            self.trigger.suppress();
+        }
+        "ok".to_string()
+    }
+
+    /// Take up the instants the aligner released, oldest first, each
+    /// through the triggers; `cause` computes the ones with every reading
+    /// from their instant, and the ones that waited too long time out
+    fn take_aligned(&mut self, released: Vec<Aligned>, cause: Cause) {
+        for aligned in released {
+            let inputs = &self.controller.system.inputs;
+            for (index, value) in aligned.values.iter().enumerate() {
+                // A hold keeps an aligned reading back like any other
+                if let Some(value) = *value
+                    && !self.overrides.keep(&inputs[index].name, value)
+                {
+                    self.raw[index] = value;
+                }
+            }
+            self.instant = Some((aligned.at, aligned.timed_out));
+            if self.trigger.should_compute(inputs, &self.raw) {
+                self.cause = match aligned.timed_out {
+                    true => Cause::AlignTimeout,
+                    false => cause.clone(),
+                };
+                self.compute();
+            } else {
+                self.trigger.suppress();
+            }
+        }
+    }
+
+    /// Take up the readings kept back while the `ended` holds lasted
+    fn reconcile(&mut self, ended: Vec<(String, Option<f64>)>) {
+        let inputs = &self.controller.system.inputs;
+        for (name, reading) in ended {
+            if let (Some(index), Some(reading)) =
+                (inputs.iter().position(|var| var.name == name), reading)
+            {
+                self.raw[index] = reading;
+            }
+        }
+    }
+
+    /// Index of input `name` and its raw reading `value`, as admitted by
+    /// the input's policy
+    fn admit(&self, name: &str, value: &str) -> Result<(usize, f64), String> {
+        let inputs = &self.controller.system.inputs;
+        let index = inputs
+            .iter()
+            .position(|var| var.name == name)
+            .ok_or_else(|| format!("unknown input '{}'", name))?;
+        let value = numbers::parse(value, Locale::Auto).map_err(|e| e.to_string())?;
+        Ok((index, self.admit_reading(index, value)?))
+    }
+
+    /// Raw reading `value` of input `index` as admitted by its policy
+    fn admit_reading(&self, index: usize, value: f64) -> Result<f64, String> {
+        let var = &self.controller.system.inputs[index];
+        match var.kind {
+            // Clamped, held or refused by the input's policy
+            InputKind::Direct => var.admit(value),
+            // Checked against the temperature by the adapter
+            InputKind::DewPoint { .. } => Ok(value),
+        }
+    }
+
+    /// In safe mode, load the config again if an attempt is due at `now`,
+    /// and serve it once it loads
+    fn retry_config(&mut self, now: f64) {
+        let Some(safe) = &mut self.safe_mode else {
+            return;
+        };
+        let Some(system) = safe.retry.poll(now, config::load_system) else {
+            return;
+        };
+        let state_path = safe.state_path.take();
+        eprintln!("{} config loaded, leaving safe mode", self.stamp());
+        let mut fresh = start_runtime(Ok(system), state_path, None);
+        fresh.clock = std::mem::replace(&mut self.clock, Box::new(SystemClock::new()));
+        fresh.audit = self.audit.take();
+        fresh.interlocks.take_over(&self.interlocks);
+        fresh.cause = Cause::ConfigLoaded;
+        fresh.shadow = self.shadow.take().filter(|shadow| {
+            let fits = shadow.fits(&fresh.controller.system);
+            if let Err(e) = &fits {
+                eprintln!("error: shadow {}: {}; shadow stopped", shadow.path, e);
+            }
+            fits.is_ok()
+        });
+        *self = fresh;
+    }
+
+    /// Replace the weight of every rule, keeping the current ones for
+    /// `weights revert` unless earlier ones are kept already
+    fn set_weights(&mut self, words: &[&str]) -> Result<(), String> {
+        let rules = &mut self.controller.system.rules;
+        if words.len() != rules.len() {
+            return Err(format!(
+                "expected {} weights, got {}",
+                rules.len(),
+                words.len()
+            ));
+        }
+        let weights = words
+            .iter()
+            .map(|word| {
+                numbers::parse(word, Locale::Auto)
+                    .ok()
+                    .filter(|w| (0.0..=1.0).contains(w))
+                    .ok_or_else(|| format!("weight '{}' is not between 0 and 1", word))
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+        self.saved_weights
+            .get_or_insert_with(|| rules.iter().map(|rule| rule.weight).collect());
+        for (rule, weight) in rules.iter_mut().zip(weights) {
+            rule.weight = weight;
+        }
+        Ok(())
+    }
+
+    /// Raw readings of every `evaluate` point
+    fn parse_points(&self, words: &[&str]) -> Result<Vec<Vec<f64>>, String> {
+        let inputs = self.controller.system.inputs.len();
+        if words.is_empty() {
+            return Err("no points to evaluate".to_string());
+        }
+        words
+            .iter()
+            .map(|word| {
+                let values = word
+                    .split(',')
+                    .map(|v| v.parse::<f64>().ok().filter(|v| v.is_finite()))
+                    .collect::<Option<Vec<f64>>>()
+                    .filter(|values| values.len() == inputs);
+                values.ok_or_else(|| format!("point '{}' needs {} numbers", word, inputs))
+            })
+            .collect()
+    }
+
+    /// `outputs` line for a batch of points, evaluated with the current
+    /// system but none of the daemon's state
+    fn evaluate_points(&self, words: &[&str]) -> String {
+        let points = match self.parse_points(words) {
+            Ok(points) => points,
+            Err(e) => return format!("error {}", e),
+        };
+        let system = &self.controller.system;
+        let mut line = "outputs".to_string();
+        for raw in points {
+            let (values, _) = adapters::convert(&system.inputs, &raw);
+            let outputs: Vec<String> = self
+                .controller
+                .evaluate(&values)
+                .outputs
+                .iter()
+                .map(f64::to_string)
+                .collect();
+            line.push(' ');
+            line.push_str(&outputs.join(","));
+        }
+        line
+    }
+
+    /// Write the rule statistics if they changed since the last save
+    fn save_state(&mut self) -> Result<(), String> {
+        match &self.state_path {
+            Some(path) if self.rule_stats.is_dirty() => {
+                self.rule_stats.save(path, &self.bookmarks, &self.layout)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The reply to a binary request, run as the commands it stands for
+    fn answer(&mut self, message: &Message) -> Message {
+        let replied = |reply: String| match reply.strip_prefix("error ") {
+            Some(reason) => Message::Error(reason.to_string()),
+            None => Message::Ok,
+        };
+        let inputs = &self.controller.system.inputs;
+        match message {
+            Message::Init => {
+                let names =
+                    |vars: &[crate::FuzzyVariable]| vars.iter().map(|v| v.name.clone()).collect();
+                Message::Ready {
+                    inputs: names(inputs),
+                    outputs: names(&self.controller.system.outputs),
+                }
+            }
+            Message::SetInputs(readings) => {
+                let commands: Result<Vec<String>, String> = (readings.iter())
+                    .map(|(index, value)| match inputs.get(*index as usize) {
+                        Some(var) => Ok(format!("set {} {}", var.name, value)),
+                        None => Err(format!("unknown input index {}", index)),
+                    })
+                    .collect();
+                let commands = match commands {
+                    Ok(commands) => commands,
+                    Err(e) => return Message::Error(e),
+                };
+                for command in commands {
+                    if let Message::Error(reason) = replied(self.execute(&command)) {
+                        return Message::Error(reason);
+                    }
+                }
+                Message::Ok
+            }
+            Message::Compute { .. } | Message::GetState => Message::State(wire::State {
+                time: self.clock.now(),
+                outputs: vec![self.snapshot.fan_speed],
+                actuator: self.snapshot.output.value,
+                band: self.snapshot.output.band,
+            }),
+            Message::Weights(weights) => {
+                let weights: Vec<String> = weights.iter().map(f64::to_string).collect();
+                replied(self.execute(&format!("weights {}", weights.join(" "))))
+            }
+            Message::Terminate => Message::Ok,
+            _ => Message::Error("not a request".to_string()),
+        }
+    }
+
+    fn execute(&mut self, command: &str) -> String {
+        // A command that computes is the cause of what is published next
+        let (executed, previous) = (
+            self.trigger.executed,
+            std::mem::replace(&mut self.cause, Cause::Command(command.trim().to_string())),
+        );
+        let reply = self.run_command(command);
+        if self.trigger.executed == executed {
+            self.cause = previous;
+        }
+        reply
+    }
+
+    fn run_command(&mut self, command: &str) -> String {
+        if self.closing {
+            return "error shutting down".to_string();
+        }
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words[..] {
+            ["snapshot"] => self.snapshot.to_line(),
+            ["stats"] => {
+                let detector = self.oscillation.as_ref();
+                let mut line = format!(
+                    "stats executed {} suppressed {} support_warnings {} oscillating {} amplitude {} degraded {} interlock {}",
+                    self.trigger.executed,
+                    self.trigger.suppressed,
+                    self.support_warnings,
+                    detector.is_some_and(Detector::is_oscillating),
+                    detector
+                        .and_then(Detector::swing)
+                        .map_or(0.0, |swing| swing.amplitude()),
+                    self.budget.as_ref().map_or(0, Budget::degraded),
+                    self.interlocks
+                        .active()
+                        .map_or("none", |interlock| interlock.name.as_str())
+                );
+                if let Some(aligner) = &self.aligner {
+                    line.push_str(&format!(
+                        " aligned {} align_timeouts {} late {}",
+                        aligner.aligned, aligner.timed_out, aligner.late
+                    ));
+                }
+                if let Some(exec) = &self.exec {
+                    let stats = exec.stats();
+                    line.push_str(&format!(
+                        " exec_coalesced {} exec_dropped {} exec_latency {}",
+                        stats.coalesced, stats.dropped, stats.latency
+                    ));
+                }
+                if let Some(shadow) = &self.shadow {
+                    let summary = shadow.summary();
+                    line.push_str(&format!(
+                        " shadow_mean_abs_diff {} shadow_band_differs {} shadow_energy_diff {}",
+                        summary.mean_difference(),
+                        summary.band_differs(),
+                        summary.energy_difference()
+                    ));
+                }
+                line
+            }
+            ["alarms"] => {
+                let mut line = "alarms".to_string();
+                for name in self.alarms.active() {
+                    let condition = self.alarms.condition(name).unwrap_or_default();
+                    line.push_str(&format!(
+                        " {}={}",
+                        config::quote(name),
+                        config::quote(condition)
+                    ));
+                }
+                if let Some(failure) = &self.exec_alarm {
+                    line.push_str(&format!(" \"exec\"={}", config::quote(failure)));
+                }
+                if let Some(stall) = &self.exec_stalled {
+                    line.push_str(&format!(" \"exec_stalled\"={}", config::quote(stall)));
+                }
+                let system = &self.controller.system;
+                for (var, fusion) in system.inputs.iter().zip(&self.fusions) {
+                    let Some(fusion) = fusion else {
+                        continue;
+                    };
+                    for source in fusion.report(&var.name) {
+                        if let Some(reason) = fusion.reason(&source.source) {
+                            line.push_str(&format!(
+                                " {}={}",
+                                config::quote(&source.route()),
+                                config::quote(reason)
+                            ));
+                        }
+                    }
+                }
+                line
+            }
+            ["objective"] => match &self.objective {
+                Some(objective) => {
+                    let score =
+                        |mark: Option<&crate::objective::Mark>| mark.map_or(f64::NAN, |m| m.score);
+                    format!(
+                        "objective {} mean {} best {} worst {} count {}",
+                        objective.current().unwrap_or(f64::NAN),
+                        objective.mean().unwrap_or(f64::NAN),
+                        score(objective.best()),
+                        score(objective.worst()),
+                        objective.count()
+                    )
+                }
+                None => "objective off".to_string(),
+            },
+            ["metadata"] => {
+                let mut line = "metadata".to_string();
+                for (key, value) in self.controller.system.metadata.fields() {
+                    line.push_str(&format!(" {}={}", key, config::quote(value)));
+                }
+                line
+            }
+            ["units"] => {
+                let system = &self.controller.system;
+                let mut line = "units".to_string();
+                for var in system.inputs.iter().chain(&system.outputs) {
+                    if !var.unit.name.is_empty() {
+                        line.push_str(&format!(" {}={}", var.name, config::quote(&var.unit.name)));
+                    }
+                }
+                line
+            }
+            ["random"] => {
+                let secs = self.controller.system.overrides.duration_secs;
+                if secs == 0.0 {
+                    return "error random inputs are off: [override] duration_secs = 0 \
+                            leaves the inputs to their readings"
+                        .to_string();
+                }
+                let kind = self.controller.system.generator.kind;
+                let sample = Sampler::new(&self.controller).sample(kind, &mut rand::thread_rng());
+                let Some(values) = sample else {
+                    return "error no sample satisfies the generator constraints".to_string();
+                };
+                let inputs = &self.controller.system.inputs;
+                self.raw = adapters::raw_readings(inputs, &values);
+                for var in inputs {
+                    self.overrides.hold(&var.name, self.clock.now() + secs);
+                }
+                self.compute();
+                "ok".to_string()
+            }
+            ["override", name, value, ref secs @ ..] if secs.len() <= 1 => {
+                let mut duration = self.controller.system.overrides.duration_secs;
+                if duration == 0.0 {
+                    return "error overrides are off: [override] duration_secs = 0".to_string();
+                }
+                if let [secs] = secs {
+                    duration = match numbers::parse(secs, Locale::Auto) {
+                        Ok(secs) if secs > 0.0 => secs,
+                        Ok(_) => return "error the duration must be positive".to_string(),
+                        Err(e) => return format!("error {}", e),
+                    };
+                }
+                let (index, value) = match self.admit(name, value) {
+                    Ok(admitted) => admitted,
+                    Err(e) => return format!("error {}", e),
+                };
+                self.raw[index] = value;
+                self.overrides.hold(name, self.clock.now() + duration);
+                self.compute();
+                "ok".to_string()
+            }
+            ["release"] => {
+                let ended = self.overrides.release();
+                if !ended.is_empty() {
+                    self.reconcile(ended);
+                    self.compute();
+                }
+                "ok".to_string()
+            }
+            ["reset_latches"] => {
+                self.rule_stats.reset_latches();
+                match self.save_state() {
+                    Ok(()) => "ok".to_string(),
+                    Err(e) => format!("error {}", e),
+                }
+            }
+            ["group", name, ref change @ ..] => {
+                let Some(group) = self
+                    .controller
+                    .system
+                    .groups
+                    .iter_mut()
+                    .find(|g| g.name == name)
+                else {
+                    return format!("error unknown group '{}'", name);
+                };
+                match change {
+                    ["on"] => group.enabled = true,
+                    ["off"] => group.enabled = false,
+                    ["weight", weight] => match numbers::parse(weight, Locale::Auto) {
+                        Ok(w) if (0.0..=1.0).contains(&w) => group.weight = w,
+                        _ => return "error weight must be between 0 and 1".to_string(),
+                    },
+                    _ => return format!("error unknown command '{}'", command.trim()),
+                }
+                self.compute();
+                "ok".to_string()
+            }
+            ["caps"] => {
+                let settings = &self.controller.system.defuzzification;
+                let mut line = format!("caps {}", settings.cap_profile.as_deref().unwrap_or("off"));
+                for profile in settings.cap_profiles() {
+                    line.push(' ');
+                    line.push_str(profile);
+                }
+                line
+            }
+            ["caps", profile] => {
+                let edit = Edit::CapProfile {
+                    profile: (profile != "off").then(|| profile.to_string()),
+                };
+                if let Err(e) = edit.apply(&mut self.controller.system) {
+                    return format!("error {}", e);
+                }
+                self.compute();
+                "ok".to_string()
+            }
+            ["aggregation", aggregation, temperature] => {
+                let Some(aggregation) = Aggregation::parse(aggregation) else {
+                    return format!("error unknown aggregation '{}'", aggregation);
+                };
+                let temperature = match numbers::parse(temperature, Locale::Auto) {
+                    Ok(temperature) => temperature,
+                    Err(e) => return format!("error {}", e),
+                };
+                let edit = Edit::Aggregation {
+                    aggregation,
+                    temperature,
+                };
+                if let Err(e) = edit.apply(&mut self.controller.system) {
+                    return format!("error {}", e);
+                }
+                self.compute();
+                "ok".to_string()
+            }
+            ["method", output, method] => {
+                let Some(method) = DefuzzMethod::parse(method) else {
+                    return format!("error unknown method '{}'", method);
+                };
+                let edit = Edit::Method {
+                    output: output.to_string(),
+                    method,
+                };
+                if let Err(e) = edit.apply(&mut self.controller.system) {
+                    return format!("error {}", e);
+                }
+                self.compute();
+                "ok".to_string()
+            }
+            ["inject", ..] => {
+                let now = self.clock.now();
+                let inputs = &self.controller.system.inputs;
+                match self.injector.command(&words[1..].join(" "), inputs, now) {
+                    Ok(message) => {
+                        eprintln!("{} {}", self.stamp(), message);
+                        self.compute();
+                        "ok".to_string()
+                    }
+                    Err(e) => format!("error {}", e),
+                }
+            }
+            ["interlock", name, state @ ("on" | "off")] => {
+                match self.interlocks.set(name, state == "on") {
+                    Ok(true) => {
+                        eprintln!("{} interlock '{}' {}", self.stamp(), name, state);
+                        self.cause = Cause::Interlock(format!("{} {}", name, state));
+                        self.compute();
+                        "ok".to_string()
+                    }
+                    Ok(false) => "ok".to_string(),
+                    Err(e) => format!("error {}", e),
+                }
+            }
+            ["interlocks"] => {
+                let mut line = "interlocks".to_string();
+                for config in &self.controller.system.interlocks {
+                    let engaged = self.interlocks.is_engaged(&config.name);
+                    line.push_str(&format!(
+                        " {}={}",
+                        config::quote(&config.name),
+                        if engaged { "on" } else { "off" }
+                    ));
+                }
+                line
+            }
+            ["weights"] => {
+                let weights: Vec<String> = self
+                    .controller
+                    .system
+                    .rules
+                    .iter()
+                    .map(|rule| rule.weight.to_string())
+                    .collect();
+                format!("weights {}", weights.join(" "))
+            }
+            ["weights", "revert"] => {
+                let Some(weights) = self.saved_weights.take() else {
+                    return "error no weights to revert".to_string();
+                };
+                for (rule, weight) in self.controller.system.rules.iter_mut().zip(weights) {
+                    rule.weight = weight;
+                }
+                self.compute();
+                "ok".to_string()
+            }
+            ["weights", ref rest @ ..] => {
+                let (weights, points) = match rest.iter().position(|w| *w == "evaluate") {
+                    Some(at) => (&rest[..at], Some(&rest[at + 1..])),
+                    None => (rest, None),
+                };
+                // Checked before the swap so a bad batch changes nothing
+                if let Some(points) = points
+                    && let Err(e) = self.parse_points(points)
+                {
+                    return format!("error {}", e);
+                }
+                if let Err(e) = self.set_weights(weights) {
+                    return format!("error {}", e);
+                }
+                self.compute();
+                match points {
+                    Some(points) => self.evaluate_points(points),
+                    None => "ok".to_string(),
+                }
+            }
+            ["evaluate", ref points @ ..] => self.evaluate_points(points),
+            ["param", variable, set, param, value] => {
+                let system = &mut self.controller.system;
+                let Some(var) = system
+                    .inputs
+                    .iter_mut()
+                    .chain(system.outputs.iter_mut())
+                    .find(|var| var.name == variable)
+                else {
+                    return format!("error unknown variable '{}'", variable);
+                };
+                let Some(param) = links::param_index(param) else {
+                    return format!("error unknown parameter '{}'", param);
+                };
+                let value = match numbers::parse(value, Locale::Auto) {
+                    Ok(value) => value,
+                    Err(e) => return format!("error {}", e),
+                };
+                // Moved on a copy, so a refused move changes nothing
+                let mut moved = var.clone();
+                if let Err(e) = links::set_param(&mut moved.sets, set, param, value) {
+                    return format!("error {}", e);
+                }
+                let warnings = match guard::check(var, &moved, &system.tuning) {
+                    Ok(warnings) => warnings,
+                    Err(e) => return format!("error {}", e),
+                };
+                *var = moved;
+                self.compute();
+                if warnings.is_empty() {
+                    return "ok".to_string();
+                }
+                let described: Vec<String> =
+                    warnings.iter().map(|w| w.describe(variable)).collect();
+                format!("ok warn {}", described.join("; "))
+            }
+            ["set", name, value] => {
+                if let Some((index, source)) = self.route(name) {
+                    return self.fuse(index, source, value);
+                }
+                let inputs = &self.controller.system.inputs;
+                if let Some(var) = inputs.iter().find(|var| var.name == name)
+                    && var.fusion.is_some()
+                {
+                    return format!("error '{}' is fused: set {}.<source>", name, name);
+                }
+                let (index, value) = match self.admit(name, value) {
+                    Ok(admitted) => admitted,
+                    Err(e) => return format!("error {}", e),
+                };
+                self.take_reading(index, value)
+            }
+            _ => format!("error unknown command '{}'", command.trim()),
+        }
+    }
+}
+
+fn write_line(writer: &Mutex<UnixStream>, line: &str) -> io::Result<()> {
+    let mut stream = writer.lock().unwrap_or_else(|e| e.into_inner());
+    writeln!(stream, "{}", line)
+}
+
+fn handle_client(stream: UnixStream, runtime: Arc<Mutex<Runtime>>) -> io::Result<()> {
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    let connected = Arc::new(AtomicBool::new(true));
+
+    let mut reader = BufReader::new(stream);
+    let mut first = true;
+    loop {
+        let mut line = String::new();
+        let read = (&mut reader)
+            .take(MAX_LINE as u64 + 1)
+            .read_line(&mut line)?;
+        if read == 0 {
+            break;
+        }
+        if read > MAX_LINE {
+            // Drop the client rather than buffer an endless line
+            let _ = write_line(&writer, "error line too long");
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        match wire::requested(&line).filter(|_| first) {
+            Some(Ok(())) => {
+                write_line(&writer, &wire::handshake())?;
+                return serve_frames(reader, &writer, &runtime);
+            }
+            Some(Err(e)) => {
+                write_line(&writer, &format!("error {}", e))?;
+                first = false;
+                continue;
+            }
+            None => first = false,
+        }
+        if line.trim() == "subscribe" {
+            write_line(&writer, "ok")?;
+            let (writer, runtime, connected) = (writer.clone(), runtime.clone(), connected.clone());
+            std::thread::spawn(move || {
+                while connected.load(Ordering::Relaxed) {
+                    let snapshot = runtime
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .snapshot
+                        .clone();
+                    if write_line(&writer, &snapshot.to_line()).is_err() {
+                        break;
+                    }
+                    std::thread::sleep(STATE_INTERVAL);
+                }
+            });
+            continue;
+        }
+        let reply = runtime
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .execute(&line);
+        write_line(&writer, &reply)?;
+    }
+
+    connected.store(false, Ordering::Relaxed);
+    Ok(())
+}
+
+/// The binary rest of a connection (wire.rs)
+fn serve_frames(
+    mut reader: BufReader<UnixStream>,
+    writer: &Mutex<UnixStream>,
+    runtime: &Mutex<Runtime>,
+) -> io::Result<()> {
+    while let Some(frame) = wire::read_frame(&mut reader)? {
+        let message = wire::decode(&frame);
+        let reply = match &message {
+            Ok(message) => (runtime.lock().unwrap_or_else(|e| e.into_inner())).answer(message),
+            Err(e) => Message::Error(e.clone()),
+        };
+        let mut stream = writer.lock().unwrap_or_else(|e| e.into_inner());
+        wire::write(&mut *stream, &reply)?;
+        if message == Ok(Message::Terminate) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+struct SocketArgs {
+    socket: String,
+    config: Option<String>,
+    /// Profile that stands in for a broken config (safemode.rs)
+    fallback: Option<String>,
+    state_path: Option<String>,
+    /// Output the daemon leaves behind when it shuts down
+    safe_output: Option<f64>,
+    /// Audit file, its size limit and how many rotated files it keeps
+    audit: Option<(String, u64, usize)>,
+    /// Config evaluated next to the served one (shadow.rs)
+    shadow: Option<String>,
+}
+
+fn parse_socket_args(args: &[String]) -> Result<SocketArgs, String> {
+    let mut socket = None;
+    let mut config = None;
+    let mut fallback = None;
+    let mut state_path = None;
+    let mut safe_output = None;
+    let mut audit_path = None;
+    let mut shadow = None;
+    let (mut audit_size, mut audit_keep) = (audit::DEFAULT_SIZE, audit::DEFAULT_KEEP);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match (arg.as_str(), iter.next()) {
+            ("--socket", Some(path)) => socket = Some(path.clone()),
+            ("--config", Some(path)) => config = Some(path.clone()),
+            ("--fallback", Some(path)) => fallback = Some(path.clone()),
+            ("--state", Some(path)) => state_path = Some(path.clone()),
+            ("--safe-output", Some(value)) => {
+                safe_output = Some(
+                    value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|v| v.is_finite())
+                        .ok_or_else(|| format!("--safe-output: invalid number '{}'", value))?,
+                )
+            }
+            ("--audit", Some(path)) => audit_path = Some(path.clone()),
+            ("--shadow", Some(path)) => shadow = Some(path.clone()),
+            ("--audit-size", Some(size)) => {
+                audit_size = size
+                    .parse()
+                    .ok()
+                    .filter(|size| *size > 0)
+                    .ok_or_else(|| format!("--audit-size: invalid size '{}'", size))?
+            }
+            ("--audit-keep", Some(keep)) => {
+                audit_keep = keep
+                    .parse()
+                    .map_err(|_| format!("--audit-keep: invalid count '{}'", keep))?
+            }
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    Ok(SocketArgs {
+        socket: socket.ok_or("--socket PATH is required")?,
+        config,
+        fallback,
+        state_path,
+        safe_output,
+        audit: audit_path.map(|path| (path, audit_size, audit_keep)),
+        shadow,
+    })
+}
+
+/// Register the daemon's shutdown hooks (see the module comment)
+fn register_shutdown(
+    coordinator: &mut Coordinator,
+    runtime: &Arc<Mutex<Runtime>>,
+    ticker: std::thread::JoinHandle<()>,
+    socket: &str,
+    safe_output: Option<f64>,
+) {
+    {
+        let (runtime, socket) = (runtime.clone(), socket.to_string());
+        coordinator.register(
+            Stage::StopInputs,
+            "socket",
+            shutdown::DEFAULT_TIMEOUT,
+            move || {
+                runtime.lock().unwrap_or_else(|e| e.into_inner()).closing = true;
+                match std::fs::remove_file(&socket) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                        Err(format!("{}: {}", socket, e))
+                    }
+                    _ => Ok(()),
+                }
+            },
+        );
+    }
+    coordinator.register(
+        Stage::StopInputs,
+        "ticker",
+        shutdown::DEFAULT_TIMEOUT,
+        move || ticker.join().map_err(|_| "panicked".to_string()),
+    );
+    if let Some(value) = safe_output {
+        let runtime = runtime.clone();
+        coordinator.register(
+            Stage::FlushOutputs,
+            "safe output",
+            shutdown::DEFAULT_TIMEOUT,
+            move || {
+                {
+                    let mut runtime = runtime.lock().unwrap_or_else(|e| e.into_inner());
+                    runtime.snapshot.output = PipelineOutput {
+                        value,
+                        band: FanBand::from_speed(value),
+                        pending: None,
+                    };
+                    let runtime = &mut *runtime;
+                    let record = Record {
+                        safe_output: Some(value),
+                        ..Record::new(
+                            &Cause::Shutdown,
+                            runtime.clock.now(),
+                            runtime.snapshot.fan_speed,
+                            runtime.snapshot.fan_speed,
+                            &runtime.pipeline,
+                            runtime.snapshot.output,
+                        )
+                    };
+                    runtime.audit(&record);
+                    runtime.publish_exec(true);
+                    eprintln!("{} output set to safe value {}", runtime.stamp(), value);
+                }
+                // Long enough for every subscriber to be sent it
+                std::thread::sleep(STATE_INTERVAL * 2);
+                Ok(())
+            },
+        );
+    }
+    let runtime = runtime.clone();
+    coordinator.register(
+        Stage::PersistState,
+        "state",
+        shutdown::DEFAULT_TIMEOUT,
+        move || {
+            runtime
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .save_state()
+        },
+    );
+}
+
+/// Print what went wrong in a shutdown, returning the exit code
+fn report_shutdown(report: &Report) -> i32 {
+    for problem in report.problems() {
+        eprintln!("error: shutdown: {}", problem);
+    }
+    if report.problems().is_empty() { 0 } else { 1 }
+}
+
+/// The daemon's runtime for `system`, or in safe mode on `fallback` for
+/// the config that failed to load (see safemode.rs); a state file that
+/// cannot be read is left out
+fn start_runtime(
+    system: Result<FuzzySystem, (Retry, FuzzySystem)>,
+    state_path: Option<String>,
+    safe_output: Option<f64>,
+) -> Runtime {
+    let (system, retry) = match system {
+        Ok(system) => (system, None),
+        Err((retry, fallback)) => {
+            eprintln!(
+                "error: {}; safe mode, retrying every {}s",
+                retry.error,
+                safemode::RETRY_INTERVAL
+            );
+            (fallback, Some(retry))
+        }
+    };
+    // The state belongs to the configured system, not to a fallback
+    let (state_path, pending_state) = match retry {
+        Some(_) => (None, state_path),
+        None => (state_path, None),
+    };
+    let mut runtime = match Runtime::new(system.clone(), state_path) {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("error: {}; running without the state file", e);
+            Runtime::new(system, None).expect("nothing to load without a state file")
+        }
+    };
+    if let Some(retry) = retry {
+        runtime.safe_mode = Some(SafeMode {
+            retry,
+            output: safe_output,
+            state_path: pending_state,
+        });
+        runtime.tick();
+    }
+    runtime
+}
+
+/// `daemon` subcommand: serve the controller until SIGINT or SIGTERM
+pub fn run_daemon(args: &[String]) -> i32 {
+    let SocketArgs {
+        socket: path,
+        config,
+        fallback,
+        state_path,
+        safe_output,
+        audit,
+        shadow,
+    } = match parse_socket_args(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    let started = safemode::start(config.as_deref(), fallback.as_deref(), config::load_system);
+    let system = match (started.error, config) {
+        (Some(error), Some(config)) => Err((Retry::new(&config, error, 0.0), started.system)),
+        _ => Ok(started.system),
+    };
+    let mut runtime = start_runtime(system, state_path, safe_output);
+    if let Some(path) = shadow {
+        match Shadow::load(&path, &runtime.controller.system) {
+            Ok(shadow) => runtime.shadow = Some(shadow),
+            Err(e) => {
+                eprintln!("error: shadow {}", e);
+                return 2;
+            }
+        }
+    }
+    if let Some((path, size, keep)) = audit {
+        match AuditLog::open(&path, size, keep) {
+            Ok(log) => runtime.audit = Some(log),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return 2;
+            }
+        }
+    }
+    let runtime = Arc::new(Mutex::new(runtime));
+
+    // A socket file left by a previous run would make bind fail
+    if UnixStream::connect(&path).is_err() {
+        let _ = std::fs::remove_file(&path);
+    }
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("error: {}: {}", path, e);
+            return 2;
+        }
+    };
+    if let Err(e) = listener.set_nonblocking(true) {
+        eprintln!("error: {}: {}", path, e);
+        return 2;
+    }
+    match runtime
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .controller
+        .system
+        .metadata
+        .summary()
+    {
+        Some(summary) => eprintln!("Listening on {} ({})", path, summary),
+        None => eprintln!("Listening on {}", path),
+    }
+
+    let mut coordinator = Coordinator::default();
+    let token = coordinator.token();
+    shutdown::cancel_on_signals(&token);
+
+    let ticker = {
+        let (runtime, token) = (runtime.clone(), token.clone());
+        std::thread::spawn(move || {
+            let mut last_save = std::time::Instant::now();
+            while !token.is_cancelled() {
+                let mut runtime = runtime.lock().unwrap_or_else(|e| e.into_inner());
+                let now = runtime.clock.now();
+                runtime.retry_config(now);
+                runtime.tick();
+                if last_save.elapsed() >= STATE_SAVE_INTERVAL {
+                    if let Err(e) = runtime.save_state() {
+                        eprintln!("error: {}", e);
+                    }
+                    last_save = std::time::Instant::now();
+                }
+                drop(runtime);
+                std::thread::sleep(TICK_INTERVAL);
+            }
+        })
+    };
+
+    while !token.is_cancelled() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                // Accepted sockets may inherit the listener's mode
+                if let Err(e) = stream.set_nonblocking(false) {
+                    eprintln!("accept failed: {}", e);
+                    continue;
+                }
+                let runtime = runtime.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = handle_client(stream, runtime) {
+                        eprintln!("client disconnected: {}", e);
+                    }
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(ACCEPT_POLL),
+            Err(e) => eprintln!("accept failed: {}", e),
+        }
+    }
+
+    eprintln!("Shutting down");
+    drop(listener);
+    register_shutdown(&mut coordinator, &runtime, ticker, &path, safe_output);
+    report_shutdown(&coordinator.shutdown())
+}
+
+/// Client side of an attached TUI
+pub struct RemoteLink {
+    writer: UnixStream,
+    updates: Receiver<Snapshot>,
+}
+
+impl RemoteLink {
+    pub fn connect(path: &str) -> io::Result<Self> {
+        Self::from_stream(UnixStream::connect(path)?)
+    }
+
+    fn from_stream(mut writer: UnixStream) -> io::Result<Self> {
+        let reader = BufReader::new(writer.try_clone()?);
+        writeln!(writer, "subscribe")?;
+
+        let (sender, updates) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else { break };
+                if let Some(snapshot) = Snapshot::parse(&line)
+                    && sender.send(snapshot).is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Ok(RemoteLink { writer, updates })
+    }
+
+    /// Send both inputs, as `override` so they hold against the daemon's
+    /// readings when `hold`, else as `set`
+    pub fn send_inputs(&mut self, temperature: f64, humidity: f64, hold: bool) -> io::Result<()> {
+        let command = if hold { "override" } else { "set" };
+        writeln!(self.writer, "{} temperature {}", command, temperature)?;
+        writeln!(self.writer, "{} humidity {}", command, humidity)
+    }
+
+    pub fn send_edit(&mut self, edit: &Edit) -> io::Result<()> {
+        match edit {
+            Edit::GroupEnabled { group, enabled } => {
+                let state = if *enabled { "on" } else { "off" };
+                writeln!(self.writer, "group {} {}", group, state)
+            }
+            Edit::GroupWeight { group, weight } => {
+                writeln!(self.writer, "group {} weight {}", group, weight)
+            }
+            Edit::Aggregation {
+                aggregation,
+                temperature,
+            } => writeln!(
+                self.writer,
+                "aggregation {} {}",
+                aggregation.name(),
+                temperature
+            ),
+            Edit::CapProfile { profile } => {
+                writeln!(self.writer, "caps {}", profile.as_deref().unwrap_or("off"))
+            }
+            Edit::Method { output, method } => {
+                writeln!(self.writer, "method {} {}", output, method.name())
+            }
+            // The daemon keeps the rules of its own config
+            Edit::RulesCsv { .. } => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the daemon's rules cannot be reloaded",
+            )),
+        }
+    }
+
+    pub fn inject(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.writer, "inject {}", command)
+    }
+
+    pub fn interlock(&mut self, name: &str, engaged: bool) -> io::Result<()> {
+        let state = if engaged { "on" } else { "off" };
+        writeln!(self.writer, "interlock {} {}", name, state)
+    }
+
+    pub fn reset_latches(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "reset_latches")
+    }
+
+    pub fn release(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "release")
+    }
+
+    /// Most recent state received since the last call
+    pub fn latest(&self) -> Option<Snapshot> {
+        self.updates.try_iter().last()
+    }
+}
+
+/// Parse `attach` arguments, returning the socket path and the system the
+/// TUI should use to render memberships (must match the daemon's), loaded
+/// with `load` so the TUI can refuse inputs it cannot drive
+pub fn parse_attach_args(
+    args: &[String],
+    load: fn(&str) -> Result<FuzzySystem, String>,
+) -> Result<(String, FuzzySystem), String> {
+    let parsed = parse_socket_args(args)?;
+    if parsed.state_path.is_some() {
+        return Err("--state belongs to the daemon, not to attach".to_string());
+    }
+    if parsed.safe_output.is_some() {
+        return Err("--safe-output belongs to the daemon, not to attach".to_string());
+    }
+    if parsed.fallback.is_some() {
+        return Err("--fallback belongs to the daemon, not to attach".to_string());
+    }
+    if parsed.audit.is_some() {
+        return Err("--audit belongs to the daemon, not to attach".to_string());
+    }
+    if parsed.shadow.is_some() {
+        return Err("--shadow belongs to the daemon, not to attach".to_string());
+    }
+    let system = match &parsed.config {
+        Some(path) => load(path)?,
+        None => FuzzySystem::demo(),
+    };
+    Ok((parsed.socket, system))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MembershipFunction;
+    use crate::fixtures;
+    use crate::guard::TuningConfig;
+    use std::time::Instant;
+
+    /// Serve `system` on one end of a socketpair and return the other
+    fn serve(runtime: &Arc<Mutex<Runtime>>) -> UnixStream {
+        let (client, server) = UnixStream::pair().unwrap();
+        let runtime = runtime.clone();
+        std::thread::spawn(move || handle_client(server, runtime));
+        client
+    }
+
+    fn ask(client: &mut UnixStream, command: &str) -> String {
+        writeln!(client, "{}", command).unwrap();
+        let mut reply = String::new();
+        BufReader::new(client.try_clone().unwrap())
+            .read_line(&mut reply)
+            .unwrap();
+        reply.trim_end().to_string()
+    }
+
+    fn co2_system() -> FuzzySystem {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        config::parse_system(&fan.replacen(
+            "[[input]]",
+            "[[input]]\nname = \"co2\"\nmin = 0.0\nmax = 3000.0\n\n\
+             [[input.set]]\nname = \"Stale\"\nshape = \"triangular\"\n\
+             params = [1000.0, 3000.0, 3000.0]\n\n[[input]]",
+            1,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn state_lines_round_trip() {
+        let snapshot = Snapshot {
+            inputs: vec![
+                ("temperature".to_string(), 31.5),
+                ("co2".to_string(), 900.0),
+            ],
+            fan_speed: 62.5,
+            output: PipelineOutput {
+                value: 55.0,
+                band: FanBand::Medium,
+                pending: Some(Pending {
+                    band: FanBand::High,
+                    remaining_secs: 1.5,
+                }),
+            },
+            held: None,
+            windows: Vec::new(),
+            sources: Vec::new(),
+            lag: None,
+        };
+        assert_eq!(Snapshot::parse(&snapshot.to_line()), Some(snapshot.clone()));
+        let held = Snapshot {
+            held: Some(Held {
+                inputs: vec!["temperature".to_string(), "co2".to_string()],
+                remaining_secs: 12.5,
+            }),
+            ..snapshot
+        };
+        assert_eq!(Snapshot::parse(&held.to_line()), Some(held.clone()));
+        let windowed = Snapshot {
+            windows: vec![
+                Sampled {
+                    input: "temperature".to_string(),
+                    count: 10,
+                    spread: 0.25,
+                },
+                Sampled {
+                    input: "co2".to_string(),
+                    count: 0,
+                    spread: 0.0,
+                },
+            ],
+            ..held.clone()
+        };
+        assert_eq!(Snapshot::parse(&windowed.to_line()), Some(windowed.clone()));
+        let fused = Snapshot {
+            sources: vec![
+                Reported {
+                    input: "temperature".to_string(),
+                    source: "north".to_string(),
+                    value: 21.5,
+                    status: fusion::Status::Healthy,
+                },
+                Reported {
+                    input: "temperature".to_string(),
+                    source: "south".to_string(),
+                    value: 80.0,
+                    status: fusion::Status::OutOfRange,
+                },
+            ],
+            ..windowed
+        };
+        assert_eq!(Snapshot::parse(&fused.to_line()), Some(fused.clone()));
+        let late = Snapshot {
+            lag: Some(4.5),
+            ..fused
+        };
+        assert_eq!(Snapshot::parse(&late.to_line()), Some(late));
+        assert_eq!(Snapshot::parse("state 1 2 Low sources t.a:1"), None);
+        assert_eq!(Snapshot::parse("state 1 2 Low window t:1"), None);
+        let line = held.to_line().replacen(" High 1.5", "", 1);
+        assert_eq!(Snapshot::parse(&line).unwrap().held, held.held);
+        assert_eq!(Snapshot::parse("state 1 2"), None);
+        assert_eq!(Snapshot::parse("state 1 2 Low held 3"), None);
+    }
+
+    #[test]
+    fn an_optimizer_swaps_rule_weights_and_reverts_them() {
+        let runtime = Arc::new(Mutex::new(Runtime::new(fixtures::fan(), None).unwrap()));
+        let mut client = serve(&runtime);
+        let points: Vec<String> = [10.0, 25.0, 40.0]
+            .iter()
+            .flat_map(|t| [20.0, 50.0, 80.0].map(|h| format!("{},{}", t, h)))
+            .collect();
+        let points = points.join(" ");
+        let outputs = |reply: String| -> Vec<f64> {
+            let reply = reply.strip_prefix("outputs ").expect(&reply).to_string();
+            reply.split(' ').map(|o| o.parse().unwrap()).collect()
+        };
+
+        // What the optimizer should find: two rules damped
+        let mut target = fixtures::fan();
+        target.rules[4].weight = 0.3;
+        target.rules[8].weight = 0.5;
+        let target = FuzzyController::from_system(target);
+        let wanted: Vec<f64> = [10.0, 25.0, 40.0]
+            .iter()
+            .flat_map(|t| [20.0, 50.0, 80.0].map(|h| target.compute(&[*t, h])))
+            .collect();
+        let error =
+            |got: &[f64]| -> f64 { got.iter().zip(&wanted).map(|(a, b)| (a - b).powi(2)).sum() };
+
+        let original = outputs(ask(&mut client, &format!("evaluate {}", points)));
+        let mut weights = vec![1.0; 9];
+        let mut best = error(&original);
+        let start = best;
+        // Coordinate descent, one round trip per candidate
+        for _ in 0..2 {
+            for rule in 0..weights.len() {
+                for step in 0..=10 {
+                    let mut candidate = weights.clone();
+                    candidate[rule] = step as f64 / 10.0;
+                    let words: Vec<String> = candidate.iter().map(f64::to_string).collect();
+                    let reply = ask(
+                        &mut client,
+                        &format!("weights {} evaluate {}", words.join(" "), points),
+                    );
+                    let got = error(&outputs(reply));
+                    if got < best - 1e-12 {
+                        (best, weights) = (got, candidate);
+                    }
+                }
+            }
+        }
+        assert!(best < start / 100.0, "{} from {}", best, start);
+
+        // The daemon runs on the last proposal until told otherwise
+        let words: Vec<String> = weights.iter().map(f64::to_string).collect();
+        assert_eq!(
+            ask(&mut client, &format!("weights {}", words.join(" "))),
+            "ok"
+        );
+        assert_eq!(
+            ask(&mut client, "weights"),
+            format!("weights {}", words.join(" "))
+        );
+        assert_eq!(ask(&mut client, "set temperature 40"), "ok");
+        let state = Snapshot::parse(&ask(&mut client, "snapshot")).unwrap();
+        let mut found = fixtures::fan();
+        for (rule, weight) in found.rules.iter_mut().zip(&weights) {
+            rule.weight = *weight;
+        }
+        let found = FuzzyController::from_system(found);
+        assert_eq!(state.fan_speed, found.compute(&[40.0, 50.0]));
+
+        assert_eq!(ask(&mut client, "weights revert"), "ok");
+        assert_eq!(ask(&mut client, "weights"), "weights 1 1 1 1 1 1 1 1 1");
+        assert_eq!(
+            outputs(ask(&mut client, &format!("evaluate {}", points))),
+            original
+        );
+        assert_eq!(
+            ask(&mut client, "weights revert"),
+            "error no weights to revert"
+        );
+    }
+
+    #[test]
+    fn bad_weight_batches_change_nothing() {
+        let runtime = Arc::new(Mutex::new(Runtime::new(fixtures::fan(), None).unwrap()));
+        let mut client = serve(&runtime);
+        assert_eq!(
+            ask(&mut client, "weights 1 1"),
+            "error expected 9 weights, got 2"
+        );
+        assert_eq!(
+            ask(&mut client, "weights 1 1 1 1 1 1 1 1 2"),
+            "error weight '2' is not between 0 and 1"
+        );
+        assert_eq!(
+            ask(&mut client, "weights 0 0 0 0 0 0 0 0 0 evaluate 30"),
+            "error point '30' needs 2 numbers"
+        );
+        assert_eq!(ask(&mut client, "evaluate"), "error no points to evaluate");
+        assert_eq!(ask(&mut client, "weights"), "weights 1 1 1 1 1 1 1 1 1");
+        assert_eq!(
+            ask(&mut client, "weights revert"),
+            "error no weights to revert"
+        );
+    }
+
+    #[test]
+    fn binary_frames_stand_for_the_commands_after_the_handshake() {
+        let runtime = Arc::new(Mutex::new(Runtime::new(fixtures::fan(), None).unwrap()));
+        let mut client = serve(&runtime);
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        assert!(wire::negotiate(&mut reader, &mut client).unwrap());
+        let mut request = |message: Message| {
+            wire::write(&mut client, &message).unwrap();
+            wire::decode(&wire::read_frame(&mut reader).unwrap().unwrap()).unwrap()
+        };
+
+        assert_eq!(
+            request(Message::Init),
+            Message::Ready {
+                inputs: vec!["temperature".to_string(), "humidity".to_string()],
+                outputs: vec!["fan_speed".to_string()],
+            }
+        );
+        assert_eq!(
+            request(Message::SetInputs(vec![(0, 33.0), (1, 80.0)])),
+            Message::Ok
+        );
+        let Message::State(state) = request(Message::GetState) else {
+            panic!("no state");
+        };
+        assert_eq!(
+            state.outputs,
+            [FuzzyController::from_system(fixtures::fan()).compute(&[33.0, 80.0])]
+        );
+        let Message::State(again) = request(Message::Compute { time: 0.0 }) else {
+            panic!("no state");
+        };
+        assert_eq!((again.outputs, again.band), (state.outputs, state.band));
+        assert_eq!(
+            request(Message::SetInputs(vec![(0, 20.0), (5, 1.0)])),
+            Message::Error("unknown input index 5".to_string())
+        );
+        assert_eq!(
+            runtime.lock().unwrap().snapshot.input("temperature"),
+            Some(33.0)
+        );
+        assert_eq!(
+            request(Message::SetInputs(vec![(0, f64::NAN)])),
+            Message::Error("invalid number 'NaN'".to_string())
+        );
+        assert_eq!(
+            request(Message::Weights(vec![1.0])),
+            Message::Error("expected 9 weights, got 1".to_string())
+        );
+        assert_eq!(request(Message::Weights(vec![0.5; 9])), Message::Ok);
+        assert_eq!(
+            runtime.lock().unwrap().execute("weights"),
+            "weights 0.5 0.5 0.5 0.5 0.5 0.5 0.5 0.5 0.5"
+        );
+        assert_eq!(request(Message::Terminate), Message::Ok);
+        assert!(wire::read_frame(&mut reader).unwrap().is_none());
+
+        // Text from the second line on, and for a version it does not speak
+        let mut client = serve(&runtime);
+        assert_eq!(
+            ask(&mut client, "wire 2"),
+            "error unsupported wire version 2"
+        );
+        assert_eq!(ask(&mut client, "wire 1"), "error unknown command 'wire 1'");
+    }
+
+    #[test]
+    fn units_name_what_each_value_is_stored_in() {
+        let runtime = Arc::new(Mutex::new(Runtime::new(fixtures::fan(), None).unwrap()));
+        let mut client = serve(&runtime);
+        assert_eq!(
+            ask(&mut client, "units"),
+            "units temperature=\"°C\" humidity=\"%\" fan_speed=\"%\""
+        );
+    }
+
+    #[test]
+    fn values_take_either_decimal_separator() {
+        let runtime = Arc::new(Mutex::new(Runtime::new(fixtures::fan(), None).unwrap()));
+        let mut client = serve(&runtime);
+        assert_eq!(ask(&mut client, "set temperature 23,5"), "ok");
+        assert_eq!(
+            ask(&mut client, "set humidity 1,234"),
+            "error '1,234' is ambiguous: 1.234 or 1234? Type '.' for the decimal point"
+        );
+        assert_eq!(
+            ask(&mut client, "set humidity lots"),
+            "error invalid number 'lots'"
+        );
+        let state = Snapshot::parse(&ask(&mut client, "snapshot")).unwrap();
+        assert_eq!(state.input("temperature"), Some(23.5));
+    }
+
+    #[test]
+    fn a_client_sets_inputs_and_reads_the_state_back() {
+        let runtime = Arc::new(Mutex::new(Runtime::new(fixtures::fan(), None).unwrap()));
+        let mut client = serve(&runtime);
+        assert_eq!(ask(&mut client, "set temperature 35"), "ok");
+        assert_eq!(ask(&mut client, "set humidity 500"), "ok");
+        assert_eq!(ask(&mut client, "set co2 1"), "error unknown input 'co2'");
+
+        let state = Snapshot::parse(&ask(&mut client, "snapshot")).unwrap();
+        assert_eq!(state.input("temperature"), Some(35.0));
+        assert_eq!(state.input("humidity"), Some(100.0));
+        let expected = FuzzyController::from_system(fixtures::fan()).compute(&[35.0, 100.0]);
+        assert_eq!(state.fan_speed, expected);
+    }
+
+    #[test]
+    fn inputs_other_than_temperature_and_humidity_are_served() {
+        let system = co2_system();
+        let runtime = Arc::new(Mutex::new(Runtime::new(system.clone(), None).unwrap()));
+        let mut client = serve(&runtime);
+        assert_eq!(ask(&mut client, "set co2 2500"), "ok");
+        assert_eq!(ask(&mut client, "set humidity 80"), "ok");
+
+        let state = Snapshot::parse(&ask(&mut client, "snapshot")).unwrap();
+        assert_eq!(state.input("co2"), Some(2500.0));
+        assert_eq!(state.input("temperature"), Some(25.0));
+        assert_eq!(state.input("humidity"), Some(80.0));
+        let expected = FuzzyController::from_system(system).compute(&[2500.0, 25.0, 80.0]);
+        assert_eq!(state.fan_speed, expected);
+    }
+
+    #[test]
+    fn dewpoint_inputs_take_a_dew_point_and_publish_humidity() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let system = config::parse_system(&fan.replace(
+            "name = \"humidity\"\n",
+            "name = \"humidity\"\nkind = \"dewpoint\"\ntemperature = \"temperature\"\n",
+        ))
+        .unwrap();
+        let runtime = Arc::new(Mutex::new(Runtime::new(system, None).unwrap()));
+        let mut client = serve(&runtime);
+        assert_eq!(ask(&mut client, "set temperature 25"), "ok");
+        assert_eq!(ask(&mut client, "set humidity 20"), "ok");
+        let state = Snapshot::parse(&ask(&mut client, "snapshot")).unwrap();
+        let humidity = state.input("humidity").unwrap();
+        assert!((humidity - 73.8).abs() < 0.1, "{}", humidity);
+    }
+
+    #[test]
+    fn attached_clients_mirror_the_daemon_and_the_last_set_wins() {
+        let runtime = Arc::new(Mutex::new(Runtime::new(fixtures::fan(), None).unwrap()));
+        let mut first = RemoteLink::from_stream(serve(&runtime)).unwrap();
+        let mut second = RemoteLink::from_stream(serve(&runtime)).unwrap();
+        first.send_inputs(30.0, 70.0, false).unwrap();
+        // Give the first client's commands time to land before the second
+        std::thread::sleep(STATE_INTERVAL);
+        second.send_inputs(12.0, 40.0, false).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut seen = [None, None];
+        while seen.iter().any(|s: &Option<Snapshot>| {
+            s.as_ref().is_none_or(|s| {
+                (s.input("temperature"), s.input("humidity")) != (Some(12.0), Some(40.0))
+            })
+        }) {
+            assert!(Instant::now() < deadline, "last states {:?}", seen);
+            for (slot, link) in seen.iter_mut().zip([&first, &second]) {
+                if let Some(state) = link.latest() {
+                    *slot = Some(state);
+                }
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        for state in seen.iter().flatten() {
+            assert_eq!(
+                state.fan_speed,
+                FuzzyController::from_system(fixtures::fan()).compute(&[12.0, 40.0])
+            );
+        }
+
+        // Detaching one client leaves the other served
+        drop(first);
+        assert_eq!(
+            Snapshot::parse(&ask(&mut serve(&runtime), "snapshot"))
+                .unwrap()
+                .input("temperature"),
+            Some(12.0)
+        );
+    }
+
+    #[test]
+    fn reset_latches_clears_the_latches_and_saves_the_state() {
+        let path = std::env::temp_dir().join(format!("fuzzy-daemon-{}.toml", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let runtime = Arc::new(Mutex::new(
+            Runtime::new(fixtures::fan(), Some(path.clone())).unwrap(),
+        ));
+        let mut client = serve(&runtime);
+        assert_eq!(ask(&mut client, "set temperature 40"), "ok");
+        let latched = |runtime: &Arc<Mutex<Runtime>>| {
+            let runtime = runtime.lock().unwrap();
+            (0..runtime.controller.system.rules.len())
+                .filter(|i| runtime.rule_stats.get(*i).unwrap().latched)
+                .count()
+        };
+        assert!(latched(&runtime) > 0);
+
+        assert_eq!(ask(&mut client, "reset_latches"), "ok");
+        assert_eq!(latched(&runtime), 0);
+        let saved = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(saved.contains("latched = false") && !saved.contains("latched = true"));
+    }
+
+    #[test]
+    fn a_stuck_ticker_does_not_keep_the_state_from_being_saved() {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let path = dir.join(format!("fuzzy-shutdown-{}.toml", id));
+        let path = path.to_string_lossy().to_string();
+        let socket = dir.join(format!("fuzzy-shutdown-{}.sock", id));
+        let socket = socket.to_string_lossy().to_string();
+        std::fs::write(&socket, "").unwrap();
+        let runtime = Arc::new(Mutex::new(
+            Runtime::new(fixtures::fan(), Some(path.clone())).unwrap(),
+        ));
+        let mut client = serve(&runtime);
+        assert_eq!(ask(&mut client, "set temperature 40"), "ok");
+
+        let mut coordinator = Coordinator::default();
+        let ticker = std::thread::spawn(|| std::thread::sleep(Duration::from_secs(30)));
+        register_shutdown(&mut coordinator, &runtime, ticker, &socket, Some(0.0));
+        let start = Instant::now();
+        let report = coordinator.shutdown();
+        assert!(start.elapsed() < shutdown::DEFAULT_TIMEOUT + Duration::from_secs(2));
+        assert_eq!(
+            report.problems(),
+            ["ticker (stop inputs): still running after 2.0s, left behind"]
+        );
+
+        assert_eq!(
+            ask(&mut client, "set temperature 20"),
+            "error shutting down"
+        );
+        assert!(!std::path::Path::new(&socket).exists());
+        let output = runtime.lock().unwrap().snapshot.output;
+        assert_eq!((output.value, output.band), (0.0, FanBand::Off));
+        let system = fixtures::fan();
+        let saved = RuleUsageStats::load(&path, system.rule_texts()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!((0..system.rules.len()).any(|i| saved.get(i).unwrap().latched));
+    }
+
+    #[test]
+    fn readings_under_min_delta_are_counted_but_not_computed() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let system = config::parse_system(&fan.replacen(
+            "name = \"temperature\"\n",
+            "name = \"temperature\"\nmin_delta = 0.5\n",
+            1,
+        ))
+        .unwrap();
+        let runtime = Arc::new(Mutex::new(Runtime::new(system, None).unwrap()));
+        let mut client = serve(&runtime);
+        for reading in ["30", "30.2", "29.9", "30.3", "31"] {
+            assert_eq!(
+                ask(&mut client, &format!("set temperature {}", reading)),
+                "ok"
+            );
+        }
+        // The start-up computation, 30 and 31
+        assert_eq!(
+            ask(&mut client, "stats"),
+            "stats executed 3 suppressed 3 support_warnings 0 oscillating false amplitude 0 degraded 0 interlock none"
+        );
+        let state = Snapshot::parse(&ask(&mut client, "snapshot")).unwrap();
+        assert_eq!(state.input("temperature"), Some(31.0));
+    }
+
+    #[test]
+    fn the_caps_command_switches_profiles_and_refuses_unknown_ones() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let system = config::parse_system(&format!(
+            "{}\n[[cap]]\nprofile = \"quiet\"\noutput = \"fan_speed\"\nset = \"High\"\nmax = 0.3\n",
+            fan
+        ))
+        .unwrap();
+        let runtime = Arc::new(Mutex::new(Runtime::new(system, None).unwrap()));
+        let mut client = serve(&runtime);
+        assert_eq!(ask(&mut client, "set temperature 38"), "ok");
+        let uncapped = Snapshot::parse(&ask(&mut client, "snapshot")).unwrap();
+        assert_eq!(ask(&mut client, "caps"), "caps off quiet");
+        assert_eq!(ask(&mut client, "caps quiet"), "ok");
+        assert_eq!(ask(&mut client, "caps"), "caps quiet quiet");
+        let capped = Snapshot::parse(&ask(&mut client, "snapshot")).unwrap();
+        assert!(capped.fan_speed < uncapped.fan_speed);
+        assert!(ask(&mut client, "caps loud").starts_with("error"));
+        assert_eq!(ask(&mut client, "caps off"), "ok");
+        assert_eq!(ask(&mut client, "caps"), "caps off quiet");
+    }
+
+    #[test]
+    fn the_method_command_changes_one_output_and_refuses_what_it_cannot_take() {
+        let system = config::load_system("configs/fan.toml").unwrap();
+        let runtime = Arc::new(Mutex::new(Runtime::new(system, None).unwrap()));
+        let mut client = serve(&runtime);
+        assert_eq!(ask(&mut client, "set temperature 27.5"), "ok");
+        assert_eq!(ask(&mut client, "set humidity 64"), "ok");
+        let centroid = Snapshot::parse(&ask(&mut client, "snapshot")).unwrap();
+        assert_eq!(ask(&mut client, "method fan_speed weighted_average"), "ok");
+        let weighted = Snapshot::parse(&ask(&mut client, "snapshot")).unwrap();
+        assert_ne!(weighted.fan_speed, centroid.fan_speed);
+        assert!(ask(&mut client, "aggregation softmax 0.1").starts_with("error"));
+        assert_eq!(
+            ask(&mut client, "method fan_speed mean"),
+            "error unknown method 'mean'"
+        );
+        assert_eq!(
+            ask(&mut client, "method damper centroid"),
+            "error unknown output 'damper'"
+        );
+    }
+
+    #[test]
+    fn param_moves_warn_of_holes_and_refuse_past_max_hole() {
+        let system = FuzzySystem {
+            tuning: TuningConfig {
+                max_hole: Some(2.0),
+                ..TuningConfig::default()
+            },
+            ..fixtures::fan()
+        };
+        let runtime = Arc::new(Mutex::new(Runtime::new(system, None).unwrap()));
+        let mut client = serve(&runtime);
+        // Cold ends at 20
+        assert_eq!(ask(&mut client, "param temperature Mild a 19"), "ok");
+        assert_eq!(
+            ask(&mut client, "param temperature Mild a 21,5"),
+            "ok warn hole in temperature from 20 to 21.5"
+        );
+        assert_eq!(
+            ask(&mut client, "param temperature Mild a 22.5"),
+            "error would open a hole in temperature from 20 to 22.5, wider than max_hole 2"
+        );
+        let mild = runtime
+            .lock()
+            .unwrap()
+            .controller
+            .system
+            .input("temperature")
+            .unwrap()
+            .sets[1]
+            .function
+            .clone();
+        assert_eq!(
+            mild,
+            MembershipFunction::Triangular {
+                a: 21.5,
+                b: 22.5,
+                c: 30.0
+            }
+        );
+        // Closing the hole again is always allowed
+        assert_eq!(ask(&mut client, "param temperature Mild a 15"), "ok");
+    }
+
+    #[test]
+    fn a_broken_config_publishes_the_safe_output_until_it_loads() {
+        let dir = std::env::temp_dir().join(format!("fuzzy-safe-daemon-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("fan.toml").to_string_lossy().to_string();
+        let state = dir.join("state.toml").to_string_lossy().to_string();
+        std::fs::copy("tests/data/truncated.toml", &config).unwrap();
+
+        let started = safemode::start(Some(&config), None, config::load_system);
+        let retry = Retry::new(&config, started.error.unwrap(), 0.0);
+        let mut runtime = start_runtime(
+            Err((retry, started.system)),
+            Some(state.clone()),
+            Some(35.0),
+        );
+        assert_eq!(runtime.snapshot.output.value, 35.0);
+        runtime.raw[0] = 45.0;
+        runtime.compute();
+        assert_eq!(runtime.snapshot.output.value, 35.0);
+        // Nothing of the fallback's is saved over the configured state
+        assert_eq!(runtime.state_path, None);
+
+        std::fs::copy("tests/data/invalid.toml", &config).unwrap();
+        runtime.retry_config(safemode::RETRY_INTERVAL);
+        assert!(runtime.safe_mode.is_some());
+        std::fs::copy("configs/fan.toml", &config).unwrap();
+        runtime.retry_config(safemode::RETRY_INTERVAL + 1.0);
+        assert!(runtime.safe_mode.is_some(), "retried too soon");
+        runtime.retry_config(2.0 * safemode::RETRY_INTERVAL);
+        assert!(runtime.safe_mode.is_none());
+        assert_eq!(runtime.state_path, Some(state.clone()));
+        assert_ne!(runtime.snapshot.output.value, 35.0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn an_unreadable_state_file_is_left_alone() {
+        let state = std::env::temp_dir()
+            .join(format!("fuzzy-safe-state-{}.toml", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        std::fs::write(&state, "[[rule]\ntext = ").unwrap();
+        let mut runtime = start_runtime(Ok(fixtures::fan()), Some(state.clone()), None);
+        assert_eq!(runtime.state_path, None);
+        assert!(runtime.safe_mode.is_none());
+        runtime.save_state().unwrap();
+        assert_eq!(std::fs::read_to_string(&state).unwrap(), "[[rule]\ntext = ");
+        std::fs::remove_file(state).unwrap();
+    }
+
+    /// Time set by the test, shareable with the ticker's thread
+    struct TestClock(Arc<Mutex<f64>>);
+
+    impl Clock for TestClock {
+        fn now(&self) -> f64 {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn the_daemon_stages_follow_its_clock_like_the_stepper() {
+        let system = FuzzySystem {
+            pipeline: crate::pipeline::PipelineConfig {
+                rate_limit: 5.0,
+                min_off_secs: 10.0,
+                ..Default::default()
+            },
+            ..fixtures::fan()
+        };
+        let mut stepper = crate::stepper::Stepper::new(system.clone());
+        let mut runtime = Runtime::new(system, None).unwrap();
+        let time = Arc::new(Mutex::new(0.0));
+        runtime.clock = Box::new(TestClock(time.clone()));
+        // Start the stages over on the test's clock
+        runtime.pipeline = OutputPipeline::new(runtime.controller.system.pipeline.clone());
+        runtime.compute();
+        stepper.step_at(&runtime.raw, 0.0).unwrap();
+
+        for i in 1..=60 {
+            *time.lock().unwrap() = i as f64;
+            runtime.raw = vec![if i < 30 { 45.0 } else { 5.0 }, 50.0];
+            runtime.compute();
+            let step = stepper.step_at(&runtime.raw, i as f64).unwrap();
+            assert_eq!(runtime.snapshot.output, step.output);
+        }
+    }
+
+    #[test]
+    fn hand_set_inputs_hold_against_the_readings_until_they_end() {
+        let mut runtime = Runtime::new(fixtures::fan(), None).unwrap();
+        let time = Arc::new(Mutex::new(0.0));
+        runtime.clock = Box::new(TestClock(time.clone()));
+        let at = |runtime: &mut Runtime, secs: f64| {
+            *time.lock().unwrap() = secs;
+            runtime.tick();
+            runtime.snapshot.clone()
+        };
+
+        // A drawn value outlives the sensor's next reading
+        assert_eq!(runtime.execute("random"), "ok");
+        let drawn = runtime.snapshot.clone();
+        assert_eq!(runtime.execute("set temperature 21"), "ok held");
+        let state = at(&mut runtime, 10.0);
+        assert_eq!(state.inputs, drawn.inputs);
+        let held = state.held.unwrap();
+        assert_eq!(held.inputs, ["temperature", "humidity"]);
+        assert_eq!(held.remaining_secs, 20.0);
+
+        // On expiry the kept reading is taken up; the humidity had none
+        let state = at(&mut runtime, 30.0);
+        assert_eq!(state.held, None);
+        assert_eq!(state.input("temperature"), Some(21.0));
+        assert_eq!(state.input("humidity"), drawn.input("humidity"));
+        assert_eq!(runtime.cause, Cause::OverrideExpired);
+
+        // An override lasts as long as asked, or until released
+        assert_eq!(runtime.execute("override humidity 80 5"), "ok");
+        assert_eq!(runtime.execute("set humidity 40"), "ok held");
+        assert_eq!(runtime.execute("set temperature 25"), "ok");
+        assert_eq!(at(&mut runtime, 34.0).input("humidity"), Some(80.0));
+        assert_eq!(runtime.execute("release"), "ok");
+        assert_eq!(runtime.snapshot.input("humidity"), Some(40.0));
+        assert_eq!(runtime.execute("set humidity 45"), "ok");
+        assert!(
+            runtime
+                .execute("override humidity 80 0")
+                .starts_with("error")
+        );
+        assert!(
+            runtime
+                .execute("override co2 80")
+                .starts_with("error unknown input")
+        );
+
+        // With holds off, the readings are all there is
+        let mut system = fixtures::fan();
+        system.overrides.duration_secs = 0.0;
+        let mut runtime = Runtime::new(system, None).unwrap();
+        assert!(
+            runtime
+                .execute("random")
+                .starts_with("error random inputs are off")
+        );
+        assert!(
+            runtime
+                .execute("override humidity 80")
+                .starts_with("error overrides are off")
+        );
+        assert_eq!(runtime.execute("set humidity 80"), "ok");
+    }
+
+    #[test]
+    fn windowed_inputs_compute_once_per_window_on_the_aggregate() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let system = config::parse_system(&fan.replacen(
+            "name = \"temperature\"\n",
+            "name = \"temperature\"\nwindow_secs = 1.0\naggregate = \"median\"\n\
+             stale_windows = 2\n",
+            1,
+        ))
+        .unwrap();
+        let mut runtime = Runtime::new(system, None).unwrap();
+        let time = Arc::new(Mutex::new(0.0));
+        runtime.clock = Box::new(TestClock(time.clone()));
+        let executed = runtime.trigger.executed;
+
+        // Ten readings a tenth of a second apart, one of them a spike
+        for i in 0..10 {
+            *time.lock().unwrap() = 10.0 + i as f64 * 0.1;
+            runtime.tick();
+            let reading = if i == 4 { 45.0 } else { 30.0 + i as f64 * 0.1 };
+            assert_eq!(
+                runtime.execute(&format!("set temperature {}", reading)),
+                "ok"
+            );
+        }
+        assert_eq!(runtime.trigger.executed, executed);
+        *time.lock().unwrap() = 11.0;
+        runtime.tick();
+        assert_eq!(runtime.trigger.executed, executed + 1);
+        assert_eq!(runtime.cause, Cause::WindowClosed);
+        let state = Snapshot::parse(&runtime.execute("snapshot")).unwrap();
+        assert!((state.input("temperature").unwrap() - 30.55).abs() < 1e-9);
+        assert_eq!(state.windows[0].count, 10);
+        assert!(state.windows[0].spread > 4.0);
+        let expected = FuzzyController::from_system(runtime.controller.system.clone())
+            .compute(&[state.input("temperature").unwrap(), 50.0]);
+        assert_eq!(state.fan_speed, expected);
+
+        // The other inputs are read as before
+        assert_eq!(runtime.execute("set humidity 70"), "ok");
+        assert_eq!(runtime.snapshot.input("humidity"), Some(70.0));
+
+        // Silence holds the aggregate, then counts as a failed sensor
+        *time.lock().unwrap() = 12.0;
+        runtime.tick();
+        assert!((runtime.snapshot.input("temperature").unwrap() - 30.55).abs() < 1e-9);
+        *time.lock().unwrap() = 13.0;
+        runtime.tick();
+        assert!(runtime.snapshot.input("temperature").unwrap().is_nan());
+        assert_eq!(runtime.snapshot.windows[0].count, 0);
+    }
+
+    #[test]
+    fn late_inputs_are_paired_with_readings_taken_at_the_same_instant() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let system = config::parse_system(&fan.replacen(
+            "name = \"humidity\"\n",
+            "name = \"humidity\"\nlatency_secs = 4.0\n",
+            1,
+        ))
+        .unwrap();
+        let mut runtime = Runtime::new(system, None).unwrap();
+        let time = Arc::new(Mutex::new(0.0));
+        runtime.clock = Box::new(TestClock(time.clone()));
+        let executed = runtime.trigger.executed;
+
+        // The temperature taken at 10 waits for the humidity taken then,
+        // which arrives four seconds later
+        *time.lock().unwrap() = 10.0;
+        assert_eq!(runtime.execute("set temperature 30"), "ok");
+        assert_eq!(runtime.trigger.executed, executed);
+        *time.lock().unwrap() = 12.0;
+        runtime.tick();
+        assert_eq!(runtime.trigger.executed, executed);
+        *time.lock().unwrap() = 14.0;
+        assert_eq!(runtime.execute("set humidity 70"), "ok");
+        assert_eq!(runtime.trigger.executed, executed + 1);
+        assert_eq!(runtime.instant, Some((10.0, false)));
+        assert_eq!(runtime.snapshot.lag, Some(4.0));
+        let expected =
+            FuzzyController::from_system(runtime.controller.system.clone()).compute(&[30.0, 70.0]);
+        assert_eq!(runtime.snapshot.fan_speed, expected);
+
+        // Humidity going quiet releases the next instant after the wait,
+        // with the freshest reading it has
+        *time.lock().unwrap() = 20.0;
+        assert_eq!(runtime.execute("set temperature 10"), "ok");
+        *time.lock().unwrap() = 24.9;
+        runtime.tick();
+        assert_eq!(runtime.trigger.executed, executed + 1);
+        *time.lock().unwrap() = 25.0;
+        runtime.tick();
+        assert_eq!(runtime.trigger.executed, executed + 2);
+        assert_eq!(runtime.cause, Cause::AlignTimeout);
+        assert_eq!(runtime.instant, Some((20.0, true)));
+        assert_eq!(runtime.raw, [10.0, 70.0]);
+        let stats = runtime.execute("stats");
+        assert!(
+            stats.ends_with(" aligned 1 align_timeouts 1 late 0"),
+            "{}",
+            stats
+        );
+    }
+
+    #[test]
+    fn a_limit_cycle_shows_in_the_stats() {
+        let system = FuzzySystem {
+            oscillation: Some(crate::oscillation::OscillationConfig::default()),
+            ..fixtures::fan()
+        };
+        let mut runtime = Runtime::new(system, None).unwrap();
+        let time = Arc::new(Mutex::new(0.0));
+        runtime.clock = Box::new(TestClock(time.clone()));
+        let stats = |runtime: &mut Runtime| runtime.execute("stats");
+
+        // Steady, then the temperature toggling every five seconds
+        for i in 0..120 {
+            *time.lock().unwrap() = i as f64;
+            let cold = i < 60 || (i / 5) % 2 == 0;
+            runtime.raw = vec![if cold { 10.0 } else { 40.0 }, 50.0];
+            runtime.compute();
+            if i == 59 {
+                assert!(stats(&mut runtime).contains("oscillating false"));
+            }
+        }
+        let line = stats(&mut runtime);
+        let (_, amplitude) = line.split_once(" amplitude ").unwrap();
+        let amplitude: f64 = amplitude.split(' ').next().unwrap().parse().unwrap();
+        assert!(line.contains("oscillating true"), "{}", line);
+        assert!(amplitude > 40.0, "{}", line);
+    }
+
+    /// Time that moves 10 ms at every reading, so every computation runs
+    /// over a 2 ms budget
+    struct SlowClock(Mutex<f64>);
+
+    impl Clock for SlowClock {
+        fn now(&self) -> f64 {
+            let mut now = self.0.lock().unwrap();
+            *now += 0.01;
+            *now
+        }
+    }
+
+    #[test]
+    fn cycles_over_the_budget_count_as_degraded_in_the_stats() {
+        let system = FuzzySystem {
+            budget: Some(crate::budget::BudgetConfig::default()),
+            ..fixtures::fan()
+        };
+        let mut runtime = Runtime::new(system, None).unwrap();
+        assert!(runtime.execute("stats").contains(" degraded 0 "));
+        runtime.clock = Box::new(SlowClock(Mutex::new(0.0)));
+        for i in 0..4 {
+            runtime.raw = vec![20.0 + i as f64, 50.0];
+            runtime.compute();
+        }
+        // The first slow cycle still ran at the full rung
+        let line = runtime.execute("stats");
+        assert!(line.contains(" degraded 3 interlock none"), "{}", line);
+        let rung = runtime.budget.as_ref().map(Budget::rung);
+        assert_eq!(rung, Some(crate::budget::Rung::WeightedAverage));
+    }
+
+    #[test]
+    fn alarms_list_the_conditions_holding_on_the_daemon_clock() {
+        let mut system = fixtures::fan();
+        let when = "holds_for(temperature > 35, 30s)";
+        let alarm = crate::alarm::AlarmConfig {
+            name: "hot".to_string(),
+            when: crate::alarm::Expression::parse(when, &system).unwrap(),
+        };
+        system.alarms.push(alarm);
+        let mut runtime = Runtime::new(system, None).unwrap();
+        let time = Arc::new(Mutex::new(0.0));
+        runtime.clock = Box::new(TestClock(time.clone()));
+
+        for (t, temperature) in [(0.0, 40.0), (29.0, 40.0)] {
+            *time.lock().unwrap() = t;
+            runtime.raw = vec![temperature, 50.0];
+            runtime.compute();
+        }
+        assert_eq!(runtime.execute("alarms"), "alarms");
+        *time.lock().unwrap() = 30.0;
+        runtime.compute();
+        assert_eq!(
+            runtime.execute("alarms"),
+            format!("alarms \"hot\"={}", config::quote(when))
+        );
+        runtime.raw = vec![20.0, 50.0];
+        runtime.compute();
+        assert_eq!(runtime.execute("alarms"), "alarms");
+    }
+
+    #[test]
+    fn the_objective_command_reports_the_running_score() {
+        let mut runtime = Runtime::new(fixtures::fan(), None).unwrap();
+        assert_eq!(runtime.execute("objective"), "objective off");
+
+        let mut system = fixtures::fan();
+        let score = crate::alarm::Expression::parse("abs(temperature - 22)", &system).unwrap();
+        system.objective = Some(crate::objective::ObjectiveConfig {
+            score,
+            better: Default::default(),
+        });
+        // Starts at 25 (the middle of 0..50), then 30 and 21
+        let mut runtime = Runtime::new(system, None).unwrap();
+        runtime.execute("set temperature 30");
+        runtime.execute("set temperature 21");
+        assert_eq!(
+            runtime.execute("objective"),
+            "objective 1 mean 4 best 1 worst 8 count 3"
+        );
+    }
+
+    #[test]
+    fn the_audit_trail_records_each_stage_of_every_published_output() {
+        let system = FuzzySystem {
+            pipeline: crate::pipeline::PipelineConfig {
+                rate_limit: 10.0,
+                hysteresis: 4.0,
+                min_off_secs: 10.0,
+                ..Default::default()
+            },
+            ..fixtures::fan()
+        };
+        let mut runtime = Runtime::new(system, None).unwrap();
+        let time = Arc::new(Mutex::new(0.0));
+        runtime.clock = Box::new(TestClock(time.clone()));
+        runtime.pipeline = OutputPipeline::new(runtime.controller.system.pipeline.clone());
+        let path = std::env::temp_dir().join(format!("fuzzy-daemon-audit-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        runtime.audit = Some(AuditLog::open(path, audit::DEFAULT_SIZE, 0).unwrap());
+        let hot = runtime.controller.compute(&[33.0, 45.0]);
+        let cold = runtime.controller.compute(&[8.0, 45.0]);
+
+        runtime.raw = vec![33.0, 45.0];
+        runtime.compute();
+        for t in 1..=18 {
+            *time.lock().unwrap() = t as f64;
+            match t {
+                1 => assert_eq!(runtime.execute("set temperature 8"), "ok"),
+                9 => assert_eq!(runtime.execute("set temperature 33"), "ok"),
+                _ => {
+                    // Computes nothing, so causes nothing
+                    runtime.execute("stats");
+                    runtime.tick();
+                }
+            }
+        }
+
+        use FanBand::*;
+        let some = |band, secs| {
+            Some(Pending {
+                band,
+                remaining_secs: secs,
+            })
+        };
+        // Down at 10/s from hot: HIGH held to 63, LOW to 13; then up
+        // again, held off for 10 s from the fan turning off at 7 s
+        #[rustfmt::skip]
+        let expected = [
+            // session, defuzzified, limited, band, hysteresis, dwell, value, output band
+            (0.0, hot, hot, High, None, None, hot, High),
+            (1.0, cold, hot - 10.0, High, Some(Medium), None, hot - 10.0, High),
+            (2.0, cold, hot - 20.0, Medium, None, None, hot - 20.0, Medium),
+            (3.0, cold, hot - 30.0, Medium, None, None, hot - 30.0, Medium),
+            (4.0, cold, hot - 40.0, Low, None, None, hot - 40.0, Low),
+            (5.0, cold, hot - 50.0, Low, None, None, hot - 50.0, Low),
+            (6.0, cold, hot - 60.0, Low, Some(Off), None, hot - 60.0, Low),
+            (7.0, cold, cold, Off, None, None, cold, Off),
+            (9.0, hot, cold + 10.0, Off, Some(Low), None, cold + 10.0, Off),
+            (10.0, hot, cold + 20.0, Low, None, some(Low, 7.0), cold + 10.0, Off),
+            (12.0, hot, cold + 40.0, Medium, None, some(Medium, 5.0), cold + 10.0, Off),
+            (15.0, hot, hot, High, None, some(High, 2.0), cold + 10.0, Off),
+            (17.0, hot, hot, High, None, None, hot, High),
+        ];
+        let (records, problems) = audit::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(records.len(), expected.len());
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        for (record, row) in records.iter().zip(expected) {
+            let (session, defuzzified, limited, band, hysteresis, dwell, value, output_band) = row;
+            assert_eq!(record.session, session);
+            assert!(close(record.defuzzified, defuzzified), "{:?}", record);
+            assert!(
+                close(record.defuzzified + record.rate_limit, limited),
+                "{:?}",
+                record
+            );
+            assert_eq!(
+                (record.band, record.hysteresis),
+                (band, hysteresis),
+                "{:?}",
+                record
+            );
+            assert_eq!(record.dwell, dwell, "{:?}", record);
+            assert!(close(record.output.value, value), "{:?}", record);
+            assert_eq!(record.output.band, output_band, "{:?}", record);
+            let cause = match session {
+                0.0 => Cause::Start,
+                9.0.. => Cause::Command("set temperature 33".to_string()),
+                _ => Cause::Command("set temperature 8".to_string()),
+            };
+            assert_eq!(record.cause, cause);
+            assert_eq!((record.cascade, record.safe_output), (None, None));
+        }
+    }
+
+    #[test]
+    fn fused_sources_make_the_reading_and_raise_alarms_when_excluded() {
+        let fan = std::fs::read_to_string("configs/fan.toml").unwrap();
+        let source = |name: &str| {
+            format!(
+                "[[input.source]]\nname = \"{}\"\nmin = 0.0\nmax = 50.0\ntimeout_secs = 10.0\n",
+                name
+            )
+        };
+        let system = config::parse_system(&fan.replacen(
+            "[[input]]\nname = \"humidity\"",
+            &format!(
+                "{}\n{}\n[[input]]\nname = \"humidity\"",
+                source("north"),
+                source("south")
+            ),
+            1,
+        ))
+        .unwrap();
+        let mut runtime = Runtime::new(system, None).unwrap();
+        let time = Arc::new(Mutex::new(0.0));
+        runtime.clock = Box::new(TestClock(time.clone()));
+        let reading = |runtime: &mut Runtime| {
+            let state = Snapshot::parse(&runtime.execute("snapshot")).unwrap();
+            state.input("temperature").unwrap()
+        };
+
+        assert_eq!(runtime.execute("set temperature.north 20"), "ok");
+        assert_eq!(reading(&mut runtime), 20.0);
+        assert_eq!(runtime.execute("set temperature.south 24"), "ok");
+        assert_eq!(reading(&mut runtime), 22.0);
+        assert!(
+            runtime
+                .execute("set temperature 30")
+                .starts_with("error 'temperature' is fused")
+        );
+        assert!(
+            runtime
+                .execute("set temperature.east 30")
+                .starts_with("error")
+        );
+
+        // An implausible source is left out until it recovers
+        assert_eq!(runtime.execute("set temperature.south 90"), "ok");
+        assert_eq!(reading(&mut runtime), 20.0);
+        assert_eq!(
+            runtime.execute("alarms"),
+            "alarms \"temperature.south\"=\"90 is above 50\""
+        );
+        let state = Snapshot::parse(&runtime.execute("snapshot")).unwrap();
+        let statuses: Vec<_> = state.sources.iter().map(|s| (s.value, s.status)).collect();
+        assert_eq!(
+            statuses,
+            [
+                (20.0, fusion::Status::Healthy),
+                (90.0, fusion::Status::OutOfRange)
+            ]
+        );
+        for _ in 0..fusion::RECOVER {
+            runtime.execute("set temperature.south 22");
+        }
+        assert_eq!(runtime.execute("alarms"), "alarms");
+        assert_eq!(reading(&mut runtime), 21.0);
+
+        // With every source silent the input fails
+        *time.lock().unwrap() = 10.0;
+        runtime.tick();
+        assert_eq!(runtime.cause, Cause::SourceSilent);
+        assert!(reading(&mut runtime).is_nan());
+        assert_eq!(runtime.execute("set temperature.north 25"), "ok");
+        assert!(reading(&mut runtime).is_nan(), "not yet recovered");
+    }
+
+    #[test]
+    fn a_failing_exec_program_is_audited_and_raises_an_alarm() {
+        use crate::exec::{ExecConfig, Status, Template};
+        let failing = ExecConfig {
+            retries: 0,
+            ..ExecConfig::new(
+                Template::parse("sh -c \"echo no fan at {value} >&2; exit 4\"").unwrap(),
+            )
+        };
+        let system = FuzzySystem {
+            exec: Some(failing),
+            ..fixtures::fan()
+        };
+        let mut runtime = Runtime::new(system, None).unwrap();
+        let path = std::env::temp_dir().join(format!("fuzzy-daemon-exec-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        runtime.audit = Some(AuditLog::open(path, audit::DEFAULT_SIZE, 0).unwrap());
+        // The runs finish on the worker; ticks pick them up
+        let tick_until = |runtime: &mut Runtime, done: &dyn Fn(&str) -> bool| {
+            for _ in 0..500 {
+                runtime.tick();
+                if done(&runtime.execute("alarms")) {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            panic!("{}", runtime.execute("alarms"));
+        };
+        tick_until(&mut runtime, &|alarms| alarms != "alarms");
+        let value = runtime.snapshot.output.value;
+        let stderr = format!("no fan at {}", value);
+        assert_eq!(
+            runtime.execute("alarms"),
+            format!(
+                "alarms \"exec\"=\"exec of {} exited with 4: {}\"",
+                value, stderr
+            )
+        );
+
+        // A run that succeeds clears it
+        runtime.exec = Some(Exec::new(ExecConfig::new(Template::parse("true").unwrap())));
+        assert_eq!(runtime.execute("set temperature 8"), "ok");
+        tick_until(&mut runtime, &|alarms| alarms == "alarms");
+
+        let (records, problems) = audit::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(problems.is_empty(), "{:?}", problems);
+        let runs: Vec<_> = records.iter().filter_map(|r| r.exec.as_ref()).collect();
+        assert_eq!(runs.len(), 2);
+        assert_eq!((runs[0].value, runs[0].status), (value, Status::Exited(4)));
+        assert_eq!(runs[0].stderr, stderr);
+        assert_eq!(runs[1].status, Status::Exited(0));
+        assert_ne!(runs[1].value, value);
+    }
+
+    #[test]
+    fn a_shadow_runs_on_the_same_inputs_into_the_audit_and_stats() {
+        let mut runtime = Runtime::new(fixtures::fan(), None).unwrap();
+        let time = Arc::new(Mutex::new(0.0));
+        runtime.clock = Box::new(TestClock(time.clone()));
+        runtime.pipeline = OutputPipeline::new(runtime.controller.system.pipeline.clone());
+        // The rules calling for High call for Medium in the shadow
+        let mut lower = fixtures::fan();
+        for rule in &mut lower.rules {
+            for (_, set) in &mut rule.consequents {
+                if set == "High" {
+                    *set = "Medium".to_string();
+                }
+            }
+        }
+        let medium = FuzzyController::from_system(lower.clone()).compute(&[35.0, 80.0]);
+        assert!(!runtime.execute("stats").contains("shadow"));
+        runtime.shadow =
+            Some(Shadow::new("lower.toml", lower, &runtime.controller.system).unwrap());
+        let path = std::env::temp_dir().join(format!("fuzzy-daemon-shadow-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        runtime.audit = Some(AuditLog::open(path, audit::DEFAULT_SIZE, 0).unwrap());
+        let high = runtime.controller.compute(&[35.0, 80.0]);
+        let off = runtime.controller.compute(&[15.0, 30.0]);
+
+        // Hot and humid for 10 s, cold and dry for 20 s, hot and humid
+        // again for 10 s
+        runtime.raw = vec![35.0, 80.0];
+        runtime.compute();
+        for (secs, readings) in [(10.0, ["15", "30"]), (30.0, ["35", "80"])] {
+            *time.lock().unwrap() = secs;
+            assert_eq!(
+                runtime.execute(&format!("set temperature {}", readings[0])),
+                "ok"
+            );
+            assert_eq!(
+                runtime.execute(&format!("set humidity {}", readings[1])),
+                "ok"
+            );
+        }
+        *time.lock().unwrap() = 40.0;
+        runtime.tick();
+
+        let (records, problems) = audit::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(problems.is_empty(), "{:?}", problems);
+        let published: Vec<_> = records
+            .iter()
+            .map(|record| (record.session, record.output.value, record.shadow))
+            .collect();
+        let first = published.first().unwrap();
+        assert_eq!(*first, (0.0, high, Some((medium, FanBand::Medium))));
+        assert!(published.contains(&(10.0, off, Some((off, FanBand::Off)))));
+        assert_eq!(
+            published.last().unwrap(),
+            &(30.0, high, Some((medium, FanBand::Medium)))
+        );
+
+        let stats = runtime.execute("stats");
+        let field = |key: &str| -> f64 {
+            let words: Vec<&str> = stats.split_whitespace().collect();
+            let at = words.iter().position(|word| *word == key).unwrap();
+            words[at + 1].parse().unwrap()
+        };
+        assert!((field("shadow_mean_abs_diff") - (high - medium) / 2.0).abs() < 1e-9);
+        assert_eq!(field("shadow_band_differs"), 0.5);
+        let energy = 20.0 * (medium - high) / 100.0 / 3600.0;
+        assert!(
+            (field("shadow_energy_diff") - energy).abs() < 1e-12,
+            "{}",
+            stats
+        );
+    }
+
+    #[test]
+    fn interlocks_force_the_output_by_priority_and_release_through_the_dwell() {
+        let interlock = |name: &str, value, priority| crate::interlock::InterlockConfig {
+            name: name.to_string(),
+            value,
+            priority,
+            engaged: false,
+        };
+        let system = FuzzySystem {
+            pipeline: crate::pipeline::PipelineConfig {
+                rate_limit: 10.0,
+                min_off_secs: 10.0,
+                ..Default::default()
+            },
+            interlocks: vec![interlock("door", 0.0, 10), interlock("overtemp", 100.0, 20)],
+            ..fixtures::fan()
+        };
+        let mut runtime = Runtime::new(system, None).unwrap();
+        let time = Arc::new(Mutex::new(0.0));
+        runtime.clock = Box::new(TestClock(time.clone()));
+        runtime.pipeline = OutputPipeline::new(runtime.controller.system.pipeline.clone());
+        let path =
+            std::env::temp_dir().join(format!("fuzzy-daemon-interlock-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        runtime.audit = Some(AuditLog::open(path, audit::DEFAULT_SIZE, 0).unwrap());
+        let hot = runtime.controller.compute(&[33.0, 45.0]);
+        runtime.raw = vec![33.0, 45.0];
+        runtime.compute();
+        let output =
+            |runtime: &Runtime| (runtime.snapshot.output.value, runtime.snapshot.output.band);
+        let engaged = |runtime: &mut Runtime| {
+            let stats = runtime.execute("stats");
+            stats.rsplit(' ').next().unwrap().to_string()
+        };
+        assert_eq!(output(&runtime), (hot, FanBand::High));
+        assert_eq!(engaged(&mut runtime), "none");
+
+        // Engaged: off at once, whatever the rate limit says
+        *time.lock().unwrap() = 1.0;
+        assert_eq!(runtime.execute("interlock door on"), "ok");
+        assert_eq!(output(&runtime), (0.0, FanBand::Off));
+        assert_eq!(engaged(&mut runtime), "door");
+        // The higher priority wins while both are engaged
+        *time.lock().unwrap() = 2.0;
+        assert_eq!(runtime.execute("interlock overtemp on"), "ok");
+        assert_eq!(output(&runtime), (100.0, FanBand::High));
+        assert_eq!(
+            runtime.execute("interlocks"),
+            "interlocks \"door\"=on \"overtemp\"=on"
+        );
+        *time.lock().unwrap() = 3.0;
+        assert_eq!(runtime.execute("interlock overtemp off"), "ok");
+        assert_eq!(output(&runtime), (0.0, FanBand::Off));
+        assert_eq!(
+            runtime.execute("interlock window on"),
+            "error unknown interlock 'window' (door, overtemp)"
+        );
+
+        // Released: the rate limiter climbs from the forced value, the fan
+        // staying off for the 10 s dwell from t=3; by then the limiter is
+        // back at the fuzzy output
+        *time.lock().unwrap() = 4.0;
+        assert_eq!(runtime.execute("interlock door off"), "ok");
+        assert_eq!(engaged(&mut runtime), "none");
+        assert_eq!(output(&runtime), (10.0, FanBand::Off));
+        for t in 5..=14 {
+            *time.lock().unwrap() = t as f64;
+            runtime.tick();
+            if t < 13 {
+                assert_eq!(runtime.snapshot.output.band, FanBand::Off, "at {}", t);
+            }
+        }
+        assert_eq!(output(&runtime), (hot, FanBand::High));
+
+        let (records, problems) = audit::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(problems.is_empty(), "{:?}", problems);
+        let switched: Vec<_> = records
+            .iter()
+            .filter_map(|record| match &record.cause {
+                Cause::Interlock(switch) => Some((
+                    switch.as_str(),
+                    record.interlock.as_deref(),
+                    record.output.value,
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            switched[..4],
+            [
+                ("door on", Some("door"), 0.0),
+                ("overtemp on", Some("overtemp"), 100.0),
+                ("overtemp off", Some("door"), 0.0),
+                ("door off", None, 10.0),
+            ]
+        );
+        // The release is the cause of the way back, until the fuzzy output
+        assert_eq!(switched.last(), Some(&("door off", None, hot)));
+    }
+
+    #[test]
+    fn overlong_lines_drop_the_client() {
+        let runtime = Arc::new(Mutex::new(Runtime::new(fixtures::fan(), None).unwrap()));
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let handler = {
+            let runtime = runtime.clone();
+            std::thread::spawn(move || handle_client(server, runtime))
+        };
+        client.write_all(&vec![b'a'; MAX_LINE + 10]).unwrap();
+
+        let mut replies = String::new();
+        client.read_to_string(&mut replies).unwrap();
+        assert_eq!(replies, "error line too long\n");
+        assert!(handler.join().unwrap().is_ok());
+    }
+}
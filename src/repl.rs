@@ -0,0 +1,385 @@
+// ============================================================================
+// REPL - Modo de linha sem terminal
+// ============================================================================
+//
+// The TUI needs a terminal on both ends: it puts stdin in raw mode and
+// draws on stdout with escape sequences. Run from a script or CI, where
+// they are pipes, it would fail with an opaque error or garble the pipe,
+// so `main` asks `detect` first:
+//
+//     stdin      stdout     what runs
+//     terminal   terminal   the TUI
+//     terminal   pipe       the line mode below, for the TUI's own
+//                           invocation; `bundle --open` and `attach`
+//                           get the hint
+//     pipe       either     a hint naming the headless commands, exit
+//                           code NO_TERMINAL (3)
+//
+// The line mode, also `fuzzy_logic repl [--config FILE] [--locale NAME]`
+// on any stdin, reads readings a line at a time and answers each line with
+// the outputs:
+//
+//     temp=31 hum=70        ->  fan_speed=78.2%
+//
+// An input is named in full or by any prefix only it starts with; inputs
+// not named keep their reading, the middle of their universe at first. A
+// line with a mistake changes nothing. Values are read like typed numbers
+// (numbers.rs) and dew point inputs take a dew point, as in the daemon.
+// The outputs go to stdout, one line per line read; the prompt, `help`,
+// warnings and errors to stderr, so a pipe gets only the outputs. `quit`
+// or the end of the input ends it. There is no time here, so the outputs
+// are the controller's, before the output pipeline (pipeline.rs).
+
+use crate::numbers::{self, Locale};
+use crate::{FuzzyController, FuzzySystem, adapters, config};
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Exit code when the TUI is asked for without a terminal
+pub const NO_TERMINAL: i32 = 3;
+
+const USAGE: &str = "Usage: fuzzy_logic repl [--config FILE] [--locale auto|point|comma|system]";
+
+/// Commands that run without a terminal, with what they do
+const HEADLESS: [(&str, &str, bool); 14] = [
+    (
+        "repl",
+        "type readings, read outputs, a line at a time",
+        true,
+    ),
+    (
+        "batch",
+        "evaluate a CSV of readings",
+        cfg!(feature = "batch"),
+    ),
+    (
+        "cosim",
+        "step in lockstep over stdin/stdout",
+        cfg!(feature = "cosim"),
+    ),
+    (
+        "daemon",
+        "serve the controller on a Unix socket",
+        cfg!(all(unix, feature = "daemon")),
+    ),
+    ("check", "validate a config and run its scenarios", true),
+    ("surface", "print the control surface", true),
+    ("rules", "print or export the rule base", true),
+    ("explore", "sample the input space", true),
+    ("diff", "compare two configs", true),
+    ("migrate", "bring a config to the current format", true),
+    ("snapshot", "fingerprint the rule usage", true),
+    ("bundle --out", "write a bug report bundle", true),
+    (
+        "audit",
+        "read the daemon's audit trail",
+        cfg!(feature = "daemon"),
+    ),
+    ("fuzz", "fuzz the readers", true),
+];
+
+/// What the standard streams allow
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Terminal {
+    /// Both are terminals: the TUI
+    Full,
+    /// Someone types, but the output goes down a pipe: the line mode
+    Lines,
+    /// Nobody types
+    None,
+}
+
+/// `Terminal` for stdin and stdout being terminals or not
+pub fn detect(stdin: bool, stdout: bool) -> Terminal {
+    match (stdin, stdout) {
+        (true, true) => Terminal::Full,
+        (true, false) => Terminal::Lines,
+        (false, _) => Terminal::None,
+    }
+}
+
+/// `Terminal` of this process
+pub fn terminal() -> Terminal {
+    detect(io::stdin().is_terminal(), io::stdout().is_terminal())
+}
+
+/// Say why the TUI did not start and what runs without a terminal; the
+/// exit code
+pub fn no_terminal() -> i32 {
+    let mut hint = "error: the interactive interface needs a terminal, and stdin or \
+                    stdout is not one\nCommands that run without one:\n"
+        .to_string();
+    for (command, purpose, built) in HEADLESS {
+        if built {
+            hint.push_str(&format!("    fuzzy_logic {:<13} {}\n", command, purpose));
+        }
+    }
+    eprint!("{}", hint);
+    NO_TERMINAL
+}
+
+/// `repl` subcommand
+pub fn run(args: &[String]) -> i32 {
+    match parse_args(args, false) {
+        Ok((system, locale)) => serve(system, locale, false),
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            2
+        }
+    }
+}
+
+/// The TUI's own invocation, `args`, with stdout not a terminal
+pub fn fallback(args: &[String]) -> i32 {
+    eprintln!("stdout is not a terminal: reading lines instead ('help' explains, 'quit' ends)");
+    match parse_args(args, true) {
+        Ok((system, locale)) => serve(system, locale, true),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            2
+        }
+    }
+}
+
+/// The system and locale `args` ask for; with `tui`, the TUI's other
+/// options are passed over with a note
+fn parse_args(args: &[String], tui: bool) -> Result<(FuzzySystem, Locale), String> {
+    let mut system = FuzzySystem::demo();
+    let mut locale = Locale::Auto;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--no-color" | "--tutorial" if tui => {
+                eprintln!("note: {} needs the interactive interface", arg)
+            }
+            "--config" | "--locale" | "--state" | "--history" | "--fallback" | "--backups"
+            | "--shadow" => {
+                let value = iter
+                    .next()
+                    .ok_or_else(|| format!("{} needs a value", arg))?;
+                match arg.as_str() {
+                    "--config" => system = config::load_system(value)?,
+                    "--locale" => {
+                        locale = Locale::parse(value).ok_or(format!(
+                            "unknown locale '{}' (auto, point, comma or system)",
+                            value
+                        ))?
+                    }
+                    _ if tui => eprintln!("note: {} needs the interactive interface", arg),
+                    _ => return Err(format!("unknown argument '{}'", arg)),
+                }
+            }
+            other => return Err(format!("unknown argument '{}'", other)),
+        }
+    }
+    Ok((system, locale))
+}
+
+/// Run a session on the standard streams; the exit code
+fn serve(system: FuzzySystem, locale: Locale, prompt: bool) -> i32 {
+    let result = session(
+        FuzzyController::from_system(system),
+        locale,
+        io::stdin().lock(),
+        &mut io::stdout(),
+        &mut io::stderr(),
+        prompt,
+    );
+    match result {
+        Ok(()) => 0,
+        // The reader of the pipe went away
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => 0,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+/// Index of the input `name` calls, in full or by a prefix only it has
+fn resolve(system: &FuzzySystem, name: &str) -> Result<usize, String> {
+    let inputs = &system.inputs;
+    if let Some(i) = inputs.iter().position(|var| var.name == name) {
+        return Ok(i);
+    }
+    let matches: Vec<usize> = (0..inputs.len())
+        .filter(|&i| !name.is_empty() && inputs[i].name.starts_with(name))
+        .collect();
+    let names = |indices: &mut dyn Iterator<Item = usize>| {
+        indices
+            .map(|i| inputs[i].name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match matches[..] {
+        [i] => Ok(i),
+        [] => Err(format!(
+            "unknown input '{}' ({})",
+            name,
+            names(&mut (0..inputs.len()))
+        )),
+        _ => Err(format!(
+            "'{}' could be {}",
+            name,
+            names(&mut matches.iter().copied())
+        )),
+    }
+}
+
+/// New raw readings after `line`, or what is wrong with it
+fn assign(
+    system: &FuzzySystem,
+    raw: &[f64],
+    line: &str,
+    locale: Locale,
+) -> Result<Vec<f64>, String> {
+    let mut raw = raw.to_vec();
+    for word in line.split_whitespace() {
+        let (name, value) = word
+            .split_once('=')
+            .ok_or_else(|| format!("expected <input>=<value>, got '{}'", word))?;
+        let index = resolve(system, name)?;
+        raw[index] = numbers::parse(value, locale)
+            .map_err(|e| format!("{}: {}", system.inputs[index].name, e))?;
+    }
+    Ok(raw)
+}
+
+/// Read lines from `input` until `quit` or its end, answering readings
+/// with the outputs on `out` and everything else on `err`
+fn session(
+    controller: FuzzyController,
+    locale: Locale,
+    input: impl BufRead,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+    prompt: bool,
+) -> io::Result<()> {
+    let system = &controller.system;
+    let middles: Vec<f64> = (system.inputs.iter())
+        .map(|var| (var.min + var.max) / 2.0)
+        .collect();
+    let mut raw = adapters::raw_readings(&system.inputs, &middles);
+    let mut lines = input.lines();
+    loop {
+        if prompt {
+            write!(err, "> ")?;
+            err.flush()?;
+        }
+        let Some(line) = lines.next() else {
+            return Ok(());
+        };
+        let line = line?;
+        match line.trim() {
+            "" => continue,
+            "quit" | "exit" => return Ok(()),
+            "help" => {
+                let names: Vec<&str> = system.inputs.iter().map(|v| v.name.as_str()).collect();
+                writeln!(
+                    err,
+                    "<input>=<value> ... sets readings and prints the outputs; inputs: {}; \
+                     'quit' ends",
+                    names.join(", ")
+                )?;
+                continue;
+            }
+            line => match assign(system, &raw, line, locale) {
+                Ok(assigned) => raw = assigned,
+                Err(e) => {
+                    writeln!(err, "error: {}", e)?;
+                    continue;
+                }
+            },
+        }
+        let (values, warnings) = adapters::convert(&system.inputs, &raw);
+        let evaluation = controller.evaluate(&values);
+        for warning in warnings.iter().chain(&evaluation.diagnostics) {
+            writeln!(err, "warning: {}", warning)?;
+        }
+        let outputs: Vec<String> = (system.outputs.iter())
+            .zip(&evaluation.outputs)
+            .map(|(var, value)| format!("{}={}", var.name, var.unit.stored().format(*value)))
+            .collect();
+        writeln!(out, "{}", outputs.join(" "))?;
+        out.flush()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn run_session(lines: &str) -> (String, String) {
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let controller = FuzzyController::from_system(fixtures::fan());
+        session(
+            controller,
+            Locale::Auto,
+            lines.as_bytes(),
+            &mut out,
+            &mut err,
+            false,
+        )
+        .unwrap();
+        let text = |bytes: Vec<u8>| String::from_utf8(bytes).unwrap();
+        (text(out), text(err))
+    }
+
+    #[test]
+    fn the_streams_pick_the_interface() {
+        assert_eq!(detect(true, true), Terminal::Full);
+        assert_eq!(detect(true, false), Terminal::Lines);
+        assert_eq!(detect(false, true), Terminal::None);
+        assert_eq!(detect(false, false), Terminal::None);
+    }
+
+    #[test]
+    fn the_tuis_options_are_passed_over_in_line_mode_only() {
+        let args: Vec<String> = [
+            "--config",
+            "configs/fan.toml",
+            "--shadow",
+            "configs/fan.toml",
+        ]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+        assert!(parse_args(&args, true).is_ok());
+        assert_eq!(
+            parse_args(&args, false).unwrap_err(),
+            "unknown argument '--shadow'"
+        );
+    }
+
+    #[test]
+    fn lines_of_readings_answer_with_the_outputs() {
+        let controller = FuzzyController::from_system(fixtures::fan());
+        let expected = |t: f64, h: f64| {
+            let value = controller.evaluate(&[t, h]).outputs[0];
+            let unit = controller.system.outputs[0].unit.stored();
+            format!("fan_speed={}", unit.format(value))
+        };
+        let (out, err) = run_session(
+            "temp=31 hum=70\n\nhumidity=40,5\nhum=x\nco2=400\ntemperature=12 nonsense\nhelp\n\
+             h=50\nquit\ntemp=40\n",
+        );
+        // The line with a mistake left the temperature alone
+        assert_eq!(
+            out.lines().collect::<Vec<_>>(),
+            [
+                expected(31.0, 70.0),
+                expected(31.0, 40.5),
+                expected(31.0, 50.0)
+            ]
+        );
+        let errors: Vec<&str> = err.lines().collect();
+        assert!(errors[0].starts_with("error: humidity: "), "{}", errors[0]);
+        assert_eq!(
+            errors[1],
+            "error: unknown input 'co2' (temperature, humidity)"
+        );
+        assert_eq!(errors[2], "error: expected <input>=<value>, got 'nonsense'");
+        assert!(errors[3].contains("inputs: temperature, humidity"));
+        assert_eq!(errors.len(), 4);
+    }
+}
@@ -0,0 +1,62 @@
+// ============================================================================
+// RULE COLORS - Cores das regras
+// ============================================================================
+//
+// Every rule has a color of its own, taken from the palette's rule colors
+// (palette.rs) by its index, so the same rule looks the same wherever it
+// shows up:
+//
+//     rule table     a swatch before each rule
+//     regime strip   a row under each input chart, one cell per history
+//                    entry in the color of the rule that dominated it
+//     markers        the operating point on the control surface, on the
+//                    membership curves and the latest point of the input
+//                    charts, in the color of the rule dominating now
+//
+// The dominant rule is the one firing the strongest, the first of them on
+// a tie within the controller's tolerance (ties.rs); with no rule firing
+// there is none and markers take the palette's text color. Watching the
+// markers change color is watching the controller change regime.
+
+use crate::palette::Palette;
+use crate::ties;
+use ratatui::style::Color;
+
+/// Color of the rule at `index`, the palette's rule colors starting over
+/// after the last
+pub fn of(palette: &Palette<Color>, index: usize) -> Color {
+    palette.rules[index % palette.rules.len()]
+}
+
+/// Color of the dominant rule, the text color without one
+pub fn of_dominant(palette: &Palette<Color>, dominant: Option<usize>) -> Color {
+    dominant.map_or(palette.text, |index| of(palette, index))
+}
+
+/// The rule firing the strongest, the first of those within `tolerance`
+/// of it; none when no rule fires
+pub fn dominant(strengths: &[f64], tolerance: f64) -> Option<usize> {
+    ties::strongest(strengths.iter().copied(), tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::PaletteName;
+
+    #[test]
+    fn the_strongest_rule_dominates_and_keeps_its_color() {
+        assert_eq!(dominant(&[0.2, 0.7, 0.7, 0.1], ties::TOLERANCE), Some(1));
+        assert_eq!(dominant(&[0.0, 0.0], ties::TOLERANCE), None);
+        assert_eq!(dominant(&[], ties::TOLERANCE), None);
+        let palette = Palette::of(PaletteName::Classic).map(|_| Color::Reset);
+        let palette = Palette {
+            rules: vec![Color::Cyan, Color::Red, Color::Green],
+            ..palette
+        };
+        assert_eq!(of(&palette, 1), of(&palette, 4));
+        assert_ne!(of(&palette, 0), of(&palette, 2));
+        assert_eq!(of_dominant(&palette, Some(5)), Color::Green);
+        assert_eq!(of_dominant(&palette, None), palette.text);
+    }
+}
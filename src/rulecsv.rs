@@ -0,0 +1,481 @@
+// ============================================================================
+// RULE CSV - Regras em planilha
+// ============================================================================
+//
+// `rules export --csv FILE` writes the rule base as a CSV a spreadsheet
+// opens as a table, one rule per row; `rules import --csv FILE` reads it
+// back and prints the config with its rules replaced:
+//
+//     temperature,humidity,fan_speed,weight,group,enabled
+//     Cold,Low,Off,1,,
+//     Hot,,High,0.8,safety,true
+//
+// The header names the columns: an input's column holds the set the rule
+// requires of it (blank: any), an output's the set it concludes (blank:
+// nothing for that output), and the optional `weight` (blank: 1), `group`
+// (blank: none) and `enabled` columns the rule's weight and group. Rules
+// are switched on and off by group, so `enabled` sets the group's flag:
+// the rows of one group must agree on it, and a rule outside a group
+// cannot be disabled. Groups keep their weight, and those of the config
+// no row names are kept, empty.
+//
+// The rules have no hedges, so a cell such as `very Hot` is refused like
+// any unknown set. A rule that names one variable twice has no row and
+// stops the export. Weights take either decimal separator (numbers.rs),
+// and a header without commas but with semicolons, as spreadsheets write
+// under a decimal-comma locale, switches the separator to ';'.
+//
+// The import checks every cell before giving up and reports all the
+// problems at once, each at its spreadsheet coordinates:
+//
+//     row 3, column A (temperature): no set 'Hott' (Cold, Mild, Hot)
+//
+// The TUI reloads the rules from the CSV next to its config (`path_for`)
+// with 'L' in the rule table.
+
+use crate::config;
+use crate::numbers::{self, Locale};
+use crate::{FuzzyRule, FuzzySystem, FuzzyVariable, RuleGroup};
+
+const USAGE: &str = "Usage: fuzzy_logic rules export|import --csv FILE [--config FILE]";
+
+/// The optional columns after the variables
+const EXTRA: [&str; 3] = ["weight", "group", "enabled"];
+
+/// Rule CSV kept next to the config at `config_path`
+pub fn path_for(config_path: &str) -> String {
+    let stem = config_path.strip_suffix(".toml").unwrap_or(config_path);
+    format!("{}.rules.csv", stem)
+}
+
+/// Spreadsheet name of the zero-based column `index`: A, ..., Z, AA, ...
+fn column_name(index: usize) -> String {
+    let mut name = String::new();
+    let mut n = index + 1;
+    while n > 0 {
+        name.insert(0, (b'A' + ((n - 1) % 26) as u8) as char);
+        n = (n - 1) / 26;
+    }
+    name
+}
+
+/// A cell quoted when a spreadsheet would need it to be
+fn cell(text: &str, separator: char) -> String {
+    if text.contains([separator, '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Cells of one line, honouring quotes
+fn split(line: &str, separator: char) -> Vec<String> {
+    let mut cells = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let current = cells.last_mut().expect("never empty");
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => cells.push(String::new()),
+            c => current.push(c),
+        }
+    }
+    cells.into_iter().map(|c| c.trim().to_string()).collect()
+}
+
+/// The rule base of `system` as CSV
+pub fn export(system: &FuzzySystem) -> Result<String, String> {
+    let mut header: Vec<&str> = system
+        .inputs
+        .iter()
+        .chain(&system.outputs)
+        .map(|var| var.name.as_str())
+        .collect();
+    header.push("weight");
+    if !system.groups.is_empty() {
+        header.extend(["group", "enabled"]);
+    }
+    let row = |cells: Vec<String>| {
+        let cells: Vec<String> = cells.iter().map(|text| cell(text, ',')).collect();
+        cells.join(",") + "\n"
+    };
+    let mut out = row(header.iter().map(|name| name.to_string()).collect());
+
+    for (index, rule) in system.rules.iter().enumerate() {
+        let set_of = |pairs: &[(String, String)], var: &FuzzyVariable| {
+            let mut sets = pairs.iter().filter(|(name, _)| *name == var.name);
+            match (sets.next(), sets.next()) {
+                (_, Some(_)) => Err(format!(
+                    "rule {} names {} twice, which a CSV row cannot hold",
+                    index + 1,
+                    var.name
+                )),
+                (set, None) => Ok(set.map_or(String::new(), |(_, set)| set.clone())),
+            }
+        };
+        let mut cells = Vec::new();
+        for var in &system.inputs {
+            cells.push(set_of(&rule.conditions, var)?);
+        }
+        for var in &system.outputs {
+            cells.push(set_of(&rule.consequents, var)?);
+        }
+        cells.push(rule.weight.to_string());
+        if !system.groups.is_empty() {
+            let group = rule.group.as_deref().and_then(|name| system.group(name));
+            cells.push(group.map_or(String::new(), |g| g.name.clone()));
+            cells.push(group.map_or(String::new(), |g| g.enabled.to_string()));
+        }
+        out.push_str(&row(cells));
+    }
+    Ok(out)
+}
+
+/// What a header cell names
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Column<'a> {
+    Input(&'a FuzzyVariable),
+    Output(&'a FuzzyVariable),
+    Weight,
+    Group,
+    Enabled,
+}
+
+fn parse_enabled(text: &str) -> Option<bool> {
+    match text.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "1" => Some(true),
+        "false" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// `system` with the rules of the CSV `text` instead of its own, or every
+/// problem found
+pub fn import(text: &str, system: &FuzzySystem) -> Result<FuzzySystem, Vec<String>> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut lines = text.lines().enumerate();
+    let Some((_, header)) = lines.next() else {
+        return Err(vec!["the CSV is empty".to_string()]);
+    };
+    let separator = if !header.contains(',') && header.contains(';') {
+        ';'
+    } else {
+        ','
+    };
+    let mut errors = Vec::new();
+    let at = |row: usize, column: usize, name: &str| {
+        format!("row {}, column {} ({})", row, column_name(column), name)
+    };
+
+    let names = split(header, separator);
+    let mut columns = Vec::new();
+    for (c, name) in names.iter().enumerate() {
+        let column = if let Some(var) = system.input(name) {
+            Column::Input(var)
+        } else if let Some(var) = system.outputs.iter().find(|var| var.name == *name) {
+            Column::Output(var)
+        } else {
+            match name.to_ascii_lowercase().as_str() {
+                "weight" => Column::Weight,
+                "group" => Column::Group,
+                "enabled" => Column::Enabled,
+                _ => {
+                    errors.push(format!(
+                        "row 1, column {}: '{}' is no input, output or one of {}",
+                        column_name(c),
+                        name,
+                        EXTRA.join(", ")
+                    ));
+                    continue;
+                }
+            }
+        };
+        if names[..c].contains(name) {
+            errors.push(format!("{}: repeated column", at(1, c, name)));
+        }
+        columns.push((c, name.as_str(), column));
+    }
+    if !columns
+        .iter()
+        .any(|(_, _, k)| matches!(k, Column::Output(_)))
+    {
+        errors.push("row 1: no output column".to_string());
+    }
+
+    let mut rules = Vec::new();
+    let mut groups = system.groups.clone();
+    // Row that set each group's flag, to report the one that disagrees
+    let mut flags: Vec<(String, usize, bool)> = Vec::new();
+    for (index, line) in lines {
+        let row = index + 1;
+        let cells = split(line, separator);
+        if cells.iter().all(String::is_empty) {
+            continue;
+        }
+        if cells.len() > names.len() {
+            errors.push(format!(
+                "row {}: {} cells for {} columns",
+                row,
+                cells.len(),
+                names.len()
+            ));
+            continue;
+        }
+        let mut rule = FuzzyRule::new(&[], &[]);
+        let mut enabled = None;
+        let bad_cells = errors.len();
+        for &(c, name, column) in &columns {
+            let text = cells.get(c).map_or("", String::as_str);
+            if text.is_empty() {
+                continue;
+            }
+            let at = at(row, c, name);
+            match column {
+                Column::Input(var) | Column::Output(var) => {
+                    if var.set(text).is_none() {
+                        let sets: Vec<&str> = var.sets.iter().map(|s| s.name.as_str()).collect();
+                        let hedged = text
+                            .rsplit_once(' ')
+                            .filter(|(_, set)| var.set(set).is_some());
+                        errors.push(match hedged {
+                            Some((hedge, _)) => {
+                                format!("{}: the rules have no hedges such as '{}'", at, hedge)
+                            }
+                            None => format!("{}: no set '{}' ({})", at, text, sets.join(", ")),
+                        });
+                        continue;
+                    }
+                    let pair = (var.name.clone(), text.to_string());
+                    match column {
+                        Column::Input(_) => rule.conditions.push(pair),
+                        _ => rule.consequents.push(pair),
+                    }
+                }
+                Column::Weight => match numbers::parse(text, Locale::Auto) {
+                    Ok(weight) if (0.0..=1.0).contains(&weight) => rule.weight = weight,
+                    Ok(_) => errors.push(format!("{}: weight must be between 0 and 1", at)),
+                    Err(e) => errors.push(format!("{}: {}", at, e)),
+                },
+                Column::Group => rule.group = Some(text.to_string()),
+                Column::Enabled => match parse_enabled(text) {
+                    Some(flag) => enabled = Some((c, flag)),
+                    None => errors.push(format!("{}: expected true or false", at)),
+                },
+            }
+        }
+        // An empty side is only news when no cell of the row was refused
+        if errors.len() == bad_cells && rule.conditions.is_empty() {
+            errors.push(format!("row {}: the rule has no condition", row));
+        }
+        if errors.len() == bad_cells && rule.consequents.is_empty() {
+            errors.push(format!("row {}: the rule concludes nothing", row));
+        }
+        match (&rule.group, enabled) {
+            (None, Some((c, false))) => errors.push(format!(
+                "{}: only groups can be disabled, name one in the group column",
+                at(row, c, "enabled")
+            )),
+            (None, _) => {}
+            (Some(name), enabled) => {
+                if !groups.iter().any(|g| g.name == *name) {
+                    groups.push(RuleGroup {
+                        name: name.clone(),
+                        enabled: true,
+                        weight: 1.0,
+                    });
+                }
+                if let Some((c, flag)) = enabled {
+                    match flags.iter().find(|(group, ..)| group == name) {
+                        Some((_, first, set)) if *set != flag => errors.push(format!(
+                            "{}: group '{}' is {} in row {}",
+                            at(row, c, "enabled"),
+                            name,
+                            if *set { "enabled" } else { "disabled" },
+                            first
+                        )),
+                        Some(_) => {}
+                        None => flags.push((name.clone(), row, flag)),
+                    }
+                }
+            }
+        }
+        rules.push(rule);
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    for (name, _, flag) in flags {
+        if let Some(group) = groups.iter_mut().find(|g| g.name == name) {
+            group.enabled = flag;
+        }
+    }
+
+    let mut imported = system.clone();
+    imported.rules = rules;
+    imported.groups = groups;
+    imported.validate().map_err(|e| vec![e])?;
+    Ok(imported)
+}
+
+/// Read the CSV at `path` into `system`, the problems joined on one line
+pub fn load(path: &str, system: &FuzzySystem) -> Result<FuzzySystem, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    import(&text, system).map_err(|errors| format!("{}: {}", path, errors.join("; ")))
+}
+
+/// `rules export` and `rules import`
+pub fn run(args: &[String]) -> i32 {
+    let mut system = FuzzySystem::demo();
+    let mut csv = None;
+    let Some(action) = args.first().map(String::as_str) else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        let result = match (arg.as_str(), iter.next()) {
+            ("--config", Some(path)) => config::load_system(path).map(|s| system = s),
+            ("--csv", Some(path)) => {
+                csv = Some(path.clone());
+                Ok(())
+            }
+            _ => Err(USAGE.to_string()),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            return 2;
+        }
+    }
+    let Some(path) = csv else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+
+    match action {
+        "export" => {
+            let written = export(&system)
+                .and_then(|text| std::fs::write(&path, text).map_err(|e| e.to_string()));
+            match written {
+                Ok(()) => {
+                    eprintln!("{} rules written to {}", system.rules.len(), path);
+                    0
+                }
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    1
+                }
+            }
+        }
+        "import" => {
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) => {
+                    eprintln!("error: {}: {}", path, e);
+                    return 1;
+                }
+            };
+            match import(&text, &system) {
+                Ok(imported) => {
+                    print!("{}", config::to_toml(&imported, true));
+                    0
+                }
+                Err(errors) => {
+                    for error in &errors {
+                        eprintln!("{}: {}", path, error);
+                    }
+                    eprintln!("{} problems, nothing imported", errors.len());
+                    1
+                }
+            }
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn fan() -> FuzzySystem {
+        config::parse_system(&std::fs::read_to_string("configs/fan.toml").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn the_demo_rules_round_trip() {
+        let system = fixtures::fan();
+        let csv = export(&system).unwrap();
+        assert_eq!(
+            csv.lines().take(2).collect::<Vec<_>>(),
+            ["temperature,humidity,fan_speed,weight", "Cold,Low,Off,1"]
+        );
+        assert_eq!(import(&csv, &system).unwrap(), system);
+
+        // Groups, weights and a don't-care survive too
+        let mut grouped = fan();
+        grouped.rules[0].conditions.remove(1);
+        grouped.rules[1].weight = 0.25;
+        grouped.rules[2].group = Some("night".to_string());
+        grouped.groups.push(RuleGroup {
+            name: "night".to_string(),
+            enabled: false,
+            weight: 0.5,
+        });
+        let csv = export(&grouped).unwrap();
+        assert_eq!(csv.lines().nth(1), Some("Cold,,Off,1,,"));
+        assert_eq!(csv.lines().nth(3), Some("Cold,High,Low,1,night,false"));
+        assert_eq!(import(&csv, &grouped).unwrap(), grouped);
+    }
+
+    #[test]
+    fn every_bad_cell_is_reported_with_its_coordinates() {
+        let csv = "temperature;humidity;fan_speed;weight;group;enabled\n\
+                   Hott;Low;Off;0,5;;\n\
+                   very Hot;;;2;;false\n\
+                   ;High;;;;\n\
+                   \n\
+                   Mild;High;Medium;;comfort;true\n\
+                   Hot;High;High;;comfort;no\n";
+        assert_eq!(
+            import(csv, &fan()).unwrap_err(),
+            [
+                "row 2, column A (temperature): no set 'Hott' (Cold, Mild, Hot)",
+                "row 3, column A (temperature): the rules have no hedges such as 'very'",
+                "row 3, column D (weight): weight must be between 0 and 1",
+                "row 3, column F (enabled): only groups can be disabled, name one in the group column",
+                "row 4: the rule concludes nothing",
+                "row 7, column F (enabled): group 'comfort' is enabled in row 6",
+            ]
+        );
+        let unknown = import("temperature,speed\nCold,Off\n", &fan()).unwrap_err();
+        assert_eq!(
+            unknown,
+            [
+                "row 1, column B: 'speed' is no input, output or one of weight, group, enabled",
+                "row 1: no output column",
+                "row 2: the rule concludes nothing",
+            ]
+        );
+    }
+
+    #[test]
+    fn quoted_cells_and_spreadsheet_columns() {
+        assert_eq!(
+            split("\"a,b\",\"say \"\"hi\"\"\", c ", ','),
+            ["a,b", "say \"hi\"", "c"]
+        );
+        assert_eq!(cell("a,b", ','), "\"a,b\"");
+        assert_eq!(column_name(0), "A");
+        assert_eq!(column_name(25), "Z");
+        assert_eq!(column_name(27), "AB");
+        assert_eq!(path_for("configs/fan.toml"), "configs/fan.rules.csv");
+    }
+}
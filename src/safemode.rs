@@ -0,0 +1,228 @@
+// ============================================================================
+// SAFE MODE - Modo de segurança
+// ============================================================================
+//
+// A `--config` that cannot be read, parsed or validated no longer stops the
+// program before it controls anything. It starts on a fallback instead: the
+// `--fallback FILE` profile when one is given and loads, the built-in demo
+// controller otherwise, and says so:
+//
+//     TUI      a red "SAFE MODE — config error: ..." title bar; 'E' shows
+//              the whole error
+//     daemon   logs the error and, with `--safe-output VALUE`, publishes
+//              VALUE as its output rather than the fallback's; every
+//              RETRY_INTERVAL it loads the config again, and once it loads
+//              serves it and leaves safe mode
+//
+// A `--state` file that cannot be read is handled alike: the program runs
+// without it, and the file is left as it is for inspection rather than
+// overwritten with fresh statistics.
+//
+// Neither decision needs a terminal or a socket: `start` picks the system
+// and `Retry::poll` is driven by the caller's clock.
+
+use crate::FuzzySystem;
+use crate::logging::event;
+
+/// Seconds between attempts to load the config again, daemon only
+pub const RETRY_INTERVAL: f64 = 30.0;
+
+/// The system to start with, and why it is not the configured one
+#[derive(Debug)]
+pub struct Startup {
+    pub system: FuzzySystem,
+    /// Set when starting in safe mode
+    pub error: Option<String>,
+}
+
+/// Load `config` with `load`, falling back to `fallback` and then to the
+/// demo controller when it fails; without a config the demo is no error
+pub fn start(
+    config: Option<&str>,
+    fallback: Option<&str>,
+    load: fn(&str) -> Result<FuzzySystem, String>,
+) -> Startup {
+    let Some(path) = config else {
+        return Startup {
+            system: FuzzySystem::demo(),
+            error: None,
+        };
+    };
+    let mut error = match load(path) {
+        Ok(system) => {
+            return Startup {
+                system,
+                error: None,
+            };
+        }
+        Err(e) => e,
+    };
+    let system = match fallback.map(load) {
+        Some(Ok(system)) => system,
+        Some(Err(e)) => {
+            error.push_str(&format!("; fallback: {}", e));
+            FuzzySystem::demo()
+        }
+        None => FuzzySystem::demo(),
+    };
+    Startup {
+        system,
+        error: Some(error),
+    }
+}
+
+/// Title bar text for why a safe mode started, e.g. "config error: ...":
+/// its first line only
+pub fn banner(reason: &str) -> String {
+    format!("SAFE MODE — {}", reason.lines().next().unwrap_or_default())
+}
+
+/// Loading the config again while in safe mode
+#[derive(Debug, Clone)]
+pub struct Retry {
+    path: String,
+    next_at: f64,
+    /// Why the last attempt failed
+    pub error: String,
+}
+
+impl Retry {
+    /// First attempt RETRY_INTERVAL after `now`
+    pub fn new(path: &str, error: String, now: f64) -> Self {
+        Retry {
+            path: path.to_string(),
+            next_at: now + RETRY_INTERVAL,
+            error,
+        }
+    }
+
+    /// The config, once an attempt due at `now` loads it
+    pub fn poll(
+        &mut self,
+        now: f64,
+        load: fn(&str) -> Result<FuzzySystem, String>,
+    ) -> Option<FuzzySystem> {
+        if now < self.next_at {
+            return None;
+        }
+        self.next_at = now + RETRY_INTERVAL;
+        match load(&self.path) {
+            Ok(system) => {
+                event!(Info, "config {} loaded again, leaving safe mode", self.path);
+                Some(system)
+            }
+            Err(e) => {
+                self.error = e;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::fixtures;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn a_broken_config_starts_the_fallback_and_says_why() {
+        let fan = "configs/fan.toml".to_string();
+        let started = start(Some(&fan), None, config::load_system);
+        assert!(started.error.is_none());
+
+        for name in ["truncated.toml", "invalid.toml"] {
+            let path = fixture(name);
+            let started = start(Some(&path), None, config::load_system);
+            let error = started.error.unwrap();
+            assert!(error.starts_with(&path), "{}", error);
+            assert_eq!(started.system, fixtures::fan());
+        }
+        let error = start(Some(&fixture("invalid.toml")), None, config::load_system)
+            .error
+            .unwrap();
+        assert!(error.contains("a <= b <= c"), "{}", error);
+
+        // A configured fallback profile, and the demo when that fails too
+        let mut quiet = config::load_system(&fan).unwrap();
+        quiet.metadata.name = Some("quiet".to_string());
+        let quiet_path =
+            std::env::temp_dir().join(format!("fuzzy-safemode-{}.toml", std::process::id()));
+        std::fs::write(&quiet_path, config::to_toml(&quiet, true)).unwrap();
+        let quiet_path = quiet_path.to_string_lossy().to_string();
+        let started = start(
+            Some(&fixture("truncated.toml")),
+            Some(&quiet_path),
+            config::load_system,
+        );
+        assert_eq!(started.system.metadata.name.as_deref(), Some("quiet"));
+        let started = start(
+            Some(&fixture("truncated.toml")),
+            Some(&fixture("invalid.toml")),
+            config::load_system,
+        );
+        assert_eq!(started.system, fixtures::fan());
+        assert!(started.error.unwrap().contains("; fallback: "));
+        std::fs::remove_file(quiet_path).unwrap();
+
+        assert_eq!(
+            banner("config error: fan.toml: line 3: unexpected end\nmore"),
+            "SAFE MODE — config error: fan.toml: line 3: unexpected end"
+        );
+    }
+
+    #[test]
+    fn the_config_is_retried_every_interval_until_it_loads() {
+        let path =
+            std::env::temp_dir().join(format!("fuzzy-safemode-retry-{}.toml", std::process::id()));
+        let path_text = path.to_string_lossy().to_string();
+        std::fs::copy(fixture("truncated.toml"), &path).unwrap();
+        let started = start(Some(&path_text), None, config::load_system);
+        let mut retry = Retry::new(&path_text, started.error.unwrap(), 100.0);
+
+        // Not before the interval, and not again right after a failure
+        assert!(
+            retry
+                .poll(100.0 + RETRY_INTERVAL - 1.0, config::load_system)
+                .is_none()
+        );
+        std::fs::copy(fixture("invalid.toml"), &path).unwrap();
+        assert!(
+            retry
+                .poll(100.0 + RETRY_INTERVAL, config::load_system)
+                .is_none()
+        );
+        assert!(retry.error.contains("a <= b <= c"), "{}", retry.error);
+
+        std::fs::copy("configs/fan.toml", &path).unwrap();
+        assert!(
+            retry
+                .poll(100.0 + RETRY_INTERVAL + 1.0, config::load_system)
+                .is_none()
+        );
+        let system = retry.poll(100.0 + 2.0 * RETRY_INTERVAL, config::load_system);
+        assert_eq!(system.unwrap().inputs.len(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn loading_the_config_again_is_logged_once() {
+        let mut retry = Retry::new("configs/fan.toml", "config error".to_string(), 0.0);
+        let logged = crate::logging::capture(|| {
+            assert!(retry.poll(1.0, config::load_system).is_none());
+            assert!(retry.poll(RETRY_INTERVAL, config::load_system).is_some());
+        });
+        assert_eq!(
+            logged,
+            [(
+                log::Level::Info,
+                "safemode: config configs/fan.toml loaded again, leaving safe mode".to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,450 @@
+// ============================================================================
+// SCENARIOS - Cenários e traços de referência
+// ============================================================================
+//
+// Operating points worth keeping an eye on live next to their config, in
+// `<config>.scenarios.toml` (`configs/fan.scenarios.toml` for
+// `configs/fan.toml`):
+//
+//     [[scenario]]
+//     name = "hot and humid"
+//     inputs = [35.0, 80.0]      # raw readings, in declaration order
+//     expect = 82.5              # optional, the first output...
+//     tolerance = 0.5            # ...within this much (default 0.01)
+//     golden = true              # optional, pin the whole inference
+//
+// A golden scenario also pins what led to the output: `check CONFIG
+// --scenarios --bless` records every rule's firing strength, each
+// output's dominant set and the outputs themselves into the scenario
+//
+//     strengths = [0.0, 0.0, ...]
+//     dominant = ["High"]        # "none" when no rule concludes the output
+//     outputs = [82.531]
+//
+// and `check CONFIG --scenarios` (or 'g' in the TUI) compares against
+// them to TRACE_TOLERANCE, naming what drifted: "Rule 5 strength changed
+// 0.42 -> 0.38". That catches rule edits whose centroid only happens to
+// land close to the old one. Blessing rewrites the file from the model, so
+// comments in it are not kept.
+//
+// Without `--scenarios`, `check` follows the config's `ok` with the rule
+// base's complexity metrics (complexity.rs); `--crowd K` sets how many
+// rules firing at once count as crowded.
+
+use crate::config::{self, Section};
+use crate::{FuzzyController, adapters, complexity, dominant_set};
+
+/// How far a traced number may move before it counts as drifted
+pub const TRACE_TOLERANCE: f64 = 1e-6;
+
+const USAGE: &str = "Usage: fuzzy_logic check CONFIG [--crowd K] [--scenarios [--bless]]";
+
+/// What one inference did, as recorded by a bless
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    pub strengths: Vec<f64>,
+    pub dominant: Vec<String>,
+    pub outputs: Vec<f64>,
+}
+
+impl Trace {
+    /// Run `controller` on raw `inputs`
+    pub fn capture(controller: &FuzzyController, inputs: &[f64]) -> Self {
+        let system = &controller.system;
+        let (inputs, _) = adapters::convert(&system.inputs, inputs);
+        let evaluation = controller.evaluate(&inputs);
+        let dominant = system
+            .outputs
+            .iter()
+            .map(|output| {
+                dominant_set(
+                    &evaluation.strengths,
+                    &system.rules,
+                    output,
+                    system.defuzzification.tie_tolerance,
+                )
+                .unwrap_or("none")
+                .to_string()
+            })
+            .collect();
+        Trace {
+            strengths: evaluation.strengths,
+            dominant,
+            outputs: evaluation.outputs,
+        }
+    }
+
+    /// What differs in `actual`, one readable line per change
+    pub fn diff(&self, actual: &Trace, output_names: &[&str]) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.strengths.len() != actual.strengths.len() {
+            changes.push(format!(
+                "rule count changed {} -> {}, bless again",
+                self.strengths.len(),
+                actual.strengths.len()
+            ));
+        } else {
+            for (i, (old, new)) in self.strengths.iter().zip(&actual.strengths).enumerate() {
+                if (old - new).abs() > TRACE_TOLERANCE {
+                    changes.push(format!(
+                        "Rule {} strength changed {} -> {}",
+                        i + 1,
+                        number(*old),
+                        number(*new)
+                    ));
+                }
+            }
+        }
+        let name = |i: usize| output_names.get(i).copied().unwrap_or("output");
+        for (i, (old, new)) in self.dominant.iter().zip(&actual.dominant).enumerate() {
+            if old != new {
+                changes.push(format!(
+                    "{} dominant set changed {} -> {}",
+                    name(i),
+                    old,
+                    new
+                ));
+            }
+        }
+        for (i, (old, new)) in self.outputs.iter().zip(&actual.outputs).enumerate() {
+            if (old - new).abs() > TRACE_TOLERANCE {
+                changes.push(format!(
+                    "{} changed {} -> {}",
+                    name(i),
+                    number(*old),
+                    number(*new)
+                ));
+            }
+        }
+        changes
+    }
+}
+
+/// Shortest form to the traced precision
+fn number(value: f64) -> String {
+    let text = format!("{:.6}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pub name: String,
+    pub inputs: Vec<f64>,
+    pub expect: Option<f64>,
+    pub tolerance: f64,
+    pub golden: bool,
+    /// Recorded by the last bless, for golden scenarios
+    pub trace: Option<Trace>,
+}
+
+impl Scenario {
+    /// Problems found running `controller` here; none when it passes
+    pub fn check(&self, controller: &FuzzyController) -> Vec<String> {
+        let system = &controller.system;
+        if self.inputs.len() != system.inputs.len() {
+            return vec![format!(
+                "needs {} inputs, the system has {}",
+                self.inputs.len(),
+                system.inputs.len()
+            )];
+        }
+        let actual = Trace::capture(controller, &self.inputs);
+        let mut problems = Vec::new();
+        if let Some(expect) = self.expect
+            && (actual.outputs[0] - expect).abs() > self.tolerance
+        {
+            problems.push(format!(
+                "{} is {}, expected {} ± {}",
+                system.output().name,
+                number(actual.outputs[0]),
+                number(expect),
+                number(self.tolerance)
+            ));
+        }
+        if self.golden {
+            match &self.trace {
+                Some(trace) => {
+                    let names: Vec<&str> = system.outputs.iter().map(|v| v.name.as_str()).collect();
+                    problems.extend(trace.diff(&actual, &names));
+                }
+                None => problems.push("golden trace not blessed yet".to_string()),
+            }
+        }
+        problems
+    }
+
+    /// Record the golden trace from `controller`
+    pub fn bless(&mut self, controller: &FuzzyController) {
+        if self.golden && self.inputs.len() == controller.system.inputs.len() {
+            self.trace = Some(Trace::capture(controller, &self.inputs));
+        }
+    }
+}
+
+/// Scenario file kept next to the config at `config_path`
+pub fn path_for(config_path: &str) -> String {
+    let stem = config_path.strip_suffix(".toml").unwrap_or(config_path);
+    format!("{}.scenarios.toml", stem)
+}
+
+fn scenario_from_section(section: &Section) -> Result<Scenario, String> {
+    let expect = match section.get("expect") {
+        Some(_) => Some(section.num("expect")?),
+        None => None,
+    };
+    let tolerance = section.num_or("tolerance", 0.01)?;
+    if tolerance.is_nan() || tolerance < 0.0 {
+        return Err(format!(
+            "line {}: 'tolerance' must not be negative",
+            section.line
+        ));
+    }
+    let trace = match section.get("strengths") {
+        None => None,
+        Some(_) => {
+            let dominant: Vec<String> = section
+                .strs("dominant")?
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect();
+            Some(Trace {
+                strengths: section.nums("strengths")?,
+                dominant,
+                outputs: section.nums("outputs")?,
+            })
+        }
+    };
+    Ok(Scenario {
+        name: section.str("name")?.to_string(),
+        inputs: section.nums("inputs")?,
+        expect,
+        tolerance,
+        golden: section.bool_or("golden", false)?,
+        trace,
+    })
+}
+
+pub fn parse(text: &str) -> Result<Vec<Scenario>, String> {
+    config::parse_document(text)?
+        .iter()
+        .filter(|section| !(section.header.is_empty() && section.entries.is_empty()))
+        .map(
+            |section| match (section.header.as_str(), section.is_array) {
+                ("scenario", true) => scenario_from_section(section),
+                (header, _) => Err(format!(
+                    "line {}: unknown section '{}'",
+                    section.line, header
+                )),
+            },
+        )
+        .collect()
+}
+
+pub fn load(path: &str) -> Result<Vec<Scenario>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse(&text).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn numbers(values: &[f64]) -> String {
+    let items: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+pub fn to_toml(scenarios: &[Scenario]) -> String {
+    let mut out = String::new();
+    for scenario in scenarios {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!(
+            "[[scenario]]\nname = {}\ninputs = {}\n",
+            config::quote(&scenario.name),
+            numbers(&scenario.inputs)
+        ));
+        if let Some(expect) = scenario.expect {
+            out.push_str(&format!(
+                "expect = {:?}\ntolerance = {:?}\n",
+                expect, scenario.tolerance
+            ));
+        }
+        if scenario.golden {
+            out.push_str("golden = true\n");
+        }
+        if let Some(trace) = &scenario.trace {
+            let dominant: Vec<String> = trace.dominant.iter().map(|s| config::quote(s)).collect();
+            out.push_str(&format!(
+                "strengths = {}\ndominant = [{}]\noutputs = {}\n",
+                numbers(&trace.strengths),
+                dominant.join(", "),
+                numbers(&trace.outputs)
+            ));
+        }
+    }
+    out
+}
+
+/// Check every scenario, one line per problem, prefixed with its name
+pub fn check_all(scenarios: &[Scenario], controller: &FuzzyController) -> Vec<String> {
+    scenarios
+        .iter()
+        .flat_map(|scenario| {
+            scenario
+                .check(controller)
+                .into_iter()
+                .map(move |problem| format!("{}: {}", scenario.name, problem))
+        })
+        .collect()
+}
+
+/// `check` subcommand. Exit code 0 when the config loads and every
+/// scenario passes, 1 when a scenario drifted and 2 on errors.
+pub fn run(args: &[String]) -> i32 {
+    let (mut path, mut scenarios, mut bless) = (None, false, false);
+    let mut crowd = complexity::CROWD;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--scenarios" => scenarios = true,
+            "--bless" => bless = true,
+            "--crowd" => match iter.next().and_then(|k| k.parse().ok()) {
+                Some(k) => crowd = k,
+                None => {
+                    eprintln!("error: --crowd needs a number of rules");
+                    return 2;
+                }
+            },
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg.as_str()),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    let (Some(path), true) = (path, scenarios || !bless) else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+
+    let controller = match config::load_system(path) {
+        Ok(system) => FuzzyController::from_system(system),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 2;
+        }
+    };
+    if !scenarios {
+        println!("{}: ok", path);
+        let report = complexity::analyze(&controller.system, crowd);
+        for line in report.lines(complexity::LISTED) {
+            println!("{}", line);
+        }
+        return 0;
+    }
+    let scenario_path = path_for(path);
+    let mut list = match load(&scenario_path) {
+        Ok(list) => list,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return 2;
+        }
+    };
+
+    if bless {
+        for scenario in &mut list {
+            scenario.bless(&controller);
+        }
+        if let Err(e) = std::fs::write(&scenario_path, to_toml(&list)) {
+            eprintln!("error: {}: {}", scenario_path, e);
+            return 2;
+        }
+        let blessed = list.iter().filter(|s| s.golden).count();
+        println!("Blessed {} golden traces in {}", blessed, scenario_path);
+        return 0;
+    }
+
+    let problems = check_all(&list, &controller);
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    let failed = list
+        .iter()
+        .filter(|s| {
+            problems
+                .iter()
+                .any(|p| p.starts_with(&format!("{}: ", s.name)))
+        })
+        .count();
+    println!(
+        "{} scenarios, {} passed, {} failed",
+        list.len(),
+        list.len() - failed,
+        failed
+    );
+    if failed > 0 { 1 } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fan() -> FuzzyController {
+        let text = std::fs::read_to_string("configs/fan.toml").unwrap();
+        FuzzyController::from_system(config::parse_system(&text).unwrap())
+    }
+
+    #[test]
+    fn a_blessed_trace_names_the_rule_that_drifted() {
+        let mut scenarios = parse(
+            "[[scenario]]\nname = \"mild\"\ninputs = [24.0, 55.0]\ngolden = true\n\n\
+             [[scenario]]\nname = \"cold\"\ninputs = [5.0, 20.0]\nexpect = 10.0\n",
+        )
+        .unwrap();
+        let mut controller = fan();
+        assert_eq!(
+            scenarios[0].check(&controller),
+            ["golden trace not blessed yet"]
+        );
+        for scenario in &mut scenarios {
+            scenario.bless(&controller);
+        }
+        assert!(scenarios[1].trace.is_none());
+        // The trace survives the file
+        let scenarios = parse(&to_toml(&scenarios)).unwrap();
+        assert_eq!(scenarios[0].check(&controller), Vec::<String>::new());
+
+        // Rule 5 (Mild/Medium) fires here; halving its weight drifts it
+        let before = Trace::capture(&controller, &[24.0, 55.0]).strengths[4];
+        controller.system.rules[4].weight = 0.5;
+        let problems = scenarios[0].check(&controller);
+        assert_eq!(
+            problems[0],
+            format!(
+                "Rule 5 strength changed {} -> {}",
+                number(before),
+                number(before / 2.0)
+            )
+        );
+        assert!(
+            problems
+                .iter()
+                .all(|p| !p.starts_with("Rule ") || p.starts_with("Rule 5 "))
+        );
+    }
+
+    #[test]
+    fn plain_scenarios_check_the_output_only() {
+        let scenarios = parse(
+            "[[scenario]]\nname = \"cold\"\ninputs = [5.0, 20.0]\nexpect = 50.0\ntolerance = 1\n",
+        )
+        .unwrap();
+        let problems = check_all(&scenarios, &fan());
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0].starts_with("cold: fan_speed is "),
+            "{}",
+            problems[0]
+        );
+        assert!(problems[0].ends_with("expected 50 ± 1"), "{}", problems[0]);
+        assert_eq!(path_for("configs/fan.toml"), "configs/fan.scenarios.toml");
+    }
+}
@@ -0,0 +1,290 @@
+// ============================================================================
+// SCHEMA - Versões do formato de configuração
+// ============================================================================
+//
+// A config file may say which version of the format it was written for,
+// as a top-level key before any section:
+//
+//     schema = 1
+//
+// A file without it is schema 1, the format as it has been so far: every
+// key added since the first config (groups, units, metadata, caps, ...)
+// was optional, so every file written until now still loads, and there is
+// nothing to migrate yet. There is no JSON form of the config.
+//
+// When the format next changes in a way old files would not load, the
+// change becomes a `Migration` from one schema to the next in `MIGRATIONS`.
+// Migrations work on the text, so comments and the order of everything
+// they do not touch survive. Loading an older file migrates it in memory
+// and says so on stderr, without touching the file; `fuzzy_logic migrate
+// FILE` rewrites it (keeping the original as FILE.bak) or writes the
+// result to `--out`. A file newer than this program is refused with the
+// schema it needs. Files the program writes carry the current schema.
+
+use crate::backup;
+use crate::config;
+
+/// The schema this program writes and reads natively
+pub const CURRENT: u32 = 1;
+
+pub const USAGE: &str = "Usage: fuzzy_logic migrate FILE [--out FILE]";
+
+/// One step of the format, from schema `from` to `from + 1`
+pub struct Migration {
+    pub from: u32,
+    /// What changed, for the messages
+    pub what: &'static str,
+    pub apply: fn(&str) -> Result<String, String>,
+}
+
+/// Every step so far, oldest first
+const MIGRATIONS: &[Migration] = &[];
+
+/// A config text brought to the current schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upgrade {
+    pub text: String,
+    /// The schema the text declared
+    pub from: u32,
+    /// What each migration applied changed, in order
+    pub steps: Vec<&'static str>,
+}
+
+/// The schema `text` declares, 1 without a `schema` key
+pub fn version(text: &str) -> Result<u32, String> {
+    let sections = config::parse_document(text)?;
+    let top = &sections[0];
+    if top.get("schema").is_none() {
+        return Ok(1);
+    }
+    let line = top.line_of("schema");
+    let schema = top.num("schema")?;
+    if schema.fract() != 0.0 || schema < 1.0 {
+        return Err(format!(
+            "line {}: 'schema' must be a whole number from 1",
+            line
+        ));
+    }
+    let schema = schema as u32;
+    if schema > CURRENT {
+        return Err(format!(
+            "line {}: schema {} is newer than this program reads (up to {}); upgrade fuzzy_logic",
+            line, schema, CURRENT
+        ));
+    }
+    Ok(schema)
+}
+
+/// `text` brought to the current schema, in memory
+pub fn upgrade(text: &str) -> Result<Upgrade, String> {
+    upgrade_with(text, MIGRATIONS)
+}
+
+fn upgrade_with(text: &str, migrations: &[Migration]) -> Result<Upgrade, String> {
+    let from = version(text)?;
+    let mut upgrade = Upgrade {
+        text: text.to_string(),
+        from,
+        steps: Vec::new(),
+    };
+    for migration in migrations.iter().filter(|m| m.from >= from) {
+        upgrade.text = (migration.apply)(&upgrade.text)
+            .map_err(|e| format!("schema {}: {}", migration.from, e))?;
+        upgrade.steps.push(migration.what);
+    }
+    Ok(upgrade)
+}
+
+/// `text` with its top-level `schema` set to `schema`: the line replaced
+/// where it is, or added as the first line
+pub fn stamp(text: &str, schema: u32) -> String {
+    let line = format!("schema = {}", schema);
+    let mut lines: Vec<&str> = text.lines().collect();
+    // Top-level keys come before the first section header
+    let top = lines
+        .iter()
+        .position(|l| l.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    match lines[..top].iter().position(|l| {
+        l.trim_start()
+            .strip_prefix("schema")
+            .is_some_and(|rest| rest.trim_start().starts_with('='))
+    }) {
+        Some(i) => lines[i] = &line,
+        None => lines.insert(0, &line),
+    }
+    lines.join("\n") + "\n"
+}
+
+/// `migrate` subcommand. Exit code 0 on success, 2 on errors.
+pub fn run(args: &[String]) -> i32 {
+    let mut path = None;
+    let mut out = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--out" => match iter.next() {
+                Some(file) => out = Some(file.clone()),
+                None => {
+                    eprintln!("--out expects a value");
+                    return 2;
+                }
+            },
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg.clone()),
+            _ => {
+                eprintln!("unexpected argument '{}'", arg);
+                return 2;
+            }
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+    match migrate(&path, out.as_deref()) {
+        Ok(message) => {
+            println!("{}", message);
+            0
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            2
+        }
+    }
+}
+
+/// Rewrite the config at `path` in the current schema, to `out` or in
+/// place with the original kept as `path`.bak; what was done
+fn migrate(path: &str, out: Option<&str>) -> Result<String, String> {
+    let error = |e: String| format!("{}: {}", path, e);
+    let text = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+    let upgrade = upgrade(&text).map_err(error)?;
+    let migrated = stamp(&upgrade.text, CURRENT);
+    // Only a file that loads is written
+    config::parse_system(&migrated).map_err(error)?;
+
+    let target = out.unwrap_or(path);
+    if out.is_none() {
+        if migrated == text {
+            return Ok(format!("{}: already schema {}", path, CURRENT));
+        }
+        let backup = format!("{}.bak", path);
+        std::fs::copy(path, &backup).map_err(|e| format!("{}: {}", backup, e))?;
+    }
+    backup::atomic_write(target, &migrated)?;
+
+    let mut message = match upgrade.from {
+        CURRENT => format!("{}: schema {} stated, written to {}", path, CURRENT, target),
+        from => format!(
+            "{}: schema {} -> {}, written to {}",
+            path, from, CURRENT, target
+        ),
+    };
+    for step in upgrade.steps {
+        message.push_str(&format!("\n  {}", step));
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FuzzyController;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    /// Outputs over a grid of the first two inputs
+    fn outputs(system: &crate::FuzzySystem) -> Vec<Vec<f64>> {
+        let controller = FuzzyController::from_system(system.clone());
+        let (a, b) = (&system.inputs[0], &system.inputs[1]);
+        let mut outputs = Vec::new();
+        for i in 0..=10 {
+            for j in 0..=10 {
+                let x = a.min + (a.max - a.min) * i as f64 / 10.0;
+                let y = b.min + (b.max - b.min) * j as f64 / 10.0;
+                outputs.push(controller.evaluate(&[x, y]).outputs);
+            }
+        }
+        outputs
+    }
+
+    #[test]
+    fn every_schema_migrates_to_a_system_that_computes_the_same() {
+        // One fixture per historical schema
+        for schema in 1..=CURRENT {
+            let path = fixture(&format!("schema-{}.toml", schema));
+            let text = std::fs::read_to_string(&path).unwrap();
+            assert_eq!(version(&text), Ok(schema));
+            let original = config::load_system(&path).unwrap();
+
+            let out = std::env::temp_dir()
+                .join(format!(
+                    "fuzzy-migrate-{}-{}.toml",
+                    schema,
+                    std::process::id()
+                ))
+                .to_string_lossy()
+                .to_string();
+            migrate(&path, Some(&out)).unwrap();
+            let migrated = std::fs::read_to_string(&out).unwrap();
+            std::fs::remove_file(&out).unwrap();
+            assert!(migrated.starts_with(&format!("schema = {}\n", CURRENT)));
+            // Comments survive
+            assert!(migrated.contains("# Office fan, as written before schemas"));
+            assert_eq!(version(&migrated), Ok(CURRENT));
+            let migrated = config::parse_system(&migrated).unwrap();
+            assert_eq!(migrated, original);
+            assert_eq!(outputs(&migrated), outputs(&original));
+        }
+    }
+
+    #[test]
+    fn migrations_chain_from_the_declared_schema_and_newer_files_are_refused() {
+        let rename: fn(&str) -> Result<String, String> = |t| Ok(t.replace("old_key", "mid_key"));
+        let again: fn(&str) -> Result<String, String> = |t| Ok(t.replace("mid_key", "new_key"));
+        let chain = [
+            Migration {
+                from: 0,
+                what: "old_key is now mid_key",
+                apply: rename,
+            },
+            Migration {
+                from: 1,
+                what: "mid_key is now new_key",
+                apply: again,
+            },
+        ];
+        let upgrade = upgrade_with("# keep me\nold_key = 1\n", &chain).unwrap();
+        assert_eq!(upgrade.from, 1);
+        // Schema 1 starts at the second step
+        assert_eq!(upgrade.text, "# keep me\nold_key = 1\n");
+        assert_eq!(upgrade.steps, ["mid_key is now new_key"]);
+        let upgrade = upgrade_with("# keep me\nmid_key = 1\n", &chain).unwrap();
+        assert_eq!(upgrade.text, "# keep me\nnew_key = 1\n");
+
+        assert_eq!(
+            version("# a comment\nschema = 99\n").unwrap_err(),
+            format!(
+                "line 2: schema 99 is newer than this program reads (up to {}); upgrade fuzzy_logic",
+                CURRENT
+            )
+        );
+        assert_eq!(
+            version("schema = 1.5\n").unwrap_err(),
+            "line 1: 'schema' must be a whole number from 1"
+        );
+        assert!(
+            config::parse_system("schema = 99\n")
+                .unwrap_err()
+                .contains("newer than this program reads")
+        );
+
+        assert_eq!(stamp("rules = []\n", 1), "schema = 1\nrules = []\n");
+        assert_eq!(
+            stamp("# top\nschema  =  0\n[metadata]\nschema = 5\n", 1),
+            "# top\nschema = 1\n[metadata]\nschema = 5\n"
+        );
+    }
+}
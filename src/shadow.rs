@@ -0,0 +1,314 @@
+// ============================================================================
+// SHADOW - Perfil em sombra sobre as mesmas entradas
+// ============================================================================
+//
+// `--shadow FILE` runs a second config, the shadow, next to the live one
+// in the daemon and the TUI, so a candidate can soak against real readings
+// before it takes over. Every computation of the live system hands its
+// preprocessed inputs (after the adapters, sensor noise and injected
+// failures) to the shadow, which evaluates them and runs them through its
+// own output pipeline on the live ticks. Only the live output is
+// published; the shadow's only ever reaches the log, the comparison below
+// and the displays:
+//
+//   - the daemon adds the shadow's output to each log line and to its
+//     audit records (`shadow` and `shadow_band`, audit.rs), and `stats`
+//     answers with the comparison while a shadow runs
+//   - the TUI draws both outputs under the fan gauge, the shadow dashed,
+//     with the comparison in the chart's title
+//
+// The comparison holds each published output until the next tick, so it
+// weighs by time rather than by computations:
+//
+//     mean |Δ|       mean absolute difference of the two published outputs
+//     band differs   share of the time the shadow's band (pipeline.rs) was
+//                    another one
+//     energy         duty of each output, its value as a share of the live
+//                    output's universe, integrated over time; the
+//                    difference is in hours at full output and as a share
+//                    of the live energy
+//
+// There is no power model: a duty of 1 is whatever the output draws at its
+// maximum, so the energy difference is an estimate in those units.
+//
+// Inputs reach the shadow already preprocessed, so it must take the same
+// inputs in the same order, and its adapters, windows and injections are
+// not used. Its first output must be the live one's. A cascade drives the
+// actuator against a measurement, which a shadow cannot close a loop
+// over, so a shadow has none. Interlocks and a safe output force the live
+// output only: the comparison counts what the shadow would have chosen.
+
+use crate::config;
+use crate::pipeline::{FanBand, OutputPipeline, PipelineOutput};
+use crate::{FuzzyController, FuzzySystem};
+
+pub struct Shadow {
+    /// The config file, for messages
+    pub path: String,
+    controller: FuzzyController,
+    pipeline: OutputPipeline,
+    /// The shadow's defuzzified first output at the last computation
+    pub fan_speed: f64,
+    /// What the shadow's pipeline gave at the last tick
+    pub output: PipelineOutput,
+    /// Universe of the live output, the scale of the duty
+    range: (f64, f64),
+    summary: Summary,
+}
+
+impl Shadow {
+    pub fn load(path: &str, live: &FuzzySystem) -> Result<Self, String> {
+        let system = config::load_system(path)?;
+        Shadow::new(path, system, live).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn new(path: &str, system: FuzzySystem, live: &FuzzySystem) -> Result<Self, String> {
+        check(&system, live)?;
+        let output = live.output();
+        Ok(Shadow {
+            path: path.to_string(),
+            pipeline: OutputPipeline::new(system.pipeline.clone()),
+            controller: FuzzyController::from_system(system),
+            fan_speed: 0.0,
+            output: PipelineOutput {
+                value: 0.0,
+                band: FanBand::Off,
+                pending: None,
+            },
+            range: (output.min, output.max),
+            summary: Summary::default(),
+        })
+    }
+
+    /// Whether the shadow still fits `live`, after the live config changed
+    pub fn fits(&self, live: &FuzzySystem) -> Result<(), String> {
+        check(&self.controller.system, live)
+    }
+
+    /// Evaluate the live computation's preprocessed `inputs`
+    pub fn infer(&mut self, inputs: &[f64]) {
+        self.fan_speed = self.controller.evaluate(inputs).outputs[0];
+    }
+
+    /// Run the shadow's pipeline at `now` and compare its output with the
+    /// `live` one published at the same tick
+    pub fn tick(&mut self, live: PipelineOutput, now: f64) -> PipelineOutput {
+        self.output = self.pipeline.process(self.fan_speed, now);
+        let duty = |value: f64| {
+            let (min, max) = self.range;
+            ((value - min) / (max - min)).clamp(0.0, 1.0)
+        };
+        self.summary.record(
+            now,
+            Compared {
+                difference: (self.output.value - live.value).abs(),
+                band_differs: self.output.band != live.band,
+                live_duty: duty(live.value),
+                shadow_duty: duty(self.output.value),
+            },
+        );
+        self.output
+    }
+
+    pub fn summary(&self) -> &Summary {
+        &self.summary
+    }
+}
+
+/// Why `shadow` cannot run next to `live`, if it cannot
+fn check(shadow: &FuzzySystem, live: &FuzzySystem) -> Result<(), String> {
+    let names = |system: &FuzzySystem| -> Vec<String> {
+        system.inputs.iter().map(|var| var.name.clone()).collect()
+    };
+    if names(shadow) != names(live) {
+        return Err(format!(
+            "the shadow takes inputs {} but the live config {}",
+            names(shadow).join(", "),
+            names(live).join(", ")
+        ));
+    }
+    if shadow.output().name != live.output().name {
+        return Err(format!(
+            "the shadow's output '{}' is not the live output '{}'",
+            shadow.output().name,
+            live.output().name
+        ));
+    }
+    if shadow.cascade.is_some() {
+        return Err("a shadow drives nothing, so it cannot run a cascade".to_string());
+    }
+    Ok(())
+}
+
+/// One tick of both outputs
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Compared {
+    difference: f64,
+    band_differs: bool,
+    live_duty: f64,
+    shadow_duty: f64,
+}
+
+/// The comparison so far, each tick held until the next
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    /// Seconds compared
+    pub seconds: f64,
+    /// Integral of the absolute difference
+    difference: f64,
+    /// Seconds the bands differed
+    band_differs: f64,
+    /// Integrals of each duty, in seconds at full output
+    live_duty: f64,
+    shadow_duty: f64,
+    last: Option<(f64, Compared)>,
+}
+
+impl Summary {
+    fn record(&mut self, now: f64, compared: Compared) {
+        if let Some((then, last)) = self.last {
+            let held = (now - then).max(0.0);
+            self.seconds += held;
+            self.difference += last.difference * held;
+            if last.band_differs {
+                self.band_differs += held;
+            }
+            self.live_duty += last.live_duty * held;
+            self.shadow_duty += last.shadow_duty * held;
+        }
+        self.last = Some((now, compared));
+    }
+
+    /// Mean absolute difference of the outputs, in the output's unit
+    pub fn mean_difference(&self) -> f64 {
+        self.per_second(self.difference)
+    }
+
+    /// Share of the time the bands differed, from 0 to 1
+    pub fn band_differs(&self) -> f64 {
+        self.per_second(self.band_differs)
+    }
+
+    /// The shadow's energy less the live one, in hours at full output
+    pub fn energy_difference(&self) -> f64 {
+        (self.shadow_duty - self.live_duty) / 3600.0
+    }
+
+    /// The energy difference as a share of the live energy, none while
+    /// the live output drew nothing
+    pub fn energy_share(&self) -> Option<f64> {
+        (self.live_duty > 0.0).then(|| (self.shadow_duty - self.live_duty) / self.live_duty)
+    }
+
+    fn per_second(&self, integral: f64) -> f64 {
+        match self.seconds > 0.0 {
+            true => integral / self.seconds,
+            false => 0.0,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let share = match self.energy_share() {
+            Some(share) => format!("{:+.1}%", share * 100.0),
+            None => "-".to_string(),
+        };
+        format!(
+            "mean |Δ| {:.2}, band differs {:.1}% of the time, energy {} ({:+.3} h at full output)",
+            self.mean_difference(),
+            self.band_differs() * 100.0,
+            share,
+            self.energy_difference()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters;
+    use crate::fixtures;
+
+    /// The demo with the rules calling for "High" calling for "Medium"
+    fn lower() -> FuzzySystem {
+        let mut system = fixtures::fan();
+        for rule in &mut system.rules {
+            for (_, set) in &mut rule.consequents {
+                if set == "High" {
+                    *set = "Medium".to_string();
+                }
+            }
+        }
+        system
+    }
+
+    #[test]
+    fn a_scripted_sequence_compares_by_time_held() {
+        let live_system = fixtures::fan();
+        let live = FuzzyController::from_system(live_system.clone());
+        let mut pipeline = OutputPipeline::new(live_system.pipeline.clone());
+        let mut shadow = Shadow::new("lower.toml", lower(), &live_system).unwrap();
+
+        // Readings at 0s, 10s and 30s, the last tick at 40s
+        let script = [
+            (0.0, [35.0, 80.0]),
+            (10.0, [15.0, 30.0]),
+            (30.0, [35.0, 80.0]),
+        ];
+        let mut published = Vec::new();
+        for (now, readings) in script {
+            let (inputs, _) = adapters::convert(&live_system.inputs, &readings);
+            let fan_speed = live.evaluate(&inputs).outputs[0];
+            shadow.infer(&inputs);
+            let output = pipeline.process(fan_speed, now);
+            let compared = shadow.tick(output, now);
+            published.push((output.value, compared.value));
+        }
+        let last = pipeline.process(live.evaluate(&[35.0, 80.0]).outputs[0], 40.0);
+        shadow.tick(last, 40.0);
+
+        // Hot and humid: High live, Medium in the shadow; cold and dry:
+        // Off in both
+        let high = live.evaluate(&[35.0, 80.0]).outputs[0];
+        let medium = shadow.controller.evaluate(&[35.0, 80.0]).outputs[0];
+        let low = live.evaluate(&[15.0, 30.0]).outputs[0];
+        assert_eq!(published, vec![(high, medium), (low, low), (high, medium)]);
+        assert_eq!(FanBand::from_speed(high), FanBand::High);
+        assert_eq!(FanBand::from_speed(medium), FanBand::Medium);
+
+        // 20 of the 40 seconds at High against Medium
+        let summary = shadow.summary();
+        assert_eq!(summary.seconds, 40.0);
+        assert!((summary.mean_difference() - (high - medium) / 2.0).abs() < 1e-9);
+        assert_eq!(summary.band_differs(), 0.5);
+        let (live_duty, shadow_duty) = (
+            (10.0 * high + 20.0 * low + 10.0 * high) / 100.0,
+            (10.0 * medium + 20.0 * low + 10.0 * medium) / 100.0,
+        );
+        assert!((summary.energy_difference() - (shadow_duty - live_duty) / 3600.0).abs() < 1e-12);
+        let share = summary.energy_share().unwrap();
+        assert!((share - (shadow_duty - live_duty) / live_duty).abs() < 1e-12);
+        assert!(share < 0.0);
+        assert!(summary.describe().starts_with(&format!(
+            "mean |Δ| {:.2}, band differs 50.0% of the time, energy -",
+            (high - medium) / 2.0
+        )));
+    }
+
+    #[test]
+    fn a_shadow_that_cannot_take_the_live_inputs_is_refused() {
+        let live = fixtures::fan();
+        let mut swapped = fixtures::fan();
+        swapped.inputs.reverse();
+        let Err(e) = Shadow::new("swapped.toml", swapped, &live) else {
+            panic!("inputs in another order are not the same inputs");
+        };
+        assert_eq!(
+            e,
+            "the shadow takes inputs humidity, temperature but the live config temperature, humidity"
+        );
+
+        let mut renamed = fixtures::fan();
+        renamed.outputs[0].name = "damper".to_string();
+        assert!(Shadow::new("renamed.toml", renamed, &live).is_err());
+    }
+}
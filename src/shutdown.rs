@@ -0,0 +1,268 @@
+// ============================================================================
+// SHUTDOWN - Encerramento ordenado
+// ============================================================================
+//
+// Every mode that spawns components (the daemon's ticker and clients, the
+// TUI's journal and terminal) ends through a Coordinator. Its Token is
+// handed to every loop, which stops once it is cancelled: by 'q' in the
+// TUI or, in any mode, by SIGINT or SIGTERM (`cancel_on_signals`). A
+// second signal exits at once.
+//
+// Components register a hook for the stage they belong to, and the hooks
+// run in stage order, each in registration order within its stage:
+//
+//   1. stop inputs      - refuse commands, stop the tickers
+//   2. flush outputs    - drive the output to a safe value, if asked
+//   3. flush logs       - close (remove) the edit journal
+//   4. persist state    - write the state file
+//   5. restore terminal - leave the alternate screen, raw mode
+//
+// Each hook gets its own timeout. A hook that does not finish in time is
+// left behind, reported, and the sequence moves on to the next one; the
+// process then exits without waiting for it. Journal and state files are
+// replaced or removed in one step (state.rs, journal.rs), so a hook cut
+// short leaves them as they were before, never half written.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How long a hook may run when it does not say
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Shared flag telling every component to stop
+#[derive(Debug, Clone, Default)]
+pub struct Token(Arc<AtomicBool>);
+
+impl Token {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Shutdown stages, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    StopInputs,
+    FlushOutputs,
+    FlushLogs,
+    PersistState,
+    RestoreTerminal,
+}
+
+impl Stage {
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::StopInputs => "stop inputs",
+            Stage::FlushOutputs => "flush outputs",
+            Stage::FlushLogs => "flush logs",
+            Stage::PersistState => "persist state",
+            Stage::RestoreTerminal => "restore terminal",
+        }
+    }
+}
+
+type Hook = Box<dyn FnOnce() -> Result<(), String> + Send>;
+
+/// How one hook ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Done,
+    Failed(String),
+    /// Still running when its timeout ran out
+    TimedOut(Duration),
+}
+
+/// The outcome of every hook, in the order they ran
+#[derive(Debug, Default)]
+pub struct Report {
+    pub steps: Vec<(Stage, String, Outcome)>,
+}
+
+impl Report {
+    /// One line per hook that failed or timed out
+    pub fn problems(&self) -> Vec<String> {
+        self.steps
+            .iter()
+            .filter_map(|(stage, name, outcome)| match outcome {
+                Outcome::Done => None,
+                Outcome::Failed(e) => Some(format!("{} ({}): {}", name, stage.label(), e)),
+                Outcome::TimedOut(timeout) => Some(format!(
+                    "{} ({}): still running after {:.1}s, left behind",
+                    name,
+                    stage.label(),
+                    timeout.as_secs_f64()
+                )),
+            })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+pub struct Coordinator {
+    token: Token,
+    hooks: Vec<(Stage, String, Duration, Hook)>,
+}
+
+impl Coordinator {
+    /// The token cancelled when the shutdown starts
+    pub fn token(&self) -> Token {
+        self.token.clone()
+    }
+
+    pub fn register(
+        &mut self,
+        stage: Stage,
+        name: &str,
+        timeout: Duration,
+        hook: impl FnOnce() -> Result<(), String> + Send + 'static,
+    ) {
+        self.hooks
+            .push((stage, name.to_string(), timeout, Box::new(hook)));
+    }
+
+    /// Cancel the token and run every hook (see the module comment)
+    pub fn shutdown(mut self) -> Report {
+        self.token.cancel();
+        // Stable, so registration order holds within a stage
+        self.hooks.sort_by_key(|(stage, ..)| *stage);
+        let mut report = Report::default();
+        for (stage, name, timeout, hook) in self.hooks {
+            let (done, finished) = mpsc::channel();
+            std::thread::spawn(move || {
+                let _ = done.send(hook());
+            });
+            let outcome = match finished.recv_timeout(timeout) {
+                Ok(Ok(())) => Outcome::Done,
+                Ok(Err(e)) => Outcome::Failed(e),
+                Err(mpsc::RecvTimeoutError::Timeout) => Outcome::TimedOut(timeout),
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    Outcome::Failed("panicked".to_string())
+                }
+            };
+            report.steps.push((stage, name, outcome));
+        }
+        report
+    }
+}
+
+#[cfg(unix)]
+mod signals {
+    use super::Token;
+    use libc::{SIGINT, SIGTERM};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    /// How often the watcher looks at the flag the handler sets
+    const POLL: Duration = Duration::from_millis(50);
+
+    static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+    /// Only async-signal-safe work: set the flag, or leave on the second
+    /// signal
+    extern "C" fn handle(signum: libc::c_int) {
+        if SIGNALLED.swap(true, Ordering::SeqCst) {
+            unsafe { libc::_exit(128 + signum) }
+        }
+    }
+
+    pub fn cancel_on_signals(token: &Token) {
+        unsafe {
+            libc::signal(SIGINT, handle as *const () as libc::sighandler_t);
+            libc::signal(SIGTERM, handle as *const () as libc::sighandler_t);
+        }
+        let token = token.clone();
+        std::thread::spawn(move || {
+            while !token.is_cancelled() {
+                if SIGNALLED.load(Ordering::SeqCst) {
+                    token.cancel();
+                }
+                std::thread::sleep(POLL);
+            }
+        });
+    }
+}
+
+/// Cancel `token` on SIGINT or SIGTERM; a second one exits at once
+#[cfg(unix)]
+pub use signals::cancel_on_signals;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    #[test]
+    fn hooks_run_by_stage_then_registration() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut coordinator = Coordinator::default();
+        for (stage, name) in [
+            (Stage::RestoreTerminal, "terminal"),
+            (Stage::PersistState, "state"),
+            (Stage::StopInputs, "socket"),
+            (Stage::FlushLogs, "journal"),
+            (Stage::StopInputs, "ticker"),
+        ] {
+            let order = order.clone();
+            coordinator.register(stage, name, DEFAULT_TIMEOUT, move || {
+                order.lock().unwrap().push(name);
+                Ok(())
+            });
+        }
+        let token = coordinator.token();
+        assert!(!token.is_cancelled());
+        let report = coordinator.shutdown();
+        assert!(token.is_cancelled());
+        assert_eq!(
+            *order.lock().unwrap(),
+            ["socket", "ticker", "journal", "state", "terminal"]
+        );
+        assert!(report.problems().is_empty());
+    }
+
+    #[test]
+    fn a_slow_hook_is_left_behind_after_its_timeout() {
+        let mut coordinator = Coordinator::default();
+        coordinator.register(
+            Stage::StopInputs,
+            "stuck reader",
+            Duration::from_millis(100),
+            || {
+                std::thread::sleep(Duration::from_secs(10));
+                Ok(())
+            },
+        );
+        coordinator.register(Stage::FlushLogs, "journal", DEFAULT_TIMEOUT, || {
+            Err("disk full".to_string())
+        });
+        let persisted = Arc::new(AtomicBool::new(false));
+        let flag = persisted.clone();
+        coordinator.register(Stage::PersistState, "state", DEFAULT_TIMEOUT, move || {
+            flag.store(true, Ordering::SeqCst);
+            Ok(())
+        });
+
+        let start = Instant::now();
+        let report = coordinator.shutdown();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        // The later stages still ran
+        assert!(persisted.load(Ordering::SeqCst));
+        assert_eq!(
+            report.steps[0].2,
+            Outcome::TimedOut(Duration::from_millis(100))
+        );
+        assert_eq!(
+            report.problems(),
+            [
+                "stuck reader (stop inputs): still running after 0.1s, left behind",
+                "journal (flush logs): disk full"
+            ]
+        );
+    }
+}
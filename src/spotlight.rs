@@ -0,0 +1,77 @@
+// ============================================================================
+// SPOTLIGHT - Destaque de painéis
+// ============================================================================
+//
+// Draws attention to some panels of a frame by dimming the rest: every
+// cell outside the kept areas is redrawn dark gray on the default
+// background, its symbol untouched, so the screen still reads as the
+// screen. Whatever is drawn after the dimming (an explanation box, the
+// status bar) is drawn as usual. It is a widget, rendered over the whole
+// frame, since a frame only lends its buffer to widgets.
+//
+// The tutorial (tutorial.rs) lights one step's panels at a time; anything
+// else that explains part of the screen can do the same.
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::Widget;
+
+/// Style of a dimmed cell
+pub const DIMMED: Style = Style {
+    fg: Some(Color::DarkGray),
+    bg: Some(Color::Reset),
+    underline_color: None,
+    add_modifier: Modifier::empty(),
+    sub_modifier: Modifier::all(),
+};
+
+/// Dims every cell of its area outside the `keep` areas
+pub struct Spotlight<'a> {
+    pub keep: &'a [Rect],
+}
+
+impl Widget for Spotlight<'_> {
+    fn render(self, area: Rect, buffer: &mut Buffer) {
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                if !is_kept(self.keep, x, y) {
+                    buffer.get_mut(x, y).set_style(DIMMED);
+                }
+            }
+        }
+    }
+}
+
+fn is_kept(keep: &[Rect], x: u16, y: u16) -> bool {
+    keep.iter().any(|rect| {
+        (rect.left()..rect.right()).contains(&x) && (rect.top()..rect.bottom()).contains(&y)
+    })
+}
+
+/// Whether the cell at (`x`, `y`) is dimmed
+#[cfg(test)]
+pub fn is_dimmed(buffer: &Buffer, x: u16, y: u16) -> bool {
+    buffer.get(x, y).fg == Color::DarkGray
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_kept_areas_keep_their_style() {
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 10, 4));
+        buffer.set_string(0, 0, "bright", Style::default().fg(Color::Yellow));
+        buffer.set_string(0, 2, "bold", Style::default().add_modifier(Modifier::BOLD));
+        let keep = [Rect::new(0, 0, 3, 1), Rect::new(8, 3, 2, 1)];
+        Spotlight { keep: &keep }.render(buffer.area, &mut buffer);
+
+        assert_eq!(buffer.get(0, 0).fg, Color::Yellow);
+        assert!(is_dimmed(&buffer, 3, 0));
+        assert_eq!(buffer.get(3, 0).symbol, "g");
+        assert!(is_dimmed(&buffer, 0, 2));
+        assert!(!buffer.get(0, 2).modifier.contains(Modifier::BOLD));
+        assert!(!is_dimmed(&buffer, 9, 3));
+    }
+}
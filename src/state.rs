@@ -0,0 +1,269 @@
+// ============================================================================
+// APP STATE - Estado persistente
+// ============================================================================
+//
+// Diagnostics that should survive restarts are kept in a state file, written
+// in the same TOML subset as the system definition:
+//
+//     [[rule]]
+//     text = "IF temperature IS Hot AND humidity IS High THEN fan_speed IS High"
+//     first_fired = 1760612345.2    # wall-clock epoch seconds
+//     last_fired = 1760698765.9
+//     max_strength = 0.8
+//     latched = true
+//
+// Rules are matched by their text, so editing the rule list keeps the
+// statistics of the rules that did not change. Rules that never fired are
+// not written. The TUI's bookmarks (bookmarks.rs) and its panel layout
+// (layout.rs) follow the rules in the same file.
+
+use crate::backup;
+use crate::bookmarks::Bookmarks;
+use crate::config;
+use crate::layout::PanelLayout;
+
+/// Lifetime diagnostics for one rule
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleUsage {
+    pub first_fired: Option<f64>,
+    pub last_fired: Option<f64>,
+    pub max_strength: f64,
+    /// Set whenever the rule fires, cleared only by `reset_latches`
+    pub latched: bool,
+}
+
+/// Per-rule usage, in the order of the system's rules
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleUsageStats {
+    rules: Vec<(String, RuleUsage)>,
+    dirty: bool,
+}
+
+impl RuleUsageStats {
+    /// Empty statistics for rules with the given texts
+    pub fn new(texts: Vec<String>) -> Self {
+        RuleUsageStats {
+            rules: texts
+                .into_iter()
+                .map(|text| (text, RuleUsage::default()))
+                .collect(),
+            dirty: false,
+        }
+    }
+
+    /// Follow a change of the rules: the usage of each rule whose text is
+    /// still there is kept, in the new order
+    pub fn retarget(&mut self, texts: Vec<String>) {
+        if self.rules.iter().map(|(text, _)| text).eq(texts.iter()) {
+            return;
+        }
+        let mut old = std::mem::take(&mut self.rules);
+        self.rules = texts
+            .into_iter()
+            .map(|text| match old.iter().position(|(t, _)| *t == text) {
+                Some(i) => old.swap_remove(i),
+                None => (text, RuleUsage::default()),
+            })
+            .collect();
+        self.dirty = true;
+    }
+
+    pub fn get(&self, index: usize) -> Option<&RuleUsage> {
+        self.rules.get(index).map(|(_, usage)| usage)
+    }
+
+    /// Whether anything changed since the last save
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Update with the firing strength of every rule at wall-clock `now`
+    pub fn record(&mut self, strengths: &[f64], now: f64) {
+        for ((_, usage), strength) in self.rules.iter_mut().zip(strengths) {
+            if *strength <= 0.0 {
+                continue;
+            }
+            usage.first_fired.get_or_insert(now);
+            usage.last_fired = Some(now);
+            usage.max_strength = usage.max_strength.max(*strength);
+            usage.latched = true;
+            self.dirty = true;
+        }
+    }
+
+    pub fn reset_latches(&mut self) {
+        for (_, usage) in &mut self.rules {
+            usage.latched = false;
+        }
+        self.dirty = true;
+    }
+
+    /// Statistics for `texts` from a state file; a missing file gives empty
+    /// statistics, entries for rules not in `texts` are dropped
+    pub fn load(path: &str, texts: Vec<String>) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => RuleUsageStats::parse(&text, texts).map_err(|e| format!("{}: {}", path, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(RuleUsageStats::new(texts)),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        }
+    }
+
+    /// Statistics for `texts` from the contents of a state file
+    pub fn parse(text: &str, texts: Vec<String>) -> Result<Self, String> {
+        let mut stats = RuleUsageStats::new(texts);
+        let sections = config::parse_document(text)?;
+
+        for section in sections.iter().filter(|s| s.header == "rule") {
+            let parse = || -> Result<(String, RuleUsage), String> {
+                let optional = |key: &str| match section.get(key) {
+                    None => Ok(None),
+                    Some(_) => section.num(key).map(Some),
+                };
+                Ok((
+                    section.str("text")?.to_string(),
+                    RuleUsage {
+                        first_fired: optional("first_fired")?,
+                        last_fired: optional("last_fired")?,
+                        max_strength: section.num_or("max_strength", 0.0)?,
+                        latched: section.bool_or("latched", false)?,
+                    },
+                ))
+            };
+            let (text, usage) = parse()?;
+            if let Some((_, slot)) = stats.rules.iter_mut().find(|(t, _)| *t == text) {
+                *slot = usage;
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Contents of the state file
+    pub fn to_toml(&self) -> String {
+        let mut out = String::from("# fuzzy_logic app state\n");
+        for (text, usage) in &self.rules {
+            if usage.first_fired.is_none() {
+                continue;
+            }
+            out.push_str(&format!("\n[[rule]]\ntext = {}\n", config::quote(text)));
+            if let Some(first) = usage.first_fired {
+                out.push_str(&format!("first_fired = {}\n", first));
+            }
+            if let Some(last) = usage.last_fired {
+                out.push_str(&format!("last_fired = {}\n", last));
+            }
+            out.push_str(&format!(
+                "max_strength = {}\nlatched = {}\n",
+                usage.max_strength, usage.latched
+            ));
+        }
+        out
+    }
+
+    /// Write the state file, with `bookmarks` and `layout` after the rules
+    pub fn save(
+        &mut self,
+        path: &str,
+        bookmarks: &Bookmarks,
+        layout: &PanelLayout,
+    ) -> Result<(), String> {
+        backup::atomic_write(
+            path,
+            &(self.to_toml() + &bookmarks.to_toml() + &layout.to_toml()),
+        )?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts() -> Vec<String> {
+        vec![
+            "IF temperature IS Hot THEN fan_speed IS High".to_string(),
+            "IF humidity IS \"odd\" THEN fan_speed IS Low".to_string(),
+            "IF temperature IS Cold THEN fan_speed IS Low".to_string(),
+        ]
+    }
+
+    #[test]
+    fn a_state_file_round_trips() {
+        let path = std::env::temp_dir().join(format!("fuzzy-state-{}.toml", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+
+        let mut stats = RuleUsageStats::new(texts());
+        stats.record(&[0.25, 0.5, 0.0], 1760612345.5);
+        stats.record(&[0.75, 0.0, 0.0], 1760698765.25);
+        stats.reset_latches();
+        stats.record(&[0.0, 0.1, 0.0], 1760700000.0);
+        assert!(stats.is_dirty());
+        stats
+            .save(&path, &Bookmarks::default(), &PanelLayout::default())
+            .unwrap();
+        assert!(!stats.is_dirty());
+
+        let loaded = RuleUsageStats::load(&path, texts()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, stats);
+        assert_eq!(
+            loaded.get(0),
+            Some(&RuleUsage {
+                first_fired: Some(1760612345.5),
+                last_fired: Some(1760698765.25),
+                max_strength: 0.75,
+                latched: false,
+            })
+        );
+        assert!(loaded.get(1).unwrap().latched);
+        assert_eq!(loaded.get(2), Some(&RuleUsage::default()));
+    }
+
+    #[test]
+    fn a_missing_file_is_empty_and_dropped_rules_are_forgotten() {
+        let path = std::env::temp_dir().join(format!("fuzzy-state-{}-b.toml", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        assert_eq!(
+            RuleUsageStats::load(&path, texts()).unwrap(),
+            RuleUsageStats::new(texts())
+        );
+
+        let mut stats = RuleUsageStats::new(texts());
+        stats.record(&[1.0, 1.0, 1.0], 10.0);
+        stats
+            .save(&path, &Bookmarks::default(), &PanelLayout::default())
+            .unwrap();
+        let kept = texts()[1..].to_vec();
+        let loaded = RuleUsageStats::load(&path, kept).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.get(0).unwrap().first_fired, Some(10.0));
+        assert_eq!(loaded.get(2), None);
+    }
+
+    #[test]
+    fn latches_hold_until_reset_and_set_again_on_the_next_fire() {
+        let mut stats = RuleUsageStats::new(texts());
+        stats.record(&[0.5, 0.0, 0.0], 1.0);
+        stats.record(&[0.0, 0.0, 0.0], 2.0);
+        let usage = stats.get(0).unwrap().clone();
+        assert!(usage.latched);
+        assert_eq!(
+            (usage.first_fired, usage.last_fired),
+            (Some(1.0), Some(1.0))
+        );
+
+        stats.reset_latches();
+        let reset = stats.get(0).unwrap();
+        assert!(!reset.latched);
+        // Only the latch is cleared
+        assert_eq!(reset.max_strength, 0.5);
+        assert_eq!(reset.first_fired, Some(1.0));
+
+        stats.record(&[0.0, 0.0, 0.0], 3.0);
+        assert!(!stats.get(0).unwrap().latched);
+        stats.record(&[0.2, 0.0, 0.0], 4.0);
+        let usage = stats.get(0).unwrap();
+        assert!(usage.latched);
+        assert_eq!((usage.last_fired, usage.max_strength), (Some(4.0), 0.5));
+    }
+}
@@ -0,0 +1,304 @@
+// ============================================================================
+// SIMULATION STEPPER - Simulação passo a passo
+// ============================================================================
+//
+// A controller session with every time-dependent stage, driven only by
+// explicit steps: `Stepper::step(raw, dt)` advances the session's own time
+// by `dt` seconds and runs one computation through the same stages as the
+// other front ends, in the order the processing chain lists them (chain.rs):
+//
+//     adapters    raw readings to input values (adapters.rs)
+//     range       each input's out_of_range policy
+//     inference   rules, caps and safety envelopes (FuzzyController)
+//     cascade     PID on the first output, with an [output.cascade]
+//     pipeline    rate limit, hysteresis band, dwell (pipeline.rs)
+//
+// The Stepper walks that description rather than its own copy of the
+// order, so `fuzzy_logic pipeline` prints what a step does.
+//
+// Nothing reads the wall clock, so the same steps always give the same
+// results; `cosim` answers its requests with a Stepper. The TUI and the
+// daemon run the same stages against a `Clock` instead (pipeline.rs), which
+// their tests replace.
+//
+// Simulated sensor noise and failure injections stay with the TUI and the
+// daemon: they are what a test would script itself.
+
+use crate::adapters;
+use crate::cascade::Cascade;
+use crate::chain::{Chain, Kind};
+use crate::pipeline::{OutputPipeline, PipelineOutput};
+use crate::{Evaluation, FuzzyController, FuzzySystem};
+
+/// What one step computed
+pub struct Step {
+    /// Session time of the step, in seconds
+    pub time: f64,
+    /// Input values after their adapters, in declaration order
+    pub inputs: Vec<f64>,
+    /// Crisp outputs, strengths and diagnostics of the computation
+    pub evaluation: Evaluation,
+    /// The first output after the cascade and the pipeline
+    pub output: PipelineOutput,
+}
+
+/// A controller session stepped by its caller
+pub struct Stepper {
+    controller: FuzzyController,
+    pipeline: OutputPipeline,
+    /// The cascade and the index of the input it measures
+    cascade: Option<(Cascade, usize)>,
+    /// Session time of the last step, none before the first
+    time: Option<f64>,
+    /// The stages a step runs, in order (chain.rs)
+    plan: Vec<Kind>,
+}
+
+impl Stepper {
+    pub fn new(system: FuzzySystem) -> Self {
+        let cascade = system.cascade.clone().map(|config| {
+            let index = system
+                .inputs
+                .iter()
+                .position(|var| var.name == config.measured)
+                .expect("validated cascade input");
+            (Cascade::new(config), index)
+        });
+        Stepper {
+            plan: Chain::of(&system).stepped(),
+            pipeline: OutputPipeline::new(system.pipeline.clone()),
+            cascade,
+            controller: FuzzyController::from_system(system),
+            time: None,
+        }
+    }
+
+    /// Advance by `dt` seconds, from 0 before the first step, and compute
+    /// from `raw`, one raw reading per input in declaration order; a
+    /// reading an input refuses fails the step and leaves the session as
+    /// it was
+    // The front ends step at given times; this is for embedders' tests
+    pub fn step(&mut self, raw: &[f64], dt: f64) -> Result<Step, String> {
+        if !(dt >= 0.0 && dt.is_finite()) {
+            return Err(format!("dt must be a duration of 0 or more, got {}", dt));
+        }
+        self.step_at(raw, self.time.unwrap_or(0.0) + dt)
+    }
+
+    /// As `step`, at session time `time`, which must not go backwards
+    pub fn step_at(&mut self, raw: &[f64], time: f64) -> Result<Step, String> {
+        let system = &self.controller.system;
+        if let Some(last) = self.time
+            && time < last
+        {
+            return Err(format!(
+                "time {} is before the previous step at {}",
+                time, last
+            ));
+        }
+        if raw.len() != system.inputs.len() {
+            return Err(format!(
+                "expected {} readings, got {}",
+                system.inputs.len(),
+                raw.len()
+            ));
+        }
+        // The stages in the order the chain describes them (chain.rs); the
+        // inference is one evaluation, run at the first of its stages
+        let mut inputs = raw.to_vec();
+        let mut evaluation = None;
+        let mut value = 0.0;
+        let mut output = None;
+        for kind in &self.plan {
+            match kind {
+                Kind::Adapter => inputs = adapters::convert(&system.inputs, &inputs).0,
+                Kind::Range => {
+                    for (var, value) in system.inputs.iter().zip(&inputs) {
+                        var.admit(*value)?;
+                    }
+                }
+                kind if kind.is_inference() && evaluation.is_none() => {
+                    let done = self.controller.evaluate(&inputs);
+                    value = done.outputs[0];
+                    evaluation = Some(done);
+                }
+                Kind::Cascade => {
+                    if let Some((cascade, index)) = &mut self.cascade {
+                        value = cascade.process(value, inputs[*index], time);
+                    }
+                }
+                Kind::RateLimit => self.pipeline.limit(value, time),
+                Kind::Hysteresis => self.pipeline.map_band(),
+                Kind::Dwell => output = Some(self.pipeline.dwell(time)),
+                _ => {}
+            }
+        }
+        self.time = Some(time);
+        let evaluation = evaluation.expect("the chain evaluates the rules");
+        let output = output.expect("the chain ends at the dwell");
+        Ok(Step {
+            time,
+            inputs,
+            evaluation,
+            output,
+        })
+    }
+
+    /// Replace the weight of every rule, in config order; a weight count
+    /// that does not match or one outside 0..=1 changes nothing
+    #[cfg(feature = "cosim")]
+    pub(crate) fn set_weights(&mut self, weights: &[f64]) -> Result<(), String> {
+        let rules = &mut self.controller.system.rules;
+        if weights.len() != rules.len() {
+            return Err(format!(
+                "expected {} weights, got {}",
+                rules.len(),
+                weights.len()
+            ));
+        }
+        if let Some(weight) = weights.iter().find(|w| !(0.0..=1.0).contains(*w)) {
+            return Err(format!("weight {} is not between 0 and 1", weight));
+        }
+        for (rule, weight) in rules.iter_mut().zip(weights) {
+            rule.weight = *weight;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::pipeline::{FanBand, PipelineConfig};
+
+    /// A day compressed into 1,000 half-second steps: temperature and
+    /// humidity swing, with a few sharp jumps the stages have to absorb
+    fn scripted() -> Vec<[f64; 2]> {
+        (0..1000)
+            .map(|i| {
+                let t = i as f64;
+                let temperature =
+                    24.0 + 12.0 * (t / 80.0).sin() + if i % 250 < 5 { 8.0 } else { 0.0 };
+                let humidity = 55.0 + 30.0 * (t / 130.0).cos();
+                [temperature, humidity]
+            })
+            .collect()
+    }
+
+    fn system() -> FuzzySystem {
+        FuzzySystem {
+            pipeline: PipelineConfig {
+                rate_limit: 8.0,
+                hysteresis: 6.0,
+                min_on_secs: 20.0,
+                min_off_secs: 30.0,
+                ..PipelineConfig::default()
+            },
+            ..fixtures::fan()
+        }
+    }
+
+    fn run(script: &[[f64; 2]]) -> Vec<Step> {
+        let mut stepper = Stepper::new(system());
+        script
+            .iter()
+            .map(|raw| stepper.step(raw, 0.5).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn a_scripted_thousand_steps_give_the_same_outputs_every_time() {
+        let script = scripted();
+        let steps = run(&script);
+        assert_eq!(steps.len(), 1000);
+        assert_eq!(steps[999].time, 500.0);
+
+        // Bit for bit on a second run
+        let again = run(&script);
+        for (a, b) in steps.iter().zip(&again) {
+            assert_eq!(a.output, b.output);
+            assert_eq!(a.evaluation.outputs, b.evaluation.outputs);
+        }
+
+        // The rate limit holds on every step, and the dwell keeps the fan
+        // off for at least min_off_secs
+        for pair in steps.windows(2) {
+            assert!((pair[1].output.value - pair[0].output.value).abs() <= 8.0 * 0.5 + 1e-9);
+        }
+        let mut off_since = None;
+        for step in &steps {
+            match (step.output.band, off_since) {
+                (FanBand::Off, None) => off_since = Some(step.time),
+                (FanBand::Off, Some(_)) => {}
+                (_, Some(since)) => {
+                    assert!(step.time - since >= 30.0, "on again at {}", step.time);
+                    off_since = None;
+                }
+                (_, None) => {}
+            }
+        }
+
+        // Exact outputs at a few points of the script
+        let at = |i: usize| (steps[i].evaluation.outputs[0], steps[i].output.value);
+        let golden = [(0, at(0)), (249, at(249)), (250, at(250)), (999, at(999))];
+        assert_eq!(golden, EXPECTED);
+        let bands: Vec<FanBand> = steps.iter().map(|s| s.output.band).collect();
+        let changes = bands.windows(2).filter(|w| w[0] != w[1]).count();
+        assert_eq!(changes, EXPECTED_BAND_CHANGES);
+    }
+
+    /// Fuzzy output and pipeline output at steps of the script: the jump
+    /// at step 250 is held to the rate limit
+    const EXPECTED: [(usize, (f64, f64)); 4] = [
+        (0, (83.66666666666667, 83.66666666666667)),
+        (249, (42.609240195082336, 42.609240195082336)),
+        (250, (72.95001200827062, 46.609240195082336)),
+        (999, (49.99999999999998, 49.99999999999998)),
+    ];
+    const EXPECTED_BAND_CHANGES: usize = 15;
+
+    #[test]
+    fn a_refused_step_leaves_the_session_as_it_was() {
+        let mut stepper = Stepper::new(system());
+        stepper.step(&[30.0, 60.0], 1.0).unwrap();
+        let error = stepper.step(&[30.0], 1.0).err().unwrap();
+        assert!(error.contains("expected 2"), "{}", error);
+        assert!(stepper.step(&[30.0, 60.0], -1.0).is_err());
+        assert!(stepper.step_at(&[30.0, 60.0], 0.5).is_err());
+        assert_eq!(stepper.step(&[30.0, 60.0], 0.0).unwrap().time, 1.0);
+    }
+
+    #[test]
+    fn a_step_runs_the_stages_in_the_order_the_chain_describes() {
+        let system = system();
+        let chain = Chain::of(&system);
+        let stepper = Stepper::new(system);
+        let names: Vec<&str> = stepper.plan.iter().map(|kind| kind.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "adapter",
+                "range",
+                "fuzzify",
+                "rules",
+                "implication",
+                "aggregation",
+                "caps",
+                "defuzzify",
+                "envelopes",
+                "cascade",
+                "rate limit",
+                "hysteresis",
+                "dwell"
+            ]
+        );
+        // The same stages, in the same order, as printed
+        let printed: Vec<Kind> = (chain.inputs[0].1.iter())
+            .chain(&chain.outputs[0].1)
+            .map(|stage| stage.kind)
+            .filter(|kind| stepper.plan.contains(kind))
+            .collect();
+        assert_eq!(printed, stepper.plan);
+    }
+}
@@ -0,0 +1,452 @@
+// ============================================================================
+// SET SUGGESTIONS - Sugestão de conjuntos a partir de dados
+// ============================================================================
+//
+// `suggest-sets FILE.csv --column NAME --sets K` places K sets over the
+// values observed in one CSV column and prints them as an `[[input]]`
+// section ready to paste into a config.
+//
+// Set centers come from the quantiles of the data (`--method quantile`,
+// the default: first and last at the min and max) or from 1-D k-means
+// (`--method kmeans`). Neighbouring sets cross at 0.5 halfway between their
+// centers; `--overlap` (0 to 1) is the fraction of the gap over which
+// they blend, 1 giving triangles that reach the next center. The outer
+// sets are shoulders covering the rest of the range.
+//
+// Constant data, or data so skewed that two centers nearly coincide, falls
+// back to equal spacing over the observed range, with a warning.
+
+use crate::config;
+use crate::{FuzzyVariable, MembershipFunction};
+use std::io::{self, BufRead};
+
+const USAGE: &str = "Usage: fuzzy_logic suggest-sets FILE.csv --column NAME --sets K \
+                     [--method quantile|kmeans] [--overlap 0..1] [--names A,B,...]";
+
+/// Centers closer than this fraction of the range count as coinciding
+const MIN_CENTER_GAP: f64 = 0.02;
+const KMEANS_ITERATIONS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Placement {
+    Quantile,
+    KMeans,
+}
+
+/// `q`-quantile of sorted values, interpolating between neighbours
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let (low, high) = (position.floor() as usize, position.ceil() as usize);
+    sorted[low] + (sorted[high] - sorted[low]) * (position - low as f64)
+}
+
+/// Lloyd's algorithm on sorted values, starting from `centers`
+fn kmeans(sorted: &[f64], mut centers: Vec<f64>) -> Vec<f64> {
+    for _ in 0..KMEANS_ITERATIONS {
+        let mut sums = vec![(0.0, 0usize); centers.len()];
+        for value in sorted {
+            let nearest = (0..centers.len())
+                .min_by(|a, b| {
+                    (value - centers[*a])
+                        .abs()
+                        .total_cmp(&(value - centers[*b]).abs())
+                })
+                .unwrap_or(0);
+            sums[nearest].0 += value;
+            sums[nearest].1 += 1;
+        }
+        let next: Vec<f64> = sums
+            .iter()
+            .zip(&centers)
+            .map(|((sum, n), center)| if *n > 0 { sum / *n as f64 } else { *center })
+            .collect();
+        if next == centers {
+            break;
+        }
+        centers = next;
+    }
+    centers.sort_by(f64::total_cmp);
+    centers
+}
+
+/// Default names for `count` sets
+fn set_names(count: usize) -> Vec<String> {
+    let names: &[&str] = match count {
+        2 => &["Low", "High"],
+        3 => &["Low", "Medium", "High"],
+        4 => &["VeryLow", "Low", "High", "VeryHigh"],
+        5 => &["VeryLow", "Low", "Medium", "High", "VeryHigh"],
+        _ => &[],
+    };
+    if names.is_empty() {
+        (1..=count).map(|i| format!("S{}", i)).collect()
+    } else {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+}
+
+/// Sets named `names` (at least two) for `values` (at least one, all
+/// finite) following the module comment, with warnings about the fallbacks taken
+pub fn suggest_sets(
+    values: &[f64],
+    names: &[String],
+    placement: Placement,
+    overlap: f64,
+) -> (FuzzyVariable, Vec<String>) {
+    let mut warnings = Vec::new();
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let count = names.len();
+    let (mut min, mut max) = (sorted[0], sorted[sorted.len() - 1]);
+    let equal = |min: f64, max: f64| -> Vec<f64> {
+        (0..count)
+            .map(|i| min + (max - min) * i as f64 / (count - 1) as f64)
+            .collect()
+    };
+    let centers = if max - min <= f64::EPSILON * min.abs().max(1.0) {
+        warnings.push(format!(
+            "all values are {}, spreading the sets over ±1 around it",
+            min
+        ));
+        (min, max) = (min - 1.0, max + 1.0);
+        equal(min, max)
+    } else {
+        let mut centers: Vec<f64> = (0..count)
+            .map(|i| quantile(&sorted, i as f64 / (count - 1) as f64))
+            .collect();
+        if placement == Placement::KMeans {
+            centers = kmeans(&sorted, centers);
+        }
+        if centers
+            .windows(2)
+            .any(|w| w[1] - w[0] < MIN_CENTER_GAP * (max - min))
+        {
+            warnings.push(
+                "the data is too concentrated to separate the sets, using equal spacing"
+                    .to_string(),
+            );
+            centers = equal(min, max);
+        }
+        centers
+    };
+
+    // Blend zone between neighbours i and i + 1, centered on the midpoint
+    let zone = |i: usize| {
+        let (mid, half) = (
+            (centers[i] + centers[i + 1]) / 2.0,
+            overlap * (centers[i + 1] - centers[i]) / 2.0,
+        );
+        (mid - half, mid + half)
+    };
+    let mut var = FuzzyVariable::new("", min, max);
+    for (i, name) in names.iter().enumerate() {
+        let (a, b) = if i == 0 { (min, min) } else { zone(i - 1) };
+        let (c, d) = if i + 1 == count { (max, max) } else { zone(i) };
+        // A plateau lost in rounding is no plateau
+        let function = if c - b <= 1e-9 * (max - min) {
+            MembershipFunction::Triangular { a, b, c: d }
+        } else {
+            MembershipFunction::Trapezoidal { a, b, c, d }
+        };
+        var = var.with_set(name, function);
+    }
+    (var, warnings)
+}
+
+impl FuzzyVariable {
+    /// Variable `name` with sets suggested from observed `values`, see
+    /// [`suggest_sets`]
+    pub fn from_observations(
+        name: &str,
+        values: &[f64],
+        names: &[String],
+        placement: Placement,
+        overlap: f64,
+    ) -> (Self, Vec<String>) {
+        let (mut var, warnings) = suggest_sets(values, names, placement, overlap);
+        var.name = name.to_string();
+        (var, warnings)
+    }
+}
+
+/// Finite values of `column` in a CSV file with a header row
+fn read_column(path: &str, column: &str) -> Result<Vec<f64>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut lines = io::BufReader::new(file).lines();
+    let header = match lines.next() {
+        Some(line) => config::split_csv_line(&line.map_err(|e| format!("{}: {}", path, e))?),
+        None => return Err(format!("{}: empty file", path)),
+    };
+    let index = header
+        .iter()
+        .position(|h| h == column)
+        .ok_or_else(|| format!("{}: missing column '{}'", path, column))?;
+
+    let mut values = Vec::new();
+    let mut skipped = 0;
+    for line in lines {
+        let line = line.map_err(|e| format!("{}: {}", path, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match config::split_csv_line(&line)
+            .get(index)
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|v| v.is_finite())
+        {
+            Some(value) => values.push(value),
+            None => skipped += 1,
+        }
+    }
+    if skipped > 0 {
+        eprintln!("warning: skipped {} rows without a number", skipped);
+    }
+    if values.is_empty() {
+        return Err(format!("{}: no values in column '{}'", path, column));
+    }
+    Ok(values)
+}
+
+/// `suggest-sets` subcommand. Exit code 0 on success, 2 on errors.
+pub fn run(args: &[String]) -> i32 {
+    let result = (|| -> Result<String, String> {
+        let mut path = None;
+        let mut column = None;
+        let mut count = None;
+        let mut placement = Placement::Quantile;
+        let mut overlap = 1.0;
+        let mut names: Option<Vec<String>> = None;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || {
+                iter.next()
+                    .ok_or_else(|| format!("{} expects a value", arg))
+            };
+            match arg.as_str() {
+                "--column" => column = Some(value()?.clone()),
+                "--sets" => {
+                    count = Some(
+                        value()?
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|k| *k >= 2)
+                            .ok_or("--sets expects a number of at least 2")?,
+                    )
+                }
+                "--method" => {
+                    placement = match value()?.as_str() {
+                        "quantile" => Placement::Quantile,
+                        "kmeans" => Placement::KMeans,
+                        other => return Err(format!("unknown method '{}'", other)),
+                    }
+                }
+                "--overlap" => {
+                    overlap = value()?
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|o| *o > 0.0 && *o <= 1.0)
+                        .ok_or("--overlap expects a number in (0, 1]")?
+                }
+                "--names" => {
+                    names = Some(value()?.split(',').map(|n| n.trim().to_string()).collect())
+                }
+                _ if path.is_none() && !arg.starts_with("--") => path = Some(arg.clone()),
+                _ => return Err(format!("unexpected argument '{}'", arg)),
+            }
+        }
+        let (Some(path), Some(column)) = (path, column) else {
+            return Err(USAGE.to_string());
+        };
+        let names: Vec<String> = match (names, count) {
+            (Some(names), Some(count)) if names.len() != count => {
+                return Err(format!(
+                    "--names lists {} names for {} sets",
+                    names.len(),
+                    count
+                ));
+            }
+            (Some(names), _) if names.len() >= 2 => names,
+            (Some(_), _) => return Err("--names needs at least 2 names".to_string()),
+            (None, Some(count)) => set_names(count),
+            (None, None) => return Err(USAGE.to_string()),
+        };
+
+        let values = read_column(&path, &column)?;
+        let (var, warnings) =
+            FuzzyVariable::from_observations(&column, &values, &names, placement, overlap);
+        for warning in warnings {
+            eprintln!("warning: {}", warning);
+        }
+        Ok(format!(
+            "# {} sets from {} values of '{}' in {}\n{}",
+            names.len(),
+            values.len(),
+            column,
+            path,
+            config::variable_to_toml("input", true, &var, false)
+        ))
+    })();
+    match result {
+        Ok(toml) => {
+            print!("{}", toml);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn names(count: usize) -> Vec<String> {
+        set_names(count)
+    }
+
+    /// Box-Muller samples of N(`mean`, `sd`)
+    fn normal(rng: &mut StdRng, count: usize, mean: f64, sd: f64) -> Vec<f64> {
+        (0..count)
+            .map(|_| {
+                let (u, v): (f64, f64) = (rng.r#gen::<f64>().max(1e-12), rng.r#gen());
+                mean + sd * (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+            })
+            .collect()
+    }
+
+    /// Center a set was placed at, with a full overlap: the apex, or the
+    /// inner end of a shoulder's plateau
+    fn center(var: &FuzzyVariable, set: usize) -> f64 {
+        match var.sets[set].function {
+            MembershipFunction::Triangular { b, .. } => b,
+            MembershipFunction::Trapezoidal { c, .. } if set == 0 => c,
+            MembershipFunction::Trapezoidal { b, .. } => b,
+            MembershipFunction::Gaussian { mean, .. } => mean,
+            MembershipFunction::Sigmoid { center, .. } => center,
+            MembershipFunction::GeneralizedBell { c, .. } => c,
+            MembershipFunction::SCurve { b, .. } => b,
+            MembershipFunction::ZCurve { a, .. } => a,
+            MembershipFunction::Custom(_) => f64::NAN,
+        }
+    }
+
+    fn close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn uniform_data_gets_evenly_spaced_triangles() {
+        let values: Vec<f64> = (0..=1000).map(|i| i as f64 / 10.0).collect();
+        let (var, warnings) = suggest_sets(&values, &names(3), Placement::Quantile, 1.0);
+        assert!(warnings.is_empty());
+        assert_eq!((var.min, var.max), (0.0, 100.0));
+        assert_eq!(
+            var.sets
+                .iter()
+                .map(|s| s.function.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                MembershipFunction::Triangular {
+                    a: 0.0,
+                    b: 0.0,
+                    c: 50.0
+                },
+                MembershipFunction::Triangular {
+                    a: 0.0,
+                    b: 50.0,
+                    c: 100.0
+                },
+                MembershipFunction::Triangular {
+                    a: 50.0,
+                    b: 100.0,
+                    c: 100.0
+                },
+            ]
+        );
+
+        // k-means splits the range into thirds and centers a set on each
+        let (var, _) = suggest_sets(&values, &names(3), Placement::KMeans, 1.0);
+        for (set, expected) in [100.0 / 6.0, 50.0, 500.0 / 6.0].into_iter().enumerate() {
+            close(center(&var, set), expected, 0.5);
+        }
+    }
+
+    #[test]
+    fn normal_data_centers_the_middle_set_on_the_mean() {
+        let mut rng = StdRng::seed_from_u64(429);
+        let values = normal(&mut rng, 4000, 20.0, 5.0);
+        for placement in [Placement::Quantile, Placement::KMeans] {
+            let (var, warnings) = suggest_sets(&values, &names(3), placement, 1.0);
+            assert!(warnings.is_empty());
+            close(center(&var, 1), 20.0, 0.5);
+        }
+
+        // Quantile centers crowd where the data is dense
+        let (var, _) = suggest_sets(&values, &names(5), Placement::Quantile, 1.0);
+        close(center(&var, 1), 20.0 - 0.674 * 5.0, 0.5);
+        close(center(&var, 3), 20.0 + 0.674 * 5.0, 0.5);
+    }
+
+    #[test]
+    fn bimodal_data_puts_a_set_on_each_mode() {
+        let mut rng = StdRng::seed_from_u64(430);
+        let mut values = normal(&mut rng, 1000, 10.0, 1.0);
+        values.extend(normal(&mut rng, 1000, 40.0, 1.0));
+        let (var, warnings) = suggest_sets(&values, &names(2), Placement::KMeans, 1.0);
+        assert!(warnings.is_empty());
+        close(center(&var, 0), 10.0, 0.5);
+        close(center(&var, 1), 40.0, 0.5);
+        // Neighbours cross at 0.5 halfway between the centers
+        let middle = (center(&var, 0) + center(&var, 1)) / 2.0;
+        close(var.sets[0].function.evaluate(middle), 0.5, 1e-9);
+        close(var.sets[1].function.evaluate(middle), 0.5, 1e-9);
+    }
+
+    #[test]
+    fn a_partial_overlap_leaves_plateaus() {
+        let values: Vec<f64> = (0..=100).map(f64::from).collect();
+        let (var, _) = suggest_sets(&values, &names(3), Placement::Quantile, 0.5);
+        assert_eq!(
+            var.sets[1].function,
+            MembershipFunction::Trapezoidal {
+                a: 12.5,
+                b: 37.5,
+                c: 62.5,
+                d: 87.5
+            }
+        );
+    }
+
+    #[test]
+    fn constant_and_skewed_data_fall_back_to_equal_spacing() {
+        let (var, warnings) = suggest_sets(&[5.0; 20], &names(3), Placement::Quantile, 1.0);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((var.min, var.max), (4.0, 6.0));
+        assert_eq!(center(&var, 1), 5.0);
+
+        let mut values = vec![0.0; 990];
+        values.extend([100.0; 10]);
+        let (var, warnings) = suggest_sets(&values, &names(3), Placement::Quantile, 1.0);
+        assert!(warnings[0].contains("equal spacing"));
+        assert_eq!(center(&var, 1), 50.0);
+    }
+
+    #[test]
+    fn the_builder_names_the_variable() {
+        let values = [1.0, 2.0, 3.0];
+        let (var, _) =
+            FuzzyVariable::from_observations("co2", &values, &names(2), Placement::Quantile, 1.0);
+        assert_eq!(var.name, "co2");
+        assert_eq!(var.sets[0].name, "Low");
+    }
+}
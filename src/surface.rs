@@ -0,0 +1,754 @@
+// ============================================================================
+// CONTROL SURFACE - Superfície de controle
+// ============================================================================
+//
+// `surface` samples the output over a grid of the first two inputs (any
+// further inputs are held at the middle of their range) and prints it as
+// CSV. With `--samples K`, K controllers are built once with every set
+// parameter perturbed inside its configured `uncertainty` range and the
+// pointwise min/max over them is added as an uncertainty band.
+//
+// `--ascii` draws the surface as text instead, one character per grid
+// point from the RAMP (low to high over the output universe), with tick
+// labels on both axes and `--at X,Y` marked with 'X'; it pastes into
+// issues and chat as is. The TUI surface view ('v') uses the same
+// rendering on terminals without color.
+
+use crate::config;
+use crate::{FuzzyController, FuzzySystem, MembershipFunction};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::io::Write;
+
+const USAGE: &str = "Usage: fuzzy_logic surface [--config FILE] [--grid N] \
+                     [--samples K] [--sampling uniform|lhs] [--seed S] \
+                     [--ascii [--at X,Y]]";
+
+/// Characters of the text rendering, from the output minimum to maximum
+pub const RAMP: &[u8] = b" .:-=+*#%@";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Sampling {
+    Uniform,
+    LatinHypercube,
+}
+
+/// Output over a `size` x `size` grid of the first two inputs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Surface {
+    pub xs: Vec<f64>,
+    pub ys: Vec<f64>,
+    /// `values[row][column]`, row indexing `ys` and column indexing `xs`
+    pub values: Vec<Vec<f64>>,
+}
+
+/// Evenly spaced grid coordinates covering `[min, max]`
+fn axis(min: f64, max: f64, size: usize) -> Vec<f64> {
+    (0..size)
+        .map(|i| min + (max - min) * i as f64 / (size - 1).max(1) as f64)
+        .collect()
+}
+
+impl Surface {
+    /// The surface on a `columns` x `rows` grid over the same ranges, each
+    /// point taking the value of the nearest computed one: a coarse
+    /// surface fills a larger panel in blocks
+    pub fn resample(&self, columns: usize, rows: usize) -> Surface {
+        let nearest = |axis: &[f64], value: f64| {
+            let (first, last) = (axis[0], axis[axis.len() - 1]);
+            let share = if last > first {
+                (value - first) / (last - first)
+            } else {
+                0.0
+            };
+            ((share * (axis.len() - 1) as f64).round() as usize).min(axis.len() - 1)
+        };
+        let xs = axis(self.xs[0], self.xs[self.xs.len() - 1], columns);
+        let ys = match self.ys.len() {
+            1 => self.ys.clone(),
+            _ => axis(self.ys[0], self.ys[self.ys.len() - 1], rows),
+        };
+        let values = ys
+            .iter()
+            .map(|y| {
+                let row = &self.values[nearest(&self.ys, *y)];
+                xs.iter().map(|x| row[nearest(&self.xs, *x)]).collect()
+            })
+            .collect();
+        Surface { xs, ys, values }
+    }
+}
+
+/// Evaluate several controllers on the same `columns` x `rows` grid,
+/// splitting the rows across the available cores
+pub fn compute_surfaces(
+    controllers: &[FuzzyController],
+    columns: usize,
+    rows: usize,
+) -> Vec<Surface> {
+    compute_surfaces_until(controllers, columns, rows, &|| false).unwrap_or_default()
+}
+
+/// `compute_surfaces`, given up between rows once `stop` says so: none
+/// then
+pub fn compute_surfaces_until(
+    controllers: &[FuzzyController],
+    columns: usize,
+    rows: usize,
+    stop: &(dyn Fn() -> bool + Sync),
+) -> Option<Vec<Surface>> {
+    let Some(first) = controllers.first() else {
+        return Some(Vec::new());
+    };
+    let inputs = &first.system.inputs;
+    let xs = axis(inputs[0].min, inputs[0].max, columns);
+    let ys = match inputs.get(1) {
+        Some(var) => axis(var.min, var.max, rows),
+        None => vec![0.0],
+    };
+    let fixed: Vec<f64> = inputs
+        .iter()
+        .skip(2)
+        .map(|var| (var.min + var.max) / 2.0)
+        .collect();
+
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let rows_per_thread = ys.len().div_ceil(threads);
+
+    controllers
+        .iter()
+        .map(|controller| {
+            let mut values = vec![Vec::new(); ys.len()];
+            std::thread::scope(|scope| {
+                for (chunk, ys) in values
+                    .chunks_mut(rows_per_thread)
+                    .zip(ys.chunks(rows_per_thread))
+                {
+                    let (xs, fixed) = (&xs, &fixed);
+                    scope.spawn(move || {
+                        let mut session = controller.session();
+                        for (row, y) in chunk.iter_mut().zip(ys) {
+                            if stop() {
+                                return;
+                            }
+                            *row = xs
+                                .iter()
+                                .map(|x| {
+                                    let mut point = vec![*x];
+                                    if controller.system.inputs.len() > 1 {
+                                        point.push(*y);
+                                    }
+                                    point.extend_from_slice(fixed);
+                                    session.compute(&point)
+                                })
+                                .collect();
+                        }
+                    });
+                }
+            });
+            Surface {
+                xs: xs.clone(),
+                ys: ys.clone(),
+                values,
+            }
+        })
+        .map(|surface| (!stop()).then_some(surface))
+        .collect()
+}
+
+/// Per input, the range whose memberships a change from `old` to `new`
+/// can move: the union of the old and new supports of every set whose
+/// shape changed, none where no set did. A set is 0 outside its support
+/// under either shape, so a reading outside the range fires every rule as
+/// before. None when anything but set shapes changed (the rules, the
+/// outputs, a universe or any other setting), which moves the whole
+/// surface
+pub fn dependencies(old: &FuzzySystem, new: &FuzzySystem) -> Option<Vec<Option<(f64, f64)>>> {
+    if old.inputs.len() != new.inputs.len() {
+        return None;
+    }
+    // `new` with the old shapes back must be `old` itself
+    let mut unchanged = new.clone();
+    let mut ranges = vec![None; old.inputs.len()];
+    for ((was, var), range) in old
+        .inputs
+        .iter()
+        .zip(&mut unchanged.inputs)
+        .zip(&mut ranges)
+    {
+        if was.sets.len() != var.sets.len() {
+            return None;
+        }
+        for (before, set) in was.sets.iter().zip(&mut var.sets) {
+            if before.function == set.function {
+                continue;
+            }
+            let (a, b) = (before.function.support(), set.function.support());
+            let (lo, hi) = range.unwrap_or((f64::INFINITY, f64::NEG_INFINITY));
+            *range = Some((lo.min(a.0).min(b.0), hi.max(a.1).max(b.1)));
+            set.function = before.function.clone();
+        }
+    }
+    (unchanged == *old).then_some(ranges)
+}
+
+/// The surface of `new` from `surface`, the surface of `old` on the same
+/// grid, computing again only the cells whose readings fall within the
+/// `dependencies` of the change, widened by a grid step either way. None
+/// when the change moves the whole surface, or an input held at the
+/// middle of its range depends on it; the caller computes it all then
+pub fn update(surface: &Surface, old: &FuzzySystem, new: &FuzzySystem) -> Option<Surface> {
+    let ranges = dependencies(old, new)?;
+    let middle = |var: &crate::FuzzyVariable| (var.min + var.max) / 2.0;
+    let held: Vec<f64> = new.inputs.iter().skip(2).map(middle).collect();
+    for (range, value) in ranges.iter().skip(2).zip(&held) {
+        if range.is_some_and(|(lo, hi)| (lo..=hi).contains(value)) {
+            return None;
+        }
+    }
+    let step = |axis: &[f64]| match axis {
+        [first, second, ..] => second - first,
+        _ => 0.0,
+    };
+    let within = |index: usize, value: f64, margin: f64| {
+        ranges
+            .get(index)
+            .copied()
+            .flatten()
+            .is_some_and(|(lo, hi)| value >= lo - margin && value <= hi + margin)
+    };
+    let (x_margin, y_margin) = (step(&surface.xs), step(&surface.ys));
+
+    let controller = FuzzyController::from_system(new.clone());
+    let mut session = controller.session();
+    let mut patched = surface.clone();
+    for (row, y) in patched.values.iter_mut().zip(&surface.ys) {
+        let row_moves = new.inputs.len() > 1 && within(1, *y, y_margin);
+        for (value, x) in row.iter_mut().zip(&surface.xs) {
+            if !row_moves && !within(0, *x, x_margin) {
+                continue;
+            }
+            let mut point = vec![*x];
+            if new.inputs.len() > 1 {
+                point.push(*y);
+            }
+            point.extend_from_slice(&held);
+            *value = session.compute(&point);
+        }
+    }
+    Some(patched)
+}
+
+/// Copies of `system` with every uncertain set parameter drawn from its
+/// ± range. Latin hypercube sampling stratifies each parameter into
+/// `count` slices and uses every slice exactly once.
+fn perturbed_systems(
+    system: &FuzzySystem,
+    count: usize,
+    sampling: Sampling,
+    rng: &mut StdRng,
+) -> Vec<FuzzySystem> {
+    let mut systems = vec![system.clone(); count];
+    let variables = system
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(i, var)| (Some(i), var))
+        .chain(std::iter::once((None, system.output())));
+
+    for (input_index, var) in variables {
+        for (set_index, set) in var.sets.iter().enumerate() {
+            if set.uncertainty.iter().all(|u| *u == 0.0) {
+                continue;
+            }
+            let base = set.function.params();
+            let mut samples: Vec<Vec<f64>> = vec![base.clone(); count];
+
+            for (p, range) in set.uncertainty.iter().enumerate() {
+                let offsets: Vec<f64> = match sampling {
+                    Sampling::Uniform => (0..count).map(|_| rng.gen_range(-1.0..=1.0)).collect(),
+                    Sampling::LatinHypercube => {
+                        let mut strata: Vec<usize> = (0..count).collect();
+                        strata.shuffle(rng);
+                        strata
+                            .into_iter()
+                            .map(|s| -1.0 + 2.0 * (s as f64 + rng.r#gen::<f64>()) / count as f64)
+                            .collect()
+                    }
+                };
+                for (sample, offset) in samples.iter_mut().zip(offsets) {
+                    sample[p] += offset * range;
+                }
+            }
+
+            // Keep breakpoints ordered so the shape stays valid; the
+            // parameters of the other shapes have no order to keep
+            let breakpoints = matches!(
+                set.function,
+                MembershipFunction::Triangular { .. }
+                    | MembershipFunction::Trapezoidal { .. }
+                    | MembershipFunction::SCurve { .. }
+                    | MembershipFunction::ZCurve { .. }
+            );
+            for (perturbed, mut params) in systems.iter_mut().zip(samples) {
+                if breakpoints {
+                    params.sort_by(f64::total_cmp);
+                }
+                let target = match input_index {
+                    Some(i) => &mut perturbed.inputs[i].sets[set_index],
+                    None => &mut perturbed.outputs[0].sets[set_index],
+                };
+                if let Ok(function) = MembershipFunction::from_params(set.function.kind(), &params)
+                {
+                    target.function = function;
+                }
+            }
+        }
+    }
+
+    systems
+}
+
+/// Ramp character for `value` on the `min..max` scale, '?' for a failed
+/// computation
+pub fn ramp(value: f64, min: f64, max: f64) -> char {
+    if !value.is_finite() {
+        return '?';
+    }
+    let share = ((value - min) / (max - min)).clamp(0.0, 1.0);
+    RAMP[(share * (RAMP.len() - 1) as f64).round() as usize] as char
+}
+
+/// Grid cell nearest to `point`, as (row, column)
+pub fn cell_of(surface: &Surface, point: (f64, f64)) -> (usize, usize) {
+    let nearest = |axis: &[f64], value: f64| {
+        (0..axis.len())
+            .min_by(|a, b| {
+                (axis[*a] - value)
+                    .abs()
+                    .total_cmp(&(axis[*b] - value).abs())
+            })
+            .unwrap_or(0)
+    };
+    (nearest(&surface.ys, point.1), nearest(&surface.xs, point.0))
+}
+
+/// `surface` of `system` as text: the highest `y` row on top, `point`
+/// marked with 'X', ticks at both ends and the middle of each axis
+pub fn render_ascii(
+    surface: &Surface,
+    system: &FuzzySystem,
+    point: Option<(f64, f64)>,
+) -> Vec<String> {
+    let output = system.output();
+    let marked = point.map(|p| cell_of(surface, p));
+    let rows = surface.ys.len();
+    let ticked = |i: usize, count: usize| i == 0 || i + 1 == count || i == (count - 1) / 2;
+    let labels: Vec<String> = (0..rows)
+        .map(|row| match ticked(row, rows) {
+            true => format!("{:.1}", surface.ys[row]),
+            false => String::new(),
+        })
+        .collect();
+    let width = labels.iter().map(String::len).max().unwrap_or(0);
+    let pad = " ".repeat(width);
+
+    let mut lines = vec![format!(
+        "{} {}",
+        pad,
+        system.inputs.get(1).map_or("", |v| v.name.as_str())
+    )];
+    for row in (0..rows).rev() {
+        let cells: String = surface.values[row]
+            .iter()
+            .enumerate()
+            .map(|(column, value)| match marked == Some((row, column)) {
+                true => 'X',
+                false => ramp(*value, output.min, output.max),
+            })
+            .collect();
+        lines.push(format!("{:>width$} |{}", labels[row], cells));
+    }
+    lines.push(format!("{} +{}", pad, "-".repeat(surface.xs.len())));
+
+    // Tick labels under their columns as far as they fit, the last one
+    // ending at its column when there is room
+    let columns = surface.xs.len();
+    let mut ticks: Vec<char> = vec![' '; columns + 8];
+    for column in (0..columns).filter(|c| ticked(*c, columns)) {
+        let label = format!("{:.1}", surface.xs[column]);
+        let fits = |start: usize, ticks: &[char]| {
+            let end = (start + label.len() + 1).min(ticks.len());
+            ticks[start.saturating_sub(1)..end]
+                .iter()
+                .all(|c| *c == ' ')
+        };
+        let right = (column + 1).saturating_sub(label.len());
+        let start = match column + 1 == columns && fits(right, &ticks) {
+            true => right,
+            false if fits(column, &ticks) => column,
+            false => continue,
+        };
+        ticks.splice(start..start + label.len(), label.chars());
+    }
+    let ticks: String = ticks.into_iter().collect();
+    lines.push(format!("{}  {}", pad, ticks.trim_end()));
+    lines.push(format!(
+        "{}  {} -> {}, \"{}\" = {} to {}",
+        pad,
+        system.inputs[0].name,
+        output.name,
+        String::from_utf8_lossy(RAMP),
+        output.min,
+        output.max
+    ));
+    lines
+}
+
+/// Pointwise min and max over every surface, the nominal one included
+fn band(surfaces: &[Surface], row: usize, column: usize) -> (f64, f64) {
+    let values = surfaces.iter().map(|s| s.values[row][column]);
+    let min = values.clone().fold(f64::INFINITY, f64::min);
+    let max = values.fold(f64::NEG_INFINITY, f64::max);
+    (min, max)
+}
+
+/// Header of the CSV output, naming the unit of each column; the band
+/// columns follow the output with `--samples`
+fn csv_header(system: &FuzzySystem, band: bool) -> String {
+    let x = &system.inputs[0];
+    let y = match system.inputs.get(1) {
+        Some(var) => var.unit.column(&var.name),
+        None => "_".to_string(),
+    };
+    let output = system.output();
+    let mut header = format!(
+        "{},{},{}",
+        x.unit.column(&x.name),
+        y,
+        output.unit.column(&output.name)
+    );
+    if band {
+        header.push_str(&format!(
+            ",{},{},band",
+            output.unit.column("min"),
+            output.unit.column("max")
+        ));
+    }
+    header
+}
+
+#[doc(hidden)]
+pub fn run(args: &[String]) -> i32 {
+    let mut system = FuzzySystem::demo();
+    let mut size = 21;
+    let mut samples = 0;
+    let mut sampling = Sampling::LatinHypercube;
+    let mut seed = 0;
+    let mut ascii = false;
+    let mut point = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--ascii" {
+            ascii = true;
+            continue;
+        }
+        let value = iter.next();
+        let result = match (arg.as_str(), value) {
+            ("--at", Some(at)) => match at.split_once(',').map(|(x, y)| (x.parse(), y.parse())) {
+                Some((Ok(x), Ok(y))) => {
+                    point = Some((x, y));
+                    Ok(())
+                }
+                _ => Err("--at expects X,Y".to_string()),
+            },
+            ("--config", Some(path)) => config::load_system(path).map(|s| system = s),
+            ("--grid", Some(n)) => match n.parse::<usize>() {
+                Ok(n) if n >= 2 => {
+                    size = n;
+                    Ok(())
+                }
+                _ => Err("--grid expects an integer of at least 2".to_string()),
+            },
+            ("--samples", Some(k)) => k
+                .parse()
+                .map(|k| samples = k)
+                .map_err(|_| "--samples expects an integer".to_string()),
+            ("--sampling", Some(kind)) => match kind.as_str() {
+                "uniform" => {
+                    sampling = Sampling::Uniform;
+                    Ok(())
+                }
+                "lhs" => {
+                    sampling = Sampling::LatinHypercube;
+                    Ok(())
+                }
+                _ => Err(format!("unknown sampling '{}'", kind)),
+            },
+            ("--seed", Some(s)) => s
+                .parse()
+                .map(|s| seed = s)
+                .map_err(|_| "--seed expects an integer".to_string()),
+            _ => Err(USAGE.to_string()),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            return 2;
+        }
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut controllers = vec![FuzzyController::from_system(system.clone())];
+    controllers.extend(
+        perturbed_systems(&system, samples, sampling, &mut rng)
+            .into_iter()
+            .map(FuzzyController::from_system),
+    );
+    let surfaces = compute_surfaces(&controllers, size, size);
+    let nominal = &surfaces[0];
+    if ascii {
+        for line in render_ascii(nominal, &system, point) {
+            println!("{}", line);
+        }
+        return 0;
+    }
+
+    match write_csv(&system, &surfaces, &mut std::io::stdout().lock()) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+/// Write the first of `surfaces` as CSV, one line per grid point, with
+/// the uncertainty band over all of them when there are several
+pub fn write_csv(
+    system: &FuzzySystem,
+    surfaces: &[Surface],
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    let Some(nominal) = surfaces.first() else {
+        return Ok(());
+    };
+    writeln!(out, "{}", csv_header(system, surfaces.len() > 1))?;
+    for (row, y) in nominal.ys.iter().enumerate() {
+        for (column, x) in nominal.xs.iter().enumerate() {
+            let value = nominal.values[row][column];
+            if surfaces.len() == 1 {
+                writeln!(out, "{:.4},{:.4},{:.4}", x, y, value)?;
+                continue;
+            }
+            let (min, max) = band(surfaces, row, column);
+            writeln!(
+                out,
+                "{:.4},{:.4},{:.4},{:.4},{:.4},{:.4}",
+                x,
+                y,
+                value,
+                min,
+                max,
+                max - min
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// `x` fires `B` fully across its universe; `B` is a triangle whose
+    /// peak has the given ± range
+    fn system(peak_uncertainty: f64) -> FuzzySystem {
+        config::parse_system(&format!(
+            "rules = [\"IF x IS A THEN y IS B\"]\n\
+             [[input]]\nname = \"x\"\nmin = 0\nmax = 10\n\
+             [[input.set]]\nname = \"A\"\nshape = \"trapezoidal\"\nparams = [-1, 0, 10, 11]\n\
+             [output]\nname = \"y\"\nmin = 0\nmax = 10\n\
+             [[output.set]]\nname = \"B\"\nshape = \"triangular\"\nparams = [0, 5, 10]\n\
+             uncertainty = [0, {}, 0]\n",
+            peak_uncertainty
+        ))
+        .unwrap()
+    }
+
+    fn widths(system: &FuzzySystem, samples: usize, sampling: Sampling) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut controllers = vec![FuzzyController::from_system(system.clone())];
+        controllers.extend(
+            perturbed_systems(system, samples, sampling, &mut rng)
+                .into_iter()
+                .map(FuzzyController::from_system),
+        );
+        let surfaces = compute_surfaces(&controllers, 11, 11);
+        (0..11)
+            .map(|column| {
+                let (min, max) = band(&surfaces, 0, column);
+                max - min
+            })
+            .collect()
+    }
+
+    fn bits(surface: &Surface) -> Vec<Vec<u64>> {
+        (surface.values.iter())
+            .map(|row| row.iter().map(|v| v.to_bits()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn patching_a_moved_set_matches_computing_the_surface_again() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut system = fixtures::fan();
+        let surface = |system: &FuzzySystem| {
+            compute_surfaces(&[FuzzyController::from_system(system.clone())], 23, 17).remove(0)
+        };
+        let mut patched = surface(&system);
+        for _ in 0..60 {
+            let mut moved = system.clone();
+            let input = rng.gen_range(0..2);
+            let var = &mut moved.inputs[input];
+            let set = rng.gen_range(0..var.sets.len());
+            let function = &var.sets[set].function;
+            let mut params = function.params();
+            let param = rng.gen_range(0..params.len());
+            params[param] += rng.gen_range(-5.0..5.0);
+            params.sort_by(f64::total_cmp);
+            var.sets[set].function =
+                MembershipFunction::from_params(function.kind(), &params).unwrap();
+
+            let ranges = dependencies(&system, &moved).unwrap();
+            assert!(ranges[input].is_some());
+            assert!(ranges[1 - input].is_none());
+            patched = update(&patched, &system, &moved).unwrap();
+            assert_eq!(bits(&patched), bits(&surface(&moved)));
+            system = moved;
+        }
+    }
+
+    #[test]
+    fn changes_beyond_set_shapes_need_the_whole_surface() {
+        let system = fixtures::fan();
+        let surface = compute_surfaces(&[FuzzyController::from_system(system.clone())], 5, 5);
+        let unchanged = update(&surface[0], &system, &system).unwrap();
+        assert_eq!(unchanged, surface[0]);
+
+        let mut reweighted = system.clone();
+        reweighted.rules[0].weight = 0.5;
+        assert_eq!(dependencies(&system, &reweighted), None);
+        let mut output = system.clone();
+        output.outputs[0].sets[0].function = MembershipFunction::Triangular {
+            a: 0.0,
+            b: 10.0,
+            c: 20.0,
+        };
+        assert!(update(&surface[0], &system, &output).is_none());
+        let mut wider = system.clone();
+        wider.inputs[0].max = 60.0;
+        assert!(update(&surface[0], &system, &wider).is_none());
+    }
+
+    #[test]
+    fn zero_ranges_give_a_zero_band() {
+        for sampling in [Sampling::Uniform, Sampling::LatinHypercube] {
+            let widths = widths(&system(0.0), 16, sampling);
+            assert!(widths.iter().all(|w| *w == 0.0), "{:?}", widths);
+        }
+    }
+
+    #[test]
+    fn a_perturbed_peak_moves_the_centroid_by_a_third() {
+        // The triangle [0, b, 10] is never clipped and its centroid is
+        // (0 + b + 10) / 3, so b in 5 ± 3 spans a band of 2 everywhere
+        let widths = widths(&system(3.0), 64, Sampling::LatinHypercube);
+        assert!(widths.iter().all(|w| *w > 1.9 && *w < 2.05), "{:?}", widths);
+    }
+
+    #[test]
+    fn parameters_that_are_no_breakpoints_keep_their_places() {
+        let system = config::parse_system(
+            "rules = [\"IF x IS A THEN y IS B\"]\n\
+             [[input]]\nname = \"x\"\nmin = 0\nmax = 10\n\
+             [[input.set]]\nname = \"A\"\nshape = \"gaussian\"\nparams = [5, 1]\n\
+             uncertainty = [0, 0.1]\n\
+             [output]\nname = \"y\"\nmin = 0\nmax = 10\n\
+             [[output.set]]\nname = \"B\"\nshape = \"sigmoid\"\nparams = [5, -2]\n\
+             uncertainty = [0, 0.5]\n",
+        )
+        .unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        for sampling in [Sampling::Uniform, Sampling::LatinHypercube] {
+            for perturbed in perturbed_systems(&system, 16, sampling, &mut rng) {
+                match perturbed.inputs[0].sets[0].function {
+                    MembershipFunction::Gaussian { mean, sigma } => {
+                        assert_eq!(mean, 5.0);
+                        assert!((0.9..=1.1).contains(&sigma), "{}", sigma);
+                    }
+                    ref other => panic!("{:?}", other),
+                }
+                match perturbed.outputs[0].sets[0].function {
+                    MembershipFunction::Sigmoid { center, slope } => {
+                        assert_eq!(center, 5.0);
+                        assert!((-2.5..=-1.5).contains(&slope), "{}", slope);
+                    }
+                    ref other => panic!("{:?}", other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_ascii_rendering_of_the_demo_is_pinned() {
+        let system = fixtures::fan();
+        let controllers = [FuzzyController::from_system(system.clone())];
+        let surface = compute_surfaces(&controllers, 5, 5).remove(0);
+        let lines = render_ascii(&surface, &system, Some((30.0, 60.0)));
+        assert_eq!(
+            lines,
+            [
+                "      humidity",
+                "100.0 |::+%%",
+                "      |::+%%",
+                " 50.0 |..X%%",
+                "      |..:++",
+                "  0.0 |..:++",
+                "      +-----",
+                "       0.0 50.0",
+                "       temperature -> fan_speed, \" .:-=+*#%@\" = 0 to 100",
+            ]
+        );
+        assert_eq!(ramp(f64::NAN, 0.0, 100.0), '?');
+        assert_eq!(ramp(100.0, 0.0, 100.0), '@');
+
+        // Stretched over 10x3, each point from the nearest of the 5x5
+        let stretched = surface.resample(10, 3);
+        assert_eq!((stretched.xs.len(), stretched.ys.len()), (10, 3));
+        assert_eq!(stretched.xs[9], surface.xs[4]);
+        let columns = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4];
+        assert_eq!(stretched.values[1], columns.map(|c| surface.values[2][c]));
+        assert_eq!(
+            compute_surfaces_until(&controllers, 5, 5, &|| true).map(|s| s.len()),
+            None
+        );
+    }
+
+    #[test]
+    fn csv_columns_name_their_unit() {
+        let system = fixtures::fan();
+        assert_eq!(
+            csv_header(&system, false),
+            "temperature [°C],humidity [%],fan_speed [%]"
+        );
+        assert_eq!(
+            csv_header(&system, true),
+            "temperature [°C],humidity [%],fan_speed [%],min [%],max [%],band"
+        );
+    }
+}
@@ -0,0 +1,401 @@
+// ============================================================================
+// TERMINAL GUARD - Guarda do terminal
+// ============================================================================
+//
+// The TUI runs the terminal in raw mode on the alternate screen, with mouse
+// and focus reporting. The Guard is the one place that switches that on
+// and off, and it knows which way it is, so every transition can be asked
+// for more than once: the shutdown's restore stage (shutdown.rs) leaves
+// the terminal through the guard and does nothing if a suspension already
+// did.
+//
+// Ctrl-Z: raw mode keeps the terminal from turning it into SIGTSTP, so the
+// TUI gets a key and asks for a stop (`request_stop`). A SIGTSTP sent from
+// elsewhere (`kill -TSTP`) asks for the same. Stopping gives the shell its
+// terminal back, then stops the process with SIGSTOP, which no handler
+// stands in the way of. On `fg` the process continues where it stopped:
+// the guard takes the terminal again and asks for a full redraw, which
+// replaces the manual resize this used to take. A SIGCONT after someone
+// else's SIGSTOP refreshes the terminal the same way, since the shell may
+// have reset it meanwhile.
+//
+// The handlers only set flags. The TUI loop takes them between two event
+// polls, on the thread that reads the input, so no read is in flight while
+// the terminal changes mode; prompts and everything else in the App stay
+// as they were. Once the shutdown has started (its token cancelled) a stop
+// is ignored: the restore stage is about to give the terminal back anyway.
+//
+// Manual test: with FUZZY_LOGIC_GUARD_TRACE=FILE every transition is
+// appended to FILE. Run the TUI, press Ctrl-Z, `fg`, send `kill -TSTP` and
+// `kill -CONT` from another terminal, and read the file. tests/suspend.rs
+// does the same on a child process, with a screen that switches nothing.
+
+use crate::shutdown::Token;
+use crossterm::event::{
+    DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture,
+};
+use crossterm::terminal::{
+    EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+};
+use crossterm::{cursor, execute};
+use std::io::{self, Write};
+
+/// Environment variable naming the file transitions are traced to
+pub const TRACE_VAR: &str = "FUZZY_LOGIC_GUARD_TRACE";
+
+/// What the guard switches on and off
+pub trait Screen: Send {
+    /// Raw mode, alternate screen, mouse and focus reporting
+    fn enter(&mut self) -> io::Result<()>;
+    /// The terminal as the shell expects it, cursor shown
+    fn leave(&mut self) -> io::Result<()>;
+}
+
+/// The terminal on stdout
+pub struct Crossterm;
+
+impl Screen for Crossterm {
+    fn enter(&mut self) -> io::Result<()> {
+        enable_raw_mode()?;
+        execute!(
+            io::stdout(),
+            EnterAlternateScreen,
+            EnableMouseCapture,
+            EnableFocusChange
+        )
+    }
+
+    fn leave(&mut self) -> io::Result<()> {
+        disable_raw_mode()?;
+        execute!(
+            io::stdout(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            DisableFocusChange,
+            cursor::Show
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Not taken yet, or given back for good
+    Off,
+    Active,
+    /// Given back to the shell while the process is stopped
+    Suspended,
+}
+
+/// What the signals asked for since they were last taken
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Pending {
+    /// SIGTSTP or Ctrl-Z
+    pub stop: bool,
+    /// SIGCONT
+    pub resumed: bool,
+}
+
+pub struct Guard<S: Screen = Crossterm> {
+    screen: S,
+    mode: Mode,
+    redraw: bool,
+    trace: Option<Box<dyn Write + Send>>,
+}
+
+impl<S: Screen> Guard<S> {
+    pub fn new(screen: S) -> Self {
+        Guard {
+            screen,
+            mode: Mode::Off,
+            redraw: false,
+            trace: None,
+        }
+    }
+
+    /// Append every transition to `path`, one per line
+    pub fn trace_to(&mut self, path: &str) -> io::Result<()> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        self.trace = Some(Box::new(file));
+        Ok(())
+    }
+
+    fn note(&mut self, transition: &str) {
+        if let Some(trace) = &mut self.trace {
+            // A trace that cannot be written is not worth stopping for
+            let _ = writeln!(trace, "{}", transition).and_then(|_| trace.flush());
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Take the terminal
+    pub fn enter(&mut self) -> io::Result<()> {
+        self.screen.enter()?;
+        self.mode = Mode::Active;
+        self.redraw = true;
+        self.note("enter");
+        Ok(())
+    }
+
+    /// Give the terminal back until `resume`; only when it is taken
+    pub fn suspend(&mut self) -> io::Result<()> {
+        if self.mode != Mode::Active {
+            return Ok(());
+        }
+        self.screen.leave()?;
+        self.mode = Mode::Suspended;
+        self.note("suspend");
+        Ok(())
+    }
+
+    /// Take the terminal back after `suspend`, for a full redraw
+    pub fn resume(&mut self) -> io::Result<()> {
+        if self.mode != Mode::Suspended {
+            return Ok(());
+        }
+        self.screen.enter()?;
+        self.mode = Mode::Active;
+        self.redraw = true;
+        self.note("resume");
+        Ok(())
+    }
+
+    /// Set the taken terminal up again, for a full redraw: something else
+    /// may have changed it
+    pub fn refresh(&mut self) -> io::Result<()> {
+        if self.mode != Mode::Active {
+            return Ok(());
+        }
+        self.screen.enter()?;
+        self.redraw = true;
+        self.note("refresh");
+        Ok(())
+    }
+
+    /// Give the terminal back for good; a suspended one already is
+    pub fn restore(&mut self) -> io::Result<()> {
+        if self.mode == Mode::Active {
+            self.screen.leave()?;
+        }
+        if self.mode != Mode::Off {
+            self.mode = Mode::Off;
+            self.note("restore");
+        }
+        Ok(())
+    }
+
+    /// Whether the screen has to be drawn in full, once
+    pub fn take_redraw(&mut self) -> bool {
+        std::mem::take(&mut self.redraw)
+    }
+
+    /// Carry out what the signals asked for: for a stop, give the terminal
+    /// back, `stop` the process and take the terminal again once it
+    /// continues, unless the shutdown started meanwhile
+    pub fn handle(
+        &mut self,
+        pending: Pending,
+        token: &Token,
+        stop: impl FnOnce(),
+    ) -> io::Result<()> {
+        if pending.stop && !token.is_cancelled() && self.mode == Mode::Active {
+            self.suspend()?;
+            stop();
+            self.note("continued");
+            if !token.is_cancelled() {
+                self.resume()?;
+            }
+            return Ok(());
+        }
+        if pending.resumed {
+            self.refresh()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+mod signals {
+    use super::Pending;
+    use libc::{SIGCONT, SIGSTOP, SIGTSTP};
+    use std::sync::atomic::{AtomicU8, Ordering};
+
+    const STOP: u8 = 1;
+    const RESUMED: u8 = 2;
+
+    static PENDING: AtomicU8 = AtomicU8::new(0);
+
+    /// Only async-signal-safe work: set the flag
+    extern "C" fn handle(signum: libc::c_int) {
+        let flag = match signum {
+            SIGTSTP => STOP,
+            _ => RESUMED,
+        };
+        PENDING.fetch_or(flag, Ordering::SeqCst);
+    }
+
+    pub fn install() {
+        unsafe {
+            libc::signal(SIGTSTP, handle as *const () as libc::sighandler_t);
+            libc::signal(SIGCONT, handle as *const () as libc::sighandler_t);
+        }
+    }
+
+    pub fn request_stop() {
+        PENDING.fetch_or(STOP, Ordering::SeqCst);
+    }
+
+    pub fn take() -> Pending {
+        let flags = PENDING.swap(0, Ordering::SeqCst);
+        Pending {
+            stop: flags & STOP != 0,
+            resumed: flags & RESUMED != 0,
+        }
+    }
+
+    /// Stop the process until a SIGCONT; the SIGCONT that ends this stop
+    /// is not a refresh of its own
+    pub fn stop_process() {
+        unsafe {
+            libc::raise(SIGSTOP);
+        }
+        PENDING.fetch_and(!RESUMED, Ordering::SeqCst);
+    }
+}
+
+/// Catch SIGTSTP and SIGCONT for `Guard::handle_signals`
+#[cfg(unix)]
+pub use signals::install;
+
+/// Ask for a stop as SIGTSTP does, for Ctrl-Z in raw mode
+#[cfg(unix)]
+pub use signals::request_stop;
+
+#[cfg(unix)]
+impl<S: Screen> Guard<S> {
+    /// `handle` what the signals asked for since the last call
+    pub fn handle_signals(&mut self, token: &Token) -> io::Result<()> {
+        self.handle(signals::take(), token, signals::stop_process)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Screen noting what it was asked to do
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<&'static str>>>);
+
+    impl Screen for Recorder {
+        fn enter(&mut self) -> io::Result<()> {
+            self.0.lock().unwrap().push("enter");
+            Ok(())
+        }
+
+        fn leave(&mut self) -> io::Result<()> {
+            self.0.lock().unwrap().push("leave");
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn each_transition_happens_once_whatever_asks_for_it() {
+        let screen = Recorder::default();
+        let mut guard = Guard::new(screen.clone());
+        // Nothing to give back before the terminal is taken
+        guard.suspend().unwrap();
+        guard.restore().unwrap();
+        guard.enter().unwrap();
+        assert!(guard.take_redraw());
+        assert!(!guard.take_redraw());
+
+        guard.suspend().unwrap();
+        guard.suspend().unwrap();
+        assert_eq!(guard.mode(), Mode::Suspended);
+        guard.refresh().unwrap();
+        guard.resume().unwrap();
+        assert!(guard.take_redraw());
+        guard.resume().unwrap();
+        assert!(!guard.take_redraw());
+        guard.refresh().unwrap();
+        assert!(guard.take_redraw());
+
+        // Shut down while suspended: the terminal is the shell's already
+        guard.suspend().unwrap();
+        guard.restore().unwrap();
+        guard.restore().unwrap();
+        assert_eq!(guard.mode(), Mode::Off);
+        assert_eq!(
+            *screen.0.lock().unwrap(),
+            ["enter", "leave", "enter", "enter", "leave"]
+        );
+    }
+
+    #[test]
+    fn a_stop_gives_the_terminal_back_until_the_process_continues() {
+        let screen = Recorder::default();
+        let mut guard = Guard::new(screen.clone());
+        let trace = std::env::temp_dir().join(format!("guard-trace-{}", std::process::id()));
+        let _ = std::fs::remove_file(&trace);
+        guard.trace_to(trace.to_str().unwrap()).unwrap();
+        guard.enter().unwrap();
+        guard.take_redraw();
+        let token = Token::default();
+        let stop = Pending {
+            stop: true,
+            resumed: false,
+        };
+
+        let calls = screen.0.clone();
+        let mut stopped = false;
+        guard
+            .handle(stop, &token, || {
+                // Stopped with the terminal given back
+                assert_eq!(calls.lock().unwrap().last(), Some(&"leave"));
+                stopped = true;
+            })
+            .unwrap();
+        assert!(stopped);
+        assert_eq!(guard.mode(), Mode::Active);
+        assert!(guard.take_redraw());
+
+        // A SIGCONT of its own sets the terminal up again
+        let resumed = Pending {
+            stop: false,
+            resumed: true,
+        };
+        guard.handle(resumed, &token, || unreachable!()).unwrap();
+        assert!(guard.take_redraw());
+
+        // Once the shutdown started, a stop is left to it
+        token.cancel();
+        guard.handle(stop, &token, || unreachable!()).unwrap();
+        assert_eq!(guard.mode(), Mode::Active);
+        guard.restore().unwrap();
+
+        assert_eq!(
+            *screen.0.lock().unwrap(),
+            ["enter", "leave", "enter", "enter", "leave"]
+        );
+        let traced = std::fs::read_to_string(&trace).unwrap();
+        std::fs::remove_file(&trace).unwrap();
+        assert_eq!(
+            traced.lines().collect::<Vec<_>>(),
+            [
+                "enter",
+                "suspend",
+                "continued",
+                "resume",
+                "refresh",
+                "restore"
+            ]
+        );
+    }
+}
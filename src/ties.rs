@@ -0,0 +1,95 @@
+// ============================================================================
+// TIES - Empates
+// ============================================================================
+//
+// Several readings pick one winner among strengths, and equal strengths are
+// no rare accident: a temperature exactly where two sets cross holds both at
+// 0.5, and the rules on them fire alike. Every one of them settles a tie the
+// same way, the lowest index winning:
+//
+//     classification   the output set declared first (`dominant_set` in
+//                      lib.rs), which the trace and scenarios record
+//     rule colors      the rule written first (rulecolor.rs), for the
+//                      markers and the regime strip
+//     rule matrix      the input sets declared first (matrix.rs)
+//     plateau centroid the first sample at the peak, the lowest along the
+//                      output universe
+//     envelopes        the envelope declared first, and of one envelope's
+//                      two limits the low one (envelope.rs)
+//     fused sources    of those furthest from the median, the source
+//                      declared first is outvoted (fusion.rs)
+//     interlocks       the interlock declared first (interlock.rs); their
+//                      priorities are integers and tie only when equal
+//
+// Strengths computed along different paths can differ in their last bits,
+// so two strengths within the controller's `tie_tolerance` of each other
+// count as equal ([defuzzification] in config.rs, TOLERANCE by default).
+// The winner is the first within the tolerance of the strongest, not the
+// first that no later one beats by more, so it does not depend on the
+// order the candidates are visited in beyond their index. `strongest` is
+// for strengths, where nothing above 0 means no winner; `greatest` takes
+// any values, distances and priorities included.
+
+/// Strengths this close count as equal, unless the config says otherwise
+pub const TOLERANCE: f64 = 1e-9;
+
+/// Index of the strongest of `strengths`, the lowest of those within
+/// `tolerance` of it; none when none is above 0
+pub fn strongest<I>(strengths: I, tolerance: f64) -> Option<usize>
+where
+    I: IntoIterator<Item = f64>,
+    I::IntoIter: Clone,
+{
+    let strengths = strengths.into_iter();
+    let peak = strengths.clone().fold(0.0, f64::max);
+    if peak <= 0.0 {
+        return None;
+    }
+    strengths
+        .into_iter()
+        .position(|strength| strength > 0.0 && strength >= peak - tolerance)
+}
+
+/// Index of the greatest of `values`, the lowest of those within
+/// `tolerance` of it; none when there are no values but NaN
+pub fn greatest<I>(values: I, tolerance: f64) -> Option<usize>
+where
+    I: IntoIterator<Item = f64>,
+    I::IntoIter: Clone,
+{
+    let values = values.into_iter();
+    let peak = values.clone().fold(f64::NAN, f64::max);
+    if peak.is_nan() {
+        return None;
+    }
+    values
+        .into_iter()
+        .position(|value| value >= peak - tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_lowest_index_wins_within_the_tolerance() {
+        assert_eq!(strongest([0.2, 0.5, 0.5, 0.1], 0.0), Some(1));
+        assert_eq!(strongest([0.2, 0.5, 0.5 + 1e-12], TOLERANCE), Some(1));
+        assert_eq!(strongest([0.2, 0.5, 0.5 + 1e-12], 0.0), Some(2));
+        // Against the strongest, not the one ahead of each
+        let drifting = [0.5, 0.5 + 0.6 * TOLERANCE, 0.5 + 1.2 * TOLERANCE];
+        assert_eq!(strongest(drifting, TOLERANCE), Some(1));
+
+        assert_eq!(strongest([0.0, 0.0], TOLERANCE), None);
+        assert_eq!(strongest([], TOLERANCE), None);
+        assert_eq!(strongest([0.0, 1e-12], TOLERANCE), Some(1));
+        assert_eq!(strongest([f64::NAN, 0.3], TOLERANCE), Some(1));
+
+        // Any values, the ones at or below 0 too
+        assert_eq!(greatest([-3.0, 0.0, 0.0], TOLERANCE), Some(1));
+        assert_eq!(greatest([-2.0, -1.0 - 1e-12, -1.0], TOLERANCE), Some(1));
+        assert_eq!(greatest([f64::NAN, -5.0], TOLERANCE), Some(1));
+        assert_eq!(greatest([f64::NAN], TOLERANCE), None);
+        assert_eq!(greatest([], TOLERANCE), None);
+    }
+}
@@ -0,0 +1,324 @@
+// ============================================================================
+// TIMESTAMPS - Marcas de tempo
+// ============================================================================
+//
+// Every wall-clock time this program keeps is UTC, as epoch seconds (the
+// state file, bookmarks). Wherever one is written for a person or another
+// system to read, it is RFC 3339 in UTC with milliseconds:
+//
+//     2026-10-17T08:30:00.250Z
+//
+// That covers journal entries, the bundle manifest and the daemon's log
+// lines. Whatever reads timestamps back (batch `--validate-time`, journal
+// replay) takes RFC 3339 with any offset, or plain epoch seconds.
+//
+// Only the TUI converts, to its display time zone:
+//
+//     [display]              # optional
+//     timezone = "local"     # the default; or "UTC", or an offset "+02:00"
+//
+// Local time comes from the C library's `localtime_r`, through the libc
+// crate, on Linux, Android, macOS, iOS and the BSDs, whose `struct tm`
+// carries the offset from UTC. Elsewhere it is UTC.
+//
+// The wall clock can step backwards (NTP, a manual change), so no duration
+// is ever taken from it: durations within a session come from `Instant`
+// (pipeline.rs), which never goes back, and `Instant::elapsed` saturates at
+// zero anyway. Wall-clock times are only recorded and shown.
+//
+// The two artifacts stamped with the time they were made, bundles
+// (bundle.rs) and fingerprint snapshots (fingerprint.rs), take
+// `SOURCE_DATE_EPOCH` instead when it is set, as reproducible builds do:
+// everything else they hold comes from their inputs, so two exports of
+// the same inputs then match byte for byte.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current wall-clock time in epoch seconds, negative before 1970
+pub fn now() -> f64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    }
+}
+
+/// Time to stamp an exported artifact with: `SOURCE_DATE_EPOCH` when it
+/// holds epoch seconds, the current time otherwise
+pub fn export_time() -> f64 {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse::<i64>().ok())
+        .map_or_else(now, |epoch| epoch as f64)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Year, month and day of a count of days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (
+        if month <= 2 {
+            yoe + era * 400 + 1
+        } else {
+            yoe + era * 400
+        },
+        month,
+        day,
+    )
+}
+
+/// Date and time of day of `epoch` shifted by `offset` seconds, to the
+/// millisecond: (year, month, day, hour, minute, millisecond of the minute)
+fn fields(epoch: f64, offset: i64) -> (i64, i64, i64, i64, i64, i64) {
+    let millis = (epoch * 1000.0).round() as i64 + offset * 1000;
+    let (days, of_day) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+    let (year, month, day) = civil_from_days(days);
+    (
+        year,
+        month,
+        day,
+        of_day / 3_600_000,
+        of_day % 3_600_000 / 60_000,
+        of_day % 60_000,
+    )
+}
+
+/// `epoch` as RFC 3339 in UTC, to the millisecond
+pub fn rfc3339(epoch: f64) -> String {
+    let (year, month, day, hour, minute, millis) = fields(epoch, 0);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        millis / 1000,
+        millis % 1000
+    )
+}
+
+/// Parse `YYYY-MM-DDTHH:MM:SS[.fff](Z|±HH:MM)` into epoch seconds
+pub fn parse_rfc3339(text: &str) -> Option<f64> {
+    let (date, rest) = text.split_once(['T', 't', ' '])?;
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: i64 = date_parts.next()?.parse().ok()?;
+    let day: i64 = date_parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (time, offset_secs) = if let Some(time) = rest.strip_suffix(['Z', 'z']) {
+        (time, 0)
+    } else {
+        let split = rest.rfind(['+', '-'])?;
+        let (time, offset) = rest.split_at(split);
+        (time, parse_offset(offset)?)
+    };
+
+    let mut time_parts = time.splitn(3, ':');
+    let hour: i64 = time_parts.next()?.parse().ok()?;
+    let minute: i64 = time_parts.next()?.parse().ok()?;
+    let second: f64 = time_parts.next()?.parse().ok()?;
+    if hour > 23 || minute > 59 || !(0.0..61.0).contains(&second) {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    Some((days * 86400 + hour * 3600 + minute * 60 - offset_secs) as f64 + second)
+}
+
+/// `±HH:MM` in seconds east of UTC
+fn parse_offset(text: &str) -> Option<i64> {
+    let sign = match text.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let (hours, minutes) = text[1..].split_once(':')?;
+    let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// A timestamp as RFC 3339 or as (fractional) epoch seconds
+pub fn parse(text: &str) -> Option<f64> {
+    text.parse::<f64>()
+        .ok()
+        .filter(|t| t.is_finite())
+        .or_else(|| parse_rfc3339(text))
+}
+
+/// Time zone the TUI shows wall-clock times in
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Zone {
+    #[default]
+    Local,
+    Utc,
+    /// Seconds east of UTC
+    Fixed(i64),
+}
+
+impl Zone {
+    /// "local", "UTC" or an offset `±HH:MM`
+    pub fn parse(text: &str) -> Option<Zone> {
+        match text {
+            "local" => Some(Zone::Local),
+            "UTC" | "utc" | "Z" => Some(Zone::Utc),
+            _ => parse_offset(text).map(Zone::Fixed),
+        }
+    }
+
+    /// The name `parse` takes back
+    pub fn name(self) -> String {
+        match self {
+            Zone::Local => "local".to_string(),
+            Zone::Utc => "UTC".to_string(),
+            Zone::Fixed(offset) => format!(
+                "{}{:02}:{:02}",
+                if offset < 0 { '-' } else { '+' },
+                offset.abs() / 3600,
+                offset.abs() % 3600 / 60
+            ),
+        }
+    }
+
+    /// How a title names the zone
+    pub fn label(self) -> String {
+        match self {
+            Zone::Local => "local time".to_string(),
+            Zone::Utc => "UTC".to_string(),
+            Zone::Fixed(_) => format!("UTC{}", self.name()),
+        }
+    }
+
+    /// Seconds east of UTC at `epoch`
+    pub fn offset_at(self, epoch: f64) -> i64 {
+        match self {
+            Zone::Local => local_offset(epoch),
+            Zone::Utc => 0,
+            Zone::Fixed(offset) => offset,
+        }
+    }
+
+    /// `MM-DD HH:MM` of `epoch` in this zone, compact enough for a table
+    /// column
+    pub fn short(self, epoch: f64) -> String {
+        let (_, month, day, hour, minute, _) = fields(epoch, self.offset_at(epoch));
+        format!("{:02}-{:02} {:02}:{:02}", month, day, hour, minute)
+    }
+}
+
+/// Offset of the local time zone at `epoch`, in seconds east of UTC, on
+/// the systems whose `struct tm` carries one
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+))]
+fn local_offset(epoch: f64) -> i64 {
+    let time = epoch.floor() as libc::time_t;
+    let mut tm = std::mem::MaybeUninit::<libc::tm>::uninit();
+    // SAFETY: both pointers are valid for the call, and `tm` is only read
+    // once localtime_r has filled it in
+    unsafe {
+        if libc::localtime_r(&time, tm.as_mut_ptr()).is_null() {
+            0
+        } else {
+            // c_long is 32 bits on some targets
+            #[allow(clippy::useless_conversion)]
+            i64::from(tm.assume_init().tm_gmtoff)
+        }
+    }
+}
+
+/// Elsewhere local time is UTC
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+)))]
+fn local_offset(_epoch: f64) -> i64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_are_written_in_utc_to_the_millisecond() {
+        assert_eq!(rfc3339(0.0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(rfc3339(1790841600.25), "2026-10-01T08:00:00.250Z");
+        // Rounded, carrying into the next second and day
+        assert_eq!(rfc3339(1790899199.9996), "2026-10-02T00:00:00.000Z");
+        assert_eq!(rfc3339(-0.5), "1969-12-31T23:59:59.500Z");
+        assert_eq!(rfc3339(951782400.0), "2000-02-29T00:00:00.000Z");
+        for epoch in [0.0, 1790841600.25, -86400.125, 4102444800.999] {
+            assert_eq!(parse(&rfc3339(epoch)), Some(epoch));
+        }
+    }
+
+    #[test]
+    fn timestamps_are_rfc3339_or_epoch_seconds() {
+        assert_eq!(parse("2026-10-01T08:00:00Z"), Some(1790841600.0));
+        assert_eq!(parse("2026-10-01T10:00:00.5+02:00"), Some(1790841600.5));
+        assert_eq!(parse("2026-10-01T03:30:00-04:30"), Some(1790841600.0));
+        assert_eq!(parse("1790841600.25"), Some(1790841600.25));
+        assert_eq!(parse("2026-13-01T08:00:00Z"), None);
+        assert_eq!(parse("2026-10-01T08:00:00+25:00"), None);
+        assert_eq!(parse("inf"), None);
+        assert_eq!(parse("yesterday"), None);
+    }
+
+    #[test]
+    fn display_zones_parse_and_shift_the_short_form() {
+        assert_eq!(Zone::parse("local"), Some(Zone::Local));
+        assert_eq!(Zone::parse("UTC"), Some(Zone::Utc));
+        assert_eq!(Zone::parse("-03:30"), Some(Zone::Fixed(-12600)));
+        assert_eq!(Zone::parse("Europe/Lisbon"), None);
+        for zone in [
+            Zone::Local,
+            Zone::Utc,
+            Zone::Fixed(-12600),
+            Zone::Fixed(3600),
+        ] {
+            assert_eq!(Zone::parse(&zone.name()), Some(zone));
+        }
+
+        let epoch = 1790841600.0;
+        assert_eq!(Zone::Utc.short(epoch), "10-01 08:00");
+        assert_eq!(Zone::Fixed(-12600).short(epoch), "10-01 04:30");
+        assert_eq!(Zone::Fixed(16 * 3600).short(epoch), "10-02 00:00");
+        assert!(Zone::Local.offset_at(epoch).abs() <= 14 * 3600);
+    }
+}
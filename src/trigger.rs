@@ -0,0 +1,163 @@
+// ============================================================================
+// RECOMPUTE TRIGGERS - Gatilhos de recálculo
+// ============================================================================
+//
+// A noisy sensor streaming `set` commands to the daemon would otherwise
+// cause a computation, a log line and a state update per reading. Each
+// input may declare a `min_delta`: a reading that moved less than that
+// from the value used by the last computation is kept for the next one
+// without computing. An input past its delta, or without one and changed
+// at all, triggers a computation with every current reading.
+//
+// `[recompute] max_interval_secs` forces a computation when that long has
+// passed since the last one, so a drift that stays under the deltas is
+// still picked up eventually.
+//
+// With no `min_delta` anywhere every reading is computed, as before.
+
+use crate::FuzzyVariable;
+
+/// `[recompute]` settings; zero disables the forced recompute
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RecomputeConfig {
+    pub max_interval_secs: f64,
+}
+
+/// Decides which readings are worth a computation and counts both kinds
+#[derive(Debug, Clone, Default)]
+pub struct Trigger {
+    /// Readings and clock time of the last computation
+    last: Option<(Vec<f64>, f64)>,
+    pub executed: u64,
+    pub suppressed: u64,
+}
+
+impl Trigger {
+    /// Whether `values` (declaration order) moved far enough from the last
+    /// computation to need a new one
+    pub fn should_compute(&self, inputs: &[FuzzyVariable], values: &[f64]) -> bool {
+        let Some((last, _)) = &self.last else {
+            return true;
+        };
+        if inputs.iter().all(|var| var.min_delta == 0.0) {
+            return true;
+        }
+        inputs
+            .iter()
+            .zip(values.iter().zip(last))
+            .any(|(var, (value, last))| {
+                if value.is_nan() || last.is_nan() {
+                    // A sensor failing or coming back always counts
+                    value.is_nan() != last.is_nan()
+                } else {
+                    (value - last).abs() > var.min_delta
+                }
+            })
+    }
+
+    /// Whether the forced recompute is due at clock time `now`
+    pub fn is_due(&self, config: &RecomputeConfig, now: f64) -> bool {
+        match &self.last {
+            Some((_, time)) => {
+                config.max_interval_secs > 0.0 && now - time >= config.max_interval_secs
+            }
+            None => false,
+        }
+    }
+
+    /// Note a computation with `values` at clock time `now`
+    pub fn computed(&mut self, values: &[f64], now: f64) {
+        self.last = Some((values.to_vec(), now));
+        self.executed += 1;
+    }
+
+    pub fn suppress(&mut self) {
+        self.suppressed += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(min_delta: f64) -> FuzzyVariable {
+        let mut var = FuzzyVariable::new("temperature", 0.0, 50.0);
+        var.min_delta = min_delta;
+        var
+    }
+
+    /// Feed `readings` one per second, as the daemon's `set` does
+    fn stream(
+        trigger: &mut Trigger,
+        config: &RecomputeConfig,
+        var: &FuzzyVariable,
+        readings: &[f64],
+    ) {
+        for (i, reading) in readings.iter().enumerate() {
+            let now = i as f64;
+            let values = [*reading];
+            if trigger.should_compute(std::slice::from_ref(var), &values)
+                || trigger.is_due(config, now)
+            {
+                trigger.computed(&values, now);
+            } else {
+                trigger.suppress();
+            }
+        }
+    }
+
+    #[test]
+    fn noise_under_the_delta_is_suppressed() {
+        // Jitter of +-0.1 around 20, then one real step to 25
+        let mut readings: Vec<f64> = (0..20)
+            .map(|i| 20.0 + if i % 2 == 0 { 0.1 } else { -0.1 })
+            .collect();
+        readings.push(25.0);
+        let mut trigger = Trigger::default();
+        stream(
+            &mut trigger,
+            &RecomputeConfig::default(),
+            &input(0.5),
+            &readings,
+        );
+        assert_eq!((trigger.executed, trigger.suppressed), (2, 19));
+    }
+
+    #[test]
+    fn a_zero_delta_computes_every_reading() {
+        let readings = [20.0, 20.0, 20.01, 20.0];
+        let mut trigger = Trigger::default();
+        stream(
+            &mut trigger,
+            &RecomputeConfig::default(),
+            &input(0.0),
+            &readings,
+        );
+        assert_eq!((trigger.executed, trigger.suppressed), (4, 0));
+    }
+
+    #[test]
+    fn a_slow_drift_is_picked_up_by_the_max_interval() {
+        // 0.1 per second never passes a delta of 1 between neighbours
+        let readings: Vec<f64> = (0..25).map(|i| 20.0 + i as f64 * 0.01).collect();
+        let config = RecomputeConfig {
+            max_interval_secs: 10.0,
+        };
+        let mut trigger = Trigger::default();
+        stream(&mut trigger, &config, &input(1.0), &readings);
+        // The first reading, then at 10 s and 20 s
+        assert_eq!((trigger.executed, trigger.suppressed), (3, 22));
+        assert!(!trigger.is_due(&RecomputeConfig::default(), 1e9));
+    }
+
+    #[test]
+    fn a_failing_sensor_always_counts() {
+        let mut trigger = Trigger::default();
+        let var = input(5.0);
+        trigger.computed(&[20.0], 0.0);
+        assert!(trigger.should_compute(std::slice::from_ref(&var), &[f64::NAN]));
+        trigger.computed(&[f64::NAN], 1.0);
+        assert!(!trigger.should_compute(std::slice::from_ref(&var), &[f64::NAN]));
+        assert!(trigger.should_compute(std::slice::from_ref(&var), &[20.0]));
+    }
+}
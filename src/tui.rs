@@ -0,0 +1,8451 @@
+// ============================================================================
+// TUI - Interface de terminal
+// ============================================================================
+//
+// The interactive interface: application state, event handling and the
+// drawing code, plus the ways into it (the local session, a replayed bug
+// report bundle and a TUI attached to a daemon). Built with the `tui`
+// feature, the only part of the program that needs crossterm and ratatui.
+
+use crate::activation::Activations;
+use crate::adapt::{self, Adaptation};
+use crate::adapters::InputKind;
+use crate::alarm::{Alarms, Sample};
+use crate::annotations::{self, Annotation, Annotations};
+use crate::autorange::{self, RangeMode, Transition};
+use crate::backup::{self, Backup};
+use crate::bookmarks::Bookmarks;
+use crate::budget::Budget;
+use crate::cascade::Cascade;
+use crate::chain::Chain;
+use crate::comfort::Occupancy;
+use crate::complexity;
+use crate::exec::{Exec, Publication};
+use crate::forecast::{self, Forecast};
+use crate::fusion::Reported;
+use crate::generator::GeneratorKind;
+use crate::inbox::{Inbox, Severity};
+use crate::inject::Injector;
+use crate::interlock::Interlocks;
+use crate::layout::{self, Pane, PanelLayout};
+#[cfg(feature = "logging")]
+use crate::logging;
+use crate::noise::Noise;
+use crate::nudge::{self, Accelerator};
+use crate::numbers::{self, Locale, NumberError};
+use crate::objective::{Mark, Objective};
+use crate::oscillation::Detector;
+use crate::overrides::{Held, Overrides};
+use crate::palette::{Ansi, Depth, Palette, PaletteName, Tone};
+use crate::pipeline::{Clock, FanBand, OutputPipeline, PipelineOutput, StatusMode, SystemClock};
+use crate::quantize;
+use crate::refine::Refiner;
+#[cfg(all(unix, feature = "daemon"))]
+use crate::remote;
+use crate::rulecolor;
+use crate::shadow::Shadow;
+use crate::shutdown::{self, Coordinator, Stage, Token};
+use crate::spotlight;
+use crate::state::RuleUsageStats;
+use crate::surface::Surface;
+use crate::termguard::{self, Crossterm, Guard};
+use crate::trigger::Trigger;
+use crate::tutorial::{self, Panel};
+use crate::units::{self, Unit};
+use crate::viewport::Viewport;
+use crate::window::Sampled;
+use crate::{
+    Aggregation, CapHit, FuzzyController, FuzzyRule, FuzzySet, FuzzySystem, FuzzyVariable,
+    Overflow, aggregated, apply_rules, defuzzify, dominant_set,
+};
+use crate::{
+    adapters, bookmarks, bundle, config, diff, gauge, generator, inbox, journal, lod, matrix,
+    noise, rulecsv, safemode, scenario, surface, timestamp, viewport,
+};
+use crossterm::{
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    },
+    terminal as crossterm_terminal,
+};
+use ratatui::{
+    Terminal,
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    symbols,
+    text::{Line, Span},
+    widgets::{
+        Axis, Bar, BarChart, BarGroup, Block, Borders, Cell, Chart, Clear, Dataset, GraphType,
+        List, ListItem, Paragraph, Row, Sparkline, Table, Wrap,
+    },
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::sync::mpsc;
+
+// ============================================================================
+// APPLICATION STATE
+// ============================================================================
+
+/// Number of computations kept in the history, unless `--history` says
+const HISTORY_LIMIT: usize = 100;
+/// Number of computations the history panel lists
+const HISTORY_ROWS: usize = 5;
+/// Grid sizes the surface view refines through, each capped at the
+/// panel's size
+const SURFACE_LADDER: [usize; 3] = [20, 50, 200];
+/// Grid a step table is fitted on (quantize.rs)
+const STEP_TABLE_GRID: usize = 41;
+/// Number of recent values shown by the input sparklines
+const SPARKLINE_WINDOW: usize = 60;
+/// Rows of the status bar at the bottom, borders included
+const STATUS_HEIGHT: u16 = 3;
+/// Rows of the objective panel: its bar and two records, boxed
+const OBJECTIVE_HEIGHT: u16 = 5;
+/// Rows of the shadow chart under the fan gauge and the score, borders
+/// included
+const SHADOW_HEIGHT: u16 = 6;
+/// Rows of the forecast chart under the fan gauge, borders included
+const FORECAST_HEIGHT: u16 = 6;
+/// What the status bar says when there is nothing else to say
+const IDLE_HINT: &str = "Press 'r' for random, 't' to set temperature, 'h' for humidity, 'u' for rule usage, 'M' for messages, 'T' for the tutorial, 'q' to quit";
+
+/// One computation, stamped with the app clock
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    /// Stays with the entry while older ones drop off, for the what-if
+    /// cache
+    id: u64,
+    time: f64,
+    temperature: f64,
+    humidity: f64,
+    fan_speed: f64,
+    /// Status label and color at the time, in the active status mode
+    status: (String, Color),
+    /// A failure injection was active
+    injected: bool,
+    /// Interlock forcing the output at the time
+    interlock: Option<String>,
+    /// Generator that drew the inputs, when they were random
+    generator: Option<GeneratorKind>,
+    /// Temperature and humidity the simulated sensors reported, while
+    /// sensor noise is on
+    sensed: Option<(f64, f64)>,
+    /// Readings that were outside their universe
+    overflows: Vec<Overflow>,
+    /// The published output, and what a shadow config would have
+    /// published (shadow.rs)
+    output: f64,
+    shadow: Option<f64>,
+    /// Rule firing the strongest (rulecolor.rs), if any fired
+    dominant: Option<usize>,
+    /// Raw readings in the controller's declaration order, as
+    /// `App::input_values` gave them, for the what-if column
+    raw: Vec<f64>,
+}
+
+impl HistoryEntry {
+    /// Temperature and humidity, the series with levels of detail
+    fn inputs(&self) -> [f64; 2] {
+        [self.temperature, self.humidity]
+    }
+}
+
+/// Temperature, humidity and output as shown at the last two
+/// computations, for the change next to each gauge
+#[derive(Debug, Clone, Copy, Default)]
+struct Trend {
+    previous: Option<[f64; 3]>,
+    latest: Option<[f64; 3]>,
+}
+
+impl Trend {
+    fn record(&mut self, values: [f64; 3]) {
+        self.previous = self.latest.replace(values);
+    }
+
+    /// The newest computation was taken back; the one before it is
+    /// compared against next
+    fn undo(&mut self) {
+        self.latest = self.previous.take();
+    }
+
+    /// Change of value `index` since the computation before the last
+    fn delta(&self, index: usize) -> Option<f64> {
+        Some(self.latest?[index] - self.previous?[index])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InputMode {
+    Menu,
+    Temperature,
+    Humidity,
+    Inject,
+    Noise,
+    /// Naming an interlock to engage or release
+    Interlock,
+    /// Naming a bookmark of the current inputs
+    Bookmark,
+    /// Browsing the bookmark popup
+    Bookmarks,
+    /// Renaming the bookmark selected in the popup
+    RenameBookmark,
+    /// Writing the note of the state frozen in `App::frozen`
+    Annotate,
+    /// Browsing the annotation popup
+    Annotations,
+    /// Choosing a backup of the config to restore
+    Backups,
+    /// Asking whether to replay a journal left by a crashed session
+    Recover,
+    /// Reviewing the edits made since the system was loaded
+    Diff,
+    /// Scrolling back through the status messages
+    Messages,
+    /// Reading the rule base's complexity report
+    Complexity,
+    /// Reading the processing chain, stage by stage
+    Chain,
+    /// Watching the sources of an attached daemon's fused inputs
+    Sources,
+    /// Previewing the palettes
+    Palettes,
+    /// Sizing a step table to draw over the surface
+    Quantize,
+    /// Walking through the tutorial's steps
+    Tutorial,
+}
+
+impl InputMode {
+    /// Whether the mode reads a line of text
+    fn is_prompt(self) -> bool {
+        !matches!(
+            self,
+            InputMode::Menu
+                | InputMode::Bookmarks
+                | InputMode::Annotations
+                | InputMode::Backups
+                | InputMode::Recover
+                | InputMode::Diff
+                | InputMode::Messages
+                | InputMode::Complexity
+                | InputMode::Chain
+                | InputMode::Sources
+                | InputMode::Palettes
+                | InputMode::Tutorial
+        )
+    }
+}
+
+/// What the diff popup shows: how the running system differs from the one
+/// loaded, and how far that moves the primary output
+struct Review {
+    changes: Vec<String>,
+    /// Max and mean |Δ output| over a coarse grid, once the worker is done
+    deviation: Option<Result<(f64, f64), String>>,
+    /// Worker sampling both systems for `deviation`
+    worker: Option<mpsc::Receiver<Result<(f64, f64), String>>>,
+}
+
+/// The open tutorial: its step, and the right panel's views from before
+/// it took the panel over (show_rules, show_matrix, show_surface,
+/// show_scatter, show_curves)
+struct Tutorial {
+    step: usize,
+    views: [bool; 5],
+}
+
+impl Review {
+    /// Compare `running` with `baseline`, sampling them on a worker thread
+    fn start(baseline: &FuzzySystem, running: &FuzzySystem) -> Self {
+        let mut changes = diff::structural_diff(baseline, running);
+        if changes.is_empty() {
+            changes.push("(only the declaration order differs)".to_string());
+        }
+        let (sender, receiver) = mpsc::channel();
+        let (old, new) = (baseline.clone(), running.clone());
+        std::thread::spawn(move || {
+            // The popup may be closed before the worker is done
+            let _ = sender.send(diff::deviation(&old, &new));
+        });
+        Review {
+            changes,
+            deviation: None,
+            worker: Some(receiver),
+        }
+    }
+
+    /// Take the worker's result if it is ready
+    fn poll(&mut self) {
+        let Some(worker) = &self.worker else {
+            return;
+        };
+        let result = match worker.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => Err("the comparison failed".to_string()),
+        };
+        self.deviation = Some(result);
+        self.worker = None;
+    }
+}
+
+/// The history's what-if column: what the running system makes of each
+/// entry's raw readings. Filled on a worker thread for the entries the
+/// history panel lists, and started over when the system changes
+struct WhatIf {
+    /// The system the values are for
+    system: FuzzySystem,
+    /// Primary output by entry id
+    values: HashMap<u64, f64>,
+    /// Worker computing the listed entries still missing
+    worker: Option<mpsc::Receiver<Vec<(u64, f64)>>>,
+}
+
+impl WhatIf {
+    fn new(system: &FuzzySystem) -> Self {
+        WhatIf {
+            system: system.clone(),
+            values: HashMap::new(),
+            worker: None,
+        }
+    }
+
+    /// Forget every value when `system` is not the one they are for; a
+    /// running worker's result is dropped with it
+    fn follow(&mut self, system: &FuzzySystem) {
+        if *system != self.system {
+            *self = WhatIf::new(system);
+        }
+    }
+
+    /// Take the worker's values if they are ready, then start one for the
+    /// `entries` still missing
+    fn poll(&mut self, entries: &[HistoryEntry]) {
+        if let Some(worker) = &self.worker {
+            match worker.try_recv() {
+                Ok(values) => self.values.extend(values),
+                Err(mpsc::TryRecvError::Empty) => return,
+                // Not asked for again: shown as unknown
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    for entry in entries {
+                        self.values.entry(entry.id).or_insert(f64::NAN);
+                    }
+                }
+            }
+            self.worker = None;
+        }
+        let missing: Vec<(u64, Vec<f64>)> = entries
+            .iter()
+            .filter(|entry| !self.values.contains_key(&entry.id))
+            .map(|entry| (entry.id, entry.raw.clone()))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let controller = FuzzyController::from_system(self.system.clone());
+        std::thread::spawn(move || {
+            let values = missing
+                .into_iter()
+                .map(|(id, raw)| (id, what_if(&controller, &raw)))
+                .collect();
+            // The column may be hidden or the system changed meanwhile
+            let _ = sender.send(values);
+        });
+        self.worker = Some(receiver);
+    }
+
+    fn get(&self, id: u64) -> Option<f64> {
+        self.values.get(&id).copied()
+    }
+}
+
+/// The forecast panel's predicted path (forecast.rs), made on a worker
+/// thread when a new entry moved an input past its `min_delta` since the
+/// last one, and started over when the system changes
+struct Outlook {
+    /// The system the forecast is for
+    system: FuzzySystem,
+    /// Readings the last forecast started from
+    trigger: Trigger,
+    /// Id of the newest entry looked at
+    seen: Option<u64>,
+    /// The latest forecast, or why there is none
+    forecast: Option<Result<Forecast, String>>,
+    worker: Option<mpsc::Receiver<Result<Forecast, String>>>,
+}
+
+impl Outlook {
+    fn new(system: &FuzzySystem) -> Self {
+        Outlook {
+            system: system.clone(),
+            trigger: Trigger::default(),
+            seen: None,
+            forecast: None,
+            worker: None,
+        }
+    }
+
+    /// Start over when `system` is not the one the forecast is for
+    fn follow(&mut self, system: &FuzzySystem) {
+        if *system != self.system {
+            *self = Outlook::new(system);
+        }
+    }
+
+    /// Take the worker's forecast if it is ready, then start one if the
+    /// newest of `entries` asks for it
+    fn poll(&mut self, entries: &[HistoryEntry], now: f64) {
+        if let Some(worker) = &self.worker {
+            self.forecast = match worker.try_recv() {
+                Ok(forecast) => Some(forecast),
+                Err(mpsc::TryRecvError::Empty) => return,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    Some(Err("the forecast failed".to_string()))
+                }
+            };
+            self.worker = None;
+        }
+        let Some(latest) = entries.last() else {
+            return;
+        };
+        let inputs = &self.system.inputs;
+        if self.seen == Some(latest.id)
+            || latest.raw.len() != inputs.len()
+            || !self.trigger.should_compute(inputs, &latest.raw)
+        {
+            self.seen = Some(latest.id);
+            return;
+        }
+        self.seen = Some(latest.id);
+        self.trigger.computed(&latest.raw, now);
+        let config = self.system.forecast.clone();
+        let since = now - config.window_secs;
+        let series: Vec<Vec<(f64, f64)>> = (0..inputs.len())
+            .map(|i| {
+                (entries.iter())
+                    .filter(|entry| entry.time >= since && entry.raw.len() > i)
+                    .map(|entry| (entry.time, entry.raw[i]))
+                    .collect()
+            })
+            .collect();
+        let (sender, receiver) = mpsc::channel();
+        let controller = FuzzyController::from_system(self.system.clone());
+        std::thread::spawn(move || {
+            // The panel may be hidden or the system changed meanwhile
+            let _ = sender.send(forecast::predict(&controller, &series, now, &config));
+        });
+        self.worker = Some(receiver);
+    }
+}
+
+/// The primary output `controller` gives for raw readings in its
+/// declaration order, without the sensor noise or injections the entry
+/// may have had; NaN when the inputs no longer match
+fn what_if(controller: &FuzzyController, raw: &[f64]) -> f64 {
+    let inputs = &controller.system.inputs;
+    if raw.len() != inputs.len() {
+        return f64::NAN;
+    }
+    let (values, _) = adapters::convert(inputs, raw);
+    controller.evaluate(&values).outputs[0]
+}
+
+pub struct App {
+    controller: FuzzyController,
+    temperature: f64,
+    /// Relative humidity, converted from `dew_point` when that is set
+    humidity: f64,
+    /// Raw humidity reading when the humidity input takes a dew point
+    dew_point: Option<f64>,
+    fan_speed: f64,
+    /// Crisp values of the outputs after the first, in declaration order
+    other_outputs: Vec<f64>,
+    injector: Injector,
+    /// Simulated sensor errors, ahead of the injections
+    noise: Noise,
+    /// Temperature and humidity as sensed in the last computation, while
+    /// sensor noise is on
+    sensed: Option<(f64, f64)>,
+    /// Readings outside their universe in the last computation
+    overflows: Vec<Overflow>,
+    /// Output sets the cap profile held down in the last computation
+    cap_hits: Vec<CapHit>,
+    /// Computations whose output fell outside the activated sets
+    support_warnings: usize,
+    pipeline: OutputPipeline,
+    /// Watches the output for a limit cycle, with an [oscillation]
+    oscillation: Option<Detector>,
+    /// The config's [[alarm]] conditions, local only
+    alarms: Alarms,
+    /// The running score, with an [objective]; local only
+    objective: Option<Objective>,
+    /// A config evaluated next to this one, with `--shadow`; local only
+    shadow: Option<Shadow>,
+    /// The program run with the output, with an [output.exec]; local
+    /// only, an attached daemon runs its own
+    exec: Option<Exec>,
+    /// Set while the `exec` alarm is raised
+    exec_failing: bool,
+    /// Set while the `exec_stalled` alarm is raised
+    exec_stalled: bool,
+    /// The rung computations run at, with a [budget]; local only
+    budget: Option<Budget>,
+    /// PID after the fuzzy output, against a simulated fan when local
+    cascade: Option<Cascade>,
+    /// The config's [[interlock]] switches; an attached daemon applies
+    /// its own, switched along with these
+    interlocks: Interlocks,
+    clock: Box<dyn Clock>,
+    output: PipelineOutput,
+    input_mode: InputMode,
+    input_buffer: String,
+    /// Question or hint of the current mode, shown with what has been typed
+    prompt: String,
+    /// The system as loaded, before any edit: what the diff popup compares
+    /// against and what 'revert all' restores
+    baseline: FuzzySystem,
+    /// Open diff popup
+    review: Option<Review>,
+    tutorial: Option<Tutorial>,
+    /// Set by `AppAction::Quit`; the run loop ends after the frame
+    quit: bool,
+    /// Set by Ctrl-Z; the run loop stops the process after the frame
+    /// (termguard.rs)
+    suspend_requested: bool,
+    /// The simulation stands still while the terminal is out of focus,
+    /// with `[display] pause_on_blur`
+    paused: bool,
+    /// Status messages, the most pressing one shown in the status bar
+    inbox: Inbox,
+    /// Newest-first row the message popup starts at
+    messages_scroll: usize,
+    /// Lines of the complexity popup, worked out when it opens
+    complexity: Vec<String>,
+    /// Lines of the processing chain popup, worked out when it opens
+    chain: Vec<String>,
+    /// First line of the chain popup shown
+    chain_scroll: usize,
+    history: Vec<HistoryEntry>,
+    /// Computations recorded so far, the id of the newest entry
+    entries_recorded: u64,
+    /// The history's what-if column, while it is shown
+    what_if: Option<WhatIf>,
+    /// The forecast panel, while it is shown
+    outlook: Option<Outlook>,
+    /// Computations kept in the history
+    history_limit: usize,
+    /// Levels of detail of the temperature and humidity in the history
+    history_series: [lod::Series; 2],
+    /// Change of each gauge's value since the previous computation
+    trend: Trend,
+    /// What the value axes of the temperature and humidity charts span
+    range_modes: [RangeMode; 2],
+    /// Value axes of those charts, moving toward their ranges
+    axes: [Transition; 2],
+    /// Firing strength of every rule in the last computation
+    rule_strengths: Vec<f64>,
+    rule_stats: RuleUsageStats,
+    /// Where `rule_stats`, `bookmarks` and `layout` are persisted, if
+    /// anywhere
+    state_path: Option<String>,
+    /// Feedback on the output and the rule weights it moved, kept next to
+    /// the state file (adapt.rs)
+    adaptation: Adaptation,
+    bookmarks: Bookmarks,
+    /// Which panels are shown and how big
+    layout: PanelLayout,
+    /// Highlighted row of the bookmark popup
+    selected_bookmark: usize,
+    /// Annotated states, kept next to the state file (annotations.rs)
+    annotations: Annotations,
+    /// The state frozen when the annotation prompt opened
+    frozen: Option<Annotation>,
+    /// Highlighted row of the annotation popup, newest first
+    selected_annotation: usize,
+    /// Right panel shows the rule table instead of the membership charts
+    show_rules: bool,
+    /// Membership charts draw the curves over the universe, not bars
+    show_curves: bool,
+    /// Right panel shows the control surface
+    show_surface: bool,
+    surface_style: SurfaceStyle,
+    /// Step table drawn over the surface view ('S')
+    step_table: Option<StepTable>,
+    /// Palette the colors below come from, and how many colors the
+    /// terminal shows (palette.rs)
+    palette: PaletteName,
+    depth: Depth,
+    /// Every color drawn with, by its role
+    theme: Palette<Color>,
+    /// Palette highlighted in the preview popup
+    palette_choice: usize,
+    /// The surface view's grid for the system and panel size it was drawn
+    /// at, refined in the background; filled while drawing
+    surface: RefCell<Refiner<SurfaceKey, Surface>>,
+    /// Right panel shows the rule matrix
+    show_matrix: bool,
+    /// Right panel plots the history over temperature and humidity ('i')
+    show_scatter: bool,
+    /// Time the readings spent inside the comfort zone (comfort.rs)
+    comfort_time: Occupancy,
+    /// Visible part of the temperature and humidity curves
+    curve_views: [Viewport; 2],
+    /// Visible part of the sparklines, in seconds before now; `None` shows
+    /// the latest SPARKLINE_WINDOW computations
+    history_view: Option<Viewport>,
+    /// What 'r' draws from
+    generator: GeneratorKind,
+    /// Generator of the inputs being computed, for the history
+    sampled: Option<GeneratorKind>,
+    /// Rule groups folded in the rule table
+    collapsed_groups: Vec<String>,
+    bar_scale: BarScale,
+    /// Values are shown in their storage units, not the display units
+    storage_units: bool,
+    /// Step of the arrow-key nudges, growing while a key is held
+    accelerator: Accelerator,
+    /// Time of the history entry of the current nudge burst
+    nudged_at: Option<f64>,
+    /// How numbers typed at the prompts are read
+    locale: Locale,
+    /// Rule highlighted in the rule table and previewed on the fan gauge
+    selected_rule: Option<usize>,
+    /// Where edits to the system are journaled, with a state file
+    journal: Option<journal::Journal>,
+    /// Entries of a crashed session's journal awaiting the recovery answer
+    recovery: Vec<Result<journal::Edit, String>>,
+    /// Where the system came from, recorded in bug report bundles
+    source: String,
+    /// The config file saves go to, when the system was loaded from one
+    config_path: Option<String>,
+    /// Backups a save keeps of the config it replaces (backup.rs)
+    keep_backups: usize,
+    /// The config's backups while their popup is open, and the
+    /// highlighted one
+    backups: Vec<Backup>,
+    selected_backup: usize,
+    /// Why the TUI started in safe mode (safemode.rs), if it did
+    safe_mode: Option<String>,
+    /// The whole safe-mode error is shown over the panels
+    show_safe_mode: bool,
+    /// Inputs of an attached daemon held against its readings
+    /// (overrides.rs)
+    held: Option<Held>,
+    /// Last sample window of each windowed input of an attached daemon
+    /// (window.rs)
+    windows: Vec<Sampled>,
+    /// Every source of the fused inputs of an attached daemon
+    /// (fusion.rs)
+    sources: Vec<Reported>,
+    /// How long before its publication an attached daemon's last output
+    /// was computed from readings taken (align.rs); its history entries
+    /// go at the time of the readings
+    lag: f64,
+    /// Set when attached to a daemon, which then owns the computation
+    #[cfg(all(unix, feature = "daemon"))]
+    remote: Option<remote::RemoteLink>,
+}
+
+impl App {
+    fn new(controller: FuzzyController) -> Self {
+        let pipeline = OutputPipeline::new(controller.system.pipeline.clone());
+        let cascade = controller.system.cascade.clone().map(Cascade::new);
+        let oscillation = controller.system.oscillation.clone().map(Detector::new);
+        let alarms = Alarms::new(&controller.system.alarms);
+        let objective = controller.system.objective.clone().map(Objective::new);
+        let palette = controller.system.palette;
+        let depth = Depth::from_count(crossterm::style::available_color_count());
+        let budget = controller.system.budget.clone().map(Budget::new);
+        let interlocks = Interlocks::new(&controller.system.interlocks);
+        let exec = controller.system.exec.clone().map(Exec::new);
+        let rule_stats = RuleUsageStats::new(controller.system.rule_texts());
+        let generator = controller.system.generator.kind;
+        let noise = Noise::new(&controller.system);
+        let dew_point = controller
+            .system
+            .input("humidity")
+            .filter(|var| var.kind != InputKind::Direct)
+            .map(|_| adapters::dew_point(25.0, 50.0));
+        let baseline = controller.system.clone();
+        let adaptation = Adaptation::new(&controller.system);
+        let mut app = App {
+            controller,
+            temperature: 25.0,
+            humidity: 50.0,
+            dew_point,
+            fan_speed: 0.0,
+            other_outputs: Vec::new(),
+            injector: Injector::default(),
+            noise,
+            sensed: None,
+            overflows: Vec::new(),
+            cap_hits: Vec::new(),
+            support_warnings: 0,
+            pipeline,
+            oscillation,
+            alarms,
+            objective,
+            shadow: None,
+            exec,
+            exec_failing: false,
+            exec_stalled: false,
+            budget,
+            cascade,
+            interlocks,
+            clock: Box::new(SystemClock::new()),
+            output: PipelineOutput {
+                value: 0.0,
+                band: FanBand::Off,
+                pending: None,
+            },
+            input_mode: InputMode::Menu,
+            input_buffer: String::new(),
+            prompt: String::new(),
+            baseline,
+            review: None,
+            tutorial: None,
+            quit: false,
+            suspend_requested: false,
+            paused: false,
+            inbox: Inbox::default(),
+            messages_scroll: 0,
+            complexity: Vec::new(),
+            chain: Vec::new(),
+            chain_scroll: 0,
+            history: Vec::new(),
+            entries_recorded: 0,
+            what_if: None,
+            outlook: None,
+            history_limit: HISTORY_LIMIT,
+            history_series: Default::default(),
+            trend: Trend::default(),
+            range_modes: Default::default(),
+            axes: [Transition::at((0.0, 1.0)); 2],
+            rule_strengths: Vec::new(),
+            rule_stats,
+            state_path: None,
+            adaptation,
+            bookmarks: Bookmarks::default(),
+            layout: PanelLayout::default(),
+            selected_bookmark: 0,
+            annotations: Annotations::default(),
+            frozen: None,
+            selected_annotation: 0,
+            show_rules: false,
+            show_curves: false,
+            show_surface: false,
+            show_matrix: false,
+            show_scatter: false,
+            comfort_time: Occupancy::default(),
+            surface_style: SurfaceStyle::detect(),
+            step_table: None,
+            palette,
+            depth,
+            theme: theme(palette, depth),
+            palette_choice: 0,
+            surface: RefCell::default(),
+            curve_views: [Viewport::full(0.0, 1.0); 2],
+            history_view: None,
+            generator,
+            sampled: None,
+            collapsed_groups: Vec::new(),
+            bar_scale: BarScale::Linear,
+            storage_units: false,
+            accelerator: Accelerator::default(),
+            nudged_at: None,
+            locale: Locale::Auto,
+            selected_rule: None,
+            journal: None,
+            recovery: Vec::new(),
+            source: "built-in demo".to_string(),
+            config_path: None,
+            keep_backups: backup::DEFAULT_KEEP,
+            backups: Vec::new(),
+            selected_backup: 0,
+            safe_mode: None,
+            show_safe_mode: false,
+            held: None,
+            windows: Vec::new(),
+            sources: Vec::new(),
+            lag: 0.0,
+            #[cfg(all(unix, feature = "daemon"))]
+            remote: None,
+        };
+        app.reset_view();
+        app.compute_fan_speed();
+        let axes = [0, 1].map(|i| Transition::at(app.axis_target(i)));
+        app.axes = axes;
+        app
+    }
+
+    /// Whether input `name` takes reading `value`; readings of an input
+    /// the controller does not have are not checked
+    fn admit(&self, name: &str, value: f64) -> Result<f64, String> {
+        match self.controller.system.input(name) {
+            Some(var) => var.admit(value),
+            None => Ok(value),
+        }
+    }
+
+    /// Unit values of variable `name` are shown in
+    fn unit(&self, name: &str) -> Unit {
+        let system = &self.controller.system;
+        let unit = system
+            .input(name)
+            .or_else(|| system.output_var(name))
+            .map(|var| var.unit.clone())
+            .unwrap_or_default();
+        if self.storage_units {
+            unit.stored()
+        } else {
+            unit
+        }
+    }
+
+    /// Post a status message at the app time
+    fn post(&mut self, severity: Severity, text: impl Into<String>) {
+        let now = self.clock.now();
+        self.inbox.post(severity, text, now);
+    }
+
+    fn info(&mut self, text: impl Into<String>) {
+        self.post(Severity::Info, text);
+    }
+
+    fn warn(&mut self, text: impl Into<String>) {
+        self.post(Severity::Warning, text);
+    }
+
+    fn error(&mut self, text: impl Into<String>) {
+        self.post(Severity::Error, text);
+    }
+
+    /// Post the records logged since the last frame (logging.rs)
+    #[cfg(feature = "logging")]
+    fn post_logged(&mut self) {
+        for (level, line) in logging::drain() {
+            let severity = match level {
+                log::Level::Error => Severity::Error,
+                log::Level::Warn => Severity::Warning,
+                _ => Severity::Info,
+            };
+            self.post(severity, line);
+        }
+    }
+
+    /// Take down the error the status bar shows
+    fn acknowledge(&mut self) {
+        let now = self.clock.now();
+        self.inbox.acknowledge(now);
+    }
+
+    fn open_messages(&mut self) {
+        if self.inbox.is_empty() {
+            self.info("No messages yet");
+            return;
+        }
+        self.messages_scroll = 0;
+        self.input_mode = InputMode::Messages;
+        self.prompt = "Messages, newest first: ↑/↓ scroll, Esc closes".to_string();
+    }
+
+    fn open_complexity(&mut self) {
+        let report = complexity::analyze(&self.controller.system, complexity::CROWD);
+        self.complexity = report.lines(complexity::LISTED);
+        self.input_mode = InputMode::Complexity;
+        self.prompt = "Rule base complexity: Esc closes".to_string();
+    }
+
+    fn open_chain(&mut self) {
+        self.chain = Chain::of(&self.controller.system).lines();
+        self.chain_scroll = 0;
+        self.input_mode = InputMode::Chain;
+        self.prompt = "Processing chain: ↑/↓ scroll, Esc closes".to_string();
+    }
+
+    fn scroll_chain(&mut self, step: isize) {
+        let last = self.chain.len().saturating_sub(1);
+        self.chain_scroll = self.chain_scroll.saturating_add_signed(step).min(last);
+    }
+
+    fn open_sources(&mut self) {
+        if self.sources.is_empty() {
+            self.info("No fused inputs: sources report to a daemon, see 'attach'");
+            return;
+        }
+        self.input_mode = InputMode::Sources;
+        self.prompt = "Sources of the fused inputs: Esc closes".to_string();
+    }
+
+    /// Take up the daemon's sources, warning of each one excluded since
+    /// the last state line and noting each one that joined again
+    #[cfg(any(test, all(unix, feature = "daemon")))]
+    fn mirror_sources(&mut self, sources: Vec<Reported>) {
+        for source in &sources {
+            let before = self
+                .sources
+                .iter()
+                .find(|s| s.route() == source.route())
+                .is_some_and(|s| s.status.is_excluded());
+            match (before, source.status.is_excluded()) {
+                (false, true) => self.warn(format!(
+                    "Source {} excluded: {}",
+                    source.route(),
+                    source.status.describe()
+                )),
+                (true, false) => self.info(format!("Source {} joined again", source.route())),
+                _ => {}
+            }
+        }
+        self.sources = sources;
+    }
+
+    fn open_palettes(&mut self) {
+        self.palette_choice = PaletteName::ALL
+            .iter()
+            .position(|name| *name == self.palette)
+            .unwrap_or(0);
+        self.input_mode = InputMode::Palettes;
+        self.prompt = "Palettes: ↑/↓ choose, Enter applies, Esc closes".to_string();
+    }
+
+    fn select_palette(&mut self, step: isize) {
+        let last = PaletteName::ALL.len() - 1;
+        self.palette_choice = self.palette_choice.saturating_add_signed(step).min(last);
+    }
+
+    /// Draw with the palette highlighted in the popup, for this session
+    fn apply_palette(&mut self) {
+        let name = PaletteName::ALL[self.palette_choice];
+        self.palette = name;
+        self.theme = theme(name, self.depth);
+        self.input_mode = InputMode::Menu;
+        self.info(format!(
+            "Palette {}: palette = \"{}\" under [display] keeps it",
+            name.name(),
+            name.name()
+        ));
+    }
+
+    fn open_tutorial(&mut self) {
+        self.tutorial = Some(Tutorial {
+            step: 0,
+            views: [
+                self.show_rules,
+                self.show_matrix,
+                self.show_surface,
+                self.show_scatter,
+                self.show_curves,
+            ],
+        });
+        self.input_mode = InputMode::Tutorial;
+        self.prompt =
+            "Tutorial: Space next, Backspace back, 'r' and the arrows change the inputs, Esc ends"
+                .to_string();
+        self.show_tutorial_step();
+    }
+
+    /// Move `step` steps through the tutorial; past the last one it ends
+    fn step_tutorial(&mut self, step: isize) {
+        let Some(tutorial) = &mut self.tutorial else {
+            return;
+        };
+        tutorial.step = tutorial.step.saturating_add_signed(step);
+        if tutorial.step >= tutorial::STEPS.len() {
+            self.close_tutorial();
+            self.info("Tutorial finished: 'T' starts it again");
+            return;
+        }
+        self.show_tutorial_step();
+    }
+
+    /// Give the right panel the view the current step talks about
+    fn show_tutorial_step(&mut self) {
+        let Some(tutorial) = &self.tutorial else {
+            return;
+        };
+        let panel = tutorial::STEPS[tutorial.step].panel;
+        self.show_matrix = false;
+        self.show_surface = false;
+        self.show_scatter = false;
+        self.show_rules = panel == Panel::Rules;
+        self.show_curves = matches!(panel, Panel::Aggregated | Panel::Centroid);
+    }
+
+    fn close_tutorial(&mut self) {
+        if let Some(tutorial) = self.tutorial.take() {
+            [
+                self.show_rules,
+                self.show_matrix,
+                self.show_surface,
+                self.show_scatter,
+                self.show_curves,
+            ] = tutorial.views;
+        }
+        self.input_mode = InputMode::Menu;
+    }
+
+    /// The live values the tutorial's texts refer to
+    fn tutorial_values(&self) -> Vec<(&'static str, String)> {
+        let system = &self.controller.system;
+        let memberships = |name: &str, value: f64| {
+            let sets = system
+                .input(name)
+                .map(|var| var.fuzzify(value))
+                .unwrap_or_default();
+            let sets: Vec<String> = sets
+                .iter()
+                .map(|set| format!("{} {:.2}", set.name, set.membership))
+                .collect();
+            sets.join(", ")
+        };
+        let fired = self.rule_strengths.iter().filter(|s| **s > 0.0).count();
+        let strongest = self.dominant_rule();
+        let output = &system.output().name;
+        vec![
+            ("temperature", self.show("temperature", self.temperature)),
+            ("humidity", self.show("humidity", self.humidity)),
+            (
+                "temperature_sets",
+                memberships("temperature", self.temperature),
+            ),
+            ("humidity_sets", memberships("humidity", self.humidity)),
+            ("fired", fired.to_string()),
+            (
+                "rule",
+                strongest.map_or("none".to_string(), |i| system.rules[i].to_text()),
+            ),
+            (
+                "strength",
+                format!("{:.2}", strongest.map_or(0.0, |i| self.rule_strengths[i])),
+            ),
+            (
+                "aggregation",
+                system.defuzzification.aggregation.name().to_string(),
+            ),
+            (
+                "method",
+                system
+                    .defuzzification
+                    .method_for(system.output())
+                    .name()
+                    .to_string(),
+            ),
+            ("output_name", output.clone()),
+            ("output", self.show(output, self.fan_speed)),
+        ]
+    }
+
+    fn scroll_messages(&mut self, step: isize) {
+        let last = self.inbox.len().saturating_sub(1);
+        self.messages_scroll = self.messages_scroll.saturating_add_signed(step).min(last);
+    }
+
+    fn toggle_safe_mode_error(&mut self) {
+        if self.safe_mode.is_none() {
+            self.info("Not in safe mode");
+            return;
+        }
+        self.show_safe_mode = !self.show_safe_mode;
+    }
+
+    /// The rule dominating the current computation (rulecolor.rs)
+    fn dominant_rule(&self) -> Option<usize> {
+        let tolerance = self.controller.system.defuzzification.tie_tolerance;
+        rulecolor::dominant(&self.rule_strengths, tolerance)
+    }
+
+    /// Color of the rule dominating the current computation, for the
+    /// operating point markers
+    fn regime_color(&self) -> Color {
+        rulecolor::of_dominant(&self.theme, self.dominant_rule())
+    }
+
+    /// `value` of variable `name` with its unit
+    fn show(&self, name: &str, value: f64) -> String {
+        self.unit(name).format(value)
+    }
+
+    /// Arrow and size of the change of variable `name` (temperature,
+    /// humidity or the first output) since the previous computation, none
+    /// on the first one or when it rounds away at the variable's precision
+    fn trend(&self, name: &str) -> Option<Span<'static>> {
+        let index = match name {
+            "temperature" => 0,
+            "humidity" => 1,
+            _ => 2,
+        };
+        let unit = self.unit(name);
+        // A difference: the display scale applies, the offset cancels out
+        let delta = unit.to_display(self.trend.delta(index)?) - unit.to_display(0.0);
+        let precision = unit.precision.unwrap_or(units::DEFAULT_PRECISION);
+        let steps = (delta.abs() * 10f64.powi(precision as i32)).round();
+        if steps == 0.0 || !steps.is_finite() {
+            return None;
+        }
+        let (arrow, color) = if delta > 0.0 {
+            ("▲", self.theme.rising)
+        } else {
+            ("▼", self.theme.falling)
+        };
+        Some(Span::styled(
+            format!(" {}{:.*}", arrow, precision, delta.abs()),
+            Style::default().fg(color),
+        ))
+    }
+
+    /// Gauge label: `value` of variable `name`, its trend, then `rest`
+    fn gauge_label(&self, name: &str, value: f64, rest: String) -> Line<'static> {
+        let mut spans = vec![Span::raw(self.show(name, value))];
+        spans.extend(self.trend(name));
+        spans.push(Span::raw(rest));
+        Line::from(spans)
+    }
+
+    /// Move `input` (temperature or humidity) one press of an arrow key,
+    /// within its universe
+    fn nudge(&mut self, input: &str, direction: nudge::Direction) {
+        let now = self.clock.now();
+        let nudge = self.accelerator.press(input, direction, now);
+        let dew_point = self.dew_point.filter(|_| input == "humidity");
+        let current = match input {
+            "temperature" => self.temperature,
+            _ => dew_point.unwrap_or(self.humidity),
+        };
+        let mut value = current + nudge.delta;
+        // A dew point is converted, and clamped, on the way in
+        if dew_point.is_none()
+            && let Some(var) = self.controller.system.input(input)
+        {
+            value = value.clamp(var.min, var.max);
+        }
+        match input {
+            "temperature" => self.temperature = value,
+            _ if dew_point.is_some() => self.dew_point = Some(value),
+            _ => self.humidity = value,
+        }
+        // The whole burst is one change: its previous entry is replaced
+        if nudge.continues
+            && self.nudged_at.is_some()
+            && self.history.last().map(|e| e.time) == self.nudged_at
+        {
+            self.pop_history();
+        }
+        let shown = if dew_point.is_some() {
+            "temperature"
+        } else {
+            input
+        };
+        self.info(format!(
+            "{} {} {} (step {})",
+            if dew_point.is_some() {
+                "dew point"
+            } else {
+                input
+            },
+            if nudge.delta > 0.0 { "▲" } else { "▼" },
+            self.show(shown, value),
+            self.unit(shown).format_difference(nudge.delta.abs())
+        ));
+        self.compute_fan_speed();
+        self.nudged_at = self.history.last().map(|e| e.time);
+    }
+
+    fn toggle_units(&mut self) {
+        self.storage_units = !self.storage_units;
+        self.info(if self.storage_units {
+            "Showing storage units"
+        } else {
+            "Showing display units"
+        });
+    }
+
+    /// Current raw readings in the controller's declaration order
+    fn input_values(&self) -> Vec<f64> {
+        self.controller
+            .system
+            .inputs
+            .iter()
+            .map(|var| match (var.name.as_str(), &self.cascade) {
+                (name, Some(cascade)) if name == cascade.config().measured => cascade.measured,
+                ("temperature", _) => self.temperature,
+                _ => self.dew_point.unwrap_or(self.humidity),
+            })
+            .collect()
+    }
+
+    /// Take raw readings in the controller's declaration order, as
+    /// `input_values` gives them; the cascade's measured input keeps
+    /// following the simulated plant
+    fn set_input_values(&mut self, raw: &[f64]) {
+        let names: Vec<String> = self
+            .controller
+            .system
+            .inputs
+            .iter()
+            .map(|var| var.name.clone())
+            .collect();
+        for (name, value) in names.iter().zip(raw) {
+            match (name.as_str(), &self.cascade) {
+                (name, Some(cascade)) if name == cascade.config().measured => {}
+                ("temperature", _) => self.temperature = *value,
+                _ => match &mut self.dew_point {
+                    Some(dew_point) => *dew_point = *value,
+                    None => self.humidity = *value,
+                },
+            }
+        }
+    }
+
+    /// Follow a humidity that was set directly with the dew point reading
+    fn sync_dew_point(&mut self) {
+        if let Some(dew_point) = &mut self.dew_point {
+            *dew_point = adapters::dew_point(self.temperature, self.humidity);
+        }
+    }
+
+    fn compute_fan_speed(&mut self) {
+        #[cfg(all(unix, feature = "daemon"))]
+        if let Some(link) = &mut self.remote {
+            let humidity = self.dew_point.unwrap_or(self.humidity);
+            // Held against the daemon's sensors, unless holds are off
+            let hold = self.controller.system.overrides.duration_secs > 0.0;
+            if let Err(e) = link.send_inputs(self.temperature, humidity, hold) {
+                self.error(format!("Lost connection to the daemon: {}", e));
+            }
+            return;
+        }
+
+        self.evaluate_rules();
+        self.tick();
+        self.push_history();
+    }
+
+    /// Recompute the output and rule strengths from the current inputs
+    fn evaluate_rules(&mut self) {
+        let system = &self.controller.system;
+        let (values, warnings) = adapters::convert(&system.inputs, &self.input_values());
+        if let Some(i) = system.inputs.iter().position(|v| v.name == "humidity") {
+            self.humidity = values[i];
+        }
+        self.sensed = None;
+        let mut values = values;
+        if self.noise.is_active() {
+            values = self.noise.apply(&system.inputs, &values, self.clock.now());
+            let sensed = |name: &str| {
+                let index = system.inputs.iter().position(|v| v.name == name);
+                index.map_or(f64::NAN, |i| values[i])
+            };
+            self.sensed = Some((sensed("temperature"), sensed("humidity")));
+        }
+        let values = self
+            .injector
+            .apply(&system.inputs, &values, self.clock.now());
+        if !warnings.is_empty() {
+            self.warn(warnings.join("; "));
+        }
+        // The same preprocessed inputs, noise and injections included
+        if let Some(shadow) = &mut self.shadow {
+            shadow.infer(&values);
+        }
+        let mut evaluation = match &mut self.budget {
+            Some(budget) => self
+                .controller
+                .evaluate_within(&values, budget, &*self.clock),
+            None => self.controller.evaluate(&values),
+        };
+        if !evaluation.diagnostics.is_empty() {
+            self.support_warnings += 1;
+            self.warn(format!(
+                "⚠ {} ({} so far)",
+                evaluation.diagnostics.join("; "),
+                self.support_warnings
+            ));
+        }
+        if !evaluation.overflows.is_empty() {
+            let described: Vec<String> = evaluation
+                .overflows
+                .iter()
+                .map(Overflow::describe)
+                .collect();
+            self.warn(format!("⚠ {}", described.join("; ")));
+        }
+        if let Some(envelope) = &evaluation.envelope {
+            self.warn(format!("🛡 {}", envelope.describe()));
+        }
+        let sample = Sample {
+            system: &self.controller.system,
+            inputs: &values,
+            outputs: &evaluation.outputs,
+            strengths: &evaluation.strengths,
+        };
+        if let Some(objective) = &mut self.objective {
+            objective.update(&sample, self.clock.now(), timestamp::now());
+        }
+        for change in self.alarms.update(&sample, self.clock.now()) {
+            if change.raised {
+                let condition = self.alarms.condition(&change.name).unwrap_or_default();
+                self.warn(format!("Alarm '{}': {}", change.name, condition));
+            } else {
+                self.info(format!("Alarm '{}' cleared", change.name));
+            }
+        }
+        self.overflows = std::mem::take(&mut evaluation.overflows);
+        self.cap_hits = std::mem::take(&mut evaluation.cap_hits);
+        self.fan_speed = evaluation.outputs[0];
+        self.other_outputs = evaluation.outputs.split_off(1);
+        self.rule_stats
+            .record(&evaluation.strengths, timestamp::now());
+        self.rule_strengths = evaluation.strengths;
+    }
+
+    /// Run `shadow` next to the controller from the current computation on
+    fn start_shadow(&mut self, mut shadow: Shadow) {
+        let (values, _) = adapters::convert(&self.controller.system.inputs, &self.input_values());
+        shadow.infer(&values);
+        shadow.tick(self.output, self.clock.now());
+        if let Some(entry) = self.history.last_mut() {
+            entry.shadow = Some(shadow.output.value);
+        }
+        self.info(format!("Shadow {} runs next to this config", shadow.path));
+        self.shadow = Some(shadow);
+    }
+
+    /// Give the held inputs back to the daemon's readings
+    fn release(&mut self) {
+        #[cfg(all(unix, feature = "daemon"))]
+        if let Some(link) = &mut self.remote
+            && let Err(e) = link.release()
+        {
+            self.error(format!("Lost connection to the daemon: {}", e));
+            return;
+        }
+        self.held = None;
+        self.info("Inputs released to the daemon's readings");
+    }
+
+    /// Nudge the weights of the firing rules toward an output further
+    /// `direction`, and keep them with the feedback
+    fn give_feedback(&mut self, direction: adapt::Direction) {
+        #[cfg(all(unix, feature = "daemon"))]
+        if self.remote.is_some() {
+            self.info("Attached: the daemon's weights are set with `weights`");
+            return;
+        }
+        let (values, _) = adapters::convert(&self.controller.system.inputs, &self.input_values());
+        let system = &mut self.controller.system;
+        let changed = match self
+            .adaptation
+            .give(system, &values, direction, timestamp::now())
+        {
+            Ok(feedback) => feedback.changes.len(),
+            Err(e) => {
+                self.warn(format!("Feedback not taken: {}", e));
+                return;
+            }
+        };
+        let done = format!(
+            "Output should have been {}: {} rule weight{} nudged",
+            direction.name(),
+            changed,
+            if changed == 1 { "" } else { "s" }
+        );
+        self.adaptation_changed(done);
+        self.compute_fan_speed();
+    }
+
+    /// Put the configured rule weights back, forgetting the feedback
+    fn revert_weights(&mut self) {
+        let reverted = self.adaptation.revert(&mut self.controller.system);
+        self.adaptation_changed(format!(
+            "Configured weights back on {} rule{}",
+            reverted,
+            if reverted == 1 { "" } else { "s" }
+        ));
+        self.compute_fan_speed();
+    }
+
+    /// `done` after the adapted weights changed, which are saved right away
+    fn adaptation_changed(&mut self, done: String) {
+        let Some(path) = &self.state_path else {
+            self.info(done);
+            return;
+        };
+        let path = adapt::path_for(path);
+        match self.adaptation.save(&path, &self.controller.system) {
+            Ok(()) => self.info(done),
+            Err(e) => self.error(format!("{}, but saving failed: {}", done, e)),
+        }
+    }
+
+    fn reset_latches(&mut self) {
+        #[cfg(all(unix, feature = "daemon"))]
+        if let Some(link) = &mut self.remote {
+            self.rule_stats.reset_latches();
+            match link.reset_latches() {
+                Ok(()) => self.info("Rule latches reset on the daemon"),
+                Err(e) => self.error(format!("Lost connection to the daemon: {}", e)),
+            }
+            return;
+        }
+
+        self.rule_stats.reset_latches();
+        match self.save_state() {
+            Ok(()) => self.info("Rule latches reset"),
+            Err(e) => self.error(format!("Rule latches reset, but saving failed: {}", e)),
+        }
+    }
+
+    /// Fold or unfold the n-th rule group in the rule table
+    /// Rule indices in the order the rule table shows them: ungrouped
+    /// rules, then the rules of every unfolded group
+    fn visible_rules(&self) -> Vec<usize> {
+        let system = &self.controller.system;
+        let in_group = |group: Option<&str>| -> Vec<usize> {
+            system
+                .rules
+                .iter()
+                .enumerate()
+                .filter(|(_, rule)| rule.group.as_deref() == group)
+                .map(|(i, _)| i)
+                .collect()
+        };
+        let mut rules = in_group(None);
+        for group in &system.groups {
+            if !self.collapsed_groups.contains(&group.name) {
+                rules.extend(in_group(Some(&group.name)));
+            }
+        }
+        rules
+    }
+
+    /// Move the rule selection by `step` rows of the rule table
+    fn move_selection(&mut self, step: isize) {
+        let rules = self.visible_rules();
+        let position = self
+            .selected_rule
+            .and_then(|selected| rules.iter().position(|i| *i == selected));
+        let next = match position {
+            Some(p) => p
+                .saturating_add_signed(step)
+                .min(rules.len().saturating_sub(1)),
+            None if step < 0 => rules.len().saturating_sub(1),
+            None => 0,
+        };
+        self.selected_rule = rules.get(next).copied();
+    }
+
+    /// Selected rule with the primary output it alone would command at full
+    /// strength and, if it fires, at its current strength
+    fn rule_preview(&self) -> Option<(usize, f64, Option<f64>)> {
+        let index = self.selected_rule.filter(|_| self.show_rules)?;
+        let output = self.controller.system.output();
+        let set = self.controller.system.rules[index].consequent(&output.name)?;
+        let strength = self.rule_strengths.get(index).copied().unwrap_or(0.0);
+        // The (clipped) consequent set defuzzified as the output is
+        let settings = &self.controller.system.defuzzification;
+        let set = output.sets.iter().position(|s| s.name == set)?;
+        let centroid = |height: f64| {
+            defuzzify(
+                &Activations::single(output.sets.len(), set, height),
+                output,
+                settings,
+            )
+        };
+        let now = (strength > 0.0).then(|| centroid(strength));
+        Some((index, centroid(1.0), now))
+    }
+
+    /// Apply an edit to the running system, journaled first so a crash
+    /// does not lose it
+    fn edit(&mut self, edit: journal::Edit) {
+        let mut system = self.controller.system.clone();
+        if let Err(e) = edit.apply(&mut system) {
+            self.warn(format!("Edit failed: {}", e));
+            return;
+        }
+        if let Some(journal) = &mut self.journal
+            && let Err(e) = journal.record(&edit)
+        {
+            self.error(format!("Edit not applied, journal failed: {}", e));
+            return;
+        }
+        self.controller.system = system;
+        self.follow_rules();
+        self.info(format!("Edited: {}", edit.describe()));
+        #[cfg(all(unix, feature = "daemon"))]
+        if let Some(link) = &mut self.remote
+            && let Err(e) = link.send_edit(&edit)
+        {
+            self.error(format!("Lost connection to the daemon: {}", e));
+        }
+        self.compute_fan_speed();
+    }
+
+    /// Edit the group of the selected rule with `change`, given its
+    /// current enabled flag and weight
+    fn edit_selected_group(&mut self, change: impl Fn(&str, bool, f64) -> journal::Edit) {
+        let system = &self.controller.system;
+        let group = self
+            .selected_rule
+            .and_then(|i| system.rules[i].group.as_deref())
+            .and_then(|name| system.group(name));
+        match group {
+            Some(group) => {
+                let edit = change(&group.name, group.enabled, group.weight);
+                self.edit(edit);
+            }
+            None => self.info("Select a rule of a group with ↑/↓ first"),
+        }
+    }
+
+    /// Change the output aggregation or its temperature, like any tuning
+    /// edit
+    fn edit_aggregation(&mut self, change: impl Fn(Aggregation, f64) -> (Aggregation, f64)) {
+        let settings = &self.controller.system.defuzzification;
+        let (aggregation, temperature) = change(settings.aggregation, settings.softmax_temperature);
+        self.edit(journal::Edit::Aggregation {
+            aggregation,
+            temperature,
+        });
+    }
+
+    /// Give the output on screen the next defuzzification method it can
+    /// take, like any tuning edit; the other outputs keep theirs
+    fn cycle_method(&mut self) {
+        let system = &self.controller.system;
+        let settings = &system.defuzzification;
+        let output = system.output();
+        let current = settings.method_for(output);
+        let mut method = current.next();
+        let mut changed = output.clone();
+        while method != current {
+            changed.defuzz.method = Some(method);
+            if settings.check(&changed).is_ok() {
+                break;
+            }
+            method = method.next();
+        }
+        if method == current {
+            self.info(format!(
+                "No other method fits {} under {} aggregation",
+                output.name,
+                settings.aggregation.name()
+            ));
+            return;
+        }
+        let output = output.name.clone();
+        self.edit(journal::Edit::Method { output, method });
+    }
+
+    /// Switch to the next cap profile of the config, then back to none
+    fn cycle_caps(&mut self) {
+        let settings = &self.controller.system.defuzzification;
+        if settings.caps.is_empty() {
+            self.info("No [[cap]] profiles in this config");
+            return;
+        }
+        let profile = settings.next_cap_profile();
+        self.edit(journal::Edit::CapProfile { profile });
+    }
+
+    /// Replace the rules with those of the CSV next to the config, like
+    /// any edit
+    fn reload_rules_csv(&mut self) {
+        #[cfg(all(unix, feature = "daemon"))]
+        if self.remote.is_some() {
+            self.info("Attached: the daemon keeps the rules of its own config");
+            return;
+        }
+        let path = rulecsv::path_for(&self.source);
+        self.edit(journal::Edit::RulesCsv { path });
+    }
+
+    /// Keep the usage statistics and the selection in step with the rules
+    /// after an edit replaced them
+    fn follow_rules(&mut self) {
+        let system = &self.controller.system;
+        self.rule_stats.retarget(system.rule_texts());
+        self.adaptation.retarget(system);
+        if self.selected_rule.is_some_and(|i| i >= system.rules.len()) {
+            self.selected_rule = None;
+        }
+    }
+
+    /// Answer the recovery question for a crashed session's journal
+    fn recover(&mut self, replay: bool) {
+        let entries = std::mem::take(&mut self.recovery);
+        self.input_mode = InputMode::Menu;
+        if !replay {
+            self.info(format!("Discarded {} unsaved changes", entries.len()));
+            if let Some(journal) = &mut self.journal
+                && let Err(e) = journal.discard()
+            {
+                self.error(format!("Could not discard the journal: {}", e));
+            }
+            return;
+        }
+
+        let mut skipped = Vec::new();
+        let mut system = self.controller.system.clone();
+        for entry in &entries {
+            match entry {
+                Ok(edit) => {
+                    if let Err(e) = edit.apply(&mut system) {
+                        skipped.push(format!("{}: {}", edit.describe(), e));
+                    }
+                }
+                Err(e) => skipped.push(e.clone()),
+            }
+        }
+        self.controller.system = system;
+        self.follow_rules();
+        let recovered = format!("Recovered {} changes", entries.len() - skipped.len());
+        if skipped.is_empty() {
+            self.info(recovered);
+        } else {
+            self.warn(format!(
+                "{}, skipped {}: {}",
+                recovered,
+                skipped.len(),
+                skipped.join("; ")
+            ));
+        }
+        self.compute_fan_speed();
+    }
+
+    /// Share of the session the readings spent in the comfort zone, once
+    /// time has passed with one configured
+    fn comfort_share(&self) -> Option<String> {
+        self.controller.system.comfort.as_ref()?;
+        let share = self.comfort_time.share(self.clock.now())?;
+        Some(format!(
+            "{:.0}% of the time in the comfort zone",
+            share * 100.0
+        ))
+    }
+
+    /// Whether the running system differs from the one loaded
+    fn is_modified(&self) -> bool {
+        self.controller.system != self.baseline
+    }
+
+    /// Open the diff popup of the edits since the system was loaded
+    fn open_diff(&mut self) {
+        if !self.is_modified() {
+            self.info("No changes since the system was loaded");
+            return;
+        }
+        self.review = Some(Review::start(&self.baseline, &self.controller.system));
+        self.input_mode = InputMode::Diff;
+        self.prompt = "Changes since load: 'R' reverts them all, Esc closes".to_string();
+    }
+
+    /// Follow the diff popup's worker
+    fn poll_review(&mut self) {
+        if let Some(review) = &mut self.review {
+            review.poll();
+        }
+    }
+
+    /// Swap in a finer surface grid if one is ready; the refinement stops
+    /// with the view closed
+    fn poll_surface(&mut self) {
+        let refiner = self.surface.get_mut();
+        if self.show_surface {
+            refiner.poll();
+        } else {
+            refiner.clear();
+        }
+    }
+
+    /// Show or hide the history's what-if column
+    fn toggle_what_if(&mut self) {
+        if self.what_if.take().is_some() {
+            self.info("What-if column hidden");
+            return;
+        }
+        self.what_if = Some(WhatIf::new(&self.controller.system));
+        self.info("What-if: each entry's readings through the current rules ('W' hides)");
+    }
+
+    /// Show or hide the forecast panel
+    fn toggle_forecast(&mut self) {
+        if self.outlook.take().is_some() {
+            self.info("Forecast hidden");
+            return;
+        }
+        self.outlook = Some(Outlook::new(&self.controller.system));
+        let config = &self.controller.system.forecast;
+        self.info(format!(
+            "Forecast: a naive extrapolation, inputs {} over {}s, nothing more ('f' hides)",
+            match config.extrapolation {
+                forecast::Extrapolation::Linear => "on their recent trend",
+                forecast::Extrapolation::Hold => "held",
+            },
+            config.horizon_secs
+        ));
+    }
+
+    /// Keep the forecast up with the system and the latest readings
+    fn poll_forecast(&mut self) {
+        if let Some(outlook) = &mut self.outlook {
+            outlook.follow(&self.controller.system);
+            outlook.poll(&self.history, self.clock.now());
+        }
+    }
+
+    /// Keep the what-if column up with the system and the listed entries
+    fn poll_what_if(&mut self) {
+        if let Some(what_if) = &mut self.what_if {
+            what_if.follow(&self.controller.system);
+            let listed = self.history.len().saturating_sub(HISTORY_ROWS);
+            what_if.poll(&self.history[listed..]);
+        }
+    }
+
+    /// Put the system as loaded back, every edit at once. The journal is
+    /// emptied first: there is nothing left for a crash to lose
+    fn revert_all(&mut self) {
+        #[cfg(all(unix, feature = "daemon"))]
+        if self.remote.is_some() {
+            self.info("Attached: the daemon keeps its edits, undo them one by one");
+            return;
+        }
+        if !self.is_modified() {
+            self.info("Nothing to revert");
+            return;
+        }
+        if let Some(journal) = &mut self.journal
+            && let Err(e) = journal.discard()
+        {
+            self.error(format!("Not reverted, journal failed: {}", e));
+            return;
+        }
+        self.controller.system = self.baseline.clone();
+        self.follow_rules();
+        self.review = None;
+        self.input_mode = InputMode::Menu;
+        self.info("Reverted to the system as loaded");
+        self.compute_fan_speed();
+    }
+
+    fn toggle_group_fold(&mut self, digit: char) {
+        let index = digit as usize - '1' as usize;
+        if let Some(group) = self.controller.system.groups.get(index) {
+            match self.collapsed_groups.iter().position(|g| *g == group.name) {
+                Some(i) => {
+                    self.collapsed_groups.remove(i);
+                }
+                None => self.collapsed_groups.push(group.name.clone()),
+            }
+        }
+    }
+
+    /// Write a bug report bundle with the system, history and rule usage
+    fn export_bundle(&mut self) {
+        let now = self.clock.now();
+        // Every entry, not only the listed ones the worker filled
+        let controller = self
+            .what_if
+            .as_ref()
+            .map(|_| FuzzyController::from_system(self.controller.system.clone()));
+        let history = self
+            .history
+            .iter()
+            .map(|entry| bundle::HistoryRow {
+                age_secs: now - entry.time,
+                temperature: entry.temperature,
+                humidity: entry.humidity,
+                fan_speed: entry.fan_speed,
+                status: entry.status.0.clone(),
+                injected: entry.injected,
+                generator: entry.generator,
+                sensed: entry.sensed,
+                what_if: controller
+                    .as_ref()
+                    .map(|controller| what_if(controller, &entry.raw)),
+                interlock: entry.interlock.clone(),
+            })
+            .collect();
+        let path = format!("fuzzy_logic-report-{}.tar.gz", timestamp::now() as u64);
+        let report = bundle::Bundle::new(
+            &self.source,
+            &self.controller.system,
+            history,
+            Some(&self.rule_stats),
+        );
+        match report.write(&path) {
+            Ok(()) => self.info(format!("Bug report written to {}", path)),
+            Err(e) => self.error(format!("Could not write the bug report: {}", e)),
+        }
+    }
+
+    /// Run the scenarios kept next to the loaded config
+    fn check_scenarios(&mut self) {
+        let path = scenario::path_for(&self.source);
+        match scenario::load(&path) {
+            Ok(scenarios) => {
+                let problems = scenario::check_all(&scenarios, &self.controller);
+                match problems.first() {
+                    None => self.info(format!("{} scenarios pass", scenarios.len())),
+                    Some(first) if problems.len() == 1 => {
+                        self.warn(format!("Scenario failed: {}", first))
+                    }
+                    Some(first) => self.warn(format!(
+                        "Scenario failed: {} (and {} more, see `check --scenarios`)",
+                        first,
+                        problems.len() - 1
+                    )),
+                }
+            }
+            Err(e) => self.warn(format!("No scenarios: {}", e)),
+        }
+    }
+
+    fn save_state(&mut self) -> Result<(), String> {
+        match &self.state_path {
+            Some(path) => self.rule_stats.save(path, &self.bookmarks, &self.layout),
+            None => Ok(()),
+        }
+    }
+
+    /// Hand the journal and the state file to the shutdown: the journal is
+    /// removed, then the state saved
+    fn register_shutdown(&mut self, coordinator: &mut Coordinator) {
+        if let Some(journal) = self.journal.take() {
+            coordinator.register(
+                Stage::FlushLogs,
+                "journal",
+                shutdown::DEFAULT_TIMEOUT,
+                move || journal.close(),
+            );
+        }
+        if let Some(path) = self.state_path.clone() {
+            let (mut stats, bookmarks) = (self.rule_stats.clone(), self.bookmarks.clone());
+            let layout = self.layout.clone();
+            coordinator.register(
+                Stage::PersistState,
+                "state",
+                shutdown::DEFAULT_TIMEOUT,
+                move || stats.save(&path, &bookmarks, &layout),
+            );
+        }
+    }
+
+    /// `done` after a bookmark change, which is saved right away
+    fn bookmarks_changed(&mut self, done: String) {
+        match self.save_state() {
+            Ok(()) => self.info(done),
+            Err(e) => self.error(format!("{}, but saving failed: {}", done, e)),
+        }
+    }
+
+    fn add_bookmark(&mut self, name: &str) {
+        let result = self.bookmarks.add(
+            name,
+            self.input_values(),
+            &self.controller,
+            timestamp::now(),
+        );
+        match result {
+            Ok(()) => self.bookmarks_changed(format!("Bookmarked '{}'", name.trim())),
+            Err(e) => self.warn(format!("Not bookmarked: {}", e)),
+        }
+    }
+
+    fn open_bookmarks(&mut self) {
+        if self.bookmarks.is_empty() {
+            self.info("No bookmarks yet, 'B' saves the current inputs");
+            return;
+        }
+        self.selected_bookmark = self.selected_bookmark.min(self.bookmarks.len() - 1);
+        self.input_mode = InputMode::Bookmarks;
+        self.prompt =
+            "Bookmarks: Enter jumps, 'r' renames, 'd' deletes, 'p' promotes to a scenario"
+                .to_string();
+    }
+
+    fn move_bookmark_selection(&mut self, delta: isize) {
+        let last = self.bookmarks.len().saturating_sub(1);
+        self.selected_bookmark = self
+            .selected_bookmark
+            .saturating_add_signed(delta)
+            .min(last);
+    }
+
+    fn jump_to_bookmark(&mut self) {
+        let Some(bookmark) = self.bookmarks.get(self.selected_bookmark).cloned() else {
+            return;
+        };
+        if bookmark.inputs.len() != self.controller.system.inputs.len() {
+            self.warn(format!(
+                "Bookmark '{}' has {} inputs, the system {}",
+                bookmark.name,
+                bookmark.inputs.len(),
+                self.controller.system.inputs.len()
+            ));
+            return;
+        }
+        self.input_mode = InputMode::Menu;
+        self.set_input_values(&bookmark.inputs);
+        self.info(format!("Jumped to '{}'", bookmark.name));
+        self.compute_fan_speed();
+    }
+
+    fn rename_bookmark(&mut self, name: &str) {
+        self.input_mode = InputMode::Bookmarks;
+        match self.bookmarks.rename(self.selected_bookmark, name) {
+            Ok(()) => self.bookmarks_changed(format!("Renamed to '{}'", name.trim())),
+            Err(e) => self.warn(format!("Not renamed: {}", e)),
+        }
+    }
+
+    fn delete_bookmark(&mut self) {
+        if let Some(bookmark) = self.bookmarks.remove(self.selected_bookmark) {
+            self.bookmarks_changed(format!("Deleted '{}'", bookmark.name));
+        }
+        if self.bookmarks.is_empty() {
+            self.input_mode = InputMode::Menu;
+        } else {
+            self.move_bookmark_selection(0);
+        }
+    }
+
+    /// Add the selected bookmark to the config's scenario presets
+    fn promote_bookmark(&mut self) {
+        let Some(bookmark) = self.bookmarks.get(self.selected_bookmark) else {
+            return;
+        };
+        let path = scenario::path_for(&self.source);
+        match bookmarks::promote(bookmark, &path, &self.controller) {
+            Ok(()) => self.info(format!("'{}' added to {}", bookmark.name, path)),
+            Err(e) => self.warn(format!("Not promoted: {}", e)),
+        }
+    }
+
+    /// Keep the frozen state with `note`, saved right away when there is a
+    /// state file
+    fn annotate(&mut self, note: &str) {
+        let Some(frozen) = self.frozen.take() else {
+            return;
+        };
+        if let Err(e) = self.annotations.add(frozen, note) {
+            self.warn(format!("Not annotated: {}", e));
+            return;
+        }
+        let done = format!("Annotated '{}'", note.trim());
+        let Some(path) = &self.state_path else {
+            self.info(format!("{}, for this session only: see --state", done));
+            return;
+        };
+        match self.annotations.save(&annotations::path_for(path)) {
+            Ok(()) => self.info(done),
+            Err(e) => self.error(format!("{}, but saving failed: {}", done, e)),
+        }
+    }
+
+    fn open_annotations(&mut self) {
+        if self.annotations.is_empty() {
+            self.info("No annotations yet, 'a' freezes the current state with a note");
+            return;
+        }
+        self.selected_annotation = self.selected_annotation.min(self.annotations.len() - 1);
+        self.input_mode = InputMode::Annotations;
+        self.prompt = "Annotations: Enter jumps, 'J' exports JSON, 'H' an HTML section".to_string();
+    }
+
+    fn move_annotation_selection(&mut self, delta: isize) {
+        let last = self.annotations.len().saturating_sub(1);
+        self.selected_annotation = self
+            .selected_annotation
+            .saturating_add_signed(delta)
+            .min(last);
+    }
+
+    fn jump_to_annotation(&mut self) {
+        let hash = annotations::config_hash(&self.controller.system);
+        let entries = self.annotations.entries(hash);
+        let Some(entry) = entries.get(self.selected_annotation) else {
+            return;
+        };
+        let (note, inputs) = (
+            entry.annotation.note.clone(),
+            entry.annotation.inputs.clone(),
+        );
+        let stale = entry.stale;
+        if inputs.len() != self.controller.system.inputs.len() {
+            self.warn(format!(
+                "Annotation '{}' has {} inputs, the system {}",
+                note,
+                inputs.len(),
+                self.controller.system.inputs.len()
+            ));
+            return;
+        }
+        self.input_mode = InputMode::Menu;
+        self.set_input_values(&inputs);
+        match stale {
+            true => self.warn(format!(
+                "Jumped to '{}', annotated with another config: possibly stale",
+                note
+            )),
+            false => self.info(format!("Jumped to '{}'", note)),
+        }
+        self.compute_fan_speed();
+    }
+
+    /// Write the annotations next to the state file, as an HTML section
+    /// or as JSON
+    fn export_annotations(&mut self, html: bool) {
+        let Some(state) = self.state_path.clone() else {
+            self.warn("Not exported: annotations are exported next to the state file, see --state");
+            return;
+        };
+        let system = &self.controller.system;
+        let (path, text) = match html {
+            true => (
+                format!("{}.annotations.html", state),
+                self.annotations.to_html(
+                    annotations::config_hash(system),
+                    system.timezone,
+                    &system.output().name,
+                ),
+            ),
+            false => (
+                format!("{}.annotations.json", state),
+                self.annotations.to_json(),
+            ),
+        };
+        match std::fs::write(&path, text) {
+            Ok(()) => self.info(format!("Annotations exported to {}", path)),
+            Err(e) => self.error(format!("Not exported: {}: {}", path, e)),
+        }
+    }
+
+    /// Write the running system over the config it was loaded from,
+    /// keeping the replaced text as a backup (backup.rs). The saved
+    /// system is the new baseline and the journal has nothing left to keep
+    fn save_config(&mut self) {
+        #[cfg(all(unix, feature = "daemon"))]
+        if self.remote.is_some() {
+            self.info("Attached: the daemon's config is saved where it runs");
+            return;
+        }
+        let Some(path) = self.config_path.clone() else {
+            self.info("Nothing to save to: start with --config FILE");
+            return;
+        };
+        let text = config::to_toml(&self.controller.system, true);
+        if let Err(e) = backup::save(&path, &text, self.keep_backups) {
+            self.error(format!("Not saved: {}", e));
+            return;
+        }
+        self.saved(format!("Saved {}", path));
+    }
+
+    /// The config on disk is now the running system
+    fn saved(&mut self, done: String) {
+        self.baseline = self.controller.system.clone();
+        match self.journal.as_mut().map(|journal| journal.discard()) {
+            Some(Err(e)) => self.error(format!("{}, but the journal failed: {}", done, e)),
+            _ => self.info(done),
+        }
+    }
+
+    fn open_backups(&mut self) {
+        let Some(path) = &self.config_path else {
+            self.info("No config file, so no backups");
+            return;
+        };
+        self.backups = backup::list(path);
+        if self.backups.is_empty() {
+            self.info("No backups yet, 'o' saves the config and keeps one");
+            return;
+        }
+        self.selected_backup = 0;
+        self.input_mode = InputMode::Backups;
+        self.prompt = "Backups: Enter restores the selected one, Esc closes".to_string();
+    }
+
+    fn move_backup_selection(&mut self, delta: isize) {
+        let last = self.backups.len().saturating_sub(1);
+        self.selected_backup = self.selected_backup.saturating_add_signed(delta).min(last);
+    }
+
+    /// Put the selected backup back as the config and run it. Unsaved
+    /// edits are not thrown away for it
+    fn restore_backup(&mut self) {
+        let (Some(path), Some(chosen)) = (
+            self.config_path.clone(),
+            self.backups.get(self.selected_backup).cloned(),
+        ) else {
+            return;
+        };
+        if self.is_modified() {
+            self.warn("Unsaved changes: save them with 'o' or revert them ('D', 'R') first");
+            return;
+        }
+        let system = match std::fs::read_to_string(&chosen.path)
+            .map_err(|e| format!("{}: {}", chosen.path, e))
+            .and_then(|text| config::parse_system(&text))
+            .and_then(|system| check_tui_system(&system, &chosen.path).map(|_| system))
+        {
+            Ok(system) => system,
+            Err(e) => {
+                self.warn(format!("Not restored: {}", e));
+                return;
+            }
+        };
+        if let Err(e) = backup::restore(&path, chosen.number, self.keep_backups) {
+            self.error(format!("Not restored: {}", e));
+            return;
+        }
+        self.input_mode = InputMode::Menu;
+        self.backups.clear();
+        self.controller.system = system;
+        self.follow_rules();
+        self.saved(format!("Restored bak{} to {}", chosen.number, path));
+        self.compute_fan_speed();
+    }
+
+    /// Status label and color, following the configured status mode
+    fn status(&self) -> (String, Color) {
+        let band_colors = self.theme.bands;
+        let system = &self.controller.system;
+        match system.pipeline.status {
+            StatusMode::Thresholds => (
+                self.output.band.label().to_string(),
+                band_colors[self.output.band as usize],
+            ),
+            StatusMode::DominantSet => {
+                match dominant_set(
+                    &self.rule_strengths,
+                    &system.rules,
+                    system.output(),
+                    system.defuzzification.tie_tolerance,
+                ) {
+                    // Sets are ordered along the output range, so their
+                    // position picks the color
+                    Some(name) => {
+                        let index = system.output().sets.iter().position(|s| s.name == name);
+                        let level =
+                            index.unwrap_or(0) * band_colors.len() / system.output().sets.len();
+                        (name.to_uppercase(), band_colors[level])
+                    }
+                    None => ("NONE".to_string(), self.theme.faint),
+                }
+            }
+        }
+    }
+
+    fn push_history(&mut self) {
+        self.entries_recorded += 1;
+        // Never before the last entry, so the history stays in order
+        let time = self.clock.now() - self.lag;
+        let time = self.history.last().map_or(time, |last| time.max(last.time));
+        self.history.push(HistoryEntry {
+            id: self.entries_recorded,
+            time,
+            temperature: self.temperature,
+            humidity: self.humidity,
+            fan_speed: self.fan_speed,
+            status: self.status(),
+            injected: self.injector.is_active(),
+            interlock: self.interlocks.active().map(|i| i.name.clone()),
+            generator: self.sampled,
+            sensed: self.sensed,
+            overflows: self.overflows.clone(),
+            output: self.output.value,
+            shadow: self.shadow.as_ref().map(|shadow| shadow.output.value),
+            dominant: self.dominant_rule(),
+            raw: self.input_values(),
+        });
+        self.trend
+            .record([self.temperature, self.humidity, self.output.value]);
+        let entry = self.history.last().expect("just pushed");
+        if let Some(comfort) = &self.controller.system.comfort {
+            let inside = comfort.zone.contains(entry.temperature, entry.humidity);
+            self.comfort_time.record(entry.time, inside);
+        }
+        for (series, value) in self.history_series.iter_mut().zip(entry.inputs()) {
+            series.push(entry.time, value);
+        }
+        if self.history.len() > self.history_limit {
+            let entry = self.history.remove(0);
+            for (i, series) in self.history_series.iter_mut().enumerate() {
+                let rest = self.history.iter().map(|e| (e.time, e.inputs()[i]));
+                series.remove_first(entry.inputs()[i], rest);
+            }
+        }
+    }
+
+    /// Take the newest entry out of the history
+    fn pop_history(&mut self) {
+        let Some(entry) = self.history.pop() else {
+            return;
+        };
+        self.trend.undo();
+        for (i, series) in self.history_series.iter_mut().enumerate() {
+            let rest = self.history.iter().map(|e| (e.time, e.inputs()[i]));
+            series.remove_last(entry.inputs()[i], rest);
+        }
+    }
+
+    fn clear_history(&mut self) {
+        self.history.clear();
+        self.history_series = Default::default();
+        self.trend = Trend::default();
+    }
+
+    /// Summarize the history again after its entries were rewritten
+    fn resummarize_history(&mut self) {
+        let history = &self.history;
+        self.history_series = [0, 1]
+            .map(|i| lod::Series::from_points(history.iter().map(|e| (e.time, e.inputs()[i]))));
+    }
+
+    /// Advance the time-dependent output stages
+    fn tick(&mut self) {
+        #[cfg(all(unix, feature = "daemon"))]
+        if let Some(link) = &self.remote {
+            // Mirror the daemon, which runs the pipeline itself
+            if let Some(state) = link.latest() {
+                let temperature = state.input("temperature").unwrap_or(self.temperature);
+                let humidity = state.input("humidity").unwrap_or(self.humidity);
+                let changed = self.history.last().is_none_or(|last| {
+                    (last.temperature, last.humidity, last.fan_speed)
+                        != (temperature, humidity, state.fan_speed)
+                });
+                self.temperature = temperature;
+                self.humidity = humidity;
+                self.sync_dew_point();
+                if let Some(cascade) = &mut self.cascade {
+                    // The daemon's measurement instead of the simulated one
+                    if let Some(measured) = state.input(&cascade.config().measured) {
+                        cascade.measured = measured;
+                    }
+                }
+                self.output = state.output;
+                self.held = state.held;
+                self.windows = state.windows;
+                self.mirror_sources(state.sources);
+                self.lag = state.lag.unwrap_or(0.0);
+                self.watch_oscillation();
+                if changed {
+                    // Rule strengths are not part of the protocol; the
+                    // mirrored inputs give the same ones locally
+                    self.evaluate_rules();
+                    self.fan_speed = state.fan_speed;
+                    self.push_history();
+                }
+            }
+            return;
+        }
+
+        let expired = self.injector.expire(self.clock.now());
+        // Noisy sensors keep reporting while the true values stand still
+        let sample = self.noise.is_active()
+            && self
+                .history
+                .last()
+                .is_some_and(|e| self.clock.now() - e.time >= noise::SAMPLE_INTERVAL);
+        if expired || sample {
+            self.evaluate_rules();
+        }
+        let value = match &mut self.cascade {
+            Some(cascade) => cascade.simulate(self.fan_speed, self.clock.now()),
+            None => self.fan_speed,
+        };
+        let output = self.pipeline.process(value, self.clock.now());
+        self.output = self
+            .interlocks
+            .apply(&mut self.pipeline, output, self.clock.now());
+        if let Some(shadow) = &mut self.shadow {
+            shadow.tick(self.output, self.clock.now());
+        }
+        self.watch_oscillation();
+        if expired || sample {
+            self.push_history();
+        }
+        if expired {
+            self.info("Injection expired");
+        }
+        self.run_exec();
+    }
+
+    /// Hand the output to the exec program and say how its finished runs
+    /// went: failures warn, and one that used up the retries or nothing
+    /// delivered for `stall_secs` is an error
+    fn run_exec(&mut self) {
+        let Some(exec) = &mut self.exec else {
+            return;
+        };
+        exec.publish(Publication {
+            output: self.controller.system.output().name.clone(),
+            value: self.output.value,
+            band: self.output.band,
+            // What an interlock forces is never coalesced away
+            priority: self.interlocks.active().is_some(),
+        });
+        match (exec.stalled(), self.exec_stalled) {
+            (Some(secs), false) => {
+                let stats = exec.stats();
+                self.exec_stalled = true;
+                self.error(format!(
+                    "Alarm 'exec_stalled': nothing delivered for {:.0}s, {} coalesced, {} dropped",
+                    secs, stats.coalesced, stats.dropped
+                ));
+            }
+            (None, true) => {
+                self.exec_stalled = false;
+                self.info("Alarm 'exec_stalled' cleared");
+            }
+            _ => {}
+        }
+        let Some(exec) = &self.exec else {
+            return;
+        };
+        for outcome in exec.finished() {
+            let invocation = &outcome.invocation;
+            if invocation.status.succeeded() {
+                if std::mem::take(&mut self.exec_failing) {
+                    self.info("Alarm 'exec' cleared");
+                }
+            } else if outcome.gave_up {
+                self.exec_failing = true;
+                self.error(format!("Alarm 'exec': {}", invocation.describe()));
+            } else {
+                self.warn(invocation.describe());
+            }
+        }
+    }
+
+    /// Feed the output to the oscillation detector, saying when an
+    /// oscillation starts or ends
+    fn watch_oscillation(&mut self) {
+        let Some(detector) = &mut self.oscillation else {
+            return;
+        };
+        if !detector.push(self.clock.now(), self.output.value) {
+            return;
+        }
+        let name = &self.controller.system.output().name;
+        match detector.oscillation() {
+            Some(swing) => self.warn(format!(
+                "Oscillation: {} swinging {} to {}, {} reversals",
+                name,
+                self.show(name, swing.low),
+                self.show(name, swing.high),
+                swing.reversals
+            )),
+            None => self.info("Oscillation over"),
+        }
+    }
+
+    /// Universe and current value of the temperature and humidity inputs
+    fn curve_axes(&self) -> [(f64, f64, f64); 2] {
+        let system = &self.controller.system;
+        [
+            ("temperature", self.temperature),
+            ("humidity", self.humidity),
+        ]
+        .map(|(name, value)| match system.input(name) {
+            Some(var) => (var.min, var.max, value),
+            None => (0.0, 1.0, value),
+        })
+    }
+
+    /// Seconds between the oldest computation kept and now
+    fn history_age(&self) -> f64 {
+        self.history
+            .first()
+            .map_or(0.0, |entry| self.clock.now() - entry.time)
+    }
+
+    /// Zoom the curves when shown, the sparklines otherwise, around the
+    /// current values
+    fn zoom(&mut self, factor: f64) {
+        if self.show_curves {
+            let axes = self.curve_axes();
+            for (view, (min, max, value)) in self.curve_views.iter_mut().zip(axes) {
+                *view = view.zoom(factor, value, min, max);
+            }
+        } else {
+            let age = self.history_age();
+            if age > 0.0 {
+                let view = self.history_view.unwrap_or(Viewport::full(0.0, age));
+                self.history_view = Some(view.zoom(factor, 0.0, 0.0, age));
+            }
+        }
+    }
+
+    /// Pan the zoomed charts by `steps`, positive toward larger values or
+    /// later times
+    fn pan(&mut self, steps: f64) {
+        if self.show_curves {
+            let axes = self.curve_axes();
+            for (view, (min, max, _)) in self.curve_views.iter_mut().zip(axes) {
+                *view = view.pan(steps, min, max);
+            }
+        } else {
+            let age = self.history_age();
+            if let Some(view) = &mut self.history_view {
+                // The time axis counts seconds back from now
+                *view = view.pan(-steps, 0.0, age);
+            }
+        }
+    }
+
+    /// History entries inside the sparkline view, all without one
+    fn visible_history(&self) -> &[HistoryEntry] {
+        let Some(view) = self.history_view else {
+            return &self.history;
+        };
+        let now = self.clock.now();
+        let first = self.history.partition_point(|e| now - e.time > view.end);
+        let last = self.history.partition_point(|e| now - e.time >= view.start);
+        &self.history[first..last.max(first)]
+    }
+
+    /// Start and end of the zoomed sparkline view on the app clock
+    fn history_window(&self) -> Option<(f64, f64)> {
+        let view = self.history_view?;
+        let now = self.clock.now();
+        Some((now - view.end, now - view.start))
+    }
+
+    /// History entries the input charts draw: the latest SPARKLINE_WINDOW,
+    /// or the zoomed view up to the history limit
+    fn charted_history(&self) -> &[HistoryEntry] {
+        let window = match self.history_view {
+            Some(_) => self.history_limit,
+            None => SPARKLINE_WINDOW,
+        };
+        let history = self.visible_history();
+        &history[history.len().saturating_sub(window)..]
+    }
+
+    /// Range of the value axis of input chart `i` (0 temperature, 1
+    /// humidity) in its mode, from the level-of-detail buckets of a long
+    /// zoomed view
+    fn axis_target(&self, i: usize) -> (f64, f64) {
+        let (min, max, _) = self.curve_axes()[i];
+        let history = self.charted_history();
+        let buckets = match self.history_window() {
+            Some((from, to)) if history.len() > autorange::RANGE_BUCKETS => {
+                let series = &self.history_series[i];
+                let level = series
+                    .level_for(from, to, autorange::RANGE_BUCKETS)
+                    .unwrap_or(lod::LEVELS.len() - 1);
+                series.buckets(level, from, to)
+            }
+            _ => history
+                .iter()
+                .flat_map(|e| {
+                    let sensed = e.sensed.map(|s| [s.0, s.1][i]);
+                    [Some(e.inputs()[i]), sensed]
+                        .into_iter()
+                        .flatten()
+                        .map(|v| autorange::point(e.time, v))
+                })
+                .collect(),
+        };
+        self.range_modes[i].range(&buckets, (min, max))
+    }
+
+    /// Move the chart axes one frame toward their ranges
+    fn update_axes(&mut self) {
+        for i in 0..2 {
+            let target = self.axis_target(i);
+            self.axes[i].retarget(target);
+            self.axes[i].advance();
+        }
+    }
+
+    /// Switch the range of input chart `i` to the next mode
+    fn cycle_range(&mut self, i: usize) {
+        self.range_modes[i] = self.range_modes[i].next();
+        let name = ["Temperature", "Humidity"][i];
+        self.info(format!("{} chart: {}", name, self.range_modes[i].label()));
+    }
+
+    /// Level of detail the zoomed sparklines draw `points` wide, none while
+    /// the entries in view fit and are drawn themselves
+    fn sparkline_level(&self, points: usize) -> Option<usize> {
+        let (from, to) = self.history_window()?;
+        if self.visible_history().len() <= points {
+            return None;
+        }
+        let series = &self.history_series[0];
+        Some(
+            series
+                .level_for(from, to, points)
+                .unwrap_or(lod::LEVELS.len() - 1),
+        )
+    }
+
+    fn reset_view(&mut self) {
+        self.curve_views = self
+            .curve_axes()
+            .map(|(min, max, _)| Viewport::full(min, max));
+        self.history_view = None;
+    }
+
+    /// Focus the next panel for the layout keys
+    fn focus_next_panel(&mut self) {
+        let pane = self.layout.focus_next();
+        let hidden = if self.layout.is_visible(pane) {
+            ""
+        } else {
+            " (hidden)"
+        };
+        self.info(format!(
+            "Focused the {} panel{}: Ctrl+arrows resize it, 'p' hides or shows it, 'P' resets the layout",
+            pane.name(),
+            hidden
+        ));
+    }
+
+    fn toggle_panel(&mut self) {
+        let name = self.layout.focus().name();
+        match self.layout.toggle() {
+            true => self.info(format!("The {} panel is shown", name)),
+            false => self.info(format!("The {} panel is hidden ('p' shows it)", name)),
+        }
+    }
+
+    /// Grow or shrink the focused panel by `steps`
+    fn resize_panel(&mut self, steps: i16) {
+        let pane = self.layout.focus();
+        let size = self.layout.resize(steps);
+        match pane {
+            Pane::History => self.info(format!("History panel: {} rows", size)),
+            _ => self.info(format!("The {} panel's weight: {} of 9", pane.name(), size)),
+        }
+    }
+
+    /// Run a failure injection command; an attached daemon gets it too
+    fn inject(&mut self, command: &str) {
+        let inputs = &self.controller.system.inputs;
+        match self.injector.command(command, inputs, self.clock.now()) {
+            Ok(message) => self.info(message),
+            Err(e) => {
+                self.warn(format!("Injection failed: {}", e));
+                return;
+            }
+        }
+        #[cfg(all(unix, feature = "daemon"))]
+        if let Some(link) = &mut self.remote {
+            // Kept locally as well, for the gauges and the mirrored rules
+            if let Err(e) = link.inject(command) {
+                self.error(format!("Lost connection to the daemon: {}", e));
+            }
+        }
+        self.compute_fan_speed();
+    }
+
+    /// Engage interlock `name`, or release it when engaged; an attached
+    /// daemon switches its own too
+    fn switch_interlock(&mut self, name: &str) {
+        let engaged = !self.interlocks.is_engaged(name);
+        if let Err(e) = self.interlocks.set(name, engaged) {
+            self.warn(format!("Interlock not switched: {}", e));
+            return;
+        }
+        #[cfg(all(unix, feature = "daemon"))]
+        if let Some(link) = &mut self.remote
+            && let Err(e) = link.interlock(name, engaged)
+        {
+            self.error(format!("Lost connection to the daemon: {}", e));
+        }
+        match engaged {
+            true => self.warn(format!("Interlock '{}' engaged", name)),
+            false => self.info(format!("Interlock '{}' released", name)),
+        }
+        // Recorded in the history, with the interlock now forcing it
+        self.compute_fan_speed();
+    }
+
+    /// Pause the simulation when the terminal loses focus and go on when
+    /// it is back, if the config asks for it
+    fn set_focus(&mut self, focused: bool) {
+        if !self.controller.system.pause_on_blur || self.paused != focused {
+            return;
+        }
+        self.paused = !focused;
+        match self.paused {
+            true => self.info("Paused while the terminal is out of focus"),
+            false => self.info("Focus back, simulation running"),
+        }
+    }
+
+    /// Change the simulated sensor noise of the session
+    fn set_noise(&mut self, command: &str) {
+        match self.noise.command(command, &self.controller.system.inputs) {
+            Ok(message) => self.info(message),
+            Err(e) => {
+                self.warn(format!("Noise not changed: {}", e));
+                return;
+            }
+        }
+        self.compute_fan_speed();
+    }
+
+    /// Fit a step table of the size in `command` ("4x3", optionally
+    /// followed by the objective) to the surface and draw it over the
+    /// surface view; an empty command takes it off
+    fn quantize_surface(&mut self, command: &str) {
+        let mut words = command.split_whitespace();
+        let Some(size) = words.next() else {
+            self.step_table = None;
+            self.info("Step table cleared");
+            return;
+        };
+        let system = self.controller.system.clone();
+        let fitted = quantize::parse_size(size).and_then(|size| {
+            let objective = words
+                .next()
+                .map_or(Ok(quantize::Objective::Worst), quantize::Objective::parse)?;
+            let surface = quantize::sample(&system, STEP_TABLE_GRID);
+            let (_, naive) = quantize::equal_spacing(&surface, size, objective)?;
+            let (table, fit) = quantize::optimize(&surface, size, objective)?;
+            Ok((size, table, fit, naive))
+        });
+        match fitted {
+            Ok((size, table, fit, naive)) => {
+                self.info(format!(
+                    "{}x{} step table: {} (equal spacing: {})",
+                    size.0,
+                    size.1,
+                    fit.summary(),
+                    naive.summary()
+                ));
+                self.step_table = Some(StepTable { system, table, fit });
+                self.show_surface = true;
+            }
+            Err(e) => self.warn(format!("No step table: {}", e)),
+        }
+    }
+
+    /// Draw the inputs from the selected generator
+    fn generate_random(&mut self) {
+        #[cfg(all(unix, feature = "daemon"))]
+        if self.remote.is_some() && self.controller.system.overrides.duration_secs == 0.0 {
+            self.warn(
+                "Random inputs are off: [override] duration_secs = 0 leaves the daemon's \
+                 inputs to its readings",
+            );
+            return;
+        }
+        let sampler = generator::Sampler::new(&self.controller);
+        let Some(values) = sampler.sample(self.generator, &mut rand::thread_rng()) else {
+            self.warn("No sample satisfies the generator constraints");
+            return;
+        };
+        for (var, value) in self.controller.system.inputs.iter().zip(values) {
+            match var.name.as_str() {
+                "temperature" => self.temperature = value,
+                "humidity" => self.humidity = value,
+                _ => {}
+            }
+        }
+        self.sync_dew_point();
+        self.sampled = Some(self.generator);
+        self.compute_fan_speed();
+        self.sampled = None;
+        self.info(format!(
+            "Generated random values ({})!",
+            self.generator.name()
+        ));
+    }
+
+    fn cycle_generator(&mut self) {
+        self.generator = self.generator.next();
+        self.info(format!(
+            "Random values now drawn {}",
+            match self.generator {
+                GeneratorKind::Uniform => "uniformly",
+                GeneratorKind::Boundary => "near the set breakpoints",
+                GeneratorKind::Constrained => "within the generator constraints",
+            }
+        ));
+    }
+}
+
+// ============================================================================
+// UI RENDERING
+// ============================================================================
+
+fn ui<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, app: &App) {
+    let size = f.size();
+    if size.width < layout::MIN_WIDTH || size.height < layout::MIN_HEIGHT {
+        let note = Paragraph::new(format!(
+            "Terminal too small: {}x{}, needs {}x{}",
+            size.width,
+            size.height,
+            layout::MIN_WIDTH,
+            layout::MIN_HEIGHT
+        ))
+        .wrap(Wrap { trim: true })
+        .style(Style::default().fg(app.theme.caution));
+        f.render_widget(note, size);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(STATUS_HEIGHT),
+        ])
+        .split(size);
+
+    // Title, naming the loaded system when its definition does
+    let title = match app.controller.system.metadata.summary() {
+        Some(summary) => format!("🤖 FUZZY LOGIC FAN CONTROLLER · {}", summary),
+        None => "🤖 FUZZY LOGIC FAN CONTROLLER".to_string(),
+    };
+    let title = if app.is_modified() {
+        format!("{} · ✎ modified ('D' shows the changes)", title)
+    } else {
+        title
+    };
+    // An interlock forcing the output, or else a safe mode, or else inputs
+    // held against an attached daemon's readings, takes the title bar over
+    let output = app.controller.system.output();
+    let (title, color) = match (app.interlocks.active(), &app.safe_mode, &app.held) {
+        (Some(interlock), _, _) => (
+            Interlocks::banner(interlock, &app.show(&output.name, interlock.value)),
+            app.theme.error,
+        ),
+        (None, Some(error), _) => (safemode::banner(error), app.theme.error),
+        (None, None, Some(held)) => (Overrides::banner(held), app.theme.caution),
+        (None, None, None) => (title, app.theme.accent),
+    };
+    let title = Paragraph::new(title)
+        .style(Style::default().fg(color).add_modifier(Modifier::BOLD))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(color)),
+        );
+    f.render_widget(title, chunks[0]);
+
+    // The panels, where the layout puts them; the tutorial points at all
+    // of them
+    let areas = match &app.tutorial {
+        Some(_) => PanelLayout::default().split(chunks[1]),
+        None => app.layout.split(chunks[1]),
+    };
+    if let Some(area) = areas.readings {
+        render_left_panel(f, app, area);
+    }
+    if let Some(area) = areas.sets {
+        render_right_panel(f, app, area);
+    }
+    if let Some(area) = areas.history {
+        render_history(f, app, area);
+    }
+    if areas.main.is_none() && areas.history.is_none() {
+        let hint = Paragraph::new(
+            "All panels are hidden: Tab focuses each in turn and 'p' shows it, \
+             'P' brings back the default layout",
+        )
+        .wrap(Wrap { trim: true })
+        .block(Block::default().borders(Borders::ALL).title("Panels"));
+        f.render_widget(hint, chunks[1]);
+    }
+
+    if let (Some(tutorial), Some(left), Some(right), Some(history)) =
+        (&app.tutorial, areas.readings, areas.sets, areas.history)
+    {
+        render_tutorial(f, app, tutorial, left, right, history);
+    }
+
+    // Popups open over the readings and sets, or the whole body without them
+    let popup = areas.main.unwrap_or(chunks[1]);
+    if matches!(
+        app.input_mode,
+        InputMode::Bookmarks | InputMode::RenameBookmark
+    ) {
+        render_bookmarks(f, app, popup);
+    }
+    if app.input_mode == InputMode::Annotations {
+        render_annotations(f, app, popup);
+    }
+    if app.input_mode == InputMode::Backups {
+        render_backups(f, app, popup);
+    }
+    if let Some(review) = &app.review {
+        render_review(f, &app.theme, review, popup);
+    }
+    if app.input_mode == InputMode::Messages {
+        render_messages(f, app, popup);
+    }
+    if app.input_mode == InputMode::Complexity {
+        render_complexity(f, app, popup);
+    }
+    if app.input_mode == InputMode::Chain {
+        render_chain(f, app, popup);
+    }
+    if app.input_mode == InputMode::Sources {
+        render_sources(f, app, popup);
+    }
+    if app.input_mode == InputMode::Palettes {
+        render_palettes(f, app, popup);
+    }
+    if let Some(error) = app.safe_mode.as_ref().filter(|_| app.show_safe_mode) {
+        render_safe_mode_error(f, &app.theme, error, popup);
+    }
+
+    let (message, style) = status_line(app);
+    let msg = Paragraph::new(message)
+        .style(style)
+        .block(Block::default().borders(Borders::ALL).title("Status"));
+    f.render_widget(msg, chunks[2]);
+}
+
+/// The tutorial's current step: everything but its panels dimmed, and its
+/// explanation over the history panel
+fn render_tutorial<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    app: &App,
+    tutorial: &Tutorial,
+    left: Rect,
+    right: Rect,
+    history: Rect,
+) {
+    let step = tutorial::STEPS[tutorial.step];
+    let (_, left_chunks) = left_panel_layout(left);
+    let inputs = left_chunks[0].union(left_chunks[1]);
+    let aggregated = curves_layout(right)[2];
+    let keep = match step.panel {
+        Panel::Inputs => vec![inputs],
+        Panel::Memberships | Panel::Rules => vec![right],
+        Panel::Aggregated => vec![aggregated],
+        Panel::Centroid => vec![aggregated, left_chunks[2]],
+    };
+    f.render_widget(spotlight::Spotlight { keep: &keep }, f.size());
+
+    let text = tutorial::fill(step.text, &app.tutorial_values());
+    let title = format!(
+        " Tutorial {}/{}: {} ",
+        tutorial.step + 1,
+        tutorial::STEPS.len(),
+        step.title
+    );
+    let explanation = Paragraph::new(text)
+        .wrap(Wrap { trim: true })
+        .style(Style::default().fg(app.theme.text))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(app.theme.caution))
+                .title(title),
+        );
+    f.render_widget(Clear, history);
+    f.render_widget(explanation, history);
+}
+
+/// Color of a message of `severity`
+fn severity_style(theme: &Palette<Color>, severity: Severity) -> Style {
+    match severity {
+        Severity::Info => Style::default().fg(theme.caution),
+        Severity::Warning => Style::default().fg(theme.warning),
+        Severity::Error => Style::default()
+            .fg(theme.error)
+            .add_modifier(Modifier::BOLD),
+    }
+}
+
+/// Status bar text: an open prompt echoing what has been typed so far,
+/// else the most pressing message, else the mode's hint
+fn status_line(app: &App) -> (String, Style) {
+    let now = app.clock.now();
+    if app.input_mode.is_prompt() {
+        let text = format!("{} {}", app.prompt, app.input_buffer);
+        return (text, severity_style(&app.theme, Severity::Info));
+    }
+    let Some(message) = app.inbox.current(now) else {
+        let hint = match app.input_mode {
+            InputMode::Menu => IDLE_HINT.to_string(),
+            _ => app.prompt.clone(),
+        };
+        return (hint, Style::default().fg(app.theme.muted));
+    };
+    let mut text = message.text.clone();
+    if message.severity == Severity::Error {
+        text.push_str("  ('k' acknowledges)");
+    }
+    match app.inbox.waiting(now) {
+        0 => {}
+        waiting => text.push_str(&format!("  +{} more, 'M' lists them", waiting)),
+    }
+    (text, severity_style(&app.theme, message.severity))
+}
+
+/// Message popup over the middle of `area`: the messages kept, newest
+/// first from the scroll position, with their age and severity
+fn render_messages<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, app: &App, area: Rect) {
+    let width = area.width.saturating_sub(4).min(100);
+    let height = (app.inbox.len() as u16 + 2).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let now = app.clock.now();
+    let lines: Vec<Line> = app
+        .inbox
+        .newest_first()
+        .skip(app.messages_scroll)
+        .map(|message| {
+            let mark = match message.severity {
+                Severity::Info => "·",
+                Severity::Warning => "⚠",
+                Severity::Error if message.acknowledged => "✔",
+                Severity::Error => "✖",
+            };
+            Line::from(Span::styled(
+                format!("{:>7.1}s ago {} {}", now - message.time, mark, message.text),
+                severity_style(&app.theme, message.severity),
+            ))
+        })
+        .collect();
+    let list = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Messages, last {} (Esc closes)", inbox::SCROLLBACK)),
+    );
+    f.render_widget(Clear, popup);
+    f.render_widget(list, popup);
+}
+
+/// Complexity popup over the middle of `area`: the report `check` prints
+fn render_complexity<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    app: &App,
+    area: Rect,
+) {
+    let width = area.width.saturating_sub(4).min(70);
+    let height = (app.complexity.len() as u16 + 2).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let lines: Vec<Line> = app
+        .complexity
+        .iter()
+        .map(|l| Line::from(l.as_str()))
+        .collect();
+    let text = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Rule base complexity (Esc closes)"),
+    );
+    f.render_widget(Clear, popup);
+    f.render_widget(text, popup);
+}
+
+/// Processing chain popup over the middle of `area`: what `pipeline`
+/// prints, from the scrolled-to line
+fn render_chain<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, app: &App, area: Rect) {
+    let width = area.width.saturating_sub(4).min(90);
+    let height = (app.chain.len() as u16 + 2).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let lines: Vec<Line> = (app.chain.iter().skip(app.chain_scroll))
+        .map(|l| Line::from(l.as_str()))
+        .collect();
+    let text = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Processing chain (Esc closes)"),
+    );
+    f.render_widget(Clear, popup);
+    f.render_widget(text, popup);
+}
+
+/// Sources popup over the middle of `area`: each source of the fused
+/// inputs with its latest reading and status, excluded ones in the
+/// warning color
+fn render_sources<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, app: &App, area: Rect) {
+    let width = area.width.saturating_sub(4).min(60);
+    let height = (app.sources.len() as u16 + 2).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let routes = app.sources.iter().map(|s| s.route().chars().count());
+    let column = routes.max().unwrap_or(0);
+    let lines: Vec<Line> = app
+        .sources
+        .iter()
+        .map(|source| {
+            let value = match source.value.is_nan() {
+                true => "-".to_string(),
+                false => app.show(&source.input, source.value),
+            };
+            let style = match source.status.is_excluded() {
+                true => Style::default().fg(app.theme.warning),
+                false => Style::default(),
+            };
+            Line::from(Span::styled(
+                format!(
+                    "{:<column$}  {:>10}  {}",
+                    source.route(),
+                    value,
+                    source.status.describe()
+                ),
+                style,
+            ))
+        })
+        .collect();
+    let text = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Sources (Esc closes)"),
+    );
+    f.render_widget(Clear, popup);
+    f.render_widget(text, popup);
+}
+
+/// Palette popup over the middle of `area`: each palette with swatches of
+/// its set colors, its ramp and its status colors, at the terminal's depth
+fn render_palettes<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, app: &App, area: Rect) {
+    let width = area.width.saturating_sub(4).min(70);
+    let height = (PaletteName::ALL.len() as u16 * 2 + 2).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let swatch = |color: Color| Span::styled("██", Style::default().fg(color));
+    let mut lines = Vec::new();
+    for (i, name) in PaletteName::ALL.into_iter().enumerate() {
+        let shown = theme(name, app.depth);
+        let marker = if i == app.palette_choice {
+            "▶ "
+        } else {
+            "  "
+        };
+        let mut style = Style::default().add_modifier(Modifier::BOLD);
+        if name == app.palette {
+            style = style.fg(app.theme.accent);
+        }
+        lines.push(Line::from(vec![
+            Span::raw(marker),
+            Span::styled(name.name(), style),
+            Span::raw(format!(" · {}", name.describe())),
+        ]));
+        let mut spans = vec![Span::raw("    ")];
+        spans.extend(shown.sets.iter().copied().map(swatch));
+        spans.push(Span::raw(" "));
+        spans.extend(shown.ramp.iter().copied().map(swatch));
+        spans.push(Span::raw(" "));
+        let status = [shown.good, shown.caution, shown.warning, shown.error];
+        spans.extend(status.into_iter().map(swatch));
+        lines.push(Line::from(spans));
+    }
+    let text = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Palettes, {} (Esc closes)", app.depth.name())),
+    );
+    f.render_widget(Clear, popup);
+    f.render_widget(text, popup);
+}
+
+/// End of the input's gauge its last reading ran past, if it did
+fn overflow_edge(app: &App, input: &str) -> Option<gauge::Edge> {
+    let overflow = app.overflows.iter().find(|o| o.input == input)?;
+    Some(if overflow.high {
+        gauge::Edge::High
+    } else {
+        gauge::Edge::Low
+    })
+}
+
+/// Input gauge title, flagged while a failure injection is active on the
+/// input, with what its simulated sensor reports while noisy, the samples
+/// behind a daemon's windowed reading and what became of a reading
+/// outside the universe
+fn gauge_title(app: &App, input: &str, title: &str) -> Span<'static> {
+    // The sparkline under the gauge shows this stretch when zoomed, with
+    // its range and mean over the whole seconds in it
+    let mut title = match app.history_view {
+        Some(view) => format!("{} · {:.0}–{:.0}s ago", title, view.end, view.start),
+        None => title.to_string(),
+    };
+    let series = if input == "humidity" { 1 } else { 0 };
+    let summary = app
+        .history_window()
+        .and_then(|(from, to)| app.history_series[series].summary(from.ceil(), to.floor()));
+    if let Some(summary) = summary {
+        title.push_str(&format!(
+            " {}/{}/{}",
+            app.show(input, summary.min),
+            app.show(input, summary.mean()),
+            app.show(input, summary.max)
+        ));
+    }
+    let sensed = app
+        .sensed
+        .map(|(t, h)| if input == "humidity" { h } else { t });
+    if let (Some(noise), Some(sensed)) = (app.noise.get(input), sensed) {
+        title.push_str(&format!(" · sensed {:.1} (σ {})", sensed, noise.sigma));
+    }
+    if let Some(window) = app.windows.iter().find(|w| w.input == input) {
+        title.push_str(&format!(" · {}", window.describe()));
+    }
+    if let Some(overflow) = app.overflows.iter().find(|o| o.input == input) {
+        title.push_str(&format!(
+            " · {} {}, {}",
+            if overflow.high {
+                "▲ above"
+            } else {
+                "▼ below"
+            },
+            overflow.limit,
+            overflow.action()
+        ));
+    }
+    match app.injector.get(input) {
+        Some(injection) => Span::styled(
+            format!(
+                "{} ⚠ INJECTED {} ({:.0}s left)",
+                title,
+                injection.value.fault.label(),
+                (injection.until - app.clock.now()).max(0.0).ceil()
+            ),
+            Style::default()
+                .fg(app.theme.highlight)
+                .add_modifier(Modifier::BOLD),
+        ),
+        None => Span::raw(title),
+    }
+}
+
+/// Rows under each input gauge for its sparkline and regime strip, and
+/// the areas of the temperature, the humidity, the output and what is left
+/// (the objective)
+fn left_panel_layout(area: Rect) -> (u16, std::rc::Rc<[Rect]>) {
+    // Sparklines get two rows under each input gauge and a regime strip
+    // under them, and are the first thing dropped when the terminal is too
+    // short, the strip before the charts
+    let spark_rows = if area.height >= 5 + 5 + 7 + 2 * 3 {
+        3
+    } else if area.height >= 5 + 5 + 7 + 2 * 2 {
+        2
+    } else {
+        0
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(5 + spark_rows),
+            Constraint::Length(5 + spark_rows),
+            Constraint::Length(7),
+            Constraint::Min(0),
+        ])
+        .split(area);
+    (spark_rows, chunks)
+}
+
+fn render_left_panel<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    app: &App,
+    area: Rect,
+) {
+    let (spark_rows, chunks) = left_panel_layout(area);
+    let input_rows = |area: Rect| {
+        Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(5),
+                Constraint::Length(spark_rows.min(2)),
+                Constraint::Length(spark_rows.saturating_sub(2)),
+            ])
+            .split(area)
+    };
+    let temp_rows = input_rows(chunks[0]);
+    let hum_rows = input_rows(chunks[1]);
+
+    // Temperature gauge
+    let [cold, mild, hot] = app.theme.temperature;
+    let temp_color = if app.temperature < 20.0 {
+        cold
+    } else if app.temperature < 30.0 {
+        mild
+    } else {
+        hot
+    };
+
+    let temp_gauge = gauge::MarkerGauge::default()
+        .block(Block::default().borders(Borders::ALL).title(gauge_title(
+            app,
+            "temperature",
+            &app.unit("temperature").title("🌡️  Temperature"),
+        )))
+        .gauge_style(Style::default().fg(temp_color))
+        .ratio(app.temperature / 50.0)
+        .label(app.gauge_label("temperature", app.temperature, String::new()))
+        .overflow(overflow_edge(app, "temperature"));
+    f.render_widget(temp_gauge, temp_rows[0]);
+    // Zoomed sparklines show every computation in view, or its summaries
+    // when there are too many
+    let window = match app.history_view {
+        Some(_) => app.history_limit,
+        None => SPARKLINE_WINDOW,
+    };
+    let history = app.charted_history();
+    let temp_chart = render_value_axis(f, app, 0, temp_rows[1]);
+    let level = app.sparkline_level(temp_chart.width as usize * 2);
+    let bounds = app.axes[0].bounds();
+    let temps: Vec<f64> = history.iter().map(|e| e.temperature).collect();
+    let sensed_temps: Vec<Option<f64>> = history.iter().map(|e| e.sensed.map(|s| s.0)).collect();
+    if let (Some(level), Some((from, to))) = (level, app.history_window()) {
+        let buckets = app.history_series[0].buckets(level, from, to);
+        render_band(f, &buckets, (from, to), bounds, temp_chart, temp_color);
+    } else if sensed_temps.iter().any(Option::is_some) {
+        let series = (&temps[..], &sensed_temps[..]);
+        let colors = (temp_color, app.regime_color());
+        render_trajectories(f, &app.theme, series, window, bounds, temp_chart, colors);
+    } else {
+        let colors = (temp_color, app.regime_color());
+        render_sparkline(f, &temps, window, bounds, temp_chart, colors);
+    }
+    render_regime_strip(
+        f,
+        &app.theme,
+        history,
+        strip_under(temp_chart, temp_rows[2]),
+    );
+
+    // Humidity gauge
+    let [dry, humid, wet] = app.theme.humidity;
+    let hum_color = if app.humidity < 40.0 {
+        dry
+    } else if app.humidity < 70.0 {
+        humid
+    } else {
+        wet
+    };
+
+    let hum_gauge = gauge::MarkerGauge::default()
+        .block(Block::default().borders(Borders::ALL).title(gauge_title(
+            app,
+            "humidity",
+            &app.unit("humidity").title("💧 Humidity"),
+        )))
+        .gauge_style(Style::default().fg(hum_color))
+        .ratio(app.humidity / 100.0)
+        .label(app.gauge_label(
+            "humidity",
+            app.humidity,
+            match app.dew_point {
+                // A dew point is read in the temperature's unit
+                Some(dew_point) => format!(" (dew point {})", app.show("temperature", dew_point)),
+                None => String::new(),
+            },
+        ))
+        .overflow(overflow_edge(app, "humidity"));
+    f.render_widget(hum_gauge, hum_rows[0]);
+    let hum_chart = render_value_axis(f, app, 1, hum_rows[1]);
+    let bounds = app.axes[1].bounds();
+    let hums: Vec<f64> = history.iter().map(|e| e.humidity).collect();
+    let sensed_hums: Vec<Option<f64>> = history.iter().map(|e| e.sensed.map(|s| s.1)).collect();
+    if let (Some(level), Some((from, to))) = (level, app.history_window()) {
+        let buckets = app.history_series[1].buckets(level, from, to);
+        render_band(f, &buckets, (from, to), bounds, hum_chart, hum_color);
+    } else if sensed_hums.iter().any(Option::is_some) {
+        let series = (&hums[..], &sensed_hums[..]);
+        let colors = (hum_color, app.regime_color());
+        render_trajectories(f, &app.theme, series, window, bounds, hum_chart, colors);
+    } else {
+        let colors = (hum_color, app.regime_color());
+        render_sparkline(f, &hums, window, bounds, hum_chart, colors);
+    }
+    render_regime_strip(f, &app.theme, history, strip_under(hum_chart, hum_rows[2]));
+
+    // Fan speed output
+    let (status, fan_color) = app.status();
+    let output = app.controller.system.output();
+    let mut label = format!(" [{}]", status);
+    if app.cascade.is_none() && (app.output.value - app.fan_speed).abs() >= 0.05 {
+        label.push_str(&format!(" fuzzy {}", app.show(&output.name, app.fan_speed)));
+    }
+    if let Some(pending) = app.output.pending {
+        label.push_str(&format!(
+            " pending: {} in {:.0}s",
+            pending.band.label(),
+            pending.remaining_secs.ceil()
+        ));
+    }
+    for (var, value) in app.controller.system.outputs[1..]
+        .iter()
+        .zip(&app.other_outputs)
+    {
+        label.push_str(&format!(" · {} {}", var.name, app.show(&var.name, *value)));
+    }
+    // The score goes on the bar when its panel does not fit, and so does
+    // the shadow's output when its chart does not fit under it
+    let mut below = chunks[3];
+    if let Some(objective) = &app.objective {
+        if chunks[3].height >= OBJECTIVE_HEIGHT {
+            below.y += OBJECTIVE_HEIGHT;
+            below.height -= OBJECTIVE_HEIGHT;
+            let area = Rect {
+                height: OBJECTIVE_HEIGHT,
+                ..chunks[3]
+            };
+            render_objective(f, app, objective, area);
+        } else if let Some(score) = objective.current() {
+            label.push_str(&format!(" · objective {:.3}", score));
+        }
+    }
+    if let Some(shadow) = &app.shadow {
+        if below.height >= SHADOW_HEIGHT {
+            let area = Rect {
+                height: SHADOW_HEIGHT,
+                ..below
+            };
+            render_shadow(f, app, shadow, area);
+            below.y += SHADOW_HEIGHT;
+            below.height -= SHADOW_HEIGHT;
+        } else {
+            label.push_str(&format!(
+                " · shadow {}",
+                app.show(&output.name, shadow.output.value)
+            ));
+        }
+    }
+    if let Some(outlook) = &app.outlook {
+        let ahead = outlook.forecast.as_ref().and_then(|f| f.as_ref().ok());
+        if below.height >= FORECAST_HEIGHT {
+            let area = Rect {
+                height: FORECAST_HEIGHT,
+                ..below
+            };
+            render_forecast(f, app, outlook, area);
+        } else if let Some(step) = ahead.and_then(|f| f.steps.last()) {
+            label.push_str(&format!(
+                " · forecast {} in {:.0}s",
+                app.show(&output.name, step.output),
+                step.ahead
+            ));
+        }
+    }
+
+    // Where the selected rule alone would put the output: a dashed marker
+    // at full strength, a solid one at its current strength
+    let mut title = app.unit(&output.name).title("🌀 Fan Speed");
+    let mut markers = Vec::new();
+    let ratio = |v: f64| (v - output.min) / (output.max - output.min);
+    // In a cascade the bar is the actuator, and the fuzzy setpoint and the
+    // measurement it tracks are marked in the output's units
+    let mut fan_ratio = app.output.value / 100.0;
+    if let Some(cascade) = &app.cascade {
+        let config = cascade.config();
+        fan_ratio = (app.output.value - config.min) / (config.max - config.min);
+        title.push_str(&format!(
+            " · setpoint │ {:.0} · {} ┆ {:.0}",
+            app.fan_speed, config.measured, cascade.measured
+        ));
+        markers.push(gauge::Marker {
+            ratio: ratio(app.fan_speed),
+            symbol: "│",
+            color: app.theme.text,
+        });
+        markers.push(gauge::Marker {
+            ratio: ratio(cascade.measured),
+            symbol: "┆",
+            color: app.theme.measured,
+        });
+    }
+    // The band a limit cycle swings over, on the bar's scale
+    if let Some(swing) = app.oscillation.as_ref().and_then(Detector::oscillation) {
+        let bar = |v: f64| match &app.cascade {
+            Some(cascade) => {
+                let config = cascade.config();
+                (v - config.min) / (config.max - config.min)
+            }
+            None => v / 100.0,
+        };
+        title.push_str(&format!(
+            " · ⚠ oscillating ⟦{:.0}–{:.0}⟧",
+            swing.low, swing.high
+        ));
+        for (value, symbol) in [(swing.low, "⟦"), (swing.high, "⟧")] {
+            markers.push(gauge::Marker {
+                ratio: bar(value),
+                symbol,
+                color: app.theme.warning,
+            });
+        }
+    }
+    if let Some(budget) = app.budget.as_ref().filter(|b| b.degraded() > 0) {
+        title.push_str(&format!(" · ⏱ {}", budget.describe()));
+    }
+    if let Some((rule, full, now)) = app.rule_preview() {
+        title.push_str(&format!(
+            " · rule {}: ╎ full {}",
+            rule + 1,
+            app.show(&output.name, full)
+        ));
+        markers.push(gauge::Marker {
+            ratio: ratio(full),
+            symbol: "╎",
+            color: app.theme.highlight,
+        });
+        if let Some(now) = now {
+            title.push_str(&format!(" ┃ now {}", app.show(&output.name, now)));
+            markers.push(gauge::Marker {
+                ratio: ratio(now),
+                symbol: "┃",
+                color: app.theme.highlight_soft,
+            });
+        }
+    }
+
+    let fan_gauge = gauge::MarkerGauge::default()
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .gauge_style(Style::default().fg(fan_color).add_modifier(Modifier::BOLD))
+        .ratio(fan_ratio)
+        .label(app.gauge_label(&output.name, app.output.value, label))
+        .markers(markers);
+    f.render_widget(fan_gauge, chunks[2]);
+}
+
+/// The published output as a line and the shadow's as a dashed one, the
+/// latest at the right edge, with the comparison so far in the title
+fn render_shadow<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    app: &App,
+    shadow: &Shadow,
+    area: Rect,
+) {
+    let block = Block::default().borders(Borders::ALL).title(format!(
+        "👥 Shadow {}: {}",
+        shadow.path,
+        shadow.summary().describe()
+    ));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    if inner.height == 0 || inner.width < 2 {
+        return;
+    }
+    // Two Braille dots across each cell
+    let window = inner.width as usize * 2;
+    let history = app.charted_history();
+    let shown = &history[history.len().saturating_sub(window)..];
+    let offset = window - shown.len();
+    let live: Vec<(f64, f64)> = shown
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| ((offset + i) as f64, entry.output))
+        .collect();
+    // A cell of dots, then a cell of none
+    let dashed: Vec<(f64, f64)> = shown
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| (offset + i) % 4 < 2)
+        .filter_map(|(i, entry)| Some(((offset + i) as f64, entry.shadow?)))
+        .collect();
+    let output = app.controller.system.output();
+    let datasets = vec![
+        Dataset::default()
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(app.status().1))
+            .data(&live),
+        Dataset::default()
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(app.theme.text))
+            .data(&dashed),
+    ];
+    let chart = Chart::new(datasets)
+        .x_axis(Axis::default().bounds([0.0, (window - 1) as f64]))
+        .y_axis(Axis::default().bounds([output.min, output.max]));
+    f.render_widget(chart, inner);
+}
+
+/// The output of the last `horizon_secs` as a line and the forecast's
+/// path over the next as a dashed one, the cone around it dimmed, on a
+/// time axis centered on now. The title says how naive the forecast is
+fn render_forecast<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    app: &App,
+    outlook: &Outlook,
+    area: Rect,
+) {
+    let config = &outlook.system.forecast;
+    let mut title = format!(
+        "🔮 Naive forecast: inputs {} over {}s",
+        match config.extrapolation {
+            forecast::Extrapolation::Linear => "extrapolated linearly",
+            forecast::Extrapolation::Hold => "held",
+        },
+        config.horizon_secs
+    );
+    match &outlook.forecast {
+        Some(Ok(forecast)) => {
+            if let Some(step) = forecast.steps.last() {
+                title.push_str(&format!(
+                    " · {}",
+                    app.show(&outlook.system.output().name, step.output)
+                ));
+            }
+        }
+        Some(Err(e)) => title.push_str(&format!(" · {}", e)),
+        None => title.push_str(" · …"),
+    }
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    if inner.height == 0 || inner.width < 2 {
+        return;
+    }
+    let now = app.clock.now();
+    let horizon = config.horizon_secs;
+    let past: Vec<(f64, f64)> = (app.history.iter())
+        .map(|entry| (entry.time - now, entry.fan_speed))
+        .filter(|(x, _)| *x >= -horizon)
+        .collect();
+    // Two Braille dots across each cell, half of them ahead of now
+    let dots = inner.width as usize;
+    let (mut path, mut cone) = (Vec::new(), Vec::new());
+    if let Some(Ok(forecast)) = &outlook.forecast {
+        let shift = forecast.made_at - now;
+        // The path from now, where it starts at the output, on
+        let mut points = vec![(shift, app.fan_speed, app.fan_speed, app.fan_speed)];
+        points.extend((forecast.steps.iter()).map(|s| (shift + s.ahead, s.output, s.low, s.high)));
+        let output = outlook.system.output();
+        let rows = inner.height as usize * 4;
+        for dot in 0..dots {
+            let x = horizon * dot as f64 / (dots - 1) as f64;
+            let Some(pair) = points.windows(2).find(|p| p[0].0 <= x && x <= p[1].0) else {
+                continue;
+            };
+            let t = match pair[1].0 > pair[0].0 {
+                true => (x - pair[0].0) / (pair[1].0 - pair[0].0),
+                false => 0.0,
+            };
+            let at = |a: f64, b: f64| a + (b - a) * t;
+            let (value, low, high) = (
+                at(pair[0].1, pair[1].1),
+                at(pair[0].2, pair[1].2),
+                at(pair[0].3, pair[1].3),
+            );
+            // A cell of dots, then a cell of none
+            if dot % 4 < 2 {
+                path.push((x, value));
+            }
+            // Every dot row between the cone's edges
+            let step = (output.max - output.min) / rows as f64;
+            let mut y = low;
+            while step > 0.0 && y <= high {
+                cone.push((x, y));
+                y += step;
+            }
+        }
+    }
+    let output = outlook.system.output();
+    let datasets = vec![
+        Dataset::default()
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(app.theme.muted))
+            .data(&cone),
+        Dataset::default()
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(app.status().1))
+            .data(&past),
+        Dataset::default()
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(app.theme.text))
+            .data(&path),
+    ];
+    let chart = Chart::new(datasets)
+        .x_axis(Axis::default().bounds([-horizon, horizon]))
+        .y_axis(Axis::default().bounds([output.min, output.max]));
+    f.render_widget(chart, inner);
+}
+
+/// The objective's current score on a bar from the worst score so far
+/// (empty) to the best (full), and the two records under it
+fn render_objective<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    app: &App,
+    objective: &Objective,
+    area: Rect,
+) {
+    let config = objective.config();
+    let block = Block::default().borders(Borders::ALL).title(format!(
+        "🎯 Objective ({} is better): {}",
+        config.better.name(),
+        config.score.source()
+    ));
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1); 3])
+        .split(block.inner(area));
+    f.render_widget(block, area);
+
+    let mut label = match objective.current() {
+        Some(score) => format!("{:.3}", score),
+        None => "-".to_string(),
+    };
+    if let Some(mean) = objective.mean() {
+        label.push_str(&format!(" · mean {:.3} over {}", mean, objective.count()));
+    }
+    let gauge = gauge::MarkerGauge::default()
+        .gauge_style(Style::default().fg(app.theme.good))
+        .ratio(objective.ratio().unwrap_or(0.0))
+        .label(label);
+    f.render_widget(gauge, rows[0]);
+
+    let system = &app.controller.system;
+    let record = |name: &str, mark: Option<&Mark>| match mark {
+        Some(mark) => {
+            let inputs: Vec<String> = system
+                .inputs
+                .iter()
+                .zip(&mark.inputs)
+                .map(|(var, value)| format!("{} {}", var.name, app.show(&var.name, *value)))
+                .collect();
+            format!(
+                "{:<5} {:.3} at {} · {}",
+                name,
+                mark.score,
+                system.timezone.short(mark.stamp),
+                inputs.join(" · ")
+            )
+        }
+        None => format!("{:<5} -", name),
+    };
+    f.render_widget(Paragraph::new(record("best", objective.best())), rows[1]);
+    f.render_widget(Paragraph::new(record("worst", objective.worst())), rows[2]);
+}
+
+/// Reduce `values` to at most `width` points, keeping each bucket's maximum
+/// so short spikes stay visible
+fn downsample_max(values: &[f64], width: usize) -> Vec<f64> {
+    if values.len() <= width || width == 0 {
+        return values.to_vec();
+    }
+    (0..width)
+        .map(|i| {
+            values[i * values.len() / width..(i + 1) * values.len() / width]
+                .iter()
+                .copied()
+                .fold(f64::NEG_INFINITY, f64::max)
+        })
+        .collect()
+}
+
+/// The range of input chart `i` as labels left of `area`, marked with its
+/// mode; the rest of `area` is left for the chart
+fn render_value_axis<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    app: &App,
+    i: usize,
+    area: Rect,
+) -> Rect {
+    let name = ["temperature", "humidity"][i];
+    let (lo, hi) = app.axes[i].bounds();
+    let (top, bottom) = app.range_modes[i].marks();
+    let labels = [
+        format!("{}{}", top, app.show(name, hi)),
+        format!("{}{}", bottom, app.show(name, lo)),
+    ];
+    let width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0) as u16 + 1;
+    // Too little room for labels and a chart: the chart alone
+    if area.height < 2 || area.width < width + 10 {
+        return area;
+    }
+    let lines: Vec<Line> = labels
+        .iter()
+        .map(|label| Line::from(format!("{:>1$}", label, width as usize - 1)))
+        .collect();
+    let axis = Rect {
+        width: width - 1,
+        ..area
+    };
+    let text = Paragraph::new(lines).style(Style::default().fg(app.theme.faint));
+    f.render_widget(text, axis);
+    Rect {
+        x: area.x + width,
+        width: area.width - width,
+        ..area
+    }
+}
+
+/// The row `strip` below `chart`, as wide as it
+fn strip_under(chart: Rect, strip: Rect) -> Rect {
+    Rect {
+        x: chart.x,
+        width: chart.width,
+        ..strip
+    }
+}
+
+/// The dominant rule of each of `entries` as a row of colored cells, the
+/// latest in the last column like the sparklines; with more entries than
+/// columns a column shows the last entry it covers
+fn render_regime_strip<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    theme: &Palette<Color>,
+    entries: &[HistoryEntry],
+    area: Rect,
+) {
+    let Some((current, previous)) = entries.split_last() else {
+        return;
+    };
+    if area.height == 0 || area.width < 2 {
+        return;
+    }
+    let columns = area.width as usize - 1;
+    let shown: Vec<Option<usize>> = if previous.len() <= columns {
+        previous.iter().map(|e| e.dominant).collect()
+    } else {
+        (1..=columns)
+            .map(|i| previous[i * previous.len() / columns - 1].dominant)
+            .collect()
+    };
+    let cell = |dominant: Option<usize>| match dominant {
+        Some(_) => Span::styled(
+            "▀",
+            Style::default().fg(rulecolor::of_dominant(theme, dominant)),
+        ),
+        None => Span::raw(" "),
+    };
+    let mut spans = vec![Span::raw(" ".repeat(columns - shown.len()))];
+    spans.extend(shown.into_iter().map(cell));
+    spans.push(cell(current.dominant));
+    f.render_widget(Paragraph::new(Line::from(spans)), area);
+}
+
+/// Sparkline of the last `window` values scaled to the axis `bounds`, with
+/// the current value drawn as a last bar in `marker`
+fn render_sparkline<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    values: &[f64],
+    window: usize,
+    (min, max): (f64, f64),
+    area: Rect,
+    (color, marker): (Color, Color),
+) {
+    let Some((current, previous)) = values.split_last() else {
+        return;
+    };
+    if area.height == 0 || area.width < 2 {
+        return;
+    }
+
+    let start = previous.len().saturating_sub(window - 1);
+    let scale = |v: f64| {
+        if max > min {
+            // Keep the axis minimum visible as a thin bar, and values past
+            // the axis at its edges
+            10 + (((v - min) / (max - min)).clamp(0.0, 1.0) * 90.0).round() as u64
+        } else {
+            50
+        }
+    };
+
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(area);
+    let data: Vec<u64> = downsample_max(&previous[start..], chunks[0].width as usize)
+        .into_iter()
+        .map(scale)
+        .collect();
+
+    // Right-align the history so it runs into the current value
+    let width = (data.len() as u16).min(chunks[0].width);
+    let history_area = Rect {
+        x: chunks[0].right() - width,
+        width,
+        ..chunks[0]
+    };
+    let history = Sparkline::default()
+        .data(&data)
+        .max(100)
+        .style(Style::default().fg(color).add_modifier(Modifier::DIM));
+    f.render_widget(history, history_area);
+
+    let latest = [scale(*current)];
+    let current = Sparkline::default()
+        .data(&latest)
+        .max(100)
+        .style(Style::default().fg(marker).add_modifier(Modifier::BOLD));
+    f.render_widget(current, chunks[1]);
+}
+
+/// Summaries of a long stretch of history: the mean of each bucket as a
+/// line inside a dim band from its min to its max
+fn render_band<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    buckets: &[lod::Bucket],
+    (from, to): (f64, f64),
+    (min, max): (f64, f64),
+    area: Rect,
+    color: Color,
+) {
+    if area.height == 0 || area.width < 2 || buckets.is_empty() {
+        return;
+    }
+    let line = |value: fn(&lod::Bucket) -> f64| -> Vec<(f64, f64)> {
+        buckets.iter().map(|b| (b.start, value(b))).collect()
+    };
+    let (mins, means, maxes) = (line(|b| b.min), line(lod::Bucket::mean), line(|b| b.max));
+    let edge = |data| {
+        Dataset::default()
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(color).add_modifier(Modifier::DIM))
+            .data(data)
+    };
+    let datasets = vec![
+        edge(&mins),
+        edge(&maxes),
+        Dataset::default()
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(color))
+            .data(&means),
+    ];
+    let chart = Chart::new(datasets)
+        .x_axis(Axis::default().bounds([from, to]))
+        .y_axis(Axis::default().bounds([min, max]));
+    f.render_widget(chart, area);
+}
+
+/// The last `window` true values as a line with what the simulated sensor
+/// reported as points, both on the axis `bounds`, and the latest true value
+/// as a point in `marker`
+fn render_trajectories<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    theme: &Palette<Color>,
+    (truth, sensed): (&[f64], &[Option<f64>]),
+    window: usize,
+    (min, max): (f64, f64),
+    area: Rect,
+    (color, marker): (Color, Color),
+) {
+    if area.height == 0 || area.width < 2 {
+        return;
+    }
+    let start = truth.len().saturating_sub(window);
+    // Right-aligned like the sparklines, so the latest value is at the edge
+    let offset = window.saturating_sub(truth.len() - start);
+    let points = |values: Vec<Option<f64>>| -> Vec<(f64, f64)> {
+        values
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, v)| Some(((offset + i) as f64, v.filter(|v| v.is_finite())?)))
+            .collect()
+    };
+    let truth = points(truth[start..].iter().copied().map(Some).collect());
+    let sensed = points(sensed[start..].to_vec());
+    let latest: Vec<(f64, f64)> = truth.last().copied().into_iter().collect();
+    let datasets = vec![
+        Dataset::default()
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(color))
+            .data(&truth),
+        Dataset::default()
+            .marker(symbols::Marker::Dot)
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(theme.text))
+            .data(&sensed),
+        Dataset::default()
+            .marker(symbols::Marker::Block)
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(marker))
+            .data(&latest),
+    ];
+    let chart = Chart::new(datasets)
+        .x_axis(Axis::default().bounds([0.0, (window.max(2) - 1) as f64]))
+        .y_axis(Axis::default().bounds([min, max]));
+    f.render_widget(chart, area);
+}
+
+/// How the surface view ('v') draws the output over the first two inputs
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SurfaceStyle {
+    /// Colored cells
+    Heatmap,
+    /// Characters of `surface::RAMP`, for terminals without color
+    Ascii,
+}
+
+impl SurfaceStyle {
+    /// Text when NO_COLOR is set or the terminal reports fewer than 8
+    /// colors
+    fn detect() -> Self {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        if no_color || crossterm::style::available_color_count() < 8 {
+            SurfaceStyle::Ascii
+        } else {
+            SurfaceStyle::Heatmap
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            SurfaceStyle::Heatmap => SurfaceStyle::Ascii,
+            SurfaceStyle::Ascii => SurfaceStyle::Heatmap,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SurfaceStyle::Heatmap => "heatmap",
+            SurfaceStyle::Ascii => "ascii",
+        }
+    }
+}
+
+/// What the terminal draws for `tone`
+fn color(tone: Tone) -> Color {
+    match tone {
+        Tone::Ansi(ansi) => match ansi {
+            Ansi::Black => Color::Black,
+            Ansi::Red => Color::Red,
+            Ansi::Green => Color::Green,
+            Ansi::Yellow => Color::Yellow,
+            Ansi::Blue => Color::Blue,
+            Ansi::Magenta => Color::Magenta,
+            Ansi::Cyan => Color::Cyan,
+            Ansi::Gray => Color::Gray,
+            Ansi::DarkGray => Color::DarkGray,
+            Ansi::LightRed => Color::LightRed,
+            Ansi::LightGreen => Color::LightGreen,
+            Ansi::LightYellow => Color::LightYellow,
+            Ansi::LightBlue => Color::LightBlue,
+            Ansi::LightMagenta => Color::LightMagenta,
+            Ansi::LightCyan => Color::LightCyan,
+            Ansi::White => Color::White,
+        },
+        Tone::Indexed(index) => Color::Indexed(index),
+        Tone::Rgb(r, g, b, _) => Color::Rgb(r, g, b),
+    }
+}
+
+/// The colors of palette `name` on a terminal of `depth`
+fn theme(name: PaletteName, depth: Depth) -> Palette<Color> {
+    Palette::of(name).quantized(depth).map(color)
+}
+
+/// How membership degrees map to bar heights in the membership charts
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BarScale {
+    Linear,
+    /// Stretches small memberships so weak activations stay visible
+    Sqrt,
+    Log,
+}
+
+impl BarScale {
+    fn next(self) -> Self {
+        match self {
+            BarScale::Linear => BarScale::Sqrt,
+            BarScale::Sqrt => BarScale::Log,
+            BarScale::Log => BarScale::Linear,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            BarScale::Linear => "linear",
+            BarScale::Sqrt => "sqrt",
+            BarScale::Log => "log",
+        }
+    }
+
+    /// Bar height in 0..=1 for a membership degree
+    fn apply(self, membership: f64) -> f64 {
+        match self {
+            BarScale::Linear => membership,
+            BarScale::Sqrt => membership.sqrt(),
+            BarScale::Log => (1.0 + 999.0 * membership).log10() / 3.0,
+        }
+    }
+}
+
+/// Membership as shown on its bar: two decimals, three below 0.01 so weak
+/// activations are not shown as zero
+fn membership_text(membership: f64) -> String {
+    if membership >= 0.01 {
+        format!("{:.2}", membership)
+    } else if membership >= 0.0005 {
+        format!("{:.3}", membership)
+    } else {
+        "<.001".to_string()
+    }
+}
+
+fn render_membership_chart<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    theme: &Palette<Color>,
+    title: &str,
+    sets: &[FuzzySet],
+    color: impl Fn(&str) -> Color,
+    scale: BarScale,
+    area: Rect,
+) {
+    const FULL: u64 = 1000;
+    // A nonzero membership always fills at least the bottom row
+    let one_row = FULL.div_ceil(area.height.saturating_sub(2).max(1) as u64);
+
+    let bars: Vec<Bar> = sets
+        .iter()
+        .map(|set| {
+            let color = color(&set.name);
+            let mut value = (scale.apply(set.membership.clamp(0.0, 1.0)) * FULL as f64) as u64;
+            if set.membership > 0.0 {
+                value = value.max(one_row);
+            }
+            Bar::default()
+                .value(value)
+                .text_value(membership_text(set.membership))
+                .style(Style::default().fg(color))
+                // The value sits on the bottom row, drawn in the bar color
+                .value_style(Style::default().fg(theme.ink).bg(color))
+        })
+        .collect();
+
+    let title = match scale {
+        BarScale::Linear => title.to_string(),
+        other => format!("{} ({} scale)", title, other.label()),
+    };
+    let chart = BarChart::default()
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .data(BarGroup::default().bars(&bars))
+        .max(FULL)
+        .bar_width(8)
+        .bar_gap(2)
+        .value_style(Style::default().fg(theme.text).add_modifier(Modifier::BOLD))
+        .label_style(Style::default().fg(theme.text));
+    f.render_widget(chart, area);
+}
+
+/// Areas of the temperature curves, the humidity curves and the
+/// aggregated output in the right panel
+fn curves_layout(area: Rect) -> std::rc::Rc<[Rect]> {
+    Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(35),
+            Constraint::Percentage(35),
+            Constraint::Percentage(30),
+        ])
+        .split(area)
+}
+
+fn render_right_panel<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    app: &App,
+    area: Rect,
+) {
+    if app.show_rules {
+        render_rule_table(f, app, area);
+        return;
+    }
+    if app.show_matrix {
+        render_matrix(f, app, area);
+        return;
+    }
+    if app.show_surface {
+        render_surface(f, app, area);
+        return;
+    }
+    if app.show_scatter {
+        render_scatter(f, app, area);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+
+    let system = &app.controller.system;
+    if app.show_curves {
+        let chunks = curves_layout(area);
+        let charts = [
+            ("temperature", "Temperature Fuzzy Sets", app.temperature),
+            ("humidity", "Humidity Fuzzy Sets", app.humidity),
+        ];
+        for (i, (name, title, value)) in charts.into_iter().enumerate() {
+            if let Some(var) = system.input(name) {
+                let view = app.curve_views[i];
+                let marker = (value, app.regime_color());
+                render_membership_curves(f, &app.theme, title, var, marker, view, chunks[i]);
+            }
+        }
+        render_aggregated_output(f, app, chunks[2]);
+        return;
+    }
+
+    // Temperature memberships
+    let temp_sets = system
+        .input("temperature")
+        .map(|var| var.fuzzify(app.temperature))
+        .unwrap_or_default();
+    render_membership_chart(
+        f,
+        &app.theme,
+        "Temperature Fuzzy Sets",
+        &temp_sets,
+        |name| match name {
+            "Cold" => app.theme.temperature[0],
+            "Mild" => app.theme.temperature[1],
+            "Hot" => app.theme.temperature[2],
+            _ => app.theme.text,
+        },
+        app.bar_scale,
+        chunks[0],
+    );
+
+    // Humidity memberships
+    let hum_sets = system
+        .input("humidity")
+        .map(|var| var.fuzzify(app.humidity))
+        .unwrap_or_default();
+    render_membership_chart(
+        f,
+        &app.theme,
+        "Humidity Fuzzy Sets",
+        &hum_sets,
+        |name| match name {
+            "Low" => app.theme.humidity[0],
+            "Medium" => app.theme.humidity[1],
+            "High" => app.theme.humidity[2],
+            _ => app.theme.text,
+        },
+        app.bar_scale,
+        chunks[1],
+    );
+}
+
+/// Step table fitted to the surface of `system`, drawn over the surface
+/// view
+struct StepTable {
+    system: FuzzySystem,
+    table: quantize::Table,
+    fit: quantize::Fit,
+}
+
+/// What the surface view's grid is computed for: the system and the
+/// panel's columns and rows
+type SurfaceKey = (FuzzySystem, usize, usize);
+
+/// Output over the first two inputs at the panel's resolution, with the
+/// current operating point marked 'X', on the dominant rule's color in the
+/// heatmap. A coarse grid is drawn at once and finer ones replace it as
+/// the worker has them (refine.rs), the grid size in the title; an edit
+/// that only moves set shapes patches the finest grid instead. The marker
+/// is placed on whichever is shown. A step table ('S') is drawn in the
+/// surface's place, its cells marked '+' or '-' where they run more than a
+/// tenth of the output range above or below the surface and '!' where they
+/// are furthest from it, its fit under the legend.
+fn render_surface<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, app: &App, area: Rect) {
+    let system = &app.controller.system;
+    let columns = area.width.saturating_sub(10).max(2) as usize;
+    let rows = area.height.saturating_sub(7).max(2) as usize;
+    let mut ladder: Vec<usize> = SURFACE_LADDER
+        .iter()
+        .map(|size| (*size).min(columns.max(rows)))
+        .collect();
+    ladder.dedup();
+    let mut refiner = app.surface.borrow_mut();
+    refiner.request_patched(
+        (system.clone(), columns, rows),
+        &ladder,
+        |(system, columns, rows): &SurfaceKey, size, token| {
+            let controllers = [FuzzyController::from_system(system.clone())];
+            let (columns, rows) = (size.min(*columns), size.min(*rows));
+            surface::compute_surfaces_until(&controllers, columns, rows, &|| token.is_cancelled())
+                .map(|mut surfaces| surfaces.remove(0))
+        },
+        // A moved set parameter recomputes only the cells it reaches
+        |(old, old_columns, old_rows), (new, columns, rows), shown| {
+            ((old_columns, old_rows) == (columns, rows))
+                .then(|| surface::update(shown, old, new))
+                .flatten()
+        },
+    );
+    let (Some(computed), Some((size, refining))) = (refiner.current(), refiner.level()) else {
+        return;
+    };
+    let grid = computed.resample(columns, rows);
+    let (values, _) = adapters::convert(&system.inputs, &app.input_values());
+    let point = (values[0], values.get(1).copied().unwrap_or(0.0));
+    let step = app.step_table.as_ref();
+    let stepped = step.map(|step| step.table.sampled(&grid));
+    let shown = stepped.as_ref().unwrap_or(&grid);
+    let outline = (system.comfort.as_ref())
+        .filter(|comfort| comfort.show)
+        .map(|comfort| comfort.zone.outline(&grid.xs, &grid.ys));
+    let on_outline = |row: usize, column: usize| outline.as_ref().is_some_and(|o| o[row][column]);
+    let mut lines = surface::render_ascii(shown, system, Some(point));
+    if outline.is_some() {
+        // Grid rows follow the y-axis title, highest y first
+        for (i, line) in lines.iter_mut().enumerate().skip(1).take(rows) {
+            let row = rows - i;
+            let Some((label, cells)) = line.split_once('|') else {
+                continue;
+            };
+            let cells: String = (cells.chars().enumerate())
+                .map(|(column, c)| match c != 'X' && on_outline(row, column) {
+                    true => 'o',
+                    false => c,
+                })
+                .collect();
+            *line = format!("{}|{}", label, cells);
+        }
+    }
+    if let Some(step) = step {
+        lines.push(format!(
+            "{}x{} step table{}: {}",
+            step.table.columns.len(),
+            step.table.rows.len(),
+            match step.system == *system {
+                true => "",
+                false => " (stale, 'S' refits)",
+            },
+            step.fit.summary()
+        ));
+    }
+
+    let text: Vec<Line> = match app.surface_style {
+        SurfaceStyle::Ascii => lines.into_iter().map(Line::from).collect(),
+        SurfaceStyle::Heatmap => {
+            let colors = app.theme.ramp;
+            let output = system.output();
+            let marked = surface::cell_of(&grid, point);
+            let worst = step.map(|step| surface::cell_of(&grid, step.fit.at));
+            let tenth = (output.max - output.min) / 10.0;
+            let marker = Style::default()
+                .fg(app.theme.ink)
+                .bg(app.regime_color())
+                .add_modifier(Modifier::BOLD);
+            lines
+                .into_iter()
+                .enumerate()
+                .map(|(i, line)| {
+                    // Grid rows follow the y-axis title, highest y first
+                    let Some(row) = i.checked_sub(1).filter(|r| *r < rows).map(|r| rows - 1 - r)
+                    else {
+                        return Line::from(line);
+                    };
+                    let (label, _) = line.split_at(line.find('|').unwrap_or(0) + 1);
+                    let mut spans = vec![Span::raw(label.to_string())];
+                    spans.extend(shown.values[row].iter().enumerate().map(|(column, value)| {
+                        let share =
+                            ((value - output.min) / (output.max - output.min)).clamp(0.0, 1.0);
+                        let color = colors[(share * (colors.len() - 1) as f64).round() as usize];
+                        if (row, column) == marked {
+                            return Span::styled("X", marker);
+                        }
+                        let deviation = value - grid.values[row][column];
+                        let glyph = match step {
+                            None if on_outline(row, column) => "o",
+                            None => " ",
+                            Some(_) if worst == Some((row, column)) => "!",
+                            Some(_) if deviation > tenth => "+",
+                            Some(_) if deviation < -tenth => "-",
+                            Some(_) if on_outline(row, column) => "o",
+                            Some(_) => " ",
+                        };
+                        Span::styled(glyph, Style::default().fg(app.theme.text).bg(color))
+                    }));
+                    Line::from(spans)
+                })
+                .collect()
+        }
+    };
+    let title = format!(
+        "Control Surface ({}, 'V' switches) {}x{}{}",
+        app.surface_style.label(),
+        size.min(columns),
+        size.min(rows),
+        if refining { "…" } else { "" }
+    );
+    let paragraph = Paragraph::new(text).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(paragraph, area);
+}
+
+/// The history plotted over temperature and humidity, the current reading
+/// in the regime's color, inside the comfort zone's outline when one is
+/// configured and shown
+fn render_scatter<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, app: &App, area: Rect) {
+    let system = &app.controller.system;
+    let (Some(temperature), Some(humidity)) =
+        (system.input("temperature"), system.input("humidity"))
+    else {
+        return;
+    };
+    let points: Vec<(f64, f64)> = app
+        .history
+        .iter()
+        .map(|entry| (entry.temperature, entry.humidity))
+        .collect();
+    let current = [(app.temperature, app.humidity)];
+    let zone: Vec<(f64, f64)> = match system.comfort.as_ref().filter(|c| c.show) {
+        Some(comfort) => {
+            let vertices = comfort.zone.vertices();
+            vertices.iter().chain(vertices.first()).copied().collect()
+        }
+        None => Vec::new(),
+    };
+    let datasets = vec![
+        Dataset::default()
+            .name("comfort zone")
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(app.theme.accent))
+            .data(&zone),
+        Dataset::default()
+            .marker(symbols::Marker::Dot)
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(app.theme.muted))
+            .data(&points),
+        Dataset::default()
+            .marker(symbols::Marker::Block)
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(app.regime_color()))
+            .data(&current),
+    ];
+    let title = match app.comfort_share() {
+        Some(share) => format!("Inputs ('i' hides) · {}", share),
+        None => "Inputs ('i' hides)".to_string(),
+    };
+    let axis = |var: &FuzzyVariable| {
+        Axis::default()
+            .title(app.unit(&var.name).title(&var.name))
+            .bounds([var.min, var.max])
+            .labels(vec![
+                Span::raw(app.show(&var.name, var.min)),
+                Span::raw(app.show(&var.name, var.max)),
+            ])
+            .style(Style::default().fg(app.theme.muted))
+    };
+    let chart = Chart::new(datasets)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .x_axis(axis(temperature))
+        .y_axis(axis(humidity));
+    f.render_widget(chart, area);
+}
+
+/// The rule matrix of the first two inputs with the cell of their dominant
+/// sets highlighted
+fn render_matrix<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, app: &App, area: Rect) {
+    let system = &app.controller.system;
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Rule Matrix ('m' closes)");
+    let matrix = match matrix::build(system, &matrix::Axes::default()) {
+        Ok(matrix) => matrix,
+        Err(e) => {
+            f.render_widget(Paragraph::new(e).block(block), area);
+            return;
+        }
+    };
+    let (values, _) = adapters::convert(&system.inputs, &app.input_values());
+    let dominant = matrix.dominant(system, &values);
+    let table = matrix.table("-");
+    let widths: Vec<Constraint> = matrix::Matrix::widths(&table)
+        .into_iter()
+        .map(|w| Constraint::Length(w as u16))
+        .collect();
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    let mut lines = table.into_iter();
+    let header = Row::new(lines.next().unwrap_or_default()).style(bold);
+    let rows: Vec<Row> = lines
+        .enumerate()
+        .map(|(r, row)| {
+            Row::new(row.into_iter().enumerate().map(|(c, text)| {
+                let style = match c {
+                    0 => bold,
+                    _ if dominant == Some((r, c - 1)) => Style::default()
+                        .fg(app.theme.good)
+                        .add_modifier(Modifier::BOLD | Modifier::REVERSED),
+                    _ if text == "-" => Style::default().fg(app.theme.faint),
+                    _ => Style::default(),
+                };
+                Cell::from(text).style(style)
+            }))
+        })
+        .collect();
+    let table = Table::new(rows)
+        .header(header)
+        .block(block)
+        .widths(&widths)
+        .column_spacing(2);
+    f.render_widget(table, area);
+}
+
+/// The whole error behind a safe mode, over the middle of `area`
+fn render_safe_mode_error<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    theme: &Palette<Color>,
+    error: &str,
+    area: Rect,
+) {
+    let width = area.width.saturating_sub(4).min(90);
+    let height = area.height.saturating_sub(2).min(12);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let text = Paragraph::new(error.to_string())
+        .style(Style::default().fg(theme.error))
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("⚠ SAFE MODE ('E' or Esc closes)"),
+        );
+    f.render_widget(Clear, popup);
+    f.render_widget(text, popup);
+}
+
+/// Diff popup over the middle of `area`: one line per change since the
+/// system was loaded, then the output deviation they make
+fn render_review<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    theme: &Palette<Color>,
+    review: &Review,
+    area: Rect,
+) {
+    let width = area.width.saturating_sub(4).min(90);
+    let height = (review.changes.len() as u16 + 5).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let mut lines: Vec<Line> = review
+        .changes
+        .iter()
+        .map(|change| {
+            let color = match change.chars().next() {
+                Some('+') => theme.good,
+                Some('-') => theme.error,
+                Some('~') => theme.caution,
+                _ => theme.muted,
+            };
+            Line::from(Span::styled(change.clone(), Style::default().fg(color)))
+        })
+        .collect();
+    lines.push(Line::from(""));
+    lines.push(Line::from(match &review.deviation {
+        None => "Output deviation: sampling…".to_string(),
+        Some(Ok((max, mean))) => format!(
+            "Output deviation, {} points per input: max {:.4}, mean {:.4}",
+            diff::DEVIATION_POINTS,
+            max,
+            mean
+        ),
+        Some(Err(e)) => format!("Output deviation: {}", e),
+    }));
+    let text = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("✎ Changes since load ('R' reverts all, Esc closes)"),
+    );
+    f.render_widget(Clear, popup);
+    f.render_widget(text, popup);
+}
+
+/// Bookmark popup over the middle of `area`: each bookmark's inputs, the
+/// outputs recorded with it and the current ones, flagged when they drifted
+fn render_bookmarks<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    app: &App,
+    area: Rect,
+) {
+    let width = area.width.saturating_sub(4).min(90);
+    let height = (app.bookmarks.len() as u16 + 3).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let numbers = |values: &[f64]| {
+        values
+            .iter()
+            .map(|v| format!("{:.1}", v))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let rows: Vec<Row> = app
+        .bookmarks
+        .list()
+        .iter()
+        .enumerate()
+        .map(|(i, bookmark)| {
+            let comparison = bookmark.compare(&app.controller);
+            let current = comparison
+                .current
+                .as_deref()
+                .map_or("-".to_string(), numbers);
+            let flag = if comparison.drifted { "⚠ drift" } else { "" };
+            let style = if i == app.selected_bookmark {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Row::new(vec![
+                Cell::from(bookmark.name.clone()),
+                Cell::from(numbers(&bookmark.inputs)),
+                Cell::from(numbers(&bookmark.outputs)),
+                Cell::from(current),
+                Cell::from(flag).style(Style::default().fg(app.theme.error)),
+            ])
+            .style(style)
+        })
+        .collect();
+    let header = Row::new(vec!["Name", "Inputs", "Recorded", "Now", ""])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    let widths = [
+        Constraint::Percentage(30),
+        Constraint::Percentage(25),
+        Constraint::Percentage(15),
+        Constraint::Percentage(15),
+        Constraint::Percentage(15),
+    ];
+    let table = Table::new(rows)
+        .header(header)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("🔖 Bookmarks (Esc closes)"),
+        )
+        .widths(&widths)
+        .column_spacing(1);
+    f.render_widget(Clear, popup);
+    f.render_widget(table, popup);
+}
+
+/// Annotation popup over the middle of `area`, newest first: each note,
+/// when it was saved and the primary output then, flagged when another
+/// config made it
+fn render_annotations<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    app: &App,
+    area: Rect,
+) {
+    let width = area.width.saturating_sub(4).min(90);
+    let height = (app.annotations.len() as u16 + 3).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let system = &app.controller.system;
+    let output = &system.output().name;
+    let rows: Vec<Row> = app
+        .annotations
+        .entries(annotations::config_hash(system))
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let annotation = entry.annotation;
+            let value = annotation
+                .outputs
+                .first()
+                .map_or("-".to_string(), |v| app.show(output, *v));
+            let flag = if entry.stale {
+                "⚠ possibly stale"
+            } else {
+                ""
+            };
+            let style = if i == app.selected_annotation {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            Row::new(vec![
+                Cell::from(annotation.note.clone()),
+                Cell::from(system.timezone.short(annotation.saved)),
+                Cell::from(value),
+                Cell::from(flag).style(Style::default().fg(app.theme.error)),
+            ])
+            .style(style)
+        })
+        .collect();
+    let header = Row::new(vec!["Note", "Saved", "Output", ""])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    let widths = [
+        Constraint::Percentage(45),
+        Constraint::Percentage(17),
+        Constraint::Percentage(16),
+        Constraint::Percentage(22),
+    ];
+    let table = Table::new(rows)
+        .header(header)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("📌 Annotations (Esc closes)"),
+        )
+        .widths(&widths)
+        .column_spacing(1);
+    f.render_widget(Clear, popup);
+    f.render_widget(table, popup);
+}
+
+/// Backup popup over the middle of `area`, newest first
+fn render_backups<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, app: &App, area: Rect) {
+    let width = area.width.saturating_sub(4).min(90);
+    let height = (app.backups.len() as u16 + 2).min(area.height);
+    let popup = Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    );
+    let lines: Vec<Line> = app
+        .backups
+        .iter()
+        .enumerate()
+        .map(|(i, backup)| {
+            let style = if i == app.selected_backup {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else if backup.error.is_some() {
+                Style::default().fg(app.theme.error)
+            } else {
+                Style::default()
+            };
+            Line::from(Span::styled(backup.describe(), style))
+        })
+        .collect();
+    let list = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Backups (Enter restores, Esc closes)"),
+    );
+    f.render_widget(Clear, popup);
+    f.render_widget(list, popup);
+}
+
+/// Membership curves of `var` over `view`, sampled again at the chart's
+/// resolution, with a vertical line at the current `value`
+fn render_membership_curves<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    theme: &Palette<Color>,
+    title: &str,
+    var: &FuzzyVariable,
+    (value, marker_color): (f64, Color),
+    view: Viewport,
+    area: Rect,
+) {
+    // Braille cells hold two dots across
+    let xs = view.samples(area.width.saturating_sub(2) as usize * 2);
+    let curves: Vec<Vec<(f64, f64)>> = var
+        .sets
+        .iter()
+        .map(|set| xs.iter().map(|&x| (x, set.function.evaluate(x))).collect())
+        .collect();
+    let marker = [(value, 0.0), (value, 1.0)];
+
+    let mut datasets: Vec<Dataset> = var
+        .sets
+        .iter()
+        .zip(&curves)
+        .enumerate()
+        .map(|(i, (set, points))| {
+            Dataset::default()
+                .name(set.name.clone())
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(theme.sets[i % theme.sets.len()]))
+                .data(points)
+        })
+        .collect();
+    if (view.start..=view.end).contains(&value) {
+        datasets.push(
+            Dataset::default()
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(marker_color))
+                .data(&marker),
+        );
+    }
+
+    let title = if view.is_full(var.min, var.max) {
+        title.to_string()
+    } else {
+        format!("{} · {:.2}–{:.2}", title, view.start, view.end)
+    };
+    let label = |x: f64| Span::raw(format!("{:.1}", x));
+    let chart = Chart::new(datasets)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .x_axis(
+            Axis::default()
+                .bounds([view.start, view.end])
+                .labels(vec![label(view.start), label(view.end)])
+                .style(Style::default().fg(theme.muted)),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds([0.0, 1.0])
+                .labels(vec![Span::raw("0"), Span::raw("1")])
+                .style(Style::default().fg(theme.muted)),
+        );
+    f.render_widget(chart, area);
+}
+
+/// The primary output's aggregated set, which the centroid is taken of,
+/// with the active caps drawn over the sets they hold down
+fn render_aggregated_output<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    app: &App,
+    area: Rect,
+) {
+    let system = &app.controller.system;
+    let output = system.output();
+    let settings = &system.defuzzification;
+    let memberships = apply_rules(&app.rule_strengths, &system.rules, output);
+    let view = Viewport::full(output.min, output.max);
+    let xs = view.samples(area.width.saturating_sub(2) as usize * 2);
+    let curve: Vec<(f64, f64)> = xs
+        .iter()
+        .map(|&x| (x, aggregated(&memberships, output, settings, x)))
+        .collect();
+    // Each cap as a line across the support of its set
+    let caps: Vec<(String, Vec<(f64, f64)>)> = output
+        .sets
+        .iter()
+        .filter_map(|set| {
+            let max = settings.cap(&output.name, &set.name)?;
+            let support: Vec<f64> = xs
+                .iter()
+                .copied()
+                .filter(|&x| set.function.evaluate(x) > 0.0)
+                .collect();
+            let (first, last) = (support.first()?, support.last()?);
+            Some((
+                format!("{} cap", set.name),
+                vec![(*first, max), (*last, max)],
+            ))
+        })
+        .collect();
+    let marker = [(app.fan_speed, 0.0), (app.fan_speed, 1.0)];
+
+    let mut datasets = vec![
+        Dataset::default()
+            .name("aggregated")
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(app.theme.good))
+            .data(&curve),
+    ];
+    for (name, line) in &caps {
+        datasets.push(
+            Dataset::default()
+                .name(name.clone())
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(app.theme.error))
+                .data(line),
+        );
+    }
+    datasets.push(
+        Dataset::default()
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(app.theme.text))
+            .data(&marker),
+    );
+
+    let mut title = format!("{} aggregated", output.name);
+    if let Some(profile) = &settings.cap_profile {
+        title.push_str(&format!(" · {} caps", profile));
+    }
+    for hit in &app.cap_hits {
+        title.push_str(&format!(
+            " · {} {:.2}→{:.2}",
+            hit.set, hit.activation, hit.max
+        ));
+    }
+    let label = |x: f64| Span::raw(format!("{:.1}", x));
+    let chart = Chart::new(datasets)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .x_axis(
+            Axis::default()
+                .bounds([output.min, output.max])
+                .labels(vec![label(output.min), label(output.max)])
+                .style(Style::default().fg(app.theme.muted)),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds([0.0, 1.0])
+                .labels(vec![Span::raw("0"), Span::raw("1")])
+                .style(Style::default().fg(app.theme.muted)),
+        );
+    f.render_widget(chart, area);
+}
+
+/// Rules with their current strength and lifetime usage ('u' toggles,
+/// 'l' clears the latches)
+fn render_rule_table<B: ratatui::backend::Backend>(
+    f: &mut ratatui::Frame<B>,
+    app: &App,
+    area: Rect,
+) {
+    let system = &app.controller.system;
+    let zone = system.timezone;
+    let time = |t: Option<f64>| t.map(|t| zone.short(t)).unwrap_or_else(|| "-".to_string());
+    // Feedback moves the weights: how far each is from the configured one
+    let adapting = system.adaptation.is_some();
+    let rule_row = |(i, rule): (usize, &FuzzyRule)| {
+        let strength = app.rule_strengths.get(i).copied().unwrap_or(0.0);
+        let usage = app.rule_stats.get(i).cloned().unwrap_or_default();
+        let conditions: Vec<&str> = rule.conditions.iter().map(|(_, s)| s.as_str()).collect();
+        // The primary output's set alone, other outputs named
+        let consequents: Vec<String> = rule
+            .consequents
+            .iter()
+            .map(|(var, set)| {
+                if *var == system.output().name {
+                    set.clone()
+                } else {
+                    format!("{}={}", var, set)
+                }
+            })
+            .collect();
+        let mut style = if strength > 0.0 {
+            Style::default()
+                .fg(app.theme.good)
+                .add_modifier(Modifier::BOLD)
+        } else if usage.latched {
+            Style::default().fg(app.theme.caution)
+        } else {
+            Style::default().fg(app.theme.faint)
+        };
+        if app.selected_rule == Some(i) {
+            style = style.add_modifier(Modifier::REVERSED);
+        }
+        let mut cells = vec![
+            Cell::from(if usage.latched { "●" } else { " " }),
+            Cell::from(Line::from(vec![
+                Span::styled("■ ", Style::default().fg(rulecolor::of(&app.theme, i))),
+                Span::raw(format!(
+                    "{} → {}",
+                    conditions.join("+"),
+                    consequents.join(",")
+                )),
+            ])),
+            Cell::from(format!("{:.2}", strength)),
+            Cell::from(format!("{:.2}", usage.max_strength)),
+            Cell::from(time(usage.first_fired)),
+            Cell::from(time(usage.last_fired)),
+        ];
+        if adapting {
+            let drift = app.adaptation.drift(system, i);
+            cells.push(Cell::from(if drift == 0.0 {
+                "-".to_string()
+            } else {
+                format!("{:+.2}", drift)
+            }));
+        }
+        Row::new(cells).style(style)
+    };
+    let in_group = |group: Option<String>| {
+        system
+            .rules
+            .iter()
+            .enumerate()
+            .filter(move |(_, rule)| rule.group == group)
+    };
+
+    // Ungrouped rules first, then a collapsible section per group
+    let mut rows: Vec<Row> = in_group(None).map(rule_row).collect();
+    for (number, group) in system.groups.iter().enumerate() {
+        let collapsed = app.collapsed_groups.contains(&group.name);
+        let state = if group.enabled {
+            format!("on ×{}", group.weight)
+        } else {
+            "off".to_string()
+        };
+        rows.push(
+            Row::new(vec![
+                Cell::from(if collapsed { "▸" } else { "▾" }),
+                Cell::from(format!("{} {} ({})", number + 1, group.name, state)),
+            ])
+            .style(
+                Style::default()
+                    .fg(app.theme.accent)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        );
+        if !collapsed {
+            rows.extend(in_group(Some(group.name.clone())).map(rule_row));
+        }
+    }
+
+    // The rule column takes whatever the fixed columns, the column spacing
+    // and the borders leave
+    let drift_width = if adapting { 5 + 1 } else { 0 };
+    let rule_width = area
+        .width
+        .saturating_sub(1 + 4 + 4 + 11 + 11 + 5 + 2 + drift_width)
+        .max(20);
+    let mut widths = vec![
+        Constraint::Length(1),
+        Constraint::Length(rule_width),
+        Constraint::Length(4),
+        Constraint::Length(4),
+        Constraint::Length(11),
+        Constraint::Length(11),
+    ];
+    let mut header = vec!["L", "Rule", "Now", "Max", "First", "Last"];
+    if adapting {
+        widths.push(Constraint::Length(5));
+        header.push("Drift");
+    }
+    let table = Table::new(rows)
+        .header(
+            Row::new(header).style(
+                Style::default()
+                    .fg(app.theme.text)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        )
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "Rule Usage, {} ('l' resets latches, 1-9 fold groups, ↑/↓ preview)",
+            zone.label()
+        )))
+        .widths(&widths)
+        .column_spacing(1);
+    f.render_widget(table, area);
+}
+
+fn render_history<B: ratatui::backend::Backend>(f: &mut ratatui::Frame<B>, app: &App, area: Rect) {
+    let now = app.clock.now();
+    let output = app.controller.system.output();
+    let items: Vec<ListItem> = app
+        .history
+        .iter()
+        .rev()
+        .take(HISTORY_ROWS)
+        .map(|entry| {
+            let (t, h, f) = (entry.temperature, entry.humidity, entry.fan_speed);
+            let status = &entry.status;
+
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{:>5.0}s ago ", now - entry.time),
+                    Style::default().fg(app.theme.faint),
+                ),
+                Span::styled(
+                    format!("T: {} ", app.show("temperature", t)),
+                    Style::default().fg(app.theme.accent),
+                ),
+                Span::styled(
+                    format!("H: {} ", app.show("humidity", h)),
+                    Style::default().fg(app.theme.accent_alt),
+                ),
+                Span::styled(
+                    format!("→ Fan: {} ", app.show(&output.name, f)),
+                    Style::default().fg(app.theme.text),
+                ),
+                what_if_span(app, entry),
+                Span::styled(
+                    format!("[{}]", status.0),
+                    Style::default().fg(status.1).add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(
+                    entry
+                        .sensed
+                        .map(|(t, h)| {
+                            format!(
+                                "(sensed {} {}) ",
+                                app.show("temperature", t),
+                                app.show("humidity", h)
+                            )
+                        })
+                        .unwrap_or_default(),
+                    Style::default().fg(app.theme.muted),
+                ),
+                Span::styled(
+                    if entry.injected { " INJECTED" } else { "" },
+                    Style::default().fg(app.theme.highlight),
+                ),
+                Span::styled(
+                    entry
+                        .interlock
+                        .as_ref()
+                        .map(|name| format!(" 🔒 {}", name))
+                        .unwrap_or_default(),
+                    Style::default()
+                        .fg(app.theme.error)
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(
+                    entry
+                        .overflows
+                        .iter()
+                        .map(|o| format!(" ⚠ {} {}", o.input, o.action()))
+                        .collect::<String>(),
+                    Style::default().fg(app.theme.error),
+                ),
+                Span::styled(
+                    entry
+                        .generator
+                        .map(|kind| format!(" {}", kind.name()))
+                        .unwrap_or_default(),
+                    Style::default().fg(app.theme.faint),
+                ),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(
+            match (&app.what_if, app.comfort_share()) {
+                (Some(_), _) => "📊 Recent History, now: the current rules ('W' hides)".to_string(),
+                (None, Some(share)) => format!("📊 Recent History · {}", share),
+                (None, None) => "📊 Recent History".to_string(),
+            },
+        ))
+        .style(Style::default().fg(app.theme.text));
+    f.render_widget(list, area);
+}
+
+/// The what-if column of a history entry: the output the current rules
+/// give for its readings and the change from the recorded one, colored by
+/// its share of the output's range. "≈" marks entries recorded with sensor
+/// noise or an injection, which the replay leaves out
+fn what_if_span(app: &App, entry: &HistoryEntry) -> Span<'static> {
+    let Some(what_if) = &app.what_if else {
+        return Span::raw("");
+    };
+    let output = app.controller.system.output();
+    let approximate = if entry.injected || entry.sensed.is_some() {
+        "≈"
+    } else {
+        ""
+    };
+    let now = match what_if.get(entry.id) {
+        Some(now) if now.is_finite() => now,
+        Some(_) => return Span::styled("now ? ", Style::default().fg(app.theme.faint)),
+        None => return Span::styled("now … ", Style::default().fg(app.theme.faint)),
+    };
+    let unit = app.unit(&output.name);
+    let delta = now - entry.fan_speed;
+    // A difference: the display scale applies, the offset cancels out
+    let shown = unit.to_display(delta) - unit.to_display(0.0);
+    let precision = unit.precision.unwrap_or(units::DEFAULT_PRECISION);
+    let share = delta.abs() / (output.max - output.min);
+    let color = if share < 0.005 {
+        app.theme.faint
+    } else if share < 0.05 {
+        app.theme.caution
+    } else if share < 0.15 {
+        app.theme.warning
+    } else {
+        app.theme.error
+    };
+    Span::styled(
+        format!(
+            "now {}{} (Δ{:+.*}) ",
+            approximate,
+            app.show(&output.name, now),
+            precision,
+            shown
+        ),
+        Style::default().fg(color),
+    )
+}
+
+// ============================================================================
+// EVENT HANDLING
+// ============================================================================
+//
+// Keys become actions in `action_for`, which only reads the app to know
+// what a key means in its current mode; `update` then carries an action
+// out. Anything that can produce an `AppAction` can drive the app the way
+// the keyboard does, and the tests drive `update` directly.
+
+/// Something the user asked the app to do
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AppAction {
+    Quit,
+    /// Ctrl-Z: stop the process, the terminal given back to the shell
+    Suspend,
+    /// The terminal gained (true) or lost focus
+    Focus(bool),
+    Random,
+    CycleGenerator,
+    ToggleRules,
+    ToggleUnits,
+    CycleBarScale,
+    ResetLatches,
+    /// The output should have gone this way
+    Feedback(adapt::Direction),
+    RevertWeights,
+    ExportBundle,
+    CheckScenarios,
+    /// Open a text prompt: temperature, humidity, injection, noise, a new
+    /// bookmark, a bookmark's new name or the note of a frozen state
+    Prompt(InputMode),
+    OpenBookmarks,
+    OpenAnnotations,
+    SelectAnnotation(isize),
+    /// Export the annotations as an HTML section (true) or as JSON
+    ExportAnnotations(bool),
+    /// Write the running system over its config file
+    SaveConfig,
+    OpenBackups,
+    SelectBackup(isize),
+    ToggleGroupFold(char),
+    ReloadRulesCsv,
+    ToggleSelectedGroup,
+    /// Change the selected group's weight by this much
+    StepGroupWeight(f64),
+    SelectRule(isize),
+    Nudge(&'static str, nudge::Direction),
+    ToggleSurface,
+    CycleSurfaceStyle,
+    ToggleScatter,
+    ToggleMatrix,
+    ToggleCurves,
+    CycleAggregation,
+    /// Next defuzzification method of the output on screen
+    CycleMethod,
+    /// Multiply the softmax temperature by this factor
+    ScaleSoftmaxTemperature(f64),
+    CycleCaps,
+    /// Switch the range mode of input chart 0 or 1
+    CycleRange(usize),
+    ToggleSafeModeError,
+    Zoom(f64),
+    Pan(f64),
+    ResetView,
+    FocusNextPanel,
+    TogglePanel,
+    /// Grow (positive) or shrink the focused panel by this many steps
+    ResizePanel(i16),
+    ResetLayout,
+    ToggleWhatIf,
+    ToggleForecast,
+    /// Answer the journal recovery question
+    Recover(bool),
+    OpenDiff,
+    RevertAll,
+    /// Take down the error in the status bar
+    Acknowledge,
+    OpenMessages,
+    ScrollMessages(isize),
+    OpenComplexity,
+    OpenChain,
+    ScrollChain(isize),
+    OpenSources,
+    OpenPalettes,
+    SelectPalette(isize),
+    OpenTutorial,
+    /// Move through the tutorial by this many steps
+    StepTutorial(isize),
+    SelectBookmark(isize),
+    DeleteBookmark,
+    PromoteBookmark,
+    /// Edit the text of the open prompt
+    Type(char),
+    Backspace,
+    /// Enter: take the prompt's text, or jump to the selected bookmark
+    Submit,
+    /// Esc: close whatever is open, back to where it was opened from
+    Cancel,
+}
+
+/// What `key` asks for in the app's current mode, if anything
+fn action_for(app: &App, key: KeyCode) -> Option<AppAction> {
+    // 'k' takes an error down before it does anything else
+    let acknowledging = app.inbox.has_error(app.clock.now());
+    let action = match app.input_mode {
+        InputMode::Menu => match key {
+            KeyCode::Char('q') => AppAction::Quit,
+            KeyCode::Char('r') => AppAction::Random,
+            KeyCode::Char('R') => AppAction::CycleGenerator,
+            KeyCode::Char('u') => AppAction::ToggleRules,
+            KeyCode::Char('U') => AppAction::ToggleUnits,
+            KeyCode::Char('s') => AppAction::CycleBarScale,
+            KeyCode::Char('l') => AppAction::ResetLatches,
+            KeyCode::Char('b') => AppAction::ExportBundle,
+            KeyCode::Char('g') => AppAction::CheckScenarios,
+            KeyCode::Char('t') => AppAction::Prompt(InputMode::Temperature),
+            KeyCode::Char('h') => AppAction::Prompt(InputMode::Humidity),
+            KeyCode::Char('F') => AppAction::Prompt(InputMode::Inject),
+            KeyCode::Char('B') => AppAction::Prompt(InputMode::Bookmark),
+            KeyCode::Char('N') => AppAction::Prompt(InputMode::Noise),
+            KeyCode::Char('I') => AppAction::Prompt(InputMode::Interlock),
+            KeyCode::Char('k') if acknowledging => AppAction::Acknowledge,
+            KeyCode::Char('k') => AppAction::OpenBookmarks,
+            KeyCode::Char('a') => AppAction::Prompt(InputMode::Annotate),
+            KeyCode::Char('j') => AppAction::OpenAnnotations,
+            KeyCode::Char('o') => AppAction::SaveConfig,
+            KeyCode::Char('K') => AppAction::OpenBackups,
+            KeyCode::Char('M') => AppAction::OpenMessages,
+            KeyCode::Char('C') => AppAction::OpenComplexity,
+            KeyCode::Char('Y') => AppAction::OpenChain,
+            KeyCode::Char('G') => AppAction::OpenSources,
+            KeyCode::Char('O') => AppAction::OpenPalettes,
+            KeyCode::Char('>') => AppAction::Feedback(adapt::Direction::Higher),
+            KeyCode::Char('<') => AppAction::Feedback(adapt::Direction::Lower),
+            KeyCode::Char('w') => AppAction::RevertWeights,
+            KeyCode::Char('T') => AppAction::OpenTutorial,
+            KeyCode::Char(c @ '1'..='9') if app.show_rules => AppAction::ToggleGroupFold(c),
+            KeyCode::Char('L') if app.show_rules => AppAction::ReloadRulesCsv,
+            KeyCode::Char('e') if app.show_rules => AppAction::ToggleSelectedGroup,
+            KeyCode::Char('+') if app.show_rules => AppAction::StepGroupWeight(0.1),
+            KeyCode::Char('-') if app.show_rules => AppAction::StepGroupWeight(-0.1),
+            KeyCode::Up if app.show_rules => AppAction::SelectRule(-1),
+            KeyCode::Down if app.show_rules => AppAction::SelectRule(1),
+            KeyCode::Up => AppAction::Nudge("temperature", nudge::Direction::Up),
+            KeyCode::Down => AppAction::Nudge("temperature", nudge::Direction::Down),
+            KeyCode::PageUp => AppAction::Nudge("humidity", nudge::Direction::Up),
+            KeyCode::PageDown => AppAction::Nudge("humidity", nudge::Direction::Down),
+            KeyCode::Char('v') => AppAction::ToggleSurface,
+            KeyCode::Char('V') => AppAction::CycleSurfaceStyle,
+            KeyCode::Char('i') => AppAction::ToggleScatter,
+            KeyCode::Char('S') => AppAction::Prompt(InputMode::Quantize),
+            KeyCode::Char('m') => AppAction::ToggleMatrix,
+            KeyCode::Char('c') => AppAction::ToggleCurves,
+            KeyCode::Char('A') => AppAction::CycleAggregation,
+            KeyCode::Char('d') => AppAction::CycleMethod,
+            KeyCode::Char(']') => AppAction::ScaleSoftmaxTemperature(2.0),
+            KeyCode::Char('[') => AppAction::ScaleSoftmaxTemperature(0.5),
+            KeyCode::Char('Q') => AppAction::CycleCaps,
+            KeyCode::Char('x') => AppAction::CycleRange(0),
+            KeyCode::Char('X') => AppAction::CycleRange(1),
+            KeyCode::Char('E') => AppAction::ToggleSafeModeError,
+            KeyCode::Char('+') => AppAction::Zoom(viewport::ZOOM_STEP),
+            KeyCode::Char('-') => AppAction::Zoom(1.0 / viewport::ZOOM_STEP),
+            KeyCode::Left => AppAction::Pan(-1.0),
+            KeyCode::Right => AppAction::Pan(1.0),
+            KeyCode::Char('0') => AppAction::ResetView,
+            KeyCode::Tab => AppAction::FocusNextPanel,
+            KeyCode::Char('p') => AppAction::TogglePanel,
+            KeyCode::Char('P') => AppAction::ResetLayout,
+            KeyCode::Char('W') => AppAction::ToggleWhatIf,
+            KeyCode::Char('f') => AppAction::ToggleForecast,
+            KeyCode::Char('D') => AppAction::OpenDiff,
+            KeyCode::Esc => AppAction::Cancel,
+            _ => return None,
+        },
+        InputMode::Recover => match key {
+            KeyCode::Char('y') => AppAction::Recover(true),
+            KeyCode::Char('n') => AppAction::Recover(false),
+            _ => return None,
+        },
+        InputMode::Messages => match key {
+            KeyCode::Up => AppAction::ScrollMessages(-1),
+            KeyCode::Down => AppAction::ScrollMessages(1),
+            KeyCode::Char('k') if acknowledging => AppAction::Acknowledge,
+            KeyCode::Esc | KeyCode::Char('M') => AppAction::Cancel,
+            _ => return None,
+        },
+        InputMode::Complexity => match key {
+            KeyCode::Esc | KeyCode::Char('C') => AppAction::Cancel,
+            _ => return None,
+        },
+        InputMode::Chain => match key {
+            KeyCode::Up => AppAction::ScrollChain(-1),
+            KeyCode::Down => AppAction::ScrollChain(1),
+            KeyCode::Esc | KeyCode::Char('Y') => AppAction::Cancel,
+            _ => return None,
+        },
+        InputMode::Sources => match key {
+            KeyCode::Char('k') if acknowledging => AppAction::Acknowledge,
+            KeyCode::Esc | KeyCode::Char('G') => AppAction::Cancel,
+            _ => return None,
+        },
+        InputMode::Palettes => match key {
+            KeyCode::Up => AppAction::SelectPalette(-1),
+            KeyCode::Down => AppAction::SelectPalette(1),
+            KeyCode::Enter => AppAction::Submit,
+            KeyCode::Esc | KeyCode::Char('O') => AppAction::Cancel,
+            _ => return None,
+        },
+        InputMode::Tutorial => match key {
+            KeyCode::Char(' ') | KeyCode::Enter | KeyCode::Right => AppAction::StepTutorial(1),
+            KeyCode::Backspace | KeyCode::Left => AppAction::StepTutorial(-1),
+            KeyCode::Char('r') => AppAction::Random,
+            KeyCode::Up => AppAction::Nudge("temperature", nudge::Direction::Up),
+            KeyCode::Down => AppAction::Nudge("temperature", nudge::Direction::Down),
+            KeyCode::PageUp => AppAction::Nudge("humidity", nudge::Direction::Up),
+            KeyCode::PageDown => AppAction::Nudge("humidity", nudge::Direction::Down),
+            KeyCode::Char('k') if acknowledging => AppAction::Acknowledge,
+            KeyCode::Esc | KeyCode::Char('T') => AppAction::Cancel,
+            _ => return None,
+        },
+        InputMode::Diff => match key {
+            KeyCode::Char('k') if acknowledging => AppAction::Acknowledge,
+            KeyCode::Char('R') => AppAction::RevertAll,
+            KeyCode::Esc | KeyCode::Char('D') => AppAction::Cancel,
+            _ => return None,
+        },
+        InputMode::Bookmarks => match key {
+            KeyCode::Up => AppAction::SelectBookmark(-1),
+            KeyCode::Down => AppAction::SelectBookmark(1),
+            KeyCode::Enter => AppAction::Submit,
+            KeyCode::Char('d') => AppAction::DeleteBookmark,
+            KeyCode::Char('p') => AppAction::PromoteBookmark,
+            KeyCode::Char('k') if acknowledging => AppAction::Acknowledge,
+            KeyCode::Char('r') => AppAction::Prompt(InputMode::RenameBookmark),
+            KeyCode::Esc | KeyCode::Char('k') => AppAction::Cancel,
+            _ => return None,
+        },
+        InputMode::Annotations => match key {
+            KeyCode::Up => AppAction::SelectAnnotation(-1),
+            KeyCode::Down => AppAction::SelectAnnotation(1),
+            KeyCode::Enter => AppAction::Submit,
+            KeyCode::Char('J') => AppAction::ExportAnnotations(false),
+            KeyCode::Char('H') => AppAction::ExportAnnotations(true),
+            KeyCode::Char('k') if acknowledging => AppAction::Acknowledge,
+            KeyCode::Esc | KeyCode::Char('j') => AppAction::Cancel,
+            _ => return None,
+        },
+        InputMode::Backups => match key {
+            KeyCode::Up => AppAction::SelectBackup(-1),
+            KeyCode::Down => AppAction::SelectBackup(1),
+            KeyCode::Enter => AppAction::Submit,
+            KeyCode::Char('k') if acknowledging => AppAction::Acknowledge,
+            KeyCode::Esc | KeyCode::Char('K') => AppAction::Cancel,
+            _ => return None,
+        },
+        // The text prompts
+        _ => match key {
+            KeyCode::Enter => AppAction::Submit,
+            KeyCode::Char(c) => AppAction::Type(c),
+            KeyCode::Backspace => AppAction::Backspace,
+            KeyCode::Esc => AppAction::Cancel,
+            _ => return None,
+        },
+    };
+    Some(action)
+}
+
+/// Carry out `action`; an action that means nothing in the current mode,
+/// such as typing with no prompt open, changes nothing
+fn update(app: &mut App, action: AppAction) {
+    match action {
+        AppAction::Quit => app.quit = true,
+        AppAction::Suspend => app.suspend_requested = true,
+        AppAction::Focus(focused) => app.set_focus(focused),
+        AppAction::Random => app.generate_random(),
+        AppAction::CycleGenerator => app.cycle_generator(),
+        AppAction::ToggleRules => app.show_rules = !app.show_rules,
+        AppAction::ToggleUnits => app.toggle_units(),
+        AppAction::CycleBarScale => {
+            app.bar_scale = app.bar_scale.next();
+            app.info(format!("Membership bars: {} scale", app.bar_scale.label()));
+        }
+        AppAction::ResetLatches => app.reset_latches(),
+        AppAction::Feedback(direction) => app.give_feedback(direction),
+        AppAction::RevertWeights => app.revert_weights(),
+        AppAction::ExportBundle => app.export_bundle(),
+        AppAction::CheckScenarios => app.check_scenarios(),
+        AppAction::Prompt(mode) => open_prompt(app, mode),
+        AppAction::OpenBookmarks => app.open_bookmarks(),
+        AppAction::OpenAnnotations => app.open_annotations(),
+        AppAction::SelectAnnotation(step) => app.move_annotation_selection(step),
+        AppAction::ExportAnnotations(html) => {
+            if app.input_mode == InputMode::Annotations {
+                app.export_annotations(html);
+            }
+        }
+        AppAction::SaveConfig => app.save_config(),
+        AppAction::OpenBackups => app.open_backups(),
+        AppAction::SelectBackup(step) => app.move_backup_selection(step),
+        AppAction::ToggleGroupFold(digit) => app.toggle_group_fold(digit),
+        AppAction::ReloadRulesCsv => app.reload_rules_csv(),
+        AppAction::ToggleSelectedGroup => {
+            app.edit_selected_group(|group, enabled, _| journal::Edit::GroupEnabled {
+                group: group.to_string(),
+                enabled: !enabled,
+            })
+        }
+        AppAction::StepGroupWeight(step) => {
+            app.edit_selected_group(|group, _, weight| journal::Edit::GroupWeight {
+                group: group.to_string(),
+                weight: ((weight + step) * 10.0).round().clamp(0.0, 10.0) / 10.0,
+            })
+        }
+        AppAction::SelectRule(step) => app.move_selection(step),
+        AppAction::Nudge(input, direction) => app.nudge(input, direction),
+        AppAction::ToggleSurface => {
+            app.show_surface = !app.show_surface;
+            let message = if app.show_surface {
+                format!(
+                    "Control surface ({}), 'V' switches the style",
+                    app.surface_style.label()
+                )
+            } else {
+                "Membership charts".to_string()
+            };
+            app.info(message);
+        }
+        AppAction::CycleSurfaceStyle => {
+            app.surface_style = app.surface_style.next();
+            app.info(format!("Surface style: {}", app.surface_style.label()));
+        }
+        AppAction::ToggleScatter => {
+            app.show_scatter = !app.show_scatter;
+            app.info(if app.show_scatter {
+                "Input scatter: the history over temperature and humidity"
+            } else {
+                "Membership charts"
+            });
+        }
+        AppAction::ToggleMatrix => {
+            app.show_matrix = !app.show_matrix;
+            app.info(if app.show_matrix {
+                "Rule matrix, the dominant cell highlighted"
+            } else {
+                "Membership charts"
+            });
+        }
+        AppAction::ToggleCurves => {
+            app.show_curves = !app.show_curves;
+            app.info(if app.show_curves {
+                "Membership curves: '+'/'-' zoom, ←/→ pan, '0' resets"
+            } else {
+                "Membership bars"
+            });
+        }
+        AppAction::CycleAggregation => app.edit_aggregation(|a, t| (a.next(), t)),
+        AppAction::CycleMethod => app.cycle_method(),
+        AppAction::ScaleSoftmaxTemperature(factor) => {
+            app.edit_aggregation(|a, t| (a, (t * factor).clamp(0.001, 100.0)))
+        }
+        AppAction::CycleCaps => app.cycle_caps(),
+        AppAction::CycleRange(chart) => app.cycle_range(chart),
+        AppAction::ToggleSafeModeError => app.toggle_safe_mode_error(),
+        AppAction::Zoom(factor) => app.zoom(factor),
+        AppAction::Pan(steps) => app.pan(steps),
+        AppAction::ResetView => app.reset_view(),
+        AppAction::FocusNextPanel => app.focus_next_panel(),
+        AppAction::TogglePanel => app.toggle_panel(),
+        AppAction::ResizePanel(steps) => app.resize_panel(steps),
+        AppAction::ResetLayout => {
+            app.layout.reset();
+            app.info("Default panel layout");
+        }
+        AppAction::ToggleWhatIf => app.toggle_what_if(),
+        AppAction::ToggleForecast => app.toggle_forecast(),
+        AppAction::Recover(replay) => {
+            if app.input_mode == InputMode::Recover {
+                app.recover(replay);
+            }
+        }
+        AppAction::OpenDiff => app.open_diff(),
+        AppAction::Acknowledge => app.acknowledge(),
+        AppAction::OpenMessages => app.open_messages(),
+        AppAction::ScrollMessages(step) => app.scroll_messages(step),
+        AppAction::OpenComplexity => app.open_complexity(),
+        AppAction::OpenChain => app.open_chain(),
+        AppAction::ScrollChain(step) => app.scroll_chain(step),
+        AppAction::OpenSources => app.open_sources(),
+        AppAction::OpenPalettes => app.open_palettes(),
+        AppAction::SelectPalette(step) => {
+            if app.input_mode == InputMode::Palettes {
+                app.select_palette(step);
+            }
+        }
+        AppAction::OpenTutorial => app.open_tutorial(),
+        AppAction::StepTutorial(step) => {
+            if app.input_mode == InputMode::Tutorial {
+                app.step_tutorial(step);
+            }
+        }
+        AppAction::RevertAll => {
+            if app.input_mode == InputMode::Diff {
+                app.revert_all();
+            }
+        }
+        AppAction::SelectBookmark(step) => app.move_bookmark_selection(step),
+        AppAction::DeleteBookmark => app.delete_bookmark(),
+        AppAction::PromoteBookmark => app.promote_bookmark(),
+        AppAction::Type(c) => {
+            if app.input_mode.is_prompt() {
+                app.input_buffer.push(c);
+            }
+        }
+        AppAction::Backspace => {
+            app.input_buffer.pop();
+        }
+        AppAction::Submit => submit(app),
+        AppAction::Cancel => cancel(app),
+    }
+}
+
+/// Open the text prompt of `mode` with an empty buffer
+fn open_prompt(app: &mut App, mode: InputMode) {
+    app.prompt = match mode {
+        InputMode::Temperature => format!(
+            "Enter {} and press Enter:",
+            app.unit("temperature").title("temperature")
+        ),
+        InputMode::Humidity => match app.dew_point {
+            Some(_) => format!(
+                "Enter {} and press Enter:",
+                app.unit("temperature").title("dew point")
+            ),
+            None => format!(
+                "Enter {} and press Enter:",
+                app.unit("humidity").title("humidity")
+            ),
+        },
+        InputMode::Inject => {
+            "Inject: <input> freeze|nan|offset <d>|noise <a> <secs>, or clear:".to_string()
+        }
+        InputMode::Noise => {
+            "Noise: <input> <sigma> <correlation> [<drift>], or <input> off:".to_string()
+        }
+        InputMode::Interlock => {
+            let names: Vec<&str> = (app.controller.system.interlocks.iter())
+                .map(|interlock| interlock.name.as_str())
+                .collect();
+            format!("Interlock to switch ({}):", names.join(", "))
+        }
+        InputMode::Bookmark => "Bookmark the current inputs as:".to_string(),
+        InputMode::Quantize => "Step table COLUMNSxROWS [worst|mean], empty to clear:".to_string(),
+        InputMode::RenameBookmark => "Rename the bookmark to:".to_string(),
+        InputMode::Annotate => {
+            // Frozen now, so the note describes what was on screen
+            app.frozen = Some(Annotation::freeze(
+                &app.controller,
+                app.input_values(),
+                timestamp::now(),
+            ));
+            "State frozen, annotate it with:".to_string()
+        }
+        InputMode::Menu
+        | InputMode::Bookmarks
+        | InputMode::Annotations
+        | InputMode::Backups
+        | InputMode::Recover
+        | InputMode::Diff
+        | InputMode::Messages
+        | InputMode::Complexity
+        | InputMode::Chain
+        | InputMode::Sources
+        | InputMode::Palettes
+        | InputMode::Tutorial => return,
+    };
+    app.input_mode = mode;
+    app.input_buffer.clear();
+}
+
+/// Enter in the current mode
+fn submit(app: &mut App) {
+    match app.input_mode {
+        InputMode::Temperature | InputMode::Humidity => {
+            match numbers::parse(&app.input_buffer, app.locale) {
+                // The prompt stays open for the number to be retyped
+                Err(e @ NumberError::Ambiguous { .. }) => {
+                    app.prompt = e.to_string();
+                    return;
+                }
+                Ok(typed) => set_typed_reading(app, typed),
+                Err(_) => app.warn("Invalid input! Try again."),
+            }
+            app.input_mode = InputMode::Menu;
+            app.input_buffer.clear();
+        }
+        InputMode::RenameBookmark => {
+            let name = std::mem::take(&mut app.input_buffer);
+            app.rename_bookmark(&name);
+        }
+        InputMode::Inject
+        | InputMode::Noise
+        | InputMode::Interlock
+        | InputMode::Bookmark
+        | InputMode::Annotate
+        | InputMode::Quantize => {
+            let command = std::mem::take(&mut app.input_buffer);
+            match app.input_mode {
+                InputMode::Noise => app.set_noise(&command),
+                InputMode::Interlock => app.switch_interlock(command.trim()),
+                InputMode::Bookmark => app.add_bookmark(&command),
+                InputMode::Annotate => app.annotate(&command),
+                InputMode::Quantize => app.quantize_surface(&command),
+                _ => app.inject(&command),
+            }
+            app.input_mode = InputMode::Menu;
+        }
+        InputMode::Bookmarks => app.jump_to_bookmark(),
+        InputMode::Annotations => app.jump_to_annotation(),
+        InputMode::Backups => app.restore_backup(),
+        InputMode::Palettes => app.apply_palette(),
+        InputMode::Menu
+        | InputMode::Recover
+        | InputMode::Diff
+        | InputMode::Messages
+        | InputMode::Complexity
+        | InputMode::Chain
+        | InputMode::Sources
+        | InputMode::Tutorial => {}
+    }
+}
+
+/// Take `typed`, in display units, as the reading the open temperature or
+/// humidity prompt asked for
+fn set_typed_reading(app: &mut App, typed: f64) {
+    if app.input_mode == InputMode::Humidity && app.dew_point.is_some() {
+        let val = app.unit("temperature").to_stored(typed);
+        app.dew_point = Some(val);
+        app.info(format!("Dew point set to {}", app.show("temperature", val)));
+        app.compute_fan_speed();
+        return;
+    }
+    let name = match app.input_mode {
+        InputMode::Temperature => "temperature",
+        _ => "humidity",
+    };
+    let val = app.unit(name).to_stored(typed);
+    if let Err(e) = app.admit(name, val) {
+        app.warn(format!("Refused: {}", e));
+        return;
+    }
+    if name == "temperature" {
+        app.temperature = val;
+    } else {
+        app.humidity = val;
+    }
+    app.info(match name {
+        "temperature" => format!("Temperature set to {}", app.show(name, val)),
+        _ => format!("Humidity set to {}", app.show(name, val)),
+    });
+    app.compute_fan_speed();
+}
+
+/// Esc in the current mode
+fn cancel(app: &mut App) {
+    match app.input_mode {
+        InputMode::Menu if app.show_safe_mode => app.show_safe_mode = false,
+        InputMode::Menu if app.held.is_some() => app.release(),
+        InputMode::Menu => app.selected_rule = None,
+        InputMode::Bookmarks => {
+            app.input_mode = InputMode::Menu;
+            app.info("Bookmarks closed.");
+        }
+        InputMode::Annotations => {
+            app.input_mode = InputMode::Menu;
+            app.info("Annotations closed.");
+        }
+        InputMode::Backups => {
+            app.input_mode = InputMode::Menu;
+            app.backups.clear();
+            app.info("Backups closed.");
+        }
+        InputMode::RenameBookmark => {
+            app.input_mode = InputMode::Bookmarks;
+            app.info("Cancelled.");
+            app.input_buffer.clear();
+        }
+        InputMode::Messages
+        | InputMode::Complexity
+        | InputMode::Chain
+        | InputMode::Sources
+        | InputMode::Palettes => app.input_mode = InputMode::Menu,
+        InputMode::Tutorial => {
+            app.close_tutorial();
+            app.info("Tutorial closed: 'T' starts it again");
+        }
+        InputMode::Diff => {
+            app.input_mode = InputMode::Menu;
+            app.review = None;
+            app.info("Diff closed.");
+        }
+        // The journal recovery question has to be answered
+        InputMode::Recover => {}
+        InputMode::Temperature
+        | InputMode::Humidity
+        | InputMode::Inject
+        | InputMode::Noise
+        | InputMode::Interlock
+        | InputMode::Bookmark
+        | InputMode::Annotate
+        | InputMode::Quantize => {
+            app.input_mode = InputMode::Menu;
+            app.frozen = None;
+            app.info("Cancelled.");
+            app.input_buffer.clear();
+        }
+    }
+}
+
+/// What `key` with Ctrl held asks for: the arrows resize the focused
+/// panel
+fn control_action(app: &App, key: KeyCode) -> Option<AppAction> {
+    if app.input_mode != InputMode::Menu {
+        return None;
+    }
+    match key {
+        KeyCode::Right | KeyCode::Up => Some(AppAction::ResizePanel(1)),
+        KeyCode::Left | KeyCode::Down => Some(AppAction::ResizePanel(-1)),
+        _ => None,
+    }
+}
+
+/// What a mouse event asks for: a click on the status bar acknowledges
+/// the error it shows, on a terminal `height` rows high
+fn click_action(app: &App, mouse: MouseEvent, height: u16) -> Option<AppAction> {
+    let on_status = mouse.row >= height.saturating_sub(STATUS_HEIGHT);
+    (mouse.kind == MouseEventKind::Down(MouseButton::Left)
+        && on_status
+        && app.inbox.has_error(app.clock.now()))
+    .then_some(AppAction::Acknowledge)
+}
+
+/// What a key event asks for: Ctrl-Z suspends in every mode, leaving an
+/// open prompt as it is, and Ctrl with another key falls back to the key
+/// alone
+fn key_action(app: &App, key: KeyEvent) -> Option<AppAction> {
+    if !key.modifiers.contains(KeyModifiers::CONTROL) {
+        return action_for(app, key.code);
+    }
+    match key.code {
+        KeyCode::Char('z') => Some(AppAction::Suspend),
+        code => control_action(app, code).or_else(|| action_for(app, code)),
+    }
+}
+
+fn handle_events(app: &mut App) -> io::Result<bool> {
+    if event::poll(std::time::Duration::from_millis(100))? {
+        let action = match event::read()? {
+            Event::Key(key) => key_action(app, key),
+            Event::Mouse(mouse) => click_action(app, mouse, crossterm_terminal::size()?.1),
+            Event::FocusGained => Some(AppAction::Focus(true)),
+            Event::FocusLost => Some(AppAction::Focus(false)),
+            _ => None,
+        };
+        if let Some(action) = action {
+            update(app, action);
+        }
+    }
+    Ok(app.quit)
+}
+
+/// Local TUI, optionally with a custom system and a state file for the
+/// rule usage statistics
+pub fn standalone(args: &[String]) -> Result<App, String> {
+    let mut source = None;
+    let mut fallback = None;
+    let mut state_path = None;
+    let mut no_color = false;
+    let mut locale = Locale::Auto;
+    let mut history_limit = HISTORY_LIMIT;
+    let mut tutorial = false;
+    let mut shadow = None;
+    let mut keep_backups = backup::DEFAULT_KEEP;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--no-color" {
+            no_color = true;
+            continue;
+        }
+        if arg == "--tutorial" {
+            tutorial = true;
+            continue;
+        }
+        match (arg.as_str(), iter.next()) {
+            ("--config", Some(path)) => source = Some(path.clone()),
+            ("--fallback", Some(path)) => fallback = Some(path.clone()),
+            ("--state", Some(path)) => state_path = Some(path.clone()),
+            ("--shadow", Some(path)) => shadow = Some(path.clone()),
+            ("--history", Some(count)) => {
+                history_limit = count.parse().ok().filter(|n| *n > 0).ok_or(format!(
+                    "--history expects a positive count, got '{}'",
+                    count
+                ))?
+            }
+            ("--locale", Some(name)) => {
+                locale = Locale::parse(name).ok_or(format!(
+                    "unknown locale '{}' (auto, point, comma or system)",
+                    name
+                ))?
+            }
+            ("--backups", Some(count)) => {
+                keep_backups = count
+                    .parse()
+                    .map_err(|_| format!("--backups expects a count, got '{}'", count))?
+            }
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let started = safemode::start(source.as_deref(), fallback.as_deref(), load_tui_system);
+    let mut app = App::new(FuzzyController::from_system(started.system));
+    app.locale = locale;
+    app.history_limit = history_limit;
+    app.keep_backups = keep_backups;
+    if no_color {
+        app.surface_style = SurfaceStyle::Ascii;
+    }
+    match (started.error, source) {
+        (Some(e), _) => app.safe_mode = Some(format!("config error: {}", e)),
+        (None, Some(source)) => {
+            app.config_path = Some(source.clone());
+            app.source = source;
+        }
+        (None, None) => {}
+    }
+    // The state of the configured system is left alone while another one
+    // stands in for it
+    if let Some(path) = state_path.filter(|_| app.safe_mode.is_none())
+        && let Err(e) = load_state(&mut app, path)
+    {
+        app.safe_mode = Some(format!("state error: {}", e));
+    }
+    if let Some(path) = shadow {
+        let shadow =
+            Shadow::load(&path, &app.controller.system).map_err(|e| format!("shadow {}", e))?;
+        app.start_shadow(shadow);
+    }
+    if app.safe_mode.is_some() {
+        app.error("SAFE MODE: press 'E' for the whole error");
+    }
+    // The journal recovery question comes first
+    if tutorial && app.input_mode == InputMode::Menu {
+        app.open_tutorial();
+    }
+    Ok(app)
+}
+
+/// Take the rule usage, bookmarks, annotations, panel layout and journal
+/// kept with the state file at `path`, all of them or none
+fn load_state(app: &mut App, path: String) -> Result<(), String> {
+    let mut stats = RuleUsageStats::load(&path, app.controller.system.rule_texts())?;
+    // The computation App::new already did belongs to this session too
+    stats.record(&app.rule_strengths, timestamp::now());
+    let bookmarks = Bookmarks::load(&path)?;
+    let annotations = Annotations::load(&annotations::path_for(&path))?;
+    let layout = PanelLayout::load(&path)?;
+    let journal_path = journal::path_for(&path);
+    let recovery = journal::read(&journal_path)?;
+    let journal = journal::Journal::open(&journal_path)?;
+    // The statistics above know the rules by their configured weights
+    let mut system = app.controller.system.clone();
+    let mut adaptation = app.adaptation.clone();
+    let skipped = adaptation.load(&adapt::path_for(&path), &mut system)?;
+
+    app.controller.system = system;
+    app.adaptation = adaptation;
+    if !skipped.is_empty() {
+        app.warn(format!(
+            "Adapted weights left out, their rule changed: {}",
+            skipped.join("; ")
+        ));
+    }
+    app.rule_stats = stats;
+    app.bookmarks = bookmarks;
+    app.annotations = annotations;
+    app.layout = layout;
+    app.recovery = recovery;
+    if !app.recovery.is_empty() {
+        app.input_mode = InputMode::Recover;
+        app.prompt = format!(
+            "Recover {} unsaved changes from {}? y/n",
+            app.recovery.len(),
+            journal_path
+        );
+    }
+    app.journal = Some(journal);
+    app.state_path = Some(path);
+    Ok(())
+}
+
+pub const USAGE_BUNDLE_OPEN: &str = "Usage: fuzzy_logic bundle --open FILE.tar.gz";
+
+/// Replay a bug report bundle: its history is recomputed with its system,
+/// and entries whose output changed are counted
+pub fn open_bundle(path: &str) -> Result<App, String> {
+    let report = bundle::Bundle::read(path)?;
+    check_tui_system(&report.system, path)?;
+
+    let mut app = App::new(FuzzyController::from_system(report.system));
+    app.source = report.source;
+    app.clear_history();
+    // The recorded sensor values stand in for the noise
+    let noise = std::mem::take(&mut app.noise);
+    let (mut differing, mut injected) = (0, 0);
+    // Scored over the replay only, next to the recorded outputs' scores
+    let config = app.controller.system.objective.clone();
+    app.objective = config.clone().map(Objective::new);
+    let mut recorded = config.map(Objective::new);
+    for row in report.history.iter().rev().take(app.history_limit).rev() {
+        let (temperature, humidity) = row.sensed.unwrap_or((row.temperature, row.humidity));
+        app.temperature = temperature;
+        app.humidity = humidity;
+        app.sync_dew_point();
+        app.compute_fan_speed();
+        if let Some(objective) = &mut recorded {
+            let system = &app.controller.system;
+            let (inputs, _) = adapters::convert(&system.inputs, &app.input_values());
+            let sample = Sample {
+                system,
+                inputs: &inputs,
+                outputs: &[row.fan_speed],
+                strengths: &[],
+            };
+            objective.update(&sample, app.clock.now(), timestamp::now());
+        }
+        if let (Some(entry), Some(_)) = (app.history.last_mut(), row.sensed) {
+            entry.temperature = row.temperature;
+            entry.humidity = row.humidity;
+            entry.sensed = row.sensed;
+        }
+        // The injected values were not recorded, so those rows cannot be
+        // reproduced
+        if row.injected {
+            injected += 1;
+            if let Some(entry) = app.history.last_mut() {
+                entry.injected = true;
+            }
+        } else if (app.fan_speed - row.fan_speed).abs() > 1e-6 {
+            differing += 1;
+        }
+    }
+    // Keep the recorded spacing between entries
+    let now = app.clock.now();
+    for (entry, row) in app
+        .history
+        .iter_mut()
+        .rev()
+        .zip(report.history.iter().rev())
+    {
+        entry.time = now - row.age_secs;
+    }
+    app.resummarize_history();
+    if let Some(entry) = app.history.last() {
+        (app.temperature, app.humidity) = (entry.temperature, entry.humidity);
+        app.sync_dew_point();
+    }
+    app.noise = noise;
+
+    // Loaded after the replay so it does not count as usage
+    if let Some(text) = &report.state {
+        app.rule_stats = RuleUsageStats::parse(text, app.controller.system.rule_texts())
+            .map_err(|e| format!("{}: state.toml: {}", path, e))?;
+    }
+    let mut replayed = format!(
+        "Replayed {} entries from {} ({}): {} differ from the recorded output",
+        report.history.len(),
+        path,
+        app.source,
+        differing
+    );
+    if injected > 0 {
+        replayed.push_str(&format!(", {} injected ones not compared", injected));
+    }
+    if let (Some(recorded), Some(objective)) = (&recorded, &app.objective) {
+        let mean = |objective: &Objective| {
+            objective
+                .mean()
+                .map_or("-".to_string(), |mean| format!("{:.3}", mean))
+        };
+        replayed.push_str(&format!(
+            "; objective mean {} recorded, {} replayed",
+            mean(recorded),
+            mean(objective)
+        ));
+    }
+    if differing > 0 {
+        app.warn(replayed);
+    } else {
+        app.info(replayed);
+    }
+    Ok(app)
+}
+
+/// TUI mirroring a running daemon; `--config` must match the daemon's so
+/// the membership charts describe the same system
+#[cfg(all(unix, feature = "daemon"))]
+pub fn attach(args: &[String]) -> Result<App, String> {
+    let (path, system) = remote::parse_attach_args(args, load_tui_system)?;
+    let link = remote::RemoteLink::connect(&path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut app = App::new(FuzzyController::from_system(system));
+    app.remote = Some(link);
+    // The daemon runs the program
+    app.exec = None;
+    app.clear_history();
+    app.info(format!("Attached to {}. Press 'q' to detach.", path));
+    Ok(app)
+}
+
+fn load_tui_system(path: &str) -> Result<FuzzySystem, String> {
+    let system = config::load_system(path)?;
+    check_tui_system(&system, path)?;
+    Ok(system)
+}
+
+/// The TUI drives exactly two inputs, temperature and humidity, plus the
+/// measured input of a cascade, which it simulates
+fn check_tui_system(system: &FuzzySystem, path: &str) -> Result<(), String> {
+    let mut names: Vec<&str> = system
+        .inputs
+        .iter()
+        .map(|v| v.name.as_str())
+        .filter(|name| system.cascade.as_ref().is_none_or(|c| c.measured != *name))
+        .collect();
+    names.sort();
+    if names != ["humidity", "temperature"] {
+        return Err(format!(
+            "{}: the TUI needs exactly the inputs 'temperature' and 'humidity'",
+            path
+        ));
+    }
+    Ok(())
+}
+
+fn run_loop(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    guard: &mut Guard,
+    app: &mut App,
+    token: &Token,
+) -> io::Result<()> {
+    while !token.is_cancelled() {
+        if guard.take_redraw() {
+            terminal.clear()?;
+        }
+        terminal.draw(|f| ui(f, app))?;
+        if handle_events(app)? {
+            break;
+        }
+        // Between two polls, so no read is in flight (termguard.rs)
+        #[cfg(unix)]
+        {
+            if std::mem::take(&mut app.suspend_requested) {
+                termguard::request_stop();
+            }
+            guard.handle_signals(token)?;
+        }
+        if !app.paused {
+            app.tick();
+        }
+        app.update_axes();
+        app.poll_review();
+        app.poll_what_if();
+        app.poll_forecast();
+        app.poll_surface();
+        #[cfg(feature = "logging")]
+        app.post_logged();
+    }
+    Ok(())
+}
+
+fn run_tui(mut app: App) -> io::Result<()> {
+    let mut coordinator = Coordinator::default();
+    let token = coordinator.token();
+    #[cfg(unix)]
+    {
+        shutdown::cancel_on_signals(&token);
+        termguard::install();
+    }
+
+    // Setup terminal
+    let mut guard = Guard::new(Crossterm);
+    if let Ok(path) = std::env::var(termguard::TRACE_VAR) {
+        guard.trace_to(&path)?;
+    }
+    guard.enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    // An error in the loop still goes through the shutdown
+    let result = run_loop(&mut terminal, &mut guard, &mut app, &token);
+    drop(terminal);
+
+    app.register_shutdown(&mut coordinator);
+    coordinator.register(
+        Stage::RestoreTerminal,
+        "terminal",
+        shutdown::DEFAULT_TIMEOUT,
+        move || guard.restore().map_err(|e| e.to_string()),
+    );
+    let problems = coordinator.shutdown().problems();
+    #[cfg(feature = "logging")]
+    logging::release();
+    for problem in &problems {
+        eprintln!("error: shutdown: {}", problem);
+    }
+    result?;
+    if !problems.is_empty() {
+        // Hooks left running must not hold the process
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Run the TUI on `app`, or report why it could not be set up
+pub fn launch(app: Result<App, String>) -> io::Result<()> {
+    match app {
+        Ok(app) => run_tui(app),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, assert_output_close};
+    use crate::{DefuzzMethod, OutOfRange};
+    use ratatui::backend::TestBackend;
+
+    /// The left panel of `app` drawn `width` x `height`, row by row
+    fn left_panel(app: &App, width: u16, height: u16) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal
+            .draw(|f| render_left_panel(f, app, f.size()))
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| buffer.get(x, y).symbol.as_str())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    /// The message `app` posted last
+    fn said(app: &App) -> &str {
+        app.inbox
+            .newest_first()
+            .next()
+            .map_or("", |message| message.text.as_str())
+    }
+
+    /// The demo app after computing at `readings` (temperature, humidity)
+    fn app_after(readings: &[(f64, f64)]) -> App {
+        let mut app = App::new(FuzzyController::from_system(fixtures::fan()));
+        // Drop the reading App::new starts from
+        app.clear_history();
+        for (temperature, humidity) in readings {
+            app.temperature = *temperature;
+            app.humidity = *humidity;
+            app.compute_fan_speed();
+        }
+        // Frames enough for the chart axes to settle
+        for _ in 0..autorange::TRANSITION_FRAMES {
+            app.update_axes();
+        }
+        app
+    }
+
+    #[test]
+    fn zoomed_curves_name_their_range_and_reset() {
+        let mut app = app_after(&[(20.0, 50.0)]);
+        app.show_curves = true;
+        app.zoom(viewport::ZOOM_STEP);
+        app.zoom(viewport::ZOOM_STEP);
+        app.zoom(viewport::ZOOM_STEP);
+        assert_eq!(
+            app.curve_views[0],
+            Viewport {
+                start: 17.5,
+                end: 23.75
+            }
+        );
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 24)).unwrap();
+        terminal
+            .draw(|f| render_right_panel(f, &app, f.size()))
+            .unwrap();
+        let top: String = (0..60)
+            .map(|x| terminal.backend().buffer().get(x, 0).symbol.clone())
+            .collect();
+        assert!(
+            top.contains("Temperature Fuzzy Sets · 17.50–23.75"),
+            "{}",
+            top
+        );
+
+        app.reset_view();
+        assert_eq!(app.curve_views[0], Viewport::full(0.0, 50.0));
+    }
+
+    /// Clock the test moves by hand
+    struct SharedClock(std::rc::Rc<std::cell::Cell<f64>>);
+
+    impl Clock for SharedClock {
+        fn now(&self) -> f64 {
+            self.0.get()
+        }
+    }
+
+    /// Clock moving two seconds at every reading
+    struct SteppingClock(std::cell::Cell<f64>);
+
+    impl Clock for SteppingClock {
+        fn now(&self) -> f64 {
+            self.0.set(self.0.get() + 2.0);
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn a_shadow_config_is_charted_and_compared_next_to_the_output() {
+        // The rules calling for High call for Medium in the shadow
+        let mut lower = fixtures::fan();
+        for rule in &mut lower.rules {
+            for (_, set) in &mut rule.consequents {
+                if set == "High" {
+                    *set = "Medium".to_string();
+                }
+            }
+        }
+        let path =
+            std::env::temp_dir().join(format!("fuzzy-tui-shadow-{}.toml", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        std::fs::write(&path, config::to_toml(&lower, false)).unwrap();
+        let args = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let mut app = standalone(&args(&["--shadow", &path])).unwrap();
+        assert_eq!(
+            said(&app),
+            format!("Shadow {} runs next to this config", path)
+        );
+        let time = std::rc::Rc::new(std::cell::Cell::new(0.0));
+        app.clock = Box::new(SharedClock(time.clone()));
+        app.clear_history();
+        for (secs, temperature, humidity) in
+            [(0.0, 35.0, 80.0), (10.0, 15.0, 30.0), (30.0, 35.0, 80.0)]
+        {
+            time.set(secs);
+            app.temperature = temperature;
+            app.humidity = humidity;
+            app.compute_fan_speed();
+        }
+        time.set(40.0);
+        app.tick();
+
+        let high = app.controller.compute(&[35.0, 80.0]);
+        let off = app.controller.compute(&[15.0, 30.0]);
+        let medium = FuzzyController::from_system(lower).compute(&[35.0, 80.0]);
+        let charted: Vec<(f64, Option<f64>)> =
+            app.history.iter().map(|e| (e.output, e.shadow)).collect();
+        assert_eq!(
+            charted,
+            vec![(high, Some(medium)), (off, Some(off)), (high, Some(medium))]
+        );
+        let summary = app.shadow.as_ref().unwrap().summary();
+        assert_eq!(summary.band_differs(), 0.5);
+        assert!((summary.mean_difference() - (high - medium) / 2.0).abs() < 1e-9);
+
+        let rows = left_panel(&app, 140, 40);
+        let title = rows.iter().find(|r| r.contains("👥")).unwrap();
+        assert!(
+            title.contains("band differs 50.0% of the time"),
+            "{}",
+            title
+        );
+        // Too short for the chart: the shadow's output goes on the bar
+        let rows = left_panel(&app, 140, 24);
+        assert!(!rows.iter().any(|r| r.contains("👥")));
+        assert!(rows.iter().any(|r| r.contains(" · shadow ")));
+
+        // A shadow of other inputs is refused
+        std::fs::write(&path, "[[input]]\nname = \"pressure\"\n").unwrap();
+        assert!(standalone(&args(&["--shadow", &path])).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_limit_cycle_is_announced_and_marked_on_the_fan_gauge() {
+        let system = FuzzySystem {
+            oscillation: Some(crate::oscillation::OscillationConfig::default()),
+            ..fixtures::fan()
+        };
+        let mut app = App::new(FuzzyController::from_system(system));
+        let time = std::rc::Rc::new(std::cell::Cell::new(0.0));
+        app.clock = Box::new(SharedClock(time.clone()));
+        let run = |app: &mut App, from: usize, to: usize, toggle: bool| {
+            for i in from..to {
+                time.set(i as f64);
+                let cold = !toggle || (i / 5) % 2 == 0;
+                app.temperature = if cold { 10.0 } else { 40.0 };
+                app.compute_fan_speed();
+                app.tick();
+            }
+        };
+
+        run(&mut app, 0, 60, true);
+        assert!(
+            said(&app).starts_with("Oscillation: fan_speed swinging"),
+            "{}",
+            said(&app)
+        );
+        let rows = left_panel(&app, 60, 26);
+        let gauge = rows.iter().position(|r| r.contains("🌀")).unwrap();
+        assert!(rows[gauge].contains("⚠ oscillating ⟦"), "{}", rows[gauge]);
+        assert!(
+            rows[gauge + 1..]
+                .iter()
+                .any(|r| r.contains('⟦') && r.contains('⟧'))
+        );
+
+        run(&mut app, 60, 130, false);
+        assert_eq!(said(&app), "Oscillation over");
+        let rows = left_panel(&app, 60, 26);
+        assert!(!rows.iter().any(|r| r.contains("oscillating")));
+    }
+
+    #[test]
+    fn a_long_history_is_drawn_from_its_summaries_and_keeps_its_spikes() {
+        let mut app = app_after(&[]);
+        let time = std::rc::Rc::new(std::cell::Cell::new(0.0));
+        app.clock = Box::new(SharedClock(time.clone()));
+        app.history_limit = 20_000;
+        for step in 0..30_000 {
+            time.set(step as f64 * 0.1);
+            app.temperature = if step == 25_000 {
+                45.0
+            } else {
+                20.0 + (step % 7) as f64
+            };
+            app.compute_fan_speed();
+        }
+        assert_eq!(app.history.len(), 20_000);
+        let (from, to) = (0.0, time.get() + 1.0);
+        let points = app.history.iter().map(|e| (e.time, e.temperature));
+        assert_eq!(
+            app.history_series[0].buckets(0, from, to),
+            lod::Series::from_points(points).buckets(0, from, to)
+        );
+
+        // Zoomed out over all 2000 seconds: per-minute bands
+        app.zoom(1.0 / viewport::ZOOM_STEP);
+        assert_eq!(app.sparkline_level(160), Some(1));
+        let lines = left_panel(&app, 80, 30);
+        let title = &lines[0];
+        assert!(title.contains("20.0°C/23.0°C/45.0°C"), "{}", title);
+        // Zoomed in as far as it goes, 31 seconds: per-second bands, and
+        // the entries themselves once they fit
+        for _ in 0..8 {
+            app.zoom(viewport::ZOOM_STEP);
+        }
+        assert_eq!(app.sparkline_level(160), Some(0));
+        assert_eq!(app.sparkline_level(400), None);
+    }
+
+    #[test]
+    fn noisy_sensors_are_sampled_and_kept_next_to_the_true_values() {
+        let mut app = app_after(&[]);
+        let time = std::rc::Rc::new(std::cell::Cell::new(0.0));
+        app.clock = Box::new(SharedClock(time.clone()));
+        app.temperature = 30.0;
+        app.set_noise("temperature 2 0.5 0.1");
+        assert!(
+            said(&app).starts_with("Noise on temperature"),
+            "{}",
+            said(&app)
+        );
+
+        // One sample per interval while the true value stands still
+        for step in 1..=10 {
+            time.set(step as f64 * 0.5);
+            app.tick();
+        }
+        assert_eq!(app.history.len(), 6);
+        assert!(app.history.iter().all(|e| e.temperature == 30.0));
+        let sensed: Vec<f64> = app.history.iter().map(|e| e.sensed.unwrap().0).collect();
+        assert!(sensed.iter().all(|t| *t != 30.0), "{:?}", sensed);
+        assert!(app.history.iter().all(|e| e.sensed.unwrap().1 == 50.0));
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 30)).unwrap();
+        terminal
+            .draw(|f| render_left_panel(f, &app, f.size()))
+            .unwrap();
+        let buffer = terminal.backend().buffer().clone();
+        let top: String = (0..80).map(|x| buffer.get(x, 0).symbol.clone()).collect();
+        assert!(top.contains("sensed"), "{}", top);
+
+        app.set_noise("temperature off");
+        assert_eq!(app.history.last().unwrap().sensed, None);
+    }
+
+    #[test]
+    fn the_rule_matrix_highlights_the_dominant_cell() {
+        let mut app = app_after(&[(40.0, 90.0)]);
+        app.show_matrix = true;
+        let mut terminal = Terminal::new(TestBackend::new(60, 8)).unwrap();
+        terminal
+            .draw(|f| render_right_panel(f, &app, f.size()))
+            .unwrap();
+        let buffer = terminal.backend().buffer().clone();
+        let line = |y| {
+            (0..60)
+                .map(|x| buffer.get(x, y).symbol.clone())
+                .collect::<String>()
+        };
+        assert!(line(0).contains("Rule Matrix"), "{}", line(0));
+        assert!(
+            line(1).contains("temperature \\ humidity  Low"),
+            "{}",
+            line(1)
+        );
+        assert!(line(4).contains("Hot"), "{}", line(4));
+        let highlighted: String = (0..60)
+            .filter(|x| buffer.get(*x, 4).modifier.contains(Modifier::REVERSED))
+            .map(|x| buffer.get(x, 4).symbol.clone())
+            .collect();
+        assert_eq!(highlighted.trim(), "High");
+    }
+
+    #[test]
+    fn the_surface_view_marks_the_operating_point_in_either_style() {
+        let mut app = app_after(&[(30.0, 60.0)]);
+        app.show_surface = true;
+        let screen = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(40, 20)).unwrap();
+            terminal
+                .draw(|f| render_right_panel(f, app, f.size()))
+                .unwrap();
+            let buffer = terminal.backend().buffer().clone();
+            (0..20)
+                .map(|y| (0..40).map(|x| buffer.get(x, y).symbol.clone()).collect())
+                .collect::<Vec<String>>()
+        };
+
+        app.surface_style = SurfaceStyle::Ascii;
+        let ascii = screen(&app);
+        assert!(ascii[0].contains("Control Surface (ascii"), "{}", ascii[0]);
+        assert_eq!(ascii.iter().filter(|l| l.contains('X')).count(), 1);
+        assert!(ascii.iter().any(|l| l.contains('#')));
+
+        app.surface_style = SurfaceStyle::Heatmap;
+        let heatmap = screen(&app);
+        assert_eq!(heatmap.iter().filter(|l| l.contains('X')).count(), 1);
+        assert!(!heatmap.iter().any(|l| l.contains('#')));
+    }
+
+    #[test]
+    fn the_comfort_zone_is_outlined_and_its_share_of_the_time_reported() {
+        let mut app = app_after(&[]);
+        let time = std::rc::Rc::new(std::cell::Cell::new(0.0));
+        app.clock = Box::new(SharedClock(time.clone()));
+        app.controller.system.comfort = Some(crate::comfort::ComfortConfig::default());
+        for (secs, temperature, humidity) in [(0.0, 25.0, 45.0), (30.0, 35.0, 80.0)] {
+            time.set(secs);
+            (app.temperature, app.humidity) = (temperature, humidity);
+            app.compute_fan_speed();
+        }
+        time.set(60.0);
+        assert_eq!(
+            app.comfort_share().as_deref(),
+            Some("50% of the time in the comfort zone")
+        );
+
+        let screen = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(40, 20)).unwrap();
+            terminal
+                .draw(|f| render_right_panel(f, app, f.size()))
+                .unwrap();
+            let buffer = terminal.backend().buffer().clone();
+            (0..20)
+                .map(|y| (0..40).map(|x| buffer.get(x, y).symbol.clone()).collect())
+                .collect::<Vec<String>>()
+        };
+        run_actions(&mut app, &[AppAction::ToggleScatter]);
+        let scatter = screen(&app);
+        assert!(
+            scatter[0].contains("Inputs ('i' hides) · 50%"),
+            "{}",
+            scatter[0]
+        );
+
+        // Only the grid, right of the y axis
+        let outlined = |app: &App| {
+            screen(app).iter().any(|l| {
+                l.split_once('|')
+                    .is_some_and(|(_, cells)| cells.contains('o'))
+            })
+        };
+        app.show_surface = true;
+        for style in [SurfaceStyle::Ascii, SurfaceStyle::Heatmap] {
+            app.surface_style = style;
+            assert!(outlined(&app));
+        }
+        app.controller.system.comfort.as_mut().unwrap().show = false;
+        assert!(!outlined(&app));
+    }
+
+    #[test]
+    fn a_step_table_is_drawn_over_the_surface_until_cleared() {
+        let mut app = app_after(&[(30.0, 60.0)]);
+        app.surface_style = SurfaceStyle::Heatmap;
+        let open = action_for(&app, KeyCode::Char('S')).unwrap();
+        let mut actions = vec![open];
+        actions.extend(typing("4x3"));
+        actions.push(AppAction::Submit);
+        run_actions(&mut app, &actions);
+        assert!(
+            said(&app).starts_with("4x3 step table: worst "),
+            "{}",
+            said(&app)
+        );
+        assert!(app.show_surface);
+        let fit = app.step_table.as_ref().unwrap().fit;
+        let rows = screen(&app, 120, 40);
+        let summary = format!("4x3 step table: {}", fit.summary());
+        assert!(rows.iter().any(|l| l.contains(&summary)), "{}", summary);
+        assert_eq!(
+            rows.iter().map(|l| l.matches('!').count()).sum::<usize>(),
+            1
+        );
+
+        // An edit leaves the table behind the rules it was fitted to
+        app.controller.system.rules.pop();
+        assert!(
+            screen(&app, 120, 40)
+                .iter()
+                .any(|l| l.contains("(stale, 'S' refits)"))
+        );
+
+        let mut actions = vec![AppAction::Prompt(InputMode::Quantize)];
+        actions.extend(typing("4x0"));
+        actions.push(AppAction::Submit);
+        run_actions(&mut app, &actions);
+        assert!(said(&app).starts_with("No step table: "), "{}", said(&app));
+        run_actions(
+            &mut app,
+            &[AppAction::Prompt(InputMode::Quantize), AppAction::Submit],
+        );
+        assert_eq!(said(&app), "Step table cleared");
+        assert!(app.step_table.is_none());
+    }
+
+    #[test]
+    fn the_surface_view_refines_and_starts_over_when_the_rules_change() {
+        let mut app = app_after(&[(30.0, 60.0)]);
+        app.show_surface = true;
+        app.surface_style = SurfaceStyle::Ascii;
+        let screen = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+            terminal
+                .draw(|f| render_right_panel(f, app, f.size()))
+                .unwrap();
+            let buffer = terminal.backend().buffer().clone();
+            (0..40)
+                .map(|y| (0..120).map(|x| buffer.get(x, y).symbol.clone()).collect())
+                .collect::<Vec<String>>()
+        };
+        let settle = |app: &mut App| {
+            while app
+                .surface
+                .borrow()
+                .level()
+                .is_some_and(|(_, refining)| refining)
+            {
+                app.poll_surface();
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        };
+
+        // 20x20 at once, stretched over the 110x33 panel
+        let coarse = screen(&app);
+        assert!(coarse[0].contains("'V' switches) 20x20…"), "{}", coarse[0]);
+        assert_eq!(coarse.iter().filter(|l| l.contains('X')).count(), 1);
+        settle(&mut app);
+        let fine = screen(&app);
+        assert!(fine[0].contains("'V' switches) 110x33─"), "{}", fine[0]);
+        assert_eq!(fine.iter().filter(|l| l.contains('X')).count(), 1);
+        assert_ne!(fine, coarse);
+
+        // A moved set keeps the fine grid, patched where the set reaches
+        let hot = &mut app.controller.system.inputs[0].sets[2];
+        hot.function =
+            crate::MembershipFunction::from_params("trapezoidal", &[28.0, 33.0, 50.0, 50.0])
+                .unwrap();
+        let moved = screen(&app);
+        assert!(moved[0].contains("'V' switches) 110x33─"), "{}", moved[0]);
+        assert_ne!(moved, fine);
+
+        // Another rule base starts from the coarse grid again
+        app.controller.system.rules[0].weight = 0.5;
+        assert!(screen(&app)[0].contains("20x20…"));
+        app.show_surface = false;
+        app.poll_surface();
+        assert!(app.surface.borrow().current().is_none());
+    }
+
+    #[test]
+    fn markers_and_the_regime_strip_follow_the_dominant_rule() {
+        // Cold + Medium (rule 2) dominates at first, Hot + Medium (rule 8)
+        // after the temperature rises
+        let classic = Palette::of(PaletteName::Classic).map(color);
+        let cold = rulecolor::of(&classic, 1);
+        let hot = rulecolor::of(&classic, 7);
+        let draw = |app: &App, render: fn(&mut ratatui::Frame<TestBackend>, &App, Rect)| {
+            let mut terminal = Terminal::new(TestBackend::new(40, 26)).unwrap();
+            terminal.draw(|f| render(f, app, f.size())).unwrap();
+            terminal.backend().buffer().clone()
+        };
+        let cells = |buffer: &ratatui::buffer::Buffer, y: u16| {
+            (0..40)
+                .map(|x| buffer.get(x, y).clone())
+                .collect::<Vec<_>>()
+        };
+
+        let mut readings = vec![(10.0, 50.0); 4];
+        let mut app = app_after(&readings);
+        assert_eq!(app.regime_color(), cold);
+        readings.extend([(40.0, 50.0); 4]);
+        app = app_after(&readings);
+        let dominant: Vec<Option<usize>> = app.history.iter().map(|e| e.dominant).collect();
+        assert_eq!(dominant, [[Some(1); 4], [Some(7); 4]].concat());
+        assert_eq!(app.regime_color(), hot);
+
+        // The strip under each chart, oldest first, and the latest bar
+        let left = draw(&app, render_left_panel);
+        for strip in [7, 15] {
+            let colors: Vec<Color> = cells(&left, strip)
+                .iter()
+                .filter(|cell| cell.symbol == "▀")
+                .map(|cell| cell.fg)
+                .collect();
+            assert_eq!(colors, [[cold; 4], [hot; 4]].concat());
+        }
+        assert_eq!(cells(&left, 6)[39].fg, hot);
+
+        // The surface crosshair and the membership curve markers
+        app.show_surface = true;
+        app.surface_style = SurfaceStyle::Heatmap;
+        let surface = draw(&app, render_right_panel);
+        let marked: Vec<_> = (0..26)
+            .flat_map(|y| cells(&surface, y))
+            .filter(|cell| cell.symbol == "X")
+            .collect();
+        assert_eq!(marked.len(), 1);
+        assert_eq!(marked[0].bg, hot);
+        app.show_surface = false;
+        app.show_curves = true;
+        let curves = draw(&app, render_right_panel);
+        let temperature_chart = (1..8).flat_map(|y| cells(&curves, y));
+        assert!(temperature_chart.clone().any(|cell| cell.fg == hot));
+        assert!(!temperature_chart.clone().any(|cell| cell.fg == cold));
+
+        // The rule table's swatches in the same colors
+        app.show_curves = false;
+        app.show_rules = true;
+        let table = draw(&app, render_right_panel);
+        let swatches: Vec<Color> = (0..26)
+            .flat_map(|y| cells(&table, y))
+            .filter(|cell| cell.symbol == "■")
+            .map(|cell| cell.fg)
+            .collect();
+        assert_eq!(swatches[1], cold);
+        assert_eq!(swatches[7], hot);
+    }
+
+    #[test]
+    fn downsampling_keeps_each_buckets_spike() {
+        assert_eq!(
+            downsample_max(&[1.0, 9.0, 2.0, 3.0, 4.0, 1.0], 3),
+            vec![9.0, 3.0, 4.0]
+        );
+        assert_eq!(downsample_max(&[1.0, 2.0], 4), vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn sparklines_sit_under_the_gauges_of_a_tall_panel() {
+        let readings: Vec<(f64, f64)> = (0..8)
+            .map(|i| (20.0 + i as f64, 80.0 - 5.0 * i as f64))
+            .collect();
+        let rows = left_panel(&app_after(&readings), 24, 26);
+        // The current value is the last column, after the window it ends
+        assert_eq!(
+            rows[5..7],
+            ["max 27.0°C          ▁▃▅█", "min 20.0°C      ▁▃▅▇████"]
+        );
+        assert_eq!(rows[7], format!("{}{}", " ".repeat(16), "▀".repeat(8)));
+        assert_eq!(
+            rows[13..15],
+            ["max 80.0%       █▅▃▁", "min 45.0%       ████▇▅▃▁"]
+        );
+        assert!(rows[8].starts_with("┌💧"));
+        assert!(rows[16].starts_with("┌🌀"));
+    }
+
+    #[test]
+    fn the_chart_axes_follow_their_range_mode_smoothly() {
+        // A steady temperature with one spike
+        let mut readings = vec![(22.0, 50.0); 59];
+        readings[30] = (45.0, 50.0);
+        readings.push((23.0, 50.0));
+        let mut app = app_after(&readings);
+        assert_eq!(app.axes[0].bounds(), (22.0, 45.0));
+
+        app.cycle_range(0);
+        assert_eq!(said(&app), "Temperature chart: 2nd–98th percentile");
+        app.update_axes();
+        let (lo, hi) = app.axes[0].bounds();
+        assert!(lo == 22.0 && hi < 45.0 && hi > 30.0, "{} {}", lo, hi);
+        for _ in 0..autorange::TRANSITION_FRAMES {
+            app.update_axes();
+        }
+        // Between the two highest readings, the spike left out
+        let (lo, hi) = app.axes[0].bounds();
+        assert!(lo == 22.0 && (hi - 22.82).abs() < 1e-9, "{} {}", lo, hi);
+        let rows = left_panel(&app, 24, 26);
+        assert!(rows[5].starts_with("p98 22.8°C"), "{}", rows[5]);
+        assert!(rows[6].starts_with(" p2 22.0°C"), "{}", rows[6]);
+
+        // The humidity chart keeps its own mode
+        app.cycle_range(1);
+        app.cycle_range(1);
+        for _ in 0..autorange::TRANSITION_FRAMES {
+            app.update_axes();
+        }
+        assert_eq!(app.axes[1].bounds(), (0.0, 100.0));
+        assert_eq!(app.range_modes[0], RangeMode::Percentile);
+    }
+
+    #[test]
+    fn sparklines_are_dropped_first_from_a_short_panel() {
+        let readings: Vec<(f64, f64)> = (0..8)
+            .map(|i| (20.0 + i as f64, 80.0 - 5.0 * i as f64))
+            .collect();
+        let rows = left_panel(&app_after(&readings), 24, 17);
+        // Gauges keep their five rows each, the fan gauge the rest
+        assert!(rows[4].starts_with("└"));
+        assert!(rows[5].starts_with("┌💧"));
+        assert!(rows[10].starts_with("┌🌀"));
+        assert_eq!(rows[13], "│ 56.2% ▼2.0 [MEDIUM]  │");
+        assert!(rows.iter().all(|row| !row.contains('█')));
+    }
+
+    #[test]
+    fn status_modes_label_a_point_where_they_disagree() {
+        // 28 °C and 45 % give 60.9 %, inside the MEDIUM band, while the
+        // rules concluding High fire the strongest
+        let label = |status| {
+            let mut system = fixtures::fan();
+            system.pipeline.status = status;
+            let mut app = App::new(FuzzyController::from_system(system));
+            app.temperature = 28.0;
+            app.humidity = 45.0;
+            app.compute_fan_speed();
+            let last = &app.history.last().unwrap().status;
+            assert_eq!(last, &app.status());
+            last.0.clone()
+        };
+        assert_eq!(label(StatusMode::Thresholds), "MEDIUM");
+        assert_eq!(label(StatusMode::DominantSet), "HIGH");
+    }
+
+    fn chart(sets: &[(&str, f64)], scale: BarScale) -> Vec<String> {
+        let sets: Vec<FuzzySet> = sets
+            .iter()
+            .map(|(name, membership)| FuzzySet {
+                name: name.to_string(),
+                membership: *membership,
+            })
+            .collect();
+        let theme = Palette::of(PaletteName::Classic).map(color);
+        let mut terminal = Terminal::new(TestBackend::new(22, 8)).unwrap();
+        terminal
+            .draw(|f| {
+                let area = f.size();
+                render_membership_chart(f, &theme, "Sets", &sets, |_| Color::Cyan, scale, area)
+            })
+            .unwrap();
+        let buffer = terminal.backend().buffer().clone();
+        (0..8)
+            .map(|y| (0..22).map(|x| buffer.get(x, y).symbol.clone()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn a_weak_membership_shows_a_bar_and_its_value() {
+        for scale in [BarScale::Linear, BarScale::Sqrt, BarScale::Log] {
+            let rows = chart(&[("Weak", 0.003), ("Zero", 0.0)], scale);
+            // One row of bar with the value drawn on it; the zero bar stays
+            // empty
+            assert_eq!(rows[6], "│█0.003██            │", "{:?}", scale);
+            assert!(rows[1..6].iter().all(|row| !row.contains('█')));
+        }
+    }
+
+    #[test]
+    fn membership_text_never_rounds_an_activation_to_zero() {
+        assert_eq!(membership_text(0.5), "0.50");
+        assert_eq!(membership_text(0.007), "0.007");
+        assert_eq!(membership_text(0.0002), "<.001");
+    }
+
+    #[test]
+    fn the_rule_preview_marks_the_consequent_centroids() {
+        let mut app = app_after(&[(40.0, 60.0)]);
+        // IF temperature IS Hot AND humidity IS High THEN fan_speed IS High
+        app.selected_rule = Some(8);
+        assert_eq!(app.rule_preview(), None, "hidden without the rule table");
+
+        app.show_rules = true;
+        let (index, full, now) = app.rule_preview().unwrap();
+        assert_eq!(index, 8);
+        assert_eq!(app.rule_strengths[8], 0.5);
+        // High is the triangle [50, 100, 100]: its centroid is 83.3, and
+        // clipped at 0.5 it is a ramp to 75 then flat, centroid 80.6
+        assert_output_close(full, 83.33, 0.5);
+        assert_output_close(now.unwrap(), 80.56, 0.5);
+
+        // A rule that does not fire only has its full-strength marker
+        app.selected_rule = Some(0);
+        assert_eq!(app.rule_preview().unwrap().2, None);
+
+        // Under a weighted average the set counts by its centroid whatever
+        // its strength, so both markers sit there
+        app.controller.system.defuzzification.method = DefuzzMethod::WeightedAverage;
+        app.selected_rule = Some(8);
+        let (_, full, now) = app.rule_preview().unwrap();
+        assert_output_close(full, 83.33, 0.5);
+        assert_eq!(now, Some(full));
+    }
+
+    #[test]
+    fn an_overflow_is_hatched_on_the_gauge_and_kept_in_the_history() {
+        let mut app = app_after(&[(55.0, 60.0)]);
+        let rows = left_panel(&app, 50, 26);
+        assert!(rows[0].contains("▲ above 50, clamped"), "{}", rows[0]);
+        assert!(rows[1].ends_with("╱╱╱╱│"), "{}", rows[1]);
+        assert!(rows[2].contains("55.0°C"), "{}", rows[2]);
+        assert_eq!(
+            app.history.last().unwrap().overflows[0].describe(),
+            "temperature 55 above 50, clamped"
+        );
+        assert!(said(&app).contains("clamped"), "{}", said(&app));
+        // Same output as at the limit
+        let at_limit = app_after(&[(50.0, 60.0)]);
+        assert_eq!(app.fan_speed, at_limit.fan_speed);
+
+        app.temperature = 30.0;
+        app.compute_fan_speed();
+        assert!(app.history.last().unwrap().overflows.is_empty());
+        assert!(!left_panel(&app, 50, 26)[1].contains('╱'));
+
+        app.controller.system.inputs[0].out_of_range = OutOfRange::Error;
+        assert!(app.admit("temperature", 55.0).is_err());
+        assert_eq!(app.admit("temperature", 45.0), Ok(45.0));
+    }
+
+    #[test]
+    fn a_broken_config_or_state_starts_the_tui_in_safe_mode() {
+        let screen = |app: &App| -> String {
+            let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+            terminal.draw(|f| ui(f, app)).unwrap();
+            let buffer = terminal.backend().buffer();
+            (0..40)
+                .map(|y| (0..120).map(|x| buffer.get(x, y).symbol.as_str()).collect())
+                .collect::<Vec<String>>()
+                .join("\n")
+        };
+        let args = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+        let mut app = standalone(&args(&["--config", "tests/data/truncated.toml"])).unwrap();
+        assert_eq!(app.controller.system, fixtures::fan());
+        assert_eq!(app.source, "built-in demo");
+        let error = app.safe_mode.clone().unwrap();
+        assert!(error.starts_with("config error: tests/data/truncated.toml: "));
+        let lines = screen(&app);
+        assert!(
+            lines.contains("SAFE MODE — config error: tests/data/truncated.toml"),
+            "{}",
+            lines
+        );
+        app.toggle_safe_mode_error();
+        assert!(screen(&app).contains("SAFE MODE ('E' or Esc closes)"));
+
+        let app = standalone(&args(&["--config", "tests/data/invalid.toml"])).unwrap();
+        assert!(app.safe_mode.unwrap().contains("a <= b <= c"));
+
+        // An unreadable state file is neither used nor overwritten
+        let state = std::env::temp_dir()
+            .join(format!("fuzzy-tui-safe-state-{}.toml", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        std::fs::write(&state, "[[rule]\ntext = ").unwrap();
+        let mut app = standalone(&args(&["--state", &state])).unwrap();
+        assert!(app.safe_mode.clone().unwrap().starts_with("state error: "));
+        assert!(app.state_path.is_none() && app.journal.is_none());
+        app.save_state().unwrap();
+        assert_eq!(std::fs::read_to_string(&state).unwrap(), "[[rule]\ntext = ");
+        std::fs::remove_file(state).unwrap();
+
+        let mut app = standalone(&[]).unwrap();
+        assert!(app.safe_mode.is_none());
+        app.toggle_safe_mode_error();
+        assert!(!app.show_safe_mode);
+    }
+
+    #[test]
+    fn each_gauge_shows_its_change_since_the_previous_computation() {
+        // The centered labels of the temperature, humidity and fan gauges
+        let labels = |app: &App| -> Vec<String> {
+            left_panel(app, 60, 26)
+                .iter()
+                .map(|row| row.trim_matches(|c| c == '│' || c == ' ').to_string())
+                .filter(|row| row.starts_with(|c: char| c.is_ascii_digit()))
+                .collect()
+        };
+        // Nothing to compare the first computation against
+        let mut app = app_after(&[(30.0, 60.0)]);
+        assert_eq!(labels(&app), ["30.0°C", "60.0%", "80.8% [HIGH]"]);
+
+        app = app_after(&[(30.0, 60.0), (30.4, 60.0)]);
+        assert_eq!(labels(&app), ["30.4°C ▲0.4", "60.0%", "80.8% [HIGH]"]);
+        let buffer = {
+            let mut terminal = Terminal::new(TestBackend::new(60, 26)).unwrap();
+            terminal
+                .draw(|f| render_left_panel(f, &app, f.size()))
+                .unwrap();
+            terminal.backend().buffer().clone()
+        };
+        let arrow = buffer.content().iter().find(|c| c.symbol == "▲").unwrap();
+        assert_eq!(arrow.fg, Color::LightRed);
+
+        app = app_after(&[(30.0, 60.0), (28.0, 45.0)]);
+        assert_eq!(
+            labels(&app),
+            ["28.0°C ▼2.0", "45.0% ▼15.0", "61.5% ▼19.3 [MEDIUM]"]
+        );
+
+        // A change that rounds away at the display precision is not shown
+        app = app_after(&[(30.0, 60.0), (30.04, 60.0)]);
+        assert_eq!(labels(&app), ["30.0°C", "60.0%", "80.8% [HIGH]"]);
+
+        // A cleared history starts over
+        app = app_after(&[(30.0, 60.0), (30.4, 60.0)]);
+        app.clear_history();
+        assert_eq!(labels(&app), ["30.4°C", "60.0%", "80.8% [HIGH]"]);
+    }
+
+    #[test]
+    fn every_panel_shows_values_in_the_display_units() {
+        let mut system = fixtures::fan();
+        system.inputs[0].unit.display = crate::units::Conversion::known("°C", "°F");
+        let mut app = App::new(FuzzyController::from_system(system));
+        app.temperature = 30.0;
+        app.humidity = 60.0;
+        app.compute_fan_speed();
+
+        let rows = left_panel(&app, 60, 26);
+        assert!(rows[0].contains("Temperature (°F)"), "{}", rows[0]);
+        assert!(rows[2].contains("86.0°F"), "{}", rows[2]);
+        assert!(rows.iter().any(|r| r.contains("Humidity (%)")));
+        assert!(rows.iter().any(|r| r.contains("60.0%")));
+        assert!(rows.iter().any(|r| r.contains("Fan Speed (%)")));
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 7)).unwrap();
+        terminal
+            .draw(|f| render_history(f, &app, f.size()))
+            .unwrap();
+        let history: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect();
+        assert!(
+            history.contains("T: 86.0°F H: 60.0% → Fan: "),
+            "{}",
+            history
+        );
+
+        // Typed values are converted back to the storage unit
+        assert_eq!(app.unit("temperature").to_stored(95.0), 35.0);
+        app.toggle_units();
+        assert!(left_panel(&app, 60, 26)[2].contains("30.0°C"));
+        assert_eq!(app.unit("temperature").to_stored(35.0), 35.0);
+        assert_eq!(app.show("damper", 0.5), "0.5");
+    }
+
+    #[test]
+    fn reloading_the_rule_csv_keeps_the_usage_of_surviving_rules() {
+        let config =
+            std::env::temp_dir().join(format!("fuzzy-tui-csv-{}.toml", std::process::id()));
+        let config = config.to_string_lossy().to_string();
+        let csv = rulecsv::path_for(&config);
+        let journal_path = journal::path_for(&config);
+        // Hot and humid fires the last rule only
+        let mut app = app_after(&[(35.0, 80.0)]);
+        app.source = config.clone();
+        app.journal = Some(journal::Journal::open(&journal_path).unwrap());
+        let hot = app.controller.system.rules[8].to_text();
+        let fired = app.rule_stats.get(8).and_then(|u| u.first_fired);
+        assert!(fired.is_some());
+
+        app.show_rules = true;
+        app.reload_rules_csv();
+        assert!(said(&app).starts_with("Edit failed:"), "{}", said(&app));
+        assert_eq!(app.controller.system.rules.len(), 9);
+
+        std::fs::write(
+            &csv,
+            "temperature,humidity,fan_speed\nHot,High,High\nCold,,Off\n",
+        )
+        .unwrap();
+        app.reload_rules_csv();
+        let entries = journal::read(&journal_path).unwrap();
+        std::fs::remove_file(&csv).unwrap();
+        std::fs::remove_file(&journal_path).unwrap();
+        assert_eq!(said(&app), format!("Edited: rules from {}", csv));
+        assert_eq!(app.controller.system.rule_texts()[0], hot);
+        // Recomputed since, yet first fired before the reload
+        assert_eq!(app.rule_stats.get(0).and_then(|u| u.first_fired), fired);
+        assert_eq!(app.rule_stats.get(1).and_then(|u| u.first_fired), None);
+        assert_eq!(entries, vec![Ok(journal::Edit::RulesCsv { path: csv })]);
+    }
+
+    #[test]
+    fn the_shutdown_removes_the_journal_and_saves_the_state() {
+        let path = std::env::temp_dir().join(format!("fuzzy-tui-exit-{}.toml", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let journal_path = journal::path_for(&path);
+        let mut app = app_after(&[(35.0, 80.0)]);
+        app.journal = Some(journal::Journal::open(&journal_path).unwrap());
+        app.state_path = Some(path.clone());
+
+        let mut coordinator = Coordinator::default();
+        coordinator.register(
+            Stage::StopInputs,
+            "slow reader",
+            std::time::Duration::from_millis(50),
+            || {
+                std::thread::sleep(std::time::Duration::from_secs(30));
+                Ok(())
+            },
+        );
+        app.register_shutdown(&mut coordinator);
+        let report = coordinator.shutdown();
+        assert_eq!(report.problems().len(), 1);
+
+        assert!(!std::path::Path::new(&journal_path).exists());
+        let rules = app.controller.system.rule_texts();
+        let saved = RuleUsageStats::load(&path, rules.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for i in 0..rules.len() {
+            assert_eq!(saved.get(i), app.rule_stats.get(i));
+        }
+    }
+
+    #[test]
+    fn a_held_arrow_key_accelerates_into_one_history_entry() {
+        let mut app = app_after(&[(10.0, 50.0)]);
+        let time = std::rc::Rc::new(std::cell::Cell::new(100.0));
+        app.clock = Box::new(SharedClock(time.clone()));
+        let entries = app.history.len();
+        for _ in 0..19 {
+            time.set(time.get() + 0.03);
+            app.nudge("temperature", nudge::Direction::Up);
+        }
+        assert_eq!(app.temperature, 36.0);
+        assert_eq!(app.history.len(), entries + 1);
+        assert_eq!(app.history.last().unwrap().temperature, 36.0);
+        assert_eq!(said(&app), "temperature ▲ 36.0°C (step 5.0°C)");
+
+        // After a pause the next press is a new, small change
+        time.set(time.get() + 2.0);
+        app.nudge("temperature", nudge::Direction::Down);
+        assert_eq!(app.temperature, 35.5);
+        assert_eq!(app.history.len(), entries + 2);
+
+        // Nudges stay inside the universe
+        for _ in 0..40 {
+            time.set(time.get() + 0.03);
+            app.nudge("humidity", nudge::Direction::Up);
+        }
+        assert_eq!(app.humidity, 100.0);
+        assert_eq!(app.history.len(), entries + 3);
+    }
+
+    #[test]
+    fn bookmarks_are_jumped_to_and_flag_drift_in_their_popup() {
+        let mut app = app_after(&[(27.5, 64.0)]);
+        app.add_bookmark("oscillates");
+        let recorded = app.fan_speed;
+        app.add_bookmark("oscillates");
+        assert!(said(&app).contains("exists"), "{}", said(&app));
+
+        app.temperature = 5.0;
+        app.humidity = 20.0;
+        app.compute_fan_speed();
+        app.open_bookmarks();
+        app.jump_to_bookmark();
+        assert_eq!((app.temperature, app.humidity), (27.5, 64.0));
+        assert_eq!(app.fan_speed, recorded);
+        assert!(matches!(app.input_mode, InputMode::Menu));
+
+        // Summing the rules moves the bookmarked point
+        app.edit(journal::Edit::Aggregation {
+            aggregation: Aggregation::BoundedSum,
+            temperature: 0.1,
+        });
+        app.open_bookmarks();
+        let mut terminal = Terminal::new(TestBackend::new(100, 40)).unwrap();
+        terminal.draw(|f| ui(f, &app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let screen: String = (0..40)
+            .map(|y| {
+                (0..100)
+                    .map(|x| buffer.get(x, y).symbol.as_str())
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert!(screen.contains("Bookmarks"), "{}", screen);
+        let row = screen.lines().find(|l| l.contains("oscillates")).unwrap();
+        assert!(row.contains("27.5, 64.0"), "{}", row);
+        assert!(row.contains("⚠ drift"), "{}", row);
+
+        app.rename_bookmark("wobbles");
+        assert_eq!(app.bookmarks.list()[0].name, "wobbles");
+        app.delete_bookmark();
+        assert!(app.bookmarks.is_empty());
+        assert!(matches!(app.input_mode, InputMode::Menu));
+    }
+
+    #[test]
+    fn annotated_states_are_frozen_browsed_jumped_to_and_exported() {
+        let path =
+            std::env::temp_dir().join(format!("fuzzy-tui-notes-{}.toml", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let mut app = app_after(&[(27.5, 64.0)]);
+        let press = |app: &mut App, key| update(app, action_for(app, key).unwrap());
+        press(&mut app, KeyCode::Char('j'));
+        assert!(
+            said(&app).starts_with("No annotations yet"),
+            "{}",
+            said(&app)
+        );
+
+        // The state is frozen when the prompt opens, not when it is answered
+        press(&mut app, KeyCode::Char('a'));
+        let recorded = app.fan_speed;
+        app.temperature = 5.0;
+        app.compute_fan_speed();
+        run_actions(&mut app, &typing("fan jumps"));
+        press(&mut app, KeyCode::Enter);
+        assert!(said(&app).ends_with("for this session only: see --state"));
+        let annotation = app.annotations.list()[0].clone();
+        assert_eq!(annotation.inputs, [27.5, 64.0]);
+        assert_eq!(annotation.outputs[0], recorded);
+
+        app.state_path = Some(path.clone());
+        app.frozen = None;
+        press(&mut app, KeyCode::Char('a'));
+        press(&mut app, KeyCode::Esc);
+        assert!(app.frozen.is_none() && app.annotations.len() == 1);
+        press(&mut app, KeyCode::Char('a'));
+        run_actions(&mut app, &typing("cold"));
+        press(&mut app, KeyCode::Enter);
+        let saved = Annotations::load(&annotations::path_for(&path)).unwrap();
+        assert_eq!(saved, app.annotations);
+
+        // Another config marks both possibly stale
+        app.edit(journal::Edit::Aggregation {
+            aggregation: Aggregation::BoundedSum,
+            temperature: 0.1,
+        });
+        press(&mut app, KeyCode::Char('j'));
+        let rows = screen(&app, 100, 40);
+        let cold = rows.iter().position(|l| l.contains("cold")).unwrap();
+        let jumps = rows.iter().position(|l| l.contains("fan jumps")).unwrap();
+        assert!(cold < jumps, "newest first");
+        assert!(rows[jumps].contains("⚠ possibly stale"), "{}", rows[jumps]);
+
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Char('J'));
+        let json = std::fs::read_to_string(format!("{}.annotations.json", path)).unwrap();
+        assert!(json.contains("\"note\": \"fan jumps\""));
+        press(&mut app, KeyCode::Char('H'));
+        let html = std::fs::read_to_string(format!("{}.annotations.html", path)).unwrap();
+        assert!(html.contains("(possibly stale)"));
+        for suffix in ["toml", "json", "html"] {
+            std::fs::remove_file(format!("{}.annotations.{}", path, suffix)).unwrap();
+        }
+
+        press(&mut app, KeyCode::Enter);
+        assert_eq!((app.temperature, app.humidity), (27.5, 64.0));
+        assert!(said(&app).ends_with("possibly stale"), "{}", said(&app));
+        assert!(matches!(app.input_mode, InputMode::Menu));
+    }
+
+    /// `app` after each of `actions` in turn
+    fn run_actions(app: &mut App, actions: &[AppAction]) {
+        for action in actions {
+            update(app, *action);
+        }
+    }
+
+    /// The actions typing `text` into the open prompt
+    fn typing(text: &str) -> Vec<AppAction> {
+        text.chars().map(AppAction::Type).collect()
+    }
+
+    #[test]
+    fn keys_translate_to_actions_by_mode() {
+        let mut app = app_after(&[(22.0, 50.0)]);
+        let cases = [
+            (
+                InputMode::Menu,
+                false,
+                KeyCode::Char('q'),
+                Some(AppAction::Quit),
+            ),
+            (
+                InputMode::Menu,
+                false,
+                KeyCode::Char('t'),
+                Some(AppAction::Prompt(InputMode::Temperature)),
+            ),
+            (
+                InputMode::Menu,
+                false,
+                KeyCode::Up,
+                Some(AppAction::Nudge("temperature", nudge::Direction::Up)),
+            ),
+            (
+                InputMode::Menu,
+                true,
+                KeyCode::Up,
+                Some(AppAction::SelectRule(-1)),
+            ),
+            (
+                InputMode::Menu,
+                false,
+                KeyCode::Char('+'),
+                Some(AppAction::Zoom(viewport::ZOOM_STEP)),
+            ),
+            (
+                InputMode::Menu,
+                true,
+                KeyCode::Char('+'),
+                Some(AppAction::StepGroupWeight(0.1)),
+            ),
+            (InputMode::Menu, false, KeyCode::Char('3'), None),
+            (
+                InputMode::Menu,
+                true,
+                KeyCode::Char('3'),
+                Some(AppAction::ToggleGroupFold('3')),
+            ),
+            (
+                InputMode::Menu,
+                false,
+                KeyCode::Esc,
+                Some(AppAction::Cancel),
+            ),
+            (InputMode::Menu, false, KeyCode::Enter, None),
+            (
+                InputMode::Temperature,
+                false,
+                KeyCode::Char('q'),
+                Some(AppAction::Type('q')),
+            ),
+            (
+                InputMode::Humidity,
+                false,
+                KeyCode::Backspace,
+                Some(AppAction::Backspace),
+            ),
+            (
+                InputMode::Noise,
+                false,
+                KeyCode::Enter,
+                Some(AppAction::Submit),
+            ),
+            (InputMode::Inject, false, KeyCode::Up, None),
+            (
+                InputMode::Bookmarks,
+                false,
+                KeyCode::Char('k'),
+                Some(AppAction::Cancel),
+            ),
+            (
+                InputMode::Bookmarks,
+                false,
+                KeyCode::Down,
+                Some(AppAction::SelectBookmark(1)),
+            ),
+            (
+                InputMode::Bookmarks,
+                false,
+                KeyCode::Char('r'),
+                Some(AppAction::Prompt(InputMode::RenameBookmark)),
+            ),
+            (
+                InputMode::RenameBookmark,
+                false,
+                KeyCode::Char('k'),
+                Some(AppAction::Type('k')),
+            ),
+            (
+                InputMode::Recover,
+                false,
+                KeyCode::Char('y'),
+                Some(AppAction::Recover(true)),
+            ),
+            (InputMode::Recover, false, KeyCode::Esc, None),
+            (
+                InputMode::Menu,
+                false,
+                KeyCode::Char('D'),
+                Some(AppAction::OpenDiff),
+            ),
+            (
+                InputMode::Diff,
+                false,
+                KeyCode::Char('R'),
+                Some(AppAction::RevertAll),
+            ),
+            (InputMode::Diff, false, KeyCode::Char('r'), None),
+        ];
+        for (mode, show_rules, key, expected) in cases {
+            app.input_mode = mode;
+            app.show_rules = show_rules;
+            assert_eq!(
+                action_for(&app, key),
+                expected,
+                "{:?} in {:?}, rules shown {}",
+                key,
+                mode,
+                show_rules
+            );
+        }
+    }
+
+    #[test]
+    fn ctrl_z_keeps_an_open_prompt_and_focus_loss_pauses_when_asked() {
+        let mut app = app_after(&[(22.0, 50.0)]);
+        let mut actions = vec![AppAction::Prompt(InputMode::Temperature)];
+        actions.extend(typing("31"));
+        run_actions(&mut app, &actions);
+        let ctrl_z = KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL);
+        let suspend = key_action(&app, ctrl_z);
+        assert_eq!(suspend, Some(AppAction::Suspend));
+        update(&mut app, AppAction::Suspend);
+        assert!(app.suspend_requested);
+        assert_eq!(app.input_mode, InputMode::Temperature);
+        assert_eq!(app.input_buffer, "31");
+        let z = KeyEvent::new(KeyCode::Char('z'), KeyModifiers::NONE);
+        assert_eq!(key_action(&app, z), Some(AppAction::Type('z')));
+
+        // Focus changes mean nothing unless the config asks
+        update(&mut app, AppAction::Focus(false));
+        assert!(!app.paused);
+        app.controller.system.pause_on_blur = true;
+        update(&mut app, AppAction::Focus(false));
+        assert!(app.paused);
+        assert_eq!(said(&app), "Paused while the terminal is out of focus");
+        update(&mut app, AppAction::Focus(false));
+        update(&mut app, AppAction::Focus(true));
+        assert!(!app.paused);
+        assert_eq!(said(&app), "Focus back, simulation running");
+    }
+
+    #[test]
+    fn typed_readings_are_set_refused_or_cancelled() {
+        let mut app = app_after(&[(22.0, 50.0)]);
+        let mut actions = vec![AppAction::Prompt(InputMode::Temperature)];
+        actions.extend(typing("31.x"));
+        actions.extend([
+            AppAction::Backspace,
+            AppAction::Type('5'),
+            AppAction::Submit,
+        ]);
+        run_actions(&mut app, &actions);
+        assert_eq!(app.temperature, 31.5);
+        assert_eq!(app.input_mode, InputMode::Menu);
+        assert!(app.input_buffer.is_empty());
+        assert!(
+            said(&app).starts_with("Temperature set to"),
+            "{}",
+            said(&app)
+        );
+        let computed = app.history.len();
+
+        // Enter on an empty buffer
+        run_actions(
+            &mut app,
+            &[AppAction::Prompt(InputMode::Humidity), AppAction::Submit],
+        );
+        assert_eq!(said(&app), "Invalid input! Try again.");
+        assert_eq!(app.input_mode, InputMode::Menu);
+        assert_eq!(app.humidity, 50.0);
+
+        // Esc mid-entry keeps the reading and empties the buffer
+        let mut actions = vec![AppAction::Prompt(InputMode::Humidity)];
+        actions.extend(typing("7"));
+        actions.push(AppAction::Cancel);
+        run_actions(&mut app, &actions);
+        assert_eq!(said(&app), "Cancelled.");
+        assert_eq!(app.input_mode, InputMode::Menu);
+        assert!(app.input_buffer.is_empty());
+        assert_eq!(app.humidity, 50.0);
+        assert_eq!(app.history.len(), computed);
+
+        // Outside a prompt typing does nothing, and Esc drops the
+        // selection
+        app.selected_rule = Some(2);
+        run_actions(&mut app, &[AppAction::Type('5'), AppAction::Cancel]);
+        assert!(app.input_buffer.is_empty());
+        assert_eq!(app.selected_rule, None);
+    }
+
+    #[test]
+    fn group_weights_step_by_tenths_within_zero_and_one() {
+        let mut app = app_after(&[(22.0, 50.0)]);
+        update(&mut app, AppAction::StepGroupWeight(0.1));
+        assert_eq!(said(&app), "Select a rule of a group with ↑/↓ first");
+
+        app.controller.system.groups.push(crate::RuleGroup {
+            name: "comfort".to_string(),
+            enabled: true,
+            weight: 0.9,
+        });
+        app.controller.system.rules[0].group = Some("comfort".to_string());
+        app.selected_rule = Some(0);
+        let weight = |app: &App| app.controller.system.group("comfort").unwrap().weight;
+        run_actions(&mut app, &[AppAction::StepGroupWeight(0.1); 3]);
+        assert_eq!(weight(&app), 1.0);
+        run_actions(&mut app, &[AppAction::StepGroupWeight(-0.1); 12]);
+        assert_eq!(weight(&app), 0.0);
+        update(&mut app, AppAction::StepGroupWeight(0.1));
+        assert_eq!(weight(&app), 0.1);
+
+        update(&mut app, AppAction::ToggleSelectedGroup);
+        assert!(!app.controller.system.group("comfort").unwrap().enabled);
+        // The softmax temperature stays within its bounds
+        run_actions(&mut app, &[AppAction::ScaleSoftmaxTemperature(2.0); 20]);
+        assert_eq!(
+            app.controller.system.defuzzification.softmax_temperature,
+            100.0
+        );
+    }
+
+    #[test]
+    fn the_bookmark_popup_and_its_rename_prompt_back_out_one_level() {
+        let mut app = app_after(&[(22.0, 50.0)]);
+        let mut actions = vec![AppAction::Prompt(InputMode::Bookmark)];
+        actions.extend(typing("mild"));
+        actions.extend([AppAction::Submit, AppAction::OpenBookmarks]);
+        actions.push(AppAction::Prompt(InputMode::RenameBookmark));
+        actions.extend(typing("calm"));
+        actions.push(AppAction::Cancel);
+        run_actions(&mut app, &actions);
+        assert_eq!(app.input_mode, InputMode::Bookmarks);
+        assert_eq!(said(&app), "Cancelled.");
+        assert_eq!(app.bookmarks.list()[0].name, "mild");
+
+        let mut actions = vec![AppAction::Prompt(InputMode::RenameBookmark)];
+        actions.extend(typing("calm"));
+        actions.push(AppAction::Submit);
+        run_actions(&mut app, &actions);
+        assert_eq!(app.bookmarks.list()[0].name, "calm");
+
+        update(&mut app, AppAction::Cancel);
+        assert_eq!(app.input_mode, InputMode::Menu);
+        assert_eq!(said(&app), "Bookmarks closed.");
+        assert!(!app.quit);
+        update(&mut app, AppAction::Quit);
+        assert!(app.quit);
+    }
+
+    /// The demo with rule 1 in group "comfort" and a "quiet" cap profile
+    fn editable_app() -> App {
+        let mut system = fixtures::fan();
+        system.groups.push(crate::RuleGroup {
+            name: "comfort".to_string(),
+            enabled: true,
+            weight: 1.0,
+        });
+        system.rules[0].group = Some("comfort".to_string());
+        system.defuzzification.caps.push(crate::ConsequentCap {
+            profile: "quiet".to_string(),
+            output: "fan_speed".to_string(),
+            set: "High".to_string(),
+            max: 0.4,
+        });
+        App::new(FuzzyController::from_system(system))
+    }
+
+    /// The open diff popup, once its worker is done
+    fn reviewed(app: &mut App) -> &Review {
+        while app.review.as_ref().is_some_and(|r| r.deviation.is_none()) {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            app.poll_review();
+        }
+        app.review.as_ref().unwrap()
+    }
+
+    #[test]
+    fn the_diff_popup_lists_each_kind_of_edit() {
+        let csv = std::env::temp_dir().join(format!("fuzzy-tui-diff-{}.csv", std::process::id()));
+        let csv = csv.to_string_lossy().to_string();
+        std::fs::write(&csv, "temperature,humidity,fan_speed\nHot,,High\n").unwrap();
+        let cases = [
+            (
+                journal::Edit::GroupEnabled {
+                    group: "comfort".to_string(),
+                    enabled: false,
+                },
+                vec!["~ group 'comfort' enabled: true -> false"],
+            ),
+            (
+                journal::Edit::GroupWeight {
+                    group: "comfort".to_string(),
+                    weight: 0.5,
+                },
+                vec!["~ group 'comfort' weight: 1 -> 0.5"],
+            ),
+            (
+                journal::Edit::Aggregation {
+                    aggregation: Aggregation::SoftMax,
+                    temperature: 0.2,
+                },
+                vec![
+                    "~ defuzzification aggregation: max -> softmax",
+                    "~ defuzzification softmax_temperature: 0.1 -> 0.2",
+                ],
+            ),
+            (
+                journal::Edit::CapProfile {
+                    profile: Some("quiet".to_string()),
+                },
+                vec!["~ defuzzification cap_profile: none -> quiet"],
+            ),
+        ];
+        for (edit, expected) in cases {
+            let mut app = editable_app();
+            update(&mut app, AppAction::OpenDiff);
+            assert_eq!(said(&app), "No changes since the system was loaded");
+            assert_eq!(app.input_mode, InputMode::Menu);
+
+            app.edit(edit.clone());
+            update(&mut app, AppAction::OpenDiff);
+            assert_eq!(app.input_mode, InputMode::Diff);
+            let review = reviewed(&mut app);
+            assert_eq!(review.changes, expected, "{:?}", edit);
+            let (max, mean) = review.deviation.clone().unwrap().unwrap();
+            assert!(max > 0.0 && mean > 0.0 && mean <= max, "{:?}", edit);
+        }
+
+        // A rule reload removes every rule but one, which joins no group
+        let mut app = editable_app();
+        let before = app.controller.system.rule_texts();
+        app.edit(journal::Edit::RulesCsv { path: csv.clone() });
+        std::fs::remove_file(&csv).unwrap();
+        update(&mut app, AppAction::OpenDiff);
+        let changes = &reviewed(&mut app).changes;
+        assert_eq!(changes.len(), before.len() + 1, "{:?}", changes);
+        assert!(
+            changes.contains(&"+ rule IF temperature IS Hot THEN fan_speed IS High".to_string())
+        );
+        assert!(changes.contains(&format!("- rule [comfort] {}", before[0])));
+
+        // Closing the popup keeps the edits
+        update(&mut app, AppAction::Cancel);
+        assert!(app.review.is_none() && app.is_modified());
+    }
+
+    #[test]
+    fn revert_all_restores_the_loaded_system_and_empties_the_journal() {
+        let path = std::env::temp_dir().join(format!("fuzzy-tui-revert-{}", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let mut app = editable_app();
+        let loaded = app.controller.system.clone();
+        // Hot and humid, where the cap holds the output down
+        (app.temperature, app.humidity) = (35.0, 80.0);
+        app.compute_fan_speed();
+        let fan_speed = app.fan_speed;
+        app.journal = Some(journal::Journal::open(&path).unwrap());
+        app.selected_rule = Some(0);
+        run_actions(
+            &mut app,
+            &[
+                AppAction::ToggleSelectedGroup,
+                AppAction::StepGroupWeight(-0.1),
+                AppAction::CycleAggregation,
+                AppAction::CycleCaps,
+                // Only the popup reverts
+                AppAction::RevertAll,
+            ],
+        );
+        assert_eq!(journal::read(&path).unwrap().len(), 4);
+        assert_ne!(app.fan_speed, fan_speed);
+        let title = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+            terminal.draw(|f| ui(f, app)).unwrap();
+            let buffer = terminal.backend().buffer();
+            (0..120)
+                .map(|x| buffer.get(x, 1).symbol.as_str())
+                .collect::<String>()
+        };
+        assert!(title(&app).contains("✎ modified"), "{}", title(&app));
+
+        run_actions(&mut app, &[AppAction::OpenDiff, AppAction::RevertAll]);
+        let entries = journal::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(app.controller.system, loaded);
+        assert!(entries.is_empty(), "{:?}", entries);
+        assert_eq!(app.fan_speed, fan_speed);
+        assert_eq!(app.input_mode, InputMode::Menu);
+        assert!(app.review.is_none());
+        assert_eq!(said(&app), "Reverted to the system as loaded");
+        assert!(!title(&app).contains("modified"));
+    }
+
+    #[test]
+    fn saving_keeps_a_backup_the_popup_restores() {
+        let dir = std::env::temp_dir().join(format!("fuzzy-tui-save-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("fan.toml").to_string_lossy().to_string();
+        std::fs::write(
+            &path,
+            config::to_toml(&editable_app().controller.system, false),
+        )
+        .unwrap();
+        let args = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let mut app = standalone(&args(&["--config", &path, "--backups", "2"])).unwrap();
+        let loaded = app.controller.system.clone();
+        run_actions(
+            &mut app,
+            &[AppAction::CycleAggregation, AppAction::SaveConfig],
+        );
+        assert_eq!(said(&app), format!("Saved {}", path));
+        assert!(!app.is_modified());
+        assert_eq!(config::load_system(&path).unwrap(), app.controller.system);
+
+        // Unsaved edits are not overwritten by a restore
+        run_actions(&mut app, &[AppAction::CycleCaps, AppAction::OpenBackups]);
+        assert_eq!(app.input_mode, InputMode::Backups);
+        run_actions(&mut app, &[AppAction::Submit]);
+        assert!(said(&app).starts_with("Unsaved changes"), "{}", said(&app));
+        run_actions(&mut app, &[AppAction::Cancel, AppAction::CycleCaps]);
+        assert!(!app.is_modified());
+
+        run_actions(&mut app, &[AppAction::OpenBackups, AppAction::Submit]);
+        assert_eq!(said(&app), format!("Restored bak1 to {}", path));
+        assert_eq!(app.input_mode, InputMode::Menu);
+        assert_eq!(app.controller.system, loaded);
+        assert_eq!(config::load_system(&path).unwrap(), loaded);
+        assert_eq!(backup::list(&path).len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn d_cycles_the_method_of_the_output_on_screen_past_what_it_cannot_take() {
+        let mut app = app_after(&[(27.5, 64.0)]);
+        let method = |app: &App| {
+            let system = &app.controller.system;
+            system.defuzzification.method_for(system.output())
+        };
+        assert_eq!(
+            action_for(&app, KeyCode::Char('d')),
+            Some(AppAction::CycleMethod)
+        );
+        let centroid = app.fan_speed;
+        update(&mut app, AppAction::CycleMethod);
+        assert_eq!(method(&app), DefuzzMethod::PlateauCentroid);
+        assert_eq!(said(&app), "Edited: fan_speed by plateau_centroid");
+        update(&mut app, AppAction::CycleMethod);
+        assert_eq!(method(&app), DefuzzMethod::WeightedAverage);
+        assert_ne!(app.fan_speed, centroid);
+        // Nothing but max aggregation goes with the weighted average
+        update(&mut app, AppAction::CycleAggregation);
+        assert!(said(&app).starts_with("Edit failed: output 'fan_speed'"));
+        update(&mut app, AppAction::CycleMethod);
+        assert_eq!(method(&app), DefuzzMethod::Centroid);
+        assert_eq!(app.fan_speed, centroid);
+        update(&mut app, AppAction::CycleAggregation);
+        update(&mut app, AppAction::CycleMethod);
+        update(&mut app, AppAction::CycleMethod);
+        assert_eq!(method(&app), DefuzzMethod::Centroid);
+        // The controller's method is untouched
+        assert_eq!(
+            app.controller.system.defuzzification.method,
+            DefuzzMethod::Centroid
+        );
+    }
+
+    #[test]
+    fn the_status_bar_keeps_warnings_and_errors_in_view() {
+        let mut app = app_after(&[(22.0, 50.0)]);
+        let time = std::rc::Rc::new(std::cell::Cell::new(0.0));
+        app.clock = Box::new(SharedClock(time.clone()));
+        assert_eq!(status_line(&app).0, IDLE_HINT);
+
+        // A clamped reading is not covered by the random values after it
+        run_actions(&mut app, &[AppAction::Prompt(InputMode::Temperature)]);
+        assert!(status_line(&app).0.starts_with("Enter temperature"));
+        let mut actions = typing("500");
+        actions.extend([AppAction::Submit, AppAction::Random]);
+        run_actions(&mut app, &actions);
+        assert!(said(&app).starts_with("Generated random values"));
+        let (text, style) = status_line(&app);
+        assert!(text.starts_with("⚠ temperature 500 above 50"), "{}", text);
+        assert!(text.ends_with("+2 more, 'M' lists them"), "{}", text);
+        assert_eq!(style, severity_style(&app.theme, Severity::Warning));
+        time.set(inbox::WARNING_SECS);
+        assert_eq!(status_line(&app).0, IDLE_HINT);
+
+        // An error stays until 'k' or a click on the status bar; 'k' opens
+        // the bookmarks again after
+        app.error("Lost connection to the daemon: broken pipe");
+        app.error("Could not write the bug report: read-only");
+        time.set(1000.0);
+        assert!(status_line(&app).0.contains("('k' acknowledges)"));
+        assert_eq!(
+            action_for(&app, KeyCode::Char('k')),
+            Some(AppAction::Acknowledge)
+        );
+        update(&mut app, AppAction::Acknowledge);
+        assert!(status_line(&app).0.starts_with("Lost connection"));
+        let click = |row| MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column: 10,
+            row,
+            modifiers: crossterm::event::KeyModifiers::NONE,
+        };
+        assert_eq!(click_action(&app, click(10), 40), None);
+        assert_eq!(
+            click_action(&app, click(38), 40),
+            Some(AppAction::Acknowledge)
+        );
+        update(&mut app, AppAction::Acknowledge);
+        assert_eq!(status_line(&app).0, IDLE_HINT);
+        assert_eq!(click_action(&app, click(38), 40), None);
+        assert_eq!(
+            action_for(&app, KeyCode::Char('k')),
+            Some(AppAction::OpenBookmarks)
+        );
+
+        // Every message is still in the popup, newest first
+        update(&mut app, AppAction::OpenMessages);
+        assert_eq!(app.input_mode, InputMode::Messages);
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        terminal.draw(|f| ui(f, &app)).unwrap();
+        let screen: Vec<String> = (0..40)
+            .map(|y| {
+                (0..120)
+                    .map(|x| terminal.backend().buffer().get(x, y).symbol.as_str())
+                    .collect()
+            })
+            .collect();
+        let rows: Vec<&String> = screen
+            .iter()
+            .filter(|r| {
+                ["·", "⚠", "✔", "✖"]
+                    .iter()
+                    .any(|m| r.contains(&format!("s ago {}", m)))
+            })
+            .collect();
+        assert_eq!(rows.len(), app.inbox.len());
+        assert!(rows[0].contains("✔ Could not write"), "{}", rows[0]);
+        assert!(rows[2].contains("· Generated random"), "{}", rows[2]);
+        assert!(rows[3].contains("⚠ ⚠ temperature 500"), "{}", rows[3]);
+        assert!(rows[4].contains("· Temperature set"), "{}", rows[4]);
+        update(&mut app, AppAction::Cancel);
+        assert_eq!(app.input_mode, InputMode::Menu);
+    }
+
+    #[test]
+    fn the_objective_panel_shows_the_score_and_its_records() {
+        let mut system = fixtures::fan();
+        let score = crate::alarm::Expression::parse("abs(temperature - 22)", &system).unwrap();
+        system.objective = Some(crate::objective::ObjectiveConfig {
+            score,
+            better: crate::objective::Better::Lower,
+        });
+        let mut app = App::new(FuzzyController::from_system(system));
+        for temperature in [30.0, 22.0, 26.0] {
+            app.temperature = temperature;
+            app.compute_fan_speed();
+        }
+        // 25 at the start, then 30, 22 and 26
+        let rows = left_panel(&app, 90, 30);
+        let at = rows
+            .iter()
+            .position(|row| row.contains("Objective (lower is better): abs(temperature - 22)"))
+            .unwrap();
+        assert!(
+            rows[at + 1].contains("4.000 · mean 3.750 over 4"),
+            "{}",
+            rows[at + 1]
+        );
+        assert!(
+            rows[at + 2].starts_with("│best  0.000 at "),
+            "{}",
+            rows[at + 2]
+        );
+        assert!(rows[at + 2].contains(" · temperature 22.0°C · humidity 50.0%"));
+        assert!(
+            rows[at + 3].starts_with("│worst 8.000 at "),
+            "{}",
+            rows[at + 3]
+        );
+        assert!(rows[at + 4].starts_with('└'));
+        // Too short for the panel: the score goes on the output bar
+        let rows = left_panel(&app, 90, 17);
+        assert!(
+            rows.iter()
+                .any(|row| row.contains("[MEDIUM] · objective 4.000"))
+        );
+    }
+
+    #[test]
+    fn the_tutorial_lights_one_panel_per_step_with_live_numbers() {
+        let mut app = app_after(&[(27.5, 64.0)]);
+        app.show_surface = true;
+        run_actions(
+            &mut app,
+            &[AppAction::OpenTutorial, AppAction::StepTutorial(1)],
+        );
+        assert_eq!(app.input_mode, InputMode::Tutorial);
+        assert!(!app.show_surface && !app.show_rules && !app.show_curves);
+
+        // Rows of the explanation box, and whether a cell is dimmed
+        let draw = |app: &App| {
+            let mut terminal = Terminal::new(TestBackend::new(100, 40)).unwrap();
+            terminal.draw(|f| ui(f, app)).unwrap();
+            let buffer = terminal.backend().buffer().clone();
+            let rows: Vec<String> = (25..37)
+                .map(|y| {
+                    (0..100)
+                        .map(|x| buffer.get(x, y).symbol.as_str())
+                        .collect::<String>()
+                        .trim_end()
+                        .to_string()
+                })
+                .collect();
+            (rows, buffer)
+        };
+
+        let (rows, buffer) = draw(&app);
+        assert_eq!(
+            rows[..4],
+            [
+                "┌ Tutorial 2/5: Fuzzification ─────────────────────────────────────────────────────────────────────┐",
+                "│Each fuzzy set gives a degree of membership from 0 to 1. Temperature: Cold 0.00, Mild 0.33, Hot   │",
+                "│0.50. Humidity: Low 0.00, Medium 0.30, High 0.70. A value can belong to two sets at once, partly  │",
+                "│to each.                                                                                          │",
+            ]
+        );
+        // The membership bars are lit, the gauges dimmed
+        assert!(!spotlight::is_dimmed(&buffer, 60, 5));
+        assert!(spotlight::is_dimmed(&buffer, 10, 5));
+        assert!(spotlight::is_dimmed(&buffer, 10, 20));
+
+        run_actions(&mut app, &[AppAction::StepTutorial(1); 3]);
+        let (rows, buffer) = draw(&app);
+        assert_eq!(
+            rows[..3],
+            [
+                "┌ Tutorial 5/5: Defuzzification ───────────────────────────────────────────────────────────────────┐",
+                "│One number has to come out. The centroid is the balance point of the shape: fan_speed = 68.4%, the│",
+                "│value the output gauge shows and the fan is driven with.                                          │",
+            ]
+        );
+        // The output gauge and the aggregated set are lit, the input
+        // gauges and the membership curves dimmed
+        assert!(!spotlight::is_dimmed(&buffer, 10, 20));
+        assert!(!spotlight::is_dimmed(&buffer, 60, 22));
+        assert!(spotlight::is_dimmed(&buffer, 10, 5));
+        assert!(spotlight::is_dimmed(&buffer, 60, 5));
+
+        // Past the last step it ends, and the views come back
+        update(&mut app, AppAction::StepTutorial(1));
+        assert_eq!(app.input_mode, InputMode::Menu);
+        assert!(app.show_surface && !app.show_curves);
+    }
+
+    /// `app` drawn whole, `width` x `height`, row by row
+    fn screen(app: &App, width: u16, height: u16) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|f| ui(f, app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| buffer.get(x, y).symbol.as_str())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_complexity_popup_shows_the_check_report() {
+        let mut app = app_after(&[(30.0, 60.0)]);
+        let press = |app: &mut App, key| update(app, action_for(app, key).unwrap());
+        press(&mut app, KeyCode::Char('C'));
+        assert_eq!(app.input_mode, InputMode::Complexity);
+        let report = complexity::analyze(&app.controller.system, complexity::CROWD);
+        assert_eq!(app.complexity, report.lines(complexity::LISTED));
+        let rows = screen(&app, 120, 40);
+        for line in &app.complexity {
+            assert!(rows.iter().any(|r| r.contains(line.as_str())), "{}", line);
+        }
+        press(&mut app, KeyCode::Char('C'));
+        assert_eq!(app.input_mode, InputMode::Menu);
+    }
+
+    #[test]
+    fn the_chain_popup_shows_what_the_pipeline_command_prints() {
+        let mut app = app_after(&[(30.0, 60.0)]);
+        let press = |app: &mut App, key| update(app, action_for(app, key).unwrap());
+        press(&mut app, KeyCode::Char('Y'));
+        assert_eq!(app.input_mode, InputMode::Chain);
+        assert_eq!(app.chain, Chain::of(&app.controller.system).lines());
+        let rows = screen(&app, 120, 60);
+        for line in &app.chain {
+            assert!(rows.iter().any(|r| r.contains(line.as_str())), "{}", line);
+        }
+        // Scrolling starts the popup further down
+        press(&mut app, KeyCode::Down);
+        let rows = screen(&app, 120, 60);
+        assert!(!rows.iter().any(|r| r.contains("input temperature")));
+        press(&mut app, KeyCode::Up);
+        press(&mut app, KeyCode::Char('Y'));
+        assert_eq!(app.input_mode, InputMode::Menu);
+    }
+
+    #[test]
+    fn the_palette_popup_previews_and_applies_a_palette() {
+        let mut app = app_after(&[(30.0, 60.0)]);
+        let press = |app: &mut App, key| update(app, action_for(app, key).unwrap());
+        // The classic palette draws what the TUI always drew
+        let classic = theme(PaletteName::Classic, Depth::TrueColor);
+        assert_eq!(
+            classic.temperature,
+            [Color::Cyan, Color::Yellow, Color::Red]
+        );
+        assert_eq!(classic.sets[..3], [Color::Cyan, Color::Yellow, Color::Red]);
+        // Named colors only, so at any depth
+        assert_eq!(app.theme, classic);
+
+        press(&mut app, KeyCode::Char('O'));
+        assert_eq!(app.input_mode, InputMode::Palettes);
+        let rows = screen(&app, 120, 40);
+        for name in PaletteName::ALL {
+            assert!(
+                rows.iter().any(|r| r.contains(name.describe())),
+                "{}",
+                name.name()
+            );
+        }
+        // Esc leaves the palette as it was
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(
+            (app.input_mode, app.palette),
+            (InputMode::Menu, PaletteName::Classic)
+        );
+
+        press(&mut app, KeyCode::Char('O'));
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(app.palette, PaletteName::OkabeIto);
+        assert_eq!(app.theme, theme(PaletteName::OkabeIto, app.depth));
+        assert!(
+            said(&app).contains("palette = \"okabe_ito\""),
+            "{}",
+            said(&app)
+        );
+        // On a 16-color terminal every color is a named one
+        let named = theme(PaletteName::OkabeIto, Depth::Ansi16);
+        assert!(
+            named
+                .sets
+                .iter()
+                .all(|c| !matches!(c, Color::Rgb(..) | Color::Indexed(_)))
+        );
+    }
+
+    #[test]
+    fn panels_can_be_hidden_and_resized() {
+        let mut app = app_after(&[(30.0, 60.0)]);
+        let press = |app: &mut App, key| update(app, action_for(app, key).unwrap());
+        let ctrl = |app: &mut App, key| update(app, control_action(app, key).unwrap());
+
+        // Readings widened to 7 against 5, the history hidden
+        ctrl(&mut app, KeyCode::Right);
+        ctrl(&mut app, KeyCode::Up);
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Char('p'));
+        assert!(
+            app.inbox
+                .newest_first()
+                .next()
+                .unwrap()
+                .text
+                .contains("history panel is hidden")
+        );
+        let rows = screen(&app, 100, 30);
+        // 7 of 12 of the width
+        assert!(rows[3].starts_with("┌🌡️   Temperature (°C)"), "{}", rows[3]);
+        assert!(
+            rows[3].ends_with("─┐┌Temperature Fuzzy Sets──────────────────┐"),
+            "{}",
+            rows[3]
+        );
+        // The body goes down to the status bar
+        assert!(rows[26].starts_with("   "), "{}", rows[26]);
+        assert!(rows[26].ends_with("└────────────────────────────────────────┘"));
+        assert!(rows[27].starts_with("┌Status"));
+
+        // Back to the default, then the readings hidden and a history of
+        // 6 rows
+        press(&mut app, KeyCode::Char('P'));
+        press(&mut app, KeyCode::Char('p'));
+        press(&mut app, KeyCode::Tab);
+        press(&mut app, KeyCode::Tab);
+        for _ in 0..3 {
+            ctrl(&mut app, KeyCode::Down);
+        }
+        assert_eq!(
+            app.inbox.newest_first().next().unwrap().text,
+            "History panel: 6 rows"
+        );
+        let rows = screen(&app, 100, 30);
+        assert!(
+            rows[3].starts_with("┌Temperature Fuzzy Sets──"),
+            "{}",
+            rows[3]
+        );
+        assert!(rows[20].starts_with("└──") && rows[20].ends_with("──┘"));
+        assert!(rows[21].starts_with("┌📊  Recent History"), "{}", rows[21]);
+        assert!(rows[27].starts_with("┌Status"));
+
+        // Nothing shown: the history, then the sets hidden too
+        app.layout.toggle();
+        app.layout.focus_next();
+        app.layout.focus_next();
+        app.layout.toggle();
+        let rows = screen(&app, 100, 30);
+        assert!(rows[4].contains("All panels are hidden: Tab focuses each in turn"));
+
+        assert_eq!(
+            screen(&app, 30, 10)[..2],
+            ["Terminal too small: 30x10,", "needs 40x12"]
+        );
+    }
+
+    /// The what-if values of the listed entries, once the worker is done
+    fn what_ifs(app: &mut App) -> Vec<f64> {
+        loop {
+            app.poll_what_if();
+            let what_if = app.what_if.as_ref().unwrap();
+            let values: Option<Vec<f64>> = app.history.iter().map(|e| what_if.get(e.id)).collect();
+            if let Some(values) = values {
+                return values;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn the_what_if_column_follows_the_rule_base() {
+        let mut app = app_after(&[(18.0, 40.0), (30.0, 60.0), (35.0, 80.0)]);
+        let recorded: Vec<f64> = app.history.iter().map(|e| e.fan_speed).collect();
+        let toggle = action_for(&app, KeyCode::Char('W')).unwrap();
+        update(&mut app, toggle);
+        assert_eq!(what_ifs(&mut app), recorded);
+        let rows = screen(&app, 160, 40);
+        let row = rows.iter().find(|r| r.contains("T: 35")).unwrap();
+        assert!(row.contains("now 83.7% (Δ+0.0)"), "{}", row);
+
+        // Every rule now asks for High
+        for rule in &mut app.controller.system.rules {
+            for consequent in &mut rule.consequents {
+                consequent.1 = "High".to_string();
+            }
+        }
+        let now = what_ifs(&mut app);
+        assert_ne!(now, recorded);
+        let rows = screen(&app, 160, 40);
+        assert!(
+            rows.iter()
+                .any(|r| r.contains("Recent History, now: the current rules"))
+        );
+        let row = rows.iter().find(|r| r.contains("T: 18")).unwrap();
+        let expected = format!(
+            "now {} (Δ+{:.1})",
+            app.show("fan_speed", now[0]),
+            now[0] - recorded[0]
+        );
+        assert!(row.contains(&expected), "{}", row);
+
+        // Exports compute every entry the same way
+        let history = app.history.clone();
+        let controller = FuzzyController::from_system(app.controller.system.clone());
+        assert!(
+            history
+                .iter()
+                .zip(&now)
+                .all(|(e, v)| what_if(&controller, &e.raw) == *v)
+        );
+        update(&mut app, AppAction::ToggleWhatIf);
+        assert!(app.what_if.is_none());
+        assert!(!screen(&app, 160, 40).iter().any(|r| r.contains("now ")));
+    }
+
+    /// The forecast, once the worker is done
+    fn forecast_of(app: &mut App) -> Forecast {
+        loop {
+            app.poll_forecast();
+            let outlook = app.outlook.as_ref().unwrap();
+            if outlook.worker.is_none()
+                && let Some(forecast) = &outlook.forecast
+            {
+                return forecast.clone().unwrap();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn the_forecast_follows_the_trend_and_says_it_is_naive() {
+        let mut app = App::new(FuzzyController::from_system(fixtures::fan()));
+        let time = std::rc::Rc::new(std::cell::Cell::new(0.0));
+        app.clock = Box::new(SharedClock(time.clone()));
+        app.clear_history();
+        // Warming a degree every 10s at a steady humidity
+        for i in 0..7 {
+            time.set(10.0 * i as f64);
+            app.temperature = 20.0 + i as f64;
+            app.humidity = 60.0;
+            app.compute_fan_speed();
+        }
+        for var in &mut app.controller.system.inputs {
+            var.min_delta = 0.5;
+        }
+        let toggle = action_for(&app, KeyCode::Char('f')).unwrap();
+        update(&mut app, toggle);
+        assert!(said(&app).contains("naive extrapolation"), "{}", said(&app));
+        let forecast = forecast_of(&mut app);
+        let last = forecast.steps.last().unwrap();
+        assert_eq!(last.ahead, 300.0);
+        assert!((last.inputs[0] - 56.0).abs() < 1e-9, "{:?}", last.inputs);
+        assert_eq!(last.output, app.controller.compute(&[50.0, 60.0]));
+
+        let rows = left_panel(&app, 140, 40);
+        let title = rows.iter().find(|r| r.contains("🔮")).unwrap();
+        assert!(
+            title.contains("Naive forecast: inputs extrapolated linearly over 300s"),
+            "{}",
+            title
+        );
+        // Too short for the chart: the end of the path goes on the bar
+        let rows = left_panel(&app, 140, 24);
+        assert!(!rows.iter().any(|r| r.contains("🔮")));
+        assert!(rows.iter().any(|r| r.contains(" · forecast ")));
+
+        // A reading that did not move past the deltas leaves it as it is
+        time.set(61.0);
+        app.compute_fan_speed();
+        app.poll_forecast();
+        assert!(app.outlook.as_ref().unwrap().worker.is_none());
+        // A change of system starts it over
+        app.controller.system.forecast.extrapolation = forecast::Extrapolation::Hold;
+        let held = forecast_of(&mut app);
+        assert!(held.steps.iter().all(|s| s.inputs[0] == 26.0));
+
+        update(&mut app, AppAction::ToggleForecast);
+        assert!(app.outlook.is_none());
+        assert!(!left_panel(&app, 140, 40).iter().any(|r| r.contains("🔮")));
+    }
+
+    #[test]
+    fn interlocks_switched_with_i_take_the_title_bar_and_mark_the_history() {
+        let interlock = |name: &str, value, priority| crate::interlock::InterlockConfig {
+            name: name.to_string(),
+            value,
+            priority,
+            engaged: false,
+        };
+        let system = FuzzySystem {
+            interlocks: vec![interlock("door", 0.0, 10), interlock("overtemp", 100.0, 20)],
+            ..fixtures::fan()
+        };
+        let mut app = App::new(FuzzyController::from_system(system));
+        let time = std::rc::Rc::new(std::cell::Cell::new(0.0));
+        app.clock = Box::new(SharedClock(time.clone()));
+        app.temperature = 33.0;
+        app.compute_fan_speed();
+        app.tick();
+        let switch = |app: &mut App, name: &str| {
+            let open = action_for(app, KeyCode::Char('I')).unwrap();
+            update(app, open);
+            assert_eq!(app.prompt, "Interlock to switch (door, overtemp):");
+            for c in name.chars() {
+                update(app, AppAction::Type(c));
+            }
+            update(app, AppAction::Submit);
+            time.set(time.get() + 1.0);
+            app.tick();
+        };
+
+        switch(&mut app, "door");
+        assert_eq!(said(&app), "Interlock 'door' engaged");
+        assert_eq!((app.output.value, app.output.band), (0.0, FanBand::Off));
+        let rows = screen(&app, 160, 40);
+        assert!(
+            rows[1].contains("INTERLOCK 'door' forces the output to 0.0% (OFF, priority 10)"),
+            "{}",
+            rows[1]
+        );
+        let row = rows.iter().find(|r| r.contains("T: 33")).unwrap();
+        assert!(row.contains(" door") && row.contains('🔒'), "{}", row);
+
+        // The higher priority takes over, and gives the output back to
+        // the lower one when released
+        switch(&mut app, "overtemp");
+        assert_eq!(app.output.value, 100.0);
+        assert!(screen(&app, 160, 40)[1].contains("INTERLOCK 'overtemp'"));
+        switch(&mut app, "overtemp");
+        assert_eq!(said(&app), "Interlock 'overtemp' released");
+        assert_eq!(app.output.value, 0.0);
+
+        switch(&mut app, "window");
+        assert_eq!(
+            said(&app),
+            "Interlock not switched: unknown interlock 'window' (door, overtemp)"
+        );
+        switch(&mut app, "door");
+        assert!(app.interlocks.active().is_none());
+        assert!(!screen(&app, 160, 40)[1].contains("INTERLOCK"));
+        assert_eq!(app.history.last().unwrap().interlock, None);
+    }
+
+    #[test]
+    fn a_daemon_s_sources_warn_when_excluded_and_show_in_a_popup() {
+        use crate::fusion::Status;
+        let mut app = App::new(FuzzyController::from_system(fixtures::fan()));
+        let press = |app: &mut App, key| update(app, action_for(app, key).unwrap());
+        press(&mut app, KeyCode::Char('G'));
+        assert_eq!(app.input_mode, InputMode::Menu);
+        let source = |name: &str, value: f64, status: Status| Reported {
+            input: "temperature".to_string(),
+            source: name.to_string(),
+            value,
+            status,
+        };
+        app.mirror_sources(vec![
+            source("north", 21.5, Status::Healthy),
+            source("south", f64::NAN, Status::Waiting),
+        ]);
+        assert_eq!(app.inbox.len(), 1, "only the note that there are none");
+        app.mirror_sources(vec![
+            source("north", 21.5, Status::Healthy),
+            source("south", 80.0, Status::OutOfRange),
+        ]);
+        assert_eq!(
+            app.inbox.newest_first().next().unwrap().text,
+            "Source temperature.south excluded: out of range"
+        );
+
+        press(&mut app, KeyCode::Char('G'));
+        assert_eq!(app.input_mode, InputMode::Sources);
+        let rows = screen(&app, 120, 40).join("\n");
+        assert!(rows.contains("temperature.north"), "{}", rows);
+        assert!(rows.contains("healthy"), "{}", rows);
+        assert!(rows.contains("out of range"), "{}", rows);
+        app.mirror_sources(vec![
+            source("north", 21.5, Status::Healthy),
+            source("south", 22.0, Status::Healthy),
+        ]);
+        assert_eq!(
+            app.inbox.newest_first().next().unwrap().text,
+            "Source temperature.south joined again"
+        );
+        press(&mut app, KeyCode::Char('G'));
+        assert_eq!(app.input_mode, InputMode::Menu);
+    }
+
+    #[test]
+    fn a_daemon_s_sample_windows_show_in_the_gauge_titles() {
+        let mut app = App::new(FuzzyController::from_system(fixtures::fan()));
+        app.windows = vec![Sampled {
+            input: "humidity".to_string(),
+            count: 10,
+            spread: 1.234,
+        }];
+        let rows = screen(&app, 160, 40).join("\n");
+        assert!(rows.contains("· 10 samples ±1.23"), "{}", rows);
+        assert_eq!(rows.matches("samples").count(), 1);
+    }
+
+    #[test]
+    fn degraded_cycles_show_in_the_fan_speed_title() {
+        let mut system = fixtures::fan();
+        system.budget = Some(crate::budget::BudgetConfig {
+            cycle_ms: 1000.0,
+            recover_cycles: 20,
+        });
+        let mut app = App::new(FuzzyController::from_system(system));
+        app.compute_fan_speed();
+        assert!(!screen(&app, 160, 40).join("\n").contains("degraded"));
+
+        // Every computation takes two seconds of the app clock
+        app.clock = Box::new(SteppingClock(std::cell::Cell::new(0.0)));
+        app.compute_fan_speed();
+        app.compute_fan_speed();
+        let rows = screen(&app, 160, 40).join("\n");
+        assert!(rows.contains("· ⏱ degraded 1 cycle · coarse"), "{}", rows);
+    }
+
+    #[test]
+    fn inputs_held_against_a_daemon_take_the_title_and_esc_releases_them() {
+        let mut app = App::new(FuzzyController::from_system(fixtures::fan()));
+        app.held = Some(Held {
+            inputs: vec!["temperature".to_string(), "humidity".to_string()],
+            remaining_secs: 12.3,
+        });
+        let rows = screen(&app, 160, 40);
+        assert!(
+            rows[1].contains("HELD temperature, humidity against the daemon's sensors for 13s"),
+            "{}",
+            rows[1]
+        );
+        update(&mut app, AppAction::Cancel);
+        assert_eq!(app.held, None);
+        assert_eq!(said(&app), "Inputs released to the daemon's readings");
+        assert!(!screen(&app, 160, 40)[1].contains("HELD"));
+    }
+
+    #[test]
+    fn feedback_nudges_the_firing_rules_and_is_kept_apart_from_the_config() {
+        let path = std::env::temp_dir()
+            .join(format!("fuzzy-tui-adapt-{}.toml", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let adaptive = || {
+            let mut app = app_after(&[(27.5, 64.0)]);
+            app.controller.system.adaptation = Some(adapt::AdaptationConfig::default());
+            load_state(&mut app, path.clone()).unwrap();
+            app
+        };
+        let mut app = app_after(&[(27.5, 64.0)]);
+        update(&mut app, AppAction::Feedback(adapt::Direction::Higher));
+        assert!(
+            said(&app).contains("no [adaptation] section"),
+            "{}",
+            said(&app)
+        );
+
+        let mut app = adaptive();
+        let before = app.fan_speed;
+        let press = |app: &mut App, key| update(app, action_for(app, key).unwrap());
+        for _ in 0..3 {
+            press(&mut app, KeyCode::Char('>'));
+        }
+        assert_eq!(
+            said(&app),
+            "Output should have been higher: 2 rule weights nudged"
+        );
+        assert!(app.fan_speed > before, "{} then {}", before, app.fan_speed);
+        let drift = app.adaptation.drift(&app.controller.system, 4);
+        assert!((-0.15..0.0).contains(&drift), "{}", drift);
+        assert_eq!(app.adaptation.events().len(), 3);
+        // The drift column, and the config untouched on disk
+        app.show_rules = true;
+        let rows = screen(&app, 160, 40);
+        assert!(rows.iter().any(|r| r.contains("Drift")));
+        assert!(rows.iter().any(|r| r.contains(&format!("{:+.2}", drift))));
+        let saved = std::fs::read_to_string(adapt::path_for(&path)).unwrap();
+        assert_eq!(saved.matches("[[feedback]]").count(), 3);
+
+        // A new session starts from the adapted weights
+        let mut reopened = adaptive();
+        assert_eq!(reopened.controller.system, app.controller.system);
+        assert_eq!(reopened.adaptation, app.adaptation);
+        press(&mut reopened, KeyCode::Char('w'));
+        assert_eq!(said(&reopened), "Configured weights back on 2 rules");
+        assert_eq!(
+            reopened.adaptation.drift(&reopened.controller.system, 4),
+            0.0
+        );
+        let saved = std::fs::read_to_string(adapt::path_for(&path)).unwrap();
+        assert!(!saved.contains("[[weight]]") && !saved.contains("[[feedback]]"));
+
+        for file in [
+            path.clone(),
+            adapt::path_for(&path),
+            journal::path_for(&path),
+        ] {
+            let _ = std::fs::remove_file(file);
+        }
+    }
+
+    #[test]
+    fn the_layout_is_kept_in_the_state_file() {
+        let path = std::env::temp_dir()
+            .join(format!("fuzzy-tui-layout-{}.toml", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let mut app = app_after(&[(30.0, 60.0)]);
+        load_state(&mut app, path.clone()).unwrap();
+        app.layout.focus_next();
+        app.layout.toggle();
+        app.layout.resize(-2);
+        app.save_state().unwrap();
+        assert!(
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .ends_with("[layout]\nfocus = \"sets\"\nhidden = [\"sets\"]\nreadings = 5\nsets = 3\nhistory = 12\n")
+        );
+
+        let mut reopened = app_after(&[]);
+        load_state(&mut reopened, path.clone()).unwrap();
+        assert_eq!(reopened.layout, app.layout);
+        std::fs::remove_file(&path).unwrap();
+        let _ = std::fs::remove_file(journal::path_for(&path));
+    }
+}
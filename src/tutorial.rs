@@ -0,0 +1,122 @@
+// ============================================================================
+// TUTORIAL - Inferência fuzzy passo a passo
+// ============================================================================
+//
+// A guided walk through one computation, for teaching: each step lights the
+// panel it talks about and dims the rest (spotlight.rs), with a short
+// explanation in a box over the history panel. Space moves on, Backspace
+// goes back, Esc ends it; `fuzzy_logic --tutorial` starts with it and 'T'
+// opens it any time. Inputs may still be changed with 'r' and the arrows
+// while it is open, and the numbers in the text follow.
+//
+// The steps are data: a panel and an English title and text, where
+// `{name}` stands for a live value the TUI fills in:
+//
+//     {temperature} {humidity}            the inputs, in their units
+//     {temperature_sets} {humidity_sets}  membership in every set
+//     {fired}                             how many rules fire
+//     {rule} {strength}                   the strongest rule and its strength
+//     {aggregation}                       how the clipped sets combine
+//     {method}                            the defuzzification method
+//     {output_name} {output}              the first output
+//
+// The app has no translation catalog yet; another language is another
+// table of the same steps, its texts using the same names.
+
+/// The part of the screen a step is about
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Panel {
+    /// The input gauges
+    Inputs,
+    /// The membership bars of the inputs
+    Memberships,
+    /// The rule table, with each rule's strength
+    Rules,
+    /// The aggregated output set
+    Aggregated,
+    /// The aggregated set and the output gauge
+    Centroid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    pub panel: Panel,
+    pub title: &'static str,
+    pub text: &'static str,
+}
+
+pub const STEPS: [Step; 5] = [
+    Step {
+        panel: Panel::Inputs,
+        title: "Crisp inputs",
+        text: "A fuzzy controller starts from ordinary numbers: the temperature is \
+               {temperature} and the humidity {humidity}. Fuzzification asks how well \
+               each number fits each word we use for it.",
+    },
+    Step {
+        panel: Panel::Memberships,
+        title: "Fuzzification",
+        text: "Each fuzzy set gives a degree of membership from 0 to 1. Temperature: \
+               {temperature_sets}. Humidity: {humidity_sets}. A value can belong to two \
+               sets at once, partly to each.",
+    },
+    Step {
+        panel: Panel::Rules,
+        title: "Rule firing",
+        text: "Every rule takes the smallest membership of its conditions (AND is a \
+               minimum) times its weight. {fired} rules fire now; the strongest is \
+               \"{rule}\" at {strength}.",
+    },
+    Step {
+        panel: Panel::Aggregated,
+        title: "Aggregation",
+        text: "Each firing rule clips the output set it concludes at its strength. The \
+               clipped sets combine by {aggregation} into the one shape drawn here: \
+               everything the rules say about {output_name} at once.",
+    },
+    Step {
+        panel: Panel::Centroid,
+        title: "Defuzzification",
+        text: "One number has to come out. The {method} is the balance point of the \
+               shape: {output_name} = {output}, the value the output gauge shows and \
+               the fan is driven with.",
+    },
+];
+
+/// `text` with every `{name}` of `values` replaced by its value; unknown
+/// names are left as they are
+pub fn fill(text: &str, values: &[(&str, String)]) -> String {
+    let mut filled = text.to_string();
+    for (name, value) in values {
+        filled = filled.replace(&format!("{{{}}}", name), value);
+    }
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_placeholder_of_the_steps_is_filled() {
+        let names = [
+            "temperature",
+            "humidity",
+            "temperature_sets",
+            "humidity_sets",
+            "fired",
+            "rule",
+            "strength",
+            "aggregation",
+            "method",
+            "output_name",
+            "output",
+        ];
+        let values: Vec<(&str, String)> = names.iter().map(|n| (*n, "·".to_string())).collect();
+        for step in STEPS {
+            let filled = fill(step.text, &values);
+            assert!(!filled.contains('{'), "{}", filled);
+        }
+        assert_eq!(fill("{a} and {b}", &[("a", "1".to_string())]), "1 and {b}");
+    }
+}
@@ -0,0 +1,258 @@
+// ============================================================================
+// UNITS - Unidades de medida
+// ============================================================================
+//
+// Once arbitrary configs exist "50" means nothing on its own, so every
+// variable may name its unit and how many decimals to show:
+//
+//     [[input]]
+//     name = "temperature"
+//     unit = "°C"            # optional, the unit of min/max and the sets
+//     precision = 1          # optional decimals, 1 by default
+//     display_unit = "°F"    # optional, shown instead of `unit`
+//     display_scale = 1.8    # shown = stored * scale + offset; both may
+//     display_offset = 32.0  # be left out for the pairs Conversion::known
+//
+// The model, the rules and the saved state always work in the storage
+// unit. A display conversion is a linear map to another unit that only
+// the TUI applies: its labels, its history and the values typed at its
+// prompts, which are converted back on the way in. 'U' switches the TUI
+// between the display and the storage units. Machine-readable output
+// (batch and surface CSV headers, the cosim `init` reply, the daemon's
+// `units` command) names the storage unit, which is what its numbers
+// are in.
+
+/// Decimals shown when a variable does not say
+pub const DEFAULT_PRECISION: usize = 1;
+
+/// Most decimals a variable may ask for
+pub const MAX_PRECISION: usize = 6;
+
+/// A linear map from the storage unit to a display unit
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversion {
+    pub unit: String,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl Conversion {
+    /// The conversion between two units common enough to need no
+    /// `display_scale` and `display_offset`
+    pub fn known(from: &str, to: &str) -> Option<Self> {
+        let (scale, offset) = match (from, to) {
+            ("°C", "°F") => (1.8, 32.0),
+            ("°F", "°C") => (1.0 / 1.8, -32.0 / 1.8),
+            ("°C", "K") => (1.0, 273.15),
+            ("K", "°C") => (1.0, -273.15),
+            ("m/s", "km/h") => (3.6, 0.0),
+            ("km/h", "m/s") => (1.0 / 3.6, 0.0),
+            ("l/s", "m³/h") => (3.6, 0.0),
+            ("m³/h", "l/s") => (1.0 / 3.6, 0.0),
+            _ => return None,
+        };
+        Some(Conversion {
+            unit: to.to_string(),
+            scale,
+            offset,
+        })
+    }
+
+    pub fn to_display(&self, stored: f64) -> f64 {
+        stored * self.scale + self.offset
+    }
+
+    pub fn to_stored(&self, shown: f64) -> f64 {
+        (shown - self.offset) / self.scale
+    }
+}
+
+/// How the values of one variable are labelled
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Unit {
+    /// Unit of the universe and the sets; empty when unitless
+    pub name: String,
+    pub precision: Option<usize>,
+    pub display: Option<Conversion>,
+}
+
+impl Unit {
+    pub fn new(name: &str) -> Self {
+        Unit {
+            name: name.to_string(),
+            ..Unit::default()
+        }
+    }
+
+    /// The same unit without its display conversion
+    pub fn stored(&self) -> Self {
+        Unit {
+            display: None,
+            ..self.clone()
+        }
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        if self.precision.is_some_and(|p| p > MAX_PRECISION) {
+            return Err(format!("'precision' must be at most {}", MAX_PRECISION));
+        }
+        if let Some(conversion) = &self.display {
+            if conversion.unit.trim().is_empty() {
+                return Err("'display_unit' must not be empty".to_string());
+            }
+            if !(conversion.scale.is_finite() && conversion.scale != 0.0) {
+                return Err("'display_scale' must be a nonzero number".to_string());
+            }
+            if !conversion.offset.is_finite() {
+                return Err("'display_offset' must be a number".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Summary for `diff`, e.g. "°C, 1 decimals, shown as °F (x 1.8 + 32)"
+    pub fn describe(&self) -> String {
+        let mut text = match self.name.as_str() {
+            "" => "none".to_string(),
+            name => name.to_string(),
+        };
+        if let Some(precision) = self.precision {
+            text.push_str(&format!(", {} decimals", precision));
+        }
+        if let Some(conversion) = &self.display {
+            text.push_str(&format!(
+                ", shown as {} (x {} + {})",
+                conversion.unit, conversion.scale, conversion.offset
+            ));
+        }
+        text
+    }
+
+    /// The unit values are shown in
+    pub fn label(&self) -> &str {
+        match &self.display {
+            Some(conversion) => &conversion.unit,
+            None => &self.name,
+        }
+    }
+
+    pub fn to_display(&self, stored: f64) -> f64 {
+        self.display
+            .as_ref()
+            .map_or(stored, |conversion| conversion.to_display(stored))
+    }
+
+    pub fn to_stored(&self, shown: f64) -> f64 {
+        self.display
+            .as_ref()
+            .map_or(shown, |conversion| conversion.to_stored(shown))
+    }
+
+    /// A stored value as shown, e.g. "86.0°F" or "1200 m³/h"
+    pub fn format(&self, stored: f64) -> String {
+        let precision = self.precision.unwrap_or(DEFAULT_PRECISION);
+        attach(
+            &format!("{:.*}", precision, self.to_display(stored)),
+            self.label(),
+        )
+    }
+
+    /// A difference of stored values as shown, without the offset, e.g.
+    /// "3.6°F" for 2 °C
+    pub fn format_difference(&self, stored: f64) -> String {
+        let scale = self.display.as_ref().map_or(1.0, |c| c.scale);
+        let precision = self.precision.unwrap_or(DEFAULT_PRECISION);
+        attach(&format!("{:.*}", precision, stored * scale), self.label())
+    }
+
+    /// `title` followed by the unit in parentheses, if there is one
+    pub fn title(&self, title: &str) -> String {
+        match self.label() {
+            "" => title.to_string(),
+            unit => format!("{} ({})", title, unit),
+        }
+    }
+
+    /// Column header for `name` holding stored values, e.g. "fan_speed [%]"
+    pub fn column(&self, name: &str) -> String {
+        match self.name.as_str() {
+            "" => name.to_string(),
+            unit => format!("{} [{}]", name, unit),
+        }
+    }
+}
+
+/// `number` followed by `unit`, spaced except for the symbols written
+/// against the number
+fn attach(number: &str, unit: &str) -> String {
+    if unit.is_empty() || unit.starts_with('°') || unit == "%" {
+        format!("{}{}", number, unit)
+    } else {
+        format!("{} {}", number, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fahrenheit() -> Unit {
+        Unit {
+            display: Conversion::known("°C", "°F"),
+            ..Unit::new("°C")
+        }
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        let unit = fahrenheit();
+        for stored in [-40.0, 0.0, 21.5, 37.0, 100.0] {
+            assert!((unit.to_stored(unit.to_display(stored)) - stored).abs() < 1e-9);
+        }
+        assert_eq!(unit.to_display(100.0), 212.0);
+        assert_eq!(unit.to_display(-40.0), -40.0);
+        let back = Conversion::known("°F", "°C").unwrap();
+        assert!((back.to_display(212.0) - 100.0).abs() < 1e-9);
+        assert_eq!(Conversion::known("°C", "m/s"), None);
+        // A user-defined pair: liters per second shown as m³/h
+        let airflow = Unit {
+            name: "l/s".to_string(),
+            precision: Some(0),
+            display: Some(Conversion {
+                unit: "m³/h".to_string(),
+                scale: 3.6,
+                offset: 0.0,
+            }),
+        };
+        assert_eq!(airflow.to_stored(360.0), 100.0);
+        assert_eq!(airflow.format(100.0), "360 m³/h");
+        assert_eq!(airflow.stored().format(100.0), "100 l/s");
+    }
+
+    #[test]
+    fn values_titles_and_columns_carry_the_unit() {
+        assert_eq!(fahrenheit().format(30.0), "86.0°F");
+        assert_eq!(fahrenheit().stored().format(30.0), "30.0°C");
+        assert_eq!(Unit::new("%").format(60.94), "60.9%");
+        assert_eq!(fahrenheit().format_difference(2.0), "3.6°F");
+        assert_eq!(Unit::default().format(2.0), "2.0");
+        assert_eq!(fahrenheit().title("Temperature"), "Temperature (°F)");
+        assert_eq!(Unit::default().title("damper"), "damper");
+        // Columns name what the numbers are stored in
+        assert_eq!(fahrenheit().column("temperature"), "temperature [°C]");
+        assert_eq!(Unit::default().column("damper"), "damper");
+    }
+
+    #[test]
+    fn bad_conversions_are_refused() {
+        let mut unit = fahrenheit();
+        assert_eq!(unit.check(), Ok(()));
+        unit.display.as_mut().unwrap().scale = 0.0;
+        assert!(unit.check().unwrap_err().contains("display_scale"));
+        let unit = Unit {
+            precision: Some(9),
+            ..Unit::new("°C")
+        };
+        assert!(unit.check().unwrap_err().contains("precision"));
+    }
+}
@@ -0,0 +1,203 @@
+// ============================================================================
+// CHART VIEWPORTS - Zoom e deslocamento dos gráficos
+// ============================================================================
+//
+// The membership curves (over each input's universe) and the input
+// sparklines (over the history's time) can be zoomed and panned from the
+// keyboard: '+'/'-' zoom around the current value, '←'/'→' pan and '0'
+// shows everything again. A viewport is the visible part of an axis; the
+// charts sample their data again for it, so zooming in shows detail
+// instead of stretching the full-range samples.
+//
+// Zooming stops at MAX_ZOOM and never shows more than the whole axis, and
+// every step keeps the view inside the axis bounds.
+
+/// Factor of one zoom step
+pub const ZOOM_STEP: f64 = 2.0;
+/// Narrowest view, as a fraction of the whole axis
+pub const MAX_ZOOM: f64 = 64.0;
+/// Share of the visible span one pan step moves
+pub const PAN_STEP: f64 = 0.25;
+
+/// Visible range `start..end` of an axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl Viewport {
+    pub fn full(min: f64, max: f64) -> Self {
+        Viewport {
+            start: min,
+            end: max,
+        }
+    }
+
+    pub fn span(self) -> f64 {
+        self.end - self.start
+    }
+
+    pub fn is_full(self, min: f64, max: f64) -> bool {
+        self.start <= min && self.end >= max
+    }
+
+    /// Zoom in by `factor` (below 1 zooms out) keeping `center` where it
+    /// is on screen, within the axis `min..max`
+    pub fn zoom(self, factor: f64, center: f64, min: f64, max: f64) -> Self {
+        let whole = max - min;
+        let span = (self.span() / factor).clamp(whole / MAX_ZOOM, whole);
+        let center = center.clamp(self.start, self.end);
+        let position = if self.span() > 0.0 {
+            (center - self.start) / self.span()
+        } else {
+            0.5
+        };
+        Viewport::clamped(center - position * span, span, min, max)
+    }
+
+    /// Move by `steps` pan steps, positive toward `max`
+    pub fn pan(self, steps: f64, min: f64, max: f64) -> Self {
+        Viewport::clamped(
+            self.start + steps * PAN_STEP * self.span(),
+            self.span(),
+            min,
+            max,
+        )
+    }
+
+    /// A view of `span` starting at `start`, moved inside `min..max`
+    fn clamped(start: f64, span: f64, min: f64, max: f64) -> Self {
+        let span = span.min(max - min);
+        let start = start.clamp(min, max - span);
+        Viewport {
+            start,
+            end: start + span,
+        }
+    }
+
+    /// `samples` evenly spaced points across the view, ends included
+    pub fn samples(self, samples: usize) -> Vec<f64> {
+        let samples = samples.max(2);
+        (0..samples)
+            .map(|i| self.start + self.span() * i as f64 / (samples - 1) as f64)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zooming_keeps_the_center_in_place() {
+        let view = Viewport::full(0.0, 50.0).zoom(ZOOM_STEP, 20.0, 0.0, 50.0);
+        assert_eq!(
+            view,
+            Viewport {
+                start: 10.0,
+                end: 35.0
+            }
+        );
+        let view = view.zoom(ZOOM_STEP, 20.0, 0.0, 50.0);
+        assert_eq!(
+            view,
+            Viewport {
+                start: 15.0,
+                end: 27.5
+            }
+        );
+        // Zooming back out returns to where it started
+        let view = view.zoom(1.0 / ZOOM_STEP, 20.0, 0.0, 50.0);
+        assert_eq!(
+            view,
+            Viewport {
+                start: 10.0,
+                end: 35.0
+            }
+        );
+    }
+
+    #[test]
+    fn zoom_stops_at_its_limits() {
+        let mut view = Viewport::full(0.0, 64.0);
+        for _ in 0..20 {
+            view = view.zoom(ZOOM_STEP, 32.0, 0.0, 64.0);
+        }
+        assert_eq!(view.span(), 1.0);
+        for _ in 0..20 {
+            view = view.zoom(1.0 / ZOOM_STEP, 32.0, 0.0, 64.0);
+        }
+        assert_eq!(view, Viewport::full(0.0, 64.0));
+        assert!(view.is_full(0.0, 64.0));
+    }
+
+    #[test]
+    fn views_stay_inside_the_axis() {
+        let view = Viewport::full(0.0, 100.0).zoom(4.0, 100.0, 0.0, 100.0);
+        assert_eq!(
+            view,
+            Viewport {
+                start: 75.0,
+                end: 100.0
+            }
+        );
+        // Zooming out near an edge pushes the view back in
+        let wide = Viewport {
+            start: 80.0,
+            end: 100.0,
+        }
+        .zoom(0.5, 99.0, 0.0, 100.0);
+        assert_eq!(
+            wide,
+            Viewport {
+                start: 60.0,
+                end: 100.0
+            }
+        );
+
+        let view = view.pan(1.0, 0.0, 100.0);
+        assert_eq!(
+            view,
+            Viewport {
+                start: 75.0,
+                end: 100.0
+            }
+        );
+        let view = view.pan(-2.0, 0.0, 100.0);
+        assert_eq!(
+            view,
+            Viewport {
+                start: 62.5,
+                end: 87.5
+            }
+        );
+        let view = view.pan(-100.0, 0.0, 100.0);
+        assert_eq!(
+            view,
+            Viewport {
+                start: 0.0,
+                end: 25.0
+            }
+        );
+
+        // An axis that shrank under the view clamps it too
+        let view = Viewport {
+            start: 40.0,
+            end: 80.0,
+        }
+        .pan(0.0, 0.0, 30.0);
+        assert_eq!(view, Viewport::full(0.0, 30.0));
+    }
+
+    #[test]
+    fn samples_cover_the_view_at_the_requested_resolution() {
+        let view = Viewport {
+            start: 19.0,
+            end: 21.0,
+        };
+        let samples = view.samples(5);
+        assert_eq!(samples, [19.0, 19.5, 20.0, 20.5, 21.0]);
+        assert_eq!(view.samples(0).len(), 2);
+    }
+}
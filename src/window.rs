@@ -0,0 +1,289 @@
+// ============================================================================
+// SAMPLE WINDOWS - Janelas de amostras
+// ============================================================================
+//
+// A sensor streaming ten `set` readings a second to the daemon is better
+// served by one robust value a second than by ten computations. An input
+// with a window collects its readings as samples instead:
+//
+//     [[input]]
+//     name = "temperature"
+//     window_secs = 1.0       # optional, collect samples this long
+//     aggregate = "median"    # "mean" (default), "median",
+//                             # "trimmed_mean" or "max"
+//     stale_windows = 5       # optional, empty windows in a row before
+//                             # the input counts as failed; 0 never
+//
+// Windows follow the daemon's clock and are aligned to multiples of
+// `window_secs`, from the first sample on. A sample belongs to the window
+// open when it arrives, however irregularly they come. When a window
+// closes, its aggregate becomes the input's reading and goes through the
+// recompute triggers (trigger.rs), so the controller runs at window
+// boundaries rather than per sample. The trimmed mean leaves out TRIM of
+// the samples at either end first.
+//
+// The tree had no stale-input policy to fall back on, so windows bring
+// their own: an empty window keeps the last aggregate, and after
+// `stale_windows` empty windows in a row the reading is NaN, which fires
+// none of the input's rules like a failed sensor does.
+//
+// The daemon logs each aggregate with its sample count and spread (their
+// standard deviation), and its state lines carry both (remote.rs) for the
+// gauge titles of an attached TUI.
+
+/// Share of the samples the trimmed mean leaves out at either end
+pub const TRIM: f64 = 0.1;
+
+/// How a window's samples make one reading
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Aggregate {
+    #[default]
+    Mean,
+    Median,
+    TrimmedMean,
+    Max,
+}
+
+impl Aggregate {
+    pub const ALL: [Aggregate; 4] = [
+        Aggregate::Mean,
+        Aggregate::Median,
+        Aggregate::TrimmedMean,
+        Aggregate::Max,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aggregate::Mean => "mean",
+            Aggregate::Median => "median",
+            Aggregate::TrimmedMean => "trimmed_mean",
+            Aggregate::Max => "max",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.name() == name)
+    }
+
+    /// The reading of `samples`, none when there are none
+    pub fn apply(&self, samples: &[f64]) -> Option<f64> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let n = sorted.len();
+        Some(match self {
+            Aggregate::Mean => mean(&sorted),
+            Aggregate::Median if n.is_multiple_of(2) => (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0,
+            Aggregate::Median => sorted[n / 2],
+            Aggregate::TrimmedMean => {
+                let cut = (n as f64 * TRIM) as usize;
+                mean(&sorted[cut..n - cut])
+            }
+            Aggregate::Max => sorted[n - 1],
+        })
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// An input's window settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowConfig {
+    pub secs: f64,
+    pub aggregate: Aggregate,
+    /// Empty windows in a row before the reading is NaN; zero never
+    pub stale_windows: usize,
+}
+
+/// A closed window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    /// The input's reading from now on
+    pub value: f64,
+    pub count: usize,
+    /// Standard deviation of the samples
+    pub spread: f64,
+}
+
+/// What the state lines say of an input's last window
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sampled {
+    pub input: String,
+    pub count: usize,
+    pub spread: f64,
+}
+
+impl Sampled {
+    /// `10 samples ±0.40`, for log lines and gauge titles
+    pub fn describe(&self) -> String {
+        if self.count == 0 {
+            return "no samples".to_string();
+        }
+        format!(
+            "{} sample{} ±{:.2}",
+            self.count,
+            if self.count == 1 { "" } else { "s" },
+            self.spread
+        )
+    }
+}
+
+/// Collects an input's samples and closes its windows on a clock the
+/// caller passes in
+#[derive(Debug, Clone)]
+pub struct Aggregator {
+    config: WindowConfig,
+    /// Start of the open window, from the first sample on
+    start: Option<f64>,
+    samples: Vec<f64>,
+    /// Empty windows in a row
+    empty: usize,
+    /// Reading of the last window that had samples
+    last: Option<f64>,
+    /// The latest window closed and not yet taken
+    closed: Option<Window>,
+}
+
+impl Aggregator {
+    pub fn new(config: WindowConfig) -> Self {
+        Aggregator {
+            config,
+            start: None,
+            samples: Vec::new(),
+            empty: 0,
+            last: None,
+            closed: None,
+        }
+    }
+
+    /// File a sample arriving at clock time `now`
+    pub fn push(&mut self, now: f64, sample: f64) {
+        self.roll(now);
+        let secs = self.config.secs;
+        self.start.get_or_insert((now / secs).floor() * secs);
+        self.samples.push(sample);
+    }
+
+    /// The latest window closed by `now`, if one closed since the last call
+    pub fn poll(&mut self, now: f64) -> Option<Window> {
+        self.roll(now);
+        self.closed.take()
+    }
+
+    /// Close the windows that ended by `now`
+    fn roll(&mut self, now: f64) {
+        let secs = self.config.secs;
+        let Some(start) = self.start else {
+            return;
+        };
+        let ended = ((now - start) / secs).floor();
+        if ended < 1.0 {
+            return;
+        }
+        self.start = Some(start + ended * secs);
+        let samples = std::mem::take(&mut self.samples);
+        if let Some(value) = self.config.aggregate.apply(&samples) {
+            let mean = mean(&samples);
+            let variance =
+                samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+            self.empty = 0;
+            self.last = Some(value);
+            self.closed = Some(Window {
+                value,
+                count: samples.len(),
+                spread: variance.sqrt(),
+            });
+            if ended < 2.0 {
+                return;
+            }
+        }
+        // Windows that went by without a sample
+        self.empty += ended as usize - usize::from(!samples.is_empty());
+        let stale = self.config.stale_windows > 0 && self.empty >= self.config.stale_windows;
+        self.closed = Some(Window {
+            value: match self.last {
+                Some(last) if !stale => last,
+                _ => f64::NAN,
+            },
+            count: 0,
+            spread: 0.0,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aggregator(aggregate: Aggregate, stale_windows: usize) -> Aggregator {
+        Aggregator::new(WindowConfig {
+            secs: 1.0,
+            aggregate,
+            stale_windows,
+        })
+    }
+
+    #[test]
+    fn each_aggregate_resists_what_it_should() {
+        // Nine readings near 20 and one spike
+        let mut samples = vec![19.8, 20.2, 19.9, 20.1, 20.0, 19.9, 20.1, 20.0, 20.0];
+        samples.push(35.0);
+        let value = |a: Aggregate| a.apply(&samples).unwrap();
+        assert!((value(Aggregate::Mean) - 21.5).abs() < 1e-9);
+        assert_eq!(value(Aggregate::Median), 20.0);
+        assert!((value(Aggregate::TrimmedMean) - 20.025).abs() < 1e-9);
+        assert_eq!(value(Aggregate::Max), 35.0);
+        assert_eq!(Aggregate::Median.apply(&[3.0, 1.0, 2.0]), Some(2.0));
+        assert_eq!(Aggregate::Mean.apply(&[]), None);
+        for aggregate in Aggregate::ALL {
+            assert_eq!(Aggregate::parse(aggregate.name()), Some(aggregate));
+        }
+    }
+
+    #[test]
+    fn irregular_samples_fall_in_the_window_open_when_they_arrive() {
+        let mut window = aggregator(Aggregate::Mean, 0);
+        assert_eq!(window.poll(5.0), None);
+        for (now, sample) in [(10.05, 1.0), (10.1, 3.0), (10.95, 5.0), (11.0, 7.0)] {
+            window.push(now, sample);
+        }
+        // The sample at 11.0 opened the next window
+        let closed = window.poll(11.0).unwrap();
+        assert_eq!((closed.value, closed.count), (3.0, 3));
+        assert!((closed.spread - (8.0f64 / 3.0).sqrt()).abs() < 1e-9);
+        assert_eq!(window.poll(11.5), None);
+        let closed = window.poll(12.0).unwrap();
+        assert_eq!((closed.value, closed.count, closed.spread), (7.0, 1, 0.0));
+    }
+
+    #[test]
+    fn empty_windows_hold_the_last_reading_until_it_goes_stale() {
+        let mut window = aggregator(Aggregate::Median, 3);
+        window.push(0.5, 20.0);
+        assert_eq!(window.poll(1.0).map(|w| w.value), Some(20.0));
+        let held = window.poll(2.0).unwrap();
+        assert_eq!((held.value, held.count), (20.0, 0));
+        assert_eq!(window.poll(3.0).map(|w| w.value), Some(20.0));
+        assert!(window.poll(4.0).unwrap().value.is_nan());
+
+        // A late poll closes the missed windows at once
+        window.push(4.2, 22.0);
+        assert_eq!(window.poll(5.0).map(|w| w.value), Some(22.0));
+        assert!(window.poll(8.5).unwrap().value.is_nan());
+        window.push(9.0, 23.0);
+        window.push(9.4, 24.0);
+        assert_eq!(
+            window.poll(12.0).map(|w| (w.value, w.count)),
+            Some((23.5, 0))
+        );
+        assert_eq!(
+            aggregator(Aggregate::Mean, 0).poll(100.0),
+            None,
+            "no window before the first sample"
+        );
+    }
+}
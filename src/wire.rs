@@ -0,0 +1,506 @@
+// ============================================================================
+// BINARY WIRE FORMAT - Formato binário de transmissão
+// ============================================================================
+//
+// The daemon's line protocol (remote.rs) and the co-simulation's JSON
+// lines (cosim.rs) are easy to drive by hand, but formatting and parsing
+// text costs more than the controller itself at a 100 Hz exchange. A peer
+// that wants less can ask for binary frames instead, with one line right
+// after connecting:
+//
+//     wire 1            -> wire 1              (frames from here on)
+//                       -> error ... / {"error":...}   (stay on text)
+//
+// A server answering anything but the line it was sent has refused it, or
+// does not know it, and the connection goes on in text: `negotiate` does
+// the client's part and says which it got. A server that knows the
+// handshake but not the version refuses it the same way.
+//
+// Every frame, both ways, is
+//
+//     u32   length of what follows, at most MAX_FRAME bytes
+//     u8    version, VERSION
+//     u8    message id
+//     ...   the message's fields
+//
+// little-endian throughout, with f64 as their IEEE 754 bits (NaN for a
+// failed sensor included), counts as u16 and strings as a u16 byte count
+// and UTF-8. The messages, by id:
+//
+//     0x01  init                                  -> ready
+//     0x02  set inputs   u16 n, n × (u16 input index, f64 value)
+//                                                 -> ok | error
+//     0x03  compute      f64 time                 -> state | error
+//     0x04  get state                             -> state | error
+//     0x05  weights      u16 n, n × f64           -> ok | error
+//     0x06  terminate                             -> ok
+//
+//     0x81  ok
+//     0x82  error        string
+//     0x83  ready        u16 n, n × string inputs; u16 m, m × string outputs
+//     0x84  state        f64 time, u16 n, n × f64 outputs, f64 actuator,
+//                        u8 band (0 OFF, 1 LOW, 2 MEDIUM, 3 HIGH)
+//
+// Inputs go by their index in declaration order, which `ready` lists. A
+// frame longer than MAX_FRAME ends the connection before it is read, as a
+// line longer than MAX_LINE does on the daemon; a frame that reads but
+// does not decode is answered with an error and the next one is read. A
+// new version gets new ids rather than changing the fields of old ones.
+// What each message does is up to the server: see cosim.rs and remote.rs.
+
+use crate::pipeline::FanBand;
+use std::io::{self, BufRead, Read, Write};
+
+/// Version of the frames, sent in each and in the handshake
+pub const VERSION: u8 = 1;
+
+/// Longest frame either side reads, length prefix left out
+pub const MAX_FRAME: usize = 64 * 1024;
+
+const BANDS: [FanBand; 4] = [FanBand::Off, FanBand::Low, FanBand::Medium, FanBand::High];
+
+/// What a server answers `compute` and `get state` with
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    pub time: f64,
+    /// Crisp outputs in declaration order
+    pub outputs: Vec<f64>,
+    /// The first output after the pipeline
+    pub actuator: f64,
+    pub band: FanBand,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Init,
+    SetInputs(Vec<(u16, f64)>),
+    Compute {
+        time: f64,
+    },
+    GetState,
+    Weights(Vec<f64>),
+    Terminate,
+    Ok,
+    Error(String),
+    Ready {
+        inputs: Vec<String>,
+        outputs: Vec<String>,
+    },
+    State(State),
+}
+
+impl Message {
+    fn id(&self) -> u8 {
+        match self {
+            Message::Init => 0x01,
+            Message::SetInputs(_) => 0x02,
+            Message::Compute { .. } => 0x03,
+            Message::GetState => 0x04,
+            Message::Weights(_) => 0x05,
+            Message::Terminate => 0x06,
+            Message::Ok => 0x81,
+            Message::Error(_) => 0x82,
+            Message::Ready { .. } => 0x83,
+            Message::State(_) => 0x84,
+        }
+    }
+}
+
+/// The client's handshake line
+pub fn handshake() -> String {
+    format!("wire {}", VERSION)
+}
+
+/// Whether `line` asks for frames: none for any other line, an error for
+/// a version this side does not speak
+pub fn requested(line: &str) -> Option<Result<(), String>> {
+    match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["wire", version] if version == VERSION.to_string() => Some(Ok(())),
+        ["wire", version] => Some(Err(format!("unsupported wire version {}", version))),
+        _ => None,
+    }
+}
+
+/// Ask the server for frames; true when it agreed, false when the
+/// connection stays on text
+pub fn negotiate(reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<bool> {
+    writeln!(writer, "{}", handshake())?;
+    writer.flush()?;
+    let mut reply = String::new();
+    reader.read_line(&mut reply)?;
+    Ok(reply.trim() == handshake())
+}
+
+// ---------------------------------------------------------------------------
+// Encoding
+// ---------------------------------------------------------------------------
+
+fn put_count(out: &mut Vec<u8>, count: usize) -> Result<(), String> {
+    let count = u16::try_from(count).map_err(|_| format!("{} items do not fit a frame", count))?;
+    out.extend(count.to_le_bytes());
+    Ok(())
+}
+
+fn put_str(out: &mut Vec<u8>, text: &str) -> Result<(), String> {
+    put_count(out, text.len())?;
+    out.extend(text.as_bytes());
+    Ok(())
+}
+
+fn put_f64(out: &mut Vec<u8>, value: f64) {
+    out.extend(value.to_le_bytes());
+}
+
+/// `message` as a whole frame, length prefix included
+pub fn encode(message: &Message) -> Result<Vec<u8>, String> {
+    let mut out = vec![0; 4];
+    out.extend([VERSION, message.id()]);
+    match message {
+        Message::Init | Message::GetState | Message::Terminate | Message::Ok => {}
+        Message::SetInputs(inputs) => {
+            put_count(&mut out, inputs.len())?;
+            for (index, value) in inputs {
+                out.extend(index.to_le_bytes());
+                put_f64(&mut out, *value);
+            }
+        }
+        Message::Compute { time } => put_f64(&mut out, *time),
+        Message::Weights(weights) => {
+            put_count(&mut out, weights.len())?;
+            for weight in weights {
+                put_f64(&mut out, *weight);
+            }
+        }
+        Message::Error(reason) => put_str(&mut out, reason)?,
+        Message::Ready { inputs, outputs } => {
+            for names in [inputs, outputs] {
+                put_count(&mut out, names.len())?;
+                for name in names {
+                    put_str(&mut out, name)?;
+                }
+            }
+        }
+        Message::State(state) => {
+            put_f64(&mut out, state.time);
+            put_count(&mut out, state.outputs.len())?;
+            for output in &state.outputs {
+                put_f64(&mut out, *output);
+            }
+            put_f64(&mut out, state.actuator);
+            let band = BANDS.iter().position(|b| *b == state.band).unwrap_or(0);
+            out.push(band as u8);
+        }
+    }
+    let length = out.len() - 4;
+    if length > MAX_FRAME {
+        return Err(format!(
+            "frame of {} bytes is over the limit of {}",
+            length, MAX_FRAME
+        ));
+    }
+    out[..4].copy_from_slice(&(length as u32).to_le_bytes());
+    Ok(out)
+}
+
+// ---------------------------------------------------------------------------
+// Decoding
+// ---------------------------------------------------------------------------
+
+/// The fields of a frame, read front to back
+struct Fields<'a> {
+    bytes: &'a [u8],
+}
+
+impl Fields<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], String> {
+        if self.bytes.len() < n {
+            return Err(format!(
+                "truncated frame: {} more bytes expected, {} left",
+                n,
+                self.bytes.len()
+            ));
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        let bytes = self.take(8)?;
+        Ok(f64::from_le_bytes(bytes.try_into().expect("8 bytes")))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.u16()? as usize;
+        String::from_utf8(self.take(length)?.to_vec())
+            .map_err(|_| "string is not UTF-8".to_string())
+    }
+
+    fn list<T>(&mut self, item: impl Fn(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let count = self.u16()?;
+        (0..count).map(|_| item(self)).collect()
+    }
+}
+
+/// The message of a frame, length prefix left out
+pub fn decode(frame: &[u8]) -> Result<Message, String> {
+    let mut fields = Fields { bytes: frame };
+    let version = fields.u8()?;
+    if version != VERSION {
+        return Err(format!("unsupported wire version {}", version));
+    }
+    let message = match fields.u8()? {
+        0x01 => Message::Init,
+        0x02 => Message::SetInputs(fields.list(|f| Ok((f.u16()?, f.f64()?)))?),
+        0x03 => Message::Compute {
+            time: fields.f64()?,
+        },
+        0x04 => Message::GetState,
+        0x05 => Message::Weights(fields.list(Fields::f64)?),
+        0x06 => Message::Terminate,
+        0x81 => Message::Ok,
+        0x82 => Message::Error(fields.string()?),
+        0x83 => Message::Ready {
+            inputs: fields.list(Fields::string)?,
+            outputs: fields.list(Fields::string)?,
+        },
+        0x84 => {
+            let time = fields.f64()?;
+            let outputs = fields.list(Fields::f64)?;
+            let actuator = fields.f64()?;
+            let band = fields.u8()?;
+            let band = *BANDS
+                .get(band as usize)
+                .ok_or_else(|| format!("unknown band {}", band))?;
+            Message::State(State {
+                time,
+                outputs,
+                actuator,
+                band,
+            })
+        }
+        id => return Err(format!("unknown message id 0x{:02x}", id)),
+    };
+    if !fields.bytes.is_empty() {
+        return Err(format!(
+            "{} bytes past the end of the message",
+            fields.bytes.len()
+        ));
+    }
+    Ok(message)
+}
+
+/// The next frame on `reader`, length prefix left out; none at the end of
+/// the input between frames. A frame cut short is an `UnexpectedEof`, one
+/// over MAX_FRAME `InvalidData`, left unread
+pub fn read_frame(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0; 4];
+    let mut filled = 0;
+    while filled < length.len() {
+        match reader.read(&mut length[filled..])? {
+            0 if filled == 0 => return Ok(None),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => filled += n,
+        }
+    }
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "frame of {} bytes is over the limit of {}",
+                length, MAX_FRAME
+            ),
+        ));
+    }
+    let mut frame = vec![0; length];
+    reader.read_exact(&mut frame)?;
+    Ok(Some(frame))
+}
+
+/// Send `message` as one frame
+pub fn write(writer: &mut impl Write, message: &Message) -> io::Result<()> {
+    let frame = encode(message).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every_message() -> Vec<Message> {
+        vec![
+            Message::Init,
+            Message::SetInputs(vec![]),
+            Message::SetInputs(vec![(0, 21.5), (1, f64::NAN), (65535, -0.0)]),
+            Message::Compute { time: 0.01 },
+            Message::GetState,
+            Message::Weights(vec![1.0, 0.25, 0.0]),
+            Message::Terminate,
+            Message::Ok,
+            Message::Error(String::new()),
+            Message::Error("unknown input 'co₂'".to_string()),
+            Message::Ready {
+                inputs: vec!["temperature".to_string(), "humidity".to_string()],
+                outputs: vec!["fan_speed".to_string()],
+            },
+            Message::State(State {
+                time: 1e9,
+                outputs: vec![62.5, f64::INFINITY],
+                actuator: 58.125,
+                band: FanBand::High,
+            }),
+        ]
+    }
+
+    /// Equal, with NaN equal to itself, as the bits go through unchanged
+    fn same(a: &Message, b: &Message) -> bool {
+        match (a, b) {
+            (Message::SetInputs(a), Message::SetInputs(b)) => {
+                a.len() == b.len()
+                    && (a.iter().zip(b))
+                        .all(|((i, x), (j, y))| i == j && x.to_bits() == y.to_bits())
+            }
+            _ => a == b,
+        }
+    }
+
+    #[test]
+    fn every_message_round_trips_through_a_frame() {
+        let mut stream = Vec::new();
+        for message in every_message() {
+            let frame = encode(&message).unwrap();
+            assert_eq!(
+                frame.len() - 4,
+                u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize
+            );
+            assert_eq!(frame[4], VERSION);
+            let decoded = decode(&frame[4..]).unwrap();
+            assert!(
+                same(&decoded, &message),
+                "{:?} came back {:?}",
+                message,
+                decoded
+            );
+            write(&mut stream, &message).unwrap();
+        }
+        // Back to back on a stream, then a clean end
+        let mut reader = &stream[..];
+        for message in every_message() {
+            let frame = read_frame(&mut reader).unwrap().unwrap();
+            assert!(same(&decode(&frame).unwrap(), &message));
+        }
+        assert!(read_frame(&mut reader).unwrap().is_none());
+        for band in BANDS {
+            let state = Message::State(State {
+                time: 0.0,
+                outputs: vec![],
+                actuator: 0.0,
+                band,
+            });
+            assert_eq!(decode(&encode(&state).unwrap()[4..]), Ok(state));
+        }
+    }
+
+    #[test]
+    fn truncated_frames_are_refused_at_every_cut() {
+        for message in every_message() {
+            let frame = encode(&message).unwrap();
+            for cut in 1..frame.len() {
+                let error = read_frame(&mut &frame[..cut]).unwrap_err();
+                assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof, "{:?}", message);
+            }
+            // A length prefix that promises less than the fields need
+            for cut in 4..frame.len() {
+                assert!(
+                    decode(&frame[4..cut]).is_err(),
+                    "{:?} cut at {}",
+                    message,
+                    cut
+                );
+            }
+        }
+        assert_eq!(
+            decode(&[VERSION, 0x03, 0, 0]),
+            Err("truncated frame: 8 more bytes expected, 2 left".to_string())
+        );
+    }
+
+    #[test]
+    fn oversized_and_malformed_frames_are_refused() {
+        // Read no further than the length
+        let mut stream = ((MAX_FRAME + 1) as u32).to_le_bytes().to_vec();
+        stream.extend([VERSION, 0x01]);
+        let error = read_frame(&mut &stream[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let huge = Message::Weights(vec![0.5; 9000]);
+        assert!(encode(&huge).unwrap_err().contains("over the limit"));
+        let long = Message::SetInputs(vec![(0, 0.0); 70000]);
+        assert_eq!(
+            encode(&long),
+            Err("70000 items do not fit a frame".to_string())
+        );
+        let fits = encode(&Message::Weights(vec![0.5; 8000])).unwrap();
+        assert!(fits.len() - 4 <= MAX_FRAME);
+        assert!(read_frame(&mut &fits[..]).unwrap().is_some());
+
+        assert_eq!(
+            decode(&[2, 0x01]),
+            Err("unsupported wire version 2".to_string())
+        );
+        assert_eq!(
+            decode(&[VERSION, 0x7f]),
+            Err("unknown message id 0x7f".to_string())
+        );
+        assert_eq!(
+            decode(&[VERSION, 0x01, 0]),
+            Err("1 bytes past the end of the message".to_string())
+        );
+        assert_eq!(
+            decode(&[VERSION, 0x82, 1, 0, 0xff]),
+            Err("string is not UTF-8".to_string())
+        );
+        assert_eq!(
+            decode(&[]),
+            Err("truncated frame: 1 more bytes expected, 0 left".to_string())
+        );
+        let mut state = encode(&Message::State(State {
+            time: 0.0,
+            outputs: vec![],
+            actuator: 0.0,
+            band: FanBand::Off,
+        }))
+        .unwrap();
+        *state.last_mut().unwrap() = 4;
+        assert_eq!(decode(&state[4..]), Err("unknown band 4".to_string()));
+    }
+
+    #[test]
+    fn the_handshake_falls_back_to_text_unless_answered_in_kind() {
+        assert_eq!(requested("wire 1"), Some(Ok(())));
+        assert_eq!(requested(" wire  1 \n"), Some(Ok(())));
+        assert_eq!(
+            requested("wire 2"),
+            Some(Err("unsupported wire version 2".to_string()))
+        );
+        assert_eq!(requested("set temperature 20"), None);
+        assert_eq!(requested(r#"{"cmd":"init"}"#), None);
+
+        let mut sent = Vec::new();
+        assert!(negotiate(&mut &b"wire 1\n"[..], &mut sent).unwrap());
+        assert_eq!(sent, b"wire 1\n");
+        let older = &b"error unknown command 'wire 1'\n"[..];
+        assert!(!negotiate(&mut { older }, &mut Vec::new()).unwrap());
+        assert!(!negotiate(&mut &b""[..], &mut Vec::new()).unwrap());
+    }
+}
@@ -0,0 +1,191 @@
+// ============================================================================
+// DETERMINISM - Artefatos estáveis byte a byte
+// ============================================================================
+//
+// Exports are reviewed by diffing them, so the same inputs must give the
+// same bytes: variables and sets in declaration order, rules in definition
+// order, no map iteration anywhere on the way out. Each artifact is written
+// twice from inputs read twice, then the binary writes it again in fresh
+// processes. Artifacts stamped with the time they were made take
+// SOURCE_DATE_EPOCH (timestamp.rs).
+
+use fuzzy_logic::adapt::{Adaptation, Direction};
+use fuzzy_logic::bundle::Bundle;
+use fuzzy_logic::fingerprint::{self, Snapshot};
+use fuzzy_logic::{FuzzySystem, config, matrix, rulecsv, surface};
+use std::process::{Command, Output};
+
+const FAN: &str = "configs/fan.toml";
+
+fn fan() -> FuzzySystem {
+    config::load_system(FAN).unwrap()
+}
+
+fn temp(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("fuzzy-determinism-{}-{}", std::process::id(), name))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// `artifact` made twice, from inputs read afresh each time
+fn twice<T: AsRef<[u8]>>(name: &str, artifact: impl Fn() -> T) {
+    let (first, second) = (artifact(), artifact());
+    assert!(
+        first.as_ref() == second.as_ref(),
+        "{} differs between two writes",
+        name
+    );
+}
+
+#[test]
+fn each_artifact_serializes_to_the_same_bytes_twice() {
+    twice("config", || config::to_toml(&fan(), false));
+    twice("config with links", || config::to_toml(&fan(), true));
+    // Written, read and written again: the same file
+    let written = config::to_toml(&fan(), false);
+    let again = config::to_toml(&config::parse_system(&written).unwrap(), false);
+    assert_eq!(written, again);
+
+    twice("rules csv", || rulecsv::export(&fan()).unwrap());
+    twice("rule matrix", || {
+        let system = fan();
+        let matrix = matrix::build(&system, &matrix::Axes::default()).unwrap();
+        matrix.to_csv() + &matrix.to_text(&system).join("\n")
+    });
+    twice("surface csv", || {
+        let controller = fuzzy_logic::FuzzyController::from_system(fan());
+        let surfaces = surface::compute_surfaces(&[controller], 21, 11);
+        let mut out = Vec::new();
+        surface::write_csv(&fan(), &surfaces, &mut out).unwrap();
+        out
+    });
+    twice("snapshot", || {
+        fingerprint::to_toml(&[Snapshot::take(&fan(), 1760612345.0)])
+    });
+    twice("bundle", || {
+        Bundle::new(FAN, &fan(), Vec::new(), None).to_bytes(1760612345.0)
+    });
+    twice("adapted weights", || {
+        let text = std::fs::read_to_string(FAN).unwrap() + "\n[adaptation]\n";
+        let mut system = config::parse_system(&text).unwrap();
+        let mut adaptation = Adaptation::new(&system);
+        for direction in [Direction::Higher, Direction::Lower, Direction::Higher] {
+            adaptation
+                .give(&mut system, &[27.5, 64.0], direction, 1760612345.0)
+                .unwrap();
+        }
+        adaptation.to_toml(&system)
+    });
+}
+
+fn fuzzy_logic(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fuzzy_logic"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(args)
+        .env("SOURCE_DATE_EPOCH", "1760612345")
+        .output()
+        .expect("the binary runs")
+}
+
+/// The command's stdout, and the file it writes at `out` if any, from two
+/// fresh processes
+fn rerun(args: &[&str], out: Option<&str>) {
+    rerun_exiting(args, out, 0)
+}
+
+/// `rerun` of a command that exits with `code`
+fn rerun_exiting(args: &[&str], out: Option<&str>, code: i32) {
+    let mut runs = Vec::new();
+    for _ in 0..2 {
+        if let Some(out) = out {
+            let _ = std::fs::remove_file(out);
+        }
+        let output = fuzzy_logic(args);
+        assert_eq!(
+            output.status.code(),
+            Some(code),
+            "{:?}: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let file = out.map(|out| std::fs::read(out).unwrap());
+        runs.push((output.stdout, file));
+    }
+    if let Some(out) = out {
+        std::fs::remove_file(out).unwrap();
+    }
+    assert!(runs[0] == runs[1], "{:?} differs between two runs", args);
+}
+
+#[test]
+fn exports_match_across_fresh_processes() {
+    rerun(&["surface", "--config", FAN, "--grid", "9"], None);
+    rerun(
+        &[
+            "surface",
+            "--config",
+            FAN,
+            "--sampling",
+            "lhs",
+            "--samples",
+            "3",
+        ],
+        None,
+    );
+    rerun(&["rules", "--config", FAN, "--matrix", "--csv"], None);
+    for format in ["csv", "json", "c"] {
+        rerun(
+            &[
+                "quantize", "--config", FAN, "--size", "4x3", "--format", format,
+            ],
+            None,
+        );
+    }
+    rerun(&["check", FAN], None);
+    // The demo's sampled dip makes it not monotone
+    rerun_exiting(&["monotone", "--config", FAN], None, 1);
+
+    let csv = temp("rules.csv");
+    rerun(
+        &["rules", "export", "--csv", &csv, "--config", FAN],
+        Some(&csv),
+    );
+    let migrated = temp("migrated.toml");
+    rerun(&["migrate", FAN, "--out", &migrated], Some(&migrated));
+    let bundle = temp("bundle.tar.gz");
+    rerun(
+        &["bundle", "--out", &bundle, "--config", FAN],
+        Some(&bundle),
+    );
+
+    // A snapshot is appended to the history next to the state file: a
+    // fresh history each run
+    let state = temp("state.toml");
+    let history = fingerprint::history_path(&state);
+    let mut runs = Vec::new();
+    for _ in 0..2 {
+        let output = fuzzy_logic(&["snapshot", "--state", &state, "--config", FAN]);
+        assert!(output.status.success());
+        runs.push(std::fs::read(&history).unwrap());
+        std::fs::remove_file(&history).unwrap();
+    }
+    assert_eq!(runs[0], runs[1]);
+}
+
+#[cfg(feature = "batch")]
+#[test]
+fn batch_output_matches_across_fresh_processes() {
+    let out = temp("batch.csv");
+    rerun(
+        &[
+            "batch",
+            "tests/data/no-time.csv",
+            "--config",
+            FAN,
+            "--out",
+            &out,
+        ],
+        Some(&out),
+    );
+}
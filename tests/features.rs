@@ -0,0 +1,67 @@
+// ============================================================================
+// FEATURE MATRIX - Builds com subconjuntos de funcionalidades
+// ============================================================================
+//
+// Each runtime mode is a cargo feature (see Cargo.toml). These tests build
+// the binary with subsets of them in a target directory of their own, so
+// they take a while and are ignored by default:
+//
+//     cargo test --test features -- --ignored
+
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Feature sets that must build on their own, without the defaults
+const MATRIX: [&str; 7] = [
+    "minimal",
+    "tui",
+    "daemon",
+    "batch",
+    "cosim",
+    "tui,batch",
+    "daemon,batch,cosim",
+];
+
+fn target_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/features")
+}
+
+/// Build with only `features`, returning the path of the binary
+fn build(features: &str) -> PathBuf {
+    let status = Command::new(env!("CARGO"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["build", "--no-default-features", "--features", features])
+        .arg("--target-dir")
+        .arg(target_dir())
+        .status()
+        .expect("cargo runs");
+    assert!(status.success(), "build with '{}' failed", features);
+    target_dir().join("debug/fuzzy_logic")
+}
+
+#[test]
+#[ignore = "builds the binary once per feature set"]
+fn every_feature_set_builds() {
+    for features in MATRIX {
+        build(features);
+    }
+}
+
+#[test]
+#[ignore = "builds the binary without default features"]
+fn the_minimal_build_leaves_the_tui_out() {
+    let binary = build("minimal");
+    let bytes = std::fs::read(&binary).unwrap();
+    assert!(
+        !bytes.windows(b"ratatui".len()).any(|w| w == b"ratatui"),
+        "{} contains ratatui symbols",
+        binary.display()
+    );
+
+    let output = Command::new(&binary).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: built without feature 'tui'\n"
+    );
+}
@@ -0,0 +1,271 @@
+// ============================================================================
+// PUBLIC API - Superfície pública da biblioteca
+// ============================================================================
+//
+// The documented surface of the library (lib.rs) is what other crates may
+// build on, so changing it should be a decision rather than an accident.
+// This lists every `pub` item of lib.rs and of each module it does not
+// hide from the docs, the way a reader of the source sees them, and
+// compares the list with tests/data/public-api.txt. After a deliberate
+// change, write the listing again and review its diff:
+//
+//     FUZZY_LOGIC_BLESS=1 cargo test --test public_api
+//
+// The listing is read from the source, not from the compiler, so it goes
+// by lines: each item on one line, signatures split over several joined,
+// under the `impl` or type it sits in. Items behind `#[doc(hidden)]` or
+// `#[cfg(test)]` are left out.
+
+use fuzzy_logic::prelude::*;
+
+const LISTING: &str = "tests/data/public-api.txt";
+const BLESS_VAR: &str = "FUZZY_LOGIC_BLESS";
+
+/// Modules declared `pub mod` in lib.rs without `#[doc(hidden)]`
+fn documented_modules(lib: &str) -> Vec<String> {
+    let mut hidden = false;
+    let mut modules = Vec::new();
+    for line in lib.lines() {
+        if line == "#[doc(hidden)]" {
+            hidden = true;
+        } else if let Some(name) = line.strip_prefix("pub mod ") {
+            if !hidden {
+                modules.push(name.trim_end_matches(';').to_string());
+            }
+            hidden = false;
+        } else if !line.starts_with("#[") {
+            hidden = false;
+        }
+    }
+    modules
+}
+
+/// The `pub` items of `source`, up to its tests, each as `module: item`
+/// or `module: context: item` inside an `impl` or a type
+fn items(module: &str, source: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut attributes: Vec<&str> = Vec::new();
+    let mut context: Option<String> = None;
+    // Types declared `pub` so far; the items of any other are not public
+    let mut public: Vec<String> = Vec::new();
+    let mut lines = source.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        if line == "mod tests {" {
+            break;
+        }
+        if line == "}" {
+            context = None;
+        }
+        if trimmed.starts_with("#[") {
+            attributes.push(trimmed);
+            continue;
+        }
+        if trimmed.starts_with("///") || trimmed.starts_with("//") {
+            continue;
+        }
+        if let Some(rest) = trimmed.strip_prefix("pub ") {
+            // Imports run to their semicolon, functions to their body as
+            // well, and fields to their comma
+            let ends: &[char] = if rest.starts_with("use ") {
+                &[';']
+            } else if trimmed.contains("fn ") {
+                &['{', ';']
+            } else {
+                &['{', ';', ',']
+            };
+            let mut item = trimmed.to_string();
+            while !item.ends_with(ends) {
+                match lines.next() {
+                    Some(next) => {
+                        item.push(' ');
+                        item.push_str(next.trim());
+                    }
+                    None => break,
+                }
+            }
+            let item = item
+                .trim_end_matches('{')
+                .trim_end()
+                .replace("( ", "(")
+                .replace("{ ", "{")
+                .replace(", )", ")")
+                .replace(", }", "}");
+            if !line.starts_with(' ')
+                && let Some(name) = ["struct ", "enum ", "trait "]
+                    .iter()
+                    .find_map(|kind| rest.strip_prefix(kind))
+            {
+                public.push(type_name(name));
+            }
+            let within = context.as_ref().filter(|_| line.starts_with(' '));
+            let shown = !attributes
+                .iter()
+                .any(|a| *a == "#[doc(hidden)]" || *a == "#[cfg(test)]")
+                && within.is_none_or(|context| {
+                    public.contains(&type_name(context.rsplit(' ').next().unwrap_or_default()))
+                });
+            if shown {
+                let mut entry = format!("{}: ", module);
+                if let Some(context) = within {
+                    entry.push_str(&format!("{}: ", context));
+                }
+                if attributes.contains(&"#[non_exhaustive]") {
+                    entry.push_str("#[non_exhaustive] ");
+                }
+                entry.push_str(&item);
+                items.push(entry);
+            }
+        }
+        if !line.starts_with(' ') && trimmed.ends_with('{') {
+            context = Some(trimmed.trim_end_matches('{').trim_end().to_string());
+        }
+        attributes.clear();
+    }
+    items
+}
+
+/// `Name` of `Name<T> {`, `Name: Trait` or `Name;`
+fn type_name(declared: &str) -> String {
+    declared
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn listing() -> String {
+    let lib = std::fs::read_to_string("src/lib.rs").unwrap();
+    let mut listed = items("lib", &lib);
+    for module in documented_modules(&lib) {
+        let source = std::fs::read_to_string(format!("src/{}.rs", module)).unwrap();
+        listed.extend(items(&module, &source));
+    }
+    listed.join("\n") + "\n"
+}
+
+#[test]
+fn the_documented_surface_matches_its_listing() {
+    let listed = listing();
+    if std::env::var_os(BLESS_VAR).is_some() {
+        std::fs::write(LISTING, &listed).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(LISTING).unwrap();
+    if listed == expected {
+        return;
+    }
+    let (now, before): (Vec<&str>, Vec<&str>) =
+        (listed.lines().collect(), expected.lines().collect());
+    let mut changes: Vec<String> = (before.iter())
+        .filter(|line| !now.contains(line))
+        .map(|line| format!("- {}", line))
+        .collect();
+    changes.extend(
+        (now.iter())
+            .filter(|line| !before.contains(line))
+            .map(|line| format!("+ {}", line)),
+    );
+    panic!(
+        "the public API changed; if that is meant, run with {}=1 and review {}:\n{}",
+        BLESS_VAR,
+        LISTING,
+        changes.join("\n")
+    );
+}
+
+#[test]
+fn the_prelude_builds_and_steps_a_system() {
+    let temperature = FuzzyVariable::new("temperature", 0.0, 40.0)
+        .with_unit("°C")
+        .with_set(
+            "Cold",
+            MembershipFunction::Trapezoidal {
+                a: 0.0,
+                b: 0.0,
+                c: 10.0,
+                d: 20.0,
+            },
+        )
+        .with_set(
+            "Hot",
+            MembershipFunction::Triangular {
+                a: 15.0,
+                b: 40.0,
+                c: 40.0,
+            },
+        );
+    let fan = FuzzyVariable::new("fan", 0.0, 100.0)
+        .with_set(
+            "Off",
+            MembershipFunction::Triangular {
+                a: 0.0,
+                b: 0.0,
+                c: 50.0,
+            },
+        )
+        .with_set(
+            "Full",
+            MembershipFunction::Triangular {
+                a: 50.0,
+                b: 100.0,
+                c: 100.0,
+            },
+        );
+    let rules = [
+        "IF temperature IS Cold THEN fan IS Off",
+        "IF temperature IS Hot THEN fan IS Full",
+    ]
+    .into_iter()
+    .map(FuzzyRule::parse)
+    .collect::<Result<Vec<_>, String>>()
+    .unwrap();
+    let system = FuzzySystem::new(vec![temperature], vec![fan], rules).unwrap();
+
+    let controller = FuzzyController::from_system(system.clone());
+    let evaluation: Evaluation = controller.evaluate(&[35.0]);
+    assert!(evaluation.outputs[0] > 50.0);
+    assert!(controller.compute(&[5.0]) < 50.0);
+
+    let mut stepper = Stepper::new(system.with_pipeline(PipelineConfig::default()));
+    let step: Step = stepper.step(&[35.0], 1.0).unwrap();
+    let output: PipelineOutput = step.output;
+    assert_eq!(output.band, FanBand::from_speed(output.value));
+
+    // Outside this crate the enums that will grow need a `_` arm; were one
+    // exhaustive, the arm would be unreachable and fail the lints
+    let named = |method: DefuzzMethod| match method {
+        DefuzzMethod::Centroid => "centroid",
+        DefuzzMethod::PlateauCentroid | DefuzzMethod::WeightedAverage => "other",
+        _ => "newer",
+    };
+    assert_eq!(named(DefuzzMethod::default()), "centroid");
+    let shape = |function: &MembershipFunction| match function {
+        MembershipFunction::Triangular { .. } => "triangular",
+        MembershipFunction::Trapezoidal { .. } => "trapezoidal",
+        MembershipFunction::Gaussian { .. } => "gaussian",
+        MembershipFunction::Sigmoid { .. } => "sigmoid",
+        MembershipFunction::GeneralizedBell { .. } => "gbell",
+        MembershipFunction::SCurve { .. } => "s_curve",
+        MembershipFunction::ZCurve { .. } => "z_curve",
+        MembershipFunction::Custom(_) => "custom",
+        _ => "newer",
+    };
+    let triangle = MembershipFunction::Triangular {
+        a: 0.0,
+        b: 1.0,
+        c: 2.0,
+    };
+    assert_eq!(shape(&triangle), "triangular");
+
+    // Any closure can shape a set, and every shape is a `MembershipFn`
+    let warm = MembershipFunction::custom(|x: f64| (x - 20.0) / 10.0);
+    assert_eq!(shape(&warm), "custom");
+    let shapes: [&dyn MembershipFn; 3] = [&triangle, &warm, &|x: f64| x / 2.0];
+    assert_eq!(shapes.map(|s| s.evaluate(1.0)), [1.0, 0.0, 0.5]);
+    assert!(matches!(Aggregation::default(), Aggregation::Max));
+    assert!(matches!(Implication::default(), Implication::Min));
+    assert_eq!(OutOfRange::default(), OutOfRange::Clamp);
+    let clock = SystemClock::new();
+    assert!(clock.now() >= 0.0);
+}
@@ -0,0 +1,57 @@
+// ============================================================================
+// REPL - Execução sem terminal
+// ============================================================================
+//
+// Runs the binary with pipes for its standard streams, as a script or CI
+// would. Stdin here is never a terminal, so the TUI's invocations must
+// stop with the hint; the line mode itself is driven through `repl`, the
+// same session the fallback serves when only stdout is piped.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn fuzzy_logic(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fuzzy_logic"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the binary runs");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn without_a_terminal_the_tui_names_the_headless_commands() {
+    for args in [&[][..], &["--config", "configs/fan.toml"], &["--no-color"]] {
+        let output = fuzzy_logic(args, "");
+        assert_eq!(output.status.code(), Some(3), "{:?}", args);
+        assert!(output.stdout.is_empty());
+        let err = String::from_utf8(output.stderr).unwrap();
+        assert!(err.contains("needs a terminal"), "{}", err);
+        assert!(err.contains("fuzzy_logic repl"), "{}", err);
+        assert!(err.contains("fuzzy_logic check"), "{}", err);
+    }
+}
+
+#[test]
+fn repl_answers_piped_readings_on_stdout_and_mistakes_on_stderr() {
+    let output = fuzzy_logic(&["repl"], "temp=31 hum=70\nbogus=1\nhum=40\nquit\n");
+    assert!(output.status.success());
+    let out = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", out);
+    assert!(lines.iter().all(|line| line.starts_with("fan_speed=")));
+    let err = String::from_utf8(output.stderr).unwrap();
+    assert!(err.contains("unknown input 'bogus'"), "{}", err);
+
+    let output = fuzzy_logic(&["repl", "--locale", "klingon"], "");
+    assert_eq!(output.status.code(), Some(2));
+}
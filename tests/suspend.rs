@@ -0,0 +1,140 @@
+// ============================================================================
+// SUSPEND - Ctrl-Z e fg num processo filho
+// ============================================================================
+//
+// The signal paths of termguard.rs on a real process. This test binary
+// runs itself again as a child that takes a silent screen through the
+// guard and handles signals until it has been stopped, continued and
+// refreshed. The parent sends SIGTSTP and SIGCONT with `kill` and reads
+// the transitions the child traced.
+
+#![cfg(all(unix, feature = "tui"))]
+
+use fuzzy_logic::shutdown::Token;
+use fuzzy_logic::termguard::{self, Guard, Screen};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Set in the child to the file it traces to
+const CHILD: &str = "FUZZY_LOGIC_SUSPEND_CHILD";
+const PATIENCE: Duration = Duration::from_secs(20);
+
+/// A screen with nothing to switch: no terminal in the child
+struct Silent;
+
+impl Screen for Silent {
+    fn enter(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn leave(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn traced(path: &str) -> Vec<String> {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+/// The child's side: a TUI loop without the TUI
+#[test]
+fn child() {
+    let Ok(trace) = std::env::var(CHILD) else {
+        return;
+    };
+    termguard::install();
+    let mut guard = Guard::new(Silent);
+    guard.trace_to(&trace).unwrap();
+    guard.enter().unwrap();
+    let token = Token::default();
+    let start = Instant::now();
+    while start.elapsed() < PATIENCE && !traced(&trace).iter().any(|l| l == "refresh") {
+        guard.handle_signals(&token).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    guard.restore().unwrap();
+}
+
+fn kill(signal: &str, pid: u32) {
+    let status = Command::new("kill")
+        .args([signal, &pid.to_string()])
+        .status()
+        .expect("kill runs");
+    assert!(status.success());
+}
+
+fn wait_for(trace: &str, transition: &str) {
+    let start = Instant::now();
+    while !traced(trace).iter().any(|l| l == transition) {
+        assert!(
+            start.elapsed() < PATIENCE,
+            "no '{}' in {:?}",
+            transition,
+            traced(trace)
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Whether `pid` is stopped, from its state in /proc
+#[cfg(target_os = "linux")]
+fn stopped(pid: u32) -> bool {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap_or_default();
+    // The state follows the command name, which may hold anything
+    stat.rsplit_once(')')
+        .is_some_and(|(_, rest)| rest.trim_start().starts_with('T'))
+}
+
+#[test]
+fn a_stopped_child_gives_the_terminal_back_and_takes_it_again_on_continue() {
+    if std::env::var(CHILD).is_ok() {
+        return;
+    }
+    let trace = std::env::temp_dir()
+        .join(format!("fuzzy-suspend-{}", std::process::id()))
+        .to_string_lossy()
+        .to_string();
+    let _ = std::fs::remove_file(&trace);
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "child", "--test-threads=1"])
+        .env(CHILD, &trace)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let pid = child.id();
+
+    wait_for(&trace, "enter");
+    kill("-TSTP", pid);
+    wait_for(&trace, "suspend");
+    #[cfg(target_os = "linux")]
+    {
+        let start = Instant::now();
+        while !stopped(pid) {
+            assert!(start.elapsed() < PATIENCE, "the child never stopped");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+    kill("-CONT", pid);
+    wait_for(&trace, "resume");
+    // A SIGCONT with no stop before it only sets the terminal up again
+    kill("-CONT", pid);
+    wait_for(&trace, "restore");
+    assert!(child.wait().unwrap().success());
+    assert_eq!(
+        traced(&trace),
+        [
+            "enter",
+            "suspend",
+            "continued",
+            "resume",
+            "refresh",
+            "restore"
+        ]
+    );
+    std::fs::remove_file(&trace).unwrap();
+}